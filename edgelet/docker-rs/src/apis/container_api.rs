@@ -99,7 +99,7 @@ pub trait ContainerApi: Send + Sync {
         &self,
         id: &str,
         signal: &str,
-    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>>>;
+    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>> + Send>;
     fn container_list(
         &self,
         all: bool,
@@ -117,6 +117,7 @@ pub trait ContainerApi: Send + Sync {
         stdout: bool,
         stderr: bool,
         since: i32,
+        until: Option<i32>,
         timestamps: bool,
         tail: &str,
     ) -> Box<dyn Future<Item = hyper::Body, Error = Error<serde_json::Value>> + Send>;
@@ -702,7 +703,7 @@ where
         &self,
         id: &str,
         signal: &str,
-    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>>> {
+    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>> + Send> {
         let configuration: &configuration::Configuration<C> = self.configuration.borrow();
 
         let method = hyper::Method::POST;
@@ -819,6 +820,7 @@ where
         stdout: bool,
         stderr: bool,
         since: i32,
+        until: Option<i32>,
         timestamps: bool,
         tail: &str,
     ) -> Box<dyn Future<Item = hyper::Body, Error = Error<serde_json::Value>> + Send> {
@@ -826,14 +828,19 @@ where
 
         let method = hyper::Method::GET;
 
-        let query = ::url::form_urlencoded::Serializer::new(String::new())
+        let mut query = ::url::form_urlencoded::Serializer::new(String::new());
+        query
             .append_pair("follow", &follow.to_string())
             .append_pair("stdout", &stdout.to_string())
             .append_pair("stderr", &stderr.to_string())
             .append_pair("since", &since.to_string())
             .append_pair("timestamps", &timestamps.to_string())
-            .append_pair("tail", &tail.to_string())
-            .finish();
+            .append_pair("tail", &tail.to_string());
+        // until=0 is not treated as "no limit" by all daemon versions, so only send it when set
+        if let Some(until) = until {
+            query.append_pair("until", &until.to_string());
+        }
+        let query = query.finish();
         let uri_str = format!("/containers/{id}/logs?{}", query, id = id);
 
         let uri = (configuration.uri_composer)(&configuration.base_path, &uri_str);
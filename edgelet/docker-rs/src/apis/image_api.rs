@@ -86,12 +86,25 @@ pub trait ImageApi: Send + Sync {
         x_registry_auth: &str,
         platform: &str,
     ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>> + Send>;
+    /// Like `image_create`, but resolves to the progress stream of the pull as
+    /// soon as it starts, so that the caller controls how fast it's read. Pass
+    /// the whole stream to `image_create_result` for the outcome of the pull.
+    fn image_create_progress(
+        &self,
+        from_image: &str,
+        from_src: &str,
+        repo: &str,
+        tag: &str,
+        input_image: &str,
+        x_registry_auth: &str,
+        platform: &str,
+    ) -> Box<dyn Future<Item = hyper::Body, Error = Error<serde_json::Value>> + Send>;
     fn image_delete(
         &self,
         name: &str,
         force: bool,
         noprune: bool,
-    ) -> Box<dyn Future<Item = Vec<ImageDeleteResponseItem>, Error = Error<serde_json::Value>>>;
+    ) -> Box<dyn Future<Item = Vec<ImageDeleteResponseItem>, Error = Error<serde_json::Value>> + Send>;
     fn image_get(
         &self,
         name: &str,
@@ -109,18 +122,21 @@ pub trait ImageApi: Send + Sync {
     fn image_inspect(
         &self,
         name: &str,
-    ) -> Box<dyn Future<Item = crate::models::Image, Error = Error<serde_json::Value>>>;
+    ) -> Box<dyn Future<Item = crate::models::Image, Error = Error<serde_json::Value>> + Send>;
     fn image_list(
         &self,
         all: bool,
         filters: &str,
         digests: bool,
-    ) -> Box<dyn Future<Item = Vec<crate::models::ImageSummary>, Error = Error<serde_json::Value>>>;
+    ) -> Box<
+        dyn Future<Item = Vec<crate::models::ImageSummary>, Error = Error<serde_json::Value>>
+            + Send,
+    >;
     fn image_load(
         &self,
         images_tarball: Vec<u8>,
         quiet: bool,
-    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>>>;
+    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>> + Send>;
     fn image_prune(
         &self,
         filters: &str,
@@ -392,6 +408,31 @@ where
         x_registry_auth: &str,
         platform: &str,
     ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>> + Send> {
+        Box::new(
+            self.image_create_progress(
+                from_image,
+                from_src,
+                repo,
+                tag,
+                input_image,
+                x_registry_auth,
+                platform,
+            )
+            .and_then(|body| body.concat2().map_err(|e| Error::from(e)))
+            .and_then(|body| image_create_result(&body)),
+        )
+    }
+
+    fn image_create_progress(
+        &self,
+        from_image: &str,
+        from_src: &str,
+        repo: &str,
+        tag: &str,
+        input_image: &str,
+        x_registry_auth: &str,
+        platform: &str,
+    ) -> Box<dyn Future<Item = hyper::Body, Error = Error<serde_json::Value>> + Send> {
         let configuration: &configuration::Configuration<C> = self.configuration.borrow();
 
         let method = hyper::Method::POST;
@@ -435,33 +476,18 @@ where
                 .map_err(|e| Error::from(e))
                 .and_then(|resp| {
                     let (http::response::Parts { status, .. }, body) = resp.into_parts();
-                    body.concat2()
-                        .and_then(move |body| Ok((status, body)))
-                        .map_err(|e| Error::from(e))
-                })
-                .and_then(|(status, body)| -> Result<(), Error<serde_json::Value>> {
-                    if !status.is_success() {
-                        return Err(Error::from((status, &*body)));
-                    }
-
-                    // Response body is a sequence of JSON objects.
-                    // Each object is either a `{ "status": ... }` or an `{ "errorDetail": ... }`
-                    //
-                    // The overall success or failure of the operation is determined by which one
-                    // the last object is.
-
-                    let mut deserializer = serde_json::Deserializer::from_slice(&body).into_iter();
-                    let mut last_response: serde_json::Map<String, serde_json::Value> =
-                        deserializer.last().ok_or_else(|| {
-                            Error::Serde(serde::de::Error::custom(
-                                "empty response from container runtime",
-                            ))
-                        })??;
-                    if let Some(error_detail) = last_response.remove("errorDetail") {
-                        Err(Error::from((status, error_detail)))
+                    let result: Box<
+                        dyn Future<Item = hyper::Body, Error = Error<serde_json::Value>> + Send,
+                    > = if status.is_success() {
+                        Box::new(futures::future::ok(body))
                     } else {
-                        Ok(())
-                    }
+                        Box::new(
+                            body.concat2()
+                                .map_err(|e| Error::from(e))
+                                .and_then(move |body| Err(Error::from((status, &*body)))),
+                        )
+                    };
+                    result
                 }),
         )
     }
@@ -471,7 +497,7 @@ where
         name: &str,
         force: bool,
         noprune: bool,
-    ) -> Box<dyn Future<Item = Vec<ImageDeleteResponseItem>, Error = Error<serde_json::Value>>>
+    ) -> Box<dyn Future<Item = Vec<ImageDeleteResponseItem>, Error = Error<serde_json::Value>> + Send>
     {
         let configuration: &configuration::Configuration<C> = self.configuration.borrow();
 
@@ -683,7 +709,7 @@ where
     fn image_inspect(
         &self,
         name: &str,
-    ) -> Box<dyn Future<Item = crate::models::Image, Error = Error<serde_json::Value>>> {
+    ) -> Box<dyn Future<Item = crate::models::Image, Error = Error<serde_json::Value>> + Send> {
         let configuration: &configuration::Configuration<C> = self.configuration.borrow();
 
         let method = hyper::Method::GET;
@@ -735,8 +761,10 @@ where
         all: bool,
         filters: &str,
         digests: bool,
-    ) -> Box<dyn Future<Item = Vec<crate::models::ImageSummary>, Error = Error<serde_json::Value>>>
-    {
+    ) -> Box<
+        dyn Future<Item = Vec<crate::models::ImageSummary>, Error = Error<serde_json::Value>>
+            + Send,
+    > {
         let configuration: &configuration::Configuration<C> = self.configuration.borrow();
 
         let method = hyper::Method::GET;
@@ -793,7 +821,7 @@ where
         &self,
         images_tarball: Vec<u8>,
         quiet: bool,
-    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>>> {
+    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>> + Send> {
         let configuration: &configuration::Configuration<C> = self.configuration.borrow();
 
         let method = hyper::Method::POST;
@@ -808,8 +836,8 @@ where
         // if let Err(e) = uri {
         //     return Box::new(futures::future::err(e));
         // }
-        let serialized = serde_json::to_string(&images_tarball).unwrap();
-        let serialized_len = serialized.len();
+        // The tarball is sent as is, not as JSON
+        let serialized_len = images_tarball.len();
 
         let mut req = hyper::Request::builder();
         req.method(method).uri(uri.unwrap());
@@ -817,10 +845,13 @@ where
             req.header(http::header::USER_AGENT, &**user_agent);
         }
         let mut req = req
-            .body(hyper::Body::from(serialized))
+            .body(hyper::Body::from(images_tarball))
             .expect("could not build hyper::Request");
-        req.headers_mut()
-            .typed_insert(&typed_headers::ContentType(mime::APPLICATION_JSON));
+        req.headers_mut().typed_insert(&typed_headers::ContentType(
+            "application/x-tar"
+                .parse()
+                .expect("application/x-tar is a valid MIME type"),
+        ));
         req.headers_mut()
             .typed_insert(&typed_headers::ContentLength(serialized_len as u64));
 
@@ -1068,3 +1099,26 @@ where
         )
     }
 }
+
+/// The outcome of a pull from the complete progress stream of
+/// `image_create_progress`.
+pub fn image_create_result(body: &[u8]) -> Result<(), Error<serde_json::Value>> {
+    // Response body is a sequence of JSON objects.
+    // Each object is either a `{ "status": ... }` or an `{ "errorDetail": ... }`
+    //
+    // The overall success or failure of the operation is determined by which one
+    // the last object is.
+
+    let mut deserializer = serde_json::Deserializer::from_slice(body).into_iter();
+    let mut last_response: serde_json::Map<String, serde_json::Value> =
+        deserializer.last().ok_or_else(|| {
+            Error::Serde(serde::de::Error::custom(
+                "empty response from container runtime",
+            ))
+        })??;
+    if let Some(error_detail) = last_response.remove("errorDetail") {
+        Err(Error::from((hyper::StatusCode::OK, error_detail)))
+    } else {
+        Ok(())
+    }
+}
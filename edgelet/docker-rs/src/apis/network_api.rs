@@ -36,7 +36,7 @@ pub trait NetworkApi: Send + Sync {
         &self,
         id: &str,
         container: crate::models::Container,
-    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>>>;
+    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>> + Send>;
     fn network_create(
         &self,
         network_config: crate::models::NetworkConfig,
@@ -79,7 +79,7 @@ where
         &self,
         id: &str,
         container: crate::models::Container,
-    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>>> {
+    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>> + Send> {
         let configuration: &configuration::Configuration<C> = self.configuration.borrow();
 
         let method = hyper::Method::POST;
@@ -21,6 +21,10 @@ pub struct AuthConfig {
     email: Option<String>,
     #[serde(rename = "serveraddress", skip_serializing_if = "Option::is_none")]
     serveraddress: Option<String>,
+    #[serde(rename = "identitytoken", skip_serializing_if = "Option::is_none")]
+    identitytoken: Option<String>,
+    #[serde(rename = "registrytoken", skip_serializing_if = "Option::is_none")]
+    registrytoken: Option<String>,
 }
 
 impl AuthConfig {
@@ -30,6 +34,8 @@ impl AuthConfig {
             password: None,
             email: None,
             serveraddress: None,
+            identitytoken: None,
+            registrytoken: None,
         }
     }
 
@@ -100,4 +106,38 @@ impl AuthConfig {
     pub fn reset_serveraddress(&mut self) {
         self.serveraddress = None;
     }
+
+    pub fn set_identitytoken(&mut self, identitytoken: String) {
+        self.identitytoken = Some(identitytoken);
+    }
+
+    pub fn with_identitytoken(mut self, identitytoken: String) -> Self {
+        self.identitytoken = Some(identitytoken);
+        self
+    }
+
+    pub fn identitytoken(&self) -> Option<&str> {
+        self.identitytoken.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_identitytoken(&mut self) {
+        self.identitytoken = None;
+    }
+
+    pub fn set_registrytoken(&mut self, registrytoken: String) {
+        self.registrytoken = Some(registrytoken);
+    }
+
+    pub fn with_registrytoken(mut self, registrytoken: String) -> Self {
+        self.registrytoken = Some(registrytoken);
+        self
+    }
+
+    pub fn registrytoken(&self) -> Option<&str> {
+        self.registrytoken.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_registrytoken(&mut self) {
+        self.registrytoken = None;
+    }
 }
@@ -48,6 +48,9 @@ pub struct ContainerConfig {
     /// A list of environment variables to set inside the container in the form `[\"VAR=value\", ...]`. A variable without `=` is removed from the environment, rather than to have an empty value.
     #[serde(rename = "Env", skip_serializing_if = "Option::is_none")]
     env: Option<Vec<String>>,
+    /// Command to run specified as a string or an array of strings.
+    #[serde(rename = "Cmd", skip_serializing_if = "Option::is_none")]
+    cmd: Option<Vec<String>>,
     #[serde(rename = "Healthcheck", skip_serializing_if = "Option::is_none")]
     healthcheck: Option<crate::models::HealthConfig>,
     /// Command is already escaped (Windows only)
@@ -61,6 +64,9 @@ pub struct ContainerConfig {
     /// The working directory for commands to run in.
     #[serde(rename = "WorkingDir", skip_serializing_if = "Option::is_none")]
     working_dir: Option<String>,
+    /// The entry point for the container as a string or an array of strings.
+    #[serde(rename = "Entrypoint", skip_serializing_if = "Option::is_none")]
+    entrypoint: Option<Vec<String>>,
     /// Disable networking for the container.
     #[serde(rename = "NetworkDisabled", skip_serializing_if = "Option::is_none")]
     network_disabled: Option<bool>,
@@ -99,11 +105,13 @@ impl ContainerConfig {
             open_stdin: None,
             stdin_once: None,
             env: None,
+            cmd: None,
             healthcheck: None,
             args_escaped: None,
             image: None,
             volumes: None,
             working_dir: None,
+            entrypoint: None,
             network_disabled: None,
             mac_address: None,
             on_build: None,
@@ -304,6 +312,23 @@ impl ContainerConfig {
         self.env = None;
     }
 
+    pub fn set_cmd(&mut self, cmd: Vec<String>) {
+        self.cmd = Some(cmd);
+    }
+
+    pub fn with_cmd(mut self, cmd: Vec<String>) -> Self {
+        self.cmd = Some(cmd);
+        self
+    }
+
+    pub fn cmd(&self) -> Option<&[String]> {
+        self.cmd.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_cmd(&mut self) {
+        self.cmd = None;
+    }
+
     pub fn set_healthcheck(&mut self, healthcheck: crate::models::HealthConfig) {
         self.healthcheck = Some(healthcheck);
     }
@@ -389,6 +414,23 @@ impl ContainerConfig {
         self.working_dir = None;
     }
 
+    pub fn set_entrypoint(&mut self, entrypoint: Vec<String>) {
+        self.entrypoint = Some(entrypoint);
+    }
+
+    pub fn with_entrypoint(mut self, entrypoint: Vec<String>) -> Self {
+        self.entrypoint = Some(entrypoint);
+        self
+    }
+
+    pub fn entrypoint(&self) -> Option<&[String]> {
+        self.entrypoint.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_entrypoint(&mut self) {
+        self.entrypoint = None;
+    }
+
     pub fn set_network_disabled(&mut self, network_disabled: bool) {
         self.network_disabled = Some(network_disabled);
     }
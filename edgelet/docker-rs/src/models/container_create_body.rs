@@ -40,9 +40,9 @@ pub struct ContainerCreateBody {
     // /// The domain name to use for the container.
     // #[serde(rename = "Domainname", skip_serializing_if = "Option::is_none")]
     // domainname: Option<String>,
-    // /// The user that commands are run as inside the container.
-    // #[serde(rename = "User", skip_serializing_if = "Option::is_none")]
-    // user: Option<String>,
+    /// The user that commands are run as inside the container.
+    #[serde(rename = "User", skip_serializing_if = "Option::is_none")]
+    user: Option<String>,
     // /// Whether to attach to `stdin`.
     // #[serde(rename = "AttachStdin", skip_serializing_if = "Option::is_none")]
     // attach_stdin: Option<bool>,
@@ -80,9 +80,9 @@ pub struct ContainerCreateBody {
     image: Option<String>,
     #[serde(rename = "Volumes", skip_serializing_if = "Option::is_none")]
     volumes: Option<::std::collections::HashMap<String, Value>>,
-    // /// The working directory for commands to run in.
-    // #[serde(rename = "WorkingDir", skip_serializing_if = "Option::is_none")]
-    // working_dir: Option<String>,
+    /// The working directory for commands to run in.
+    #[serde(rename = "WorkingDir", skip_serializing_if = "Option::is_none")]
+    working_dir: Option<String>,
     /// The entry point for the container as a string or an array of strings.
     /// If the array consists of exactly one empty string ([""]) then the entry
     /// point is reset to system default (i.e., the entry point used by docker
@@ -101,12 +101,12 @@ pub struct ContainerCreateBody {
     /// User-defined key/value metadata.
     #[serde(rename = "Labels", skip_serializing_if = "Option::is_none")]
     labels: Option<::std::collections::HashMap<String, String>>,
-    // /// Signal to stop a container as a string or unsigned integer.
-    // #[serde(rename = "StopSignal", skip_serializing_if = "Option::is_none")]
-    // stop_signal: Option<String>,
-    // /// Timeout to stop a container in seconds.
-    // #[serde(rename = "StopTimeout", skip_serializing_if = "Option::is_none")]
-    // stop_timeout: Option<i32>,
+    /// Signal to stop a container as a string or unsigned integer.
+    #[serde(rename = "StopSignal", skip_serializing_if = "Option::is_none")]
+    stop_signal: Option<String>,
+    /// Timeout to stop a container in seconds.
+    #[serde(rename = "StopTimeout", skip_serializing_if = "Option::is_none")]
+    stop_timeout: Option<i32>,
     // /// Shell for when `RUN`, `CMD`, and `ENTRYPOINT` uses a shell.
     // #[serde(rename = "Shell", skip_serializing_if = "Option::is_none")]
     // shell: Option<Vec<String>>,
@@ -123,7 +123,7 @@ impl ContainerCreateBody {
         ContainerCreateBody {
             hostname: None,
             // domainname: None,
-            // user: None,
+            user: None,
             // attach_stdin: None,
             // attach_stdout: None,
             // attach_stderr: None,
@@ -137,14 +137,14 @@ impl ContainerCreateBody {
             // args_escaped: None,
             image: None,
             volumes: None,
-            // working_dir: None,
+            working_dir: None,
             entrypoint: None,
             // network_disabled: None,
             // mac_address: None,
             // on_build: None,
             labels: None,
-            // stop_signal: None,
-            // stop_timeout: None,
+            stop_signal: None,
+            stop_timeout: None,
             // shell: None,
             host_config: None,
             networking_config: None,
@@ -186,22 +186,22 @@ impl ContainerCreateBody {
     //     self.domainname = None;
     // }
 
-    // pub fn set_user(&mut self, user: String) {
-    //     self.user = Some(user);
-    // }
+    pub fn set_user(&mut self, user: String) {
+        self.user = Some(user);
+    }
 
-    // pub fn with_user(mut self, user: String) -> Self {
-    //     self.user = Some(user);
-    //     self
-    // }
+    pub fn with_user(mut self, user: String) -> Self {
+        self.user = Some(user);
+        self
+    }
 
-    // pub fn user(&self) -> Option<&str> {
-    //     self.user.as_ref().map(AsRef::as_ref)
-    // }
+    pub fn user(&self) -> Option<&str> {
+        self.user.as_ref().map(AsRef::as_ref)
+    }
 
-    // pub fn reset_user(&mut self) {
-    //     self.user = None;
-    // }
+    pub fn reset_user(&mut self) {
+        self.user = None;
+    }
 
     // pub fn set_attach_stdin(&mut self, attach_stdin: bool) {
     //     self.attach_stdin = Some(attach_stdin);
@@ -427,18 +427,18 @@ impl ContainerCreateBody {
         self.volumes = None;
     }
 
-    // pub fn set_working_dir(&mut self, working_dir: String) {
-    //     self.working_dir = Some(working_dir);
-    // }
+    pub fn set_working_dir(&mut self, working_dir: String) {
+        self.working_dir = Some(working_dir);
+    }
 
-    // pub fn with_working_dir(mut self, working_dir: String) -> Self {
-    //     self.working_dir = Some(working_dir);
-    //     self
-    // }
+    pub fn with_working_dir(mut self, working_dir: String) -> Self {
+        self.working_dir = Some(working_dir);
+        self
+    }
 
-    // pub fn working_dir(&self) -> Option<&str> {
-    //     self.working_dir.as_ref().map(AsRef::as_ref)
-    // }
+    pub fn working_dir(&self) -> Option<&str> {
+        self.working_dir.as_ref().map(AsRef::as_ref)
+    }
 
     pub fn set_entrypoint(&mut self, entrypoint: Vec<String>) {
         self.entrypoint = Some(entrypoint);
@@ -457,9 +457,9 @@ impl ContainerCreateBody {
         self.entrypoint = None;
     }
 
-    // pub fn reset_working_dir(&mut self) {
-    //     self.working_dir = None;
-    // }
+    pub fn reset_working_dir(&mut self) {
+        self.working_dir = None;
+    }
 
     // pub fn set_network_disabled(&mut self, network_disabled: bool) {
     //     self.network_disabled = Some(network_disabled);
@@ -529,39 +529,39 @@ impl ContainerCreateBody {
         self.labels = None;
     }
 
-    // pub fn set_stop_signal(&mut self, stop_signal: String) {
-    //     self.stop_signal = Some(stop_signal);
-    // }
+    pub fn set_stop_signal(&mut self, stop_signal: String) {
+        self.stop_signal = Some(stop_signal);
+    }
 
-    // pub fn with_stop_signal(mut self, stop_signal: String) -> Self {
-    //     self.stop_signal = Some(stop_signal);
-    //     self
-    // }
+    pub fn with_stop_signal(mut self, stop_signal: String) -> Self {
+        self.stop_signal = Some(stop_signal);
+        self
+    }
 
-    // pub fn stop_signal(&self) -> Option<&str> {
-    //     self.stop_signal.as_ref().map(AsRef::as_ref)
-    // }
+    pub fn stop_signal(&self) -> Option<&str> {
+        self.stop_signal.as_ref().map(AsRef::as_ref)
+    }
 
-    // pub fn reset_stop_signal(&mut self) {
-    //     self.stop_signal = None;
-    // }
+    pub fn reset_stop_signal(&mut self) {
+        self.stop_signal = None;
+    }
 
-    // pub fn set_stop_timeout(&mut self, stop_timeout: i32) {
-    //     self.stop_timeout = Some(stop_timeout);
-    // }
+    pub fn set_stop_timeout(&mut self, stop_timeout: i32) {
+        self.stop_timeout = Some(stop_timeout);
+    }
 
-    // pub fn with_stop_timeout(mut self, stop_timeout: i32) -> Self {
-    //     self.stop_timeout = Some(stop_timeout);
-    //     self
-    // }
+    pub fn with_stop_timeout(mut self, stop_timeout: i32) -> Self {
+        self.stop_timeout = Some(stop_timeout);
+        self
+    }
 
-    // pub fn stop_timeout(&self) -> Option<i32> {
-    //     self.stop_timeout
-    // }
+    pub fn stop_timeout(&self) -> Option<i32> {
+        self.stop_timeout
+    }
 
-    // pub fn reset_stop_timeout(&mut self) {
-    //     self.stop_timeout = None;
-    // }
+    pub fn reset_stop_timeout(&mut self) {
+        self.stop_timeout = None;
+    }
 
     // pub fn set_shell(&mut self, shell: Vec<String>) {
     //     self.shell = Some(shell);
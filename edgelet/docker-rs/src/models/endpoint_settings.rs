@@ -40,8 +40,8 @@ pub struct EndpointSettings {
     // ipam_config: Option<crate::models::EndpointIpamConfig>,
     // #[serde(rename = "Links", skip_serializing_if = "Option::is_none")]
     // links: Option<Vec<String>>,
-    // #[serde(rename = "Aliases", skip_serializing_if = "Option::is_none")]
-    // aliases: Option<Vec<String>>,
+    #[serde(rename = "Aliases", skip_serializing_if = "Option::is_none")]
+    aliases: Option<Vec<String>>,
     /// Unique ID of the network.
     #[serde(rename = "NetworkID", skip_serializing_if = "Option::is_none")]
     network_id: Option<String>,
@@ -51,18 +51,18 @@ pub struct EndpointSettings {
     // /// Gateway address for this network.
     // #[serde(rename = "Gateway", skip_serializing_if = "Option::is_none")]
     // gateway: Option<String>,
-    // /// IPv4 address.
-    // #[serde(rename = "IPAddress", skip_serializing_if = "Option::is_none")]
-    // ip_address: Option<String>,
+    /// IPv4 address.
+    #[serde(rename = "IPAddress", skip_serializing_if = "Option::is_none")]
+    ip_address: Option<String>,
     // /// Mask length of the IPv4 address.
     // #[serde(rename = "IPPrefixLen", skip_serializing_if = "Option::is_none")]
     // ip_prefix_len: Option<i32>,
     // /// IPv6 gateway address.
     // #[serde(rename = "IPv6Gateway", skip_serializing_if = "Option::is_none")]
     // i_pv6_gateway: Option<String>,
-    // /// Global IPv6 address.
-    // #[serde(rename = "GlobalIPv6Address", skip_serializing_if = "Option::is_none")]
-    // global_i_pv6_address: Option<String>,
+    /// Global IPv6 address.
+    #[serde(rename = "GlobalIPv6Address", skip_serializing_if = "Option::is_none")]
+    global_i_pv6_address: Option<String>,
     // /// Mask length of the global IPv6 address.
     // #[serde(
     //     rename = "GlobalIPv6PrefixLen",
@@ -85,14 +85,14 @@ impl EndpointSettings {
         EndpointSettings {
             // ipam_config: None,
             // links: None,
-            // aliases: None,
+            aliases: None,
             network_id: None,
             // endpoint_id: None,
             // gateway: None,
-            // ip_address: None,
+            ip_address: None,
             // ip_prefix_len: None,
             // i_pv6_gateway: None,
-            // global_i_pv6_address: None,
+            global_i_pv6_address: None,
             // global_i_pv6_prefix_len: None,
             // mac_address: None,
             // driver_opts: None,
@@ -134,22 +134,22 @@ impl EndpointSettings {
     //     self.links = None;
     // }
 
-    // pub fn set_aliases(&mut self, aliases: Vec<String>) {
-    //     self.aliases = Some(aliases);
-    // }
+    pub fn set_aliases(&mut self, aliases: Vec<String>) {
+        self.aliases = Some(aliases);
+    }
 
-    // pub fn with_aliases(mut self, aliases: Vec<String>) -> Self {
-    //     self.aliases = Some(aliases);
-    //     self
-    // }
+    pub fn with_aliases(mut self, aliases: Vec<String>) -> Self {
+        self.aliases = Some(aliases);
+        self
+    }
 
-    // pub fn aliases(&self) -> Option<&[String]> {
-    //     self.aliases.as_ref().map(AsRef::as_ref)
-    // }
+    pub fn aliases(&self) -> Option<&[String]> {
+        self.aliases.as_ref().map(AsRef::as_ref)
+    }
 
-    // pub fn reset_aliases(&mut self) {
-    //     self.aliases = None;
-    // }
+    pub fn reset_aliases(&mut self) {
+        self.aliases = None;
+    }
 
     pub fn set_network_id(&mut self, network_id: String) {
         self.network_id = Some(network_id);
@@ -202,22 +202,22 @@ impl EndpointSettings {
     //     self.gateway = None;
     // }
 
-    // pub fn set_ip_address(&mut self, ip_address: String) {
-    //     self.ip_address = Some(ip_address);
-    // }
+    pub fn set_ip_address(&mut self, ip_address: String) {
+        self.ip_address = Some(ip_address);
+    }
 
-    // pub fn with_ip_address(mut self, ip_address: String) -> Self {
-    //     self.ip_address = Some(ip_address);
-    //     self
-    // }
+    pub fn with_ip_address(mut self, ip_address: String) -> Self {
+        self.ip_address = Some(ip_address);
+        self
+    }
 
-    // pub fn ip_address(&self) -> Option<&str> {
-    //     self.ip_address.as_ref().map(AsRef::as_ref)
-    // }
+    pub fn ip_address(&self) -> Option<&str> {
+        self.ip_address.as_ref().map(AsRef::as_ref)
+    }
 
-    // pub fn reset_ip_address(&mut self) {
-    //     self.ip_address = None;
-    // }
+    pub fn reset_ip_address(&mut self) {
+        self.ip_address = None;
+    }
 
     // pub fn set_ip_prefix_len(&mut self, ip_prefix_len: i32) {
     //     self.ip_prefix_len = Some(ip_prefix_len);
@@ -253,22 +253,22 @@ impl EndpointSettings {
     //     self.i_pv6_gateway = None;
     // }
 
-    // pub fn set_global_i_pv6_address(&mut self, global_i_pv6_address: String) {
-    //     self.global_i_pv6_address = Some(global_i_pv6_address);
-    // }
+    pub fn set_global_i_pv6_address(&mut self, global_i_pv6_address: String) {
+        self.global_i_pv6_address = Some(global_i_pv6_address);
+    }
 
-    // pub fn with_global_i_pv6_address(mut self, global_i_pv6_address: String) -> Self {
-    //     self.global_i_pv6_address = Some(global_i_pv6_address);
-    //     self
-    // }
+    pub fn with_global_i_pv6_address(mut self, global_i_pv6_address: String) -> Self {
+        self.global_i_pv6_address = Some(global_i_pv6_address);
+        self
+    }
 
-    // pub fn global_i_pv6_address(&self) -> Option<&str> {
-    //     self.global_i_pv6_address.as_ref().map(AsRef::as_ref)
-    // }
+    pub fn global_i_pv6_address(&self) -> Option<&str> {
+        self.global_i_pv6_address.as_ref().map(AsRef::as_ref)
+    }
 
-    // pub fn reset_global_i_pv6_address(&mut self) {
-    //     self.global_i_pv6_address = None;
-    // }
+    pub fn reset_global_i_pv6_address(&mut self) {
+        self.global_i_pv6_address = None;
+    }
 
     // pub fn set_global_i_pv6_prefix_len(&mut self, global_i_pv6_prefix_len: i64) {
     //     self.global_i_pv6_prefix_len = Some(global_i_pv6_prefix_len);
@@ -36,9 +36,9 @@ use serde_json::Value;
 
 #[derive(Debug, serde_derive::Serialize, serde_derive::Deserialize, Clone)]
 pub struct HostConfig {
-    // /// An integer value representing this container's relative CPU weight versus other containers.
-    // #[serde(rename = "CpuShares", skip_serializing_if = "Option::is_none")]
-    // cpu_shares: Option<i32>,
+    /// An integer value representing this container's relative CPU weight versus other containers.
+    #[serde(rename = "CpuShares", skip_serializing_if = "Option::is_none")]
+    cpu_shares: Option<i32>,
     /// Memory limit in bytes.
     #[serde(rename = "Memory", skip_serializing_if = "Option::is_none")]
     memory: Option<i64>,
@@ -90,9 +90,9 @@ pub struct HostConfig {
     // /// Memory nodes (MEMs) in which to allow execution (0-3, 0,1). Only effective on NUMA systems.
     // #[serde(rename = "CpusetMems", skip_serializing_if = "Option::is_none")]
     // cpuset_mems: Option<String>,
-    // /// A list of devices to add to the container.
-    // #[serde(rename = "Devices", skip_serializing_if = "Option::is_none")]
-    // devices: Option<Vec<crate::models::DeviceMapping>>,
+    /// A list of devices to add to the container.
+    #[serde(rename = "Devices", skip_serializing_if = "Option::is_none")]
+    devices: Option<Vec<crate::models::DeviceMapping>>,
     // /// a list of cgroup rules to apply to the container
     // #[serde(rename = "DeviceCgroupRules", skip_serializing_if = "Option::is_none")]
     // device_cgroup_rules: Option<Vec<String>>,
@@ -105,9 +105,9 @@ pub struct HostConfig {
     // /// Memory soft limit in bytes.
     // #[serde(rename = "MemoryReservation", skip_serializing_if = "Option::is_none")]
     // memory_reservation: Option<i64>,
-    // /// Total memory limit (memory + swap). Set as `-1` to enable unlimited swap.
-    // #[serde(rename = "MemorySwap", skip_serializing_if = "Option::is_none")]
-    // memory_swap: Option<i64>,
+    /// Total memory limit (memory + swap). Set as `-1` to enable unlimited swap.
+    #[serde(rename = "MemorySwap", skip_serializing_if = "Option::is_none")]
+    memory_swap: Option<i64>,
     // /// Tune a container's memory swappiness behavior. Accepts an integer between 0 and 100.
     // #[serde(rename = "MemorySwappiness", skip_serializing_if = "Option::is_none")]
     // memory_swappiness: Option<i64>,
@@ -117,12 +117,12 @@ pub struct HostConfig {
     // /// Disable OOM Killer for the container.
     // #[serde(rename = "OomKillDisable", skip_serializing_if = "Option::is_none")]
     // oom_kill_disable: Option<bool>,
-    // /// Tune a container's pids limit. Set -1 for unlimited.
-    // #[serde(rename = "PidsLimit", skip_serializing_if = "Option::is_none")]
-    // pids_limit: Option<i64>,
-    // /// A list of resource limits to set in the container. For example: `{\"Name\": \"nofile\", \"Soft\": 1024, \"Hard\": 2048}`\"
-    // #[serde(rename = "Ulimits", skip_serializing_if = "Option::is_none")]
-    // ulimits: Option<Vec<crate::models::ResourcesUlimits>>,
+    /// Tune a container's pids limit. Set -1 for unlimited.
+    #[serde(rename = "PidsLimit", skip_serializing_if = "Option::is_none")]
+    pids_limit: Option<i64>,
+    /// A list of resource limits to set in the container. For example: `{\"Name\": \"nofile\", \"Soft\": 1024, \"Hard\": 2048}`\"
+    #[serde(rename = "Ulimits", skip_serializing_if = "Option::is_none")]
+    ulimits: Option<Vec<crate::models::ResourcesUlimits>>,
     // /// The number of usable CPUs (Windows only).  On Windows Server containers, the processor resource controls are mutually exclusive. The order of precedence is `CPUCount` first, then `CPUShares`, and `CPUPercent` last.
     // #[serde(rename = "CpuCount", skip_serializing_if = "Option::is_none")]
     // cpu_count: Option<i64>,
@@ -141,17 +141,17 @@ pub struct HostConfig {
     // /// Path to a file where the container ID is written
     // #[serde(rename = "ContainerIDFile", skip_serializing_if = "Option::is_none")]
     // container_id_file: Option<String>,
-    // #[serde(rename = "LogConfig", skip_serializing_if = "Option::is_none")]
-    // log_config: Option<crate::models::HostConfigLogConfig>,
-    // /// Network mode to use for this container. Supported standard values are: `bridge`, `host`, `none`, and `container:<name|id>`. Any other value is taken as a custom network's name to which this container should connect to.
-    // #[serde(rename = "NetworkMode", skip_serializing_if = "Option::is_none")]
-    // network_mode: Option<String>,
+    #[serde(rename = "LogConfig", skip_serializing_if = "Option::is_none")]
+    log_config: Option<crate::models::HostConfigLogConfig>,
+    /// Network mode to use for this container. Supported standard values are: `bridge`, `host`, `none`, and `container:<name|id>`. Any other value is taken as a custom network's name to which this container should connect to.
+    #[serde(rename = "NetworkMode", skip_serializing_if = "Option::is_none")]
+    network_mode: Option<String>,
     /// A map of exposed container ports and the host port they should map to.
     #[serde(rename = "PortBindings", skip_serializing_if = "Option::is_none")]
     port_bindings:
         Option<::std::collections::HashMap<String, Vec<crate::models::HostConfigPortBindings>>>,
-    // #[serde(rename = "RestartPolicy", skip_serializing_if = "Option::is_none")]
-    // restart_policy: Option<crate::models::RestartPolicy>,
+    #[serde(rename = "RestartPolicy", skip_serializing_if = "Option::is_none")]
+    restart_policy: Option<crate::models::RestartPolicy>,
     // /// Automatically remove the container when the container's process exits. This has no effect if `RestartPolicy` is set.
     // #[serde(rename = "AutoRemove", skip_serializing_if = "Option::is_none")]
     // auto_remove: Option<bool>,
@@ -164,21 +164,21 @@ pub struct HostConfig {
     /// Specification for mounts to be added to the container.
     #[serde(rename = "Mounts", skip_serializing_if = "Option::is_none")]
     mounts: Option<Vec<crate::models::Mount>>,
-    // /// A list of kernel capabilities to add to the container.
-    // #[serde(rename = "CapAdd", skip_serializing_if = "Option::is_none")]
-    // cap_add: Option<Vec<String>>,
-    // /// A list of kernel capabilities to drop from the container.
-    // #[serde(rename = "CapDrop", skip_serializing_if = "Option::is_none")]
-    // cap_drop: Option<Vec<String>>,
-    // /// A list of DNS servers for the container to use.
-    // #[serde(rename = "Dns", skip_serializing_if = "Option::is_none")]
-    // dns: Option<Vec<String>>,
+    /// A list of kernel capabilities to add to the container.
+    #[serde(rename = "CapAdd", skip_serializing_if = "Option::is_none")]
+    cap_add: Option<Vec<String>>,
+    /// A list of kernel capabilities to drop from the container.
+    #[serde(rename = "CapDrop", skip_serializing_if = "Option::is_none")]
+    cap_drop: Option<Vec<String>>,
+    /// A list of DNS servers for the container to use.
+    #[serde(rename = "Dns", skip_serializing_if = "Option::is_none")]
+    dns: Option<Vec<String>>,
     // /// A list of DNS options.
     // #[serde(rename = "DnsOptions", skip_serializing_if = "Option::is_none")]
     // dns_options: Option<Vec<String>>,
-    // /// A list of DNS search domains.
-    // #[serde(rename = "DnsSearch", skip_serializing_if = "Option::is_none")]
-    // dns_search: Option<Vec<String>>,
+    /// A list of DNS search domains.
+    #[serde(rename = "DnsSearch", skip_serializing_if = "Option::is_none")]
+    dns_search: Option<Vec<String>>,
     // /// A list of hostnames/IP mappings to add to the container's `/etc/hosts` file. Specified in the form `[\"hostname:IP\"]`.
     // #[serde(rename = "ExtraHosts", skip_serializing_if = "Option::is_none")]
     // extra_hosts: Option<Vec<String>>,
@@ -206,18 +206,18 @@ pub struct HostConfig {
     // /// Allocates a random host port for all of a container's exposed ports.
     // #[serde(rename = "PublishAllPorts", skip_serializing_if = "Option::is_none")]
     // publish_all_ports: Option<bool>,
-    // /// Mount the container's root filesystem as read only.
-    // #[serde(rename = "ReadonlyRootfs", skip_serializing_if = "Option::is_none")]
-    // readonly_rootfs: Option<bool>,
-    // /// A list of string values to customize labels for MLS systems, such as SELinux.
-    // #[serde(rename = "SecurityOpt", skip_serializing_if = "Option::is_none")]
-    // security_opt: Option<Vec<String>>,
+    /// Mount the container's root filesystem as read only.
+    #[serde(rename = "ReadonlyRootfs", skip_serializing_if = "Option::is_none")]
+    readonly_rootfs: Option<bool>,
+    /// A list of string values to customize labels for MLS systems, such as SELinux.
+    #[serde(rename = "SecurityOpt", skip_serializing_if = "Option::is_none")]
+    security_opt: Option<Vec<String>>,
     // /// Storage driver options for this container, in the form `{\"size\": \"120G\"}`.
     // #[serde(rename = "StorageOpt", skip_serializing_if = "Option::is_none")]
     // storage_opt: Option<::std::collections::HashMap<String, String>>,
-    // /// A map of container directories which should be replaced by tmpfs mounts, and their corresponding mount options. For example: `{ \"/run\": \"rw,noexec,nosuid,size=65536k\" }`.
-    // #[serde(rename = "Tmpfs", skip_serializing_if = "Option::is_none")]
-    // tmpfs: Option<::std::collections::HashMap<String, String>>,
+    /// A map of container directories which should be replaced by tmpfs mounts, and their corresponding mount options. For example: `{ \"/run\": \"rw,noexec,nosuid,size=65536k\" }`.
+    #[serde(rename = "Tmpfs", skip_serializing_if = "Option::is_none")]
+    tmpfs: Option<::std::collections::HashMap<String, String>>,
     // /// UTS namespace to use for the container.
     // #[serde(rename = "UTSMode", skip_serializing_if = "Option::is_none")]
     // uts_mode: Option<String>,
@@ -230,9 +230,9 @@ pub struct HostConfig {
     // /// A list of kernel parameters (sysctls) to set in the container. For example: `{\"net.ipv4.ip_forward\": \"1\"}`
     // #[serde(rename = "Sysctls", skip_serializing_if = "Option::is_none")]
     // sysctls: Option<::std::collections::HashMap<String, String>>,
-    // /// Runtime to use with this container.
-    // #[serde(rename = "Runtime", skip_serializing_if = "Option::is_none")]
-    // runtime: Option<String>,
+    /// Runtime to use with this container.
+    #[serde(rename = "Runtime", skip_serializing_if = "Option::is_none")]
+    runtime: Option<String>,
     // /// Initial console size, as an `[height, width]` array. (Windows only)
     // #[serde(rename = "ConsoleSize", skip_serializing_if = "Option::is_none")]
     // console_size: Option<Vec<i32>>,
@@ -247,7 +247,7 @@ impl HostConfig {
     /// Container configuration that depends on the host we are running on
     pub fn new() -> Self {
         HostConfig {
-            // cpu_shares: None,
+            cpu_shares: None,
             memory: None,
             // cgroup_parent: None,
             // blkio_weight: None,
@@ -262,36 +262,36 @@ impl HostConfig {
             // cpu_realtime_runtime: None,
             // cpuset_cpus: None,
             // cpuset_mems: None,
-            // devices: None,
+            devices: None,
             // device_cgroup_rules: None,
             // disk_quota: None,
             // kernel_memory: None,
             // memory_reservation: None,
-            // memory_swap: None,
+            memory_swap: None,
             // memory_swappiness: None,
             // nano_cp_us: None,
             // oom_kill_disable: None,
-            // pids_limit: None,
-            // ulimits: None,
+            pids_limit: None,
+            ulimits: None,
             // cpu_count: None,
             // cpu_percent: None,
             // io_maximum_i_ops: None,
             // io_maximum_bandwidth: None,
             binds: None,
             // container_id_file: None,
-            // log_config: None,
-            // network_mode: None,
+            log_config: None,
+            network_mode: None,
             port_bindings: None,
-            // restart_policy: None,
+            restart_policy: None,
             // auto_remove: None,
             // volume_driver: None,
             // volumes_from: None,
             mounts: None,
-            // cap_add: None,
-            // cap_drop: None,
-            // dns: None,
+            cap_add: None,
+            cap_drop: None,
+            dns: None,
             // dns_options: None,
-            // dns_search: None,
+            dns_search: None,
             // extra_hosts: None,
             // group_add: None,
             // ipc_mode: None,
@@ -301,37 +301,37 @@ impl HostConfig {
             // pid_mode: None,
             privileged: None,
             // publish_all_ports: None,
-            // readonly_rootfs: None,
-            // security_opt: None,
+            readonly_rootfs: None,
+            security_opt: None,
             // storage_opt: None,
-            // tmpfs: None,
+            tmpfs: None,
             // uts_mode: None,
             // userns_mode: None,
             // shm_size: None,
             // sysctls: None,
-            // runtime: None,
+            runtime: None,
             // console_size: None,
             // isolation: None,
             other_properties: Default::default(),
         }
     }
 
-    // pub fn set_cpu_shares(&mut self, cpu_shares: i32) {
-    //     self.cpu_shares = Some(cpu_shares);
-    // }
+    pub fn set_cpu_shares(&mut self, cpu_shares: i32) {
+        self.cpu_shares = Some(cpu_shares);
+    }
 
-    // pub fn with_cpu_shares(mut self, cpu_shares: i32) -> Self {
-    //     self.cpu_shares = Some(cpu_shares);
-    //     self
-    // }
+    pub fn with_cpu_shares(mut self, cpu_shares: i32) -> Self {
+        self.cpu_shares = Some(cpu_shares);
+        self
+    }
 
-    // pub fn cpu_shares(&self) -> Option<i32> {
-    //     self.cpu_shares
-    // }
+    pub fn cpu_shares(&self) -> Option<i32> {
+        self.cpu_shares
+    }
 
-    // pub fn reset_cpu_shares(&mut self) {
-    //     self.cpu_shares = None;
-    // }
+    pub fn reset_cpu_shares(&mut self) {
+        self.cpu_shares = None;
+    }
 
     pub fn set_memory(&mut self, memory: i64) {
         self.memory = Some(memory);
@@ -601,22 +601,22 @@ impl HostConfig {
     //     self.cpuset_mems = None;
     // }
 
-    // pub fn set_devices(&mut self, devices: Vec<crate::models::DeviceMapping>) {
-    //     self.devices = Some(devices);
-    // }
+    pub fn set_devices(&mut self, devices: Vec<crate::models::DeviceMapping>) {
+        self.devices = Some(devices);
+    }
 
-    // pub fn with_devices(mut self, devices: Vec<crate::models::DeviceMapping>) -> Self {
-    //     self.devices = Some(devices);
-    //     self
-    // }
+    pub fn with_devices(mut self, devices: Vec<crate::models::DeviceMapping>) -> Self {
+        self.devices = Some(devices);
+        self
+    }
 
-    // pub fn devices(&self) -> Option<&[crate::models::DeviceMapping]> {
-    //     self.devices.as_ref().map(AsRef::as_ref)
-    // }
+    pub fn devices(&self) -> Option<&[crate::models::DeviceMapping]> {
+        self.devices.as_ref().map(AsRef::as_ref)
+    }
 
-    // pub fn reset_devices(&mut self) {
-    //     self.devices = None;
-    // }
+    pub fn reset_devices(&mut self) {
+        self.devices = None;
+    }
 
     // pub fn set_device_cgroup_rules(&mut self, device_cgroup_rules: Vec<String>) {
     //     self.device_cgroup_rules = Some(device_cgroup_rules);
@@ -686,22 +686,22 @@ impl HostConfig {
     //     self.memory_reservation = None;
     // }
 
-    // pub fn set_memory_swap(&mut self, memory_swap: i64) {
-    //     self.memory_swap = Some(memory_swap);
-    // }
+    pub fn set_memory_swap(&mut self, memory_swap: i64) {
+        self.memory_swap = Some(memory_swap);
+    }
 
-    // pub fn with_memory_swap(mut self, memory_swap: i64) -> Self {
-    //     self.memory_swap = Some(memory_swap);
-    //     self
-    // }
+    pub fn with_memory_swap(mut self, memory_swap: i64) -> Self {
+        self.memory_swap = Some(memory_swap);
+        self
+    }
 
-    // pub fn memory_swap(&self) -> Option<i64> {
-    //     self.memory_swap
-    // }
+    pub fn memory_swap(&self) -> Option<i64> {
+        self.memory_swap
+    }
 
-    // pub fn reset_memory_swap(&mut self) {
-    //     self.memory_swap = None;
-    // }
+    pub fn reset_memory_swap(&mut self) {
+        self.memory_swap = None;
+    }
 
     // pub fn set_memory_swappiness(&mut self, memory_swappiness: i64) {
     //     self.memory_swappiness = Some(memory_swappiness);
@@ -754,39 +754,39 @@ impl HostConfig {
     //     self.oom_kill_disable = None;
     // }
 
-    // pub fn set_pids_limit(&mut self, pids_limit: i64) {
-    //     self.pids_limit = Some(pids_limit);
-    // }
+    pub fn set_pids_limit(&mut self, pids_limit: i64) {
+        self.pids_limit = Some(pids_limit);
+    }
 
-    // pub fn with_pids_limit(mut self, pids_limit: i64) -> Self {
-    //     self.pids_limit = Some(pids_limit);
-    //     self
-    // }
+    pub fn with_pids_limit(mut self, pids_limit: i64) -> Self {
+        self.pids_limit = Some(pids_limit);
+        self
+    }
 
-    // pub fn pids_limit(&self) -> Option<i64> {
-    //     self.pids_limit
-    // }
+    pub fn pids_limit(&self) -> Option<i64> {
+        self.pids_limit
+    }
 
-    // pub fn reset_pids_limit(&mut self) {
-    //     self.pids_limit = None;
-    // }
+    pub fn reset_pids_limit(&mut self) {
+        self.pids_limit = None;
+    }
 
-    // pub fn set_ulimits(&mut self, ulimits: Vec<crate::models::ResourcesUlimits>) {
-    //     self.ulimits = Some(ulimits);
-    // }
+    pub fn set_ulimits(&mut self, ulimits: Vec<crate::models::ResourcesUlimits>) {
+        self.ulimits = Some(ulimits);
+    }
 
-    // pub fn with_ulimits(mut self, ulimits: Vec<crate::models::ResourcesUlimits>) -> Self {
-    //     self.ulimits = Some(ulimits);
-    //     self
-    // }
+    pub fn with_ulimits(mut self, ulimits: Vec<crate::models::ResourcesUlimits>) -> Self {
+        self.ulimits = Some(ulimits);
+        self
+    }
 
-    // pub fn ulimits(&self) -> Option<&[crate::models::ResourcesUlimits]> {
-    //     self.ulimits.as_ref().map(AsRef::as_ref)
-    // }
+    pub fn ulimits(&self) -> Option<&[crate::models::ResourcesUlimits]> {
+        self.ulimits.as_ref().map(AsRef::as_ref)
+    }
 
-    // pub fn reset_ulimits(&mut self) {
-    //     self.ulimits = None;
-    // }
+    pub fn reset_ulimits(&mut self) {
+        self.ulimits = None;
+    }
 
     // pub fn set_cpu_count(&mut self, cpu_count: i64) {
     //     self.cpu_count = Some(cpu_count);
@@ -890,39 +890,39 @@ impl HostConfig {
     //     self.container_id_file = None;
     // }
 
-    // pub fn set_log_config(&mut self, log_config: crate::models::HostConfigLogConfig) {
-    //     self.log_config = Some(log_config);
-    // }
+    pub fn set_log_config(&mut self, log_config: crate::models::HostConfigLogConfig) {
+        self.log_config = Some(log_config);
+    }
 
-    // pub fn with_log_config(mut self, log_config: crate::models::HostConfigLogConfig) -> Self {
-    //     self.log_config = Some(log_config);
-    //     self
-    // }
+    pub fn with_log_config(mut self, log_config: crate::models::HostConfigLogConfig) -> Self {
+        self.log_config = Some(log_config);
+        self
+    }
 
-    // pub fn log_config(&self) -> Option<&crate::models::HostConfigLogConfig> {
-    //     self.log_config.as_ref()
-    // }
+    pub fn log_config(&self) -> Option<&crate::models::HostConfigLogConfig> {
+        self.log_config.as_ref()
+    }
 
-    // pub fn reset_log_config(&mut self) {
-    //     self.log_config = None;
-    // }
+    pub fn reset_log_config(&mut self) {
+        self.log_config = None;
+    }
 
-    // pub fn set_network_mode(&mut self, network_mode: String) {
-    //     self.network_mode = Some(network_mode);
-    // }
+    pub fn set_network_mode(&mut self, network_mode: String) {
+        self.network_mode = Some(network_mode);
+    }
 
-    // pub fn with_network_mode(mut self, network_mode: String) -> Self {
-    //     self.network_mode = Some(network_mode);
-    //     self
-    // }
+    pub fn with_network_mode(mut self, network_mode: String) -> Self {
+        self.network_mode = Some(network_mode);
+        self
+    }
 
-    // pub fn network_mode(&self) -> Option<&str> {
-    //     self.network_mode.as_ref().map(AsRef::as_ref)
-    // }
+    pub fn network_mode(&self) -> Option<&str> {
+        self.network_mode.as_ref().map(AsRef::as_ref)
+    }
 
-    // pub fn reset_network_mode(&mut self) {
-    //     self.network_mode = None;
-    // }
+    pub fn reset_network_mode(&mut self) {
+        self.network_mode = None;
+    }
 
     pub fn set_port_bindings(
         &mut self,
@@ -956,22 +956,22 @@ impl HostConfig {
         self.port_bindings = None;
     }
 
-    // pub fn set_restart_policy(&mut self, restart_policy: crate::models::RestartPolicy) {
-    //     self.restart_policy = Some(restart_policy);
-    // }
+    pub fn set_restart_policy(&mut self, restart_policy: crate::models::RestartPolicy) {
+        self.restart_policy = Some(restart_policy);
+    }
 
-    // pub fn with_restart_policy(mut self, restart_policy: crate::models::RestartPolicy) -> Self {
-    //     self.restart_policy = Some(restart_policy);
-    //     self
-    // }
+    pub fn with_restart_policy(mut self, restart_policy: crate::models::RestartPolicy) -> Self {
+        self.restart_policy = Some(restart_policy);
+        self
+    }
 
-    // pub fn restart_policy(&self) -> Option<&crate::models::RestartPolicy> {
-    //     self.restart_policy.as_ref()
-    // }
+    pub fn restart_policy(&self) -> Option<&crate::models::RestartPolicy> {
+        self.restart_policy.as_ref()
+    }
 
-    // pub fn reset_restart_policy(&mut self) {
-    //     self.restart_policy = None;
-    // }
+    pub fn reset_restart_policy(&mut self) {
+        self.restart_policy = None;
+    }
 
     // pub fn set_auto_remove(&mut self, auto_remove: bool) {
     //     self.auto_remove = Some(auto_remove);
@@ -1041,56 +1041,56 @@ impl HostConfig {
         self.mounts = None;
     }
 
-    // pub fn set_cap_add(&mut self, cap_add: Vec<String>) {
-    //     self.cap_add = Some(cap_add);
-    // }
+    pub fn set_cap_add(&mut self, cap_add: Vec<String>) {
+        self.cap_add = Some(cap_add);
+    }
 
-    // pub fn with_cap_add(mut self, cap_add: Vec<String>) -> Self {
-    //     self.cap_add = Some(cap_add);
-    //     self
-    // }
+    pub fn with_cap_add(mut self, cap_add: Vec<String>) -> Self {
+        self.cap_add = Some(cap_add);
+        self
+    }
 
-    // pub fn cap_add(&self) -> Option<&[String]> {
-    //     self.cap_add.as_ref().map(AsRef::as_ref)
-    // }
+    pub fn cap_add(&self) -> Option<&[String]> {
+        self.cap_add.as_ref().map(AsRef::as_ref)
+    }
 
-    // pub fn reset_cap_add(&mut self) {
-    //     self.cap_add = None;
-    // }
+    pub fn reset_cap_add(&mut self) {
+        self.cap_add = None;
+    }
 
-    // pub fn set_cap_drop(&mut self, cap_drop: Vec<String>) {
-    //     self.cap_drop = Some(cap_drop);
-    // }
+    pub fn set_cap_drop(&mut self, cap_drop: Vec<String>) {
+        self.cap_drop = Some(cap_drop);
+    }
 
-    // pub fn with_cap_drop(mut self, cap_drop: Vec<String>) -> Self {
-    //     self.cap_drop = Some(cap_drop);
-    //     self
-    // }
+    pub fn with_cap_drop(mut self, cap_drop: Vec<String>) -> Self {
+        self.cap_drop = Some(cap_drop);
+        self
+    }
 
-    // pub fn cap_drop(&self) -> Option<&[String]> {
-    //     self.cap_drop.as_ref().map(AsRef::as_ref)
-    // }
+    pub fn cap_drop(&self) -> Option<&[String]> {
+        self.cap_drop.as_ref().map(AsRef::as_ref)
+    }
 
-    // pub fn reset_cap_drop(&mut self) {
-    //     self.cap_drop = None;
-    // }
+    pub fn reset_cap_drop(&mut self) {
+        self.cap_drop = None;
+    }
 
-    // pub fn set_dns(&mut self, dns: Vec<String>) {
-    //     self.dns = Some(dns);
-    // }
+    pub fn set_dns(&mut self, dns: Vec<String>) {
+        self.dns = Some(dns);
+    }
 
-    // pub fn with_dns(mut self, dns: Vec<String>) -> Self {
-    //     self.dns = Some(dns);
-    //     self
-    // }
+    pub fn with_dns(mut self, dns: Vec<String>) -> Self {
+        self.dns = Some(dns);
+        self
+    }
 
-    // pub fn dns(&self) -> Option<&[String]> {
-    //     self.dns.as_ref().map(AsRef::as_ref)
-    // }
+    pub fn dns(&self) -> Option<&[String]> {
+        self.dns.as_ref().map(AsRef::as_ref)
+    }
 
-    // pub fn reset_dns(&mut self) {
-    //     self.dns = None;
-    // }
+    pub fn reset_dns(&mut self) {
+        self.dns = None;
+    }
 
     // pub fn set_dns_options(&mut self, dns_options: Vec<String>) {
     //     self.dns_options = Some(dns_options);
@@ -1109,22 +1109,22 @@ impl HostConfig {
     //     self.dns_options = None;
     // }
 
-    // pub fn set_dns_search(&mut self, dns_search: Vec<String>) {
-    //     self.dns_search = Some(dns_search);
-    // }
+    pub fn set_dns_search(&mut self, dns_search: Vec<String>) {
+        self.dns_search = Some(dns_search);
+    }
 
-    // pub fn with_dns_search(mut self, dns_search: Vec<String>) -> Self {
-    //     self.dns_search = Some(dns_search);
-    //     self
-    // }
+    pub fn with_dns_search(mut self, dns_search: Vec<String>) -> Self {
+        self.dns_search = Some(dns_search);
+        self
+    }
 
-    // pub fn dns_search(&self) -> Option<&[String]> {
-    //     self.dns_search.as_ref().map(AsRef::as_ref)
-    // }
+    pub fn dns_search(&self) -> Option<&[String]> {
+        self.dns_search.as_ref().map(AsRef::as_ref)
+    }
 
-    // pub fn reset_dns_search(&mut self) {
-    //     self.dns_search = None;
-    // }
+    pub fn reset_dns_search(&mut self) {
+        self.dns_search = None;
+    }
 
     // pub fn set_extra_hosts(&mut self, extra_hosts: Vec<String>) {
     //     self.extra_hosts = Some(extra_hosts);
@@ -1279,39 +1279,39 @@ impl HostConfig {
     //     self.publish_all_ports = None;
     // }
 
-    // pub fn set_readonly_rootfs(&mut self, readonly_rootfs: bool) {
-    //     self.readonly_rootfs = Some(readonly_rootfs);
-    // }
+    pub fn set_readonly_rootfs(&mut self, readonly_rootfs: bool) {
+        self.readonly_rootfs = Some(readonly_rootfs);
+    }
 
-    // pub fn with_readonly_rootfs(mut self, readonly_rootfs: bool) -> Self {
-    //     self.readonly_rootfs = Some(readonly_rootfs);
-    //     self
-    // }
+    pub fn with_readonly_rootfs(mut self, readonly_rootfs: bool) -> Self {
+        self.readonly_rootfs = Some(readonly_rootfs);
+        self
+    }
 
-    // pub fn readonly_rootfs(&self) -> Option<&bool> {
-    //     self.readonly_rootfs.as_ref()
-    // }
+    pub fn readonly_rootfs(&self) -> Option<&bool> {
+        self.readonly_rootfs.as_ref()
+    }
 
-    // pub fn reset_readonly_rootfs(&mut self) {
-    //     self.readonly_rootfs = None;
-    // }
+    pub fn reset_readonly_rootfs(&mut self) {
+        self.readonly_rootfs = None;
+    }
 
-    // pub fn set_security_opt(&mut self, security_opt: Vec<String>) {
-    //     self.security_opt = Some(security_opt);
-    // }
+    pub fn set_security_opt(&mut self, security_opt: Vec<String>) {
+        self.security_opt = Some(security_opt);
+    }
 
-    // pub fn with_security_opt(mut self, security_opt: Vec<String>) -> Self {
-    //     self.security_opt = Some(security_opt);
-    //     self
-    // }
+    pub fn with_security_opt(mut self, security_opt: Vec<String>) -> Self {
+        self.security_opt = Some(security_opt);
+        self
+    }
 
-    // pub fn security_opt(&self) -> Option<&[String]> {
-    //     self.security_opt.as_ref().map(AsRef::as_ref)
-    // }
+    pub fn security_opt(&self) -> Option<&[String]> {
+        self.security_opt.as_ref().map(AsRef::as_ref)
+    }
 
-    // pub fn reset_security_opt(&mut self) {
-    //     self.security_opt = None;
-    // }
+    pub fn reset_security_opt(&mut self) {
+        self.security_opt = None;
+    }
 
     // pub fn set_storage_opt(&mut self, storage_opt: ::std::collections::HashMap<String, String>) {
     //     self.storage_opt = Some(storage_opt);
@@ -1333,22 +1333,22 @@ impl HostConfig {
     //     self.storage_opt = None;
     // }
 
-    // pub fn set_tmpfs(&mut self, tmpfs: ::std::collections::HashMap<String, String>) {
-    //     self.tmpfs = Some(tmpfs);
-    // }
+    pub fn set_tmpfs(&mut self, tmpfs: ::std::collections::HashMap<String, String>) {
+        self.tmpfs = Some(tmpfs);
+    }
 
-    // pub fn with_tmpfs(mut self, tmpfs: ::std::collections::HashMap<String, String>) -> Self {
-    //     self.tmpfs = Some(tmpfs);
-    //     self
-    // }
+    pub fn with_tmpfs(mut self, tmpfs: ::std::collections::HashMap<String, String>) -> Self {
+        self.tmpfs = Some(tmpfs);
+        self
+    }
 
-    // pub fn tmpfs(&self) -> Option<&::std::collections::HashMap<String, String>> {
-    //     self.tmpfs.as_ref()
-    // }
+    pub fn tmpfs(&self) -> Option<&::std::collections::HashMap<String, String>> {
+        self.tmpfs.as_ref()
+    }
 
-    // pub fn reset_tmpfs(&mut self) {
-    //     self.tmpfs = None;
-    // }
+    pub fn reset_tmpfs(&mut self) {
+        self.tmpfs = None;
+    }
 
     // pub fn set_uts_mode(&mut self, uts_mode: String) {
     //     self.uts_mode = Some(uts_mode);
@@ -1418,22 +1418,22 @@ impl HostConfig {
     //     self.sysctls = None;
     // }
 
-    // pub fn set_runtime(&mut self, runtime: String) {
-    //     self.runtime = Some(runtime);
-    // }
+    pub fn set_runtime(&mut self, runtime: String) {
+        self.runtime = Some(runtime);
+    }
 
-    // pub fn with_runtime(mut self, runtime: String) -> Self {
-    //     self.runtime = Some(runtime);
-    //     self
-    // }
+    pub fn with_runtime(mut self, runtime: String) -> Self {
+        self.runtime = Some(runtime);
+        self
+    }
 
-    // pub fn runtime(&self) -> Option<&str> {
-    //     self.runtime.as_ref().map(AsRef::as_ref)
-    // }
+    pub fn runtime(&self) -> Option<&str> {
+        self.runtime.as_ref().map(AsRef::as_ref)
+    }
 
-    // pub fn reset_runtime(&mut self) {
-    //     self.runtime = None;
-    // }
+    pub fn reset_runtime(&mut self) {
+        self.runtime = None;
+    }
 
     // pub fn set_console_size(&mut self, console_size: Vec<i32>) {
     //     self.console_size = Some(console_size);
@@ -36,8 +36,10 @@ pub struct NetworkSettings {
         skip_serializing_if = "Option::is_none"
     )]
     link_local_i_pv6_prefix_len: Option<i32>,
+    /// The container's ports by `<port>/<protocol>`, with the host addresses
+    /// that they're mapped to. A port that isn't published maps to `null`.
     #[serde(rename = "Ports", skip_serializing_if = "Option::is_none")]
-    ports: Option<crate::models::PortMap>,
+    ports: Option<::std::collections::HashMap<String, Option<Vec<crate::models::PortBinding>>>>,
     /// SandboxKey identifies the sandbox
     #[serde(rename = "SandboxKey", skip_serializing_if = "Option::is_none")]
     sandbox_key: Option<String>,
@@ -195,16 +197,24 @@ impl NetworkSettings {
         self.link_local_i_pv6_prefix_len = None;
     }
 
-    pub fn set_ports(&mut self, ports: crate::models::PortMap) {
+    pub fn set_ports(
+        &mut self,
+        ports: ::std::collections::HashMap<String, Option<Vec<crate::models::PortBinding>>>,
+    ) {
         self.ports = Some(ports);
     }
 
-    pub fn with_ports(mut self, ports: crate::models::PortMap) -> Self {
+    pub fn with_ports(
+        mut self,
+        ports: ::std::collections::HashMap<String, Option<Vec<crate::models::PortBinding>>>,
+    ) -> Self {
         self.ports = Some(ports);
         self
     }
 
-    pub fn ports(&self) -> Option<&crate::models::PortMap> {
+    pub fn ports(
+        &self,
+    ) -> Option<&::std::collections::HashMap<String, Option<Vec<crate::models::PortBinding>>>> {
         self.ports.as_ref()
     }
 
@@ -18,10 +18,10 @@ pub struct ResourcesUlimits {
     name: Option<String>,
     /// Soft limit
     #[serde(rename = "Soft", skip_serializing_if = "Option::is_none")]
-    soft: Option<i32>,
+    soft: Option<i64>,
     /// Hard limit
     #[serde(rename = "Hard", skip_serializing_if = "Option::is_none")]
-    hard: Option<i32>,
+    hard: Option<i64>,
 }
 
 impl ResourcesUlimits {
@@ -50,16 +50,16 @@ impl ResourcesUlimits {
         self.name = None;
     }
 
-    pub fn set_soft(&mut self, soft: i32) {
+    pub fn set_soft(&mut self, soft: i64) {
         self.soft = Some(soft);
     }
 
-    pub fn with_soft(mut self, soft: i32) -> Self {
+    pub fn with_soft(mut self, soft: i64) -> Self {
         self.soft = Some(soft);
         self
     }
 
-    pub fn soft(&self) -> Option<i32> {
+    pub fn soft(&self) -> Option<i64> {
         self.soft
     }
 
@@ -67,16 +67,16 @@ impl ResourcesUlimits {
         self.soft = None;
     }
 
-    pub fn set_hard(&mut self, hard: i32) {
+    pub fn set_hard(&mut self, hard: i64) {
         self.hard = Some(hard);
     }
 
-    pub fn with_hard(mut self, hard: i32) -> Self {
+    pub fn with_hard(mut self, hard: i64) -> Self {
         self.hard = Some(hard);
         self
     }
 
-    pub fn hard(&self) -> Option<i32> {
+    pub fn hard(&self) -> Option<i64> {
         self.hard
     }
 
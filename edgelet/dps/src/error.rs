@@ -47,6 +47,9 @@ pub enum ErrorKind {
     #[fail(display = "DPS registration succeeded but returned an empty response")]
     RegisterWithAuthUnexpectedlySucceeded,
 
+    #[fail(display = "Could not wait to retry the DPS request")]
+    RetryTimer,
+
     #[fail(display = "Symmetric key based registration failed")]
     RegisterWithSymmetricChallengeKey,
 
@@ -14,6 +14,7 @@ pub mod dps;
 pub mod error;
 mod model;
 pub mod registration;
+pub mod retry;
 
 pub use error::{Error, ErrorKind};
 pub use model::{
@@ -21,5 +22,6 @@ pub use model::{
     TpmRegistrationResult, X509CertificateInfo, X509RegistrationResult,
 };
 pub use registration::{DpsClient, DpsTokenSource};
+pub use retry::RetryPolicy;
 
 pub const DPS_API_VERSION: &str = "2018-11-01";
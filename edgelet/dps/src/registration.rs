@@ -26,6 +26,7 @@ use crate::model::{
     DeviceRegistration, DeviceRegistrationResult, RegistrationOperationStatus, TpmAttestation,
     TpmRegistrationResult,
 };
+use crate::retry::{retry, RetryPolicy};
 
 /// This is the interval at which to poll DPS for registration assignment status
 const DPS_ASSIGNMENT_RETRY_INTERVAL_SECS: u64 = 10;
@@ -89,6 +90,7 @@ where
     }
 }
 
+#[derive(Clone)]
 pub enum DpsAuthKind {
     Tpm { ek: Bytes, srk: Bytes },
     SymmetricKey,
@@ -106,6 +108,7 @@ where
     registration_id: String,
     auth: DpsAuthKind,
     key_store: A,
+    retry_policy: RetryPolicy,
 }
 
 impl<C, K, A> DpsClient<C, K, A>
@@ -127,9 +130,15 @@ where
             registration_id,
             auth,
             key_store,
+            retry_policy: RetryPolicy::default(),
         })
     }
 
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
     fn get_tpm_challenge_key(body: &str, key_store: &mut A) -> Result<K, Error> {
         let tpm_challenge: TpmRegistrationResult =
             serde_json::from_str(body).context(ErrorKind::GetTpmChallengeKey)?;
@@ -452,8 +461,19 @@ where
         Box::new(r)
     }
 
+    /// Registers the device with DPS, retrying transient failures according to
+    /// the client's retry policy.
     pub fn register(
         &self,
+    ) -> Box<dyn Future<Item = (String, String, Option<String>), Error = Error> + Send> {
+        let client = self.clone();
+        Box::new(retry(self.retry_policy.clone(), move || {
+            client.register_once()
+        }))
+    }
+
+    fn register_once(
+        &self,
     ) -> Box<dyn Future<Item = (String, String, Option<String>), Error = Error> + Send> {
         let key_store = self.key_store.clone();
         let mut key_store_status = self.key_store.clone();
@@ -568,6 +588,24 @@ where
     }
 }
 
+impl<C, K, A> Clone for DpsClient<C, K, A>
+where
+    C: ClientImpl,
+    K: 'static + Sign + Clone,
+    A: 'static + KeyStore<Key = K> + Activate<Key = K> + Clone,
+{
+    fn clone(&self) -> Self {
+        DpsClient {
+            client: self.client.clone(),
+            scope_id: self.scope_id.clone(),
+            registration_id: self.registration_id.clone(),
+            auth: self.auth.clone(),
+            key_store: self.key_store.clone(),
+            retry_policy: self.retry_policy.clone(),
+        }
+    }
+}
+
 fn get_device_info(
     registration_result: &DeviceRegistrationResult,
 ) -> Result<(String, String, Option<String>), Error> {
@@ -1291,6 +1329,110 @@ mod tests {
             .unwrap();
     }
 
+    fn x509_dps_client<C>(handler: C) -> DpsClient<C, MemoryKey, MemoryKeyStore>
+    where
+        C: 'static + ClientImpl,
+    {
+        let client = Client::new(
+            handler,
+            None,
+            DPS_API_VERSION.to_string(),
+            Url::parse("https://global.azure-devices-provisioning.net/").unwrap(),
+        )
+        .unwrap();
+        DpsClient::new(
+            client,
+            "scope".to_string(),
+            "reg".to_string(),
+            DpsAuthKind::X509,
+            MemoryKeyStore::new(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn server_register_retries_after_throttling() {
+        let registrations = Arc::new(Mutex::new(0));
+        let registrations_inner = registrations.clone();
+        let handler = move |req: Request<Body>| {
+            let status = if req.method() == Method::PUT {
+                let mut registrations = registrations_inner.lock().unwrap();
+                *registrations += 1;
+                if *registrations == 1 {
+                    let response = Response::builder()
+                        .status(StatusCode::TOO_MANY_REQUESTS)
+                        .header(hyper::header::RETRY_AFTER, "1")
+                        .body(Body::empty())
+                        .expect("could not build hyper::Response");
+                    return future::ok(response);
+                }
+
+                RegistrationOperationStatus::new("operation".to_string())
+                    .with_status("assigning".to_string())
+            } else {
+                RegistrationOperationStatus::new("operation".to_string()).with_registration_state(
+                    DeviceRegistrationResult::new()
+                        .with_registration_id("reg".to_string())
+                        .with_status("assigned".to_string())
+                        .with_device_id("device".to_string())
+                        .with_assigned_hub("hub".to_string()),
+                )
+            };
+            future::ok(Response::new(
+                serde_json::to_string(&status).unwrap().into(),
+            ))
+        };
+
+        // backing off would take longer than the policy allows, so this only
+        // succeeds if the client waits for as long as DPS asked it to instead
+        let dps = x509_dps_client(handler).with_retry_policy(
+            RetryPolicy::new(3, Duration::from_secs(10))
+                .with_initial_delay(Duration::from_secs(30)),
+        );
+        let task = dps.register();
+        let (device_id, hub, _) = tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task)
+            .unwrap();
+        assert_eq!("device", device_id);
+        assert_eq!("hub", hub);
+        assert_eq!(2, *registrations.lock().unwrap());
+    }
+
+    #[test]
+    fn server_register_does_not_retry_forbidden() {
+        let registrations = Arc::new(Mutex::new(0));
+        let registrations_inner = registrations.clone();
+        let handler = move |_req: Request<Body>| {
+            *registrations_inner.lock().unwrap() += 1;
+            let response = Response::builder()
+                .status(StatusCode::FORBIDDEN)
+                .body(Body::empty())
+                .expect("could not build hyper::Response");
+            future::ok(response)
+        };
+
+        let dps = x509_dps_client(handler).with_retry_policy(
+            RetryPolicy::new(3, Duration::from_secs(10))
+                .with_initial_delay(Duration::from_millis(1)),
+        );
+        let task = dps.register().then(|result| match result {
+            Ok(_) => panic!("Excepted err got success"),
+            Err(err) => match err.kind() {
+                ErrorKind::RegisterWithX509IdentityCertificate => Ok::<_, Error>(()),
+                _ => panic!(
+                    "Wrong error kind. Expected `RegisterWithX509IdentityCertificate` found {:?}",
+                    err
+                ),
+            },
+        });
+        tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task)
+            .unwrap();
+        assert_eq!(1, *registrations.lock().unwrap());
+    }
+
     #[test]
     fn get_device_info_success() {
         assert_eq!(
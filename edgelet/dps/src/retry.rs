@@ -0,0 +1,278 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::cmp;
+use std::time::{Duration, Instant};
+
+use failure::Fail;
+use futures::future::{self, Either, Loop};
+use futures::Future;
+use hyper::StatusCode;
+use log::{debug, warn};
+use tokio::timer::Delay;
+
+use edgelet_http::{Error as HttpError, ErrorKind as HttpErrorKind};
+
+use crate::error::{Error, ErrorKind};
+
+const DEFAULT_MAX_ATTEMPTS: u32 = 10;
+const DEFAULT_MAX_ELAPSED_SECS: u64 = 600;
+
+const DEFAULT_INITIAL_DELAY_SECS: u64 = 1;
+const DEFAULT_MAX_DELAY_SECS: u64 = 60;
+
+/// How a DPS operation that failed with a transient error is retried.
+///
+/// Retries back off exponentially, starting at the initial delay and doubling
+/// up to the max delay, unless DPS says how long to wait with a `Retry-After`
+/// header. The operation is given up on once it has been attempted
+/// `max_attempts` times or the next attempt would start after `max_elapsed`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    max_elapsed: Duration,
+    initial_delay: Duration,
+    max_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn new(max_attempts: u32, max_elapsed: Duration) -> Self {
+        RetryPolicy {
+            max_attempts: cmp::max(max_attempts, 1),
+            max_elapsed,
+            initial_delay: Duration::from_secs(DEFAULT_INITIAL_DELAY_SECS),
+            max_delay: Duration::from_secs(DEFAULT_MAX_DELAY_SECS),
+        }
+    }
+
+    /// A policy that attempts the operation once.
+    pub fn no_retry() -> Self {
+        RetryPolicy::new(1, Duration::from_secs(0))
+    }
+
+    pub fn with_initial_delay(mut self, initial_delay: Duration) -> Self {
+        self.initial_delay = initial_delay;
+        self
+    }
+
+    pub fn with_max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    pub fn max_elapsed(&self) -> Duration {
+        self.max_elapsed
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        // attempt is 1-based, and the delay doubles after every attempt
+        let factor = 1_u32.checked_shl(attempt - 1).unwrap_or(u32::max_value());
+        self.initial_delay
+            .checked_mul(factor)
+            .map_or(self.max_delay, |delay| cmp::min(delay, self.max_delay))
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy::new(
+            DEFAULT_MAX_ATTEMPTS,
+            Duration::from_secs(DEFAULT_MAX_ELAPSED_SECS),
+        )
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Disposition {
+    Retryable(Option<Duration>),
+    Terminal,
+}
+
+// Network errors, timeouts, throttling and server errors are worth retrying.
+// Anything else, like DPS rejecting the device's credentials, will fail the
+// same way the next time around.
+fn disposition(err: &Error) -> Disposition {
+    let http_err = Fail::iter_chain(err).find_map(|cause| cause.downcast_ref::<HttpError>());
+
+    match http_err {
+        Some(http_err) => match http_err.kind() {
+            HttpErrorKind::HttpWithErrorResponse(status, _) => {
+                if *status == StatusCode::REQUEST_TIMEOUT
+                    || *status == StatusCode::TOO_MANY_REQUESTS
+                    || status.is_server_error()
+                {
+                    Disposition::Retryable(http_err.retry_after())
+                } else {
+                    Disposition::Terminal
+                }
+            }
+            // the request didn't make it to DPS, as opposed to DPS sending
+            // back a response that couldn't be parsed
+            HttpErrorKind::Http
+                if Fail::iter_chain(http_err)
+                    .any(|cause| cause.downcast_ref::<hyper::Error>().is_some()) =>
+            {
+                Disposition::Retryable(None)
+            }
+            _ => Disposition::Terminal,
+        },
+        None => Disposition::Terminal,
+    }
+}
+
+/// Runs `operation` until it succeeds, fails with an error that isn't worth
+/// retrying, or the policy gives up, in which case the last error is returned.
+pub fn retry<F, R, T>(
+    policy: RetryPolicy,
+    operation: F,
+) -> impl Future<Item = T, Error = Error> + Send
+where
+    F: FnMut() -> R + Send,
+    R: Future<Item = T, Error = Error> + Send,
+    T: Send,
+{
+    let start = Instant::now();
+
+    future::loop_fn((operation, 1), move |(mut operation, attempt)| {
+        let policy = policy.clone();
+        operation().then(move |result| match result {
+            Ok(value) => Either::A(future::ok(Loop::Break(value))),
+            Err(err) => {
+                let retry_after = match disposition(&err) {
+                    Disposition::Retryable(retry_after) => retry_after,
+                    Disposition::Terminal => {
+                        debug!("DPS request failed with an error that can't be retried");
+                        return Either::A(future::err(err));
+                    }
+                };
+
+                if attempt >= policy.max_attempts {
+                    warn!("Giving up on DPS request after {} attempts", attempt);
+                    return Either::A(future::err(err));
+                }
+
+                let delay = retry_after.unwrap_or_else(|| policy.backoff(attempt));
+                let next_attempt = Instant::now() + delay;
+                if next_attempt - start > policy.max_elapsed {
+                    warn!(
+                        "Giving up on DPS request after {} attempts in {} seconds",
+                        attempt,
+                        start.elapsed().as_secs()
+                    );
+                    return Either::A(future::err(err));
+                }
+
+                warn!(
+                    "DPS request failed, retrying in {} seconds (attempt {} of {}): {}",
+                    delay.as_secs(),
+                    attempt + 1,
+                    policy.max_attempts,
+                    err
+                );
+                Either::B(
+                    Delay::new(next_attempt)
+                        .map_err(|err| Error::from(err.context(ErrorKind::RetryTimer)))
+                        .map(move |()| Loop::Continue((operation, attempt + 1))),
+                )
+            }
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+
+    fn http_error(status: StatusCode, retry_after: Option<Duration>) -> Error {
+        Error::from(
+            HttpError::http_with_error_response(status, b"")
+                .with_retry_after(retry_after)
+                .context(ErrorKind::RegisterWithAuthUnexpectedlyFailed),
+        )
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_max_delay() {
+        let policy = RetryPolicy::default();
+        assert_eq!(Duration::from_secs(1), policy.backoff(1));
+        assert_eq!(Duration::from_secs(2), policy.backoff(2));
+        assert_eq!(Duration::from_secs(32), policy.backoff(6));
+        assert_eq!(Duration::from_secs(60), policy.backoff(7));
+        assert_eq!(Duration::from_secs(60), policy.backoff(100));
+    }
+
+    #[test]
+    fn disposition_distinguishes_transient_errors() {
+        let retry_after = Some(Duration::from_secs(3));
+        assert_eq!(
+            Disposition::Retryable(retry_after),
+            disposition(&http_error(StatusCode::TOO_MANY_REQUESTS, retry_after))
+        );
+        assert_eq!(
+            Disposition::Retryable(None),
+            disposition(&http_error(StatusCode::SERVICE_UNAVAILABLE, None))
+        );
+        assert_eq!(
+            Disposition::Terminal,
+            disposition(&http_error(StatusCode::UNAUTHORIZED, None))
+        );
+        assert_eq!(
+            Disposition::Terminal,
+            disposition(&http_error(StatusCode::FORBIDDEN, None))
+        );
+        assert_eq!(
+            Disposition::Terminal,
+            disposition(&Error::from(
+                HttpError::from(HttpErrorKind::Http).context(ErrorKind::GetOperationId)
+            ))
+        );
+        assert_eq!(
+            Disposition::Terminal,
+            disposition(&Error::from(ErrorKind::InvalidTpmToken))
+        );
+    }
+
+    #[test]
+    fn gives_up_after_max_attempts() {
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let attempts_inner = attempts.clone();
+        let policy = RetryPolicy::new(3, Duration::from_secs(60))
+            .with_initial_delay(Duration::from_millis(1));
+        let task = retry(policy, move || {
+            attempts_inner.fetch_add(1, Ordering::SeqCst);
+            future::err::<(), _>(http_error(StatusCode::INTERNAL_SERVER_ERROR, None))
+        });
+
+        tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task)
+            .unwrap_err();
+        assert_eq!(3, attempts.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn gives_up_when_retry_after_exceeds_max_elapsed() {
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let attempts_inner = attempts.clone();
+        let policy = RetryPolicy::new(10, Duration::from_secs(5));
+        let task = retry(policy, move || {
+            attempts_inner.fetch_add(1, Ordering::SeqCst);
+            future::err::<(), _>(http_error(
+                StatusCode::TOO_MANY_REQUESTS,
+                Some(Duration::from_secs(30)),
+            ))
+        });
+
+        tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task)
+            .unwrap_err();
+        assert_eq!(1, attempts.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,282 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::{Fail, ResultExt};
+use futures::future::{self, Either};
+use futures::{Future, IntoFuture, Stream};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Client, Method, Request, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use url::form_urlencoded::Serializer;
+use url::percent_encoding::{utf8_percent_encode, PATH_SEGMENT_ENCODE_SET};
+use url::Url;
+
+use edgelet_core::{LogOptions, UrlExt};
+use edgelet_http::{UrlConnector, API_VERSION};
+use management::models::{ErrorResponse, ModuleDetails, ModuleList, ModuleSpec, SystemInfo};
+
+use crate::error::{Error, ErrorKind};
+
+/// A client of the management API. Every request is sent with the latest
+/// `api-version` that this crate was built with.
+#[derive(Clone)]
+pub struct ManagementClient {
+    client: Client<UrlConnector>,
+    scheme: String,
+    base_path: String,
+}
+
+impl ManagementClient {
+    /// A client of the management API at `url`, like the daemon's
+    /// `unix:///var/run/iotedge/mgmt.sock`.
+    pub fn new(url: &Url) -> Result<Self, Error> {
+        let connector = UrlConnector::new(url).context(ErrorKind::InitializeClient)?;
+        let base_path = url.to_base_path().context(ErrorKind::InitializeClient)?;
+        let base_path = base_path
+            .to_str()
+            .ok_or(ErrorKind::InitializeClient)?
+            .to_string();
+
+        Ok(ManagementClient {
+            client: Client::builder().build(connector),
+            scheme: url.scheme().to_string(),
+            base_path,
+        })
+    }
+
+    pub fn list_modules(&self) -> impl Future<Item = Vec<ModuleDetails>, Error = Error> + Send {
+        self.json::<ModuleList>(Method::GET, "/modules", &[], None)
+            .map(|list| list.modules().to_vec())
+    }
+
+    pub fn get_module(
+        &self,
+        name: &str,
+    ) -> impl Future<Item = ModuleDetails, Error = Error> + Send {
+        self.json(Method::GET, &module_path(name, ""), &[], None)
+    }
+
+    pub fn create_module(
+        &self,
+        spec: &ModuleSpec,
+    ) -> impl Future<Item = ModuleDetails, Error = Error> + Send {
+        let path = "/modules";
+        match to_body(path, spec) {
+            Ok(body) => Either::A(self.json(Method::POST, path, &[], Some(body))),
+            Err(err) => Either::B(future::err(err)),
+        }
+    }
+
+    /// Updates the module that `spec` names.
+    pub fn update_module(
+        &self,
+        spec: &ModuleSpec,
+    ) -> impl Future<Item = ModuleDetails, Error = Error> + Send {
+        let path = module_path(spec.name(), "");
+        match to_body(&path, spec) {
+            Ok(body) => Either::A(self.json(Method::PUT, &path, &[], Some(body))),
+            Err(err) => Either::B(future::err(err)),
+        }
+    }
+
+    pub fn delete_module(&self, name: &str) -> impl Future<Item = (), Error = Error> + Send {
+        self.send(Method::DELETE, &module_path(name, ""), &[], None)
+            .map(|_| ())
+    }
+
+    /// Starts the module. A module that is already running isn't an error.
+    pub fn start_module(&self, name: &str) -> impl Future<Item = (), Error = Error> + Send {
+        self.action(&module_path(name, "/start"))
+    }
+
+    /// Stops the module. A module that isn't running isn't an error.
+    pub fn stop_module(&self, name: &str) -> impl Future<Item = (), Error = Error> + Send {
+        self.action(&module_path(name, "/stop"))
+    }
+
+    pub fn restart_module(&self, name: &str) -> impl Future<Item = (), Error = Error> + Send {
+        self.action(&module_path(name, "/restart"))
+    }
+
+    /// Retries a module that the watchdog paused after it kept failing. A
+    /// module that isn't paused isn't an error.
+    pub fn resume_module(&self, name: &str) -> impl Future<Item = (), Error = Error> + Send {
+        self.action(&module_path(name, "/resume"))
+    }
+
+    /// The logs of the module, framed with which of stdout and stderr each
+    /// chunk is from like `edgelet_core::decode_logs` reads them. With
+    /// `follow` the body streams until the module exits or it is dropped.
+    pub fn logs(
+        &self,
+        name: &str,
+        options: &LogOptions,
+    ) -> impl Future<Item = Body, Error = Error> + Send {
+        let mut query = vec![
+            ("follow", options.follow().to_string()),
+            ("tail", options.tail().to_string()),
+            ("since", options.since().to_string()),
+            ("timestamps", options.timestamps().to_string()),
+        ];
+        if let Some(until) = options.until() {
+            query.push(("until", until.to_string()));
+        }
+        self.send(Method::GET, &module_path(name, "/logs"), &query, None)
+            .map(Response::into_body)
+    }
+
+    pub fn system_info(&self) -> impl Future<Item = SystemInfo, Error = Error> + Send {
+        self.json(Method::GET, "/systeminfo", &[], None)
+    }
+
+    /// Saves the modules that are running and leaves them running when the
+    /// daemon stops, so that the daemon that starts next adopts them instead
+    /// of recreating them.
+    pub fn prepare_upgrade(&self) -> impl Future<Item = (), Error = Error> + Send {
+        self.send(Method::POST, "/prepare-upgrade", &[], None)
+            .map(|_| ())
+    }
+
+    /// Resumes supervising the modules if the daemon isn't upgraded or
+    /// restarted after all.
+    pub fn resume_from_upgrade(&self) -> impl Future<Item = (), Error = Error> + Send {
+        self.send(Method::POST, "/resume", &[], None).map(|_| ())
+    }
+
+    // A request that answers `304 Not Modified` when the module already is
+    // the way that it asks for.
+    fn action(&self, path: &str) -> impl Future<Item = (), Error = Error> + Send {
+        self.send(Method::POST, path, &[], None)
+            .then(|result| match result {
+                Ok(_) => Ok(()),
+                Err(err) => match err.kind() {
+                    ErrorKind::Api(StatusCode::NOT_MODIFIED, _) => Ok(()),
+                    _ => Err(err),
+                },
+            })
+    }
+
+    fn json<T>(
+        &self,
+        method: Method,
+        path: &str,
+        query: &[(&str, String)],
+        body: Option<Vec<u8>>,
+    ) -> impl Future<Item = T, Error = Error> + Send
+    where
+        T: DeserializeOwned + Send,
+    {
+        self.send(method, path, query, body)
+            .and_then(|response| {
+                response
+                    .into_body()
+                    .concat2()
+                    .map_err(|err| Error::from(err.context(ErrorKind::Transport)))
+            })
+            .and_then(|body| -> Result<T, Error> {
+                let value = serde_json::from_slice(&body).context(ErrorKind::MalformedResponse)?;
+                Ok(value)
+            })
+    }
+
+    // Sends the request, failing with the message of the error response if
+    // it doesn't succeed.
+    fn send(
+        &self,
+        method: Method,
+        path: &str,
+        query: &[(&str, String)],
+        body: Option<Vec<u8>>,
+    ) -> impl Future<Item = Response<Body>, Error = Error> + Send {
+        let client = self.client.clone();
+        self.request(method, path, query, body)
+            .into_future()
+            .and_then(move |request| {
+                client
+                    .request(request)
+                    .map_err(|err| Error::from(err.context(ErrorKind::Transport)))
+            })
+            .and_then(|response| {
+                let status = response.status();
+                if status.is_success() {
+                    return Either::A(future::ok(response));
+                }
+
+                Either::B(response.into_body().concat2().then(
+                    move |body| -> Result<Response<Body>, Error> {
+                        let message = body
+                            .ok()
+                            .and_then(|body| serde_json::from_slice::<ErrorResponse>(&body).ok())
+                            .map_or_else(
+                                || status.canonical_reason().unwrap_or_default().to_string(),
+                                |error| error.message().clone(),
+                            );
+                        Err(Error::from(ErrorKind::Api(status, message)))
+                    },
+                ))
+            })
+    }
+
+    fn request(
+        &self,
+        method: Method,
+        path: &str,
+        query: &[(&str, String)],
+        body: Option<Vec<u8>>,
+    ) -> Result<Request<Body>, Error> {
+        let query = Serializer::new(String::new())
+            .append_pair("api-version", &API_VERSION.to_string())
+            .extend_pairs(query)
+            .finish();
+        let uri = UrlConnector::build_hyper_uri(
+            &self.scheme,
+            &self.base_path,
+            &format!("{}?{}", path, query),
+        )
+        .with_context(|_| ErrorKind::MalformedRequest(path.to_string()))?;
+
+        let mut request = Request::builder();
+        request.method(method).uri(uri);
+        let body = match body {
+            Some(body) => {
+                request
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(CONTENT_LENGTH, body.len().to_string().as_str());
+                Body::from(body)
+            }
+            None => Body::empty(),
+        };
+        let request = request
+            .body(body)
+            .with_context(|_| ErrorKind::MalformedRequest(path.to_string()))?;
+        Ok(request)
+    }
+}
+
+fn module_path(name: &str, action: &str) -> String {
+    format!(
+        "/modules/{}{}",
+        utf8_percent_encode(name, PATH_SEGMENT_ENCODE_SET),
+        action
+    )
+}
+
+fn to_body<T>(path: &str, value: &T) -> Result<Vec<u8>, Error>
+where
+    T: Serialize,
+{
+    let body = serde_json::to_vec(value)
+        .with_context(|_| ErrorKind::MalformedRequest(path.to_string()))?;
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn module_names_are_encoded() {
+        assert_eq!("/modules/a%20b/start", module_path("a b", "/start"));
+        assert_eq!("/modules/a%2Fb", module_path("a/b", ""));
+    }
+}
@@ -0,0 +1,66 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::fmt::{self, Display};
+
+use failure::{Backtrace, Context, Fail};
+use hyper::StatusCode;
+
+#[derive(Debug)]
+pub struct Error {
+    inner: Context<ErrorKind>,
+}
+
+#[derive(Clone, Debug, Fail, PartialEq)]
+pub enum ErrorKind {
+    /// The API refused the request, with the message of its error response.
+    #[fail(display = "The management API returned {}: {}", _0, _1)]
+    Api(StatusCode, String),
+
+    #[fail(display = "Could not initialize the management client")]
+    InitializeClient,
+
+    #[fail(display = "The management API returned a malformed response")]
+    MalformedResponse,
+
+    #[fail(display = "Could not build the request for {}", _0)]
+    MalformedRequest(String),
+
+    #[fail(display = "Could not reach the management API")]
+    Transport,
+}
+
+impl Fail for Error {
+    fn cause(&self) -> Option<&dyn Fail> {
+        self.inner.cause()
+    }
+
+    fn backtrace(&self) -> Option<&Backtrace> {
+        self.inner.backtrace()
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.inner, f)
+    }
+}
+
+impl Error {
+    pub fn kind(&self) -> &ErrorKind {
+        self.inner.get_context()
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Self {
+        Error {
+            inner: Context::new(kind),
+        }
+    }
+}
+
+impl From<Context<ErrorKind>> for Error {
+    fn from(inner: Context<ErrorKind>) -> Self {
+        Error { inner }
+    }
+}
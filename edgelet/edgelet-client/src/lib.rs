@@ -0,0 +1,21 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! A client of the management API for tools that run on the host, like
+//! deployment scripts. Requests and responses are the `management::models`
+//! types that the daemon serves, so a change to them changes both sides.
+
+#![deny(rust_2018_idioms, warnings)]
+#![deny(clippy::all, clippy::pedantic)]
+#![allow(
+    clippy::missing_errors_doc,
+    clippy::module_name_repetitions,
+    clippy::must_use_candidate,
+    clippy::use_self
+)]
+
+mod client;
+mod error;
+
+pub use client::ManagementClient;
+pub use error::{Error, ErrorKind};
+pub use management::models;
@@ -0,0 +1,173 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+#![cfg(unix)]
+#![deny(rust_2018_idioms, warnings)]
+#![deny(clippy::all, clippy::pedantic)]
+
+use std::io;
+
+use futures::future;
+use futures::prelude::*;
+use hyper::{Body, Method, Request, Response, StatusCode};
+use serde_json::json;
+use tempdir::TempDir;
+use tokio::runtime::current_thread::Runtime;
+use url::Url;
+
+use edgelet_client::models::{Config, ModuleSpec};
+use edgelet_client::{ErrorKind, ManagementClient};
+use edgelet_core::{LogOptions, LogTail};
+use edgelet_test_utils::run_uds_server;
+
+const LOGS: &[u8] = b"\x01\x00\x00\x00\x00\x00\x00\x05hello";
+
+fn details(name: &str) -> serde_json::Value {
+    json!({
+        "id": "abc",
+        "name": name,
+        "type": "docker",
+        "config": { "settings": { "image": "microsoft/test-image" } },
+        "status": { "runtimeStatus": { "status": "running" } },
+    })
+}
+
+fn response(status: StatusCode, body: &serde_json::Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("content-type", "application/json")
+        .body(body.to_string().into())
+        .unwrap()
+}
+
+fn empty(status: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::empty())
+        .unwrap()
+}
+
+fn handler(req: Request<Body>) -> Box<dyn Future<Item = Response<Body>, Error = io::Error> + Send> {
+    assert!(req
+        .uri()
+        .query()
+        .unwrap_or("")
+        .contains("api-version=2019-11-05"));
+
+    let response = match (req.method(), req.uri().path()) {
+        (&Method::GET, "/modules") => {
+            response(StatusCode::OK, &json!({ "modules": [details("m1")] }))
+        }
+        (&Method::POST, "/modules") => {
+            return Box::new(
+                req.into_body()
+                    .concat2()
+                    .map(|body| {
+                        let spec: ModuleSpec = serde_json::from_slice(&body).unwrap();
+                        response(StatusCode::CREATED, &details(spec.name()))
+                    })
+                    .map_err(|err| io::Error::new(io::ErrorKind::Other, err)),
+            );
+        }
+        (&Method::POST, "/modules/m1/restart") => empty(StatusCode::NO_CONTENT),
+        (&Method::POST, "/modules/m1/start") => empty(StatusCode::NOT_MODIFIED),
+        (&Method::POST, "/prepare-upgrade") => response(
+            StatusCode::OK,
+            &json!({ "modules": [{ "name": "m1", "labels": {} }] }),
+        ),
+        (&Method::GET, "/modules/m1/logs") => {
+            assert!(req.uri().query().unwrap().contains("tail=10"));
+            Response::new(LOGS.into())
+        }
+        _ => response(
+            StatusCode::NOT_FOUND,
+            &json!({ "message": "Module not found" }),
+        ),
+    };
+    Box::new(future::ok(response))
+}
+
+fn run<F>(test: F)
+where
+    F: FnOnce(ManagementClient, &mut Runtime),
+{
+    let dir = TempDir::new("client").unwrap();
+    let file_path = dir.path().join("mgmt.sock");
+    let file_path = file_path.to_str().unwrap();
+
+    let server = run_uds_server(&file_path, handler).map_err(|err| eprintln!("{}", err));
+
+    let mut url = Url::from_file_path(file_path).unwrap();
+    url.set_scheme("unix").unwrap();
+    let client = ManagementClient::new(&url).unwrap();
+
+    let mut runtime = Runtime::new().unwrap();
+    runtime.spawn(server);
+    test(client, &mut runtime);
+}
+
+#[test]
+fn modules_are_listed() {
+    run(|client, runtime| {
+        let modules = runtime.block_on(client.list_modules()).unwrap();
+
+        assert_eq!(1, modules.len());
+        assert_eq!("m1", modules[0].name());
+        assert_eq!("running", modules[0].status().runtime_status().status());
+    });
+}
+
+#[test]
+fn created_module_is_returned() {
+    run(|client, runtime| {
+        let spec = ModuleSpec::new(
+            "m2".to_string(),
+            "docker".to_string(),
+            Config::new(json!({ "image": "microsoft/test-image" })),
+        );
+        let module = runtime.block_on(client.create_module(&spec)).unwrap();
+
+        assert_eq!("m2", module.name());
+    });
+}
+
+#[test]
+fn module_that_is_already_started_is_not_an_error() {
+    run(|client, runtime| {
+        runtime.block_on(client.restart_module("m1")).unwrap();
+        runtime.block_on(client.start_module("m1")).unwrap();
+    });
+}
+
+#[test]
+fn upgrade_is_prepared() {
+    run(|client, runtime| {
+        runtime.block_on(client.prepare_upgrade()).unwrap();
+    });
+}
+
+#[test]
+fn logs_are_streamed() {
+    run(|client, runtime| {
+        let options = LogOptions::new().with_tail(LogTail::Num(10));
+        let logs = runtime
+            .block_on(client.logs("m1", &options).and_then(|body| {
+                body.concat2()
+                    .map_err(|err| panic!("could not read logs: {}", err))
+            }))
+            .unwrap();
+
+        assert_eq!(LOGS, &logs[..]);
+    });
+}
+
+#[test]
+fn error_message_is_returned() {
+    run(|client, runtime| {
+        let err = runtime.block_on(client.get_module("missing")).unwrap_err();
+
+        assert_eq!(
+            &ErrorKind::Api(StatusCode::NOT_FOUND, "Module not found".to_string()),
+            err.kind()
+        );
+    });
+}
@@ -0,0 +1,209 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Checks the certificate and key files in the settings when the daemon
+//! starts, since a file that can't be used otherwise only fails once a TLS
+//! handshake or the HSM needs it.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use failure::ResultExt;
+
+use crate::error::{Error, ErrorKind, Result};
+use crate::settings::{AttestationMethod, ManualAuthMethod, ProvisioningType, RuntimeSettings};
+
+/// Fails with an error that names the first certificate, key or CA file in
+/// `settings` that doesn't exist or can't be read, or whose private key can
+/// be read by any user.
+pub fn check_certificate_files<S>(settings: &S) -> Result<()>
+where
+    S: RuntimeSettings,
+{
+    for (path, setting, kind) in certificate_files(settings)? {
+        check_file(&path, setting, kind)?;
+    }
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum FileKind {
+    Certificate,
+    PrivateKey,
+}
+
+fn certificate_files<S>(settings: &S) -> Result<Vec<(PathBuf, &'static str, FileKind)>>
+where
+    S: RuntimeSettings,
+{
+    let mut files = vec![];
+
+    if let Some(device_cert) = settings.certificates().device_cert() {
+        files.push((
+            device_cert.device_ca_cert()?,
+            "certificates.device_ca_cert",
+            FileKind::Certificate,
+        ));
+        files.push((
+            device_cert.device_ca_pk()?,
+            "certificates.device_ca_pk",
+            FileKind::PrivateKey,
+        ));
+        files.push((
+            device_cert.trusted_ca_certs()?,
+            "certificates.trusted_ca_certs",
+            FileKind::Certificate,
+        ));
+    }
+
+    match settings.provisioning().provisioning_type() {
+        ProvisioningType::Manual(manual) => {
+            if let ManualAuthMethod::X509(x509) = manual.authentication_method() {
+                files.push((
+                    x509.identity_cert()?,
+                    "provisioning.authentication.identity_cert",
+                    FileKind::Certificate,
+                ));
+                files.push((
+                    x509.identity_pk()?,
+                    "provisioning.authentication.identity_pk",
+                    FileKind::PrivateKey,
+                ));
+            }
+        }
+        ProvisioningType::Dps(dps) => {
+            if let AttestationMethod::X509(x509) = dps.attestation() {
+                files.push((
+                    x509.identity_cert()?,
+                    "provisioning.attestation.identity_cert",
+                    FileKind::Certificate,
+                ));
+                files.push((
+                    x509.identity_pk()?,
+                    "provisioning.attestation.identity_pk",
+                    FileKind::PrivateKey,
+                ));
+            }
+        }
+        // The credentials of external provisioning aren't known until it's run.
+        ProvisioningType::External(_) => (),
+    }
+
+    if let Some(tls) = settings.listen().management_tls() {
+        files.push((
+            tls.server_cert().to_path_buf(),
+            "listen.management_tls.server_cert",
+            FileKind::Certificate,
+        ));
+        files.push((
+            tls.server_key().to_path_buf(),
+            "listen.management_tls.server_key",
+            FileKind::PrivateKey,
+        ));
+        files.push((
+            tls.client_ca().to_path_buf(),
+            "listen.management_tls.client_ca",
+            FileKind::Certificate,
+        ));
+    }
+
+    Ok(files)
+}
+
+fn check_file(path: &Path, setting: &'static str, kind: FileKind) -> Result<()> {
+    let display = path.display().to_string();
+    let unreadable = || ErrorKind::ReadCertificateFile(display.clone(), setting);
+
+    // Opening the file checks that it can be read and not only that it exists.
+    let file = fs::File::open(path).with_context(|_| unreadable())?;
+    let metadata = file.metadata().with_context(|_| unreadable())?;
+    if !metadata.is_file() {
+        return Err(Error::from(unreadable()));
+    }
+
+    // Files on Windows are protected by ACLs instead, which aren't checked.
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        if kind == FileKind::PrivateKey && metadata.permissions().mode() & 0o004 != 0 {
+            return Err(Error::from(ErrorKind::CertificateFilePermissions(
+                display, setting,
+            )));
+        }
+    }
+    #[cfg(windows)]
+    let _ = kind;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn missing_file_fails() {
+        let dir = TempDir::new("cert_files").unwrap();
+        let path = dir.path().join("device_ca_cert.pem");
+
+        let err =
+            check_file(&path, "certificates.device_ca_cert", FileKind::Certificate).unwrap_err();
+
+        match err.kind() {
+            ErrorKind::ReadCertificateFile(file, "certificates.device_ca_cert") => {
+                assert_eq!(&path.display().to_string(), file)
+            }
+            kind => panic!("Expected `ReadCertificateFile` error but got {:?}", kind),
+        }
+        assert!(err.to_string().contains("device_ca_cert.pem"));
+    }
+
+    #[test]
+    fn directory_fails() {
+        let dir = TempDir::new("cert_files").unwrap();
+
+        match check_file(
+            dir.path(),
+            "certificates.trusted_ca_certs",
+            FileKind::Certificate,
+        )
+        .unwrap_err()
+        .kind()
+        {
+            ErrorKind::ReadCertificateFile(_, "certificates.trusted_ca_certs") => (),
+            kind => panic!("Expected `ReadCertificateFile` error but got {:?}", kind),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn world_readable_key_fails() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new("cert_files").unwrap();
+        let path = dir.path().join("device_ca_pk.pem");
+        fs::write(&path, "key").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+
+        match check_file(&path, "certificates.device_ca_pk", FileKind::PrivateKey)
+            .unwrap_err()
+            .kind()
+        {
+            ErrorKind::CertificateFilePermissions(file, "certificates.device_ca_pk") => {
+                assert_eq!(&path.display().to_string(), file)
+            }
+            kind => panic!(
+                "Expected `CertificateFilePermissions` error but got {:?}",
+                kind
+            ),
+        }
+
+        // Certificates are public, so they may be read by anyone.
+        check_file(&path, "certificates.device_ca_cert", FileKind::Certificate).unwrap();
+
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        check_file(&path, "certificates.device_ca_pk", FileKind::PrivateKey).unwrap();
+    }
+}
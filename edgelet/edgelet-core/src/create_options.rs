@@ -0,0 +1,607 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Checks the `createOptions` of Docker modules against the fields of Docker's
+//! create container request, so that a typo like `HostConfg` doesn't silently
+//! do nothing.
+//!
+//! Only the top-level fields are checked. Keys that aren't Docker fields are
+//! warnings, or errors in strict mode, and fields whose values have the wrong
+//! JSON type are always errors.
+//!
+//! Create options that are too long for a single field of the deployment can
+//! be split into the JSON strings `createOptions`, `createOptions01`,
+//! `createOptions02` and so on, which are joined in order and parsed when the
+//! module spec is read.
+//!
+//! The daemon can also have default create options that every module's are
+//! merged over. Objects are merged key by key, and the module's value wins
+//! over the default for any other value. Arrays that make up one value, which
+//! are `Cmd`, `Entrypoint`, `Shell` and the `Test` of a `Healthcheck`, are the
+//! module's if it sets them. `Env` gets the default variables that the module
+//! doesn't set itself. Any other array, like `CapDrop` or `SecurityOpt`, is the
+//! default entries followed by the module's, without the entries that both
+//! have repeated.
+
+use std::result::Result as StdResult;
+
+use serde_json::Value;
+
+use crate::module::{ModuleSpecViolation, ValidationError};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum FieldType {
+    Bool,
+    Integer,
+    String,
+    Array,
+    Object,
+}
+
+impl FieldType {
+    fn matches(self, value: &Value) -> bool {
+        match self {
+            FieldType::Bool => value.is_boolean(),
+            FieldType::Integer => value.is_i64() || value.is_u64(),
+            FieldType::String => value.is_string(),
+            FieldType::Array => value.is_array(),
+            FieldType::Object => value.is_object(),
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            FieldType::Bool => "a boolean",
+            FieldType::Integer => "an integer",
+            FieldType::String => "a string",
+            FieldType::Array => "an array",
+            FieldType::Object => "an object",
+        }
+    }
+}
+
+const CREATE_OPTIONS_KEY: &str = "createOptions";
+
+/// The most bytes that the segments of split create options may add up to.
+pub const MAX_CREATE_OPTIONS_LEN: usize = 64 * 1024;
+
+// The fields of the body of Docker's `POST /containers/create`
+const CREATE_OPTIONS_FIELDS: &[(&str, FieldType)] = &[
+    ("ArgsEscaped", FieldType::Bool),
+    ("AttachStderr", FieldType::Bool),
+    ("AttachStdin", FieldType::Bool),
+    ("AttachStdout", FieldType::Bool),
+    ("Cmd", FieldType::Array),
+    ("Domainname", FieldType::String),
+    ("Entrypoint", FieldType::Array),
+    ("Env", FieldType::Array),
+    ("ExposedPorts", FieldType::Object),
+    ("Healthcheck", FieldType::Object),
+    ("HostConfig", FieldType::Object),
+    ("Hostname", FieldType::String),
+    ("Image", FieldType::String),
+    ("Labels", FieldType::Object),
+    ("MacAddress", FieldType::String),
+    ("NetworkDisabled", FieldType::Bool),
+    ("NetworkingConfig", FieldType::Object),
+    ("OnBuild", FieldType::Array),
+    ("OpenStdin", FieldType::Bool),
+    ("Shell", FieldType::Array),
+    ("StdinOnce", FieldType::Bool),
+    ("StopSignal", FieldType::String),
+    ("StopTimeout", FieldType::Integer),
+    ("Tty", FieldType::Bool),
+    ("User", FieldType::String),
+    ("Volumes", FieldType::Object),
+    ("WorkingDir", FieldType::String),
+];
+
+// Arrays that the module's value replaces the default's of instead of adding
+// to it, since their entries only mean something together.
+const REPLACED_ARRAYS: &[&str] = &["Cmd", "Entrypoint", "Shell", "Test"];
+
+const ENV_KEY: &str = "Env";
+
+/// Renames the keys of `create_options` that only differ from a Docker field
+/// in case to the name of the field, since Docker itself matches them
+/// case-insensitively, and then checks them like `validate_create_options`
+/// does outside of strict mode.
+pub fn normalize_create_options(create_options: &mut Value) -> StdResult<(), ValidationError> {
+    if let Value::Object(options) = create_options {
+        let renames: Vec<_> = options
+            .keys()
+            .filter_map(|key| {
+                CREATE_OPTIONS_FIELDS
+                    .iter()
+                    .find(|(name, _)| *name != key.as_str() && name.eq_ignore_ascii_case(key))
+                    .filter(|(name, _)| !options.contains_key(*name))
+                    .map(|(name, _)| (key.clone(), *name))
+            })
+            .collect();
+        for (key, name) in renames {
+            if let Some(value) = options.remove(&key) {
+                options.insert(name.to_string(), value);
+            }
+        }
+    }
+
+    validate_create_options(create_options, false).map(|_| ())
+}
+
+/// Replaces the `createOptions` of a module's `config` with the JSON that
+/// its string segments `createOptions`, `createOptions01`, `createOptions02`
+/// and so on add up to, and removes the numbered segments. Segments are
+/// numbered with two digits and must follow each other without gaps. Configs
+/// whose `createOptions` aren't a string are left alone.
+pub fn join_create_options(config: &mut Value) -> StdResult<(), ValidationError> {
+    let config = match config {
+        Value::Object(config) => config,
+        _ => return Ok(()),
+    };
+
+    let mut numbers: Vec<(u32, String)> = config
+        .keys()
+        .filter_map(|key| {
+            if !key.starts_with(CREATE_OPTIONS_KEY) {
+                return None;
+            }
+            let suffix = &key[CREATE_OPTIONS_KEY.len()..];
+            if suffix.len() == 2 && suffix.chars().all(|c| c.is_ascii_digit()) {
+                suffix.parse().ok().map(|number| (number, key.clone()))
+            } else {
+                None
+            }
+        })
+        .collect();
+    let is_split = config
+        .get(CREATE_OPTIONS_KEY)
+        .map_or(false, Value::is_string);
+    if numbers.is_empty() && !is_split {
+        return Ok(());
+    }
+    numbers.sort();
+
+    let mut keys = vec![CREATE_OPTIONS_KEY.to_string()];
+    for (expected, (number, key)) in (1..).zip(numbers) {
+        if number != expected {
+            return Err(ValidationError::new(vec![
+                ModuleSpecViolation::MissingCreateOptionsSegment(segment_key(expected)),
+            ]));
+        }
+        keys.push(key);
+    }
+
+    let mut joined = String::new();
+    for key in &keys {
+        match config.remove(key) {
+            Some(Value::String(segment)) => joined.push_str(&segment),
+            Some(_) => {
+                return Err(ValidationError::new(vec![
+                    ModuleSpecViolation::CreateOptionsSegmentNotString(key.clone()),
+                ]))
+            }
+            None => {
+                return Err(ValidationError::new(vec![
+                    ModuleSpecViolation::MissingCreateOptionsSegment(key.clone()),
+                ]))
+            }
+        }
+        if joined.len() > MAX_CREATE_OPTIONS_LEN {
+            return Err(ValidationError::new(vec![
+                ModuleSpecViolation::CreateOptionsTooLarge(MAX_CREATE_OPTIONS_LEN),
+            ]));
+        }
+    }
+
+    let create_options = serde_json::from_str(&joined).map_err(|err| {
+        ValidationError::new(vec![ModuleSpecViolation::InvalidCreateOptionsJson(
+            err.to_string(),
+        )])
+    })?;
+    config.insert(CREATE_OPTIONS_KEY.to_string(), create_options);
+    Ok(())
+}
+
+fn segment_key(number: u32) -> String {
+    format!("{}{:02}", CREATE_OPTIONS_KEY, number)
+}
+
+/// Merges `defaults` under `create_options`, as described in the module
+/// docs. Fields that are `null` in `create_options` are treated as unset.
+pub fn merge_create_options(defaults: &Value, create_options: &mut Value) {
+    merge(None, defaults, create_options)
+}
+
+fn merge(key: Option<&str>, default: &Value, value: &mut Value) {
+    match (default, value) {
+        (Value::Object(defaults), Value::Object(options)) => {
+            for (key, default) in defaults {
+                match options.get_mut(key).filter(|value| !value.is_null()) {
+                    Some(value) => merge(Some(key), default, value),
+                    None => {
+                        options.insert(key.clone(), default.clone());
+                    }
+                }
+            }
+        }
+        (Value::Array(defaults), Value::Array(values)) => {
+            let mut merged: Vec<Value> = match key {
+                Some(key) if REPLACED_ARRAYS.contains(&key) => return,
+                Some(ENV_KEY) => defaults
+                    .iter()
+                    .filter(|default| {
+                        !values
+                            .iter()
+                            .any(|value| env_name(value) == env_name(default))
+                    })
+                    .cloned()
+                    .collect(),
+                _ => defaults
+                    .iter()
+                    .filter(|default| !values.contains(default))
+                    .cloned()
+                    .collect(),
+            };
+            merged.append(values);
+            *values = merged;
+        }
+        // Scalars, and values of another type than the default, are the
+        // module's.
+        _ => (),
+    }
+}
+
+fn env_name(variable: &Value) -> Option<&str> {
+    variable
+        .as_str()
+        .and_then(|variable| variable.split('=').next())
+}
+
+/// Checks `create_options` against the fields of Docker's create container
+/// request. Returns the keys that aren't Docker fields as warnings, unless
+/// `strict` is set, in which case they are errors like fields of the wrong
+/// type are. Fields that are `null` are treated as unset.
+pub fn validate_create_options(
+    create_options: &Value,
+    strict: bool,
+) -> StdResult<Vec<ModuleSpecViolation>, ValidationError> {
+    let options = match create_options {
+        Value::Object(options) => options,
+        _ => {
+            return Err(ValidationError::new(vec![
+                ModuleSpecViolation::CreateOptionsNotObject,
+            ]))
+        }
+    };
+
+    let mut errors = vec![];
+    let mut warnings = vec![];
+    for (key, value) in options {
+        match CREATE_OPTIONS_FIELDS
+            .iter()
+            .find(|(name, _)| *name == key.as_str())
+        {
+            Some((_, field_type)) => {
+                if !value.is_null() && !field_type.matches(value) {
+                    errors.push(ModuleSpecViolation::InvalidCreateOptionType(
+                        key.clone(),
+                        field_type.description(),
+                    ));
+                }
+            }
+            None => warnings.push(ModuleSpecViolation::UnknownCreateOption(key.clone())),
+        }
+    }
+
+    if strict {
+        errors.append(&mut warnings);
+    }
+    if errors.is_empty() {
+        Ok(warnings)
+    } else {
+        Err(ValidationError::new(errors))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn valid_options() -> Value {
+        json!({
+            "Env": ["LOG_LEVEL=debug"],
+            "Labels": { "app": "sensor" },
+            "StopTimeout": 30,
+            "Tty": false,
+            "User": "1000",
+            "HostConfig": {
+                "PortBindings": {
+                    "8080/tcp": [{ "HostPort": "8080" }]
+                }
+            },
+            "Healthcheck": null,
+        })
+    }
+
+    #[test]
+    fn join_two_segments() {
+        let mut config = json!({
+            "image": "ml-model:1.0",
+            "createOptions": "{\"Env\":[\"MODEL=resnet\"],",
+            "createOptions01": "\"Labels\":{\"app\":\"ml\"}}",
+        });
+        join_create_options(&mut config).unwrap();
+        assert_eq!(
+            json!({
+                "image": "ml-model:1.0",
+                "createOptions": {
+                    "Env": ["MODEL=resnet"],
+                    "Labels": { "app": "ml" },
+                },
+            }),
+            config
+        );
+    }
+
+    #[test]
+    fn join_segments_in_numbered_order() {
+        let mut config = json!({
+            "createOptions02": "[\"c\"]}",
+            "createOptions": "{\"Cmd\":",
+            "createOptions10": "",
+            "createOptions01": "[\"a\",\"b\"] , \"Shell\":",
+            "createOptions03": "",
+            "createOptions04": "",
+            "createOptions05": "",
+            "createOptions06": "",
+            "createOptions07": "",
+            "createOptions08": "",
+            "createOptions09": "",
+        });
+        join_create_options(&mut config).unwrap();
+        assert_eq!(
+            json!({ "createOptions": { "Cmd": ["a", "b"], "Shell": ["c"] } }),
+            config
+        );
+    }
+
+    #[test]
+    fn join_fails_for_missing_segment() {
+        let mut config = json!({
+            "createOptions": "{\"Env\":",
+            "createOptions01": "[\"A=1\"],",
+            "createOptions03": "\"Tty\":true}",
+        });
+        let err = join_create_options(&mut config).unwrap_err();
+        assert_eq!(
+            &[ModuleSpecViolation::MissingCreateOptionsSegment(
+                "createOptions02".to_string()
+            )],
+            err.violations()
+        );
+
+        let mut config = json!({ "createOptions01": "{}" });
+        let err = join_create_options(&mut config).unwrap_err();
+        assert_eq!(
+            &[ModuleSpecViolation::MissingCreateOptionsSegment(
+                "createOptions".to_string()
+            )],
+            err.violations()
+        );
+    }
+
+    #[test]
+    fn join_fails_when_too_large() {
+        let segment = format!("\"{}\"", "a".repeat(MAX_CREATE_OPTIONS_LEN / 2));
+        let mut config = json!({
+            "createOptions": "{\"Image\":",
+            "createOptions01": segment,
+            "createOptions02": segment,
+            "createOptions03": "}",
+        });
+        let err = join_create_options(&mut config).unwrap_err();
+        assert_eq!(
+            &[ModuleSpecViolation::CreateOptionsTooLarge(
+                MAX_CREATE_OPTIONS_LEN
+            )],
+            err.violations()
+        );
+    }
+
+    #[test]
+    fn join_leaves_object_create_options_alone() {
+        let mut config = json!({ "createOptions": valid_options() });
+        join_create_options(&mut config).unwrap();
+        assert_eq!(json!({ "createOptions": valid_options() }), config);
+    }
+
+    #[test]
+    fn valid_options_have_no_warnings() {
+        assert_eq!(
+            Vec::<ModuleSpecViolation>::new(),
+            validate_create_options(&valid_options(), false).unwrap()
+        );
+        assert_eq!(
+            Vec::<ModuleSpecViolation>::new(),
+            validate_create_options(&valid_options(), true).unwrap()
+        );
+    }
+
+    #[test]
+    fn unknown_key_is_a_warning() {
+        let options = json!({ "HostConfg": { "Privileged": true } });
+        assert_eq!(
+            vec![ModuleSpecViolation::UnknownCreateOption(
+                "HostConfg".to_string()
+            )],
+            validate_create_options(&options, false).unwrap()
+        );
+    }
+
+    #[test]
+    fn unknown_key_is_an_error_in_strict_mode() {
+        let options = json!({ "HostConfg": { "Privileged": true } });
+        let err = validate_create_options(&options, true).unwrap_err();
+        assert_eq!(
+            &[ModuleSpecViolation::UnknownCreateOption(
+                "HostConfg".to_string()
+            )],
+            err.violations()
+        );
+    }
+
+    #[test]
+    fn type_mismatch_is_rejected() {
+        let options = json!({ "HostConfig": "--privileged", "Tty": "yes" });
+        let err = validate_create_options(&options, false).unwrap_err();
+        assert!(err
+            .violations()
+            .contains(&ModuleSpecViolation::InvalidCreateOptionType(
+                "HostConfig".to_string(),
+                "an object"
+            )));
+        assert!(err
+            .violations()
+            .contains(&ModuleSpecViolation::InvalidCreateOptionType(
+                "Tty".to_string(),
+                "a boolean"
+            )));
+        assert_eq!(2, err.violations().len());
+    }
+
+    #[test]
+    fn options_must_be_an_object() {
+        let err = validate_create_options(&json!("{\"Tty\":true}"), false).unwrap_err();
+        assert_eq!(
+            &[ModuleSpecViolation::CreateOptionsNotObject],
+            err.violations()
+        );
+    }
+
+    #[test]
+    fn normalize_fixes_case_of_known_fields() {
+        let mut options = json!({
+            "hostConfig": { "Privileged": true },
+            "ENV": ["A=1"],
+            "Labels": {},
+            "labels": { "app": "sensor" },
+            "HostConfg": {},
+        });
+        normalize_create_options(&mut options).unwrap();
+
+        assert_eq!(
+            json!({
+                "HostConfig": { "Privileged": true },
+                "Env": ["A=1"],
+                "Labels": {},
+                // the field is already set, so this is left for Docker
+                "labels": { "app": "sensor" },
+                "HostConfg": {},
+            }),
+            options
+        );
+    }
+
+    #[test]
+    fn normalize_rejects_type_mismatch() {
+        let mut options = json!({ "hostconfig": [] });
+        let err = normalize_create_options(&mut options).unwrap_err();
+        assert_eq!(
+            &[ModuleSpecViolation::InvalidCreateOptionType(
+                "HostConfig".to_string(),
+                "an object"
+            )],
+            err.violations()
+        );
+    }
+
+    fn defaults() -> Value {
+        json!({
+            "User": "1000",
+            "Env": ["LOG_LEVEL=info", "TZ=UTC"],
+            "Cmd": ["--verbose"],
+            "HostConfig": {
+                "CapDrop": ["ALL"],
+                "SecurityOpt": ["no-new-privileges"],
+                "Privileged": false,
+                "LogConfig": { "Type": "json-file", "Config": { "max-size": "10m" } },
+            },
+        })
+    }
+
+    #[test]
+    fn merge_fills_unset_fields() {
+        let mut options = json!({ "Image": "sensor:1.0", "User": null });
+        merge_create_options(&defaults(), &mut options);
+
+        let mut expected = defaults();
+        expected["Image"] = json!("sensor:1.0");
+        assert_eq!(expected, options);
+    }
+
+    #[test]
+    fn merge_scalar_override() {
+        let mut options = json!({
+            "User": "root",
+            "HostConfig": { "Privileged": true },
+        });
+        merge_create_options(&defaults(), &mut options);
+
+        assert_eq!(json!("root"), options["User"]);
+        assert_eq!(json!(true), options["HostConfig"]["Privileged"]);
+    }
+
+    #[test]
+    fn merge_deep_merges_maps() {
+        let mut options = json!({
+            "HostConfig": {
+                "LogConfig": { "Config": { "max-file": "3" } },
+                "PortBindings": { "8080/tcp": [{ "HostPort": "8080" }] },
+            },
+        });
+        merge_create_options(&defaults(), &mut options);
+
+        assert_eq!(
+            json!({
+                "CapDrop": ["ALL"],
+                "SecurityOpt": ["no-new-privileges"],
+                "Privileged": false,
+                "LogConfig": {
+                    "Type": "json-file",
+                    "Config": { "max-size": "10m", "max-file": "3" },
+                },
+                "PortBindings": { "8080/tcp": [{ "HostPort": "8080" }] },
+            }),
+            options["HostConfig"]
+        );
+    }
+
+    #[test]
+    fn merge_arrays() {
+        let mut options = json!({
+            "Env": ["TZ=Europe/Paris", "MODE=fast"],
+            "Cmd": ["--quiet"],
+            "HostConfig": {
+                "CapAdd": ["NET_ADMIN"],
+                "SecurityOpt": ["no-new-privileges", "apparmor=sensor"],
+            },
+        });
+        merge_create_options(&defaults(), &mut options);
+
+        // Lists of independent entries are concatenated without repeats.
+        assert_eq!(
+            json!(["no-new-privileges", "apparmor=sensor"]),
+            options["HostConfig"]["SecurityOpt"]
+        );
+        assert_eq!(json!(["ALL"]), options["HostConfig"]["CapDrop"]);
+        assert_eq!(json!(["NET_ADMIN"]), options["HostConfig"]["CapAdd"]);
+        // The module's environment variables win by name.
+        assert_eq!(
+            json!(["LOG_LEVEL=info", "TZ=Europe/Paris", "MODE=fast"]),
+            options["Env"]
+        );
+        // Command lines are replaced.
+        assert_eq!(json!(["--quiet"]), options["Cmd"]);
+    }
+}
@@ -3,6 +3,7 @@
 use std::collections::HashMap;
 use std::convert::{AsRef, From};
 use std::fmt;
+use std::str::FromStr;
 use std::string::ToString;
 use std::sync::{Arc, RwLock};
 
@@ -11,7 +12,7 @@ use chrono::{DateTime, Utc};
 use consistenttime::ct_u8_slice_eq;
 use failure::ResultExt;
 use hmac::{Hmac, Mac};
-use sha2::Sha256;
+use sha2::{Sha256, Sha384, Sha512};
 
 use crate::certificate_properties::{CertificateIssuer, CertificateProperties};
 use crate::error::{Error, ErrorKind};
@@ -57,6 +58,11 @@ pub trait Sign {
         signature_algorithm: SignatureAlgorithm,
         data: &[u8],
     ) -> Result<Self::Signature, Error>;
+
+    /// The signature algorithms this key can sign with.
+    fn supported_algorithms(&self) -> &'static [SignatureAlgorithm] {
+        &SignatureAlgorithm::ALL
+    }
 }
 
 pub trait KeyStore {
@@ -65,9 +71,46 @@ pub trait KeyStore {
     fn get(&self, identity: &KeyIdentity, key_name: &str) -> Result<Self::Key, Error>;
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum SignatureAlgorithm {
     HMACSHA256,
+    HMACSHA384,
+    HMACSHA512,
+}
+
+impl SignatureAlgorithm {
+    pub const ALL: [SignatureAlgorithm; 3] = [
+        SignatureAlgorithm::HMACSHA256,
+        SignatureAlgorithm::HMACSHA384,
+        SignatureAlgorithm::HMACSHA512,
+    ];
+}
+
+impl fmt::Display for SignatureAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignatureAlgorithm::HMACSHA256 => write!(f, "HMAC-SHA256"),
+            SignatureAlgorithm::HMACSHA384 => write!(f, "HMAC-SHA384"),
+            SignatureAlgorithm::HMACSHA512 => write!(f, "HMAC-SHA512"),
+        }
+    }
+}
+
+impl FromStr for SignatureAlgorithm {
+    type Err = Error;
+
+    // `HMACSHA256` and `hmac` are what clients of the workload API sent before other
+    // algorithms were supported, when the algorithm wasn't looked at.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "HMAC-SHA256" | "HMACSHA256" | "hmac" => Ok(SignatureAlgorithm::HMACSHA256),
+            "HMAC-SHA384" | "HMACSHA384" => Ok(SignatureAlgorithm::HMACSHA384),
+            "HMAC-SHA512" | "HMACSHA512" => Ok(SignatureAlgorithm::HMACSHA512),
+            _ => Err(Error::from(ErrorKind::UnsupportedSignatureAlgorithm(
+                s.to_string(),
+            ))),
+        }
+    }
 }
 
 pub trait Signature {
@@ -250,6 +293,18 @@ impl Sign for MemoryKey {
 
                 Digest::new(Bytes::from(code_bytes.as_ref()))
             }
+            SignatureAlgorithm::HMACSHA384 => {
+                let mut mac = Hmac::<Sha384>::new(&self.key)
+                    .map_err(|_| ErrorKind::SignInvalidKeyLength(self.key.len()))?;
+                mac.input(data);
+                Digest::new(Bytes::from(mac.result().code().as_ref()))
+            }
+            SignatureAlgorithm::HMACSHA512 => {
+                let mut mac = Hmac::<Sha512>::new(&self.key)
+                    .map_err(|_| ErrorKind::SignInvalidKeyLength(self.key.len()))?;
+                mac.input(data);
+                Digest::new(Bytes::from(mac.result().code().as_ref()))
+            }
         };
         Ok(signature)
     }
@@ -455,6 +510,65 @@ mod tests {
         assert_ne!(expected, result_hmac256.as_bytes());
     }
 
+    #[test]
+    fn sha384_sign_test_positive() {
+        let in_memory_key = MemoryKey::new("key");
+        let data = b"The quick brown fox jumps over the lazy dog";
+
+        let result = in_memory_key
+            .sign(SignatureAlgorithm::HMACSHA384, data)
+            .unwrap();
+
+        assert_eq!(
+            "1/RyfiwLOa4PHkDMlvYCQtW3gBhBzqb8WSxdPhrlBwBYKpbPNeHlVJlf5OAzgcI3",
+            base64::encode(result.as_bytes())
+        );
+    }
+
+    #[test]
+    fn sha512_sign_test_positive() {
+        let in_memory_key = MemoryKey::new("key");
+        let data = b"The quick brown fox jumps over the lazy dog";
+
+        let result = in_memory_key
+            .sign(SignatureAlgorithm::HMACSHA512, data)
+            .unwrap();
+
+        assert_eq!(
+            "tCrwkFe6weLUFwjkipAuCbX/fxKrQopP6GZTxz3SSPuC+UilSfe3kaW0GRXuTR7Dk1NX5OIxclDQNyr6Lr7rOg==",
+            base64::encode(result.as_bytes())
+        );
+    }
+
+    #[test]
+    fn signature_algorithm_round_trips_through_its_name() {
+        for algorithm in &SignatureAlgorithm::ALL {
+            assert_eq!(
+                *algorithm,
+                algorithm.to_string().parse::<SignatureAlgorithm>().unwrap()
+            );
+        }
+
+        assert_eq!(
+            SignatureAlgorithm::HMACSHA256,
+            "HMACSHA256".parse::<SignatureAlgorithm>().unwrap()
+        );
+        assert_eq!(
+            SignatureAlgorithm::HMACSHA256,
+            "hmac".parse::<SignatureAlgorithm>().unwrap()
+        );
+        match "HMAC-MD5".parse::<SignatureAlgorithm>() {
+            Err(err) => match err.kind() {
+                ErrorKind::UnsupportedSignatureAlgorithm(name) => assert_eq!("HMAC-MD5", name),
+                kind => panic!(
+                    "Expected `UnsupportedSignatureAlgorithm` but got {:?}",
+                    kind
+                ),
+            },
+            Ok(_) => panic!("Expected HMAC-MD5 to be rejected"),
+        }
+    }
+
     //MemoryKeyStoreTests
     #[test]
     fn create_empty_memory_keystore() {
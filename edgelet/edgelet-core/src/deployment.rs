@@ -0,0 +1,843 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Deployments of modules from the desired properties that the cloud sends,
+//! which are validated as a whole before the module set they describe is
+//! applied. A payload that isn't well formed, such as one without `modules`,
+//! would otherwise be read as an empty module set and remove every module, so
+//! it's rejected and the module set that was last applied is kept.
+//!
+//! The payload names the specs of its modules by module name:
+//!
+//! ```json
+//! { "modules": { "tempSensor": { "type": "docker", "config": { ... } } } }
+//! ```
+//!
+//! A deployment with admission limits rejects a module set that doesn't fit
+//! in them, see `ModuleSet::admit`.
+//!
+//! A deployment with a trust key only applies payloads that are a
+//! `SignedManifest` of such a payload, signed with that key.
+//!
+//! Whether a payload was applied or rejected, and why, is reported on the
+//! status channel of the deployment, if it has one. `LastDeploymentStatus`
+//! keeps the latest of them.
+//!
+//! A `DeploymentReconciler` changes the modules of a runtime to match each
+//! module set that's applied, one deployment at a time. With maintenance
+//! windows, a deployment that changes the image of a module is queued until
+//! the next window opens, unless the module is labeled as a critical update.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use failure::ResultExt;
+use futures::future::{self, Either};
+use futures::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use futures::{Future, Stream};
+use log::{info, warn, Level};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use tokio::timer::Delay;
+
+use edgelet_utils::log_failure;
+
+use crate::error::{Error, ErrorKind, Result};
+use crate::maintenance::{deferred_for, MaintenanceWindows};
+use crate::manifest_signature::{ManifestTrustKey, SignedManifest};
+use crate::module::{ImageReference, ModuleRuntime, ModuleRuntimeErrorReason, ModuleSpec};
+use crate::module_set::{adopt_existing, ModuleChange, ModuleSet, ModuleSetDiff};
+use crate::module_set_store::ModuleSetStore;
+use crate::settings::AdmissionSettings;
+
+#[derive(Clone, Copy, Debug, PartialEq, serde_derive::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeploymentState {
+    Applied,
+    Rejected,
+}
+
+/// The outcome of a deployment payload, with what was wrong with it if it
+/// was rejected.
+#[derive(Clone, Debug, PartialEq, serde_derive::Serialize)]
+pub struct DeploymentStatus {
+    state: DeploymentState,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<String>,
+    timestamp: DateTime<Utc>,
+}
+
+impl DeploymentStatus {
+    pub fn state(&self) -> DeploymentState {
+        self.state
+    }
+
+    /// Each problem with the payload, prefixed with where in it it is, like
+    /// `modules.tempSensor.config`.
+    pub fn errors(&self) -> &[String] {
+        &self.errors
+    }
+
+    pub fn timestamp(&self) -> DateTime<Utc> {
+        self.timestamp
+    }
+}
+
+/// The module set that was last applied from a valid deployment payload.
+pub struct Deployment<T> {
+    current: Option<ModuleSet<T>>,
+    store: Option<ModuleSetStore>,
+    status: Option<UnboundedSender<DeploymentStatus>>,
+    trust_key: Option<ManifestTrustKey>,
+    admission: AdmissionSettings,
+}
+
+impl<T> Deployment<T>
+where
+    T: DeserializeOwned + ImageReference + Serialize,
+{
+    /// A deployment that starts from `last_good`, such as the module set that
+    /// a `ModuleSetStore` saved before the daemon restarted.
+    pub fn new(last_good: Option<ModuleSet<T>>) -> Self {
+        Deployment {
+            current: last_good,
+            store: None,
+            status: None,
+            trust_key: None,
+            admission: AdmissionSettings::default(),
+        }
+    }
+
+    /// Saves each module set that is applied to `store`.
+    pub fn with_store(mut self, store: ModuleSetStore) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// Reports the outcome of each payload on `status`.
+    pub fn with_status_channel(mut self, status: UnboundedSender<DeploymentStatus>) -> Self {
+        self.status = Some(status);
+        self
+    }
+
+    /// Only applies payloads that are signed with `trust_key`.
+    pub fn with_trust_key(mut self, trust_key: ManifestTrustKey) -> Self {
+        self.trust_key = Some(trust_key);
+        self
+    }
+
+    /// Rejects the module sets that don't fit in `admission`.
+    pub fn with_admission(mut self, admission: AdmissionSettings) -> Self {
+        self.admission = admission;
+        self
+    }
+
+    /// The module set that was last applied, if any.
+    pub fn current(&self) -> Option<&ModuleSet<T>> {
+        self.current.as_ref()
+    }
+
+    /// Validates `payload` and makes the module set it describes the current
+    /// one. A payload that isn't valid is rejected with all that is wrong
+    /// with it, and the current module set is kept. With a trust key, a
+    /// payload that isn't signed with it is rejected before it's validated. A
+    /// valid payload whose modules don't fit in the admission limits is
+    /// rejected with `ErrorKind::DeploymentOverBudget`.
+    pub fn apply(&mut self, payload: &Value) -> Result<&ModuleSet<T>> {
+        let verified = match &self.trust_key {
+            Some(trust_key) => match verify_manifest(payload, trust_key) {
+                Ok(manifest) => Some(manifest),
+                Err(err) => {
+                    warn!(
+                        "Rejected the deployment, keeping the modules that were last applied: {}",
+                        err
+                    );
+                    self.report(DeploymentState::Rejected, vec![err.to_string()]);
+                    return Err(err);
+                }
+            },
+            None => None,
+        };
+        let payload = verified.as_ref().unwrap_or(payload);
+
+        let modules = match parse_deployment(payload) {
+            Ok(modules) => modules,
+            Err(errors) => {
+                warn!(
+                    "Rejected the deployment, keeping the modules that were last applied: {}",
+                    errors.join("; ")
+                );
+                self.report(DeploymentState::Rejected, errors.clone());
+                return Err(Error::from(ErrorKind::InvalidDeployment(errors.join("; "))));
+            }
+        };
+        if let Err(err) = modules.admit(&self.admission) {
+            warn!(
+                "Rejected the deployment, keeping the modules that were last applied: {}",
+                err
+            );
+            self.report(DeploymentState::Rejected, vec![err.to_string()]);
+            return Err(err);
+        }
+
+        info!(
+            "Applying a deployment of {} modules",
+            modules.modules().len()
+        );
+        if let Some(store) = &self.store {
+            if let Err(err) = store.save(&modules) {
+                warn!("Could not save the deployment's modules:");
+                log_failure(Level::Warn, &err);
+            }
+        }
+        self.report(DeploymentState::Applied, vec![]);
+        self.current = Some(modules);
+        Ok(self.current.as_ref().expect("modules were just applied"))
+    }
+
+    fn report(&mut self, state: DeploymentState, errors: Vec<String>) {
+        if let Some(status) = &self.status {
+            let status = status.unbounded_send(DeploymentStatus {
+                state,
+                errors,
+                timestamp: Utc::now(),
+            });
+            // Nobody is listening for the status anymore.
+            if status.is_err() {
+                self.status = None;
+            }
+        }
+    }
+}
+
+/// The status of the deployment that was last applied or rejected, kept from
+/// the status channel of a `Deployment` for as long as it's followed.
+#[derive(Clone, Debug, Default)]
+pub struct LastDeploymentStatus {
+    last: Arc<Mutex<Option<DeploymentStatus>>>,
+}
+
+impl LastDeploymentStatus {
+    pub fn new() -> Self {
+        LastDeploymentStatus::default()
+    }
+
+    /// The last status, or `None` if no deployment was applied since the
+    /// daemon started.
+    pub fn get(&self) -> Option<DeploymentStatus> {
+        self.last
+            .lock()
+            .expect("deployment status lock poisoned")
+            .clone()
+    }
+
+    /// Keeps each status received on `statuses`. Resolves once the
+    /// `Deployment` that sends them is dropped.
+    pub fn follow(
+        &self,
+        statuses: UnboundedReceiver<DeploymentStatus>,
+    ) -> impl Future<Item = (), Error = ()> + Send {
+        let last = self.last.clone();
+        statuses.for_each(move |status| {
+            *last.lock().expect("deployment status lock poisoned") = Some(status);
+            Ok(())
+        })
+    }
+}
+
+/// Applies deployments to the modules of a runtime. Each valid payload is
+/// applied as the diff from the module set that was applied before it, so
+/// only the modules that changed are touched. Deployments are applied one at
+/// a time, and one that comes while another is being applied is refused with
+/// `ErrorKind::DeploymentInProgress`.
+pub struct DeploymentReconciler<M>
+where
+    M: ModuleRuntime,
+{
+    runtime: M,
+    deployment: Arc<Mutex<Deployment<M::Config>>>,
+    applying: Arc<AtomicBool>,
+    timeout: Duration,
+    parallelism: usize,
+    maintenance: Option<MaintenanceWindows>,
+}
+
+impl<M> Clone for DeploymentReconciler<M>
+where
+    M: ModuleRuntime + Clone,
+{
+    fn clone(&self) -> Self {
+        DeploymentReconciler {
+            runtime: self.runtime.clone(),
+            deployment: self.deployment.clone(),
+            applying: self.applying.clone(),
+            timeout: self.timeout,
+            parallelism: self.parallelism,
+            maintenance: self.maintenance.clone(),
+        }
+    }
+}
+
+impl<M> DeploymentReconciler<M>
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+    M::Config: DeserializeOwned + ImageReference + Serialize,
+    for<'r> &'r <M as ModuleRuntime>::Error: Into<ModuleRuntimeErrorReason>,
+{
+    /// Modules that are removed or recreated are given `timeout` to exit
+    /// before they are killed, unless their spec has a stop timeout.
+    pub fn new(runtime: M, deployment: Deployment<M::Config>, timeout: Duration) -> Self {
+        DeploymentReconciler {
+            runtime,
+            deployment: Arc::new(Mutex::new(deployment)),
+            applying: Arc::new(AtomicBool::new(false)),
+            timeout,
+            parallelism: 1,
+            maintenance: None,
+        }
+    }
+
+    /// Changes up to `parallelism` modules at the same time, see
+    /// `ModuleSetDiff::with_parallelism`. Modules are changed one at a time
+    /// by default.
+    pub fn with_parallelism(mut self, parallelism: usize) -> Self {
+        self.parallelism = parallelism;
+        self
+    }
+
+    pub fn parallelism(&self) -> usize {
+        self.parallelism
+    }
+
+    /// Only changes the image of a module while one of `maintenance` is open,
+    /// queueing the deployment until the next one opens otherwise. Modules
+    /// labeled as critical updates don't hold a deployment back. Deployments
+    /// aren't held back by default.
+    pub fn with_maintenance_windows(mut self, maintenance: MaintenanceWindows) -> Self {
+        self.maintenance = Some(maintenance);
+        self
+    }
+
+    /// Starts the modules that were last applied, see `ModuleSet::start_all`,
+    /// such as the ones that a `ModuleSetStore` saved before the daemon
+    /// restarted. A deployment that comes while they are being started is
+    /// refused like one that comes while another is being applied.
+    pub fn start(&self) -> impl Future<Item = (), Error = Error> + Send {
+        let applying = match Applying::start(&self.applying) {
+            Some(applying) => applying,
+            None => return Either::A(future::err(Error::from(ErrorKind::DeploymentInProgress))),
+        };
+
+        match self.current() {
+            Some(modules) => Either::B(modules.start_all(self.runtime.clone(), None).then(
+                move |result| {
+                    drop(applying);
+                    result
+                },
+            )),
+            None => Either::A(future::ok(())),
+        }
+    }
+
+    /// Stops the modules that were last applied in shutdown order, see
+    /// `ModuleSet::stop_all`, giving each the reconciler's timeout unless its
+    /// spec has one. A deployment that comes while they are being stopped is
+    /// refused like one that comes while another is being applied.
+    pub fn stop(&self) -> impl Future<Item = (), Error = Error> + Send {
+        let applying = match Applying::start(&self.applying) {
+            Some(applying) => applying,
+            None => return Either::A(future::err(Error::from(ErrorKind::DeploymentInProgress))),
+        };
+
+        match self.current() {
+            Some(modules) => Either::B(modules.stop_all(self.runtime.clone(), self.timeout).then(
+                move |result| {
+                    drop(applying);
+                    result
+                },
+            )),
+            None => Either::A(future::ok(())),
+        }
+    }
+
+    /// The module set that was last applied, if any.
+    pub fn current(&self) -> Option<ModuleSet<M::Config>> {
+        self.deployment
+            .lock()
+            .expect("deployment lock poisoned")
+            .current()
+            .cloned()
+    }
+
+    /// Validates `payload`, see `Deployment::apply`, and changes the modules
+    /// to the module set it describes. Fails with
+    /// `ErrorKind::ModulesNotApplied` if some of the modules couldn't be
+    /// changed, in which case the next deployment is applied from the new
+    /// module set all the same.
+    ///
+    /// A deployment that is held back for a maintenance window resolves right
+    /// away to how long it is until the window opens, and is applied then in
+    /// the background, on the executor that `apply` runs on. Other
+    /// deployments are refused until it has been applied.
+    pub fn apply(
+        &self,
+        payload: &Value,
+    ) -> impl Future<Item = Option<Duration>, Error = Error> + Send {
+        let applying = match Applying::start(&self.applying) {
+            Some(applying) => applying,
+            None => return Either::A(future::err(Error::from(ErrorKind::DeploymentInProgress))),
+        };
+
+        let diff = {
+            let mut deployment = self.deployment.lock().expect("deployment lock poisoned");
+            let previous = match deployment.current() {
+                Some(previous) => previous.clone(),
+                None => ModuleSet::new(vec![]).expect("an empty module set is consistent"),
+            };
+            deployment
+                .apply(payload)
+                .and_then(|desired| previous.diff(desired))
+                .map(|diff| {
+                    let until_open =
+                        image_change_deferred_for(self.maintenance.as_ref(), &previous, &diff);
+                    (diff, until_open)
+                })
+        };
+
+        let (diff, until_open) = match diff {
+            Ok(diff) => diff,
+            Err(err) => return Either::A(future::err(err)),
+        };
+        let applied = diff
+            .with_parallelism(self.parallelism)
+            .apply(self.runtime.clone(), self.timeout)
+            .then(move |result| {
+                drop(applying);
+                result
+            });
+
+        match until_open {
+            Some(until_open) => {
+                info!(
+                    "Applying the deployment once a maintenance window opens in {} seconds",
+                    until_open.as_secs()
+                );
+                tokio::spawn(
+                    Delay::new(Instant::now() + until_open)
+                        .then(|_| applied)
+                        .map_err(|err| {
+                            warn!("Could not apply the deployment that was held back:");
+                            log_failure(Level::Warn, &err);
+                        }),
+                );
+                Either::B(Either::A(future::ok(Some(until_open))))
+            }
+            None => Either::B(Either::B(applied.map(|()| None))),
+        }
+    }
+
+    /// Takes over the modules that were last applied, see `adopt_existing`,
+    /// rather than starting them. Resolves to the names of the modules that
+    /// were left running as they were.
+    pub fn adopt(&self) -> impl Future<Item = Vec<String>, Error = Error> + Send {
+        let applying = match Applying::start(&self.applying) {
+            Some(applying) => applying,
+            None => return Either::A(future::err(Error::from(ErrorKind::DeploymentInProgress))),
+        };
+
+        match self.current() {
+            Some(modules) => Either::B(
+                adopt_existing(
+                    self.runtime.clone(),
+                    &modules,
+                    self.timeout,
+                    self.parallelism,
+                )
+                .then(move |result| {
+                    drop(applying);
+                    result
+                }),
+            ),
+            None => Either::A(future::ok(vec![])),
+        }
+    }
+}
+
+// How long `diff` is held back until one of `maintenance` opens, if it changes
+// the image that a module of `previous` runs and that module isn't labeled as
+// a critical update.
+fn image_change_deferred_for<T>(
+    maintenance: Option<&MaintenanceWindows>,
+    previous: &ModuleSet<T>,
+    diff: &ModuleSetDiff<T>,
+) -> Option<Duration>
+where
+    T: ImageReference,
+{
+    diff.changes()
+        .iter()
+        .filter_map(|change| match change {
+            ModuleChange::Restart(spec) => Some(spec),
+            _ => None,
+        })
+        .filter(|spec| {
+            previous
+                .modules()
+                .iter()
+                .find(|current| current.name() == spec.name())
+                .map_or(false, |current| {
+                    current.config().image() != spec.config().image()
+                })
+        })
+        .filter_map(|spec| deferred_for(maintenance, spec))
+        .next()
+}
+
+// Lets the next deployment be applied once it's dropped, whether the one
+// being applied finished or was given up on.
+struct Applying(Arc<AtomicBool>);
+
+impl Applying {
+    fn start(applying: &Arc<AtomicBool>) -> Option<Self> {
+        applying
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .ok()
+            .map(|_| Applying(applying.clone()))
+    }
+}
+
+impl Drop for Applying {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
+// The manifest that `payload` is the signed manifest of, if it's signed with
+// `trust_key`.
+fn verify_manifest(payload: &Value, trust_key: &ManifestTrustKey) -> Result<Value> {
+    let signed: SignedManifest =
+        serde_json::from_value(payload.clone()).context(ErrorKind::InvalidManifestSignature)?;
+    signed.verify(trust_key).map(Clone::clone)
+}
+
+// The module set of `payload`, or every problem with it.
+fn parse_deployment<T>(payload: &Value) -> std::result::Result<ModuleSet<T>, Vec<String>>
+where
+    T: DeserializeOwned + ImageReference,
+{
+    let modules = match payload.get("modules") {
+        Some(Value::Object(modules)) => modules,
+        Some(_) => {
+            return Err(vec![
+                "modules: must be an object of module specs by name".into()
+            ])
+        }
+        None if payload.is_object() => return Err(vec!["modules: is missing".to_string()]),
+        None => return Err(vec!["deployment must be a JSON object".to_string()]),
+    };
+
+    let mut errors = vec![];
+    let mut specs = vec![];
+    for (name, spec) in modules {
+        let path = format!("modules.{}", name);
+        let mut spec = match spec {
+            Value::Object(spec) => spec.clone(),
+            _ => {
+                errors.push(format!("{}: must be an object", path));
+                continue;
+            }
+        };
+        match spec.get("name") {
+            Some(Value::String(spec_name)) if spec_name != name => {
+                errors.push(format!("{}.name: {:?} is not its key", path, spec_name));
+                continue;
+            }
+            _ => {
+                spec.insert("name".to_string(), Value::String(name.clone()));
+            }
+        }
+
+        match serde_json::from_value::<ModuleSpec<T>>(Value::Object(spec)) {
+            Ok(spec) => match spec.validate() {
+                Ok(()) => specs.push(spec),
+                Err(err) => errors.extend(
+                    err.violations()
+                        .iter()
+                        .map(|violation| format!("{}: {}", path, violation)),
+                ),
+            },
+            Err(err) => errors.push(format!("{}: {}", path, err)),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    ModuleSet::new(specs).map_err(|err| vec![format!("modules: {}", err)])
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::FixedOffset;
+    use futures::sync::mpsc;
+    use futures::{Future, Stream};
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::hash::MessageDigest;
+    use openssl::nid::Nid;
+    use openssl::pkey::PKey;
+    use openssl::sign::Signer;
+    use serde_json::json;
+    use tempdir::TempDir;
+
+    use super::*;
+    use crate::maintenance::{MaintenanceWindow, TimeZone};
+    use crate::manifest_signature::canonicalize;
+
+    #[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
+    struct TestConfig {
+        image: String,
+    }
+
+    impl ImageReference for TestConfig {
+        fn image(&self) -> &str {
+            &self.image
+        }
+    }
+
+    fn names(modules: &ModuleSet<TestConfig>) -> Vec<&str> {
+        modules.modules().iter().map(ModuleSpec::name).collect()
+    }
+
+    fn valid_payload() -> Value {
+        json!({
+            "modules": {
+                "tempSensor": { "type": "docker", "config": { "image": "sensor:1.0" } },
+                "filter": {
+                    "type": "docker",
+                    "config": { "image": "filter:1.0" },
+                    "dependsOn": ["tempSensor"],
+                },
+            },
+        })
+    }
+
+    #[test]
+    fn valid_payload_is_applied() {
+        let dir = TempDir::new("deployment").unwrap();
+        let store = ModuleSetStore::new(dir.path().join("module_set_state"));
+        let (sender, receiver) = mpsc::unbounded();
+        let mut deployment = Deployment::<TestConfig>::new(None)
+            .with_store(store.clone())
+            .with_status_channel(sender);
+
+        let modules = deployment.apply(&valid_payload()).unwrap();
+        assert_eq!(
+            vec!["tempSensor", "filter"],
+            modules
+                .startup_order()
+                .map(ModuleSpec::name)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec!["filter", "tempSensor"],
+            names(&store.load::<TestConfig>().unwrap())
+        );
+
+        drop(deployment);
+        let statuses = receiver.collect().wait().unwrap();
+        assert_eq!(1, statuses.len());
+        assert_eq!(DeploymentState::Applied, statuses[0].state());
+        assert!(statuses[0].errors().is_empty());
+    }
+
+    #[test]
+    fn malformed_payload_keeps_last_good() {
+        let (sender, receiver) = mpsc::unbounded();
+        let mut deployment = Deployment::<TestConfig>::new(None).with_status_channel(sender);
+        deployment.apply(&valid_payload()).unwrap();
+
+        for payload in &[
+            json!("modules"),
+            json!({ "modulesContent": {} }),
+            json!({ "modules": [] }),
+            json!({
+                "modules": {
+                    "tempSensor": { "type": "docker", "config": { "image": "sensor:1.0" } },
+                    "filter": { "type": "docker" },
+                    "bad name": { "type": "docker", "config": { "image": "x:1.0" } },
+                    "alias": { "name": "other", "type": "docker", "config": { "image": "x:1.0" } },
+                },
+            }),
+            json!({
+                "modules": {
+                    "filter": {
+                        "type": "docker",
+                        "config": { "image": "filter:1.0" },
+                        "dependsOn": ["missing"],
+                    },
+                },
+            }),
+        ] {
+            let err = match deployment.apply(payload) {
+                Ok(_) => panic!("{} should be rejected", payload),
+                Err(err) => err,
+            };
+            match err.kind() {
+                ErrorKind::InvalidDeployment(_) => (),
+                kind => panic!("Expected `InvalidDeployment` error but got {:?}", kind),
+            }
+            assert_eq!(
+                vec!["filter", "tempSensor"],
+                names(deployment.current().unwrap())
+            );
+        }
+
+        drop(deployment);
+        let statuses = receiver.collect().wait().unwrap();
+        assert_eq!(6, statuses.len());
+        assert!(statuses[1..]
+            .iter()
+            .all(|status| status.state() == DeploymentState::Rejected));
+        assert_eq!(
+            &["deployment must be a JSON object".to_string()],
+            statuses[1].errors()
+        );
+        assert_eq!(&["modules: is missing".to_string()], statuses[2].errors());
+
+        // Every problem is reported at once, by where it is.
+        let errors = statuses[4].errors();
+        assert_eq!(3, errors.len(), "{:?}", errors);
+        assert!(errors[0].starts_with("modules.alias.name: "));
+        assert!(errors[1].starts_with("modules.bad name: "));
+        assert!(errors[2].starts_with("modules.filter: "));
+        assert!(statuses[5].errors()[0].starts_with("modules: "));
+    }
+
+    #[test]
+    fn unsigned_payload_is_rejected_with_trust_key() {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let private = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+        let trust_key = ManifestTrustKey::from_pem(&private.public_key_to_pem().unwrap()).unwrap();
+        let sign = |manifest: &Value| {
+            let mut signer = Signer::new(MessageDigest::sha256(), &private).unwrap();
+            signer.update(&canonicalize(manifest)).unwrap();
+            let signature = base64::encode(&signer.sign_to_vec().unwrap());
+            serde_json::to_value(SignedManifest::new(manifest.clone(), signature)).unwrap()
+        };
+
+        let (sender, receiver) = mpsc::unbounded();
+        let mut deployment = Deployment::<TestConfig>::new(None)
+            .with_status_channel(sender)
+            .with_trust_key(trust_key);
+        deployment.apply(&sign(&valid_payload())).unwrap();
+
+        let mut tampered = sign(&json!({ "modules": {} }));
+        tampered["manifest"] = valid_payload();
+        tampered["manifest"]["modules"]["tempSensor"]["config"]["image"] = json!("evil:1.0");
+        for payload in &[valid_payload(), tampered] {
+            match deployment.apply(payload).unwrap_err().kind() {
+                ErrorKind::InvalidManifestSignature => (),
+                kind => panic!(
+                    "Expected `InvalidManifestSignature` error but got {:?}",
+                    kind
+                ),
+            }
+            assert_eq!(
+                vec!["filter", "tempSensor"],
+                names(deployment.current().unwrap())
+            );
+        }
+
+        drop(deployment);
+        let statuses = receiver.collect().wait().unwrap();
+        assert_eq!(3, statuses.len());
+        assert_eq!(DeploymentState::Applied, statuses[0].state());
+        for status in &statuses[1..] {
+            assert_eq!(DeploymentState::Rejected, status.state());
+            assert_eq!(
+                &["The deployment manifest is not signed with the trusted key".to_string()],
+                status.errors()
+            );
+        }
+    }
+
+    #[test]
+    fn payload_over_budget_keeps_last_good() {
+        let (sender, receiver) = mpsc::unbounded();
+        let mut deployment = Deployment::<TestConfig>::new(None)
+            .with_status_channel(sender)
+            .with_admission(AdmissionSettings::new().with_max_modules(2));
+        deployment.apply(&valid_payload()).unwrap();
+
+        let mut payload = valid_payload();
+        payload["modules"]["extra"] = json!({ "type": "docker", "config": { "image": "x:1.0" } });
+        match deployment.apply(&payload).unwrap_err().kind() {
+            ErrorKind::DeploymentOverBudget(_) => (),
+            kind => panic!("Expected `DeploymentOverBudget` error but got {:?}", kind),
+        }
+        assert_eq!(
+            vec!["filter", "tempSensor"],
+            names(deployment.current().unwrap())
+        );
+
+        drop(deployment);
+        let statuses = receiver.collect().wait().unwrap();
+        assert_eq!(DeploymentState::Rejected, statuses[1].state());
+        assert_eq!(
+            &["The deployment does not fit on this device: 3 modules are deployed, 1 more than the maximum of 2".to_string()],
+            statuses[1].errors()
+        );
+    }
+
+    #[test]
+    fn image_changes_are_held_back_for_maintenance_windows() {
+        let start = (Utc::now() + chrono::Duration::hours(1)).time();
+        let maintenance = MaintenanceWindows::new(
+            TimeZone::Fixed(FixedOffset::east(0)),
+            vec![MaintenanceWindow::new(
+                vec![],
+                start,
+                start + chrono::Duration::hours(1),
+            )],
+        );
+        let mut deployment = Deployment::<TestConfig>::new(None);
+        let previous = deployment.apply(&valid_payload()).unwrap().clone();
+        let deferred_for = |payload: &Value| {
+            let desired = parse_deployment::<TestConfig>(payload).unwrap();
+            let diff = previous.diff(&desired).unwrap();
+            image_change_deferred_for(Some(&maintenance), &previous, &diff)
+        };
+
+        let mut payload = valid_payload();
+        payload["modules"]["tempSensor"]["config"]["image"] = json!("sensor:2.0");
+        let until_open = deferred_for(&payload).unwrap();
+        assert!(
+            until_open <= Duration::from_secs(60 * 60),
+            "{:?}",
+            until_open
+        );
+
+        // Unless the module is a critical update.
+        payload["modules"]["tempSensor"]["labels"] =
+            json!({ "net.azure-devices.edge.critical-update": "true" });
+        assert_eq!(None, deferred_for(&payload));
+
+        // Changes that keep the images, and new modules, aren't held back.
+        let mut payload = valid_payload();
+        payload["modules"]["tempSensor"]["env"] = json!({ "A": "1" });
+        payload["modules"]["extra"] = json!({ "type": "docker", "config": { "image": "x:1.0" } });
+        assert_eq!(None, deferred_for(&payload));
+    }
+
+    #[test]
+    fn empty_modules_are_applied() {
+        let mut deployment = Deployment::<TestConfig>::new(None);
+        deployment.apply(&valid_payload()).unwrap();
+
+        // Removing every module is only done when asked for.
+        let modules = deployment.apply(&json!({ "modules": {} })).unwrap();
+        assert!(modules.modules().is_empty());
+    }
+}
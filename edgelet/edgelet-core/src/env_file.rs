@@ -0,0 +1,90 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Files of environment variables on the host that modules can share, so
+//! that variables that many modules need don't have to be repeated in the
+//! spec of each of them.
+//!
+//! An env file has a `KEY=VALUE` variable per line, and blank lines and lines
+//! starting with `#` are skipped. The value is everything after the first
+//! `=`, so it can contain `=` itself.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use failure::{Fail, ResultExt};
+
+use crate::error::{Error, ErrorKind, Result};
+
+/// Reads the env files at `paths`. A variable that is in more than one of
+/// them has its value from the last one.
+pub fn load_env_files<P>(paths: &[P]) -> Result<HashMap<String, String>>
+where
+    P: AsRef<Path>,
+{
+    let mut env = HashMap::new();
+    for path in paths {
+        let display = path.as_ref().display().to_string();
+        let contents =
+            fs::read_to_string(path).with_context(|_| ErrorKind::ReadEnvFile(display.clone()))?;
+        let vars = parse_env_file(&contents)
+            .map_err(|err| Error::from(err.context(ErrorKind::ReadEnvFile(display))))?;
+        env.extend(vars);
+    }
+    Ok(env)
+}
+
+/// Parses the contents of an env file.
+pub fn parse_env_file(contents: &str) -> Result<HashMap<String, String>> {
+    let mut env = HashMap::new();
+    for (i, line) in contents.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        let mut parts = line.trim_start().splitn(2, '=');
+        match (parts.next().map(str::trim_end), parts.next()) {
+            (Some(key), Some(value)) if !key.is_empty() => {
+                env.insert(key.to_string(), value.to_string());
+            }
+            _ => return Err(Error::from(ErrorKind::InvalidEnvFileLine(i + 1))),
+        }
+    }
+    Ok(env)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_skips_comments_and_blank_lines() {
+        let env = parse_env_file(
+            "# shared settings\n\
+             \n\
+             LOG_LEVEL=debug\n\
+             \x20 # indented comment\n\
+             CONNECTION=HostName=hub;DeviceId=d1\n\
+             EMPTY=\n\
+             NOT#COMMENT=value # kept\n",
+        )
+        .unwrap();
+
+        assert_eq!(4, env.len());
+        assert_eq!("debug", env["LOG_LEVEL"]);
+        assert_eq!("HostName=hub;DeviceId=d1", env["CONNECTION"]);
+        assert_eq!("", env["EMPTY"]);
+        assert_eq!("value # kept", env["NOT#COMMENT"]);
+    }
+
+    #[test]
+    fn parse_fails_for_line_without_key() {
+        for contents in &["LOG_LEVEL=debug\nno_equals_sign\n", "A=1\n=value\n"] {
+            match parse_env_file(contents).unwrap_err().kind() {
+                ErrorKind::InvalidEnvFileLine(line) => assert_eq!(2, *line),
+                kind => panic!("Expected `InvalidEnvFileLine` error but got {:?}", kind),
+            }
+        }
+    }
+}
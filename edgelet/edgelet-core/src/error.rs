@@ -31,6 +31,12 @@ pub enum ErrorKind {
     #[fail(display = "An error occurred obtaining the certificate's details")]
     CertificateDetail,
 
+    #[fail(
+        display = "The private key file {:?} specified for '{}' must not be readable by all users. Please check its permissions.",
+        _0, _1
+    )]
+    CertificateFilePermissions(String, &'static str),
+
     #[fail(display = "An error occurred getting the certificate")]
     CertificateGet,
 
@@ -63,6 +69,18 @@ pub enum ErrorKind {
     #[fail(display = "An error occurred when signing using the device identity private key.")]
     DeviceIdentitySign,
 
+    #[fail(display = "Module {:?} is declared more than once", _0)]
+    DuplicateModuleName(String),
+
+    #[fail(display = "A deployment is already being applied")]
+    DeploymentInProgress,
+
+    #[fail(display = "The deployment does not fit on this device: {}", _0)]
+    DeploymentOverBudget(String),
+
+    #[fail(display = "The deployment is invalid: {}", _0)]
+    InvalidDeployment(String),
+
     #[fail(
         display = "Edge runtime module has not been created in IoT Hub. Please make sure this device is an IoT Edge capable device."
     )]
@@ -83,15 +101,41 @@ pub enum ErrorKind {
     #[fail(display = "Invalid or unsupported certificate issuer.")]
     InvalidIssuer,
 
+    #[fail(display = "Invalid restart policy configuration {:?}", _0)]
+    InvalidRestartPolicy(String),
+
     #[fail(display = "Invalid log tail {:?}", _0)]
     InvalidLogTail(String),
 
+    #[fail(display = "The deployment manifest is not signed with the trusted key")]
+    InvalidManifestSignature,
+
+    #[fail(
+        display = "The key that deployment manifests must be signed with is not a valid public key"
+    )]
+    InvalidManifestTrustKey,
+
     #[fail(display = "Invalid module name {:?}", _0)]
     InvalidModuleName(String),
 
     #[fail(display = "Invalid module type {:?}", _0)]
     InvalidModuleType(String),
 
+    #[fail(
+        display = "Environment variable {:?} has a secret reference without a closing brace",
+        _0
+    )]
+    InvalidSecretReference(String),
+
+    #[fail(
+        display = "Line {} of the secrets file is not of the form name=value",
+        _0
+    )]
+    InvalidSecretsFileLine(usize),
+
+    #[fail(display = "Line {} of the env file is not of the form KEY=VALUE", _0)]
+    InvalidEnvFileLine(usize),
+
     #[fail(
         display = "Error parsing URI {} specified for '{}'. Please check the config.yaml file.",
         _0, _1
@@ -104,6 +148,12 @@ pub enum ErrorKind {
     )]
     InvalidSettingsUriFilePath(String, &'static str),
 
+    #[fail(
+        display = "The schema_version {} in the config.yaml is not a whole number",
+        _0
+    )]
+    InvalidSettingsSchemaVersion(String),
+
     #[fail(display = "Invalid URL {:?}", _0)]
     InvalidUrl(String),
 
@@ -113,21 +163,84 @@ pub enum ErrorKind {
     #[fail(display = "Item not found.")]
     KeyStoreItemNotFound,
 
+    #[fail(display = "Could not load the applied modules from {:?}", _0)]
+    LoadModuleSet(String),
+
     #[fail(display = "An error occured when generating a random number.")]
     MakeRandom,
 
+    #[fail(
+        display = "Environment variable {:?} refers to secret {:?}, which is not in the secrets file",
+        _0, _1
+    )]
+    MissingSecret(String, String),
+
+    #[fail(display = "Module dependencies form a cycle between: {}", _0)]
+    ModuleDependencyCycle(String),
+
+    #[fail(display = "Could not apply modules: {}", _0)]
+    ModulesNotApplied(String),
+
     #[fail(display = "A module runtime error occurred.")]
     ModuleRuntime,
 
+    #[fail(display = "Could not compute the spec hash of module {:?}", _0)]
+    ModuleSpecHash(String),
+
+    #[fail(display = "Module {:?} did not reach the running state in time", _0)]
+    ModuleStartupTimeout(String),
+
+    #[fail(display = "Could not wait for modules to reach the running state")]
+    ModuleStartupTimer,
+
     #[fail(display = "Unable to parse since.")]
     ParseSince,
 
+    #[fail(display = "Could not prepare the modules for the upgrade")]
+    PrepareUpgrade,
+
+    #[fail(
+        display = "Could not read the file {:?} specified for '{}'. Please check the config.yaml file.",
+        _0, _1
+    )]
+    ReadCertificateFile(String, &'static str),
+
+    #[fail(display = "Could not read the env file {:?}", _0)]
+    ReadEnvFile(String),
+
+    #[fail(display = "Could not read the secrets file {:?}", _0)]
+    ReadSecretsFile(String),
+
+    #[fail(display = "Could not resume the modules after the upgrade")]
+    ResumeFromUpgrade,
+
+    #[fail(display = "Could not save the applied modules to {:?}", _0)]
+    SaveModuleSet(String),
+
+    #[fail(
+        display = "The secrets file {:?} must not be accessible by users other than its owner",
+        _0
+    )]
+    SecretsFilePermissions(String),
+
     #[fail(display = "Signing error occurred.")]
     Sign,
 
     #[fail(display = "Signing error occurred. Invalid key length: {}", _0)]
     SignInvalidKeyLength(usize),
 
+    #[fail(display = "Signature algorithm {:?} is not supported", _0)]
+    UnsupportedSignatureAlgorithm(String),
+
+    #[fail(
+        display = "The config.yaml has schema_version {}, but this version of iotedged only supports up to {}. Was it written for a newer release?",
+        _0, _1
+    )]
+    UnsupportedSettingsSchemaVersion(u32, u32),
+
+    #[fail(display = "Module {:?} depends on unknown module {:?}", _0, _1)]
+    UnknownModuleDependency(String, String),
+
     #[fail(
         display = "URI {} is unsupported for '{}'. Please check the config.yaml file.",
         _0, _1
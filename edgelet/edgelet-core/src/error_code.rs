@@ -0,0 +1,113 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Stable codes for the errors that the daemon's HTTP APIs return, next to
+//! their messages, so that clients can tell failures apart without matching on
+//! messages that may be reworded. A code is never renamed or reused once it
+//! has been released.
+
+use std::fmt;
+
+use failure::{Context, Fail};
+
+#[derive(Clone, Copy, Debug, serde_derive::Deserialize, Eq, PartialEq, serde_derive::Serialize)]
+pub enum ErrorCode {
+    /// The certificate the request is for doesn't exist.
+    CertificateNotFound,
+    /// The target of the request is busy with another operation.
+    Conflict,
+    /// The caller isn't allowed to use this API.
+    Forbidden,
+    /// The module's image is not on the device and wasn't pulled.
+    ImageNotFound,
+    /// The module's image couldn't be pulled from its registry.
+    ImagePullFailed,
+    /// Something failed that the request can't be blamed for.
+    Internal,
+    /// The request is malformed, such as a parameter that doesn't parse.
+    InvalidRequest,
+    /// The module spec was rejected, by the daemon or the container runtime.
+    InvalidModuleSpec,
+    /// A request to IoT Hub failed.
+    IotHubRequestFailed,
+    /// The module the request is for doesn't exist.
+    ModuleNotFound,
+    /// The module isn't running.
+    ModuleNotRunning,
+    /// The container runtime didn't respond in time.
+    RuntimeTimeout,
+    /// The caller isn't authorized for the request.
+    Unauthorized,
+    /// The `api-version` of the request is not supported.
+    UnsupportedApiVersion,
+}
+
+impl ErrorCode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::CertificateNotFound => "CertificateNotFound",
+            ErrorCode::Conflict => "Conflict",
+            ErrorCode::Forbidden => "Forbidden",
+            ErrorCode::ImageNotFound => "ImageNotFound",
+            ErrorCode::ImagePullFailed => "ImagePullFailed",
+            ErrorCode::Internal => "Internal",
+            ErrorCode::InvalidRequest => "InvalidRequest",
+            ErrorCode::InvalidModuleSpec => "InvalidModuleSpec",
+            ErrorCode::IotHubRequestFailed => "IotHubRequestFailed",
+            ErrorCode::ModuleNotFound => "ModuleNotFound",
+            ErrorCode::ModuleNotRunning => "ModuleNotRunning",
+            ErrorCode::RuntimeTimeout => "RuntimeTimeout",
+            ErrorCode::Unauthorized => "Unauthorized",
+            ErrorCode::UnsupportedApiVersion => "UnsupportedApiVersion",
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The kinds of error of a crate, some of which clients know by a code.
+pub trait ErrorCodeKind {
+    fn error_code(&self) -> Option<ErrorCode>;
+}
+
+/// The code of `fail` if it is either an error kind `K` or the context of
+/// one.
+pub fn kind_error_code<K>(fail: &dyn Fail) -> Option<ErrorCode>
+where
+    K: ErrorCodeKind + Fail,
+{
+    if let Some(kind) = fail.downcast_ref::<Context<K>>() {
+        kind.get_context().error_code()
+    } else if let Some(kind) = fail.downcast_ref::<K>() {
+        kind.error_code()
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn code_is_serialized_as_its_name() {
+        for code in &[
+            ErrorCode::ImagePullFailed,
+            ErrorCode::ModuleNotFound,
+            ErrorCode::InvalidModuleSpec,
+            ErrorCode::UnsupportedApiVersion,
+        ] {
+            assert_eq!(
+                format!("\"{}\"", code),
+                serde_json::to_string(code).unwrap()
+            );
+            assert_eq!(
+                *code,
+                serde_json::from_str::<ErrorCode>(&format!("\"{}\"", code.as_str())).unwrap()
+            );
+        }
+    }
+}
@@ -0,0 +1,301 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Module lifecycle events, published by the runtime as modules are created,
+//! started and stopped, so that a subscriber can follow them without polling
+//! the module list.
+//!
+//! Events are numbered in the order they are published. A subscriber asks for
+//! the events from the number after the last one it saw, and the most recent
+//! events are buffered so that one that stops asking for a while doesn't miss
+//! any, as long as it's back before the buffer wraps around.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::prelude::*;
+use futures::future::{self, Either};
+use futures::sync::oneshot;
+use futures::Future;
+use tokio::timer::Delay;
+
+use crate::error::Error;
+
+/// The number of events that are kept for subscribers that haven't seen them.
+pub const DEFAULT_EVENT_BUFFER_SIZE: usize = 256;
+
+#[derive(Clone, Copy, Debug, serde_derive::Deserialize, PartialEq, serde_derive::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModuleLifecycleEventKind {
+    Created,
+    Started,
+    Stopped,
+    Failed,
+    /// The module was killed for running out of memory.
+    #[serde(rename = "oomkilled")]
+    OomKilled,
+}
+
+#[derive(Clone, Debug, serde_derive::Deserialize, PartialEq, serde_derive::Serialize)]
+pub struct ModuleLifecycleEvent {
+    name: String,
+    kind: ModuleLifecycleEventKind,
+    timestamp: DateTime<Utc>,
+}
+
+impl ModuleLifecycleEvent {
+    pub fn new(name: String, kind: ModuleLifecycleEventKind, timestamp: DateTime<Utc>) -> Self {
+        ModuleLifecycleEvent {
+            name,
+            kind,
+            timestamp,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn kind(&self) -> ModuleLifecycleEventKind {
+        self.kind
+    }
+
+    pub fn timestamp(&self) -> DateTime<Utc> {
+        self.timestamp
+    }
+}
+
+/// The events a subscriber hasn't seen yet.
+#[derive(Clone, Debug, PartialEq, serde_derive::Serialize)]
+pub struct ModuleLifecycleEvents {
+    events: Vec<ModuleLifecycleEvent>,
+    next: u64,
+    missed: u64,
+}
+
+impl ModuleLifecycleEvents {
+    pub fn events(&self) -> &[ModuleLifecycleEvent] {
+        &self.events
+    }
+
+    /// The number to ask for the events after these from.
+    pub fn next(&self) -> u64 {
+        self.next
+    }
+
+    /// How many events were dropped from the buffer before the subscriber
+    /// asked for them.
+    pub fn missed(&self) -> u64 {
+        self.missed
+    }
+}
+
+#[derive(Clone)]
+pub struct ModuleEvents {
+    inner: Arc<Mutex<Inner>>,
+}
+
+struct Inner {
+    capacity: usize,
+    events: VecDeque<ModuleLifecycleEvent>,
+    next: u64,
+    waiters: Vec<oneshot::Sender<()>>,
+}
+
+impl Default for ModuleEvents {
+    fn default() -> Self {
+        ModuleEvents::new(DEFAULT_EVENT_BUFFER_SIZE)
+    }
+}
+
+impl ModuleEvents {
+    /// Keeps the last `capacity` events, which must be at least one.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "event buffer must have room for an event");
+        ModuleEvents {
+            inner: Arc::new(Mutex::new(Inner {
+                capacity,
+                events: VecDeque::with_capacity(capacity),
+                next: 0,
+                waiters: Vec::new(),
+            })),
+        }
+    }
+
+    pub fn publish(&self, name: &str, kind: ModuleLifecycleEventKind) {
+        let mut inner = self.inner.lock().expect("module events lock poisoned");
+        if inner.events.len() == inner.capacity {
+            inner.events.pop_front();
+        }
+        inner.events.push_back(ModuleLifecycleEvent::new(
+            name.to_string(),
+            kind,
+            Utc::now(),
+        ));
+        inner.next += 1;
+
+        for waiter in inner.waiters.drain(..) {
+            let _ = waiter.send(());
+        }
+    }
+
+    /// The number that the next event to be published gets, to wait for the
+    /// events after now from.
+    pub fn next(&self) -> u64 {
+        self.inner.lock().expect("module events lock poisoned").next
+    }
+
+    /// The buffered events numbered `since` or later.
+    ///
+    /// A `since` past the last event is from before the daemon restarted and
+    /// its events were numbered from zero again, so it gets all of them.
+    pub fn since(&self, since: u64) -> ModuleLifecycleEvents {
+        let inner = self.inner.lock().expect("module events lock poisoned");
+        let since = if since > inner.next { 0 } else { since };
+        let oldest = inner.next - inner.events.len() as u64;
+        let start = since.max(oldest);
+
+        #[allow(clippy::cast_possible_truncation)]
+        let events = inner
+            .events
+            .iter()
+            .skip((start - oldest) as usize)
+            .cloned()
+            .collect();
+        ModuleLifecycleEvents {
+            events,
+            next: inner.next,
+            missed: start - since,
+        }
+    }
+
+    /// Like `since`, but if there are no events yet waits up to `timeout` for
+    /// one to be published. A zero `timeout` doesn't wait.
+    pub fn wait(
+        &self,
+        since: u64,
+        timeout: Duration,
+    ) -> impl Future<Item = ModuleLifecycleEvents, Error = Error> + Send {
+        let published = {
+            let mut inner = self.inner.lock().expect("module events lock poisoned");
+            if since == inner.next && timeout > Duration::from_secs(0) {
+                let (sender, receiver) = oneshot::channel();
+                inner.waiters.retain(|waiter| !waiter.is_canceled());
+                inner.waiters.push(sender);
+                Some(receiver)
+            } else {
+                None
+            }
+        };
+
+        let events = self.clone();
+        match published {
+            Some(published) => Either::A(
+                published
+                    .map_err(|_| ())
+                    .select2(Delay::new(Instant::now() + timeout).map_err(|_| ()))
+                    .then(move |_| Ok(events.since(since))),
+            ),
+            None => Either::B(future::ok(events.since(since))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio::runtime::current_thread::Runtime;
+
+    fn kinds(events: &ModuleLifecycleEvents) -> Vec<(&str, ModuleLifecycleEventKind)> {
+        events
+            .events()
+            .iter()
+            .map(|event| (event.name(), event.kind()))
+            .collect()
+    }
+
+    #[test]
+    fn events_are_in_the_order_they_are_published() {
+        let events = ModuleEvents::default();
+        events.publish("m1", ModuleLifecycleEventKind::Created);
+        events.publish("m2", ModuleLifecycleEventKind::Created);
+        events.publish("m1", ModuleLifecycleEventKind::Started);
+
+        let all = events.since(0);
+        assert_eq!(
+            vec![
+                ("m1", ModuleLifecycleEventKind::Created),
+                ("m2", ModuleLifecycleEventKind::Created),
+                ("m1", ModuleLifecycleEventKind::Started),
+            ],
+            kinds(&all)
+        );
+        assert_eq!(3, all.next());
+        assert_eq!(0, all.missed());
+        assert!(all.events()[0].timestamp() <= all.events()[2].timestamp());
+
+        events.publish("m1", ModuleLifecycleEventKind::Stopped);
+        let rest = events.since(all.next());
+        assert_eq!(
+            vec![("m1", ModuleLifecycleEventKind::Stopped)],
+            kinds(&rest)
+        );
+        assert_eq!(4, rest.next());
+    }
+
+    #[test]
+    fn events_that_fall_out_of_the_buffer_are_missed() {
+        let events = ModuleEvents::new(2);
+        events.publish("m1", ModuleLifecycleEventKind::Created);
+        events.publish("m1", ModuleLifecycleEventKind::Started);
+        events.publish("m1", ModuleLifecycleEventKind::Stopped);
+
+        let rest = events.since(0);
+        assert_eq!(
+            vec![
+                ("m1", ModuleLifecycleEventKind::Started),
+                ("m1", ModuleLifecycleEventKind::Stopped),
+            ],
+            kinds(&rest)
+        );
+        assert_eq!(1, rest.missed());
+        assert_eq!(3, rest.next());
+    }
+
+    #[test]
+    fn since_past_the_last_event_starts_over() {
+        let events = ModuleEvents::default();
+        events.publish("m1", ModuleLifecycleEventKind::Created);
+
+        let all = events.since(10);
+        assert_eq!(vec![("m1", ModuleLifecycleEventKind::Created)], kinds(&all));
+        assert_eq!(1, all.next());
+    }
+
+    #[test]
+    fn wait_returns_when_an_event_is_published() {
+        let events = ModuleEvents::default();
+        let wait = events.wait(0, Duration::from_secs(60));
+        events.publish("m1", ModuleLifecycleEventKind::Failed);
+
+        let published = Runtime::new().unwrap().block_on(wait).unwrap();
+        assert_eq!(
+            vec![("m1", ModuleLifecycleEventKind::Failed)],
+            kinds(&published)
+        );
+    }
+
+    #[test]
+    fn wait_times_out_without_events() {
+        let events = ModuleEvents::default();
+        events.publish("m1", ModuleLifecycleEventKind::Created);
+
+        let none = Runtime::new()
+            .unwrap()
+            .block_on(events.wait(1, Duration::from_millis(10)))
+            .unwrap();
+        assert!(none.events().is_empty());
+        assert_eq!(1, none.next());
+    }
+}
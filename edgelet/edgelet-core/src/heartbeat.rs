@@ -0,0 +1,148 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Detects the supervision loop of the daemon getting stuck, such as on a
+//! blocking call, which leaves the process looking alive to its service
+//! manager while it doesn't do anything.
+//!
+//! The loop beats a `Heartbeat` every time around, and a `HeartbeatMonitor`
+//! on a thread of its own reports when it hasn't for longer than a timeout.
+
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Debug)]
+pub struct Heartbeat {
+    last: Arc<Mutex<Instant>>,
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Heartbeat::new()
+    }
+}
+
+impl Heartbeat {
+    pub fn new() -> Self {
+        Heartbeat {
+            last: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    pub fn beat(&self) {
+        *self.last.lock().expect("heartbeat lock poisoned") = Instant::now();
+    }
+
+    /// How long it has been since the last beat.
+    pub fn elapsed(&self) -> Duration {
+        self.last.lock().expect("heartbeat lock poisoned").elapsed()
+    }
+}
+
+/// Watches a heartbeat until it is dropped.
+#[derive(Debug)]
+pub struct HeartbeatMonitor {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl HeartbeatMonitor {
+    /// Calls `on_stall` with how long it has been since the last beat once
+    /// `heartbeat` hasn't beaten for `timeout`. It is called once per stall,
+    /// and again only after the heartbeat has resumed and stalled anew.
+    pub fn start<F>(heartbeat: Heartbeat, timeout: Duration, mut on_stall: F) -> io::Result<Self>
+    where
+        F: FnMut(Duration) + Send + 'static,
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let check_interval = timeout / 4;
+
+        let thread = thread::Builder::new()
+            .name("heartbeat-monitor".to_string())
+            .spawn(move || {
+                let mut stalled = false;
+                loop {
+                    thread::park_timeout(check_interval);
+                    if stopped.load(Ordering::SeqCst) {
+                        break;
+                    }
+
+                    let elapsed = heartbeat.elapsed();
+                    if elapsed < timeout {
+                        stalled = false;
+                    } else if !stalled {
+                        stalled = true;
+                        on_stall(elapsed);
+                    }
+                }
+            })?;
+
+        Ok(HeartbeatMonitor {
+            stop,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for HeartbeatMonitor {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    #[test]
+    fn stalled_heartbeat_is_reported_once() {
+        let heartbeat = Heartbeat::new();
+        let (sender, receiver) = mpsc::channel();
+        let _monitor = HeartbeatMonitor::start(
+            heartbeat.clone(),
+            Duration::from_millis(50),
+            move |elapsed| sender.send(elapsed).unwrap(),
+        )
+        .unwrap();
+
+        let elapsed = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(elapsed >= Duration::from_millis(50));
+        // the same stall isn't reported again
+        assert!(receiver.recv_timeout(Duration::from_millis(200)).is_err());
+
+        // but a new one is, once the heartbeat has resumed
+        heartbeat.beat();
+        thread::sleep(Duration::from_millis(30));
+        heartbeat.beat();
+        assert!(receiver.recv_timeout(Duration::from_secs(5)).is_ok());
+    }
+
+    #[test]
+    fn beating_heartbeat_is_not_reported() {
+        let heartbeat = Heartbeat::new();
+        let (sender, receiver) = mpsc::channel();
+        let monitor = HeartbeatMonitor::start(
+            heartbeat.clone(),
+            Duration::from_millis(200),
+            move |elapsed| sender.send(elapsed).unwrap(),
+        )
+        .unwrap();
+
+        for _ in 0..20 {
+            heartbeat.beat();
+            thread::sleep(Duration::from_millis(10));
+        }
+        drop(monitor);
+
+        assert!(receiver.try_recv().is_err());
+    }
+}
@@ -18,48 +18,112 @@ use url::Url;
 
 mod authentication;
 mod authorization;
+mod cert_files;
 mod certificate_properties;
+mod create_options;
 pub mod crypto;
+mod deployment;
+mod env_file;
 mod error;
+mod error_code;
+mod events;
+mod heartbeat;
 mod identity;
 mod logs;
+mod maintenance;
+mod manifest_signature;
 mod module;
+mod module_set;
+mod module_set_store;
 mod network;
 mod parse_since;
+mod redact;
+mod secrets;
 mod settings;
+mod settings_migration;
+mod upgrade;
+mod upstream;
 pub mod watchdog;
 pub mod workload;
 
 pub use authentication::Authenticator;
 pub use authorization::{AuthId, ModuleId, Policy};
+pub use cert_files::check_certificate_files;
 pub use certificate_properties::{CertificateIssuer, CertificateProperties, CertificateType};
+pub use create_options::{
+    join_create_options, merge_create_options, normalize_create_options, validate_create_options,
+    MAX_CREATE_OPTIONS_LEN,
+};
 pub use crypto::{
     Certificate, CreateCertificate, Decrypt, Encrypt, GetDeviceIdentityCertificate, GetHsmVersion,
     GetIssuerAlias, GetTrustBundle, KeyBytes, KeyIdentity, KeyStore, MakeRandom,
     MasterEncryptionKey, PrivateKey, Signature, IOTEDGED_CA_ALIAS,
 };
+pub use deployment::{
+    Deployment, DeploymentReconciler, DeploymentState, DeploymentStatus, LastDeploymentStatus,
+};
 pub use error::{Error, ErrorKind};
+pub use error_code::{kind_error_code, ErrorCode, ErrorCodeKind};
+pub use events::{
+    ModuleEvents, ModuleLifecycleEvent, ModuleLifecycleEventKind, ModuleLifecycleEvents,
+    DEFAULT_EVENT_BUFFER_SIZE,
+};
+pub use heartbeat::{Heartbeat, HeartbeatMonitor};
 pub use identity::{AuthType, Identity, IdentityManager, IdentityOperation, IdentitySpec};
-pub use logs::{Chunked, LogChunk, LogDecode};
+pub use logs::{
+    decode_logs, decode_logs_with_max_line_length, Chunked, LogChunk, LogDecode,
+    DEFAULT_MAX_LOG_LINE_LENGTH,
+};
+pub use maintenance::{
+    deferred_for, is_critical_update, MaintenanceWindow, MaintenanceWindows, TimeZone,
+    CRITICAL_UPDATE_LABEL,
+};
+pub use manifest_signature::{canonicalize, ManifestTrustKey, SignedManifest};
 pub use module::{
-    DiskInfo, ImagePullPolicy, LogOptions, LogTail, MakeModuleRuntime, Module, ModuleOperation,
-    ModuleRegistry, ModuleRuntime, ModuleRuntimeErrorReason, ModuleRuntimeState, ModuleSpec,
-    ModuleStatus, ModuleTop, ProvisioningResult, RegistryOperation, RuntimeOperation, SystemInfo,
-    SystemResources,
+    is_valid_image_digest, validate_dns_servers, validate_global_env, validate_injected_labels,
+    validate_module_name, DiskInfo, ExecOutput, ExitReason, HealthState, ImagePruneFilter,
+    ImagePullPolicy, ImageReference, LogOptions, LogTail, MakeModuleRuntime, Module, ModuleBind,
+    ModuleDevice, ModuleError, ModuleErrorCode, ModuleLogConfig, ModuleNetwork, ModuleOperation,
+    ModuleRegistry, ModuleResources, ModuleRuntime, ModuleRuntimeErrorReason, ModuleRuntimeState,
+    ModuleSpec, ModuleSpecViolation, ModuleStats, ModuleStatus, ModuleTmpfs, ModuleTop,
+    ModuleUlimit, PortBinding, PreStopHook, ProvisioningResult, PrunedImages, RegistryOperation,
+    RestartPolicy, RuntimeOperation, SystemInfo, SystemResources, ValidationError,
+    DEFAULT_PRE_STOP_TIMEOUT_SECS, DEFAULT_STARTUP_ORDER, MAX_STOP_TIMEOUT_SECS,
+    MIN_MEMORY_LIMIT_BYTES, PRE_STOP_LABEL, RESERVED_LABEL_PREFIX, SPEC_FIELDS_LABEL,
+    SPEC_HASH_LABEL,
+};
+pub use module_set::{
+    adopt_existing, pull_image, replace_module, update_module, ModuleChange, ModuleSet,
+    ModuleSetDiff, PlannedAction, PlannedChange,
 };
+pub use module_set_store::ModuleSetStore;
 pub use network::{Ipam, IpamConfig, MobyNetwork, Network};
 pub use parse_since::parse_since;
+pub use redact::{redact_failure, redact_secrets};
+pub use secrets::{has_secret_references, HostSecrets};
 pub use settings::{
-    AttestationMethod, Certificates, Connect, Dps, External, Listen, Manual, ManualAuthMethod,
-    ManualDeviceConnectionString, ManualX509Auth, Protocol, Provisioning, ProvisioningType,
-    RetryLimit, RuntimeSettings, Settings, SymmetricKeyAttestationInfo, TpmAttestationInfo,
-    WatchdogSettings, X509AttestationInfo,
+    AdmissionSettings, AttestationMethod, AuditSettings, BackoffPolicy, Certificates, Connect, Dps,
+    DpsRetry, External, ExternalCommand, HealthSettings, Listen, LogForwardingSettings,
+    LogsSettings, ManagementTls, Manual, ManualAuthMethod, ManualDeviceConnectionString,
+    ManualX509Auth, OcspFailurePolicy, OcspSettings, Protocol, Provisioning, ProvisioningType,
+    RetryLimit, RuntimeSettings, Settings, SocketOwnership, SymmetricKeyAttestationInfo,
+    SyslogFacility, TpmAttestationInfo, UpstreamSettings, WatchdogSettings, WebhookSettings,
+    X509AttestationInfo,
 };
+pub use settings_migration::{migrate_settings, SettingsMigration, SETTINGS_SCHEMA_VERSION};
+pub use upgrade::{UpgradeGuard, UpgradeModule, UpgradeState};
+pub use upstream::UpstreamEndpoints;
 pub use workload::WorkloadConfig;
 
 /// This is the default auto generated certificate life
 pub const DEFAULT_AUTO_GENERATED_CA_LIFETIME_DAYS: u16 = 90;
 
+/// This is the default number of days before a certificate expires that it is reported as expiring.
+pub const DEFAULT_CERT_EXPIRY_WARNING_DAYS: u16 = 7;
+
+/// This is the default percentage of a module server certificate's lifetime after which it is re-issued.
+pub const DEFAULT_SERVER_CERT_RENEWAL_THRESHOLD_PERCENT: u8 = 80;
+
 lazy_static! {
     static ref VERSION: &'static str =
         option_env!("VERSION").unwrap_or_else(|| include_str!("../../version.txt").trim());
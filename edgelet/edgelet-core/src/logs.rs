@@ -3,13 +3,21 @@
 use std::cmp;
 use std::io;
 
-use bytes::{Buf, BufMut, Bytes, BytesMut, IntoBuf};
+use bytes::{BufMut, Bytes, BytesMut};
+use failure::Fail;
 use futures::prelude::*;
-use futures::try_ready;
-use tokio::codec::length_delimited;
-use tokio::codec::FramedRead;
+use tokio::codec::{Decoder, FramedRead};
 use tokio::io::AsyncRead;
 
+/// The most bytes of a frame that are kept by default, so that a module that
+/// writes a huge line without a newline can't make the daemon buffer all of
+/// it.
+pub const DEFAULT_MAX_LOG_LINE_LENGTH: usize = 1024 * 1024;
+
+// A frame's stream type, 3 unused bytes and the big-endian length of its
+// payload.
+const HEADER_LENGTH: usize = 8;
+
 /// Logs parser
 /// Logs are emitted with a simple header to specify stdout or stderr
 ///
@@ -22,9 +30,13 @@ use tokio::io::AsyncRead;
 ///       unused
 ///
 /// The following set of structs converts a `Stream<&[u8]>` into a `Stream<LogChunk>`
-/// by implementing [`AsyncRead`] on `Stream<&[u8]>` and then using the `length_delimited`
-/// decoder in tokio to emit [`BytesMut`] with complete frames. The [`LogChunk`]
-/// is then constructed from these [`BytesMut`]s
+/// by implementing [`AsyncRead`] on `Stream<&[u8]>` and then using a decoder
+/// that emits a [`LogChunk`] for each complete frame.
+///
+/// A frame that is longer than the maximum line length is truncated to it,
+/// with a marker that says how many bytes were dropped. The rest of the frame
+/// is skipped as it is read rather than buffered, so the frames after it are
+/// decoded as usual.
 
 #[derive(Debug, PartialEq)]
 pub enum LogChunk {
@@ -34,19 +46,35 @@ pub enum LogChunk {
     Unknown(Bytes),
 }
 
+impl LogChunk {
+    fn new(stream_type: u8, payload: Bytes) -> Self {
+        match stream_type {
+            0 => LogChunk::Stdin(payload),
+            1 => LogChunk::Stdout(payload),
+            2 => LogChunk::Stderr(payload),
+            _ => LogChunk::Unknown(payload),
+        }
+    }
+}
+
 pub struct LogDecode<T: AsyncRead> {
-    inner: FramedRead<T, length_delimited::LengthDelimitedCodec>,
+    inner: FramedRead<T, LogCodec>,
 }
 
 impl<T: AsyncRead> LogDecode<T> {
     pub fn new(inner: T) -> Self {
-        let delimited = length_delimited::Builder::new()
-            .length_field_offset(4)
-            .length_field_length(4)
-            .length_adjustment(8)
-            .num_skip(0)
-            .new_read(inner);
-        LogDecode { inner: delimited }
+        LogDecode::with_max_line_length(inner, DEFAULT_MAX_LOG_LINE_LENGTH)
+    }
+
+    /// Truncates the frames that are longer than `max_line_length` bytes.
+    pub fn with_max_line_length(inner: T, max_line_length: usize) -> Self {
+        let codec = LogCodec {
+            max_line_length,
+            skipping: 0,
+        };
+        LogDecode {
+            inner: FramedRead::new(inner, codec),
+        }
     }
 }
 
@@ -55,31 +83,89 @@ impl<T: AsyncRead> Stream for LogDecode<T> {
     type Error = io::Error;
 
     fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
-        // This parser assumes that the length_delimited decoder
-        // wraps this decoder. In this case, the BytesMut is
-        // guaranteed to have a full frame worth of bytes.
-        // We can simply read the needed bytes out of the BytesMut
-        // to construct the parsed Frame.
-
-        let option = try_ready!(self.inner.poll());
-        let result = option.map(|bytes| {
-            let mut buf = bytes.into_buf();
-            let stream_type = buf.get_u8();
-            buf.advance(3);
-            let _length = buf.get_u32_be();
-            let payload: Bytes = buf.collect();
-
-            match stream_type {
-                0 => LogChunk::Stdin(payload),
-                1 => LogChunk::Stdout(payload),
-                2 => LogChunk::Stderr(payload),
-                _ => LogChunk::Unknown(payload),
+        self.inner.poll()
+    }
+}
+
+struct LogCodec {
+    max_line_length: usize,
+    // The bytes of a truncated frame that haven't been read yet.
+    skipping: usize,
+}
+
+impl Decoder for LogCodec {
+    type Item = LogChunk;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<LogChunk>> {
+        if self.skipping > 0 {
+            let skipped = cmp::min(self.skipping, src.len());
+            src.advance(skipped);
+            self.skipping -= skipped;
+            if self.skipping > 0 {
+                return Ok(None);
             }
-        });
-        Ok(Async::Ready(result))
+        }
+
+        if src.len() < HEADER_LENGTH {
+            return Ok(None);
+        }
+        let stream_type = src[0];
+        let length = (usize::from(src[4]) << 24)
+            | (usize::from(src[5]) << 16)
+            | (usize::from(src[6]) << 8)
+            | usize::from(src[7]);
+
+        let kept = cmp::min(length, self.max_line_length);
+        if src.len() < HEADER_LENGTH + kept {
+            src.reserve(HEADER_LENGTH + kept - src.len());
+            return Ok(None);
+        }
+
+        let frame = src.split_to(HEADER_LENGTH + kept).freeze();
+        let payload = frame.slice_from(HEADER_LENGTH);
+        if kept == length {
+            return Ok(Some(LogChunk::new(stream_type, payload)));
+        }
+
+        self.skipping = length - kept;
+        let marker = format!("... [truncated {} bytes]\n", self.skipping);
+        let mut truncated = BytesMut::with_capacity(kept + marker.len());
+        truncated.put_slice(&payload);
+        truncated.put_slice(marker.as_bytes());
+        Ok(Some(LogChunk::new(stream_type, truncated.freeze())))
     }
 }
 
+/// De-frames the raw logs of `ModuleRuntime::logs` into the chunks that the
+/// module wrote to stdout and stderr. Chunks are truncated to
+/// `DEFAULT_MAX_LOG_LINE_LENGTH`.
+pub fn decode_logs<S>(logs: S) -> impl Stream<Item = LogChunk, Error = io::Error> + Send
+where
+    S: Stream + Send,
+    S::Item: AsRef<[u8]>,
+    S::Error: Fail,
+{
+    decode_logs_with_max_line_length(logs, DEFAULT_MAX_LOG_LINE_LENGTH)
+}
+
+/// Like `decode_logs`, truncating the chunks that are longer than
+/// `max_line_length` bytes.
+pub fn decode_logs_with_max_line_length<S>(
+    logs: S,
+    max_line_length: usize,
+) -> impl Stream<Item = LogChunk, Error = io::Error> + Send
+where
+    S: Stream + Send,
+    S::Item: AsRef<[u8]>,
+    S::Error: Fail,
+{
+    LogDecode::with_max_line_length(
+        Chunked::new(logs.map_err(|err| io::Error::new(io::ErrorKind::Other, err.compat()))),
+        max_line_length,
+    )
+}
+
 pub struct Chunked<S, C>
 where
     C: AsRef<[u8]>,
@@ -126,36 +212,28 @@ where
     S: Stream<Item = C, Error = io::Error>,
 {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Bytes left over from the last read are handed out before polling
+        // for more, or the end of a followed log would wait for the module to
+        // write again.
+        if self.remaining.is_some() {
+            return self.read_remaining(buf);
+        }
+
         match self.inner.poll() {
             Ok(Async::Ready(Some(ref t))) => {
-                let read = self.read_remaining(buf)?;
-                let (result, new_remaining) = if let Some(ref mut remaining) = self.remaining {
-                    // There's still some data waiting to be written into the read buffer.
-                    // Append to the remaining buffer
-                    // Return the amount read from remaining
-                    let mut r = BytesMut::with_capacity(remaining.len() + t.as_ref().len());
-                    r.put_slice(remaining);
-                    r.put_slice(t.as_ref());
-                    (Ok(read), Some(r.freeze()))
-                } else {
-                    // The remaining buffer was cleared.
-                    // Attempt to read everything from the poll and add to the remaining buffer
-                    // if needed.
-                    let data = Bytes::from(t.as_ref());
-                    let amt = cmp::min(data.len(), buf.len() - read);
-                    let (a, b) = data.split_at(amt);
-                    buf[read..read + amt].copy_from_slice(a);
-
-                    if b.is_empty() {
-                        (Ok(amt + read), None)
-                    } else {
-                        (Ok(amt + read), Some(Bytes::from(b)))
-                    }
-                };
-                self.remaining = new_remaining;
-                result
+                // Attempt to read everything from the poll and add to the remaining buffer
+                // if needed.
+                let data = t.as_ref();
+                let amt = cmp::min(data.len(), buf.len());
+                let (a, b) = data.split_at(amt);
+                buf[..amt].copy_from_slice(a);
+
+                if !b.is_empty() {
+                    self.remaining = Some(Bytes::from(b));
+                }
+                Ok(amt)
             }
-            Ok(Async::Ready(None)) => self.read_remaining(buf),
+            Ok(Async::Ready(None)) => Ok(0),
             Ok(Async::NotReady) => Err(io::Error::from(io::ErrorKind::WouldBlock)),
             Err(e) => Err(e),
         }
@@ -175,7 +253,11 @@ mod tests {
 
     use std::io::Read;
 
-    use futures::stream::iter_ok;
+    use futures::future;
+    use futures::stream::{self, iter_ok};
+    use futures::sync::mpsc;
+
+    use crate::error::{Error, ErrorKind};
 
     #[test]
     fn smoke_test() {
@@ -201,6 +283,114 @@ mod tests {
         assert_eq!(expected, decoded);
     }
 
+    #[test]
+    fn decode_logs_separates_stdout_and_stderr() {
+        let chunks = vec![
+            &[
+                0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, b'o', b'u', b't',
+            ][..],
+            &[
+                0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, b'e', b'r', b'r',
+            ][..],
+        ];
+
+        let decoded = decode_logs(iter_ok::<_, Error>(chunks))
+            .collect()
+            .wait()
+            .unwrap();
+        assert_eq!(
+            vec![
+                LogChunk::Stdout(Bytes::from("out")),
+                LogChunk::Stderr(Bytes::from("err")),
+            ],
+            decoded
+        );
+    }
+
+    #[test]
+    fn decode_logs_fails_with_the_stream_error() {
+        let logs = iter_ok::<_, Error>(vec![&[0x01_u8, 0x00][..]])
+            .chain(stream::once(Err(Error::from(ErrorKind::ModuleRuntime))));
+
+        let err = decode_logs(logs).collect().wait().unwrap_err();
+        assert_eq!(io::ErrorKind::Other, err.kind());
+        assert_eq!("A module runtime error occurred.", err.to_string());
+    }
+
+    #[test]
+    fn oversized_frame_is_truncated_without_corrupting_the_next_one() {
+        let mut line = vec![0x01, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00];
+        line.extend(vec![b'a'; 0x10_0000]);
+        let next = vec![
+            0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, b'e', b'r', b'r',
+        ];
+        // Fed in small chunks, like a slow stream, to check that the rest of
+        // the frame isn't buffered.
+        let logs: Vec<Vec<u8>> = line
+            .chunks(4096)
+            .chain(next.chunks(5))
+            .map(<[u8]>::to_vec)
+            .collect();
+
+        let mut decoded = decode_logs_with_max_line_length(iter_ok::<_, Error>(logs), 16)
+            .collect()
+            .wait()
+            .unwrap();
+
+        assert_eq!(2, decoded.len());
+        assert_eq!(LogChunk::Stderr(Bytes::from("err")), decoded.pop().unwrap());
+        assert_eq!(
+            LogChunk::Stdout(Bytes::from(
+                "aaaaaaaaaaaaaaaa... [truncated 1048560 bytes]\n"
+            )),
+            decoded.pop().unwrap()
+        );
+    }
+
+    #[test]
+    fn oversized_frame_is_skipped_as_it_is_read() {
+        let mut codec = LogCodec {
+            max_line_length: 4,
+            skipping: 0,
+        };
+        let mut src = BytesMut::from(&[0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00][..]);
+        src.extend_from_slice(&[b'a'; 100]);
+
+        let chunk = codec.decode(&mut src).unwrap().unwrap();
+        assert_eq!(
+            LogChunk::Stdout(Bytes::from("aaaa... [truncated 1020 bytes]\n")),
+            chunk
+        );
+
+        // What was read of the rest of the frame is dropped rather than kept.
+        assert_eq!(None, codec.decode(&mut src).unwrap());
+        assert!(src.is_empty());
+        assert_eq!(1020 - 96, codec.skipping);
+    }
+
+    #[test]
+    fn remaining_bytes_are_read_before_polling_again() {
+        let (sender, receiver) = mpsc::unbounded();
+        sender.unbounded_send(&b"Roses are red"[..]).unwrap();
+        let mut stream = Chunked::new(receiver.map_err(|()| io::Error::from(io::ErrorKind::Other)));
+
+        future::lazy(move || {
+            let read_buffer = &mut [0_u8; 8];
+            assert_eq!(8, stream.read(read_buffer).unwrap());
+            // the sender has nothing more, but the rest of the chunk is still there
+            assert_eq!(5, stream.read(read_buffer).unwrap());
+            assert_eq!(b"e red", &read_buffer[..5]);
+            assert_eq!(
+                io::ErrorKind::WouldBlock,
+                stream.read(read_buffer).unwrap_err().kind()
+            );
+            drop(sender);
+            Ok::<_, ()>(())
+        })
+        .wait()
+        .unwrap();
+    }
+
     #[test]
     fn test_read() {
         let chunks = vec![
@@ -0,0 +1,425 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! The maintenance windows of a device, such as the hours outside of a
+//! factory's production shifts, which are the only times at which the
+//! watchdog updates the edge runtime module to a new spec, and at which the
+//! management API updates modules and deploys new images. An update that
+//! arrives outside of them is held back until the next one opens, unless its
+//! spec is labeled as a critical update.
+//!
+//! A window opens at its `start` on each of its `days`, every day if none are
+//! listed, and closes at its `end`, on the next day if that is earlier than
+//! the start. Times are in the `timezone` of the windows, the device's local
+//! time by default.
+
+use std::fmt;
+use std::time::Duration;
+
+use chrono::{
+    DateTime, Datelike, Duration as ChronoDuration, FixedOffset, Local, NaiveDateTime, NaiveTime,
+    Utc, Weekday,
+};
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+
+use crate::module::ModuleSpec;
+
+/// A module spec with this label set to `true` is a critical update, such as
+/// a security fix, which is applied right away even outside of the
+/// maintenance windows.
+pub const CRITICAL_UPDATE_LABEL: &str = "net.azure-devices.edge.critical-update";
+
+/// Whether `spec` is labeled as a critical update.
+pub fn is_critical_update<T>(spec: &ModuleSpec<T>) -> bool {
+    spec.labels()
+        .get(CRITICAL_UPDATE_LABEL)
+        .map_or(false, |value| value.eq_ignore_ascii_case("true"))
+}
+
+/// How long an update of a module to `desired` is held back until one of
+/// `maintenance` opens, if it is. A critical update isn't held back, and
+/// nothing is without maintenance windows.
+pub fn deferred_for<T>(
+    maintenance: Option<&MaintenanceWindows>,
+    desired: &ModuleSpec<T>,
+) -> Option<Duration> {
+    if is_critical_update(desired) {
+        None
+    } else {
+        maintenance?.until_open(Utc::now())
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct MaintenanceWindows {
+    #[serde(default)]
+    timezone: TimeZone,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    windows: Vec<MaintenanceWindow>,
+}
+
+impl MaintenanceWindows {
+    pub fn new(timezone: TimeZone, windows: Vec<MaintenanceWindow>) -> Self {
+        MaintenanceWindows { timezone, windows }
+    }
+
+    pub fn timezone(&self) -> TimeZone {
+        self.timezone
+    }
+
+    pub fn windows(&self) -> &[MaintenanceWindow] {
+        &self.windows
+    }
+
+    /// How long it is from `now` until the next window opens, or `None` if
+    /// one is open, so that updates aren't held back. Without any windows
+    /// updates are never held back.
+    pub fn until_open(&self, now: DateTime<Utc>) -> Option<Duration> {
+        if self.windows.is_empty() {
+            return None;
+        }
+
+        let now = self.timezone.naive_local(now);
+        if self.windows.iter().any(|window| window.is_open_at(now)) {
+            return None;
+        }
+
+        // Each window opens again within a week.
+        let opens = self
+            .windows
+            .iter()
+            .filter_map(|window| window.next_opening_after(now))
+            .min()?;
+        Some((opens - now).to_std().unwrap_or_default())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct MaintenanceWindow {
+    #[serde(default, with = "weekdays", skip_serializing_if = "Vec::is_empty")]
+    days: Vec<Weekday>,
+    #[serde(with = "time_of_day")]
+    start: NaiveTime,
+    #[serde(with = "time_of_day")]
+    end: NaiveTime,
+}
+
+impl MaintenanceWindow {
+    /// A window from `start` to `end` on each of `days`, or on every day if
+    /// `days` is empty.
+    pub fn new(days: Vec<Weekday>, start: NaiveTime, end: NaiveTime) -> Self {
+        MaintenanceWindow { days, start, end }
+    }
+
+    pub fn days(&self) -> &[Weekday] {
+        &self.days
+    }
+
+    pub fn start(&self) -> NaiveTime {
+        self.start
+    }
+
+    pub fn end(&self) -> NaiveTime {
+        self.end
+    }
+
+    fn opens_on(&self, day: Weekday) -> bool {
+        self.days.is_empty() || self.days.contains(&day)
+    }
+
+    // A window that ends when it starts lasts the whole day.
+    fn length(&self) -> ChronoDuration {
+        let length = self.end.signed_duration_since(self.start);
+        if length <= ChronoDuration::zero() {
+            length + ChronoDuration::days(1)
+        } else {
+            length
+        }
+    }
+
+    // Whether the window is open at `now`, having opened either today or,
+    // if it goes past midnight, yesterday.
+    fn is_open_at(&self, now: NaiveDateTime) -> bool {
+        (0..=1).any(|days_ago| {
+            let day = now.date() - ChronoDuration::days(days_ago);
+            let opened = day.and_time(self.start);
+            self.opens_on(day.weekday()) && opened <= now && now < opened + self.length()
+        })
+    }
+
+    fn next_opening_after(&self, now: NaiveDateTime) -> Option<NaiveDateTime> {
+        (0..=7)
+            .map(|days| now.date() + ChronoDuration::days(days))
+            .filter(|day| self.opens_on(day.weekday()))
+            .map(|day| day.and_time(self.start))
+            .find(|opens| *opens > now)
+    }
+}
+
+/// The timezone that the times of maintenance windows are in: `local` for the
+/// device's local time, `UTC`, or an offset from UTC like `+01:00`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimeZone {
+    Local,
+    Fixed(FixedOffset),
+}
+
+impl Default for TimeZone {
+    fn default() -> Self {
+        TimeZone::Local
+    }
+}
+
+impl TimeZone {
+    fn naive_local(self, now: DateTime<Utc>) -> NaiveDateTime {
+        match self {
+            TimeZone::Local => now.with_timezone(&Local).naive_local(),
+            TimeZone::Fixed(offset) => now.with_timezone(&offset).naive_local(),
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        if value.eq_ignore_ascii_case("local") {
+            return Some(TimeZone::Local);
+        }
+        if value.eq_ignore_ascii_case("utc") || value == "Z" {
+            return Some(TimeZone::Fixed(FixedOffset::east(0)));
+        }
+
+        let (sign, offset) = match value.chars().next()? {
+            '+' => (1, &value[1..]),
+            '-' => (-1, &value[1..]),
+            _ => return None,
+        };
+        let mut parts = offset.splitn(2, ':');
+        let hours: i32 = parts.next()?.parse().ok()?;
+        let minutes: i32 = parts
+            .next()
+            .map_or(Some(0), |minutes| minutes.parse().ok())?;
+        if hours > 23 || minutes > 59 {
+            return None;
+        }
+        Some(TimeZone::Fixed(FixedOffset::east(
+            sign * (hours * 3600 + minutes * 60),
+        )))
+    }
+}
+
+impl fmt::Display for TimeZone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimeZone::Local => write!(f, "local"),
+            TimeZone::Fixed(offset) => write!(f, "{}", offset),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for TimeZone {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = String::deserialize(deserializer)?;
+        TimeZone::parse(&value).ok_or_else(|| {
+            de::Error::custom(format!(
+                "invalid timezone {:?}, expected \"local\", \"UTC\" or an offset like \"+01:00\"",
+                value
+            ))
+        })
+    }
+}
+
+impl Serialize for TimeZone {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+// Times of day like "22:00" or "22:00:30".
+mod time_of_day {
+    use chrono::NaiveTime;
+    use serde::de::{self, Deserialize, Deserializer};
+    use serde::ser::Serializer;
+
+    pub fn serialize<S>(time: &NaiveTime, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&time.format("%H:%M:%S").to_string())
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<NaiveTime, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = String::deserialize(deserializer)?;
+        NaiveTime::parse_from_str(&value, "%H:%M:%S")
+            .or_else(|_| NaiveTime::parse_from_str(&value, "%H:%M"))
+            .map_err(|_| {
+                de::Error::custom(format!(
+                    "invalid time of day {:?}, expected one like \"22:00\"",
+                    value
+                ))
+            })
+    }
+}
+
+// Days of the week like "mon" or "Monday".
+mod weekdays {
+    use chrono::Weekday;
+    use serde::de::{self, Deserialize, Deserializer};
+    use serde::ser::Serializer;
+
+    pub fn serialize<S>(days: &[Weekday], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_seq(days.iter().map(|day| format!("{:?}", day)))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<Weekday>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|day| {
+                day.parse()
+                    .map_err(|_| de::Error::custom(format!("invalid day of the week {:?}", day)))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use chrono::TimeZone as _;
+
+    use super::*;
+    use crate::module::ImagePullPolicy;
+
+    fn windows(value: serde_json::Value) -> MaintenanceWindows {
+        serde_json::from_value(value).unwrap()
+    }
+
+    // 2020-01-06 was a Monday.
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.ymd(2020, 1, day).and_hms(hour, minute, 0)
+    }
+
+    #[test]
+    fn window_is_open_between_start_and_end() {
+        let windows = windows(serde_json::json!({
+            "timezone": "UTC",
+            "windows": [{ "days": ["sat", "Sunday"], "start": "08:00", "end": "18:00" }],
+        }));
+
+        // Saturday
+        assert_eq!(None, windows.until_open(at(11, 8, 0)));
+        assert_eq!(None, windows.until_open(at(11, 17, 59)));
+        assert_eq!(
+            Some(Duration::from_secs(14 * 3600)),
+            windows.until_open(at(11, 18, 0))
+        );
+        // Monday, until Saturday morning.
+        assert_eq!(
+            Some(Duration::from_secs((4 * 24 + 20) * 3600)),
+            windows.until_open(at(6, 12, 0))
+        );
+    }
+
+    #[test]
+    fn window_that_ends_before_it_starts_goes_past_midnight() {
+        let windows = windows(serde_json::json!({
+            "timezone": "+01:00",
+            "windows": [{ "days": ["fri"], "start": "22:00", "end": "04:00" }],
+        }));
+
+        // 23:30 and 03:30 on the night from Friday to Saturday, local time.
+        assert_eq!(None, windows.until_open(at(10, 22, 30)));
+        assert_eq!(None, windows.until_open(at(11, 2, 30)));
+        // 04:30 on Saturday is after it closed.
+        assert_eq!(
+            Some(Duration::from_secs((6 * 24 + 17) * 3600 + 30 * 60)),
+            windows.until_open(at(11, 3, 30))
+        );
+    }
+
+    #[test]
+    fn updates_are_not_held_back_without_windows() {
+        let windows = MaintenanceWindows::default();
+        assert_eq!(TimeZone::Local, windows.timezone());
+        assert_eq!(None, windows.until_open(Utc::now()));
+    }
+
+    #[test]
+    fn invalid_windows_are_rejected() {
+        for value in &[
+            serde_json::json!({ "timezone": "Mars/Olympus" }),
+            serde_json::json!({ "windows": [{ "start": "25:00", "end": "04:00" }] }),
+            serde_json::json!({ "windows": [{ "days": ["someday"], "start": "22:00", "end": "04:00" }] }),
+        ] {
+            assert!(serde_json::from_value::<MaintenanceWindows>(value.clone()).is_err());
+        }
+    }
+
+    #[test]
+    fn critical_updates_are_labeled() {
+        let spec = ModuleSpec::new(
+            "edgeAgent".to_string(),
+            "docker".to_string(),
+            (),
+            HashMap::new(),
+            ImagePullPolicy::default(),
+        )
+        .unwrap();
+        assert!(!is_critical_update(&spec));
+
+        let mut labels = HashMap::new();
+        labels.insert(CRITICAL_UPDATE_LABEL.to_string(), "true".to_string());
+        assert!(is_critical_update(&spec.with_labels(labels)));
+    }
+
+    #[test]
+    fn critical_updates_are_not_deferred() {
+        let spec = ModuleSpec::new(
+            "tempSensor".to_string(),
+            "docker".to_string(),
+            (),
+            HashMap::new(),
+            ImagePullPolicy::default(),
+        )
+        .unwrap();
+        // Opens an hour from now.
+        let start = (Utc::now() + ChronoDuration::hours(1)).time();
+        let closed = MaintenanceWindows::new(
+            TimeZone::Fixed(FixedOffset::east(0)),
+            vec![MaintenanceWindow::new(
+                vec![],
+                start,
+                start + ChronoDuration::hours(1),
+            )],
+        );
+
+        let until_open = deferred_for(Some(&closed), &spec).unwrap();
+        assert!(
+            until_open <= Duration::from_secs(60 * 60),
+            "{:?}",
+            until_open
+        );
+        assert_eq!(None, deferred_for(None, &spec));
+        assert_eq!(
+            None,
+            deferred_for(Some(&MaintenanceWindows::default()), &spec)
+        );
+
+        let mut labels = HashMap::new();
+        labels.insert(CRITICAL_UPDATE_LABEL.to_string(), "true".to_string());
+        assert_eq!(None, deferred_for(Some(&closed), &spec.with_labels(labels)));
+    }
+}
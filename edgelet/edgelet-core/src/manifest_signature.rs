@@ -0,0 +1,219 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Signed deployment manifests, so that a device only applies manifests that
+//! were signed with a key it trusts.
+//!
+//! A signed manifest carries the manifest and a detached signature of it:
+//!
+//! ```json
+//! { "manifest": { ... }, "signature": "<base64>" }
+//! ```
+//!
+//! The signature is a SHA-256 RSA (PKCS #1 v1.5) or ECDSA signature over the
+//! canonical form of the manifest, which is its JSON without whitespace and
+//! with the keys of every object sorted, so that it doesn't depend on how the
+//! manifest was formatted when it was signed.
+
+use std::fmt::Write;
+
+use failure::ResultExt;
+use openssl::hash::MessageDigest;
+use openssl::pkey::{PKey, Public};
+use openssl::sign::Verifier;
+use serde_json::Value;
+
+use crate::error::{Error, ErrorKind, Result};
+
+/// The public key that manifests must be signed with.
+pub struct ManifestTrustKey {
+    key: PKey<Public>,
+}
+
+impl ManifestTrustKey {
+    /// Reads a PEM-encoded RSA or EC public key.
+    pub fn from_pem(pem: &[u8]) -> Result<Self> {
+        let key = PKey::public_key_from_pem(pem).context(ErrorKind::InvalidManifestTrustKey)?;
+        Ok(ManifestTrustKey { key })
+    }
+}
+
+#[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct SignedManifest {
+    manifest: Value,
+    signature: String,
+}
+
+impl SignedManifest {
+    pub fn new(manifest: Value, signature: String) -> Self {
+        SignedManifest {
+            manifest,
+            signature,
+        }
+    }
+
+    /// The manifest if it is signed with `key`. A manifest that was changed
+    /// after it was signed and one that was signed with another key both
+    /// fail, so the manifest can't be had without verifying it.
+    pub fn verify(&self, key: &ManifestTrustKey) -> Result<&Value> {
+        let signature =
+            base64::decode(&self.signature).context(ErrorKind::InvalidManifestSignature)?;
+
+        let verified = Verifier::new(MessageDigest::sha256(), &key.key)
+            .and_then(|mut verifier| {
+                verifier.update(&canonicalize(&self.manifest))?;
+                verifier.verify(&signature)
+            })
+            // A signature that isn't even well formed for the key is no
+            // different from one that doesn't match.
+            .unwrap_or(false);
+
+        if verified {
+            Ok(&self.manifest)
+        } else {
+            Err(Error::from(ErrorKind::InvalidManifestSignature))
+        }
+    }
+}
+
+/// The bytes of `value` that manifests are signed over: compact JSON with the
+/// keys of every object in sorted order.
+pub fn canonicalize(value: &Value) -> Vec<u8> {
+    let mut canonical = String::new();
+    write_canonical(&mut canonical, value);
+    canonical.into_bytes()
+}
+
+fn write_canonical(out: &mut String, value: &Value) {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+            out.push('{');
+            for (i, (key, value)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(out, &Value::String(key.clone()));
+                out.push(':');
+                write_canonical(out, value);
+            }
+            out.push('}');
+        }
+        Value::Array(values) => {
+            out.push('[');
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(out, value);
+            }
+            out.push(']');
+        }
+        // Scalars have a single compact form.
+        scalar => write!(out, "{}", scalar).expect("writing to a String cannot fail"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::nid::Nid;
+    use openssl::pkey::Private;
+    use openssl::sign::Signer;
+    use serde_json::json;
+
+    use super::*;
+
+    fn key_pair() -> (PKey<Private>, ManifestTrustKey) {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let private = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+        let public = ManifestTrustKey::from_pem(&private.public_key_to_pem().unwrap()).unwrap();
+        (private, public)
+    }
+
+    fn sign(key: &PKey<Private>, manifest: &Value) -> SignedManifest {
+        let mut signer = Signer::new(MessageDigest::sha256(), key).unwrap();
+        signer.update(&canonicalize(manifest)).unwrap();
+        SignedManifest::new(
+            manifest.clone(),
+            base64::encode(&signer.sign_to_vec().unwrap()),
+        )
+    }
+
+    fn manifest() -> Value {
+        json!({
+            "modules": {
+                "tempSensor": { "image": "tempSensor:1.0", "env": { "B": "2", "A": "1" } },
+                "edgeHub": { "image": "edgeHub:1.0" },
+            },
+            "schemaVersion": "1.0",
+        })
+    }
+
+    #[test]
+    fn canonical_form_sorts_keys_and_drops_whitespace() {
+        let value: Value = serde_json::from_str(
+            "{ \"b\": [1, 2.5, null], \"a\": { \"d\": \"\\\"\", \"c\": true } }",
+        )
+        .unwrap();
+
+        assert_eq!(
+            "{\"a\":{\"c\":true,\"d\":\"\\\"\"},\"b\":[1,2.5,null]}",
+            String::from_utf8(canonicalize(&value)).unwrap()
+        );
+    }
+
+    #[test]
+    fn valid_signature_verifies() {
+        let (private, public) = key_pair();
+        let signed = sign(&private, &manifest());
+
+        // Whitespace and key order in transit don't matter.
+        let text = serde_json::to_string_pretty(&signed).unwrap();
+        let received: SignedManifest = serde_json::from_str(&text).unwrap();
+
+        assert_eq!(&manifest(), received.verify(&public).unwrap());
+    }
+
+    #[test]
+    fn tampered_manifest_fails() {
+        let (private, public) = key_pair();
+        let mut signed = sign(&private, &manifest());
+        signed.manifest["modules"]["tempSensor"]["image"] = json!("evil:1.0");
+
+        match signed.verify(&public).unwrap_err().kind() {
+            ErrorKind::InvalidManifestSignature => (),
+            kind => panic!(
+                "Expected `InvalidManifestSignature` error but got {:?}",
+                kind
+            ),
+        }
+
+        signed.signature = "not base64!".to_string();
+        match signed.verify(&public).unwrap_err().kind() {
+            ErrorKind::InvalidManifestSignature => (),
+            kind => panic!(
+                "Expected `InvalidManifestSignature` error but got {:?}",
+                kind
+            ),
+        }
+    }
+
+    #[test]
+    fn untrusted_key_fails() {
+        let (private, _) = key_pair();
+        let (_, trusted) = key_pair();
+        let signed = sign(&private, &manifest());
+
+        match signed.verify(&trusted).unwrap_err().kind() {
+            ErrorKind::InvalidManifestSignature => (),
+            kind => panic!(
+                "Expected `InvalidManifestSignature` error but got {:?}",
+                kind
+            ),
+        }
+
+        assert!(ManifestTrustKey::from_pem(b"not a key").is_err());
+    }
+}
@@ -1,21 +1,36 @@
 // Copyright (c) Microsoft. All rights reserved.
 
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::TryFrom;
 use std::default::Default;
 use std::fmt;
+use std::net::IpAddr;
+use std::path::{Component, Path, PathBuf};
 use std::result::Result as StdResult;
 use std::str::FromStr;
 use std::string::ToString;
 use std::time::Duration;
 
+use bytes::Bytes;
 use chrono::prelude::*;
 use failure::{Fail, ResultExt};
 use futures::{Future, Stream};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::de::{self, DeserializeOwned, Deserializer};
+use serde::{Deserialize, Serialize};
 use serde_json;
+use sha2::{Digest, Sha256};
 
 use edgelet_utils::{ensure_not_empty_with_context, serialize_ordered};
 
+use crate::create_options::join_create_options;
+use crate::env_file::load_env_files;
 use crate::error::{Error, ErrorKind, Result};
+use crate::error_code::{ErrorCode, ErrorCodeKind};
+use crate::events::ModuleEvents;
+use crate::maintenance::CRITICAL_UPDATE_LABEL;
 use crate::settings::RuntimeSettings;
 use crate::GetTrustBundle;
 
@@ -48,15 +63,205 @@ impl fmt::Display for ModuleStatus {
     }
 }
 
+/// The result of a module's health check, for runtimes that support them.
+#[derive(Clone, Copy, Debug, serde_derive::Deserialize, PartialEq, serde_derive::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthState {
+    Starting,
+    Healthy,
+    Unhealthy,
+}
+
+impl FromStr for HealthState {
+    type Err = serde_json::Error;
+
+    fn from_str(s: &str) -> StdResult<Self, Self::Err> {
+        serde_json::from_str(&format!("\"{}\"", s))
+    }
+}
+
+impl fmt::Display for HealthState {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            formatter,
+            "{}",
+            serde_json::to_string(self)
+                .map(|s| s.trim_matches('"').to_string())
+                .map_err(|_| fmt::Error)?
+        )
+    }
+}
+
+/// Why a module last exited, when the runtime knows of a reason other than
+/// the module exiting on its own.
+#[derive(Clone, Copy, Debug, serde_derive::Deserialize, PartialEq, serde_derive::Serialize)]
+pub enum ExitReason {
+    /// The module was killed for running out of memory.
+    #[serde(rename = "OOMKilled")]
+    OomKilled,
+}
+
+impl FromStr for ExitReason {
+    type Err = serde_json::Error;
+
+    fn from_str(s: &str) -> StdResult<Self, Self::Err> {
+        serde_json::from_str(&format!("\"{}\"", s))
+    }
+}
+
+impl fmt::Display for ExitReason {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExitReason::OomKilled => write!(formatter, "OOMKilled"),
+        }
+    }
+}
+
+/// What kind of failure a `ModuleError` is.
+#[derive(Clone, Copy, Debug, serde_derive::Deserialize, PartialEq, serde_derive::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ModuleErrorCode {
+    /// The image of the module couldn't be pulled.
+    PullFailed,
+    /// The module couldn't be created from its spec.
+    CreateFailed,
+    /// The module was killed for running out of memory.
+    OomKilled,
+    /// The module exited with a non-zero exit code.
+    NonZeroExit,
+}
+
+impl fmt::Display for ModuleErrorCode {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            formatter,
+            "{}",
+            serde_json::to_string(self)
+                .map(|s| s.trim_matches('"').to_string())
+                .map_err(|_| fmt::Error)?
+        )
+    }
+}
+
+impl FromStr for ModuleErrorCode {
+    type Err = serde_json::Error;
+
+    fn from_str(s: &str) -> StdResult<Self, Self::Err> {
+        serde_json::from_str(&format!("\"{}\"", s))
+    }
+}
+
+/// The most recent failure of a module, so that why a module failed can be
+/// seen without reading its logs.
+#[derive(Clone, Debug, serde_derive::Deserialize, PartialEq, serde_derive::Serialize)]
+pub struct ModuleError {
+    code: ModuleErrorCode,
+    message: String,
+    timestamp: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    exit_code: Option<i64>,
+}
+
+impl ModuleError {
+    pub fn new(code: ModuleErrorCode, message: String, timestamp: DateTime<Utc>) -> Self {
+        ModuleError {
+            code,
+            message,
+            timestamp,
+            exit_code: None,
+        }
+    }
+
+    pub fn code(&self) -> ModuleErrorCode {
+        self.code
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn timestamp(&self) -> &DateTime<Utc> {
+        &self.timestamp
+    }
+
+    /// The exit code of the module, for failures where it exited.
+    pub fn exit_code(&self) -> Option<i64> {
+        self.exit_code
+    }
+
+    pub fn with_exit_code(mut self, exit_code: Option<i64>) -> Self {
+        self.exit_code = exit_code;
+        self
+    }
+}
+
+/// A port of a module's container and the host address that it's bound to,
+/// if it's published.
+#[derive(Clone, Debug, serde_derive::Deserialize, PartialEq, serde_derive::Serialize)]
+pub struct PortBinding {
+    container_port: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    host_ip: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    host_port: Option<String>,
+}
+
+impl PortBinding {
+    /// A port that isn't published, like `80/tcp`.
+    pub fn new(container_port: String) -> Self {
+        PortBinding {
+            container_port,
+            host_ip: None,
+            host_port: None,
+        }
+    }
+
+    pub fn container_port(&self) -> &str {
+        &self.container_port
+    }
+
+    /// `None` if the port isn't published.
+    pub fn host_ip(&self) -> Option<&str> {
+        self.host_ip.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn with_host_ip(mut self, host_ip: Option<String>) -> Self {
+        self.host_ip = host_ip;
+        self
+    }
+
+    /// `None` if the port isn't published.
+    pub fn host_port(&self) -> Option<&str> {
+        self.host_port.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn with_host_port(mut self, host_port: Option<String>) -> Self {
+        self.host_port = host_port;
+        self
+    }
+}
+
 #[derive(serde_derive::Serialize, serde_derive::Deserialize, Debug, PartialEq, Clone)]
 pub struct ModuleRuntimeState {
     status: ModuleStatus,
     exit_code: Option<i64>,
+    #[serde(default)]
+    exit_reason: Option<ExitReason>,
     status_description: Option<String>,
     started_at: Option<DateTime<Utc>>,
     finished_at: Option<DateTime<Utc>>,
     image_id: Option<String>,
     pid: Option<i32>,
+    health: Option<HealthState>,
+    restart_count: Option<u32>,
+    #[serde(default)]
+    labels: HashMap<String, String>,
+    #[serde(default)]
+    paused: bool,
+    #[serde(default)]
+    last_error: Option<ModuleError>,
+    #[serde(default)]
+    ports: Vec<PortBinding>,
 }
 
 impl Default for ModuleRuntimeState {
@@ -64,11 +269,18 @@ impl Default for ModuleRuntimeState {
         ModuleRuntimeState {
             status: ModuleStatus::Unknown,
             exit_code: None,
+            exit_reason: None,
             status_description: None,
             started_at: None,
             finished_at: None,
             image_id: None,
             pid: None,
+            health: None,
+            restart_count: None,
+            labels: HashMap::new(),
+            paused: false,
+            last_error: None,
+            ports: Vec::new(),
         }
     }
 }
@@ -92,6 +304,16 @@ impl ModuleRuntimeState {
         self
     }
 
+    /// `None` if the module exited on its own, or hasn't exited.
+    pub fn exit_reason(&self) -> Option<ExitReason> {
+        self.exit_reason
+    }
+
+    pub fn with_exit_reason(mut self, exit_reason: Option<ExitReason>) -> Self {
+        self.exit_reason = exit_reason;
+        self
+    }
+
     pub fn status_description(&self) -> Option<&str> {
         self.status_description.as_ref().map(AsRef::as_ref)
     }
@@ -136,6 +358,71 @@ impl ModuleRuntimeState {
         self.pid = pid;
         self
     }
+
+    /// `None` if the module has no health check.
+    pub fn health(&self) -> Option<HealthState> {
+        self.health
+    }
+
+    pub fn with_health(mut self, health: Option<HealthState>) -> Self {
+        self.health = health;
+        self
+    }
+
+    /// The number of times the runtime has restarted the module since it was
+    /// created, if the runtime keeps track of it.
+    pub fn restart_count(&self) -> Option<u32> {
+        self.restart_count
+    }
+
+    pub fn with_restart_count(mut self, restart_count: Option<u32>) -> Self {
+        self.restart_count = restart_count;
+        self
+    }
+
+    /// The labels of the module's container, which include the labels of its
+    /// spec.
+    pub fn labels(&self) -> &HashMap<String, String> {
+        &self.labels
+    }
+
+    pub fn with_labels(mut self, labels: HashMap<String, String>) -> Self {
+        self.labels = labels;
+        self
+    }
+
+    /// Whether the watchdog stopped retrying the module after it kept
+    /// failing. The container runtime doesn't know, so this is only set by
+    /// the daemon's management API.
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn with_paused(mut self, paused: bool) -> Self {
+        self.paused = paused;
+        self
+    }
+
+    /// The most recent failure of the module, if the runtime knows of one.
+    pub fn last_error(&self) -> Option<&ModuleError> {
+        self.last_error.as_ref()
+    }
+
+    pub fn with_last_error(mut self, last_error: Option<ModuleError>) -> Self {
+        self.last_error = last_error;
+        self
+    }
+
+    /// The ports of the module's container, with the host addresses that the
+    /// published ones are bound to.
+    pub fn ports(&self) -> &[PortBinding] {
+        &self.ports
+    }
+
+    pub fn with_ports(mut self, ports: Vec<PortBinding>) -> Self {
+        self.ports = ports;
+        self
+    }
 }
 
 #[derive(serde_derive::Deserialize, Debug, serde_derive::Serialize)]
@@ -143,13 +430,98 @@ pub struct ModuleSpec<T> {
     name: String,
     #[serde(rename = "type")]
     type_: String,
+    #[serde(
+        deserialize_with = "deserialize_config",
+        bound(deserialize = "T: DeserializeOwned")
+    )]
     config: T,
     #[serde(default = "HashMap::new")]
     #[serde(serialize_with = "serialize_ordered")]
     env: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    env_files: Vec<PathBuf>,
     #[serde(default)]
     #[serde(rename = "imagePullPolicy")]
     image_pull_policy: ImagePullPolicy,
+    #[serde(default, rename = "dependsOn", skip_serializing_if = "Vec::is_empty")]
+    depends_on: Vec<String>,
+    #[serde(
+        default,
+        rename = "startupOrder",
+        skip_serializing_if = "Option::is_none"
+    )]
+    startup_order: Option<u32>,
+    #[serde(default, skip_serializing_if = "ModuleResources::is_empty")]
+    resources: ModuleResources,
+    #[serde(default, skip_serializing_if = "ModuleLogConfig::is_empty")]
+    log_config: ModuleLogConfig,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    binds: Vec<ModuleBind>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    read_only_root_fs: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tmpfs: Vec<ModuleTmpfs>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stop_timeout_secs: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pre_stop: Option<PreStopHook>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stop_signal: Option<String>,
+    #[serde(
+        default,
+        serialize_with = "serialize_ordered",
+        skip_serializing_if = "HashMap::is_empty"
+    )]
+    labels: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    dns: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    dns_search: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    networks: Vec<ModuleNetwork>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    devices: Vec<ModuleDevice>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    runtime: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    cap_add: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    cap_drop: Vec<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    no_new_privileges: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    seccomp_profile: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    apparmor_profile: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    ulimits: Vec<ModuleUlimit>,
+    #[serde(default, skip_serializing_if = "is_default_restart_policy")]
+    restart_policy: RestartPolicy,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_retries: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    user: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    working_dir: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    identity_integration: bool,
+}
+
+// Specs that were saved before restart policies existed hash the same.
+fn is_default_restart_policy(policy: &RestartPolicy) -> bool {
+    *policy == RestartPolicy::default()
+}
+
+// Joins split create options before the config is read, see
+// `join_create_options`.
+fn deserialize_config<'de, D, T>(deserializer: D) -> StdResult<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    let mut config = serde_json::Value::deserialize(deserializer)?;
+    join_create_options(&mut config).map_err(de::Error::custom)?;
+    serde_json::from_value(config).map_err(de::Error::custom)
 }
 
 impl<T> Clone for ModuleSpec<T>
@@ -162,7 +534,35 @@ where
             type_: self.type_.clone(),
             config: self.config.clone(),
             env: self.env.clone(),
+            env_files: self.env_files.clone(),
             image_pull_policy: self.image_pull_policy,
+            depends_on: self.depends_on.clone(),
+            startup_order: self.startup_order,
+            resources: self.resources.clone(),
+            log_config: self.log_config.clone(),
+            binds: self.binds.clone(),
+            read_only_root_fs: self.read_only_root_fs,
+            tmpfs: self.tmpfs.clone(),
+            stop_timeout_secs: self.stop_timeout_secs,
+            pre_stop: self.pre_stop.clone(),
+            stop_signal: self.stop_signal.clone(),
+            labels: self.labels.clone(),
+            dns: self.dns.clone(),
+            dns_search: self.dns_search.clone(),
+            networks: self.networks.clone(),
+            devices: self.devices.clone(),
+            runtime: self.runtime.clone(),
+            cap_add: self.cap_add.clone(),
+            cap_drop: self.cap_drop.clone(),
+            no_new_privileges: self.no_new_privileges,
+            seccomp_profile: self.seccomp_profile.clone(),
+            apparmor_profile: self.apparmor_profile.clone(),
+            ulimits: self.ulimits.clone(),
+            restart_policy: self.restart_policy,
+            max_retries: self.max_retries,
+            user: self.user.clone(),
+            working_dir: self.working_dir.clone(),
+            identity_integration: self.identity_integration,
         }
     }
 }
@@ -183,7 +583,35 @@ impl<T> ModuleSpec<T> {
             type_,
             config,
             env,
+            env_files: Vec::new(),
             image_pull_policy,
+            depends_on: Vec::new(),
+            startup_order: None,
+            resources: ModuleResources::default(),
+            log_config: ModuleLogConfig::default(),
+            binds: Vec::new(),
+            read_only_root_fs: false,
+            tmpfs: Vec::new(),
+            stop_timeout_secs: None,
+            pre_stop: None,
+            stop_signal: None,
+            labels: HashMap::new(),
+            dns: Vec::new(),
+            dns_search: Vec::new(),
+            networks: Vec::new(),
+            devices: Vec::new(),
+            runtime: None,
+            cap_add: Vec::new(),
+            cap_drop: Vec::new(),
+            no_new_privileges: false,
+            seccomp_profile: None,
+            apparmor_profile: None,
+            ulimits: Vec::new(),
+            restart_policy: RestartPolicy::default(),
+            max_retries: None,
+            user: None,
+            working_dir: None,
+            identity_integration: false,
         })
     }
 
@@ -235,6 +663,26 @@ impl<T> ModuleSpec<T> {
         self
     }
 
+    /// Host files with `KEY=VALUE` lines that the environment variables of
+    /// the module are loaded from, under its own `env`.
+    pub fn env_files(&self) -> &[PathBuf] {
+        &self.env_files
+    }
+
+    pub fn with_env_files(mut self, env_files: Vec<PathBuf>) -> Self {
+        self.env_files = env_files;
+        self
+    }
+
+    /// The environment variables of the module: those of its env files, read
+    /// now, with its own `env` on top. A variable that is in more than one env
+    /// file has its value from the last one.
+    pub fn merged_env(&self) -> Result<HashMap<String, String>> {
+        let mut env = load_env_files(&self.env_files)?;
+        env.extend(self.env.iter().map(|(k, v)| (k.clone(), v.clone())));
+        Ok(env)
+    }
+
     pub fn image_pull_policy(&self) -> ImagePullPolicy {
         self.image_pull_policy
     }
@@ -243,503 +691,3756 @@ impl<T> ModuleSpec<T> {
         self.image_pull_policy = image_pull_policy;
         self
     }
-}
 
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub enum LogTail {
-    All,
-    Num(u64),
-}
+    /// Names of the modules that must be started before, and stopped after, this one.
+    pub fn depends_on(&self) -> &[String] {
+        &self.depends_on
+    }
 
-impl Default for LogTail {
-    fn default() -> Self {
-        LogTail::All
+    pub fn with_depends_on(mut self, depends_on: Vec<String>) -> Self {
+        self.depends_on = depends_on;
+        self
     }
-}
 
-impl FromStr for LogTail {
-    type Err = Error;
+    /// Modules with a lower startup order are started first. Modules that
+    /// don't have one get `DEFAULT_STARTUP_ORDER`, so they start last.
+    pub fn startup_order(&self) -> u32 {
+        self.startup_order.unwrap_or(DEFAULT_STARTUP_ORDER)
+    }
 
-    fn from_str(s: &str) -> Result<Self> {
-        let tail = if s == "all" {
-            LogTail::All
-        } else {
-            let num = s
-                .parse::<u64>()
-                .with_context(|_| ErrorKind::InvalidLogTail(s.to_string()))?;
-            LogTail::Num(num)
-        };
-        Ok(tail)
+    pub fn with_startup_order(mut self, startup_order: u32) -> Self {
+        self.startup_order = Some(startup_order);
+        self
     }
-}
 
-impl ToString for LogTail {
-    fn to_string(&self) -> String {
-        match self {
-            LogTail::All => "all".to_string(),
-            LogTail::Num(n) => n.to_string(),
-        }
+    pub fn resources(&self) -> &ModuleResources {
+        &self.resources
     }
-}
 
-#[derive(Debug, Default)]
-pub struct LogOptions {
-    follow: bool,
-    tail: LogTail,
-    since: i32,
-}
+    pub fn with_resources(mut self, resources: ModuleResources) -> Self {
+        self.resources = resources;
+        self
+    }
 
-impl LogOptions {
-    pub fn new() -> Self {
-        LogOptions {
-            follow: false,
-            tail: LogTail::All,
-            since: 0,
-        }
+    /// How the module's logs are stored. If empty, the runtime's default is
+    /// used.
+    pub fn log_config(&self) -> &ModuleLogConfig {
+        &self.log_config
     }
 
-    pub fn with_follow(mut self, follow: bool) -> Self {
-        self.follow = follow;
+    pub fn with_log_config(mut self, log_config: ModuleLogConfig) -> Self {
+        self.log_config = log_config;
         self
     }
 
-    pub fn with_tail(mut self, tail: LogTail) -> Self {
-        self.tail = tail;
+    /// Host paths mounted into the module's container.
+    pub fn binds(&self) -> &[ModuleBind] {
+        &self.binds
+    }
+
+    pub fn with_binds(mut self, binds: Vec<ModuleBind>) -> Self {
+        self.binds = binds;
         self
     }
 
-    pub fn with_since(mut self, since: i32) -> Self {
-        self.since = since;
+    /// Whether the module's container has a read-only root filesystem, so that
+    /// it can only write to its tmpfs mounts, writable binds and volumes.
+    pub fn read_only_root_fs(&self) -> bool {
+        self.read_only_root_fs
+    }
+
+    pub fn with_read_only_root_fs(mut self, read_only_root_fs: bool) -> Self {
+        self.read_only_root_fs = read_only_root_fs;
         self
     }
 
-    pub fn follow(&self) -> bool {
-        self.follow
+    pub fn tmpfs(&self) -> &[ModuleTmpfs] {
+        &self.tmpfs
     }
 
-    pub fn tail(&self) -> &LogTail {
-        &self.tail
+    pub fn with_tmpfs(mut self, tmpfs: Vec<ModuleTmpfs>) -> Self {
+        self.tmpfs = tmpfs;
+        self
     }
 
-    pub fn since(&self) -> i32 {
-        self.since
+    /// How long the module is given to exit once it is asked to stop, before
+    /// it is killed. Modules that don't set one get the runtime's default.
+    pub fn stop_timeout(&self) -> Option<Duration> {
+        self.stop_timeout_secs.map(Duration::from_secs)
     }
-}
 
-pub trait Module {
-    type Config;
-    type Error: Fail;
-    type RuntimeStateFuture: Future<Item = ModuleRuntimeState, Error = Self::Error> + Send;
+    pub fn with_stop_timeout(mut self, stop_timeout: Option<Duration>) -> Self {
+        self.stop_timeout_secs = stop_timeout.map(|timeout| timeout.as_secs());
+        self
+    }
 
-    fn name(&self) -> &str;
-    fn type_(&self) -> &str;
-    fn config(&self) -> &Self::Config;
-    fn runtime_state(&self) -> Self::RuntimeStateFuture;
-}
+    /// A command that is run in the module's container before it is asked
+    /// to stop, so that it can drain what it is working on.
+    pub fn pre_stop(&self) -> Option<&PreStopHook> {
+        self.pre_stop.as_ref()
+    }
 
-pub trait ModuleRegistry {
-    type Error: Fail;
-    type PullFuture: Future<Item = (), Error = Self::Error> + Send;
-    type RemoveFuture: Future<Item = (), Error = Self::Error>;
-    type Config;
+    pub fn with_pre_stop(mut self, pre_stop: Option<PreStopHook>) -> Self {
+        self.pre_stop = pre_stop;
+        self
+    }
 
-    fn pull(&self, config: &Self::Config) -> Self::PullFuture;
-    fn remove(&self, name: &str) -> Self::RemoveFuture;
-}
+    /// The signal the module is asked to stop with, like `SIGQUIT` or `3`.
+    /// Modules that don't set one get the runtime's default, `SIGTERM` for
+    /// Docker.
+    pub fn stop_signal(&self) -> Option<&str> {
+        self.stop_signal.as_ref().map(AsRef::as_ref)
+    }
 
-#[derive(Debug)]
-pub struct SystemInfo {
-    /// OS Type of the Host. Example of value expected: \"linux\" and \"windows\".
-    os_type: String,
-    /// Hardware architecture of the host. Example of value expected: arm32, x86, amd64
-    architecture: String,
-    /// iotedge version string
-    version: &'static str,
-}
+    pub fn with_stop_signal(mut self, stop_signal: Option<String>) -> Self {
+        self.stop_signal = stop_signal;
+        self
+    }
 
-impl SystemInfo {
-    pub fn new(os_type: String, architecture: String) -> Self {
-        SystemInfo {
-            os_type,
-            architecture,
-            version: super::version_with_source_version(),
-        }
+    /// Labels the runtime puts on the module, which can be used to select
+    /// modules in the management API.
+    pub fn labels(&self) -> &HashMap<String, String> {
+        &self.labels
     }
 
-    pub fn os_type(&self) -> &str {
-        &self.os_type
+    pub fn with_labels(mut self, labels: HashMap<String, String>) -> Self {
+        self.labels = labels;
+        self
     }
 
-    pub fn architecture(&self) -> &str {
-        &self.architecture
+    /// The IP addresses of the DNS servers of the module. Modules that don't
+    /// set any get the runtime's default.
+    pub fn dns(&self) -> &[String] {
+        &self.dns
     }
 
-    pub fn version(&self) -> &str {
-        self.version
+    pub fn with_dns(mut self, dns: Vec<String>) -> Self {
+        self.dns = dns;
+        self
     }
-}
 
-#[derive(Debug, serde_derive::Serialize)]
-pub struct SystemResources {
-    host_uptime: u64,
-    process_uptime: u64,
-    used_cpu: f64,
-    used_ram: u64,
-    total_ram: u64,
-    disks: Vec<DiskInfo>,
-    docker_stats: String,
-}
+    /// The DNS search domains of the module. Modules that don't set any get
+    /// the runtime's default.
+    pub fn dns_search(&self) -> &[String] {
+        &self.dns_search
+    }
 
-impl SystemResources {
-    pub fn new(
-        host_uptime: u64,
-        process_uptime: u64,
-        used_cpu: f64,
-        used_ram: u64,
-        total_ram: u64,
-        disks: Vec<DiskInfo>,
-        docker_stats: String,
-    ) -> Self {
-        SystemResources {
-            host_uptime,
-            process_uptime,
-            used_cpu,
-            used_ram,
-            total_ram,
-            disks,
-            docker_stats,
-        }
+    pub fn with_dns_search(mut self, dns_search: Vec<String>) -> Self {
+        self.dns_search = dns_search;
+        self
     }
-}
 
-#[derive(Debug, serde_derive::Serialize)]
-pub struct DiskInfo {
-    name: String,
-    available_space: u64,
-    total_space: u64,
-    file_system: String,
-    file_type: String,
-}
+    /// The networks the module's container joins in addition to the one it
+    /// is created on.
+    pub fn networks(&self) -> &[ModuleNetwork] {
+        &self.networks
+    }
 
-impl DiskInfo {
-    pub fn new(
-        name: String,
-        available_space: u64,
-        total_space: u64,
-        file_system: String,
-        file_type: String,
-    ) -> Self {
-        DiskInfo {
-            name,
-            available_space,
-            total_space,
-            file_system,
-            file_type,
-        }
+    pub fn with_networks(mut self, networks: Vec<ModuleNetwork>) -> Self {
+        self.networks = networks;
+        self
     }
-}
 
-#[derive(Debug)]
-pub struct ModuleTop {
-    /// Name of the module. Example: tempSensor
-    name: String,
-    /// A vector of process IDs (PIDs) representing a snapshot of all processes running inside the module.
-    process_ids: Vec<i32>,
-}
+    /// Host devices, like GPUs, that the module's container can access.
+    pub fn devices(&self) -> &[ModuleDevice] {
+        &self.devices
+    }
 
-impl ModuleTop {
-    pub fn new(name: String, process_ids: Vec<i32>) -> Self {
-        ModuleTop { name, process_ids }
+    pub fn with_devices(mut self, devices: Vec<ModuleDevice>) -> Self {
+        self.devices = devices;
+        self
     }
 
-    pub fn name(&self) -> &str {
-        &self.name
+    /// The container runtime that runs the module's container, like
+    /// `nvidia`, instead of the container runtime's default one.
+    pub fn runtime(&self) -> Option<&str> {
+        self.runtime.as_ref().map(AsRef::as_ref)
     }
 
-    pub fn process_ids(&self) -> &[i32] {
-        &self.process_ids
+    pub fn with_runtime(mut self, runtime: Option<String>) -> Self {
+        self.runtime = runtime;
+        self
     }
-}
 
-pub trait ProvisioningResult {
-    fn device_id(&self) -> &str;
-    fn hub_name(&self) -> &str;
-}
+    /// Linux capabilities that the module's container gets in addition to
+    /// the container runtime's default ones, like `NET_ADMIN`.
+    pub fn cap_add(&self) -> &[String] {
+        &self.cap_add
+    }
 
-pub trait MakeModuleRuntime {
-    type Config: Clone + Send;
-    type Settings: RuntimeSettings<Config = Self::Config>;
-    type ProvisioningResult: ProvisioningResult;
-    type ModuleRuntime: ModuleRuntime<Config = Self::Config>;
-    type Error: Fail;
-    type Future: Future<Item = Self::ModuleRuntime, Error = Self::Error> + Send;
+    pub fn with_cap_add(mut self, cap_add: Vec<String>) -> Self {
+        self.cap_add = cap_add;
+        self
+    }
 
-    fn make_runtime(
-        settings: Self::Settings,
-        provisioning_result: Self::ProvisioningResult,
-        crypto: impl GetTrustBundle + Send + 'static,
-    ) -> Self::Future;
-}
+    /// Linux capabilities that the module's container doesn't get, or `ALL`
+    /// for none but those of `cap_add`.
+    pub fn cap_drop(&self) -> &[String] {
+        &self.cap_drop
+    }
 
-pub trait ModuleRuntime: Sized {
-    type Error: Fail;
+    pub fn with_cap_drop(mut self, cap_drop: Vec<String>) -> Self {
+        self.cap_drop = cap_drop;
+        self
+    }
 
-    type Config: Clone + Send;
-    type Module: Module<Config = Self::Config> + Send;
-    type ModuleRegistry: ModuleRegistry<Config = Self::Config, Error = Self::Error>;
-    type Chunk: AsRef<[u8]>;
-    type Logs: Stream<Item = Self::Chunk, Error = Self::Error> + Send;
+    /// Whether the processes of the module's container are kept from gaining
+    /// privileges, such as through setuid binaries.
+    pub fn no_new_privileges(&self) -> bool {
+        self.no_new_privileges
+    }
 
-    type CreateFuture: Future<Item = (), Error = Self::Error> + Send;
-    type GetFuture: Future<Item = (Self::Module, ModuleRuntimeState), Error = Self::Error> + Send;
-    type ListFuture: Future<Item = Vec<Self::Module>, Error = Self::Error> + Send;
-    type ListWithDetailsStream: Stream<Item = (Self::Module, ModuleRuntimeState), Error = Self::Error>
-        + Send;
-    type LogsFuture: Future<Item = Self::Logs, Error = Self::Error> + Send;
-    type RemoveFuture: Future<Item = (), Error = Self::Error> + Send;
-    type RestartFuture: Future<Item = (), Error = Self::Error> + Send;
-    type StartFuture: Future<Item = (), Error = Self::Error> + Send;
-    type StopFuture: Future<Item = (), Error = Self::Error> + Send;
-    type SystemInfoFuture: Future<Item = SystemInfo, Error = Self::Error> + Send;
-    type SystemResourcesFuture: Future<Item = SystemResources, Error = Self::Error> + Send;
-    type RemoveAllFuture: Future<Item = (), Error = Self::Error> + Send;
+    pub fn with_no_new_privileges(mut self, no_new_privileges: bool) -> Self {
+        self.no_new_privileges = no_new_privileges;
+        self
+    }
 
-    fn create(&self, module: ModuleSpec<Self::Config>) -> Self::CreateFuture;
-    fn get(&self, id: &str) -> Self::GetFuture;
-    fn start(&self, id: &str) -> Self::StartFuture;
-    fn stop(&self, id: &str, wait_before_kill: Option<Duration>) -> Self::StopFuture;
-    fn restart(&self, id: &str) -> Self::RestartFuture;
-    fn remove(&self, id: &str) -> Self::RemoveFuture;
-    fn system_info(&self) -> Self::SystemInfoFuture;
-    fn system_resources(&self) -> Self::SystemResourcesFuture;
-    fn list(&self) -> Self::ListFuture;
-    fn list_with_details(&self) -> Self::ListWithDetailsStream;
-    fn logs(&self, id: &str, options: &LogOptions) -> Self::LogsFuture;
-    fn registry(&self) -> &Self::ModuleRegistry;
-    fn remove_all(&self) -> Self::RemoveAllFuture;
-}
+    /// Host path of the JSON seccomp profile that the module's container runs
+    /// with instead of the container runtime's default one. The runtime reads
+    /// it when the module is created.
+    pub fn seccomp_profile(&self) -> Option<&Path> {
+        self.seccomp_profile.as_ref().map(AsRef::as_ref)
+    }
 
-#[derive(Clone, Copy, Debug)]
-pub enum ModuleRuntimeErrorReason {
-    NotFound,
-    Other,
-}
+    pub fn with_seccomp_profile(mut self, seccomp_profile: Option<PathBuf>) -> Self {
+        self.seccomp_profile = seccomp_profile;
+        self
+    }
 
-// Useful for error contexts
-#[derive(Clone, Copy, Debug)]
-pub enum ModuleOperation {
-    RuntimeState,
-}
+    /// Name of the AppArmor profile, loaded on the host, that the module's
+    /// container runs with instead of the container runtime's default one.
+    pub fn apparmor_profile(&self) -> Option<&str> {
+        self.apparmor_profile.as_ref().map(AsRef::as_ref)
+    }
 
-impl fmt::Display for ModuleOperation {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            ModuleOperation::RuntimeState => write!(f, "Could not query module runtime state"),
-        }
+    pub fn with_apparmor_profile(mut self, apparmor_profile: Option<String>) -> Self {
+        self.apparmor_profile = apparmor_profile;
+        self
     }
-}
 
-// Useful for error contexts
-#[derive(Clone, Debug)]
-pub enum RegistryOperation {
-    PullImage(String),
-    RemoveImage(String),
-}
+    /// Resource limits of the processes in the module's container, which
+    /// take precedence over the container runtime's defaults.
+    pub fn ulimits(&self) -> &[ModuleUlimit] {
+        &self.ulimits
+    }
 
-impl fmt::Display for RegistryOperation {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            RegistryOperation::PullImage(name) => write!(f, "Could not pull image {}", name),
-            RegistryOperation::RemoveImage(name) => write!(f, "Could not remove image {}", name),
-        }
+    pub fn with_ulimits(mut self, ulimits: Vec<ModuleUlimit>) -> Self {
+        self.ulimits = ulimits;
+        self
     }
-}
 
-// Useful for error contexts
-#[derive(Clone, Debug, PartialEq)]
-pub enum RuntimeOperation {
-    CreateModule(String),
-    GetModule(String),
-    GetModuleLogs(String),
-    Init,
-    ListModules,
-    RemoveModule(String),
-    RestartModule(String),
-    StartModule(String),
-    StopModule(String),
-    SystemInfo,
-    SystemResources,
-    TopModule(String),
-}
+    pub fn restart_policy(&self) -> RestartPolicy {
+        self.restart_policy
+    }
 
-impl fmt::Display for RuntimeOperation {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            RuntimeOperation::CreateModule(name) => write!(f, "Could not create module {}", name),
-            RuntimeOperation::GetModule(name) => write!(f, "Could not get module {}", name),
-            RuntimeOperation::GetModuleLogs(name) => {
-                write!(f, "Could not get logs for module {}", name)
-            }
-            RuntimeOperation::Init => write!(f, "Could not initialize module runtime"),
-            RuntimeOperation::ListModules => write!(f, "Could not list modules"),
-            RuntimeOperation::RemoveModule(name) => write!(f, "Could not remove module {}", name),
-            RuntimeOperation::RestartModule(name) => write!(f, "Could not restart module {}", name),
-            RuntimeOperation::StartModule(name) => write!(f, "Could not start module {}", name),
-            RuntimeOperation::StopModule(name) => write!(f, "Could not stop module {}", name),
-            RuntimeOperation::SystemInfo => write!(f, "Could not query system info"),
-            RuntimeOperation::SystemResources => write!(f, "Could not query system resources"),
-            RuntimeOperation::TopModule(name) => write!(f, "Could not top module {}", name),
-        }
+    pub fn with_restart_policy(mut self, restart_policy: RestartPolicy) -> Self {
+        self.restart_policy = restart_policy;
+        self
     }
-}
 
-#[derive(Clone, Copy, Debug, serde_derive::Deserialize, PartialEq, serde_derive::Serialize)]
-#[serde(rename_all = "lowercase")]
-pub enum ImagePullPolicy {
-    #[serde(rename = "on-create")]
-    OnCreate,
-    Never,
-}
+    /// How many times a module with the `on-failure` restart policy is
+    /// started again before it is left failed. `None` is no limit.
+    pub fn max_retries(&self) -> Option<u32> {
+        self.max_retries
+    }
 
-impl Default for ImagePullPolicy {
-    fn default() -> Self {
-        ImagePullPolicy::OnCreate
+    pub fn with_max_retries(mut self, max_retries: Option<u32>) -> Self {
+        self.max_retries = max_retries;
+        self
     }
-}
 
-impl FromStr for ImagePullPolicy {
-    type Err = Error;
+    /// The user that the processes of the module's container run as instead
+    /// of the image's, as `user[:group]` where each is a name or a numeric ID.
+    pub fn user(&self) -> Option<&str> {
+        self.user.as_ref().map(AsRef::as_ref)
+    }
 
-    fn from_str(s: &str) -> StdResult<ImagePullPolicy, Self::Err> {
-        match s.to_lowercase().as_str() {
-            "on-create" => Ok(ImagePullPolicy::OnCreate),
-            "never" => Ok(ImagePullPolicy::Never),
-            _ => Err(Error::from(ErrorKind::InvalidImagePullPolicy(
-                s.to_string(),
-            ))),
-        }
+    pub fn with_user(mut self, user: Option<String>) -> Self {
+        self.user = user;
+        self
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Absolute path in the module's container that its processes start in
+    /// instead of the image's working directory.
+    pub fn working_dir(&self) -> Option<&str> {
+        self.working_dir.as_ref().map(AsRef::as_ref)
+    }
 
-    use std::str::FromStr;
-    use std::string::ToString;
+    pub fn with_working_dir(mut self, working_dir: Option<String>) -> Self {
+        self.working_dir = working_dir;
+        self
+    }
 
-    use crate::error::ErrorKind;
+    /// Whether the runtime gives the module what it needs to use its
+    /// workload identity: the workload socket mounted into its container and
+    /// the `IOTEDGE_*` environment variables of the SDK. What the spec sets
+    /// itself takes precedence over them.
+    pub fn identity_integration(&self) -> bool {
+        self.identity_integration
+    }
+
+    pub fn with_identity_integration(mut self, identity_integration: bool) -> Self {
+        self.identity_integration = identity_integration;
+        self
+    }
+
+    /// Checks that none of the binds of this spec give the module write
+    /// access to a sensitive host path, like `/etc` or the container runtime's
+    /// socket. Runtimes call this in addition to `validate` when their strict
+    /// bind policy is enabled.
+    pub fn validate_strict_binds(&self) -> StdResult<(), ValidationError> {
+        let violations: Vec<_> = self
+            .binds
+            .iter()
+            .filter(|bind| !bind.read_only && is_sensitive_host_path(Path::new(&bind.source)))
+            .map(|bind| ModuleSpecViolation::WritableSensitiveBind(bind.source.clone()))
+            .collect();
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationError { violations })
+        }
+    }
+}
+
+/// The startup order of modules that don't specify one.
+pub const DEFAULT_STARTUP_ORDER: u32 = std::u32::MAX;
+
+/// Smallest memory limit the container runtime accepts for a container.
+pub const MIN_MEMORY_LIMIT_BYTES: u64 = 6 * 1024 * 1024;
+
+/// Longest a module may ask to be given to exit before it is killed.
+pub const MAX_STOP_TIMEOUT_SECS: u64 = 300;
+
+/// How long a pre-stop hook that doesn't set a timeout is given to finish.
+pub const DEFAULT_PRE_STOP_TIMEOUT_SECS: u64 = 30;
+
+/// Labels in this namespace are set by the runtime, not by module specs, save
+/// for the one that marks a critical update.
+pub const RESERVED_LABEL_PREFIX: &str = "net.azure-devices.edge.";
+
+/// The label of a container with the `spec_hash` of the spec that it runs,
+/// which `ModuleSet::adopt` adopts a running container by and
+/// `ModuleSet::plan` compares proposed specs with.
+pub const SPEC_HASH_LABEL: &str = "net.azure-devices.edge.spec-hash";
+
+/// The label of a container with the `spec_field_hashes` of the spec that it
+/// runs as a JSON object, so that what changed in a spec is known.
+pub const SPEC_FIELDS_LABEL: &str = "net.azure-devices.edge.spec-fields";
+
+// The field hashes are only compared, so they're kept short to keep the label
+// small.
+const SPEC_FIELD_HASH_LEN: usize = 12;
+
+/// The label of a container with its module's pre-stop hook as JSON, so that
+/// the hook is known when the module is stopped, even by a later daemon.
+pub const PRE_STOP_LABEL: &str = "net.azure-devices.edge.pre-stop";
+
+const MIN_CPU_SHARES: u32 = 2;
+const MAX_CPU_SHARES: u32 = 262_144;
+
+/// Resource limits applied to a module's container. Unset limits are left to
+/// the container runtime's defaults.
+#[derive(Clone, Debug, Default, PartialEq, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct ModuleResources {
+    /// Relative CPU weight versus other modules.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cpu_shares: Option<u32>,
+    /// Memory limit in bytes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    memory_bytes: Option<u64>,
+    /// Total memory limit (memory + swap) in bytes, or -1 for unlimited swap.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    memory_swap: Option<i64>,
+    /// Maximum number of processes, or -1 for unlimited.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pids_limit: Option<i64>,
+}
+
+impl ModuleResources {
+    pub fn new() -> Self {
+        ModuleResources::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == ModuleResources::default()
+    }
+
+    pub fn cpu_shares(&self) -> Option<u32> {
+        self.cpu_shares
+    }
+
+    pub fn with_cpu_shares(mut self, cpu_shares: u32) -> Self {
+        self.cpu_shares = Some(cpu_shares);
+        self
+    }
+
+    pub fn memory_bytes(&self) -> Option<u64> {
+        self.memory_bytes
+    }
+
+    pub fn with_memory_bytes(mut self, memory_bytes: u64) -> Self {
+        self.memory_bytes = Some(memory_bytes);
+        self
+    }
+
+    pub fn memory_swap(&self) -> Option<i64> {
+        self.memory_swap
+    }
+
+    pub fn with_memory_swap(mut self, memory_swap: i64) -> Self {
+        self.memory_swap = Some(memory_swap);
+        self
+    }
+
+    pub fn pids_limit(&self) -> Option<i64> {
+        self.pids_limit
+    }
+
+    pub fn with_pids_limit(mut self, pids_limit: i64) -> Self {
+        self.pids_limit = Some(pids_limit);
+        self
+    }
+
+    fn violations(&self) -> Vec<ModuleSpecViolation> {
+        let mut violations = vec![];
+
+        if let Some(cpu_shares) = self.cpu_shares {
+            if !(MIN_CPU_SHARES..=MAX_CPU_SHARES).contains(&cpu_shares) {
+                violations.push(ModuleSpecViolation::InvalidResourceLimit(
+                    "cpu_shares",
+                    format!(
+                        "{} is outside of the range {} to {}",
+                        cpu_shares, MIN_CPU_SHARES, MAX_CPU_SHARES
+                    ),
+                ));
+            }
+        }
+
+        if let Some(memory_bytes) = self.memory_bytes {
+            if memory_bytes < MIN_MEMORY_LIMIT_BYTES {
+                violations.push(ModuleSpecViolation::InvalidResourceLimit(
+                    "memory_bytes",
+                    format!(
+                        "{} is below the minimum of {} bytes",
+                        memory_bytes, MIN_MEMORY_LIMIT_BYTES
+                    ),
+                ));
+            } else if i64::try_from(memory_bytes).is_err() {
+                violations.push(ModuleSpecViolation::InvalidResourceLimit(
+                    "memory_bytes",
+                    format!("{} is too large", memory_bytes),
+                ));
+            }
+        }
+
+        // -1 means unlimited swap and is always allowed
+        if let Some(memory_swap) = self.memory_swap.filter(|swap| *swap != -1) {
+            match self.memory_bytes {
+                None => violations.push(ModuleSpecViolation::InvalidResourceLimit(
+                    "memory_swap",
+                    "memory_bytes must be set when setting memory_swap".to_string(),
+                )),
+                Some(memory_bytes) => {
+                    if u64::try_from(memory_swap)
+                        .ok()
+                        .map_or(true, |swap| swap < memory_bytes)
+                    {
+                        violations.push(ModuleSpecViolation::InvalidResourceLimit(
+                            "memory_swap",
+                            format!(
+                                "{} must be -1 or at least memory_bytes ({})",
+                                memory_swap, memory_bytes
+                            ),
+                        ));
+                    }
+                }
+            }
+        }
+
+        if let Some(pids_limit) = self.pids_limit {
+            if pids_limit == 0 || pids_limit < -1 {
+                violations.push(ModuleSpecViolation::InvalidResourceLimit(
+                    "pids_limit",
+                    format!("{} must be -1 or greater than 0", pids_limit),
+                ));
+            }
+        }
+
+        violations
+    }
+}
+
+// Log drivers that rotate log files, and so understand max_size and max_file
+const ROTATING_LOG_DRIVERS: [&str; 2] = ["json-file", "local"];
+
+/// How a module's logs are stored and rotated. Unset fields are left to the
+/// container runtime's defaults.
+#[derive(Clone, Debug, Default, PartialEq, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct ModuleLogConfig {
+    /// The log driver, like `json-file` or `journald`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    log_driver: Option<String>,
+    /// Size at which a log file is rotated, in bytes or with a `k`, `m` or
+    /// `g` suffix.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_size: Option<String>,
+    /// Number of log files kept, including the one being written.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_file: Option<u32>,
+}
+
+impl ModuleLogConfig {
+    pub fn new() -> Self {
+        ModuleLogConfig::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == ModuleLogConfig::default()
+    }
+
+    pub fn log_driver(&self) -> Option<&str> {
+        self.log_driver.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn with_log_driver(mut self, log_driver: String) -> Self {
+        self.log_driver = Some(log_driver);
+        self
+    }
+
+    pub fn max_size(&self) -> Option<&str> {
+        self.max_size.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn with_max_size(mut self, max_size: String) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    pub fn max_file(&self) -> Option<u32> {
+        self.max_file
+    }
+
+    pub fn with_max_file(mut self, max_file: u32) -> Self {
+        self.max_file = Some(max_file);
+        self
+    }
+
+    /// This config, or `default` if this one is empty. The two are not
+    /// merged, since rotation options for one driver may not apply to the
+    /// other's.
+    pub fn or_default<'a>(&'a self, default: &'a ModuleLogConfig) -> &'a ModuleLogConfig {
+        if self.is_empty() {
+            default
+        } else {
+            self
+        }
+    }
+
+    /// Validates this config on its own, as for the runtime's default.
+    pub fn validate(&self) -> StdResult<(), ValidationError> {
+        let violations = self.violations();
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationError { violations })
+        }
+    }
+
+    fn violations(&self) -> Vec<ModuleSpecViolation> {
+        let mut violations = vec![];
+
+        if let Some(log_driver) = &self.log_driver {
+            if log_driver.trim().is_empty() {
+                violations.push(ModuleSpecViolation::InvalidLogConfig(
+                    "log_driver",
+                    "must not be empty".to_string(),
+                ));
+            } else if (self.max_size.is_some() || self.max_file.is_some())
+                && !ROTATING_LOG_DRIVERS.contains(&log_driver.as_str())
+            {
+                violations.push(ModuleSpecViolation::InvalidLogConfig(
+                    "log_driver",
+                    format!(
+                        "{:?} does not rotate logs, so max_size and max_file can't be set",
+                        log_driver
+                    ),
+                ));
+            }
+        }
+
+        if let Some(max_size) = &self.max_size {
+            if let Err(reason) = parse_log_size(max_size) {
+                violations.push(ModuleSpecViolation::InvalidLogConfig("max_size", reason));
+            }
+        }
+
+        match self.max_file {
+            Some(0) => violations.push(ModuleSpecViolation::InvalidLogConfig(
+                "max_file",
+                "must be greater than 0".to_string(),
+            )),
+            // json-file only keeps more than one file if it rotates them
+            Some(max_file)
+                if max_file > 1
+                    && self.max_size.is_none()
+                    && self
+                        .log_driver()
+                        .map_or(true, |driver| driver == "json-file") =>
+            {
+                violations.push(ModuleSpecViolation::InvalidLogConfig(
+                    "max_file",
+                    "max_size must be set when keeping more than one log file".to_string(),
+                ))
+            }
+            _ => (),
+        }
+
+        violations
+    }
+}
+
+/// Parses a log file size like `10m` into bytes. The size is a number of
+/// bytes, optionally followed by `k`, `m` or `g` (in either case) for KiB, MiB
+/// or GiB, and must not be zero.
+fn parse_log_size(size: &str) -> StdResult<u64, String> {
+    let size = size.trim();
+    let (digits, multiplier) = match size.chars().last().map(|c| c.to_ascii_lowercase()) {
+        Some('k') => (&size[..size.len() - 1], 1 << 10),
+        Some('m') => (&size[..size.len() - 1], 1 << 20),
+        Some('g') => (&size[..size.len() - 1], 1 << 30),
+        _ => (size, 1),
+    };
+
+    let bytes = digits
+        .parse::<u64>()
+        .ok()
+        .filter(|_| digits.chars().all(|c| c.is_ascii_digit()))
+        .ok_or_else(|| {
+            format!(
+                "{:?} is not a number of bytes with an optional k, m or g suffix",
+                size
+            )
+        })?
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("{:?} is too large", size))?;
+
+    if bytes == 0 {
+        Err("must be greater than 0".to_string())
+    } else {
+        Ok(bytes)
+    }
+}
+
+/// A host path mounted into a module's container.
+#[derive(Clone, Debug, PartialEq, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct ModuleBind {
+    /// Absolute path on the host.
+    source: String,
+    /// Absolute path in the container.
+    target: String,
+    #[serde(default)]
+    read_only: bool,
+}
+
+impl ModuleBind {
+    pub fn new(source: String, target: String) -> Self {
+        ModuleBind {
+            source,
+            target,
+            read_only: false,
+        }
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    pub fn read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    fn violations(&self) -> Vec<ModuleSpecViolation> {
+        let mut violations = vec![];
+
+        for (field, path) in &[("source", &self.source), ("target", &self.target)] {
+            if let Some(reason) = mount_path_violation(path) {
+                violations.push(ModuleSpecViolation::InvalidBind(
+                    self.source.clone(),
+                    format!("{} {}", field, reason),
+                ));
+            }
+        }
+
+        violations
+    }
+}
+
+// `..` is rejected so that the strict bind policy can't be sidestepped with a
+// path like /data/../etc.
+fn mount_path_violation(path: &str) -> Option<&'static str> {
+    let path = Path::new(path);
+    if !path.is_absolute() {
+        Some("must be an absolute path")
+    } else if path.components().any(|c| c == Component::ParentDir) {
+        Some("must not contain \"..\"")
+    } else {
+        None
+    }
+}
+
+// A user of a module's container is `user[:group]`, as Docker takes it. Each
+// part is a numeric ID, which must fit in 32 bits, or the name of a user or
+// group in the image, which Docker looks up when the container starts.
+fn user_violation(user: &str) -> Option<&'static str> {
+    let mut parts = user.split(':');
+    let user = parts.next().unwrap_or_default();
+    let group = parts.next();
+    if parts.next().is_some() {
+        return Some("must be of the form user[:group]");
+    }
+
+    for part in std::iter::once(user).chain(group) {
+        if part.is_empty() {
+            return Some("must be of the form user[:group]");
+        }
+        if part.chars().all(|c| c.is_ascii_digit()) {
+            if part.parse::<u32>().is_err() {
+                return Some("numeric IDs must be less than 4294967296");
+            }
+        } else if part.starts_with('-')
+            || !part
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.')
+        {
+            return Some("names must only contain letters, digits, '_', '-' and '.'");
+        }
+    }
+    None
+}
+
+/// A tmpfs mount in a module's container, for scratch space in modules with a
+/// read-only root filesystem.
+#[derive(Clone, Debug, PartialEq, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct ModuleTmpfs {
+    /// Absolute path in the container.
+    target: String,
+    /// Size limit in bytes. Left to the container runtime if not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size_bytes: Option<u64>,
+}
+
+impl ModuleTmpfs {
+    pub fn new(target: String) -> Self {
+        ModuleTmpfs {
+            target,
+            size_bytes: None,
+        }
+    }
+
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    pub fn size_bytes(&self) -> Option<u64> {
+        self.size_bytes
+    }
+
+    pub fn with_size_bytes(mut self, size_bytes: u64) -> Self {
+        self.size_bytes = Some(size_bytes);
+        self
+    }
+
+    fn violations(&self) -> Vec<ModuleSpecViolation> {
+        let mut violations = vec![];
+
+        if let Some(reason) = mount_path_violation(&self.target) {
+            violations.push(ModuleSpecViolation::InvalidTmpfs(
+                self.target.clone(),
+                format!("target {}", reason),
+            ));
+        }
+        if self.size_bytes == Some(0) {
+            violations.push(ModuleSpecViolation::InvalidTmpfs(
+                self.target.clone(),
+                "size_bytes must be greater than 0".to_string(),
+            ));
+        }
+
+        violations
+    }
+}
+
+/// A host device that a module's container can access.
+#[derive(Clone, Debug, PartialEq, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct ModuleDevice {
+    /// Absolute path of the device on the host.
+    host_path: String,
+    /// Absolute path of the device in the container.
+    container_path: String,
+    /// What the container may do with the device, some of `r` (read), `w`
+    /// (write) and `m` (mknod).
+    #[serde(default = "default_device_permissions")]
+    permissions: String,
+}
+
+fn default_device_permissions() -> String {
+    "rwm".to_string()
+}
+
+impl ModuleDevice {
+    pub fn new(host_path: String, container_path: String) -> Self {
+        ModuleDevice {
+            host_path,
+            container_path,
+            permissions: default_device_permissions(),
+        }
+    }
+
+    pub fn host_path(&self) -> &str {
+        &self.host_path
+    }
+
+    pub fn container_path(&self) -> &str {
+        &self.container_path
+    }
+
+    pub fn permissions(&self) -> &str {
+        &self.permissions
+    }
+
+    pub fn with_permissions(mut self, permissions: String) -> Self {
+        self.permissions = permissions;
+        self
+    }
+
+    fn violations(&self) -> Vec<ModuleSpecViolation> {
+        let mut violations = vec![];
+
+        for (field, path) in &[
+            ("host_path", &self.host_path),
+            ("container_path", &self.container_path),
+        ] {
+            if let Some(reason) = mount_path_violation(path) {
+                violations.push(ModuleSpecViolation::InvalidDevice(
+                    self.host_path.clone(),
+                    format!("{} {}", field, reason),
+                ));
+            }
+        }
+
+        let mut seen = HashSet::new();
+        if self.permissions.is_empty()
+            || !self
+                .permissions
+                .chars()
+                .all(|c| "rwm".contains(c) && seen.insert(c))
+        {
+            violations.push(ModuleSpecViolation::InvalidDevice(
+                self.host_path.clone(),
+                format!(
+                    "permissions {:?} must be some of r, w and m, each at most once",
+                    self.permissions
+                ),
+            ));
+        }
+
+        violations
+    }
+}
+
+/// A command run in a module's container before it is asked to stop. The
+/// module is stopped once the command exits, fails or times out, whichever
+/// comes first.
+#[derive(Clone, Debug, PartialEq, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct PreStopHook {
+    command: Vec<String>,
+    #[serde(default = "default_pre_stop_timeout_secs")]
+    timeout_secs: u64,
+}
+
+fn default_pre_stop_timeout_secs() -> u64 {
+    DEFAULT_PRE_STOP_TIMEOUT_SECS
+}
+
+impl PreStopHook {
+    pub fn new(command: Vec<String>) -> Self {
+        PreStopHook {
+            command,
+            timeout_secs: DEFAULT_PRE_STOP_TIMEOUT_SECS,
+        }
+    }
+
+    pub fn command(&self) -> &[String] {
+        &self.command
+    }
+
+    /// How long the command is given to finish before the module is stopped
+    /// anyway.
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs)
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout_secs = timeout.as_secs();
+        self
+    }
+
+    fn violations(&self) -> Vec<ModuleSpecViolation> {
+        let mut violations = vec![];
+        if self
+            .command
+            .first()
+            .map_or(true, |arg| arg.trim().is_empty())
+        {
+            violations.push(ModuleSpecViolation::InvalidPreStopHook(
+                "command must not be empty".to_string(),
+            ));
+        }
+        if self.timeout_secs == 0 || self.timeout_secs > MAX_STOP_TIMEOUT_SECS {
+            violations.push(ModuleSpecViolation::InvalidPreStopHook(format!(
+                "timeout of {} seconds must be between 1 and {} seconds",
+                self.timeout_secs, MAX_STOP_TIMEOUT_SECS
+            )));
+        }
+        violations
+    }
+}
+
+/// A resource limit of the processes in a module's container, like the
+/// number of files they can have open.
+#[derive(Clone, Debug, PartialEq, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct ModuleUlimit {
+    /// Name of the limit as `ulimit` knows it, like `nofile`.
+    name: String,
+    /// The limit the processes start with, or -1 for unlimited.
+    soft: i64,
+    /// The limit up to which the processes can raise the soft limit, or -1
+    /// for unlimited.
+    hard: i64,
+}
+
+impl ModuleUlimit {
+    pub fn new(name: String, soft: i64, hard: i64) -> Self {
+        ModuleUlimit { name, soft, hard }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn soft(&self) -> i64 {
+        self.soft
+    }
+
+    pub fn hard(&self) -> i64 {
+        self.hard
+    }
+
+    fn violations(&self) -> Vec<ModuleSpecViolation> {
+        if !ULIMITS.contains(&self.name.as_str()) {
+            return vec![ModuleSpecViolation::UnknownUlimit(self.name.clone())];
+        }
+
+        let mut violations = vec![];
+        for (field, limit) in &[("soft", self.soft), ("hard", self.hard)] {
+            if *limit < -1 {
+                violations.push(ModuleSpecViolation::InvalidUlimit(
+                    self.name.clone(),
+                    format!("{} limit {} must be -1 (unlimited) or more", field, limit),
+                ));
+            }
+        }
+        if violations.is_empty() && ulimit_value(self.soft) > ulimit_value(self.hard) {
+            violations.push(ModuleSpecViolation::InvalidUlimit(
+                self.name.clone(),
+                format!(
+                    "soft limit {} is greater than hard limit {}",
+                    self.soft, self.hard
+                ),
+            ));
+        }
+        violations
+    }
+}
+
+// The resource limits that the container runtime can set, as `ulimit` names
+// them.
+const ULIMITS: &[&str] = &[
+    "core",
+    "cpu",
+    "data",
+    "fsize",
+    "locks",
+    "memlock",
+    "msgqueue",
+    "nice",
+    "nofile",
+    "nproc",
+    "rss",
+    "rtprio",
+    "rttime",
+    "sigpending",
+    "stack",
+];
+
+// Unlimited is more than any limit.
+fn ulimit_value(limit: i64) -> u64 {
+    u64::try_from(limit).unwrap_or(u64::max_value())
+}
+
+/// A network that a module's container joins after it is created.
+#[derive(Clone, Debug, PartialEq, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct ModuleNetwork {
+    name: String,
+    /// Names the module can be reached by on the network, in addition to its
+    /// own.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    aliases: Vec<String>,
+}
+
+impl ModuleNetwork {
+    pub fn new(name: String) -> Self {
+        ModuleNetwork {
+            name,
+            aliases: Vec::new(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn aliases(&self) -> &[String] {
+        &self.aliases
+    }
+
+    pub fn with_aliases(mut self, aliases: Vec<String>) -> Self {
+        self.aliases = aliases;
+        self
+    }
+}
+
+#[cfg(not(windows))]
+const SENSITIVE_HOST_PATHS: &[&str] = &[
+    "/bin",
+    "/boot",
+    "/dev",
+    "/etc",
+    "/lib",
+    "/lib64",
+    "/proc",
+    "/root",
+    "/run/docker.sock",
+    "/sbin",
+    "/sys",
+    "/usr",
+    "/var/lib/docker",
+    "/var/lib/iotedge",
+    "/var/run/docker.sock",
+];
+
+#[cfg(windows)]
+const SENSITIVE_HOST_PATHS: &[&str] = &[
+    r"C:\Program Files",
+    r"C:\ProgramData\docker",
+    r"C:\ProgramData\iotedge",
+    r"C:\ProgramData\iotedge-moby",
+    r"C:\Windows",
+    r"\\.\pipe\docker_engine",
+];
+
+// A path is sensitive if it is, is inside of, or contains one of the
+// sensitive host paths. So binding / is sensitive, and so is /etc/ssl.
+fn is_sensitive_host_path(path: &Path) -> bool {
+    SENSITIVE_HOST_PATHS.iter().any(|sensitive| {
+        let sensitive = Path::new(sensitive);
+        path.starts_with(sensitive) || sensitive.starts_with(path)
+    })
+}
+
+/// Maximum length of a module name.
+const MODULE_NAME_MAX_LEN: usize = 64;
+
+lazy_static! {
+    // Adapted from the reference grammar used by docker/distribution:
+    // [domain[:port]/]path-component[/path-component...][:tag][@digest]
+    // The tag can also be a pattern like `1.*`, which the runtime resolves.
+    static ref IMAGE_REFERENCE_REGEX: Regex = Regex::new(
+        r"^(?:[a-zA-Z0-9](?:[a-zA-Z0-9-]*[a-zA-Z0-9])?(?:\.[a-zA-Z0-9](?:[a-zA-Z0-9-]*[a-zA-Z0-9])?)*(?::[0-9]+)?/)?[a-z0-9]+(?:(?:[._]|__|-+)[a-z0-9]+)*(?:/[a-z0-9]+(?:(?:[._]|__|-+)[a-z0-9]+)*)*(?::[a-zA-Z0-9_*][a-zA-Z0-9_.*-]{0,127})?(?:@[A-Za-z][A-Za-z0-9]*(?:[-_+.][A-Za-z][A-Za-z0-9]*)*:[0-9a-fA-F]{32,})?$"
+    )
+    .expect("This hard-coded regex is expected to be valid.");
+}
+
+/// Gives `ModuleSpec::validate` access to the image a module config refers to.
+pub trait ImageReference {
+    fn image(&self) -> &str;
+
+    /// The digest the image is pinned to, if the image is of the form
+    /// `repo[:tag]@sha256:...`.
+    fn digest(&self) -> Option<&str> {
+        image_digest(self.image())
+    }
+
+    /// The reference the image should be pulled and run with. When the image
+    /// is pinned to a digest the tag, if any, is dropped so that the digest
+    /// wins.
+    fn pinned_image(&self) -> Cow<'_, str> {
+        pinned_image(self.image())
+    }
+}
+
+fn image_digest(image: &str) -> Option<&str> {
+    image.find('@').map(|i| &image[i + 1..])
+}
+
+fn pinned_image(image: &str) -> Cow<'_, str> {
+    let (name, digest) = match image.find('@') {
+        Some(i) => (&image[..i], &image[i + 1..]),
+        None => return Cow::Borrowed(image),
+    };
+
+    // A ':' after the last '/' separates the tag; any earlier one is a registry port.
+    let path_start = name.rfind('/').map_or(0, |i| i + 1);
+    match name[path_start..].find(':') {
+        Some(i) => Cow::Owned(format!("{}@{}", &name[..path_start + i], digest)),
+        None => Cow::Borrowed(image),
+    }
+}
+
+/// Checks that `digest` is a `sha256:` digest with 64 lowercase hex digits.
+pub fn is_valid_image_digest(digest: &str) -> bool {
+    let mut parts = digest.splitn(2, ':');
+    parts.next() == Some("sha256")
+        && parts.next().map_or(false, |hex| {
+            hex.len() == 64
+                && hex
+                    .chars()
+                    .all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c))
+        })
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ModuleSpecViolation {
+    EmptyModuleName,
+    ModuleNameTooLong(String),
+    ModuleNameInvalidStart(String),
+    ModuleNameInvalidCharacter(String, char),
+    InvalidImage(String),
+    InvalidImageDigest(String),
+    InvalidEnvKey(String),
+    InvalidResourceLimit(&'static str, String),
+    InvalidLogConfig(&'static str, String),
+    InvalidBind(String, String),
+    InvalidTmpfs(String, String),
+    InvalidDevice(String, String),
+    EmptyRuntime,
+    UnknownCapability(String),
+    InvalidSecurityProfile(&'static str, String),
+    UnknownUlimit(String),
+    InvalidUlimit(String, String),
+    InvalidMaxRetries(String),
+    InvalidUser(String, &'static str),
+    InvalidWorkingDir(String, &'static str),
+    DuplicateMountTarget(String),
+    WritableSensitiveBind(String),
+    StopTimeoutTooLong(u64),
+    UnknownStopSignal(String),
+    InvalidPreStopHook(String),
+    InvalidLabel(String),
+    ReservedLabel(String),
+    InvalidDnsServer(String),
+    EmptyNetworkName,
+    DuplicateNetwork(String),
+    CreateOptionsNotObject,
+    UnknownCreateOption(String),
+    InvalidCreateOptionType(String, &'static str),
+    MissingCreateOptionsSegment(String),
+    CreateOptionsSegmentNotString(String),
+    CreateOptionsTooLarge(usize),
+    InvalidCreateOptionsJson(String),
+}
+
+impl fmt::Display for ModuleSpecViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModuleSpecViolation::EmptyModuleName => write!(f, "module name is empty"),
+            ModuleSpecViolation::ModuleNameTooLong(name) => write!(
+                f,
+                "module name {:?} is longer than {} characters",
+                name, MODULE_NAME_MAX_LEN
+            ),
+            ModuleSpecViolation::ModuleNameInvalidStart(name) => {
+                write!(f, "module name {:?} must start with a letter", name)
+            }
+            ModuleSpecViolation::ModuleNameInvalidCharacter(name, c) => write!(
+                f,
+                "module name {:?} contains invalid character {:?}",
+                name, c
+            ),
+            ModuleSpecViolation::InvalidImage(image) => {
+                write!(f, "image {:?} is not a valid image reference", image)
+            }
+            ModuleSpecViolation::InvalidImageDigest(image) => write!(
+                f,
+                "image {:?} must be pinned to a digest of the form sha256:<64 hex digits>",
+                image
+            ),
+            ModuleSpecViolation::InvalidEnvKey(key) => {
+                write!(f, "environment variable name {:?} is invalid", key)
+            }
+            ModuleSpecViolation::InvalidResourceLimit(field, reason) => {
+                write!(f, "resource limit {} is invalid: {}", field, reason)
+            }
+            ModuleSpecViolation::InvalidLogConfig(field, reason) => {
+                write!(f, "log config {} is invalid: {}", field, reason)
+            }
+            ModuleSpecViolation::InvalidBind(source, reason) => {
+                write!(f, "bind of {:?} is invalid: {}", source, reason)
+            }
+            ModuleSpecViolation::InvalidTmpfs(target, reason) => {
+                write!(f, "tmpfs mount {:?} is invalid: {}", target, reason)
+            }
+            ModuleSpecViolation::InvalidDevice(host_path, reason) => {
+                write!(f, "device {:?} is invalid: {}", host_path, reason)
+            }
+            ModuleSpecViolation::EmptyRuntime => write!(f, "container runtime is empty"),
+            ModuleSpecViolation::UnknownCapability(capability) => {
+                write!(f, "capability {:?} is not a Linux capability", capability)
+            }
+            ModuleSpecViolation::InvalidSecurityProfile(field, reason) => {
+                write!(f, "{} is invalid: {}", field, reason)
+            }
+            ModuleSpecViolation::UnknownUlimit(name) => {
+                write!(f, "ulimit {:?} is not a known resource limit", name)
+            }
+            ModuleSpecViolation::InvalidUlimit(name, reason) => {
+                write!(f, "ulimit {:?} is invalid: {}", name, reason)
+            }
+            ModuleSpecViolation::InvalidMaxRetries(reason) => {
+                write!(f, "max retries are invalid: {}", reason)
+            }
+            ModuleSpecViolation::InvalidUser(user, reason) => {
+                write!(f, "user {:?} is invalid: {}", user, reason)
+            }
+            ModuleSpecViolation::InvalidWorkingDir(working_dir, reason) => {
+                write!(
+                    f,
+                    "working directory {:?} is invalid: {}",
+                    working_dir, reason
+                )
+            }
+            ModuleSpecViolation::DuplicateMountTarget(target) => {
+                write!(f, "more than one bind or tmpfs mount targets {:?}", target)
+            }
+            ModuleSpecViolation::WritableSensitiveBind(source) => write!(
+                f,
+                "bind of sensitive host path {:?} must be read-only",
+                source
+            ),
+            ModuleSpecViolation::InvalidLabel(key) => {
+                write!(f, "label key {:?} must not be empty or contain '='", key)
+            }
+            ModuleSpecViolation::ReservedLabel(key) => write!(
+                f,
+                "label key {:?} is in the reserved namespace {:?}",
+                key, RESERVED_LABEL_PREFIX
+            ),
+            ModuleSpecViolation::InvalidDnsServer(server) => {
+                write!(f, "DNS server {:?} is not an IP address", server)
+            }
+            ModuleSpecViolation::EmptyNetworkName => write!(f, "network name is empty"),
+            ModuleSpecViolation::DuplicateNetwork(name) => {
+                write!(f, "network {:?} is joined more than once", name)
+            }
+            ModuleSpecViolation::StopTimeoutTooLong(secs) => write!(
+                f,
+                "stop timeout of {} seconds is longer than the maximum of {} seconds",
+                secs, MAX_STOP_TIMEOUT_SECS
+            ),
+            ModuleSpecViolation::UnknownStopSignal(signal) => {
+                write!(f, "stop signal {:?} is not a signal", signal)
+            }
+            ModuleSpecViolation::InvalidPreStopHook(reason) => {
+                write!(f, "pre-stop hook is invalid: {}", reason)
+            }
+            ModuleSpecViolation::CreateOptionsNotObject => {
+                write!(f, "create options must be a JSON object")
+            }
+            ModuleSpecViolation::UnknownCreateOption(key) => {
+                write!(f, "create option {:?} is not a Docker create option", key)
+            }
+            ModuleSpecViolation::InvalidCreateOptionType(key, expected) => {
+                write!(f, "create option {:?} must be {}", key, expected)
+            }
+            ModuleSpecViolation::MissingCreateOptionsSegment(key) => {
+                write!(f, "create options segment {:?} is missing", key)
+            }
+            ModuleSpecViolation::CreateOptionsSegmentNotString(key) => {
+                write!(f, "create options segment {:?} must be a string", key)
+            }
+            ModuleSpecViolation::CreateOptionsTooLarge(max) => {
+                write!(f, "create options are longer than {} bytes", max)
+            }
+            ModuleSpecViolation::InvalidCreateOptionsJson(reason) => {
+                write!(f, "create options are not valid JSON: {}", reason)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValidationError {
+    violations: Vec<ModuleSpecViolation>,
+}
+
+impl ValidationError {
+    pub(crate) fn new(violations: Vec<ModuleSpecViolation>) -> Self {
+        ValidationError { violations }
+    }
+
+    pub fn violations(&self) -> &[ModuleSpecViolation] {
+        &self.violations
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid module spec: ")?;
+        for (i, violation) in self.violations.iter().enumerate() {
+            if i > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{}", violation)?;
+        }
+        Ok(())
+    }
+}
+
+impl Fail for ValidationError {}
+
+impl ErrorCodeKind for ValidationError {
+    fn error_code(&self) -> Option<ErrorCode> {
+        Some(ErrorCode::InvalidModuleSpec)
+    }
+}
+
+fn module_name_violations(name: &str) -> Vec<ModuleSpecViolation> {
+    let mut violations = vec![];
+
+    match name.chars().next() {
+        None => violations.push(ModuleSpecViolation::EmptyModuleName),
+        Some(c) if !c.is_ascii_alphabetic() && !c.is_whitespace() => violations.push(
+            ModuleSpecViolation::ModuleNameInvalidStart(name.to_string()),
+        ),
+        Some(_) => (),
+    }
+
+    if name.chars().count() > MODULE_NAME_MAX_LEN {
+        violations.push(ModuleSpecViolation::ModuleNameTooLong(name.to_string()));
+    }
+
+    if let Some(c) = name
+        .chars()
+        .find(|c| !(c.is_ascii_alphanumeric() || *c == '-' || *c == '_' || *c == '.'))
+    {
+        violations.push(ModuleSpecViolation::ModuleNameInvalidCharacter(
+            name.to_string(),
+            c,
+        ));
+    }
+
+    violations
+}
+
+// The Linux capabilities that the container runtime can add or drop.
+const LINUX_CAPABILITIES: &[&str] = &[
+    "AUDIT_CONTROL",
+    "AUDIT_READ",
+    "AUDIT_WRITE",
+    "BLOCK_SUSPEND",
+    "BPF",
+    "CHECKPOINT_RESTORE",
+    "CHOWN",
+    "DAC_OVERRIDE",
+    "DAC_READ_SEARCH",
+    "FOWNER",
+    "FSETID",
+    "IPC_LOCK",
+    "IPC_OWNER",
+    "KILL",
+    "LEASE",
+    "LINUX_IMMUTABLE",
+    "MAC_ADMIN",
+    "MAC_OVERRIDE",
+    "MKNOD",
+    "NET_ADMIN",
+    "NET_BIND_SERVICE",
+    "NET_BROADCAST",
+    "NET_RAW",
+    "PERFMON",
+    "SETFCAP",
+    "SETGID",
+    "SETPCAP",
+    "SETUID",
+    "SYSLOG",
+    "SYS_ADMIN",
+    "SYS_BOOT",
+    "SYS_CHROOT",
+    "SYS_MODULE",
+    "SYS_NICE",
+    "SYS_PACCT",
+    "SYS_PTRACE",
+    "SYS_RAWIO",
+    "SYS_RESOURCE",
+    "SYS_TIME",
+    "SYS_TTY_CONFIG",
+    "WAKE_ALARM",
+];
+
+// A capability is named like Docker takes it, in any case and with or without
+// the `CAP_` prefix, or is `ALL`.
+fn is_known_capability(capability: &str) -> bool {
+    let capability = capability.to_ascii_uppercase();
+    let name = if capability.starts_with("CAP_") {
+        &capability["CAP_".len()..]
+    } else {
+        &capability
+    };
+    name == "ALL" || LINUX_CAPABILITIES.contains(&name)
+}
+
+// The signals that a container can be stopped with, as Linux names them.
+const SIGNALS: &[&str] = &[
+    "ABRT", "ALRM", "BUS", "CHLD", "CONT", "FPE", "HUP", "ILL", "INT", "IO", "IOT", "KILL", "PIPE",
+    "POLL", "PROF", "PWR", "QUIT", "SEGV", "STKFLT", "STOP", "SYS", "TERM", "TRAP", "TSTP", "TTIN",
+    "TTOU", "URG", "USR1", "USR2", "VTALRM", "WINCH", "XCPU", "XFSZ",
+];
+
+// A signal is named like Docker takes it, in any case and with or without the
+// `SIG` prefix, or is a number up to SIGRTMAX. `RTMIN+n` and `RTMAX-n` are
+// taken as well.
+fn is_known_signal(signal: &str) -> bool {
+    if let Ok(number) = signal.parse::<u32>() {
+        return (1..=64).contains(&number);
+    }
+
+    let signal = signal.to_ascii_uppercase();
+    let name = if signal.starts_with("SIG") {
+        &signal["SIG".len()..]
+    } else {
+        &signal
+    };
+    if SIGNALS.contains(&name) || name == "RTMIN" || name == "RTMAX" {
+        return true;
+    }
+    if !name.starts_with("RTMIN+") && !name.starts_with("RTMAX-") {
+        return false;
+    }
+    // Both prefixes are as long.
+    name["RTMIN+".len()..]
+        .parse::<u32>()
+        .ok()
+        .map_or(false, |offset| (1..=15).contains(&offset))
+}
+
+fn is_valid_env_key(key: &str) -> bool {
+    !key.is_empty() && !key.contains(|c: char| c == '=' || c == '\0' || c.is_whitespace())
+}
+
+/// Checks that a module name can be used as a container name. Module names must
+/// be at most 64 characters long, must start with a letter and may only contain
+/// ASCII letters, digits, `-`, `_` and `.`.
+pub fn validate_module_name(name: &str) -> StdResult<(), ValidationError> {
+    let violations = module_name_violations(name);
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(ValidationError { violations })
+    }
+}
+
+impl<T> ModuleSpec<T>
+where
+    T: ImageReference,
+{
+    /// Validates the module name, the image reference and the environment
+    /// variable names of this spec. All violations are reported at once rather
+    /// than stopping at the first one.
+    pub fn validate(&self) -> StdResult<(), ValidationError> {
+        let mut violations = module_name_violations(&self.name);
+
+        let image = self.config.image();
+        match self.config.digest() {
+            Some(digest) if !is_valid_image_digest(digest) => {
+                violations.push(ModuleSpecViolation::InvalidImageDigest(image.to_string()));
+            }
+            _ if !IMAGE_REFERENCE_REGEX.is_match(image) => {
+                violations.push(ModuleSpecViolation::InvalidImage(image.to_string()));
+            }
+            _ => (),
+        }
+
+        let mut invalid_keys: Vec<_> = self
+            .env
+            .keys()
+            .filter(|key| !is_valid_env_key(key))
+            .collect();
+        invalid_keys.sort();
+        violations.extend(
+            invalid_keys
+                .into_iter()
+                .map(|key| ModuleSpecViolation::InvalidEnvKey(key.clone())),
+        );
+
+        violations.extend(self.resources.violations());
+        violations.extend(self.log_config.violations());
+
+        let mut targets = HashSet::new();
+        for bind in &self.binds {
+            violations.extend(bind.violations());
+            if !targets.insert(&bind.target) {
+                violations.push(ModuleSpecViolation::DuplicateMountTarget(
+                    bind.target.clone(),
+                ));
+            }
+        }
+        for tmpfs in &self.tmpfs {
+            violations.extend(tmpfs.violations());
+            if !targets.insert(&tmpfs.target) {
+                violations.push(ModuleSpecViolation::DuplicateMountTarget(
+                    tmpfs.target.clone(),
+                ));
+            }
+        }
+
+        for device in &self.devices {
+            violations.extend(device.violations());
+        }
+        if self
+            .runtime
+            .as_ref()
+            .map_or(false, |runtime| runtime.trim().is_empty())
+        {
+            violations.push(ModuleSpecViolation::EmptyRuntime);
+        }
+        for capability in self.cap_add.iter().chain(&self.cap_drop) {
+            if !is_known_capability(capability) {
+                violations.push(ModuleSpecViolation::UnknownCapability(capability.clone()));
+            }
+        }
+        if let Some(reason) = self
+            .seccomp_profile
+            .as_ref()
+            .and_then(|path| mount_path_violation(&path.to_string_lossy()))
+        {
+            violations.push(ModuleSpecViolation::InvalidSecurityProfile(
+                "seccomp_profile",
+                reason.to_string(),
+            ));
+        }
+        match &self.apparmor_profile {
+            Some(profile) if profile.trim().is_empty() => {
+                violations.push(ModuleSpecViolation::InvalidSecurityProfile(
+                    "apparmor_profile",
+                    "must not be empty".to_string(),
+                ));
+            }
+            _ => (),
+        }
+        let mut ulimits = HashSet::new();
+        for ulimit in &self.ulimits {
+            violations.extend(ulimit.violations());
+            if !ulimits.insert(&ulimit.name) {
+                violations.push(ModuleSpecViolation::InvalidUlimit(
+                    ulimit.name.clone(),
+                    "is set more than once".to_string(),
+                ));
+            }
+        }
+        match (self.restart_policy, self.max_retries) {
+            (RestartPolicy::OnFailure, Some(0)) => {
+                violations.push(ModuleSpecViolation::InvalidMaxRetries(
+                    "must be at least 1".to_string(),
+                ));
+            }
+            (RestartPolicy::OnFailure, _) | (_, None) => (),
+            (policy, Some(_)) => violations.push(ModuleSpecViolation::InvalidMaxRetries(format!(
+                "only apply to the on-failure restart policy, not {}",
+                policy
+            ))),
+        }
+
+        if let Some(user) = &self.user {
+            if let Some(reason) = user_violation(user) {
+                violations.push(ModuleSpecViolation::InvalidUser(user.clone(), reason));
+            }
+        }
+        if let Some(working_dir) = &self.working_dir {
+            if let Some(reason) = mount_path_violation(working_dir) {
+                violations.push(ModuleSpecViolation::InvalidWorkingDir(
+                    working_dir.clone(),
+                    reason,
+                ));
+            }
+        }
+
+        match self.stop_timeout_secs {
+            Some(secs) if secs > MAX_STOP_TIMEOUT_SECS => {
+                violations.push(ModuleSpecViolation::StopTimeoutTooLong(secs));
+            }
+            _ => (),
+        }
+        if let Some(signal) = &self.stop_signal {
+            if !is_known_signal(signal) {
+                violations.push(ModuleSpecViolation::UnknownStopSignal(signal.clone()));
+            }
+        }
+        if let Some(pre_stop) = &self.pre_stop {
+            violations.extend(pre_stop.violations());
+        }
+
+        violations.extend(label_violations(&self.labels, false));
+
+        violations.extend(dns_server_violations(&self.dns));
+
+        let mut networks = HashSet::new();
+        for network in &self.networks {
+            if network.name.is_empty() {
+                violations.push(ModuleSpecViolation::EmptyNetworkName);
+            } else if !networks.insert(&network.name) {
+                violations.push(ModuleSpecViolation::DuplicateNetwork(network.name.clone()));
+            }
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationError { violations })
+        }
+    }
+}
+
+impl<T> ModuleSpec<T>
+where
+    T: Serialize,
+{
+    /// A hash of the parts of this spec that the module's container is created
+    /// from: the config (its image and create options), the environment
+    /// variables and the paths of the env files, the resource limits, the log
+    /// config, the mounts, the stop timeout and signal, the labels, the DNS settings, the
+    /// networks, the devices, the container runtime, the capabilities and
+    /// privileges, the security profiles, the ulimits, the restart policy, the
+    /// user, the working directory, the pre-stop hook and whether the identity
+    /// integration is on. Only the path of the seccomp
+    /// profile is hashed, like those of the env files.
+    /// Two specs with the same hash don't need the module to be recreated to go
+    /// from one to the other.
+    ///
+    /// The spec is normalized first, so the hash doesn't depend on the order
+    /// of map keys or on whether unset fields are left out or set to null.
+    pub fn spec_hash(&self) -> Result<String> {
+        let spec = self.hashed_spec()?;
+        Ok(base64::encode(&Sha256::digest(spec.to_string().as_bytes())))
+    }
+
+    /// A hash of each part of `spec_hash`, by the name of the part. The config
+    /// is split up by its fields, like `config.image`, so that the part of it
+    /// that changed is known. Unset parts are left out.
+    pub fn spec_field_hashes(&self) -> Result<BTreeMap<String, String>> {
+        let spec = match self.hashed_spec()? {
+            serde_json::Value::Object(spec) => spec,
+            _ => unreachable!("the hashed spec is an object"),
+        };
+
+        let hash = |value: &serde_json::Value| {
+            let mut hash = base64::encode(&Sha256::digest(value.to_string().as_bytes()));
+            hash.truncate(SPEC_FIELD_HASH_LEN);
+            hash
+        };
+        let mut hashes = BTreeMap::new();
+        for (name, value) in spec {
+            match value {
+                serde_json::Value::Object(config) if name == "config" => {
+                    for (field, value) in config {
+                        hashes.insert(format!("config.{}", field), hash(&value));
+                    }
+                }
+                value => {
+                    hashes.insert(name, hash(&value));
+                }
+            }
+        }
+        Ok(hashes)
+    }
+
+    /// The labels that record on the module's container which spec it was
+    /// created from: its `spec_hash` and its `spec_field_hashes`.
+    pub fn spec_labels(&self) -> Result<HashMap<String, String>> {
+        let mut labels = HashMap::new();
+        labels.insert(SPEC_HASH_LABEL.to_string(), self.spec_hash()?);
+        labels.insert(
+            SPEC_FIELDS_LABEL.to_string(),
+            serde_json::json!(self.spec_field_hashes()?).to_string(),
+        );
+        Ok(labels)
+    }
+
+    fn hashed_spec(&self) -> Result<serde_json::Value> {
+        let config = serde_json::to_value(&self.config)
+            .with_context(|_| ErrorKind::ModuleSpecHash(self.name.clone()))?;
+        let resources = serde_json::to_value(&self.resources)
+            .with_context(|_| ErrorKind::ModuleSpecHash(self.name.clone()))?;
+        let env: BTreeMap<_, _> = self.env.iter().collect();
+
+        let mut spec = serde_json::json!({
+            "config": normalize(config),
+            "env": env,
+            "resources": normalize(resources),
+        });
+        // Left out when unset so that the hashes of specs that don't use them
+        // are the same as before they could be set.
+        if !self.log_config.is_empty() {
+            let log_config = serde_json::to_value(&self.log_config)
+                .with_context(|_| ErrorKind::ModuleSpecHash(self.name.clone()))?;
+            spec["log_config"] = log_config;
+        }
+        if !self.binds.is_empty() {
+            let binds = serde_json::to_value(&self.binds)
+                .with_context(|_| ErrorKind::ModuleSpecHash(self.name.clone()))?;
+            spec["binds"] = binds;
+        }
+        if self.read_only_root_fs {
+            spec["read_only_root_fs"] = serde_json::Value::Bool(true);
+        }
+        if !self.tmpfs.is_empty() {
+            let tmpfs = serde_json::to_value(&self.tmpfs)
+                .with_context(|_| ErrorKind::ModuleSpecHash(self.name.clone()))?;
+            spec["tmpfs"] = tmpfs;
+        }
+        if let Some(secs) = self.stop_timeout_secs {
+            spec["stop_timeout_secs"] = secs.into();
+        }
+        if let Some(pre_stop) = &self.pre_stop {
+            spec["pre_stop"] = serde_json::json!(pre_stop);
+        }
+        if let Some(signal) = &self.stop_signal {
+            spec["stop_signal"] = signal.as_str().into();
+        }
+        if !self.labels.is_empty() {
+            let labels: BTreeMap<_, _> = self.labels.iter().collect();
+            spec["labels"] = serde_json::json!(labels);
+        }
+        if !self.env_files.is_empty() {
+            spec["env_files"] = serde_json::json!(self.env_files);
+        }
+        if !self.dns.is_empty() {
+            spec["dns"] = serde_json::json!(self.dns);
+        }
+        if !self.dns_search.is_empty() {
+            spec["dns_search"] = serde_json::json!(self.dns_search);
+        }
+        if !self.networks.is_empty() {
+            let networks = serde_json::to_value(&self.networks)
+                .with_context(|_| ErrorKind::ModuleSpecHash(self.name.clone()))?;
+            spec["networks"] = networks;
+        }
+        if !self.devices.is_empty() {
+            let devices = serde_json::to_value(&self.devices)
+                .with_context(|_| ErrorKind::ModuleSpecHash(self.name.clone()))?;
+            spec["devices"] = devices;
+        }
+        if let Some(runtime) = &self.runtime {
+            spec["runtime"] = serde_json::json!(runtime);
+        }
+        if !self.cap_add.is_empty() {
+            spec["cap_add"] = serde_json::json!(self.cap_add);
+        }
+        if !self.cap_drop.is_empty() {
+            spec["cap_drop"] = serde_json::json!(self.cap_drop);
+        }
+        if self.no_new_privileges {
+            spec["no_new_privileges"] = serde_json::Value::Bool(true);
+        }
+        if let Some(seccomp_profile) = &self.seccomp_profile {
+            spec["seccomp_profile"] = serde_json::json!(seccomp_profile);
+        }
+        if let Some(apparmor_profile) = &self.apparmor_profile {
+            spec["apparmor_profile"] = serde_json::json!(apparmor_profile);
+        }
+        if !self.ulimits.is_empty() {
+            spec["ulimits"] = serde_json::json!(self.ulimits);
+        }
+        if !is_default_restart_policy(&self.restart_policy) {
+            spec["restart_policy"] = serde_json::json!(self.restart_policy);
+        }
+        if let Some(max_retries) = self.max_retries {
+            spec["max_retries"] = serde_json::json!(max_retries);
+        }
+        if let Some(user) = &self.user {
+            spec["user"] = serde_json::json!(user);
+        }
+        if let Some(working_dir) = &self.working_dir {
+            spec["working_dir"] = serde_json::json!(working_dir);
+        }
+        if self.identity_integration {
+            spec["identity_integration"] = serde_json::Value::Bool(true);
+        }
+        Ok(spec)
+    }
+}
+
+/// Checks that each of `servers` is an IP address, as the DNS servers of
+/// modules and the runtime's default ones must be.
+pub fn validate_dns_servers(servers: &[String]) -> StdResult<(), ValidationError> {
+    let violations = dns_server_violations(servers);
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(ValidationError { violations })
+    }
+}
+
+/// Checks the labels that the runtime adds to every module it creates. Unlike
+/// the labels of a module spec they may be in the reserved namespace.
+pub fn validate_injected_labels(
+    labels: &HashMap<String, String>,
+) -> StdResult<(), ValidationError> {
+    let violations = label_violations(labels, true);
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(ValidationError { violations })
+    }
+}
+
+/// Checks the environment variables that the runtime adds to every module it
+/// creates.
+pub fn validate_global_env(env: &HashMap<String, String>) -> StdResult<(), ValidationError> {
+    let mut invalid_keys: Vec<_> = env.keys().filter(|key| !is_valid_env_key(key)).collect();
+    invalid_keys.sort();
+    let violations: Vec<_> = invalid_keys
+        .into_iter()
+        .map(|key| ModuleSpecViolation::InvalidEnvKey(key.clone()))
+        .collect();
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(ValidationError { violations })
+    }
+}
+
+fn label_violations(
+    labels: &HashMap<String, String>,
+    allow_reserved: bool,
+) -> Vec<ModuleSpecViolation> {
+    let mut keys: Vec<_> = labels.keys().collect();
+    keys.sort();
+    keys.into_iter()
+        .filter_map(|key| {
+            if key.is_empty() || key.contains('=') {
+                Some(ModuleSpecViolation::InvalidLabel(key.clone()))
+            } else if !allow_reserved
+                && key.starts_with(RESERVED_LABEL_PREFIX)
+                && key != CRITICAL_UPDATE_LABEL
+            {
+                Some(ModuleSpecViolation::ReservedLabel(key.clone()))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+fn dns_server_violations(servers: &[String]) -> Vec<ModuleSpecViolation> {
+    servers
+        .iter()
+        .filter(|server| server.parse::<IpAddr>().is_err())
+        .map(|server| ModuleSpecViolation::InvalidDnsServer(server.clone()))
+        .collect()
+}
+
+// serde_json sorts object keys, so only null members need to be dropped.
+fn normalize(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(map) => serde_json::Value::Object(
+            map.into_iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(key, value)| (key, normalize(value)))
+                .collect(),
+        ),
+        serde_json::Value::Array(values) => {
+            serde_json::Value::Array(values.into_iter().map(normalize).collect())
+        }
+        value => value,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogTail {
+    All,
+    Num(u64),
+}
+
+impl Default for LogTail {
+    fn default() -> Self {
+        LogTail::All
+    }
+}
+
+impl FromStr for LogTail {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let tail = if s == "all" {
+            LogTail::All
+        } else {
+            let num = s
+                .parse::<u64>()
+                .with_context(|_| ErrorKind::InvalidLogTail(s.to_string()))?;
+            LogTail::Num(num)
+        };
+        Ok(tail)
+    }
+}
+
+impl ToString for LogTail {
+    fn to_string(&self) -> String {
+        match self {
+            LogTail::All => "all".to_string(),
+            LogTail::Num(n) => n.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct LogOptions {
+    follow: bool,
+    tail: LogTail,
+    since: i32,
+    until: Option<i32>,
+    timestamps: bool,
+}
+
+impl LogOptions {
+    pub fn new() -> Self {
+        LogOptions {
+            follow: false,
+            tail: LogTail::All,
+            since: 0,
+            until: None,
+            timestamps: false,
+        }
+    }
+
+    pub fn with_follow(mut self, follow: bool) -> Self {
+        self.follow = follow;
+        self
+    }
+
+    pub fn with_tail(mut self, tail: LogTail) -> Self {
+        self.tail = tail;
+        self
+    }
+
+    pub fn with_since(mut self, since: i32) -> Self {
+        self.since = since;
+        self
+    }
+
+    pub fn with_until(mut self, until: i32) -> Self {
+        self.until = Some(until);
+        self
+    }
+
+    /// Prefixes each line with the time the module wrote it.
+    pub fn with_timestamps(mut self, timestamps: bool) -> Self {
+        self.timestamps = timestamps;
+        self
+    }
+
+    pub fn follow(&self) -> bool {
+        self.follow
+    }
+
+    pub fn tail(&self) -> &LogTail {
+        &self.tail
+    }
+
+    pub fn since(&self) -> i32 {
+        self.since
+    }
+
+    pub fn until(&self) -> Option<i32> {
+        self.until
+    }
+
+    pub fn timestamps(&self) -> bool {
+        self.timestamps
+    }
+}
+
+pub trait Module {
+    type Config;
+    type Error: Fail;
+    type RuntimeStateFuture: Future<Item = ModuleRuntimeState, Error = Self::Error> + Send;
+
+    fn name(&self) -> &str;
+    fn type_(&self) -> &str;
+    fn config(&self) -> &Self::Config;
+    fn runtime_state(&self) -> Self::RuntimeStateFuture;
+}
+
+pub trait ModuleRegistry {
+    type Error: Fail;
+    type PullFuture: Future<Item = (), Error = Self::Error> + Send;
+    type RemoveFuture: Future<Item = (), Error = Self::Error>;
+    type ImageExistsFuture: Future<Item = bool, Error = Self::Error> + Send;
+    type Config;
+
+    fn pull(&self, config: &Self::Config) -> Self::PullFuture;
+    fn remove(&self, name: &str) -> Self::RemoveFuture;
+    /// Whether the image of `config` is already on the device, so that it
+    /// doesn't have to be pulled.
+    fn image_exists(&self, config: &Self::Config) -> Self::ImageExistsFuture;
+}
+
+#[derive(Debug)]
+pub struct SystemInfo {
+    /// OS Type of the Host. Example of value expected: \"linux\" and \"windows\".
+    os_type: String,
+    /// Hardware architecture of the host. Example of value expected: arm32, x86, amd64
+    architecture: String,
+    /// iotedge version string
+    version: &'static str,
+    /// The container runtime, like docker.
+    runtime_type: Option<String>,
+    /// The version of the container runtime.
+    runtime_version: Option<String>,
+    /// The name of the host's operating system, like Ubuntu 18.04.3 LTS.
+    operating_system: Option<String>,
+    kernel_version: Option<String>,
+    /// Total memory of the host, in bytes.
+    total_memory: Option<u64>,
+}
+
+impl SystemInfo {
+    pub fn new(os_type: String, architecture: String) -> Self {
+        SystemInfo {
+            os_type,
+            architecture,
+            version: super::version_with_source_version(),
+            runtime_type: None,
+            runtime_version: None,
+            operating_system: None,
+            kernel_version: None,
+            total_memory: None,
+        }
+    }
+
+    pub fn with_runtime_type(mut self, runtime_type: String) -> Self {
+        self.runtime_type = Some(runtime_type);
+        self
+    }
+
+    pub fn with_runtime_version(mut self, runtime_version: Option<String>) -> Self {
+        self.runtime_version = runtime_version;
+        self
+    }
+
+    pub fn with_operating_system(mut self, operating_system: Option<String>) -> Self {
+        self.operating_system = operating_system;
+        self
+    }
+
+    pub fn with_kernel_version(mut self, kernel_version: Option<String>) -> Self {
+        self.kernel_version = kernel_version;
+        self
+    }
+
+    pub fn with_total_memory(mut self, total_memory: Option<u64>) -> Self {
+        self.total_memory = total_memory;
+        self
+    }
+
+    pub fn os_type(&self) -> &str {
+        &self.os_type
+    }
+
+    pub fn architecture(&self) -> &str {
+        &self.architecture
+    }
+
+    pub fn version(&self) -> &str {
+        self.version
+    }
+
+    pub fn runtime_type(&self) -> Option<&str> {
+        self.runtime_type.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn runtime_version(&self) -> Option<&str> {
+        self.runtime_version.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn operating_system(&self) -> Option<&str> {
+        self.operating_system.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn kernel_version(&self) -> Option<&str> {
+        self.kernel_version.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn total_memory(&self) -> Option<u64> {
+        self.total_memory
+    }
+}
+
+#[derive(Debug, serde_derive::Serialize)]
+pub struct SystemResources {
+    host_uptime: u64,
+    process_uptime: u64,
+    used_cpu: f64,
+    used_ram: u64,
+    total_ram: u64,
+    disks: Vec<DiskInfo>,
+    docker_stats: String,
+}
+
+impl SystemResources {
+    pub fn new(
+        host_uptime: u64,
+        process_uptime: u64,
+        used_cpu: f64,
+        used_ram: u64,
+        total_ram: u64,
+        disks: Vec<DiskInfo>,
+        docker_stats: String,
+    ) -> Self {
+        SystemResources {
+            host_uptime,
+            process_uptime,
+            used_cpu,
+            used_ram,
+            total_ram,
+            disks,
+            docker_stats,
+        }
+    }
+
+    pub fn host_uptime(&self) -> u64 {
+        self.host_uptime
+    }
+
+    pub fn process_uptime(&self) -> u64 {
+        self.process_uptime
+    }
+
+    pub fn used_cpu(&self) -> f64 {
+        self.used_cpu
+    }
+
+    pub fn used_ram(&self) -> u64 {
+        self.used_ram
+    }
+
+    pub fn total_ram(&self) -> u64 {
+        self.total_ram
+    }
+
+    pub fn disks(&self) -> &[DiskInfo] {
+        &self.disks
+    }
+
+    /// The runtime's stats for each module, as a JSON array.
+    pub fn docker_stats(&self) -> &str {
+        &self.docker_stats
+    }
+}
+
+#[derive(Debug, serde_derive::Serialize)]
+pub struct DiskInfo {
+    name: String,
+    available_space: u64,
+    total_space: u64,
+    file_system: String,
+    file_type: String,
+}
+
+impl DiskInfo {
+    pub fn new(
+        name: String,
+        available_space: u64,
+        total_space: u64,
+        file_system: String,
+        file_type: String,
+    ) -> Self {
+        DiskInfo {
+            name,
+            available_space,
+            total_space,
+            file_system,
+            file_type,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ModuleTop {
+    /// Name of the module. Example: tempSensor
+    name: String,
+    /// A vector of process IDs (PIDs) representing a snapshot of all processes running inside the module.
+    process_ids: Vec<i32>,
+}
+
+impl ModuleTop {
+    pub fn new(name: String, process_ids: Vec<i32>) -> Self {
+        ModuleTop { name, process_ids }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn process_ids(&self) -> &[i32] {
+        &self.process_ids
+    }
+}
+
+/// A snapshot of the resources a running module is using.
+#[derive(Clone, Debug, PartialEq, serde_derive::Serialize)]
+pub struct ModuleStats {
+    name: String,
+    /// CPU usage since the previous sample, as a percentage of a single CPU.
+    /// A module using two CPUs fully reports 200.
+    cpu_percent: f64,
+    /// Bytes in use, not counting page cache that can be reclaimed.
+    memory_usage: u64,
+    /// 0 if the runtime doesn't report the module's memory limit.
+    memory_limit: u64,
+}
+
+impl ModuleStats {
+    pub fn new(name: String, cpu_percent: f64, memory_usage: u64, memory_limit: u64) -> Self {
+        ModuleStats {
+            name,
+            cpu_percent,
+            memory_usage,
+            memory_limit,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn cpu_percent(&self) -> f64 {
+        self.cpu_percent
+    }
+
+    pub fn memory_usage(&self) -> u64 {
+        self.memory_usage
+    }
+
+    pub fn memory_limit(&self) -> u64 {
+        self.memory_limit
+    }
+}
+
+/// Which images `ModuleRuntime::prune_images` removes. Images that a module
+/// uses are never removed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImagePruneFilter {
+    /// Images that no longer have a tag, like the ones that pulling a newer
+    /// image for a tag leaves behind.
+    Dangling,
+    /// Images that no module uses, whether they have a tag or not.
+    Unused,
+}
+
+/// The images that `ModuleRuntime::prune_images` removed.
+#[derive(Clone, Debug, Default, PartialEq, serde_derive::Serialize)]
+pub struct PrunedImages {
+    images: Vec<String>,
+    space_reclaimed: u64,
+}
+
+impl PrunedImages {
+    pub fn new(images: Vec<String>, space_reclaimed: u64) -> Self {
+        PrunedImages {
+            images,
+            space_reclaimed,
+        }
+    }
+
+    /// The IDs of the removed images.
+    pub fn images(&self) -> &[String] {
+        &self.images
+    }
+
+    /// The bytes of storage that removing the images freed.
+    pub fn space_reclaimed(&self) -> u64 {
+        self.space_reclaimed
+    }
+}
+
+/// What a command run by `ModuleRuntime::exec` writes, in the order it was
+/// written, followed by how it exited.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExecOutput {
+    /// Output of the command to stdout or stderr.
+    Output(Bytes),
+    /// The exit code of the command, which is the last item of the stream.
+    Exited(i64),
+}
+
+pub trait ProvisioningResult {
+    fn device_id(&self) -> &str;
+    fn hub_name(&self) -> &str;
+}
+
+/// Constructs a `ModuleRuntime` from the daemon settings. This is the entry
+/// point the daemon uses to instantiate whichever runtime it was built with.
+pub trait MakeModuleRuntime {
+    type Config: Clone + Send;
+    type Settings: RuntimeSettings<Config = Self::Config>;
+    type ProvisioningResult: ProvisioningResult;
+    type ModuleRuntime: ModuleRuntime<Config = Self::Config>;
+    type Error: Fail;
+    type Future: Future<Item = Self::ModuleRuntime, Error = Self::Error> + Send;
+
+    fn make_runtime(
+        settings: Self::Settings,
+        provisioning_result: Self::ProvisioningResult,
+        crypto: impl GetTrustBundle + Send + 'static,
+    ) -> Self::Future;
+}
+
+/// The integration seam between the daemon and a container runtime.
+///
+/// The daemon and the watchdog only drive modules through this trait, so a
+/// new runtime can live in its own crate (see `edgelet-docker` and
+/// `edgelet-kube`). The management API isn't independent of Docker yet: it
+/// picks the status codes of runtime errors from `edgelet_docker::ErrorKind`,
+/// so the errors of other runtimes are internal server errors, and its client
+/// only shows the image of modules whose settings are a `DockerConfig`.
+///
+/// Implementations must uphold the following:
+///
+/// - Every operation is asynchronous and its future must be `Send`, since
+///   futures are spawned on a multi-threaded tokio runtime.
+/// - `Error` must implement `Fail`, and `&Error` must convert into
+///   `ModuleRuntimeErrorReason` so that callers can tell a missing module
+///   apart from other failures.
+/// - `id` parameters are module names, not runtime specific identifiers.
+/// - `stop` sends a graceful stop request and only kills the module after
+///   `wait_before_kill` has elapsed (or the runtime's default if `None`).
+/// - `logs` yields the raw log stream in the Docker multiplexed framing
+///   (see `decode_logs` to separate stdout and stderr), ending when the module
+///   exits or, without `follow`, when the requested logs have been sent.
+///   Dropping the stream must close the underlying connection right away, even
+///   while a followed module isn't writing anything.
+/// - `stats` fails with an error that names the module as not running, rather
+///   than returning zeroes, when the module has stopped.
+pub trait ModuleRuntime: Sized {
+    type Error: Fail;
+
+    type Config: Clone + Send;
+    type Module: Module<Config = Self::Config> + Send;
+    type ModuleRegistry: ModuleRegistry<Config = Self::Config, Error = Self::Error>;
+    type Chunk: AsRef<[u8]>;
+    type Logs: Stream<Item = Self::Chunk, Error = Self::Error> + Send;
+
+    type CreateFuture: Future<Item = (), Error = Self::Error> + Send;
+    type GetFuture: Future<Item = (Self::Module, ModuleRuntimeState), Error = Self::Error> + Send;
+    type ListFuture: Future<Item = Vec<Self::Module>, Error = Self::Error> + Send;
+    type ListWithDetailsStream: Stream<Item = (Self::Module, ModuleRuntimeState), Error = Self::Error>
+        + Send;
+    type LogsFuture: Future<Item = Self::Logs, Error = Self::Error> + Send;
+    type RemoveFuture: Future<Item = (), Error = Self::Error> + Send;
+    type RestartFuture: Future<Item = (), Error = Self::Error> + Send;
+    type StartFuture: Future<Item = (), Error = Self::Error> + Send;
+    type StopFuture: Future<Item = (), Error = Self::Error> + Send;
+    type SystemInfoFuture: Future<Item = SystemInfo, Error = Self::Error> + Send;
+    type SystemResourcesFuture: Future<Item = SystemResources, Error = Self::Error> + Send;
+    type RemoveAllFuture: Future<Item = (), Error = Self::Error> + Send;
+    type StatsFuture: Future<Item = ModuleStats, Error = Self::Error> + Send;
+    type PruneImagesFuture: Future<Item = PrunedImages, Error = Self::Error> + Send;
+    type ExecStream: Stream<Item = ExecOutput, Error = Self::Error> + Send;
+    type EffectiveSpecFuture: Future<Item = ModuleSpec<Self::Config>, Error = Self::Error> + Send;
+
+    /// Creates the module without starting it. Fails if the module already exists.
+    fn create(&self, module: ModuleSpec<Self::Config>) -> Self::CreateFuture;
+    /// Checks that `create` would accept the module without changing any
+    /// runtime state and without pulling the image.
+    fn validate(&self, module: &ModuleSpec<Self::Config>) -> StdResult<(), Self::Error>;
+    /// Gets the module and its current state.
+    fn get(&self, id: &str) -> Self::GetFuture;
+    /// The spec that the module was last created from, with the defaults of
+    /// the runtime applied and the variables that every module gets merged in,
+    /// as it differs from the spec that was asked for. Secrets are redacted.
+    fn effective_spec(&self, id: &str) -> Self::EffectiveSpecFuture;
+    fn start(&self, id: &str) -> Self::StartFuture;
+    fn stop(&self, id: &str, wait_before_kill: Option<Duration>) -> Self::StopFuture;
+    /// Stops the module if it is running and starts it again, so a module
+    /// that isn't running is only started. Restarting or starting the module
+    /// while it restarts waits for that restart instead of cycling it again.
+    fn restart(&self, id: &str) -> Self::RestartFuture;
+    /// Removes the module, stopping it first if it is still running.
+    fn remove(&self, id: &str) -> Self::RemoveFuture;
+    /// Removes the module like `remove` along with its anonymous volumes,
+    /// also when it is stuck, such as in the middle of being removed. A
+    /// module that doesn't exist is taken to be removed already. Runtimes
+    /// that can't do more than `remove` only remove the module.
+    fn force_remove(&self, id: &str) -> Self::RemoveFuture {
+        self.remove(id)
+    }
+    fn system_info(&self) -> Self::SystemInfoFuture;
+    fn system_resources(&self) -> Self::SystemResourcesFuture;
+    /// Lists the modules managed by the daemon. Other workloads the runtime
+    /// knows about must not be returned.
+    fn list(&self) -> Self::ListFuture;
+    fn list_with_details(&self) -> Self::ListWithDetailsStream;
+    fn logs(&self, id: &str, options: &LogOptions) -> Self::LogsFuture;
+    fn registry(&self) -> &Self::ModuleRegistry;
+    /// Removes every module returned by `list`.
+    fn remove_all(&self) -> Self::RemoveAllFuture;
+    /// Gets the module's current CPU and memory usage.
+    fn stats(&self, id: &str) -> Self::StatsFuture;
+    /// Removes the images that `filter` selects and that no module uses, such
+    /// as the ones that failed updates leave behind.
+    fn prune_images(&self, filter: ImagePruneFilter) -> Self::PruneImagesFuture;
+    /// Runs `cmd` in the running module with `env` added to the module's
+    /// environment, for diagnostics. The command doesn't get a stdin or a TTY.
+    fn exec(&self, id: &str, cmd: Vec<String>, env: HashMap<String, String>) -> Self::ExecStream;
+    /// The lifecycle events of the modules this runtime creates, starts and
+    /// stops.
+    fn events(&self) -> &ModuleEvents;
+    /// Keeps `error` as the last error of the module, for failures that
+    /// happen outside of the runtime's own operations, like pulling the image
+    /// of a module before it is created. Runtimes that don't keep the errors
+    /// of modules ignore it.
+    fn record_error(&self, _id: &str, _error: ModuleError) {}
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum ModuleRuntimeErrorReason {
+    NotFound,
+    Other,
+}
+
+// Useful for error contexts
+#[derive(Clone, Copy, Debug)]
+pub enum ModuleOperation {
+    RuntimeState,
+}
+
+impl fmt::Display for ModuleOperation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModuleOperation::RuntimeState => write!(f, "Could not query module runtime state"),
+        }
+    }
+}
+
+// Useful for error contexts
+#[derive(Clone, Debug)]
+pub enum RegistryOperation {
+    ImportImage(String),
+    PullImage(String),
+    RemoveImage(String),
+}
+
+impl fmt::Display for RegistryOperation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegistryOperation::ImportImage(path) => {
+                write!(f, "Could not import images from {}", path)
+            }
+            RegistryOperation::PullImage(name) => write!(f, "Could not pull image {}", name),
+            RegistryOperation::RemoveImage(name) => write!(f, "Could not remove image {}", name),
+        }
+    }
+}
+
+// Useful for error contexts
+#[derive(Clone, Debug, PartialEq)]
+pub enum RuntimeOperation {
+    CreateModule(String),
+    ExecModule(String),
+    GetEffectiveSpec(String),
+    GetModule(String),
+    GetModuleLogs(String),
+    GetModuleStats(String),
+    Init,
+    ListModules,
+    PruneImages,
+    RemoveModule(String),
+    RestartModule(String),
+    StartModule(String),
+    StopModule(String),
+    SystemInfo,
+    SystemResources,
+    TopModule(String),
+}
+
+impl fmt::Display for RuntimeOperation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeOperation::CreateModule(name) => write!(f, "Could not create module {}", name),
+            RuntimeOperation::ExecModule(name) => {
+                write!(f, "Could not run command in module {}", name)
+            }
+            RuntimeOperation::GetEffectiveSpec(name) => {
+                write!(f, "Could not get the effective spec of module {}", name)
+            }
+            RuntimeOperation::GetModule(name) => write!(f, "Could not get module {}", name),
+            RuntimeOperation::GetModuleLogs(name) => {
+                write!(f, "Could not get logs for module {}", name)
+            }
+            RuntimeOperation::GetModuleStats(name) => {
+                write!(f, "Could not get stats for module {}", name)
+            }
+            RuntimeOperation::Init => write!(f, "Could not initialize module runtime"),
+            RuntimeOperation::ListModules => write!(f, "Could not list modules"),
+            RuntimeOperation::PruneImages => write!(f, "Could not prune images"),
+            RuntimeOperation::RemoveModule(name) => write!(f, "Could not remove module {}", name),
+            RuntimeOperation::RestartModule(name) => write!(f, "Could not restart module {}", name),
+            RuntimeOperation::StartModule(name) => write!(f, "Could not start module {}", name),
+            RuntimeOperation::StopModule(name) => write!(f, "Could not stop module {}", name),
+            RuntimeOperation::SystemInfo => write!(f, "Could not query system info"),
+            RuntimeOperation::SystemResources => write!(f, "Could not query system resources"),
+            RuntimeOperation::TopModule(name) => write!(f, "Could not top module {}", name),
+        }
+    }
+}
+
+/// When the image of a module is pulled from its registry.
+#[derive(Clone, Copy, Debug, serde_derive::Deserialize, PartialEq, serde_derive::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImagePullPolicy {
+    /// The image is pulled whenever the module is created. Images are only
+    /// pulled when modules are created, so `always` is another name for it.
+    #[serde(rename = "on-create", alias = "always")]
+    OnCreate,
+    /// The image is pulled when the module is created only if it isn't on
+    /// the device yet.
+    #[serde(rename = "if-not-present")]
+    IfNotPresent,
+    /// The image is never pulled, so it has to be on the device already.
+    Never,
+}
+
+impl Default for ImagePullPolicy {
+    fn default() -> Self {
+        ImagePullPolicy::OnCreate
+    }
+}
+
+/// Whether a module is started again after it exits. Both the container
+/// runtime and the watchdog honor it, the watchdog leaving a module whose
+/// policy doesn't restart it exited for good.
+#[derive(Clone, Copy, Debug, serde_derive::Deserialize, PartialEq, serde_derive::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartPolicy {
+    /// The module is started again whenever it exits.
+    Always,
+    /// The module is started again only if it exits with a non-zero exit
+    /// code, up to the module's `max_retries` times if it has them.
+    OnFailure,
+    /// The module is never started again, so it runs to completion once.
+    Never,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        RestartPolicy::Always
+    }
+}
+
+impl FromStr for RestartPolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> StdResult<RestartPolicy, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "always" => Ok(RestartPolicy::Always),
+            "on-failure" => Ok(RestartPolicy::OnFailure),
+            "never" => Ok(RestartPolicy::Never),
+            _ => Err(Error::from(ErrorKind::InvalidRestartPolicy(s.to_string()))),
+        }
+    }
+}
+
+impl fmt::Display for RestartPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RestartPolicy::Always => write!(f, "always"),
+            RestartPolicy::OnFailure => write!(f, "on-failure"),
+            RestartPolicy::Never => write!(f, "never"),
+        }
+    }
+}
+
+impl FromStr for ImagePullPolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> StdResult<ImagePullPolicy, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "on-create" | "always" => Ok(ImagePullPolicy::OnCreate),
+            "if-not-present" => Ok(ImagePullPolicy::IfNotPresent),
+            "never" => Ok(ImagePullPolicy::Never),
+            _ => Err(Error::from(ErrorKind::InvalidImagePullPolicy(
+                s.to_string(),
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::str::FromStr;
+    use std::string::ToString;
+
+    use crate::error::ErrorKind;
     use crate::module::ModuleStatus;
 
-    fn get_inputs() -> Vec<(&'static str, ModuleStatus)> {
-        vec![
-            ("unknown", ModuleStatus::Unknown),
-            ("running", ModuleStatus::Running),
-            ("stopped", ModuleStatus::Stopped),
-            ("failed", ModuleStatus::Failed),
-        ]
+    fn get_inputs() -> Vec<(&'static str, ModuleStatus)> {
+        vec![
+            ("unknown", ModuleStatus::Unknown),
+            ("running", ModuleStatus::Running),
+            ("stopped", ModuleStatus::Stopped),
+            ("failed", ModuleStatus::Failed),
+        ]
+    }
+
+    #[test]
+    fn module_status_ser() {
+        let inputs = get_inputs();
+        for &(expected, ref status) in &inputs {
+            assert_eq!(expected, &status.to_string());
+        }
+    }
+
+    #[test]
+    fn module_status_deser() {
+        let inputs = get_inputs();
+        for &(status, ref expected) in &inputs {
+            assert_eq!(*expected, ModuleStatus::from_str(status).unwrap());
+        }
+    }
+
+    #[test]
+    fn image_pull_policy_from_str() {
+        let inputs = vec![
+            ("On-Create", ImagePullPolicy::OnCreate),
+            ("always", ImagePullPolicy::OnCreate),
+            ("If-Not-Present", ImagePullPolicy::IfNotPresent),
+            ("never", ImagePullPolicy::Never),
+        ];
+        for &(policy, expected) in &inputs {
+            assert_eq!(expected, ImagePullPolicy::from_str(policy).unwrap());
+        }
+
+        match ImagePullPolicy::from_str("sometimes").unwrap_err().kind() {
+            ErrorKind::InvalidImagePullPolicy(policy) => assert_eq!("sometimes", policy),
+            kind => panic!("Expected `InvalidImagePullPolicy` error but got {:?}", kind),
+        }
+
+        let policy: ImagePullPolicy = serde_json::from_str("\"always\"").unwrap();
+        assert_eq!(ImagePullPolicy::OnCreate, policy);
+        assert_eq!(
+            "\"if-not-present\"",
+            serde_json::to_string(&ImagePullPolicy::IfNotPresent).unwrap()
+        );
+    }
+
+    #[test]
+    fn health_state_ser_deser() {
+        let inputs = vec![
+            ("starting", HealthState::Starting),
+            ("healthy", HealthState::Healthy),
+            ("unhealthy", HealthState::Unhealthy),
+        ];
+        for &(health, expected) in &inputs {
+            assert_eq!(expected, HealthState::from_str(health).unwrap());
+            assert_eq!(health, &expected.to_string());
+        }
+
+        // Docker reports "none" for containers without a health check
+        assert!(HealthState::from_str("none").is_err());
+    }
+
+    #[test]
+    fn module_config_empty_name_fails() {
+        let name = "".to_string();
+        match ModuleSpec::new(
+            name.clone(),
+            "docker".to_string(),
+            10_i32,
+            HashMap::new(),
+            ImagePullPolicy::default(),
+        ) {
+            Ok(_) => panic!("Expected error"),
+            Err(err) => {
+                if let ErrorKind::InvalidModuleName(s) = err.kind() {
+                    assert_eq!(s, &name);
+                } else {
+                    panic!("Expected `InvalidModuleName` but got {:?}", err);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn module_config_white_space_name_fails() {
+        let name = "    ".to_string();
+        match ModuleSpec::new(
+            name.clone(),
+            "docker".to_string(),
+            10_i32,
+            HashMap::new(),
+            ImagePullPolicy::default(),
+        ) {
+            Ok(_) => panic!("Expected error"),
+            Err(err) => {
+                if let ErrorKind::InvalidModuleName(s) = err.kind() {
+                    assert_eq!(s, &name);
+                } else {
+                    panic!("Expected `InvalidModuleName` but got {:?}", err);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn module_config_empty_type_fails() {
+        let type_ = "    ".to_string();
+        match ModuleSpec::new(
+            "m1".to_string(),
+            type_.clone(),
+            10_i32,
+            HashMap::new(),
+            ImagePullPolicy::default(),
+        ) {
+            Ok(_) => panic!("Expected error"),
+            Err(err) => {
+                if let ErrorKind::InvalidModuleType(s) = err.kind() {
+                    assert_eq!(s, &type_);
+                } else {
+                    panic!("Expected `InvalidModuleType` but got {:?}", err);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn module_config_white_space_type_fails() {
+        let type_ = "    ".to_string();
+        match ModuleSpec::new(
+            "m1".to_string(),
+            type_.clone(),
+            10_i32,
+            HashMap::new(),
+            ImagePullPolicy::default(),
+        ) {
+            Ok(_) => panic!("Expected error"),
+            Err(err) => {
+                if let ErrorKind::InvalidModuleType(s) = err.kind() {
+                    assert_eq!(s, &type_);
+                } else {
+                    panic!("Expected `InvalidModuleType` but got {:?}", err);
+                }
+            }
+        }
+    }
+
+    struct TestImage(&'static str);
+
+    impl ImageReference for TestImage {
+        fn image(&self) -> &str {
+            self.0
+        }
+    }
+
+    fn spec_with(name: &str, image: &'static str, env: &[(&str, &str)]) -> ModuleSpec<TestImage> {
+        ModuleSpec::new(
+            name.to_string(),
+            "docker".to_string(),
+            TestImage(image),
+            env.iter()
+                .map(|(k, v)| ((*k).to_string(), (*v).to_string()))
+                .collect(),
+            ImagePullPolicy::default(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn validate_module_name_succeeds() {
+        for name in &["edgeAgent", "edgeHub", "temp-sensor_1.0", "a"] {
+            validate_module_name(name).unwrap();
+        }
+    }
+
+    #[test]
+    fn validate_module_name_fails() {
+        let too_long = "a".repeat(MODULE_NAME_MAX_LEN + 1);
+        for (name, expected) in &[
+            ("", ModuleSpecViolation::EmptyModuleName),
+            (
+                "1module",
+                ModuleSpecViolation::ModuleNameInvalidStart("1module".to_string()),
+            ),
+            (
+                "my module",
+                ModuleSpecViolation::ModuleNameInvalidCharacter("my module".to_string(), ' '),
+            ),
+            (
+                too_long.as_str(),
+                ModuleSpecViolation::ModuleNameTooLong(too_long.clone()),
+            ),
+        ] {
+            let err = validate_module_name(name).unwrap_err();
+            assert_eq!(&[expected.clone()], err.violations());
+        }
+    }
+
+    #[test]
+    fn validate_spec_succeeds() {
+        for image in &[
+            "ubuntu",
+            "nginx:latest",
+            "mcr.microsoft.com/azureiotedge-agent:1.0",
+            "localhost:5000/my_org/my-module:1.0.0-amd64",
+            "localhost:5000/my_org/my-module:1.*-amd64",
+            "repo.azurecr.io/module@sha256:0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef",
+        ] {
+            spec_with("m1", image, &[("KEY_1", "value"), ("key2", "")])
+                .validate()
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn validate_spec_reports_all_violations() {
+        let err = spec_with(
+            "1 module",
+            "Not An Image!",
+            &[("GOOD", "v"), ("BAD=KEY", "v"), ("", "v")],
+        )
+        .validate()
+        .unwrap_err();
+
+        assert_eq!(
+            &[
+                ModuleSpecViolation::ModuleNameInvalidStart("1 module".to_string()),
+                ModuleSpecViolation::ModuleNameInvalidCharacter("1 module".to_string(), ' '),
+                ModuleSpecViolation::InvalidImage("Not An Image!".to_string()),
+                ModuleSpecViolation::InvalidEnvKey("".to_string()),
+                ModuleSpecViolation::InvalidEnvKey("BAD=KEY".to_string()),
+            ],
+            err.violations()
+        );
+    }
+
+    #[test]
+    fn validate_spec_reports_malformed_digest() {
+        for image in &[
+            "nginx@sha256:not-a-digest",
+            "nginx@sha256:0123456789abcdef",
+            "nginx@md5:0123456789abcdef0123456789abcdef",
+            "nginx@sha256:0123456789ABCDEF0123456789ABCDEF0123456789ABCDEF0123456789ABCDEF",
+        ] {
+            let err = spec_with("m1", image, &[]).validate().unwrap_err();
+            assert_eq!(
+                &[ModuleSpecViolation::InvalidImageDigest(image.to_string())],
+                err.violations()
+            );
+        }
+    }
+
+    #[test]
+    fn pinned_image_prefers_digest_over_tag() {
+        let digest = "sha256:0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+        for (image, expected) in &[
+            ("nginx:latest", "nginx:latest".to_string()),
+            (&*format!("nginx@{}", digest), format!("nginx@{}", digest)),
+            (
+                &*format!("nginx:1.17@{}", digest),
+                format!("nginx@{}", digest),
+            ),
+            (
+                &*format!("localhost:5000/org/module:1.0@{}", digest),
+                format!("localhost:5000/org/module@{}", digest),
+            ),
+            (
+                &*format!("localhost:5000/org/module@{}", digest),
+                format!("localhost:5000/org/module@{}", digest),
+            ),
+        ] {
+            assert_eq!(expected, &pinned_image(image));
+        }
+
+        assert_eq!(None, image_digest("nginx:latest"));
+        assert_eq!(
+            Some(digest),
+            image_digest(&format!("nginx:1.17@{}", digest))
+        );
+    }
+
+    #[test]
+    fn validate_resources_succeeds() {
+        let resources = ModuleResources::new()
+            .with_cpu_shares(512)
+            .with_memory_bytes(MIN_MEMORY_LIMIT_BYTES)
+            .with_memory_swap(-1)
+            .with_pids_limit(100);
+        spec_with("m1", "ubuntu", &[])
+            .with_resources(resources)
+            .validate()
+            .unwrap();
+    }
+
+    #[test]
+    fn validate_resources_reports_invalid_limits() {
+        let resources = ModuleResources::new()
+            .with_cpu_shares(1)
+            .with_memory_bytes(4 * 1024 * 1024)
+            .with_memory_swap(1024)
+            .with_pids_limit(0);
+        let err = spec_with("m1", "ubuntu", &[])
+            .with_resources(resources)
+            .validate()
+            .unwrap_err();
+
+        let fields: Vec<_> = err
+            .violations()
+            .iter()
+            .map(|violation| match violation {
+                ModuleSpecViolation::InvalidResourceLimit(field, _) => *field,
+                violation => panic!("unexpected violation {:?}", violation),
+            })
+            .collect();
+        assert_eq!(
+            vec!["cpu_shares", "memory_bytes", "memory_swap", "pids_limit"],
+            fields
+        );
+    }
+
+    #[test]
+    fn validate_memory_swap_requires_memory_bytes() {
+        let resources = ModuleResources::new().with_memory_swap(64 * 1024 * 1024);
+        let err = spec_with("m1", "ubuntu", &[])
+            .with_resources(resources)
+            .validate()
+            .unwrap_err();
+
+        assert_eq!(
+            &[ModuleSpecViolation::InvalidResourceLimit(
+                "memory_swap",
+                "memory_bytes must be set when setting memory_swap".to_string()
+            )],
+            err.violations()
+        );
+    }
+
+    #[test]
+    fn parse_log_size_accepts_suffixes() {
+        assert_eq!(Ok(512), parse_log_size("512"));
+        assert_eq!(Ok(100 * 1024), parse_log_size("100k"));
+        assert_eq!(Ok(10 * 1024 * 1024), parse_log_size("10m"));
+        assert_eq!(Ok(10 * 1024 * 1024), parse_log_size("10M"));
+        assert_eq!(Ok(2 * 1024 * 1024 * 1024), parse_log_size("2g"));
     }
 
     #[test]
-    fn module_status_ser() {
-        let inputs = get_inputs();
-        for &(expected, ref status) in &inputs {
-            assert_eq!(expected, &status.to_string());
-        }
+    fn parse_log_size_rejects_invalid_sizes() {
+        assert!(parse_log_size("0").is_err());
+        assert!(parse_log_size("0m").is_err());
+        assert!(parse_log_size("").is_err());
+        assert!(parse_log_size("m").is_err());
+        assert!(parse_log_size("10mb").is_err());
+        assert!(parse_log_size("10t").is_err());
+        assert!(parse_log_size("-10m").is_err());
+        assert!(parse_log_size("+10m").is_err());
+        assert!(parse_log_size("99999999999999999999g").is_err());
     }
 
     #[test]
-    fn module_status_deser() {
-        let inputs = get_inputs();
-        for &(status, ref expected) in &inputs {
-            assert_eq!(*expected, ModuleStatus::from_str(status).unwrap());
-        }
+    fn validate_log_config_reports_invalid_options() {
+        let log_config = ModuleLogConfig::new()
+            .with_log_driver("journald".to_string())
+            .with_max_size("0".to_string())
+            .with_max_file(0);
+        let err = spec_with("m1", "ubuntu", &[])
+            .with_log_config(log_config)
+            .validate()
+            .unwrap_err();
+
+        let fields: Vec<_> = err
+            .violations()
+            .iter()
+            .map(|violation| match violation {
+                ModuleSpecViolation::InvalidLogConfig(field, _) => *field,
+                violation => panic!("unexpected violation {:?}", violation),
+            })
+            .collect();
+        assert_eq!(vec!["log_driver", "max_size", "max_file"], fields);
     }
 
     #[test]
-    fn module_config_empty_name_fails() {
-        let name = "".to_string();
-        match ModuleSpec::new(
-            name.clone(),
+    fn validate_max_file_requires_max_size() {
+        let log_config = ModuleLogConfig::new().with_max_file(3);
+        let err = log_config.validate().unwrap_err();
+        assert_eq!(
+            &[ModuleSpecViolation::InvalidLogConfig(
+                "max_file",
+                "max_size must be set when keeping more than one log file".to_string()
+            )],
+            err.violations()
+        );
+
+        ModuleLogConfig::new()
+            .with_log_driver("local".to_string())
+            .with_max_file(3)
+            .validate()
+            .unwrap();
+        ModuleLogConfig::new()
+            .with_max_size("10m".to_string())
+            .with_max_file(3)
+            .validate()
+            .unwrap();
+    }
+
+    #[test]
+    fn log_config_falls_back_to_default() {
+        let default = ModuleLogConfig::new()
+            .with_log_driver("json-file".to_string())
+            .with_max_size("10m".to_string())
+            .with_max_file(3);
+        let own = ModuleLogConfig::new().with_log_driver("journald".to_string());
+
+        assert_eq!(&default, ModuleLogConfig::new().or_default(&default));
+        assert_eq!(&own, own.or_default(&default));
+    }
+
+    #[test]
+    fn spec_hash_ignores_empty_log_config() {
+        let spec = ModuleSpec::new(
+            "m1".to_string(),
             "docker".to_string(),
-            10_i32,
+            serde_json::json!({ "image": "ubuntu" }),
             HashMap::new(),
             ImagePullPolicy::default(),
-        ) {
-            Ok(_) => panic!("Expected error"),
-            Err(err) => {
-                if let ErrorKind::InvalidModuleName(s) = err.kind() {
-                    assert_eq!(s, &name);
-                } else {
-                    panic!("Expected `InvalidModuleName` but got {:?}", err);
-                }
-            }
+        )
+        .unwrap();
+        let hash = spec.spec_hash().unwrap();
+
+        assert_eq!(
+            hash,
+            spec.clone()
+                .with_log_config(ModuleLogConfig::new())
+                .spec_hash()
+                .unwrap()
+        );
+        assert_ne!(
+            hash,
+            spec.with_log_config(ModuleLogConfig::new().with_max_size("10m".to_string()))
+                .spec_hash()
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn spec_deser_joins_split_create_options() {
+        let spec: ModuleSpec<serde_json::Value> = serde_json::from_value(serde_json::json!({
+            "name": "ml",
+            "type": "docker",
+            "config": {
+                "image": "ml-model:1.0",
+                "createOptions": "{\"Env\":[\"MODEL",
+                "createOptions01": "=resnet\"]}",
+            },
+        }))
+        .unwrap();
+        assert_eq!(
+            &serde_json::json!({
+                "image": "ml-model:1.0",
+                "createOptions": { "Env": ["MODEL=resnet"] },
+            }),
+            spec.config()
+        );
+
+        let err = serde_json::from_value::<ModuleSpec<serde_json::Value>>(serde_json::json!({
+            "name": "ml",
+            "type": "docker",
+            "config": {
+                "image": "ml-model:1.0",
+                "createOptions": "{",
+                "createOptions02": "}",
+            },
+        }))
+        .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("create options segment \"createOptions01\" is missing"),
+            "{}",
+            err
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn validate_binds_requires_absolute_paths() {
+        let binds = vec![
+            ModuleBind::new("/opt/sensors".to_string(), "/sensors".to_string()),
+            ModuleBind::new("sensors".to_string(), "/data".to_string()),
+            ModuleBind::new("/data/../etc".to_string(), "data".to_string()),
+            ModuleBind::new("/var/log".to_string(), "/sensors".to_string()),
+        ];
+        let err = spec_with("m1", "ubuntu", &[])
+            .with_binds(binds)
+            .validate()
+            .unwrap_err();
+
+        assert_eq!(
+            &[
+                ModuleSpecViolation::InvalidBind(
+                    "sensors".to_string(),
+                    "source must be an absolute path".to_string()
+                ),
+                ModuleSpecViolation::InvalidBind(
+                    "/data/../etc".to_string(),
+                    "source must not contain \"..\"".to_string()
+                ),
+                ModuleSpecViolation::InvalidBind(
+                    "/data/../etc".to_string(),
+                    "target must be an absolute path".to_string()
+                ),
+                ModuleSpecViolation::DuplicateMountTarget("/sensors".to_string()),
+            ],
+            err.violations()
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn validate_strict_binds_forbids_writable_sensitive_paths() {
+        let binds = vec![
+            ModuleBind::new("/etc/ssl".to_string(), "/ssl".to_string()),
+            ModuleBind::new(
+                "/var/run/docker.sock".to_string(),
+                "/docker.sock".to_string(),
+            ),
+            ModuleBind::new("/".to_string(), "/host".to_string()),
+            ModuleBind::new("/etc".to_string(), "/etc-ro".to_string()).with_read_only(true),
+            ModuleBind::new("/opt/sensors".to_string(), "/sensors".to_string()),
+        ];
+        let spec = spec_with("m1", "ubuntu", &[]).with_binds(binds);
+
+        assert!(spec.validate().is_ok());
+        assert_eq!(
+            &[
+                ModuleSpecViolation::WritableSensitiveBind("/etc/ssl".to_string()),
+                ModuleSpecViolation::WritableSensitiveBind("/var/run/docker.sock".to_string()),
+                ModuleSpecViolation::WritableSensitiveBind("/".to_string()),
+            ],
+            spec.validate_strict_binds().unwrap_err().violations()
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn validate_tmpfs_reports_invalid_mounts() {
+        let err = spec_with("m1", "ubuntu", &[])
+            .with_read_only_root_fs(true)
+            .with_binds(vec![ModuleBind::new(
+                "/opt/sensors".to_string(),
+                "/tmp".to_string(),
+            )])
+            .with_tmpfs(vec![
+                ModuleTmpfs::new("/run".to_string()).with_size_bytes(64 * 1024 * 1024),
+                ModuleTmpfs::new("scratch".to_string()).with_size_bytes(0),
+                ModuleTmpfs::new("/tmp".to_string()),
+            ])
+            .validate()
+            .unwrap_err();
+
+        assert_eq!(
+            &[
+                ModuleSpecViolation::InvalidTmpfs(
+                    "scratch".to_string(),
+                    "target must be an absolute path".to_string()
+                ),
+                ModuleSpecViolation::InvalidTmpfs(
+                    "scratch".to_string(),
+                    "size_bytes must be greater than 0".to_string()
+                ),
+                ModuleSpecViolation::DuplicateMountTarget("/tmp".to_string()),
+            ],
+            err.violations()
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn validate_devices_reports_invalid_paths_and_permissions() {
+        let spec = spec_with("m1", "ubuntu", &[])
+            .with_devices(vec![
+                ModuleDevice::new("/dev/nvidia0".to_string(), "/dev/nvidia0".to_string()),
+                ModuleDevice::new("/dev/nvidiactl".to_string(), "/dev/nvidiactl".to_string())
+                    .with_permissions("rw".to_string()),
+            ])
+            .with_runtime(Some("nvidia".to_string()));
+        assert!(spec.validate().is_ok());
+
+        let err = spec
+            .with_devices(vec![
+                ModuleDevice::new("dev/ttyUSB0".to_string(), "/dev/ttyUSB0".to_string()),
+                ModuleDevice::new("/dev/i2c-1".to_string(), "i2c".to_string())
+                    .with_permissions("rx".to_string()),
+                ModuleDevice::new("/dev/video0".to_string(), "/dev/video0".to_string())
+                    .with_permissions("rr".to_string()),
+                ModuleDevice::new("/dev/video1".to_string(), "/dev/video1".to_string())
+                    .with_permissions(String::new()),
+            ])
+            .with_runtime(Some(" ".to_string()))
+            .validate()
+            .unwrap_err();
+
+        assert_eq!(
+            &[
+                ModuleSpecViolation::InvalidDevice(
+                    "dev/ttyUSB0".to_string(),
+                    "host_path must be an absolute path".to_string()
+                ),
+                ModuleSpecViolation::InvalidDevice(
+                    "/dev/i2c-1".to_string(),
+                    "container_path must be an absolute path".to_string()
+                ),
+                ModuleSpecViolation::InvalidDevice(
+                    "/dev/i2c-1".to_string(),
+                    "permissions \"rx\" must be some of r, w and m, each at most once".to_string()
+                ),
+                ModuleSpecViolation::InvalidDevice(
+                    "/dev/video0".to_string(),
+                    "permissions \"rr\" must be some of r, w and m, each at most once".to_string()
+                ),
+                ModuleSpecViolation::InvalidDevice(
+                    "/dev/video1".to_string(),
+                    "permissions \"\" must be some of r, w and m, each at most once".to_string()
+                ),
+                ModuleSpecViolation::EmptyRuntime,
+            ],
+            err.violations()
+        );
+    }
+
+    #[test]
+    fn validate_capabilities() {
+        let spec = spec_with("m1", "ubuntu", &[])
+            .with_cap_add(vec!["NET_ADMIN".to_string(), "cap_sys_time".to_string()])
+            .with_cap_drop(vec!["ALL".to_string()])
+            .with_no_new_privileges(true);
+        assert!(spec.validate().is_ok());
+
+        let err = spec
+            .with_cap_add(vec!["NET_ADMIN".to_string(), "NET_ADMINS".to_string()])
+            .with_cap_drop(vec!["CAP_".to_string()])
+            .validate()
+            .unwrap_err();
+
+        assert_eq!(
+            &[
+                ModuleSpecViolation::UnknownCapability("NET_ADMINS".to_string()),
+                ModuleSpecViolation::UnknownCapability("CAP_".to_string()),
+            ],
+            err.violations()
+        );
+        assert_eq!(
+            "capability \"NET_ADMINS\" is not a Linux capability",
+            err.violations()[0].to_string()
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn validate_security_profiles() {
+        let spec = spec_with("m1", "ubuntu", &[])
+            .with_seccomp_profile(Some(PathBuf::from("/etc/iotedge/seccomp/sensor.json")))
+            .with_apparmor_profile(Some("iotedge-sensor".to_string()));
+        assert!(spec.validate().is_ok());
+
+        let err = spec
+            .with_seccomp_profile(Some(PathBuf::from("seccomp/sensor.json")))
+            .with_apparmor_profile(Some(" ".to_string()))
+            .validate()
+            .unwrap_err();
+
+        assert_eq!(
+            &[
+                ModuleSpecViolation::InvalidSecurityProfile(
+                    "seccomp_profile",
+                    "must be an absolute path".to_string()
+                ),
+                ModuleSpecViolation::InvalidSecurityProfile(
+                    "apparmor_profile",
+                    "must not be empty".to_string()
+                ),
+            ],
+            err.violations()
+        );
+    }
+
+    #[test]
+    fn restart_policy_from_str() {
+        let inputs = vec![
+            ("Always", RestartPolicy::Always),
+            ("on-failure", RestartPolicy::OnFailure),
+            ("never", RestartPolicy::Never),
+        ];
+        for &(policy, expected) in &inputs {
+            assert_eq!(expected, RestartPolicy::from_str(policy).unwrap());
+        }
+
+        match RestartPolicy::from_str("unless-stopped")
+            .unwrap_err()
+            .kind()
+        {
+            ErrorKind::InvalidRestartPolicy(policy) => assert_eq!("unless-stopped", policy),
+            kind => panic!("Expected `InvalidRestartPolicy` error but got {:?}", kind),
         }
+
+        let spec: ModuleSpec<serde_json::Value> = serde_json::from_value(serde_json::json!({
+            "name": "init",
+            "type": "docker",
+            "config": { "image": "init:1.0" },
+            "restart_policy": "on-failure",
+            "max_retries": 3,
+        }))
+        .unwrap();
+        assert_eq!(RestartPolicy::OnFailure, spec.restart_policy());
+        assert_eq!(Some(3), spec.max_retries());
     }
 
     #[test]
-    fn module_config_white_space_name_fails() {
-        let name = "    ".to_string();
-        match ModuleSpec::new(
-            name.clone(),
+    fn validate_max_retries() {
+        let spec = spec_with("m1", "ubuntu", &[])
+            .with_restart_policy(RestartPolicy::OnFailure)
+            .with_max_retries(Some(3));
+        assert!(spec.validate().is_ok());
+
+        let err = spec_with("m1", "ubuntu", &[])
+            .with_restart_policy(RestartPolicy::OnFailure)
+            .with_max_retries(Some(0))
+            .validate()
+            .unwrap_err();
+        assert_eq!(
+            &[ModuleSpecViolation::InvalidMaxRetries(
+                "must be at least 1".to_string()
+            )],
+            err.violations()
+        );
+
+        let err = spec
+            .with_restart_policy(RestartPolicy::Never)
+            .validate()
+            .unwrap_err();
+        assert_eq!(
+            "max retries are invalid: only apply to the on-failure restart policy, not never",
+            err.violations()[0].to_string()
+        );
+    }
+
+    #[test]
+    fn user_is_parsed() {
+        for user in &[
+            "1000",
+            "1000:1000",
+            "0:0",
+            "app",
+            "app:app",
+            "app:1000",
+            "_svc.io-1",
+        ] {
+            assert_eq!(None, user_violation(user), "{}", user);
+        }
+
+        let form = Some("must be of the form user[:group]");
+        assert_eq!(form, user_violation(""));
+        assert_eq!(form, user_violation(":1000"));
+        assert_eq!(form, user_violation("1000:"));
+        assert_eq!(form, user_violation("1000:1000:1000"));
+        assert_eq!(
+            Some("numeric IDs must be less than 4294967296"),
+            user_violation("1000:4294967296")
+        );
+        let name = Some("names must only contain letters, digits, '_', '-' and '.'");
+        assert_eq!(name, user_violation("-1"));
+        assert_eq!(name, user_violation("app user"));
+        assert_eq!(name, user_violation("app:$group"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn validate_user_and_working_dir() {
+        let spec = spec_with("m1", "ubuntu", &[])
+            .with_user(Some("1000:1000".to_string()))
+            .with_working_dir(Some("/app".to_string()));
+        assert!(spec.validate().is_ok());
+
+        let err = spec
+            .with_user(Some("1000:".to_string()))
+            .with_working_dir(Some("app".to_string()))
+            .validate()
+            .unwrap_err();
+        assert_eq!(
+            &[
+                ModuleSpecViolation::InvalidUser(
+                    "1000:".to_string(),
+                    "must be of the form user[:group]"
+                ),
+                ModuleSpecViolation::InvalidWorkingDir(
+                    "app".to_string(),
+                    "must be an absolute path"
+                ),
+            ],
+            err.violations()
+        );
+        assert_eq!(
+            "user \"1000:\" is invalid: must be of the form user[:group]",
+            err.violations()[0].to_string()
+        );
+    }
+
+    #[test]
+    fn spec_hash_changes_with_user_and_working_dir() {
+        let spec = ModuleSpec::new(
+            "m1".to_string(),
             "docker".to_string(),
-            10_i32,
+            serde_json::json!({ "image": "ubuntu" }),
             HashMap::new(),
             ImagePullPolicy::default(),
-        ) {
-            Ok(_) => panic!("Expected error"),
-            Err(err) => {
-                if let ErrorKind::InvalidModuleName(s) = err.kind() {
-                    assert_eq!(s, &name);
-                } else {
-                    panic!("Expected `InvalidModuleName` but got {:?}", err);
-                }
-            }
-        }
+        )
+        .unwrap();
+        let hash = spec.spec_hash().unwrap();
+
+        let user = spec.clone().with_user(Some("1000".to_string()));
+        let working_dir = spec.with_working_dir(Some("/app".to_string()));
+        assert_ne!(hash, user.spec_hash().unwrap());
+        assert_ne!(hash, working_dir.spec_hash().unwrap());
+        assert_ne!(user.spec_hash().unwrap(), working_dir.spec_hash().unwrap());
     }
 
     #[test]
-    fn module_config_empty_type_fails() {
-        let type_ = "    ".to_string();
-        match ModuleSpec::new(
+    fn spec_hash_changes_with_stop_signal() {
+        let spec = ModuleSpec::new(
             "m1".to_string(),
-            type_.clone(),
-            10_i32,
+            "docker".to_string(),
+            serde_json::json!({ "image": "ubuntu" }),
             HashMap::new(),
             ImagePullPolicy::default(),
-        ) {
-            Ok(_) => panic!("Expected error"),
-            Err(err) => {
-                if let ErrorKind::InvalidModuleType(s) = err.kind() {
-                    assert_eq!(s, &type_);
-                } else {
-                    panic!("Expected `InvalidModuleType` but got {:?}", err);
-                }
-            }
-        }
+        )
+        .unwrap();
+        let hash = spec.spec_hash().unwrap();
+
+        let quit = spec.clone().with_stop_signal(Some("SIGQUIT".to_string()));
+        assert_ne!(hash, quit.spec_hash().unwrap());
+        assert_eq!(hash, spec.with_stop_signal(None).spec_hash().unwrap());
     }
 
     #[test]
-    fn module_config_white_space_type_fails() {
-        let type_ = "    ".to_string();
-        match ModuleSpec::new(
+    fn spec_hash_changes_with_identity_integration() {
+        let spec = ModuleSpec::new(
             "m1".to_string(),
-            type_.clone(),
-            10_i32,
+            "docker".to_string(),
+            serde_json::json!({ "image": "ubuntu" }),
             HashMap::new(),
             ImagePullPolicy::default(),
-        ) {
-            Ok(_) => panic!("Expected error"),
-            Err(err) => {
-                if let ErrorKind::InvalidModuleType(s) = err.kind() {
-                    assert_eq!(s, &type_);
-                } else {
-                    panic!("Expected `InvalidModuleType` but got {:?}", err);
-                }
-            }
+        )
+        .unwrap();
+        let hash = spec.spec_hash().unwrap();
+
+        let integrated: ModuleSpec<serde_json::Value> = serde_json::from_value(serde_json::json!({
+            "name": "m1",
+            "type": "docker",
+            "config": { "image": "ubuntu" },
+            "identity_integration": true,
+        }))
+        .unwrap();
+        assert!(integrated.identity_integration());
+        assert_ne!(hash, integrated.spec_hash().unwrap());
+        assert_eq!(
+            hash,
+            integrated
+                .with_identity_integration(false)
+                .spec_hash()
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn validate_ulimits() {
+        let spec = spec_with("m1", "ubuntu", &[]).with_ulimits(vec![
+            ModuleUlimit::new("nofile".to_string(), 65536, 65536),
+            ModuleUlimit::new("nproc".to_string(), 1024, 2048),
+            ModuleUlimit::new("memlock".to_string(), 1024, -1),
+        ]);
+        assert!(spec.validate().is_ok());
+
+        let err = spec
+            .with_ulimits(vec![
+                ModuleUlimit::new("nofile".to_string(), 4096, 1024),
+                ModuleUlimit::new("nofiles".to_string(), 1024, 1024),
+                ModuleUlimit::new("nproc".to_string(), -1, 1024),
+                ModuleUlimit::new("nofile".to_string(), -2, 1024),
+            ])
+            .validate()
+            .unwrap_err();
+
+        assert_eq!(
+            &[
+                ModuleSpecViolation::InvalidUlimit(
+                    "nofile".to_string(),
+                    "soft limit 4096 is greater than hard limit 1024".to_string()
+                ),
+                ModuleSpecViolation::UnknownUlimit("nofiles".to_string()),
+                ModuleSpecViolation::InvalidUlimit(
+                    "nproc".to_string(),
+                    "soft limit -1 is greater than hard limit 1024".to_string()
+                ),
+                ModuleSpecViolation::InvalidUlimit(
+                    "nofile".to_string(),
+                    "soft limit -2 must be -1 (unlimited) or more".to_string()
+                ),
+                ModuleSpecViolation::InvalidUlimit(
+                    "nofile".to_string(),
+                    "is set more than once".to_string()
+                ),
+            ],
+            err.violations()
+        );
+        assert_eq!(
+            "ulimit \"nofile\" is invalid: soft limit 4096 is greater than hard limit 1024",
+            err.violations()[0].to_string()
+        );
+    }
+
+    #[test]
+    fn devices_default_to_all_permissions() {
+        let device: ModuleDevice = serde_json::from_value(serde_json::json!({
+            "host_path": "/dev/nvidia0",
+            "container_path": "/dev/nvidia0",
+        }))
+        .unwrap();
+        assert_eq!("rwm", device.permissions());
+    }
+
+    #[test]
+    fn validate_labels() {
+        let mut labels = HashMap::new();
+        labels.insert("com.example.group".to_string(), "sensors".to_string());
+        labels.insert(CRITICAL_UPDATE_LABEL.to_string(), "true".to_string());
+        let spec = spec_with("m1", "ubuntu", &[]).with_labels(labels.clone());
+        assert!(spec.validate().is_ok());
+
+        labels.insert("".to_string(), "empty".to_string());
+        labels.insert("a=b".to_string(), "c".to_string());
+        labels.insert("net.azure-devices.edge.owner".to_string(), "me".to_string());
+        let err = spec.with_labels(labels).validate().unwrap_err();
+        assert_eq!(
+            &[
+                ModuleSpecViolation::InvalidLabel("".to_string()),
+                ModuleSpecViolation::InvalidLabel("a=b".to_string()),
+                ModuleSpecViolation::ReservedLabel("net.azure-devices.edge.owner".to_string()),
+            ],
+            err.violations()
+        );
+    }
+
+    #[test]
+    fn injected_labels_may_be_reserved() {
+        let mut labels = HashMap::new();
+        labels.insert(
+            "net.azure-devices.edge.deployment".to_string(),
+            "d1".to_string(),
+        );
+        assert!(validate_injected_labels(&labels).is_ok());
+
+        labels.insert("a=b".to_string(), "c".to_string());
+        let err = validate_injected_labels(&labels).unwrap_err();
+        assert_eq!(
+            &[ModuleSpecViolation::InvalidLabel("a=b".to_string())],
+            err.violations()
+        );
+    }
+
+    #[test]
+    fn validate_global_env_keys() {
+        let mut env = HashMap::new();
+        env.insert("HTTPS_PROXY".to_string(), "http://proxy:3128".to_string());
+        assert!(validate_global_env(&env).is_ok());
+
+        env.insert("NO PROXY".to_string(), "localhost".to_string());
+        let err = validate_global_env(&env).unwrap_err();
+        assert_eq!(
+            &[ModuleSpecViolation::InvalidEnvKey("NO PROXY".to_string())],
+            err.violations()
+        );
+    }
+
+    #[test]
+    fn validate_dns_servers_are_ip_addresses() {
+        let spec = spec_with("m1", "ubuntu", &[])
+            .with_dns(vec!["10.0.0.53".to_string(), "fd00::53".to_string()])
+            .with_dns_search(vec!["corp.example.com".to_string()]);
+        assert!(spec.validate().is_ok());
+
+        let err = spec
+            .with_dns(vec![
+                "10.0.0.53".to_string(),
+                "dns.corp.example.com".to_string(),
+                "10.0.0.256".to_string(),
+            ])
+            .validate()
+            .unwrap_err();
+        assert_eq!(
+            &[
+                ModuleSpecViolation::InvalidDnsServer("dns.corp.example.com".to_string()),
+                ModuleSpecViolation::InvalidDnsServer("10.0.0.256".to_string()),
+            ],
+            err.violations()
+        );
+    }
+
+    #[test]
+    fn validate_networks_are_named_once() {
+        let spec = spec_with("m1", "ubuntu", &[]).with_networks(vec![
+            ModuleNetwork::new("sensors".to_string()).with_aliases(vec!["probe".to_string()]),
+            ModuleNetwork::new("azure-iot-edge".to_string()),
+        ]);
+        assert!(spec.validate().is_ok());
+
+        let err = spec
+            .with_networks(vec![
+                ModuleNetwork::new("sensors".to_string()),
+                ModuleNetwork::new("".to_string()),
+                ModuleNetwork::new("sensors".to_string()),
+            ])
+            .validate()
+            .unwrap_err();
+        assert_eq!(
+            &[
+                ModuleSpecViolation::EmptyNetworkName,
+                ModuleSpecViolation::DuplicateNetwork("sensors".to_string()),
+            ],
+            err.violations()
+        );
+    }
+
+    #[test]
+    fn validate_stop_timeout_has_upper_bound() {
+        let spec = spec_with("m1", "ubuntu", &[])
+            .with_stop_timeout(Some(Duration::from_secs(MAX_STOP_TIMEOUT_SECS)));
+        assert!(spec.validate().is_ok());
+
+        let err = spec
+            .with_stop_timeout(Some(Duration::from_secs(MAX_STOP_TIMEOUT_SECS + 1)))
+            .validate()
+            .unwrap_err();
+        assert_eq!(
+            &[ModuleSpecViolation::StopTimeoutTooLong(
+                MAX_STOP_TIMEOUT_SECS + 1
+            )],
+            err.violations()
+        );
+    }
+
+    #[test]
+    fn validate_stop_signal_is_a_signal() {
+        for signal in &["SIGQUIT", "sigint", "TERM", "usr1", "9", "64", "SIGRTMIN+3"] {
+            let spec = spec_with("m1", "ubuntu", &[]).with_stop_signal(Some(signal.to_string()));
+            assert!(spec.validate().is_ok(), "{} should be valid", signal);
+        }
+
+        for signal in &["", "SIGFOO", "0", "65", "-1", "SIG", "RTMIN+16"] {
+            let err = spec_with("m1", "ubuntu", &[])
+                .with_stop_signal(Some(signal.to_string()))
+                .validate()
+                .unwrap_err();
+            assert_eq!(
+                &[ModuleSpecViolation::UnknownStopSignal(signal.to_string())],
+                err.violations()
+            );
         }
     }
 
+    #[test]
+    fn validate_pre_stop_hook() {
+        let hook = PreStopHook::new(vec!["/app/drain".to_string()]);
+        let spec = spec_with("m1", "ubuntu", &[]).with_pre_stop(Some(hook.clone()));
+        assert!(spec.validate().is_ok());
+
+        let err = spec_with("m1", "ubuntu", &[])
+            .with_pre_stop(Some(PreStopHook::new(vec![])))
+            .validate()
+            .unwrap_err();
+        assert_eq!(
+            "pre-stop hook is invalid: command must not be empty",
+            err.violations()[0].to_string()
+        );
+
+        let err = spec
+            .with_pre_stop(Some(
+                hook.with_timeout(Duration::from_secs(MAX_STOP_TIMEOUT_SECS + 1)),
+            ))
+            .validate()
+            .unwrap_err();
+        assert_eq!(
+            &[ModuleSpecViolation::InvalidPreStopHook(format!(
+                "timeout of {} seconds must be between 1 and {} seconds",
+                MAX_STOP_TIMEOUT_SECS + 1,
+                MAX_STOP_TIMEOUT_SECS
+            ))],
+            err.violations()
+        );
+    }
+
+    #[test]
+    fn pre_stop_hook_timeout_defaults() {
+        let hook: PreStopHook =
+            serde_json::from_str(r#"{ "command": ["/app/drain", "--fast"] }"#).unwrap();
+        assert_eq!(&["/app/drain", "--fast"], hook.command());
+        assert_eq!(
+            Duration::from_secs(DEFAULT_PRE_STOP_TIMEOUT_SECS),
+            hook.timeout()
+        );
+    }
+
+    #[test]
+    fn bind_read_only_defaults_to_false() {
+        let bind: ModuleBind =
+            serde_json::from_str(r#"{ "source": "/opt/sensors", "target": "/sensors" }"#).unwrap();
+        assert!(!bind.read_only());
+
+        let bind: ModuleBind = serde_json::from_str(
+            r#"{ "source": "/opt/sensors", "target": "/sensors", "read_only": true }"#,
+        )
+        .unwrap();
+        assert!(bind.read_only());
+    }
+
     #[test]
     fn system_info_new_and_access_succeed() {
         //arrange
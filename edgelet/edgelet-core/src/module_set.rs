@@ -0,0 +1,1591 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use failure::Fail;
+use futures::future::{self, Either, Loop};
+use futures::{stream, Future, Stream};
+use log::{debug, info, warn, Level};
+use serde::de::{self, Deserialize, DeserializeOwned, Deserializer};
+use serde::{Serialize, Serializer};
+use tokio::timer::Delay;
+
+use edgelet_utils::log_failure;
+
+use crate::error::{Error, ErrorKind, Result};
+use crate::module::{
+    ImagePullPolicy, Module, ModuleError, ModuleErrorCode, ModuleRegistry, ModuleRuntime,
+    ModuleRuntimeErrorReason, ModuleRuntimeState, ModuleSpec, ModuleStatus, SPEC_FIELDS_LABEL,
+    SPEC_HASH_LABEL,
+};
+use crate::redact::redact_failure;
+use crate::settings::AdmissionSettings;
+
+// How often `ModuleSet::start_all` checks whether a group has started running
+const RUNNING_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A set of module specs whose `dependsOn` declarations are known to be
+/// consistent: every dependency refers to a module in the set and there are
+/// no cycles. This is checked when the set is constructed or deserialized.
+#[derive(Debug)]
+pub struct ModuleSet<T> {
+    modules: Vec<ModuleSpec<T>>,
+    // Indexes into `modules`, grouped in startup (topological) order
+    groups: Vec<Vec<usize>>,
+}
+
+impl<T> ModuleSet<T> {
+    pub fn new(modules: Vec<ModuleSpec<T>>) -> Result<Self> {
+        let groups = startup_groups(&modules)?;
+        Ok(ModuleSet { modules, groups })
+    }
+
+    pub fn modules(&self) -> &[ModuleSpec<T>] {
+        &self.modules
+    }
+
+    /// Modules ordered so that every module comes after its dependencies.
+    /// Otherwise modules with a lower startup order come first, and modules
+    /// with the same startup order are ordered by name.
+    pub fn startup_order(&self) -> impl Iterator<Item = &ModuleSpec<T>> {
+        self.groups.iter().flatten().map(move |&i| &self.modules[i])
+    }
+
+    /// `startup_order` split into the groups of modules that can be started
+    /// at the same time. The modules of a group have the same startup order
+    /// and don't depend on each other.
+    pub fn startup_groups(&self) -> Vec<Vec<&ModuleSpec<T>>> {
+        self.groups
+            .iter()
+            .map(|group| group.iter().map(|&i| &self.modules[i]).collect())
+            .collect()
+    }
+
+    /// Checks that the set fits in the budgets of the host before it is
+    /// applied. Fails with every budget that the set exceeds and by how much.
+    pub fn admit(&self, budget: &AdmissionSettings) -> Result<()> {
+        let mut overcommits = vec![];
+
+        if let Some(max_modules) = budget.max_modules() {
+            if self.modules.len() > max_modules {
+                overcommits.push(format!(
+                    "{} modules are deployed, {} more than the maximum of {}",
+                    self.modules.len(),
+                    self.modules.len() - max_modules,
+                    max_modules
+                ));
+            }
+        }
+
+        if let Some(memory_bytes) = budget.memory_bytes() {
+            let requested = self
+                .modules
+                .iter()
+                .filter_map(|spec| spec.resources().memory_bytes())
+                .fold(0, u64::saturating_add);
+            if requested > memory_bytes {
+                overcommits.push(format!(
+                    "the modules request {} bytes of memory, {} more than the budget of {}",
+                    requested,
+                    requested - memory_bytes,
+                    memory_bytes
+                ));
+            }
+        }
+
+        if let Some(cpu_shares) = budget.cpu_shares() {
+            let requested = self
+                .modules
+                .iter()
+                .filter_map(|spec| spec.resources().cpu_shares())
+                .map(u64::from)
+                .fold(0, u64::saturating_add);
+            if requested > cpu_shares {
+                overcommits.push(format!(
+                    "the modules request {} CPU shares, {} more than the budget of {}",
+                    requested,
+                    requested - cpu_shares,
+                    cpu_shares
+                ));
+            }
+        }
+
+        if overcommits.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::from(ErrorKind::DeploymentOverBudget(
+                overcommits.join("; "),
+            )))
+        }
+    }
+
+    /// The reverse of `startup_order`.
+    pub fn shutdown_order(&self) -> impl Iterator<Item = &ModuleSpec<T>> {
+        self.groups
+            .iter()
+            .flatten()
+            .rev()
+            .map(move |&i| &self.modules[i])
+    }
+
+    /// Starts the modules one group of `startup_groups` at a time. The modules
+    /// of a group are started concurrently, and one that is running already
+    /// counts as started. With `wait_for_running`, every module of a group has
+    /// to reach the running state within that time before the next group is
+    /// started.
+    pub fn start_all<M>(
+        &self,
+        runtime: M,
+        wait_for_running: Option<Duration>,
+    ) -> impl Future<Item = (), Error = Error>
+    where
+        M: 'static + ModuleRuntime + Clone,
+    {
+        let groups: Vec<Vec<_>> = self
+            .startup_groups()
+            .iter()
+            .map(|group| group.iter().map(|spec| spec.name().to_string()).collect())
+            .collect();
+
+        stream::iter_ok(groups).for_each(move |names| {
+            let started = future::join_all(
+                names
+                    .iter()
+                    .map(|name| {
+                        info!("Starting module {}", name);
+                        let runtime = runtime.clone();
+                        let name = name.clone();
+                        runtime.start(&name).or_else(move |err| {
+                            // Docker refuses to start a container that is running.
+                            runtime.get(&name).then(move |module| match module {
+                                Ok((_, state)) if *state.status() == ModuleStatus::Running => {
+                                    Ok(())
+                                }
+                                _ => Err(Error::from(err.context(ErrorKind::ModuleRuntime))),
+                            })
+                        })
+                    })
+                    .collect::<Vec<_>>(),
+            );
+
+            let runtime = runtime.clone();
+            started.and_then(move |_| match wait_for_running {
+                Some(timeout) => Either::A(
+                    future::join_all(
+                        names
+                            .into_iter()
+                            .map(|name| wait_until_running(runtime.clone(), name, timeout))
+                            .collect::<Vec<_>>(),
+                    )
+                    .map(|_| ()),
+                ),
+                None => Either::B(future::ok(())),
+            })
+        })
+    }
+
+    /// Stops the modules one at a time in `shutdown_order`, so that a module is
+    /// only stopped once every module depending on it has exited. Each module
+    /// is given its stop timeout, or `timeout` if it doesn't have one, to exit
+    /// before it is killed. Modules that don't exist are skipped.
+    pub fn stop_all<M>(
+        &self,
+        runtime: M,
+        timeout: Duration,
+    ) -> impl Future<Item = (), Error = Error>
+    where
+        M: 'static + ModuleRuntime,
+        for<'r> &'r <M as ModuleRuntime>::Error: Into<ModuleRuntimeErrorReason>,
+    {
+        let modules: Vec<_> = self
+            .shutdown_order()
+            .map(|spec| {
+                let timeout = spec.stop_timeout().unwrap_or(timeout);
+                (spec.name().to_string(), timeout)
+            })
+            .collect();
+
+        stream::iter_ok(modules).for_each(move |(name, timeout)| {
+            info!("Stopping module {}", name);
+            runtime
+                .stop(&name, Some(timeout))
+                .or_else(|err| match (&err).into() {
+                    ModuleRuntimeErrorReason::NotFound => Ok(()),
+                    _ => Err(Error::from(err.context(ErrorKind::ModuleRuntime))),
+                })
+        })
+    }
+}
+
+impl<T> ModuleSet<T>
+where
+    T: Clone + Serialize,
+{
+    /// Works out what has to change to go from `self`, the modules that are
+    /// running, to `desired`. A module in both sets is only restarted if its
+    /// `spec_hash` changed.
+    ///
+    /// Removals come first, one at a time in the shutdown order of `self`,
+    /// followed by the modules of `desired` a startup group at a time.
+    pub fn diff(&self, desired: &ModuleSet<T>) -> Result<ModuleSetDiff<T>> {
+        let mut current = BTreeMap::new();
+        for spec in &self.modules {
+            current.insert(spec.name(), spec.spec_hash()?);
+        }
+
+        let mut changes: Vec<_> = self
+            .shutdown_order()
+            .filter(|spec| !desired.modules.iter().any(|d| d.name() == spec.name()))
+            .map(|spec| ModuleChange::Remove(spec.name().to_string()))
+            .collect();
+        let mut stages = vec![1; changes.len()];
+
+        for group in desired.startup_groups() {
+            stages.push(group.len());
+            for spec in group {
+                let change = match current.get(spec.name()) {
+                    None => ModuleChange::Create(spec.clone()),
+                    Some(hash) if *hash == spec.spec_hash()? => {
+                        ModuleChange::NoOp(spec.name().to_string())
+                    }
+                    Some(_) => ModuleChange::Restart(spec.clone()),
+                };
+                changes.push(change);
+            }
+        }
+
+        Ok(ModuleSetDiff::new(changes, stages))
+    }
+
+    /// Works out what has to change to take over the modules that are already
+    /// on the device, such as the containers of a device that is migrated to
+    /// the daemon. A module of `self` is adopted and left running as it is if
+    /// a running module has its name and a `SPEC_HASH_LABEL` with its
+    /// `spec_hash`. A module with its name that is stopped or has another hash
+    /// is recreated, and a missing one is created. Modules that aren't in
+    /// `self` are left alone, since the daemon may not own them.
+    pub fn adopt<M>(&self, existing: &[(M, ModuleRuntimeState)]) -> Result<ModuleSetDiff<T>>
+    where
+        M: Module,
+    {
+        let mut changes = vec![];
+        let mut stages = vec![];
+        for group in self.startup_groups() {
+            stages.push(group.len());
+            for spec in group {
+                let found = existing
+                    .iter()
+                    .find(|(module, _)| module.name() == spec.name());
+                let change = match found {
+                    None => ModuleChange::Create(spec.clone()),
+                    Some((_, state))
+                        if *state.status() == ModuleStatus::Running
+                            && state.labels().get(SPEC_HASH_LABEL) == Some(&spec.spec_hash()?) =>
+                    {
+                        ModuleChange::NoOp(spec.name().to_string())
+                    }
+                    Some(_) => ModuleChange::Restart(spec.clone()),
+                };
+                changes.push(change);
+            }
+        }
+
+        Ok(ModuleSetDiff::new(changes, stages))
+    }
+
+    /// Works out what applying `self` would do to the modules on the device,
+    /// without changing anything. Whether a module has to be recreated is
+    /// known by the labels that record on its container which spec it was
+    /// created from, see `ModuleSpec::spec_labels`, and a restart comes with
+    /// the reasons for it. The modules that aren't in `self` are removed
+    /// first, by name, followed by the modules of `self` in startup order.
+    pub fn plan<M>(&self, existing: &[(M, ModuleRuntimeState)]) -> Result<Vec<PlannedChange>>
+    where
+        M: Module,
+    {
+        let mut removed: Vec<_> = existing
+            .iter()
+            .map(|(module, _)| module.name())
+            .filter(|name| !self.modules.iter().any(|spec| spec.name() == *name))
+            .collect();
+        removed.sort();
+        let mut plan: Vec<_> = removed
+            .into_iter()
+            .map(|name| PlannedChange::new(name.to_string(), PlannedAction::Remove, vec![]))
+            .collect();
+
+        for spec in self.startup_order() {
+            let found = existing
+                .iter()
+                .find(|(module, _)| module.name() == spec.name());
+            let change = match found {
+                None => PlannedChange::new(spec.name().to_string(), PlannedAction::Create, vec![]),
+                Some((_, state)) => {
+                    let reasons = restart_reasons(spec, state)?;
+                    let action = if reasons.is_empty() {
+                        PlannedAction::NoOp
+                    } else {
+                        PlannedAction::Restart
+                    };
+                    PlannedChange::new(spec.name().to_string(), action, reasons)
+                }
+            };
+            plan.push(change);
+        }
+
+        Ok(plan)
+    }
+}
+
+// Why the module of `spec` would be recreated given its `state`, if it would.
+fn restart_reasons<T>(spec: &ModuleSpec<T>, state: &ModuleRuntimeState) -> Result<Vec<String>>
+where
+    T: Serialize,
+{
+    let mut reasons = vec![];
+    let labels = state.labels();
+    if labels.get(SPEC_HASH_LABEL) != Some(&spec.spec_hash()?) {
+        let current: Option<BTreeMap<String, String>> = labels
+            .get(SPEC_FIELDS_LABEL)
+            .and_then(|fields| serde_json::from_str(fields).ok());
+        match current {
+            Some(current) => {
+                let desired = spec.spec_field_hashes()?;
+                let fields: BTreeSet<_> = current.keys().chain(desired.keys()).collect();
+                reasons.extend(
+                    fields
+                        .into_iter()
+                        .filter(|field| current.get(*field) != desired.get(*field))
+                        .map(|field| format!("{} changed", field.trim_start_matches("config."))),
+                );
+                if reasons.is_empty() {
+                    reasons.push("spec changed".to_string());
+                }
+            }
+            None => reasons.push("the spec the module was created from is unknown".to_string()),
+        }
+    }
+    if *state.status() != ModuleStatus::Running {
+        reasons.push(format!("module is {}", state.status()));
+    }
+    Ok(reasons)
+}
+
+/// What would happen to a module, see `ModuleSet::plan`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PlannedAction {
+    Create,
+    Remove,
+    Restart,
+    NoOp,
+}
+
+impl fmt::Display for PlannedAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let action = match self {
+            PlannedAction::Create => "create",
+            PlannedAction::Remove => "remove",
+            PlannedAction::Restart => "restart",
+            PlannedAction::NoOp => "no-op",
+        };
+        write!(f, "{}", action)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlannedChange {
+    name: String,
+    action: PlannedAction,
+    reasons: Vec<String>,
+}
+
+impl PlannedChange {
+    pub fn new(name: String, action: PlannedAction, reasons: Vec<String>) -> Self {
+        PlannedChange {
+            name,
+            action,
+            reasons,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn action(&self) -> PlannedAction {
+        self.action
+    }
+
+    /// Why the module would be restarted, like `image changed` or
+    /// `env changed`.
+    pub fn reasons(&self) -> &[String] {
+        &self.reasons
+    }
+}
+
+/// Adopts the modules of `desired` that are already running as they should,
+/// without restarting them, and recreates or creates the others, up to
+/// `parallelism` at a time. See `ModuleSet::adopt`. Resolves to the names of
+/// the adopted modules.
+pub fn adopt_existing<M>(
+    runtime: M,
+    desired: &ModuleSet<M::Config>,
+    timeout: Duration,
+    parallelism: usize,
+) -> impl Future<Item = Vec<String>, Error = Error>
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+    M::Config: Serialize,
+    for<'r> &'r <M as ModuleRuntime>::Error: Into<ModuleRuntimeErrorReason>,
+{
+    let desired = desired.clone();
+    runtime
+        .list_with_details()
+        .collect()
+        .map_err(|err| Error::from(err.context(ErrorKind::ModuleRuntime)))
+        .and_then(move |existing| {
+            let diff = desired.adopt(&existing)?;
+            let adopted: Vec<_> = diff
+                .changes()
+                .iter()
+                .filter_map(|change| match change {
+                    ModuleChange::NoOp(name) => Some(name.clone()),
+                    _ => None,
+                })
+                .collect();
+            info!(
+                "Adopting {} running modules, creating or recreating the other {}",
+                adopted.len(),
+                diff.changes().len() - adopted.len()
+            );
+            Ok((diff.with_parallelism(parallelism), adopted))
+        })
+        .and_then(move |(diff, adopted)| diff.apply(runtime, timeout).map(|()| adopted))
+}
+
+/// What happens to a single module when a `ModuleSetDiff` is applied.
+#[derive(Debug)]
+pub enum ModuleChange<T> {
+    /// The module isn't running and is created and started.
+    Create(ModuleSpec<T>),
+    /// The module is no longer wanted and is stopped and removed.
+    Remove(String),
+    /// The module's spec changed, so it is recreated from the new spec.
+    Restart(ModuleSpec<T>),
+    /// The module's spec didn't change and it is left alone.
+    NoOp(String),
+}
+
+impl<T> ModuleChange<T> {
+    pub fn name(&self) -> &str {
+        match self {
+            ModuleChange::Create(spec) | ModuleChange::Restart(spec) => spec.name(),
+            ModuleChange::Remove(name) | ModuleChange::NoOp(name) => name,
+        }
+    }
+}
+
+/// The changes needed to go from one `ModuleSet` to another, in the order
+/// they are applied. See `ModuleSet::diff`.
+///
+/// The changes are split into stages that are applied one after the other.
+/// The changes of a stage don't depend on each other, so up to `parallelism`
+/// of them are applied at the same time.
+#[derive(Debug)]
+pub struct ModuleSetDiff<T> {
+    changes: Vec<ModuleChange<T>>,
+    // The number of changes in each stage
+    stages: Vec<usize>,
+    parallelism: usize,
+}
+
+impl<T> ModuleSetDiff<T> {
+    fn new(changes: Vec<ModuleChange<T>>, stages: Vec<usize>) -> Self {
+        ModuleSetDiff {
+            changes,
+            stages,
+            parallelism: 1,
+        }
+    }
+
+    pub fn changes(&self) -> &[ModuleChange<T>] {
+        &self.changes
+    }
+
+    /// Applies up to `parallelism` changes of a stage at the same time, or
+    /// one at a time if it is 0.
+    pub fn with_parallelism(mut self, parallelism: usize) -> Self {
+        self.parallelism = parallelism.max(1);
+        self
+    }
+
+    pub fn parallelism(&self) -> usize {
+        self.parallelism
+    }
+
+    /// Whether applying the diff would leave every module alone.
+    pub fn is_empty(&self) -> bool {
+        self.changes.iter().all(|change| match change {
+            ModuleChange::NoOp(_) => true,
+            _ => false,
+        })
+    }
+
+    /// Applies the changes a stage at a time. Modules that are removed or
+    /// restarted are given `timeout` to exit before they are killed, unless a
+    /// restarted module's spec has a stop timeout.
+    ///
+    /// A module that can't be changed doesn't stop the others, but the
+    /// modules that depend on it are left alone. Fails with every module that
+    /// wasn't applied once the rest have been.
+    pub fn apply<M>(self, runtime: M, timeout: Duration) -> impl Future<Item = (), Error = Error>
+    where
+        T: 'static + Send,
+        M: 'static + ModuleRuntime<Config = T> + Clone + Send,
+        for<'r> &'r <M as ModuleRuntime>::Error: Into<ModuleRuntimeErrorReason>,
+    {
+        apply_stages(
+            self.changes,
+            &self.stages,
+            self.parallelism,
+            move |change| -> Box<dyn Future<Item = (), Error = Error> + Send> {
+                match change {
+                    ModuleChange::Create(spec) => {
+                        info!("Creating module {}", spec.name());
+                        Box::new(create_and_start(runtime.clone(), spec))
+                    }
+                    ModuleChange::Remove(name) => {
+                        info!("Removing module {}", name);
+                        Box::new(stop_and_remove(runtime.clone(), name, timeout))
+                    }
+                    ModuleChange::Restart(spec) => {
+                        info!("Recreating module {} since its spec changed", spec.name());
+                        let runtime = runtime.clone();
+                        let name = spec.name().to_string();
+                        let timeout = spec.stop_timeout().unwrap_or(timeout);
+                        Box::new(update_module(runtime.clone(), spec, timeout).and_then(
+                            move |()| {
+                                runtime.start(&name).map_err(|err| {
+                                    Error::from(err.context(ErrorKind::ModuleRuntime))
+                                })
+                            },
+                        ))
+                    }
+                    ModuleChange::NoOp(name) => {
+                        debug!("Module {} is unchanged", name);
+                        Box::new(future::ok(()))
+                    }
+                }
+            },
+        )
+    }
+}
+
+// Applies `changes` a stage at a time, running up to `parallelism` of the
+// changes of a stage with `apply` at the same time. A create or restart is
+// skipped if a module it depends on failed or was skipped. Resolves once
+// every stage has been applied, failing with the modules that weren't.
+fn apply_stages<T, F, R>(
+    changes: Vec<ModuleChange<T>>,
+    stages: &[usize],
+    parallelism: usize,
+    apply: F,
+) -> impl Future<Item = (), Error = Error>
+where
+    T: 'static,
+    F: 'static + Fn(ModuleChange<T>) -> R + Clone,
+    R: 'static + Future<Item = (), Error = Error>,
+{
+    let mut changes = changes.into_iter();
+    let stages: Vec<Vec<_>> = stages
+        .iter()
+        .map(|&len| changes.by_ref().take(len).collect())
+        .collect();
+
+    stream::iter_ok(stages)
+        .fold(vec![], move |mut failed: Vec<String>, stage| {
+            let mut ready = vec![];
+            for change in stage {
+                let dependencies = match &change {
+                    ModuleChange::Create(spec) | ModuleChange::Restart(spec) => spec.depends_on(),
+                    ModuleChange::Remove(_) | ModuleChange::NoOp(_) => &[],
+                };
+                match dependencies.iter().find(|name| failed.contains(name)) {
+                    Some(dependency) => {
+                        warn!(
+                            "Skipping module {} since module {} that it depends on could not be applied",
+                            change.name(),
+                            dependency
+                        );
+                        failed.push(change.name().to_string());
+                    }
+                    None => ready.push(change),
+                }
+            }
+
+            let apply = apply.clone();
+            stream::iter_ok::<_, Error>(ready)
+                .map(move |change| {
+                    let name = change.name().to_string();
+                    apply(change).then(move |result| Ok((name, result)))
+                })
+                .buffer_unordered(parallelism)
+                .fold(failed, |mut failed, (name, result)| {
+                    if let Err(err) = result {
+                        warn!("Could not apply module {}:", name);
+                        log_failure(Level::Warn, &err);
+                        failed.push(name);
+                    }
+                    Ok::<_, Error>(failed)
+                })
+        })
+        .and_then(|failed| {
+            if failed.is_empty() {
+                Ok(())
+            } else {
+                Err(Error::from(ErrorKind::ModulesNotApplied(failed.join(", "))))
+            }
+        })
+}
+
+fn create_and_start<M>(
+    runtime: M,
+    spec: ModuleSpec<M::Config>,
+) -> impl Future<Item = (), Error = Error>
+where
+    M: 'static + ModuleRuntime,
+{
+    let name = spec.name().to_string();
+    runtime
+        .create(spec)
+        .and_then(move |()| runtime.start(&name))
+        .map_err(|err| Error::from(err.context(ErrorKind::ModuleRuntime)))
+}
+
+/// Pulls the image of the module as its pull policy allows. Resolves to
+/// whether the image was pulled. A failed pull is recorded as the last error
+/// of the module.
+pub fn pull_image<M>(
+    runtime: &M,
+    spec: &ModuleSpec<M::Config>,
+) -> impl Future<Item = bool, Error = M::Error>
+where
+    M: ModuleRuntime + Clone,
+{
+    let recorder = runtime.clone();
+    let name = spec.name().to_string();
+    let pull = match spec.image_pull_policy() {
+        ImagePullPolicy::OnCreate => {
+            Either::A(runtime.registry().pull(spec.config()).map(|()| true))
+        }
+        ImagePullPolicy::IfNotPresent => {
+            let runtime = runtime.clone();
+            let config = spec.config().clone();
+            Either::B(Either::A(
+                runtime
+                    .registry()
+                    .image_exists(spec.config())
+                    .and_then(move |exists| {
+                        if exists {
+                            Either::A(future::ok(false))
+                        } else {
+                            Either::B(runtime.registry().pull(&config).map(|()| true))
+                        }
+                    }),
+            ))
+        }
+        ImagePullPolicy::Never => Either::B(Either::B(future::ok(false))),
+    };
+
+    pull.map_err(move |err| {
+        let error = ModuleError::new(
+            ModuleErrorCode::PullFailed,
+            redact_failure(&err),
+            Utc::now(),
+        );
+        recorder.record_error(&name, error);
+        err
+    })
+}
+
+/// Updates a module to `spec` in two phases, so that the module isn't down
+/// for as long as its new image takes to pull. The image is pulled while the
+/// old module keeps running, and only once the pull succeeds is the old
+/// module stopped, given `timeout` to exit, removed and created again from
+/// `spec`. If the pull fails the old module is left as it was.
+///
+/// The new module isn't started.
+pub fn update_module<M>(
+    runtime: M,
+    spec: ModuleSpec<M::Config>,
+    timeout: Duration,
+) -> impl Future<Item = (), Error = Error>
+where
+    M: 'static + ModuleRuntime + Clone,
+    for<'r> &'r <M as ModuleRuntime>::Error: Into<ModuleRuntimeErrorReason>,
+{
+    pull_image(&runtime, &spec)
+        .map_err(|err| Error::from(err.context(ErrorKind::ModuleRuntime)))
+        .and_then(move |_| {
+            debug!(
+                "Image of module {} is ready, replacing the module",
+                spec.name()
+            );
+            replace_module(runtime, spec, timeout)
+        })
+}
+
+/// Replaces a module with one created from `spec` without pulling its image,
+/// for a module whose image is already there. The old module is stopped,
+/// given `timeout` to exit, and removed.
+///
+/// The new module isn't started.
+pub fn replace_module<M>(
+    runtime: M,
+    spec: ModuleSpec<M::Config>,
+    timeout: Duration,
+) -> impl Future<Item = (), Error = Error>
+where
+    M: 'static + ModuleRuntime + Clone,
+    for<'r> &'r <M as ModuleRuntime>::Error: Into<ModuleRuntimeErrorReason>,
+{
+    let name = spec.name().to_string();
+    stop_and_remove(runtime.clone(), name, timeout).and_then(move |()| {
+        runtime
+            .create(spec)
+            .map_err(|err| Error::from(err.context(ErrorKind::ModuleRuntime)))
+    })
+}
+
+// A module that is restarting can show up as stopped or failed in between, so
+// only running out of time ends the wait early.
+fn wait_until_running<M>(
+    runtime: M,
+    name: String,
+    timeout: Duration,
+) -> impl Future<Item = (), Error = Error>
+where
+    M: 'static + ModuleRuntime,
+{
+    let deadline = Instant::now() + timeout;
+    future::loop_fn((runtime, name), move |(runtime, name)| {
+        runtime
+            .get(&name)
+            .map_err(|err| Error::from(err.context(ErrorKind::ModuleRuntime)))
+            .and_then(move |(_, state)| {
+                if *state.status() == ModuleStatus::Running {
+                    debug!("Module {} is running", name);
+                    Either::A(future::ok(Loop::Break(())))
+                } else if Instant::now() >= deadline {
+                    Either::A(future::err(Error::from(ErrorKind::ModuleStartupTimeout(
+                        name,
+                    ))))
+                } else {
+                    Either::B(
+                        Delay::new(Instant::now() + RUNNING_POLL_INTERVAL)
+                            .map_err(|err| Error::from(err.context(ErrorKind::ModuleStartupTimer)))
+                            .map(move |()| Loop::Continue((runtime, name))),
+                    )
+                }
+            })
+    })
+}
+
+// Modules that don't exist are skipped, since there's nothing to remove.
+pub(crate) fn stop_and_remove<M>(
+    runtime: M,
+    name: String,
+    timeout: Duration,
+) -> impl Future<Item = (), Error = Error>
+where
+    M: 'static + ModuleRuntime,
+    for<'r> &'r <M as ModuleRuntime>::Error: Into<ModuleRuntimeErrorReason>,
+{
+    runtime
+        .stop(&name, Some(timeout))
+        .and_then(move |()| runtime.remove(&name))
+        .or_else(|err| match (&err).into() {
+            ModuleRuntimeErrorReason::NotFound => Ok(()),
+            _ => Err(Error::from(err.context(ErrorKind::ModuleRuntime))),
+        })
+}
+
+impl<T> Clone for ModuleSet<T>
+where
+    T: Clone,
+{
+    fn clone(&self) -> Self {
+        ModuleSet {
+            modules: self.modules.clone(),
+            groups: self.groups.clone(),
+        }
+    }
+}
+
+impl<'de, T> Deserialize<'de> for ModuleSet<T>
+where
+    T: DeserializeOwned,
+{
+    fn deserialize<D>(deserializer: D) -> ::std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let modules = Vec::<ModuleSpec<T>>::deserialize(deserializer)?;
+        ModuleSet::new(modules).map_err(de::Error::custom)
+    }
+}
+
+impl<T> Serialize for ModuleSet<T>
+where
+    T: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.modules.serialize(serializer)
+    }
+}
+
+// Kahn's algorithm, a layer at a time. Of the modules whose dependencies have
+// all been started, the ones with the lowest startup order form the next
+// group. Groups are sorted by name so that the order is stable regardless of
+// how the modules were declared.
+fn startup_groups<T>(modules: &[ModuleSpec<T>]) -> Result<Vec<Vec<usize>>> {
+    let mut index = BTreeMap::new();
+    for (i, module) in modules.iter().enumerate() {
+        if index.insert(module.name(), i).is_some() {
+            return Err(Error::from(ErrorKind::DuplicateModuleName(
+                module.name().to_string(),
+            )));
+        }
+    }
+
+    let mut pending = Vec::with_capacity(modules.len());
+    for module in modules {
+        let mut dependencies = BTreeSet::new();
+        for dependency in module.depends_on() {
+            let i = index.get(dependency.as_str()).ok_or_else(|| {
+                ErrorKind::UnknownModuleDependency(module.name().to_string(), dependency.clone())
+            })?;
+            dependencies.insert(*i);
+        }
+        pending.push(dependencies);
+    }
+
+    let mut started = vec![false; modules.len()];
+    let mut groups = Vec::new();
+    loop {
+        let ready: Vec<_> = (0..modules.len())
+            .filter(|&i| !started[i] && pending[i].is_empty())
+            .collect();
+        let order = match ready.iter().map(|&i| modules[i].startup_order()).min() {
+            Some(order) => order,
+            None => break,
+        };
+
+        let mut group: Vec<_> = ready
+            .into_iter()
+            .filter(|&i| modules[i].startup_order() == order)
+            .collect();
+        group.sort_by_key(|&i| modules[i].name());
+        for &i in &group {
+            started[i] = true;
+        }
+        for dependencies in &mut pending {
+            for i in &group {
+                dependencies.remove(i);
+            }
+        }
+        groups.push(group);
+    }
+
+    if started.iter().all(|started| *started) {
+        Ok(groups)
+    } else {
+        let mut cycle: Vec<_> = pending
+            .iter()
+            .enumerate()
+            .filter(|(_, dependencies)| !dependencies.is_empty())
+            .map(|(i, _)| modules[i].name())
+            .collect();
+        cycle.sort();
+        Err(Error::from(ErrorKind::ModuleDependencyCycle(
+            cycle.join(", "),
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::rc::Rc;
+    use std::time::Instant;
+
+    use serde_json::json;
+    use tokio::runtime::current_thread::Runtime;
+
+    use super::*;
+    use crate::module::{ImagePullPolicy, ModuleResources, DEFAULT_STARTUP_ORDER};
+
+    fn spec(name: &str, depends_on: &[&str]) -> ModuleSpec<String> {
+        ModuleSpec::new(
+            name.to_string(),
+            "docker".to_string(),
+            "image".to_string(),
+            HashMap::new(),
+            ImagePullPolicy::default(),
+        )
+        .unwrap()
+        .with_depends_on(depends_on.iter().map(ToString::to_string).collect())
+    }
+
+    fn spec_with_resources(name: &str, memory_bytes: u64, cpu_shares: u32) -> ModuleSpec<String> {
+        spec(name, &[]).with_resources(
+            ModuleResources::new()
+                .with_memory_bytes(memory_bytes)
+                .with_cpu_shares(cpu_shares),
+        )
+    }
+
+    fn names<'a>(specs: impl Iterator<Item = &'a ModuleSpec<String>>) -> Vec<&'a str> {
+        specs.map(ModuleSpec::name).collect()
+    }
+
+    #[test]
+    fn orders_small_graph() {
+        // broker <- telemetry <- dashboard
+        //        <- alerts
+        let set = ModuleSet::new(vec![
+            spec("dashboard", &["telemetry"]),
+            spec("telemetry", &["broker"]),
+            spec("alerts", &["broker"]),
+            spec("broker", &[]),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            vec!["broker", "alerts", "telemetry", "dashboard"],
+            names(set.startup_order())
+        );
+        assert_eq!(
+            vec!["dashboard", "telemetry", "alerts", "broker"],
+            names(set.shutdown_order())
+        );
+    }
+
+    #[test]
+    fn independent_modules_are_ordered_by_name() {
+        let set = ModuleSet::new(vec![spec("c", &[]), spec("a", &[]), spec("b", &[])]).unwrap();
+
+        assert_eq!(vec!["a", "b", "c"], names(set.startup_order()));
+    }
+
+    #[test]
+    fn groups_modules_by_startup_order() {
+        let set = ModuleSet::new(vec![
+            spec("logger", &[]),
+            spec("leaf2", &[]).with_startup_order(10),
+            spec("cache", &["logger"]).with_startup_order(0),
+            spec("dashboard", &["leaf1"]).with_startup_order(10),
+            spec("leaf1", &[]).with_startup_order(10),
+            spec("gateway", &[]).with_startup_order(0),
+        ])
+        .unwrap();
+
+        let groups: Vec<_> = set
+            .startup_groups()
+            .into_iter()
+            .map(|group| names(group.into_iter()))
+            .collect();
+        // Dependencies come before startup order, and modules without one
+        // start last
+        assert_eq!(
+            vec![
+                vec!["gateway"],
+                vec!["leaf1", "leaf2"],
+                vec!["dashboard"],
+                vec!["logger"],
+                vec!["cache"],
+            ],
+            groups
+        );
+        assert_eq!(
+            vec!["gateway", "leaf1", "leaf2", "dashboard", "logger", "cache"],
+            names(set.startup_order())
+        );
+        assert_eq!(
+            vec!["cache", "logger", "dashboard", "leaf2", "leaf1", "gateway"],
+            names(set.shutdown_order())
+        );
+    }
+
+    #[test]
+    fn rejects_cycle() {
+        let err = ModuleSet::new(vec![
+            spec("a", &["c"]),
+            spec("b", &["a"]),
+            spec("c", &["b"]),
+            spec("d", &[]),
+        ])
+        .unwrap_err();
+
+        match err.kind() {
+            ErrorKind::ModuleDependencyCycle(modules) => assert_eq!("a, b, c", modules),
+            kind => panic!("Expected `ModuleDependencyCycle` but got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn rejects_self_dependency() {
+        let err = ModuleSet::new(vec![spec("a", &["a"])]).unwrap_err();
+
+        match err.kind() {
+            ErrorKind::ModuleDependencyCycle(modules) => assert_eq!("a", modules),
+            kind => panic!("Expected `ModuleDependencyCycle` but got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_dependency() {
+        let err = ModuleSet::new(vec![spec("a", &["b"])]).unwrap_err();
+
+        match err.kind() {
+            ErrorKind::UnknownModuleDependency(module, dependency) => {
+                assert_eq!("a", module);
+                assert_eq!("b", dependency);
+            }
+            kind => panic!("Expected `UnknownModuleDependency` but got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn rejects_duplicate_module() {
+        let err = ModuleSet::new(vec![spec("a", &[]), spec("a", &[])]).unwrap_err();
+
+        match err.kind() {
+            ErrorKind::DuplicateModuleName(module) => assert_eq!("a", module),
+            kind => panic!("Expected `DuplicateModuleName` but got {:?}", kind),
+        }
+    }
+
+    fn docker_spec(
+        name: &str,
+        env: &[(&str, &str)],
+        config: serde_json::Value,
+    ) -> ModuleSpec<serde_json::Value> {
+        ModuleSpec::new(
+            name.to_string(),
+            "docker".to_string(),
+            config,
+            env.iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+            ImagePullPolicy::default(),
+        )
+        .unwrap()
+    }
+
+    fn changes(diff: &ModuleSetDiff<serde_json::Value>) -> Vec<(&str, &str)> {
+        diff.changes()
+            .iter()
+            .map(|change| {
+                let kind = match change {
+                    ModuleChange::Create(_) => "create",
+                    ModuleChange::Remove(_) => "remove",
+                    ModuleChange::Restart(_) => "restart",
+                    ModuleChange::NoOp(_) => "noop",
+                };
+                (change.name(), kind)
+            })
+            .collect()
+    }
+
+    fn sensor_config() -> serde_json::Value {
+        json!({
+            "image": "sensor:1.0",
+            "createOptions": {
+                "HostConfig": { "Privileged": false, "Binds": ["/data:/data"] },
+                "Labels": { "a": "1", "b": "2" },
+            },
+        })
+    }
+
+    #[test]
+    fn diff_unchanged_spec_is_noop() {
+        let current = ModuleSet::new(vec![docker_spec(
+            "sensor",
+            &[("A", "1"), ("B", "2")],
+            sensor_config(),
+        )])
+        .unwrap();
+        // The same spec with its keys in a different order and an unset field
+        // that is explicitly null.
+        let desired = ModuleSet::new(vec![docker_spec(
+            "sensor",
+            &[("B", "2"), ("A", "1")],
+            json!({
+                "createOptions": {
+                    "Labels": { "b": "2", "a": "1" },
+                    "HostConfig": { "Binds": ["/data:/data"], "Privileged": false },
+                    "Cmd": null,
+                },
+                "image": "sensor:1.0",
+            }),
+        )])
+        .unwrap();
+
+        let diff = current.diff(&desired).unwrap();
+        assert_eq!(vec![("sensor", "noop")], changes(&diff));
+        assert!(diff.is_empty());
+    }
+
+    #[test]
+    fn diff_env_change_is_restart() {
+        let current =
+            ModuleSet::new(vec![docker_spec("sensor", &[("A", "1")], sensor_config())]).unwrap();
+        let desired =
+            ModuleSet::new(vec![docker_spec("sensor", &[("A", "2")], sensor_config())]).unwrap();
+
+        let diff = current.diff(&desired).unwrap();
+        assert_eq!(vec![("sensor", "restart")], changes(&diff));
+        assert!(!diff.is_empty());
+    }
+
+    #[test]
+    fn diff_create_options_change_is_restart() {
+        let current = ModuleSet::new(vec![docker_spec("sensor", &[], sensor_config())]).unwrap();
+        let mut config = sensor_config();
+        config["createOptions"]["HostConfig"]["Privileged"] = json!(true);
+        let desired = ModuleSet::new(vec![docker_spec("sensor", &[], config)]).unwrap();
+
+        let diff = current.diff(&desired).unwrap();
+        assert_eq!(vec![("sensor", "restart")], changes(&diff));
+    }
+
+    #[test]
+    fn diff_creates_and_removes() {
+        let current = ModuleSet::new(vec![
+            docker_spec("broker", &[], json!({ "image": "broker:1.0" })),
+            docker_spec("old", &[], json!({ "image": "old:1.0" })),
+        ])
+        .unwrap();
+        let desired = ModuleSet::new(vec![
+            docker_spec("broker", &[], json!({ "image": "broker:1.0" })),
+            docker_spec("new", &[], json!({ "image": "new:1.0" }))
+                .with_depends_on(vec!["broker".to_string()]),
+            docker_spec("extra", &[], json!({ "image": "extra:1.0" })),
+        ])
+        .unwrap();
+
+        let diff = current.diff(&desired).unwrap();
+        assert_eq!(
+            vec![
+                ("old", "remove"),
+                ("broker", "noop"),
+                ("extra", "create"),
+                ("new", "create"),
+            ],
+            changes(&diff)
+        );
+    }
+
+    struct TestModule {
+        name: String,
+        config: serde_json::Value,
+    }
+
+    impl Module for TestModule {
+        type Config = serde_json::Value;
+        type Error = Error;
+        type RuntimeStateFuture = future::FutureResult<ModuleRuntimeState, Self::Error>;
+
+        fn name(&self) -> &str {
+            &self.name
+        }
+
+        fn type_(&self) -> &str {
+            "docker"
+        }
+
+        fn config(&self) -> &Self::Config {
+            &self.config
+        }
+
+        fn runtime_state(&self) -> Self::RuntimeStateFuture {
+            unimplemented!()
+        }
+    }
+
+    fn existing(
+        name: &str,
+        status: ModuleStatus,
+        spec_hash: &str,
+    ) -> (TestModule, ModuleRuntimeState) {
+        let mut labels = HashMap::new();
+        labels.insert(SPEC_HASH_LABEL.to_string(), spec_hash.to_string());
+        let module = TestModule {
+            name: name.to_string(),
+            config: sensor_config(),
+        };
+        let state = ModuleRuntimeState::default()
+            .with_status(status)
+            .with_labels(labels);
+        (module, state)
+    }
+
+    #[test]
+    fn adopt_matching_running_module_is_noop() {
+        let spec = docker_spec("sensor", &[("A", "1")], sensor_config());
+        let hash = spec.spec_hash().unwrap();
+        let desired = ModuleSet::new(vec![spec]).unwrap();
+
+        let diff = desired
+            .adopt(&[
+                existing("sensor", ModuleStatus::Running, &hash),
+                // Not in the desired set, so it isn't the daemon's to remove.
+                existing("other", ModuleStatus::Running, "abc"),
+            ])
+            .unwrap();
+        assert_eq!(vec![("sensor", "noop")], changes(&diff));
+        assert!(diff.is_empty());
+    }
+
+    #[test]
+    fn adopt_mismatching_module_is_recreated() {
+        let spec = docker_spec("sensor", &[("A", "1")], sensor_config());
+        let hash = spec.spec_hash().unwrap();
+        let desired = ModuleSet::new(vec![
+            spec,
+            docker_spec("stopped", &[], sensor_config()),
+            docker_spec("missing", &[], sensor_config()),
+        ])
+        .unwrap();
+        let stopped_hash = desired.modules()[1].spec_hash().unwrap();
+
+        let diff = desired
+            .adopt(&[
+                existing("sensor", ModuleStatus::Running, "abc"),
+                existing("stopped", ModuleStatus::Stopped, &stopped_hash),
+            ])
+            .unwrap();
+        assert_eq!(
+            vec![
+                ("missing", "create"),
+                ("sensor", "restart"),
+                ("stopped", "restart"),
+            ],
+            changes(&diff)
+        );
+
+        // A running module without the label is recreated too.
+        let (module, state) = existing("sensor", ModuleStatus::Running, &hash);
+        let state = state.with_labels(HashMap::new());
+        let desired = ModuleSet::new(vec![desired.modules()[0].clone()]).unwrap();
+        let diff = desired.adopt(&[(module, state)]).unwrap();
+        assert_eq!(vec![("sensor", "restart")], changes(&diff));
+    }
+
+    // A module whose container was created from `spec`.
+    fn created_from(
+        spec: &ModuleSpec<serde_json::Value>,
+        status: ModuleStatus,
+    ) -> (TestModule, ModuleRuntimeState) {
+        let module = TestModule {
+            name: spec.name().to_string(),
+            config: spec.config().clone(),
+        };
+        let state = ModuleRuntimeState::default()
+            .with_status(status)
+            .with_labels(spec.spec_labels().unwrap());
+        (module, state)
+    }
+
+    fn plan(plan: &[PlannedChange]) -> Vec<(&str, String, &[String])> {
+        plan.iter()
+            .map(|change| (change.name(), change.action().to_string(), change.reasons()))
+            .collect()
+    }
+
+    #[test]
+    fn plan_has_each_action() {
+        let sensor = docker_spec("sensor", &[("A", "1")], sensor_config());
+        let filter = docker_spec("filter", &[], sensor_config());
+        let desired = ModuleSet::new(vec![
+            sensor.clone(),
+            filter.clone().with_env(
+                vec![("A".to_string(), "2".to_string())]
+                    .into_iter()
+                    .collect(),
+            ),
+            docker_spec("new", &[], sensor_config()),
+        ])
+        .unwrap();
+
+        let existing = vec![
+            created_from(&sensor, ModuleStatus::Running),
+            created_from(&filter, ModuleStatus::Running),
+            created_from(
+                &docker_spec("old", &[], sensor_config()),
+                ModuleStatus::Running,
+            ),
+        ];
+        let no_reasons: &[String] = &[];
+        assert_eq!(
+            vec![
+                ("old", "remove".to_string(), no_reasons),
+                (
+                    "filter",
+                    "restart".to_string(),
+                    &["env changed".to_string()][..]
+                ),
+                ("new", "create".to_string(), no_reasons),
+                ("sensor", "no-op".to_string(), no_reasons),
+            ],
+            plan(&desired.plan(&existing).unwrap())
+        );
+    }
+
+    #[test]
+    fn plan_names_what_changed() {
+        let current = docker_spec("sensor", &[("A", "1")], sensor_config());
+        let mut config = sensor_config();
+        config["image"] = json!("sensor:2.0");
+        config["createOptions"]["Labels"]["a"] = json!("3");
+        let desired = ModuleSet::new(vec![docker_spec("sensor", &[("A", "1")], config)
+            .with_stop_timeout(Some(Duration::from_secs(10)))])
+        .unwrap();
+
+        let changes = desired
+            .plan(&[created_from(&current, ModuleStatus::Stopped)])
+            .unwrap();
+        assert_eq!(PlannedAction::Restart, changes[0].action());
+        assert_eq!(
+            &[
+                "createOptions changed".to_string(),
+                "image changed".to_string(),
+                "stop_timeout_secs changed".to_string(),
+                "module is stopped".to_string(),
+            ],
+            changes[0].reasons()
+        );
+
+        // A container without the labels wasn't created from a known spec.
+        let (module, state) = created_from(&current, ModuleStatus::Running);
+        let state = state.with_labels(HashMap::new());
+        let desired = ModuleSet::new(vec![current]).unwrap();
+        let changes = desired.plan(&[(module, state)]).unwrap();
+        assert_eq!(PlannedAction::Restart, changes[0].action());
+        assert_eq!(
+            &["the spec the module was created from is unknown".to_string()],
+            changes[0].reasons()
+        );
+    }
+
+    #[test]
+    fn deserialize_rejects_cycle() {
+        let value = json!([
+            { "name": "a", "type": "docker", "config": "image", "dependsOn": ["b"] },
+            { "name": "b", "type": "docker", "config": "image", "dependsOn": ["a"] },
+        ]);
+
+        let err = serde_json::from_value::<ModuleSet<String>>(value).unwrap_err();
+        assert!(err.to_string().contains("cycle"));
+    }
+
+    #[test]
+    fn deserialize_startup_order() {
+        let value = json!([
+            { "name": "a", "type": "docker", "config": "image" },
+            { "name": "b", "type": "docker", "config": "image", "startupOrder": 1 },
+        ]);
+
+        let set: ModuleSet<String> = serde_json::from_value(value).unwrap();
+        assert_eq!(vec!["b", "a"], names(set.startup_order()));
+        assert_eq!(DEFAULT_STARTUP_ORDER, set.modules()[0].startup_order());
+    }
+
+    #[test]
+    fn deployment_within_budget_is_admitted() {
+        let set = ModuleSet::new(vec![
+            spec_with_resources("a", 256 * 1024 * 1024, 512),
+            spec_with_resources("b", 256 * 1024 * 1024, 512),
+            // Modules without limits don't count against the budgets
+            spec("c", &[]),
+        ])
+        .unwrap();
+        let budget = AdmissionSettings::new()
+            .with_max_modules(3)
+            .with_memory_bytes(512 * 1024 * 1024)
+            .with_cpu_shares(1024);
+
+        set.admit(&budget).unwrap();
+        set.admit(&AdmissionSettings::new()).unwrap();
+    }
+
+    #[test]
+    fn deployment_over_budget_is_rejected() {
+        let set = ModuleSet::new(vec![
+            spec_with_resources("a", 512 * 1024 * 1024, 1024),
+            spec_with_resources("b", 256 * 1024 * 1024, 512),
+        ])
+        .unwrap();
+        let budget = AdmissionSettings::new()
+            .with_max_modules(1)
+            .with_memory_bytes(512 * 1024 * 1024)
+            .with_cpu_shares(2048);
+
+        let err = set.admit(&budget).unwrap_err();
+        match err.kind() {
+            ErrorKind::DeploymentOverBudget(overcommits) => assert_eq!(
+                "2 modules are deployed, 1 more than the maximum of 1; \
+                 the modules request 805306368 bytes of memory, 268435456 more than the budget of 536870912",
+                overcommits
+            ),
+            kind => panic!("unexpected error kind {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn deployment_over_cpu_budget_is_rejected() {
+        let set = ModuleSet::new(vec![
+            spec_with_resources("a", 64 * 1024 * 1024, 1024),
+            spec_with_resources("b", 64 * 1024 * 1024, 1024),
+        ])
+        .unwrap();
+
+        let err = set
+            .admit(&AdmissionSettings::new().with_cpu_shares(1536))
+            .unwrap_err();
+        assert_eq!(
+            "The deployment does not fit on this device: the modules request 2048 CPU shares, 512 more than the budget of 1536",
+            err.to_string()
+        );
+    }
+
+    #[derive(Default)]
+    struct Applied {
+        running: usize,
+        peak: usize,
+        order: Vec<String>,
+    }
+
+    // Applies `diff` with changes that each take a moment, failing the ones
+    // in `failing`, and records how many were applied at the same time.
+    fn apply_diff(
+        diff: ModuleSetDiff<String>,
+        failing: &'static [&'static str],
+    ) -> (Result<()>, Applied) {
+        let applied = Rc::new(RefCell::new(Applied::default()));
+        let apply = {
+            let applied = applied.clone();
+            move |change: ModuleChange<String>| {
+                let name = change.name().to_string();
+                {
+                    let mut applied = applied.borrow_mut();
+                    applied.running += 1;
+                    applied.peak = applied.peak.max(applied.running);
+                }
+                let applied = applied.clone();
+                Delay::new(Instant::now() + Duration::from_millis(20)).then(move |_| {
+                    let mut applied = applied.borrow_mut();
+                    applied.running -= 1;
+                    applied.order.push(name.clone());
+                    if failing.contains(&name.as_str()) {
+                        Err(Error::from(ErrorKind::ModuleRuntime))
+                    } else {
+                        Ok(())
+                    }
+                })
+            }
+        };
+
+        let result = Runtime::new().unwrap().block_on(apply_stages(
+            diff.changes,
+            &diff.stages,
+            diff.parallelism,
+            apply,
+        ));
+        let applied = Rc::try_unwrap(applied)
+            .unwrap_or_else(|_| panic!("changes are still being applied"))
+            .into_inner();
+        (result, applied)
+    }
+
+    #[test]
+    fn independent_modules_are_applied_concurrently() {
+        let current = ModuleSet::new(vec![]).unwrap();
+        let desired = ModuleSet::new(vec![
+            spec("a", &[]),
+            spec("b", &[]),
+            spec("c", &[]),
+            spec("d", &[]),
+            spec("e", &[]),
+        ])
+        .unwrap();
+
+        let diff = current.diff(&desired).unwrap().with_parallelism(2);
+        let (result, applied) = apply_diff(diff, &[]);
+        result.unwrap();
+        assert_eq!(2, applied.peak);
+        assert_eq!(5, applied.order.len());
+
+        let diff = current.diff(&desired).unwrap();
+        assert_eq!(1, diff.parallelism());
+        let (result, applied) = apply_diff(diff, &[]);
+        result.unwrap();
+        assert_eq!(1, applied.peak);
+        assert_eq!(vec!["a", "b", "c", "d", "e"], applied.order);
+    }
+
+    #[test]
+    fn dependent_modules_are_applied_in_order() {
+        let current = ModuleSet::new(vec![spec("old", &[])]).unwrap();
+        let desired =
+            ModuleSet::new(vec![spec("c", &["b"]), spec("b", &["a"]), spec("a", &[])]).unwrap();
+
+        let diff = current.diff(&desired).unwrap().with_parallelism(4);
+        let (result, applied) = apply_diff(diff, &[]);
+        result.unwrap();
+        assert_eq!(1, applied.peak);
+        assert_eq!(vec!["old", "a", "b", "c"], applied.order);
+    }
+
+    #[test]
+    fn failed_module_only_stops_its_dependents() {
+        let current = ModuleSet::new(vec![]).unwrap();
+        let desired = ModuleSet::new(vec![
+            spec("a", &[]),
+            spec("b", &["a"]),
+            spec("c", &["b"]),
+            spec("d", &[]),
+            spec("e", &["d"]),
+        ])
+        .unwrap();
+
+        let diff = current.diff(&desired).unwrap().with_parallelism(2);
+        let (result, applied) = apply_diff(diff, &["a"]);
+        match result.unwrap_err().kind() {
+            ErrorKind::ModulesNotApplied(modules) => assert_eq!("a, b, c", modules),
+            kind => panic!("Expected `ModulesNotApplied` but got {:?}", kind),
+        }
+        let mut order = applied.order;
+        order.sort();
+        assert_eq!(vec!["a", "d", "e"], order);
+    }
+
+    #[test]
+    fn deserialize_succeeds() {
+        let value = json!([
+            { "name": "a", "type": "docker", "config": "image", "dependsOn": ["b"] },
+            { "name": "b", "type": "docker", "config": "image" },
+        ]);
+
+        let set: ModuleSet<String> = serde_json::from_value(value).unwrap();
+        assert_eq!(vec!["b", "a"], names(set.startup_order()));
+    }
+}
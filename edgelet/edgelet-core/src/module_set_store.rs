@@ -0,0 +1,176 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! The module set that the daemon last applied, saved on disk so that a daemon
+//! that restarts, such as after a crash, reconciles from what it deployed
+//! instead of assuming that every module is new.
+
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use failure::{Fail, ResultExt};
+use log::{info, warn, Level};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use edgelet_utils::log_failure;
+
+use crate::error::{Error, ErrorKind};
+use crate::module_set::ModuleSet;
+
+#[derive(Clone, Debug)]
+pub struct ModuleSetStore {
+    path: Arc<PathBuf>,
+}
+
+impl ModuleSetStore {
+    pub fn new<P>(path: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        ModuleSetStore {
+            path: Arc::new(path.into()),
+        }
+    }
+
+    /// The saved module set, or `None` if none was saved. A file that can't
+    /// be read or parsed is logged and treated as missing, so that the daemon
+    /// falls back to the config.
+    pub fn load<T>(&self) -> Option<ModuleSet<T>>
+    where
+        T: DeserializeOwned,
+    {
+        let display = self.path.display().to_string();
+        let contents = match fs::read_to_string(&*self.path) {
+            Ok(contents) => contents,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return None,
+            Err(err) => {
+                let err = Error::from(err.context(ErrorKind::LoadModuleSet(display)));
+                warn!("Ignoring the saved modules, falling back to the config:");
+                log_failure(Level::Warn, &err);
+                return None;
+            }
+        };
+
+        match serde_json::from_str(&contents) {
+            Ok(modules) => {
+                info!("Loaded the modules that were last applied from {}", display);
+                Some(modules)
+            }
+            Err(err) => {
+                let err = Error::from(err.context(ErrorKind::LoadModuleSet(display)));
+                warn!("Ignoring the saved modules, falling back to the config:");
+                log_failure(Level::Warn, &err);
+                None
+            }
+        }
+    }
+
+    /// Replaces the saved module set with `modules`. The file is written next
+    /// to it first and renamed over it, so that a crash never leaves a
+    /// partially written one behind.
+    pub fn save<T>(&self, modules: &ModuleSet<T>) -> Result<(), Error>
+    where
+        T: Serialize,
+    {
+        let display = self.path.display().to_string();
+        let contents = serde_json::to_string(modules)
+            .with_context(|_| ErrorKind::SaveModuleSet(display.clone()))?;
+
+        let mut temp_path = OsString::from(self.path.as_os_str());
+        temp_path.push(".tmp");
+        let temp_path = PathBuf::from(temp_path);
+
+        let mut file =
+            File::create(&temp_path).with_context(|_| ErrorKind::SaveModuleSet(display.clone()))?;
+        file.write_all(contents.as_bytes())
+            .with_context(|_| ErrorKind::SaveModuleSet(display.clone()))?;
+        file.sync_all()
+            .with_context(|_| ErrorKind::SaveModuleSet(display.clone()))?;
+        fs::rename(&temp_path, &*self.path).with_context(|_| ErrorKind::SaveModuleSet(display))?;
+        Ok(())
+    }
+
+    /// Forgets the saved module set, such as when the modules are removed.
+    pub fn clear(&self) -> Result<(), Error> {
+        match fs::remove_file(&*self.path) {
+            Ok(()) => Ok(()),
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(Error::from(
+                err.context(ErrorKind::SaveModuleSet(self.path.display().to_string())),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use tempdir::TempDir;
+
+    use super::*;
+    use crate::module::{ImagePullPolicy, ModuleSpec};
+
+    fn modules(names: &[&str]) -> ModuleSet<String> {
+        ModuleSet::new(
+            names
+                .iter()
+                .map(|name| {
+                    ModuleSpec::new(
+                        (*name).to_string(),
+                        "docker".to_string(),
+                        format!("{}:1.0", name),
+                        HashMap::new(),
+                        ImagePullPolicy::default(),
+                    )
+                    .unwrap()
+                })
+                .collect(),
+        )
+        .unwrap()
+    }
+
+    fn names(modules: &ModuleSet<String>) -> Vec<&str> {
+        modules.modules().iter().map(ModuleSpec::name).collect()
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let dir = TempDir::new("module_set_store").unwrap();
+        let store = ModuleSetStore::new(dir.path().join("module_set_state"));
+        assert!(store.load::<String>().is_none());
+
+        store.save(&modules(&["edgeAgent", "edgeHub"])).unwrap();
+        store.save(&modules(&["edgeAgent"])).unwrap();
+
+        let loaded = store.load::<String>().unwrap();
+        assert_eq!(vec!["edgeAgent"], names(&loaded));
+        assert_eq!("edgeAgent:1.0", loaded.modules()[0].config().as_str());
+        assert!(!dir.path().join("module_set_state.tmp").exists());
+
+        store.clear().unwrap();
+        store.clear().unwrap();
+        assert!(store.load::<String>().is_none());
+    }
+
+    #[test]
+    fn corrupt_file_falls_back() {
+        let dir = TempDir::new("module_set_store").unwrap();
+        let path = dir.path().join("module_set_state");
+        let store = ModuleSetStore::new(&path);
+
+        fs::write(&path, "[{\"name\": \"edgeAgent\", \"ty").unwrap();
+        assert!(store.load::<String>().is_none());
+
+        // So is valid JSON that isn't a module set.
+        fs::write(&path, "{\"modules\": 1}").unwrap();
+        assert!(store.load::<String>().is_none());
+
+        // The next save replaces the corrupt file.
+        store.save(&modules(&["edgeHub"])).unwrap();
+        assert_eq!(vec!["edgeHub"], names(&store.load::<String>().unwrap()));
+    }
+}
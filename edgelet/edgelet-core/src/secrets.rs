@@ -0,0 +1,239 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Secrets that the environment variables of modules can refer to, so that
+//! they don't have to be in plain text in the deployment manifest.
+//!
+//! A value like `${SECRET:db_password}` is replaced with the `db_password`
+//! secret from a file on the host when the module's container is created. The
+//! file has a `name=value` secret per line, and blank lines and lines starting
+//! with `#` are skipped. The values of secrets are never logged, nor are they
+//! part of any error.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use failure::{Fail, ResultExt};
+
+use crate::error::{Error, ErrorKind, Result};
+
+const SECRET_REFERENCE_START: &str = "${SECRET:";
+const SECRET_REFERENCE_END: char = '}';
+
+#[derive(Clone, Default)]
+pub struct HostSecrets {
+    secrets: HashMap<String, String>,
+}
+
+impl HostSecrets {
+    pub fn new() -> Self {
+        HostSecrets::default()
+    }
+
+    /// Reads the secrets file at `path`. On Unix the file must not be
+    /// accessible by anyone but its owner, the user the daemon runs as.
+    pub fn load(path: &Path) -> Result<Self> {
+        let display = path.display().to_string();
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let metadata =
+                fs::metadata(path).with_context(|_| ErrorKind::ReadSecretsFile(display.clone()))?;
+            if metadata.permissions().mode() & 0o077 != 0 {
+                return Err(Error::from(ErrorKind::SecretsFilePermissions(display)));
+            }
+        }
+
+        let contents = fs::read_to_string(path)
+            .with_context(|_| ErrorKind::ReadSecretsFile(display.clone()))?;
+        HostSecrets::parse(&contents)
+            .map_err(|err| Error::from(err.context(ErrorKind::ReadSecretsFile(display))))
+    }
+
+    /// Parses the contents of a secrets file. Whitespace around names and
+    /// values is trimmed.
+    pub fn parse(contents: &str) -> Result<Self> {
+        let mut secrets = HashMap::new();
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut parts = line.splitn(2, '=');
+            match (parts.next().map(str::trim), parts.next()) {
+                (Some(name), Some(value)) if !name.is_empty() => {
+                    secrets.insert(name.to_string(), value.trim().to_string());
+                }
+                _ => return Err(Error::from(ErrorKind::InvalidSecretsFileLine(i + 1))),
+            }
+        }
+
+        Ok(HostSecrets { secrets })
+    }
+
+    pub fn with_secret(mut self, name: String, value: String) -> Self {
+        self.secrets.insert(name, value);
+        self
+    }
+
+    /// Returns `env` with the secret references in its values replaced by the
+    /// secrets they name. Fails with `ErrorKind::MissingSecret` if a secret
+    /// isn't in this set.
+    pub fn resolve_env(&self, env: &HashMap<String, String>) -> Result<HashMap<String, String>> {
+        env.iter()
+            .map(|(key, value)| Ok((key.clone(), self.resolve(key, value)?)))
+            .collect()
+    }
+
+    fn resolve(&self, key: &str, value: &str) -> Result<String> {
+        let mut resolved = String::with_capacity(value.len());
+        let mut rest = value;
+        while let Some(start) = rest.find(SECRET_REFERENCE_START) {
+            resolved.push_str(&rest[..start]);
+            rest = &rest[start + SECRET_REFERENCE_START.len()..];
+
+            let end = rest
+                .find(SECRET_REFERENCE_END)
+                .ok_or_else(|| ErrorKind::InvalidSecretReference(key.to_string()))?;
+            let name = &rest[..end];
+            let secret = self
+                .secrets
+                .get(name)
+                .ok_or_else(|| ErrorKind::MissingSecret(key.to_string(), name.to_string()))?;
+            resolved.push_str(secret);
+            rest = &rest[end + 1..];
+        }
+        resolved.push_str(rest);
+        Ok(resolved)
+    }
+}
+
+/// Whether any of the values of `env` refer to a secret.
+pub fn has_secret_references(env: &HashMap<String, String>) -> bool {
+    env.values()
+        .any(|value| value.contains(SECRET_REFERENCE_START))
+}
+
+// Only the names of the secrets, so that they can't end up in a log
+impl fmt::Debug for HostSecrets {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names: Vec<_> = self.secrets.keys().collect();
+        names.sort();
+        f.debug_struct("HostSecrets")
+            .field("names", &names)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(vars: &[(&str, &str)]) -> HashMap<String, String> {
+        vars.iter()
+            .map(|(k, v)| ((*k).to_string(), (*v).to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn parse_skips_comments_and_blank_lines() {
+        let secrets = HostSecrets::parse(
+            "# database\n\
+             db_password = p@ss=word \n\
+             \n\
+             api_key=abc123\n",
+        )
+        .unwrap();
+
+        let resolved = secrets
+            .resolve_env(&env(&[
+                ("DB_PASSWORD", "${SECRET:db_password}"),
+                ("API_KEY", "${SECRET:api_key}"),
+            ]))
+            .unwrap();
+        assert_eq!("p@ss=word", resolved["DB_PASSWORD"]);
+        assert_eq!("abc123", resolved["API_KEY"]);
+    }
+
+    #[test]
+    fn parse_rejects_lines_without_name() {
+        for contents in &["db_password\n", "=hunter2\n"] {
+            let err = HostSecrets::parse(contents).unwrap_err();
+            match err.kind() {
+                ErrorKind::InvalidSecretsFileLine(1) => (),
+                kind => panic!("Expected `InvalidSecretsFileLine` but got {:?}", kind),
+            }
+            assert!(!err.to_string().contains("hunter2"));
+        }
+    }
+
+    #[test]
+    fn resolve_env_substitutes_secrets() {
+        let secrets = HostSecrets::new()
+            .with_secret("user".to_string(), "admin".to_string())
+            .with_secret("password".to_string(), "hunter2".to_string());
+
+        let resolved = secrets
+            .resolve_env(&env(&[
+                (
+                    "CONNECTION",
+                    "Server=db;User=${SECRET:user};Password=${SECRET:password}",
+                ),
+                ("PLAIN", "value"),
+                ("BRACES", "${NOT_A_SECRET}"),
+            ]))
+            .unwrap();
+
+        assert_eq!(
+            "Server=db;User=admin;Password=hunter2",
+            resolved["CONNECTION"]
+        );
+        assert_eq!("value", resolved["PLAIN"]);
+        assert_eq!("${NOT_A_SECRET}", resolved["BRACES"]);
+    }
+
+    #[test]
+    fn resolve_env_fails_for_missing_secret() {
+        let secrets = HostSecrets::new().with_secret("user".to_string(), "admin".to_string());
+
+        let err = secrets
+            .resolve_env(&env(&[("PASSWORD", "${SECRET:password}")]))
+            .unwrap_err();
+
+        match err.kind() {
+            ErrorKind::MissingSecret(key, name) => {
+                assert_eq!("PASSWORD", key);
+                assert_eq!("password", name);
+            }
+            kind => panic!("Expected `MissingSecret` but got {:?}", kind),
+        }
+        assert_eq!(
+            "Environment variable \"PASSWORD\" refers to secret \"password\", which is not in the secrets file",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn resolve_env_fails_for_unterminated_reference() {
+        let err = HostSecrets::new()
+            .resolve_env(&env(&[("PASSWORD", "${SECRET:password")]))
+            .unwrap_err();
+
+        match err.kind() {
+            ErrorKind::InvalidSecretReference(key) => assert_eq!("PASSWORD", key),
+            kind => panic!("Expected `InvalidSecretReference` but got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn debug_leaves_out_values() {
+        let secrets = HostSecrets::new().with_secret("password".to_string(), "hunter2".to_string());
+        let debug = format!("{:?}", secrets);
+        assert!(debug.contains("password"));
+        assert!(!debug.contains("hunter2"));
+    }
+}
@@ -4,16 +4,25 @@ use std::cmp::Ordering;
 use std::fmt::Display;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::time::Duration;
 
+use rand::Rng;
 use regex::Regex;
+use serde::de::DeserializeOwned;
 use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use url::Url;
 use url_serde;
 
+use edgelet_utils::deserialize_duration;
+
 use crate::crypto::MemoryKey;
 use crate::error::{Error, ErrorKind};
+use crate::maintenance::MaintenanceWindows;
 use crate::module::ModuleSpec;
-use crate::DEFAULT_AUTO_GENERATED_CA_LIFETIME_DAYS;
+use crate::{
+    DEFAULT_AUTO_GENERATED_CA_LIFETIME_DAYS, DEFAULT_CERT_EXPIRY_WARNING_DAYS,
+    DEFAULT_MAX_LOG_LINE_LENGTH, DEFAULT_SERVER_CERT_RENEWAL_THRESHOLD_PERCENT,
+};
 
 const DEVICEID_KEY: &str = "DeviceId";
 const HOSTNAME_KEY: &str = "HostName";
@@ -196,7 +205,7 @@ impl<'de> serde::Deserialize<'de> for Manual {
 
         let authentication = match (value.device_connection_string, value.authentication) {
             (Some(_), Some(_)) => {
-                return Err(serde::de::Error::custom(
+                return Err(de::Error::custom(
                         "Only one of provisioning.device_connection_string or provisioning.authentication must be set in the config.yaml.",
                     ));
             }
@@ -205,7 +214,7 @@ impl<'de> serde::Deserialize<'de> for Manual {
             }
             (None, Some(auth)) => auth,
             (None, None) => {
-                return Err(serde::de::Error::custom(
+                return Err(de::Error::custom(
                     "One of provisioning.device_connection_string or provisioning.authentication must be set in the config.yaml.",
                 ));
             }
@@ -318,12 +327,54 @@ impl X509AttestationInfo {
     }
 }
 
+/// Controls how DPS registration is retried when DPS can't be reached, is
+/// throttling the device or fails with a server error. Registration is given up
+/// on after `max_attempts` attempts or once `max_elapsed` has passed.
+#[derive(Clone, Debug, PartialEq, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct DpsRetry {
+    #[serde(default = "default_dps_retry_max_attempts")]
+    max_attempts: u32,
+    #[serde(
+        default = "default_dps_retry_max_elapsed",
+        deserialize_with = "deserialize_duration"
+    )]
+    max_elapsed: Duration,
+}
+
+fn default_dps_retry_max_attempts() -> u32 {
+    10
+}
+
+fn default_dps_retry_max_elapsed() -> Duration {
+    Duration::from_secs(600)
+}
+
+impl Default for DpsRetry {
+    fn default() -> Self {
+        DpsRetry {
+            max_attempts: default_dps_retry_max_attempts(),
+            max_elapsed: default_dps_retry_max_elapsed(),
+        }
+    }
+}
+
+impl DpsRetry {
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    pub fn max_elapsed(&self) -> Duration {
+        self.max_elapsed
+    }
+}
+
 #[derive(Clone, Debug, serde_derive::Serialize)]
 pub struct Dps {
     #[serde(with = "url_serde")]
     global_endpoint: Url,
     scope_id: String,
     attestation: AttestationMethod,
+    retry: DpsRetry,
 }
 
 impl<'de> serde::Deserialize<'de> for Dps {
@@ -339,20 +390,22 @@ impl<'de> serde::Deserialize<'de> for Dps {
             registration_id: Option<String>,
             #[serde(skip_serializing_if = "Option::is_none")]
             attestation: Option<AttestationMethod>,
+            #[serde(default)]
+            retry: DpsRetry,
         }
 
         let value: Inner = serde::Deserialize::deserialize(deserializer)?;
 
         let attestation = match (value.attestation, value.registration_id) {
             (Some(_att), Some(_)) => {
-                return Err(serde::de::Error::custom(
+                return Err(de::Error::custom(
                     "Provisioning registration_id has to be set only in attestation",
                 ));
             }
             (Some(att), None) => att,
             (None, Some(reg_id)) => AttestationMethod::Tpm(TpmAttestationInfo::new(reg_id)),
             (None, None) => {
-                return Err(serde::de::Error::custom(
+                return Err(de::Error::custom(
                     "Provisioning registration_id has to be set",
                 ));
             }
@@ -362,6 +415,7 @@ impl<'de> serde::Deserialize<'de> for Dps {
             global_endpoint: value.global_endpoint,
             scope_id: value.scope_id,
             attestation,
+            retry: value.retry,
         })
     }
 }
@@ -378,22 +432,103 @@ impl Dps {
     pub fn attestation(&self) -> &AttestationMethod {
         &self.attestation
     }
+
+    pub fn retry(&self) -> &DpsRetry {
+        &self.retry
+    }
 }
 
+/// External provisioning gets the device's provisioning information either
+/// from an HTTP endpoint or from the output of a command.
 #[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
 #[serde(rename_all = "lowercase")]
 pub struct External {
-    #[serde(with = "url_serde")]
-    endpoint: Url,
+    #[serde(flatten)]
+    source: ExternalSource,
+}
+
+#[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
+#[serde(untagged)]
+enum ExternalSource {
+    Endpoint {
+        #[serde(with = "url_serde")]
+        endpoint: Url,
+    },
+    Command {
+        command: ExternalCommand,
+    },
 }
 
 impl External {
     pub fn new(endpoint: Url) -> Self {
-        External { endpoint }
+        External {
+            source: ExternalSource::Endpoint { endpoint },
+        }
+    }
+
+    pub fn from_command(command: ExternalCommand) -> Self {
+        External {
+            source: ExternalSource::Command { command },
+        }
+    }
+
+    pub fn endpoint(&self) -> Option<&Url> {
+        match &self.source {
+            ExternalSource::Endpoint { endpoint } => Some(endpoint),
+            ExternalSource::Command { .. } => None,
+        }
     }
 
-    pub fn endpoint(&self) -> &Url {
-        &self.endpoint
+    pub fn command(&self) -> Option<&ExternalCommand> {
+        match &self.source {
+            ExternalSource::Endpoint { .. } => None,
+            ExternalSource::Command { command } => Some(command),
+        }
+    }
+}
+
+/// A command that prints the device's provisioning information as JSON to
+/// stdout, in the same format as the external provisioning endpoint returns it.
+#[derive(Clone, Debug, PartialEq, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct ExternalCommand {
+    program: PathBuf,
+    #[serde(default)]
+    args: Vec<String>,
+    #[serde(
+        default = "default_external_command_timeout",
+        deserialize_with = "deserialize_duration"
+    )]
+    timeout: Duration,
+}
+
+fn default_external_command_timeout() -> Duration {
+    Duration::from_secs(30)
+}
+
+impl ExternalCommand {
+    pub fn new(program: PathBuf, args: Vec<String>) -> Self {
+        ExternalCommand {
+            program,
+            args,
+            timeout: default_external_command_timeout(),
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn program(&self) -> &Path {
+        &self.program
+    }
+
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
     }
 }
 
@@ -452,6 +587,29 @@ pub struct Listen {
     management_uri: Url,
     #[serde(default = "Protocol::default")]
     min_tls_version: Protocol,
+    #[serde(default, skip_serializing_if = "is_false")]
+    enable_metrics: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    enable_exec: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    management_socket_group: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    management_allowed_users: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    management_allowed_groups: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    management_tls: Option<ManagementTls>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    management_socket: Option<SocketOwnership>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    workload_socket: Option<SocketOwnership>,
+}
+
+// Settings that are off by default are left out when serialized, so that the
+// digest of settings from before they existed doesn't change.
+#[allow(clippy::trivially_copy_pass_by_ref)]
+fn is_false(value: &bool) -> bool {
+    !*value
 }
 
 impl Listen {
@@ -466,6 +624,270 @@ impl Listen {
     pub fn min_tls_version(&self) -> Protocol {
         self.min_tls_version
     }
+
+    /// Whether the management API serves Prometheus metrics at `/metrics`.
+    pub fn enable_metrics(&self) -> bool {
+        self.enable_metrics
+    }
+
+    /// Whether the management API runs commands in modules for diagnostics
+    /// at `/modules/{name}/exec`.
+    pub fn enable_exec(&self) -> bool {
+        self.enable_exec
+    }
+
+    /// The group that may connect to the management API when it listens on
+    /// a Unix domain socket. The socket is only accessible by the daemon's
+    /// user and this group if set.
+    pub fn management_socket_group(&self) -> Option<&str> {
+        self.management_socket_group.as_ref().map(AsRef::as_ref)
+    }
+
+    /// The users, besides the daemon's own, whose clients may use the
+    /// management API over a Unix domain socket or named pipe.
+    pub fn management_allowed_users(&self) -> &[String] {
+        &self.management_allowed_users
+    }
+
+    /// The groups whose members may use the management API over a Unix
+    /// domain socket. Not supported on Windows.
+    pub fn management_allowed_groups(&self) -> &[String] {
+        &self.management_allowed_groups
+    }
+
+    /// The certificates that the management API authenticates itself and its
+    /// clients with when it listens over TLS.
+    pub fn management_tls(&self) -> Option<&ManagementTls> {
+        self.management_tls.as_ref()
+    }
+
+    /// The ownership and mode that the management socket must have once it is
+    /// bound, when the management API listens on a Unix domain socket.
+    pub fn management_socket(&self) -> Option<&SocketOwnership> {
+        self.management_socket.as_ref()
+    }
+
+    /// The ownership and mode that the workload socket must have once it is
+    /// bound, when the workload API listens on a Unix domain socket.
+    pub fn workload_socket(&self) -> Option<&SocketOwnership> {
+        self.workload_socket.as_ref()
+    }
+}
+
+/// The owner, group and mode that a Unix domain socket of the daemon is
+/// checked for at startup. A socket left behind by a previous run can have
+/// others, which keeps modules from connecting to it. Unless `repair` is off,
+/// the daemon corrects them; otherwise it fails to start.
+#[derive(Clone, Debug, PartialEq, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct SocketOwnership {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    owner: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    group: Option<String>,
+    #[serde(default, with = "octal_mode", skip_serializing_if = "Option::is_none")]
+    mode: Option<u32>,
+    #[serde(default = "default_repair_socket", skip_serializing_if = "is_true")]
+    repair: bool,
+}
+
+fn default_repair_socket() -> bool {
+    true
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+fn is_true(value: &bool) -> bool {
+    *value
+}
+
+impl SocketOwnership {
+    pub fn new() -> Self {
+        SocketOwnership {
+            owner: None,
+            group: None,
+            mode: None,
+            repair: default_repair_socket(),
+        }
+    }
+
+    /// The user, by name or uid, that must own the socket.
+    pub fn owner(&self) -> Option<&str> {
+        self.owner.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn with_owner(mut self, owner: String) -> Self {
+        self.owner = Some(owner);
+        self
+    }
+
+    /// The group, by name or gid, that must own the socket.
+    pub fn group(&self) -> Option<&str> {
+        self.group.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn with_group(mut self, group: String) -> Self {
+        self.group = Some(group);
+        self
+    }
+
+    /// The permission bits the socket must have, like `0o660`.
+    pub fn mode(&self) -> Option<u32> {
+        self.mode
+    }
+
+    pub fn with_mode(mut self, mode: u32) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Whether a socket with another owner, group or mode is corrected
+    /// rather than failing startup.
+    pub fn repair(&self) -> bool {
+        self.repair
+    }
+
+    pub fn with_repair(mut self, repair: bool) -> Self {
+        self.repair = repair;
+        self
+    }
+}
+
+impl Default for SocketOwnership {
+    fn default() -> Self {
+        SocketOwnership::new()
+    }
+}
+
+// File modes written in octal like "0660", since YAML would read 0660 as a
+// decimal number.
+mod octal_mode {
+    use serde::de::{self, Deserialize, Deserializer};
+    use serde::ser::Serializer;
+
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    pub fn serialize<S>(mode: &Option<u32>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match mode {
+            Some(mode) => serializer.serialize_str(&format!("{:04o}", mode)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<u32>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = String::deserialize(deserializer)?;
+        let digits = if value.starts_with("0o") {
+            &value[2..]
+        } else {
+            &value
+        };
+        match u32::from_str_radix(digits, 8) {
+            Ok(mode) if mode <= 0o7777 => Ok(Some(mode)),
+            _ => Err(de::Error::custom(format!(
+                "invalid mode {:?}, expected octal permission bits like \"0660\"",
+                value
+            ))),
+        }
+    }
+}
+
+/// TLS for a management API that is used remotely. Clients must present a
+/// certificate issued by `client_ca` that hasn't been revoked.
+#[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct ManagementTls {
+    server_cert: PathBuf,
+    server_key: PathBuf,
+    client_ca: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    crl_file: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ocsp: Option<OcspSettings>,
+}
+
+impl ManagementTls {
+    /// The PEM file of the server's certificate, followed by the rest of its
+    /// chain if any.
+    pub fn server_cert(&self) -> &Path {
+        &self.server_cert
+    }
+
+    pub fn server_key(&self) -> &Path {
+        &self.server_key
+    }
+
+    /// The PEM file of the CA certificates that client certificates must be
+    /// issued by.
+    pub fn client_ca(&self) -> &Path {
+        &self.client_ca
+    }
+
+    /// The PEM or DER file of a certificate revocation list issued by one of
+    /// the CAs in `client_ca`. Clients with a certificate in it are rejected.
+    pub fn crl_file(&self) -> Option<&Path> {
+        self.crl_file.as_ref().map(AsRef::as_ref)
+    }
+
+    /// The OCSP responder that client certificates are checked with.
+    pub fn ocsp(&self) -> Option<&OcspSettings> {
+        self.ocsp.as_ref()
+    }
+}
+
+#[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct OcspSettings {
+    #[serde(with = "url_serde")]
+    responder_uri: Url,
+    #[serde(default)]
+    on_unreachable: OcspFailurePolicy,
+    #[serde(
+        default = "default_ocsp_timeout",
+        deserialize_with = "deserialize_duration"
+    )]
+    timeout: Duration,
+}
+
+fn default_ocsp_timeout() -> Duration {
+    Duration::from_secs(5)
+}
+
+impl OcspSettings {
+    /// The http URL that OCSP requests are posted to.
+    pub fn responder_uri(&self) -> &Url {
+        &self.responder_uri
+    }
+
+    /// Whether clients are accepted when the responder can't be reached or
+    /// doesn't know their certificate.
+    pub fn on_unreachable(&self) -> OcspFailurePolicy {
+        self.on_unreachable
+    }
+
+    /// How long to wait for the responder before it is considered
+    /// unreachable.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+}
+
+/// What to do with a client whose certificate's revocation status couldn't
+/// be checked because the OCSP responder couldn't be reached. A response that
+/// doesn't verify or doesn't know the certificate always rejects the client.
+#[derive(Clone, Copy, Debug, PartialEq, serde_derive::Deserialize, serde_derive::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OcspFailurePolicy {
+    /// Accept the client, as if the certificate weren't revoked.
+    FailOpen,
+    /// Reject the client.
+    FailClosed,
+}
+
+impl Default for OcspFailurePolicy {
+    fn default() -> Self {
+        OcspFailurePolicy::FailClosed
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -529,6 +951,12 @@ pub struct Certificates {
     device_cert: Option<DeviceCertificate>,
     #[serde(default = "default_auto_generated_ca_lifetime_days")]
     auto_generated_ca_lifetime_days: u16,
+    #[serde(default = "default_expiry_warning_days")]
+    expiry_warning_days: u16,
+    #[serde(default)]
+    auto_rotate_server_certs: bool,
+    #[serde(default = "default_server_cert_renewal_threshold_percent")]
+    server_cert_renewal_threshold_percent: u8,
 }
 
 #[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
@@ -542,6 +970,14 @@ fn default_auto_generated_ca_lifetime_days() -> u16 {
     DEFAULT_AUTO_GENERATED_CA_LIFETIME_DAYS
 }
 
+fn default_expiry_warning_days() -> u16 {
+    DEFAULT_CERT_EXPIRY_WARNING_DAYS
+}
+
+fn default_server_cert_renewal_threshold_percent() -> u8 {
+    DEFAULT_SERVER_CERT_RENEWAL_THRESHOLD_PERCENT
+}
+
 fn is_supported_uri(uri: &Url) -> bool {
     if uri.scheme() == "file" && uri.port().is_none() && uri.query().is_none() {
         if let Some(host) = uri.host_str() {
@@ -643,6 +1079,18 @@ impl Certificates {
         // Convert days to seconds (86,400 seconds per day)
         u64::from(self.auto_generated_ca_lifetime_days) * 86_400
     }
+
+    pub fn expiry_warning_days(&self) -> u16 {
+        self.expiry_warning_days
+    }
+
+    pub fn auto_rotate_server_certs(&self) -> bool {
+        self.auto_rotate_server_certs
+    }
+
+    pub fn server_cert_renewal_threshold_percent(&self) -> u8 {
+        self.server_cert_renewal_threshold_percent
+    }
 }
 
 #[derive(Clone, Copy, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
@@ -667,100 +1115,1182 @@ impl Default for RetryLimit {
     }
 }
 
-#[derive(Clone, Debug, Default, serde_derive::Deserialize, serde_derive::Serialize)]
-pub struct WatchdogSettings {
-    #[serde(default)]
-    max_retries: RetryLimit,
+/// Controls how long the watchdog waits between consecutive restarts of a
+/// failing module. The Nth consecutive failure waits
+/// `min(initial * multiplier^n, max)`, randomly spread by up to `jitter`
+/// (a fraction of the delay) in either direction.
+#[derive(Clone, Debug, PartialEq, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct BackoffPolicy {
+    #[serde(
+        default = "default_backoff_initial",
+        deserialize_with = "deserialize_duration"
+    )]
+    initial: Duration,
+    #[serde(
+        default = "default_backoff_max",
+        deserialize_with = "deserialize_duration"
+    )]
+    max: Duration,
+    #[serde(default = "default_backoff_multiplier")]
+    multiplier: f64,
+    #[serde(default = "default_backoff_jitter")]
+    jitter: f64,
+    /// How long a module must stay up before its failure count is reset.
+    #[serde(
+        default = "default_backoff_grace_period",
+        deserialize_with = "deserialize_duration"
+    )]
+    grace_period: Duration,
+    /// The least a module that was killed for running out of memory waits
+    /// before it is started again, since it is likely to run out again
+    /// straight away. Such a module backs off like any other if not set.
+    #[serde(default, deserialize_with = "deserialize_optional_duration")]
+    oom_killed: Option<Duration>,
 }
 
-impl WatchdogSettings {
-    pub fn max_retries(&self) -> RetryLimit {
-        self.max_retries
-    }
+fn default_backoff_initial() -> Duration {
+    Duration::from_secs(10)
 }
 
-pub trait RuntimeSettings {
-    type Config;
+fn default_backoff_max() -> Duration {
+    Duration::from_secs(300)
+}
 
-    fn provisioning(&self) -> &Provisioning;
-    fn agent(&self) -> &ModuleSpec<Self::Config>;
-    fn agent_mut(&mut self) -> &mut ModuleSpec<Self::Config>;
-    fn hostname(&self) -> &str;
-    fn connect(&self) -> &Connect;
-    fn listen(&self) -> &Listen;
-    fn homedir(&self) -> &Path;
-    fn certificates(&self) -> &Certificates;
-    fn watchdog(&self) -> &WatchdogSettings;
+fn default_backoff_multiplier() -> f64 {
+    2.0
 }
 
-#[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
-pub struct Settings<T> {
-    provisioning: Provisioning,
-    agent: ModuleSpec<T>,
-    hostname: String,
-    connect: Connect,
-    listen: Listen,
-    homedir: PathBuf,
-    certificates: Option<Certificates>,
-    #[serde(default)]
-    watchdog: WatchdogSettings,
+fn default_backoff_jitter() -> f64 {
+    0.1
 }
 
-impl<T> RuntimeSettings for Settings<T>
-where
-    T: Clone,
-{
-    type Config = T;
+fn default_backoff_grace_period() -> Duration {
+    Duration::from_secs(600)
+}
 
-    fn provisioning(&self) -> &Provisioning {
-        &self.provisioning
+impl Default for BackoffPolicy {
+    fn default() -> Self {
+        BackoffPolicy {
+            initial: default_backoff_initial(),
+            max: default_backoff_max(),
+            multiplier: default_backoff_multiplier(),
+            jitter: default_backoff_jitter(),
+            grace_period: default_backoff_grace_period(),
+            oom_killed: None,
+        }
     }
+}
 
-    fn agent(&self) -> &ModuleSpec<T> {
-        &self.agent
+impl BackoffPolicy {
+    pub fn new(initial: Duration, max: Duration, multiplier: f64, jitter: f64) -> Self {
+        BackoffPolicy {
+            initial,
+            max,
+            multiplier,
+            jitter,
+            grace_period: default_backoff_grace_period(),
+            oom_killed: None,
+        }
     }
 
-    fn agent_mut(&mut self) -> &mut ModuleSpec<T> {
-        &mut self.agent
+    pub fn with_grace_period(mut self, grace_period: Duration) -> Self {
+        self.grace_period = grace_period;
+        self
     }
 
-    fn hostname(&self) -> &str {
-        &self.hostname
+    pub fn with_oom_killed(mut self, oom_killed: Option<Duration>) -> Self {
+        self.oom_killed = oom_killed;
+        self
     }
 
-    fn connect(&self) -> &Connect {
-        &self.connect
+    pub fn initial(&self) -> Duration {
+        self.initial
     }
 
-    fn listen(&self) -> &Listen {
-        &self.listen
+    pub fn max(&self) -> Duration {
+        self.max
     }
 
-    fn homedir(&self) -> &Path {
-        &self.homedir
+    pub fn multiplier(&self) -> f64 {
+        self.multiplier
     }
 
-    // Certificates is left as an option for backward compat
-    fn certificates(&self) -> &Certificates {
-        match &self.certificates {
-            None => &Certificates {
-                device_cert: None,
-                auto_generated_ca_lifetime_days: DEFAULT_AUTO_GENERATED_CA_LIFETIME_DAYS,
-            },
-            Some(c) => c,
+    pub fn jitter(&self) -> f64 {
+        self.jitter
+    }
+
+    pub fn grace_period(&self) -> Duration {
+        self.grace_period
+    }
+
+    pub fn oom_killed(&self) -> Option<Duration> {
+        self.oom_killed
+    }
+
+    /// Delay before retrying after the `failures`th consecutive failure
+    /// (zero-based), without jitter.
+    pub fn delay(&self, failures: u32) -> Duration {
+        let max = self.max.as_secs_f64();
+        let delay = self.initial.as_secs_f64() * self.multiplier.max(1.0).powf(f64::from(failures));
+        saturating_delay(delay.min(max))
+    }
+
+    /// Same as `delay`, randomly spread by up to `jitter` in either direction.
+    pub fn jittered_delay<R: Rng>(&self, failures: u32, rng: &mut R) -> Duration {
+        let delay = self.delay(failures);
+        let jitter = self.jitter.max(0.0).min(1.0);
+        if jitter <= 0.0 {
+            delay
+        } else {
+            saturating_delay(delay.as_secs_f64() * rng.gen_range(1.0 - jitter, 1.0 + jitter))
         }
     }
+}
 
-    fn watchdog(&self) -> &WatchdogSettings {
-        &self.watchdog
+// The longest that the watchdog backs off, however large `max` is, so that a
+// delay can always be added to an `Instant`: a hundred years.
+const MAX_BACKOFF_DELAY_SECS: f64 = 100.0 * 365.0 * 24.0 * 60.0 * 60.0;
+
+fn saturating_delay(secs: f64) -> Duration {
+    if secs > 0.0 {
+        Duration::from_secs_f64(secs.min(MAX_BACKOFF_DELAY_SECS))
+    } else {
+        Duration::from_secs(0)
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use test_case::test_case;
+#[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct WatchdogSettings {
+    #[serde(default)]
+    max_retries: RetryLimit,
+    /// How often the edge runtime module is checked while it is running.
+    #[serde(
+        default = "default_poll_interval",
+        deserialize_with = "deserialize_nonzero_duration"
+    )]
+    poll_interval: Duration,
+    #[serde(default)]
+    backoff: BackoffPolicy,
+    /// Restart the edge runtime module once its health check has been failing
+    /// for this long. Unhealthy modules are left running if not set.
+    #[serde(default, deserialize_with = "deserialize_optional_duration")]
+    unhealthy_restart_threshold: Option<Duration>,
+    /// How long the watchdog may go without checking the edge runtime module
+    /// before it is reported as stuck.
+    #[serde(
+        default = "default_stall_timeout",
+        deserialize_with = "deserialize_nonzero_duration"
+    )]
+    stall_timeout: Duration,
+    /// Exit once the watchdog is stuck, so that the service manager restarts
+    /// the daemon.
+    #[serde(default)]
+    exit_on_stall: bool,
+    /// Stop retrying the edge runtime module once it has failed this many
+    /// times in a row, until it is resumed through the management API. The
+    /// module is retried forever, or up to `max_retries`, if not set.
+    #[serde(default)]
+    pause_on_error: Option<u32>,
+    /// The only times at which the edge runtime module is updated to a
+    /// changed spec, other than critical updates. Any time if none are set.
+    #[serde(default)]
+    maintenance: MaintenanceWindows,
+    /// How many modules are created, recreated or removed at the same time
+    /// when a module set is reconciled. Modules are still started after the
+    /// modules they depend on.
+    #[serde(
+        default = "default_reconcile_parallelism",
+        deserialize_with = "deserialize_nonzero_usize"
+    )]
+    reconcile_parallelism: usize,
+    /// Take over the deployed modules that are already running as they
+    /// should when the daemon starts, rather than starting them, such as the
+    /// containers of a device that is migrated to the daemon.
+    #[serde(default)]
+    adopt_existing_modules: bool,
+}
 
-    use super::*;
+impl Default for WatchdogSettings {
+    fn default() -> Self {
+        WatchdogSettings {
+            max_retries: RetryLimit::default(),
+            poll_interval: default_poll_interval(),
+            backoff: BackoffPolicy::default(),
+            unhealthy_restart_threshold: None,
+            stall_timeout: default_stall_timeout(),
+            exit_on_stall: false,
+            pause_on_error: None,
+            maintenance: MaintenanceWindows::default(),
+            reconcile_parallelism: default_reconcile_parallelism(),
+            adopt_existing_modules: false,
+        }
+    }
+}
+
+fn default_poll_interval() -> Duration {
+    Duration::from_secs(60)
+}
+
+fn default_stall_timeout() -> Duration {
+    Duration::from_secs(30 * 60)
+}
+
+fn default_reconcile_parallelism() -> usize {
+    1
+}
+
+fn deserialize_nonzero_usize<'de, D>(deserializer: D) -> Result<usize, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = usize::deserialize(deserializer)?;
+    if value == 0 {
+        return Err(de::Error::custom("value must be greater than zero"));
+    }
+    Ok(value)
+}
+
+fn deserialize_nonzero_duration<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let duration = deserialize_duration(deserializer)?;
+    if duration == Duration::from_secs(0) {
+        return Err(de::Error::custom("duration must be greater than zero"));
+    }
+    Ok(duration)
+}
+
+fn deserialize_optional_duration<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(serde_derive::Deserialize)]
+    struct Wrapper(#[serde(deserialize_with = "deserialize_duration")] Duration);
+
+    Option::<Wrapper>::deserialize(deserializer).map(|wrapper| wrapper.map(|Wrapper(d)| d))
+}
+
+impl WatchdogSettings {
+    pub fn max_retries(&self) -> RetryLimit {
+        self.max_retries
+    }
+
+    pub fn poll_interval(&self) -> Duration {
+        self.poll_interval
+    }
+
+    pub fn backoff(&self) -> &BackoffPolicy {
+        &self.backoff
+    }
+
+    pub fn unhealthy_restart_threshold(&self) -> Option<Duration> {
+        self.unhealthy_restart_threshold
+    }
+
+    pub fn stall_timeout(&self) -> Duration {
+        self.stall_timeout
+    }
+
+    pub fn exit_on_stall(&self) -> bool {
+        self.exit_on_stall
+    }
+
+    pub fn pause_on_error(&self) -> Option<u32> {
+        self.pause_on_error
+    }
+
+    pub fn maintenance(&self) -> &MaintenanceWindows {
+        &self.maintenance
+    }
+
+    pub fn reconcile_parallelism(&self) -> usize {
+        self.reconcile_parallelism
+    }
+
+    pub fn adopt_existing_modules(&self) -> bool {
+        self.adopt_existing_modules
+    }
+}
+
+/// The IoT Hubs that the daemon fails over to, in order, when the one that it
+/// was provisioned with can't be reached.
+#[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct UpstreamSettings {
+    #[serde(default)]
+    failover_hostnames: Vec<String>,
+    /// The hub fails over to the next one after this many consecutive
+    /// connection failures...
+    #[serde(default = "default_upstream_failure_threshold")]
+    failure_threshold: u32,
+    /// ...that all happened within this long of the first of them.
+    #[serde(
+        default = "default_upstream_failure_window",
+        deserialize_with = "deserialize_nonzero_duration"
+    )]
+    failure_window: Duration,
+    /// How long a failover hub is used before the provisioned hub is tried
+    /// again.
+    #[serde(
+        default = "default_upstream_primary_retry_interval",
+        deserialize_with = "deserialize_nonzero_duration"
+    )]
+    primary_retry_interval: Duration,
+    /// How long IoT Hub must be unreachable on end before the device is
+    /// reported disconnected. Right away if not set.
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    offline_grace_period: Duration,
+}
+
+impl Default for UpstreamSettings {
+    fn default() -> Self {
+        UpstreamSettings {
+            failover_hostnames: vec![],
+            failure_threshold: default_upstream_failure_threshold(),
+            failure_window: default_upstream_failure_window(),
+            primary_retry_interval: default_upstream_primary_retry_interval(),
+            offline_grace_period: Duration::from_secs(0),
+        }
+    }
+}
+
+fn default_upstream_failure_threshold() -> u32 {
+    3
+}
+
+fn default_upstream_failure_window() -> Duration {
+    Duration::from_secs(5 * 60)
+}
+
+fn default_upstream_primary_retry_interval() -> Duration {
+    Duration::from_secs(30 * 60)
+}
+
+impl UpstreamSettings {
+    pub fn new(
+        failover_hostnames: Vec<String>,
+        failure_threshold: u32,
+        failure_window: Duration,
+        primary_retry_interval: Duration,
+    ) -> Self {
+        UpstreamSettings {
+            failover_hostnames,
+            failure_threshold,
+            failure_window,
+            primary_retry_interval,
+            offline_grace_period: Duration::from_secs(0),
+        }
+    }
+
+    pub fn failover_hostnames(&self) -> &[String] {
+        &self.failover_hostnames
+    }
+
+    pub fn failure_threshold(&self) -> u32 {
+        self.failure_threshold
+    }
+
+    pub fn failure_window(&self) -> Duration {
+        self.failure_window
+    }
+
+    pub fn primary_retry_interval(&self) -> Duration {
+        self.primary_retry_interval
+    }
+
+    pub fn offline_grace_period(&self) -> Duration {
+        self.offline_grace_period
+    }
+
+    pub fn with_offline_grace_period(mut self, offline_grace_period: Duration) -> Self {
+        self.offline_grace_period = offline_grace_period;
+        self
+    }
+}
+
+/// What the readiness endpoint of the management API requires for the daemon
+/// to be ready.
+#[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct HealthSettings {
+    /// The modules that must be running.
+    #[serde(default = "default_health_ready_modules")]
+    ready_modules: Vec<String>,
+    /// Whether the last request to IoT Hub must have reached it, or one within
+    /// the offline grace period.
+    #[serde(default = "default_health_require_upstream")]
+    require_upstream: bool,
+}
+
+impl Default for HealthSettings {
+    fn default() -> Self {
+        HealthSettings {
+            ready_modules: default_health_ready_modules(),
+            require_upstream: default_health_require_upstream(),
+        }
+    }
+}
+
+fn default_health_ready_modules() -> Vec<String> {
+    vec!["edgeAgent".to_string(), "edgeHub".to_string()]
+}
+
+fn default_health_require_upstream() -> bool {
+    true
+}
+
+impl HealthSettings {
+    pub fn new(ready_modules: Vec<String>, require_upstream: bool) -> Self {
+        HealthSettings {
+            ready_modules,
+            require_upstream,
+        }
+    }
+
+    pub fn ready_modules(&self) -> &[String] {
+        &self.ready_modules
+    }
+
+    pub fn require_upstream(&self) -> bool {
+        self.require_upstream
+    }
+}
+
+/// Where the management API records the calls that change the device, who
+/// made them and how they turned out. Off unless a sink is configured.
+#[derive(Clone, Debug, PartialEq, serde_derive::Deserialize, serde_derive::Serialize)]
+#[serde(tag = "sink", rename_all = "lowercase")]
+pub enum AuditSettings {
+    None,
+    /// Records are appended to `path`, one JSON object per line.
+    File {
+        path: PathBuf,
+    },
+    /// Records are sent to the system logger under `facility`. Not supported
+    /// on Windows.
+    Syslog {
+        #[serde(default)]
+        facility: SyslogFacility,
+    },
+}
+
+impl Default for AuditSettings {
+    fn default() -> Self {
+        AuditSettings::None
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, serde_derive::Deserialize, serde_derive::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyslogFacility {
+    User,
+    Daemon,
+    Auth,
+    Authpriv,
+    Local0,
+    Local1,
+    Local2,
+    Local3,
+    Local4,
+    Local5,
+    Local6,
+    Local7,
+}
+
+impl Default for SyslogFacility {
+    fn default() -> Self {
+        SyslogFacility::Authpriv
+    }
+}
+
+impl SyslogFacility {
+    /// The facility's number in the priority of syslog messages.
+    pub fn code(self) -> u8 {
+        match self {
+            SyslogFacility::User => 1,
+            SyslogFacility::Daemon => 3,
+            SyslogFacility::Auth => 4,
+            SyslogFacility::Authpriv => 10,
+            SyslogFacility::Local0 => 16,
+            SyslogFacility::Local1 => 17,
+            SyslogFacility::Local2 => 18,
+            SyslogFacility::Local3 => 19,
+            SyslogFacility::Local4 => 20,
+            SyslogFacility::Local5 => 21,
+            SyslogFacility::Local6 => 22,
+            SyslogFacility::Local7 => 23,
+        }
+    }
+}
+
+/// Where the daemon forwards what modules write to stdout and stderr, on top
+/// of the logs that the container runtime keeps of it. Off unless a sink is
+/// configured.
+#[derive(Clone, Debug, PartialEq, serde_derive::Deserialize, serde_derive::Serialize)]
+#[serde(tag = "sink", rename_all = "lowercase")]
+pub enum LogForwardingSettings {
+    None,
+    /// Each line is written to the journal with `SYSLOG_IDENTIFIER` set to
+    /// the name of the module that wrote it. Not supported on Windows.
+    Journald {
+        /// How often the module list is checked for modules whose logs aren't
+        /// being followed yet, such as ones that were created or restarted.
+        #[serde(
+            default = "default_log_forwarding_poll_interval",
+            deserialize_with = "deserialize_duration"
+        )]
+        poll_interval: Duration,
+    },
+}
+
+fn default_log_forwarding_poll_interval() -> Duration {
+    Duration::from_secs(5)
+}
+
+impl Default for LogForwardingSettings {
+    fn default() -> Self {
+        LogForwardingSettings::None
+    }
+}
+
+/// How the daemon reads the logs of modules, wherever they go: the management
+/// API, `iotedge logs`, support bundles and log forwarding.
+#[derive(Clone, Debug, PartialEq, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct LogsSettings {
+    #[serde(default = "default_max_log_line_length")]
+    max_line_length: usize,
+}
+
+fn default_max_log_line_length() -> usize {
+    DEFAULT_MAX_LOG_LINE_LENGTH
+}
+
+impl Default for LogsSettings {
+    fn default() -> Self {
+        LogsSettings {
+            max_line_length: DEFAULT_MAX_LOG_LINE_LENGTH,
+        }
+    }
+}
+
+impl LogsSettings {
+    pub fn new() -> Self {
+        LogsSettings::default()
+    }
+
+    /// The most bytes of a line that are kept when the logs are de-framed.
+    /// Longer lines are truncated, so that a module that writes a huge line
+    /// can't make the daemon buffer all of it.
+    pub fn max_line_length(&self) -> usize {
+        self.max_line_length
+    }
+
+    pub fn with_max_line_length(mut self, max_line_length: usize) -> Self {
+        self.max_line_length = max_line_length;
+        self
+    }
+}
+
+/// A webhook, like a Slack incoming webhook, that the daemon posts to when a
+/// module fails or keeps restarting, so that someone hears about it without
+/// watching the device.
+#[derive(Clone, Debug, PartialEq, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct WebhookSettings {
+    #[serde(with = "url_serde")]
+    url: Url,
+    /// The modules that are reported. All of them if none are listed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    modules: Vec<String>,
+    /// Post once a module has restarted this many times, or never if zero.
+    #[serde(default = "default_webhook_restart_threshold")]
+    restart_threshold: u32,
+    /// How often the modules are checked.
+    #[serde(
+        default = "default_webhook_poll_interval",
+        deserialize_with = "deserialize_nonzero_duration"
+    )]
+    poll_interval: Duration,
+    /// How long a post may take before it is retried.
+    #[serde(
+        default = "default_webhook_timeout",
+        deserialize_with = "deserialize_nonzero_duration"
+    )]
+    timeout: Duration,
+    /// How many times a post that failed is retried before it is dropped.
+    #[serde(default = "default_webhook_retries")]
+    retries: u32,
+    /// How long the first retry waits, doubling for each one after it.
+    #[serde(
+        default = "default_webhook_backoff",
+        deserialize_with = "deserialize_duration"
+    )]
+    backoff: Duration,
+}
+
+fn default_webhook_restart_threshold() -> u32 {
+    5
+}
+
+fn default_webhook_poll_interval() -> Duration {
+    Duration::from_secs(10)
+}
+
+fn default_webhook_timeout() -> Duration {
+    Duration::from_secs(10)
+}
+
+fn default_webhook_retries() -> u32 {
+    3
+}
+
+fn default_webhook_backoff() -> Duration {
+    Duration::from_secs(1)
+}
+
+impl WebhookSettings {
+    pub fn new(url: Url) -> Self {
+        WebhookSettings {
+            url,
+            modules: Vec::new(),
+            restart_threshold: default_webhook_restart_threshold(),
+            poll_interval: default_webhook_poll_interval(),
+            timeout: default_webhook_timeout(),
+            retries: default_webhook_retries(),
+            backoff: default_webhook_backoff(),
+        }
+    }
+
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    pub fn modules(&self) -> &[String] {
+        &self.modules
+    }
+
+    pub fn with_modules(mut self, modules: Vec<String>) -> Self {
+        self.modules = modules;
+        self
+    }
+
+    /// Whether module `name` is reported.
+    pub fn reports(&self, name: &str) -> bool {
+        self.modules.is_empty() || self.modules.iter().any(|module| module == name)
+    }
+
+    pub fn restart_threshold(&self) -> u32 {
+        self.restart_threshold
+    }
+
+    pub fn with_restart_threshold(mut self, restart_threshold: u32) -> Self {
+        self.restart_threshold = restart_threshold;
+        self
+    }
+
+    pub fn poll_interval(&self) -> Duration {
+        self.poll_interval
+    }
+
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn retries(&self) -> u32 {
+        self.retries
+    }
+
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    pub fn backoff(&self) -> Duration {
+        self.backoff
+    }
+
+    pub fn with_backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+}
+
+/// Limits on the modules that a device runs, so that a deployment that needs
+/// more than the host has is rejected before it is applied rather than left
+/// to thrash. Unset limits aren't checked.
+#[derive(Clone, Debug, Default, PartialEq, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct AdmissionSettings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_modules: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    memory_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cpu_shares: Option<u64>,
+}
+
+impl AdmissionSettings {
+    pub fn new() -> Self {
+        AdmissionSettings::default()
+    }
+
+    /// The most modules that may be deployed.
+    pub fn max_modules(&self) -> Option<usize> {
+        self.max_modules
+    }
+
+    pub fn with_max_modules(mut self, max_modules: usize) -> Self {
+        self.max_modules = Some(max_modules);
+        self
+    }
+
+    /// The budget for the sum of the memory limits of the modules. Modules
+    /// without a memory limit don't count against it.
+    pub fn memory_bytes(&self) -> Option<u64> {
+        self.memory_bytes
+    }
+
+    pub fn with_memory_bytes(mut self, memory_bytes: u64) -> Self {
+        self.memory_bytes = Some(memory_bytes);
+        self
+    }
+
+    /// The budget for the sum of the CPU shares of the modules. Modules
+    /// without CPU shares don't count against it.
+    pub fn cpu_shares(&self) -> Option<u64> {
+        self.cpu_shares
+    }
+
+    pub fn with_cpu_shares(mut self, cpu_shares: u64) -> Self {
+        self.cpu_shares = Some(cpu_shares);
+        self
+    }
+}
+
+pub trait RuntimeSettings {
+    type Config;
+
+    fn provisioning(&self) -> &Provisioning;
+    fn agent(&self) -> &ModuleSpec<Self::Config>;
+    fn agent_mut(&mut self) -> &mut ModuleSpec<Self::Config>;
+    fn hostname(&self) -> &str;
+    /// The proxy that requests to IoT Hub, DPS and container registries go
+    /// through, with credentials in its userinfo if it needs them.
+    fn https_proxy(&self) -> Option<&str>;
+    fn connect(&self) -> &Connect;
+    fn listen(&self) -> &Listen;
+    fn homedir(&self) -> &Path;
+    fn certificates(&self) -> &Certificates;
+    fn watchdog(&self) -> &WatchdogSettings;
+    fn upstream(&self) -> &UpstreamSettings;
+    fn health(&self) -> &HealthSettings;
+    fn audit(&self) -> &AuditSettings;
+    fn logs(&self) -> &LogsSettings;
+    fn log_forwarding(&self) -> &LogForwardingSettings;
+    fn admission(&self) -> &AdmissionSettings;
+    fn webhook(&self) -> Option<&WebhookSettings>;
+    /// The PEM-encoded public key that deployment manifests must be signed
+    /// with, if only signed manifests are applied.
+    fn manifest_trust_key(&self) -> Option<&Path>;
+}
+
+#[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct Settings<T> {
+    provisioning: Provisioning,
+    #[serde(bound(deserialize = "T: DeserializeOwned"))]
+    agent: ModuleSpec<T>,
+    hostname: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    https_proxy: Option<String>,
+    connect: Connect,
+    listen: Listen,
+    homedir: PathBuf,
+    certificates: Option<Certificates>,
+    #[serde(default)]
+    watchdog: WatchdogSettings,
+    #[serde(default)]
+    upstream: UpstreamSettings,
+    #[serde(default)]
+    health: HealthSettings,
+    #[serde(default)]
+    audit: AuditSettings,
+    #[serde(default)]
+    logs: LogsSettings,
+    #[serde(default)]
+    log_forwarding: LogForwardingSettings,
+    #[serde(default)]
+    admission: AdmissionSettings,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    webhook: Option<WebhookSettings>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    manifest_trust_key: Option<PathBuf>,
+}
+
+impl<T> RuntimeSettings for Settings<T>
+where
+    T: Clone,
+{
+    type Config = T;
+
+    fn provisioning(&self) -> &Provisioning {
+        &self.provisioning
+    }
+
+    fn agent(&self) -> &ModuleSpec<T> {
+        &self.agent
+    }
+
+    fn agent_mut(&mut self) -> &mut ModuleSpec<T> {
+        &mut self.agent
+    }
+
+    fn hostname(&self) -> &str {
+        &self.hostname
+    }
+
+    fn https_proxy(&self) -> Option<&str> {
+        self.https_proxy.as_ref().map(AsRef::as_ref)
+    }
+
+    fn connect(&self) -> &Connect {
+        &self.connect
+    }
+
+    fn listen(&self) -> &Listen {
+        &self.listen
+    }
+
+    fn homedir(&self) -> &Path {
+        &self.homedir
+    }
+
+    // Certificates is left as an option for backward compat
+    fn certificates(&self) -> &Certificates {
+        match &self.certificates {
+            None => &Certificates {
+                device_cert: None,
+                auto_generated_ca_lifetime_days: DEFAULT_AUTO_GENERATED_CA_LIFETIME_DAYS,
+                expiry_warning_days: DEFAULT_CERT_EXPIRY_WARNING_DAYS,
+                auto_rotate_server_certs: false,
+                server_cert_renewal_threshold_percent:
+                    DEFAULT_SERVER_CERT_RENEWAL_THRESHOLD_PERCENT,
+            },
+            Some(c) => c,
+        }
+    }
+
+    fn watchdog(&self) -> &WatchdogSettings {
+        &self.watchdog
+    }
+
+    fn upstream(&self) -> &UpstreamSettings {
+        &self.upstream
+    }
+
+    fn health(&self) -> &HealthSettings {
+        &self.health
+    }
+
+    fn audit(&self) -> &AuditSettings {
+        &self.audit
+    }
+
+    fn logs(&self) -> &LogsSettings {
+        &self.logs
+    }
+
+    fn log_forwarding(&self) -> &LogForwardingSettings {
+        &self.log_forwarding
+    }
+
+    fn admission(&self) -> &AdmissionSettings {
+        &self.admission
+    }
+
+    fn webhook(&self) -> Option<&WebhookSettings> {
+        self.webhook.as_ref()
+    }
+
+    fn manifest_trust_key(&self) -> Option<&Path> {
+        self.manifest_trust_key.as_ref().map(AsRef::as_ref)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use test_case::test_case;
+
+    use super::*;
+
+    #[test]
+    fn backoff_delay_grows_exponentially_up_to_max() {
+        let policy = BackoffPolicy::new(Duration::from_secs(1), Duration::from_secs(30), 2.0, 0.0);
+
+        let delays: Vec<_> = (0..7).map(|n| policy.delay(n).as_secs()).collect();
+        assert_eq!(vec![1, 2, 4, 8, 16, 30, 30], delays);
+        assert_eq!(Duration::from_secs(30), policy.delay(u32::max_value()));
+    }
+
+    #[test]
+    fn backoff_jittered_delay_stays_within_bounds() {
+        let policy = BackoffPolicy::new(Duration::from_secs(10), Duration::from_secs(60), 2.0, 0.5);
+        let mut rng = StdRng::from_seed([7; 32]);
+
+        for _ in 0..100 {
+            let delay = policy.jittered_delay(1, &mut rng);
+            assert!(delay >= Duration::from_secs(10) && delay <= Duration::from_secs(30));
+        }
+    }
+
+    #[test]
+    fn backoff_with_huge_max_saturates() {
+        let max = Duration::new(std::u64::MAX, 999_999_999);
+        let policy = BackoffPolicy::new(Duration::from_secs(1), max, 2.0, 1.0);
+        let mut rng = StdRng::from_seed([7; 32]);
+
+        let longest = Duration::from_secs(100 * 365 * 24 * 60 * 60);
+        assert_eq!(longest, policy.delay(u32::max_value()));
+        for _ in 0..100 {
+            let delay = policy.jittered_delay(u32::max_value(), &mut rng);
+            assert!(delay <= longest);
+            let _ = Instant::now() + delay;
+        }
+    }
+
+    #[test]
+    fn backoff_without_jitter_is_exact() {
+        let policy = BackoffPolicy::new(Duration::from_secs(3), Duration::from_secs(60), 3.0, 0.0);
+        let mut rng = StdRng::from_seed([7; 32]);
+
+        assert_eq!(Duration::from_secs(27), policy.jittered_delay(2, &mut rng));
+    }
+
+    #[test]
+    fn backoff_deserializes_with_defaults() {
+        let settings: WatchdogSettings =
+            serde_json::from_str(r#"{"backoff": {"initial": "500ms", "max": "2m"}}"#).unwrap();
+
+        let expected = BackoffPolicy::new(
+            Duration::from_millis(500),
+            Duration::from_secs(120),
+            default_backoff_multiplier(),
+            default_backoff_jitter(),
+        );
+        assert_eq!(&expected, settings.backoff());
+    }
+
+    #[test]
+    fn backoff_after_oom_kill_is_optional() {
+        let settings: WatchdogSettings = serde_json::from_str("{}").unwrap();
+        assert_eq!(None, settings.backoff().oom_killed());
+
+        let settings: WatchdogSettings =
+            serde_json::from_str(r#"{"backoff": {"oom_killed": "2m"}}"#).unwrap();
+        assert_eq!(
+            Some(Duration::from_secs(120)),
+            settings.backoff().oom_killed()
+        );
+    }
+
+    #[test]
+    fn unhealthy_restart_threshold_is_optional() {
+        let settings: WatchdogSettings = serde_json::from_str("{}").unwrap();
+        assert_eq!(None, settings.unhealthy_restart_threshold());
+
+        let settings: WatchdogSettings =
+            serde_json::from_str(r#"{"unhealthy_restart_threshold": "5m"}"#).unwrap();
+        assert_eq!(
+            Some(Duration::from_secs(300)),
+            settings.unhealthy_restart_threshold()
+        );
+    }
+
+    #[test]
+    fn poll_interval_defaults_to_a_minute() {
+        let settings: WatchdogSettings = serde_json::from_str("{}").unwrap();
+        assert_eq!(Duration::from_secs(60), settings.poll_interval());
+        assert_eq!(
+            Duration::from_secs(60),
+            WatchdogSettings::default().poll_interval()
+        );
+
+        let settings: WatchdogSettings =
+            serde_json::from_str(r#"{"poll_interval": "15m"}"#).unwrap();
+        assert_eq!(Duration::from_secs(900), settings.poll_interval());
+
+        assert!(serde_json::from_str::<WatchdogSettings>(r#"{"poll_interval": "0s"}"#).is_err());
+    }
+
+    #[test]
+    fn exit_on_stall_is_opt_in() {
+        let settings: WatchdogSettings = serde_json::from_str("{}").unwrap();
+        assert_eq!(Duration::from_secs(1800), settings.stall_timeout());
+        assert!(!settings.exit_on_stall());
+
+        let settings: WatchdogSettings =
+            serde_json::from_str(r#"{"stall_timeout": "1h", "exit_on_stall": true}"#).unwrap();
+        assert_eq!(Duration::from_secs(3600), settings.stall_timeout());
+        assert!(settings.exit_on_stall());
+    }
+
+    #[test]
+    fn pause_on_error_is_opt_in() {
+        let settings: WatchdogSettings = serde_json::from_str("{}").unwrap();
+        assert_eq!(None, settings.pause_on_error());
+
+        let settings: WatchdogSettings = serde_json::from_str(r#"{"pause_on_error": 5}"#).unwrap();
+        assert_eq!(Some(5), settings.pause_on_error());
+    }
+
+    #[test]
+    fn audit_sink_is_configurable() {
+        let settings: AuditSettings = serde_json::from_str(r#"{"sink": "none"}"#).unwrap();
+        assert_eq!(AuditSettings::None, settings);
+
+        let settings: AuditSettings =
+            serde_json::from_str(r#"{"sink": "file", "path": "/var/log/iotedge/audit.log"}"#)
+                .unwrap();
+        assert_eq!(
+            AuditSettings::File {
+                path: PathBuf::from("/var/log/iotedge/audit.log")
+            },
+            settings
+        );
+
+        let settings: AuditSettings = serde_json::from_str(r#"{"sink": "syslog"}"#).unwrap();
+        assert_eq!(
+            AuditSettings::Syslog {
+                facility: SyslogFacility::Authpriv
+            },
+            settings
+        );
+
+        let settings: AuditSettings =
+            serde_json::from_str(r#"{"sink": "syslog", "facility": "local3"}"#).unwrap();
+        assert_eq!(
+            AuditSettings::Syslog {
+                facility: SyslogFacility::Local3
+            },
+            settings
+        );
+
+        assert!(serde_json::from_str::<AuditSettings>(r#"{"sink": "file"}"#).is_err());
+        assert!(
+            serde_json::from_str::<AuditSettings>(r#"{"sink": "syslog", "facility": "kern"}"#)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn log_forwarding_sink_is_configurable() {
+        assert_eq!(
+            LogForwardingSettings::None,
+            LogForwardingSettings::default()
+        );
+
+        let settings: LogForwardingSettings =
+            serde_json::from_str(r#"{"sink": "journald"}"#).unwrap();
+        assert_eq!(
+            LogForwardingSettings::Journald {
+                poll_interval: Duration::from_secs(5),
+            },
+            settings
+        );
+
+        let settings: LogForwardingSettings =
+            serde_json::from_str(r#"{"sink": "journald", "poll_interval": "30s"}"#).unwrap();
+        assert_eq!(
+            LogForwardingSettings::Journald {
+                poll_interval: Duration::from_secs(30),
+            },
+            settings
+        );
+
+        assert!(serde_json::from_str::<LogForwardingSettings>(r#"{"sink": "json-file"}"#).is_err());
+    }
+
+    #[test]
+    fn logs_max_line_length_is_configurable() {
+        assert_eq!(
+            DEFAULT_MAX_LOG_LINE_LENGTH,
+            LogsSettings::default().max_line_length()
+        );
+
+        let settings: LogsSettings = serde_json::from_str("{}").unwrap();
+        assert_eq!(LogsSettings::default(), settings);
+
+        let settings: LogsSettings = serde_json::from_str(r#"{"max_line_length": 4096}"#).unwrap();
+        assert_eq!(LogsSettings::new().with_max_line_length(4096), settings);
+    }
+
+    #[test]
+    fn webhook_has_defaults() {
+        let settings: WebhookSettings =
+            serde_json::from_str(r#"{"url": "https://hooks.example.com/edge"}"#).unwrap();
+        assert_eq!(
+            WebhookSettings::new(Url::parse("https://hooks.example.com/edge").unwrap()),
+            settings
+        );
+        assert!(settings.reports("tempSensor"));
+
+        let settings: WebhookSettings = serde_json::from_str(
+            r#"{
+                "url": "https://hooks.example.com/edge",
+                "modules": ["edgeHub"],
+                "restart_threshold": 3,
+                "timeout": "5s",
+                "retries": 0,
+                "backoff": "500ms"
+            }"#,
+        )
+        .unwrap();
+        assert!(settings.reports("edgeHub"));
+        assert!(!settings.reports("tempSensor"));
+        assert_eq!(3, settings.restart_threshold());
+        assert_eq!(Duration::from_secs(5), settings.timeout());
+        assert_eq!(0, settings.retries());
+        assert_eq!(Duration::from_millis(500), settings.backoff());
+
+        assert!(serde_json::from_str::<WebhookSettings>(r#"{"url": "not a url"}"#).is_err());
+    }
+
+    #[test]
+    fn socket_ownership_mode_is_octal() {
+        let socket: SocketOwnership =
+            serde_json::from_str(r#"{"group": "iotedge", "mode": "0660"}"#).unwrap();
+        assert_eq!(
+            SocketOwnership::new()
+                .with_group("iotedge".to_string())
+                .with_mode(0o660),
+            socket
+        );
+        assert!(socket.repair());
+        assert_eq!(
+            r#"{"group":"iotedge","mode":"0660"}"#,
+            serde_json::to_string(&socket).unwrap()
+        );
+
+        let socket: SocketOwnership =
+            serde_json::from_str(r#"{"mode": "0o600", "repair": false}"#).unwrap();
+        assert_eq!(Some(0o600), socket.mode());
+        assert!(!socket.repair());
+
+        for mode in &["660x", "0680", "17777"] {
+            let json = format!(r#"{{"mode": {:?}}}"#, mode);
+            assert!(serde_json::from_str::<SocketOwnership>(&json).is_err());
+        }
+    }
 
     #[test]
     fn test_convert_to_path() {
@@ -0,0 +1,243 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Upgrades the config.yaml of an older release to the shape that the
+//! settings are deserialized from.
+//!
+//! The config has a top-level `schema_version`, which is `0` if it isn't set
+//! since configs from before it was introduced don't have one. Each migration
+//! upgrades the config from one schema version to the next, so a config is
+//! upgraded by running the migrations from its version on in order.
+
+use std::convert::TryFrom;
+
+use serde_json::{json, Map, Value};
+
+use crate::error::{Error, ErrorKind, Result};
+
+/// The schema version of the configs that this release writes.
+pub const SETTINGS_SCHEMA_VERSION: u32 = 1;
+
+const SCHEMA_VERSION_KEY: &str = "schema_version";
+
+#[derive(Debug)]
+pub struct SettingsMigration {
+    from: u32,
+    description: &'static str,
+    migrate: fn(&mut Map<String, Value>),
+}
+
+impl SettingsMigration {
+    /// The schema version that the migration upgrades from, to the next one.
+    pub fn from_version(&self) -> u32 {
+        self.from
+    }
+
+    pub fn description(&self) -> &'static str {
+        self.description
+    }
+}
+
+/// The migration from schema version `n` is at index `n`.
+const MIGRATIONS: &[SettingsMigration] = &[SettingsMigration {
+    from: 0,
+    description: "moved provisioning.device_connection_string into provisioning.authentication and provisioning.registration_id into provisioning.attestation",
+    migrate: nest_provisioning_credentials,
+}];
+
+/// Upgrades `settings` to `SETTINGS_SCHEMA_VERSION` and returns the migrations
+/// that were run, which is empty if it was at that version already. A config
+/// from a newer release fails, since it can't be known what changed.
+pub fn migrate_settings(
+    settings: &mut Map<String, Value>,
+) -> Result<Vec<&'static SettingsMigration>> {
+    let version = schema_version(settings)?;
+    if version > SETTINGS_SCHEMA_VERSION {
+        return Err(Error::from(ErrorKind::UnsupportedSettingsSchemaVersion(
+            version,
+            SETTINGS_SCHEMA_VERSION,
+        )));
+    }
+
+    let mut migrations = vec![];
+    for migration in &MIGRATIONS[version as usize..] {
+        (migration.migrate)(settings);
+        migrations.push(migration);
+    }
+    settings.insert(
+        SCHEMA_VERSION_KEY.to_string(),
+        Value::from(SETTINGS_SCHEMA_VERSION),
+    );
+    Ok(migrations)
+}
+
+fn schema_version(settings: &Map<String, Value>) -> Result<u32> {
+    let version = match settings.get(SCHEMA_VERSION_KEY) {
+        Some(version) => version,
+        None => return Ok(0),
+    };
+
+    // Environment variables and quoted YAML values are strings
+    let parsed = match version {
+        Value::Number(n) => n.as_u64(),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    };
+    parsed
+        .and_then(|v| u32::try_from(v).ok())
+        .ok_or_else(|| Error::from(ErrorKind::InvalidSettingsSchemaVersion(version.to_string())))
+}
+
+// Schema version 0 had the device connection string and the TPM
+// registration id directly under `provisioning`.
+fn nest_provisioning_credentials(settings: &mut Map<String, Value>) {
+    let provisioning = match settings
+        .get_mut("provisioning")
+        .and_then(Value::as_object_mut)
+    {
+        Some(provisioning) => provisioning,
+        None => return,
+    };
+
+    // A config that has both is left for the settings to reject.
+    if !provisioning.contains_key("authentication") {
+        if let Some(connection_string) = provisioning.remove("device_connection_string") {
+            provisioning.insert(
+                "authentication".to_string(),
+                json!({
+                    "method": "device_connection_string",
+                    "device_connection_string": connection_string,
+                }),
+            );
+        }
+    }
+    if !provisioning.contains_key("attestation") {
+        if let Some(registration_id) = provisioning.remove("registration_id") {
+            provisioning.insert(
+                "attestation".to_string(),
+                json!({
+                    "method": "tpm",
+                    "registration_id": registration_id,
+                }),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(value: Value) -> Map<String, Value> {
+        match value {
+            Value::Object(settings) => settings,
+            _ => panic!("settings must be a mapping"),
+        }
+    }
+
+    #[test]
+    fn migrations_are_in_order() {
+        assert_eq!(SETTINGS_SCHEMA_VERSION as usize, MIGRATIONS.len());
+        for (version, migration) in (0..).zip(MIGRATIONS) {
+            assert_eq!(version, migration.from_version());
+        }
+    }
+
+    #[test]
+    fn unversioned_config_is_migrated_to_current_shape() {
+        let mut manual = settings(json!({
+            "provisioning": {
+                "source": "manual",
+                "device_connection_string": "HostName=hub;DeviceId=d1;SharedAccessKey=a2V5",
+            },
+            "hostname": "device",
+        }));
+
+        let migrations = migrate_settings(&mut manual).unwrap();
+
+        assert_eq!(
+            vec![0],
+            migrations
+                .iter()
+                .map(|m| m.from_version())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            settings(json!({
+                "schema_version": 1,
+                "provisioning": {
+                    "source": "manual",
+                    "authentication": {
+                        "method": "device_connection_string",
+                        "device_connection_string": "HostName=hub;DeviceId=d1;SharedAccessKey=a2V5",
+                    },
+                },
+                "hostname": "device",
+            })),
+            manual
+        );
+
+        let mut dps = settings(json!({
+            "provisioning": {
+                "source": "dps",
+                "scope_id": "scope",
+                "registration_id": "reg1",
+            },
+        }));
+        migrate_settings(&mut dps).unwrap();
+        assert_eq!(
+            json!({ "method": "tpm", "registration_id": "reg1" }),
+            dps["provisioning"]["attestation"]
+        );
+        assert!(dps["provisioning"].get("registration_id").is_none());
+    }
+
+    #[test]
+    fn config_with_both_shapes_is_left_alone() {
+        let mut both = settings(json!({
+            "provisioning": {
+                "source": "manual",
+                "device_connection_string": "HostName=hub;DeviceId=d1;SharedAccessKey=a2V5",
+                "authentication": { "method": "x509" },
+            },
+        }));
+        let provisioning = both["provisioning"].clone();
+
+        migrate_settings(&mut both).unwrap();
+
+        assert_eq!(provisioning, both["provisioning"]);
+    }
+
+    #[test]
+    fn current_config_is_not_migrated() {
+        for version in &[json!(1), json!("1")] {
+            let mut current = settings(json!({
+                "schema_version": version,
+                "provisioning": { "source": "manual", "device_connection_string": "kept" },
+            }));
+
+            assert!(migrate_settings(&mut current).unwrap().is_empty());
+            assert_eq!("kept", current["provisioning"]["device_connection_string"]);
+        }
+    }
+
+    #[test]
+    fn future_or_invalid_schema_version_fails() {
+        let mut future = settings(json!({ "schema_version": 2 }));
+        match migrate_settings(&mut future).unwrap_err().kind() {
+            ErrorKind::UnsupportedSettingsSchemaVersion(2, 1) => (),
+            kind => panic!(
+                "Expected `UnsupportedSettingsSchemaVersion` error but got {:?}",
+                kind
+            ),
+        }
+
+        let mut invalid = settings(json!({ "schema_version": "one" }));
+        match migrate_settings(&mut invalid).unwrap_err().kind() {
+            ErrorKind::InvalidSettingsSchemaVersion(_) => (),
+            kind => panic!(
+                "Expected `InvalidSettingsSchemaVersion` error but got {:?}",
+                kind
+            ),
+        }
+    }
+}
@@ -0,0 +1,261 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Quiesces the modules while the daemon's package is upgraded, so that they
+//! are neither orphaned nor supervised twice while the daemon restarts.
+//!
+//! Preparing for an upgrade saves which modules are running and stops the
+//! watchdog from supervising the edge runtime module, which also leaves it
+//! running when the daemon shuts down. The daemon that starts after the
+//! upgrade resumes by adopting the containers that are still running as they
+//! were, instead of recreating them.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use failure::{Fail, ResultExt};
+use futures::future::{self, Either};
+use futures::{Future, Stream};
+use log::{info, warn};
+
+use crate::error::{Error, ErrorKind};
+use crate::module::{Module, ModuleRuntime, ModuleRuntimeState, ModuleStatus};
+
+/// A module that was running when the upgrade was prepared.
+#[derive(Clone, Debug, serde_derive::Deserialize, PartialEq, serde_derive::Serialize)]
+pub struct UpgradeModule {
+    name: String,
+    #[serde(default)]
+    labels: HashMap<String, String>,
+}
+
+impl UpgradeModule {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn labels(&self) -> &HashMap<String, String> {
+        &self.labels
+    }
+
+    // A container is this module if it has the same name and labels and is
+    // still running. One with the same name but other labels was created
+    // since, and is left to the edge runtime to reconcile.
+    fn is_adoptable(&self, name: &str, state: &ModuleRuntimeState) -> bool {
+        self.name == name
+            && self.labels == *state.labels()
+            && *state.status() == ModuleStatus::Running
+    }
+}
+
+/// The modules that were running when the upgrade was prepared.
+#[derive(Clone, Debug, Default, serde_derive::Deserialize, PartialEq, serde_derive::Serialize)]
+pub struct UpgradeState {
+    modules: Vec<UpgradeModule>,
+}
+
+impl UpgradeState {
+    pub(crate) fn from_modules<M>(modules: &[(M, ModuleRuntimeState)]) -> Self
+    where
+        M: Module,
+    {
+        let modules = modules
+            .iter()
+            .filter(|(_, state)| *state.status() == ModuleStatus::Running)
+            .map(|(module, state)| UpgradeModule {
+                name: module.name().to_string(),
+                labels: state.labels().clone(),
+            })
+            .collect();
+        UpgradeState { modules }
+    }
+
+    pub fn modules(&self) -> &[UpgradeModule] {
+        &self.modules
+    }
+
+    /// The names of the `running` modules that are the ones that were saved.
+    /// Adopting them doesn't change them.
+    pub fn adopt<M>(&self, running: &[(M, ModuleRuntimeState)]) -> Vec<String>
+    where
+        M: Module,
+    {
+        let mut adopted = vec![];
+        for saved in &self.modules {
+            if running
+                .iter()
+                .any(|(module, state)| saved.is_adoptable(module.name(), state))
+            {
+                adopted.push(saved.name().to_string());
+            } else {
+                warn!(
+                    "Module {} is no longer running as it was before the upgrade, leaving it to the edge runtime",
+                    saved.name()
+                );
+            }
+        }
+        adopted
+    }
+}
+
+/// Whether the daemon is quiesced for an upgrade, shared by the management
+/// API that prepares and resumes from it and the watchdog that honors it.
+#[derive(Clone, Debug)]
+pub struct UpgradeGuard {
+    state_path: Arc<PathBuf>,
+    quiesced: Arc<AtomicBool>,
+}
+
+impl UpgradeGuard {
+    /// The state of the modules is saved at `state_path`, which must survive
+    /// the upgrade.
+    pub fn new<P>(state_path: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        UpgradeGuard {
+            state_path: Arc::new(state_path.into()),
+            quiesced: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn is_quiesced(&self) -> bool {
+        self.quiesced.load(Ordering::SeqCst)
+    }
+
+    /// Whether an upgrade was prepared that hasn't been resumed from yet, such
+    /// as when the daemon starts after it was upgraded.
+    pub fn is_pending(&self) -> bool {
+        self.state_path.exists()
+    }
+
+    /// Quiesces the daemon and saves the modules that are running.
+    pub fn prepare<M>(&self, runtime: &M) -> impl Future<Item = UpgradeState, Error = Error>
+    where
+        M: ModuleRuntime,
+    {
+        info!("Preparing modules for the upgrade...");
+        self.quiesced.store(true, Ordering::SeqCst);
+
+        let guard = self.clone();
+        let quiesced = self.quiesced.clone();
+        runtime
+            .list_with_details()
+            .collect()
+            .map_err(|err| Error::from(err.context(ErrorKind::PrepareUpgrade)))
+            .and_then(move |modules| {
+                let state = UpgradeState::from_modules(&modules);
+                guard.save(&state)?;
+                info!(
+                    "Saved {} running modules, which are left running for the upgrade",
+                    state.modules.len()
+                );
+                Ok(state)
+            })
+            .map_err(move |err| {
+                quiesced.store(false, Ordering::SeqCst);
+                err
+            })
+    }
+
+    /// Adopts the modules that are still running as they were when the
+    /// upgrade was prepared, and un-quiesces the daemon. Returns the names of
+    /// the adopted modules, which is empty if no upgrade was prepared.
+    pub fn resume<M>(&self, runtime: &M) -> impl Future<Item = Vec<String>, Error = Error>
+    where
+        M: ModuleRuntime,
+    {
+        let state = match self.load() {
+            Ok(Some(state)) => state,
+            Ok(None) => {
+                self.quiesced.store(false, Ordering::SeqCst);
+                return Either::A(future::ok(vec![]));
+            }
+            Err(err) => return Either::A(future::err(err)),
+        };
+
+        info!("Resuming modules after the upgrade...");
+        let guard = self.clone();
+        Either::B(
+            runtime
+                .list_with_details()
+                .collect()
+                .map_err(|err| Error::from(err.context(ErrorKind::ResumeFromUpgrade)))
+                .and_then(move |running| {
+                    let adopted = state.adopt(&running);
+                    if let Err(err) = fs::remove_file(&*guard.state_path) {
+                        if err.kind() != io::ErrorKind::NotFound {
+                            return Err(Error::from(err.context(ErrorKind::ResumeFromUpgrade)));
+                        }
+                    }
+                    guard.quiesced.store(false, Ordering::SeqCst);
+                    info!("Adopted {} modules after the upgrade", adopted.len());
+                    Ok(adopted)
+                }),
+        )
+    }
+
+    fn save(&self, state: &UpgradeState) -> Result<(), Error> {
+        let contents = serde_json::to_string(state).context(ErrorKind::PrepareUpgrade)?;
+        fs::write(&*self.state_path, contents).context(ErrorKind::PrepareUpgrade)?;
+        Ok(())
+    }
+
+    fn load(&self) -> Result<Option<UpgradeState>, Error> {
+        let contents = match fs::read_to_string(&*self.state_path) {
+            Ok(contents) => contents,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(Error::from(err.context(ErrorKind::ResumeFromUpgrade))),
+        };
+        let state = serde_json::from_str(&contents).context(ErrorKind::ResumeFromUpgrade)?;
+        Ok(Some(state))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| ((*k).to_string(), (*v).to_string()))
+            .collect()
+    }
+
+    fn saved(name: &str, pairs: &[(&str, &str)]) -> UpgradeModule {
+        UpgradeModule {
+            name: name.to_string(),
+            labels: labels(pairs),
+        }
+    }
+
+    #[test]
+    fn adoptable_only_with_same_name_labels_and_running() {
+        let module = saved("tempSensor", &[("group", "sensors")]);
+        let running = ModuleRuntimeState::default()
+            .with_status(ModuleStatus::Running)
+            .with_labels(labels(&[("group", "sensors")]));
+
+        assert!(module.is_adoptable("tempSensor", &running));
+        assert!(!module.is_adoptable("edgeHub", &running));
+        assert!(!module.is_adoptable(
+            "tempSensor",
+            &running
+                .clone()
+                .with_labels(labels(&[("group", "actuators")]))
+        ));
+        assert!(!module.is_adoptable("tempSensor", &running.with_status(ModuleStatus::Stopped)));
+    }
+
+    #[test]
+    fn nothing_to_resume_without_prepared_upgrade() {
+        let guard = UpgradeGuard::new("/nonexistent/upgrade_state");
+
+        assert!(!guard.is_pending());
+        assert_eq!(None, guard.load().unwrap());
+    }
+}
@@ -0,0 +1,329 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+
+use crate::settings::UpstreamSettings;
+
+/// Tracks which of the IoT Hubs that the device can connect to is active.
+///
+/// The hub that the device was provisioned with is the primary one, followed
+/// by the failover hubs of the `UpstreamSettings` in order. After
+/// `failure_threshold` consecutive connection failures within
+/// `failure_window` the next hub becomes active, and after the last one it's
+/// the primary again. A failover hub is only used for `primary_retry_interval`
+/// before the primary is tried again, so that the device goes back to it once
+/// it can be reached.
+///
+/// The device is only reported unreachable once it couldn't reach IoT Hub for
+/// `offline_grace_period` on end, so that a blip in the network doesn't flip
+/// it to disconnected and back, and reachable again as soon as it can.
+///
+/// Clones share the same state.
+#[derive(Clone, Debug)]
+pub struct UpstreamEndpoints {
+    hostnames: Arc<Vec<String>>,
+    failure_threshold: u32,
+    failure_window: Duration,
+    primary_retry_interval: Duration,
+    offline_grace_period: Duration,
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Debug)]
+struct State {
+    active: usize,
+    failures: u32,
+    first_failure: Option<Instant>,
+    failed_over_at: Option<Instant>,
+    // When the first of the failures since the last success was.
+    unreachable_since: Option<Instant>,
+}
+
+impl UpstreamEndpoints {
+    pub fn new(primary: String, settings: &UpstreamSettings) -> Self {
+        let mut hostnames = vec![primary];
+        for hostname in settings.failover_hostnames() {
+            if !hostnames.contains(hostname) {
+                hostnames.push(hostname.clone());
+            }
+        }
+
+        UpstreamEndpoints {
+            hostnames: Arc::new(hostnames),
+            failure_threshold: settings.failure_threshold().max(1),
+            failure_window: settings.failure_window(),
+            primary_retry_interval: settings.primary_retry_interval(),
+            offline_grace_period: settings.offline_grace_period(),
+            state: Arc::new(Mutex::new(State {
+                active: 0,
+                failures: 0,
+                first_failure: None,
+                failed_over_at: None,
+                unreachable_since: None,
+            })),
+        }
+    }
+
+    pub fn primary(&self) -> &str {
+        &self.hostnames[0]
+    }
+
+    /// The primary hub followed by the failover hubs.
+    pub fn hostnames(&self) -> &[String] {
+        &self.hostnames
+    }
+
+    /// The hostname of the hub that requests should be sent to.
+    pub fn active(&self) -> String {
+        self.active_at(Instant::now())
+    }
+
+    pub fn active_at(&self, now: Instant) -> String {
+        let mut state = self.state.lock().expect("upstream endpoints lock poisoned");
+        if let Some(failed_over_at) = state.failed_over_at {
+            if now.duration_since(failed_over_at) >= self.primary_retry_interval {
+                info!(
+                    "Trying IoT Hub {} again after using {} for {:?}",
+                    self.hostnames[0], self.hostnames[state.active], self.primary_retry_interval,
+                );
+                self.switch_to(&mut state, 0, now);
+            }
+        }
+        self.hostnames[state.active].clone()
+    }
+
+    /// Whether IoT Hub was reached by the last request to it, or by one within
+    /// the offline grace period. It is assumed until a request has been made.
+    pub fn reachable(&self) -> bool {
+        self.reachable_at(Instant::now())
+    }
+
+    pub fn reachable_at(&self, now: Instant) -> bool {
+        let state = self.state.lock().expect("upstream endpoints lock poisoned");
+        state
+            .unreachable_since
+            .map_or(true, |since| now < since + self.offline_grace_period)
+    }
+
+    /// A request reached the active hub, whether or not it succeeded.
+    pub fn report_success(&self) {
+        let mut state = self.state.lock().expect("upstream endpoints lock poisoned");
+        if let Some(since) = state.unreachable_since.take() {
+            if Instant::now() >= since + self.offline_grace_period {
+                info!(
+                    "IoT Hub {} can be reached again",
+                    self.hostnames[state.active]
+                );
+            }
+        }
+        state.failures = 0;
+        state.first_failure = None;
+    }
+
+    /// The active hub couldn't be connected to.
+    pub fn report_failure(&self) {
+        self.report_failure_at(Instant::now())
+    }
+
+    pub fn report_failure_at(&self, now: Instant) {
+        let mut state = self.state.lock().expect("upstream endpoints lock poisoned");
+        if state.unreachable_since.is_none() {
+            state.unreachable_since = Some(now);
+        }
+
+        // A failure that follows the first by more than the window starts a new count.
+        match state.first_failure {
+            Some(first_failure) if now.duration_since(first_failure) <= self.failure_window => {
+                state.failures += 1
+            }
+            _ => {
+                state.failures = 1;
+                state.first_failure = Some(now);
+            }
+        }
+
+        if state.failures >= self.failure_threshold && self.hostnames.len() > 1 {
+            let next = (state.active + 1) % self.hostnames.len();
+            warn!(
+                "Could not connect to IoT Hub {} {} times in a row, failing over to {}",
+                self.hostnames[state.active], state.failures, self.hostnames[next],
+            );
+            self.switch_to(&mut state, next, now);
+        }
+    }
+
+    fn switch_to(&self, state: &mut State, active: usize, now: Instant) {
+        state.active = active;
+        state.failures = 0;
+        state.first_failure = None;
+        state.failed_over_at = if active == 0 { None } else { Some(now) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> UpstreamSettings {
+        UpstreamSettings::new(
+            vec!["secondary.azure-devices.net".to_string()],
+            3,
+            Duration::from_secs(60),
+            Duration::from_secs(600),
+        )
+    }
+
+    fn endpoints() -> UpstreamEndpoints {
+        UpstreamEndpoints::new("primary.azure-devices.net".to_string(), &settings())
+    }
+
+    #[test]
+    fn fails_over_after_threshold() {
+        let endpoints = endpoints();
+        let start = Instant::now();
+
+        endpoints.report_failure_at(start);
+        endpoints.report_failure_at(start + Duration::from_secs(10));
+        assert_eq!("primary.azure-devices.net", endpoints.active_at(start));
+
+        endpoints.report_failure_at(start + Duration::from_secs(20));
+        assert_eq!(
+            "secondary.azure-devices.net",
+            endpoints.active_at(start + Duration::from_secs(20))
+        );
+
+        // Clones share the active hub.
+        assert_eq!(
+            "secondary.azure-devices.net",
+            endpoints.clone().active_at(start + Duration::from_secs(20))
+        );
+    }
+
+    #[test]
+    fn failures_outside_window_or_after_success_do_not_fail_over() {
+        let endpoints = endpoints();
+        let start = Instant::now();
+
+        endpoints.report_failure_at(start);
+        endpoints.report_failure_at(start + Duration::from_secs(30));
+        endpoints.report_failure_at(start + Duration::from_secs(61));
+        assert_eq!("primary.azure-devices.net", endpoints.active_at(start));
+
+        endpoints.report_failure_at(start + Duration::from_secs(62));
+        endpoints.report_success();
+        endpoints.report_failure_at(start + Duration::from_secs(63));
+        assert_eq!("primary.azure-devices.net", endpoints.active_at(start));
+    }
+
+    #[test]
+    fn reachable_follows_last_request() {
+        let endpoints = endpoints();
+        assert!(endpoints.reachable());
+
+        endpoints.report_failure();
+        assert!(!endpoints.clone().reachable());
+
+        endpoints.report_success();
+        assert!(endpoints.reachable());
+    }
+
+    #[test]
+    fn blip_shorter_than_grace_period_stays_reachable() {
+        let endpoints = UpstreamEndpoints::new(
+            "primary.azure-devices.net".to_string(),
+            &settings().with_offline_grace_period(Duration::from_secs(30)),
+        );
+        let start = Instant::now();
+
+        endpoints.report_failure_at(start);
+        endpoints.report_failure_at(start + Duration::from_secs(10));
+        assert!(endpoints.reachable_at(start + Duration::from_secs(29)));
+
+        endpoints.report_success();
+        assert!(endpoints.reachable_at(start + Duration::from_secs(31)));
+
+        // The grace period of the next outage starts from its own first failure.
+        endpoints.report_failure_at(start + Duration::from_secs(40));
+        assert!(endpoints.reachable_at(start + Duration::from_secs(69)));
+    }
+
+    #[test]
+    fn sustained_outage_is_unreachable_after_grace_period() {
+        let endpoints = UpstreamEndpoints::new(
+            "primary.azure-devices.net".to_string(),
+            &settings().with_offline_grace_period(Duration::from_secs(30)),
+        );
+        let start = Instant::now();
+
+        for secs in &[0, 10, 20, 30] {
+            endpoints.report_failure_at(start + Duration::from_secs(*secs));
+        }
+        assert!(endpoints.reachable_at(start + Duration::from_secs(29)));
+        assert!(!endpoints.reachable_at(start + Duration::from_secs(30)));
+        assert!(!endpoints
+            .clone()
+            .reachable_at(start + Duration::from_secs(300)));
+
+        // Recovery is reported straight away.
+        endpoints.report_success();
+        assert!(endpoints.reachable_at(start + Duration::from_secs(300)));
+    }
+
+    #[test]
+    fn recovers_back_to_primary() {
+        let endpoints = endpoints();
+        let start = Instant::now();
+
+        for secs in 0..3 {
+            endpoints.report_failure_at(start + Duration::from_secs(secs));
+        }
+        let failed_over_at = start + Duration::from_secs(2);
+        assert_eq!(
+            "secondary.azure-devices.net",
+            endpoints.active_at(failed_over_at + Duration::from_secs(599))
+        );
+        assert_eq!(
+            "primary.azure-devices.net",
+            endpoints.active_at(failed_over_at + Duration::from_secs(600))
+        );
+
+        // The primary is used from then on, until it fails again.
+        assert_eq!(
+            "primary.azure-devices.net",
+            endpoints.active_at(failed_over_at + Duration::from_secs(6000))
+        );
+    }
+
+    #[test]
+    fn last_failover_hub_fails_back_to_primary() {
+        let endpoints = endpoints();
+        let start = Instant::now();
+
+        for secs in 0..6 {
+            endpoints.report_failure_at(start + Duration::from_secs(secs));
+        }
+
+        assert_eq!(
+            "primary.azure-devices.net",
+            endpoints.active_at(start + Duration::from_secs(6))
+        );
+    }
+
+    #[test]
+    fn without_failover_hubs_primary_stays_active() {
+        let endpoints = UpstreamEndpoints::new(
+            "primary.azure-devices.net".to_string(),
+            &UpstreamSettings::default(),
+        );
+        let start = Instant::now();
+
+        for secs in 0..10 {
+            endpoints.report_failure_at(start + Duration::from_secs(secs));
+        }
+
+        assert_eq!("primary.azure-devices.net", endpoints.active_at(start));
+    }
+}
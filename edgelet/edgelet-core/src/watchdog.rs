@@ -1,55 +1,297 @@
 // Copyright (c) Microsoft. All rights reserved.
 
 use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
 use failure::Fail;
-use futures::future::{self, Either, FutureResult};
+use futures::future::{self, Either, FutureResult, Loop};
+use futures::sync::mpsc::UnboundedReceiver;
 use futures::Future;
 use log::{info, warn, Level};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use tokio::prelude::*;
-use tokio::timer::Interval;
+use tokio::timer::Delay;
 
 use edgelet_utils::log_failure;
 
 use crate::error::{Error, ErrorKind};
+use crate::heartbeat::Heartbeat;
 use crate::identity::{Identity, IdentityManager, IdentitySpec};
+use crate::maintenance::MaintenanceWindows;
 use crate::module::{
-    ImagePullPolicy, Module, ModuleRegistry, ModuleRuntime, ModuleRuntimeErrorReason, ModuleSpec,
-    ModuleStatus,
+    ExitReason, HealthState, ImagePullPolicy, Module, ModuleRuntime, ModuleRuntimeErrorReason,
+    ModuleRuntimeState, ModuleSpec, ModuleStatus, RestartPolicy,
 };
-use crate::settings::RetryLimit;
+use crate::module_set::{pull_image, stop_and_remove, ModuleSet};
+use crate::module_set_store::ModuleSetStore;
+use crate::settings::{BackoffPolicy, RetryLimit};
+use crate::upgrade::UpgradeGuard;
 
 // Time to allow EdgeAgent to gracefully shutdown (including stopping all modules, and updating reported properties)
+// unless its spec has a stop timeout
 const EDGE_RUNTIME_STOP_TIME: Duration = Duration::from_secs(60);
 
 /// This variable holds the generation ID associated with the Edge Agent module.
 const MODULE_GENERATIONID: &str = "IOTEDGE_MODULEGENERATIONID";
 
-/// This is the frequency with which the watchdog checks for the status of the edge runtime module.
-const WATCHDOG_FREQUENCY_SECS: u64 = 60;
+/// This is the default frequency with which the watchdog checks for the status of the edge runtime module.
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(60);
 
-pub struct Watchdog<M, I> {
+type SpecUpdates<M> =
+    UnboundedReceiver<ModuleSpec<<<M as ModuleRuntime>::Module as Module>::Config>>;
+
+pub struct Watchdog<M, I>
+where
+    M: ModuleRuntime,
+{
     runtime: M,
     id_mgr: I,
     max_retries: RetryLimit,
+    backoff: BackoffPolicy,
+    poll_interval: Duration,
+    unhealthy_restart_threshold: Option<Duration>,
+    spec_updates: Option<SpecUpdates<M>>,
+    upgrade: Option<UpgradeGuard>,
+    pause: Option<WatchdogPause>,
+    pause_on_error: Option<u32>,
+    heartbeat: Option<Heartbeat>,
+    module_set_store: Option<ModuleSetStore>,
+    reconcile_timer: Option<ReconcileTimer>,
+    maintenance: Option<MaintenanceWindows>,
+}
+
+/// How long the watchdog takes to reconcile the edge runtime module, shared by
+/// the watchdog that times it and the metrics that report it. A reconcile is a
+/// check of the module, or an update of its spec. Only one runs at a time,
+/// so a slow container runtime shows up here rather than as reconciles piling
+/// up.
+#[derive(Clone, Debug, Default)]
+pub struct ReconcileTimer {
+    state: Arc<Mutex<ReconcileTime>>,
+}
+
+#[derive(Debug, Default)]
+struct ReconcileTime {
+    started: Option<Instant>,
+    last: Option<Duration>,
+}
+
+impl ReconcileTimer {
+    pub fn new() -> Self {
+        ReconcileTimer::default()
+    }
+
+    /// How long the reconcile that is running has taken so far, or how long
+    /// the last one took if none is running. `None` before the first one.
+    pub fn duration(&self) -> Option<Duration> {
+        let state = self.state.lock().expect("reconcile timer lock poisoned");
+        state
+            .started
+            .map(|started| started.elapsed())
+            .or(state.last)
+    }
+
+    fn start(&self) {
+        self.state
+            .lock()
+            .expect("reconcile timer lock poisoned")
+            .started = Some(Instant::now());
+    }
+
+    fn finish(&self) {
+        let mut state = self.state.lock().expect("reconcile timer lock poisoned");
+        if let Some(started) = state.started.take() {
+            state.last = Some(started.elapsed());
+        }
+    }
+}
+
+/// Whether the watchdog is paused, shared by the management API that pauses
+/// and resumes it and the watchdog that honors it. A pause ends by itself once
+/// its timeout has passed, so that a forgotten pause doesn't leave the edge
+/// runtime module unsupervised for good.
+///
+/// A module that kept failing can also be paused by itself, in which case it
+/// stays paused until it is resumed.
+#[derive(Clone, Debug, Default)]
+pub struct WatchdogPause {
+    until: Arc<Mutex<Option<Instant>>>,
+    failed: Arc<Mutex<HashSet<String>>>,
+}
+
+impl WatchdogPause {
+    pub fn new() -> Self {
+        WatchdogPause::default()
+    }
+
+    /// Pauses the watchdog for `timeout`, replacing any pause before.
+    pub fn pause(&self, timeout: Duration) {
+        info!("Pausing the watchdog for {} seconds", timeout.as_secs());
+        *self.until.lock().expect("watchdog pause lock poisoned") = Some(Instant::now() + timeout);
+    }
+
+    /// Resumes the watchdog. Returns whether it was paused.
+    pub fn resume(&self) -> bool {
+        let resumed = self
+            .until
+            .lock()
+            .expect("watchdog pause lock poisoned")
+            .take()
+            .map_or(false, |until| until > Instant::now());
+        if resumed {
+            info!("Resuming the watchdog");
+        }
+        resumed
+    }
+
+    /// How long until the watchdog resumes by itself, or `None` if it isn't
+    /// paused.
+    pub fn remaining(&self) -> Option<Duration> {
+        let until = *self.until.lock().expect("watchdog pause lock poisoned");
+        let now = Instant::now();
+        until.and_then(|until| if until > now { Some(until - now) } else { None })
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.remaining().is_some()
+    }
+
+    /// Stops retrying module `name` after it kept failing, until it is
+    /// resumed with `resume_module`.
+    pub fn pause_module(&self, name: &str) {
+        self.failed
+            .lock()
+            .expect("watchdog pause lock poisoned")
+            .insert(name.to_string());
+    }
+
+    /// Retries module `name` again. Returns whether it was paused.
+    pub fn resume_module(&self, name: &str) -> bool {
+        let resumed = self
+            .failed
+            .lock()
+            .expect("watchdog pause lock poisoned")
+            .remove(name);
+        if resumed {
+            info!("Resuming module {}", name);
+        }
+        resumed
+    }
+
+    pub fn is_module_paused(&self, name: &str) -> bool {
+        self.failed
+            .lock()
+            .expect("watchdog pause lock poisoned")
+            .contains(name)
+    }
 }
 
 impl<M, I> Watchdog<M, I>
 where
     M: 'static + ModuleRuntime + Clone,
     for<'r> &'r <M as ModuleRuntime>::Error: Into<ModuleRuntimeErrorReason>,
-    <M::Module as Module>::Config: Clone,
+    <M::Module as Module>::Config: Clone + Serialize,
     I: 'static + IdentityManager + Clone,
 {
-    pub fn new(runtime: M, id_mgr: I, max_retries: RetryLimit) -> Self {
+    pub fn new(runtime: M, id_mgr: I, max_retries: RetryLimit, backoff: BackoffPolicy) -> Self {
         Watchdog {
             runtime,
             id_mgr,
             max_retries,
+            backoff,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            unhealthy_restart_threshold: None,
+            spec_updates: None,
+            upgrade: None,
+            pause: None,
+            pause_on_error: None,
+            heartbeat: None,
+            module_set_store: None,
+            reconcile_timer: None,
+            maintenance: None,
         }
     }
 
+    /// Check the edge runtime module every `interval` while it is running.
+    /// Defaults to every minute.
+    pub fn with_poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Restart the edge runtime module once it has reported itself unhealthy
+    /// for longer than `threshold`. Disabled by default.
+    pub fn with_unhealthy_restart_threshold(mut self, threshold: Option<Duration>) -> Self {
+        self.unhealthy_restart_threshold = threshold;
+        self
+    }
+
+    /// Replaces the spec of the edge runtime module with each spec received
+    /// from `spec_updates`, such as when the config is reloaded. The module is
+    /// only recreated if its spec changed.
+    pub fn with_spec_updates(mut self, spec_updates: SpecUpdates<M>) -> Self {
+        self.spec_updates = Some(spec_updates);
+        self
+    }
+
+    /// Stops supervising the edge runtime module while `upgrade` is quiesced,
+    /// and leaves it running when shut down then.
+    pub fn with_upgrade_guard(mut self, upgrade: UpgradeGuard) -> Self {
+        self.upgrade = Some(upgrade);
+        self
+    }
+
+    /// Neither checks nor updates the edge runtime module while `pause` is
+    /// paused, leaving it as it is. Spec updates received meanwhile are
+    /// applied once the watchdog resumes.
+    pub fn with_pause(mut self, pause: WatchdogPause) -> Self {
+        self.pause = Some(pause);
+        self
+    }
+
+    /// Stops retrying the edge runtime module once it has failed to be
+    /// checked or started `after` times in a row, and pauses it in the pause
+    /// of `with_pause` until it is resumed there. This takes the place of
+    /// `max_retries` if it is lower. Off by default, and without a pause.
+    pub fn with_pause_on_error(mut self, after: Option<u32>) -> Self {
+        self.pause_on_error = after;
+        self
+    }
+
+    /// Beats `heartbeat` every time the edge runtime module is checked, or
+    /// would be if the watchdog weren't quiesced or paused.
+    pub fn with_heartbeat(mut self, heartbeat: Heartbeat) -> Self {
+        self.heartbeat = Some(heartbeat);
+        self
+    }
+
+    /// Saves the spec of the edge runtime module to `store` whenever it is
+    /// applied. When the watchdog starts, the module is taken to have been
+    /// created from the saved spec, so that the spec it's run with only
+    /// recreates the module if it differs.
+    pub fn with_module_set_store(mut self, store: ModuleSetStore) -> Self {
+        self.module_set_store = Some(store);
+        self
+    }
+
+    /// Times every reconcile of the edge runtime module in `timer`.
+    pub fn with_reconcile_timer(mut self, timer: ReconcileTimer) -> Self {
+        self.reconcile_timer = Some(timer);
+        self
+    }
+
+    /// Only updates the edge runtime module to a changed spec while one of
+    /// `maintenance` is open, holding the spec back until the next one opens
+    /// otherwise. Specs labeled as critical updates are applied right away.
+    /// Updates aren't held back by default.
+    pub fn with_maintenance_windows(mut self, maintenance: MaintenanceWindows) -> Self {
+        self.maintenance = Some(maintenance);
+        self
+    }
+
     // Start the edge runtime module (EdgeAgent). This also updates the identity of the module (module_id)
     // to make sure it is configured for the right authentication type (sas token)
     // spec.name = edgeAgent / module_id = $edgeAgent
@@ -61,15 +303,57 @@ where
     ) -> impl Future<Item = (), Error = Error>
     where
         F: Future<Item = (), Error = ()> + 'static,
+        <M::Module as Module>::Config: DeserializeOwned,
     {
         let runtime = self.runtime;
         let runtime_copy = runtime.clone();
         let name = spec.name().to_string();
+        let stop_timeout = spec.stop_timeout().unwrap_or(EDGE_RUNTIME_STOP_TIME);
         let id_mgr = self.id_mgr;
         let module_id = module_id.to_string();
         let max_retries = self.max_retries;
+        let backoff = self.backoff;
+        let poll_interval = self.poll_interval;
+        let unhealthy_restart_threshold = self.unhealthy_restart_threshold;
+        let spec_updates = self.spec_updates;
+        let upgrade = self.upgrade;
+        let shutdown_upgrade = upgrade.clone();
+        let pause = self.pause;
+        let pause_on_error = self.pause_on_error;
+        let heartbeat = self.heartbeat;
+        let module_set_store = self.module_set_store;
+        let reconcile_timer = self.reconcile_timer;
+        let maintenance = self.maintenance;
+        let applied = module_set_store
+            .as_ref()
+            .and_then(ModuleSetStore::load)
+            .and_then(|modules| {
+                modules
+                    .modules()
+                    .iter()
+                    .find(|module| module.name() == name)
+                    .cloned()
+            });
 
-        let watchdog = start_watchdog(runtime, id_mgr, spec, module_id, max_retries);
+        let watchdog = start_watchdog(
+            runtime,
+            id_mgr,
+            spec,
+            module_id,
+            max_retries,
+            backoff,
+            poll_interval,
+            unhealthy_restart_threshold,
+            spec_updates,
+            upgrade,
+            pause,
+            pause_on_error,
+            heartbeat,
+            module_set_store,
+            applied,
+            reconcile_timer,
+            maintenance,
+        );
 
         // Swallow any errors from shutdown_signal
         let shutdown_signal = shutdown_signal.then(|_| Ok(()));
@@ -80,15 +364,26 @@ where
         shutdown_signal
             .select(watchdog)
             .then(move |result| match result {
-                Ok(((), _)) => Ok(stop_runtime(&runtime_copy, &name)),
+                Ok(((), _)) if is_quiesced(shutdown_upgrade.as_ref()) => {
+                    info!(
+                        "Leaving edge runtime module {} running for the upgrade",
+                        name
+                    );
+                    Ok(Either::A(future::ok(())))
+                }
+                Ok(((), _)) => Ok(Either::B(stop_runtime(&runtime_copy, &name, stop_timeout))),
                 Err((err, _)) => Err(err),
             })
             .flatten()
     }
 }
 
-// Stop EdgeAgent
-fn stop_runtime<M>(runtime: &M, name: &str) -> impl Future<Item = (), Error = Error>
+// Stop EdgeAgent, giving it `timeout` to exit before it is killed
+fn stop_runtime<M>(
+    runtime: &M,
+    name: &str,
+    timeout: Duration,
+) -> impl Future<Item = (), Error = Error>
 where
     M: 'static + ModuleRuntime + Clone,
     for<'r> &'r <M as ModuleRuntime>::Error: Into<ModuleRuntimeErrorReason>,
@@ -96,60 +391,589 @@ where
 {
     info!("Stopping edge runtime module {}", name);
     runtime
-        .stop(name, Some(EDGE_RUNTIME_STOP_TIME))
+        .stop(name, Some(timeout))
         .or_else(|err| match (&err).into() {
             ModuleRuntimeErrorReason::NotFound => Ok(()),
             _ => Err(Error::from(err.context(ErrorKind::ModuleRuntime))),
         })
 }
 
-// Start watchdog on a timer for 1 minute
+fn is_quiesced(upgrade: Option<&UpgradeGuard>) -> bool {
+    upgrade.map_or(false, UpgradeGuard::is_quiesced)
+}
+
+fn paused_for(pause: Option<&WatchdogPause>) -> Option<Duration> {
+    pause.and_then(WatchdogPause::remaining)
+}
+
+fn is_module_paused(pause: Option<&WatchdogPause>, name: &str) -> bool {
+    pause.map_or(false, |pause| pause.is_module_paused(name))
+}
+
+// Whether the spec of module `name` is held back rather than applied.
+fn is_held(pause: Option<&WatchdogPause>, name: &str) -> bool {
+    paused_for(pause).is_some() || is_module_paused(pause, name)
+}
+
+/// Outcome of a single edge runtime status check.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum RuntimeCheck {
+    Running(Option<HealthState>),
+    Started,
+    /// Started again after being killed for running out of memory.
+    StartedAfterOomKill,
+    /// The container runtime is restarting it, as its restart policy has it.
+    Restarting,
+    /// Exited, and its restart policy doesn't start it again.
+    Exited,
+    Quiesced,
+    /// Paused, resuming by itself after this long.
+    Paused(Duration),
+    /// Paused after failing too many times in a row, until it is resumed.
+    PausedOnError,
+}
+
+/// Tracks consecutive failures so that the watchdog can back off between
+/// restarts of a crash-looping edge runtime module.
+#[derive(Debug, Default)]
+struct RestartState {
+    restarts: u32,
+    failed_checks: u32,
+    last_start: Option<Instant>,
+    unhealthy_since: Option<Instant>,
+}
+
+impl RestartState {
+    // The module was found running. Once it has stayed up for the grace period
+    // the restart count is forgotten.
+    fn on_running(&mut self, now: Instant, grace_period: Duration) {
+        self.failed_checks = 0;
+        if let Some(last_start) = self.last_start {
+            if now.duration_since(last_start) >= grace_period {
+                self.restarts = 0;
+                self.last_start = None;
+            }
+        }
+    }
+
+    // The module had to be (re)started. Returns the number of restarts that
+    // preceded this one, which selects the backoff delay.
+    fn on_started(&mut self, now: Instant) -> u32 {
+        let previous = self.restarts;
+        self.failed_checks = 0;
+        self.restarts = self.restarts.saturating_add(1);
+        self.last_start = Some(now);
+        self.unhealthy_since = None;
+        previous
+    }
+
+    // The running module reported its health. Returns true if it has been
+    // unhealthy for at least `threshold` and should be restarted.
+    fn on_health(
+        &mut self,
+        health: Option<HealthState>,
+        now: Instant,
+        threshold: Option<Duration>,
+    ) -> bool {
+        if health != Some(HealthState::Unhealthy) {
+            self.unhealthy_since = None;
+            return false;
+        }
+
+        let unhealthy_since = *self.unhealthy_since.get_or_insert(now);
+        threshold.map_or(false, |threshold| {
+            now.duration_since(unhealthy_since) >= threshold
+        })
+    }
+
+    // The watchdog is paused. How long the module was unhealthy before is
+    // forgotten, so that it isn't restarted as soon as the pause is over.
+    fn on_paused(&mut self) {
+        self.unhealthy_since = None;
+    }
+
+    // The check itself failed. Returns the number of failed checks that
+    // preceded this one.
+    fn on_error(&mut self) -> u32 {
+        let previous = self.failed_checks;
+        self.failed_checks = self.failed_checks.saturating_add(1);
+        previous
+    }
+
+    // The check failed again. Returns true if it has now failed `after` times
+    // in a row and the module should be paused, in which case the count starts
+    // over for when it is resumed.
+    fn on_error_pauses(&mut self, after: Option<u32>) -> bool {
+        let pause = after.map_or(false, |after| self.failed_checks.saturating_add(1) >= after);
+        if pause {
+            self.failed_checks = 0;
+        }
+        pause
+    }
+}
+
+// Start watchdog, checking the runtime every `poll_interval` while it is
+// healthy and backing off between consecutive failures. A spec received from
+// `spec_updates` is applied as soon as it arrives, and the runtime is checked
+// again right after. Neither happens while `upgrade` is quiesced. While
+// `pause` is paused the runtime isn't checked either, and a spec received
+// meanwhile is held back until the pause ends; the first check after it starts
+// from whatever state the runtime is in by then. `heartbeat` is beaten every
+// time around. With a `store` the spec is saved whenever it is applied, and if
+// `applied` was saved before it is what the runtime is checked against until
+// `spec` has been applied over it right after the first check. With
+// `pause_on_error` the module is paused in `pause` once it has failed that
+// many times in a row, and is then treated as if the watchdog were paused
+// until it is resumed.
+//
+// A check or update only starts once the one before it has finished, and is
+// timed in `timer`. The specs that arrive meanwhile, such as from reloads
+// while a slow container runtime holds up a recreate, are coalesced into one
+// update to the latest of them.
+//
+// With `maintenance` a changed spec is only applied while a maintenance window
+// is open, unless it is a critical update. Until then it is held back like
+// while paused, and applied right after the first check once a window opens.
+#[allow(clippy::too_many_arguments)]
 pub fn start_watchdog<M, I>(
     runtime: M,
     id_mgr: I,
     spec: ModuleSpec<<M::Module as Module>::Config>,
     module_id: String,
     max_retries: RetryLimit,
+    backoff: BackoffPolicy,
+    poll_interval: Duration,
+    unhealthy_restart_threshold: Option<Duration>,
+    spec_updates: Option<SpecUpdates<M>>,
+    upgrade: Option<UpgradeGuard>,
+    pause: Option<WatchdogPause>,
+    pause_on_error: Option<u32>,
+    heartbeat: Option<Heartbeat>,
+    store: Option<ModuleSetStore>,
+    applied: Option<ModuleSpec<<M::Module as Module>::Config>>,
+    timer: Option<ReconcileTimer>,
+    maintenance: Option<MaintenanceWindows>,
 ) -> impl Future<Item = (), Error = Error>
 where
     M: 'static + ModuleRuntime + Clone,
-    <M::Module as Module>::Config: Clone,
+    for<'r> &'r <M as ModuleRuntime>::Error: Into<ModuleRuntimeErrorReason>,
+    <M::Module as Module>::Config: Clone + Serialize,
     I: 'static + IdentityManager + Clone,
 {
     info!(
         "Starting watchdog with {} second frequency...",
-        WATCHDOG_FREQUENCY_SECS
+        poll_interval.as_secs()
     );
 
-    Interval::new(Instant::now(), Duration::from_secs(WATCHDOG_FREQUENCY_SECS))
-        .map_err(|err| Error::from(err.context(ErrorKind::EdgeRuntimeStatusCheckerTimer)))
-        .and_then(move |_| {
-            info!("Checking edge runtime status");
-            check_runtime(
+    let (spec, pending) = match (&store, applied) {
+        (None, _) => (spec, None),
+        (Some(_), Some(applied)) => (applied, Some(spec)),
+        // Applying the spec over itself only saves it.
+        (Some(_), None) => (spec.clone(), Some(spec)),
+    };
+
+    let state = (RestartState::default(), spec, pending, spec_updates);
+    future::loop_fn(state, move |(mut state, spec, pending, spec_updates)| {
+        if let Some(heartbeat) = &heartbeat {
+            heartbeat.beat();
+        }
+        info!("Checking edge runtime status");
+        let backoff = backoff.clone();
+        let runtime_copy = runtime.clone();
+        let update_runtime = runtime.clone();
+        let update_id_mgr = id_mgr.clone();
+        let update_module_id = module_id.clone();
+        let name = spec.name().to_string();
+        let upgrade = upgrade.clone();
+        let pause = pause.clone();
+        let store = store.clone();
+        let timer = timer.clone();
+        let maintenance = maintenance.clone();
+        if let Some(timer) = &timer {
+            timer.start();
+        }
+        let check = if is_quiesced(upgrade.as_ref()) {
+            info!("Edge runtime is quiesced for an upgrade, not checking it.");
+            Either::A(future::ok(RuntimeCheck::Quiesced))
+        } else if let Some(remaining) = paused_for(pause.as_ref()) {
+            info!(
+                "Watchdog is paused for another {} seconds, not checking the edge runtime.",
+                remaining.as_secs()
+            );
+            Either::A(future::ok(RuntimeCheck::Paused(remaining)))
+        } else if is_module_paused(pause.as_ref(), &name) {
+            info!(
+                "Edge runtime module {} is paused after failing repeatedly, not checking it until it is resumed.",
+                name
+            );
+            Either::A(future::ok(RuntimeCheck::PausedOnError))
+        } else {
+            Either::B(check_runtime(
                 runtime.clone(),
                 id_mgr.clone(),
                 spec.clone(),
                 module_id.clone(),
-            )
-            .and_then(|_| future::ok(None))
-            .or_else(|e| {
-                warn!("Error in watchdog when checking for edge runtime status:");
-                log_failure(Level::Warn, &e);
-                future::ok(Some(e))
-            })
-        })
-        .fold(0, move |exec_count: u32, result: Option<Error>| {
-            result
-                .and_then(|e| {
-                    if max_retries.compare(exec_count) == Ordering::Greater {
-                        Some(Ok(exec_count + 1))
+            ))
+        };
+        check.then(move |result| {
+            let now = Instant::now();
+            let mut restart = false;
+            let delay = match result {
+                Ok(RuntimeCheck::Running(health)) => {
+                    state.on_running(now, backoff.grace_period());
+                    if state.on_health(health, now, unhealthy_restart_threshold) {
+                        info!("Edge runtime is unhealthy, restarting module now...");
+                        restart = true;
+                        let restarts = state.on_started(now);
+                        backoff.jittered_delay(restarts, &mut rand::thread_rng())
                     } else {
-                        Some(Err(e))
+                        poll_interval
+                    }
+                }
+                Ok(RuntimeCheck::Started) => {
+                    let restarts = state.on_started(now);
+                    backoff.jittered_delay(restarts, &mut rand::thread_rng())
+                }
+                Ok(RuntimeCheck::StartedAfterOomKill) => {
+                    let restarts = state.on_started(now);
+                    let delay = backoff.jittered_delay(restarts, &mut rand::thread_rng());
+                    backoff
+                        .oom_killed()
+                        .map_or(delay, |oom_killed| delay.max(oom_killed))
+                }
+                Ok(RuntimeCheck::Restarting)
+                | Ok(RuntimeCheck::Exited)
+                | Ok(RuntimeCheck::Quiesced) => poll_interval,
+                Ok(RuntimeCheck::Paused(remaining)) => {
+                    state.on_paused();
+                    poll_interval.min(remaining)
+                }
+                Ok(RuntimeCheck::PausedOnError) => {
+                    state.on_paused();
+                    poll_interval
+                }
+                Err(e) => {
+                    warn!("Error in watchdog when checking for edge runtime status:");
+                    log_failure(Level::Warn, &e);
+                    match pause.as_ref() {
+                        Some(pause) if state.on_error_pauses(pause_on_error) => {
+                            warn!(
+                                "Edge runtime module {} failed {} times in a row, not retrying it until it is resumed",
+                                name,
+                                pause_on_error.unwrap_or_default(),
+                            );
+                            pause.pause_module(&name);
+                            poll_interval
+                        }
+                        _ => {
+                            if max_retries.compare(state.failed_checks) != Ordering::Greater {
+                                return Either::A(future::err(e));
+                            }
+                            let failures = state.on_error();
+                            backoff.jittered_delay(failures, &mut rand::thread_rng())
+                        }
+                    }
+                }
+            };
+
+            if delay != poll_interval {
+                info!(
+                    "Next edge runtime status check in {} seconds",
+                    delay.as_secs()
+                );
+            }
+
+            // A failed restart is picked up by the next check, so it is only logged.
+            let restarted = if restart {
+                Either::A(runtime_copy.restart(&name).then(|result| {
+                    if let Err(err) = result {
+                        warn!("Error in watchdog when restarting unhealthy edge runtime:");
+                        log_failure(Level::Warn, &err);
+                    }
+                    Ok::<_, Error>(())
+                }))
+            } else {
+                Either::B(future::ok(()))
+            };
+            let update_timer = timer.clone();
+            let restarted = restarted.then(move |result| {
+                if let Some(timer) = &timer {
+                    timer.finish();
+                }
+                result
+            });
+
+            // A spec held back while paused is applied right after the first
+            // check once the pause is over, and one held back for a
+            // maintenance window once the window opens.
+            let deadline = match &pending {
+                Some(pending) if !is_held(pause.as_ref(), &name) => {
+                    match deferred_for(maintenance.as_ref(), &spec, pending) {
+                        Some(until_open) => now + delay.min(until_open),
+                        None => now,
                     }
-                })
-                .unwrap_or_else(|| Ok(0))
+                }
+                _ => now + delay,
+            };
+
+            Either::B(
+                restarted
+                    .and_then(move |()| next_spec_update(deadline, spec_updates))
+                    .and_then(move |(update, spec_updates)| {
+                        let arrived = update.is_some();
+                        match update.or(pending) {
+                        Some(desired) if is_quiesced(upgrade.as_ref()) => {
+                            warn!(
+                                "Not applying the spec of edge runtime module {} while it is quiesced for an upgrade",
+                                desired.name()
+                            );
+                            Either::B(future::ok(Loop::Continue((state, spec, None, spec_updates))))
+                        }
+                        Some(desired) if is_held(pause.as_ref(), desired.name()) => {
+                            info!(
+                                "Applying the spec of edge runtime module {} once the watchdog resumes",
+                                desired.name()
+                            );
+                            Either::B(future::ok(Loop::Continue((
+                                state,
+                                spec,
+                                Some(desired),
+                                spec_updates,
+                            ))))
+                        }
+                        Some(desired) => match deferred_for(maintenance.as_ref(), &spec, &desired) {
+                            Some(until_open) => {
+                                if arrived {
+                                    info!(
+                                        "Applying the spec of edge runtime module {} once a maintenance window opens in {} seconds",
+                                        desired.name(),
+                                        until_open.as_secs()
+                                    );
+                                }
+                                Either::B(future::ok(Loop::Continue((
+                                    state,
+                                    spec,
+                                    Some(desired),
+                                    spec_updates,
+                                ))))
+                            }
+                            None => {
+                            if let Some(timer) = &update_timer {
+                                timer.start();
+                            }
+                            Either::A(
+                                update_spec(
+                                    update_runtime,
+                                    &update_id_mgr,
+                                    spec,
+                                    desired,
+                                    update_module_id,
+                                )
+                                .then(move |result| {
+                                    if let Some(timer) = &update_timer {
+                                        timer.finish();
+                                    }
+                                    result
+                                })
+                                .map(move |spec| {
+                                    if let Some(store) = &store {
+                                        save_applied(store, &spec);
+                                    }
+                                    Loop::Continue((state, spec, None, spec_updates))
+                                }),
+                            )
+                            }
+                        },
+                        None => Either::B(future::ok(Loop::Continue((state, spec, None, spec_updates)))),
+                        }
+                    }),
+            )
         })
-        .map(|_| ())
+    })
+}
+
+// Waits until `deadline` or until a spec arrives from `spec_updates`,
+// whichever comes first. Specs that arrived together, such as while the
+// watchdog was busy, are coalesced into the latest of them. Once the sender
+// is gone the updates stop being polled.
+fn next_spec_update<T>(
+    deadline: Instant,
+    spec_updates: Option<UnboundedReceiver<ModuleSpec<T>>>,
+) -> impl Future<
+    Item = (
+        Option<ModuleSpec<T>>,
+        Option<UnboundedReceiver<ModuleSpec<T>>>,
+    ),
+    Error = Error,
+> {
+    let delay = Delay::new(deadline)
+        .map_err(|err| Error::from(err.context(ErrorKind::EdgeRuntimeStatusCheckerTimer)));
+
+    match spec_updates {
+        Some(spec_updates) => {
+            Either::A(
+                delay
+                    .select2(spec_updates.into_future())
+                    .then(|result| match result {
+                        Ok(Either::A(((), spec_updates))) => {
+                            Either::A(future::ok((None, spec_updates.into_inner())))
+                        }
+                        Ok(Either::B(((Some(spec), spec_updates), _))) => {
+                            let (spec, spec_updates) = latest_spec_update(spec, spec_updates);
+                            Either::A(future::ok((Some(spec), spec_updates)))
+                        }
+                        Ok(Either::B(((None, _), delay))) | Err(Either::B((_, delay))) => {
+                            Either::B(delay.map(|()| (None, None)))
+                        }
+                        Err(Either::A((err, _))) => Either::A(future::err(err)),
+                    }),
+            )
+        }
+        None => Either::B(delay.map(|()| (None, None))),
+    }
+}
+
+// Takes the specs that have already arrived after `spec`, and returns the last
+// of them. Must be called from a task.
+fn latest_spec_update<T>(
+    mut spec: ModuleSpec<T>,
+    mut spec_updates: UnboundedReceiver<ModuleSpec<T>>,
+) -> (ModuleSpec<T>, Option<UnboundedReceiver<ModuleSpec<T>>>) {
+    let mut skipped = 0;
+    let spec_updates = loop {
+        match spec_updates.poll() {
+            Ok(Async::Ready(Some(next))) => {
+                spec = next;
+                skipped += 1;
+            }
+            Ok(Async::Ready(None)) => break None,
+            Ok(Async::NotReady) | Err(()) => break Some(spec_updates),
+        }
+    };
+
+    if skipped > 0 {
+        info!(
+            "Coalesced {} specs of edge runtime module {} that arrived while the watchdog was busy, applying the latest",
+            skipped + 1,
+            spec.name()
+        );
+    }
+    (spec, spec_updates)
+}
+
+// How long the update of the edge runtime module from `current` to `desired`
+// is held back until a maintenance window opens, if it is. A spec that didn't
+// change is only saved, which isn't held back.
+fn deferred_for<T>(
+    maintenance: Option<&MaintenanceWindows>,
+    current: &ModuleSpec<T>,
+    desired: &ModuleSpec<T>,
+) -> Option<Duration>
+where
+    T: Clone + Serialize,
+{
+    let until_open = crate::maintenance::deferred_for(maintenance, desired)?;
+    let unchanged = ModuleSet::new(vec![current.clone()])
+        .and_then(|current_set| current_set.diff(&ModuleSet::new(vec![desired.clone()])?))
+        .map_or(false, |diff| diff.is_empty());
+    if unchanged {
+        None
+    } else {
+        Some(until_open)
+    }
+}
+
+// Saves the spec that the edge runtime module is now created from. A failure
+// is only logged, since it only costs a recreation after a restart.
+fn save_applied<T>(store: &ModuleSetStore, spec: &ModuleSpec<T>)
+where
+    T: Clone + Serialize,
+{
+    if let Err(err) = ModuleSet::new(vec![spec.clone()]).and_then(|modules| store.save(&modules)) {
+        warn!(
+            "Could not save the spec of edge runtime module {}:",
+            spec.name()
+        );
+        log_failure(Level::Warn, &err);
+    }
+}
+
+// Applies the `desired` spec of the edge runtime module and returns the spec
+// that is now current. The module is removed and created again if its spec
+// changed, and left alone otherwise. Failures are logged, since the next check
+// recreates the module if it is missing.
+fn update_spec<M, I>(
+    runtime: M,
+    id_mgr: &I,
+    current: ModuleSpec<<M::Module as Module>::Config>,
+    desired: ModuleSpec<<M::Module as Module>::Config>,
+    module_id: String,
+) -> impl Future<Item = ModuleSpec<<M::Module as Module>::Config>, Error = Error>
+where
+    M: 'static + ModuleRuntime + Clone,
+    for<'r> &'r <M as ModuleRuntime>::Error: Into<ModuleRuntimeErrorReason>,
+    <M::Module as Module>::Config: Clone + Serialize,
+    I: 'static + IdentityManager + Clone,
+{
+    let changed = ModuleSet::new(vec![current.clone()])
+        .and_then(|current_set| current_set.diff(&ModuleSet::new(vec![desired.clone()])?))
+        .map(|diff| !diff.is_empty());
+
+    match changed {
+        Ok(true) => {
+            info!(
+                "Spec of edge runtime module {} changed, recreating module now...",
+                desired.name()
+            );
+            let id_mgr = id_mgr.clone();
+            let name = current.name().to_string();
+            let stop_timeout = current.stop_timeout().unwrap_or(EDGE_RUNTIME_STOP_TIME);
+            // The image is pulled before the running module is removed, and
+            // not again when the module is created.
+            let spec = desired
+                .clone()
+                .with_image_pull_policy(ImagePullPolicy::Never);
+            let recreate = pull_image(&runtime, &desired).then(move |result| match result {
+                Ok(_) => Either::A(
+                    stop_and_remove(runtime.clone(), name, stop_timeout)
+                        .and_then(move |()| create_and_start(runtime, &id_mgr, spec, module_id))
+                        .then(move |result| {
+                            if let Err(err) = result {
+                                warn!("Error in watchdog when recreating edge runtime module:");
+                                log_failure(Level::Warn, &err);
+                            }
+                            Ok::<_, Error>(desired)
+                        }),
+                ),
+                // The current module keeps running, and the update is tried
+                // again when the spec is next sent.
+                Err(err) => {
+                    let err = Error::from(err.context(ErrorKind::ModuleRuntime));
+                    warn!(
+                        "Could not pull the image of edge runtime module {}, keeping the current module:",
+                        current.name()
+                    );
+                    log_failure(Level::Warn, &err);
+                    Either::B(future::ok(current))
+                }
+            });
+            Either::A(recreate)
+        }
+        Ok(false) => {
+            info!(
+                "Spec of edge runtime module {} is unchanged",
+                desired.name()
+            );
+            Either::B(future::ok(desired))
+        }
+        Err(err) => {
+            warn!(
+                "Could not compare the specs of the edge runtime module, keeping the current one:"
+            );
+            log_failure(Level::Warn, &err);
+            Either::B(future::ok(current))
+        }
+    }
 }
 
 // Check if the edge runtime module is running, and if not, start it.
@@ -158,7 +982,7 @@ fn check_runtime<M, I>(
     id_mgr: I,
     spec: ModuleSpec<<M::Module as Module>::Config>,
     module_id: String,
-) -> impl Future<Item = (), Error = Error>
+) -> impl Future<Item = RuntimeCheck, Error = Error>
 where
     M: 'static + ModuleRuntime + Clone,
     <M::Module as Module>::Config: Clone,
@@ -175,25 +999,85 @@ where
         .and_then(move |state| match state {
             Some(state) => {
                 let res = if *state.status() == ModuleStatus::Running {
-                    info!("Edge runtime is running.");
-                    future::Either::A(future::ok(()))
+                    match state.health() {
+                        Some(health) => info!("Edge runtime is running and {}.", health),
+                        None => info!("Edge runtime is running."),
+                    }
+                    future::Either::A(future::ok(RuntimeCheck::Running(state.health())))
                 } else {
-                    info!(
-                        "Edge runtime status is {}, starting module now...",
-                        *state.status(),
-                    );
-                    future::Either::B(
-                        runtime
-                            .start(&module)
-                            .map_err(|e| Error::from(e.context(ErrorKind::ModuleRuntime))),
-                    )
+                    match stopped_check(spec.restart_policy(), spec.max_retries(), &state) {
+                        RuntimeCheck::Started => {
+                            let started = if state.exit_reason() == Some(ExitReason::OomKilled) {
+                                warn!(
+                                    "Edge runtime was killed for running out of memory, starting module now..."
+                                );
+                                RuntimeCheck::StartedAfterOomKill
+                            } else {
+                                info!(
+                                    "Edge runtime status is {}, starting module now...",
+                                    *state.status(),
+                                );
+                                RuntimeCheck::Started
+                            };
+                            future::Either::B(
+                                runtime
+                                    .start(&module)
+                                    .map(move |_| started)
+                                    .map_err(|e| Error::from(e.context(ErrorKind::ModuleRuntime))),
+                            )
+                        }
+                        check => {
+                            if check == RuntimeCheck::Restarting {
+                                info!("Edge runtime is being restarted by the container runtime.");
+                            } else {
+                                info!(
+                                    "Edge runtime status is {} and its restart policy is {}, not starting it again.",
+                                    *state.status(),
+                                    spec.restart_policy(),
+                                );
+                            }
+                            future::Either::A(future::ok(check))
+                        }
+                    }
                 };
                 Either::A(res)
             }
 
-            None => Either::B(create_and_start(runtime, &id_mgr, spec, module_id)),
+            None => Either::B(
+                create_and_start(runtime, &id_mgr, spec, module_id).map(|_| RuntimeCheck::Started),
+            ),
         })
-        .map(|_| ())
+}
+
+// What to do about the edge runtime module that isn't running, as `policy`
+// has it: start it, or leave it to the container runtime, which restarts it
+// under the same policy, or leave it exited for good. A module that never ran
+// is always started.
+fn stopped_check(
+    policy: RestartPolicy,
+    max_retries: Option<u32>,
+    state: &ModuleRuntimeState,
+) -> RuntimeCheck {
+    if state.finished_at().is_none() {
+        return RuntimeCheck::Started;
+    }
+
+    match policy {
+        RestartPolicy::Always => RuntimeCheck::Started,
+        RestartPolicy::Never => RuntimeCheck::Exited,
+        RestartPolicy::OnFailure if state.status_description() == Some("restarting") => {
+            RuntimeCheck::Restarting
+        }
+        RestartPolicy::OnFailure => {
+            let retries = state.restart_count().unwrap_or(0);
+            let exhausted = max_retries.map_or(false, |max_retries| retries >= max_retries);
+            if state.exit_code() == Some(0) || exhausted {
+                RuntimeCheck::Exited
+            } else {
+                RuntimeCheck::Started
+            }
+        }
+    }
 }
 
 // Gets the edge runtime module, if it exists.
@@ -267,12 +1151,7 @@ where
         );
         let spec = spec.with_env(env);
 
-        let pull_future = match spec.image_pull_policy() {
-            ImagePullPolicy::Never => Either::A(future::ok(())),
-            ImagePullPolicy::OnCreate => Either::B(runtime.registry().pull(spec.clone().config())),
-        };
-
-        pull_future
+        pull_image(&runtime, &spec)
             .and_then(move |_| runtime.create(spec))
             .and_then(move |_| runtime_copy.start(&module_name))
             .map_err(|e| Error::from(e.context(ErrorKind::ModuleRuntime)))
@@ -282,12 +1161,22 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::cell::RefCell;
+    use std::cell::{Cell, RefCell};
+    use std::collections::HashMap;
     use std::rc::Rc;
 
+    use chrono::{FixedOffset, Utc};
     use futures::future::{self, FutureResult};
+    use futures::stream::{self, Empty, IterOk};
 
+    use crate::events::ModuleEvents;
     use crate::identity::{AuthType, Identity, IdentityManager, IdentitySpec};
+    use crate::maintenance::{MaintenanceWindow, TimeZone, CRITICAL_UPDATE_LABEL};
+    use crate::module::{
+        ExecOutput, ImagePruneFilter, LogOptions, ModuleRegistry, ModuleRuntimeState, ModuleStats,
+        PrunedImages, SystemInfo, SystemResources,
+    };
+    use crate::upgrade::UpgradeState;
     use serde_derive::{Deserialize, Serialize};
 
     #[derive(Clone, Copy, Debug, Fail)]
@@ -299,6 +1188,15 @@ mod tests {
         ModuleNotFound,
     }
 
+    impl<'a> From<&'a Error> for ModuleRuntimeErrorReason {
+        fn from(err: &'a Error) -> Self {
+            match err {
+                Error::General => ModuleRuntimeErrorReason::Other,
+                Error::ModuleNotFound => ModuleRuntimeErrorReason::NotFound,
+            }
+        }
+    }
+
     #[derive(Clone, Debug, Deserialize, Serialize)]
     pub struct TestIdentity {
         #[serde(rename = "moduleId")]
@@ -467,6 +1365,133 @@ mod tests {
         }
     }
 
+    #[test]
+    fn restart_state_counts_consecutive_restarts() {
+        let policy = BackoffPolicy::new(Duration::from_secs(1), Duration::from_secs(8), 2.0, 0.0);
+        let grace_period = Duration::from_secs(30);
+        let mut state = RestartState::default();
+        let start = Instant::now();
+
+        let delays: Vec<_> = (0..5)
+            .map(|i| {
+                let now = start + Duration::from_secs(i);
+                state.on_running(now, grace_period);
+                policy.delay(state.on_started(now)).as_secs()
+            })
+            .collect();
+        assert_eq!(vec![1, 2, 4, 8, 8], delays);
+    }
+
+    #[test]
+    fn restart_state_resets_after_grace_period() {
+        let grace_period = Duration::from_secs(30);
+        let mut state = RestartState::default();
+        let start = Instant::now();
+
+        assert_eq!(0, state.on_started(start));
+        assert_eq!(1, state.on_started(start + Duration::from_secs(1)));
+
+        // still within the grace period, the count is kept
+        state.on_running(start + Duration::from_secs(20), grace_period);
+        assert_eq!(2, state.restarts);
+
+        state.on_running(start + Duration::from_secs(31), grace_period);
+        assert_eq!(0, state.restarts);
+        assert_eq!(0, state.on_started(start + Duration::from_secs(40)));
+    }
+
+    #[test]
+    fn restart_state_counts_failed_checks() {
+        let mut state = RestartState::default();
+
+        assert_eq!(0, state.on_error());
+        assert_eq!(1, state.on_error());
+        assert_eq!(2, state.failed_checks);
+
+        state.on_running(Instant::now(), Duration::from_secs(30));
+        assert_eq!(0, state.failed_checks);
+    }
+
+    #[test]
+    fn restart_state_pauses_after_failures_in_a_row() {
+        let mut state = RestartState::default();
+        assert!(!state.on_error_pauses(None));
+
+        assert!(!state.on_error_pauses(Some(3)));
+        state.on_error();
+        assert!(!state.on_error_pauses(Some(3)));
+        state.on_error();
+        assert!(state.on_error_pauses(Some(3)));
+
+        // a resumed module gets as many tries again
+        assert_eq!(0, state.failed_checks);
+    }
+
+    #[test]
+    fn restart_state_restarts_after_unhealthy_threshold() {
+        let threshold = Some(Duration::from_secs(120));
+        let mut state = RestartState::default();
+        let start = Instant::now();
+
+        assert!(!state.on_health(Some(HealthState::Unhealthy), start, threshold));
+        assert!(!state.on_health(
+            Some(HealthState::Unhealthy),
+            start + Duration::from_secs(60),
+            threshold
+        ));
+        assert!(state.on_health(
+            Some(HealthState::Unhealthy),
+            start + Duration::from_secs(120),
+            threshold
+        ));
+
+        // restarting the module starts the clock again
+        state.on_started(start + Duration::from_secs(120));
+        assert!(!state.on_health(
+            Some(HealthState::Unhealthy),
+            start + Duration::from_secs(180),
+            threshold
+        ));
+    }
+
+    #[test]
+    fn restart_state_resets_unhealthy_when_recovered() {
+        let threshold = Some(Duration::from_secs(120));
+        let mut state = RestartState::default();
+        let start = Instant::now();
+
+        assert!(!state.on_health(Some(HealthState::Unhealthy), start, threshold));
+        assert!(!state.on_health(
+            Some(HealthState::Healthy),
+            start + Duration::from_secs(60),
+            threshold
+        ));
+        assert!(!state.on_health(
+            Some(HealthState::Unhealthy),
+            start + Duration::from_secs(150),
+            threshold
+        ));
+    }
+
+    #[test]
+    fn restart_state_ignores_health_without_threshold() {
+        let mut state = RestartState::default();
+        let start = Instant::now();
+
+        assert!(!state.on_health(Some(HealthState::Unhealthy), start, None));
+        assert!(!state.on_health(
+            Some(HealthState::Unhealthy),
+            start + Duration::from_secs(3600),
+            None
+        ));
+        assert!(!state.on_health(None, start, Some(Duration::from_secs(0))));
+        assert!(!state.on_health(
+            Some(HealthState::Starting),
+            start,
+            Some(Duration::from_secs(0))
+        ));
+    }
+
     #[test]
     fn update_identity_get_fails() {
         let mut manager = TestIdentityManager::new(vec![]).with_fail_get(true);
@@ -523,4 +1548,943 @@ mod tests {
                 .auth_type
         );
     }
+
+    #[derive(Clone, Debug)]
+    struct TestModule {
+        name: String,
+        config: serde_json::Value,
+        state: ModuleRuntimeState,
+    }
+
+    impl Module for TestModule {
+        type Config = serde_json::Value;
+        type Error = Error;
+        type RuntimeStateFuture = FutureResult<ModuleRuntimeState, Self::Error>;
+
+        fn name(&self) -> &str {
+            &self.name
+        }
+
+        fn type_(&self) -> &str {
+            "docker"
+        }
+
+        fn config(&self) -> &Self::Config {
+            &self.config
+        }
+
+        fn runtime_state(&self) -> Self::RuntimeStateFuture {
+            future::ok(self.state.clone())
+        }
+    }
+
+    #[derive(Clone)]
+    struct TestRegistry {
+        fail_pull: bool,
+    }
+
+    impl ModuleRegistry for TestRegistry {
+        type Error = Error;
+        type PullFuture = FutureResult<(), Self::Error>;
+        type RemoveFuture = FutureResult<(), Self::Error>;
+        type ImageExistsFuture = FutureResult<bool, Self::Error>;
+        type Config = serde_json::Value;
+
+        fn pull(&self, _config: &Self::Config) -> Self::PullFuture {
+            if self.fail_pull {
+                future::err(Error::General)
+            } else {
+                future::ok(())
+            }
+        }
+
+        fn remove(&self, _name: &str) -> Self::RemoveFuture {
+            future::ok(())
+        }
+
+        fn image_exists(&self, _config: &Self::Config) -> Self::ImageExistsFuture {
+            future::ok(false)
+        }
+    }
+
+    // A runtime with a fixed set of running modules that records the
+    // operations on them.
+    #[derive(Clone)]
+    struct TestRuntime {
+        modules: Vec<TestModule>,
+        registry: TestRegistry,
+        operations: Rc<RefCell<Vec<String>>>,
+        lists: Rc<Cell<usize>>,
+    }
+
+    impl TestRuntime {
+        fn new(names: &[&str]) -> Self {
+            TestRuntime {
+                modules: names
+                    .iter()
+                    .map(|name| TestModule {
+                        name: (*name).to_string(),
+                        config: serde_json::json!({ "image": "ubuntu" }),
+                        state: ModuleRuntimeState::default().with_status(ModuleStatus::Running),
+                    })
+                    .collect(),
+                registry: TestRegistry { fail_pull: false },
+                operations: Rc::new(RefCell::new(vec![])),
+                lists: Rc::new(Cell::new(0)),
+            }
+        }
+
+        fn with_state(mut self, state: &ModuleRuntimeState) -> Self {
+            for module in &mut self.modules {
+                module.state = state.clone();
+            }
+            self
+        }
+
+        fn with_failing_pull(mut self) -> Self {
+            self.registry.fail_pull = true;
+            self
+        }
+
+        fn record(&self, operation: &str, name: &str) -> FutureResult<(), Error> {
+            self.operations
+                .borrow_mut()
+                .push(format!("{} {}", operation, name));
+            future::ok(())
+        }
+
+        fn operations(&self) -> Vec<String> {
+            self.operations.borrow().clone()
+        }
+
+        // Each check of the edge runtime module lists the modules once.
+        fn checks(&self) -> usize {
+            self.lists.get()
+        }
+    }
+
+    impl ModuleRuntime for TestRuntime {
+        type Error = Error;
+        type Config = serde_json::Value;
+        type Module = TestModule;
+        type ModuleRegistry = TestRegistry;
+        type Chunk = Vec<u8>;
+        type Logs = Empty<Self::Chunk, Self::Error>;
+
+        type CreateFuture = FutureResult<(), Self::Error>;
+        type GetFuture = FutureResult<(Self::Module, ModuleRuntimeState), Self::Error>;
+        type ListFuture = FutureResult<Vec<Self::Module>, Self::Error>;
+        type ListWithDetailsStream =
+            IterOk<std::vec::IntoIter<(Self::Module, ModuleRuntimeState)>, Self::Error>;
+        type LogsFuture = FutureResult<Self::Logs, Self::Error>;
+        type RemoveFuture = FutureResult<(), Self::Error>;
+        type RestartFuture = FutureResult<(), Self::Error>;
+        type StartFuture = FutureResult<(), Self::Error>;
+        type StopFuture = FutureResult<(), Self::Error>;
+        type SystemInfoFuture = FutureResult<SystemInfo, Self::Error>;
+        type SystemResourcesFuture = FutureResult<SystemResources, Self::Error>;
+        type RemoveAllFuture = FutureResult<(), Self::Error>;
+        type StatsFuture = FutureResult<ModuleStats, Self::Error>;
+        type PruneImagesFuture = FutureResult<PrunedImages, Self::Error>;
+        type ExecStream = Empty<ExecOutput, Self::Error>;
+        type EffectiveSpecFuture = FutureResult<ModuleSpec<Self::Config>, Self::Error>;
+
+        fn create(&self, module: ModuleSpec<Self::Config>) -> Self::CreateFuture {
+            self.record("create", module.name())
+        }
+
+        fn validate(&self, _module: &ModuleSpec<Self::Config>) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn get(&self, _id: &str) -> Self::GetFuture {
+            unimplemented!()
+        }
+
+        fn effective_spec(&self, _id: &str) -> Self::EffectiveSpecFuture {
+            unimplemented!()
+        }
+
+        fn start(&self, id: &str) -> Self::StartFuture {
+            self.record("start", id)
+        }
+
+        fn stop(&self, id: &str, _wait_before_kill: Option<Duration>) -> Self::StopFuture {
+            self.record("stop", id)
+        }
+
+        fn restart(&self, id: &str) -> Self::RestartFuture {
+            self.record("restart", id)
+        }
+
+        fn remove(&self, id: &str) -> Self::RemoveFuture {
+            self.record("remove", id)
+        }
+
+        fn system_info(&self) -> Self::SystemInfoFuture {
+            unimplemented!()
+        }
+
+        fn system_resources(&self) -> Self::SystemResourcesFuture {
+            unimplemented!()
+        }
+
+        fn list(&self) -> Self::ListFuture {
+            self.lists.set(self.lists.get() + 1);
+            future::ok(self.modules.clone())
+        }
+
+        fn list_with_details(&self) -> Self::ListWithDetailsStream {
+            let modules: Vec<_> = self
+                .modules
+                .iter()
+                .map(|module| (module.clone(), module.runtime_state().wait().unwrap()))
+                .collect();
+            stream::iter_ok(modules)
+        }
+
+        fn logs(&self, _id: &str, _options: &LogOptions) -> Self::LogsFuture {
+            future::ok(stream::empty())
+        }
+
+        fn registry(&self) -> &Self::ModuleRegistry {
+            &self.registry
+        }
+
+        fn remove_all(&self) -> Self::RemoveAllFuture {
+            unimplemented!()
+        }
+
+        fn stats(&self, _id: &str) -> Self::StatsFuture {
+            unimplemented!()
+        }
+
+        fn prune_images(&self, _filter: ImagePruneFilter) -> Self::PruneImagesFuture {
+            unimplemented!()
+        }
+
+        fn exec(
+            &self,
+            _id: &str,
+            _cmd: Vec<String>,
+            _env: HashMap<String, String>,
+        ) -> Self::ExecStream {
+            unimplemented!()
+        }
+
+        fn events(&self) -> &ModuleEvents {
+            unimplemented!()
+        }
+    }
+
+    fn agent_spec(env: &[(&str, &str)]) -> ModuleSpec<serde_json::Value> {
+        let env: HashMap<_, _> = env
+            .iter()
+            .map(|(k, v)| ((*k).to_string(), (*v).to_string()))
+            .collect();
+        ModuleSpec::new(
+            "edgeAgent".to_string(),
+            "docker".to_string(),
+            serde_json::json!({ "image": "mcr.microsoft.com/azureiotedge-agent:1.0" }),
+            env,
+            ImagePullPolicy::Never,
+        )
+        .unwrap()
+    }
+
+    fn id_mgr() -> TestIdentityManager {
+        TestIdentityManager::new(vec![TestIdentity::new(
+            "$edgeAgent",
+            "iotedge",
+            "1",
+            AuthType::Sas,
+        )])
+    }
+
+    // Runs the watchdog on `runtime` for `window`, after which it is dropped
+    // without stopping the edge runtime module.
+    fn run_watchdog(
+        runtime: &TestRuntime,
+        poll_interval: Duration,
+        pause: Option<WatchdogPause>,
+        window: Duration,
+    ) {
+        run_watchdog_with_store(runtime, agent_spec(&[]), None, poll_interval, pause, window)
+    }
+
+    fn run_watchdog_with_store(
+        runtime: &TestRuntime,
+        spec: ModuleSpec<serde_json::Value>,
+        store: Option<ModuleSetStore>,
+        poll_interval: Duration,
+        pause: Option<WatchdogPause>,
+        window: Duration,
+    ) {
+        let applied = store
+            .as_ref()
+            .and_then(ModuleSetStore::load)
+            .map(|modules| modules.modules()[0].clone());
+        let watchdog = start_watchdog(
+            runtime.clone(),
+            id_mgr(),
+            spec,
+            "$edgeAgent".to_string(),
+            RetryLimit::default(),
+            BackoffPolicy::default(),
+            poll_interval,
+            None,
+            None,
+            None,
+            pause,
+            None,
+            None,
+            store,
+            applied,
+            None,
+            None,
+        );
+        let window = Delay::new(Instant::now() + window);
+
+        let mut tokio_runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+        match tokio_runtime.block_on(watchdog.select2(window)) {
+            Ok(Either::B(_)) => (),
+            _ => panic!("watchdog stopped before the end of the window"),
+        }
+    }
+
+    #[test]
+    fn watchdog_checks_edge_runtime_every_poll_interval() {
+        let runtime = TestRuntime::new(&["edgeAgent", "tempSensor"]);
+        run_watchdog(
+            &runtime,
+            Duration::from_secs(60),
+            None,
+            Duration::from_millis(200),
+        );
+        assert_eq!(1, runtime.checks());
+
+        let runtime = TestRuntime::new(&["edgeAgent", "tempSensor"]);
+        run_watchdog(
+            &runtime,
+            Duration::from_millis(20),
+            None,
+            Duration::from_millis(200),
+        );
+        assert!(runtime.checks() >= 5, "checked {} times", runtime.checks());
+        assert!(runtime.checks() <= 11, "checked {} times", runtime.checks());
+        assert!(runtime.operations().is_empty());
+    }
+
+    #[test]
+    fn paused_watchdog_leaves_edge_runtime_alone() {
+        // the edge runtime module was removed by hand
+        let runtime = TestRuntime::new(&["tempSensor"]);
+        let pause = WatchdogPause::new();
+        pause.pause(Duration::from_secs(60));
+
+        run_watchdog(
+            &runtime,
+            Duration::from_millis(20),
+            Some(pause.clone()),
+            Duration::from_millis(100),
+        );
+
+        assert_eq!(0, runtime.checks());
+        assert!(runtime.operations().is_empty());
+        assert!(pause.is_paused());
+    }
+
+    #[test]
+    fn watchdog_resumes_from_current_state_after_pause() {
+        let runtime = TestRuntime::new(&["edgeAgent", "tempSensor"]);
+        let pause = WatchdogPause::new();
+        pause.pause(Duration::from_millis(100));
+
+        run_watchdog(
+            &runtime,
+            Duration::from_millis(20),
+            Some(pause.clone()),
+            Duration::from_millis(300),
+        );
+
+        // checking resumed by itself, and found the running module as it was
+        assert!(!pause.is_paused());
+        assert!(runtime.checks() >= 1);
+        assert!(runtime.operations().is_empty());
+    }
+
+    // Runs the watchdog with `pause_on_error` on a runtime whose edge runtime
+    // module can't be created.
+    fn run_failing_watchdog(
+        runtime: &TestRuntime,
+        pause: &WatchdogPause,
+        pause_on_error: Option<u32>,
+        window: Duration,
+    ) {
+        let watchdog = start_watchdog(
+            runtime.clone(),
+            id_mgr().with_fail_get(true),
+            agent_spec(&[]),
+            "$edgeAgent".to_string(),
+            RetryLimit::default(),
+            BackoffPolicy::new(
+                Duration::from_millis(10),
+                Duration::from_millis(10),
+                1.0,
+                0.0,
+            ),
+            Duration::from_millis(20),
+            None,
+            None,
+            None,
+            Some(pause.clone()),
+            pause_on_error,
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        let window = Delay::new(Instant::now() + window);
+
+        let mut tokio_runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+        match tokio_runtime.block_on(watchdog.select2(window)) {
+            Ok(Either::B(_)) => (),
+            _ => panic!("watchdog stopped before the end of the window"),
+        }
+    }
+
+    #[test]
+    fn failing_module_is_paused_after_failures_in_a_row() {
+        let runtime = TestRuntime::new(&["tempSensor"]);
+        let pause = WatchdogPause::new();
+
+        run_failing_watchdog(&runtime, &pause, Some(3), Duration::from_millis(300));
+
+        assert!(pause.is_module_paused("edgeAgent"));
+        assert!(!pause.is_paused());
+        // no more checks once it was paused
+        assert_eq!(3, runtime.checks());
+
+        // without pause on error it is retried for as long as the window lasts
+        let runtime = TestRuntime::new(&["tempSensor"]);
+        let pause = WatchdogPause::new();
+        run_failing_watchdog(&runtime, &pause, None, Duration::from_millis(300));
+        assert!(!pause.is_module_paused("edgeAgent"));
+        assert!(runtime.checks() > 3, "checked {} times", runtime.checks());
+    }
+
+    #[test]
+    fn paused_module_is_retried_once_resumed() {
+        // the edge runtime module is missing, and is created once resumed
+        let runtime = TestRuntime::new(&["tempSensor"]);
+        let pause = WatchdogPause::new();
+        pause.pause_module("edgeAgent");
+
+        let resume = {
+            let pause = pause.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(100));
+                assert!(pause.resume_module("edgeAgent"));
+            })
+        };
+        run_watchdog(
+            &runtime,
+            Duration::from_millis(20),
+            Some(pause.clone()),
+            Duration::from_millis(300),
+        );
+        resume.join().unwrap();
+
+        assert!(!pause.is_module_paused("edgeAgent"));
+        assert!(!pause.resume_module("edgeAgent"));
+        assert_eq!(
+            vec!["create edgeAgent", "start edgeAgent"],
+            runtime.operations()
+        );
+    }
+
+    #[test]
+    fn pause_ends_on_resume_or_timeout() {
+        let pause = WatchdogPause::new();
+        assert!(!pause.is_paused());
+        assert!(!pause.resume());
+
+        pause.pause(Duration::from_secs(60));
+        assert!(pause.remaining().unwrap() <= Duration::from_secs(60));
+        assert!(pause.resume());
+        assert_eq!(None, pause.remaining());
+
+        pause.pause(Duration::from_secs(0));
+        assert!(!pause.is_paused());
+        assert!(!pause.resume());
+    }
+
+    #[test]
+    fn update_spec_recreates_only_changed_module() {
+        let runtime = TestRuntime::new(&["edgeAgent", "tempSensor"]);
+        let current = agent_spec(&[("RuntimeLogLevel", "info")]);
+        let desired = agent_spec(&[("RuntimeLogLevel", "debug")]);
+
+        let spec = update_spec(
+            runtime.clone(),
+            &id_mgr(),
+            current,
+            desired,
+            "$edgeAgent".to_string(),
+        )
+        .wait()
+        .unwrap();
+
+        assert_eq!("debug", spec.env()["RuntimeLogLevel"]);
+        assert_eq!(
+            vec![
+                "stop edgeAgent",
+                "remove edgeAgent",
+                "create edgeAgent",
+                "start edgeAgent",
+            ],
+            runtime.operations()
+        );
+    }
+
+    #[test]
+    fn update_spec_keeps_old_module_running_when_pull_fails() {
+        let runtime = TestRuntime::new(&["edgeAgent", "tempSensor"]).with_failing_pull();
+        let current = agent_spec(&[("RuntimeLogLevel", "info")]);
+        let desired = agent_spec(&[("RuntimeLogLevel", "info")]).with_config(
+            serde_json::json!({ "image": "mcr.microsoft.com/azureiotedge-agent:1.1" }),
+        );
+        let desired = desired.with_image_pull_policy(ImagePullPolicy::OnCreate);
+
+        let spec = update_spec(
+            runtime.clone(),
+            &id_mgr(),
+            current,
+            desired,
+            "$edgeAgent".to_string(),
+        )
+        .wait()
+        .unwrap();
+
+        // The old module is neither stopped nor removed, and stays current
+        assert_eq!(
+            "mcr.microsoft.com/azureiotedge-agent:1.0",
+            spec.config()["image"]
+        );
+        assert!(runtime.operations().is_empty());
+    }
+
+    #[test]
+    fn update_spec_leaves_unchanged_module_alone() {
+        let runtime = TestRuntime::new(&["edgeAgent", "tempSensor"]);
+        let current = agent_spec(&[("RuntimeLogLevel", "info")]);
+        let desired = agent_spec(&[("RuntimeLogLevel", "info")]);
+
+        update_spec(
+            runtime.clone(),
+            &id_mgr(),
+            current,
+            desired,
+            "$edgeAgent".to_string(),
+        )
+        .wait()
+        .unwrap();
+
+        assert!(runtime.operations().is_empty());
+    }
+
+    #[test]
+    fn restarted_watchdog_recreates_module_only_if_applied_spec_changed() {
+        let dir = tempdir::TempDir::new("watchdog").unwrap();
+        let store = ModuleSetStore::new(dir.path().join("module_set_state"));
+
+        // Nothing was saved, so the running module is taken to be the config's.
+        let runtime = TestRuntime::new(&["edgeAgent", "tempSensor"]);
+        run_watchdog_with_store(
+            &runtime,
+            agent_spec(&[("RuntimeLogLevel", "debug")]),
+            Some(store.clone()),
+            DEFAULT_POLL_INTERVAL,
+            None,
+            Duration::from_millis(200),
+        );
+        assert!(runtime.operations().is_empty());
+        let applied = store.load::<serde_json::Value>().unwrap();
+        assert_eq!("debug", applied.modules()[0].env()["RuntimeLogLevel"]);
+
+        // The same spec after a restart leaves the module alone...
+        let runtime = TestRuntime::new(&["edgeAgent", "tempSensor"]);
+        run_watchdog_with_store(
+            &runtime,
+            agent_spec(&[("RuntimeLogLevel", "debug")]),
+            Some(store.clone()),
+            DEFAULT_POLL_INTERVAL,
+            None,
+            Duration::from_millis(200),
+        );
+        assert!(runtime.operations().is_empty());
+
+        // ...and a changed one recreates it.
+        let runtime = TestRuntime::new(&["edgeAgent", "tempSensor"]);
+        run_watchdog_with_store(
+            &runtime,
+            agent_spec(&[("RuntimeLogLevel", "info")]),
+            Some(store.clone()),
+            DEFAULT_POLL_INTERVAL,
+            None,
+            Duration::from_millis(200),
+        );
+        assert_eq!(
+            vec![
+                "stop edgeAgent",
+                "remove edgeAgent",
+                "create edgeAgent",
+                "start edgeAgent",
+            ],
+            runtime.operations()
+        );
+        let applied = store.load::<serde_json::Value>().unwrap();
+        assert_eq!("info", applied.modules()[0].env()["RuntimeLogLevel"]);
+    }
+
+    #[test]
+    fn spec_update_is_applied_before_next_check() {
+        let (sender, receiver) = futures::sync::mpsc::unbounded();
+        sender
+            .unbounded_send(agent_spec(&[("RuntimeLogLevel", "debug")]))
+            .unwrap();
+
+        let mut tokio_runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+        let (update, _) = tokio_runtime
+            .block_on(next_spec_update(
+                Instant::now() + DEFAULT_POLL_INTERVAL,
+                Some(receiver),
+            ))
+            .unwrap();
+
+        assert_eq!("debug", update.unwrap().env()["RuntimeLogLevel"]);
+    }
+
+    #[test]
+    fn spec_updates_that_arrive_together_are_coalesced() {
+        let (sender, receiver) = futures::sync::mpsc::unbounded();
+        for level in &["debug", "warn", "error"] {
+            sender
+                .unbounded_send(agent_spec(&[("RuntimeLogLevel", level)]))
+                .unwrap();
+        }
+
+        let mut tokio_runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+        let (update, receiver) = tokio_runtime
+            .block_on(next_spec_update(
+                Instant::now() + DEFAULT_POLL_INTERVAL,
+                Some(receiver),
+            ))
+            .unwrap();
+        assert_eq!("error", update.unwrap().env()["RuntimeLogLevel"]);
+
+        let (update, _) = tokio_runtime
+            .block_on(next_spec_update(
+                Instant::now() + Duration::from_millis(50),
+                receiver,
+            ))
+            .unwrap();
+        assert!(update.is_none());
+    }
+
+    #[test]
+    fn spec_updates_during_slow_reconcile_cause_one_update() {
+        let runtime = TestRuntime::new(&["edgeAgent", "tempSensor"]);
+        let timer = ReconcileTimer::new();
+        assert_eq!(None, timer.duration());
+
+        // The watchdog only takes the next spec once the reconcile before it
+        // has finished, so specs sent meanwhile are all waiting by then.
+        let (sender, receiver) = futures::sync::mpsc::unbounded();
+        for level in &["debug", "warn", "error"] {
+            sender
+                .unbounded_send(agent_spec(&[("RuntimeLogLevel", level)]))
+                .unwrap();
+        }
+
+        let watchdog = start_watchdog(
+            runtime.clone(),
+            id_mgr(),
+            agent_spec(&[]),
+            "$edgeAgent".to_string(),
+            RetryLimit::default(),
+            BackoffPolicy::default(),
+            Duration::from_secs(60),
+            None,
+            Some(receiver),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some(timer.clone()),
+            None,
+        );
+        let window = Delay::new(Instant::now() + Duration::from_millis(200));
+
+        let mut tokio_runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+        match tokio_runtime.block_on(watchdog.select2(window)) {
+            Ok(Either::B(_)) => (),
+            _ => panic!("watchdog stopped before the end of the window"),
+        }
+
+        let creates = runtime
+            .operations()
+            .iter()
+            .filter(|operation| *operation == "create edgeAgent")
+            .count();
+        assert_eq!(1, creates);
+        assert_eq!(2, runtime.checks());
+        assert!(timer.duration().is_some());
+    }
+
+    // Runs the watchdog with `maintenance`, sending it `update`. Returns the
+    // operations on the runtime after `before`, and after `after` more.
+    fn update_with_maintenance(
+        maintenance: MaintenanceWindows,
+        update: ModuleSpec<serde_json::Value>,
+        before: Duration,
+        after: Duration,
+    ) -> (Vec<String>, Vec<String>) {
+        let runtime = TestRuntime::new(&["edgeAgent", "tempSensor"]);
+        let (sender, receiver) = futures::sync::mpsc::unbounded();
+        sender.unbounded_send(update).unwrap();
+
+        let watchdog = start_watchdog(
+            runtime.clone(),
+            id_mgr(),
+            agent_spec(&[]),
+            "$edgeAgent".to_string(),
+            RetryLimit::default(),
+            BackoffPolicy::default(),
+            Duration::from_secs(60),
+            None,
+            Some(receiver),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some(maintenance),
+        );
+
+        let mut tokio_runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+        let watchdog =
+            match tokio_runtime.block_on(watchdog.select2(Delay::new(Instant::now() + before))) {
+                Ok(Either::B((_, watchdog))) => watchdog,
+                _ => panic!("watchdog stopped before the end of the window"),
+            };
+        let held = runtime.operations();
+        match tokio_runtime.block_on(watchdog.select2(Delay::new(Instant::now() + after))) {
+            Ok(Either::B(_)) => (),
+            _ => panic!("watchdog stopped before the end of the window"),
+        }
+        (held, runtime.operations())
+    }
+
+    // A daily maintenance window of an hour that opens `opens_in` from now.
+    fn maintenance_window(opens_in: chrono::Duration) -> MaintenanceWindows {
+        let start = (Utc::now() + opens_in).time();
+        MaintenanceWindows::new(
+            TimeZone::Fixed(FixedOffset::east(0)),
+            vec![MaintenanceWindow::new(
+                vec![],
+                start,
+                start + chrono::Duration::hours(1),
+            )],
+        )
+    }
+
+    #[test]
+    fn deferred_update_is_applied_once_maintenance_window_opens() {
+        let (held, applied) = update_with_maintenance(
+            maintenance_window(chrono::Duration::seconds(1)),
+            agent_spec(&[("RuntimeLogLevel", "debug")]),
+            Duration::from_millis(300),
+            Duration::from_millis(1500),
+        );
+
+        assert!(held.is_empty(), "updated before the window: {:?}", held);
+        assert_eq!(
+            vec![
+                "stop edgeAgent",
+                "remove edgeAgent",
+                "create edgeAgent",
+                "start edgeAgent",
+            ],
+            applied
+        );
+    }
+
+    #[test]
+    fn critical_update_is_applied_outside_maintenance_windows() {
+        let mut labels = HashMap::new();
+        labels.insert(CRITICAL_UPDATE_LABEL.to_string(), "true".to_string());
+        let critical = agent_spec(&[("RuntimeLogLevel", "debug")]).with_labels(labels);
+
+        let (applied, _) = update_with_maintenance(
+            maintenance_window(chrono::Duration::hours(1)),
+            critical,
+            Duration::from_millis(300),
+            Duration::from_millis(10),
+        );
+
+        assert_eq!(
+            vec![
+                "stop edgeAgent",
+                "remove edgeAgent",
+                "create edgeAgent",
+                "start edgeAgent",
+            ],
+            applied
+        );
+    }
+
+    #[test]
+    fn adopting_running_runtime_after_upgrade_is_a_no_op() {
+        let runtime = TestRuntime::new(&["edgeAgent", "tempSensor"]);
+        let prepared = runtime.list_with_details().collect().wait().unwrap();
+        let state = UpgradeState::from_modules(&prepared);
+
+        // the daemon restarted with the containers still running
+        let running = runtime.list_with_details().collect().wait().unwrap();
+        assert_eq!(vec!["edgeAgent", "tempSensor"], state.adopt(&running));
+
+        let check = check_runtime(
+            runtime.clone(),
+            id_mgr(),
+            agent_spec(&[]),
+            "$edgeAgent".to_string(),
+        )
+        .wait()
+        .unwrap();
+        assert_eq!(RuntimeCheck::Running(None), check);
+        assert!(runtime.operations().is_empty());
+    }
+
+    // A module that ran and exited with `exit_code`, after the container
+    // runtime restarted it `restarts` times.
+    fn exited(exit_code: i64, restarts: u32) -> ModuleRuntimeState {
+        let status = if exit_code == 0 {
+            ModuleStatus::Stopped
+        } else {
+            ModuleStatus::Failed
+        };
+        ModuleRuntimeState::default()
+            .with_status(status)
+            .with_exit_code(Some(exit_code))
+            .with_status_description(Some("exited".to_string()))
+            .with_finished_at(Some(Utc::now()))
+            .with_restart_count(Some(restarts))
+    }
+
+    #[test]
+    fn never_restart_policy_leaves_exited_module_alone() {
+        let created = ModuleRuntimeState::default().with_status(ModuleStatus::Stopped);
+        assert_eq!(
+            RuntimeCheck::Started,
+            stopped_check(RestartPolicy::Never, None, &created)
+        );
+        assert_eq!(
+            RuntimeCheck::Exited,
+            stopped_check(RestartPolicy::Never, None, &exited(0, 0))
+        );
+        assert_eq!(
+            RuntimeCheck::Exited,
+            stopped_check(RestartPolicy::Never, None, &exited(1, 0))
+        );
+        assert_eq!(
+            RuntimeCheck::Started,
+            stopped_check(RestartPolicy::Always, None, &exited(0, 0))
+        );
+
+        let runtime = TestRuntime::new(&["edgeAgent"]).with_state(&exited(0, 0));
+        let check = check_runtime(
+            runtime.clone(),
+            id_mgr(),
+            agent_spec(&[]).with_restart_policy(RestartPolicy::Never),
+            "$edgeAgent".to_string(),
+        )
+        .wait()
+        .unwrap();
+        assert_eq!(RuntimeCheck::Exited, check);
+        assert!(runtime.operations().is_empty());
+    }
+
+    #[test]
+    fn on_failure_restart_policy_restarts_failed_module_up_to_max_retries() {
+        let policy = RestartPolicy::OnFailure;
+        assert_eq!(
+            RuntimeCheck::Started,
+            stopped_check(policy, Some(3), &exited(1, 2))
+        );
+        assert_eq!(
+            RuntimeCheck::Started,
+            stopped_check(policy, None, &exited(1, 10))
+        );
+
+        // The container runtime restarts it under the same policy, which the
+        // watchdog leaves it to.
+        let restarting = exited(1, 1).with_status_description(Some("restarting".to_string()));
+        assert_eq!(
+            RuntimeCheck::Restarting,
+            stopped_check(policy, Some(3), &restarting)
+        );
+
+        // A module that completed is done.
+        assert_eq!(
+            RuntimeCheck::Exited,
+            stopped_check(policy, Some(3), &exited(0, 0))
+        );
+    }
+
+    #[test]
+    fn on_failure_module_that_used_up_its_retries_stays_failed() {
+        assert_eq!(
+            RuntimeCheck::Exited,
+            stopped_check(RestartPolicy::OnFailure, Some(3), &exited(1, 3))
+        );
+
+        let runtime = TestRuntime::new(&["edgeAgent"]).with_state(&exited(1, 3));
+        let spec = agent_spec(&[])
+            .with_restart_policy(RestartPolicy::OnFailure)
+            .with_max_retries(Some(3));
+        for _ in 0..3 {
+            let check = check_runtime(
+                runtime.clone(),
+                id_mgr(),
+                spec.clone(),
+                "$edgeAgent".to_string(),
+            )
+            .wait()
+            .unwrap();
+            assert_eq!(RuntimeCheck::Exited, check);
+        }
+        assert!(runtime.operations().is_empty());
+    }
+
+    #[test]
+    fn oom_killed_module_is_started_again() {
+        let killed = exited(137, 0).with_exit_reason(Some(ExitReason::OomKilled));
+        let runtime = TestRuntime::new(&["edgeAgent"]).with_state(&killed);
+        let check = check_runtime(
+            runtime.clone(),
+            id_mgr(),
+            agent_spec(&[]),
+            "$edgeAgent".to_string(),
+        )
+        .wait()
+        .unwrap();
+        assert_eq!(RuntimeCheck::StartedAfterOomKill, check);
+        assert_eq!(vec!["start edgeAgent".to_string()], runtime.operations());
+    }
 }
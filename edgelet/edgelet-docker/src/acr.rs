@@ -0,0 +1,619 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Gets tokens to pull images from Azure Container Registry with an Azure AD
+//! identity, instead of with credentials in the deployment.
+//!
+//! The identity, a service principal or the managed identity of the device,
+//! gets an AAD token that the registry exchanges for a refresh token. The
+//! refresh token is cached until shortly before it expires, and is exchanged
+//! for an access token that can only pull the repository of the image being
+//! pulled. Neither the tokens nor the client secret are ever logged.
+
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use chrono::Utc;
+use failure::{Fail, ResultExt};
+use futures::future::{self, Either};
+use futures::{Future, Stream};
+use hyper::header::CONTENT_TYPE;
+use hyper::{Body, Request, Uri};
+use log::debug;
+use serde::de::DeserializeOwned;
+use url::form_urlencoded;
+
+use docker::models::AuthConfig;
+use edgelet_http::client::ClientImpl;
+use edgelet_http::MaybeProxyClient;
+
+use crate::credentials::{registry_host, token_expiry, EXPIRY_MARGIN_SECS};
+use crate::error::{Error, ErrorKind};
+
+const DEFAULT_AUTHORITY_HOST: &str = "https://login.microsoftonline.com";
+const IMDS_TOKEN_URI: &str = "http://169.254.169.254/metadata/identity/oauth2/token";
+const IMDS_API_VERSION: &str = "2018-02-01";
+const ARM_RESOURCE: &str = "https://management.azure.com/";
+const ARM_SCOPE: &str = "https://management.azure.com/.default";
+
+/// A client secret, which is never printed.
+#[derive(Clone, PartialEq, serde_derive::Deserialize, serde_derive::Serialize)]
+#[serde(transparent)]
+pub struct ClientSecret(String);
+
+impl ClientSecret {
+    pub fn new(secret: String) -> Self {
+        ClientSecret(secret)
+    }
+}
+
+impl fmt::Debug for ClientSecret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+/// The Azure AD identity that gets tokens for a registry.
+#[derive(Clone, Debug, PartialEq, serde_derive::Deserialize, serde_derive::Serialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum AcrIdentity {
+    ServicePrincipal {
+        tenant_id: String,
+        client_id: String,
+        client_secret: ClientSecret,
+        /// The AAD authority of clouds other than the public one, like
+        /// `https://login.chinacloudapi.cn`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        authority_host: Option<String>,
+    },
+    /// The managed identity of the device, from the Azure Instance Metadata
+    /// Service. `client_id` picks one of several user-assigned identities.
+    ManagedIdentity {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        client_id: Option<String>,
+    },
+}
+
+impl AcrIdentity {
+    fn tenant_id(&self) -> Option<&str> {
+        match self {
+            AcrIdentity::ServicePrincipal { tenant_id, .. } => Some(tenant_id),
+            AcrIdentity::ManagedIdentity { .. } => None,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct AcrTokens {
+    identities: HashMap<String, AcrIdentity>,
+    client: MaybeProxyClient,
+    refresh_tokens: Arc<Mutex<HashMap<String, CachedToken>>>,
+}
+
+struct CachedToken {
+    token: String,
+    expires_at: i64,
+}
+
+#[derive(serde_derive::Deserialize)]
+struct AccessTokenResponse {
+    access_token: String,
+}
+
+#[derive(serde_derive::Deserialize)]
+struct RefreshTokenResponse {
+    refresh_token: String,
+}
+
+impl AcrTokens {
+    /// `identities` maps registry hosts to the identity that gets tokens for
+    /// each. Requests go through `https_proxy`, or else the proxy in
+    /// `HTTPS_PROXY`, if any.
+    pub fn new(
+        identities: HashMap<String, AcrIdentity>,
+        https_proxy: Option<&str>,
+    ) -> Result<Self, Error> {
+        Ok(AcrTokens {
+            identities,
+            client: https_client(https_proxy)?,
+            refresh_tokens: Arc::new(Mutex::new(HashMap::new())),
+        })
+    }
+
+    /// Credentials that can only pull `image`, or `None` if its registry has
+    /// no identity.
+    pub fn auth(
+        &self,
+        image: &str,
+    ) -> Option<impl Future<Item = AuthConfig, Error = Error> + Send> {
+        let registry = registry_host(image).to_string();
+        let identity = self.identities.get(&registry)?.clone();
+        let scope = format!("repository:{}:pull", repository(image));
+        let base = registry_url(&registry);
+
+        let refresh_token = match self.cached_refresh_token(&registry) {
+            Some(token) => {
+                debug!("Using cached refresh token of registry {}", registry);
+                Either::A(future::ok(token))
+            }
+            None => {
+                debug!("Getting a refresh token for registry {}", registry);
+                let client = self.client.clone();
+                let refresh_tokens = self.refresh_tokens.clone();
+                let base = base.clone();
+                let registry = registry.clone();
+                Either::B(
+                    aad_token(&self.client, &identity)
+                        .and_then(move |aad_token| {
+                            exchange_aad_token(
+                                &client,
+                                &base,
+                                &registry,
+                                identity.tenant_id(),
+                                &aad_token,
+                            )
+                            .map(move |refresh_token| (registry, refresh_token))
+                        })
+                        .map(move |(registry, refresh_token)| {
+                            if let Some(expires_at) = token_expiry(&refresh_token) {
+                                refresh_tokens
+                                    .lock()
+                                    .expect("refresh token cache lock poisoned")
+                                    .insert(
+                                        registry,
+                                        CachedToken {
+                                            token: refresh_token.clone(),
+                                            expires_at,
+                                        },
+                                    );
+                            }
+                            refresh_token
+                        }),
+                )
+            }
+        };
+
+        let client = self.client.clone();
+        let server_address = registry.clone();
+        let auth = refresh_token
+            .and_then(move |refresh_token| {
+                access_token(&client, &base, &server_address, &scope, &refresh_token).map(
+                    move |access_token| {
+                        AuthConfig::new()
+                            .with_registrytoken(access_token)
+                            .with_serveraddress(server_address)
+                    },
+                )
+            })
+            .map_err(move |err| Error::from(err.context(ErrorKind::AcrToken(registry))));
+        Some(auth)
+    }
+
+    /// Forgets the refresh token of the registry of `image`, so that the next
+    /// pull gets a new one. Returns whether the registry has an identity.
+    pub fn invalidate(&self, image: &str) -> bool {
+        let registry = registry_host(image);
+        self.refresh_tokens
+            .lock()
+            .expect("refresh token cache lock poisoned")
+            .remove(registry);
+        self.identities.contains_key(registry)
+    }
+
+    fn cached_refresh_token(&self, registry: &str) -> Option<String> {
+        let refresh_tokens = self
+            .refresh_tokens
+            .lock()
+            .expect("refresh token cache lock poisoned");
+        refresh_tokens
+            .get(registry)
+            .filter(|cached| Utc::now().timestamp() < cached.expires_at - EXPIRY_MARGIN_SECS)
+            .map(|cached| cached.token.clone())
+    }
+}
+
+fn aad_token(
+    client: &MaybeProxyClient,
+    identity: &AcrIdentity,
+) -> impl Future<Item = String, Error = Error> + Send {
+    let request = match identity {
+        AcrIdentity::ServicePrincipal {
+            tenant_id,
+            client_id,
+            client_secret,
+            authority_host,
+        } => {
+            let authority_host = authority_host
+                .as_ref()
+                .map_or(DEFAULT_AUTHORITY_HOST, String::as_str)
+                .trim_end_matches('/');
+            let uri = format!("{}/{}/oauth2/v2.0/token", authority_host, tenant_id);
+            post_form(
+                &uri,
+                &[
+                    ("grant_type", "client_credentials"),
+                    ("client_id", client_id),
+                    ("client_secret", &client_secret.0),
+                    ("scope", ARM_SCOPE),
+                ],
+            )
+            .map(|request| (uri, request))
+        }
+        AcrIdentity::ManagedIdentity { client_id } => {
+            let mut query = form_urlencoded::Serializer::new(String::new());
+            query
+                .append_pair("api-version", IMDS_API_VERSION)
+                .append_pair("resource", ARM_RESOURCE);
+            if let Some(client_id) = client_id {
+                query.append_pair("client_id", client_id);
+            }
+            Request::get(format!("{}?{}", IMDS_TOKEN_URI, query.finish()))
+                .header("Metadata", "true")
+                .body(Body::empty())
+                .with_context(|_| ErrorKind::TokenRequest(IMDS_TOKEN_URI.to_string()))
+                .map(|request| (IMDS_TOKEN_URI.to_string(), request))
+                .map_err(Error::from)
+        }
+    };
+
+    match request {
+        Ok((uri, request)) => Either::A(
+            send::<AccessTokenResponse>(client, uri, request).map(|response| response.access_token),
+        ),
+        Err(err) => Either::B(future::err(err)),
+    }
+}
+
+fn exchange_aad_token(
+    client: &MaybeProxyClient,
+    base: &str,
+    registry: &str,
+    tenant_id: Option<&str>,
+    aad_token: &str,
+) -> impl Future<Item = String, Error = Error> + Send {
+    let uri = format!("{}/oauth2/exchange", base);
+    let mut form = vec![
+        ("grant_type", "access_token"),
+        ("service", registry),
+        ("access_token", aad_token),
+    ];
+    if let Some(tenant_id) = tenant_id {
+        form.push(("tenant", tenant_id));
+    }
+
+    match post_form(&uri, &form) {
+        Ok(request) => Either::A(
+            send::<RefreshTokenResponse>(client, uri, request)
+                .map(|response| response.refresh_token),
+        ),
+        Err(err) => Either::B(future::err(err)),
+    }
+}
+
+fn access_token(
+    client: &MaybeProxyClient,
+    base: &str,
+    registry: &str,
+    scope: &str,
+    refresh_token: &str,
+) -> impl Future<Item = String, Error = Error> + Send {
+    let uri = format!("{}/oauth2/token", base);
+    let form = [
+        ("grant_type", "refresh_token"),
+        ("service", registry),
+        ("scope", scope),
+        ("refresh_token", refresh_token),
+    ];
+
+    match post_form(&uri, &form) {
+        Ok(request) => Either::A(
+            send::<AccessTokenResponse>(client, uri, request).map(|response| response.access_token),
+        ),
+        Err(err) => Either::B(future::err(err)),
+    }
+}
+
+fn post_form(uri: &str, form: &[(&str, &str)]) -> Result<Request<Body>, Error> {
+    let body = form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(form)
+        .finish();
+    let request = Request::post(uri)
+        .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(body.into())
+        .with_context(|_| ErrorKind::TokenRequest(uri.to_string()))?;
+    Ok(request)
+}
+
+// Errors only have the URI and status of the request, since the bodies of
+// requests and responses have secrets.
+fn send<T>(
+    client: &MaybeProxyClient,
+    uri: String,
+    request: Request<Body>,
+) -> impl Future<Item = T, Error = Error> + Send
+where
+    T: 'static + DeserializeOwned + Send,
+{
+    client
+        .call(request)
+        .and_then(|response| {
+            let status = response.status();
+            response
+                .into_body()
+                .concat2()
+                .map(move |body| (status, body))
+        })
+        .then(move |result| -> Result<T, Error> {
+            let (status, body) = result.with_context(|_| ErrorKind::TokenRequest(uri.clone()))?;
+            if !status.is_success() {
+                return Err(Error::from(ErrorKind::TokenRequestFailed(uri, status)));
+            }
+            let response =
+                serde_json::from_slice(&body).with_context(|_| ErrorKind::TokenRequest(uri))?;
+            Ok(response)
+        })
+}
+
+/// A client for requests to registries and identity providers, through
+/// `https_proxy`, or else the proxy in `HTTPS_PROXY`, if any.
+pub(crate) fn https_client(https_proxy: Option<&str>) -> Result<MaybeProxyClient, Error> {
+    let proxy = https_proxy
+        .map(ToString::to_string)
+        .or_else(|| env::var("HTTPS_PROXY").ok())
+        .or_else(|| env::var("https_proxy").ok())
+        .map(|proxy| proxy.parse::<Uri>().context(ErrorKind::Initialization))
+        .transpose()?;
+    let client = MaybeProxyClient::new(proxy, None, None).context(ErrorKind::Initialization)?;
+    Ok(client)
+}
+
+// Registries on the loopback interface are reached over HTTP, as Docker does.
+pub(crate) fn registry_url(registry: &str) -> String {
+    let host = match registry.rfind(':') {
+        Some(i) if !registry.ends_with(']') => &registry[..i],
+        _ => registry,
+    };
+    if host == "localhost" || host == "[::1]" || host.starts_with("127.") {
+        format!("http://{}", registry)
+    } else {
+        format!("https://{}", registry)
+    }
+}
+
+// The repository of `image` in its registry, without the registry, tag or
+// digest.
+pub(crate) fn repository(image: &str) -> &str {
+    let image = image.split('@').next().unwrap_or(image);
+    let registry = registry_host(image);
+    let path = if image.starts_with(registry) && image[registry.len()..].starts_with('/') {
+        &image[registry.len() + 1..]
+    } else {
+        image
+    };
+    match path.rfind(':') {
+        Some(i) => &path[..i],
+        None => path,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::RwLock;
+
+    use hyper::{Method, Response, StatusCode};
+    use maplit::btreemap;
+    use serde_json::json;
+    use url::form_urlencoded::parse as parse_query;
+
+    use edgelet_test_utils::web::{
+        make_req_dispatcher, HttpMethod, RequestHandler, RequestPath, ResponseFuture,
+    };
+    use edgelet_test_utils::{routes, run_tcp_server};
+
+    use super::*;
+
+    // A refresh token that is a JWT expiring in an hour.
+    fn refresh_token() -> String {
+        let claims = base64::encode_config(
+            &format!(r#"{{"exp":{}}}"#, Utc::now().timestamp() + 3600),
+            base64::URL_SAFE_NO_PAD,
+        );
+        format!("eyJhbGciOiJSUzI1NiJ9.{}.c2ln", claims)
+    }
+
+    fn parse_form(body: &[u8]) -> HashMap<String, String> {
+        parse_query(body).into_owned().collect()
+    }
+
+    fn json_response(status: StatusCode, body: serde_json::Value) -> ResponseFuture {
+        let mut response = Response::new(body.to_string().into());
+        *response.status_mut() = status;
+        Box::new(future::ok(response))
+    }
+
+    #[test]
+    fn registry_urls() {
+        assert_eq!(
+            "https://myregistry.azurecr.io",
+            registry_url("myregistry.azurecr.io")
+        );
+        assert_eq!("http://localhost:5000", registry_url("localhost:5000"));
+        assert_eq!("http://127.0.0.1:5000", registry_url("127.0.0.1:5000"));
+        assert_eq!("http://[::1]", registry_url("[::1]"));
+    }
+
+    #[test]
+    fn repositories() {
+        assert_eq!(
+            "samples/module",
+            repository("myregistry.azurecr.io/samples/module:1.0")
+        );
+        assert_eq!(
+            "module",
+            repository("localhost:5000/module@sha256:0123456789abcdef")
+        );
+        assert_eq!("library/nginx", repository("docker.io/library/nginx"));
+        assert_eq!("nginx", repository("nginx:1.17"));
+    }
+
+    #[test]
+    fn client_secret_is_not_printed() {
+        let identity = AcrIdentity::ServicePrincipal {
+            tenant_id: "tenant".to_string(),
+            client_id: "client".to_string(),
+            client_secret: ClientSecret::new("hunter2".to_string()),
+            authority_host: None,
+        };
+        assert!(!format!("{:?}", identity).contains("hunter2"));
+    }
+
+    #[test]
+    fn service_principal_token_is_exchanged_for_pull_token() {
+        let aad_requests = Arc::new(RwLock::new(0));
+        let aad_requests_copy = aad_requests.clone();
+        let refresh = refresh_token();
+        let expected_refresh = refresh.clone();
+
+        let dispatch_table = routes!(
+            POST "/tenant1/oauth2/v2.0/token" => move |req: Request<Body>| {
+                *aad_requests_copy.write().unwrap() += 1;
+                Box::new(req.into_body().concat2().and_then(|body| {
+                    let form = parse_form(&body);
+                    assert_eq!("client_credentials", form["grant_type"]);
+                    assert_eq!("client1", form["client_id"]);
+                    assert_eq!("hunter2", form["client_secret"]);
+                    assert_eq!(ARM_SCOPE, form["scope"]);
+                    json_response(StatusCode::OK, json!({ "access_token": "aad-token" }))
+                })) as ResponseFuture
+            },
+            POST "/oauth2/exchange" => move |req: Request<Body>| {
+                let refresh = refresh.clone();
+                Box::new(req.into_body().concat2().and_then(move |body| {
+                    let form = parse_form(&body);
+                    assert_eq!("access_token", form["grant_type"]);
+                    assert_eq!("aad-token", form["access_token"]);
+                    assert_eq!("tenant1", form["tenant"]);
+                    json_response(StatusCode::OK, json!({ "refresh_token": refresh }))
+                })) as ResponseFuture
+            },
+            POST "/oauth2/token" => move |req: Request<Body>| {
+                let expected_refresh = expected_refresh.clone();
+                Box::new(req.into_body().concat2().and_then(move |body| {
+                    let form = parse_form(&body);
+                    assert_eq!("refresh_token", form["grant_type"]);
+                    assert_eq!(expected_refresh, form["refresh_token"]);
+                    assert_eq!("repository:samples/module:pull", form["scope"]);
+                    json_response(StatusCode::OK, json!({ "access_token": "pull-token" }))
+                })) as ResponseFuture
+            },
+        );
+
+        let (server, port) = run_tcp_server(
+            "127.0.0.1",
+            make_req_dispatcher(dispatch_table, Box::new(not_found)),
+        );
+        let server = server.map_err(|err| panic!(err));
+
+        let registry = format!("localhost:{}", port);
+        let image = format!("{}/samples/module:1.0", registry);
+        let tokens = AcrTokens::new(
+            vec![(
+                registry.clone(),
+                AcrIdentity::ServicePrincipal {
+                    tenant_id: "tenant1".to_string(),
+                    client_id: "client1".to_string(),
+                    client_secret: ClientSecret::new("hunter2".to_string()),
+                    authority_host: Some(format!("http://localhost:{}", port)),
+                },
+            )]
+            .into_iter()
+            .collect(),
+            None,
+        )
+        .unwrap();
+
+        let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+        runtime.spawn(server);
+
+        for _ in 0..2 {
+            let auth = runtime.block_on(tokens.auth(&image).unwrap()).unwrap();
+            assert_eq!(Some("pull-token"), auth.registrytoken());
+            assert_eq!(Some(registry.as_str()), auth.serveraddress());
+        }
+
+        // The refresh token is cached
+        assert_eq!(1, *aad_requests.read().unwrap());
+
+        assert!(tokens.invalidate(&image));
+        runtime.block_on(tokens.auth(&image).unwrap()).unwrap();
+        assert_eq!(2, *aad_requests.read().unwrap());
+    }
+
+    #[test]
+    fn rejected_identity_fails() {
+        let dispatch_table = routes!(
+            POST "/tenant1/oauth2/v2.0/token" => |_| {
+                json_response(
+                    StatusCode::UNAUTHORIZED,
+                    json!({ "error": "invalid_client" }),
+                )
+            },
+        );
+
+        let (server, port) = run_tcp_server(
+            "127.0.0.1",
+            make_req_dispatcher(dispatch_table, Box::new(not_found)),
+        );
+        let server = server.map_err(|err| panic!(err));
+
+        let registry = format!("localhost:{}", port);
+        let tokens = AcrTokens::new(
+            vec![(
+                registry.clone(),
+                AcrIdentity::ServicePrincipal {
+                    tenant_id: "tenant1".to_string(),
+                    client_id: "client1".to_string(),
+                    client_secret: ClientSecret::new("hunter2".to_string()),
+                    authority_host: Some(format!("http://localhost:{}", port)),
+                },
+            )]
+            .into_iter()
+            .collect(),
+            None,
+        )
+        .unwrap();
+
+        let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+        runtime.spawn(server);
+        let err = runtime
+            .block_on(tokens.auth(&format!("{}/module:1.0", registry)).unwrap())
+            .unwrap_err();
+
+        match (err.kind(), err.cause().and_then(Fail::downcast_ref)) {
+            (
+                ErrorKind::AcrToken(failed_registry),
+                Some(ErrorKind::TokenRequestFailed(uri, StatusCode::UNAUTHORIZED)),
+            ) => {
+                assert_eq!(&registry, failed_registry);
+                assert!(uri.ends_with("/tenant1/oauth2/v2.0/token"), "{}", uri);
+            }
+            _ => panic!("Expected `TokenRequestFailed` error but got {:?}", err),
+        }
+        assert!(!err.to_string().contains("hunter2"));
+    }
+
+    #[test]
+    fn registry_without_identity_has_no_auth() {
+        let tokens = AcrTokens::new(HashMap::new(), None).unwrap();
+        assert!(tokens.auth("myregistry.azurecr.io/module:1.0").is_none());
+        assert!(!tokens.invalidate("myregistry.azurecr.io/module:1.0"));
+    }
+
+    #[allow(clippy::needless_pass_by_value)]
+    fn not_found(_: Request<Body>) -> ResponseFuture {
+        let mut response = Response::new(Body::empty());
+        *response.status_mut() = StatusCode::NOT_FOUND;
+        Box::new(future::ok(response))
+    }
+}
@@ -2,21 +2,205 @@
 
 use std::ops::Deref;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use failure::Fail;
+use futures::future::{self, Either, Loop};
+use futures::{Future, IntoFuture};
 use hyper::client::connect::Connect;
+use log::{warn, Level};
+use tokio::timer::{Delay, Timeout};
 
 use docker::apis::client::APIClient;
+use docker::apis::Error as DockerError;
+use docker::models::{ContainerCreateBody, ContainerSummary, InlineResponse200, InlineResponse201};
+use edgelet_utils::log_failure;
+
+use crate::error::{Error, ErrorKind};
+use crate::settings::RequestRetry;
 
 pub struct DockerClient<C: Connect> {
     client: Arc<APIClient<C>>,
+    request_timeout: Option<Duration>,
+    request_retry: Option<RequestRetry>,
+    container_prefix: String,
 }
 
-impl<C: Connect> DockerClient<C> {
+impl<C: 'static + Connect> DockerClient<C> {
     pub fn new(client: APIClient<C>) -> Self {
         DockerClient {
             client: Arc::new(client),
+            request_timeout: None,
+            request_retry: None,
+            container_prefix: String::new(),
+        }
+    }
+
+    /// Requests passed to `timeout` fail if Docker takes longer than
+    /// `request_timeout` to answer them. There is no limit if not set.
+    pub fn with_request_timeout(mut self, request_timeout: Duration) -> Self {
+        self.request_timeout = Some(request_timeout);
+        self
+    }
+
+    /// Requests passed to `retry` are sent again as `request_retry` says if
+    /// they fail before Docker answers them. They aren't retried if not set.
+    pub fn with_request_retry(mut self, request_retry: RequestRetry) -> Self {
+        self.request_retry = Some(request_retry);
+        self
+    }
+
+    /// The containers of modules are named `container_prefix` followed by the
+    /// name of the module, and only containers whose names start with it are
+    /// taken to be the containers of modules. Containers are named after their
+    /// modules if not set.
+    pub fn with_container_prefix(mut self, container_prefix: String) -> Self {
+        self.container_prefix = container_prefix;
+        self
+    }
+
+    /// The name of the container of the module `module`.
+    pub fn container_name(&self, module: &str) -> String {
+        format!("{}{}", self.container_prefix, module)
+    }
+
+    /// The name of the module that the container `container` is of, or `None`
+    /// if the name doesn't start with the container prefix. Docker reports the
+    /// names of containers with a leading `/`, which is ignored.
+    pub fn module_name<'a>(&self, container: &'a str) -> Option<&'a str> {
+        let container = container.trim_start_matches('/');
+        if container.starts_with(&self.container_prefix) {
+            Some(&container[self.container_prefix.len()..])
+        } else {
+            None
+        }
+    }
+
+    /// Sends the request that `request` makes, making and sending it again if
+    /// it fails before Docker answers it, like when Docker is restarting and
+    /// isn't listening yet. Answers are returned as they are, including errors
+    /// like `404 Not Found` and `409 Conflict` that a retry wouldn't change.
+    ///
+    /// Only requests that have the same effect however many times Docker gets
+    /// them must be retried, since a request can fail after Docker got it,
+    /// like when the connection is closed before the answer is read. This
+    /// includes creating a container with a name, which fails with
+    /// `409 Conflict` rather than creating a second container.
+    pub fn retry<R, F>(
+        &self,
+        mut request: R,
+    ) -> impl Future<Item = F::Item, Error = DockerError<serde_json::Value>>
+    where
+        R: FnMut() -> F,
+        F: Future<Error = DockerError<serde_json::Value>>,
+    {
+        let (max_attempts, backoff) = self
+            .request_retry
+            .as_ref()
+            .map_or((1, Duration::default()), |retry| {
+                (retry.max_attempts(), retry.backoff())
+            });
+
+        future::loop_fn((1, backoff), move |(attempt, backoff)| {
+            request().then(move |result| match result {
+                Err(DockerError::Hyper(ref err)) if attempt < max_attempts && is_transport(err) => {
+                    warn!(
+                        "Request to the container runtime failed, retrying in {:?} (attempt {} of {}): {}",
+                        backoff,
+                        attempt + 1,
+                        max_attempts,
+                        err
+                    );
+                    Either::A(Delay::new(Instant::now() + backoff).then(
+                        move |_| -> Result<_, DockerError<serde_json::Value>> {
+                            Ok(Loop::Continue((attempt + 1, backoff * 2)))
+                        },
+                    ))
+                }
+                result => Either::B(result.map(Loop::Break).into_future()),
+            })
+        })
+    }
+
+    /// Fails `request` with `ErrorKind::RuntimeTimeout`, in the context
+    /// returned by `context`, if it doesn't finish within the request timeout.
+    /// `grace` is added to the timeout for requests that are expected to keep
+    /// Docker busy for that long, like stopping a container.
+    pub fn timeout<F, G>(
+        &self,
+        request: F,
+        grace: Duration,
+        context: G,
+    ) -> impl Future<Item = F::Item, Error = Error>
+    where
+        F: Future<Error = Error>,
+        G: FnOnce() -> ErrorKind,
+    {
+        match self.request_timeout {
+            Some(request_timeout) => {
+                let timeout = request_timeout + grace;
+                Either::A(Timeout::new(request, timeout).map_err(move |err| {
+                    match err.into_inner() {
+                        Some(err) => err,
+                        // Either the request took too long, or the timer was
+                        // shut down and it can't be waited on any longer.
+                        None => {
+                            let err =
+                                Error::from(ErrorKind::RuntimeTimeout(timeout).context(context()));
+                            log_failure(Level::Warn, &err);
+                            err
+                        }
+                    }
+                }))
+            }
+            None => Either::B(request),
         }
     }
+
+    /// Creates the container of the module `name`, retrying like `retry`.
+    pub fn create_container(
+        &self,
+        create_options: ContainerCreateBody,
+        name: &str,
+    ) -> impl Future<Item = InlineResponse201, Error = DockerError<serde_json::Value>> {
+        let client = self.client.clone();
+        let name = self.container_name(name);
+        self.retry(move || {
+            client
+                .container_api()
+                .container_create(create_options.clone(), &name)
+        })
+    }
+
+    /// Inspects the container of the module `id`, retrying like `retry`.
+    pub fn inspect_container(
+        &self,
+        id: &str,
+    ) -> impl Future<Item = InlineResponse200, Error = DockerError<serde_json::Value>> {
+        let client = self.client.clone();
+        let id = self.container_name(id);
+        self.retry(move || client.container_api().container_inspect(&id, false))
+    }
+
+    /// Lists all the containers that match `filters`, retrying like `retry`.
+    pub fn list_containers(
+        &self,
+        filters: &str,
+    ) -> impl Future<Item = Vec<ContainerSummary>, Error = DockerError<serde_json::Value>> {
+        let client = self.client.clone();
+        let filters = filters.to_string();
+        self.retry(move || {
+            client
+                .container_api()
+                .container_list(true, 0, false, &filters)
+        })
+    }
+}
+
+// Whether the request failed without an answer from Docker, rather than
+// because of the request itself or an answer that couldn't be read.
+fn is_transport(err: &hyper::Error) -> bool {
+    !err.is_user() && !err.is_parse()
 }
 
 impl<C: Connect> Deref for DockerClient<C> {
@@ -31,6 +215,9 @@ impl<C: Connect> Clone for DockerClient<C> {
     fn clone(&self) -> Self {
         DockerClient {
             client: self.client.clone(),
+            request_timeout: self.request_timeout,
+            request_retry: self.request_retry.clone(),
+            container_prefix: self.container_prefix.clone(),
         }
     }
 }
@@ -1,11 +1,19 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::result::Result as StdResult;
+
 use failure::ResultExt;
+use serde::de::{self, Deserialize, Deserializer};
 
 use docker::models::{AuthConfig, ContainerCreateBody};
+use edgelet_core::{normalize_create_options, ImageReference};
 use edgelet_utils::{ensure_not_empty_with_context, serde_clone};
 
-use crate::error::{ErrorKind, Result};
+use crate::error::{Error, ErrorKind, Result};
+
+const REDACTED: &str = "***";
 
 #[derive(Debug, serde_derive::Serialize, serde_derive::Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -14,10 +22,113 @@ pub struct DockerConfig {
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(rename = "imageHash")]
     image_id: Option<String>,
-    #[serde(default = "ContainerCreateBody::new")]
+    #[serde(
+        default = "ContainerCreateBody::new",
+        deserialize_with = "deserialize_create_options"
+    )]
     create_options: ContainerCreateBody,
     #[serde(skip_serializing_if = "Option::is_none")]
-    auth: Option<AuthConfig>,
+    auth: Option<RegistryAuth>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_pull_rate: Option<u64>,
+}
+
+/// The credentials of the registry of the image. The username and password
+/// can be in files instead, which are read whenever the image is pulled, so
+/// that they aren't in the config, the deployment or their backups.
+#[derive(Debug, serde_derive::Serialize, serde_derive::Deserialize, Clone)]
+pub struct RegistryAuth {
+    #[serde(flatten)]
+    auth: AuthConfig,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    username_file: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    password_file: Option<PathBuf>,
+}
+
+impl RegistryAuth {
+    pub fn username_file(&self) -> Option<&Path> {
+        self.username_file.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn with_username_file(mut self, username_file: PathBuf) -> Self {
+        self.username_file = Some(username_file);
+        self
+    }
+
+    pub fn password_file(&self) -> Option<&Path> {
+        self.password_file.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn with_password_file(mut self, password_file: PathBuf) -> Self {
+        self.password_file = Some(password_file);
+        self
+    }
+
+    /// Whether there is both a username and a password, inline or in a file.
+    pub fn is_complete(&self) -> bool {
+        let present = |value: Option<&str>, file: Option<&PathBuf>| {
+            value.map_or(false, |value| !value.is_empty()) || file.is_some()
+        };
+        present(self.auth.username(), self.username_file.as_ref())
+            && present(self.auth.password(), self.password_file.as_ref())
+    }
+
+    /// The credentials with the username and password read from their
+    /// files, which take precedence over the inline ones.
+    pub fn read(&self) -> Result<AuthConfig> {
+        let mut auth = self.auth.clone();
+        if let Some(path) = &self.username_file {
+            auth.set_username(read_credential_file(path)?);
+        }
+        if let Some(path) = &self.password_file {
+            auth.set_password(read_credential_file(path)?);
+        }
+        Ok(auth)
+    }
+}
+
+impl From<AuthConfig> for RegistryAuth {
+    fn from(auth: AuthConfig) -> Self {
+        RegistryAuth {
+            auth,
+            username_file: None,
+            password_file: None,
+        }
+    }
+}
+
+impl Default for RegistryAuth {
+    fn default() -> Self {
+        AuthConfig::new().into()
+    }
+}
+
+// Reads a credential from the file at `path`, without the line ending that
+// editors add. On Unix the file must not be accessible by anyone but its
+// owner, the user the daemon runs as. The credential itself is never part of
+// an error.
+fn read_credential_file(path: &Path) -> Result<String> {
+    let display = path.display().to_string();
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let metadata =
+            fs::metadata(path).with_context(|_| ErrorKind::CredentialFile(display.clone()))?;
+        if metadata.permissions().mode() & 0o077 != 0 {
+            return Err(Error::from(ErrorKind::CredentialFilePermissions(display)));
+        }
+    }
+
+    let credential =
+        fs::read_to_string(path).with_context(|_| ErrorKind::CredentialFile(display.clone()))?;
+    let credential = credential.trim_end_matches(&['\r', '\n'][..]);
+    if credential.is_empty() {
+        return Err(Error::from(ErrorKind::CredentialFile(display)));
+    }
+    Ok(credential.to_string())
 }
 
 impl DockerConfig {
@@ -32,7 +143,8 @@ impl DockerConfig {
             image,
             image_id: None,
             create_options,
-            auth,
+            auth: auth.map(RegistryAuth::from),
+            max_pull_rate: None,
         };
         Ok(config)
     }
@@ -72,14 +184,79 @@ impl DockerConfig {
         self.create_options = create_options;
     }
 
+    /// The inline credentials of the registry, without the ones in files.
     pub fn auth(&self) -> Option<&AuthConfig> {
-        self.auth.as_ref()
+        self.auth.as_ref().map(|auth| &auth.auth)
     }
 
     pub fn with_auth(mut self, auth: AuthConfig) -> Self {
+        self.auth = Some(auth.into());
+        self
+    }
+
+    pub fn registry_auth(&self) -> Option<&RegistryAuth> {
+        self.auth.as_ref()
+    }
+
+    pub fn with_registry_auth(mut self, auth: RegistryAuth) -> Self {
         self.auth = Some(auth);
         self
     }
+
+    /// The credentials to pull the image with, reading the ones in files.
+    pub fn read_auth(&self) -> Result<Option<AuthConfig>> {
+        self.auth.as_ref().map(RegistryAuth::read).transpose()
+    }
+
+    /// The download rate in bytes per second that pulls of the image are
+    /// capped at, in place of the runtime's.
+    pub fn max_pull_rate(&self) -> Option<u64> {
+        self.max_pull_rate
+    }
+
+    pub fn with_max_pull_rate(mut self, max_pull_rate: u64) -> Self {
+        self.max_pull_rate = Some(max_pull_rate);
+        self
+    }
+
+    /// This config with the password and tokens of its registry credentials
+    /// replaced, so that it can be shown. The paths of credential files are
+    /// kept.
+    pub fn redacted(&self) -> Self {
+        let mut config = self.clone();
+        if let Some(auth) = &mut config.auth {
+            let auth = &mut auth.auth;
+            if auth.password().is_some() {
+                auth.set_password(REDACTED.to_string());
+            }
+            if auth.identitytoken().is_some() {
+                auth.set_identitytoken(REDACTED.to_string());
+            }
+            if auth.registrytoken().is_some() {
+                auth.set_registrytoken(REDACTED.to_string());
+            }
+        }
+        config
+    }
+}
+
+// Fields whose names are only off in case are renamed to the names that the
+// daemon looks them up by, and fields of the wrong type are rejected with the
+// name of the field. Unknown keys are kept and reported when the module is
+// validated.
+fn deserialize_create_options<'de, D>(deserializer: D) -> StdResult<ContainerCreateBody, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let mut create_options = serde_json::Value::deserialize(deserializer)?;
+    normalize_create_options(&mut create_options).map_err(de::Error::custom)?;
+    serde_json::from_value(create_options).map_err(de::Error::custom)
+}
+
+impl ImageReference for DockerConfig {
+    fn image(&self) -> &str {
+        &self.image
+    }
 }
 
 #[cfg(test)]
@@ -200,6 +377,28 @@ mod tests {
         );
     }
 
+    #[test]
+    fn redacted_config_has_no_credentials() {
+        let auth_config = AuthConfig::new()
+            .with_username("username".to_string())
+            .with_password("password".to_string())
+            .with_identitytoken("token".to_string());
+        let config = DockerConfig::new(
+            "ubuntu".to_string(),
+            ContainerCreateBody::new(),
+            Some(auth_config),
+        )
+        .unwrap();
+
+        let redacted = config.redacted();
+        let auth = redacted.auth().unwrap();
+        assert_eq!(Some("username"), auth.username());
+        assert_eq!(Some("***"), auth.password());
+        assert_eq!(Some("***"), auth.identitytoken());
+        assert_eq!(None, auth.registrytoken());
+        assert_eq!(Some("password"), config.auth().unwrap().password());
+    }
+
     #[test]
     fn docker_config_deser_no_create_options() {
         let input_json = json!({
@@ -296,4 +495,142 @@ mod tests {
             "27017"
         );
     }
+
+    #[test]
+    fn docker_config_deser_normalizes_create_options_case() {
+        let input_json = json!({
+            "image": "ubuntu",
+            "createOptions": {
+                "hostConfig": {
+                    "Privileged": true
+                }
+            }
+        });
+
+        let config: DockerConfig = serde_json::from_value(input_json).unwrap();
+        assert_eq!(
+            Some(&true),
+            config.create_options.host_config().unwrap().privileged()
+        );
+    }
+
+    #[test]
+    fn docker_config_deser_rejects_create_option_of_wrong_type() {
+        let input_json = json!({
+            "image": "ubuntu",
+            "createOptions": {
+                "HostConfig": "host"
+            }
+        });
+
+        let err = serde_json::from_value::<DockerConfig>(input_json).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("create option \"HostConfig\" must be an object"),
+            "{}",
+            err
+        );
+    }
+
+    fn credential_file(dir: &std::path::Path, name: &str, credential: &str) -> PathBuf {
+        let path = dir.join(name);
+        fs::write(&path, credential).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        }
+        path
+    }
+
+    #[test]
+    fn credentials_are_read_from_files() {
+        let dir = tempdir::TempDir::new("auth").unwrap();
+        let username_file = credential_file(dir.path(), "username", "username\n");
+        let password_file = credential_file(dir.path(), "password", "password\r\n");
+
+        let input_json = json!({
+            "image": "ubuntu",
+            "auth": {
+                "username_file": username_file,
+                "password_file": password_file,
+                "serveraddress": "repo.azurecr.io"
+            }
+        });
+        let config: DockerConfig = serde_json::from_value(input_json.clone()).unwrap();
+        assert!(config.registry_auth().unwrap().is_complete());
+
+        let auth = config.read_auth().unwrap().unwrap();
+        assert_eq!(Some("username"), auth.username());
+        assert_eq!(Some("password"), auth.password());
+        assert_eq!(Some("repo.azurecr.io"), auth.serveraddress());
+
+        // Only the paths are kept in the config.
+        assert_eq!(
+            input_json["auth"],
+            serde_json::to_value(&config).unwrap()["auth"]
+        );
+    }
+
+    #[test]
+    fn missing_credential_file_fails() {
+        let dir = tempdir::TempDir::new("auth").unwrap();
+        let username_file = credential_file(dir.path(), "username", "username");
+
+        let auth = RegistryAuth::default()
+            .with_username_file(username_file)
+            .with_password_file(dir.path().join("password"));
+        let config = DockerConfig::new("ubuntu".to_string(), ContainerCreateBody::new(), None)
+            .unwrap()
+            .with_registry_auth(auth);
+
+        let err = config.read_auth().unwrap_err();
+        match err.kind() {
+            ErrorKind::CredentialFile(path) => {
+                assert_eq!(&dir.path().join("password").display().to_string(), path)
+            }
+            kind => panic!("Expected `CredentialFile` error but got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn empty_credential_file_fails() {
+        let dir = tempdir::TempDir::new("auth").unwrap();
+        let password_file = credential_file(dir.path(), "password", "\n");
+
+        let auth = RegistryAuth::from(AuthConfig::new().with_username("username".to_string()))
+            .with_password_file(password_file.clone());
+
+        let err = auth.read().unwrap_err();
+        match err.kind() {
+            ErrorKind::CredentialFile(path) => {
+                assert_eq!(&password_file.display().to_string(), path)
+            }
+            kind => panic!("Expected `CredentialFile` error but got {:?}", kind),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn credential_file_readable_by_others_fails() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempdir::TempDir::new("auth").unwrap();
+        let password_file = credential_file(dir.path(), "password", "password");
+        fs::set_permissions(&password_file, fs::Permissions::from_mode(0o644)).unwrap();
+
+        let auth = RegistryAuth::from(AuthConfig::new().with_username("username".to_string()))
+            .with_password_file(password_file.clone());
+
+        let err = auth.read().unwrap_err();
+        match err.kind() {
+            ErrorKind::CredentialFilePermissions(path) => {
+                assert_eq!(&password_file.display().to_string(), path)
+            }
+            kind => panic!(
+                "Expected `CredentialFilePermissions` error but got {:?}",
+                kind
+            ),
+        }
+    }
 }
@@ -0,0 +1,408 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Gets registry credentials from Docker credential helpers, the
+//! `docker-credential-<name>` programs that print the credentials of the
+//! registry whose address they're given on stdin.
+//!
+//! Helpers are configured per registry host in `moby_runtime.credential_helpers`,
+//! and are run every time an image of their registry is pulled, so that
+//! rotated passwords don't have to be put into deployments. Credentials whose
+//! secret is a token with an expiry are reused until shortly before it.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+
+use chrono::Utc;
+use failure::{Fail, ResultExt};
+use log::debug;
+
+use docker::models::AuthConfig;
+
+use crate::error::{Error, ErrorKind};
+
+/// The registry of images whose name doesn't start with one.
+pub(crate) const DEFAULT_REGISTRY: &str = "docker.io";
+
+/// The address that helpers know the default registry by.
+const DEFAULT_REGISTRY_SERVER_URL: &str = "https://index.docker.io/v1/";
+
+/// The username that helpers return with identity tokens rather than passwords.
+const TOKEN_USERNAME: &str = "<token>";
+
+/// How long before their expiry cached credentials are fetched again.
+pub const EXPIRY_MARGIN_SECS: i64 = 300;
+
+#[derive(Clone, Default)]
+pub struct CredentialHelpers {
+    helpers: HashMap<String, String>,
+    cache: Arc<Mutex<HashMap<String, CachedCredentials>>>,
+}
+
+#[derive(Clone)]
+struct CachedCredentials {
+    auth: AuthConfig,
+    expires_at: i64,
+}
+
+#[derive(Debug, serde_derive::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct HelperCredentials {
+    #[serde(rename = "ServerURL", default)]
+    server_url: Option<String>,
+    username: String,
+    secret: String,
+}
+
+impl CredentialHelpers {
+    /// `helpers` maps registry hosts to the helper of each. A helper is the
+    /// suffix of a `docker-credential-<name>` program on the `PATH`, or the
+    /// path of a program.
+    pub fn new(helpers: HashMap<String, String>) -> Self {
+        CredentialHelpers {
+            helpers,
+            cache: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// The credentials of the registry of `image` from its helper, or `None`
+    /// if the registry has no helper.
+    pub fn auth(&self, image: &str) -> Option<Result<AuthConfig, Error>> {
+        let registry = registry_host(image);
+        let helper = self.helpers.get(registry)?;
+
+        let now = Utc::now().timestamp();
+        let mut cache = self.cache.lock().expect("credential cache lock poisoned");
+        if let Some(cached) = cache.get(registry) {
+            if now < cached.expires_at - EXPIRY_MARGIN_SECS {
+                debug!("Using cached credentials of registry {}", registry);
+                return Some(Ok(cached.auth.clone()));
+            }
+        }
+
+        let result = run_helper(helper, registry).map(|(auth, expires_at)| {
+            match expires_at {
+                Some(expires_at) => {
+                    cache.insert(
+                        registry.to_string(),
+                        CachedCredentials {
+                            auth: auth.clone(),
+                            expires_at,
+                        },
+                    );
+                }
+                None => {
+                    cache.remove(registry);
+                }
+            }
+            auth
+        });
+        Some(result)
+    }
+}
+
+// Returns the credentials and, if the secret is a JWT, the time it expires at.
+fn run_helper(helper: &str, registry: &str) -> Result<(AuthConfig, Option<i64>), Error> {
+    debug!(
+        "Getting credentials of registry {} from credential helper {}",
+        registry, helper
+    );
+
+    let context = || ErrorKind::CredentialHelper(registry.to_string(), helper.to_string());
+
+    let program = if Path::new(helper).components().count() > 1 {
+        helper.to_string()
+    } else {
+        format!("docker-credential-{}", helper)
+    };
+    let mut child = Command::new(program)
+        .arg("get")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|_| context())?;
+
+    let server_url = if registry == DEFAULT_REGISTRY {
+        DEFAULT_REGISTRY_SERVER_URL
+    } else {
+        registry
+    };
+    child
+        .stdin
+        .take()
+        .expect("stdin of helper is piped")
+        .write_all(server_url.as_bytes())
+        .with_context(|_| context())?;
+
+    let output = child.wait_with_output().with_context(|_| context())?;
+    if !output.status.success() {
+        // Helpers print their errors, like "credentials not found in native
+        // keychain", to stdout.
+        let message = if output.stdout.is_empty() {
+            String::from_utf8_lossy(&output.stderr)
+        } else {
+            String::from_utf8_lossy(&output.stdout)
+        };
+        let message = format!("{} ({})", message.trim(), output.status);
+        return Err(Error::from(
+            ErrorKind::CredentialHelperFailed(message).context(context()),
+        ));
+    }
+
+    let credentials: HelperCredentials =
+        serde_json::from_slice(&output.stdout).with_context(|_| context())?;
+    let server_address = credentials
+        .server_url
+        .unwrap_or_else(|| server_url.to_string());
+    let expires_at = token_expiry(&credentials.secret);
+
+    let auth = if credentials.username == TOKEN_USERNAME {
+        AuthConfig::new()
+            .with_identitytoken(credentials.secret)
+            .with_serveraddress(server_address)
+    } else {
+        AuthConfig::new()
+            .with_username(credentials.username)
+            .with_password(credentials.secret)
+            .with_serveraddress(server_address)
+    };
+    Ok((auth, expires_at))
+}
+
+/// The registry host of `image`, the first component of its name if that
+/// looks like a host, like `myregistry.azurecr.io` or `localhost:5000`.
+/// Otherwise the image is from Docker Hub.
+pub fn registry_host(image: &str) -> &str {
+    match image.find('/') {
+        Some(i) => {
+            let first = &image[..i];
+            if first.contains('.') || first.contains(':') || first == "localhost" {
+                first
+            } else {
+                DEFAULT_REGISTRY
+            }
+        }
+        None => DEFAULT_REGISTRY,
+    }
+}
+
+// The `exp` claim of a secret that is a JWT, as tokens from registries like
+// ACR are.
+pub fn token_expiry(secret: &str) -> Option<i64> {
+    let mut parts = secret.split('.');
+    let claims = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(_), Some(claims), Some(_), None) => claims,
+        _ => return None,
+    };
+    let claims = base64::decode_config(claims, base64::URL_SAFE_NO_PAD).ok()?;
+    let claims: serde_json::Value = serde_json::from_slice(&claims).ok()?;
+    claims.get("exp")?.as_i64()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registry_host_of_images() {
+        assert_eq!(
+            "myregistry.azurecr.io",
+            registry_host("myregistry.azurecr.io/module:1.0")
+        );
+        assert_eq!("localhost:5000", registry_host("localhost:5000/module"));
+        assert_eq!("localhost", registry_host("localhost/module"));
+        assert_eq!("docker.io", registry_host("nginx:1.17"));
+        assert_eq!("docker.io", registry_host("library/nginx"));
+    }
+
+    #[test]
+    fn token_expiry_of_jwt() {
+        let claims = base64::encode_config(r#"{"exp":1600000000}"#, base64::URL_SAFE_NO_PAD);
+        assert_eq!(
+            Some(1_600_000_000),
+            token_expiry(&format!("eyJhbGciOiJSUzI1NiJ9.{}.c2ln", claims))
+        );
+        assert_eq!(None, token_expiry("hunter2"));
+        assert_eq!(None, token_expiry("a.b.c"));
+    }
+
+    #[test]
+    fn registry_without_helper_has_no_auth() {
+        let helpers = CredentialHelpers::new(
+            vec![("myregistry.azurecr.io".to_string(), "acr-env".to_string())]
+                .into_iter()
+                .collect(),
+        );
+        assert!(helpers
+            .auth("otherregistry.azurecr.io/module:1.0")
+            .is_none());
+    }
+
+    #[cfg(unix)]
+    mod helper {
+        use std::fs;
+        use std::os::unix::fs::PermissionsExt;
+
+        use tempdir::TempDir;
+
+        use super::*;
+
+        // Writes a helper that logs the registry it's asked for to `calls` and
+        // then runs `body`.
+        fn write_helper(dir: &TempDir, body: &str) -> String {
+            let path = dir.path().join("docker-credential-test");
+            let calls = dir.path().join("calls");
+            fs::write(
+                &path,
+                format!(
+                    "#!/bin/sh\n[ \"$1\" = get ] || exit 2\nread registry\necho \"$registry\" >> '{}'\n{}\n",
+                    calls.display(),
+                    body
+                ),
+            )
+            .unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+            path.to_str().unwrap().to_string()
+        }
+
+        fn calls(dir: &TempDir) -> Vec<String> {
+            fs::read_to_string(dir.path().join("calls"))
+                .unwrap_or_default()
+                .lines()
+                .map(ToString::to_string)
+                .collect()
+        }
+
+        fn helpers(helper: String) -> CredentialHelpers {
+            CredentialHelpers::new(
+                vec![("myregistry.azurecr.io".to_string(), helper)]
+                    .into_iter()
+                    .collect(),
+            )
+        }
+
+        #[test]
+        fn password_is_fetched_for_every_pull() {
+            let dir = TempDir::new("credential-helper").unwrap();
+            let helper = write_helper(
+                &dir,
+                r#"echo '{"ServerURL":"myregistry.azurecr.io","Username":"user","Secret":"hunter2"}'"#,
+            );
+            let helpers = helpers(helper);
+
+            for _ in 0..2 {
+                let auth = helpers
+                    .auth("myregistry.azurecr.io/module:1.0")
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(Some("user"), auth.username());
+                assert_eq!(Some("hunter2"), auth.password());
+                assert_eq!(Some("myregistry.azurecr.io"), auth.serveraddress());
+            }
+            assert_eq!(
+                vec!["myregistry.azurecr.io", "myregistry.azurecr.io"],
+                calls(&dir)
+            );
+        }
+
+        #[test]
+        fn token_is_cached_until_near_expiry() {
+            let dir = TempDir::new("credential-helper").unwrap();
+            let claims = base64::encode_config(
+                &format!(r#"{{"exp":{}}}"#, Utc::now().timestamp() + 3600),
+                base64::URL_SAFE_NO_PAD,
+            );
+            let helper = write_helper(
+                &dir,
+                &format!(
+                    r#"echo '{{"Username":"<token>","Secret":"eyJhbGciOiJSUzI1NiJ9.{}.c2ln"}}'"#,
+                    claims
+                ),
+            );
+            let helpers = helpers(helper);
+
+            for _ in 0..2 {
+                let auth = helpers
+                    .auth("myregistry.azurecr.io/module:1.0")
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(None, auth.username());
+                assert!(auth.identitytoken().unwrap().ends_with(".c2ln"));
+            }
+            assert_eq!(vec!["myregistry.azurecr.io"], calls(&dir));
+        }
+
+        #[test]
+        fn expiring_token_is_fetched_again() {
+            let dir = TempDir::new("credential-helper").unwrap();
+            let claims = base64::encode_config(
+                &format!(r#"{{"exp":{}}}"#, Utc::now().timestamp() + 60),
+                base64::URL_SAFE_NO_PAD,
+            );
+            let helper = write_helper(
+                &dir,
+                &format!(
+                    r#"echo '{{"Username":"<token>","Secret":"eyJhbGciOiJSUzI1NiJ9.{}.c2ln"}}'"#,
+                    claims
+                ),
+            );
+            let helpers = helpers(helper);
+
+            for _ in 0..2 {
+                helpers
+                    .auth("myregistry.azurecr.io/module:1.0")
+                    .unwrap()
+                    .unwrap();
+            }
+            assert_eq!(2, calls(&dir).len());
+        }
+
+        #[test]
+        fn failing_helper_fails() {
+            let dir = TempDir::new("credential-helper").unwrap();
+            let helper = write_helper(
+                &dir,
+                "echo 'credentials not found in native keychain'\nexit 1",
+            );
+            let helpers = helpers(helper.clone());
+
+            let err = helpers
+                .auth("myregistry.azurecr.io/module:1.0")
+                .unwrap()
+                .unwrap_err();
+            match (err.kind(), err.cause().and_then(Fail::downcast_ref)) {
+                (
+                    ErrorKind::CredentialHelper(registry, failed_helper),
+                    Some(ErrorKind::CredentialHelperFailed(message)),
+                ) => {
+                    assert_eq!("myregistry.azurecr.io", registry);
+                    assert_eq!(&helper, failed_helper);
+                    assert!(
+                        message.starts_with("credentials not found in native keychain"),
+                        "{}",
+                        message
+                    );
+                }
+                _ => panic!("Expected `CredentialHelperFailed` error but got {:?}", err),
+            }
+        }
+
+        #[test]
+        fn missing_helper_fails() {
+            let helpers = helpers("no-such-helper-for-iotedge".to_string());
+            let err = helpers
+                .auth("myregistry.azurecr.io/module:1.0")
+                .unwrap()
+                .unwrap_err();
+            match err.kind() {
+                ErrorKind::CredentialHelper(_, helper) => {
+                    assert_eq!("no-such-helper-for-iotedge", helper)
+                }
+                _ => panic!("Expected `CredentialHelper` error but got {:?}", err),
+            }
+        }
+    }
+}
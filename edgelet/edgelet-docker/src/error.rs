@@ -2,6 +2,7 @@
 
 use std::fmt;
 use std::fmt::Display;
+use std::time::Duration;
 
 use failure::{Backtrace, Context, Fail};
 use hyper::StatusCode;
@@ -9,7 +10,8 @@ use serde_json;
 
 use docker::apis::{ApiError as DockerApiError, Error as DockerError};
 use edgelet_core::{
-    ModuleOperation, ModuleRuntimeErrorReason, RegistryOperation, RuntimeOperation,
+    ErrorCode, ErrorCodeKind, ModuleOperation, ModuleRuntimeErrorReason, RegistryOperation,
+    RuntimeOperation,
 };
 
 pub type Result<T> = ::std::result::Result<T, Error>;
@@ -41,12 +43,45 @@ fn get_message(
 
 #[derive(Debug, Fail)]
 pub enum ErrorKind {
+    #[fail(display = "Could not get a token to pull images from registry {}", _0)]
+    AcrToken(String),
+
+    #[fail(display = "Config file {} includes itself", _0)]
+    CircularSettingsInclude(String),
+
     #[fail(display = "Could not clone create options")]
     CloneCreateOptions,
 
     #[fail(display = "Conflict with current operation")]
     Conflict,
 
+    #[fail(
+        display = "Image {} does not have pinned digest {}; found {}",
+        _0, _1, _2
+    )]
+    DigestMismatch(String, String, String),
+
+    #[fail(
+        display = "Could not get the credentials of registry {} from credential helper {}",
+        _0, _1
+    )]
+    CredentialHelper(String, String),
+
+    #[fail(display = "Credential helper failed: {}", _0)]
+    CredentialHelperFailed(String),
+
+    #[fail(display = "Could not read registry credential file {}", _0)]
+    CredentialFile(String),
+
+    #[fail(
+        display = "Registry credential file {} must not be accessible by anyone but its owner",
+        _0
+    )]
+    CredentialFilePermissions(String),
+
+    #[fail(display = "Could not read config file {} included by {}", _0, _1)]
+    ReadSettingsInclude(String, String),
+
     #[fail(display = "Container runtime error")]
     Docker,
 
@@ -56,35 +91,134 @@ pub enum ErrorKind {
     #[fail(display = "{}", _0)]
     FormattedDockerRuntime(String),
 
+    #[fail(display = "Incomplete registry credentials for image {:?}", _0)]
+    IncompleteRegistryCredentials(String),
+
     #[fail(display = "Could not initialize module runtime")]
     Initialization,
 
+    #[fail(
+        display = "Image {} has no entrypoint or command, so its container would exit as soon as it starts. Give the module a command with the Cmd or Entrypoint of its create options.",
+        _0
+    )]
+    ImageHasNoCommand(String),
+
+    #[fail(
+        display = "Image {} is not on the device, and its registry can't be reached: {}. Devices that can't reach the registry need the image preloaded, for example with `iotedge preload`.",
+        _0, _1
+    )]
+    ImageNotPreloaded(String, String),
+
+    #[fail(
+        display = "Image {} is not on the device, and the pull policy of the module is never, so it isn't pulled",
+        _0
+    )]
+    ImageNotPresent(String),
+
+    #[fail(
+        display = "Invalid container name prefix {:?}, expected letters, digits, `_`, `.` and `-` starting with a letter or digit",
+        _0
+    )]
+    InvalidContainerPrefix(String),
+
     #[fail(display = "Invalid docker image {:?}", _0)]
     InvalidImage(String),
 
+    #[fail(
+        display = "Invalid network policy target {:?}, expected a module name or an address range like 10.0.0.0/8",
+        _0
+    )]
+    InvalidEgressTarget(String),
+
     #[fail(display = "Invalid module name {:?}", _0)]
     InvalidModuleName(String),
 
     #[fail(display = "Invalid module type {:?}", _0)]
     InvalidModuleType(String),
 
+    #[fail(display = "Container runtime rejected resource limit {}: {}", _0, _1)]
+    InvalidResourceLimit(&'static str, String),
+
+    #[fail(
+        display = "Invalid include in config file {}, expected a file name or a list of file names",
+        _0
+    )]
+    InvalidSettingsInclude(String),
+
     #[fail(display = "Invalid socket URI: {:?}", _0)]
     InvalidSocketUri(String),
 
+    #[fail(display = "Registry did not return the digest of image {}", _0)]
+    MissingDigest(String),
+
     #[fail(display = "{}", _0)]
     ModuleOperation(ModuleOperation),
 
+    #[fail(display = "Could not join network {}", _0)]
+    JoinNetwork(String),
+
+    #[fail(display = "Could not apply the network policy of module {}", _0)]
+    NetworkPolicy(String),
+
+    #[fail(display = "{}", _0)]
+    NetworkPolicyCommand(String),
+
+    #[fail(
+        display = "Network {} does not exist, and the container runtime isn't configured to create the networks that modules join",
+        _0
+    )]
+    NetworkNotFound(String),
+
+    #[fail(
+        display = "Image {} has no tag in its registry that matches {:?}",
+        _0, _1
+    )]
+    NoMatchingTag(String, String),
+
     #[fail(display = "{}", _0)]
     NotFound(String),
 
     #[fail(display = "Target of operation already in this state")]
     NotModified,
 
+    #[fail(display = "Module {} is not running", _0)]
+    NotRunning(String),
+
+    #[fail(
+        display = "Module {} has a read-only root filesystem and no writable mounts. If it needs to write files, give it a tmpfs mount or a writable bind.",
+        _0
+    )]
+    ReadOnlyRootFs(String),
+
     #[fail(display = "{}", _0)]
     RegistryOperation(RegistryOperation),
 
+    #[fail(display = "Could not send request to registry {}", _0)]
+    RegistryRequest(String),
+
+    #[fail(display = "Registry request to {} failed with {}", _0, _1)]
+    RegistryRequestFailed(String, StatusCode),
+
+    #[fail(display = "Could not resolve the tag pattern of image {}", _0)]
+    ResolveTag(String),
+
+    #[fail(display = "Could not load or save the resolved images in {}", _0)]
+    ResolvedImages(String),
+
     #[fail(display = "{}", _0)]
     RuntimeOperation(RuntimeOperation),
+
+    #[fail(display = "Container runtime did not respond within {:?}", _0)]
+    RuntimeTimeout(Duration),
+
+    #[fail(display = "Could not load seccomp profile {}", _0)]
+    SeccompProfile(String),
+
+    #[fail(display = "Could not request a token from {}", _0)]
+    TokenRequest(String),
+
+    #[fail(display = "Token request to {} failed with {}", _0, _1)]
+    TokenRequestFailed(String, StatusCode),
 }
 
 impl Fail for Error {
@@ -144,6 +278,44 @@ impl From<Context<ErrorKind>> for Error {
     }
 }
 
+impl ErrorCodeKind for ErrorKind {
+    fn error_code(&self) -> Option<ErrorCode> {
+        match self {
+            ErrorKind::Conflict => Some(ErrorCode::Conflict),
+            ErrorKind::DigestMismatch(..)
+            | ErrorKind::ImageNotPreloaded(..)
+            | ErrorKind::RegistryOperation(RegistryOperation::PullImage(_)) => {
+                Some(ErrorCode::ImagePullFailed)
+            }
+            ErrorKind::ImageNotPresent(_) | ErrorKind::NoMatchingTag(..) => {
+                Some(ErrorCode::ImageNotFound)
+            }
+            ErrorKind::ImageHasNoCommand(_)
+            | ErrorKind::IncompleteRegistryCredentials(_)
+            | ErrorKind::InvalidImage(_)
+            | ErrorKind::InvalidModuleName(_)
+            | ErrorKind::InvalidModuleType(_)
+            | ErrorKind::InvalidResourceLimit(..)
+            | ErrorKind::NetworkNotFound(_)
+            | ErrorKind::ReadOnlyRootFs(_) => Some(ErrorCode::InvalidModuleSpec),
+            // Like "No such image: mcr.microsoft.com/azureiotedge-agent:1.0"
+            ErrorKind::NotFound(message) if message.starts_with("No such image") => {
+                Some(ErrorCode::ImageNotFound)
+            }
+            ErrorKind::NotFound(_) => Some(ErrorCode::ModuleNotFound),
+            ErrorKind::NotRunning(_) => Some(ErrorCode::ModuleNotRunning),
+            ErrorKind::RuntimeTimeout(_) => Some(ErrorCode::RuntimeTimeout),
+            _ => None,
+        }
+    }
+}
+
+impl ErrorCodeKind for Error {
+    fn error_code(&self) -> Option<ErrorCode> {
+        self.kind().error_code()
+    }
+}
+
 impl<'a> From<&'a Error> for ModuleRuntimeErrorReason {
     fn from(err: &'a Error) -> Self {
         match Fail::find_root_cause(err).downcast_ref::<ErrorKind>() {
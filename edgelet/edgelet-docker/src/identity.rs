@@ -0,0 +1,304 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! What modules whose specs turn on `identity_integration` are given to use
+//! their workload identity with the SDK, so that their authors don't have to
+//! get it right by hand: the workload socket mounted at the path that its URI
+//! names, and the `IOTEDGE_*` environment variables that the SDK reads, from
+//! the runtime's settings and the device's provisioning.
+//!
+//! What a module sets itself is kept, with a warning if it differs from what
+//! it would have been given, since it's most likely a mistake. The generation
+//! ID of the module's identity is only known to whoever created it, like the
+//! edge agent, so it's left to the module's spec.
+
+use std::collections::HashMap;
+
+use log::warn;
+use url::Url;
+
+use edgelet_core::{ModuleBind, ModuleSpec, UrlExt, UNIX_SCHEME};
+use edgelet_http::API_VERSION;
+
+use crate::config::DockerConfig;
+
+const HOSTNAME_KEY: &str = "IOTEDGE_IOTHUBHOSTNAME";
+const GATEWAY_HOSTNAME_KEY: &str = "IOTEDGE_GATEWAYHOSTNAME";
+const DEVICEID_KEY: &str = "IOTEDGE_DEVICEID";
+const MODULEID_KEY: &str = "IOTEDGE_MODULEID";
+const WORKLOAD_URI_KEY: &str = "IOTEDGE_WORKLOADURI";
+const AUTHSCHEME_KEY: &str = "IOTEDGE_AUTHSCHEME";
+const API_VERSION_KEY: &str = "IOTEDGE_APIVERSION";
+
+const AUTH_SCHEME: &str = "sasToken";
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct IdentityIntegration {
+    env: HashMap<String, String>,
+    workload_socket: Option<String>,
+}
+
+impl IdentityIntegration {
+    /// The workload socket is only mounted if modules connect to the
+    /// workload API over a Unix socket.
+    pub(crate) fn new(
+        hub_name: &str,
+        device_id: &str,
+        gateway_hostname: &str,
+        workload_uri: &Url,
+    ) -> Self {
+        let mut env = HashMap::new();
+        env.insert(HOSTNAME_KEY.to_string(), hub_name.to_string());
+        env.insert(
+            GATEWAY_HOSTNAME_KEY.to_string(),
+            gateway_hostname.to_lowercase(),
+        );
+        env.insert(DEVICEID_KEY.to_string(), device_id.to_string());
+        env.insert(WORKLOAD_URI_KEY.to_string(), workload_uri.to_string());
+        env.insert(AUTHSCHEME_KEY.to_string(), AUTH_SCHEME.to_string());
+        env.insert(API_VERSION_KEY.to_string(), API_VERSION.to_string());
+
+        let workload_socket = if workload_uri.scheme() == UNIX_SCHEME {
+            workload_uri
+                .to_uds_file_path()
+                .ok()
+                .map(|path| path.to_string_lossy().into_owned())
+        } else {
+            None
+        };
+
+        IdentityIntegration {
+            env,
+            workload_socket,
+        }
+    }
+
+    /// The variables that `module` is given, leaving out those that are in
+    /// `module_env`, the variables of its spec and env files, or in its
+    /// create options.
+    pub(crate) fn env(
+        &self,
+        module: &ModuleSpec<DockerConfig>,
+        module_env: &HashMap<String, String>,
+    ) -> HashMap<String, String> {
+        let create_options_env: HashMap<_, _> = module
+            .config()
+            .create_options()
+            .env()
+            .unwrap_or_default()
+            .iter()
+            .map(|var| {
+                let mut tokens = var.splitn(2, '=');
+                let key = tokens.next().unwrap_or_default();
+                (key, tokens.next().unwrap_or_default())
+            })
+            .collect();
+
+        let mut env = self.env.clone();
+        env.insert(MODULEID_KEY.to_string(), module.name().to_string());
+        env.retain(|key, value| {
+            let set = module_env
+                .get(key)
+                .map(String::as_str)
+                .or_else(|| create_options_env.get(key.as_str()).copied());
+            match set {
+                Some(set) => {
+                    if set != value.as_str() {
+                        warn!(
+                            "Module {} sets {} to {:?} rather than the {:?} of its identity integration, so it may not be able to use its identity",
+                            module.name(),
+                            key,
+                            set,
+                            value
+                        );
+                    }
+                    false
+                }
+                None => true,
+            }
+        });
+        env
+    }
+
+    /// `module` with the workload socket bound in its container, unless it
+    /// mounts something at the socket's path already.
+    pub(crate) fn with_workload_socket(
+        &self,
+        module: ModuleSpec<DockerConfig>,
+    ) -> ModuleSpec<DockerConfig> {
+        let socket = match &self.workload_socket {
+            Some(socket) => socket,
+            None => return module,
+        };
+
+        if let Some(source) = mounted_at(&module, socket) {
+            if source.as_ref().map_or(true, |source| source != socket) {
+                warn!(
+                    "Module {} mounts {} at {} rather than the workload socket of its identity integration, so it may not be able to use its identity",
+                    module.name(),
+                    source.as_ref().map_or("a tmpfs", String::as_str),
+                    socket
+                );
+            }
+            return module;
+        }
+
+        let mut binds = module.binds().to_vec();
+        binds.push(ModuleBind::new(socket.clone(), socket.clone()));
+        module.with_binds(binds)
+    }
+}
+
+// The source of what `module` mounts at `target`, by its spec or its create
+// options, or `Some(None)` for a mount without one like a tmpfs.
+fn mounted_at(module: &ModuleSpec<DockerConfig>, target: &str) -> Option<Option<String>> {
+    if let Some(bind) = module.binds().iter().find(|bind| bind.target() == target) {
+        return Some(Some(bind.source().to_string()));
+    }
+    if module.tmpfs().iter().any(|tmpfs| tmpfs.target() == target) {
+        return Some(None);
+    }
+
+    let host_config = module.config().create_options().host_config()?;
+    let mounts = host_config.mounts().unwrap_or_default();
+    if let Some(mount) = mounts.iter().find(|mount| mount.target() == Some(target)) {
+        return Some(mount.source().map(ToString::to_string));
+    }
+    // Binds are `source:target[:options]`, and the source can have a colon
+    // of its own as a Windows drive letter.
+    let binds = host_config.binds().unwrap_or_default();
+    binds.iter().find_map(|bind| {
+        let suffix = format!(":{}", target);
+        let source = if bind.ends_with(&suffix) {
+            &bind[..bind.len() - suffix.len()]
+        } else {
+            &bind[..bind.find(&format!("{}:", suffix))?]
+        };
+        Some(Some(source.to_string()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use docker::models::{ContainerCreateBody, HostConfig};
+    use edgelet_core::{ImagePullPolicy, ModuleTmpfs};
+
+    use super::*;
+
+    fn identity() -> IdentityIntegration {
+        IdentityIntegration::new(
+            "hub.azure-devices.net",
+            "device1",
+            "Gateway",
+            &Url::parse("unix:///var/run/iotedge/workload.sock").unwrap(),
+        )
+    }
+
+    fn module(create_options: ContainerCreateBody) -> ModuleSpec<DockerConfig> {
+        ModuleSpec::new(
+            "sensor".to_string(),
+            "docker".to_string(),
+            DockerConfig::new("sensor:1.0".to_string(), create_options, None).unwrap(),
+            HashMap::new(),
+            ImagePullPolicy::default(),
+        )
+        .unwrap()
+        .with_identity_integration(true)
+    }
+
+    #[test]
+    fn env_is_that_of_the_sdk() {
+        let env = identity().env(&module(ContainerCreateBody::new()), &HashMap::new());
+
+        let mut expected = HashMap::new();
+        for (key, value) in &[
+            ("IOTEDGE_IOTHUBHOSTNAME", "hub.azure-devices.net"),
+            ("IOTEDGE_GATEWAYHOSTNAME", "gateway"),
+            ("IOTEDGE_DEVICEID", "device1"),
+            ("IOTEDGE_MODULEID", "sensor"),
+            (
+                "IOTEDGE_WORKLOADURI",
+                "unix:///var/run/iotedge/workload.sock",
+            ),
+            ("IOTEDGE_AUTHSCHEME", "sasToken"),
+            ("IOTEDGE_APIVERSION", "2019-11-05"),
+        ] {
+            expected.insert((*key).to_string(), (*value).to_string());
+        }
+        assert_eq!(expected, env);
+    }
+
+    #[test]
+    fn env_of_the_module_is_kept() {
+        let module = module(ContainerCreateBody::new().with_env(vec![
+            "IOTEDGE_AUTHSCHEME=x509".to_string(),
+            "IOTEDGE_DEVICEID=device1".to_string(),
+        ]));
+        let mut module_env = HashMap::new();
+        module_env.insert("IOTEDGE_MODULEID".to_string(), "other".to_string());
+
+        let env = identity().env(&module, &module_env);
+        assert!(!env.contains_key("IOTEDGE_MODULEID"));
+        assert!(!env.contains_key("IOTEDGE_AUTHSCHEME"));
+        assert!(!env.contains_key("IOTEDGE_DEVICEID"));
+        assert_eq!(
+            Some("hub.azure-devices.net"),
+            env.get("IOTEDGE_IOTHUBHOSTNAME").map(String::as_str)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn workload_socket_is_bound() {
+        let existing = ModuleBind::new("/opt/sensors".to_string(), "/sensors".to_string());
+        let module = module(ContainerCreateBody::new()).with_binds(vec![existing.clone()]);
+
+        let module = identity().with_workload_socket(module);
+        assert_eq!(
+            &[
+                existing,
+                ModuleBind::new(
+                    "/var/run/iotedge/workload.sock".to_string(),
+                    "/var/run/iotedge/workload.sock".to_string()
+                ),
+            ],
+            module.binds()
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn mounts_of_the_module_are_kept() {
+        let socket = "/var/run/iotedge/workload.sock";
+        let bind = ModuleBind::new("/tmp/workload.sock".to_string(), socket.to_string());
+        let modules =
+            vec![
+                module(ContainerCreateBody::new()).with_binds(vec![bind.clone()]),
+                module(ContainerCreateBody::new())
+                    .with_tmpfs(vec![ModuleTmpfs::new(socket.to_string())]),
+                module(ContainerCreateBody::new().with_host_config(
+                    HostConfig::new().with_binds(vec![format!("/tmp/workload.sock:{}:ro", socket)]),
+                )),
+                module(ContainerCreateBody::new().with_host_config(
+                    HostConfig::new().with_binds(vec![format!("{0}:{0}", socket)]),
+                )),
+            ];
+
+        for module in modules {
+            let applied = identity().with_workload_socket(module.clone());
+            assert_eq!(module.binds(), applied.binds());
+        }
+    }
+
+    #[test]
+    fn workload_socket_is_not_bound_without_unix_socket() {
+        let identity = IdentityIntegration::new(
+            "hub.azure-devices.net",
+            "device1",
+            "gateway",
+            &Url::parse("http://localhost:15581").unwrap(),
+        );
+
+        let module = identity.with_workload_socket(module(ContainerCreateBody::new()));
+        assert!(module.binds().is_empty());
+    }
+}
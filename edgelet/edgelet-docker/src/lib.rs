@@ -10,15 +10,28 @@
     clippy::use_self
 )]
 
+mod acr;
 mod client;
 mod config;
+mod credentials;
 mod error;
+mod identity;
 mod module;
+mod network_policy;
+mod pull;
+mod restart;
 mod runtime;
 mod settings;
+mod stats;
+mod tags;
+mod throttle;
 
-pub use crate::config::DockerConfig;
+pub use crate::acr::{AcrIdentity, ClientSecret};
+pub use crate::config::{DockerConfig, RegistryAuth};
 pub use error::{Error, ErrorKind};
 pub use module::{DockerModule, MODULE_TYPE};
-pub use runtime::DockerModuleRuntime;
-pub use settings::{LoadSettingsError, Settings, DEFAULTS};
+pub use network_policy::{EgressTarget, ModuleNetworkPolicy, NetworkPolicy};
+pub use runtime::{import_image, DockerModuleRuntime};
+pub use settings::{
+    read_settings_file, LoadSettingsError, RequestRetry, Settings, DEFAULTS, ENV_OVERRIDES_PREFIX,
+};
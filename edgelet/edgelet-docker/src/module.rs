@@ -1,15 +1,20 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+use std::convert::TryFrom;
 use std::str::FromStr;
+use std::time::Duration;
 
 use chrono::prelude::*;
 use failure::ResultExt;
 use futures::Future;
 use hyper::client::connect::Connect;
 
-use docker::models::{InlineResponse2001, InlineResponse200State};
+use docker::models::{
+    ContainerConfig, Health, InlineResponse200, InlineResponse2001, NetworkSettings,
+};
 use edgelet_core::{
-    Module, ModuleOperation, ModuleRuntimeState, ModuleStatus, ModuleTop, RuntimeOperation,
+    ExitReason, Module, ModuleError, ModuleErrorCode, ModuleOperation, ModuleRuntimeState,
+    ModuleStatus, ModuleTop, PortBinding, RuntimeOperation,
 };
 use edgelet_utils::ensure_not_empty_with_context;
 
@@ -20,7 +25,6 @@ use crate::error::{Error, ErrorKind, Result};
 type Deserializer = &'static mut serde_json::Deserializer<serde_json::de::IoRead<std::io::Empty>>;
 
 pub const MODULE_TYPE: &str = "docker";
-pub const MIN_DATE: &str = "0001-01-01T00:00:00Z";
 
 pub struct DockerModule<C: Connect> {
     client: DockerClient<C>,
@@ -65,7 +69,7 @@ impl<C: 'static + Connect> DockerModuleTop for DockerModule<C> {
         Box::new(
             self.client
                 .container_api()
-                .container_top(&id, "")
+                .container_top(&self.client.container_name(&id), "")
                 .then(|result| match result {
                     Ok(resp) => {
                         let p = parse_top_response::<Deserializer>(&resp).with_context(|_| {
@@ -135,39 +139,134 @@ fn status_from_exit_code(exit_code: Option<i64>) -> Option<ModuleStatus> {
     })
 }
 
-pub fn runtime_state(
-    id: Option<&str>,
-    response_state: Option<&InlineResponse200State>,
-) -> ModuleRuntimeState {
-    response_state.map_or_else(ModuleRuntimeState::default, |state| {
-        let status = state
-            .status()
-            .and_then(|status| match status {
-                "created" | "paused" | "restarting" => Some(ModuleStatus::Stopped),
-                "removing" | "dead" | "exited" => status_from_exit_code(state.exit_code()),
-                "running" => Some(ModuleStatus::Running),
-                _ => Some(ModuleStatus::Unknown),
-            })
-            .unwrap_or_else(|| ModuleStatus::Unknown);
-        ModuleRuntimeState::default()
-            .with_status(status)
-            .with_exit_code(state.exit_code())
-            .with_status_description(state.status().map(ToOwned::to_owned))
-            .with_started_at(
-                state
-                    .started_at()
-                    .and_then(|d| if d == MIN_DATE { None } else { Some(d) })
-                    .and_then(|started_at| DateTime::from_str(started_at).ok()),
-            )
-            .with_finished_at(
-                state
-                    .finished_at()
-                    .and_then(|d| if d == MIN_DATE { None } else { Some(d) })
-                    .and_then(|finished_at| DateTime::from_str(finished_at).ok()),
+// The failure that the container last exited with, which Docker keeps until
+// the container exits again.
+fn exit_error(
+    exit_code: Option<i64>,
+    oom_killed: bool,
+    finished_at: Option<DateTime<Utc>>,
+) -> Option<ModuleError> {
+    let finished_at = finished_at?;
+    if oom_killed {
+        Some(
+            ModuleError::new(
+                ModuleErrorCode::OomKilled,
+                "Module was killed for running out of memory".to_string(),
+                finished_at,
             )
-            .with_image_id(id.map(ToOwned::to_owned))
-            .with_pid(state.pid())
-    })
+            .with_exit_code(exit_code),
+        )
+    } else {
+        match exit_code {
+            Some(code) if code != 0 => Some(
+                ModuleError::new(
+                    ModuleErrorCode::NonZeroExit,
+                    format!("Module exited with code {}", code),
+                    finished_at,
+                )
+                .with_exit_code(exit_code),
+            ),
+            _ => None,
+        }
+    }
+}
+
+// Docker reports the times of a container that hasn't started or finished yet
+// as Go's zero time, which is before the Unix epoch.
+fn parse_timestamp(timestamp: Option<&str>) -> Option<DateTime<Utc>> {
+    timestamp
+        .and_then(|timestamp| DateTime::from_str(timestamp).ok())
+        .filter(|timestamp: &DateTime<Utc>| timestamp.timestamp() > 0)
+}
+
+// The ports of the container sorted by port, with one binding for each host
+// address that a published port is mapped to. Docker reports a port that
+// isn't published with no bindings at all.
+fn port_bindings(container: &InlineResponse200) -> Vec<PortBinding> {
+    let ports = match container
+        .network_settings()
+        .and_then(NetworkSettings::ports)
+    {
+        Some(ports) => ports,
+        None => return vec![],
+    };
+
+    let mut container_ports: Vec<_> = ports.iter().collect();
+    container_ports.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let non_empty = |value: Option<&str>| {
+        value
+            .filter(|value| !value.is_empty())
+            .map(ToOwned::to_owned)
+    };
+    let mut bindings = vec![];
+    for (container_port, host) in container_ports {
+        match host {
+            Some(host) if !host.is_empty() => {
+                bindings.extend(host.iter().map(|host| {
+                    PortBinding::new(container_port.clone())
+                        .with_host_ip(non_empty(host.host_ip()))
+                        .with_host_port(non_empty(host.host_port()))
+                }));
+            }
+            _ => bindings.push(PortBinding::new(container_port.clone())),
+        }
+    }
+    bindings
+}
+
+pub fn runtime_state(container: &InlineResponse200) -> ModuleRuntimeState {
+    let id = container.id();
+    let restart_count = container
+        .restart_count()
+        .and_then(|count| u32::try_from(count).ok());
+    let labels = container
+        .config()
+        .and_then(ContainerConfig::labels)
+        .cloned()
+        .unwrap_or_default();
+    let state = container
+        .state()
+        .map_or_else(ModuleRuntimeState::default, |state| {
+            let status = state
+                .status()
+                .and_then(|status| match status {
+                    "created" | "paused" | "restarting" => Some(ModuleStatus::Stopped),
+                    "removing" | "dead" | "exited" => status_from_exit_code(state.exit_code()),
+                    "running" => Some(ModuleStatus::Running),
+                    _ => Some(ModuleStatus::Unknown),
+                })
+                .unwrap_or_else(|| ModuleStatus::Unknown);
+            ModuleRuntimeState::default()
+                .with_status(status)
+                .with_exit_code(state.exit_code())
+                .with_status_description(state.status().map(ToOwned::to_owned))
+                .with_started_at(parse_timestamp(state.started_at()))
+                .with_finished_at(parse_timestamp(state.finished_at()))
+                .with_image_id(id.map(ToOwned::to_owned))
+                .with_pid(state.pid())
+                .with_restart_count(restart_count)
+                .with_exit_reason(if state.oom_killed() == Some(&true) {
+                    Some(ExitReason::OomKilled)
+                } else {
+                    None
+                })
+                .with_last_error(exit_error(
+                    state.exit_code(),
+                    state.oom_killed() == Some(&true),
+                    parse_timestamp(state.finished_at()),
+                ))
+                .with_health(
+                    state
+                        .health()
+                        .and_then(Health::status)
+                        // "none" means the container has no health check
+                        .and_then(|health| health.parse().ok()),
+                )
+        });
+    state
+        .with_labels(labels)
+        .with_ports(port_bindings(container))
 }
 
 impl<C: 'static + Connect> Module for DockerModule<C> {
@@ -189,18 +288,20 @@ impl<C: 'static + Connect> Module for DockerModule<C> {
     }
 
     fn runtime_state(&self) -> Self::RuntimeStateFuture {
-        Box::new(
-            self.client
-                .container_api()
-                .container_inspect(&self.name, false)
-                .map(|resp| runtime_state(resp.id(), resp.state()))
-                .map_err(|err| {
-                    Error::from_docker_error(
-                        err,
-                        ErrorKind::ModuleOperation(ModuleOperation::RuntimeState),
-                    )
-                }),
-        )
+        let inspect = self
+            .client
+            .inspect_container(&self.name)
+            .map(|resp| runtime_state(&resp))
+            .map_err(|err| {
+                Error::from_docker_error(
+                    err,
+                    ErrorKind::ModuleOperation(ModuleOperation::RuntimeState),
+                )
+            });
+
+        Box::new(self.client.timeout(inspect, Duration::from_secs(0), || {
+            ErrorKind::ModuleOperation(ModuleOperation::RuntimeState)
+        }))
     }
 }
 
@@ -217,14 +318,16 @@ mod tests {
 
     use docker::apis::client::APIClient;
     use docker::apis::configuration::Configuration;
-    use docker::models::{ContainerCreateBody, InlineResponse200, InlineResponse200State};
-    use edgelet_core::{Module, ModuleStatus};
+    use docker::models::{ContainerCreateBody, Health, InlineResponse200, InlineResponse200State};
+    use edgelet_core::{HealthState, Module, ModuleStatus};
     use edgelet_test_utils::JsonConnector;
 
     use crate::client::DockerClient;
     use crate::config::DockerConfig;
     use crate::module::DockerModule;
 
+    const MIN_DATE: &str = "0001-01-01T00:00:00Z";
+
     fn create_api_client<T: Serialize>(body: T) -> DockerClient<JsonConnector> {
         let client = Client::builder().build(JsonConnector::new(&body));
 
@@ -474,6 +577,244 @@ mod tests {
         assert_eq!(None, runtime_state.finished_at());
     }
 
+    #[test]
+    fn module_runtime_state_health() {
+        let inputs = vec![
+            ("starting", Some(HealthState::Starting)),
+            ("healthy", Some(HealthState::Healthy)),
+            ("unhealthy", Some(HealthState::Unhealthy)),
+            ("none", None),
+        ];
+
+        for (health, expected) in inputs {
+            let state = InlineResponse200State::new()
+                .with_status("running".to_string())
+                .with_health(Health::new().with_status(health.to_string()));
+
+            let container = InlineResponse200::new().with_state(state);
+            assert_eq!(expected, runtime_state(&container).health());
+        }
+
+        let container = InlineResponse200::new()
+            .with_state(InlineResponse200State::new().with_status("running".to_string()));
+        assert_eq!(None, runtime_state(&container).health());
+    }
+
+    // Trimmed from `docker inspect` of a module that has crashed and been
+    // restarted three times.
+    const INSPECT_RESTARTED: &str = r#"{
+        "Id": "e6ae61d4452297e8cd0ec5dc8e0ce8f9b8d5d70fbaf0d0f6d1960b8e2efd1f17",
+        "Created": "2019-12-02T18:03:49.523654315Z",
+        "Path": "dotnet",
+        "Args": ["SimulatedTemperatureSensor.dll"],
+        "State": {
+            "Status": "exited",
+            "Running": false,
+            "Paused": false,
+            "Restarting": false,
+            "OOMKilled": false,
+            "Dead": false,
+            "Pid": 0,
+            "ExitCode": 139,
+            "Error": "",
+            "StartedAt": "2019-12-02T18:10:02.271838906Z",
+            "FinishedAt": "2019-12-02T18:10:04.539383744Z"
+        },
+        "Image": "sha256:a74a1e5ea99df7a1ac2a8d4a1ad85a7b7b5a8a0e2ba93e8b6c0d1d1fa1dc1f0b",
+        "Name": "/tempSensor",
+        "RestartCount": 3,
+        "Driver": "overlay2"
+    }"#;
+
+    // Trimmed from `docker inspect` of a module that was created but never
+    // started.
+    const INSPECT_CREATED: &str = r#"{
+        "Id": "0b6c9a238a37d5c3f9a0d2ff0b1a25bdbd44d1b6e4da7cbd5a6e5dbd71a5c60d",
+        "Created": "2019-12-02T18:20:11.10234887Z",
+        "Path": "dotnet",
+        "Args": ["SimulatedTemperatureSensor.dll"],
+        "State": {
+            "Status": "created",
+            "Running": false,
+            "Paused": false,
+            "Restarting": false,
+            "OOMKilled": false,
+            "Dead": false,
+            "Pid": 0,
+            "ExitCode": 0,
+            "Error": "",
+            "StartedAt": "0001-01-01T00:00:00Z",
+            "FinishedAt": "0001-01-01T00:00:00Z"
+        },
+        "Image": "sha256:a74a1e5ea99df7a1ac2a8d4a1ad85a7b7b5a8a0e2ba93e8b6c0d1d1fa1dc1f0b",
+        "Name": "/tempSensor",
+        "RestartCount": 0,
+        "Driver": "overlay2"
+    }"#;
+
+    #[test]
+    fn runtime_state_from_inspect_of_restarted_module() {
+        let container: InlineResponse200 = serde_json::from_str(INSPECT_RESTARTED).unwrap();
+
+        let state = runtime_state(&container);
+
+        assert_eq!(&ModuleStatus::Failed, state.status());
+        assert_eq!(Some(139), state.exit_code());
+        assert_eq!(Some(3), state.restart_count());
+        assert_eq!(
+            "2019-12-02T18:10:02.271838906+00:00",
+            state.started_at().unwrap().to_rfc3339()
+        );
+        assert_eq!(
+            "2019-12-02T18:10:04.539383744+00:00",
+            state.finished_at().unwrap().to_rfc3339()
+        );
+    }
+
+    // Trimmed from `docker inspect` of a module that went over its memory
+    // limit.
+    const INSPECT_OOM_KILLED: &str = r#"{
+        "Id": "5f1c2be0a4d2e1d7a3c6e0f5b1d4c8a9e2f7b3c6d9a0e1f4b7c2d5e8a1b4c7d0",
+        "Created": "2019-12-02T18:30:41.802973628Z",
+        "Path": "dotnet",
+        "Args": ["SimulatedTemperatureSensor.dll"],
+        "State": {
+            "Status": "exited",
+            "Running": false,
+            "Paused": false,
+            "Restarting": false,
+            "OOMKilled": true,
+            "Dead": false,
+            "Pid": 0,
+            "ExitCode": 137,
+            "Error": "",
+            "StartedAt": "2019-12-02T18:30:42.107555084Z",
+            "FinishedAt": "2019-12-02T18:31:15.446259333Z"
+        },
+        "Image": "sha256:a74a1e5ea99df7a1ac2a8d4a1ad85a7b7b5a8a0e2ba93e8b6c0d1d1fa1dc1f0b",
+        "Name": "/tempSensor",
+        "RestartCount": 0,
+        "Driver": "overlay2"
+    }"#;
+
+    #[test]
+    fn runtime_state_from_inspect_of_oom_killed_module() {
+        let container: InlineResponse200 = serde_json::from_str(INSPECT_OOM_KILLED).unwrap();
+
+        let state = runtime_state(&container);
+
+        assert_eq!(&ModuleStatus::Failed, state.status());
+        assert_eq!(Some(137), state.exit_code());
+        assert_eq!(Some(ExitReason::OomKilled), state.exit_reason());
+        assert_eq!(
+            ModuleErrorCode::OomKilled,
+            state.last_error().unwrap().code()
+        );
+    }
+
+    #[test]
+    fn runtime_state_from_inspect_has_last_exit_error() {
+        let container: InlineResponse200 = serde_json::from_str(INSPECT_RESTARTED).unwrap();
+
+        let error = runtime_state(&container).last_error().cloned().unwrap();
+
+        assert_eq!(ModuleErrorCode::NonZeroExit, error.code());
+        assert_eq!(Some(139), error.exit_code());
+        assert_eq!(
+            "2019-12-02T18:10:04.539383744+00:00",
+            error.timestamp().to_rfc3339()
+        );
+
+        let container: InlineResponse200 = serde_json::from_str(INSPECT_CREATED).unwrap();
+        assert_eq!(None, runtime_state(&container).last_error());
+    }
+
+    // Trimmed from `docker inspect` of a module that publishes one of the
+    // ports that its image exposes.
+    const INSPECT_PORTS: &str = r#"{
+        "Id": "8d6c1a3f2b7e4d9c0a5f1e8b3d6c9a2f7e0b4d1c8a5f2e9b6d3c0a7f4e1b8d5c",
+        "State": {
+            "Status": "running",
+            "Running": true,
+            "Pid": 2845,
+            "ExitCode": 0,
+            "StartedAt": "2019-12-02T18:30:42.107555084Z",
+            "FinishedAt": "0001-01-01T00:00:00Z"
+        },
+        "Name": "/edgeHub",
+        "RestartCount": 0,
+        "NetworkSettings": {
+            "Ports": {
+                "443/tcp": [
+                    { "HostIp": "0.0.0.0", "HostPort": "443" },
+                    { "HostIp": "::", "HostPort": "443" }
+                ],
+                "5671/tcp": null,
+                "8883/tcp": [{ "HostIp": "192.168.1.10", "HostPort": "8884" }]
+            }
+        }
+    }"#;
+
+    #[test]
+    fn runtime_state_from_inspect_has_port_bindings() {
+        let container: InlineResponse200 = serde_json::from_str(INSPECT_PORTS).unwrap();
+
+        let state = runtime_state(&container);
+
+        assert_eq!(
+            &[
+                PortBinding::new("443/tcp".to_string())
+                    .with_host_ip(Some("0.0.0.0".to_string()))
+                    .with_host_port(Some("443".to_string())),
+                PortBinding::new("443/tcp".to_string())
+                    .with_host_ip(Some("::".to_string()))
+                    .with_host_port(Some("443".to_string())),
+                PortBinding::new("5671/tcp".to_string()),
+                PortBinding::new("8883/tcp".to_string())
+                    .with_host_ip(Some("192.168.1.10".to_string()))
+                    .with_host_port(Some("8884".to_string())),
+            ][..],
+            state.ports()
+        );
+
+        let container: InlineResponse200 = serde_json::from_str(INSPECT_CREATED).unwrap();
+        assert!(runtime_state(&container).ports().is_empty());
+    }
+
+    #[test]
+    fn runtime_state_from_inspect_of_restarted_module_has_no_exit_reason() {
+        let container: InlineResponse200 = serde_json::from_str(INSPECT_RESTARTED).unwrap();
+
+        assert_eq!(None, runtime_state(&container).exit_reason());
+    }
+
+    #[test]
+    fn runtime_state_from_inspect_of_module_that_never_started() {
+        let container: InlineResponse200 = serde_json::from_str(INSPECT_CREATED).unwrap();
+
+        let state = runtime_state(&container);
+
+        assert_eq!(&ModuleStatus::Stopped, state.status());
+        assert_eq!(Some(0), state.restart_count());
+        assert_eq!(None, state.started_at());
+        assert_eq!(None, state.finished_at());
+    }
+
+    #[test]
+    fn runtime_state_ignores_epoch_timestamps() {
+        let container = InlineResponse200::new().with_state(
+            InlineResponse200State::new()
+                .with_status("exited".to_string())
+                .with_started_at("1970-01-01T00:00:00Z".to_string())
+                .with_finished_at("1970-01-01T00:00:00Z".to_string()),
+        );
+
+        let state = runtime_state(&container);
+
+        assert_eq!(None, state.started_at());
+        assert_eq!(None, state.finished_at());
+    }
+
     #[test]
     fn parse_top_response_returns_pid_array() {
         let response = InlineResponse2001::new()
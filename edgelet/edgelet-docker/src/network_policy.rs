@@ -0,0 +1,407 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Limits the modules and addresses that a module can connect to.
+//!
+//! A module that may not connect anywhere is created without a network. The
+//! connections of a module that may only connect to some targets are limited
+//! by iptables rules in Docker's `DOCKER-USER` chain, which are added when the
+//! module starts, since its address and the addresses of the modules it may
+//! connect to are only known then.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::process::Command;
+use std::str::FromStr;
+
+use failure::{Fail, ResultExt};
+use log::debug;
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+
+use crate::error::{Error, ErrorKind, Result};
+
+// Docker doesn't touch the rules in this chain, and runs it before its own
+// rules for the traffic of containers.
+const IPTABLES_CHAIN: &str = "DOCKER-USER";
+
+#[derive(Clone, Debug, Default, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct NetworkPolicy {
+    #[serde(default)]
+    default_deny: bool,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    modules: HashMap<String, ModuleNetworkPolicy>,
+}
+
+impl NetworkPolicy {
+    pub fn new(default_deny: bool, modules: HashMap<String, ModuleNetworkPolicy>) -> Self {
+        NetworkPolicy {
+            default_deny,
+            modules,
+        }
+    }
+
+    /// Whether modules without a policy may not connect anywhere, rather than
+    /// anywhere they like.
+    pub fn default_deny(&self) -> bool {
+        self.default_deny
+    }
+
+    /// The policies of modules by their name.
+    pub fn modules(&self) -> &HashMap<String, ModuleNetworkPolicy> {
+        &self.modules
+    }
+
+    /// The targets that `module` may connect to, or `None` if it may connect
+    /// anywhere.
+    pub fn allowed(&self, module: &str) -> Option<&[EgressTarget]> {
+        match self.modules.get(module) {
+            Some(policy) => Some(policy.allow()),
+            None if self.default_deny => Some(&[]),
+            None => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct ModuleNetworkPolicy {
+    #[serde(default)]
+    allow: Vec<EgressTarget>,
+}
+
+impl ModuleNetworkPolicy {
+    pub fn new(allow: Vec<EgressTarget>) -> Self {
+        ModuleNetworkPolicy { allow }
+    }
+
+    pub fn allow(&self) -> &[EgressTarget] {
+        &self.allow
+    }
+}
+
+/// A module or a range of addresses that a module may connect to.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EgressTarget {
+    Module(String),
+    Cidr(IpAddr, u8),
+}
+
+impl FromStr for EgressTarget {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::from(ErrorKind::InvalidEgressTarget(s.to_string()));
+
+        let (address, prefix_len) = match s.find('/') {
+            Some(i) => (&s[..i], Some(&s[i + 1..])),
+            None => (s, None),
+        };
+        match (address.parse::<IpAddr>(), prefix_len) {
+            (Ok(address), prefix_len) => {
+                let max_prefix_len = if address.is_ipv4() { 32 } else { 128 };
+                let prefix_len = match prefix_len {
+                    Some(prefix_len) => prefix_len.parse().map_err(|_| invalid())?,
+                    None => max_prefix_len,
+                };
+                if prefix_len > max_prefix_len {
+                    return Err(invalid());
+                }
+                Ok(EgressTarget::Cidr(address, prefix_len))
+            }
+            (Err(_), None)
+                if !s.is_empty()
+                    && s.chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.') =>
+            {
+                Ok(EgressTarget::Module(s.to_string()))
+            }
+            (Err(_), _) => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for EgressTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EgressTarget::Module(name) => write!(f, "{}", name),
+            EgressTarget::Cidr(address, prefix_len) => write!(f, "{}/{}", address, prefix_len),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for EgressTarget {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for EgressTarget {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+/// Replaces the iptables rules of `module` with ones that only let it, at
+/// any of `sources`, connect to `destinations` and answer connections made to
+/// it.
+pub fn apply_egress_rules(
+    module: &str,
+    sources: &[IpAddr],
+    destinations: &[(IpAddr, u8)],
+) -> Result<()> {
+    clear_egress_rules(module)?;
+    for (program, rule) in egress_rules(module, sources, destinations) {
+        run(module, program, &rule)?;
+    }
+    Ok(())
+}
+
+/// Removes the iptables rules of `module`, so that they don't apply to the
+/// container that gets its address next.
+pub fn clear_egress_rules(module: &str) -> Result<()> {
+    for program in &["iptables", "ip6tables"] {
+        let listing = run(
+            module,
+            program,
+            &["-S".to_string(), IPTABLES_CHAIN.to_string()],
+        )?;
+        for rule in stale_rules(&listing, module) {
+            run(module, program, &rule)?;
+        }
+    }
+    Ok(())
+}
+
+fn rule_comment(module: &str) -> String {
+    format!("iotedge:{}", module)
+}
+
+// The rules are inserted at the top of the chain in order, so the connections
+// that are allowed are accepted before the rest are dropped.
+fn egress_rules(
+    module: &str,
+    sources: &[IpAddr],
+    destinations: &[(IpAddr, u8)],
+) -> Vec<(&'static str, Vec<String>)> {
+    let comment = rule_comment(module);
+    let mut rules = vec![];
+    for source in sources {
+        let (program, source_prefix_len) = if source.is_ipv4() {
+            ("iptables", 32)
+        } else {
+            ("ip6tables", 128)
+        };
+        let source = format!("{}/{}", source, source_prefix_len);
+        let mut position = 0;
+        let mut rule = |matches: Vec<String>, target: &str| {
+            position += 1;
+            let mut args = vec![
+                "-I".to_string(),
+                IPTABLES_CHAIN.to_string(),
+                position.to_string(),
+                "-s".to_string(),
+                source.clone(),
+            ];
+            args.extend(matches);
+            args.extend(
+                vec!["-m", "comment", "--comment", comment.as_str(), "-j", target]
+                    .into_iter()
+                    .map(ToString::to_string),
+            );
+            rules.push((program, args));
+        };
+
+        rule(
+            vec![
+                "-m".to_string(),
+                "conntrack".to_string(),
+                "--ctstate".to_string(),
+                "ESTABLISHED,RELATED".to_string(),
+            ],
+            "ACCEPT",
+        );
+        for (address, prefix_len) in destinations {
+            // iptables and ip6tables only see addresses of their own family.
+            if address.is_ipv4() == (program == "iptables") {
+                rule(
+                    vec!["-d".to_string(), format!("{}/{}", address, prefix_len)],
+                    "ACCEPT",
+                );
+            }
+        }
+        rule(vec![], "DROP");
+    }
+    rules
+}
+
+// `iptables -S` lists the rules of the chain as the arguments that append
+// them, so the rules of the module are deleted with the same arguments.
+fn stale_rules(listing: &str, module: &str) -> Vec<Vec<String>> {
+    let comment = rule_comment(module);
+    listing
+        .lines()
+        .filter_map(|line| {
+            let args: Vec<&str> = line.split_whitespace().collect();
+            let commented = args.windows(2).any(|pair| {
+                pair[0] == "--comment" && pair[1].trim_matches('"') == comment.as_str()
+            });
+            match args.split_first() {
+                Some((&"-A", rest)) if commented => Some(
+                    Some("-D")
+                        .into_iter()
+                        .chain(rest.iter().cloned())
+                        .map(|arg| arg.trim_matches('"').to_string())
+                        .collect(),
+                ),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+fn run(module: &str, program: &str, args: &[String]) -> Result<String> {
+    debug!("Running {} {}", program, args.join(" "));
+
+    let context = || ErrorKind::NetworkPolicy(module.to_string());
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .with_context(|_| context())?;
+    if !output.status.success() {
+        let message = format!(
+            "{} failed: {} ({})",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim(),
+            output.status
+        );
+        return Err(Error::from(
+            ErrorKind::NetworkPolicyCommand(message).context(context()),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn parse_egress_targets() {
+        assert_eq!(
+            EgressTarget::Module("edgeHub".to_string()),
+            "edgeHub".parse::<EgressTarget>().unwrap()
+        );
+        assert_eq!(
+            EgressTarget::Cidr("10.0.0.0".parse().unwrap(), 8),
+            "10.0.0.0/8".parse::<EgressTarget>().unwrap()
+        );
+        assert_eq!(
+            EgressTarget::Cidr("192.168.1.20".parse().unwrap(), 32),
+            "192.168.1.20".parse::<EgressTarget>().unwrap()
+        );
+        assert_eq!(
+            EgressTarget::Cidr("fd00::".parse().unwrap(), 64),
+            "fd00::/64".parse::<EgressTarget>().unwrap()
+        );
+
+        for invalid in &["", "10.0.0.0/33", "10.0.0.0/", "edge hub", "edgeHub/8"] {
+            match invalid.parse::<EgressTarget>().unwrap_err().kind() {
+                ErrorKind::InvalidEgressTarget(target) => assert_eq!(*invalid, target.as_str()),
+                kind => panic!("Expected `InvalidEgressTarget` error but got {:?}", kind),
+            }
+        }
+    }
+
+    #[test]
+    fn parse_policy() {
+        let policy: NetworkPolicy = serde_json::from_value(json!({
+            "default_deny": true,
+            "modules": {
+                "edgeHub": { "allow": ["0.0.0.0/0"] },
+                "sensor": { "allow": ["edgeHub", "10.1.0.0/16"] },
+                "offline": {},
+            },
+        }))
+        .unwrap();
+
+        assert!(policy.default_deny());
+        assert_eq!(
+            Some(
+                &[
+                    EgressTarget::Module("edgeHub".to_string()),
+                    EgressTarget::Cidr("10.1.0.0".parse().unwrap(), 16),
+                ][..]
+            ),
+            policy.allowed("sensor")
+        );
+        assert_eq!(Some(&[][..]), policy.allowed("offline"));
+        assert_eq!(Some(&[][..]), policy.allowed("unlisted"));
+
+        let open: NetworkPolicy = serde_json::from_value(json!({})).unwrap();
+        assert_eq!(None, open.allowed("unlisted"));
+
+        let err = serde_json::from_value::<NetworkPolicy>(json!({
+            "modules": { "sensor": { "allow": ["not a target"] } },
+        }))
+        .unwrap_err();
+        assert!(err.to_string().contains("not a target"), "{}", err);
+    }
+
+    #[test]
+    fn egress_rules_accept_destinations_then_drop() {
+        let rules = egress_rules(
+            "sensor",
+            &["172.18.0.5".parse().unwrap()],
+            &[
+                ("172.18.0.2".parse().unwrap(), 32),
+                ("fd00::".parse().unwrap(), 64),
+            ],
+        );
+
+        let rules: Vec<_> = rules
+            .into_iter()
+            .map(|(program, args)| format!("{} {}", program, args.join(" ")))
+            .collect();
+        assert_eq!(
+            vec![
+                "iptables -I DOCKER-USER 1 -s 172.18.0.5/32 -m conntrack --ctstate ESTABLISHED,RELATED -m comment --comment iotedge:sensor -j ACCEPT",
+                "iptables -I DOCKER-USER 2 -s 172.18.0.5/32 -d 172.18.0.2/32 -m comment --comment iotedge:sensor -j ACCEPT",
+                "iptables -I DOCKER-USER 3 -s 172.18.0.5/32 -m comment --comment iotedge:sensor -j DROP",
+            ],
+            rules
+        );
+    }
+
+    #[test]
+    fn stale_rules_are_those_of_module() {
+        let listing = "-N DOCKER-USER\n\
+                       -A DOCKER-USER -s 172.18.0.5/32 -m comment --comment iotedge:sensor -j DROP\n\
+                       -A DOCKER-USER -s 172.18.0.6/32 -m comment --comment \"iotedge:sensor2\" -j DROP\n\
+                       -A DOCKER-USER -j RETURN\n";
+
+        assert_eq!(
+            vec![vec![
+                "-D",
+                "DOCKER-USER",
+                "-s",
+                "172.18.0.5/32",
+                "-m",
+                "comment",
+                "--comment",
+                "iotedge:sensor",
+                "-j",
+                "DROP"
+            ]],
+            stale_rules(listing, "sensor")
+        );
+    }
+}
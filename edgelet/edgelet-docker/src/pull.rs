@@ -0,0 +1,280 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use failure::Fail;
+use futures::future::{self, Either, Shared};
+use futures::sync::oneshot;
+use futures::Future;
+use log::debug;
+
+use crate::error::{Error, ErrorKind};
+
+pub type PullFuture = Box<dyn Future<Item = (), Error = Error> + Send>;
+
+/// Shares a single in-flight pull between all callers pulling the same image
+/// reference, and optionally caps the number of pulls that run at once.
+#[derive(Clone, Default)]
+pub struct PullCoalescer {
+    in_flight: Arc<Mutex<HashMap<String, Shared<PullFuture>>>>,
+    limit: Option<PullLimit>,
+}
+
+impl PullCoalescer {
+    pub fn new(max_concurrent_pulls: Option<usize>) -> Self {
+        PullCoalescer {
+            in_flight: Arc::new(Mutex::new(HashMap::new())),
+            limit: max_concurrent_pulls.map(PullLimit::new),
+        }
+    }
+
+    /// Runs `pull` for `image` unless a pull of the same image is already in
+    /// flight, in which case the caller waits for that pull instead. Every
+    /// caller gets the result of the pull it waited on.
+    pub fn pull<F>(&self, image: &str, pull: F) -> PullFuture
+    where
+        F: 'static + FnOnce() -> PullFuture + Send,
+    {
+        let mut in_flight = self.in_flight.lock().expect("pull coalescer lock poisoned");
+
+        let shared = if let Some(shared) = in_flight.get(image) {
+            debug!("Waiting for in-flight pull of image {}", image);
+            shared.clone()
+        } else {
+            let pull: PullFuture = match &self.limit {
+                Some(limit) => Box::new(limit.acquire().and_then(move |permit| {
+                    pull().then(move |result| {
+                        drop(permit);
+                        result
+                    })
+                })),
+                None => Box::new(future::lazy(pull)),
+            };
+
+            // The entry is removed once the pull completes so that a later
+            // pull of the same image goes to the registry again.
+            let key = image.to_string();
+            let in_flight_copy = self.in_flight.clone();
+            let pull: PullFuture = Box::new(pull.then(move |result| {
+                in_flight_copy
+                    .lock()
+                    .expect("pull coalescer lock poisoned")
+                    .remove(&key);
+                result
+            }));
+
+            let shared = pull.shared();
+            in_flight.insert(image.to_string(), shared.clone());
+            shared
+        };
+
+        Box::new(shared.then(|result| match result {
+            Ok(_) => Ok(()),
+            Err(err) => Err(copy_error(&err)),
+        }))
+    }
+}
+
+// Errors can't be cloned, so each caller gets an error of the same kind with
+// the same message as the one the pull, or restart, failed with.
+pub(crate) fn copy_error(err: &Error) -> Error {
+    let kind = match err.kind() {
+        ErrorKind::RegistryOperation(op) => ErrorKind::RegistryOperation(op.clone()),
+        ErrorKind::RuntimeOperation(op) => ErrorKind::RuntimeOperation(op.clone()),
+        kind => ErrorKind::FormattedDockerRuntime(kind.to_string()),
+    };
+
+    match err.cause() {
+        Some(cause) => {
+            let cause = match cause.find_root_cause().downcast_ref::<ErrorKind>() {
+                Some(ErrorKind::NotFound(message)) => ErrorKind::NotFound(message.clone()),
+                Some(ErrorKind::Conflict) => ErrorKind::Conflict,
+                Some(ErrorKind::NotModified) => ErrorKind::NotModified,
+                Some(ErrorKind::DigestMismatch(image, expected, found)) => {
+                    ErrorKind::DigestMismatch(image.clone(), expected.clone(), found.clone())
+                }
+                _ => ErrorKind::FormattedDockerRuntime(cause.to_string()),
+            };
+            Error::from(cause.context(kind))
+        }
+        None => Error::from(kind),
+    }
+}
+
+// A counting semaphore. Waiters are woken in the order they arrived.
+#[derive(Clone)]
+struct PullLimit {
+    state: Arc<Mutex<PullLimitState>>,
+}
+
+struct PullLimitState {
+    available: usize,
+    waiters: VecDeque<oneshot::Sender<PullPermit>>,
+}
+
+impl PullLimit {
+    fn new(max_concurrent_pulls: usize) -> Self {
+        PullLimit {
+            state: Arc::new(Mutex::new(PullLimitState {
+                available: max_concurrent_pulls.max(1),
+                waiters: VecDeque::new(),
+            })),
+        }
+    }
+
+    fn acquire(&self) -> impl Future<Item = PullPermit, Error = Error> + Send {
+        let mut state = self.state.lock().expect("pull limit lock poisoned");
+        if state.available > 0 {
+            state.available -= 1;
+            Either::A(future::ok(PullPermit {
+                limit: Some(self.clone()),
+            }))
+        } else {
+            debug!("Waiting for one of the running image pulls to complete");
+            let (sender, receiver) = oneshot::channel();
+            state.waiters.push_back(sender);
+            // The sender is never dropped without sending while the limit is alive
+            Either::B(receiver.map_err(|_| Error::from(ErrorKind::Docker)))
+        }
+    }
+
+    fn release(&self) {
+        let mut state = self.state.lock().expect("pull limit lock poisoned");
+        let mut permit = PullPermit {
+            limit: Some(self.clone()),
+        };
+
+        // Hand the permit straight to the next waiter that is still around.
+        while let Some(waiter) = state.waiters.pop_front() {
+            match waiter.send(permit) {
+                Ok(()) => return,
+                Err(returned) => permit = returned,
+            }
+        }
+
+        permit.limit = None;
+        state.available += 1;
+    }
+}
+
+struct PullPermit {
+    limit: Option<PullLimit>,
+}
+
+impl Drop for PullPermit {
+    fn drop(&mut self) {
+        if let Some(limit) = self.limit.take() {
+            limit.release();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use edgelet_core::RegistryOperation;
+    use futures::Async;
+
+    use super::*;
+
+    fn counting_pull(
+        calls: &Arc<AtomicUsize>,
+        receiver: oneshot::Receiver<Result<(), Error>>,
+    ) -> impl FnOnce() -> PullFuture + Send + 'static {
+        let calls = calls.clone();
+        move || {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Box::new(
+                receiver
+                    .map_err(|_| Error::from(ErrorKind::Docker))
+                    .and_then(|result| result),
+            )
+        }
+    }
+
+    #[test]
+    fn concurrent_pulls_of_same_image_are_coalesced() {
+        let coalescer = PullCoalescer::new(None);
+        let calls = Arc::new(AtomicUsize::new(0));
+        let (sender, receiver) = oneshot::channel();
+        let (_, unused) = oneshot::channel();
+
+        let first = coalescer.pull("nginx:latest", counting_pull(&calls, receiver));
+        let second = coalescer.pull("nginx:latest", counting_pull(&calls, unused));
+        sender.send(Ok(())).unwrap();
+
+        first.join(second).wait().unwrap();
+        assert_eq!(1, calls.load(Ordering::SeqCst));
+
+        // the pull is no longer in flight, so the next one goes upstream again
+        let (sender, receiver) = oneshot::channel();
+        let third = coalescer.pull("nginx:latest", counting_pull(&calls, receiver));
+        sender.send(Ok(())).unwrap();
+        third.wait().unwrap();
+        assert_eq!(2, calls.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn coalesced_pulls_all_get_the_error() {
+        let coalescer = PullCoalescer::new(None);
+        let calls = Arc::new(AtomicUsize::new(0));
+        let (sender, receiver) = oneshot::channel();
+        let (_, unused) = oneshot::channel();
+
+        let first = coalescer.pull("nginx:latest", counting_pull(&calls, receiver));
+        let second = coalescer.pull("nginx:latest", counting_pull(&calls, unused));
+        sender
+            .send(Err(Error::from(
+                ErrorKind::NotFound("manifest unknown".to_string()).context(
+                    ErrorKind::RegistryOperation(RegistryOperation::PullImage(
+                        "nginx:latest".to_string(),
+                    )),
+                ),
+            )))
+            .unwrap();
+
+        for result in vec![first.wait(), second.wait()] {
+            let err = result.unwrap_err();
+            assert_eq!("Could not pull image nginx:latest", err.to_string());
+            match Fail::find_root_cause(&err).downcast_ref::<ErrorKind>() {
+                Some(ErrorKind::NotFound(message)) => assert_eq!("manifest unknown", message),
+                cause => panic!("Expected `NotFound` error but got {:?}", cause),
+            }
+        }
+        assert_eq!(1, calls.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn pulls_of_different_images_are_not_coalesced() {
+        let coalescer = PullCoalescer::new(None);
+        let calls = Arc::new(AtomicUsize::new(0));
+        let (sender1, receiver1) = oneshot::channel();
+        let (sender2, receiver2) = oneshot::channel();
+
+        let first = coalescer.pull("nginx:1.0", counting_pull(&calls, receiver1));
+        let second = coalescer.pull("nginx:2.0", counting_pull(&calls, receiver2));
+        sender1.send(Ok(())).unwrap();
+        sender2.send(Ok(())).unwrap();
+
+        first.join(second).wait().unwrap();
+        assert_eq!(2, calls.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn limit_defers_pulls_until_a_permit_is_released() {
+        let limit = PullLimit::new(1);
+
+        let first = limit.acquire().wait().unwrap();
+        let mut second = limit.acquire();
+        let ready = future::poll_fn(|| second.poll().map(|ready| Async::Ready(ready.is_ready())))
+            .wait()
+            .unwrap();
+        assert!(!ready);
+
+        drop(first);
+        let _second = second.wait().unwrap();
+        assert_eq!(0, limit.state.lock().unwrap().available);
+    }
+}
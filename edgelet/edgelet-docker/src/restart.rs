@@ -0,0 +1,139 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use futures::future::{self, Shared};
+use futures::Future;
+use log::debug;
+
+use crate::error::Error;
+use crate::pull::copy_error;
+
+pub type RestartFuture = Box<dyn Future<Item = (), Error = Error> + Send>;
+
+/// Tracks the module restarts that are in flight, so that a module that is
+/// asked to restart again, or to start, while it restarts is cycled only once.
+#[derive(Clone, Default)]
+pub struct InFlightRestarts {
+    in_flight: Arc<Mutex<HashMap<String, Shared<RestartFuture>>>>,
+}
+
+impl InFlightRestarts {
+    pub fn new() -> Self {
+        InFlightRestarts::default()
+    }
+
+    /// Runs `restart` for the module unless it is already restarting, in
+    /// which case the caller waits for that restart instead.
+    pub fn restart<F>(&self, id: &str, restart: F) -> RestartFuture
+    where
+        F: 'static + FnOnce() -> RestartFuture + Send,
+    {
+        let mut in_flight = self
+            .in_flight
+            .lock()
+            .expect("in-flight restarts lock poisoned");
+
+        let shared = if let Some(shared) = in_flight.get(id) {
+            debug!("Waiting for in-flight restart of module {}", id);
+            shared.clone()
+        } else {
+            let key = id.to_string();
+            let in_flight_copy = self.in_flight.clone();
+            let restart: RestartFuture = Box::new(future::lazy(restart).then(move |result| {
+                in_flight_copy
+                    .lock()
+                    .expect("in-flight restarts lock poisoned")
+                    .remove(&key);
+                result
+            }));
+
+            let shared = restart.shared();
+            in_flight.insert(id.to_string(), shared.clone());
+            shared
+        };
+
+        wait(shared)
+    }
+
+    /// Returns the restart of the module that is in flight, if there is one.
+    pub fn get(&self, id: &str) -> Option<RestartFuture> {
+        self.in_flight
+            .lock()
+            .expect("in-flight restarts lock poisoned")
+            .get(id)
+            .cloned()
+            .map(wait)
+    }
+}
+
+fn wait(shared: Shared<RestartFuture>) -> RestartFuture {
+    Box::new(shared.then(|result| match result {
+        Ok(_) => Ok(()),
+        Err(err) => Err(copy_error(&err)),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use futures::sync::oneshot;
+
+    use edgelet_core::RuntimeOperation;
+
+    use super::*;
+    use crate::error::ErrorKind;
+
+    #[test]
+    fn concurrent_restarts_share_one_restart() {
+        let restarts = InFlightRestarts::new();
+        let count = Arc::new(AtomicUsize::new(0));
+        let (sender, receiver) = oneshot::channel::<()>();
+
+        let count_copy = count.clone();
+        let first = restarts.restart("m1", move || {
+            count_copy.fetch_add(1, Ordering::SeqCst);
+            Box::new(receiver.map_err(|_| Error::from(ErrorKind::Docker)))
+        });
+        let count_copy = count.clone();
+        let second = restarts.restart("m1", move || {
+            count_copy.fetch_add(1, Ordering::SeqCst);
+            Box::new(future::ok(()))
+        });
+        let waiting = restarts.get("m1").unwrap();
+
+        sender.send(()).unwrap();
+        first.join3(second, waiting).wait().unwrap();
+
+        assert_eq!(1, count.load(Ordering::SeqCst));
+        assert!(restarts.get("m1").is_none());
+    }
+
+    #[test]
+    fn failed_restart_fails_every_caller() {
+        let restarts = InFlightRestarts::new();
+        let (sender, receiver) = oneshot::channel::<()>();
+
+        let first = restarts.restart("m1", move || {
+            Box::new(receiver.then(|_| {
+                Err::<(), _>(Error::from(ErrorKind::RuntimeOperation(
+                    RuntimeOperation::RestartModule("m1".to_string()),
+                )))
+            }))
+        });
+        let waiting = restarts.get("m1").unwrap();
+
+        sender.send(()).unwrap();
+        for err in &[first.wait().unwrap_err(), waiting.wait().unwrap_err()] {
+            match err.kind() {
+                ErrorKind::RuntimeOperation(RuntimeOperation::RestartModule(name)) => {
+                    assert_eq!("m1", name)
+                }
+                kind => panic!("Expected `RestartModule` error but got {:?}", kind),
+            }
+        }
+        assert!(restarts.get("m1").is_none());
+    }
+}
@@ -1,39 +1,69 @@
 // Copyright (c) Microsoft. All rights reserved.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fs;
+use std::net::IpAddr;
 use std::ops::Deref;
-use std::time::Duration;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use base64;
+use chrono::prelude::*;
 use failure::{Fail, ResultExt};
 use futures::future::Either;
 use futures::prelude::*;
 use futures::{future, stream, Async, Stream};
 use hyper::{Body, Chunk as HyperChunk, Client, Request};
 use lazy_static::lazy_static;
-use log::{debug, info, Level};
-use serde_json;
-use url::Url;
+use log::{debug, info, warn, Level};
+use serde_json::{self, json};
+use tokio::timer::{Delay, Timeout};
 
 use docker::apis::client::APIClient;
 use docker::apis::configuration::Configuration;
-use docker::models::{ContainerCreateBody, InlineResponse200, Ipam, NetworkConfig};
+use docker::apis::image_create_result;
+use docker::models::{
+    AuthConfig, Container, ContainerConfig, ContainerCreateBody,
+    ContainerCreateBodyNetworkingConfig, DeviceMapping, EndpointSettings, ExecConfig,
+    ExecStartConfig, HostConfig, HostConfigLogConfig, ImageSummary, InlineResponse200,
+    InlineResponse200State, Ipam, Mount, NetworkConfig, ResourcesUlimits,
+    RestartPolicy as DockerRestartPolicy,
+};
 use edgelet_core::{
-    AuthId, Authenticator, GetTrustBundle, Ipam as CoreIpam, LogOptions, MakeModuleRuntime,
-    MobyNetwork, Module, ModuleId, ModuleRegistry, ModuleRuntime, ModuleRuntimeState, ModuleSpec,
-    RegistryOperation, RuntimeOperation, SystemInfo as CoreSystemInfo, SystemResources, UrlExt,
+    decode_logs_with_max_line_length, has_secret_references, is_valid_image_digest,
+    merge_create_options, normalize_create_options, redact_failure, validate_create_options,
+    validate_dns_servers, validate_global_env, validate_injected_labels, AuthId, Authenticator,
+    ExecOutput, ExitReason, GetTrustBundle, HostSecrets, ImagePruneFilter, ImagePullPolicy,
+    ImageReference, Ipam as CoreIpam, LogChunk, LogOptions, MakeModuleRuntime, MobyNetwork, Module,
+    ModuleBind, ModuleDevice, ModuleError, ModuleErrorCode, ModuleEvents, ModuleId,
+    ModuleLifecycleEventKind, ModuleLogConfig, ModuleNetwork, ModuleRegistry, ModuleResources,
+    ModuleRuntime, ModuleRuntimeErrorReason, ModuleRuntimeState, ModuleSpec, ModuleStats,
+    ModuleTmpfs, ModuleUlimit, PreStopHook, ProvisioningResult as CoreProvisioningResult,
+    PrunedImages, RegistryOperation, RestartPolicy, RuntimeOperation, RuntimeSettings,
+    SystemInfo as CoreSystemInfo, SystemResources, UrlExt, PRE_STOP_LABEL,
 };
 use edgelet_http::{Pid, UrlConnector};
 use edgelet_utils::{ensure_not_empty_with_context, log_failure};
 use provisioning::ProvisioningResult;
 
+use crate::acr::AcrTokens;
 use crate::client::DockerClient;
 use crate::config::DockerConfig;
+use crate::credentials::CredentialHelpers;
 use crate::error::{Error, ErrorKind, Result};
+use crate::identity::IdentityIntegration;
 use crate::module::{
     runtime_state, DockerModule, DockerModuleTop, MODULE_TYPE as DOCKER_MODULE_TYPE,
 };
-use crate::settings::Settings;
+use crate::network_policy::{self, EgressTarget, NetworkPolicy};
+use crate::pull::PullCoalescer;
+use crate::restart::InFlightRestarts;
+use crate::settings::{MobyRuntime, Settings};
+use crate::stats::parse_stats;
+use crate::tags::{is_tag_pattern, TagResolver};
+use crate::throttle::PullThrottle;
 
 #[cfg(not(windows))]
 use edgelet_core::DiskInfo;
@@ -53,6 +83,19 @@ type Deserializer = &'static mut serde_json::Deserializer<serde_json::de::IoRead
 static LABEL_KEY: &str = "net.azure-devices.edge.owner";
 static LABEL_VALUE: &str = "Microsoft.Azure.Devices.Edge.Agent";
 
+// Where the images whose tag is a pattern are saved with what they resolved to.
+const RESOLVED_IMAGES_FILENAME: &str = "resolved_images.json";
+
+// Added to the request timeout of requests that only finish once a container
+// has stopped. Docker waits this long before killing the container if it
+// isn't told otherwise.
+const DOCKER_STOP_TIMEOUT: Duration = Duration::from_secs(10);
+const NO_GRACE: Duration = Duration::from_secs(0);
+
+/// How long a container that couldn't be force removed is given to exit after
+/// it's killed, before it's removed again.
+const FORCE_REMOVE_RETRY_DELAY: Duration = Duration::from_secs(1);
+
 lazy_static! {
     static ref LABELS: Vec<&'static str> = {
         let mut labels = vec![];
@@ -64,6 +107,31 @@ lazy_static! {
 #[derive(Clone)]
 pub struct DockerModuleRuntime {
     client: DockerClient<UrlConnector>,
+    pulls: PullCoalescer,
+    max_pull_rate: Option<u64>,
+    restarts: InFlightRestarts,
+    events: ModuleEvents,
+    log_config: ModuleLogConfig,
+    strict_binds: bool,
+    strict_create_options: bool,
+    default_create_options: Option<serde_json::Value>,
+    secrets_file: Option<PathBuf>,
+    credential_helpers: CredentialHelpers,
+    acr_tokens: AcrTokens,
+    dns: Vec<String>,
+    dns_search: Vec<String>,
+    create_networks: bool,
+    network_policy: NetworkPolicy,
+    injected_labels: HashMap<String, String>,
+    global_env: HashMap<String, String>,
+    alias_network: Option<String>,
+    identity: IdentityIntegration,
+    max_log_line_length: usize,
+    effective_specs: Arc<Mutex<HashMap<String, ModuleSpec<DockerConfig>>>>,
+    last_errors: Arc<Mutex<HashMap<String, ModuleError>>>,
+    recently_pulled: Arc<Mutex<HashSet<String>>>,
+    oom_kills: Arc<Mutex<HashMap<String, Option<DateTime<Utc>>>>>,
+    tags: TagResolver,
 }
 
 impl DockerModuleRuntime {
@@ -90,6 +158,1041 @@ impl DockerModuleRuntime {
     }
 }
 
+impl DockerModuleRuntime {
+    // The env files of the module are read for every module that is created,
+    // so that changes to them apply to the next module created without a
+    // restart. The module's variables take precedence over those of its
+    // identity integration, and those over the global ones. Secret
+    // references are left unresolved.
+    fn module_env(&self, module: &ModuleSpec<DockerConfig>) -> Result<HashMap<String, String>> {
+        let module_env = module.merged_env().with_context(|_| {
+            ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(module.name().to_string()))
+        })?;
+        let mut env = self.global_env.clone();
+        if module.identity_integration() {
+            env.extend(self.identity.env(module, &module_env));
+        }
+        env.extend(module_env);
+        Ok(env)
+    }
+
+    // The secrets file is read whenever a module that refers to a secret is
+    // created, like the env files are.
+    fn resolve_secrets(
+        &self,
+        module: &ModuleSpec<DockerConfig>,
+        env: &HashMap<String, String>,
+    ) -> Result<HashMap<String, String>> {
+        if !has_secret_references(env) {
+            return Ok(env.clone());
+        }
+
+        let context = || {
+            ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(module.name().to_string()))
+        };
+        let secrets = match &self.secrets_file {
+            Some(secrets_file) => HostSecrets::load(secrets_file).with_context(|_| context())?,
+            None => HostSecrets::new(),
+        };
+        let env = secrets.resolve_env(env).with_context(|_| context())?;
+        Ok(env)
+    }
+
+    // Only the modules that were created since the daemon started have one.
+    // Warns about the names on the alias network that the module would share
+    // with the other modules that were created, which may have been meant to
+    // reach only one of them.
+    fn warn_alias_collisions(
+        &self,
+        module: &ModuleSpec<DockerConfig>,
+        create_options: &ContainerCreateBody,
+    ) {
+        let network = match self.alias_network {
+            Some(ref network) => network,
+            None => return,
+        };
+
+        let names_on =
+            |name: &str, create_options: &ContainerCreateBody, networks: &[ModuleNetwork]| {
+                let mut names = network_aliases(create_options, networks, network);
+                names.push(self.client.container_name(name));
+                names.sort();
+                names.dedup();
+                names
+            };
+        let names = names_on(module.name(), create_options, module.networks());
+
+        let specs = self
+            .effective_specs
+            .lock()
+            .expect("Effective specs lock poisoned");
+        let others = specs
+            .values()
+            .filter(|other| other.name() != module.name())
+            .map(|other| {
+                let names = names_on(
+                    other.name(),
+                    other.config().create_options(),
+                    other.networks(),
+                );
+                (other.name(), names)
+            });
+        for (name, other) in alias_collisions(&names, others) {
+            warn!(
+                "Module {} and module {} can both be reached as {} on network {}, so connections to it may reach either of them",
+                module.name(),
+                other,
+                name,
+                network
+            );
+        }
+    }
+
+    // Drops what is kept about a module once its container is removed.
+    fn forget_module(&self, id: &str) {
+        self.effective_specs
+            .lock()
+            .expect("Effective specs lock poisoned")
+            .remove(id);
+        self.clear_last_error(id);
+
+        // Otherwise the rules would apply to the next container that gets the
+        // address of the module.
+        let has_rules = self
+            .network_policy
+            .allowed(id)
+            .map_or(false, |allowed| !allowed.is_empty());
+        if has_rules {
+            if let Err(err) = network_policy::clear_egress_rules(id) {
+                log_failure(Level::Warn, &err);
+            }
+        }
+    }
+
+    // Removes the container of the module even if it is running, along with
+    // its anonymous volumes.
+    fn delete_container(&self, id: &str) -> impl Future<Item = (), Error = Error> + Send {
+        let context = {
+            let id = id.to_string();
+            move || ErrorKind::RuntimeOperation(RuntimeOperation::RemoveModule(id))
+        };
+        let delete_context = context.clone();
+        let delete = self
+            .client
+            .container_api()
+            .container_delete(
+                &self.client.container_name(id),
+                /* remove volumes */ true,
+                /* force */ true,
+                /* remove link */ false,
+            )
+            .map_err(move |err| Error::from_docker_error(err, delete_context()));
+        self.client.timeout(delete, NO_GRACE, context)
+    }
+
+    fn kill_container(&self, id: &str) -> impl Future<Item = (), Error = Error> + Send {
+        let context = {
+            let id = id.to_string();
+            move || ErrorKind::RuntimeOperation(RuntimeOperation::RemoveModule(id))
+        };
+        let kill_context = context.clone();
+        let kill = self
+            .client
+            .container_api()
+            .container_kill(&self.client.container_name(id), "SIGKILL")
+            .map_err(move |err| Error::from_docker_error(err, kill_context()));
+        self.client.timeout(kill, NO_GRACE, context)
+    }
+
+    fn record_effective_spec(&self, spec: ModuleSpec<DockerConfig>) {
+        self.effective_specs
+            .lock()
+            .expect("Effective specs lock poisoned")
+            .insert(spec.name().to_string(), spec);
+    }
+
+    // The errors of failures that Docker doesn't remember, like failed pulls,
+    // are kept until the module is created or removed. The more recent of
+    // the kept error and the one that Docker reports is the last error.
+    fn with_last_error(&self, name: &str, state: ModuleRuntimeState) -> ModuleRuntimeState {
+        let kept = self
+            .last_errors
+            .lock()
+            .expect("Last errors lock poisoned")
+            .get(name)
+            .cloned();
+        let last_error = match (kept, state.last_error().cloned()) {
+            (Some(kept), Some(reported)) => Some(if kept.timestamp() > reported.timestamp() {
+                kept
+            } else {
+                reported
+            }),
+            (kept, reported) => kept.or(reported),
+        };
+        state.with_last_error(last_error)
+    }
+
+    fn clear_last_error(&self, name: &str) {
+        self.last_errors
+            .lock()
+            .expect("Last errors lock poisoned")
+            .remove(name);
+    }
+}
+
+// The security options of the profiles of a module. The seccomp profile is
+// read for every module that is created, like its env files are, since Docker
+// takes the profile itself rather than its path.
+fn security_profiles(module: &ModuleSpec<DockerConfig>) -> Result<Vec<String>> {
+    let mut security_opt = vec![];
+
+    if let Some(path) = module.seccomp_profile() {
+        let context = || ErrorKind::SeccompProfile(path.display().to_string());
+        let profile = fs::read_to_string(path).with_context(|_| context())?;
+        let profile: serde_json::Value =
+            serde_json::from_str(&profile).with_context(|_| context())?;
+        security_opt.push(format!("{}={}", SECCOMP, profile));
+    }
+    if let Some(profile) = module.apparmor_profile() {
+        security_opt.push(format!("{}={}", APPARMOR, profile));
+    }
+
+    Ok(security_opt)
+}
+
+impl DockerModuleRuntime {
+    // The credential helper or the ACR identity of the image's registry, if it has either, takes
+    // precedence over the credentials in the module spec.
+    fn registry_auth(
+        &self,
+        image: &str,
+        auth: Option<AuthConfig>,
+    ) -> Box<dyn Future<Item = Option<AuthConfig>, Error = Error> + Send> {
+        if let Some(auth) = self.credential_helpers.auth(image) {
+            Box::new(future::result(auth.map(Some)))
+        } else if let Some(auth) = self.acr_tokens.auth(image) {
+            Box::new(auth.map(Some))
+        } else {
+            Box::new(future::ok(auth))
+        }
+    }
+
+    fn pull_credentials(
+        &self,
+        image: &str,
+        auth: Option<AuthConfig>,
+    ) -> Box<dyn Future<Item = String, Error = Error> + Send> {
+        let auth = self.registry_auth(image, auth);
+
+        let image = image.to_string();
+        let context =
+            move || ErrorKind::RegistryOperation(RegistryOperation::PullImage(image.clone()));
+        Box::new(auth.then(move |auth| -> Result<String> {
+            let auth = auth.map_err(|err| Error::from(err.context(context())))?;
+            auth.map_or_else(
+                || Ok("".to_string()),
+                |auth| {
+                    let json = serde_json::to_string(&auth).with_context(|_| context())?;
+                    Ok(base64::encode(&json))
+                },
+            )
+        }))
+    }
+
+    fn pull_with_credentials(
+        &self,
+        image: String,
+        digest: Option<String>,
+        auth: Option<AuthConfig>,
+        max_pull_rate: Option<u64>,
+    ) -> impl Future<Item = (), Error = Error> + Send {
+        let client = self.client.clone();
+        let pulls = self.pulls.clone();
+        self.pull_credentials(&image, auth).and_then(move |creds| {
+            let pull_image = image.clone();
+            pulls.pull(&image, move || {
+                let verify_client = client.clone();
+                let create: Box<
+                    dyn Future<Item = (), Error = docker::apis::Error<serde_json::Value>> + Send,
+                > = match max_pull_rate {
+                    Some(rate) => Box::new(
+                        client
+                            .image_api()
+                            .image_create_progress(&pull_image, "", "", "", "", &creds, "")
+                            .and_then(move |progress| {
+                                PullThrottle::new(progress, rate)
+                                    .concat2()
+                                    .map_err(docker::apis::Error::from)
+                            })
+                            .and_then(|progress| image_create_result(&progress)),
+                    ),
+                    None => {
+                        client
+                            .image_api()
+                            .image_create(&pull_image, "", "", "", "", &creds, "")
+                    }
+                };
+                Box::new(
+                    create
+                        .then(move |result| match result {
+                            Ok(()) => Ok(pull_image),
+                            Err(err) => Err(Error::from_docker_error(
+                                err,
+                                ErrorKind::RegistryOperation(RegistryOperation::PullImage(
+                                    pull_image,
+                                )),
+                            )),
+                        })
+                        .and_then(move |image| match digest {
+                            Some(digest) => Either::A(verify_digest(&verify_client, image, digest)),
+                            None => Either::B(future::ok(())),
+                        }),
+                )
+            })
+        })
+    }
+
+    // The config with its image pinned to the digest of the tag that its
+    // pattern resolved to, with the same credentials that pull it.
+    fn resolve_image(
+        &self,
+        config: &DockerConfig,
+    ) -> impl Future<Item = DockerConfig, Error = Error> + Send {
+        let image = config.image().to_string();
+        let tags = self.tags.clone();
+        let config = config.clone();
+        let auth = match config.read_auth() {
+            Ok(auth) => auth,
+            Err(err) => {
+                let err = err.context(ErrorKind::ResolveTag(image));
+                return Either::B(future::err(Error::from(err)));
+            }
+        };
+        Either::A(
+            self.registry_auth(&image, auth)
+                .and_then(move |auth| tags.resolve(&image, auth))
+                .map(move |image| config.with_image(image)),
+        )
+    }
+
+    /// Loads the images in `path`, a tarball made by `docker save`, so that
+    /// modules can use them without pulling them from their registry.
+    pub fn import_image(&self, path: &Path) -> impl Future<Item = (), Error = Error> + Send {
+        load_images(&self.client, path)
+    }
+
+    fn create_container(
+        &self,
+        create_options: ContainerCreateBody,
+        module: ModuleSpec<DockerConfig>,
+    ) -> impl Future<Item = ModuleSpec<DockerConfig>, Error = Error> + Send {
+        let context = {
+            let name = module.name().to_string();
+            move || ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(name))
+        };
+
+        let client = self.client.clone();
+        let create = check_command(
+            &self.client,
+            module.config().pinned_image().into_owned(),
+            module.name().to_string(),
+            &create_options,
+        )
+        .and_then(move |()| {
+            client
+                .create_container(create_options, module.name())
+                .then(|result| match result {
+                    Ok(_) => Ok(module),
+                    Err(err) => {
+                        let err = Error::from_docker_error(
+                            err,
+                            ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(
+                                module.name().to_string(),
+                            )),
+                        );
+                        Err(resource_limit_error(err, &module))
+                    }
+                })
+        });
+
+        self.client.timeout(create, NO_GRACE, context)
+    }
+
+    // Docker can report a pull as done before it can create containers from the
+    // image, so if creating a module right after its image was pulled doesn't
+    // find the image, it's pulled again and the create is retried once. If
+    // that fails too, the create fails with the first error.
+    fn create_container_after_pull(
+        &self,
+        create_options: ContainerCreateBody,
+        module: ModuleSpec<DockerConfig>,
+    ) -> impl Future<Item = ModuleSpec<DockerConfig>, Error = Error> + Send {
+        let image = module.config().pinned_image().into_owned();
+        let runtime = self.clone();
+        let retry_options = create_options.clone();
+
+        self.create_container(create_options, module.clone())
+            .then(move |result| {
+                let pulled = runtime.take_recently_pulled(&image);
+                let err = match result {
+                    Ok(module) => return Either::A(future::ok(module)),
+                    Err(err) => err,
+                };
+                if !pulled || !is_no_such_image(&err) {
+                    return Either::A(future::err(err));
+                }
+
+                info!(
+                    "Image {} was not found right after it was pulled, pulling it again to retry creating module {}",
+                    image,
+                    module.name()
+                );
+                let retry = runtime.clone();
+                Either::B(
+                    ModuleRegistry::pull(&runtime, module.config())
+                        .and_then(move |()| {
+                            retry
+                                .create_container(retry_options, module)
+                                .then(move |result| {
+                                    retry.take_recently_pulled(&image);
+                                    result
+                                })
+                        })
+                        .or_else(move |retry_err| {
+                            log_failure(Level::Warn, &retry_err);
+                            Err(err)
+                        }),
+                )
+            })
+    }
+
+    // The iptables rules that limit the connections of a module need its
+    // addresses and the addresses of the modules that it may connect to, which
+    // are only known once the containers have started.
+    fn apply_network_policy_rules(&self, id: &str) -> impl Future<Item = (), Error = Error> + Send {
+        let allowed = match self.network_policy.allowed(id) {
+            Some(allowed) if !allowed.is_empty() => allowed.to_vec(),
+            _ => return Either::A(future::ok(())),
+        };
+
+        let mut destinations = vec![];
+        let mut modules = vec![];
+        for target in allowed {
+            match target {
+                EgressTarget::Cidr(address, prefix_len) => destinations.push((address, prefix_len)),
+                EgressTarget::Module(name) => modules.push(name),
+            }
+        }
+
+        let id = id.to_string();
+        let client = self.client.clone();
+        let addresses = stream::iter_ok(Some(id.clone()).into_iter().chain(modules))
+            .and_then(move |name| {
+                client.inspect_container(&name).then(move |container| {
+                    let addresses = container
+                        .map(|container| container_addresses(&container))
+                        .unwrap_or_default();
+                    Ok::<_, Error>((name, addresses))
+                })
+            })
+            .collect();
+
+        let apply = addresses.and_then(move |mut addresses| {
+            let (_, sources) = addresses.remove(0);
+            for (name, module_addresses) in addresses {
+                if module_addresses.is_empty() {
+                    warn!(
+                        "Module {} may connect to module {}, which has no address, so it can't connect to it until it is restarted",
+                        id, name
+                    );
+                }
+                destinations.extend(module_addresses.into_iter().map(|address| {
+                    let prefix_len = if address.is_ipv4() { 32 } else { 128 };
+                    (address, prefix_len)
+                }));
+            }
+
+            debug!("Applying the network policy of module {}", id);
+            network_policy::apply_egress_rules(&id, &sources, &destinations)
+        });
+        Either::B(apply)
+    }
+
+    // Whether `image` was pulled since a module was last created from it.
+    fn take_recently_pulled(&self, image: &str) -> bool {
+        self.recently_pulled
+            .lock()
+            .expect("recently pulled images lock poisoned")
+            .remove(image)
+    }
+
+    // Docker doesn't tell anyone when it kills a container for running out of
+    // memory, so it is noticed when the module is next looked at, and
+    // reported once for each time it exited.
+    fn report_oom_kill(&self, name: &str, state: &ModuleRuntimeState) {
+        if state.exit_reason() != Some(ExitReason::OomKilled) {
+            return;
+        }
+
+        let finished_at = state.finished_at().cloned();
+        let reported = self
+            .oom_kills
+            .lock()
+            .expect("OOM kills lock poisoned")
+            .insert(name.to_string(), finished_at);
+        if reported != Some(finished_at) {
+            warn!(
+                "Module {} was killed for running out of memory; consider raising its memory limit",
+                name
+            );
+            self.events
+                .publish(name, ModuleLifecycleEventKind::OomKilled);
+        }
+    }
+
+    // Docker connects a container to one network per request, so the module
+    // joins its networks one after the other.
+    fn join_networks(
+        &self,
+        module: &ModuleSpec<DockerConfig>,
+    ) -> impl Future<Item = (), Error = Error> + Send {
+        let runtime = self.clone();
+        let container = module.name().to_string();
+        stream::iter_ok(module.networks().to_vec()).for_each(move |network| {
+            let connect = runtime.clone();
+            let container = container.clone();
+            runtime
+                .ensure_network(network.name())
+                .and_then(move |()| connect.connect_network(&container, &network))
+        })
+    }
+
+    fn ensure_network(&self, name: &str) -> impl Future<Item = (), Error = Error> + Send {
+        let name = name.to_string();
+        let context = {
+            let name = name.clone();
+            move || ErrorKind::JoinNetwork(name)
+        };
+
+        // The name filter also matches networks whose names only contain it.
+        let filter = format!(r#"{{"name":{{"{}":true}}}}"#, name);
+        let client = self.client.clone();
+        let create_networks = self.create_networks;
+        let ensure = self
+            .client
+            .network_api()
+            .network_list(&filter)
+            .map_err({
+                let context = context.clone();
+                move |err| Error::from_docker_error(err, context())
+            })
+            .and_then(move |existing| {
+                if existing
+                    .iter()
+                    .any(|network| network.name() == Some(name.as_str()))
+                {
+                    Either::A(future::ok(()))
+                } else if create_networks {
+                    info!("Creating network {}...", name);
+                    let create = client
+                        .network_api()
+                        .network_create(NetworkConfig::new(name.clone()))
+                        .map(|_| ())
+                        .map_err(move |err| {
+                            Error::from_docker_error(err, ErrorKind::JoinNetwork(name))
+                        });
+                    Either::B(Either::A(create))
+                } else {
+                    Either::B(Either::B(future::err(Error::from(
+                        ErrorKind::NetworkNotFound(name.clone())
+                            .context(ErrorKind::JoinNetwork(name)),
+                    ))))
+                }
+            });
+
+        self.client.timeout(ensure, NO_GRACE, context)
+    }
+
+    fn connect_network(
+        &self,
+        container: &str,
+        network: &ModuleNetwork,
+    ) -> impl Future<Item = (), Error = Error> + Send {
+        debug!(
+            "Connecting container {} to network {}",
+            container,
+            network.name()
+        );
+
+        let name = network.name().to_string();
+        let context = move || ErrorKind::JoinNetwork(name);
+
+        let mut endpoint = EndpointSettings::new();
+        let aliases = with_module_alias(
+            network.aliases().to_vec(),
+            container,
+            &self.client.container_name(container),
+            self.alias_network.as_ref().map(String::as_str) == Some(network.name()),
+        );
+        if !aliases.is_empty() {
+            endpoint.set_aliases(aliases);
+        }
+        let connect = self
+            .client
+            .network_api()
+            .network_connect(
+                network.name(),
+                Container::new()
+                    .with_container(self.client.container_name(container))
+                    .with_endpoint_config(endpoint),
+            )
+            .map_err({
+                let context = context.clone();
+                move |err| Error::from_docker_error(err, context())
+            });
+
+        self.client.timeout(connect, NO_GRACE, context)
+    }
+}
+
+impl DockerModuleRuntime {
+    fn start_container(&self, id: &str) -> Box<dyn Future<Item = (), Error = Error> + Send> {
+        info!("Starting module {}...", id);
+        let id = id.to_string();
+
+        if let Err(err) = ensure_not_empty_with_context(&id, || {
+            ErrorKind::RuntimeOperation(RuntimeOperation::StartModule(id.clone()))
+        }) {
+            return Box::new(future::err(Error::from(err)));
+        }
+
+        let client = self.client.clone();
+        let event_id = id.clone();
+        let context = {
+            let id = id.clone();
+            || ErrorKind::RuntimeOperation(RuntimeOperation::StartModule(id))
+        };
+
+        let start = self
+            .client
+            .container_api()
+            .container_start(&self.client.container_name(&id), "")
+            .then(move |result| match result {
+                Ok(_) => {
+                    info!("Successfully started module {}", id);
+                    Either::A(future::ok(()))
+                }
+                Err(err) => Either::B(client.inspect_container(&id).then(move |container| {
+                    let context =
+                        ErrorKind::RuntimeOperation(RuntimeOperation::StartModule(id.clone()));
+                    let err = match container {
+                        Ok(ref container) if read_only_without_writable_mount(container) => {
+                            Error::from(
+                                Error::from_docker_error(err, ErrorKind::ReadOnlyRootFs(id))
+                                    .context(context),
+                            )
+                        }
+                        _ => Error::from_docker_error(err, context),
+                    };
+                    log_failure(Level::Warn, &err);
+                    Err(err)
+                })),
+            });
+
+        let runtime = self.clone();
+        let policy_id = event_id.clone();
+        let events = self.events.clone();
+        let start = self
+            .client
+            .timeout(start, NO_GRACE, context)
+            .and_then(move |()| {
+                let stop = runtime.clone();
+                runtime
+                    .apply_network_policy_rules(&policy_id)
+                    .or_else(move |err| {
+                        let err = Error::from(err.context(ErrorKind::RuntimeOperation(
+                            RuntimeOperation::StartModule(policy_id.clone()),
+                        )));
+                        log_failure(Level::Warn, &err);
+                        // The module isn't left running with connections that
+                        // its policy doesn't allow.
+                        ModuleRuntime::stop(&stop, &policy_id, Some(NO_GRACE))
+                            .then(move |_| Err(err))
+                    })
+            })
+            .then(move |result| {
+                publish(
+                    &events,
+                    &event_id,
+                    &result,
+                    ModuleLifecycleEventKind::Started,
+                );
+                result
+            });
+
+        Box::new(start)
+    }
+}
+
+impl DockerModuleRuntime {
+    // The stop timeout the container was created with, if it has one, and its
+    // pre-stop hook.
+    fn container_stop_config(
+        &self,
+        id: &str,
+    ) -> impl Future<Item = (Option<Duration>, Option<PreStopHook>), Error = Error> + Send {
+        let context = {
+            let id = id.to_string();
+            || ErrorKind::RuntimeOperation(RuntimeOperation::StopModule(id))
+        };
+
+        let inspect_id = id.to_string();
+        let inspect = self
+            .client
+            .inspect_container(id)
+            .then(move |result| match result {
+                Ok(container) => {
+                    let config = container.config();
+                    let stop_timeout = config
+                        .and_then(ContainerConfig::stop_timeout)
+                        .and_then(|secs| u64::try_from(secs).ok())
+                        .map(Duration::from_secs);
+                    let pre_stop = config
+                        .and_then(ContainerConfig::labels)
+                        .and_then(|labels| labels.get(PRE_STOP_LABEL))
+                        .and_then(|hook| match serde_json::from_str(hook) {
+                            Ok(hook) => Some(hook),
+                            Err(err) => {
+                                warn!(
+                                    "Ignoring the malformed pre-stop hook of module {}: {}",
+                                    inspect_id, err
+                                );
+                                None
+                            }
+                        });
+                    Ok((stop_timeout, pre_stop))
+                }
+                Err(err) => {
+                    let err = Error::from_docker_error(
+                        err,
+                        ErrorKind::RuntimeOperation(RuntimeOperation::StopModule(inspect_id)),
+                    );
+                    log_failure(Level::Warn, &err);
+                    Err(err)
+                }
+            });
+
+        self.client.timeout(inspect, NO_GRACE, context)
+    }
+
+    // Runs the pre-stop hook of the module. However the hook ends, the module
+    // is stopped after it, so its failures are only logged.
+    fn run_pre_stop(
+        &self,
+        id: &str,
+        hook: &PreStopHook,
+    ) -> impl Future<Item = (), Error = Error> + Send {
+        info!("Running the pre-stop hook of module {}...", id);
+
+        let timeout = hook.timeout();
+        let exited = ModuleRuntime::exec(self, id, hook.command().to_vec(), HashMap::new())
+            .filter_map(|output| match output {
+                ExecOutput::Exited(exit_code) => Some(exit_code),
+                ExecOutput::Output(_) => None,
+            })
+            .into_future()
+            .map(|(exit_code, _)| exit_code)
+            .map_err(|(err, _)| err);
+
+        let id = id.to_string();
+        Timeout::new(exited, timeout).then(move |result| {
+            match result {
+                Ok(Some(0)) => info!("Pre-stop hook of module {} finished", id),
+                Ok(Some(exit_code)) => warn!(
+                    "Pre-stop hook of module {} exited with code {}, stopping it anyway",
+                    id, exit_code
+                ),
+                Ok(None) => warn!(
+                    "Pre-stop hook of module {} ended without an exit code, stopping it anyway",
+                    id
+                ),
+                Err(err) => match err.into_inner() {
+                    Some(err) => {
+                        warn!("Pre-stop hook of module {} failed, stopping it anyway", id);
+                        log_failure(Level::Warn, &err);
+                    }
+                    None => warn!(
+                        "Pre-stop hook of module {} didn't finish within {:?}, stopping it anyway",
+                        id, timeout
+                    ),
+                },
+            }
+            Ok(())
+        })
+    }
+}
+
+impl DockerModuleRuntime {
+    // Creates the container of `module`, labeled with the `spec_labels` of
+    // the spec that was asked for.
+    fn create_module(
+        &self,
+        module: ModuleSpec<DockerConfig>,
+        spec_labels: HashMap<String, String>,
+    ) -> Box<dyn Future<Item = (), Error = Error> + Send> {
+        if let Err(err) = self.validate(&module) {
+            log_failure(Level::Warn, &err);
+            return Box::new(future::err(err));
+        }
+
+        // The module is created from the digest that its pattern resolved to
+        // when it was pulled, so that it keeps running the same image.
+        if is_tag_pattern(module.config().image()) {
+            let runtime = self.clone();
+            let name = module.name().to_string();
+            return Box::new(
+                self.resolve_image(module.config())
+                    .map_err(move |err| {
+                        let err = Error::from(err.context(ErrorKind::RuntimeOperation(
+                            RuntimeOperation::CreateModule(name),
+                        )));
+                        log_failure(Level::Warn, &err);
+                        err
+                    })
+                    .and_then(move |config| {
+                        runtime.create_module(module.with_config(config), spec_labels)
+                    }),
+            );
+        }
+
+        // Added once the module is validated, since the workload socket is
+        // under a path that strict binds don't allow modules to write to.
+        let module = if module.identity_integration() {
+            self.identity.with_workload_socket(module)
+        } else {
+            module
+        };
+
+        // A module whose image is never pulled can only be created from an
+        // image that is already on the device.
+        let present = match module.image_pull_policy() {
+            ImagePullPolicy::Never => Either::A(self.image_exists(module.config())),
+            _ => Either::B(future::ok(true)),
+        };
+
+        let runtime = self.clone();
+        let recorder = self.clone();
+        let events = self.events.clone();
+        let event_id = module.name().to_string();
+        let result = present
+            .and_then(move |present| {
+                if !present {
+                    return Either::A(future::err(Error::from(
+                        ErrorKind::ImageNotPresent(module.config().image().to_string()).context(
+                            ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(
+                                module.name().to_string(),
+                            )),
+                        ),
+                    )));
+                }
+
+                Either::B(
+                    module
+                        .config()
+                        .clone_create_options()
+                        .and_then(|create_options| {
+                            let create_options = apply_default_create_options(
+                                create_options,
+                                runtime.default_create_options.as_ref(),
+                            )
+                            .with_context(|_| {
+                                ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(
+                                    module.name().to_string(),
+                                ))
+                            })?;
+                            let unresolved_env = runtime.module_env(&module)?;
+                            let env = runtime.resolve_secrets(&module, &unresolved_env)?;
+                            let security_opt = security_profiles(&module).with_context(|_| {
+                                ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(
+                                    module.name().to_string(),
+                                ))
+                            })?;
+
+                            // merge environment variables
+                            let merged_env =
+                                DockerModuleRuntime::merge_env(create_options.env(), &env);
+                            let effective_env = DockerModuleRuntime::merge_env(
+                                create_options.env(),
+                                &unresolved_env,
+                            );
+
+                            let mut labels = create_options
+                                .labels()
+                                .cloned()
+                                .unwrap_or_else(HashMap::new);
+                            labels.extend(module.labels().clone());
+                            labels.extend(spec_labels);
+                            for (key, value) in &runtime.injected_labels {
+                                labels.entry(key.clone()).or_insert_with(|| value.clone());
+                            }
+                            labels.insert(LABEL_KEY.to_string(), LABEL_VALUE.to_string());
+                            if let Some(pre_stop) = module.pre_stop() {
+                                labels.insert(
+                                    PRE_STOP_LABEL.to_string(),
+                                    json!(pre_stop).to_string(),
+                                );
+                            }
+
+                            debug!(
+                                "Creating container {} with image {}",
+                                module.name(),
+                                module.config().image()
+                            );
+
+                            let create_options =
+                                apply_resources(create_options, module.resources());
+                            let create_options = apply_log_config(
+                                create_options,
+                                module.log_config(),
+                                &runtime.log_config,
+                            );
+                            let create_options = apply_dns(
+                                create_options,
+                                module.dns(),
+                                module.dns_search(),
+                                &runtime.dns,
+                                &runtime.dns_search,
+                            );
+                            let create_options = apply_binds(create_options, module.binds());
+                            let create_options =
+                                apply_devices(create_options, module.devices(), module.runtime());
+                            let create_options = apply_security(
+                                create_options,
+                                module.cap_add(),
+                                module.cap_drop(),
+                                module.no_new_privileges(),
+                            );
+                            let create_options =
+                                apply_security_profiles(create_options, security_opt);
+                            let create_options = apply_ulimits(create_options, module.ulimits());
+                            let create_options = apply_restart_policy(
+                                create_options,
+                                module.restart_policy(),
+                                module.max_retries(),
+                            );
+                            let create_options = apply_root_fs(
+                                create_options,
+                                module.read_only_root_fs(),
+                                module.tmpfs(),
+                            );
+                            let isolated = runtime
+                                .network_policy
+                                .allowed(module.name())
+                                .map_or(false, <[EgressTarget]>::is_empty);
+                            let create_options = apply_network_policy(create_options, isolated);
+                            let create_options = apply_module_alias(
+                                create_options,
+                                module.name(),
+                                &runtime.client.container_name(module.name()),
+                                runtime.alias_network.as_ref().map(String::as_str),
+                            );
+                            runtime.warn_alias_collisions(&module, &create_options);
+                            let create_options =
+                                apply_user(create_options, module.user(), module.working_dir());
+                            let create_options =
+                                apply_stop_timeout(create_options, module.stop_timeout());
+                            let create_options =
+                                apply_stop_signal(create_options, module.stop_signal())
+                                    .with_image(module.config().pinned_image().into_owned())
+                                    .with_env(merged_env)
+                                    .with_labels(labels);
+
+                            // What the module is created from, with its secrets left as
+                            // the references to them.
+                            let config = module
+                                .config()
+                                .clone()
+                                .with_create_options(create_options.clone().with_env(effective_env))
+                                .redacted();
+                            let effective =
+                                module.clone().with_config(config).with_env(unresolved_env);
+
+                            // Here we don't add the container to the iot edge docker network as the edge-agent is expected to do that.
+                            // It contains the logic to add a container to the iot edge network only if a network is not already specified.
+                            // Unless module name aliases are turned off, apply_module_alias does the same for modules created by others.
+
+                            let name = module.name().to_string();
+                            let join = runtime.clone();
+                            let create = runtime
+                                .create_container_after_pull(create_options, module)
+                                .and_then(move |module| {
+                                    // A module without a network can't join any.
+                                    let join_networks = if isolated {
+                                        Either::A(future::ok(()))
+                                    } else {
+                                        Either::B(join.join_networks(&module))
+                                    };
+                                    join_networks.then(move |result| match result {
+                                        Ok(()) => {
+                                            join.record_effective_spec(effective);
+                                            Either::A(future::ok(module))
+                                        }
+                                        Err(err) => {
+                                            let err = Error::from(err.context(
+                                                ErrorKind::RuntimeOperation(
+                                                    RuntimeOperation::CreateModule(name),
+                                                ),
+                                            ));
+                                            // Removed so that the module isn't left created
+                                            // without the networks it needs, and creating it
+                                            // can be retried.
+                                            Either::B(
+                                                ModuleRuntime::remove(&join, module.name())
+                                                    .then(move |_| Err(err)),
+                                            )
+                                        }
+                                    })
+                                });
+
+                            Ok(create)
+                        })
+                        .into_future()
+                        .flatten(),
+                )
+            })
+            .then(move |result| {
+                let result = match result {
+                    Ok(module) => {
+                        info!("Successfully created module {}", module.name());
+                        recorder.clear_last_error(module.name());
+                        Ok(())
+                    }
+                    Err(err) => {
+                        log_failure(Level::Warn, &err);
+                        recorder.record_error(
+                            &event_id,
+                            ModuleError::new(
+                                ModuleErrorCode::CreateFailed,
+                                redact_failure(&err),
+                                Utc::now(),
+                            ),
+                        );
+                        Err(err)
+                    }
+                };
+                publish(
+                    &events,
+                    &event_id,
+                    &result,
+                    ModuleLifecycleEventKind::Created,
+                );
+                result
+            });
+
+        Box::new(result)
+    }
+}
+
 impl std::fmt::Debug for DockerModuleRuntime {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("DockerModuleRuntime").finish()
@@ -100,47 +1203,127 @@ impl ModuleRegistry for DockerModuleRuntime {
     type Error = Error;
     type PullFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
     type RemoveFuture = Box<dyn Future<Item = (), Error = Self::Error>>;
+    type ImageExistsFuture = Box<dyn Future<Item = bool, Error = Self::Error> + Send>;
     type Config = DockerConfig;
 
     fn pull(&self, config: &Self::Config) -> Self::PullFuture {
-        let image = config.image().to_string();
+        if is_tag_pattern(config.image()) {
+            let runtime = self.clone();
+            let image = config.image().to_string();
+            return Box::new(
+                self.resolve_image(config)
+                    .map_err(move |err| {
+                        let err = Error::from(err.context(ErrorKind::RegistryOperation(
+                            RegistryOperation::PullImage(image),
+                        )));
+                        log_failure(Level::Warn, &err);
+                        err
+                    })
+                    .and_then(move |config| runtime.pull(&config)),
+            );
+        }
+
+        // A pinned digest takes precedence over the tag
+        let image = config.pinned_image().into_owned();
 
         info!("Pulling image {}...", image);
 
-        let creds: Result<String> = config.auth().map_or_else(
-            || Ok("".to_string()),
-            |a| {
-                let json = serde_json::to_string(a).with_context(|_| {
-                    ErrorKind::RegistryOperation(RegistryOperation::PullImage(image.clone()))
-                })?;
-                Ok(base64::encode(&json))
-            },
-        );
+        let digest = config.digest().map(ToString::to_string);
+        if let Some(digest) = &digest {
+            if !is_valid_image_digest(digest) {
+                let err = Error::from(ErrorKind::InvalidImage(image.clone()).context(
+                    ErrorKind::RegistryOperation(RegistryOperation::PullImage(image)),
+                ));
+                log_failure(Level::Warn, &err);
+                return Box::new(future::err(err));
+            }
+        }
 
-        let response = creds
-            .map(|creds| {
-                self.client
-                    .image_api()
-                    .image_create(&image, "", "", "", "", &creds, "")
-                    .then(|result| match result {
-                        Ok(()) => Ok(image),
-                        Err(err) => Err(Error::from_docker_error(
-                            err,
-                            ErrorKind::RegistryOperation(RegistryOperation::PullImage(image)),
-                        )),
+        // An image pinned to a digest that's already on the device can't be any newer in the
+        // registry, so it isn't pulled again. This is also what lets devices that can't reach the
+        // registry start modules from preloaded images.
+        let present = match &digest {
+            Some(digest) => {
+                let digest = digest.clone();
+                Either::A(local_repo_digests(&self.client, image.clone()).map(
+                    move |repo_digests| {
+                        repo_digests
+                            .map_or(false, |repo_digests| has_digest(&repo_digests, &digest))
+                    },
+                ))
+            }
+            None => Either::B(future::ok(false)),
+        };
+
+        let runtime = self.clone();
+        // Credentials in files are read for each pull, so that rotating them
+        // doesn't need the module to be deployed again.
+        let auth = match config.read_auth() {
+            Ok(auth) => auth,
+            Err(err) => {
+                let err = Error::from(err.context(ErrorKind::RegistryOperation(
+                    RegistryOperation::PullImage(image),
+                )));
+                log_failure(Level::Warn, &err);
+                return Box::new(future::err(err));
+            }
+        };
+        // The module's cap takes precedence over the runtime's, and a cap of 0
+        // is the same as none.
+        let max_pull_rate = config
+            .max_pull_rate()
+            .or(self.max_pull_rate)
+            .filter(|rate| *rate > 0);
+        let response = present
+            .and_then(move |present| {
+                if present {
+                    info!("Image {} is already on the device, not pulling it", image);
+                    return Either::A(future::ok(()));
+                }
+
+                let retry = runtime.clone();
+                let retry_image = image.clone();
+                let retry_digest = digest.clone();
+                let retry_auth = auth.clone();
+                let fallback_client = runtime.client.clone();
+                let fallback_image = image.clone();
+                let fallback_digest = digest.clone();
+                let recently_pulled = runtime.recently_pulled.clone();
+                let pull = runtime
+                    .pull_with_credentials(image.clone(), digest, auth, max_pull_rate)
+                    .or_else(move |err| {
+                        // The registry rejects tokens that were revoked or that expired early, so
+                        // the pull is retried once with new ones.
+                        if is_unauthorized(&err) && retry.acr_tokens.invalidate(&retry_image) {
+                            info!(
+                                "Registry of image {} rejected its token, retrying with a new one",
+                                retry_image
+                            );
+                            Either::A(retry.pull_with_credentials(
+                                retry_image,
+                                retry_digest,
+                                retry_auth,
+                                max_pull_rate,
+                            ))
+                        } else {
+                            Either::B(future::err(err))
+                        }
+                    })
+                    .map(move |()| {
+                        info!("Successfully pulled image {}", image);
+                        recently_pulled
+                            .lock()
+                            .expect("recently pulled images lock poisoned")
+                            .insert(image);
                     })
+                    .or_else(move |err| {
+                        pull_failed(&fallback_client, fallback_image, fallback_digest, err)
+                    });
+                Either::B(pull)
             })
-            .into_future()
-            .flatten()
-            .then(move |result| match result {
-                Ok(image) => {
-                    info!("Successfully pulled image {}", image);
-                    Ok(())
-                }
-                Err(err) => {
-                    log_failure(Level::Warn, &err);
-                    Err(err)
-                }
+            .map_err(|err| {
+                log_failure(Level::Warn, &err);
+                err
             });
 
         Box::new(response)
@@ -177,6 +1360,305 @@ impl ModuleRegistry for DockerModuleRuntime {
                 }),
         )
     }
+
+    fn image_exists(&self, config: &Self::Config) -> Self::ImageExistsFuture {
+        // An image whose tag is a pattern is only on the device once the
+        // pattern has resolved.
+        if is_tag_pattern(config.image()) {
+            return match self.tags.resolved(config.image()) {
+                Some(image) => self.image_exists(&config.clone().with_image(image)),
+                None => Box::new(future::ok(false)),
+            };
+        }
+
+        // An image pinned to a digest is only on the device if the digest is.
+        let digest = config.digest().map(ToString::to_string);
+        Box::new(
+            local_repo_digests(&self.client, config.pinned_image().into_owned()).map(
+                move |repo_digests| match (repo_digests, digest) {
+                    (Some(repo_digests), Some(digest)) => has_digest(&repo_digests, &digest),
+                    (repo_digests, None) => repo_digests.is_some(),
+                    (None, Some(_)) => false,
+                },
+            ),
+        )
+    }
+}
+
+// A container whose image has neither an entrypoint nor a command, and whose
+// create options don't give one, exits as soon as it starts, so the module
+// would only be restarted over and over. The image is inspected for this
+// before the container is created. If it can't be inspected, the create
+// reports why, like that the image is missing.
+fn check_command(
+    client: &DockerClient<UrlConnector>,
+    image: String,
+    name: String,
+    create_options: &ContainerCreateBody,
+) -> impl Future<Item = (), Error = Error> + Send {
+    if has_command(create_options, None) {
+        return Either::A(future::ok(()));
+    }
+
+    let create_options = create_options.clone();
+
+    Either::B(
+        client
+            .image_api()
+            .image_inspect(&image)
+            .then(move |result| {
+                let inspected = match result {
+                    Ok(inspected) => inspected,
+                    Err(_) => return Ok(()),
+                };
+                let config = match inspected.config() {
+                    Some(config) => config,
+                    None => return Ok(()),
+                };
+                if has_command(&create_options, Some(config)) {
+                    Ok(())
+                } else {
+                    Err(Error::from(ErrorKind::ImageHasNoCommand(image).context(
+                        ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(name)),
+                    )))
+                }
+            }),
+    )
+}
+
+// Whether a container created with `create_options` from an image with
+// `image_config` runs a command. An entrypoint of `[""]` resets the image's
+// to none, and Docker doesn't run the image's command with an entrypoint of
+// the create options.
+fn has_command(
+    create_options: &ContainerCreateBody,
+    image_config: Option<&ContainerConfig>,
+) -> bool {
+    let has_args =
+        |args: Option<&[String]>| args.map_or(false, |args| args.iter().any(|arg| !arg.is_empty()));
+
+    let entrypoint = create_options
+        .entrypoint()
+        .or_else(|| image_config.and_then(ContainerConfig::entrypoint));
+    let cmd = match create_options.entrypoint() {
+        Some(_) => create_options.cmd(),
+        None => create_options
+            .cmd()
+            .or_else(|| image_config.and_then(ContainerConfig::cmd)),
+    };
+    has_args(entrypoint) || has_args(cmd)
+}
+
+// Checks that the image the registry handed out is the one the module is
+// pinned to, in case the registry or a mirror serves different content.
+fn verify_digest(
+    client: &DockerClient<UrlConnector>,
+    image: String,
+    digest: String,
+) -> impl Future<Item = (), Error = Error> + Send {
+    client
+        .image_api()
+        .image_inspect(&image)
+        .then(move |result| match result {
+            Ok(inspected) => {
+                let repo_digests = inspected.repo_digests().unwrap_or(&[]);
+                if has_digest(repo_digests, &digest) {
+                    Ok(())
+                } else {
+                    let found = if repo_digests.is_empty() {
+                        "no digest".to_string()
+                    } else {
+                        repo_digests.join(", ")
+                    };
+                    Err(Error::from(
+                        ErrorKind::DigestMismatch(image.clone(), digest, found).context(
+                            ErrorKind::RegistryOperation(RegistryOperation::PullImage(image)),
+                        ),
+                    ))
+                }
+            }
+            Err(err) => Err(Error::from_docker_error(
+                err,
+                ErrorKind::RegistryOperation(RegistryOperation::PullImage(image)),
+            )),
+        })
+}
+
+fn has_digest(repo_digests: &[String], digest: &str) -> bool {
+    repo_digests
+        .iter()
+        .any(|repo_digest| repo_digest.ends_with(&format!("@{}", digest)))
+}
+
+// The repo digests of `image`, or `None` if it's not on the device.
+fn local_repo_digests(
+    client: &DockerClient<UrlConnector>,
+    image: String,
+) -> impl Future<Item = Option<Vec<String>>, Error = Error> + Send {
+    client
+        .image_api()
+        .image_inspect(&image)
+        .then(move |result| match result {
+            Ok(inspected) => Ok(Some(inspected.repo_digests().unwrap_or(&[]).to_vec())),
+            Err(err) => {
+                let err = Error::from_docker_error(
+                    err,
+                    ErrorKind::RegistryOperation(RegistryOperation::PullImage(image)),
+                );
+                match err.cause().and_then(Fail::downcast_ref) {
+                    Some(ErrorKind::NotFound(_)) => Ok(None),
+                    _ => Err(err),
+                }
+            }
+        })
+}
+
+// When the registry can't be reached, the image already on the device is used
+// if there is one that satisfies the pinned digest, if any. Otherwise the
+// error says that the image has to be preloaded.
+fn pull_failed(
+    client: &DockerClient<UrlConnector>,
+    image: String,
+    digest: Option<String>,
+    err: Error,
+) -> impl Future<Item = (), Error = Error> + Send {
+    let message = match registry_message(&err) {
+        Some(message) if is_registry_unreachable(message) => message.to_string(),
+        _ => return Either::A(future::err(err)),
+    };
+
+    Either::B(
+        local_repo_digests(client, image.clone()).then(move |result| match result {
+            Ok(Some(ref repo_digests))
+                if digest
+                    .as_ref()
+                    .map_or(true, |digest| has_digest(repo_digests, digest)) =>
+            {
+                warn!(
+                    "Could not reach the registry of image {}, using the image already on the device",
+                    image
+                );
+                Ok(())
+            }
+            Ok(_) => Err(Error::from(
+                ErrorKind::ImageNotPreloaded(image.clone(), message).context(
+                    ErrorKind::RegistryOperation(RegistryOperation::PullImage(image)),
+                ),
+            )),
+            Err(_) => Err(err),
+        }),
+    )
+}
+
+// Docker reports the errors of the requests it makes to the registry in their
+// message, like "Get https://mcr.microsoft.com/v2/: dial tcp: lookup
+// mcr.microsoft.com: no such host".
+fn registry_message(err: &Error) -> Option<&str> {
+    match err
+        .cause()
+        .map(|cause| cause.find_root_cause())
+        .and_then(Fail::downcast_ref)
+    {
+        Some(ErrorKind::FormattedDockerRuntime(message)) => Some(message),
+        _ => None,
+    }
+}
+
+// Like "Head https://myregistry.azurecr.io/v2/module/manifests/1.0:
+// unauthorized: authentication required"
+fn is_unauthorized(err: &Error) -> bool {
+    registry_message(err).map_or(false, |message| {
+        message.contains("unauthorized") || message.contains("authentication required")
+    })
+}
+
+// Publishes `kind` for the module if the operation succeeded, or that it
+// failed. Starting a module that is running or stopping one that isn't is
+// neither.
+fn publish(events: &ModuleEvents, id: &str, result: &Result<()>, kind: ModuleLifecycleEventKind) {
+    match result {
+        Ok(()) => events.publish(id, kind),
+        Err(err) if is_not_modified(err) => (),
+        Err(_) => events.publish(id, ModuleLifecycleEventKind::Failed),
+    }
+}
+
+// Docker answers a stop of a container that has already stopped with 304.
+fn is_not_modified(err: &Error) -> bool {
+    match Fail::find_root_cause(err).downcast_ref::<ErrorKind>() {
+        Some(ErrorKind::NotModified) => true,
+        _ => false,
+    }
+}
+
+// Like "No such image: mcr.microsoft.com/azureiotedge-agent:1.0"
+fn is_no_such_image(err: &Error) -> bool {
+    match Fail::find_root_cause(err).downcast_ref::<ErrorKind>() {
+        Some(ErrorKind::NotFound(message)) => message.starts_with("No such image"),
+        _ => false,
+    }
+}
+
+fn is_registry_unreachable(message: &str) -> bool {
+    const UNREACHABLE: &[&str] = &[
+        "dial tcp",
+        "no such host",
+        "i/o timeout",
+        "network is unreachable",
+        "connection refused",
+        "request canceled while waiting for connection",
+        "TLS handshake timeout",
+    ];
+
+    UNREACHABLE.iter().any(|reason| message.contains(reason))
+}
+
+/// Loads the images in `path`, a tarball made by `docker save`, into the
+/// container runtime of `settings`. This is for tools that don't otherwise
+/// need a `DockerModuleRuntime`.
+pub fn import_image(
+    settings: &Settings,
+    path: &Path,
+) -> impl Future<Item = (), Error = Error> + Send {
+    match init_client(settings.moby_runtime()) {
+        Ok(client) => Either::A(load_images(&client, path)),
+        Err(err) => Either::B(future::err(err)),
+    }
+}
+
+fn load_images(
+    client: &DockerClient<UrlConnector>,
+    path: &Path,
+) -> impl Future<Item = (), Error = Error> + Send {
+    info!("Importing images from {}...", path.display());
+
+    let client = client.clone();
+    let path = path.to_path_buf();
+    let display = path.display().to_string();
+    let context =
+        move || ErrorKind::RegistryOperation(RegistryOperation::ImportImage(display.clone()));
+
+    future::lazy({
+        let context = context.clone();
+        move || std::fs::read(&path).with_context(|_| context())
+    })
+    .map_err(Error::from)
+    .and_then(move |tarball| {
+        client
+            .image_api()
+            .image_load(tarball, true)
+            .map_err(move |err| Error::from_docker_error(err, context()))
+    })
+    .then(move |result| match result {
+        Ok(()) => {
+            info!("Successfully imported images");
+            Ok(())
+        }
+        Err(err) => {
+            log_failure(Level::Warn, &err);
+            Err(err)
+        }
+    })
 }
 
 fn parse_get_response<'de, D>(resp: &InlineResponse200) -> std::result::Result<String, D::Error>
@@ -200,19 +1682,114 @@ impl MakeModuleRuntime for DockerModuleRuntime {
 
     fn make_runtime(
         settings: Settings,
-        _: ProvisioningResult,
+        provisioning_result: ProvisioningResult,
         _: impl GetTrustBundle,
     ) -> Self::Future {
         info!("Initializing module runtime...");
 
+        if let Err(err) = settings.moby_runtime().log_config().validate() {
+            let err = Error::from(err.context(ErrorKind::Initialization));
+            log_failure(Level::Warn, &err);
+            return Box::new(future::err(err));
+        }
+
+        if let Err(err) = validate_dns_servers(settings.moby_runtime().dns()) {
+            let err = Error::from(err.context(ErrorKind::Initialization));
+            log_failure(Level::Warn, &err);
+            return Box::new(future::err(err));
+        }
+
+        if let Err(err) = validate_injected_labels(settings.moby_runtime().injected_labels()) {
+            let err = Error::from(err.context(ErrorKind::Initialization));
+            log_failure(Level::Warn, &err);
+            return Box::new(future::err(err));
+        }
+
+        if let Err(err) = validate_global_env(settings.moby_runtime().global_env()) {
+            let err = Error::from(err.context(ErrorKind::Initialization));
+            log_failure(Level::Warn, &err);
+            return Box::new(future::err(err));
+        }
+
+        if let Some(prefix) = settings.moby_runtime().container_name_prefix() {
+            if !is_valid_container_prefix(prefix) {
+                let err = Error::from(
+                    ErrorKind::InvalidContainerPrefix(prefix.to_string())
+                        .context(ErrorKind::Initialization),
+                );
+                log_failure(Level::Warn, &err);
+                return Box::new(future::err(err));
+            }
+        }
+
+        let default_create_options = match default_create_options(settings.moby_runtime()) {
+            Ok(default_create_options) => default_create_options,
+            Err(err) => {
+                log_failure(Level::Warn, &err);
+                return Box::new(future::err(err));
+            }
+        };
+
+        let tags = match TagResolver::new(
+            Some(settings.homedir().join(RESOLVED_IMAGES_FILENAME)),
+            settings.https_proxy(),
+        ) {
+            Ok(tags) => tags,
+            Err(err) => {
+                log_failure(Level::Warn, &err);
+                return Box::new(future::err(err));
+            }
+        };
+
+        let acr_tokens = match AcrTokens::new(
+            settings.moby_runtime().acr_auth().clone(),
+            settings.https_proxy(),
+        ) {
+            Ok(acr_tokens) => acr_tokens,
+            Err(err) => {
+                log_failure(Level::Warn, &err);
+                return Box::new(future::err(err));
+            }
+        };
+
         // Clippy incorrectly flags the use of `.map(..).unwrap_or_else(..)` code as being replaceable
         // with `.ok().map_or_else`. This is incorrect because `.ok()` will result in the error being dropped.
         // So we suppress this lint. There's an open issue for this on the Clippy repo:
         //      https://github.com/rust-lang/rust-clippy/issues/3730
         #[allow(clippy::result_map_unwrap_or_else)]
-        let created = init_client(settings.moby_runtime().uri())
+        let created = init_client(settings.moby_runtime())
             .map(|client| {
                 let network_id = settings.moby_runtime().network().name().to_string();
+                let max_concurrent_pulls = settings.moby_runtime().max_concurrent_pulls();
+                let max_pull_rate = settings.moby_runtime().max_pull_rate();
+                let log_config = settings.moby_runtime().log_config().clone();
+                let strict_binds = settings.moby_runtime().strict_binds();
+                let strict_create_options = settings.moby_runtime().strict_create_options();
+                let secrets_file = settings
+                    .moby_runtime()
+                    .secrets_file()
+                    .map(Path::to_path_buf);
+                let credential_helpers =
+                    CredentialHelpers::new(settings.moby_runtime().credential_helpers().clone());
+                let dns = settings.moby_runtime().dns().to_vec();
+                let dns_search = settings.moby_runtime().dns_search().to_vec();
+                let create_networks = settings.moby_runtime().create_networks();
+                let network_policy = settings.moby_runtime().network_policy().clone();
+                let injected_labels = settings.moby_runtime().injected_labels().clone();
+                let global_env = settings.moby_runtime().global_env().clone();
+                let max_log_line_length = settings.logs().max_line_length();
+                let identity = IdentityIntegration::new(
+                    provisioning_result.hub_name(),
+                    provisioning_result.device_id(),
+                    settings.hostname(),
+                    settings.connect().workload_uri(),
+                );
+                // The network that modules are registered on by their names.
+                let alias_network = if settings.moby_runtime().module_name_aliases() {
+                    Some(network_id.clone())
+                } else {
+                    None
+                };
                 let (enable_i_pv6, ipam) = get_ipv6_settings(settings.moby_runtime().network());
                 info!("Using runtime network id {}", network_id);
 
@@ -247,9 +1824,36 @@ impl MakeModuleRuntime for DockerModuleRuntime {
                         log_failure(Level::Warn, &e);
                         e
                     })
-                    .map(|client| {
+                    .map(move |client| {
                         info!("Successfully initialized module runtime");
-                        DockerModuleRuntime { client }
+                        DockerModuleRuntime {
+                            client,
+                            pulls: PullCoalescer::new(max_concurrent_pulls),
+                            max_pull_rate,
+                            restarts: InFlightRestarts::new(),
+                            events: ModuleEvents::default(),
+                            log_config,
+                            strict_binds,
+                            strict_create_options,
+                            default_create_options,
+                            secrets_file,
+                            credential_helpers,
+                            acr_tokens,
+                            dns,
+                            dns_search,
+                            create_networks,
+                            network_policy,
+                            injected_labels,
+                            global_env,
+                            alias_network,
+                            identity,
+                            max_log_line_length,
+                            effective_specs: Arc::new(Mutex::new(HashMap::new())),
+                            last_errors: Arc::new(Mutex::new(HashMap::new())),
+                            recently_pulled: Arc::new(Mutex::new(HashSet::new())),
+                            oom_kills: Arc::new(Mutex::new(HashMap::new())),
+                            tags,
+                        }
                     });
 
                 future::Either::A(fut)
@@ -320,72 +1924,85 @@ impl ModuleRuntime for DockerModuleRuntime {
     type SystemResourcesFuture =
         Box<dyn Future<Item = SystemResources, Error = Self::Error> + Send>;
     type RemoveAllFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type StatsFuture = Box<dyn Future<Item = ModuleStats, Error = Self::Error> + Send>;
+    type PruneImagesFuture = Box<dyn Future<Item = PrunedImages, Error = Self::Error> + Send>;
+    type ExecStream = Box<dyn Stream<Item = ExecOutput, Error = Self::Error> + Send>;
+    type EffectiveSpecFuture =
+        Box<dyn Future<Item = ModuleSpec<Self::Config>, Error = Self::Error> + Send>;
 
     fn create(&self, module: ModuleSpec<Self::Config>) -> Self::CreateFuture {
         info!("Creating module {}...", module.name());
 
+        // Worked out before a tag pattern is resolved, so that the labels are
+        // of the spec that was asked for.
+        let spec_labels = match module.spec_labels() {
+            Ok(labels) => labels,
+            Err(err) => {
+                let err = Error::from(err.context(ErrorKind::RuntimeOperation(
+                    RuntimeOperation::CreateModule(module.name().to_string()),
+                )));
+                log_failure(Level::Warn, &err);
+                return Box::new(future::err(err));
+            }
+        };
+        self.create_module(module, spec_labels)
+    }
+
+    fn validate(&self, module: &ModuleSpec<Self::Config>) -> Result<()> {
         // we only want "docker" modules
         if module.type_() != DOCKER_MODULE_TYPE {
-            return Box::new(future::err(Error::from(ErrorKind::InvalidModuleType(
+            return Err(Error::from(ErrorKind::InvalidModuleType(
                 module.type_().to_string(),
-            ))));
-        }
-
-        let result = module
-            .config()
-            .clone_create_options()
-            .and_then(|create_options| {
-                // merge environment variables
-                let merged_env = DockerModuleRuntime::merge_env(create_options.env(), module.env());
-
-                let mut labels = create_options
-                    .labels()
-                    .cloned()
-                    .unwrap_or_else(HashMap::new);
-                labels.insert(LABEL_KEY.to_string(), LABEL_VALUE.to_string());
-
-                debug!(
-                    "Creating container {} with image {}",
-                    module.name(),
-                    module.config().image()
-                );
+            )));
+        }
 
-                let create_options = create_options
-                    .with_image(module.config().image().to_string())
-                    .with_env(merged_env)
-                    .with_labels(labels);
+        module.validate().with_context(|_| {
+            ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(module.name().to_string()))
+        })?;
+        if self.strict_binds {
+            module.validate_strict_binds().with_context(|_| {
+                ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(
+                    module.name().to_string(),
+                ))
+            })?;
+        }
 
-                // Here we don't add the container to the iot edge docker network as the edge-agent is expected to do that.
-                // It contains the logic to add a container to the iot edge network only if a network is not already specified.
+        // Credentials are only sent with the pull, so an incomplete set would
+        // otherwise surface as an authorization failure from the registry.
+        if let Some(auth) = module.config().registry_auth() {
+            if !auth.is_complete() {
+                return Err(Error::from(
+                    ErrorKind::IncompleteRegistryCredentials(module.config().image().to_string())
+                        .context(ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(
+                            module.name().to_string(),
+                        ))),
+                ));
+            }
+        }
 
-                Ok(self
-                    .client
-                    .container_api()
-                    .container_create(create_options, module.name())
-                    .then(|result| match result {
-                        Ok(_) => Ok(module),
-                        Err(err) => Err(Error::from_docker_error(
-                            err,
-                            ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(
-                                module.name().to_string(),
-                            )),
-                        )),
-                    }))
-            })
-            .into_future()
-            .flatten()
-            .then(|result| match result {
-                Ok(module) => {
-                    info!("Successfully created module {}", module.name());
-                    Ok(())
-                }
-                Err(err) => {
-                    log_failure(Level::Warn, &err);
-                    Err(err)
-                }
-            });
+        module.config().clone_create_options().with_context(|_| {
+            ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(module.name().to_string()))
+        })?;
+
+        // Keys that aren't Docker create options are kept when the config is
+        // deserialized, so that they can be reported here.
+        let create_options =
+            serde_json::to_value(module.config().create_options()).with_context(|_| {
+                ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(
+                    module.name().to_string(),
+                ))
+            })?;
+        let warnings = validate_create_options(&create_options, self.strict_create_options)
+            .with_context(|_| {
+                ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(
+                    module.name().to_string(),
+                ))
+            })?;
+        for warning in warnings {
+            warn!("Module {}: {}", module.name(), warning);
+        }
 
-        Box::new(result)
+        Ok(())
     }
 
     fn get(&self, id: &str) -> Self::GetFuture {
@@ -399,72 +2016,92 @@ impl ModuleRuntime for DockerModuleRuntime {
         }
 
         let client_copy = self.client.clone();
+        let context = {
+            let id = id.clone();
+            || ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(id))
+        };
 
-        Box::new(
-            self.client
-                .container_api()
-                .container_inspect(&id, false)
-                .then(|result| match result {
-                    Ok(container) => {
-                        let name =
-                            parse_get_response::<Deserializer>(&container).with_context(|_| {
-                                ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(id.clone()))
-                            })?;
-                        let config =
-                            DockerConfig::new(name.clone(), ContainerCreateBody::new(), None)
-                                .with_context(|_| {
-                                    ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(
-                                        id.clone(),
-                                    ))
-                                })?;
-                        let module =
-                            DockerModule::new(client_copy, name, config).with_context(|_| {
-                                ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(id.clone()))
-                            })?;
-                        let state = runtime_state(container.id(), container.state());
-                        Ok((module, state))
-                    }
-                    Err(err) => {
-                        let err = Error::from_docker_error(
-                            err,
-                            ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(id)),
-                        );
-                        log_failure(Level::Warn, &err);
-                        Err(err)
-                    }
-                }),
-        )
+        let inspect = self
+            .client
+            .inspect_container(&id)
+            .then(|result| match result {
+                Ok(container) => {
+                    let name =
+                        parse_get_response::<Deserializer>(&container).with_context(|_| {
+                            ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(id.clone()))
+                        })?;
+                    let name = client_copy.module_name(&name).unwrap_or(&name).to_string();
+                    let config = DockerConfig::new(name.clone(), ContainerCreateBody::new(), None)
+                        .with_context(|_| {
+                            ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(id.clone()))
+                        })?;
+                    let module =
+                        DockerModule::new(client_copy, name, config).with_context(|_| {
+                            ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(id.clone()))
+                        })?;
+                    let state = runtime_state(&container);
+                    Ok((module, state))
+                }
+                Err(err) => {
+                    let err = Error::from_docker_error(
+                        err,
+                        ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(id)),
+                    );
+                    log_failure(Level::Warn, &err);
+                    Err(err)
+                }
+            });
+
+        let runtime = self.clone();
+        let inspect = inspect.map(move |(module, state)| {
+            runtime.report_oom_kill(module.name(), &state);
+            let state = runtime.with_last_error(module.name(), state);
+            (module, state)
+        });
+
+        Box::new(self.client.timeout(inspect, NO_GRACE, context))
     }
 
-    fn start(&self, id: &str) -> Self::StartFuture {
-        info!("Starting module {}...", id);
-        let id = id.to_string();
+    // The spec is recorded when the module is created, so a module that was
+    // created before the daemon started has none until it is created again.
+    fn effective_spec(&self, id: &str) -> Self::EffectiveSpecFuture {
+        debug!("Getting effective spec of module {}...", id);
+
+        let spec = self
+            .effective_specs
+            .lock()
+            .expect("Effective specs lock poisoned")
+            .get(id)
+            .cloned();
+        let result = spec.ok_or_else(|| {
+            Error::from(
+                ErrorKind::NotFound(format!("No effective spec of module {} was recorded", id))
+                    .context(ErrorKind::RuntimeOperation(
+                        RuntimeOperation::GetEffectiveSpec(id.to_string()),
+                    )),
+            )
+        });
+        Box::new(result.into_future())
+    }
 
-        if let Err(err) = ensure_not_empty_with_context(&id, || {
-            ErrorKind::RuntimeOperation(RuntimeOperation::StartModule(id.clone()))
-        }) {
-            return Box::new(future::err(Error::from(err)));
+    fn start(&self, id: &str) -> Self::StartFuture {
+        // A module that is restarting is started by the restart once it has
+        // stopped, so starting it in the meantime, like the watchdog does when
+        // it finds the module stopped, waits for the restart instead.
+        if let Some(restart) = self.restarts.get(id) {
+            info!(
+                "Module {} is restarting, waiting for the restart to start it",
+                id
+            );
+            let id = id.to_string();
+            return Box::new(restart.map_err(|err| {
+                Error::from(err.context(ErrorKind::RuntimeOperation(
+                    RuntimeOperation::StartModule(id),
+                )))
+            }));
         }
 
-        Box::new(
-            self.client
-                .container_api()
-                .container_start(&id, "")
-                .then(|result| match result {
-                    Ok(_) => {
-                        info!("Successfully started module {}", id);
-                        Ok(())
-                    }
-                    Err(err) => {
-                        let err = Error::from_docker_error(
-                            err,
-                            ErrorKind::RuntimeOperation(RuntimeOperation::StartModule(id)),
-                        );
-                        log_failure(Level::Warn, &err);
-                        Err(err)
-                    }
-                }),
-        )
+        self.start_container(id)
     }
 
     fn stop(&self, id: &str, wait_before_kill: Option<Duration>) -> Self::StopFuture {
@@ -477,31 +2114,80 @@ impl ModuleRuntime for DockerModuleRuntime {
             return Box::new(future::err(Error::from(err)));
         }
 
-        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
-        let wait_timeout = wait_before_kill.and_then(|s| match s.as_secs() {
-            s if s > i32::max_value() as u64 => Some(i32::max_value()),
-            s => Some(s as i32),
+        // Without a timeout the container is given the stop timeout it was
+        // created with, which also decides how long to wait for Docker. With
+        // one the container is still inspected for its pre-stop hook, but a
+        // container that can't be inspected is stopped without it.
+        let client = self.client.clone();
+        let runtime = self.clone();
+        let event_id = id.clone();
+        let stop_config = self.container_stop_config(&id);
+        let stop_config = match wait_before_kill {
+            Some(wait_before_kill) => Either::A(stop_config.then(move |result| {
+                let pre_stop = result.ok().and_then(|(_, pre_stop)| pre_stop);
+                Ok((Some(wait_before_kill), pre_stop))
+            })),
+            None => Either::B(stop_config),
+        };
+
+        let stop = stop_config.and_then(move |(wait_before_kill, pre_stop)| {
+            let pre_stop = match pre_stop {
+                Some(hook) => Either::A(runtime.run_pre_stop(&id, &hook)),
+                None => Either::B(future::ok(())),
+            };
+
+            pre_stop.and_then(move |()| {
+                #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                let wait_timeout = wait_before_kill.and_then(|s| match s.as_secs() {
+                    s if s > i32::max_value() as u64 => Some(i32::max_value()),
+                    s => Some(s as i32),
+                });
+
+                let context = {
+                    let id = id.clone();
+                    || ErrorKind::RuntimeOperation(RuntimeOperation::StopModule(id))
+                };
+
+                // Docker only answers once the container has stopped, which may take
+                // as long as it waits before killing it.
+                let stop = client
+                    .container_api()
+                    .container_stop(&client.container_name(&id), wait_timeout)
+                    .then(|result| match result {
+                        Ok(_) => {
+                            info!("Successfully stopped module {}", id);
+                            Ok(())
+                        }
+                        Err(err) => {
+                            let err = Error::from_docker_error(
+                                err,
+                                ErrorKind::RuntimeOperation(RuntimeOperation::StopModule(id)),
+                            );
+                            log_failure(Level::Warn, &err);
+                            Err(err)
+                        }
+                    });
+
+                client.timeout(
+                    stop,
+                    wait_before_kill.unwrap_or(DOCKER_STOP_TIMEOUT),
+                    context,
+                )
+            })
         });
 
-        Box::new(
-            self.client
-                .container_api()
-                .container_stop(&id, wait_timeout)
-                .then(|result| match result {
-                    Ok(_) => {
-                        info!("Successfully stopped module {}", id);
-                        Ok(())
-                    }
-                    Err(err) => {
-                        let err = Error::from_docker_error(
-                            err,
-                            ErrorKind::RuntimeOperation(RuntimeOperation::StopModule(id)),
-                        );
-                        log_failure(Level::Warn, &err);
-                        Err(err)
-                    }
-                }),
-        )
+        let events = self.events.clone();
+        let stop = stop.then(move |result| {
+            publish(
+                &events,
+                &event_id,
+                &result,
+                ModuleLifecycleEventKind::Stopped,
+            );
+            result
+        });
+
+        Box::new(stop)
     }
 
     fn restart(&self, id: &str) -> Self::RestartFuture {
@@ -514,25 +2200,60 @@ impl ModuleRuntime for DockerModuleRuntime {
             return Box::new(future::err(Error::from(err)));
         }
 
-        Box::new(
-            self.client
-                .container_api()
-                .container_restart(&id, None)
+        // The module is stopped and started rather than restarted by Docker,
+        // so that a module that isn't running is only started, and so that a
+        // start of the module while it restarts can wait for the restart.
+        let runtime = self.clone();
+        let restart_id = id.clone();
+        self.restarts.restart(&id, move || {
+            let id = restart_id;
+            let context =
+                |id: String| ErrorKind::RuntimeOperation(RuntimeOperation::RestartModule(id));
+
+            let inspect_id = id.clone();
+            let inspect = runtime
+                .client
+                .inspect_container(&id)
+                .then(move |result| match result {
+                    Ok(container) => Ok(container
+                        .state()
+                        .and_then(InlineResponse200State::running)
+                        .map_or(false, |running| *running)),
+                    Err(err) => Err(Error::from_docker_error(err, context(inspect_id))),
+                });
+            let timeout_id = id.clone();
+            let restart = runtime
+                .client
+                .timeout(inspect, NO_GRACE, move || context(timeout_id))
+                .and_then(move |running| {
+                    let err_id = id.clone();
+                    let stopped = if running {
+                        Either::A(runtime.stop(&id, None).then(|result| match result {
+                            Err(ref err) if is_not_modified(err) => Ok(()),
+                            result => result,
+                        }))
+                    } else {
+                        info!("Module {} isn't running, starting it", id);
+                        Either::B(future::ok(()))
+                    };
+
+                    stopped
+                        .and_then(move |()| runtime.start_container(&id).map(|()| id))
+                        .map_err(move |err| Error::from(err.context(context(err_id))))
+                })
                 .then(|result| match result {
-                    Ok(_) => {
+                    Ok(id) => {
                         info!("Successfully restarted module {}", id);
                         Ok(())
                     }
                     Err(err) => {
-                        let err = Error::from_docker_error(
-                            err,
-                            ErrorKind::RuntimeOperation(RuntimeOperation::RestartModule(id)),
-                        );
                         log_failure(Level::Warn, &err);
                         Err(err)
                     }
-                }),
-        )
+                });
+
+            Box::new(restart)
+        })
     }
 
     fn remove(&self, id: &str) -> Self::RemoveFuture {
@@ -546,62 +2267,141 @@ impl ModuleRuntime for DockerModuleRuntime {
             return Box::new(future::err(Error::from(err)));
         }
 
-        Box::new(
-            self.client
-                .container_api()
-                .container_delete(
-                    &id, /* remove volumes */ false, /* force */ true,
-                    /* remove link */ false,
+        let context = {
+            let id = id.clone();
+            || ErrorKind::RuntimeOperation(RuntimeOperation::RemoveModule(id))
+        };
+
+        let runtime = self.clone();
+
+        let remove = self
+            .client
+            .container_api()
+            .container_delete(
+                &self.client.container_name(&id),
+                /* remove volumes */ false,
+                /* force */ true,
+                /* remove link */ false,
+            )
+            .then(move |result| match result {
+                Ok(_) => {
+                    info!("Successfully removed module {}", id);
+                    runtime.forget_module(&id);
+                    Ok(())
+                }
+                Err(err) => {
+                    let err = Error::from_docker_error(
+                        err,
+                        ErrorKind::RuntimeOperation(RuntimeOperation::RemoveModule(id)),
+                    );
+                    log_failure(Level::Warn, &err);
+                    Err(err)
+                }
+            });
+
+        Box::new(self.client.timeout(remove, NO_GRACE, context))
+    }
+
+    fn force_remove(&self, id: &str) -> Self::RemoveFuture {
+        info!("Force removing module {}...", id);
+
+        let id = id.to_string();
+
+        if let Err(err) = ensure_not_empty_with_context(&id, || {
+            ErrorKind::RuntimeOperation(RuntimeOperation::RemoveModule(id.clone()))
+        }) {
+            return Box::new(future::err(Error::from(err)));
+        }
+
+        let runtime = self.clone();
+        let retry = self.clone();
+        let retry_id = id.clone();
+
+        let remove = self
+            .delete_container(&id)
+            .or_else(move |err| {
+                if is_not_found(&err) {
+                    return Either::A(future::ok(()));
+                }
+
+                // Docker may hold on to a container that is stuck, such as
+                // in the removing state, until its processes are killed.
+                warn!(
+                    "Could not remove module {}, killing it and trying again:",
+                    retry_id
+                );
+                log_failure(Level::Warn, &err);
+                let delete = retry.clone();
+                Either::B(
+                    retry
+                        .kill_container(&retry_id)
+                        .then(|_| {
+                            Delay::new(Instant::now() + FORCE_REMOVE_RETRY_DELAY)
+                                .then(|_| Ok::<_, Error>(()))
+                        })
+                        .and_then(move |()| delete.delete_container(&retry_id))
+                        .or_else(|err| if is_not_found(&err) { Ok(()) } else { Err(err) }),
                 )
-                .then(|result| match result {
-                    Ok(_) => {
-                        info!("Successfully removed module {}", id);
-                        Ok(())
-                    }
-                    Err(err) => {
-                        let err = Error::from_docker_error(
-                            err,
-                            ErrorKind::RuntimeOperation(RuntimeOperation::RemoveModule(id)),
-                        );
-                        log_failure(Level::Warn, &err);
-                        Err(err)
-                    }
-                }),
-        )
+            })
+            .then(move |result| match result {
+                Ok(()) => {
+                    info!("Successfully removed module {}", id);
+                    runtime.forget_module(&id);
+                    Ok(())
+                }
+                Err(err) => {
+                    log_failure(Level::Warn, &err);
+                    Err(err)
+                }
+            });
+
+        Box::new(remove)
     }
 
     fn system_info(&self) -> Self::SystemInfoFuture {
         info!("Querying system info...");
 
-        Box::new(
-            self.client
-                .system_api()
-                .system_info()
-                .then(|result| match result {
-                    Ok(system_info) => {
-                        let system_info = CoreSystemInfo::new(
-                            system_info
-                                .os_type()
-                                .unwrap_or(&String::from("Unknown"))
-                                .to_string(),
-                            system_info
-                                .architecture()
-                                .unwrap_or(&String::from("Unknown"))
-                                .to_string(),
-                        );
-                        info!("Successfully queried system info");
-                        Ok(system_info)
-                    }
-                    Err(err) => {
-                        let err = Error::from_docker_error(
-                            err,
-                            ErrorKind::RuntimeOperation(RuntimeOperation::SystemInfo),
-                        );
-                        log_failure(Level::Warn, &err);
-                        Err(err)
-                    }
-                }),
-        )
+        let system_info = self
+            .client
+            .system_api()
+            .system_info()
+            .then(|result| match result {
+                Ok(system_info) => {
+                    let system_info = CoreSystemInfo::new(
+                        system_info
+                            .os_type()
+                            .unwrap_or(&String::from("Unknown"))
+                            .to_string(),
+                        system_info
+                            .architecture()
+                            .unwrap_or(&String::from("Unknown"))
+                            .to_string(),
+                    )
+                    .with_runtime_type(DOCKER_MODULE_TYPE.to_string())
+                    .with_runtime_version(system_info.server_version().map(ToString::to_string))
+                    .with_operating_system(system_info.operating_system().map(ToString::to_string))
+                    .with_kernel_version(system_info.kernel_version().map(ToString::to_string))
+                    .with_total_memory(
+                        system_info
+                            .mem_total()
+                            .and_then(|mem_total| u64::try_from(mem_total).ok()),
+                    );
+                    info!("Successfully queried system info");
+                    Ok(system_info)
+                }
+                Err(err) => {
+                    let err = Error::from_docker_error(
+                        err,
+                        ErrorKind::RuntimeOperation(RuntimeOperation::SystemInfo),
+                    );
+                    log_failure(Level::Warn, &err);
+                    Err(err)
+                }
+            });
+
+        Box::new(self.client.timeout(system_info, NO_GRACE, || {
+            ErrorKind::RuntimeOperation(RuntimeOperation::SystemInfo)
+        }))
     }
 
     fn system_resources(&self) -> Self::SystemResourcesFuture {
@@ -615,7 +2415,9 @@ impl ModuleRuntime for DockerModuleRuntime {
                 remove_not_found(
                     stream::iter_ok(modules)
                         .and_then(move |module| {
-                            client.container_api().container_stats(module.name(), false)
+                            client
+                                .container_api()
+                                .container_stats(&client.container_name(module.name()), false)
                         })
                         .map_err(|err| {
                             Error::from_docker_error(
@@ -635,6 +2437,9 @@ impl ModuleRuntime for DockerModuleRuntime {
                     ))
                 })
             });
+        let docker_stats = self.client.timeout(docker_stats, NO_GRACE, || {
+            ErrorKind::RuntimeOperation(RuntimeOperation::SystemResources)
+        });
 
         #[cfg(not(windows))]
         {
@@ -729,13 +2534,21 @@ impl ModuleRuntime for DockerModuleRuntime {
             .context(ErrorKind::RuntimeOperation(RuntimeOperation::ListModules))
             .map_err(Error::from)
             .map(|filters| {
-                self.client
-                    .container_api()
-                    .container_list(true, 0, false, &filters)
+                let list = self
+                    .client
+                    .list_containers(&filters)
                     .map(move |containers| {
                         containers
                             .iter()
-                            .flat_map(|container| {
+                            .filter_map(|container| {
+                                // Containers that aren't named with the
+                                // prefix aren't the daemon's, even if they
+                                // have its label.
+                                let name = container.names().iter().next()?;
+                                let name = client_copy.module_name(name)?.to_string();
+                                Some((container, name))
+                            })
+                            .flat_map(|(container, name)| {
                                 DockerConfig::new(
                                     container.image().to_string(),
                                     ContainerCreateBody::new()
@@ -743,23 +2556,11 @@ impl ModuleRuntime for DockerModuleRuntime {
                                     None,
                                 )
                                 .map(|config| {
-                                    (
-                                        container,
-                                        config.with_image_id(container.image_id().clone()),
-                                    )
+                                    (name, config.with_image_id(container.image_id().clone()))
                                 })
                             })
-                            .flat_map(|(container, config)| {
-                                DockerModule::new(
-                                    client_copy.clone(),
-                                    container
-                                        .names()
-                                        .iter()
-                                        .next()
-                                        .map_or("Unknown", |s| &s[1..])
-                                        .to_string(),
-                                    config,
-                                )
+                            .flat_map(|(name, config)| {
+                                DockerModule::new(client_copy.clone(), name, config)
                             })
                             .collect()
                     })
@@ -768,7 +2569,11 @@ impl ModuleRuntime for DockerModuleRuntime {
                             err,
                             ErrorKind::RuntimeOperation(RuntimeOperation::ListModules),
                         )
-                    })
+                    });
+
+                self.client.timeout(list, NO_GRACE, || {
+                    ErrorKind::RuntimeOperation(RuntimeOperation::ListModules)
+                })
             })
             .into_future()
             .flatten()
@@ -784,7 +2589,12 @@ impl ModuleRuntime for DockerModuleRuntime {
     }
 
     fn list_with_details(&self) -> Self::ListWithDetailsStream {
-        list_with_details(self)
+        let runtime = self.clone();
+        Box::new(list_with_details(self).map(move |(module, state)| {
+            runtime.report_oom_kill(module.name(), &state);
+            let state = runtime.with_last_error(module.name(), state);
+            (module, state)
+        }))
     }
 
     fn logs(&self, id: &str, options: &LogOptions) -> Self::LogsFuture {
@@ -796,12 +2606,13 @@ impl ModuleRuntime for DockerModuleRuntime {
             .client
             .container_api()
             .container_logs(
-                &id,
+                &self.client.container_name(&id),
                 options.follow(),
                 true,
                 true,
                 options.since(),
-                false,
+                options.until(),
+                options.timestamps(),
                 tail,
             )
             .then(|result| match result {
@@ -834,6 +2645,237 @@ impl ModuleRuntime for DockerModuleRuntime {
             future::join_all(n).map(|_| ())
         }))
     }
+
+    fn events(&self) -> &ModuleEvents {
+        &self.events
+    }
+
+    fn record_error(&self, id: &str, error: ModuleError) {
+        self.last_errors
+            .lock()
+            .expect("Last errors lock poisoned")
+            .insert(id.to_string(), error);
+    }
+
+    fn stats(&self, id: &str) -> Self::StatsFuture {
+        debug!("Getting stats for module {}...", id);
+        let id = id.to_string();
+
+        if let Err(err) = ensure_not_empty_with_context(&id, || {
+            ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleStats(id.clone()))
+        }) {
+            return Box::new(future::err(Error::from(err)));
+        }
+
+        let context = {
+            let id = id.clone();
+            || ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleStats(id))
+        };
+
+        let stats = self
+            .client
+            .container_api()
+            .container_stats(&self.client.container_name(&id), false)
+            .then(|result| match result {
+                Ok(stats) => {
+                    let stats = parse_stats(&id, stats)?;
+                    debug!("Successfully got stats for module {}", id);
+                    Ok(stats)
+                }
+                Err(err) => {
+                    let err = Error::from_docker_error(
+                        err,
+                        ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleStats(id)),
+                    );
+                    log_failure(Level::Warn, &err);
+                    Err(err)
+                }
+            });
+
+        Box::new(self.client.timeout(stats, NO_GRACE, context))
+    }
+
+    fn prune_images(&self, filter: ImagePruneFilter) -> Self::PruneImagesFuture {
+        info!("Pruning {} images...", prune_filter_name(filter));
+
+        let filters = match filter {
+            ImagePruneFilter::Dangling => json!({ "dangling": ["true"] }),
+            ImagePruneFilter::Unused => json!({}),
+        }
+        .to_string();
+        let images = self
+            .client
+            .image_api()
+            .image_list(false, &filters, false)
+            .map_err(|err| {
+                Error::from_docker_error(
+                    err,
+                    ErrorKind::RuntimeOperation(RuntimeOperation::PruneImages),
+                )
+            });
+
+        let client = self.client.clone();
+        let pruned = self
+            .list()
+            .join(images)
+            .and_then(move |(modules, images)| {
+                let removals = images
+                    .into_iter()
+                    .filter(move |image| !is_image_in_use(image, &modules))
+                    .map(move |image| remove_unused_image(&client, image));
+                future::join_all(removals)
+            })
+            .map(|removed| {
+                let (images, sizes): (Vec<_>, Vec<_>) = removed.into_iter().flatten().unzip();
+                let pruned = PrunedImages::new(images, sizes.into_iter().sum());
+                info!(
+                    "Successfully pruned {} images, reclaiming {} bytes",
+                    pruned.images().len(),
+                    pruned.space_reclaimed()
+                );
+                pruned
+            });
+
+        Box::new(pruned)
+    }
+
+    fn exec(&self, id: &str, cmd: Vec<String>, env: HashMap<String, String>) -> Self::ExecStream {
+        info!("Running a command in module {}...", id);
+        let id = id.to_string();
+
+        if let Err(err) = ensure_not_empty_with_context(&id, || {
+            ErrorKind::RuntimeOperation(RuntimeOperation::ExecModule(id.clone()))
+        }) {
+            return Box::new(stream::once(Err(Error::from(err))));
+        }
+
+        let context = |id: String| ErrorKind::RuntimeOperation(RuntimeOperation::ExecModule(id));
+
+        let mut env: Vec<_> = env
+            .into_iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect();
+        env.sort();
+        let config = ExecConfig::new()
+            .with_attach_stdout(true)
+            .with_attach_stderr(true)
+            .with_tty(false)
+            .with_cmd(cmd)
+            .with_env(env);
+
+        // The output ends when the command exits, after which its exit code
+        // can be inspected.
+        let client = self.client.clone();
+        let max_line_length = self.max_log_line_length;
+        let create_id = id.clone();
+        let start_id = id.clone();
+        let started = self
+            .client
+            .exec_api()
+            .container_exec(&self.client.container_name(&id), config)
+            .map_err(move |err| Error::from_docker_error(err, context(create_id)))
+            .and_then(move |created| {
+                let exec_id = created.id().clone();
+                client
+                    .exec_api()
+                    .exec_start(
+                        &exec_id,
+                        ExecStartConfig::new().with_detach(false).with_tty(false),
+                    )
+                    .map_err(move |err| Error::from_docker_error(err, context(start_id)))
+                    .map(move |output| (client, exec_id, output))
+            });
+        let timeout_id = id.clone();
+        let exec = self
+            .client
+            .timeout(started, NO_GRACE, move || context(timeout_id))
+            .map(move |(client, exec_id, output)| {
+                let output_id = id.clone();
+                let output = decode_logs_with_max_line_length(output, max_line_length)
+                    .map(|chunk| match chunk {
+                        LogChunk::Stdin(b)
+                        | LogChunk::Stdout(b)
+                        | LogChunk::Stderr(b)
+                        | LogChunk::Unknown(b) => ExecOutput::Output(b),
+                    })
+                    .map_err(move |err| Error::from(err.context(context(output_id.clone()))));
+                let exited = future::lazy(move || {
+                    client.exec_api().exec_inspect(&exec_id).then(
+                        move |result| -> ::std::result::Result<_, Error> {
+                            let inspected = result.map_err(|err| {
+                                Error::from_docker_error(err, context(id.clone()))
+                            })?;
+                            let exit_code = inspected
+                                .exit_code()
+                                .ok_or_else(|| Error::from(context(id.clone())))?;
+                            info!("Command in module {} exited with code {}", id, exit_code);
+                            Ok(ExecOutput::Exited(i64::from(exit_code)))
+                        },
+                    )
+                });
+                output.chain(exited.into_stream())
+            })
+            .flatten_stream();
+
+        Box::new(exec)
+    }
+}
+
+fn prune_filter_name(filter: ImagePruneFilter) -> &'static str {
+    match filter {
+        ImagePruneFilter::Dangling => "dangling",
+        ImagePruneFilter::Unused => "unused",
+    }
+}
+
+// An image is in use by a module if the module's container was created from
+// it, or if it's what the module's image name refers to now, so that the
+// image a module is about to be recreated from isn't pruned either.
+fn is_image_in_use(image: &ImageSummary, modules: &[DockerModule<UrlConnector>]) -> bool {
+    modules.iter().any(|module| {
+        let config = module.config();
+        config.image_id() == Some(image.id().as_str())
+            || image
+                .repo_tags()
+                .unwrap_or(&[])
+                .iter()
+                .chain(image.repo_digests().unwrap_or(&[]))
+                .any(|name| name == config.image())
+    })
+}
+
+// Docker refuses to remove an image that any container uses, including the
+// ones that aren't modules, which is left alone like one that's already gone.
+fn remove_unused_image(
+    client: &DockerClient<UrlConnector>,
+    image: ImageSummary,
+) -> impl Future<Item = Option<(String, u64)>, Error = Error> + Send {
+    let id = image.id().to_string();
+    let size = u64::try_from(*image.size()).unwrap_or(0);
+    client
+        .image_api()
+        .image_delete(&id, false, false)
+        .then(move |result| match result {
+            Ok(_) => {
+                debug!("Removed unused image {}", id);
+                Ok(Some((id, size)))
+            }
+            Err(err) => {
+                let err = Error::from_docker_error(
+                    err,
+                    ErrorKind::RegistryOperation(RegistryOperation::RemoveImage(id.clone())),
+                );
+                match err.cause().and_then(Fail::downcast_ref) {
+                    Some(ErrorKind::Conflict) | Some(ErrorKind::NotFound(_)) => {
+                        debug!("Not removing image {}: {}", id, err);
+                        Ok(None)
+                    }
+                    _ => Err(Error::from(err.context(ErrorKind::RuntimeOperation(
+                        RuntimeOperation::PruneImages,
+                    )))),
+                }
+            }
+        })
 }
 
 impl Authenticator for DockerModuleRuntime {
@@ -846,10 +2888,13 @@ impl Authenticator for DockerModuleRuntime {
     }
 }
 
-fn init_client(docker_url: &Url) -> Result<DockerClient<UrlConnector>> {
+fn init_client(moby_runtime: &MobyRuntime) -> Result<DockerClient<UrlConnector>> {
+    let docker_url = moby_runtime.uri();
+
     // build the hyper client
-    let client =
-        Client::builder().build(UrlConnector::new(docker_url).context(ErrorKind::Initialization)?);
+    let client = Client::builder()
+        .max_idle_per_host(moby_runtime.max_idle_connections())
+        .build(UrlConnector::new(docker_url).context(ErrorKind::Initialization)?);
 
     // extract base path - the bit that comes after the scheme
     let base_path = docker_url
@@ -867,9 +2912,19 @@ fn init_client(docker_url: &Url) -> Result<DockerClient<UrlConnector>> {
             .context(ErrorKind::Initialization)?)
     });
 
-    Ok(DockerClient::new(APIClient::new(configuration)))
+    Ok(DockerClient::new(APIClient::new(configuration))
+        .with_request_timeout(moby_runtime.request_timeout())
+        .with_request_retry(moby_runtime.request_retry().clone())
+        .with_container_prefix(
+            moby_runtime
+                .container_name_prefix()
+                .unwrap_or_default()
+                .to_string(),
+        ))
 }
 
+/// The response body of the logs request. Dropping it mid-stream closes the
+/// connection to Docker, which stops Docker from reading the module's logs.
 #[derive(Debug)]
 pub struct Logs(String, Body);
 
@@ -888,6 +2943,673 @@ impl Stream for Logs {
     }
 }
 
+// Resource limits from the module spec take precedence over the ones in the
+// create options.
+fn apply_resources(
+    create_options: ContainerCreateBody,
+    resources: &ModuleResources,
+) -> ContainerCreateBody {
+    if resources.is_empty() {
+        return create_options;
+    }
+
+    let mut host_config = create_options
+        .host_config()
+        .cloned()
+        .unwrap_or_else(HostConfig::new);
+    if let Some(cpu_shares) = resources.cpu_shares() {
+        host_config.set_cpu_shares(i32::try_from(cpu_shares).unwrap_or(i32::max_value()));
+    }
+    if let Some(memory_bytes) = resources.memory_bytes() {
+        host_config.set_memory(i64::try_from(memory_bytes).unwrap_or(i64::max_value()));
+    }
+    if let Some(memory_swap) = resources.memory_swap() {
+        host_config.set_memory_swap(memory_swap);
+    }
+    if let Some(pids_limit) = resources.pids_limit() {
+        host_config.set_pids_limit(pids_limit);
+    }
+
+    create_options.with_host_config(host_config)
+}
+
+fn apply_binds(create_options: ContainerCreateBody, binds: &[ModuleBind]) -> ContainerCreateBody {
+    if binds.is_empty() {
+        return create_options;
+    }
+
+    let mut host_config = create_options
+        .host_config()
+        .cloned()
+        .unwrap_or_else(HostConfig::new);
+    // Added as mounts rather than `source:target[:ro]` binds, which can't be
+    // told apart from the drive letters of Windows paths.
+    let mut mounts = host_config
+        .mounts()
+        .map_or_else(Vec::new, <[Mount]>::to_vec);
+    mounts.extend(binds.iter().map(|bind| {
+        Mount::new()
+            .with__type("bind".to_string())
+            .with_source(bind.source().to_string())
+            .with_target(bind.target().to_string())
+            .with_read_only(bind.read_only())
+    }));
+    host_config.set_mounts(mounts);
+
+    create_options.with_host_config(host_config)
+}
+
+// The devices of the module spec are added to those of the create options,
+// and its container runtime takes precedence over theirs.
+fn apply_devices(
+    create_options: ContainerCreateBody,
+    devices: &[ModuleDevice],
+    runtime: Option<&str>,
+) -> ContainerCreateBody {
+    if devices.is_empty() && runtime.is_none() {
+        return create_options;
+    }
+
+    let mut host_config = create_options
+        .host_config()
+        .cloned()
+        .unwrap_or_else(HostConfig::new);
+    if !devices.is_empty() {
+        let mut mappings = host_config
+            .devices()
+            .map_or_else(Vec::new, <[DeviceMapping]>::to_vec);
+        mappings.extend(devices.iter().map(|device| {
+            DeviceMapping::new()
+                .with_path_on_host(device.host_path().to_string())
+                .with_path_in_container(device.container_path().to_string())
+                .with_cgroup_permissions(device.permissions().to_string())
+        }));
+        host_config.set_devices(mappings);
+    }
+    if let Some(runtime) = runtime {
+        host_config.set_runtime(runtime.to_string());
+    }
+
+    create_options.with_host_config(host_config)
+}
+
+const NO_NEW_PRIVILEGES: &str = "no-new-privileges";
+const SECCOMP: &str = "seccomp";
+const APPARMOR: &str = "apparmor";
+
+// The capabilities of the module spec are added to those of the create
+// options, and `no_new_privileges` takes precedence over the security option
+// of the create options if it's set.
+fn apply_security(
+    create_options: ContainerCreateBody,
+    cap_add: &[String],
+    cap_drop: &[String],
+    no_new_privileges: bool,
+) -> ContainerCreateBody {
+    if cap_add.is_empty() && cap_drop.is_empty() && !no_new_privileges {
+        return create_options;
+    }
+
+    let mut host_config = create_options
+        .host_config()
+        .cloned()
+        .unwrap_or_else(HostConfig::new);
+    if !cap_add.is_empty() {
+        let mut capabilities = host_config
+            .cap_add()
+            .map_or_else(Vec::new, <[String]>::to_vec);
+        capabilities.extend(cap_add.iter().cloned());
+        host_config.set_cap_add(capabilities);
+    }
+    if !cap_drop.is_empty() {
+        let mut capabilities = host_config
+            .cap_drop()
+            .map_or_else(Vec::new, <[String]>::to_vec);
+        capabilities.extend(cap_drop.iter().cloned());
+        host_config.set_cap_drop(capabilities);
+    }
+    if no_new_privileges {
+        let mut security_opt = host_config
+            .security_opt()
+            .map_or_else(Vec::new, <[String]>::to_vec);
+        // Docker also takes the option with a `:` or `=` and a bool.
+        security_opt.retain(|opt| !opt.starts_with(NO_NEW_PRIVILEGES));
+        security_opt.push(NO_NEW_PRIVILEGES.to_string());
+        host_config.set_security_opt(security_opt);
+    }
+
+    create_options.with_host_config(host_config)
+}
+
+// The security options of the module's profiles take precedence over those of
+// the create options for the same kind of profile.
+fn apply_security_profiles(
+    create_options: ContainerCreateBody,
+    profiles: Vec<String>,
+) -> ContainerCreateBody {
+    if profiles.is_empty() {
+        return create_options;
+    }
+
+    let mut host_config = create_options
+        .host_config()
+        .cloned()
+        .unwrap_or_else(HostConfig::new);
+    let mut security_opt = host_config
+        .security_opt()
+        .map_or_else(Vec::new, <[String]>::to_vec);
+    for kind in &[SECCOMP, APPARMOR] {
+        if profiles.iter().any(|profile| profile.starts_with(kind)) {
+            // Docker also takes the older `seccomp:` and `apparmor:` forms.
+            security_opt.retain(|opt| !opt.starts_with(kind));
+        }
+    }
+    security_opt.extend(profiles);
+    host_config.set_security_opt(security_opt);
+
+    create_options.with_host_config(host_config)
+}
+
+// The ulimits of the module spec take precedence over those of the create
+// options with the same name.
+fn apply_ulimits(
+    create_options: ContainerCreateBody,
+    ulimits: &[ModuleUlimit],
+) -> ContainerCreateBody {
+    if ulimits.is_empty() {
+        return create_options;
+    }
+
+    let mut host_config = create_options
+        .host_config()
+        .cloned()
+        .unwrap_or_else(HostConfig::new);
+    let mut merged = host_config
+        .ulimits()
+        .map_or_else(Vec::new, <[ResourcesUlimits]>::to_vec);
+    merged.retain(|existing| {
+        ulimits
+            .iter()
+            .all(|ulimit| existing.name() != Some(ulimit.name()))
+    });
+    merged.extend(ulimits.iter().map(|ulimit| {
+        ResourcesUlimits::new()
+            .with_name(ulimit.name().to_string())
+            .with_soft(ulimit.soft())
+            .with_hard(ulimit.hard())
+    }));
+    host_config.set_ulimits(merged);
+
+    create_options.with_host_config(host_config)
+}
+
+// A module whose restart policy isn't the default `always` one is restarted
+// by the container runtime under the same policy, which takes precedence over
+// that of the create options. Modules that are always restarted are left to
+// whoever supervises them, like the watchdog or the edge agent.
+fn apply_restart_policy(
+    create_options: ContainerCreateBody,
+    policy: RestartPolicy,
+    max_retries: Option<u32>,
+) -> ContainerCreateBody {
+    let restart_policy = match policy {
+        RestartPolicy::Always => return create_options,
+        RestartPolicy::OnFailure => {
+            // Docker retries without a limit when the count is 0.
+            let count = max_retries.map_or(0, |max_retries| {
+                i32::try_from(max_retries).unwrap_or(i32::max_value())
+            });
+            DockerRestartPolicy::new()
+                .with_name("on-failure".to_string())
+                .with_maximum_retry_count(count)
+        }
+        RestartPolicy::Never => DockerRestartPolicy::new().with_name("no".to_string()),
+    };
+
+    let host_config = create_options
+        .host_config()
+        .cloned()
+        .unwrap_or_else(HostConfig::new)
+        .with_restart_policy(restart_policy);
+    create_options.with_host_config(host_config)
+}
+
+// Only ever makes the root filesystem read-only, so that create options that
+// already do so aren't overridden.
+fn apply_root_fs(
+    create_options: ContainerCreateBody,
+    read_only_root_fs: bool,
+    tmpfs: &[ModuleTmpfs],
+) -> ContainerCreateBody {
+    if !read_only_root_fs && tmpfs.is_empty() {
+        return create_options;
+    }
+
+    let mut host_config = create_options
+        .host_config()
+        .cloned()
+        .unwrap_or_else(HostConfig::new);
+    if read_only_root_fs {
+        host_config.set_readonly_rootfs(true);
+    }
+    if !tmpfs.is_empty() {
+        let mut options = host_config.tmpfs().cloned().unwrap_or_else(HashMap::new);
+        for tmpfs in tmpfs {
+            let size = tmpfs
+                .size_bytes()
+                .map_or_else(String::new, |size_bytes| format!("size={}", size_bytes));
+            options.insert(tmpfs.target().to_string(), size);
+        }
+        host_config.set_tmpfs(options);
+    }
+
+    create_options.with_host_config(host_config)
+}
+
+// The runtime's default create options, normalized and checked when the
+// runtime starts like the create options of modules are when they're created.
+fn default_create_options(moby_runtime: &MobyRuntime) -> Result<Option<serde_json::Value>> {
+    let mut defaults = match moby_runtime.default_create_options() {
+        Some(defaults) => defaults.clone(),
+        None => return Ok(None),
+    };
+
+    normalize_create_options(&mut defaults).context(ErrorKind::Initialization)?;
+    let warnings = validate_create_options(&defaults, moby_runtime.strict_create_options())
+        .context(ErrorKind::Initialization)?;
+    for warning in warnings {
+        warn!("Default create options: {}", warning);
+    }
+
+    Ok(Some(defaults))
+}
+
+fn apply_default_create_options(
+    create_options: ContainerCreateBody,
+    defaults: Option<&serde_json::Value>,
+) -> std::result::Result<ContainerCreateBody, serde_json::Error> {
+    let defaults = match defaults {
+        Some(defaults) => defaults,
+        None => return Ok(create_options),
+    };
+
+    let mut merged = serde_json::to_value(create_options)?;
+    merge_create_options(defaults, &mut merged);
+    serde_json::from_value(merged)
+}
+
+// Docker gives the container this long to exit when it is stopped without a
+// timeout, so the module's stop timeout takes precedence over the one in its
+// create options.
+fn apply_stop_timeout(
+    create_options: ContainerCreateBody,
+    stop_timeout: Option<Duration>,
+) -> ContainerCreateBody {
+    match stop_timeout {
+        Some(timeout) => create_options
+            .with_stop_timeout(i32::try_from(timeout.as_secs()).unwrap_or(i32::max_value())),
+        None => create_options,
+    }
+}
+
+// Docker sends the container this signal when it is stopped, SIGKILLing it
+// after the stop timeout, so the module's stop signal takes precedence over
+// the one in its create options and the image's.
+fn apply_stop_signal(
+    create_options: ContainerCreateBody,
+    stop_signal: Option<&str>,
+) -> ContainerCreateBody {
+    match stop_signal {
+        Some(signal) => create_options.with_stop_signal(signal.to_string()),
+        None => create_options,
+    }
+}
+
+// The user and working directory of the module spec take precedence over
+// those of the create options, and both over the image's.
+fn apply_user(
+    create_options: ContainerCreateBody,
+    user: Option<&str>,
+    working_dir: Option<&str>,
+) -> ContainerCreateBody {
+    let create_options = match user {
+        Some(user) => create_options.with_user(user.to_string()),
+        None => create_options,
+    };
+    match working_dir {
+        Some(working_dir) => create_options.with_working_dir(working_dir.to_string()),
+        None => create_options,
+    }
+}
+
+// Whether a container with a read-only root filesystem has nowhere to write
+// to, which is a likely reason for it to fail to start.
+fn read_only_without_writable_mount(container: &InlineResponse200) -> bool {
+    let host_config = match container.host_config() {
+        Some(host_config) => host_config,
+        None => return false,
+    };
+    host_config.readonly_rootfs() == Some(&true)
+        && host_config.tmpfs().map_or(true, HashMap::is_empty)
+        && !container
+            .mounts()
+            .unwrap_or_default()
+            .iter()
+            .any(|mount| mount.RW() == Some(&true))
+}
+
+// A log config in the module spec takes precedence over the one in the create
+// options, which takes precedence over the runtime's default.
+fn apply_log_config(
+    create_options: ContainerCreateBody,
+    log_config: &ModuleLogConfig,
+    default: &ModuleLogConfig,
+) -> ContainerCreateBody {
+    let mut host_config = create_options
+        .host_config()
+        .cloned()
+        .unwrap_or_else(HostConfig::new);
+    let log_config = if !log_config.is_empty() {
+        log_config
+    } else if host_config.log_config().is_none() && !default.is_empty() {
+        default
+    } else {
+        return create_options;
+    };
+
+    let mut options = HashMap::new();
+    if let Some(max_size) = log_config.max_size() {
+        options.insert("max-size".to_string(), max_size.to_string());
+    }
+    if let Some(max_file) = log_config.max_file() {
+        options.insert("max-file".to_string(), max_file.to_string());
+    }
+
+    let mut docker_log_config = HostConfigLogConfig::new().with_config(options);
+    if let Some(log_driver) = log_config.log_driver() {
+        docker_log_config.set__type(log_driver.to_string());
+    }
+    host_config.set_log_config(docker_log_config);
+
+    create_options.with_host_config(host_config)
+}
+
+// A module that may not connect anywhere is created without a network, not
+// even the one in its create options, and doesn't join any.
+fn apply_network_policy(
+    create_options: ContainerCreateBody,
+    isolated: bool,
+) -> ContainerCreateBody {
+    if !isolated {
+        return create_options;
+    }
+
+    let host_config = create_options
+        .host_config()
+        .cloned()
+        .unwrap_or_else(HostConfig::new)
+        .with_network_mode("none".to_string());
+    let mut create_options = create_options.with_host_config(host_config);
+    create_options.reset_networking_config();
+    create_options
+}
+
+fn is_not_found(err: &Error) -> bool {
+    match ModuleRuntimeErrorReason::from(err) {
+        ModuleRuntimeErrorReason::NotFound => true,
+        ModuleRuntimeErrorReason::Other => false,
+    }
+}
+
+// Docker names containers like `[a-zA-Z0-9][a-zA-Z0-9_.-]*`, so a prefix has
+// to start like a name does.
+fn is_valid_container_prefix(prefix: &str) -> bool {
+    prefix
+        .chars()
+        .next()
+        .map_or(false, |c| c.is_ascii_alphanumeric())
+        && prefix
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-')
+}
+
+// Docker only resolves the names of containers on their networks, so a module
+// whose container is named with a prefix gets its own name as an alias, for
+// the modules that connect to it by name. On the alias network every module
+// is registered by its name, whatever its container is named.
+fn with_module_alias(
+    mut aliases: Vec<String>,
+    module: &str,
+    container: &str,
+    alias_network: bool,
+) -> Vec<String> {
+    if (alias_network || module != container) && !aliases.iter().any(|alias| alias == module) {
+        aliases.push(module.to_string());
+    }
+    aliases
+}
+
+// A module whose create options don't name a network is put on the alias
+// network, like the edge agent does for the modules that it creates. Modules
+// on the host's network, on no network, on another container's network or
+// only on other networks are left where they are.
+fn apply_module_alias(
+    create_options: ContainerCreateBody,
+    module: &str,
+    container: &str,
+    alias_network: Option<&str>,
+) -> ContainerCreateBody {
+    let network_mode = create_options
+        .host_config()
+        .and_then(HostConfig::network_mode)
+        .filter(|mode| !mode.is_empty())
+        .map(ToString::to_string);
+    let mut endpoints = create_options
+        .networking_config()
+        .and_then(ContainerCreateBodyNetworkingConfig::endpoints_config)
+        .cloned()
+        .unwrap_or_default();
+
+    let mut create_options = create_options;
+    if let Some(alias_network) = alias_network {
+        let joins = match network_mode {
+            None => endpoints.is_empty(),
+            Some(ref mode) => mode == alias_network,
+        };
+        if joins && !endpoints.contains_key(alias_network) {
+            endpoints.insert(alias_network.to_string(), EndpointSettings::new());
+        }
+        if joins && network_mode.is_none() {
+            let host_config = create_options
+                .host_config()
+                .cloned()
+                .unwrap_or_else(HostConfig::new)
+                .with_network_mode(alias_network.to_string());
+            create_options = create_options.with_host_config(host_config);
+        }
+    } else if module == container {
+        return create_options;
+    }
+
+    if endpoints.is_empty() {
+        return create_options;
+    }
+
+    let endpoints = endpoints
+        .into_iter()
+        .map(|(network, endpoint)| {
+            let aliases = with_module_alias(
+                endpoint.aliases().unwrap_or_default().to_vec(),
+                module,
+                container,
+                alias_network == Some(network.as_str()),
+            );
+            // The endpoints on other networks are left as they were.
+            if aliases.is_empty() {
+                (network, endpoint)
+            } else {
+                (network, endpoint.with_aliases(aliases))
+            }
+        })
+        .collect();
+
+    let networking_config = create_options
+        .networking_config()
+        .cloned()
+        .unwrap_or_else(ContainerCreateBodyNetworkingConfig::new)
+        .with_endpoints_config(endpoints);
+    create_options.with_networking_config(networking_config)
+}
+
+// The names that a module can be reached by on a network: the aliases of its
+// endpoint in its create options and of the network in its module spec. The
+// name of its container is added by the caller, since it's known there.
+fn network_aliases(
+    create_options: &ContainerCreateBody,
+    networks: &[ModuleNetwork],
+    network: &str,
+) -> Vec<String> {
+    let endpoint = create_options
+        .networking_config()
+        .and_then(ContainerCreateBodyNetworkingConfig::endpoints_config)
+        .and_then(|endpoints| endpoints.get(network))
+        .and_then(EndpointSettings::aliases)
+        .unwrap_or_default();
+    let joined = networks
+        .iter()
+        .filter(|joined| joined.name() == network)
+        .flat_map(|joined| joined.aliases());
+    let mut aliases: Vec<String> = endpoint.iter().chain(joined).cloned().collect();
+    aliases.sort();
+    aliases.dedup();
+    aliases
+}
+
+// The names that both a module and another one can be reached by, with the
+// other module. Docker resolves a name that several containers on a network
+// have to any of them, so modules that connect to it may reach the wrong one.
+fn alias_collisions<'a, I>(names: &[String], others: I) -> Vec<(String, String)>
+where
+    I: IntoIterator<Item = (&'a str, Vec<String>)>,
+{
+    let mut collisions: Vec<_> = others
+        .into_iter()
+        .flat_map(move |(other, other_names)| {
+            other_names
+                .into_iter()
+                .filter(move |name| names.contains(name))
+                .map(move |name| (name, other.to_string()))
+        })
+        .collect();
+    collisions.sort();
+    collisions.dedup();
+    collisions
+}
+
+// The addresses of the container on the networks that it is connected to.
+fn container_addresses(container: &InlineResponse200) -> Vec<IpAddr> {
+    container
+        .network_settings()
+        .and_then(|settings| settings.networks())
+        .into_iter()
+        .flat_map(|networks| networks.values())
+        .flat_map(|endpoint| {
+            vec![endpoint.ip_address(), endpoint.global_i_pv6_address()]
+                .into_iter()
+                .filter_map(|address| address.and_then(|address| address.parse().ok()))
+        })
+        .collect()
+}
+
+// The DNS servers and search domains in the module spec take precedence over
+// the ones in the create options, which take precedence over the runtime's
+// defaults. Servers and search domains are each chosen on their own.
+fn apply_dns(
+    create_options: ContainerCreateBody,
+    dns: &[String],
+    dns_search: &[String],
+    default_dns: &[String],
+    default_dns_search: &[String],
+) -> ContainerCreateBody {
+    let mut host_config = create_options
+        .host_config()
+        .cloned()
+        .unwrap_or_else(HostConfig::new);
+
+    let dns = if !dns.is_empty() {
+        Some(dns)
+    } else if host_config.dns().is_none() && !default_dns.is_empty() {
+        Some(default_dns)
+    } else {
+        None
+    };
+    let dns_search = if !dns_search.is_empty() {
+        Some(dns_search)
+    } else if host_config.dns_search().is_none() && !default_dns_search.is_empty() {
+        Some(default_dns_search)
+    } else {
+        None
+    };
+    if dns.is_none() && dns_search.is_none() {
+        return create_options;
+    }
+
+    if let Some(dns) = dns {
+        host_config.set_dns(dns.to_vec());
+    }
+    if let Some(dns_search) = dns_search {
+        host_config.set_dns_search(dns_search.to_vec());
+    }
+    create_options.with_host_config(host_config)
+}
+
+// Docker reports a rejected resource limit as a plain message. If it refers to
+// a limit set in the module spec, surface it as an error naming that field.
+fn resource_limit_error(err: Error, module: &ModuleSpec<DockerConfig>) -> Error {
+    let field = match Fail::find_root_cause(&err).downcast_ref::<ErrorKind>() {
+        Some(ErrorKind::FormattedDockerRuntime(message)) => {
+            rejected_resource_limit(module.resources(), message)
+                .map(|field| (field, message.clone()))
+        }
+        _ => None,
+    };
+
+    match field {
+        Some((field, message)) => Error::from(
+            ErrorKind::InvalidResourceLimit(field, message).context(ErrorKind::RuntimeOperation(
+                RuntimeOperation::CreateModule(module.name().to_string()),
+            )),
+        ),
+        None => err,
+    }
+}
+
+fn rejected_resource_limit(resources: &ModuleResources, message: &str) -> Option<&'static str> {
+    let message = message.to_lowercase();
+    let limits: [(&'static str, bool, &[&str]); 4] = [
+        ("memory_swap", resources.memory_swap().is_some(), &["swap"]),
+        (
+            "memory_bytes",
+            resources.memory_bytes().is_some(),
+            &["memory"],
+        ),
+        (
+            "cpu_shares",
+            resources.cpu_shares().is_some(),
+            &["cpu shares", "cpushares", "cpu-shares"],
+        ),
+        ("pids_limit", resources.pids_limit().is_some(), &["pids"]),
+    ];
+
+    limits
+        .iter()
+        .find(|(_, is_set, keywords)| *is_set && keywords.iter().any(|k| message.contains(k)))
+        .map(|(field, _, _)| *field)
+}
+
+// The docker response body is handed over as is, so logs are streamed to the
+// caller frame by frame and dropping the response (e.g. when the client
+// disconnects) closes the connection to docker as well.
 impl From<Logs> for Body {
     fn from(logs: Logs) -> Self {
         logs.1
@@ -1045,9 +3767,11 @@ mod tests {
     use json_patch::merge;
     use serde_json::{self, json, Value as JsonValue};
 
+    use docker::models::MountPoint;
     use edgelet_core::{
-        Certificates, Connect, Listen, ModuleRegistry, ModuleTop, Provisioning, RuntimeSettings,
-        WatchdogSettings,
+        AdmissionSettings, AuditSettings, Certificates, Connect, HealthSettings, Listen,
+        LogForwardingSettings, LogsSettings, ModuleRegistry, ModuleTop, Provisioning,
+        RuntimeSettings, UpstreamSettings, WatchdogSettings, WebhookSettings,
     };
     use edgelet_test_utils::crypto::TestHsm;
     use provisioning::ReprovisioningStatus;
@@ -1106,14 +3830,44 @@ mod tests {
             .merge(File::from_str(&config_json.to_string(), FileFormat::Json))
             .unwrap();
 
-        config.try_into().unwrap()
+        config.try_into().unwrap()
+    }
+
+    #[test]
+    fn invalid_uri_prefix_fails() {
+        let settings = make_settings(Some(json!({
+            "moby_runtime": {
+                "uri": "foo:///this/is/not/valid"
+            }
+        })));
+        let err = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+            .wait()
+            .unwrap_err();
+        assert!(failure::Fail::iter_chain(&err).any(|err| err
+            .to_string()
+            .contains("URL does not have a recognized scheme")));
+    }
+
+    #[test]
+    fn invalid_default_dns_server_fails() {
+        let settings = make_settings(Some(json!({
+            "moby_runtime": {
+                "dns": ["10.0.0.53", "dns.corp.example.com"]
+            }
+        })));
+        let err = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+            .wait()
+            .unwrap_err();
+        assert!(failure::Fail::iter_chain(&err).any(|err| err
+            .to_string()
+            .contains("DNS server \"dns.corp.example.com\" is not an IP address")));
     }
 
     #[test]
-    fn invalid_uri_prefix_fails() {
+    fn invalid_default_create_options_fail() {
         let settings = make_settings(Some(json!({
             "moby_runtime": {
-                "uri": "foo:///this/is/not/valid"
+                "default_create_options": { "HostConfig": "privileged" }
             }
         })));
         let err = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
@@ -1121,7 +3875,7 @@ mod tests {
             .unwrap_err();
         assert!(failure::Fail::iter_chain(&err).any(|err| err
             .to_string()
-            .contains("URL does not have a recognized scheme")));
+            .contains("create option \"HostConfig\" must be an object")));
     }
 
     #[cfg(unix)]
@@ -1321,6 +4075,690 @@ mod tests {
         assert_eq!("missing field `Name`", format!("{}", name.unwrap_err()));
     }
 
+    fn rotating_log_config(max_size: &str) -> ModuleLogConfig {
+        ModuleLogConfig::new()
+            .with_log_driver("json-file".to_string())
+            .with_max_size(max_size.to_string())
+            .with_max_file(3)
+    }
+
+    #[test]
+    fn apply_log_config_translates_module_log_config() {
+        let create_options = ContainerCreateBody::new().with_host_config(
+            HostConfig::new()
+                .with_log_config(HostConfigLogConfig::new().with__type("local".to_string())),
+        );
+
+        let create_options = apply_log_config(
+            create_options,
+            &rotating_log_config("10m"),
+            &rotating_log_config("1g"),
+        );
+
+        let log_config = create_options.host_config().unwrap().log_config().unwrap();
+        assert_eq!(Some("json-file"), log_config._type());
+        let options = log_config.config().unwrap();
+        assert_eq!("10m", options["max-size"]);
+        assert_eq!("3", options["max-file"]);
+    }
+
+    #[test]
+    fn apply_log_config_falls_back_to_default() {
+        let default = rotating_log_config("1g");
+
+        let create_options = apply_log_config(
+            ContainerCreateBody::new(),
+            &ModuleLogConfig::new(),
+            &default,
+        );
+        let log_config = create_options.host_config().unwrap().log_config().unwrap();
+        assert_eq!("1g", log_config.config().unwrap()["max-size"]);
+
+        // The default doesn't replace a log config in the create options
+        let create_options = ContainerCreateBody::new().with_host_config(
+            HostConfig::new()
+                .with_log_config(HostConfigLogConfig::new().with__type("local".to_string())),
+        );
+        let create_options = apply_log_config(create_options, &ModuleLogConfig::new(), &default);
+        let log_config = create_options.host_config().unwrap().log_config().unwrap();
+        assert_eq!(Some("local"), log_config._type());
+        assert_eq!(None, log_config.config());
+    }
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn apply_network_policy_isolates_module_without_allowed_targets() {
+        let mut endpoints = HashMap::new();
+        endpoints.insert("azure-iot-edge".to_string(), EndpointSettings::new());
+        let create_options = ContainerCreateBody::new()
+            .with_host_config(HostConfig::new().with_dns(strings(&["10.0.0.53"])))
+            .with_networking_config(
+                ContainerCreateBodyNetworkingConfig::new().with_endpoints_config(endpoints),
+            );
+
+        // Modules without a policy are left alone, unless the default is deny.
+        let policy = NetworkPolicy::default();
+        let isolated = policy
+            .allowed("sensor")
+            .map_or(false, <[EgressTarget]>::is_empty);
+        let unchanged = apply_network_policy(create_options.clone(), isolated);
+        assert_eq!(None, unchanged.host_config().unwrap().network_mode());
+        assert!(unchanged.networking_config().is_some());
+
+        let policy = NetworkPolicy::new(true, HashMap::new());
+        let isolated = policy
+            .allowed("sensor")
+            .map_or(false, <[EgressTarget]>::is_empty);
+        let create_options = apply_network_policy(create_options, isolated);
+        let host_config = create_options.host_config().unwrap();
+        assert_eq!(Some("none"), host_config.network_mode());
+        assert_eq!(Some(&strings(&["10.0.0.53"])[..]), host_config.dns());
+        assert!(create_options.networking_config().is_none());
+    }
+
+    #[test]
+    fn apply_module_alias_adds_module_name_to_prefixed_containers() {
+        let mut endpoints = HashMap::new();
+        endpoints.insert(
+            "azure-iot-edge".to_string(),
+            EndpointSettings::new().with_aliases(strings(&["hub"])),
+        );
+        let create_options = ContainerCreateBody::new().with_networking_config(
+            ContainerCreateBodyNetworkingConfig::new().with_endpoints_config(endpoints),
+        );
+
+        let aliases = |create_options: &ContainerCreateBody| {
+            create_options
+                .networking_config()
+                .unwrap()
+                .endpoints_config()
+                .unwrap()["azure-iot-edge"]
+                .aliases()
+                .unwrap()
+                .to_vec()
+        };
+
+        let unprefixed = apply_module_alias(create_options.clone(), "edgeHub", "edgeHub", None);
+        assert_eq!(strings(&["hub"]), aliases(&unprefixed));
+
+        let prefixed = apply_module_alias(create_options, "edgeHub", "edge-edgeHub", None);
+        assert_eq!(strings(&["hub", "edgeHub"]), aliases(&prefixed));
+
+        let again = apply_module_alias(prefixed, "edgeHub", "edge-edgeHub", None);
+        assert_eq!(strings(&["hub", "edgeHub"]), aliases(&again));
+    }
+
+    #[test]
+    fn apply_module_alias_registers_module_name_on_alias_network() {
+        let endpoint = |create_options: &ContainerCreateBody, network: &str| {
+            create_options
+                .networking_config()
+                .and_then(ContainerCreateBodyNetworkingConfig::endpoints_config)
+                .and_then(|endpoints| endpoints.get(network))
+                .cloned()
+        };
+
+        // A module without a network is put on the alias network.
+        let create_options = apply_module_alias(
+            ContainerCreateBody::new(),
+            "sensor",
+            "sensor",
+            Some("azure-iot-edge"),
+        );
+        assert_eq!(
+            Some("azure-iot-edge"),
+            create_options.host_config().unwrap().network_mode()
+        );
+        assert_eq!(
+            Some(&strings(&["sensor"])[..]),
+            endpoint(&create_options, "azure-iot-edge")
+                .unwrap()
+                .aliases()
+        );
+
+        // One on the alias network without an endpoint gets one.
+        let create_options = ContainerCreateBody::new()
+            .with_host_config(HostConfig::new().with_network_mode("azure-iot-edge".to_string()));
+        let create_options =
+            apply_module_alias(create_options, "sensor", "sensor", Some("azure-iot-edge"));
+        assert_eq!(
+            Some(&strings(&["sensor"])[..]),
+            endpoint(&create_options, "azure-iot-edge")
+                .unwrap()
+                .aliases()
+        );
+
+        // Modules on the host's network or only on another network are left
+        // where they are.
+        let host = ContainerCreateBody::new()
+            .with_host_config(HostConfig::new().with_network_mode("host".to_string()));
+        let create_options = apply_module_alias(host, "sensor", "sensor", Some("azure-iot-edge"));
+        assert_eq!(
+            Some("host"),
+            create_options.host_config().unwrap().network_mode()
+        );
+        assert!(create_options.networking_config().is_none());
+
+        let mut endpoints = HashMap::new();
+        endpoints.insert("sensors".to_string(), EndpointSettings::new());
+        let other = ContainerCreateBody::new().with_networking_config(
+            ContainerCreateBodyNetworkingConfig::new().with_endpoints_config(endpoints),
+        );
+        let create_options = apply_module_alias(other, "sensor", "sensor", Some("azure-iot-edge"));
+        assert!(create_options.host_config().is_none());
+        assert!(endpoint(&create_options, "azure-iot-edge").is_none());
+        assert_eq!(
+            None,
+            endpoint(&create_options, "sensors").unwrap().aliases()
+        );
+    }
+
+    #[test]
+    fn alias_collisions_name_the_other_module() {
+        let names = strings(&["probe", "sensor"]);
+        let others = vec![
+            ("filter", strings(&["filter"])),
+            ("backup", strings(&["backup", "probe", "sensor"])),
+        ];
+
+        assert_eq!(
+            vec![
+                ("probe".to_string(), "backup".to_string()),
+                ("sensor".to_string(), "backup".to_string()),
+            ],
+            alias_collisions(&names, others)
+        );
+        assert!(alias_collisions(&names, vec![("filter", strings(&["filter"]))]).is_empty());
+    }
+
+    #[test]
+    fn container_prefix_must_start_like_a_container_name() {
+        assert!(is_valid_container_prefix("edge-"));
+        assert!(is_valid_container_prefix("iotedge_1."));
+        assert!(!is_valid_container_prefix(""));
+        assert!(!is_valid_container_prefix("-edge"));
+        assert!(!is_valid_container_prefix("edge/"));
+    }
+
+    #[test]
+    fn apply_dns_module_overrides_default() {
+        let create_options = apply_dns(
+            ContainerCreateBody::new(),
+            &strings(&["10.0.0.53"]),
+            &[],
+            &strings(&["8.8.8.8", "8.8.4.4"]),
+            &strings(&["corp.example.com"]),
+        );
+
+        let host_config = create_options.host_config().unwrap();
+        assert_eq!(Some(&strings(&["10.0.0.53"])[..]), host_config.dns());
+        // The search domains aren't set by the module, so they are the default
+        assert_eq!(
+            Some(&strings(&["corp.example.com"])[..]),
+            host_config.dns_search()
+        );
+    }
+
+    #[test]
+    fn apply_dns_falls_back_to_default() {
+        let create_options = apply_dns(
+            ContainerCreateBody::new(),
+            &[],
+            &[],
+            &strings(&["8.8.8.8"]),
+            &[],
+        );
+        let host_config = create_options.host_config().unwrap();
+        assert_eq!(Some(&strings(&["8.8.8.8"])[..]), host_config.dns());
+        assert_eq!(None, host_config.dns_search());
+
+        // The default doesn't replace the DNS servers in the create options
+        let create_options = ContainerCreateBody::new()
+            .with_host_config(HostConfig::new().with_dns(strings(&["192.168.1.1"])));
+        let create_options = apply_dns(create_options, &[], &[], &strings(&["8.8.8.8"]), &[]);
+        assert_eq!(
+            Some(&strings(&["192.168.1.1"])[..]),
+            create_options.host_config().unwrap().dns()
+        );
+
+        // Without a default the create options are left alone
+        let create_options = apply_dns(ContainerCreateBody::new(), &[], &[], &[], &[]);
+        assert!(create_options.host_config().is_none());
+    }
+
+    #[test]
+    fn apply_binds_adds_mounts() {
+        let existing = Mount::new()
+            .with__type("volume".to_string())
+            .with_source("data".to_string())
+            .with_target("/data".to_string());
+        let create_options = ContainerCreateBody::new()
+            .with_host_config(HostConfig::new().with_mounts(vec![existing]));
+        let binds = vec![
+            ModuleBind::new("/opt/sensors".to_string(), "/sensors".to_string())
+                .with_read_only(true),
+            ModuleBind::new("/tmp/cache".to_string(), "/cache".to_string()),
+        ];
+
+        let create_options = apply_binds(create_options, &binds);
+
+        let mounts = create_options.host_config().unwrap().mounts().unwrap();
+        assert_eq!(3, mounts.len());
+        assert_eq!(Some("volume"), mounts[0]._type());
+        assert_eq!(Some("bind"), mounts[1]._type());
+        assert_eq!(Some("/opt/sensors"), mounts[1].source());
+        assert_eq!(Some("/sensors"), mounts[1].target());
+        assert_eq!(Some(&true), mounts[1].read_only());
+        assert_eq!(Some(&false), mounts[2].read_only());
+    }
+
+    #[test]
+    fn apply_devices_adds_devices_and_runtime() {
+        let existing = DeviceMapping::new()
+            .with_path_on_host("/dev/ttyUSB0".to_string())
+            .with_path_in_container("/dev/ttyUSB0".to_string())
+            .with_cgroup_permissions("rw".to_string());
+        let create_options = ContainerCreateBody::new().with_host_config(
+            HostConfig::new()
+                .with_devices(vec![existing])
+                .with_runtime("runc".to_string()),
+        );
+        let devices = vec![
+            ModuleDevice::new("/dev/nvidia0".to_string(), "/dev/nvidia0".to_string()),
+            ModuleDevice::new("/dev/nvidiactl".to_string(), "/dev/gpu".to_string())
+                .with_permissions("r".to_string()),
+        ];
+
+        let create_options = apply_devices(create_options, &devices, Some("nvidia"));
+
+        let host_config = create_options.host_config().unwrap();
+        let mappings = host_config.devices().unwrap();
+        assert_eq!(3, mappings.len());
+        assert_eq!(Some("/dev/ttyUSB0"), mappings[0].path_on_host());
+        assert_eq!(Some("/dev/nvidia0"), mappings[1].path_on_host());
+        assert_eq!(Some("/dev/nvidia0"), mappings[1].path_in_container());
+        assert_eq!(Some("rwm"), mappings[1].cgroup_permissions());
+        assert_eq!(Some("/dev/gpu"), mappings[2].path_in_container());
+        assert_eq!(Some("r"), mappings[2].cgroup_permissions());
+        assert_eq!(Some("nvidia"), host_config.runtime());
+
+        // The runtime of the create options is kept if the spec doesn't set one.
+        let create_options = ContainerCreateBody::new()
+            .with_host_config(HostConfig::new().with_runtime("runc".to_string()));
+        let create_options = apply_devices(create_options, &[], None);
+        let host_config = create_options.host_config().unwrap();
+        assert_eq!(Some("runc"), host_config.runtime());
+        assert!(host_config.devices().is_none());
+    }
+
+    #[test]
+    fn apply_restart_policy_sets_docker_restart_policy() {
+        let create_options = ContainerCreateBody::new().with_host_config(
+            HostConfig::new()
+                .with_restart_policy(DockerRestartPolicy::new().with_name("always".to_string())),
+        );
+
+        let on_failure =
+            apply_restart_policy(create_options.clone(), RestartPolicy::OnFailure, Some(3));
+        let restart_policy = on_failure.host_config().unwrap().restart_policy().unwrap();
+        assert_eq!(Some("on-failure"), restart_policy.name());
+        assert_eq!(Some(3), restart_policy.maximum_retry_count());
+
+        let unlimited =
+            apply_restart_policy(create_options.clone(), RestartPolicy::OnFailure, None);
+        let restart_policy = unlimited.host_config().unwrap().restart_policy().unwrap();
+        assert_eq!(Some(0), restart_policy.maximum_retry_count());
+
+        let never = apply_restart_policy(create_options.clone(), RestartPolicy::Never, None);
+        let restart_policy = never.host_config().unwrap().restart_policy().unwrap();
+        assert_eq!(Some("no"), restart_policy.name());
+        assert_eq!(None, restart_policy.maximum_retry_count());
+
+        let always = apply_restart_policy(create_options, RestartPolicy::Always, None);
+        let restart_policy = always.host_config().unwrap().restart_policy().unwrap();
+        assert_eq!(Some("always"), restart_policy.name());
+    }
+
+    #[test]
+    fn apply_ulimits_overrides_create_options_of_the_same_name() {
+        let create_options =
+            ContainerCreateBody::new().with_host_config(HostConfig::new().with_ulimits(vec![
+                ResourcesUlimits::new()
+                    .with_name("nofile".to_string())
+                    .with_soft(1024)
+                    .with_hard(1024),
+                ResourcesUlimits::new()
+                    .with_name("core".to_string())
+                    .with_soft(0)
+                    .with_hard(0),
+            ]));
+
+        let create_options = apply_ulimits(
+            create_options,
+            &[
+                ModuleUlimit::new("nofile".to_string(), 65536, 65536),
+                ModuleUlimit::new("memlock".to_string(), -1, -1),
+            ],
+        );
+
+        let ulimits: Vec<_> = create_options
+            .host_config()
+            .unwrap()
+            .ulimits()
+            .unwrap()
+            .iter()
+            .map(|ulimit| {
+                (
+                    ulimit.name().unwrap(),
+                    ulimit.soft().unwrap(),
+                    ulimit.hard().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                ("core", 0, 0),
+                ("nofile", 65536, 65536),
+                ("memlock", -1, -1)
+            ],
+            ulimits
+        );
+
+        let create_options = apply_ulimits(ContainerCreateBody::new(), &[]);
+        assert!(create_options.host_config().is_none());
+    }
+
+    #[test]
+    fn apply_security_adds_capabilities_and_no_new_privileges() {
+        let create_options = ContainerCreateBody::new().with_host_config(
+            HostConfig::new()
+                .with_cap_add(vec!["SYS_TIME".to_string()])
+                .with_security_opt(vec!["apparmor=sensor".to_string()]),
+        );
+
+        let create_options = apply_security(
+            create_options,
+            &["NET_ADMIN".to_string()],
+            &["ALL".to_string()],
+            true,
+        );
+
+        let host_config = create_options.host_config().unwrap();
+        assert_eq!(
+            Some(&["SYS_TIME".to_string(), "NET_ADMIN".to_string()][..]),
+            host_config.cap_add()
+        );
+        assert_eq!(Some(&["ALL".to_string()][..]), host_config.cap_drop());
+        assert_eq!(
+            Some(
+                &[
+                    "apparmor=sensor".to_string(),
+                    "no-new-privileges".to_string()
+                ][..]
+            ),
+            host_config.security_opt()
+        );
+
+        // The module spec wins over create options that allow new privileges.
+        let create_options = ContainerCreateBody::new().with_host_config(
+            HostConfig::new().with_security_opt(vec!["no-new-privileges:false".to_string()]),
+        );
+        let create_options = apply_security(create_options, &[], &[], true);
+        let host_config = create_options.host_config().unwrap();
+        assert_eq!(
+            Some(&["no-new-privileges".to_string()][..]),
+            host_config.security_opt()
+        );
+        assert!(host_config.cap_add().is_none());
+        assert!(host_config.cap_drop().is_none());
+    }
+
+    fn profile_spec() -> ModuleSpec<DockerConfig> {
+        ModuleSpec::new(
+            "m1".to_string(),
+            "docker".to_string(),
+            DockerConfig::new("ubuntu".to_string(), ContainerCreateBody::new(), None).unwrap(),
+            HashMap::new(),
+            ImagePullPolicy::default(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn security_profiles_load_seccomp_profile() {
+        let dir = tempdir::TempDir::new("seccomp").unwrap();
+        let path = dir.path().join("sensor.json");
+        fs::write(
+            &path,
+            "{\n  \"defaultAction\": \"SCMP_ACT_ERRNO\",\n  \"syscalls\": []\n}\n",
+        )
+        .unwrap();
+
+        let spec = profile_spec()
+            .with_seccomp_profile(Some(path))
+            .with_apparmor_profile(Some("iotedge-sensor".to_string()));
+
+        assert_eq!(
+            vec![
+                "seccomp={\"defaultAction\":\"SCMP_ACT_ERRNO\",\"syscalls\":[]}".to_string(),
+                "apparmor=iotedge-sensor".to_string(),
+            ],
+            security_profiles(&spec).unwrap()
+        );
+        assert!(security_profiles(&profile_spec()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn missing_or_invalid_seccomp_profile_fails() {
+        let dir = tempdir::TempDir::new("seccomp").unwrap();
+        let missing = dir.path().join("missing.json");
+        let err = security_profiles(&profile_spec().with_seccomp_profile(Some(missing.clone())))
+            .unwrap_err();
+        match err.kind() {
+            ErrorKind::SeccompProfile(path) => assert_eq!(&missing.display().to_string(), path),
+            kind => panic!("Expected `SeccompProfile` error but got {:?}", kind),
+        }
+
+        let invalid = dir.path().join("invalid.json");
+        fs::write(&invalid, "{ \"defaultAction\": ").unwrap();
+        let err =
+            security_profiles(&profile_spec().with_seccomp_profile(Some(invalid))).unwrap_err();
+        match err.kind() {
+            ErrorKind::SeccompProfile(_) => (),
+            kind => panic!("Expected `SeccompProfile` error but got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn apply_security_profiles_replaces_profiles_of_create_options() {
+        let create_options =
+            ContainerCreateBody::new().with_host_config(HostConfig::new().with_security_opt(vec![
+                "seccomp:unconfined".to_string(),
+                "apparmor=docker-default".to_string(),
+                "label=disable".to_string(),
+            ]));
+
+        let create_options = apply_security_profiles(
+            create_options,
+            vec!["seccomp={\"defaultAction\":\"SCMP_ACT_ERRNO\"}".to_string()],
+        );
+
+        assert_eq!(
+            Some(
+                &[
+                    "apparmor=docker-default".to_string(),
+                    "label=disable".to_string(),
+                    "seccomp={\"defaultAction\":\"SCMP_ACT_ERRNO\"}".to_string(),
+                ][..]
+            ),
+            create_options.host_config().unwrap().security_opt()
+        );
+
+        let create_options = apply_security_profiles(ContainerCreateBody::new(), vec![]);
+        assert!(create_options.host_config().is_none());
+    }
+
+    #[test]
+    fn apply_root_fs_sets_read_only_root_and_tmpfs() {
+        let mut existing = HashMap::new();
+        existing.insert("/run".to_string(), "size=1024".to_string());
+        let create_options =
+            ContainerCreateBody::new().with_host_config(HostConfig::new().with_tmpfs(existing));
+        let tmpfs = vec![
+            ModuleTmpfs::new("/tmp".to_string()).with_size_bytes(64 * 1024 * 1024),
+            ModuleTmpfs::new("/var/cache".to_string()),
+        ];
+
+        let create_options = apply_root_fs(create_options, true, &tmpfs);
+
+        let host_config = create_options.host_config().unwrap();
+        assert_eq!(Some(&true), host_config.readonly_rootfs());
+        let tmpfs = host_config.tmpfs().unwrap();
+        assert_eq!(3, tmpfs.len());
+        assert_eq!("size=1024", tmpfs["/run"]);
+        assert_eq!("size=67108864", tmpfs["/tmp"]);
+        assert_eq!("", tmpfs["/var/cache"]);
+    }
+
+    #[test]
+    fn apply_stop_timeout_overrides_create_options() {
+        let create_options = ContainerCreateBody::new().with_stop_timeout(10);
+
+        assert_eq!(
+            Some(10),
+            apply_stop_timeout(create_options.clone(), None).stop_timeout()
+        );
+        assert_eq!(
+            Some(45),
+            apply_stop_timeout(create_options, Some(Duration::from_secs(45))).stop_timeout()
+        );
+    }
+
+    #[test]
+    fn apply_stop_signal_overrides_create_options() {
+        let create_options = ContainerCreateBody::new().with_stop_signal("SIGINT".to_string());
+
+        assert_eq!(
+            Some("SIGINT"),
+            apply_stop_signal(create_options.clone(), None).stop_signal()
+        );
+        assert_eq!(
+            Some("SIGQUIT"),
+            apply_stop_signal(create_options, Some("SIGQUIT")).stop_signal()
+        );
+    }
+
+    #[test]
+    fn has_command_of_image_or_create_options() {
+        let args = |args: &[&str]| args.iter().map(ToString::to_string).collect::<Vec<_>>();
+        let none = ContainerConfig::new();
+        let cmd = ContainerConfig::new().with_cmd(args(&["nginx"]));
+        let entrypoint = ContainerConfig::new().with_entrypoint(args(&["/init"]));
+
+        let create_options = ContainerCreateBody::new();
+        assert!(!has_command(&create_options, None));
+        assert!(!has_command(&create_options, Some(&none)));
+        assert!(has_command(&create_options, Some(&cmd)));
+        assert!(has_command(&create_options, Some(&entrypoint)));
+
+        let create_options = ContainerCreateBody::new().with_cmd(args(&["nginx"]));
+        assert!(has_command(&create_options, Some(&none)));
+
+        // An empty entrypoint resets the image's, and the image's command
+        // isn't run with an entrypoint of the create options.
+        let create_options = ContainerCreateBody::new().with_entrypoint(args(&[""]));
+        assert!(!has_command(&create_options, Some(&entrypoint)));
+        assert!(!has_command(&create_options, Some(&cmd)));
+        assert!(has_command(
+            &create_options.with_cmd(args(&["nginx"])),
+            Some(&none)
+        ));
+    }
+
+    #[test]
+    fn apply_user_overrides_create_options() {
+        let create_options = ContainerCreateBody::new()
+            .with_user("root".to_string())
+            .with_working_dir("/".to_string());
+
+        let unchanged = apply_user(create_options.clone(), None, None);
+        assert_eq!(Some("root"), unchanged.user());
+        assert_eq!(Some("/"), unchanged.working_dir());
+
+        let create_options = apply_user(create_options, Some("1000:1000"), Some("/app"));
+        assert_eq!(Some("1000:1000"), create_options.user());
+        assert_eq!(Some("/app"), create_options.working_dir());
+    }
+
+    #[test]
+    fn apply_default_create_options_merges_under_module() {
+        let defaults = json!({
+            "User": "1000",
+            "HostConfig": {
+                "CapDrop": ["ALL"],
+                "SecurityOpt": ["no-new-privileges"],
+            },
+        });
+        let create_options: ContainerCreateBody = serde_json::from_value(json!({
+            "User": "root",
+            "HostConfig": { "CapAdd": ["NET_ADMIN"], "Privileged": false },
+        }))
+        .unwrap();
+
+        let merged = apply_default_create_options(create_options, Some(&defaults)).unwrap();
+
+        assert_eq!(
+            json!({
+                "User": "root",
+                "HostConfig": {
+                    "CapAdd": ["NET_ADMIN"],
+                    "CapDrop": ["ALL"],
+                    "Privileged": false,
+                    "SecurityOpt": ["no-new-privileges"],
+                },
+            }),
+            serde_json::to_value(merged).unwrap()
+        );
+    }
+
+    #[test]
+    fn apply_root_fs_keeps_read_only_root_from_create_options() {
+        let create_options = ContainerCreateBody::new()
+            .with_host_config(HostConfig::new().with_readonly_rootfs(true));
+
+        let create_options = apply_root_fs(create_options, false, &[]);
+
+        assert_eq!(
+            Some(&true),
+            create_options.host_config().unwrap().readonly_rootfs()
+        );
+    }
+
+    #[test]
+    fn read_only_root_without_writable_mount_is_detected() {
+        let read_only = HostConfig::new().with_readonly_rootfs(true);
+        let container = InlineResponse200::new()
+            .with_host_config(read_only.clone())
+            .with_mounts(vec![MountPoint::new().with_RW(false)]);
+        assert!(read_only_without_writable_mount(&container));
+
+        let container = InlineResponse200::new()
+            .with_host_config(read_only.clone())
+            .with_mounts(vec![MountPoint::new().with_RW(true)]);
+        assert!(!read_only_without_writable_mount(&container));
+
+        let mut tmpfs = HashMap::new();
+        tmpfs.insert("/tmp".to_string(), String::new());
+        let container = InlineResponse200::new().with_host_config(read_only.with_tmpfs(tmpfs));
+        assert!(!read_only_without_writable_mount(&container));
+
+        let container = InlineResponse200::new().with_host_config(HostConfig::new());
+        assert!(!read_only_without_writable_mount(&container));
+    }
+
     #[derive(Clone)]
     struct TestConfig;
 
@@ -1345,6 +4783,10 @@ mod tests {
             unimplemented!()
         }
 
+        fn https_proxy(&self) -> Option<&str> {
+            unimplemented!()
+        }
+
         fn connect(&self) -> &Connect {
             unimplemented!()
         }
@@ -1364,6 +4806,38 @@ mod tests {
         fn watchdog(&self) -> &WatchdogSettings {
             unimplemented!()
         }
+
+        fn upstream(&self) -> &UpstreamSettings {
+            unimplemented!()
+        }
+
+        fn health(&self) -> &HealthSettings {
+            unimplemented!()
+        }
+
+        fn audit(&self) -> &AuditSettings {
+            unimplemented!()
+        }
+
+        fn logs(&self) -> &LogsSettings {
+            unimplemented!()
+        }
+
+        fn log_forwarding(&self) -> &LogForwardingSettings {
+            unimplemented!()
+        }
+
+        fn admission(&self) -> &AdmissionSettings {
+            unimplemented!()
+        }
+
+        fn webhook(&self) -> Option<&WebhookSettings> {
+            unimplemented!()
+        }
+
+        fn manifest_trust_key(&self) -> Option<&Path> {
+            unimplemented!()
+        }
     }
 
     #[derive(Clone, Copy, Debug, PartialEq)]
@@ -1418,6 +4892,7 @@ mod tests {
         type Error = Error;
         type PullFuture = FutureResult<(), Self::Error>;
         type RemoveFuture = FutureResult<(), Self::Error>;
+        type ImageExistsFuture = FutureResult<bool, Self::Error>;
         type Config = TestConfig;
 
         fn pull(&self, _config: &Self::Config) -> Self::PullFuture {
@@ -1427,6 +4902,10 @@ mod tests {
         fn remove(&self, _name: &str) -> Self::RemoveFuture {
             unimplemented!()
         }
+
+        fn image_exists(&self, _config: &Self::Config) -> Self::ImageExistsFuture {
+            unimplemented!()
+        }
     }
 
     impl DockerModuleTop for TestModule {
@@ -1484,15 +4963,27 @@ mod tests {
         type SystemResourcesFuture =
             Box<dyn Future<Item = SystemResources, Error = Self::Error> + Send>;
         type RemoveAllFuture = FutureResult<(), Self::Error>;
+        type StatsFuture = FutureResult<ModuleStats, Self::Error>;
+        type PruneImagesFuture = FutureResult<PrunedImages, Self::Error>;
+        type ExecStream = Empty<ExecOutput, Self::Error>;
+        type EffectiveSpecFuture = FutureResult<ModuleSpec<Self::Config>, Self::Error>;
 
         fn create(&self, _module: ModuleSpec<Self::Config>) -> Self::CreateFuture {
             unimplemented!()
         }
 
+        fn validate(&self, _module: &ModuleSpec<Self::Config>) -> Result<()> {
+            unimplemented!()
+        }
+
         fn get(&self, _id: &str) -> Self::GetFuture {
             unimplemented!()
         }
 
+        fn effective_spec(&self, _id: &str) -> Self::EffectiveSpecFuture {
+            unimplemented!()
+        }
+
         fn start(&self, _id: &str) -> Self::StartFuture {
             unimplemented!()
         }
@@ -1536,6 +5027,27 @@ mod tests {
         fn remove_all(&self) -> Self::RemoveAllFuture {
             unimplemented!()
         }
+
+        fn stats(&self, _id: &str) -> Self::StatsFuture {
+            unimplemented!()
+        }
+
+        fn prune_images(&self, _filter: ImagePruneFilter) -> Self::PruneImagesFuture {
+            unimplemented!()
+        }
+
+        fn exec(
+            &self,
+            _id: &str,
+            _cmd: Vec<String>,
+            _env: HashMap<String, String>,
+        ) -> Self::ExecStream {
+            unimplemented!()
+        }
+
+        fn events(&self) -> &ModuleEvents {
+            unimplemented!()
+        }
     }
 
     impl Authenticator for TestModuleList {
@@ -1,21 +1,28 @@
 // Copyright (c) Microsoft. All rights reserved.
 
 use std::collections::HashMap;
-use std::path::Path;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use config::{Config, Environment};
 use docker::models::{ContainerCreateBodyNetworkingConfig, EndpointSettings, HostConfig};
 use edgelet_core::{
-    Certificates, Connect, Listen, MobyNetwork, ModuleSpec, Provisioning, RuntimeSettings,
-    Settings as BaseSettings, UrlExt, WatchdogSettings,
+    migrate_settings, AdmissionSettings, AuditSettings, Certificates, Connect, HealthSettings,
+    Listen, LogForwardingSettings, LogsSettings, MobyNetwork, ModuleLogConfig, ModuleSpec,
+    Provisioning, RuntimeSettings, Settings as BaseSettings, UpstreamSettings, UrlExt,
+    WatchdogSettings, WebhookSettings,
 };
-use edgelet_utils::YamlFileSource;
+use edgelet_utils::{deserialize_duration, EnvOverrides, JsonValueSource, YamlFileSource};
 use failure::{Context, Fail, ResultExt};
-
+use log::info;
+use serde_json::{Map, Value};
 use url::Url;
 
+use crate::acr::AcrIdentity;
 use crate::config::DockerConfig;
 use crate::error::{Error, ErrorKind};
+use crate::network_policy::NetworkPolicy;
 
 #[cfg(unix)]
 pub const DEFAULTS: &str = include_str!("../config/unix/default.yaml");
@@ -23,6 +30,10 @@ pub const DEFAULTS: &str = include_str!("../config/unix/default.yaml");
 #[cfg(windows)]
 pub const DEFAULTS: &str = include_str!("../config/windows/default.yaml");
 
+/// The prefix of the environment variables that override single settings,
+/// such as `EDGELET__WATCHDOG__MAX_RETRIES`.
+pub const ENV_OVERRIDES_PREFIX: &str = "EDGELET";
+
 /// This is the key for the docker network Id.
 const EDGE_NETWORKID_KEY: &str = "NetworkId";
 
@@ -33,6 +44,106 @@ pub struct MobyRuntime {
     #[serde(with = "url_serde")]
     uri: Url,
     network: MobyNetwork,
+    #[serde(default)]
+    max_concurrent_pulls: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_pull_rate: Option<u64>,
+    #[serde(default, skip_serializing_if = "ModuleLogConfig::is_empty")]
+    log_config: ModuleLogConfig,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    strict_binds: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    strict_create_options: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    default_create_options: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    secrets_file: Option<PathBuf>,
+    #[serde(
+        default = "default_request_timeout",
+        deserialize_with = "deserialize_duration"
+    )]
+    request_timeout: Duration,
+    #[serde(default)]
+    request_retry: RequestRetry,
+    #[serde(default = "default_max_idle_connections")]
+    max_idle_connections: usize,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    credential_helpers: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    acr_auth: HashMap<String, AcrIdentity>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    dns: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    dns_search: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    create_networks: Option<bool>,
+    #[serde(default)]
+    network_policy: NetworkPolicy,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    injected_labels: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    global_env: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    container_name_prefix: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    module_name_aliases: Option<bool>,
+}
+
+fn default_request_timeout() -> Duration {
+    Duration::from_secs(120)
+}
+
+fn default_max_idle_connections() -> usize {
+    4
+}
+
+/// Controls how the requests to the container runtime that can safely be sent
+/// again are retried when they fail before the container runtime answers them.
+/// A request is sent at most `max_attempts` times, waiting `backoff` before the
+/// first retry and twice as long before each one after it.
+#[derive(Clone, Debug, PartialEq, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct RequestRetry {
+    #[serde(default = "default_request_retry_max_attempts")]
+    max_attempts: u32,
+    #[serde(
+        default = "default_request_retry_backoff",
+        deserialize_with = "deserialize_duration"
+    )]
+    backoff: Duration,
+}
+
+fn default_request_retry_max_attempts() -> u32 {
+    3
+}
+
+fn default_request_retry_backoff() -> Duration {
+    Duration::from_millis(250)
+}
+
+impl Default for RequestRetry {
+    fn default() -> Self {
+        RequestRetry {
+            max_attempts: default_request_retry_max_attempts(),
+            backoff: default_request_retry_backoff(),
+        }
+    }
+}
+
+impl RequestRetry {
+    pub fn new(max_attempts: u32, backoff: Duration) -> Self {
+        RequestRetry {
+            max_attempts,
+            backoff,
+        }
+    }
+
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    pub fn backoff(&self) -> Duration {
+        self.backoff
+    }
 }
 
 impl MobyRuntime {
@@ -43,6 +154,129 @@ impl MobyRuntime {
     pub fn network(&self) -> &MobyNetwork {
         &self.network
     }
+
+    /// The maximum number of image pulls that may run at once. Unlimited if
+    /// not set.
+    pub fn max_concurrent_pulls(&self) -> Option<usize> {
+        self.max_concurrent_pulls
+    }
+
+    /// The download rate in bytes per second that image pulls are capped at,
+    /// unless their module spec sets its own. Unlimited if not set.
+    pub fn max_pull_rate(&self) -> Option<u64> {
+        self.max_pull_rate
+    }
+
+    /// The log config of modules that don't have their own.
+    pub fn log_config(&self) -> &ModuleLogConfig {
+        &self.log_config
+    }
+
+    /// Whether modules are forbidden from binding sensitive host paths
+    /// writable. Off if not set.
+    pub fn strict_binds(&self) -> bool {
+        self.strict_binds.unwrap_or(false)
+    }
+
+    /// Whether modules whose create options have keys that aren't Docker
+    /// create options are rejected rather than only warned about. Off if not
+    /// set.
+    pub fn strict_create_options(&self) -> bool {
+        self.strict_create_options.unwrap_or(false)
+    }
+
+    /// The create options that the create options of every module are merged
+    /// over.
+    pub fn default_create_options(&self) -> Option<&serde_json::Value> {
+        self.default_create_options.as_ref()
+    }
+
+    /// The file that `${SECRET:name}` references in the environment variables
+    /// of modules are resolved from.
+    pub fn secrets_file(&self) -> Option<&Path> {
+        self.secrets_file.as_ref().map(AsRef::as_ref)
+    }
+
+    /// How long to wait for the container runtime to answer a request. Image
+    /// pulls and logs aren't limited.
+    pub fn request_timeout(&self) -> Duration {
+        self.request_timeout
+    }
+
+    /// How requests to the container runtime are retried when they fail
+    /// before it answers them.
+    pub fn request_retry(&self) -> &RequestRetry {
+        &self.request_retry
+    }
+
+    /// The number of idle connections to the container runtime that are kept
+    /// open to be reused.
+    pub fn max_idle_connections(&self) -> usize {
+        self.max_idle_connections
+    }
+
+    /// The credential helpers of registries by their host, whose credentials
+    /// are used instead of the ones in module specs.
+    pub fn credential_helpers(&self) -> &HashMap<String, String> {
+        &self.credential_helpers
+    }
+
+    /// The Azure AD identities that get tokens for Azure Container Registries
+    /// by their host, which are used instead of the credentials in module
+    /// specs.
+    pub fn acr_auth(&self) -> &HashMap<String, AcrIdentity> {
+        &self.acr_auth
+    }
+
+    /// The IP addresses of the DNS servers of modules that don't set their
+    /// own.
+    pub fn dns(&self) -> &[String] {
+        &self.dns
+    }
+
+    /// The DNS search domains of modules that don't set their own.
+    pub fn dns_search(&self) -> &[String] {
+        &self.dns_search
+    }
+
+    /// Whether the networks that modules join are created if they don't
+    /// exist, rather than failing the create of the module. Off if not set.
+    pub fn create_networks(&self) -> bool {
+        self.create_networks.unwrap_or(false)
+    }
+
+    /// The modules and addresses that modules may connect to.
+    pub fn network_policy(&self) -> &NetworkPolicy {
+        &self.network_policy
+    }
+
+    /// The labels that every module is created with, unless its module spec
+    /// or create options set a label with the same key.
+    pub fn injected_labels(&self) -> &HashMap<String, String> {
+        &self.injected_labels
+    }
+
+    /// The environment variables that every module is created with, unless
+    /// it sets a variable with the same name. Like those of modules, they can
+    /// refer to secrets of the secrets file.
+    pub fn global_env(&self) -> &HashMap<String, String> {
+        &self.global_env
+    }
+
+    /// The prefix of the names of the containers of modules, so that
+    /// containers that aren't the daemon's are never touched even if they are
+    /// named like modules. Containers are named after their modules if not
+    /// set.
+    pub fn container_name_prefix(&self) -> Option<&str> {
+        self.container_name_prefix.as_ref().map(AsRef::as_ref)
+    }
+
+    /// Whether every module is registered on the network above by its
+    /// module name, so that modules on it can reach each other by name
+    /// without setting aliases in their create options. On if not set.
+    pub fn module_name_aliases(&self) -> bool {
+        self.module_name_aliases.unwrap_or(true)
+    }
 }
 
 /// This struct is the same as the Settings type from the `edgelet_core` crate
@@ -61,8 +295,9 @@ impl Settings {
     pub fn new(filename: &Path) -> Result<Self, LoadSettingsError> {
         let mut config = Config::default();
         config.merge(YamlFileSource::String(DEFAULTS))?;
-        config.merge(YamlFileSource::File(filename.into()))?;
+        config.merge(read_settings_file(filename)?)?;
         config.merge(Environment::with_prefix("iotedge"))?;
+        config.merge(EnvOverrides::from_env(ENV_OVERRIDES_PREFIX))?;
 
         let mut settings: Self = config.try_into()?;
 
@@ -95,6 +330,10 @@ impl RuntimeSettings for Settings {
         self.base.hostname()
     }
 
+    fn https_proxy(&self) -> Option<&str> {
+        self.base.https_proxy()
+    }
+
     fn connect(&self) -> &Connect {
         self.base.connect()
     }
@@ -114,6 +353,149 @@ impl RuntimeSettings for Settings {
     fn watchdog(&self) -> &WatchdogSettings {
         self.base.watchdog()
     }
+
+    fn upstream(&self) -> &UpstreamSettings {
+        self.base.upstream()
+    }
+
+    fn health(&self) -> &HealthSettings {
+        self.base.health()
+    }
+
+    fn audit(&self) -> &AuditSettings {
+        self.base.audit()
+    }
+
+    fn logs(&self) -> &LogsSettings {
+        self.base.logs()
+    }
+
+    fn log_forwarding(&self) -> &LogForwardingSettings {
+        self.base.log_forwarding()
+    }
+
+    fn admission(&self) -> &AdmissionSettings {
+        self.base.admission()
+    }
+
+    fn webhook(&self) -> Option<&WebhookSettings> {
+        self.base.webhook()
+    }
+
+    fn manifest_trust_key(&self) -> Option<&Path> {
+        self.base.manifest_trust_key()
+    }
+}
+
+/// Reads the config at `filename`, migrating it from the schema version it
+/// was written for. Only the file is migrated, since the defaults are always
+/// of the current schema version.
+///
+/// A file with the `.toml` extension is TOML, and any other is YAML (or JSON,
+/// which is YAML too).
+///
+/// The files that the config names in its `include`, relative to the
+/// directory of the file that includes them, are merged over it in order, so
+/// that the later ones win. Tables are merged key by key, and any other value
+/// replaces the one before it. Included files can include others, but not the
+/// files that include them.
+pub fn read_settings_file(filename: &Path) -> Result<JsonValueSource, LoadSettingsError> {
+    // A file that can't be resolved fails to be read just below.
+    let path = fs::canonicalize(filename).unwrap_or_else(|_| filename.to_path_buf());
+    let mut settings = read_settings_with_includes(filename, path, &mut vec![])?;
+
+    for migration in migrate_settings(&mut settings)? {
+        info!(
+            "Migrated {} from schema version {}: {}",
+            filename.display(),
+            migration.from_version(),
+            migration.description()
+        );
+    }
+    Ok(JsonValueSource::new(filename, settings))
+}
+
+const SETTINGS_INCLUDE_KEY: &str = "include";
+
+// `including` are the files that include `filename`, which it can't include
+// in turn.
+fn read_settings_with_includes(
+    filename: &Path,
+    path: PathBuf,
+    including: &mut Vec<PathBuf>,
+) -> Result<Map<String, Value>, LoadSettingsError> {
+    if including.contains(&path) {
+        return Err(Error::from(ErrorKind::CircularSettingsInclude(
+            filename.display().to_string(),
+        ))
+        .into());
+    }
+
+    let mut settings = parse_settings_file(filename)?;
+    let includes = match settings.remove(SETTINGS_INCLUDE_KEY) {
+        None => vec![],
+        Some(Value::String(include)) => vec![include],
+        Some(Value::Array(includes)) => includes
+            .into_iter()
+            .map(|include| match include {
+                Value::String(include) => Ok(include),
+                _ => Err(Error::from(ErrorKind::InvalidSettingsInclude(
+                    filename.display().to_string(),
+                ))),
+            })
+            .collect::<std::result::Result<_, _>>()?,
+        Some(_) => {
+            return Err(Error::from(ErrorKind::InvalidSettingsInclude(
+                filename.display().to_string(),
+            ))
+            .into())
+        }
+    };
+
+    including.push(path);
+    let dir = filename.parent().unwrap_or_else(|| Path::new(""));
+    for include in includes {
+        let include = dir.join(include);
+        let include_path = fs::canonicalize(&include).with_context(|_| {
+            ErrorKind::ReadSettingsInclude(
+                include.display().to_string(),
+                filename.display().to_string(),
+            )
+        })?;
+        let overlay = read_settings_with_includes(&include, include_path, including)?;
+        merge_settings(&mut settings, overlay);
+    }
+    including.pop();
+
+    Ok(settings)
+}
+
+fn parse_settings_file(filename: &Path) -> Result<Map<String, Value>, LoadSettingsError> {
+    let is_toml = filename
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map_or(false, |extension| extension.eq_ignore_ascii_case("toml"));
+    let settings = if is_toml {
+        toml::from_str(&fs::read_to_string(filename)?)?
+    } else {
+        let mut file = Config::default();
+        file.merge(YamlFileSource::File(filename.into()))?;
+        file.try_into()?
+    };
+    Ok(settings)
+}
+
+fn merge_settings(settings: &mut Map<String, Value>, overlay: Map<String, Value>) {
+    for (key, value) in overlay {
+        let value = match (settings.get_mut(&key), value) {
+            (Some(Value::Object(table)), Value::Object(overlay)) => {
+                merge_settings(table, overlay);
+                continue;
+            }
+            (_, value) => value,
+        };
+        settings.insert(key, value);
+    }
 }
 
 fn init_agent_spec(settings: &mut Settings) -> Result<(), LoadSettingsError> {
@@ -229,12 +611,24 @@ impl From<config::ConfigError> for LoadSettingsError {
     }
 }
 
+impl From<toml::de::Error> for LoadSettingsError {
+    fn from(err: toml::de::Error) -> Self {
+        LoadSettingsError(Context::new(Box::new(err)))
+    }
+}
+
 impl From<serde_json::Error> for LoadSettingsError {
     fn from(err: serde_json::Error) -> Self {
         LoadSettingsError(Context::new(Box::new(err)))
     }
 }
 
+impl From<edgelet_core::Error> for LoadSettingsError {
+    fn from(err: edgelet_core::Error) -> Self {
+        LoadSettingsError(Context::new(Box::new(err)))
+    }
+}
+
 impl From<Error> for LoadSettingsError {
     fn from(err: Error) -> Self {
         LoadSettingsError(Context::new(Box::new(err)))
@@ -260,17 +654,21 @@ mod tests {
     use std::cmp::Ordering;
     use std::fs::File;
     use std::io::prelude::*;
+    use std::time::Duration;
 
     use serde_json::json;
     use tempdir::TempDir;
 
     use edgelet_core::{
-        AttestationMethod, IpamConfig, ManualAuthMethod, ProvisioningType, DEFAULT_NETWORKID,
+        AttestationMethod, DpsRetry, IpamConfig, ManualAuthMethod, ProvisioningType,
+        DEFAULT_NETWORKID,
     };
 
     #[cfg(unix)]
     static GOOD_SETTINGS: &str = "test/linux/sample_settings.yaml";
     #[cfg(unix)]
+    static GOOD_SETTINGS_TOML: &str = "test/linux/sample_settings.toml";
+    #[cfg(unix)]
     static GOOD_SETTINGS_MANUAL_CS_AUTH: &str = "test/linux/sample_settings.manual.auth.cs.yaml";
     #[cfg(unix)]
     static BAD_SETTINGS: &str = "test/linux/bad_sample_settings.yaml";
@@ -323,6 +721,8 @@ mod tests {
     #[cfg(unix)]
     static GOOD_SETTINGS_EXTERNAL2: &str = "test/linux/sample_settings.external.2.yaml";
     #[cfg(unix)]
+    static GOOD_SETTINGS_EXTERNAL3: &str = "test/linux/sample_settings.external.3.yaml";
+    #[cfg(unix)]
     static GOOD_SETTINGS_NETWORK: &str = "test/linux/sample_settings.network.yaml";
     #[cfg(unix)]
     static GOOD_SETTINGS_DYNAMIC_REPROVISIONING: &str = "test/linux/sample_settings.dyn.repro.yaml";
@@ -335,6 +735,8 @@ mod tests {
     #[cfg(windows)]
     static GOOD_SETTINGS: &str = "test/windows/sample_settings.yaml";
     #[cfg(windows)]
+    static GOOD_SETTINGS_TOML: &str = "test/windows/sample_settings.toml";
+    #[cfg(windows)]
     static GOOD_SETTINGS_MANUAL_CS_AUTH: &str = "test/windows/sample_settings.manual.auth.cs.yaml";
     #[cfg(windows)]
     static BAD_SETTINGS: &str = "test/windows/bad_sample_settings.yaml";
@@ -387,6 +789,8 @@ mod tests {
     #[cfg(windows)]
     static GOOD_SETTINGS_EXTERNAL2: &str = "test/windows/sample_settings.external.2.yaml";
     #[cfg(windows)]
+    static GOOD_SETTINGS_EXTERNAL3: &str = "test/windows/sample_settings.external.3.yaml";
+    #[cfg(windows)]
     static GOOD_SETTINGS_NETWORK: &str = "test/windows/sample_settings.network.yaml";
     #[cfg(windows)]
     static GOOD_SETTINGS_DYNAMIC_REPROVISIONING: &str =
@@ -411,18 +815,39 @@ mod tests {
         }
     }
 
+    fn moby_runtime(network: MobyNetwork) -> MobyRuntime {
+        MobyRuntime {
+            uri: Url::parse("http://test").unwrap(),
+            network,
+            max_concurrent_pulls: None,
+            max_pull_rate: None,
+            log_config: ModuleLogConfig::default(),
+            strict_binds: None,
+            strict_create_options: None,
+            default_create_options: None,
+            secrets_file: None,
+            request_timeout: default_request_timeout(),
+            request_retry: RequestRetry::default(),
+            max_idle_connections: default_max_idle_connections(),
+            credential_helpers: HashMap::new(),
+            acr_auth: HashMap::new(),
+            dns: Vec::new(),
+            dns_search: Vec::new(),
+            create_networks: None,
+            network_policy: NetworkPolicy::default(),
+            injected_labels: HashMap::new(),
+            global_env: HashMap::new(),
+            container_name_prefix: None,
+            module_name_aliases: None,
+        }
+    }
+
     #[test]
     fn network_default() {
-        let moby1 = MobyRuntime {
-            uri: Url::parse("http://test").unwrap(),
-            network: MobyNetwork::Name("".to_string()),
-        };
+        let moby1 = moby_runtime(MobyNetwork::Name("".to_string()));
         assert_eq!(DEFAULT_NETWORKID, moby1.network().name());
 
-        let moby2 = MobyRuntime {
-            uri: Url::parse("http://test").unwrap(),
-            network: MobyNetwork::Name("some-network".to_string()),
-        };
+        let moby2 = moby_runtime(MobyNetwork::Name("some-network".to_string()));
         assert_eq!("some-network", moby2.network().name());
     }
 
@@ -538,6 +963,81 @@ mod tests {
         );
     }
 
+    #[test]
+    fn toml_and_yaml_settings_are_the_same() {
+        let yaml = Settings::new(Path::new(GOOD_SETTINGS)).unwrap();
+        let toml = Settings::new(Path::new(GOOD_SETTINGS_TOML)).unwrap();
+
+        assert_eq!(
+            serde_json::to_value(&yaml).unwrap(),
+            serde_json::to_value(&toml).unwrap()
+        );
+    }
+
+    #[test]
+    fn malformed_toml_settings_fail() {
+        let tmp_dir = TempDir::new("blah").unwrap();
+        let settings_path = tmp_dir.path().join("config.toml");
+        std::fs::write(
+            &settings_path,
+            "hostname = \"localhost\"\nhostname = \"dup\"\n",
+        )
+        .unwrap();
+
+        assert!(Settings::new(&settings_path).is_err());
+    }
+
+    #[test]
+    fn settings_from_a_newer_release_fail() {
+        let tmp_dir = TempDir::new("blah").unwrap();
+        let settings_path = tmp_dir.path().join("config.yaml");
+        let mut contents = std::fs::read_to_string(GOOD_SETTINGS).unwrap();
+        contents.push_str("\nschema_version: 2\n");
+        std::fs::write(&settings_path, contents).unwrap();
+
+        let err = Settings::new(&settings_path).unwrap_err();
+        assert!((&err as &dyn Fail)
+            .iter_chain()
+            .any(|err| err.to_string().contains("has schema_version 2")));
+    }
+
+    #[test]
+    fn included_settings_are_merged_over_the_config() {
+        let tmp_dir = TempDir::new("blah").unwrap();
+        let settings_path = tmp_dir.path().join("config.yaml");
+        let mut contents = std::fs::read_to_string(GOOD_SETTINGS).unwrap();
+        contents.push_str("\ninclude: overlay.yaml\n");
+        std::fs::write(&settings_path, contents).unwrap();
+        std::fs::write(
+            tmp_dir.path().join("overlay.yaml"),
+            "hostname: \"overlay\"\nmoby_runtime:\n  max_idle_connections: 8\n",
+        )
+        .unwrap();
+
+        let settings = Settings::new(&settings_path).unwrap();
+        assert_eq!("overlay", settings.hostname());
+        assert_eq!(8, settings.moby_runtime().max_idle_connections());
+        assert_eq!(
+            "http://localhost:2375/",
+            settings.moby_runtime().uri().as_str()
+        );
+    }
+
+    #[test]
+    fn circular_includes_fail() {
+        let tmp_dir = TempDir::new("blah").unwrap();
+        let settings_path = tmp_dir.path().join("a.yaml");
+        let mut contents = std::fs::read_to_string(GOOD_SETTINGS).unwrap();
+        contents.push_str("\ninclude: b.yaml\n");
+        std::fs::write(&settings_path, contents).unwrap();
+        std::fs::write(tmp_dir.path().join("b.yaml"), "include: [a.yaml]\n").unwrap();
+
+        let err = Settings::new(&settings_path).unwrap_err();
+        assert!((&err as &dyn Fail)
+            .iter_chain()
+            .any(|err| err.to_string().contains("includes itself")));
+    }
+
     #[test]
     fn manual_authentication_connection_string() {
         let settings = Settings::new(Path::new(GOOD_SETTINGS_MANUAL_CS_AUTH));
@@ -820,6 +1320,7 @@ mod tests {
                     }
                     _ => unreachable!(),
                 }
+                assert_eq!(&DpsRetry::default(), dps.retry());
             }
             _ => unreachable!(),
         };
@@ -867,6 +1368,8 @@ mod tests {
                     }
                     _ => unreachable!(),
                 }
+                assert_eq!(5, dps.retry().max_attempts());
+                assert_eq!(Duration::from_secs(120), dps.retry().max_elapsed());
             }
             _ => unreachable!(),
         };
@@ -1002,7 +1505,10 @@ mod tests {
         assert_eq!(s.provisioning().dynamic_reprovisioning(), false);
         match s.provisioning().provisioning_type() {
             ProvisioningType::External(ref external) => {
-                assert_eq!(external.endpoint().as_str(), "http://localhost:9999/");
+                assert_eq!(
+                    external.endpoint().unwrap().as_str(),
+                    "http://localhost:9999/"
+                );
             }
             _ => unreachable!(),
         };
@@ -1018,7 +1524,35 @@ mod tests {
 
         match s.provisioning().provisioning_type() {
             ProvisioningType::External(ref external) => {
-                assert_eq!(external.endpoint().as_str(), "http://localhost:9999/");
+                assert_eq!(
+                    external.endpoint().unwrap().as_str(),
+                    "http://localhost:9999/"
+                );
+            }
+            _ => unreachable!(),
+        };
+    }
+
+    #[test]
+    fn external_prov_get_settings_with_command() {
+        let settings = Settings::new(Path::new(GOOD_SETTINGS_EXTERNAL3));
+        println!("{:?}", settings);
+        assert!(settings.is_ok());
+        let s = settings.unwrap();
+
+        match s.provisioning().provisioning_type() {
+            ProvisioningType::External(ref external) => {
+                assert!(external.endpoint().is_none());
+                let command = external.command().unwrap();
+                #[cfg(unix)]
+                assert_eq!(Path::new("/usr/bin/attest"), command.program());
+                #[cfg(windows)]
+                assert_eq!(Path::new("C:\\attest\\attest.exe"), command.program());
+                assert_eq!(
+                    &["--format".to_string(), "json".to_string()],
+                    command.args()
+                );
+                assert_eq!(Duration::from_secs(10), command.timeout());
             }
             _ => unreachable!(),
         };
@@ -1060,6 +1594,8 @@ mod tests {
         let s = settings.unwrap();
         let watchdog_settings = s.watchdog();
         assert_eq!(watchdog_settings.max_retries().compare(3), Ordering::Equal);
+        assert_eq!(1, watchdog_settings.reconcile_parallelism());
+        assert!(!watchdog_settings.adopt_existing_modules());
     }
 
     #[test]
@@ -1080,6 +1616,101 @@ mod tests {
         );
     }
 
+    #[test]
+    fn metrics_settings_are_read() {
+        let settings = Settings::new(Path::new(GOOD_SETTINGS_TLS)).unwrap();
+        assert!(settings.listen().enable_metrics());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn management_socket_group_is_read() {
+        let settings = Settings::new(Path::new(GOOD_SETTINGS_TLS)).unwrap();
+        assert_eq!(Some("iotedge"), settings.listen().management_socket_group());
+
+        let settings = Settings::new(Path::new(GOOD_SETTINGS)).unwrap();
+        assert_eq!(None, settings.listen().management_socket_group());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn management_allowed_peers_are_read() {
+        let settings = Settings::new(Path::new(GOOD_SETTINGS_TLS)).unwrap();
+        assert_eq!(
+            &["root".to_string()],
+            settings.listen().management_allowed_users()
+        );
+        assert_eq!(
+            &["iotedge".to_string()],
+            settings.listen().management_allowed_groups()
+        );
+
+        let settings = Settings::new(Path::new(GOOD_SETTINGS)).unwrap();
+        assert!(settings.listen().management_allowed_users().is_empty());
+        assert!(settings.listen().management_allowed_groups().is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn management_tls_is_read() {
+        let settings = Settings::new(Path::new(GOOD_SETTINGS_TLS)).unwrap();
+        let tls = settings.listen().management_tls().unwrap();
+        assert_eq!(Path::new("/etc/iotedge/mgmt/server.pem"), tls.server_cert());
+        assert_eq!(
+            Path::new("/etc/iotedge/mgmt/server.key.pem"),
+            tls.server_key()
+        );
+        assert_eq!(
+            Path::new("/etc/iotedge/mgmt/client-ca.pem"),
+            tls.client_ca()
+        );
+        assert_eq!(
+            Some(Path::new("/etc/iotedge/mgmt/client-ca.crl")),
+            tls.crl_file()
+        );
+        let ocsp = tls.ocsp().unwrap();
+        assert_eq!("http://ocsp.contoso.com/", ocsp.responder_uri().as_str());
+        assert_eq!(
+            edgelet_core::OcspFailurePolicy::FailOpen,
+            ocsp.on_unreachable()
+        );
+        assert_eq!(Duration::from_secs(5), ocsp.timeout());
+
+        let settings = Settings::new(Path::new(GOOD_SETTINGS)).unwrap();
+        assert!(settings.listen().management_tls().is_none());
+    }
+
+    #[test]
+    fn metrics_are_disabled_by_default() {
+        let settings = Settings::new(Path::new(GOOD_SETTINGS)).unwrap();
+        assert!(!settings.listen().enable_metrics());
+    }
+
+    #[test]
+    fn exec_is_disabled_by_default() {
+        let settings = Settings::new(Path::new(GOOD_SETTINGS)).unwrap();
+        assert!(!settings.listen().enable_exec());
+
+        let settings = Settings::new(Path::new(GOOD_SETTINGS_TLS)).unwrap();
+        assert!(settings.listen().enable_exec());
+    }
+
+    #[test]
+    fn docker_client_defaults() {
+        let settings = Settings::new(Path::new(GOOD_SETTINGS)).unwrap();
+        assert_eq!(
+            Duration::from_secs(120),
+            settings.moby_runtime().request_timeout()
+        );
+        assert_eq!(4, settings.moby_runtime().max_idle_connections());
+        assert_eq!(
+            &RequestRetry::default(),
+            settings.moby_runtime().request_retry()
+        );
+        assert_eq!(3, settings.moby_runtime().request_retry().max_attempts());
+        assert_eq!(None, settings.moby_runtime().container_name_prefix());
+    }
+
     #[test]
     fn networking_config_is_set() {
         let settings = Settings::new(Path::new(GOOD_SETTINGS)).unwrap();
@@ -0,0 +1,278 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+use chrono::{DateTime, Datelike, Utc};
+use failure::ResultExt;
+use serde_derive::Deserialize;
+use serde_json;
+
+use edgelet_core::{ModuleStats, RuntimeOperation};
+
+use crate::error::{Error, ErrorKind, Result};
+
+// The subset of the response to `GET /containers/{id}/stats?stream=false`
+// that module stats are computed from. Linux containers report CPU usage in
+// nanoseconds against the host's `system_cpu_usage`, while Windows containers
+// report it in 100ns intervals and only have the time between the samples to
+// compare it with.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct DockerStats {
+    read: Option<DateTime<Utc>>,
+    preread: Option<DateTime<Utc>>,
+    num_procs: u32,
+    cpu_stats: CpuStats,
+    precpu_stats: CpuStats,
+    memory_stats: MemoryStats,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct CpuStats {
+    cpu_usage: CpuUsage,
+    system_cpu_usage: Option<u64>,
+    online_cpus: Option<u32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct CpuUsage {
+    total_usage: u64,
+    percpu_usage: Option<Vec<u64>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct MemoryStats {
+    usage: Option<u64>,
+    limit: Option<u64>,
+    privateworkingset: Option<u64>,
+    stats: HashMap<String, u64>,
+}
+
+/// Computes the stats of module `name` from the container's stats, failing
+/// with `ErrorKind::NotRunning` if the container isn't running. Docker
+/// reports a stopped container with a zero read time and no memory usage.
+pub(crate) fn parse_stats(name: &str, stats: serde_json::Value) -> Result<ModuleStats> {
+    let stats: DockerStats = serde_json::from_value(stats).context(ErrorKind::RuntimeOperation(
+        RuntimeOperation::GetModuleStats(name.to_string()),
+    ))?;
+
+    let running = stats.read.map_or(false, |read| read.year() > 1);
+    let memory_usage = match (
+        stats.memory_stats.usage,
+        stats.memory_stats.privateworkingset,
+    ) {
+        (Some(usage), _) if running => {
+            // Like `docker stats`, page cache that can be reclaimed doesn't
+            // count as used. The key depends on the cgroup version.
+            let inactive = stats
+                .memory_stats
+                .stats
+                .get("total_inactive_file")
+                .or_else(|| stats.memory_stats.stats.get("inactive_file"))
+                .copied()
+                .unwrap_or_default();
+            usage.saturating_sub(inactive)
+        }
+        (None, Some(private_working_set)) if running => private_working_set,
+        _ => return Err(Error::from(ErrorKind::NotRunning(name.to_string()))),
+    };
+
+    let cpu_percent = if stats.cpu_stats.system_cpu_usage.is_some() {
+        linux_cpu_percent(&stats)
+    } else {
+        windows_cpu_percent(&stats)
+    };
+
+    Ok(ModuleStats::new(
+        name.to_string(),
+        cpu_percent,
+        memory_usage,
+        stats.memory_stats.limit.unwrap_or_default(),
+    ))
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn linux_cpu_percent(stats: &DockerStats) -> f64 {
+    let cpu_delta = stats
+        .cpu_stats
+        .cpu_usage
+        .total_usage
+        .saturating_sub(stats.precpu_stats.cpu_usage.total_usage);
+    let system_delta = stats
+        .cpu_stats
+        .system_cpu_usage
+        .unwrap_or_default()
+        .saturating_sub(stats.precpu_stats.system_cpu_usage.unwrap_or_default());
+    let online_cpus = stats
+        .cpu_stats
+        .online_cpus
+        .filter(|cpus| *cpus > 0)
+        .or_else(|| {
+            stats
+                .cpu_stats
+                .cpu_usage
+                .percpu_usage
+                .as_ref()
+                .and_then(|usage| u32::try_from(usage.len()).ok())
+        })
+        .unwrap_or(1);
+
+    if cpu_delta == 0 || system_delta == 0 {
+        0.0
+    } else {
+        cpu_delta as f64 / system_delta as f64 * f64::from(online_cpus) * 100.0
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn windows_cpu_percent(stats: &DockerStats) -> f64 {
+    let interval = match (stats.read, stats.preread) {
+        (Some(read), Some(preread)) if preread.year() > 1 => read - preread,
+        _ => return 0.0,
+    };
+    let possible_intervals = interval
+        .num_nanoseconds()
+        .filter(|nanos| *nanos > 0)
+        .map_or(0.0, |nanos| {
+            nanos as f64 / 100.0 * f64::from(stats.num_procs)
+        });
+    let used_intervals = stats
+        .cpu_stats
+        .cpu_usage
+        .total_usage
+        .saturating_sub(stats.precpu_stats.cpu_usage.total_usage);
+
+    if possible_intervals > 0.0 {
+        used_intervals as f64 / possible_intervals * 100.0
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Captured from `GET /containers/tempSensor/stats?stream=false` on a
+    // four CPU Linux host, trimmed to the fields that were not empty.
+    const LINUX_STATS: &str = r#"{
+        "read": "2019-11-28T23:04:12.544517666Z",
+        "preread": "2019-11-28T23:04:11.540779332Z",
+        "pids_stats": { "current": 6 },
+        "num_procs": 0,
+        "cpu_stats": {
+            "cpu_usage": {
+                "total_usage": 1215176186,
+                "percpu_usage": [292017545, 314886275, 311634329, 296638037],
+                "usage_in_kernelmode": 210000000,
+                "usage_in_usermode": 980000000
+            },
+            "system_cpu_usage": 389643020000000,
+            "online_cpus": 4,
+            "throttling_data": { "periods": 0, "throttled_periods": 0, "throttled_time": 0 }
+        },
+        "precpu_stats": {
+            "cpu_usage": {
+                "total_usage": 1195176186,
+                "percpu_usage": [287017545, 309886275, 306634329, 291638037],
+                "usage_in_kernelmode": 210000000,
+                "usage_in_usermode": 960000000
+            },
+            "system_cpu_usage": 389639020000000,
+            "online_cpus": 4,
+            "throttling_data": { "periods": 0, "throttled_periods": 0, "throttled_time": 0 }
+        },
+        "memory_stats": {
+            "usage": 12496896,
+            "max_usage": 14381056,
+            "stats": {
+                "active_anon": 7294976,
+                "cache": 2457600,
+                "rss": 7294976,
+                "total_inactive_file": 2097152
+            },
+            "limit": 2085855232
+        },
+        "name": "/tempSensor",
+        "id": "1a5f5cd0e7fee5bb9f3bf8fa0bdc777f4f8c2d2e271fc9d264593f22fd7e3ac1"
+    }"#;
+
+    // What Docker returns for a container that has stopped.
+    const STOPPED_STATS: &str = r#"{
+        "read": "0001-01-01T00:00:00Z",
+        "preread": "0001-01-01T00:00:00Z",
+        "pids_stats": {},
+        "num_procs": 0,
+        "cpu_stats": { "cpu_usage": { "total_usage": 0 }, "throttling_data": {} },
+        "precpu_stats": { "cpu_usage": { "total_usage": 0 }, "throttling_data": {} },
+        "memory_stats": {},
+        "name": "/tempSensor",
+        "id": "1a5f5cd0e7fee5bb9f3bf8fa0bdc777f4f8c2d2e271fc9d264593f22fd7e3ac1"
+    }"#;
+
+    // Captured from a process isolated Windows container on a two CPU host.
+    const WINDOWS_STATS: &str = r#"{
+        "read": "2019-11-28T23:10:07.8534581Z",
+        "preread": "2019-11-28T23:10:06.8503544Z",
+        "num_procs": 2,
+        "cpu_stats": {
+            "cpu_usage": {
+                "total_usage": 28125000,
+                "usage_in_kernelmode": 10625000,
+                "usage_in_usermode": 17500000
+            }
+        },
+        "precpu_stats": {
+            "cpu_usage": {
+                "total_usage": 27968750,
+                "usage_in_kernelmode": 10625000,
+                "usage_in_usermode": 17343750
+            }
+        },
+        "memory_stats": {
+            "commitbytes": 41877504,
+            "commitpeakbytes": 47648768,
+            "privateworkingset": 33820672
+        },
+        "name": "/tempSensor",
+        "id": "a39e8d0e6fd6b2b6e0c9af4d2bcd5b3b5c3c7e3352a4dd7dc882d5e1d2fa7e60"
+    }"#;
+
+    #[test]
+    fn parses_linux_stats() {
+        let stats = parse_stats("tempSensor", serde_json::from_str(LINUX_STATS).unwrap()).unwrap();
+
+        assert_eq!("tempSensor", stats.name());
+        // 20ms of CPU time out of 4s of system time on four CPUs
+        assert!((stats.cpu_percent() - 2.0).abs() < 1e-9);
+        assert_eq!(12_496_896 - 2_097_152, stats.memory_usage());
+        assert_eq!(2_085_855_232, stats.memory_limit());
+    }
+
+    #[test]
+    fn parses_windows_stats() {
+        let stats =
+            parse_stats("tempSensor", serde_json::from_str(WINDOWS_STATS).unwrap()).unwrap();
+
+        // 156250 intervals used out of about 20 million on two CPUs
+        assert!((stats.cpu_percent() - 0.778_8).abs() < 1e-3);
+        assert_eq!(33_820_672, stats.memory_usage());
+        assert_eq!(0, stats.memory_limit());
+    }
+
+    #[test]
+    fn stopped_container_is_not_running() {
+        let err =
+            parse_stats("tempSensor", serde_json::from_str(STOPPED_STATS).unwrap()).unwrap_err();
+
+        match err.kind() {
+            ErrorKind::NotRunning(name) => assert_eq!("tempSensor", name),
+            kind => panic!("Expected `NotRunning` but got {:?}", kind),
+        }
+        assert_eq!("Module tempSensor is not running", err.to_string());
+    }
+}
@@ -0,0 +1,712 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Images whose tag is a pattern, like `myimage:1.*`, resolved to the highest
+//! version that their registry has a matching tag for.
+//!
+//! A pattern is a version whose components are numbers or `*`, optionally
+//! followed by a suffix like `-linux-amd64` that matching tags must have too.
+//! A `*` matches any number in its place, and a `*` at the end also matches
+//! any components after it, so `1.*` matches `1.0` and `1.2.3` but not `1` or
+//! `1.0-rc1`.
+//!
+//! A pattern is resolved once, to the digest of the tag it matched, and the
+//! resolution is saved so that the module keeps running that digest across
+//! deployments and restarts even when newer matching tags are pushed. Only a
+//! deployment with a different pattern resolves again.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use failure::{Fail, ResultExt};
+use futures::future::{self, Either};
+use futures::{Future, Stream};
+use hyper::header::{HeaderMap, ACCEPT, AUTHORIZATION, WWW_AUTHENTICATE};
+use hyper::{Body, Chunk, Method, Request, Response, StatusCode};
+use log::{info, warn, Level};
+use url::form_urlencoded;
+
+use docker::models::AuthConfig;
+use edgelet_http::client::ClientImpl;
+use edgelet_http::MaybeProxyClient;
+use edgelet_utils::log_failure;
+
+use crate::acr::{https_client, registry_url, repository};
+use crate::credentials::{registry_host, DEFAULT_REGISTRY};
+use crate::error::{Error, ErrorKind};
+
+const DOCKER_HUB_REGISTRY_URL: &str = "https://registry-1.docker.io";
+const DIGEST_HEADER: &str = "docker-content-digest";
+
+// Multi-platform images are pinned to their manifest list, so that Docker
+// still picks the platform of the device when it pulls the digest.
+const MANIFEST_TYPES: &str = "application/vnd.docker.distribution.manifest.list.v2+json, \
+                              application/vnd.docker.distribution.manifest.v2+json, \
+                              application/vnd.oci.image.index.v1+json, \
+                              application/vnd.oci.image.manifest.v1+json";
+
+/// Whether the tag of `image` is a pattern. An image that is pinned to a
+/// digest isn't resolved, whatever its tag.
+pub fn is_tag_pattern(image: &str) -> bool {
+    split_tag(image).map_or(false, |(_, tag)| tag.contains('*'))
+}
+
+/// The tag among `tags` with the highest version that matches `pattern`.
+pub fn select_tag<'a>(pattern: &str, tags: &'a [String]) -> Option<&'a str> {
+    let pattern = Version::parse(pattern)?;
+    tags.iter()
+        .filter_map(|tag| {
+            let version = Version::parse(tag)?;
+            if pattern.matches(&version) {
+                Some((version.numbers()?, tag.as_str()))
+            } else {
+                None
+            }
+        })
+        .max()
+        .map(|(_, tag)| tag)
+}
+
+#[derive(Clone)]
+pub struct TagResolver {
+    client: MaybeProxyClient,
+    path: Option<Arc<PathBuf>>,
+    resolved: Arc<Mutex<HashMap<String, String>>>,
+}
+
+impl TagResolver {
+    /// Resolutions are saved to `path`, if any, and the ones saved there
+    /// before are loaded. Requests go through `https_proxy`, or else the
+    /// proxy in `HTTPS_PROXY`, if any.
+    pub fn new(path: Option<PathBuf>, https_proxy: Option<&str>) -> Result<Self, Error> {
+        let resolved = path.as_ref().map_or_else(HashMap::new, |path| load(path));
+        Ok(TagResolver {
+            client: https_client(https_proxy)?,
+            path: path.map(Arc::new),
+            resolved: Arc::new(Mutex::new(resolved)),
+        })
+    }
+
+    /// The image that `image` was resolved to, if it was.
+    pub fn resolved(&self, image: &str) -> Option<String> {
+        self.resolved
+            .lock()
+            .expect("resolved images lock poisoned")
+            .get(image)
+            .cloned()
+    }
+
+    /// `image` pinned to the digest of the highest tag that matches its
+    /// pattern, like `myimage:1.2.3@sha256:...`. Images whose tag isn't a
+    /// pattern are returned as they are.
+    pub fn resolve(
+        &self,
+        image: &str,
+        auth: Option<AuthConfig>,
+    ) -> impl Future<Item = String, Error = Error> + Send {
+        if let Some(resolved) = self.resolved(image) {
+            return Either::A(future::ok(resolved));
+        }
+        let (name, pattern) = match split_tag(image) {
+            Some((name, pattern)) if pattern.contains('*') => {
+                (name.to_string(), pattern.to_string())
+            }
+            _ => return Either::A(future::ok(image.to_string())),
+        };
+
+        info!("Resolving the tag of image {}...", image);
+
+        let registry = Registry::new(self.client.clone(), image, auth);
+        let tags_uri = format!("{}/v2/{}/tags/list", registry.base, registry.repository);
+        let list_uri = tags_uri.clone();
+        let manifests = registry.clone();
+        let resolver = self.clone();
+        let image = image.to_string();
+        let context_image = image.clone();
+
+        let resolved = registry
+            .request(Method::GET, tags_uri)
+            .and_then(move |(_, body)| -> Result<_, Error> {
+                let tags: TagList =
+                    serde_json::from_slice(&body).context(ErrorKind::RegistryRequest(list_uri))?;
+                let tags = tags.tags.unwrap_or_default();
+                let tag = select_tag(&pattern, &tags)
+                    .ok_or_else(|| ErrorKind::NoMatchingTag(name.clone(), pattern))?
+                    .to_string();
+                Ok((name, tag))
+            })
+            .and_then(move |(name, tag)| {
+                let uri = format!(
+                    "{}/v2/{}/manifests/{}",
+                    manifests.base, manifests.repository, tag
+                );
+                manifests.request(Method::HEAD, uri).and_then(
+                    move |(headers, _)| -> Result<_, Error> {
+                        let image = format!("{}:{}", name, tag);
+                        let digest = headers
+                            .get(DIGEST_HEADER)
+                            .and_then(|digest| digest.to_str().ok())
+                            .ok_or_else(|| ErrorKind::MissingDigest(image.clone()))?;
+                        Ok(format!("{}@{}", image, digest))
+                    },
+                )
+            })
+            .map(move |resolved| {
+                info!("Resolved image {} to {}", image, resolved);
+                resolver.record(image, resolved.clone());
+                resolved
+            })
+            .map_err(move |err| Error::from(err.context(ErrorKind::ResolveTag(context_image))));
+
+        Either::B(resolved)
+    }
+
+    // A resolution that can't be saved is only logged, since it only costs
+    // resolving the pattern again after a restart.
+    fn record(&self, image: String, resolved: String) {
+        let mut images = self.resolved.lock().expect("resolved images lock poisoned");
+        images.insert(image, resolved);
+
+        if let Some(path) = &self.path {
+            if let Err(err) = save(path, &images) {
+                warn!("Could not save the resolved images:");
+                log_failure(Level::Warn, &err);
+            }
+        }
+    }
+}
+
+// A file that can't be read or parsed is logged and treated as missing, so
+// that the patterns are resolved again.
+fn load(path: &Path) -> HashMap<String, String> {
+    let display = path.display().to_string();
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => return HashMap::new(),
+        Err(err) => {
+            let err = Error::from(err.context(ErrorKind::ResolvedImages(display)));
+            log_failure(Level::Warn, &err);
+            return HashMap::new();
+        }
+    };
+
+    serde_json::from_str(&contents).unwrap_or_else(|err| {
+        let err = Error::from(err.context(ErrorKind::ResolvedImages(display)));
+        log_failure(Level::Warn, &err);
+        HashMap::new()
+    })
+}
+
+// The file is written next to it first and renamed over it, so that a crash
+// never leaves a partially written one behind.
+fn save(path: &Path, images: &HashMap<String, String>) -> Result<(), Error> {
+    let display = path.display().to_string();
+    let contents = serde_json::to_string(images)
+        .with_context(|_| ErrorKind::ResolvedImages(display.clone()))?;
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    fs::write(&temp, contents).with_context(|_| ErrorKind::ResolvedImages(display.clone()))?;
+    fs::rename(&temp, path).with_context(|_| ErrorKind::ResolvedImages(display))?;
+    Ok(())
+}
+
+// The name and tag of `image`, if it has a tag and isn't pinned to a digest.
+fn split_tag(image: &str) -> Option<(&str, &str)> {
+    if image.contains('@') {
+        return None;
+    }
+    // A ':' after the last '/' separates the tag; any earlier one is a registry port.
+    let path_start = image.rfind('/').map_or(0, |i| i + 1);
+    image[path_start..]
+        .find(':')
+        .map(|i| (&image[..path_start + i], &image[path_start + i + 1..]))
+}
+
+#[derive(serde_derive::Deserialize)]
+struct TagList {
+    #[serde(default)]
+    tags: Option<Vec<String>>,
+}
+
+#[derive(serde_derive::Deserialize)]
+struct TokenResponse {
+    #[serde(default)]
+    token: Option<String>,
+    #[serde(default)]
+    access_token: Option<String>,
+}
+
+// A tag or pattern split into its version components and its suffix. The
+// components of a pattern can be `*`.
+#[derive(Debug, PartialEq)]
+struct Version<'a> {
+    components: Vec<&'a str>,
+    suffix: Option<&'a str>,
+}
+
+impl<'a> Version<'a> {
+    fn parse(tag: &'a str) -> Option<Self> {
+        let (version, suffix) = match tag.find('-') {
+            Some(i) => (&tag[..i], Some(&tag[i + 1..])),
+            None => (tag, None),
+        };
+        let components: Vec<_> = version.split('.').collect();
+        let valid = components.iter().all(|component| {
+            *component == "*"
+                || (!component.is_empty() && component.chars().all(|c| c.is_ascii_digit()))
+        });
+        if valid {
+            Some(Version { components, suffix })
+        } else {
+            None
+        }
+    }
+
+    fn numbers(&self) -> Option<Vec<u64>> {
+        self.components
+            .iter()
+            .map(|component| component.parse().ok())
+            .collect()
+    }
+
+    fn matches(&self, tag: &Version<'_>) -> bool {
+        if self.suffix != tag.suffix || tag.numbers().is_none() {
+            return false;
+        }
+
+        let last = self.components.len() - 1;
+        for (i, component) in self.components.iter().enumerate() {
+            match tag.components.get(i) {
+                None => return false,
+                Some(_) if *component == "*" && i == last => return true,
+                Some(_) if *component == "*" => (),
+                Some(number) if number.parse::<u64>().ok() == component.parse().ok() => (),
+                Some(_) => return false,
+            }
+        }
+        self.components.len() == tag.components.len()
+    }
+}
+
+#[derive(Clone)]
+struct Registry {
+    client: MaybeProxyClient,
+    base: String,
+    repository: String,
+    auth: Option<AuthConfig>,
+}
+
+impl Registry {
+    fn new(client: MaybeProxyClient, image: &str, auth: Option<AuthConfig>) -> Self {
+        let registry = registry_host(image);
+        let repository = repository(image);
+        let (base, repository) = if registry == DEFAULT_REGISTRY {
+            // Official images are in the library of Docker Hub.
+            let repository = if repository.contains('/') {
+                repository.to_string()
+            } else {
+                format!("library/{}", repository)
+            };
+            (DOCKER_HUB_REGISTRY_URL.to_string(), repository)
+        } else {
+            (registry_url(registry), repository.to_string())
+        };
+
+        Registry {
+            client,
+            base,
+            repository,
+            auth,
+        }
+    }
+
+    // Sends a request to the registry, and if the registry asks for
+    // credentials, sends it again with them.
+    fn request(
+        &self,
+        method: Method,
+        uri: String,
+    ) -> impl Future<Item = (HeaderMap, Chunk), Error = Error> + Send {
+        let token = self
+            .auth
+            .as_ref()
+            .and_then(AuthConfig::registrytoken)
+            .map(|token| format!("Bearer {}", token));
+        let has_token = token.is_some();
+        let registry = self.clone();
+
+        send(
+            &self.client,
+            method.clone(),
+            &uri,
+            token.as_ref().map(String::as_str),
+        )
+        .and_then(move |response| {
+            if response.status() != StatusCode::UNAUTHORIZED || has_token {
+                return Either::A(finish(uri, response));
+            }
+
+            let challenge = response
+                .headers()
+                .get(WWW_AUTHENTICATE)
+                .and_then(|challenge| challenge.to_str().ok())
+                .map(ToString::to_string);
+            let client = registry.client.clone();
+            Either::B(
+                registry
+                    .authorization(challenge, &uri)
+                    .and_then(move |authorization| {
+                        send(&client, method, &uri, Some(&authorization))
+                            .and_then(move |response| finish(uri, response))
+                    }),
+            )
+        })
+    }
+
+    // The `Authorization` header that answers the `WWW-Authenticate`
+    // challenge of the registry. A bearer challenge is answered with a token
+    // from the realm that it names, which registries like Docker Hub give out
+    // even without credentials.
+    fn authorization(
+        &self,
+        challenge: Option<String>,
+        uri: &str,
+    ) -> impl Future<Item = String, Error = Error> + Send {
+        let basic = self
+            .auth
+            .as_ref()
+            .and_then(|auth| match (auth.username(), auth.password()) {
+                (Some(username), Some(password)) => Some(format!(
+                    "Basic {}",
+                    base64::encode(&format!("{}:{}", username, password))
+                )),
+                _ => None,
+            });
+        let unauthorized = || {
+            Error::from(ErrorKind::RegistryRequestFailed(
+                uri.to_string(),
+                StatusCode::UNAUTHORIZED,
+            ))
+        };
+
+        let challenge = match challenge {
+            Some(challenge) => challenge,
+            None => return Either::A(future::err(unauthorized())),
+        };
+        if challenge.starts_with("Basic") {
+            return Either::A(future::result(basic.ok_or_else(unauthorized)));
+        }
+        let params = match bearer_params(&challenge) {
+            Some(params) => params,
+            None => return Either::A(future::err(unauthorized())),
+        };
+        let realm = match params.get("realm") {
+            Some(realm) => realm,
+            None => return Either::A(future::err(unauthorized())),
+        };
+
+        let mut query = form_urlencoded::Serializer::new(String::new());
+        if let Some(service) = params.get("service") {
+            query.append_pair("service", service);
+        }
+        let scope = params
+            .get("scope")
+            .cloned()
+            .unwrap_or_else(|| format!("repository:{}:pull", self.repository));
+        query.append_pair("scope", &scope);
+        let token_uri = format!("{}?{}", realm, query.finish());
+
+        let response = send(
+            &self.client,
+            Method::GET,
+            &token_uri,
+            basic.as_ref().map(String::as_str),
+        );
+        let token = response
+            .and_then(move |response| {
+                finish(token_uri.clone(), response).map(move |response| (token_uri, response))
+            })
+            .and_then(|(token_uri, (_, body))| -> Result<_, Error> {
+                let response: TokenResponse = serde_json::from_slice(&body)
+                    .with_context(|_| ErrorKind::TokenRequest(token_uri.clone()))?;
+                let token = response
+                    .token
+                    .or(response.access_token)
+                    .ok_or_else(|| ErrorKind::TokenRequest(token_uri))?;
+                Ok(format!("Bearer {}", token))
+            });
+        Either::B(token)
+    }
+}
+
+// The parameters of a challenge like
+// `Bearer realm="https://auth.docker.io/token",service="registry.docker.io"`.
+fn bearer_params(challenge: &str) -> Option<HashMap<String, String>> {
+    if !challenge.starts_with("Bearer ") {
+        return None;
+    }
+    let params = challenge["Bearer ".len()..]
+        .split(',')
+        .filter_map(|param| {
+            let mut parts = param.trim().splitn(2, '=');
+            let key = parts.next()?;
+            let value = parts.next()?.trim_matches('"');
+            Some((key.to_string(), value.to_string()))
+        })
+        .collect();
+    Some(params)
+}
+
+fn send(
+    client: &MaybeProxyClient,
+    method: Method,
+    uri: &str,
+    authorization: Option<&str>,
+) -> impl Future<Item = Response<Body>, Error = Error> + Send {
+    let mut request = Request::builder();
+    request
+        .method(method)
+        .uri(uri)
+        .header(ACCEPT, MANIFEST_TYPES);
+    if let Some(authorization) = authorization {
+        request.header(AUTHORIZATION, authorization);
+    }
+
+    let context = ErrorKind::RegistryRequest(uri.to_string());
+    match request.body(Body::empty()) {
+        Ok(request) => Either::A(
+            client
+                .call(request)
+                .map_err(|err| Error::from(err.context(context))),
+        ),
+        Err(err) => Either::B(future::err(Error::from(err.context(context)))),
+    }
+}
+
+// Errors only have the URI and status of the request, since the bodies of
+// responses can have tokens.
+fn finish(
+    uri: String,
+    response: Response<Body>,
+) -> impl Future<Item = (HeaderMap, Chunk), Error = Error> + Send {
+    let (parts, body) = response.into_parts();
+    if !parts.status.is_success() {
+        return Either::A(future::err(Error::from(ErrorKind::RegistryRequestFailed(
+            uri,
+            parts.status,
+        ))));
+    }
+    Either::B(
+        body.concat2()
+            .map(move |body| (parts.headers, body))
+            .map_err(move |err| Error::from(err.context(ErrorKind::RegistryRequest(uri)))),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use hyper::header::HeaderValue;
+    use maplit::btreemap;
+    use serde_json::json;
+    use tempdir::TempDir;
+
+    use edgelet_test_utils::web::{
+        make_req_dispatcher, HttpMethod, RequestHandler, RequestPath, ResponseFuture,
+    };
+    use edgelet_test_utils::{routes, run_tcp_server};
+
+    use super::*;
+
+    const DIGEST: &str = "sha256:0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+
+    fn tags(tags: &[&str]) -> Vec<String> {
+        tags.iter().map(ToString::to_string).collect()
+    }
+
+    fn not_found(_: Request<Body>) -> ResponseFuture {
+        let mut response = Response::new(Body::empty());
+        *response.status_mut() = StatusCode::NOT_FOUND;
+        Box::new(future::ok(response))
+    }
+
+    // A registry with the tags `tags` of `module`, that only answers requests
+    // with the token from its realm and counts the requests for the tags.
+    fn registry(
+        tags: Vec<&'static str>,
+        requests: Arc<AtomicUsize>,
+    ) -> (u16, impl Future<Item = (), Error = ()>) {
+        let port = Arc::new(Mutex::new(0));
+        let challenge_port = port.clone();
+        let authorized = |req: &Request<Body>| {
+            req.headers().get(AUTHORIZATION) == Some(&HeaderValue::from_static("Bearer pull-token"))
+        };
+        let challenge = move || {
+            let mut response = Response::new(Body::empty());
+            *response.status_mut() = StatusCode::UNAUTHORIZED;
+            let challenge = format!(
+                "Bearer realm=\"http://localhost:{}/token\",service=\"registry\",scope=\"repository:module:pull\"",
+                *challenge_port.lock().unwrap()
+            );
+            response
+                .headers_mut()
+                .insert(WWW_AUTHENTICATE, challenge.parse().unwrap());
+            Box::new(future::ok(response)) as ResponseFuture
+        };
+        let manifest_challenge = challenge.clone();
+
+        let dispatch_table = routes!(
+            GET "/token" => |req: Request<Body>| {
+                let query = req.uri().query().unwrap_or("").to_string();
+                assert!(query.contains("scope=repository%3Amodule%3Apull"), "{}", query);
+                let response = Response::new(json!({ "token": "pull-token" }).to_string().into());
+                Box::new(future::ok(response)) as ResponseFuture
+            },
+            GET "/v2/module/tags/list" => move |req: Request<Body>| {
+                if !authorized(&req) {
+                    return challenge();
+                }
+                requests.fetch_add(1, Ordering::SeqCst);
+                let body = json!({ "name": "module", "tags": tags });
+                Box::new(future::ok(Response::new(body.to_string().into()))) as ResponseFuture
+            },
+            HEAD "/v2/module/manifests/1.10.0" => move |req: Request<Body>| {
+                if !authorized(&req) {
+                    return manifest_challenge();
+                }
+                let mut response = Response::new(Body::empty());
+                response
+                    .headers_mut()
+                    .insert(DIGEST_HEADER, HeaderValue::from_static(DIGEST));
+                Box::new(future::ok(response)) as ResponseFuture
+            },
+        );
+
+        let (server, server_port) = run_tcp_server(
+            "127.0.0.1",
+            make_req_dispatcher(dispatch_table, Box::new(not_found)),
+        );
+        *port.lock().unwrap() = server_port;
+        (server_port, server.map_err(|err| panic!(err)))
+    }
+
+    #[test]
+    fn tag_patterns() {
+        assert!(is_tag_pattern("myimage:1.*"));
+        assert!(is_tag_pattern(
+            "localhost:5000/samples/module:1.*-linux-amd64"
+        ));
+        assert!(!is_tag_pattern("myimage:1.0"));
+        assert!(!is_tag_pattern("localhost:5000/module"));
+        assert!(!is_tag_pattern(&format!("myimage:1.*@{}", DIGEST)));
+    }
+
+    #[test]
+    fn highest_matching_version_is_selected() {
+        let available = tags(&[
+            "latest",
+            "0.9",
+            "1",
+            "1.2",
+            "1.10.0",
+            "1.9.9",
+            "1.11.0-rc1",
+            "2.0",
+            "10.0",
+        ]);
+
+        assert_eq!(Some("1.10.0"), select_tag("1.*", &available));
+        assert_eq!(Some("1.9.9"), select_tag("1.9.*", &available));
+        assert_eq!(Some("10.0"), select_tag("*", &available));
+        assert_eq!(Some("1.2"), select_tag("*.2", &available));
+        assert_eq!(Some("1.11.0-rc1"), select_tag("1.*-rc1", &available));
+
+        // Versions are compared by number, not as text, and a more specific
+        // tag of the same version wins.
+        let available = tags(&["1.9", "1.10", "1.10.0", "1.2.30"]);
+        assert_eq!(Some("1.10.0"), select_tag("1.*", &available));
+
+        let available = tags(&["1.0-linux-amd64", "1.1-linux-amd64", "1.2-linux-arm32v7"]);
+        assert_eq!(
+            Some("1.1-linux-amd64"),
+            select_tag("1.*-linux-amd64", &available)
+        );
+    }
+
+    #[test]
+    fn pattern_without_match_resolves_to_nothing() {
+        let available = tags(&["latest", "0.9", "1", "2.0", "1.0-rc1"]);
+
+        assert_eq!(None, select_tag("1.*", &available));
+        assert_eq!(None, select_tag("3.*", &available));
+        assert_eq!(None, select_tag("1.x", &available));
+        assert_eq!(None, select_tag("1.*", &[]));
+    }
+
+    #[test]
+    fn pattern_is_resolved_to_digest_of_highest_match_once() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let (port, server) = registry(vec!["1.2", "1.10.0", "2.0"], requests.clone());
+        let dir = TempDir::new("tags").unwrap();
+        let path = dir.path().join("resolved_images.json");
+
+        let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+        runtime.spawn(server);
+
+        let image = format!("localhost:{}/module:1.*", port);
+        let expected = format!("localhost:{}/module:1.10.0@{}", port, DIGEST);
+        let resolver = TagResolver::new(Some(path.clone()), None).unwrap();
+        for _ in 0..2 {
+            let resolved = runtime.block_on(resolver.resolve(&image, None)).unwrap();
+            assert_eq!(expected, resolved);
+        }
+        assert_eq!(1, requests.load(Ordering::SeqCst));
+
+        // The resolution survives a restart.
+        let restarted = TagResolver::new(Some(path), None).unwrap();
+        assert_eq!(Some(expected), restarted.resolved(&image));
+        assert_eq!(1, requests.load(Ordering::SeqCst));
+
+        // Images without a pattern aren't resolved.
+        let image = format!("localhost:{}/module:1.2", port);
+        assert_eq!(
+            image,
+            runtime.block_on(resolver.resolve(&image, None)).unwrap()
+        );
+    }
+
+    #[test]
+    fn pattern_without_match_fails() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let (port, server) = registry(vec!["latest", "2.0", "2.1"], requests);
+
+        let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+        runtime.spawn(server);
+
+        let image = format!("localhost:{}/module:1.*", port);
+        let resolver = TagResolver::new(None, None).unwrap();
+        let err = runtime
+            .block_on(resolver.resolve(&image, None))
+            .unwrap_err();
+
+        match err.kind() {
+            ErrorKind::ResolveTag(resolved) => assert_eq!(&image, resolved),
+            kind => panic!("Expected `ResolveTag` error but got {:?}", kind),
+        }
+        let cause = Fail::iter_chain(&err)
+            .skip(1)
+            .find_map(|cause| cause.downcast_ref::<Error>())
+            .expect("expected a cause");
+        match cause.kind() {
+            ErrorKind::NoMatchingTag(name, pattern) => {
+                assert_eq!(&format!("localhost:{}/module", port), name);
+                assert_eq!("1.*", pattern);
+            }
+            kind => panic!("Expected `NoMatchingTag` error but got {:?}", kind),
+        }
+        assert_eq!(None, resolver.resolved(&image));
+    }
+}
@@ -0,0 +1,256 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Caps the download rate of image pulls.
+//!
+//! The Docker daemon downloads the layers of an image itself, so the bytes it
+//! downloads never pass through here. What does is the progress stream of the
+//! pull, which reports how far the download of every layer got. The daemon
+//! only gets ahead of the progress that its client has read by the little that
+//! it buffers, so reading the progress no faster than the downloaded bytes it
+//! reports are allowed holds the daemon's download to the rate on average.
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::time::{Duration, Instant};
+
+use futures::{try_ready, Async, Future, Poll, Stream};
+use hyper::Chunk;
+use tokio::timer::Delay;
+
+/// Wraps the progress stream of a pull and delays reading it whenever the
+/// layers it reports as downloaded are ahead of `rate` bytes per second.
+///
+/// Chunks are passed on as soon as they're read, however small, and only the
+/// read that follows one is delayed, so a reader never waits on bytes that are
+/// held back.
+pub struct PullThrottle<S> {
+    inner: S,
+    rate: u64,
+    started: Option<Instant>,
+    downloaded: u64,
+    layers: HashMap<String, Layer>,
+    line: Vec<u8>,
+    delay: Option<Delay>,
+}
+
+#[derive(Default)]
+struct Layer {
+    current: u64,
+    total: Option<u64>,
+}
+
+#[derive(serde_derive::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Progress {
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    status: String,
+    #[serde(default)]
+    progress_detail: Option<ProgressDetail>,
+}
+
+#[derive(Default, serde_derive::Deserialize)]
+struct ProgressDetail {
+    #[serde(default)]
+    current: Option<u64>,
+    #[serde(default)]
+    total: Option<u64>,
+}
+
+impl<S> PullThrottle<S> {
+    pub fn new(inner: S, rate: u64) -> Self {
+        PullThrottle {
+            inner,
+            rate: rate.max(1),
+            started: None,
+            downloaded: 0,
+            layers: HashMap::new(),
+            line: Vec::new(),
+            delay: None,
+        }
+    }
+
+    /// The number of bytes of layers that the progress read so far reported
+    /// as downloaded.
+    #[cfg(test)]
+    pub fn downloaded(&self) -> u64 {
+        self.downloaded
+    }
+
+    fn count(&mut self, chunk: &[u8]) {
+        // Progress messages are separated by newlines, and a chunk can end in
+        // the middle of one.
+        self.line.extend_from_slice(chunk);
+        while let Some(end) = self.line.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.line.drain(..=end).collect();
+            if let Ok(progress) = serde_json::from_slice::<Progress>(&line) {
+                self.downloaded += self.progress(progress);
+            }
+        }
+    }
+
+    // The bytes of a layer that a progress message reports on top of the ones
+    // reported before it.
+    fn progress(&mut self, progress: Progress) -> u64 {
+        let id = match progress.id {
+            Some(id) => id,
+            None => return 0,
+        };
+        let layer = self.layers.entry(id).or_insert_with(Layer::default);
+
+        let reached = match progress.status.as_str() {
+            "Downloading" => {
+                let detail = progress.progress_detail.unwrap_or_default();
+                if detail.total.is_some() {
+                    layer.total = detail.total;
+                }
+                detail.current
+            }
+            // The last progress of a layer doesn't have to be its full size.
+            "Download complete" => layer.total,
+            _ => None,
+        };
+
+        match reached {
+            Some(reached) if reached > layer.current => {
+                let added = reached - layer.current;
+                layer.current = reached;
+                added
+            }
+            _ => 0,
+        }
+    }
+
+    fn due(&self, started: Instant) -> Instant {
+        let nanos = u128::from(self.downloaded) * 1_000_000_000 / u128::from(self.rate);
+        started + Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::max_value()))
+    }
+}
+
+impl<S> Stream for PullThrottle<S>
+where
+    S: Stream<Item = Chunk>,
+{
+    type Item = Chunk;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        if let Some(delay) = &mut self.delay {
+            match delay.poll() {
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                // A timer that fails only costs the pull its cap, it doesn't
+                // fail the pull.
+                Ok(Async::Ready(())) | Err(_) => self.delay = None,
+            }
+        }
+
+        let chunk = match try_ready!(self.inner.poll()) {
+            Some(chunk) => chunk,
+            None => return Ok(Async::Ready(None)),
+        };
+
+        let now = Instant::now();
+        let started = *self.started.get_or_insert(now);
+        self.count(&chunk);
+        let due = self.due(started);
+        if due > now {
+            self.delay = Some(Delay::new(due));
+        }
+
+        Ok(Async::Ready(Some(chunk)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{future, stream};
+    use serde_json::json;
+
+    use super::*;
+
+    const LAYER_SIZE: u64 = 256 * 1024;
+    const STEP: u64 = 16 * 1024;
+
+    // The progress stream of pulling an image of 4 layers, downloaded one
+    // after the other like the daemon reports them.
+    fn progress() -> Vec<u8> {
+        let mut messages = vec![json!({ "status": "Pulling from library/image", "id": "1.0" })];
+        for layer in 0..4 {
+            let id = format!("layer{}", layer);
+            messages.push(json!({ "status": "Pulling fs layer", "id": id }));
+            let mut current = 0;
+            while current + STEP < LAYER_SIZE {
+                current += STEP;
+                messages.push(json!({
+                    "status": "Downloading",
+                    "progressDetail": { "current": current, "total": LAYER_SIZE },
+                    "progress": "[=>  ]",
+                    "id": id,
+                }));
+            }
+            messages.push(json!({ "status": "Download complete", "id": id }));
+            messages.push(json!({ "status": "Pull complete", "id": id }));
+        }
+        messages.push(json!({ "status": "Digest: sha256:0123" }));
+        messages.push(json!({ "status": "Status: Downloaded newer image for image:1.0" }));
+
+        messages
+            .into_iter()
+            .flat_map(|message| format!("{}\r\n", message).into_bytes())
+            .collect()
+    }
+
+    fn chunks(progress: &[u8], size: usize) -> Vec<Chunk> {
+        progress
+            .chunks(size)
+            .map(|chunk| Chunk::from(chunk.to_vec()))
+            .collect()
+    }
+
+    // Reads the whole stream and returns how long it took, how many bytes the
+    // throttle counted and what it read.
+    fn read(chunks: Vec<Chunk>, rate: u64) -> (Duration, u64, Vec<u8>) {
+        let mut throttle = PullThrottle::new(stream::iter_ok::<_, ()>(chunks), rate);
+        let mut body = Vec::new();
+        let started = Instant::now();
+        tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(future::poll_fn(|| -> Poll<(), ()> {
+                while let Some(chunk) = try_ready!(throttle.poll()) {
+                    body.extend_from_slice(&chunk);
+                }
+                Ok(Async::Ready(()))
+            }))
+            .unwrap();
+        (started.elapsed(), throttle.downloaded(), body)
+    }
+
+    #[test]
+    fn large_pull_respects_rate() {
+        // 1 MiB at 2 MiB/s takes half a second.
+        let (elapsed, downloaded, _) = read(chunks(&progress(), 256), 2 * 1024 * 1024);
+
+        assert_eq!(4 * LAYER_SIZE, downloaded);
+        assert!(
+            elapsed >= Duration::from_millis(450),
+            "pull took {:?}",
+            elapsed
+        );
+        assert!(
+            elapsed <= Duration::from_millis(1500),
+            "pull took {:?}",
+            elapsed
+        );
+    }
+
+    #[test]
+    fn small_reads_do_not_stall() {
+        let progress = progress();
+        let (elapsed, downloaded, body) = read(chunks(&progress, 7), 64 * 1024 * 1024);
+
+        assert_eq!(4 * LAYER_SIZE, downloaded);
+        assert_eq!(progress, body);
+        assert!(elapsed <= Duration::from_secs(5), "pull took {:?}", elapsed);
+    }
+}
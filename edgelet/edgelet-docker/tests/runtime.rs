@@ -5,31 +5,42 @@
 #![allow(clippy::too_many_lines)]
 
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::str;
-use std::sync::{Arc, RwLock};
-use std::time::Duration;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
 
 use config::{Config, File, FileFormat};
 use failure::Fail;
 use futures::future;
 use futures::prelude::*;
+use futures::sync::oneshot;
+use futures::try_ready;
+use hyper::server::conn::Http;
+use hyper::service::service_fn;
 use hyper::{Body, Method, Request, Response, StatusCode};
 use json_patch::merge;
 use maplit::btreemap;
 use serde_json::{self, json, Value as JsonValue};
+use tokio::timer::Delay;
 use typed_headers::{mime, ContentLength, ContentType, HeaderMapExt};
 use url::form_urlencoded::parse as parse_query;
 
 use docker::models::{
     AuthConfig, ContainerCreateBody, ContainerHostConfig, ContainerNetworkSettings,
-    ContainerSummary, HostConfig, HostConfigPortBindings, ImageDeleteResponseItem, NetworkConfig,
+    ContainerSummary, HostConfig, HostConfigPortBindings, ImageDeleteResponseItem, ImageSummary,
+    NetworkConfig,
 };
 
 use edgelet_core::{
-    GetTrustBundle, ImagePullPolicy, LogOptions, LogTail, MakeModuleRuntime, Module,
-    ModuleRegistry, ModuleRuntime, ModuleSpec, RegistryOperation, RuntimeOperation,
+    pull_image, ExecOutput, ExitReason, GetTrustBundle, ImagePruneFilter, ImagePullPolicy,
+    LogOptions, LogTail, MakeModuleRuntime, Module, ModuleBind, ModuleErrorCode,
+    ModuleLifecycleEventKind, ModuleNetwork, ModuleRegistry, ModuleResources, ModuleRuntime,
+    ModuleSpec, ModuleSpecViolation, PreStopHook, RegistryOperation, RuntimeOperation,
+    ValidationError, MIN_MEMORY_LIMIT_BYTES, PRE_STOP_LABEL, SPEC_FIELDS_LABEL, SPEC_HASH_LABEL,
 };
-use edgelet_docker::{DockerConfig, DockerModuleRuntime, Settings};
+use edgelet_docker::{DockerConfig, DockerModuleRuntime, RegistryAuth, Settings};
 use edgelet_docker::{Error, ErrorKind};
 use edgelet_test_utils::crypto::TestHsm;
 use edgelet_test_utils::web::{
@@ -505,42 +516,16 @@ fn image_pull_with_invalid_creds_fails() {
     }
 }
 
-#[allow(clippy::needless_pass_by_value)]
-fn image_pull_handler(req: Request<Body>) -> ResponseFuture {
-    // verify that path is /images/create and that the "fromImage" query
-    // parameter has the image name we expect
-    assert_eq!(req.uri().path(), "/images/create");
-
-    let query_map: HashMap<String, String> = parse_query(req.uri().query().unwrap().as_bytes())
-        .into_owned()
-        .collect();
-    assert!(query_map.contains_key("fromImage"));
-    assert_eq!(query_map.get("fromImage"), Some(&IMAGE_NAME.to_string()));
-
-    let response = r#"
-    {
-        "Id": "img1",
-        "Warnings": []
-    }
-    "#;
-    let response_len = response.len();
-
-    let mut response = Response::new(response.into());
-    response
-        .headers_mut()
-        .typed_insert(&ContentLength(response_len as u64));
-    response
-        .headers_mut()
-        .typed_insert(&ContentType(mime::APPLICATION_JSON));
-    Box::new(future::ok(response))
-}
-
 #[test]
-fn image_pull_succeeds() {
+fn image_pull_with_missing_credential_file_fails() {
+    use tempdir::TempDir;
+
     let dispatch_table = routes!(
         GET "/networks" => default_get_networks_handler(),
         POST "/networks/create" => default_create_network_handler(),
-        POST "/images/create" => image_pull_handler,
+        POST "/images/create" => |_: Request<Body>| -> ResponseFuture {
+            panic!("image should not be pulled without credentials")
+        },
     );
 
     let (server, port) = run_tcp_server(
@@ -555,30 +540,51 @@ fn image_pull_succeeds() {
         }
     })));
 
+    let tmp_dir = TempDir::new("auth").unwrap();
+    let password_file = tmp_dir.path().join("password");
+
     let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
-        .and_then(|runtime| {
-            let auth = AuthConfig::new()
-                .with_username("u1".to_string())
-                .with_password("bleh".to_string())
-                .with_email("u1@bleh.com".to_string())
-                .with_serveraddress("svr1".to_string());
-            let config = DockerConfig::new(
-                IMAGE_NAME.to_string(),
-                ContainerCreateBody::new(),
-                Some(auth),
+        .and_then(move |runtime| {
+            let auth = RegistryAuth::from(
+                AuthConfig::new()
+                    .with_username("u1".to_string())
+                    .with_serveraddress("svr1".to_string()),
             )
-            .unwrap();
+            .with_password_file(password_file);
+            let config =
+                DockerConfig::new(IMAGE_NAME.to_string(), ContainerCreateBody::new(), None)
+                    .unwrap()
+                    .with_registry_auth(auth);
 
             runtime.pull(&config)
         });
 
     let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
     runtime.spawn(server);
-    runtime.block_on(task).unwrap();
+
+    let err = runtime
+        .block_on(task)
+        .expect_err("Expected runtime pull method to fail without the password file.");
+
+    match (err.kind(), err.cause().and_then(Fail::downcast_ref)) {
+        (
+            edgelet_docker::ErrorKind::RegistryOperation(
+                edgelet_core::RegistryOperation::PullImage(name),
+            ),
+            Some(edgelet_docker::ErrorKind::CredentialFile(path)),
+        ) if name == IMAGE_NAME => {
+            assert!(path.ends_with("password"), "{}", path);
+        }
+
+        _ => panic!(
+            "Credential file error is expected for a missing file. Got {:?}",
+            err.kind()
+        ),
+    }
 }
 
 #[allow(clippy::needless_pass_by_value)]
-fn image_pull_with_creds_handler(req: Request<Body>) -> ResponseFuture {
+fn image_pull_handler(req: Request<Body>) -> ResponseFuture {
     // verify that path is /images/create and that the "fromImage" query
     // parameter has the image name we expect
     assert_eq!(req.uri().path(), "/images/create");
@@ -589,20 +595,6 @@ fn image_pull_with_creds_handler(req: Request<Body>) -> ResponseFuture {
     assert!(query_map.contains_key("fromImage"));
     assert_eq!(query_map.get("fromImage"), Some(&IMAGE_NAME.to_string()));
 
-    // verify registry creds
-    let auth_str = req
-        .headers()
-        .get_all("X-Registry-Auth")
-        .into_iter()
-        .map(|bytes| base64::decode(bytes).unwrap())
-        .map(|raw| str::from_utf8(&raw).unwrap().to_owned())
-        .collect::<String>();
-    let auth_config: AuthConfig = serde_json::from_str(&auth_str).unwrap();
-    assert_eq!(auth_config.username(), Some("u1"));
-    assert_eq!(auth_config.password(), Some("bleh"));
-    assert_eq!(auth_config.email(), Some("u1@bleh.com"));
-    assert_eq!(auth_config.serveraddress(), Some("svr1"));
-
     let response = r#"
     {
         "Id": "img1",
@@ -622,11 +614,11 @@ fn image_pull_with_creds_handler(req: Request<Body>) -> ResponseFuture {
 }
 
 #[test]
-fn image_pull_with_creds_succeeds() {
+fn image_pull_succeeds() {
     let dispatch_table = routes!(
         GET "/networks" => default_get_networks_handler(),
         POST "/networks/create" => default_create_network_handler(),
-        POST "/images/create" => image_pull_with_creds_handler,
+        POST "/images/create" => image_pull_handler,
     );
 
     let (server, port) = run_tcp_server(
@@ -663,33 +655,18 @@ fn image_pull_with_creds_succeeds() {
     runtime.block_on(task).unwrap();
 }
 
-#[allow(clippy::needless_pass_by_value)]
-fn image_remove_handler(req: Request<Body>) -> ResponseFuture {
-    assert_eq!(req.method(), &Method::DELETE);
-    assert_eq!(req.uri().path(), &format!("/images/{}", IMAGE_NAME));
-
-    let response = serde_json::to_string(&vec![
-        ImageDeleteResponseItem::new().with_deleted(IMAGE_NAME.to_string())
-    ])
-    .unwrap();
-    let response_len = response.len();
-
-    let mut response = Response::new(response.into());
-    response
-        .headers_mut()
-        .typed_insert(&ContentLength(response_len as u64));
-    response
-        .headers_mut()
-        .typed_insert(&ContentType(mime::APPLICATION_JSON));
-    Box::new(future::ok(response))
-}
-
 #[test]
-fn image_remove_succeeds() {
+fn concurrent_image_pulls_are_coalesced() {
+    let pull_count = Arc::new(RwLock::new(0));
+    let pull_count_cloned = pull_count.clone();
+
     let dispatch_table = routes!(
         GET "/networks" => default_get_networks_handler(),
         POST "/networks/create" => default_create_network_handler(),
-        DELETE format!("/images/{}", IMAGE_NAME) => image_remove_handler,
+        POST "/images/create" => move |req| {
+            *pull_count.write().unwrap() += 1;
+            image_pull_handler(req)
+        },
     );
 
     let (server, port) = run_tcp_server(
@@ -705,102 +682,76 @@ fn image_remove_succeeds() {
     })));
 
     let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
-        .and_then(|runtime| ModuleRegistry::remove(&runtime, IMAGE_NAME));
+        .and_then(|runtime| {
+            let config =
+                DockerConfig::new(IMAGE_NAME.to_string(), ContainerCreateBody::new(), None)
+                    .unwrap();
+
+            runtime.pull(&config).join(runtime.pull(&config))
+        });
 
     let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
     runtime.spawn(server);
     runtime.block_on(task).unwrap();
-}
-
-fn container_create_handler(req: Request<Body>) -> ResponseFuture {
-    assert_eq!(req.method(), &Method::POST);
-    assert_eq!(req.uri().path(), "/containers/create");
-
-    let response = json!({
-        "Id": "12345",
-        "Warnings": []
-    })
-    .to_string();
-    let response_len = response.len();
-
-    Box::new(
-        req.into_body()
-            .concat2()
-            .and_then(|body| {
-                let create_options: ContainerCreateBody =
-                    serde_json::from_slice(body.as_ref()).unwrap();
-
-                assert_eq!("nginx:latest", create_options.image().unwrap());
-
-                for &v in &["/do/the/custom/command", "with these args"] {
-                    assert!(create_options.cmd().unwrap().contains(&v.to_string()));
-                }
-
-                for &v in &["/also/do/the/entrypoint", "and this"] {
-                    assert!(create_options
-                        .entrypoint()
-                        .unwrap()
-                        .contains(&v.to_string()));
-                }
-
-                for &v in &["k1=v1", "k2=v2", "k3=v3", "k4=v4", "k5=v5"] {
-                    assert!(create_options.env().unwrap().contains(&v.to_string()));
-                }
 
-                let port_bindings = create_options
-                    .host_config()
-                    .unwrap()
-                    .port_bindings()
-                    .unwrap();
-                assert_eq!(
-                    "8080",
-                    port_bindings
-                        .get("80/tcp")
-                        .unwrap()
-                        .iter()
-                        .next()
-                        .unwrap()
-                        .host_port()
-                        .unwrap()
-                );
-                assert_eq!(
-                    "11022",
-                    port_bindings
-                        .get("22/tcp")
-                        .unwrap()
-                        .iter()
-                        .next()
-                        .unwrap()
-                        .host_port()
-                        .unwrap()
-                );
+    assert_eq!(1, *pull_count_cloned.read().unwrap());
+}
 
-                let volumes = create_options.volumes().unwrap();
-                let mut expected = ::std::collections::HashMap::new();
-                expected.insert("test1".to_string(), json!({}));
-                assert_eq!(*volumes, expected);
+const PINNED_DIGEST: &str =
+    "sha256:0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
 
-                Ok(())
-            })
-            .map(move |_| {
-                let mut response = Response::new(response.into());
-                response
-                    .headers_mut()
-                    .typed_insert(&ContentLength(response_len as u64));
-                response
-                    .headers_mut()
-                    .typed_insert(&ContentType(mime::APPLICATION_JSON));
-                response
-            }),
-    )
-}
+// Returns the result of the pull and the number of times the image was pulled
+// from the registry.
+fn pull_pinned_image(repo_digests: Vec<String>) -> (Result<(), Error>, usize) {
+    let pinned_image = format!("nginx@{}", PINNED_DIGEST);
+    let expected_image = pinned_image.clone();
+    let pull_count = Arc::new(RwLock::new(0));
+    let pull_count_cloned = pull_count.clone();
 
-#[test]
-fn container_create_succeeds() {
     let dispatch_table = routes!(
         GET "/networks" => default_get_networks_handler(),
         POST "/networks/create" => default_create_network_handler(),
-        POST "/containers/create" => container_create_handler,
+        POST "/images/create" => move |req: Request<Body>| {
+            *pull_count.write().unwrap() += 1;
+
+            // the digest wins over the tag
+            let query_map: HashMap<String, String> =
+                parse_query(req.uri().query().unwrap().as_bytes())
+                    .into_owned()
+                    .collect();
+            assert_eq!(query_map.get("fromImage"), Some(&expected_image));
+
+            let mut response = Response::new(r#"{ "status": "Downloaded" }"#.into());
+            response
+                .headers_mut()
+                .typed_insert(&ContentType(mime::APPLICATION_JSON));
+            Box::new(future::ok(response)) as ResponseFuture
+        },
+        GET format!("/images/{}/json", pinned_image) => move |_| {
+            let response = json!({
+                "Id": "img1",
+                "RepoDigests": repo_digests,
+                "Parent": "",
+                "Comment": "",
+                "Created": "2019-11-05T00:00:00Z",
+                "Container": "",
+                "DockerVersion": "19.03",
+                "Author": "",
+                "Architecture": "amd64",
+                "Os": "linux",
+                "Size": 0,
+                "VirtualSize": 0,
+                "GraphDriver": { "Name": "overlay2" },
+                "RootFS": { "Type": "layers" },
+            })
+            .to_string();
+
+            let mut response = Response::new(response.into());
+            response
+                .headers_mut()
+                .typed_insert(&ContentType(mime::APPLICATION_JSON));
+            Box::new(future::ok(response)) as ResponseFuture
+        },
     );
 
     let (server, port) = run_tcp_server(
@@ -817,72 +768,72 @@ fn container_create_succeeds() {
 
     let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
         .and_then(|runtime| {
-            let mut env = HashMap::new();
-            env.insert("k1".to_string(), "v1".to_string());
-            env.insert("k2".to_string(), "v2".to_string());
-            env.insert("k3".to_string(), "v3".to_string());
-
-            // add some create options
-            let mut port_bindings = HashMap::new();
-            port_bindings.insert(
-                "22/tcp".to_string(),
-                vec![HostConfigPortBindings::new().with_host_port("11022".to_string())],
-            );
-            port_bindings.insert(
-                "80/tcp".to_string(),
-                vec![HostConfigPortBindings::new().with_host_port("8080".to_string())],
-            );
-            let memory: i64 = 3_221_225_472;
-            let mut volumes = ::std::collections::HashMap::new();
-            volumes.insert("test1".to_string(), json!({}));
-            let create_options = ContainerCreateBody::new()
-                .with_host_config(
-                    HostConfig::new()
-                        .with_port_bindings(port_bindings)
-                        .with_memory(memory),
-                )
-                .with_cmd(vec![
-                    "/do/the/custom/command".to_string(),
-                    "with these args".to_string(),
-                ])
-                .with_entrypoint(vec![
-                    "/also/do/the/entrypoint".to_string(),
-                    "and this".to_string(),
-                ])
-                .with_env(vec!["k4=v4".to_string(), "k5=v5".to_string()])
-                .with_volumes(volumes);
-
-            let module_config = ModuleSpec::new(
-                "m1".to_string(),
-                "docker".to_string(),
-                DockerConfig::new("nginx:latest".to_string(), create_options, None).unwrap(),
-                env,
-                ImagePullPolicy::default(),
+            let config = DockerConfig::new(
+                format!("nginx:1.17@{}", PINNED_DIGEST),
+                ContainerCreateBody::new(),
+                None,
             )
             .unwrap();
 
-            runtime.create(module_config)
+            runtime.pull(&config)
         });
 
     let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
     runtime.spawn(server);
-    runtime.block_on(task).unwrap();
+    let result = runtime.block_on(task);
+    let pull_count = *pull_count_cloned.read().unwrap();
+    (result, pull_count)
 }
 
-#[allow(clippy::needless_pass_by_value)]
-fn container_start_handler(req: Request<Body>) -> ResponseFuture {
-    assert_eq!(req.method(), &Method::POST);
-    assert_eq!(req.uri().path(), "/containers/m1/start");
-
-    Box::new(future::ok(Response::new(Body::empty())))
+#[test]
+fn image_pull_with_matching_digest_is_skipped() {
+    let (result, pull_count) = pull_pinned_image(vec![
+        "nginx@sha256:fedcba9876543210fedcba9876543210fedcba9876543210fedcba9876543210".to_string(),
+        format!("nginx@{}", PINNED_DIGEST),
+    ]);
+    result.unwrap();
+    assert_eq!(0, pull_count);
 }
 
 #[test]
-fn container_start_succeeds() {
+fn image_pull_with_mismatched_digest_fails() {
+    let other = "nginx@sha256:fedcba9876543210fedcba9876543210fedcba9876543210fedcba9876543210";
+    let (result, pull_count) = pull_pinned_image(vec![other.to_string()]);
+    assert_eq!(1, pull_count);
+    let err = result.unwrap_err();
+
+    match (err.kind(), err.cause().and_then(Fail::downcast_ref)) {
+        (
+            ErrorKind::RegistryOperation(RegistryOperation::PullImage(name)),
+            Some(ErrorKind::DigestMismatch(image, expected, found)),
+        ) => {
+            let pinned_image = format!("nginx@{}", PINNED_DIGEST);
+            assert_eq!(&pinned_image, name);
+            assert_eq!(&pinned_image, image);
+            assert_eq!(PINNED_DIGEST, expected);
+            assert_eq!(other, found);
+        }
+        _ => panic!("Expected `DigestMismatch` error but got {:?}", err),
+    }
+}
+
+#[test]
+fn image_pull_from_unreachable_registry_needs_preloaded_image() {
     let dispatch_table = routes!(
         GET "/networks" => default_get_networks_handler(),
         POST "/networks/create" => default_create_network_handler(),
-        POST "/containers/m1/start" => container_start_handler,
+        POST "/images/create" => |_| {
+            let response = json!({
+                "message": "Get https://registry.example.com/v2/: dial tcp: lookup registry.example.com: no such host"
+            })
+            .to_string();
+            let mut response = Response::new(response.into());
+            *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+            response
+                .headers_mut()
+                .typed_insert(&ContentType(mime::APPLICATION_JSON));
+            Box::new(future::ok(response)) as ResponseFuture
+        },
     );
 
     let (server, port) = run_tcp_server(
@@ -898,27 +849,88 @@ fn container_start_succeeds() {
     })));
 
     let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
-        .and_then(|runtime| runtime.start("m1"));
+        .and_then(|runtime| {
+            let config = DockerConfig::new(
+                "registry.example.com/module:1.0".to_string(),
+                ContainerCreateBody::new(),
+                None,
+            )
+            .unwrap();
+
+            runtime.pull(&config)
+        });
 
     let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
     runtime.spawn(server);
-    runtime.block_on(task).unwrap();
-}
+    let err = runtime.block_on(task).unwrap_err();
 
-#[allow(clippy::needless_pass_by_value)]
-fn container_stop_handler(req: Request<Body>) -> ResponseFuture {
-    assert_eq!(req.method(), &Method::POST);
-    assert_eq!(req.uri().path(), "/containers/m1/stop");
+    match err.cause().and_then(Fail::downcast_ref) {
+        Some(ErrorKind::ImageNotPreloaded(image, _)) => {
+            assert_eq!("registry.example.com/module:1.0", image);
+        }
+        _ => panic!("Expected `ImageNotPreloaded` error but got {:?}", err),
+    }
+}
 
-    Box::new(future::ok(Response::new(Body::empty())))
+fn json_response(status: StatusCode, body: &JsonValue) -> ResponseFuture {
+    let mut response = Response::new(body.to_string().into());
+    *response.status_mut() = status;
+    response
+        .headers_mut()
+        .typed_insert(&ContentType(mime::APPLICATION_JSON));
+    Box::new(future::ok(response))
 }
 
 #[test]
-fn container_stop_succeeds() {
+fn image_pull_with_rejected_acr_token_is_retried_with_new_token() {
+    let aad_requests = Arc::new(AtomicUsize::new(0));
+    let aad_requests_copy = aad_requests.clone();
+    let access_tokens = Arc::new(AtomicUsize::new(0));
+    let registry_tokens = Arc::new(RwLock::new(vec![]));
+    let registry_tokens_copy = registry_tokens.clone();
+
+    // A refresh token that expires in an hour, so that it's cached
+    let claims = base64::encode_config(
+        &format!(r#"{{"exp":{}}}"#, chrono::Utc::now().timestamp() + 3600),
+        base64::URL_SAFE_NO_PAD,
+    );
+    let refresh_token = format!("eyJhbGciOiJSUzI1NiJ9.{}.c2ln", claims);
+
     let dispatch_table = routes!(
         GET "/networks" => default_get_networks_handler(),
         POST "/networks/create" => default_create_network_handler(),
-        POST "/containers/m1/stop" => container_stop_handler,
+        POST "/tenant1/oauth2/v2.0/token" => move |_| {
+            aad_requests_copy.fetch_add(1, Ordering::SeqCst);
+            json_response(StatusCode::OK, &json!({ "access_token": "aad-token" }))
+        },
+        POST "/oauth2/exchange" => move |_| {
+            json_response(StatusCode::OK, &json!({ "refresh_token": refresh_token }))
+        },
+        POST "/oauth2/token" => move |_| {
+            let n = access_tokens.fetch_add(1, Ordering::SeqCst) + 1;
+            json_response(
+                StatusCode::OK,
+                &json!({ "access_token": format!("pull-token-{}", n) }),
+            )
+        },
+        POST "/images/create" => move |req: Request<Body>| {
+            let auth = req.headers()["X-Registry-Auth"].to_str().unwrap();
+            let auth: JsonValue =
+                serde_json::from_slice(&base64::decode(auth).unwrap()).unwrap();
+            let token = auth["registrytoken"].as_str().unwrap().to_string();
+            registry_tokens_copy.write().unwrap().push(token.clone());
+
+            if token == "pull-token-1" {
+                json_response(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    &json!({
+                        "message": "Head https://myregistry.azurecr.io/v2/samples/module/manifests/1.0: unauthorized: authentication required"
+                    }),
+                )
+            } else {
+                json_response(StatusCode::OK, &json!({ "status": "Downloaded" }))
+            }
+        },
     );
 
     let (server, port) = run_tcp_server(
@@ -927,35 +939,46 @@ fn container_stop_succeeds() {
     );
     let server = server.map_err(|err| panic!(err));
 
+    let registry = format!("localhost:{}", port);
     let settings = make_settings(Some(json!({
         "moby_runtime": {
-            "uri": &format!("http://localhost:{}", port)
+            "uri": &format!("http://localhost:{}", port),
+            "acr_auth": {
+                registry.clone(): {
+                    "method": "service_principal",
+                    "tenant_id": "tenant1",
+                    "client_id": "client1",
+                    "client_secret": "hunter2",
+                    "authority_host": &format!("http://localhost:{}", port),
+                }
+            }
         }
     })));
 
+    let image = format!("{}/samples/module:1.0", registry);
     let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
-        .and_then(|runtime| runtime.stop("m1", None));
+        .and_then(move |runtime| {
+            let config = DockerConfig::new(image, ContainerCreateBody::new(), None).unwrap();
+            runtime.pull(&config)
+        });
 
     let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
     runtime.spawn(server);
     runtime.block_on(task).unwrap();
-}
-
-#[allow(clippy::needless_pass_by_value)]
-fn container_stop_with_timeout_handler(req: Request<Body>) -> ResponseFuture {
-    assert_eq!(req.method(), &Method::POST);
-    assert_eq!(req.uri().path(), "/containers/m1/stop");
-    assert_eq!(req.uri().query().unwrap(), "t=600");
 
-    Box::new(future::ok(Response::new(Body::empty())))
+    assert_eq!(
+        vec!["pull-token-1".to_string(), "pull-token-2".to_string()],
+        *registry_tokens.read().unwrap()
+    );
+    // The cached refresh token was dropped after the registry rejected its access token
+    assert_eq!(2, aad_requests.load(Ordering::SeqCst));
 }
 
 #[test]
-fn container_stop_with_timeout_succeeds() {
+fn image_pull_with_malformed_digest_fails() {
     let dispatch_table = routes!(
         GET "/networks" => default_get_networks_handler(),
         POST "/networks/create" => default_create_network_handler(),
-        POST "/containers/m1/stop" => container_stop_with_timeout_handler,
     );
 
     let (server, port) = run_tcp_server(
@@ -970,28 +993,71 @@ fn container_stop_with_timeout_succeeds() {
         }
     })));
 
+    let image = "nginx@sha256:not-a-digest";
     let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
-        .and_then(|runtime| runtime.stop("m1", Some(Duration::from_secs(600))));
+        .and_then(move |runtime| {
+            let config =
+                DockerConfig::new(image.to_string(), ContainerCreateBody::new(), None).unwrap();
+
+            runtime.pull(&config)
+        });
 
     let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
     runtime.spawn(server);
-    runtime.block_on(task).unwrap();
+    let err = runtime.block_on(task).unwrap_err();
+
+    match err.cause().and_then(Fail::downcast_ref) {
+        Some(ErrorKind::InvalidImage(name)) => assert_eq!(image, name),
+        _ => panic!("Expected `InvalidImage` error but got {:?}", err),
+    }
 }
 
 #[allow(clippy::needless_pass_by_value)]
-fn container_remove_handler(req: Request<Body>) -> ResponseFuture {
-    assert_eq!(req.method(), &Method::DELETE);
-    assert_eq!(req.uri().path(), "/containers/m1");
-
-    Box::new(future::ok(Response::new(Body::empty())))
-}
+// Pulls the image of a module as its pull policy allows and creates it, with
+// the image on the device or not. Returns the result of the create and the
+// number of times the image was pulled from the registry.
+fn create_with_pull_policy(policy: ImagePullPolicy, present: bool) -> (Result<(), Error>, usize) {
+    let pull_count = Arc::new(AtomicUsize::new(0));
+    let pull_count_cloned = pull_count.clone();
 
-#[test]
-fn container_remove_succeeds() {
     let dispatch_table = routes!(
         GET "/networks" => default_get_networks_handler(),
         POST "/networks/create" => default_create_network_handler(),
-        DELETE "/containers/m1" => container_remove_handler,
+        POST "/images/create" => move |_| {
+            pull_count.fetch_add(1, Ordering::SeqCst);
+            json_response(StatusCode::OK, &json!({ "status": "Downloaded" }))
+        },
+        GET "/images/nginx:latest/json" => move |_| {
+            if present {
+                json_response(
+                    StatusCode::OK,
+                    &json!({
+                        "Id": "img1",
+                        "RepoDigests": [],
+                        "Parent": "",
+                        "Comment": "",
+                        "Created": "2019-11-05T00:00:00Z",
+                        "Container": "",
+                        "DockerVersion": "19.03",
+                        "Author": "",
+                        "Architecture": "amd64",
+                        "Os": "linux",
+                        "Size": 0,
+                        "VirtualSize": 0,
+                        "GraphDriver": { "Name": "overlay2" },
+                        "RootFS": { "Type": "layers" },
+                    }),
+                )
+            } else {
+                json_response(
+                    StatusCode::NOT_FOUND,
+                    &json!({ "message": "No such image: nginx:latest" }),
+                )
+            }
+        },
+        POST "/containers/create" => |_| {
+            json_response(StatusCode::CREATED, &json!({ "Id": "m1", "Warnings": [] }))
+        },
     );
 
     let (server, port) = run_tcp_server(
@@ -1007,110 +1073,128 @@ fn container_remove_succeeds() {
     })));
 
     let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
-        .and_then(|runtime| ModuleRuntime::remove(&runtime, "m1"));
+        .and_then(move |runtime| {
+            let module = ModuleSpec::new(
+                "m1".to_string(),
+                "docker".to_string(),
+                DockerConfig::new(IMAGE_NAME.to_string(), ContainerCreateBody::new(), None)
+                    .unwrap(),
+                HashMap::new(),
+                policy,
+            )
+            .unwrap();
+
+            let create = runtime.clone();
+            pull_image(&runtime, &module).and_then(move |_| create.create(module))
+        });
 
     let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
     runtime.spawn(server);
-    runtime.block_on(task).unwrap();
+    let result = runtime.block_on(task);
+    (result, pull_count_cloned.load(Ordering::SeqCst))
 }
 
-#[allow(clippy::needless_pass_by_value)]
-fn container_list_handler(req: Request<Body>) -> ResponseFuture {
-    assert_eq!(req.method(), &Method::GET);
-    assert_eq!(req.uri().path(), "/containers/json");
-
-    let query_map: HashMap<String, String> = parse_query(req.uri().query().unwrap().as_bytes())
-        .into_owned()
-        .collect();
-    assert!(query_map.contains_key("filters"));
-    assert_eq!(
-        query_map.get("filters"),
-        Some(
-            &json!({
-                "label": vec!["net.azure-devices.edge.owner=Microsoft.Azure.Devices.Edge.Agent"]
-            })
-            .to_string()
-        )
-    );
+#[test]
+fn on_create_pull_policy_always_pulls() {
+    for &present in &[true, false] {
+        let (result, pull_count) = create_with_pull_policy(ImagePullPolicy::OnCreate, present);
+        result.unwrap();
+        assert_eq!(1, pull_count, "image present: {}", present);
+    }
+}
 
-    let mut labels = HashMap::new();
-    labels.insert("l1".to_string(), "v1".to_string());
-    labels.insert("l2".to_string(), "v2".to_string());
-    labels.insert("l3".to_string(), "v3".to_string());
+#[test]
+fn if_not_present_pull_policy_pulls_missing_image() {
+    let (result, pull_count) = create_with_pull_policy(ImagePullPolicy::IfNotPresent, true);
+    result.unwrap();
+    assert_eq!(0, pull_count);
+
+    let (result, pull_count) = create_with_pull_policy(ImagePullPolicy::IfNotPresent, false);
+    result.unwrap();
+    assert_eq!(1, pull_count);
+}
 
-    let modules = vec![
-        ContainerSummary::new(
-            "m1".to_string(),
-            vec!["/m1".to_string()],
-            "nginx:latest".to_string(),
-            "img1".to_string(),
-            "".to_string(),
-            10,
-            vec![],
-            10,
-            10,
-            labels.clone(),
-            "".to_string(),
-            "".to_string(),
-            ContainerHostConfig::new(""),
-            ContainerNetworkSettings::new(HashMap::new()),
-            vec![],
-        ),
-        ContainerSummary::new(
-            "m2".to_string(),
-            vec!["/m2".to_string()],
-            "ubuntu:latest".to_string(),
-            "img2".to_string(),
-            "".to_string(),
-            10,
-            vec![],
-            10,
-            10,
-            labels.clone(),
-            "".to_string(),
-            "".to_string(),
-            ContainerHostConfig::new(""),
-            ContainerNetworkSettings::new(HashMap::new()),
-            vec![],
-        ),
-        ContainerSummary::new(
-            "m3".to_string(),
-            vec!["/m3".to_string()],
-            "mongo:latest".to_string(),
-            "img3".to_string(),
-            "".to_string(),
-            10,
-            vec![],
-            10,
-            10,
-            labels,
-            "".to_string(),
-            "".to_string(),
-            ContainerHostConfig::new(""),
-            ContainerNetworkSettings::new(HashMap::new()),
-            vec![],
-        ),
-    ];
+#[test]
+fn never_pull_policy_needs_image_on_device() {
+    let (result, pull_count) = create_with_pull_policy(ImagePullPolicy::Never, true);
+    result.unwrap();
+    assert_eq!(0, pull_count);
+
+    let (result, pull_count) = create_with_pull_policy(ImagePullPolicy::Never, false);
+    assert_eq!(0, pull_count);
+    let err = result.unwrap_err();
+    match (err.kind(), err.cause().and_then(Fail::downcast_ref)) {
+        (
+            ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(name)),
+            Some(ErrorKind::ImageNotPresent(image)),
+        ) => {
+            assert_eq!("m1", name);
+            assert_eq!(IMAGE_NAME, image);
+        }
+        _ => panic!("Expected `ImageNotPresent` error but got {:?}", err),
+    }
+}
 
-    let response = serde_json::to_string(&modules).unwrap();
-    let response_len = response.len();
+type NetworkConnects = Arc<Mutex<Vec<(&'static str, JsonValue)>>>;
 
-    let mut response = Response::new(response.into());
-    response
-        .headers_mut()
-        .typed_insert(&ContentLength(response_len as u64));
-    response
-        .headers_mut()
-        .typed_insert(&ContentType(mime::APPLICATION_JSON));
-    Box::new(future::ok(response))
+fn make_connect_network_handler(
+    network: &'static str,
+    connects: NetworkConnects,
+) -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+    move |req| {
+        let connects = connects.clone();
+        Box::new(req.into_body().concat2().map(move |body| {
+            let body: JsonValue = serde_json::from_slice(&body).unwrap();
+            connects.lock().unwrap().push((network, body));
+            Response::new(Body::empty())
+        }))
+    }
 }
 
-#[test]
-fn container_list_succeeds() {
+// Creates a module that joins the sensors and backhaul networks, of which only
+// sensors exists. Returns the result of the create, the connect requests in
+// the order they were made, the number of networks that were created and
+// whether the container was removed.
+fn create_with_networks(
+    create_networks: bool,
+) -> (Result<(), Error>, NetworkConnects, usize, bool) {
+    let connects = NetworkConnects::default();
+    let create_count = Arc::new(AtomicUsize::new(0));
+    let removed = Arc::new(AtomicUsize::new(0));
+
     let dispatch_table = routes!(
-        GET "/networks" => default_get_networks_handler(),
-        POST "/networks/create" => default_create_network_handler(),
-        GET "/containers/json" => container_list_handler,
+        // Docker's name filter also matches backhaul-old, which isn't backhaul.
+        GET "/networks" => make_get_networks_handler(|| {
+            json!([
+                { "Name": "azure-iot-edge" },
+                { "Name": "sensors" },
+                { "Name": "backhaul-old" },
+            ])
+            .to_string()
+        }),
+        POST "/networks/create" => {
+            let create_count = create_count.clone();
+            make_create_network_handler(move |_| {
+                create_count.fetch_add(1, Ordering::SeqCst);
+            })
+        },
+        POST "/containers/create" => |_| {
+            json_response(StatusCode::CREATED, &json!({ "Id": "m1", "Warnings": [] }))
+        },
+        POST "/networks/sensors/connect" => make_connect_network_handler("sensors", connects.clone()),
+        POST "/networks/backhaul/connect" => make_connect_network_handler("backhaul", connects.clone()),
+        DELETE "/containers/m1" => {
+            let removed = removed.clone();
+            move |_| {
+                removed.fetch_add(1, Ordering::SeqCst);
+                Box::new(future::ok(
+                    Response::builder()
+                        .status(StatusCode::NO_CONTENT)
+                        .body(Body::default())
+                        .unwrap(),
+                )) as ResponseFuture
+            }
+        },
     );
 
     let (server, port) = run_tcp_server(
@@ -1121,77 +1205,2868 @@ fn container_list_succeeds() {
 
     let settings = make_settings(Some(json!({
         "moby_runtime": {
-            "uri": &format!("http://localhost:{}", port)
+            "uri": &format!("http://localhost:{}", port),
+            "create_networks": create_networks,
         }
     })));
 
     let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
-        .and_then(|runtime| runtime.list());
+        .and_then(|runtime| {
+            let module = ModuleSpec::new(
+                "m1".to_string(),
+                "docker".to_string(),
+                DockerConfig::new(IMAGE_NAME.to_string(), ContainerCreateBody::new(), None)
+                    .unwrap(),
+                HashMap::new(),
+                ImagePullPolicy::OnCreate,
+            )
+            .unwrap()
+            .with_networks(vec![
+                ModuleNetwork::new("sensors".to_string()).with_aliases(vec!["probe".to_string()]),
+                ModuleNetwork::new("backhaul".to_string()),
+            ]);
+
+            runtime.create(module)
+        });
 
     let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
     runtime.spawn(server);
-    let modules = runtime.block_on(task).unwrap();
-
-    assert_eq!(3, modules.len());
-
-    assert_eq!("m1", modules[0].name());
-    assert_eq!("m2", modules[1].name());
-    assert_eq!("m3", modules[2].name());
+    let result = runtime.block_on(task);
+    (
+        result,
+        connects,
+        create_count.load(Ordering::SeqCst),
+        removed.load(Ordering::SeqCst) > 0,
+    )
+}
 
-    assert_eq!("img1", modules[0].config().image_id().unwrap());
-    assert_eq!("img2", modules[1].config().image_id().unwrap());
-    assert_eq!("img3", modules[2].config().image_id().unwrap());
+#[test]
+fn container_create_joins_each_network() {
+    let (result, connects, create_count, removed) = create_with_networks(true);
+    result.unwrap();
 
-    assert_eq!("nginx:latest", modules[0].config().image());
-    assert_eq!("ubuntu:latest", modules[1].config().image());
-    assert_eq!("mongo:latest", modules[2].config().image());
+    assert_eq!(1, create_count);
+    assert!(!removed);
+    assert_eq!(
+        vec![
+            (
+                "sensors",
+                json!({ "Container": "m1", "EndpointConfig": { "Aliases": ["probe"] } }),
+            ),
+            (
+                "backhaul",
+                json!({ "Container": "m1", "EndpointConfig": {} })
+            ),
+        ],
+        *connects.lock().unwrap()
+    );
+}
 
-    for module in modules {
-        for i in 0..3 {
-            assert_eq!(
-                module
-                    .config()
-                    .create_options()
-                    .labels()
-                    .unwrap()
-                    .get(&format!("l{}", i + 1)),
-                Some(&format!("v{}", i + 1))
-            );
+#[test]
+fn container_create_with_missing_network_fails_and_removes_container() {
+    let (result, connects, create_count, removed) = create_with_networks(false);
+
+    assert_eq!(0, create_count);
+    assert!(removed);
+    assert_eq!(1, connects.lock().unwrap().len());
+    let err = result.unwrap_err();
+    match err.kind() {
+        ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(name)) => {
+            assert_eq!("m1", name);
         }
+        _ => panic!("Expected `CreateModule` error but got {:?}", err),
     }
-}
-
-#[allow(clippy::needless_pass_by_value)]
-fn container_logs_handler(req: Request<Body>) -> ResponseFuture {
-    assert_eq!(req.method(), &Method::GET);
-    assert_eq!(req.uri().path(), "/containers/mod1/logs");
+    let causes: Vec<_> = Fail::iter_chain(&err)
+        .skip(1)
+        .map(ToString::to_string)
+        .collect();
+    assert_eq!(
+        vec![
+            ErrorKind::JoinNetwork("backhaul".to_string()).to_string(),
+            ErrorKind::NetworkNotFound("backhaul".to_string()).to_string(),
+        ],
+        causes
+    );
+}
+
+// Pulls the image of a module first if `pull` is set, and creates the module,
+// with the container runtime not finding the image for the first `misses`
+// creates. Returns the result of the create and the number of pulls and
+// creates.
+fn create_with_image_misses(pull: bool, misses: usize) -> (Result<(), Error>, usize, usize) {
+    let pull_count = Arc::new(AtomicUsize::new(0));
+    let create_count = Arc::new(AtomicUsize::new(0));
+
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        POST "/images/create" => {
+            let pull_count = pull_count.clone();
+            move |_| {
+                pull_count.fetch_add(1, Ordering::SeqCst);
+                json_response(StatusCode::OK, &json!({ "status": "Downloaded" }))
+            }
+        },
+        POST "/containers/create" => {
+            let create_count = create_count.clone();
+            move |_| {
+                let attempt = create_count.fetch_add(1, Ordering::SeqCst);
+                if attempt < misses {
+                    json_response(
+                        StatusCode::NOT_FOUND,
+                        &json!({ "message": format!("No such image: nginx:latest ({})", attempt) }),
+                    )
+                } else {
+                    json_response(StatusCode::CREATED, &json!({ "Id": "m1", "Warnings": [] }))
+                }
+            }
+        },
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(move |runtime| {
+            let module = ModuleSpec::new(
+                "m1".to_string(),
+                "docker".to_string(),
+                DockerConfig::new(IMAGE_NAME.to_string(), ContainerCreateBody::new(), None)
+                    .unwrap(),
+                HashMap::new(),
+                ImagePullPolicy::OnCreate,
+            )
+            .unwrap();
+
+            let create = runtime.clone();
+            let pulled = if pull {
+                future::Either::A(pull_image(&runtime, &module))
+            } else {
+                future::Either::B(future::ok(false))
+            };
+            pulled.and_then(move |_| create.create(module))
+        });
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    let result = runtime.block_on(task);
+    (
+        result,
+        pull_count.load(Ordering::SeqCst),
+        create_count.load(Ordering::SeqCst),
+    )
+}
+
+#[test]
+fn container_create_missing_image_right_after_pull_is_retried() {
+    let (result, pull_count, create_count) = create_with_image_misses(true, 1);
+    result.unwrap();
+    assert_eq!(2, pull_count);
+    assert_eq!(2, create_count);
+}
+
+#[test]
+fn container_create_missing_image_fails_with_first_error_after_retry() {
+    let (result, pull_count, create_count) = create_with_image_misses(true, 2);
+    assert_eq!(2, pull_count);
+    assert_eq!(2, create_count);
+
+    let err = result.unwrap_err();
+    match (err.kind(), err.cause().and_then(Fail::downcast_ref)) {
+        (
+            ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(name)),
+            Some(ErrorKind::NotFound(message)),
+        ) => {
+            assert_eq!("m1", name);
+            assert_eq!("No such image: nginx:latest (0)", message);
+        }
+        _ => panic!("Expected `NotFound` error but got {:?}", err),
+    }
+}
+
+#[test]
+fn container_create_missing_image_without_pull_is_not_retried() {
+    let (result, pull_count, create_count) = create_with_image_misses(false, 1);
+    result.unwrap_err();
+    assert_eq!(0, pull_count);
+    assert_eq!(1, create_count);
+}
+
+fn image_pull_with_creds_handler(req: Request<Body>) -> ResponseFuture {
+    // verify that path is /images/create and that the "fromImage" query
+    // parameter has the image name we expect
+    assert_eq!(req.uri().path(), "/images/create");
 
     let query_map: HashMap<String, String> = parse_query(req.uri().query().unwrap().as_bytes())
         .into_owned()
         .collect();
-    assert!(query_map.contains_key("stdout"));
-    assert!(query_map.contains_key("stderr"));
-    assert!(query_map.contains_key("follow"));
-    assert!(query_map.contains_key("tail"));
-    assert_eq!("true", query_map["follow"]);
-    assert_eq!("all", query_map["tail"]);
-    assert_eq!("100000", query_map["since"]);
+    assert!(query_map.contains_key("fromImage"));
+    assert_eq!(query_map.get("fromImage"), Some(&IMAGE_NAME.to_string()));
 
-    let body = vec![
-        0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0d, 0x52, 0x6f, 0x73, 0x65, 0x73, 0x20, 0x61,
-        0x72, 0x65, 0x20, 0x72, 0x65, 0x64, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x76,
-        0x69, 0x6f, 0x6c, 0x65, 0x74, 0x73, 0x20, 0x61, 0x72, 0x65, 0x20, 0x62, 0x6c, 0x75, 0x65,
+    // verify registry creds
+    let auth_str = req
+        .headers()
+        .get_all("X-Registry-Auth")
+        .into_iter()
+        .map(|bytes| base64::decode(bytes).unwrap())
+        .map(|raw| str::from_utf8(&raw).unwrap().to_owned())
+        .collect::<String>();
+    let auth_config: AuthConfig = serde_json::from_str(&auth_str).unwrap();
+    assert_eq!(auth_config.username(), Some("u1"));
+    assert_eq!(auth_config.password(), Some("bleh"));
+    assert_eq!(auth_config.email(), Some("u1@bleh.com"));
+    assert_eq!(auth_config.serveraddress(), Some("svr1"));
+
+    let response = r#"
+    {
+        "Id": "img1",
+        "Warnings": []
+    }
+    "#;
+    let response_len = response.len();
+
+    let mut response = Response::new(response.into());
+    response
+        .headers_mut()
+        .typed_insert(&ContentLength(response_len as u64));
+    response
+        .headers_mut()
+        .typed_insert(&ContentType(mime::APPLICATION_JSON));
+    Box::new(future::ok(response))
+}
+
+#[test]
+fn image_pull_with_creds_succeeds() {
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        POST "/images/create" => image_pull_with_creds_handler,
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| {
+            let auth = AuthConfig::new()
+                .with_username("u1".to_string())
+                .with_password("bleh".to_string())
+                .with_email("u1@bleh.com".to_string())
+                .with_serveraddress("svr1".to_string());
+            let config = DockerConfig::new(
+                IMAGE_NAME.to_string(),
+                ContainerCreateBody::new(),
+                Some(auth),
+            )
+            .unwrap();
+
+            runtime.pull(&config)
+        });
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn image_remove_handler(req: Request<Body>) -> ResponseFuture {
+    assert_eq!(req.method(), &Method::DELETE);
+    assert_eq!(req.uri().path(), &format!("/images/{}", IMAGE_NAME));
+
+    let response = serde_json::to_string(&vec![
+        ImageDeleteResponseItem::new().with_deleted(IMAGE_NAME.to_string())
+    ])
+    .unwrap();
+    let response_len = response.len();
+
+    let mut response = Response::new(response.into());
+    response
+        .headers_mut()
+        .typed_insert(&ContentLength(response_len as u64));
+    response
+        .headers_mut()
+        .typed_insert(&ContentType(mime::APPLICATION_JSON));
+    Box::new(future::ok(response))
+}
+
+#[test]
+fn image_remove_succeeds() {
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        DELETE format!("/images/{}", IMAGE_NAME) => image_remove_handler,
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| ModuleRegistry::remove(&runtime, IMAGE_NAME));
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+}
+
+fn prune_container_list_handler(_req: Request<Body>) -> ResponseFuture {
+    let modules = vec![ContainerSummary::new(
+        "m1".to_string(),
+        vec!["/m1".to_string()],
+        "img-used:1.0".to_string(),
+        "sha256:used".to_string(),
+        "".to_string(),
+        10,
+        vec![],
+        10,
+        10,
+        HashMap::new(),
+        "".to_string(),
+        "".to_string(),
+        ContainerHostConfig::new(""),
+        ContainerNetworkSettings::new(HashMap::new()),
+        vec![],
+    )];
+
+    json_response(StatusCode::OK, &serde_json::to_value(&modules).unwrap())
+}
+
+fn prune_image_list_handler(_req: Request<Body>) -> ResponseFuture {
+    let images = vec![
+        ImageSummary::new(
+            "sha256:used".to_string(),
+            "".to_string(),
+            10,
+            1000,
+            0,
+            1000,
+            1,
+        )
+        .with_repo_tags(vec!["img-used:1.0".to_string()]),
+        ImageSummary::new(
+            "sha256:dangling".to_string(),
+            "".to_string(),
+            10,
+            300,
+            0,
+            300,
+            0,
+        ),
     ];
 
-    Box::new(future::ok(Response::new(body.into())))
+    json_response(StatusCode::OK, &serde_json::to_value(&images).unwrap())
 }
 
 #[test]
-fn container_logs_succeeds() {
+fn prune_images_preserves_image_in_use() {
+    let removed = Arc::new(Mutex::new(vec![]));
+    let removed_used = removed.clone();
+    let removed_dangling = removed.clone();
+
     let dispatch_table = routes!(
         GET "/networks" => default_get_networks_handler(),
         POST "/networks/create" => default_create_network_handler(),
-        GET "/containers/mod1/logs" => container_logs_handler,
+        GET "/containers/json" => prune_container_list_handler,
+        GET "/images/json" => prune_image_list_handler,
+        DELETE "/images/sha256:used" => move |_req| {
+            removed_used.lock().unwrap().push("sha256:used");
+            let body = json!({ "message": "image is being used by running container m1" });
+            json_response(StatusCode::CONFLICT, &body)
+        },
+        DELETE "/images/sha256:dangling" => move |_req| {
+            removed_dangling.lock().unwrap().push("sha256:dangling");
+            let deleted = vec![
+                ImageDeleteResponseItem::new().with_deleted("sha256:dangling".to_string())
+            ];
+            json_response(StatusCode::OK, &serde_json::to_value(&deleted).unwrap())
+        },
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| runtime.prune_images(ImagePruneFilter::Unused));
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    let pruned = runtime.block_on(task).unwrap();
+
+    assert_eq!(&["sha256:dangling".to_string()], pruned.images());
+    assert_eq!(300, pruned.space_reclaimed());
+    // The image in use isn't even asked to be removed.
+    assert_eq!(vec!["sha256:dangling"], *removed.lock().unwrap());
+}
+
+// Docker multiplexes the stdout (1) and stderr (2) of a command that doesn't
+// have a TTY into frames with an 8-byte header ending in the frame's length.
+fn exec_frame(stream: u8, data: &[u8]) -> Vec<u8> {
+    let mut frame = vec![stream, 0, 0, 0];
+    frame.extend_from_slice(&u32::try_from(data.len()).unwrap().to_be_bytes());
+    frame.extend_from_slice(data);
+    frame
+}
+
+fn exec(exit_code: i64) -> Vec<ExecOutput> {
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        POST "/containers/m1/exec" => |req: Request<Body>| -> ResponseFuture {
+            Box::new(req.into_body().concat2().and_then(|body| {
+                let config: JsonValue = serde_json::from_slice(&body).unwrap();
+                assert_eq!(json!(["sh", "-c", "ls /etc"]), config["Cmd"]);
+                assert_eq!(json!(["A=1"]), config["Env"]);
+                json_response(StatusCode::CREATED, &json!({ "Id": "e1" }))
+            }))
+        },
+        POST "/exec/e1/start" => |_req| -> ResponseFuture {
+            let mut body = exec_frame(1, b"bin\n");
+            body.extend(exec_frame(2, b"ls: etc: denied\n"));
+            Box::new(future::ok(Response::new(body.into())))
+        },
+        GET "/exec/e1/json" => move |_req| {
+            json_response(StatusCode::OK, &json!({ "ExitCode": exit_code, "Running": false }))
+        },
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let cmd = vec!["sh".to_string(), "-c".to_string(), "ls /etc".to_string()];
+    let env = vec![("A".to_string(), "1".to_string())]
+        .into_iter()
+        .collect();
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(move |runtime| runtime.exec("m1", cmd, env).collect());
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap()
+}
+
+#[test]
+fn exec_streams_output_and_exit_code() {
+    assert_eq!(
+        vec![
+            ExecOutput::Output("bin\n".into()),
+            ExecOutput::Output("ls: etc: denied\n".into()),
+            ExecOutput::Exited(0),
+        ],
+        exec(0)
+    );
+}
+
+#[test]
+fn exec_returns_non_zero_exit_code() {
+    assert_eq!(Some(&ExecOutput::Exited(2)), exec(2).last());
+}
+
+fn container_create_handler(req: Request<Body>) -> ResponseFuture {
+    assert_eq!(req.method(), &Method::POST);
+    assert_eq!(req.uri().path(), "/containers/create");
+
+    let response = json!({
+        "Id": "12345",
+        "Warnings": []
+    })
+    .to_string();
+    let response_len = response.len();
+
+    Box::new(
+        req.into_body()
+            .concat2()
+            .and_then(|body| {
+                let create_options: ContainerCreateBody =
+                    serde_json::from_slice(body.as_ref()).unwrap();
+
+                assert_eq!("nginx:latest", create_options.image().unwrap());
+
+                for &v in &["/do/the/custom/command", "with these args"] {
+                    assert!(create_options.cmd().unwrap().contains(&v.to_string()));
+                }
+
+                for &v in &["/also/do/the/entrypoint", "and this"] {
+                    assert!(create_options
+                        .entrypoint()
+                        .unwrap()
+                        .contains(&v.to_string()));
+                }
+
+                for &v in &["k1=v1", "k2=v2", "k3=v3", "k4=v4", "k5=v5"] {
+                    assert!(create_options.env().unwrap().contains(&v.to_string()));
+                }
+
+                let port_bindings = create_options
+                    .host_config()
+                    .unwrap()
+                    .port_bindings()
+                    .unwrap();
+                assert_eq!(
+                    "8080",
+                    port_bindings
+                        .get("80/tcp")
+                        .unwrap()
+                        .iter()
+                        .next()
+                        .unwrap()
+                        .host_port()
+                        .unwrap()
+                );
+                assert_eq!(
+                    "11022",
+                    port_bindings
+                        .get("22/tcp")
+                        .unwrap()
+                        .iter()
+                        .next()
+                        .unwrap()
+                        .host_port()
+                        .unwrap()
+                );
+
+                let volumes = create_options.volumes().unwrap();
+                let mut expected = ::std::collections::HashMap::new();
+                expected.insert("test1".to_string(), json!({}));
+                assert_eq!(*volumes, expected);
+
+                Ok(())
+            })
+            .map(move |_| {
+                let mut response = Response::new(response.into());
+                response
+                    .headers_mut()
+                    .typed_insert(&ContentLength(response_len as u64));
+                response
+                    .headers_mut()
+                    .typed_insert(&ContentType(mime::APPLICATION_JSON));
+                response
+            }),
+    )
+}
+
+#[test]
+fn container_create_succeeds() {
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        POST "/containers/create" => container_create_handler,
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| {
+            let mut env = HashMap::new();
+            env.insert("k1".to_string(), "v1".to_string());
+            env.insert("k2".to_string(), "v2".to_string());
+            env.insert("k3".to_string(), "v3".to_string());
+
+            // add some create options
+            let mut port_bindings = HashMap::new();
+            port_bindings.insert(
+                "22/tcp".to_string(),
+                vec![HostConfigPortBindings::new().with_host_port("11022".to_string())],
+            );
+            port_bindings.insert(
+                "80/tcp".to_string(),
+                vec![HostConfigPortBindings::new().with_host_port("8080".to_string())],
+            );
+            let memory: i64 = 3_221_225_472;
+            let mut volumes = ::std::collections::HashMap::new();
+            volumes.insert("test1".to_string(), json!({}));
+            let create_options = ContainerCreateBody::new()
+                .with_host_config(
+                    HostConfig::new()
+                        .with_port_bindings(port_bindings)
+                        .with_memory(memory),
+                )
+                .with_cmd(vec![
+                    "/do/the/custom/command".to_string(),
+                    "with these args".to_string(),
+                ])
+                .with_entrypoint(vec![
+                    "/also/do/the/entrypoint".to_string(),
+                    "and this".to_string(),
+                ])
+                .with_env(vec!["k4=v4".to_string(), "k5=v5".to_string()])
+                .with_volumes(volumes);
+
+            let module_config = ModuleSpec::new(
+                "m1".to_string(),
+                "docker".to_string(),
+                DockerConfig::new("nginx:latest".to_string(), create_options, None).unwrap(),
+                env,
+                ImagePullPolicy::default(),
+            )
+            .unwrap();
+
+            runtime.create(module_config)
+        });
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn container_start_handler(req: Request<Body>) -> ResponseFuture {
+    assert_eq!(req.method(), &Method::POST);
+    assert_eq!(req.uri().path(), "/containers/m1/start");
+
+    Box::new(future::ok(Response::new(Body::empty())))
+}
+
+#[test]
+fn container_start_succeeds() {
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        POST "/containers/m1/start" => container_start_handler,
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| runtime.start("m1"));
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn slow_container_start_handler(req: Request<Body>) -> ResponseFuture {
+    assert_eq!(req.method(), &Method::POST);
+    assert_eq!(req.uri().path(), "/containers/m1/start");
+
+    Box::new(
+        Delay::new(Instant::now() + Duration::from_secs(2))
+            .then(|_| Ok(Response::new(Body::empty()))),
+    )
+}
+
+#[test]
+fn container_start_times_out() {
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        POST "/containers/m1/start" => slow_container_start_handler,
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port),
+            "request_timeout": "100ms"
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| runtime.start("m1"));
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    let err = runtime.block_on(task).unwrap_err();
+
+    match err.kind() {
+        ErrorKind::RuntimeOperation(RuntimeOperation::StartModule(name)) => {
+            assert_eq!("m1", name)
+        }
+        kind => panic!("Expected `StartModule` error but got {:?}", kind),
+    }
+    match Fail::find_root_cause(&err).downcast_ref::<ErrorKind>() {
+        Some(ErrorKind::RuntimeTimeout(timeout)) => {
+            assert_eq!(Duration::from_millis(100), *timeout)
+        }
+        cause => panic!("Expected `RuntimeTimeout` but got {:?}", cause),
+    }
+}
+
+#[test]
+fn requests_reuse_idle_connections() {
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        POST "/containers/m1/start" => container_start_handler,
+    );
+    let dispatch = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+
+    // Like `run_tcp_server`, except that it counts the connections it accepts
+    let connections = Arc::new(AtomicUsize::new(0));
+    let serve = Http::new()
+        .serve_addr(&"127.0.0.1:0".parse().unwrap(), move || {
+            service_fn(dispatch.clone())
+        })
+        .unwrap();
+    let port = serve.incoming_ref().local_addr().port();
+    let connections_copy = connections.clone();
+    let server = serve
+        .for_each(move |connecting| {
+            connections_copy.fetch_add(1, Ordering::SeqCst);
+            connecting
+                .then(|connection| {
+                    let connection = connection.unwrap();
+                    Ok::<_, HyperError>(connection)
+                })
+                .flatten()
+        })
+        .map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| {
+            runtime
+                .start("m1")
+                .and_then(move |_| runtime.start("m1").map(|_| runtime))
+        })
+        .and_then(|runtime| runtime.start("m1"));
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+
+    assert_eq!(1, connections.load(Ordering::SeqCst));
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn container_stop_handler(req: Request<Body>) -> ResponseFuture {
+    assert_eq!(req.method(), &Method::POST);
+    assert_eq!(req.uri().path(), "/containers/m1/stop");
+
+    Box::new(future::ok(Response::new(Body::empty())))
+}
+
+#[test]
+fn container_stop_succeeds() {
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        GET "/containers/m1/json" => |_| json_response(StatusCode::OK, &json!({ "Id": "m1" })),
+        POST "/containers/m1/stop" => container_stop_handler,
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| runtime.stop("m1", None));
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn container_stop_with_timeout_handler(req: Request<Body>) -> ResponseFuture {
+    assert_eq!(req.method(), &Method::POST);
+    assert_eq!(req.uri().path(), "/containers/m1/stop");
+    assert_eq!(req.uri().query().unwrap(), "t=600");
+
+    Box::new(future::ok(Response::new(Body::empty())))
+}
+
+#[test]
+fn container_stop_with_timeout_succeeds() {
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        POST "/containers/m1/stop" => container_stop_with_timeout_handler,
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| runtime.stop("m1", Some(Duration::from_secs(600))));
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn container_stop_with_module_timeout_handler(req: Request<Body>) -> ResponseFuture {
+    assert_eq!(req.method(), &Method::POST);
+    assert_eq!(req.uri().path(), "/containers/m1/stop");
+    assert_eq!(req.uri().query().unwrap(), "t=45");
+
+    Box::new(future::ok(Response::new(Body::empty())))
+}
+
+#[test]
+fn container_stop_forwards_module_stop_timeout() {
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        GET "/containers/m1/json" => |_| json_response(
+            StatusCode::OK,
+            &json!({ "Id": "m1", "Config": { "StopTimeout": 45 } }),
+        ),
+        POST "/containers/m1/stop" => container_stop_with_module_timeout_handler,
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| runtime.stop("m1", None));
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+}
+
+// Stops m1, created with a pre-stop hook that takes `hook_duration` to run
+// and is given a second, and returns the requests that were made in order.
+fn stop_with_pre_stop_hook(hook_duration: Duration) -> Vec<&'static str> {
+    let requests = Arc::new(Mutex::new(vec![]));
+
+    let inspect_requests = requests.clone();
+    let exec_requests = requests.clone();
+    let stop_requests = requests.clone();
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        GET "/containers/m1/json" => move |_| {
+            inspect_requests.lock().unwrap().push("inspect");
+            let hook = json!({ "command": ["/app/drain"], "timeout_secs": 1 });
+            json_response(
+                StatusCode::OK,
+                &json!({
+                    "Id": "m1",
+                    "Config": {
+                        "StopTimeout": 45,
+                        "Labels": { PRE_STOP_LABEL: hook.to_string() },
+                    },
+                }),
+            )
+        },
+        POST "/containers/m1/exec" => |req: Request<Body>| -> ResponseFuture {
+            Box::new(req.into_body().concat2().and_then(|body| {
+                let config: JsonValue = serde_json::from_slice(&body).unwrap();
+                assert_eq!(json!(["/app/drain"]), config["Cmd"]);
+                json_response(StatusCode::CREATED, &json!({ "Id": "e1" }))
+            }))
+        },
+        POST "/exec/e1/start" => move |_req| -> ResponseFuture {
+            exec_requests.lock().unwrap().push("exec");
+            let output = Delay::new(Instant::now() + hook_duration)
+                .map(|()| exec_frame(1, b"drained\n"))
+                .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))
+                .into_stream();
+            Box::new(future::ok(Response::new(Body::wrap_stream(output))))
+        },
+        GET "/exec/e1/json" => |_req| {
+            json_response(StatusCode::OK, &json!({ "ExitCode": 0, "Running": false }))
+        },
+        POST "/containers/m1/stop" => move |req: Request<Body>| -> ResponseFuture {
+            assert_eq!(req.uri().query().unwrap(), "t=45");
+            stop_requests.lock().unwrap().push("stop");
+            Box::new(future::ok(Response::new(Body::empty())))
+        },
+    );
+    let dispatch = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+
+    // Like `run_tcp_server`, except that it serves its connections at the
+    // same time, so that the module can be stopped while the connection of a
+    // hook that timed out is still open.
+    let serve = Http::new()
+        .serve_addr(&"127.0.0.1:0".parse().unwrap(), move || {
+            service_fn(dispatch.clone())
+        })
+        .unwrap();
+    let port = serve.incoming_ref().local_addr().port();
+    let server = serve
+        .for_each(|connecting| {
+            let connection = connecting
+                .then(|connection| {
+                    let connection = connection.unwrap();
+                    Ok::<_, HyperError>(connection)
+                })
+                .flatten()
+                .map_err(|_| ());
+            tokio::spawn(connection);
+            Ok(())
+        })
+        .map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| runtime.stop("m1", None));
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+
+    let requests = requests.lock().unwrap();
+    requests.clone()
+}
+
+#[test]
+fn container_stop_runs_pre_stop_hook_first() {
+    assert_eq!(
+        vec!["inspect", "exec", "stop"],
+        stop_with_pre_stop_hook(Duration::from_millis(0))
+    );
+}
+
+#[test]
+fn container_stop_proceeds_after_pre_stop_hook_times_out() {
+    let started = Instant::now();
+
+    let requests = stop_with_pre_stop_hook(Duration::from_secs(60));
+
+    assert_eq!(vec!["inspect", "exec", "stop"], requests);
+    assert!(started.elapsed() < Duration::from_secs(30));
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn container_create_pre_stop_handler(req: Request<Body>) -> ResponseFuture {
+    Box::new(req.into_body().concat2().map(|body| {
+        let create_options: JsonValue = serde_json::from_slice(body.as_ref()).unwrap();
+        let hook: JsonValue =
+            serde_json::from_str(create_options["Labels"][PRE_STOP_LABEL].as_str().unwrap())
+                .unwrap();
+        assert_eq!(
+            json!({ "command": ["/app/drain", "--fast"], "timeout_secs": 20 }),
+            hook
+        );
+
+        let response = json!({ "Id": "12345", "Warnings": [] }).to_string();
+        Response::builder()
+            .status(StatusCode::CREATED)
+            .body(response.into())
+            .unwrap()
+    }))
+}
+
+#[test]
+fn create_labels_container_with_pre_stop_hook() {
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        POST "/containers/create" => container_create_pre_stop_handler,
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let hook = PreStopHook::new(vec!["/app/drain".to_string(), "--fast".to_string()])
+        .with_timeout(Duration::from_secs(20));
+    let module = resources_module_spec(ModuleResources::new()).with_pre_stop(Some(hook));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| runtime.create(module));
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn container_create_stop_signal_handler(req: Request<Body>) -> ResponseFuture {
+    Box::new(req.into_body().concat2().map(|body| {
+        let create_options: JsonValue = serde_json::from_slice(body.as_ref()).unwrap();
+        assert_eq!("SIGQUIT", create_options["StopSignal"]);
+
+        let response = json!({ "Id": "12345", "Warnings": [] }).to_string();
+        Response::builder()
+            .status(StatusCode::CREATED)
+            .body(response.into())
+            .unwrap()
+    }))
+}
+
+// Docker sends the container the stop signal it was created with whenever it
+// is stopped, including by the watchdog.
+#[test]
+fn create_sets_container_stop_signal() {
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        POST "/containers/create" => container_create_stop_signal_handler,
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let module =
+        resources_module_spec(ModuleResources::new()).with_stop_signal(Some("SIGQUIT".to_string()));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| runtime.create(module));
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+}
+
+#[cfg(unix)]
+#[allow(clippy::needless_pass_by_value)]
+fn container_create_identity_integration_handler(req: Request<Body>) -> ResponseFuture {
+    Box::new(req.into_body().concat2().map(|body| {
+        let create_options: JsonValue = serde_json::from_slice(body.as_ref()).unwrap();
+
+        let mut env: Vec<_> = create_options["Env"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|var| var.as_str().unwrap())
+            .collect();
+        env.sort();
+        assert_eq!(
+            vec![
+                "IOTEDGE_APIVERSION=2019-11-05",
+                "IOTEDGE_AUTHSCHEME=sasToken",
+                "IOTEDGE_DEVICEID=d1",
+                "IOTEDGE_GATEWAYHOSTNAME=parent",
+                "IOTEDGE_IOTHUBHOSTNAME=h1",
+                "IOTEDGE_MODULEID=m1",
+                "IOTEDGE_WORKLOADURI=unix:///var/run/iotedge/workload.sock",
+            ],
+            env
+        );
+        assert_eq!(
+            json!([{
+                "Type": "bind",
+                "Source": "/var/run/iotedge/workload.sock",
+                "Target": "/var/run/iotedge/workload.sock",
+                "ReadOnly": false,
+            }]),
+            create_options["HostConfig"]["Mounts"]
+        );
+
+        let response = json!({ "Id": "12345", "Warnings": [] }).to_string();
+        Response::builder()
+            .status(StatusCode::CREATED)
+            .body(response.into())
+            .unwrap()
+    }))
+}
+
+// The module's own gateway hostname is kept over the one it would be given.
+#[cfg(unix)]
+#[test]
+fn create_injects_identity_integration() {
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        POST "/containers/create" => container_create_identity_integration_handler,
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let mut env = HashMap::new();
+    env.insert("IOTEDGE_GATEWAYHOSTNAME".to_string(), "parent".to_string());
+    let module = resources_module_spec(ModuleResources::new())
+        .with_env(env)
+        .with_identity_integration(true);
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| runtime.create(module));
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+}
+
+#[test]
+fn container_inspect_is_retried_after_transport_error() {
+    let inspects = Arc::new(AtomicUsize::new(0));
+    let inspects_copy = inspects.clone();
+
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        GET "/containers/m1/json" => move |_| -> ResponseFuture {
+            if inspects_copy.fetch_add(1, Ordering::SeqCst) == 0 {
+                // The connection is closed in the middle of the answer.
+                let body = futures::stream::once::<Vec<u8>, _>(Err(std::io::Error::new(
+                    std::io::ErrorKind::ConnectionReset,
+                    "connection reset",
+                )));
+                Box::new(future::ok(Response::new(Body::wrap_stream(body))))
+            } else {
+                json_response(
+                    StatusCode::OK,
+                    &json!({ "Id": "m1", "Config": { "StopTimeout": 45 } }),
+                )
+            }
+        },
+        POST "/containers/m1/stop" => container_stop_with_module_timeout_handler,
+    );
+    let dispatch = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+
+    // Like `run_tcp_server`, except that it keeps serving after a connection
+    // fails
+    let serve = Http::new()
+        .serve_addr(&"127.0.0.1:0".parse().unwrap(), move || {
+            service_fn(dispatch.clone())
+        })
+        .unwrap();
+    let port = serve.incoming_ref().local_addr().port();
+    let server = serve
+        .for_each(|connecting| {
+            connecting
+                .then(|connection| {
+                    let connection = connection.unwrap();
+                    Ok::<_, HyperError>(connection)
+                })
+                .flatten()
+                .or_else(|_| Ok::<_, HyperError>(()))
+        })
+        .map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port),
+            "request_retry": { "backoff": "10ms" }
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| runtime.stop("m1", None));
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+
+    assert_eq!(2, inspects.load(Ordering::SeqCst));
+}
+
+#[test]
+fn container_create_conflict_is_not_retried() {
+    let creates = Arc::new(AtomicUsize::new(0));
+    let creates_copy = creates.clone();
+
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        POST "/containers/create" => move |_| {
+            creates_copy.fetch_add(1, Ordering::SeqCst);
+            json_response(
+                StatusCode::CONFLICT,
+                &json!({ "message": "Conflict. The container name \"/m1\" is already in use" }),
+            )
+        },
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port),
+            "request_retry": { "backoff": "10ms" }
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| {
+            let create_options = ContainerCreateBody::new()
+                .with_cmd(vec!["/run".to_string()])
+                .with_entrypoint(vec!["/bin/sh".to_string()]);
+            let module_config = ModuleSpec::new(
+                "m1".to_string(),
+                "docker".to_string(),
+                DockerConfig::new("nginx:latest".to_string(), create_options, None).unwrap(),
+                HashMap::new(),
+                ImagePullPolicy::default(),
+            )
+            .unwrap();
+
+            runtime.create(module_config)
+        });
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    let err = runtime.block_on(task).unwrap_err();
+
+    match Fail::find_root_cause(&err).downcast_ref::<ErrorKind>() {
+        Some(ErrorKind::Conflict) => (),
+        kind => panic!("Expected `Conflict` error but got {:?}", kind),
+    }
+    assert_eq!(1, creates.load(Ordering::SeqCst));
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn container_remove_handler(req: Request<Body>) -> ResponseFuture {
+    assert_eq!(req.method(), &Method::DELETE);
+    assert_eq!(req.uri().path(), "/containers/m1");
+
+    Box::new(future::ok(Response::new(Body::empty())))
+}
+
+#[test]
+fn container_remove_succeeds() {
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        DELETE "/containers/m1" => container_remove_handler,
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| ModuleRuntime::remove(&runtime, "m1"));
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+}
+
+#[test]
+fn container_force_remove_kills_stuck_container() {
+    let deletes = Arc::new(AtomicUsize::new(0));
+    let deletes_copy = deletes.clone();
+    let kills = Arc::new(AtomicUsize::new(0));
+    let kills_copy = kills.clone();
+
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        DELETE "/containers/m1" => move |req: Request<Body>| -> ResponseFuture {
+            let query_map: HashMap<String, String> =
+                parse_query(req.uri().query().unwrap().as_bytes())
+                    .into_owned()
+                    .collect();
+            assert_eq!(Some(&"true".to_string()), query_map.get("v"));
+            assert_eq!(Some(&"true".to_string()), query_map.get("force"));
+
+            // The running container is stuck until it's killed.
+            if deletes_copy.fetch_add(1, Ordering::SeqCst) == 0 {
+                json_response(
+                    StatusCode::CONFLICT,
+                    &json!({ "message": "removal of container m1 is already in progress" }),
+                )
+            } else {
+                Box::new(future::ok(Response::new(Body::empty())))
+            }
+        },
+        POST "/containers/m1/kill" => move |req: Request<Body>| -> ResponseFuture {
+            assert!(req.uri().query().unwrap().contains("signal=SIGKILL"));
+            kills_copy.fetch_add(1, Ordering::SeqCst);
+            Box::new(future::ok(
+                Response::builder()
+                    .status(StatusCode::NO_CONTENT)
+                    .body(Body::empty())
+                    .unwrap(),
+            ))
+        },
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| runtime.force_remove("m1"));
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+
+    assert_eq!(2, deletes.load(Ordering::SeqCst));
+    assert_eq!(1, kills.load(Ordering::SeqCst));
+}
+
+#[test]
+fn container_force_remove_of_absent_container_succeeds() {
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        DELETE "/containers/m1" => |_| {
+            json_response(
+                StatusCode::NOT_FOUND,
+                &json!({ "message": "No such container: m1" }),
+            )
+        },
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| runtime.force_remove("m1"));
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn container_list_handler(req: Request<Body>) -> ResponseFuture {
+    assert_eq!(req.method(), &Method::GET);
+    assert_eq!(req.uri().path(), "/containers/json");
+
+    let query_map: HashMap<String, String> = parse_query(req.uri().query().unwrap().as_bytes())
+        .into_owned()
+        .collect();
+    assert!(query_map.contains_key("filters"));
+    assert_eq!(
+        query_map.get("filters"),
+        Some(
+            &json!({
+                "label": vec!["net.azure-devices.edge.owner=Microsoft.Azure.Devices.Edge.Agent"]
+            })
+            .to_string()
+        )
+    );
+
+    let mut labels = HashMap::new();
+    labels.insert("l1".to_string(), "v1".to_string());
+    labels.insert("l2".to_string(), "v2".to_string());
+    labels.insert("l3".to_string(), "v3".to_string());
+
+    let modules = vec![
+        ContainerSummary::new(
+            "m1".to_string(),
+            vec!["/m1".to_string()],
+            "nginx:latest".to_string(),
+            "img1".to_string(),
+            "".to_string(),
+            10,
+            vec![],
+            10,
+            10,
+            labels.clone(),
+            "".to_string(),
+            "".to_string(),
+            ContainerHostConfig::new(""),
+            ContainerNetworkSettings::new(HashMap::new()),
+            vec![],
+        ),
+        ContainerSummary::new(
+            "m2".to_string(),
+            vec!["/m2".to_string()],
+            "ubuntu:latest".to_string(),
+            "img2".to_string(),
+            "".to_string(),
+            10,
+            vec![],
+            10,
+            10,
+            labels.clone(),
+            "".to_string(),
+            "".to_string(),
+            ContainerHostConfig::new(""),
+            ContainerNetworkSettings::new(HashMap::new()),
+            vec![],
+        ),
+        ContainerSummary::new(
+            "m3".to_string(),
+            vec!["/m3".to_string()],
+            "mongo:latest".to_string(),
+            "img3".to_string(),
+            "".to_string(),
+            10,
+            vec![],
+            10,
+            10,
+            labels,
+            "".to_string(),
+            "".to_string(),
+            ContainerHostConfig::new(""),
+            ContainerNetworkSettings::new(HashMap::new()),
+            vec![],
+        ),
+    ];
+
+    let response = serde_json::to_string(&modules).unwrap();
+    let response_len = response.len();
+
+    let mut response = Response::new(response.into());
+    response
+        .headers_mut()
+        .typed_insert(&ContentLength(response_len as u64));
+    response
+        .headers_mut()
+        .typed_insert(&ContentType(mime::APPLICATION_JSON));
+    Box::new(future::ok(response))
+}
+
+#[test]
+fn container_list_succeeds() {
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        GET "/containers/json" => container_list_handler,
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| runtime.list());
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    let modules = runtime.block_on(task).unwrap();
+
+    assert_eq!(3, modules.len());
+
+    assert_eq!("m1", modules[0].name());
+    assert_eq!("m2", modules[1].name());
+    assert_eq!("m3", modules[2].name());
+
+    assert_eq!("img1", modules[0].config().image_id().unwrap());
+    assert_eq!("img2", modules[1].config().image_id().unwrap());
+    assert_eq!("img3", modules[2].config().image_id().unwrap());
+
+    assert_eq!("nginx:latest", modules[0].config().image());
+    assert_eq!("ubuntu:latest", modules[1].config().image());
+    assert_eq!("mongo:latest", modules[2].config().image());
+
+    for module in modules {
+        for i in 0..3 {
+            assert_eq!(
+                module
+                    .config()
+                    .create_options()
+                    .labels()
+                    .unwrap()
+                    .get(&format!("l{}", i + 1)),
+                Some(&format!("v{}", i + 1))
+            );
+        }
+    }
+}
+
+fn container_summary(name: &str) -> ContainerSummary {
+    ContainerSummary::new(
+        name.to_string(),
+        vec![format!("/{}", name)],
+        "nginx:latest".to_string(),
+        "img1".to_string(),
+        "".to_string(),
+        10,
+        vec![],
+        10,
+        10,
+        HashMap::new(),
+        "".to_string(),
+        "".to_string(),
+        ContainerHostConfig::new(""),
+        ContainerNetworkSettings::new(HashMap::new()),
+        vec![],
+    )
+}
+
+fn list_with_container_prefix(prefix: Option<&str>) -> Vec<String> {
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        GET "/containers/json" => |_| {
+            json_response(
+                StatusCode::OK,
+                &json!(vec![
+                    container_summary("edge-m1"),
+                    container_summary("m2"),
+                    container_summary("edge-m3"),
+                ]),
+            )
+        },
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let mut moby_runtime = json!({ "uri": &format!("http://localhost:{}", port) });
+    if let Some(prefix) = prefix {
+        moby_runtime["container_name_prefix"] = json!(prefix);
+    }
+    let settings = make_settings(Some(json!({ "moby_runtime": moby_runtime })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| runtime.list());
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    let modules = runtime.block_on(task).unwrap();
+    modules
+        .iter()
+        .map(|module| module.name().to_string())
+        .collect()
+}
+
+#[test]
+fn container_list_is_scoped_to_container_prefix() {
+    assert_eq!(
+        vec!["m1".to_string(), "m3".to_string()],
+        list_with_container_prefix(Some("edge-"))
+    );
+}
+
+#[test]
+fn container_list_without_container_prefix_lists_all_modules() {
+    assert_eq!(
+        vec![
+            "edge-m1".to_string(),
+            "m2".to_string(),
+            "edge-m3".to_string()
+        ],
+        list_with_container_prefix(None)
+    );
+}
+
+#[test]
+fn container_prefix_is_used_for_module_containers() {
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        GET "/containers/edge-m1/json" => |_| {
+            json_response(
+                StatusCode::OK,
+                &json!({
+                    "Id": "0123",
+                    "Name": "/edge-m1",
+                    "Config": { "Image": "nginx:latest" },
+                    "State": { "Status": "running", "Running": true },
+                }),
+            )
+        },
+        POST "/containers/edge-m1/stop" => |_| -> ResponseFuture {
+            Box::new(future::ok(Response::new(Body::empty())))
+        },
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port),
+            "container_name_prefix": "edge-"
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| {
+            let get = runtime.clone();
+            runtime.stop("m1", None).and_then(move |()| get.get("m1"))
+        });
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    let (module, _) = runtime.block_on(task).unwrap();
+
+    assert_eq!("m1", module.name());
+}
+
+#[test]
+fn invalid_container_prefix_fails_initialization() {
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": "http://localhost:2375",
+            "container_name_prefix": "edge/"
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto());
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    assert!(runtime.block_on(task).is_err());
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn container_logs_handler(req: Request<Body>) -> ResponseFuture {
+    assert_eq!(req.method(), &Method::GET);
+    assert_eq!(req.uri().path(), "/containers/mod1/logs");
+
+    let query_map: HashMap<String, String> = parse_query(req.uri().query().unwrap().as_bytes())
+        .into_owned()
+        .collect();
+    assert!(query_map.contains_key("stdout"));
+    assert!(query_map.contains_key("stderr"));
+    assert!(query_map.contains_key("follow"));
+    assert!(query_map.contains_key("tail"));
+    assert_eq!("true", query_map["follow"]);
+    assert_eq!("all", query_map["tail"]);
+    assert_eq!("100000", query_map["since"]);
+    assert_eq!("false", query_map["timestamps"]);
+    assert!(!query_map.contains_key("until"));
+
+    let body = vec![
+        0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0d, 0x52, 0x6f, 0x73, 0x65, 0x73, 0x20, 0x61,
+        0x72, 0x65, 0x20, 0x72, 0x65, 0x64, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x76,
+        0x69, 0x6f, 0x6c, 0x65, 0x74, 0x73, 0x20, 0x61, 0x72, 0x65, 0x20, 0x62, 0x6c, 0x75, 0x65,
+    ];
+
+    Box::new(future::ok(Response::new(body.into())))
+}
+
+#[test]
+fn container_logs_succeeds() {
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        GET "/containers/mod1/logs" => container_logs_handler,
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| {
+            let options = LogOptions::new()
+                .with_follow(true)
+                .with_tail(LogTail::All)
+                .with_since(100_000);
+
+            runtime.logs("mod1", &options)
+        });
+
+    let expected_body = [
+        0x01_u8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0d, 0x52, 0x6f, 0x73, 0x65, 0x73, 0x20,
+        0x61, 0x72, 0x65, 0x20, 0x72, 0x65, 0x64, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10,
+        0x76, 0x69, 0x6f, 0x6c, 0x65, 0x74, 0x73, 0x20, 0x61, 0x72, 0x65, 0x20, 0x62, 0x6c, 0x75,
+        0x65,
+    ];
+
+    let assert = task.and_then(Stream::concat2).and_then(|b| {
+        assert_eq!(&expected_body[..], b.as_ref());
+        Ok(())
+    });
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(assert).unwrap();
+}
+
+// Writes a log line every few milliseconds until the client goes away, and
+// reports when the response is dropped.
+struct EndlessLogs {
+    interval: tokio::timer::Interval,
+    dropped: Option<oneshot::Sender<()>>,
+}
+
+impl Stream for EndlessLogs {
+    type Item = Vec<u8>;
+    type Error = HyperError;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        try_ready!(self
+            .interval
+            .poll()
+            .map_err(|err| -> HyperError { panic!("{}", err) }));
+        Ok(Async::Ready(Some(vec![
+            0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, b'A', b'\n',
+        ])))
+    }
+}
+
+impl Drop for EndlessLogs {
+    fn drop(&mut self) {
+        if let Some(dropped) = self.dropped.take() {
+            let _ = dropped.send(());
+        }
+    }
+}
+
+#[test]
+fn container_logs_dropped_closes_docker_connection() {
+    let (dropped, upstream_closed) = oneshot::channel();
+    let dropped = Arc::new(Mutex::new(Some(dropped)));
+    let logs_handler = move |req: Request<Body>| -> ResponseFuture {
+        let query_map: HashMap<String, String> = parse_query(req.uri().query().unwrap().as_bytes())
+            .into_owned()
+            .collect();
+        assert_eq!("true", query_map["timestamps"]);
+        let logs = EndlessLogs {
+            interval: tokio::timer::Interval::new_interval(Duration::from_millis(10)),
+            dropped: dropped.lock().unwrap().take(),
+        };
+        Box::new(future::ok(Response::new(Body::wrap_stream(logs))))
+    };
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        GET "/containers/mod1/logs" => logs_handler,
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| {
+            let options = LogOptions::new().with_follow(true).with_timestamps(true);
+            runtime.logs("mod1", &options)
+        })
+        .and_then(|logs| logs.into_future().map_err(|(err, _)| err))
+        .and_then(|(first, logs)| {
+            assert!(first.is_some());
+            drop(logs);
+            upstream_closed
+                .select2(Delay::new(Instant::now() + Duration::from_secs(5)))
+                .then(|result| match result {
+                    Ok(future::Either::A(_)) => Ok(()),
+                    _ => panic!("Docker connection was not closed after the logs were dropped"),
+                })
+        });
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+}
+
+#[test]
+fn image_remove_with_white_space_name_fails() {
+    let (server, port) = run_tcp_server("127.0.0.1", default_network_handler());
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+    let image_name = "     ";
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| ModuleRegistry::remove(&runtime, image_name))
+        .then(|res| match res {
+            Ok(_) => Err("Expected error but got a result.".to_string()),
+            Err(err) => match err.kind() {
+                ErrorKind::RegistryOperation(RegistryOperation::RemoveImage(s))
+                    if s == image_name =>
+                {
+                    Ok(())
+                }
+                kind => panic!(
+                    "Expected `RegistryOperation(RemoveImage)` error but got {:?}.",
+                    kind
+                ),
+            },
+        });
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+}
+
+#[test]
+fn create_fails_for_non_docker_type() {
+    let (server, port) = run_tcp_server("127.0.0.1", default_network_handler());
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+    let name = "not_docker";
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| {
+            let module_config = ModuleSpec::new(
+                "m1".to_string(),
+                name.to_string(),
+                DockerConfig::new("nginx:latest".to_string(), ContainerCreateBody::new(), None)
+                    .unwrap(),
+                HashMap::new(),
+                ImagePullPolicy::default(),
+            )
+            .unwrap();
+
+            runtime.create(module_config)
+        })
+        .then(|result| match result {
+            Ok(_) => panic!("Expected test to fail but it didn't!"),
+            Err(err) => match err.kind() {
+                ErrorKind::InvalidModuleType(s) if s == name => Ok::<_, Error>(()),
+                kind => panic!("Expected `InvalidModuleType` error but got {:?}.", kind),
+            },
+        });
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+}
+
+#[test]
+fn create_fails_for_invalid_module_spec() {
+    let (server, port) = run_tcp_server("127.0.0.1", default_network_handler());
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| {
+            let module_config = ModuleSpec::new(
+                "1 bad".to_string(),
+                "docker".to_string(),
+                DockerConfig::new("NGINX:latest".to_string(), ContainerCreateBody::new(), None)
+                    .unwrap(),
+                HashMap::new(),
+                ImagePullPolicy::default(),
+            )
+            .unwrap();
+
+            runtime.create(module_config)
+        })
+        .then(|result| match result {
+            Ok(_) => panic!("Expected test to fail but it didn't!"),
+            Err(err) => match err.kind() {
+                ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(s)) if s == "1 bad" => {
+                    Ok::<_, Error>(())
+                }
+                kind => panic!("Expected `RuntimeOperation` error but got {:?}.", kind),
+            },
+        });
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+}
+
+#[test]
+fn validate_fails_for_incomplete_registry_credentials() {
+    let (server, port) = run_tcp_server("127.0.0.1", default_network_handler());
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto()).map(
+        |runtime| {
+            let auth = AuthConfig::new()
+                .with_username("u1".to_string())
+                .with_serveraddress("registry.example.com".to_string());
+            let module_config = ModuleSpec::new(
+                "m1".to_string(),
+                "docker".to_string(),
+                DockerConfig::new(
+                    "registry.example.com/nginx:latest".to_string(),
+                    ContainerCreateBody::new(),
+                    Some(auth),
+                )
+                .unwrap(),
+                HashMap::new(),
+                ImagePullPolicy::default(),
+            )
+            .unwrap();
+
+            let err = runtime.validate(&module_config).unwrap_err();
+            match err
+                .cause()
+                .and_then(|cause| cause.downcast_ref::<ErrorKind>())
+            {
+                Some(ErrorKind::IncompleteRegistryCredentials(image)) => {
+                    assert_eq!("registry.example.com/nginx:latest", image)
+                }
+                cause => panic!(
+                    "Expected `IncompleteRegistryCredentials` error but got {:?}.",
+                    cause
+                ),
+            }
+        },
+    );
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+}
+
+fn resources_module_spec(resources: ModuleResources) -> ModuleSpec<DockerConfig> {
+    ModuleSpec::new(
+        "m1".to_string(),
+        "docker".to_string(),
+        DockerConfig::new("nginx:latest".to_string(), ContainerCreateBody::new(), None).unwrap(),
+        HashMap::new(),
+        ImagePullPolicy::default(),
+    )
+    .unwrap()
+    .with_resources(resources)
+}
+
+#[test]
+fn create_rejects_memory_limit_below_minimum() {
+    // No handler for /containers/create, the spec must be rejected before the API call
+    let (server, port) = run_tcp_server("127.0.0.1", default_network_handler());
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| {
+            runtime.create(resources_module_spec(
+                ModuleResources::new().with_memory_bytes(MIN_MEMORY_LIMIT_BYTES - 1),
+            ))
+        })
+        .then(|result| match result {
+            Ok(_) => panic!("Expected test to fail but it didn't!"),
+            Err(err) => {
+                assert!(Fail::find_root_cause(&err)
+                    .downcast_ref::<ValidationError>()
+                    .is_some());
+                Ok::<_, Error>(())
+            }
+        });
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn create_rejects_writable_sensitive_bind_with_strict_binds() {
+    // No handler for /containers/create, the spec must be rejected before the API call
+    let (server, port) = run_tcp_server("127.0.0.1", default_network_handler());
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port),
+            "strict_binds": true
+        }
+    })));
+
+    let module = resources_module_spec(ModuleResources::new()).with_binds(vec![ModuleBind::new(
+        "/etc".to_string(),
+        "/host-etc".to_string(),
+    )]);
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| runtime.create(module))
+        .then(|result| match result {
+            Ok(_) => panic!("Expected test to fail but it didn't!"),
+            Err(err) => {
+                let err = Fail::find_root_cause(&err)
+                    .downcast_ref::<ValidationError>()
+                    .unwrap();
+                assert_eq!(
+                    &[ModuleSpecViolation::WritableSensitiveBind(
+                        "/etc".to_string()
+                    )],
+                    err.violations()
+                );
+                Ok::<_, Error>(())
+            }
+        });
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+}
+
+#[test]
+fn create_rejects_unknown_create_option_with_strict_create_options() {
+    // No handler for /containers/create, the spec must be rejected before the API call
+    let (server, port) = run_tcp_server("127.0.0.1", default_network_handler());
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port),
+            "strict_create_options": true
+        }
+    })));
+
+    let config: DockerConfig = serde_json::from_value(json!({
+        "image": "nginx:latest",
+        "createOptions": {
+            "HostConfg": {
+                "Privileged": true
+            }
+        }
+    }))
+    .unwrap();
+    let module = ModuleSpec::new(
+        "m1".to_string(),
+        "docker".to_string(),
+        config,
+        HashMap::new(),
+        ImagePullPolicy::default(),
+    )
+    .unwrap();
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| runtime.create(module))
+        .then(|result| match result {
+            Ok(_) => panic!("Expected test to fail but it didn't!"),
+            Err(err) => {
+                let err = Fail::find_root_cause(&err)
+                    .downcast_ref::<ValidationError>()
+                    .unwrap();
+                assert_eq!(
+                    &[ModuleSpecViolation::UnknownCreateOption(
+                        "HostConfg".to_string()
+                    )],
+                    err.violations()
+                );
+                Ok::<_, Error>(())
+            }
+        });
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn container_create_resources_handler(req: Request<Body>) -> ResponseFuture {
+    Box::new(req.into_body().concat2().map(|body| {
+        let create_options: ContainerCreateBody = serde_json::from_slice(body.as_ref()).unwrap();
+        let host_config = create_options.host_config().unwrap();
+        assert_eq!(Some(512), host_config.cpu_shares());
+        assert_eq!(Some(64 * 1024 * 1024), host_config.memory());
+        assert_eq!(Some(-1), host_config.memory_swap());
+        assert_eq!(Some(100), host_config.pids_limit());
+
+        let body = json!({
+            "message": "Your kernel does not support pids limit capabilities or the cgroup is not mounted."
+        })
+        .to_string();
+        Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(body.into())
+            .unwrap()
+    }))
+}
+
+#[test]
+fn create_surfaces_rejected_resource_limit() {
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        POST "/containers/create" => container_create_resources_handler,
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| {
+            runtime.create(resources_module_spec(
+                ModuleResources::new()
+                    .with_cpu_shares(512)
+                    .with_memory_bytes(64 * 1024 * 1024)
+                    .with_memory_swap(-1)
+                    .with_pids_limit(100),
+            ))
+        })
+        .then(|result| match result {
+            Ok(_) => panic!("Expected test to fail but it didn't!"),
+            Err(err) => match Fail::find_root_cause(&err).downcast_ref::<ErrorKind>() {
+                Some(ErrorKind::InvalidResourceLimit(field, _)) => {
+                    assert_eq!("pids_limit", *field);
+                    Ok::<_, Error>(())
+                }
+                kind => panic!("Expected `InvalidResourceLimit` error but got {:?}.", kind),
+            },
+        });
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn container_create_secrets_handler(req: Request<Body>) -> ResponseFuture {
+    Box::new(req.into_body().concat2().map(|body| {
+        let create_options: ContainerCreateBody = serde_json::from_slice(body.as_ref()).unwrap();
+        let mut env = create_options.env().unwrap().to_vec();
+        env.sort();
+        assert_eq!(vec!["DB_PASSWORD=hunter2", "DB_USER=admin"], env);
+
+        let response = json!({ "Id": "12345", "Warnings": [] }).to_string();
+        Response::builder()
+            .status(StatusCode::CREATED)
+            .body(response.into())
+            .unwrap()
+    }))
+}
+
+#[cfg(unix)]
+#[test]
+fn create_resolves_secrets_in_env() {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    use tempdir::TempDir;
+
+    let tmp_dir = TempDir::new("secrets").unwrap();
+    let secrets_file = tmp_dir.path().join("secrets");
+    fs::write(&secrets_file, "db_password=hunter2\n").unwrap();
+    fs::set_permissions(&secrets_file, fs::Permissions::from_mode(0o600)).unwrap();
+
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        POST "/containers/create" => container_create_secrets_handler,
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port),
+            "secrets_file": secrets_file
+        }
+    })));
+
+    let mut env = HashMap::new();
+    env.insert("DB_USER".to_string(), "admin".to_string());
+    env.insert(
+        "DB_PASSWORD".to_string(),
+        "${SECRET:db_password}".to_string(),
+    );
+    let module = resources_module_spec(ModuleResources::new()).with_env(env);
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| runtime.create(module));
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+}
+
+fn container_create_global_env_handler(req: Request<Body>) -> ResponseFuture {
+    Box::new(req.into_body().concat2().map(|body| {
+        let create_options: ContainerCreateBody = serde_json::from_slice(body.as_ref()).unwrap();
+        let mut env = create_options.env().unwrap().to_vec();
+        env.sort();
+        assert_eq!(
+            vec![
+                "HTTPS_PROXY=http://proxy.example.com:3128",
+                "LOG_LEVEL=debug",
+                "NO_PROXY=localhost",
+                "TELEMETRY_KEY=hunter2",
+                "TELEMETRY_TAGS=createOptions",
+            ],
+            env
+        );
+
+        let response = json!({ "Id": "12345", "Warnings": [] }).to_string();
+        Response::builder()
+            .status(StatusCode::CREATED)
+            .body(response.into())
+            .unwrap()
+    }))
+}
+
+#[cfg(unix)]
+#[test]
+fn create_merges_global_env_under_module_env() {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    use tempdir::TempDir;
+
+    let tmp_dir = TempDir::new("secrets").unwrap();
+    let secrets_file = tmp_dir.path().join("secrets");
+    fs::write(&secrets_file, "telemetry_key=hunter2\n").unwrap();
+    fs::set_permissions(&secrets_file, fs::Permissions::from_mode(0o600)).unwrap();
+
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        POST "/containers/create" => container_create_global_env_handler,
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port),
+            "secrets_file": secrets_file,
+            "global_env": {
+                "HTTPS_PROXY": "http://proxy.example.com:3128",
+                "NO_PROXY": "*",
+                "LOG_LEVEL": "info",
+                "TELEMETRY_KEY": "${SECRET:telemetry_key}",
+                "TELEMETRY_TAGS": "global",
+            }
+        }
+    })));
+
+    // The module's env wins over the global env, and the Env of the create
+    // options over both.
+    let mut env = HashMap::new();
+    env.insert("NO_PROXY".to_string(), "localhost".to_string());
+    env.insert("LOG_LEVEL".to_string(), "debug".to_string());
+    let module = ModuleSpec::new(
+        "m1".to_string(),
+        "docker".to_string(),
+        DockerConfig::new(
+            "nginx:latest".to_string(),
+            ContainerCreateBody::new().with_env(vec!["TELEMETRY_TAGS=createOptions".to_string()]),
+            None,
+        )
+        .unwrap(),
+        env,
+        ImagePullPolicy::default(),
+    )
+    .unwrap();
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| runtime.create(module));
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+}
+
+#[test]
+fn module_names_are_registered_as_aliases_on_the_edge_network() {
+    let creates = Arc::new(Mutex::new(HashMap::new()));
+
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        POST "/containers/create" => {
+            let creates = creates.clone();
+            move |req: Request<Body>| {
+                let creates = creates.clone();
+                let name: HashMap<String, String> =
+                    parse_query(req.uri().query().unwrap().as_bytes())
+                        .into_owned()
+                        .collect();
+                let name = name["name"].clone();
+                Box::new(req.into_body().concat2().map(move |body| {
+                    let create_options: ContainerCreateBody =
+                        serde_json::from_slice(body.as_ref()).unwrap();
+                    creates.lock().unwrap().insert(name, create_options);
+                    Response::builder()
+                        .status(StatusCode::CREATED)
+                        .body(json!({ "Id": "12345", "Warnings": [] }).to_string().into())
+                        .unwrap()
+                })) as ResponseFuture
+            }
+        },
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    // The first module doesn't name a network, and the second one is put on
+    // the edge network under another alias.
+    let module = |name: &str, create_options: ContainerCreateBody| {
+        ModuleSpec::new(
+            name.to_string(),
+            "docker".to_string(),
+            DockerConfig::new("nginx:latest".to_string(), create_options, None).unwrap(),
+            HashMap::new(),
+            ImagePullPolicy::default(),
+        )
+        .unwrap()
+    };
+    let sensor: ContainerCreateBody = serde_json::from_value(json!({
+        "HostConfig": { "NetworkMode": "azure-iot-edge" },
+        "NetworkingConfig": {
+            "EndpointsConfig": { "azure-iot-edge": { "Aliases": ["probe"] } }
+        }
+    }))
+    .unwrap();
+    let modules = vec![
+        module("filter", ContainerCreateBody::new()),
+        module("sensor", sensor),
+    ];
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| {
+            future::join_all(
+                modules
+                    .into_iter()
+                    .map(move |module| runtime.create(module))
+                    .collect::<Vec<_>>(),
+            )
+        });
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+
+    let creates = creates.lock().unwrap();
+    let on_edge_network = |name: &str| {
+        let create_options = &creates[name];
+        let endpoints = create_options
+            .networking_config()
+            .unwrap()
+            .endpoints_config()
+            .unwrap();
+        (
+            create_options
+                .host_config()
+                .unwrap()
+                .network_mode()
+                .map(ToString::to_string),
+            endpoints["azure-iot-edge"].aliases().unwrap().to_vec(),
+        )
+    };
+    assert_eq!(
+        (
+            Some("azure-iot-edge".to_string()),
+            vec!["filter".to_string()]
+        ),
+        on_edge_network("filter")
+    );
+    assert_eq!(
+        (
+            Some("azure-iot-edge".to_string()),
+            vec!["probe".to_string(), "sensor".to_string()]
+        ),
+        on_edge_network("sensor")
+    );
+}
+
+#[test]
+fn invalid_global_env_fails_runtime_init() {
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": "http://localhost:2375",
+            "global_env": { "NO PROXY": "localhost" }
+        }
+    })));
+
+    let err = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .wait()
+        .unwrap_err();
+
+    match err.kind() {
+        ErrorKind::Initialization => (),
+        kind => panic!("Expected `Initialization` error but got {:?}", kind),
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn effective_spec_has_defaults_global_env_and_labels() {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    use tempdir::TempDir;
+
+    let tmp_dir = TempDir::new("secrets").unwrap();
+    let secrets_file = tmp_dir.path().join("secrets");
+    fs::write(&secrets_file, "telemetry_key=hunter2\n").unwrap();
+    fs::set_permissions(&secrets_file, fs::Permissions::from_mode(0o600)).unwrap();
+
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        POST "/containers/create" => |_: Request<Body>| -> ResponseFuture {
+            let response = json!({ "Id": "12345", "Warnings": [] }).to_string();
+            Box::new(future::ok(
+                Response::builder()
+                    .status(StatusCode::CREATED)
+                    .body(response.into())
+                    .unwrap(),
+            ))
+        },
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port),
+            "secrets_file": secrets_file,
+            "default_create_options": {
+                "User": "1000",
+                "HostConfig": { "CapDrop": ["ALL"] },
+            },
+            "global_env": {
+                "HTTPS_PROXY": "http://proxy.example.com:3128",
+                "LOG_LEVEL": "info",
+            },
+            "injected_labels": { "site": "plant-1" },
+        }
+    })));
+
+    let mut env = HashMap::new();
+    env.insert("LOG_LEVEL".to_string(), "debug".to_string());
+    env.insert(
+        "TELEMETRY_KEY".to_string(),
+        "${SECRET:telemetry_key}".to_string(),
+    );
+    let mut labels = HashMap::new();
+    labels.insert("group".to_string(), "sensors".to_string());
+    let auth = AuthConfig::new()
+        .with_username("user".to_string())
+        .with_password("password".to_string());
+    let module = ModuleSpec::new(
+        "m1".to_string(),
+        "docker".to_string(),
+        DockerConfig::new(
+            "nginx:latest".to_string(),
+            ContainerCreateBody::new().with_env(vec!["TAGS=createOptions".to_string()]),
+            Some(auth),
+        )
+        .unwrap(),
+        env,
+        ImagePullPolicy::default(),
+    )
+    .unwrap()
+    .with_labels(labels);
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| {
+            runtime
+                .create(module)
+                .and_then(move |()| runtime.effective_spec("m1"))
+        });
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    let spec = runtime.block_on(task).unwrap();
+
+    // The module's env over the global env, with the secret left unresolved.
+    let mut expected_env = HashMap::new();
+    expected_env.insert(
+        "HTTPS_PROXY".to_string(),
+        "http://proxy.example.com:3128".to_string(),
+    );
+    expected_env.insert("LOG_LEVEL".to_string(), "debug".to_string());
+    expected_env.insert(
+        "TELEMETRY_KEY".to_string(),
+        "${SECRET:telemetry_key}".to_string(),
+    );
+    assert_eq!(&expected_env, spec.env());
+
+    let create_options = spec.config().create_options();
+    let mut env = create_options.env().unwrap().to_vec();
+    env.sort();
+    assert_eq!(
+        vec![
+            "HTTPS_PROXY=http://proxy.example.com:3128",
+            "LOG_LEVEL=debug",
+            "TAGS=createOptions",
+            "TELEMETRY_KEY=${SECRET:telemetry_key}",
+        ],
+        env
+    );
+    assert_eq!(Some("1000"), create_options.user());
+    assert_eq!(
+        Some(&["ALL".to_string()][..]),
+        create_options.host_config().unwrap().cap_drop()
+    );
+    let labels = create_options.labels().unwrap();
+    assert_eq!("sensors", labels["group"]);
+    assert_eq!("plant-1", labels["site"]);
+    assert_eq!(
+        "Microsoft.Azure.Devices.Edge.Agent",
+        labels["net.azure-devices.edge.owner"]
+    );
+    assert_eq!(Some("***"), spec.config().auth().unwrap().password());
+    assert!(!serde_json::to_string(&spec).unwrap().contains("hunter2"));
+}
+
+#[test]
+fn effective_spec_of_unknown_module_is_not_found() {
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": { "uri": &format!("http://localhost:{}", port) }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| runtime.effective_spec("m1"));
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    let err = runtime.block_on(task).unwrap_err();
+
+    match Fail::find_root_cause(&err).downcast_ref::<ErrorKind>() {
+        Some(ErrorKind::NotFound(_)) => (),
+        kind => panic!("Expected `NotFound` error but got {:?}", kind),
+    }
+}
+
+fn container_create_env_files_handler(req: Request<Body>) -> ResponseFuture {
+    Box::new(req.into_body().concat2().map(|body| {
+        let create_options: ContainerCreateBody = serde_json::from_slice(body.as_ref()).unwrap();
+        let mut env = create_options.env().unwrap().to_vec();
+        env.sort();
+        assert_eq!(
+            vec![
+                "LOG_LEVEL=debug",
+                "REGION=west",
+                "SITE=plant-2",
+                "UPLINK=cellular"
+            ],
+            env
+        );
+
+        let response = json!({ "Id": "12345", "Warnings": [] }).to_string();
+        Response::builder()
+            .status(StatusCode::CREATED)
+            .body(response.into())
+            .unwrap()
+    }))
+}
+
+#[test]
+fn create_loads_env_files_under_inline_env() {
+    use std::fs;
+
+    use tempdir::TempDir;
+
+    let tmp_dir = TempDir::new("env_files").unwrap();
+    let common = tmp_dir.path().join("common.env");
+    let site = tmp_dir.path().join("site.env");
+    fs::write(
+        &common,
+        "# defaults for every device\nLOG_LEVEL=info\nREGION=west\nUPLINK=ethernet\n",
+    )
+    .unwrap();
+    fs::write(&site, "\nSITE=plant-2\nUPLINK=cellular\n").unwrap();
+
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        POST "/containers/create" => container_create_env_files_handler,
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    // The inline env wins over the env files, and later env files over
+    // earlier ones.
+    let mut env = HashMap::new();
+    env.insert("LOG_LEVEL".to_string(), "debug".to_string());
+    let module = resources_module_spec(ModuleResources::new())
+        .with_env(env)
+        .with_env_files(vec![common, site]);
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| runtime.create(module));
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn container_create_user_handler(req: Request<Body>) -> ResponseFuture {
+    Box::new(req.into_body().concat2().map(|body| {
+        let create_options: JsonValue = serde_json::from_slice(body.as_ref()).unwrap();
+        assert_eq!(json!("1000:1000"), create_options["User"]);
+        assert_eq!(json!("/app"), create_options["WorkingDir"]);
+
+        let response = json!({ "Id": "12345", "Warnings": [] }).to_string();
+        Response::builder()
+            .status(StatusCode::CREATED)
+            .body(response.into())
+            .unwrap()
+    }))
+}
+
+#[test]
+fn create_sets_user_and_working_dir() {
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        POST "/containers/create" => container_create_user_handler,
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let module = resources_module_spec(ModuleResources::new())
+        .with_user(Some("1000:1000".to_string()))
+        .with_working_dir(Some("/app".to_string()));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| runtime.create(module));
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+}
+
+#[test]
+fn create_rejects_image_without_command() {
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        GET "/images/nginx:latest/json" => |_| {
+            json_response(
+                StatusCode::OK,
+                &json!({
+                    "Id": "img1",
+                    "Parent": "",
+                    "Comment": "",
+                    "Created": "2019-11-05T00:00:00Z",
+                    "Container": "",
+                    "DockerVersion": "19.03",
+                    "Author": "",
+                    "Config": { "Cmd": null, "Entrypoint": null },
+                    "Architecture": "amd64",
+                    "Os": "linux",
+                    "Size": 0,
+                    "VirtualSize": 0,
+                    "GraphDriver": { "Name": "overlay2" },
+                    "RootFS": { "Type": "layers" },
+                }),
+            )
+        },
+        POST "/containers/create" => |_: Request<Body>| -> ResponseFuture {
+            panic!("container should not be created from an image without a command")
+        },
     );
 
     let (server, port) = run_tcp_server(
@@ -1207,34 +4082,129 @@ fn container_logs_succeeds() {
     })));
 
     let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
-        .and_then(|runtime| {
-            let options = LogOptions::new()
-                .with_follow(true)
-                .with_tail(LogTail::All)
-                .with_since(100_000);
+        .and_then(|runtime| runtime.create(resources_module_spec(ModuleResources::new())));
 
-            runtime.logs("mod1", &options)
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    let err = runtime.block_on(task).unwrap_err();
+
+    match Fail::find_root_cause(&err).downcast_ref::<ErrorKind>() {
+        Some(ErrorKind::ImageHasNoCommand(image)) => assert_eq!("nginx:latest", image),
+        kind => panic!("Expected `ImageHasNoCommand` error but got {:?}.", kind),
+    }
+}
+
+#[test]
+fn create_fails_for_missing_env_file() {
+    use tempdir::TempDir;
+
+    let tmp_dir = TempDir::new("env_files").unwrap();
+    let missing = tmp_dir.path().join("missing.env");
+
+    let (server, port) = run_tcp_server("127.0.0.1", default_network_handler());
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let module =
+        resources_module_spec(ModuleResources::new()).with_env_files(vec![missing.clone()]);
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| runtime.create(module));
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    let err = runtime.block_on(task).unwrap_err();
+
+    match err.kind() {
+        ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(name)) => {
+            assert_eq!("m1", name)
+        }
+        _ => panic!("Expected `CreateModule` error but got {:?}", err),
+    }
+    let message = Fail::iter_chain(&err)
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    assert!(
+        message.contains(&format!(
+            "Could not read the env file {:?}",
+            missing.display().to_string()
+        )),
+        "{:?}",
+        message
+    );
+}
+
+#[test]
+fn start_fails_for_empty_id() {
+    let (server, port) = run_tcp_server("127.0.0.1", default_network_handler());
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+    let name = "";
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| runtime.start(name))
+        .then(|result| match result {
+            Ok(_) => panic!("Expected test to fail but it didn't!"),
+            Err(err) => match err.kind() {
+                ErrorKind::RuntimeOperation(RuntimeOperation::StartModule(s)) if s == name => {
+                    Ok::<_, Error>(())
+                }
+                kind => panic!(
+                    "Expected `RuntimeOperation(StartModule)` error but got {:?}.",
+                    kind
+                ),
+            },
         });
 
-    let expected_body = [
-        0x01_u8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0d, 0x52, 0x6f, 0x73, 0x65, 0x73, 0x20,
-        0x61, 0x72, 0x65, 0x20, 0x72, 0x65, 0x64, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10,
-        0x76, 0x69, 0x6f, 0x6c, 0x65, 0x74, 0x73, 0x20, 0x61, 0x72, 0x65, 0x20, 0x62, 0x6c, 0x75,
-        0x65,
-    ];
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+}
 
-    let assert = task.and_then(Stream::concat2).and_then(|b| {
-        assert_eq!(&expected_body[..], b.as_ref());
-        Ok(())
-    });
+#[test]
+fn start_fails_for_white_space_id() {
+    let (server, port) = run_tcp_server("127.0.0.1", default_network_handler());
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+    let name = "      ";
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| runtime.start(name))
+        .then(|result| match result {
+            Ok(_) => panic!("Expected test to fail but it didn't!"),
+            Err(err) => match err.kind() {
+                ErrorKind::RuntimeOperation(RuntimeOperation::StartModule(s)) if s == name => {
+                    Ok::<_, Error>(())
+                }
+                kind => panic!(
+                    "Expected `RuntimeOperation(StartModule)` error but got {:?}.",
+                    kind
+                ),
+            },
+        });
 
     let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
     runtime.spawn(server);
-    runtime.block_on(assert).unwrap();
+    runtime.block_on(task).unwrap();
 }
 
 #[test]
-fn image_remove_with_white_space_name_fails() {
+fn stop_fails_for_empty_id() {
     let (server, port) = run_tcp_server("127.0.0.1", default_network_handler());
     let server = server.map_err(|err| panic!(err));
 
@@ -1243,20 +4213,18 @@ fn image_remove_with_white_space_name_fails() {
             "uri": &format!("http://localhost:{}", port)
         }
     })));
-    let image_name = "     ";
+    let name = "";
 
     let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
-        .and_then(|runtime| ModuleRegistry::remove(&runtime, image_name))
-        .then(|res| match res {
-            Ok(_) => Err("Expected error but got a result.".to_string()),
+        .and_then(|runtime| runtime.stop(name, None))
+        .then(|result| match result {
+            Ok(_) => panic!("Expected test to fail but it didn't!"),
             Err(err) => match err.kind() {
-                ErrorKind::RegistryOperation(RegistryOperation::RemoveImage(s))
-                    if s == image_name =>
-                {
-                    Ok(())
+                ErrorKind::RuntimeOperation(RuntimeOperation::StopModule(s)) if s == name => {
+                    Ok::<_, Error>(())
                 }
                 kind => panic!(
-                    "Expected `RegistryOperation(RemoveImage)` error but got {:?}.",
+                    "Expected `RuntimeOperation(StopModule)` error but got {:?}.",
                     kind
                 ),
             },
@@ -1268,7 +4236,7 @@ fn image_remove_with_white_space_name_fails() {
 }
 
 #[test]
-fn create_fails_for_non_docker_type() {
+fn stop_fails_for_white_space_id() {
     let (server, port) = run_tcp_server("127.0.0.1", default_network_handler());
     let server = server.map_err(|err| panic!(err));
 
@@ -1277,27 +4245,52 @@ fn create_fails_for_non_docker_type() {
             "uri": &format!("http://localhost:{}", port)
         }
     })));
-    let name = "not_docker";
+    let name = "     ";
 
     let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
-        .and_then(|runtime| {
-            let module_config = ModuleSpec::new(
-                "m1".to_string(),
-                name.to_string(),
-                DockerConfig::new("nginx:latest".to_string(), ContainerCreateBody::new(), None)
-                    .unwrap(),
-                HashMap::new(),
-                ImagePullPolicy::default(),
-            )
-            .unwrap();
+        .and_then(|runtime| runtime.stop(name, None))
+        .then(|result| match result {
+            Ok(_) => panic!("Expected test to fail but it didn't!"),
+            Err(err) => match err.kind() {
+                ErrorKind::RuntimeOperation(RuntimeOperation::StopModule(s)) if s == name => {
+                    Ok::<_, Error>(())
+                }
+                kind => panic!(
+                    "Expected `RuntimeOperation(StopModule)` error but got {:?}.",
+                    kind
+                ),
+            },
+        });
 
-            runtime.create(module_config)
-        })
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+}
+
+#[test]
+fn restart_fails_for_empty_id() {
+    let (server, port) = run_tcp_server("127.0.0.1", default_network_handler());
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+    let name = "";
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| runtime.restart(name))
         .then(|result| match result {
             Ok(_) => panic!("Expected test to fail but it didn't!"),
             Err(err) => match err.kind() {
-                ErrorKind::InvalidModuleType(s) if s == name => Ok::<_, Error>(()),
-                kind => panic!("Expected `InvalidModuleType` error but got {:?}.", kind),
+                ErrorKind::RuntimeOperation(RuntimeOperation::RestartModule(s)) if s == name => {
+                    Ok::<_, Error>(())
+                }
+                kind => panic!(
+                    "Expected `RuntimeOperation(RestartModule)` error but got {:?}.",
+                    kind
+                ),
             },
         });
 
@@ -1307,7 +4300,7 @@ fn create_fails_for_non_docker_type() {
 }
 
 #[test]
-fn start_fails_for_empty_id() {
+fn restart_fails_for_white_space_id() {
     let (server, port) = run_tcp_server("127.0.0.1", default_network_handler());
     let server = server.map_err(|err| panic!(err));
 
@@ -1316,63 +4309,297 @@ fn start_fails_for_empty_id() {
             "uri": &format!("http://localhost:{}", port)
         }
     })));
-    let name = "";
+    let name = "      ";
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| runtime.restart(name))
+        .then(|result| match result {
+            Ok(_) => panic!("Expected test to fail but it didn't!"),
+            Err(err) => match err.kind() {
+                ErrorKind::RuntimeOperation(RuntimeOperation::RestartModule(s)) if s == name => {
+                    Ok::<_, Error>(())
+                }
+                kind => panic!(
+                    "Expected `RuntimeOperation(RestartModule)` error but got {:?}.",
+                    kind
+                ),
+            },
+        });
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+}
+
+// Restarts m1 twice and starts it while it restarts, like the watchdog would on
+// finding it stopped. Returns the number of times m1 was stopped and started.
+fn restart_m1(running: bool) -> (usize, usize) {
+    let stops = Arc::new(AtomicUsize::new(0));
+    let stops_copy = stops.clone();
+    let starts = Arc::new(AtomicUsize::new(0));
+    let starts_copy = starts.clone();
+
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        GET "/containers/m1/json" => move |_| {
+            let status = if running { "running" } else { "exited" };
+            json_response(
+                StatusCode::OK,
+                &json!({ "Id": "m1", "State": { "Status": status, "Running": running } }),
+            )
+        },
+        POST "/containers/m1/stop" => move |_| -> ResponseFuture {
+            stops_copy.fetch_add(1, Ordering::SeqCst);
+            Box::new(future::ok(Response::new(Body::empty())))
+        },
+        POST "/containers/m1/start" => move |_| -> ResponseFuture {
+            starts_copy.fetch_add(1, Ordering::SeqCst);
+            Box::new(future::ok(Response::new(Body::empty())))
+        },
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| {
+            let restart = runtime.restart("m1");
+            let again = runtime.restart("m1");
+            let start = runtime.start("m1");
+            restart.join3(again, start)
+        });
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+
+    (stops.load(Ordering::SeqCst), starts.load(Ordering::SeqCst))
+}
+
+#[test]
+fn restart_of_stopped_module_only_starts_it() {
+    assert_eq!((0, 1), restart_m1(false));
+}
+
+#[test]
+fn restart_of_running_module_cycles_it_once() {
+    assert_eq!((1, 1), restart_m1(true));
+}
+
+#[test]
+fn labels_round_trip_through_create_and_get() {
+    let container_labels = Arc::new(RwLock::new(JsonValue::Null));
+    let create_labels = container_labels.clone();
+    let inspect_labels = container_labels.clone();
+
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        POST "/containers/create" => move |req: Request<Body>| -> ResponseFuture {
+            let create_labels = create_labels.clone();
+            Box::new(req.into_body().concat2().and_then(move |body| {
+                let body: JsonValue = serde_json::from_slice(&body).unwrap();
+                *create_labels.write().unwrap() = body["Labels"].clone();
+                json_response(StatusCode::CREATED, &json!({ "Id": "m1", "Warnings": [] }))
+            }))
+        },
+        GET "/containers/m1/json" => move |_| {
+            let labels = inspect_labels.read().unwrap().clone();
+            json_response(
+                StatusCode::OK,
+                &json!({
+                    "Id": "m1",
+                    "Name": "/m1",
+                    "Config": { "Image": "nginx:latest", "Labels": labels },
+                    "State": { "Status": "running", "Running": true },
+                }),
+            )
+        },
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
 
     let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
-        .and_then(|runtime| runtime.start(name))
-        .then(|result| match result {
-            Ok(_) => panic!("Expected test to fail but it didn't!"),
-            Err(err) => match err.kind() {
-                ErrorKind::RuntimeOperation(RuntimeOperation::StartModule(s)) if s == name => {
-                    Ok::<_, Error>(())
-                }
-                kind => panic!(
-                    "Expected `RuntimeOperation(StartModule)` error but got {:?}.",
-                    kind
-                ),
-            },
+        .and_then(|runtime| {
+            let mut create_option_labels = HashMap::new();
+            create_option_labels.insert("from".to_string(), "createOptions".to_string());
+            let mut labels = HashMap::new();
+            labels.insert("group".to_string(), "sensors".to_string());
+            labels.insert("tier".to_string(), "edge".to_string());
+
+            let module = ModuleSpec::new(
+                "m1".to_string(),
+                "docker".to_string(),
+                DockerConfig::new(
+                    "nginx:latest".to_string(),
+                    ContainerCreateBody::new().with_labels(create_option_labels),
+                    None,
+                )
+                .unwrap(),
+                HashMap::new(),
+                ImagePullPolicy::default(),
+            )
+            .unwrap()
+            .with_labels(labels);
+            let spec_labels = module.spec_labels().unwrap();
+
+            let get = runtime.clone();
+            runtime
+                .create(module)
+                .and_then(move |()| get.get("m1"))
+                .map(|(_, state)| (state, spec_labels))
         });
 
     let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
     runtime.spawn(server);
-    runtime.block_on(task).unwrap();
+    let (state, spec_labels) = runtime.block_on(task).unwrap();
+
+    // The container records the spec that it was created from.
+    let labels = state.labels();
+    assert_eq!(6, labels.len());
+    assert_eq!(spec_labels[SPEC_HASH_LABEL], labels[SPEC_HASH_LABEL]);
+    assert_eq!(spec_labels[SPEC_FIELDS_LABEL], labels[SPEC_FIELDS_LABEL]);
+    assert_eq!("sensors", labels["group"]);
+    assert_eq!("edge", labels["tier"]);
+    assert_eq!("createOptions", labels["from"]);
+    assert_eq!(
+        "Microsoft.Azure.Devices.Edge.Agent",
+        labels["net.azure-devices.edge.owner"]
+    );
 }
 
 #[test]
-fn start_fails_for_white_space_id() {
-    let (server, port) = run_tcp_server("127.0.0.1", default_network_handler());
+fn injected_labels_do_not_clobber_module_labels() {
+    let container_labels = Arc::new(RwLock::new(JsonValue::Null));
+    let create_labels = container_labels.clone();
+    let inspect_labels = container_labels.clone();
+
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        POST "/containers/create" => move |req: Request<Body>| -> ResponseFuture {
+            let create_labels = create_labels.clone();
+            Box::new(req.into_body().concat2().and_then(move |body| {
+                let body: JsonValue = serde_json::from_slice(&body).unwrap();
+                *create_labels.write().unwrap() = body["Labels"].clone();
+                json_response(StatusCode::CREATED, &json!({ "Id": "m1", "Warnings": [] }))
+            }))
+        },
+        GET "/containers/m1/json" => move |_| {
+            let labels = inspect_labels.read().unwrap().clone();
+            json_response(
+                StatusCode::OK,
+                &json!({
+                    "Id": "m1",
+                    "Name": "/m1",
+                    "Config": { "Image": "nginx:latest", "Labels": labels },
+                    "State": { "Status": "running", "Running": true },
+                }),
+            )
+        },
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
     let server = server.map_err(|err| panic!(err));
 
     let settings = make_settings(Some(json!({
         "moby_runtime": {
-            "uri": &format!("http://localhost:{}", port)
+            "uri": &format!("http://localhost:{}", port),
+            "injected_labels": {
+                "net.azure-devices.edge.deployment": "d1",
+                "net.azure-devices.edge.owner": "someone else",
+                "group": "fleet",
+                "from": "injected",
+            }
         }
     })));
-    let name = "      ";
 
     let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
-        .and_then(|runtime| runtime.start(name))
-        .then(|result| match result {
-            Ok(_) => panic!("Expected test to fail but it didn't!"),
-            Err(err) => match err.kind() {
-                ErrorKind::RuntimeOperation(RuntimeOperation::StartModule(s)) if s == name => {
-                    Ok::<_, Error>(())
-                }
-                kind => panic!(
-                    "Expected `RuntimeOperation(StartModule)` error but got {:?}.",
-                    kind
-                ),
-            },
+        .and_then(|runtime| {
+            let mut create_option_labels = HashMap::new();
+            create_option_labels.insert("from".to_string(), "createOptions".to_string());
+            let mut labels = HashMap::new();
+            labels.insert("group".to_string(), "sensors".to_string());
+
+            let module = ModuleSpec::new(
+                "m1".to_string(),
+                "docker".to_string(),
+                DockerConfig::new(
+                    "nginx:latest".to_string(),
+                    ContainerCreateBody::new().with_labels(create_option_labels),
+                    None,
+                )
+                .unwrap(),
+                HashMap::new(),
+                ImagePullPolicy::default(),
+            )
+            .unwrap()
+            .with_labels(labels);
+
+            let get = runtime.clone();
+            runtime.create(module).and_then(move |()| get.get("m1"))
         });
 
     let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
     runtime.spawn(server);
-    runtime.block_on(task).unwrap();
+    let (_, state) = runtime.block_on(task).unwrap();
+
+    let labels = state.labels();
+    assert_eq!(6, labels.len());
+    assert_eq!("sensors", labels["group"]);
+    assert_eq!("createOptions", labels["from"]);
+    assert_eq!("d1", labels["net.azure-devices.edge.deployment"]);
+    assert_eq!(
+        "Microsoft.Azure.Devices.Edge.Agent",
+        labels["net.azure-devices.edge.owner"]
+    );
 }
 
 #[test]
-fn stop_fails_for_empty_id() {
-    let (server, port) = run_tcp_server("127.0.0.1", default_network_handler());
+fn create_start_stop_publishes_ordered_events() {
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        POST "/containers/create" => |_| json_response(
+            StatusCode::CREATED,
+            &json!({ "Id": "m1", "Warnings": [] }),
+        ),
+        POST "/containers/m1/start" => |_| -> ResponseFuture {
+            Box::new(future::ok(Response::new(Body::empty())))
+        },
+        GET "/containers/m1/json" => |_| json_response(StatusCode::OK, &json!({ "Id": "m1" })),
+        POST "/containers/m1/stop" => |_| -> ResponseFuture {
+            Box::new(future::ok(Response::new(Body::empty())))
+        },
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
     let server = server.map_err(|err| panic!(err));
 
     let settings = make_settings(Some(json!({
@@ -1380,31 +4607,71 @@ fn stop_fails_for_empty_id() {
             "uri": &format!("http://localhost:{}", port)
         }
     })));
-    let name = "";
 
     let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
-        .and_then(|runtime| runtime.stop(name, None))
-        .then(|result| match result {
-            Ok(_) => panic!("Expected test to fail but it didn't!"),
-            Err(err) => match err.kind() {
-                ErrorKind::RuntimeOperation(RuntimeOperation::StopModule(s)) if s == name => {
-                    Ok::<_, Error>(())
-                }
-                kind => panic!(
-                    "Expected `RuntimeOperation(StopModule)` error but got {:?}.",
-                    kind
-                ),
-            },
+        .and_then(|runtime| {
+            let module = ModuleSpec::new(
+                "m1".to_string(),
+                "docker".to_string(),
+                DockerConfig::new("nginx:latest".to_string(), ContainerCreateBody::new(), None)
+                    .unwrap(),
+                HashMap::new(),
+                ImagePullPolicy::default(),
+            )
+            .unwrap();
+
+            let start = runtime.clone();
+            let stop = runtime.clone();
+            runtime
+                .create(module)
+                .and_then(move |()| start.start("m1"))
+                .and_then(move |()| stop.stop("m1", None))
+                .map(move |()| runtime.events().since(0))
         });
 
     let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
     runtime.spawn(server);
-    runtime.block_on(task).unwrap();
+    let events = runtime.block_on(task).unwrap();
+
+    let events: Vec<_> = events
+        .events()
+        .iter()
+        .map(|event| (event.name(), event.kind()))
+        .collect();
+    assert_eq!(
+        vec![
+            ("m1", ModuleLifecycleEventKind::Created),
+            ("m1", ModuleLifecycleEventKind::Started),
+            ("m1", ModuleLifecycleEventKind::Stopped),
+        ],
+        events
+    );
 }
 
 #[test]
-fn stop_fails_for_white_space_id() {
-    let (server, port) = run_tcp_server("127.0.0.1", default_network_handler());
+fn oom_killed_module_is_published_once() {
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        GET "/containers/m1/json" => |_| json_response(
+            StatusCode::OK,
+            &json!({
+                "Id": "m1",
+                "Name": "/m1",
+                "State": {
+                    "Status": "exited",
+                    "OOMKilled": true,
+                    "ExitCode": 137,
+                    "StartedAt": "2019-12-02T18:30:42.107555084Z",
+                    "FinishedAt": "2019-12-02T18:31:15.446259333Z"
+                }
+            }),
+        ),
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
     let server = server.map_err(|err| panic!(err));
 
     let settings = make_settings(Some(json!({
@@ -1412,90 +4679,137 @@ fn stop_fails_for_white_space_id() {
             "uri": &format!("http://localhost:{}", port)
         }
     })));
-    let name = "     ";
 
     let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
-        .and_then(|runtime| runtime.stop(name, None))
-        .then(|result| match result {
-            Ok(_) => panic!("Expected test to fail but it didn't!"),
-            Err(err) => match err.kind() {
-                ErrorKind::RuntimeOperation(RuntimeOperation::StopModule(s)) if s == name => {
-                    Ok::<_, Error>(())
-                }
-                kind => panic!(
-                    "Expected `RuntimeOperation(StopModule)` error but got {:?}.",
-                    kind
-                ),
-            },
+        .and_then(|runtime| {
+            let again = runtime.clone();
+            runtime
+                .get("m1")
+                .and_then(move |(_, state)| again.get("m1").map(move |_| state))
+                .map(move |state| (state, runtime.events().since(0)))
         });
 
     let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
     runtime.spawn(server);
-    runtime.block_on(task).unwrap();
+    let (state, events) = runtime.block_on(task).unwrap();
+
+    assert_eq!(Some(ExitReason::OomKilled), state.exit_reason());
+    let events: Vec<_> = events
+        .events()
+        .iter()
+        .map(|event| (event.name(), event.kind()))
+        .collect();
+    assert_eq!(vec![("m1", ModuleLifecycleEventKind::OomKilled)], events);
 }
 
 #[test]
-fn restart_fails_for_empty_id() {
-    let (server, port) = run_tcp_server("127.0.0.1", default_network_handler());
+fn failed_pull_is_last_error_of_module() {
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        POST "/images/create" => |_| json_response(
+            StatusCode::NOT_FOUND,
+            &json!({ "message": "manifest for nginx:2.0 not found" }),
+        ),
+        GET "/containers/m1/json" => |_| json_response(
+            StatusCode::OK,
+            &json!({
+                "Id": "m1",
+                "Name": "/m1",
+                "State": {
+                    "Status": "running",
+                    "ExitCode": 0,
+                    "StartedAt": "2019-12-02T18:30:42.107555084Z",
+                    "FinishedAt": "0001-01-01T00:00:00Z"
+                }
+            }),
+        ),
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
     let server = server.map_err(|err| panic!(err));
 
     let settings = make_settings(Some(json!({
-        "moby_runtime": {
-            "uri": &format!("http://localhost:{}", port)
-        }
+        "moby_runtime": { "uri": &format!("http://localhost:{}", port) }
     })));
-    let name = "";
 
     let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
-        .and_then(|runtime| runtime.restart(name))
-        .then(|result| match result {
-            Ok(_) => panic!("Expected test to fail but it didn't!"),
-            Err(err) => match err.kind() {
-                ErrorKind::RuntimeOperation(RuntimeOperation::RestartModule(s)) if s == name => {
-                    Ok::<_, Error>(())
-                }
-                kind => panic!(
-                    "Expected `RuntimeOperation(RestartModule)` error but got {:?}.",
-                    kind
-                ),
-            },
+        .and_then(|runtime| {
+            let spec = ModuleSpec::new(
+                "m1".to_string(),
+                "docker".to_string(),
+                DockerConfig::new("nginx:2.0".to_string(), ContainerCreateBody::new(), None)
+                    .unwrap(),
+                HashMap::new(),
+                ImagePullPolicy::OnCreate,
+            )
+            .unwrap();
+
+            // The running module is left as it was, like when its update fails.
+            pull_image(&runtime, &spec).then(move |result| {
+                assert!(result.is_err());
+                runtime.get("m1")
+            })
         });
 
     let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
     runtime.spawn(server);
-    runtime.block_on(task).unwrap();
+    let (_, state) = runtime.block_on(task).unwrap();
+
+    let error = state.last_error().unwrap();
+    assert_eq!(ModuleErrorCode::PullFailed, error.code());
+    assert!(error.message().contains("nginx:2.0"));
+    assert_eq!(None, error.exit_code());
 }
 
 #[test]
-fn restart_fails_for_white_space_id() {
-    let (server, port) = run_tcp_server("127.0.0.1", default_network_handler());
+fn exited_module_has_exit_code_as_last_error() {
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        GET "/containers/m1/json" => |_| json_response(
+            StatusCode::OK,
+            &json!({
+                "Id": "m1",
+                "Name": "/m1",
+                "State": {
+                    "Status": "exited",
+                    "OOMKilled": false,
+                    "ExitCode": 139,
+                    "StartedAt": "2019-12-02T18:10:02.271838906Z",
+                    "FinishedAt": "2019-12-02T18:10:04.539383744Z"
+                }
+            }),
+        ),
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
     let server = server.map_err(|err| panic!(err));
 
     let settings = make_settings(Some(json!({
-        "moby_runtime": {
-            "uri": &format!("http://localhost:{}", port)
-        }
+        "moby_runtime": { "uri": &format!("http://localhost:{}", port) }
     })));
-    let name = "      ";
 
     let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
-        .and_then(|runtime| runtime.restart(name))
-        .then(|result| match result {
-            Ok(_) => panic!("Expected test to fail but it didn't!"),
-            Err(err) => match err.kind() {
-                ErrorKind::RuntimeOperation(RuntimeOperation::RestartModule(s)) if s == name => {
-                    Ok::<_, Error>(())
-                }
-                kind => panic!(
-                    "Expected `RuntimeOperation(RestartModule)` error but got {:?}.",
-                    kind
-                ),
-            },
-        });
+        .and_then(|runtime| runtime.get("m1"));
 
     let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
     runtime.spawn(server);
-    runtime.block_on(task).unwrap();
+    let (_, state) = runtime.block_on(task).unwrap();
+
+    let error = state.last_error().unwrap();
+    assert_eq!(ModuleErrorCode::NonZeroExit, error.code());
+    assert_eq!(Some(139), error.exit_code());
+    assert_eq!(
+        "2019-12-02T18:10:04.539383744+00:00",
+        error.timestamp().to_rfc3339()
+    );
 }
 
 #[test]
@@ -1874,6 +5188,54 @@ fn runtime_system_info_succeeds() {
     assert_eq!("x86_64", system_info.architecture());
 }
 
+#[test]
+fn runtime_system_info_has_runtime_and_host_details() {
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        GET "/info" => |_| {
+            json_response(
+                StatusCode::OK,
+                &json!({
+                    "OSType": "linux",
+                    "Architecture": "aarch64",
+                    "ServerVersion": "19.03.5",
+                    "OperatingSystem": "Ubuntu 18.04.3 LTS",
+                    "KernelVersion": "4.15.0-72-generic",
+                    "MemTotal": 4_124_360_704_u64,
+                }),
+            )
+        },
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| runtime.system_info());
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    let system_info = runtime.block_on(task).unwrap();
+
+    assert_eq!("linux", system_info.os_type());
+    assert_eq!("aarch64", system_info.architecture());
+    assert_eq!(Some("docker"), system_info.runtime_type());
+    assert_eq!(Some("19.03.5"), system_info.runtime_version());
+    assert_eq!(Some("Ubuntu 18.04.3 LTS"), system_info.operating_system());
+    assert_eq!(Some("4.15.0-72-generic"), system_info.kernel_version());
+    assert_eq!(Some(4_124_360_704), system_info.total_memory());
+}
+
 #[test]
 fn runtime_system_info_none_returns_unkown() {
     let system_info_got_called_lock = Arc::new(RwLock::new(false));
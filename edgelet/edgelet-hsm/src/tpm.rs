@@ -144,9 +144,15 @@ impl Sign for TpmKey {
     /// If an identity was not given, we will sign the data with the stored key.
     fn sign(
         &self,
-        _signature_algorithm: SignatureAlgorithm,
+        signature_algorithm: SignatureAlgorithm,
         data: &[u8],
     ) -> Result<Self::Signature, CoreError> {
+        if signature_algorithm != SignatureAlgorithm::HMACSHA256 {
+            return Err(CoreError::from(
+                CoreErrorKind::UnsupportedSignatureAlgorithm(signature_algorithm.to_string()),
+            ));
+        }
+
         let _hsm_lock = self.hsm_lock.0.lock().expect("Acquiring HSM lock failed");
         match self.identity {
             KeyIdentity::Device => self
@@ -172,4 +178,9 @@ impl Sign for TpmKey {
                 .map_err(|err| CoreError::from(err.context(CoreErrorKind::KeyStore))),
         }
     }
+
+    /// The TPM only signs with HMAC-SHA256.
+    fn supported_algorithms(&self) -> &'static [SignatureAlgorithm] {
+        &[SignatureAlgorithm::HMACSHA256]
+    }
 }
@@ -0,0 +1,302 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::io::Read;
+use std::path::PathBuf;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use external_provisioning::models::DeviceProvisioningInfo;
+use failure::{Fail, ResultExt};
+use futures::sync::oneshot;
+use futures::{future, Future};
+use log::{debug, info};
+use serde_json;
+
+use edgelet_core::ExternalCommand;
+
+use crate::client::ExternalProvisioningInterface;
+use crate::error::{Error, ErrorKind};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Gets the device's provisioning information from the output of a command,
+/// for attestation done by something other than an external provisioning
+/// endpoint. The command prints the same JSON to stdout that the endpoint
+/// returns from `GET /deviceprovisioninginformation`.
+///
+/// The command runs on its own thread, since it can take up to its timeout
+/// to finish.
+#[derive(Clone, Debug)]
+pub struct ExternalProvisioningCommand {
+    program: PathBuf,
+    args: Vec<String>,
+    timeout: Duration,
+}
+
+impl ExternalProvisioningCommand {
+    pub fn new(command: &ExternalCommand) -> Self {
+        ExternalProvisioningCommand {
+            program: command.program().to_path_buf(),
+            args: command.args().to_vec(),
+            timeout: command.timeout(),
+        }
+    }
+
+    fn run(&self) -> Result<DeviceProvisioningInfo, Error> {
+        let program = self.program.display().to_string();
+        debug!("Running external provisioning command {}", program);
+
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|_| ErrorKind::RunCommand(program.clone()))?;
+
+        // Read the pipes while waiting, so that a command with a lot of output
+        // doesn't block on a full pipe.
+        let stdout = read_to_end(child.stdout.take());
+        let stderr = read_to_end(child.stderr.take());
+
+        let status = self.wait(&mut child, &program)?;
+        let stdout = stdout
+            .join()
+            .map_err(|_| ErrorKind::RunCommand(program.clone()))?;
+        let stderr = stderr
+            .join()
+            .map_err(|_| ErrorKind::RunCommand(program.clone()))?;
+        let stderr = String::from_utf8_lossy(&stderr).trim().to_string();
+
+        if !status.success() {
+            let kind = match status.code() {
+                Some(code) => ErrorKind::CommandFailed(code, stderr),
+                None => ErrorKind::CommandTerminated(stderr),
+            };
+            return Err(Error::from(kind));
+        }
+
+        let info = serde_json::from_slice(&stdout).context(ErrorKind::MalformedCommandOutput)?;
+        Ok(info)
+    }
+
+    fn wait(&self, child: &mut Child, program: &str) -> Result<ExitStatus, Error> {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            if let Some(status) = child
+                .try_wait()
+                .with_context(|_| ErrorKind::RunCommand(program.to_string()))?
+            {
+                return Ok(status);
+            }
+
+            if Instant::now() >= deadline {
+                // The command is abandoned either way, so there's nothing to
+                // do if it can't be killed.
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(Error::from(ErrorKind::CommandTimedOut(
+                    self.timeout.as_secs(),
+                )));
+            }
+
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+fn read_to_end<R>(pipe: Option<R>) -> thread::JoinHandle<Vec<u8>>
+where
+    R: 'static + Read + Send,
+{
+    thread::spawn(move || {
+        let mut buf = vec![];
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buf);
+        }
+        buf
+    })
+}
+
+impl ExternalProvisioningInterface for ExternalProvisioningCommand {
+    type Error = Error;
+
+    type DeviceProvisioningInformationFuture =
+        Box<dyn Future<Item = DeviceProvisioningInfo, Error = Self::Error> + Send>;
+
+    type ReprovisionDeviceFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+
+    fn get_device_provisioning_information(&self) -> Self::DeviceProvisioningInformationFuture {
+        let command = self.clone();
+        let (sender, receiver) = oneshot::channel();
+        thread::spawn(move || {
+            let _ = sender.send(command.run());
+        });
+
+        let program = self.program.display().to_string();
+        let info = receiver
+            .map_err(move |_| Error::from(ErrorKind::RunCommand(program)))
+            .flatten()
+            .map_err(|err| Error::from(err.context(ErrorKind::GetDeviceProvisioningInformation)));
+        Box::new(info)
+    }
+
+    fn reprovision_device(&self) -> Self::ReprovisionDeviceFuture {
+        // The command is run again the next time the daemon starts, which is
+        // what picks up the device's new provisioning information.
+        info!("External provisioning command does not need to be notified of reprovisioning");
+        Box::new(future::ok(()))
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::fs::File;
+    use std::io::Write;
+    use std::path::Path;
+
+    use tempdir::TempDir;
+
+    use super::*;
+
+    // The script is run by the shell rather than executed directly, so that
+    // it can't fail with ETXTBSY while another test is forking.
+    fn fake_command(dir: &Path, script: &str, args: &[&str]) -> ExternalCommand {
+        let path = dir.join("attest.sh");
+        File::create(&path)
+            .unwrap()
+            .write_all(script.as_bytes())
+            .unwrap();
+
+        let mut command_args = vec![path.to_str().unwrap().to_string()];
+        command_args.extend(args.iter().map(ToString::to_string));
+        ExternalCommand::new(PathBuf::from("/bin/sh"), command_args)
+    }
+
+    fn root_cause_kind(err: &Error) -> &ErrorKind {
+        Fail::iter_chain(err)
+            .filter_map(|cause| cause.downcast_ref::<Error>())
+            .last()
+            .map(Error::kind)
+            .unwrap()
+    }
+
+    #[test]
+    fn valid_payload() {
+        let dir = TempDir::new("external-provisioning").unwrap();
+        let command = ExternalProvisioningCommand::new(&fake_command(
+            dir.path(),
+            r#"echo '{"hubName": "TestHub", "deviceId": "TestDevice", "credentials": {"authType": "symmetric-key", "source": "payload", "key": "test-key"}}'"#,
+            &[],
+        ));
+
+        let info = command
+            .get_device_provisioning_information()
+            .wait()
+            .unwrap();
+        assert_eq!("TestHub", info.hub_name());
+        assert_eq!("TestDevice", info.device_id());
+        assert_eq!("symmetric-key", info.credentials().auth_type());
+        assert_eq!("payload", info.credentials().source());
+        assert_eq!(Some("test-key"), info.credentials().key());
+    }
+
+    #[test]
+    fn args_are_passed() {
+        let dir = TempDir::new("external-provisioning").unwrap();
+        let command = ExternalProvisioningCommand::new(&fake_command(
+            dir.path(),
+            r#"echo "{\"hubName\": \"$1\", \"deviceId\": \"$2\", \"credentials\": {\"authType\": \"x509\", \"source\": \"hsm\"}}""#,
+            &["TestHub", "TestDevice"],
+        ));
+
+        let info = command
+            .get_device_provisioning_information()
+            .wait()
+            .unwrap();
+        assert_eq!("TestHub", info.hub_name());
+        assert_eq!("TestDevice", info.device_id());
+    }
+
+    #[test]
+    fn malformed_payload() {
+        let dir = TempDir::new("external-provisioning").unwrap();
+        let command = ExternalProvisioningCommand::new(&fake_command(
+            dir.path(),
+            r#"echo '{"hubName": "TestHub"'"#,
+            &[],
+        ));
+
+        let err = command
+            .get_device_provisioning_information()
+            .wait()
+            .unwrap_err();
+        match err.kind() {
+            ErrorKind::GetDeviceProvisioningInformation => (),
+            kind => panic!(
+                "Expected `GetDeviceProvisioningInformation` but got {:?}",
+                kind
+            ),
+        }
+        match root_cause_kind(&err) {
+            ErrorKind::MalformedCommandOutput => (),
+            kind => panic!("Expected `MalformedCommandOutput` but got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn failure_captures_exit_code_and_stderr() {
+        let dir = TempDir::new("external-provisioning").unwrap();
+        let command = ExternalProvisioningCommand::new(&fake_command(
+            dir.path(),
+            "echo 'attestation failed' >&2\nexit 3",
+            &[],
+        ));
+
+        let err = command
+            .get_device_provisioning_information()
+            .wait()
+            .unwrap_err();
+        match root_cause_kind(&err) {
+            ErrorKind::CommandFailed(3, stderr) => assert_eq!("attestation failed", stderr),
+            kind => panic!("Expected `CommandFailed` but got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn missing_program() {
+        let dir = TempDir::new("external-provisioning").unwrap();
+        let command = ExternalProvisioningCommand::new(&ExternalCommand::new(
+            dir.path().join("missing.sh"),
+            vec![],
+        ));
+
+        let err = command
+            .get_device_provisioning_information()
+            .wait()
+            .unwrap_err();
+        match root_cause_kind(&err) {
+            ErrorKind::RunCommand(_) => (),
+            kind => panic!("Expected `RunCommand` but got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn timeout() {
+        let dir = TempDir::new("external-provisioning").unwrap();
+        let command = ExternalProvisioningCommand::new(
+            &fake_command(dir.path(), "exec sleep 10", &[])
+                .with_timeout(Duration::from_millis(200)),
+        );
+
+        let err = command
+            .get_device_provisioning_information()
+            .wait()
+            .unwrap_err();
+        match root_cause_kind(&err) {
+            ErrorKind::CommandTimedOut(_) => (),
+            kind => panic!("Expected `CommandTimedOut` but got {:?}", kind),
+        }
+    }
+}
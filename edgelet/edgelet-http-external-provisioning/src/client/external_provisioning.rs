@@ -11,9 +11,10 @@ use futures::prelude::*;
 use hyper::Client;
 use url::Url;
 
-use edgelet_core::UrlExt;
+use edgelet_core::{ExternalCommand, UrlExt};
 use edgelet_http::UrlConnector;
 
+use crate::client::ExternalProvisioningCommand;
 use crate::error::{Error, ErrorKind};
 
 pub trait ExternalProvisioningInterface {
@@ -33,8 +34,15 @@ pub trait GetApi: Send + Sync {
     fn get_api(&self) -> &dyn ExternalProvisioningApi;
 }
 
+/// Gets the device's provisioning information from the external provisioning
+/// endpoint, or from a command that stands in for it.
 pub struct ExternalProvisioningClient {
-    client: Arc<dyn GetApi>,
+    source: Source,
+}
+
+enum Source {
+    Api(Arc<dyn GetApi>),
+    Command(ExternalProvisioningCommand),
 }
 
 impl GetApi for APIClient {
@@ -63,11 +71,21 @@ impl ExternalProvisioningClient {
             Ok(UrlConnector::build_hyper_uri(&scheme, base_path, path)?)
         });
 
-        let external_provisioning_client = ExternalProvisioningClient {
-            client: Arc::new(APIClient::new(configuration)),
-        };
+        Ok(ExternalProvisioningClient::from_api(Arc::new(
+            APIClient::new(configuration),
+        )))
+    }
 
-        Ok(external_provisioning_client)
+    pub fn from_command(command: &ExternalCommand) -> Self {
+        ExternalProvisioningClient {
+            source: Source::Command(ExternalProvisioningCommand::new(command)),
+        }
+    }
+
+    fn from_api(api: Arc<dyn GetApi>) -> Self {
+        ExternalProvisioningClient {
+            source: Source::Api(api),
+        }
     }
 }
 
@@ -80,8 +98,12 @@ impl ExternalProvisioningInterface for ExternalProvisioningClient {
     type ReprovisionDeviceFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
 
     fn get_device_provisioning_information(&self) -> Self::DeviceProvisioningInformationFuture {
-        let connection_info = self
-            .client
+        let client = match &self.source {
+            Source::Api(client) => client,
+            Source::Command(command) => return command.get_device_provisioning_information(),
+        };
+
+        let connection_info = client
             .get_api()
             .get_device_provisioning_information(crate::EXTERNAL_PROVISIONING_API_VERSION)
             .map_err(|err| {
@@ -94,8 +116,12 @@ impl ExternalProvisioningInterface for ExternalProvisioningClient {
     }
 
     fn reprovision_device(&self) -> Self::ReprovisionDeviceFuture {
-        let res = self
-            .client
+        let client = match &self.source {
+            Source::Api(client) => client,
+            Source::Command(command) => return command.reprovision_device(),
+        };
+
+        let res = client
             .get_api()
             .reprovision_device(crate::EXTERNAL_PROVISIONING_API_VERSION)
             .map_err(|err| {
@@ -208,9 +234,7 @@ mod tests {
         let external_provisioning_api = TestExternalProvisioningApi {
             error: Some(external_provisioning_error),
         };
-        let client = ExternalProvisioningClient {
-            client: Arc::new(external_provisioning_api),
-        };
+        let client = ExternalProvisioningClient::from_api(Arc::new(external_provisioning_api));
 
         provisioning_info_test_assert(
             Some(ErrorKind::GetDeviceProvisioningInformation),
@@ -226,9 +250,7 @@ mod tests {
     #[test]
     fn get_device_provisioning_info_success() {
         let external_provisioning_api = TestExternalProvisioningApi { error: None };
-        let client = ExternalProvisioningClient {
-            client: Arc::new(external_provisioning_api),
-        };
+        let client = ExternalProvisioningClient::from_api(Arc::new(external_provisioning_api));
 
         provisioning_info_test_assert(None, move || {
             client
@@ -248,9 +270,7 @@ mod tests {
         let external_provisioning_api = TestExternalProvisioningApi {
             error: Some(external_provisioning_error),
         };
-        let client = ExternalProvisioningClient {
-            client: Arc::new(external_provisioning_api),
-        };
+        let client = ExternalProvisioningClient::from_api(Arc::new(external_provisioning_api));
 
         let res = client
             .reprovision_device()
@@ -270,9 +290,7 @@ mod tests {
     #[test]
     fn reprovision_device_success() {
         let external_provisioning_api = TestExternalProvisioningApi { error: None };
-        let client = ExternalProvisioningClient {
-            client: Arc::new(external_provisioning_api),
-        };
+        let client = ExternalProvisioningClient::from_api(Arc::new(external_provisioning_api));
 
         assert!(client.reprovision_device().wait().is_ok());
     }
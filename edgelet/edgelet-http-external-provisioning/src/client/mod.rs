@@ -1,4 +1,6 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+mod command;
 mod external_provisioning;
+pub use self::command::ExternalProvisioningCommand;
 pub use self::external_provisioning::{ExternalProvisioningClient, ExternalProvisioningInterface};
@@ -18,14 +18,35 @@ pub enum ErrorKind {
     #[fail(display = "Client error")]
     Client(ExternalProvisioningError<serde_json::Value>),
 
+    #[fail(
+        display = "External provisioning command exited with code {}: {}",
+        _0, _1
+    )]
+    CommandFailed(i32, String),
+
+    #[fail(display = "External provisioning command was terminated: {}", _0)]
+    CommandTerminated(String),
+
+    #[fail(
+        display = "External provisioning command did not exit within {} seconds",
+        _0
+    )]
+    CommandTimedOut(u64),
+
     #[fail(display = "Could not get device provisioning info")]
     GetDeviceProvisioningInformation,
 
     #[fail(display = "External provisioning client initialization")]
     InitializeExternalProvisioningClient,
 
+    #[fail(display = "External provisioning command printed malformed provisioning info")]
+    MalformedCommandOutput,
+
     #[fail(display = "Could not reprovision the device")]
     ReprovisionDevice,
+
+    #[fail(display = "Could not run external provisioning command {}", _0)]
+    RunCommand(String),
 }
 
 impl Fail for Error {
@@ -13,7 +13,9 @@
 pub mod client;
 pub mod error;
 
-pub use client::{ExternalProvisioningClient, ExternalProvisioningInterface};
+pub use client::{
+    ExternalProvisioningClient, ExternalProvisioningCommand, ExternalProvisioningInterface,
+};
 pub use error::{Error, ErrorKind};
 
 pub const EXTERNAL_PROVISIONING_API_VERSION: &str = "2019-04-10";
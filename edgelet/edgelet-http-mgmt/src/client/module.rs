@@ -1,5 +1,6 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+use std::collections::HashMap;
 use std::fmt;
 use std::str::FromStr;
 use std::sync::Arc;
@@ -118,14 +119,52 @@ fn runtime_status(details: &HttpModuleDetails) -> Result<ModuleRuntimeState, Err
         .status()
         .exit_status()
         .and_then(|e| e.exit_time().parse().ok());
+    let exit_reason = details
+        .status()
+        .exit_status()
+        .and_then(|e| e.reason())
+        .and_then(|reason| ExitReason::from_str(reason).ok());
     let start_time = details.status().start_time().and_then(|s| s.parse().ok());
 
+    let health = details
+        .status()
+        .runtime_status()
+        .health()
+        .and_then(|health| HealthState::from_str(health).ok());
+
+    let last_error = details.status().last_error().and_then(|error| {
+        let code = ModuleErrorCode::from_str(error.code()).ok()?;
+        let timestamp = error.timestamp().parse().ok()?;
+        Some(
+            ModuleError::new(code, error.message().clone(), timestamp)
+                .with_exit_code(error.exit_code()),
+        )
+    });
+
+    let ports = details
+        .status()
+        .ports()
+        .unwrap_or_default()
+        .iter()
+        .map(|port| {
+            PortBinding::new(port.container_port().clone())
+                .with_host_ip(port.host_ip().map(ToOwned::to_owned))
+                .with_host_port(port.host_port().map(ToOwned::to_owned))
+        })
+        .collect();
+
     let state = ModuleRuntimeState::default()
         .with_status(status)
         .with_status_description(description)
         .with_exit_code(exit_code)
+        .with_exit_reason(exit_reason)
         .with_started_at(start_time)
-        .with_finished_at(exit_time);
+        .with_finished_at(exit_time)
+        .with_health(health)
+        .with_restart_count(details.status().restart_count())
+        .with_paused(details.status().paused().unwrap_or(false))
+        .with_last_error(last_error)
+        .with_ports(ports);
     Ok(state)
 }
 
@@ -133,6 +172,7 @@ impl ModuleRegistry for ModuleClient {
     type Error = Error;
     type PullFuture = FutureResult<(), Self::Error>;
     type RemoveFuture = FutureResult<(), Self::Error>;
+    type ImageExistsFuture = FutureResult<bool, Self::Error>;
     type Config = ModuleConfig;
 
     fn pull(&self, _config: &Self::Config) -> Self::PullFuture {
@@ -142,6 +182,10 @@ impl ModuleRegistry for ModuleClient {
     fn remove(&self, _name: &str) -> Self::RemoveFuture {
         future::ok(())
     }
+
+    fn image_exists(&self, _config: &Self::Config) -> Self::ImageExistsFuture {
+        future::ok(false)
+    }
 }
 
 impl ModuleRuntime for ModuleClient {
@@ -167,15 +211,28 @@ impl ModuleRuntime for ModuleClient {
     type SystemResourcesFuture =
         Box<dyn Future<Item = SystemResources, Error = Self::Error> + Send>;
     type RemoveAllFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type StatsFuture = Box<dyn Future<Item = ModuleStats, Error = Self::Error> + Send>;
+    type PruneImagesFuture = Box<dyn Future<Item = PrunedImages, Error = Self::Error> + Send>;
+    type ExecStream = Box<dyn Stream<Item = ExecOutput, Error = Self::Error> + Send>;
+    type EffectiveSpecFuture =
+        Box<dyn Future<Item = ModuleSpec<Self::Config>, Error = Self::Error> + Send>;
 
     fn create(&self, _module: ModuleSpec<Self::Config>) -> Self::CreateFuture {
         unimplemented!()
     }
 
+    fn validate(&self, _module: &ModuleSpec<Self::Config>) -> Result<(), Self::Error> {
+        unimplemented!()
+    }
+
     fn get(&self, _id: &str) -> Self::GetFuture {
         unimplemented!()
     }
 
+    fn effective_spec(&self, _id: &str) -> Self::EffectiveSpecFuture {
+        unimplemented!()
+    }
+
     fn start(&self, id: &str) -> Self::StartFuture {
         let id = id.to_string();
 
@@ -321,6 +378,8 @@ impl ModuleRuntime for ModuleClient {
                 options.follow(),
                 tail,
                 options.since(),
+                options.until(),
+                options.timestamps(),
             )
             .then(|logs| match logs {
                 Ok(logs) => Ok(Logs(id, logs)),
@@ -345,6 +404,27 @@ impl ModuleRuntime for ModuleClient {
             future::join_all(n).map(|_| ())
         }))
     }
+
+    fn stats(&self, _id: &str) -> Self::StatsFuture {
+        unimplemented!()
+    }
+
+    fn prune_images(&self, _filter: ImagePruneFilter) -> Self::PruneImagesFuture {
+        unimplemented!()
+    }
+
+    fn exec(
+        &self,
+        _id: &str,
+        _cmd: Vec<String>,
+        _env: HashMap<String, String>,
+    ) -> Self::ExecStream {
+        unimplemented!()
+    }
+
+    fn events(&self) -> &ModuleEvents {
+        unimplemented!()
+    }
 }
 
 pub struct Logs(String, Body);
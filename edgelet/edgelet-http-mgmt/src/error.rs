@@ -2,8 +2,11 @@
 
 use std::fmt::{self, Display};
 
-use edgelet_core::{IdentityOperation, ModuleOperation, RuntimeOperation};
-use edgelet_docker::ErrorKind as DockerErrorKind;
+use edgelet_core::{
+    kind_error_code, redact_failure, ErrorCode, ErrorCodeKind, IdentityOperation, ModuleOperation,
+    RuntimeOperation, ValidationError,
+};
+use edgelet_docker::{Error as DockerError, ErrorKind as DockerErrorKind};
 use edgelet_iothub::Error as IoTHubError;
 use failure::{Backtrace, Context, Fail};
 use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
@@ -23,13 +26,40 @@ pub struct Error {
 
 #[derive(Debug, Fail)]
 pub enum ErrorKind {
+    #[fail(display = "Could not apply the deployment")]
+    ApplyDeployment,
+
     // Note: This errorkind is always wrapped in another errorkind context
     #[fail(display = "Client error")]
     Client(MgmtError<serde_json::Value>),
 
+    #[fail(display = "A deployment is already being applied")]
+    DeploymentInProgress,
+
+    #[fail(display = "Could not compare the modules with the ones on the device")]
+    DiffModules,
+
+    #[fail(display = "Running commands in modules is disabled")]
+    ExecDisabled,
+
+    #[fail(display = "Could not get the status of the deployment")]
+    GetDeploymentStatus,
+
+    #[fail(display = "Could not get the health of the daemon")]
+    GetHealth,
+
+    #[fail(display = "Could not get the upstream IoT Hub")]
+    GetUpstream,
+
+    #[fail(display = "Could not get the version of the management API")]
+    GetVersion,
+
     #[fail(display = "{}", _0)]
     IdentityOperation(IdentityOperation),
 
+    #[fail(display = "The field `{}` of a module spec can't be changed", _0)]
+    ImmutableField(&'static str),
+
     #[fail(display = "Could not initialize module client")]
     InitializeModuleClient,
 
@@ -48,24 +78,54 @@ pub enum ErrorKind {
     #[fail(display = "The request is missing required parameter `{}`", _0)]
     MissingRequiredParameter(&'static str),
 
+    #[fail(display = "Could not get module events")]
+    ModuleEvents,
+
     #[fail(display = "{}", _0)]
     ModuleOperation(ModuleOperation),
 
+    #[fail(display = "No deployment has been applied since the daemon started")]
+    NoDeployment,
+
     #[fail(display = "State not modified")]
     NotModified,
 
+    #[fail(display = "Could not patch module {:?}", _0)]
+    PatchModule(String),
+
+    #[fail(display = "Could not pause the watchdog")]
+    PauseWatchdog,
+
     #[fail(display = "Could not prepare update for module {:?}", _0)]
     PrepareUpdateModule(String),
 
+    #[fail(display = "Could not prepare for the upgrade")]
+    PrepareUpgrade,
+
+    #[fail(display = "Could not reload config")]
+    ReloadConfig,
+
     #[fail(display = "Could not reprovision device")]
     ReprovisionDevice,
 
+    #[fail(display = "Could not resume from the upgrade")]
+    ResumeFromUpgrade,
+
+    #[fail(display = "Could not resume module {:?}", _0)]
+    ResumeModule(String),
+
+    #[fail(display = "Could not resume the watchdog")]
+    ResumeWatchdog,
+
     #[fail(display = "{}", _0)]
     RuntimeOperation(RuntimeOperation),
 
     #[fail(display = "Could not start management service")]
     StartService,
 
+    #[fail(display = "Could not create support bundle")]
+    SupportBundle,
+
     #[fail(display = "Could not update module {:?}", _0)]
     UpdateModule(String),
 }
@@ -101,6 +161,42 @@ impl Error {
             MgmtError::Api(_) => Error::from(ErrorKind::Client(error).context(context)),
         }
     }
+
+    /// The code of the outermost failure in the cause chain that has one,
+    /// since the outer ones know best what was being done, such as pulling
+    /// the image that the container runtime didn't find.
+    pub fn code(&self) -> ErrorCode {
+        Fail::iter_chain(self)
+            .find_map(|fail| {
+                kind_error_code::<Error>(fail)
+                    .or_else(|| kind_error_code::<DockerError>(fail))
+                    .or_else(|| kind_error_code::<DockerErrorKind>(fail))
+                    .or_else(|| kind_error_code::<ValidationError>(fail))
+            })
+            .unwrap_or(ErrorCode::Internal)
+    }
+}
+
+impl ErrorCodeKind for ErrorKind {
+    fn error_code(&self) -> Option<ErrorCode> {
+        match self {
+            ErrorKind::DeploymentInProgress => Some(ErrorCode::Conflict),
+            ErrorKind::ExecDisabled => Some(ErrorCode::Forbidden),
+            ErrorKind::InvalidApiVersion(_) => Some(ErrorCode::UnsupportedApiVersion),
+            ErrorKind::IotHub => Some(ErrorCode::IotHubRequestFailed),
+            ErrorKind::ImmutableField(_)
+            | ErrorKind::MalformedRequestBody
+            | ErrorKind::MalformedRequestParameter(_)
+            | ErrorKind::MissingRequiredParameter(_) => Some(ErrorCode::InvalidRequest),
+            _ => None,
+        }
+    }
+}
+
+impl ErrorCodeKind for Error {
+    fn error_code(&self) -> Option<ErrorCode> {
+        self.kind().error_code()
+    }
 }
 
 impl From<ErrorKind> for Error {
@@ -119,39 +215,46 @@ impl From<Context<ErrorKind>> for Error {
 
 impl IntoResponse for Error {
     fn into_response(self) -> Response<Body> {
-        let mut message = self.to_string();
-        for cause in Fail::iter_causes(&self) {
-            message.push_str(&format!("\n\tcaused by: {}", cause));
-        }
+        let message = redact_failure(&self);
 
         // Specialize status code based on the underlying docker runtime error, if any
-        let status_code =
-            if let Some(cause) = Fail::find_root_cause(&self).downcast_ref::<DockerErrorKind>() {
-                match cause {
-                    DockerErrorKind::NotFound(_) => StatusCode::NOT_FOUND,
-                    DockerErrorKind::Conflict => StatusCode::CONFLICT,
-                    DockerErrorKind::NotModified => StatusCode::NOT_MODIFIED,
-                    _ => StatusCode::INTERNAL_SERVER_ERROR,
-                }
-            } else {
-                match self.kind() {
-                    ErrorKind::InvalidApiVersion(_)
-                    | ErrorKind::MalformedRequestBody
-                    | ErrorKind::MalformedRequestParameter(_)
-                    | ErrorKind::MissingRequiredParameter(_) => StatusCode::BAD_REQUEST,
-                    _ => {
-                        error!("Internal server error: {}", message);
-                        StatusCode::INTERNAL_SERVER_ERROR
-                    }
+        let root_cause = Fail::find_root_cause(&self);
+        let status_code = if let Some(cause) = root_cause.downcast_ref::<DockerErrorKind>() {
+            match cause {
+                DockerErrorKind::NotFound(_) => StatusCode::NOT_FOUND,
+                DockerErrorKind::Conflict | DockerErrorKind::NotRunning(_) => StatusCode::CONFLICT,
+                DockerErrorKind::NotModified => StatusCode::NOT_MODIFIED,
+                DockerErrorKind::RuntimeTimeout(_) => StatusCode::GATEWAY_TIMEOUT,
+                DockerErrorKind::IncompleteRegistryCredentials(_)
+                | DockerErrorKind::InvalidResourceLimit(..) => StatusCode::BAD_REQUEST,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            }
+        } else if root_cause.downcast_ref::<ValidationError>().is_some() {
+            // The module spec was rejected before reaching the runtime
+            StatusCode::BAD_REQUEST
+        } else {
+            match self.kind() {
+                ErrorKind::DeploymentInProgress => StatusCode::CONFLICT,
+                ErrorKind::ExecDisabled => StatusCode::FORBIDDEN,
+                ErrorKind::ImmutableField(_)
+                | ErrorKind::InvalidApiVersion(_)
+                | ErrorKind::MalformedRequestBody
+                | ErrorKind::MalformedRequestParameter(_)
+                | ErrorKind::MissingRequiredParameter(_) => StatusCode::BAD_REQUEST,
+                ErrorKind::NoDeployment => StatusCode::NOT_FOUND,
+                _ => {
+                    error!("Internal server error: {}", message);
+                    StatusCode::INTERNAL_SERVER_ERROR
                 }
-            };
+            }
+        };
 
         // Per the RFC, status code NotModified should not have a body
         let body = if status_code == StatusCode::NOT_MODIFIED {
             String::new()
         } else {
-            serde_json::to_string(&ErrorResponse::new(message))
-                .expect("serialization of ErrorResponse failed.")
+            let response = ErrorResponse::new(message).with_code(self.code().to_string());
+            serde_json::to_string(&response).expect("serialization of ErrorResponse failed.")
         };
 
         let mut response = Response::builder();
@@ -172,3 +275,115 @@ impl IntoResponse for IoTHubError {
         Error::from(self.context(ErrorKind::IotHub)).into_response()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use futures::{Future, Stream};
+
+    use edgelet_core::{validate_module_name, RegistryOperation};
+
+    use super::*;
+
+    fn docker_error(kind: DockerErrorKind, context: DockerErrorKind) -> DockerError {
+        DockerError::from(kind.context(context))
+    }
+
+    fn response_body(err: Error) -> (StatusCode, ErrorResponse) {
+        let response = err.into_response();
+        let status = response.status();
+        let body = response.into_body().concat2().wait().unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[test]
+    fn missing_container_is_module_not_found() {
+        let err = docker_error(
+            DockerErrorKind::NotFound("No such container: tempSensor".to_string()),
+            DockerErrorKind::RuntimeOperation(RuntimeOperation::GetModule(
+                "tempSensor".to_string(),
+            )),
+        );
+        let err = Error::from(err.context(ErrorKind::RuntimeOperation(
+            RuntimeOperation::GetModule("tempSensor".to_string()),
+        )));
+        assert_eq!(ErrorCode::ModuleNotFound, err.code());
+
+        let (status, body) = response_body(err);
+        assert_eq!(StatusCode::NOT_FOUND, status);
+        assert_eq!(Some(&"ModuleNotFound".to_string()), body.code());
+        // the cause chain is still in the message
+        assert!(body
+            .message()
+            .ends_with("caused by: No such container: tempSensor"));
+    }
+
+    #[test]
+    fn image_the_registry_does_not_have_is_pull_failure() {
+        let err = docker_error(
+            DockerErrorKind::NotFound("manifest for tempSensor:9.9 not found".to_string()),
+            DockerErrorKind::RegistryOperation(RegistryOperation::PullImage(
+                "tempSensor:9.9".to_string(),
+            )),
+        );
+        let err = Error::from(err.context(ErrorKind::UpdateModule("tempSensor".to_string())));
+
+        assert_eq!(ErrorCode::ImagePullFailed, err.code());
+    }
+
+    #[test]
+    fn rejected_spec_is_invalid_module_spec() {
+        let err = validate_module_name("$edgeAgent").unwrap_err();
+        let err = Error::from(err.context(ErrorKind::UpdateModule("$edgeAgent".to_string())));
+        assert_eq!(ErrorCode::InvalidModuleSpec, err.code());
+
+        let (status, body) = response_body(err);
+        assert_eq!(StatusCode::BAD_REQUEST, status);
+        assert_eq!(Some(&"InvalidModuleSpec".to_string()), body.code());
+    }
+
+    #[test]
+    fn request_errors_are_invalid_request() {
+        let err = Error::from(ErrorKind::MalformedRequestParameter("timeout"));
+        assert_eq!(ErrorCode::InvalidRequest, err.code());
+
+        let err = Error::from(ErrorKind::InvalidApiVersion("2000-01-01".to_string()));
+        assert_eq!(ErrorCode::UnsupportedApiVersion, err.code());
+    }
+
+    #[test]
+    fn create_options_in_message_are_redacted() {
+        let err = docker_error(
+            DockerErrorKind::FormattedDockerRuntime(
+                r#"invalid create options {"Image":"temp:1.0","auth":{"username":"user","password":"hunter2"}}"#
+                    .to_string(),
+            ),
+            DockerErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(
+                "tempSensor".to_string(),
+            )),
+        );
+        let err = Error::from(err.context(ErrorKind::RuntimeOperation(
+            RuntimeOperation::CreateModule("tempSensor".to_string()),
+        )));
+
+        let (_, body) = response_body(err);
+        assert!(!body.message().contains("hunter2"));
+        assert!(body
+            .message()
+            .ends_with(r#""auth":{"username":"user","password":"***"}}"#));
+    }
+
+    #[test]
+    fn unknown_failure_is_internal() {
+        let err = docker_error(
+            DockerErrorKind::FormattedDockerRuntime("disk full".to_string()),
+            DockerErrorKind::RuntimeOperation(RuntimeOperation::StartModule(
+                "tempSensor".to_string(),
+            )),
+        );
+        let err = Error::from(err.context(ErrorKind::RuntimeOperation(
+            RuntimeOperation::StartModule("tempSensor".to_string()),
+        )));
+
+        assert_eq!(ErrorCode::Internal, err.code());
+    }
+}
@@ -0,0 +1,136 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Deployments of modules, which are applied as a whole rather than a module
+//! at a time. See `DeploymentReconciler` for how the modules are changed, and
+//! `Deployment` for what makes a payload valid. The status of the last
+//! deployment, applied or rejected, is served for whoever pushed it.
+
+use failure::{Fail, ResultExt};
+use futures::{Future, IntoFuture, Stream};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use log::debug;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{self, Value};
+
+use edgelet_core::{
+    DeploymentReconciler, ErrorKind as CoreErrorKind, ImageReference, LastDeploymentStatus,
+    ModuleRuntime, ModuleRuntimeErrorReason,
+};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+pub struct ApplyDeployment<M>
+where
+    M: ModuleRuntime,
+{
+    deployments: DeploymentReconciler<M>,
+}
+
+impl<M> ApplyDeployment<M>
+where
+    M: ModuleRuntime,
+{
+    pub fn new(deployments: DeploymentReconciler<M>) -> Self {
+        ApplyDeployment { deployments }
+    }
+}
+
+impl<M> Handler<Parameters> for ApplyDeployment<M>
+where
+    M: 'static + ModuleRuntime + Clone + Send + Sync,
+    M::Config: DeserializeOwned + ImageReference + Serialize,
+    for<'r> &'r <M as ModuleRuntime>::Error: Into<ModuleRuntimeErrorReason>,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        debug!("Apply deployment");
+
+        let deployments = self.deployments.clone();
+        let response = req
+            .into_body()
+            .concat2()
+            .then(|body| -> Result<Value, Error> {
+                let body = body.context(ErrorKind::MalformedRequestBody)?;
+                let payload =
+                    serde_json::from_slice(&body).context(ErrorKind::MalformedRequestBody)?;
+                Ok(payload)
+            })
+            .and_then(move |payload| {
+                deployments.apply(&payload).then(|result| match result {
+                    // Held back until a maintenance window opens.
+                    Ok(Some(_)) => Response::builder()
+                        .status(StatusCode::ACCEPTED)
+                        .body(Body::default())
+                        .context(ErrorKind::ApplyDeployment)
+                        .map_err(Error::from),
+                    Ok(None) => Response::builder()
+                        .status(StatusCode::NO_CONTENT)
+                        .body(Body::default())
+                        .context(ErrorKind::ApplyDeployment)
+                        .map_err(Error::from),
+                    Err(err) => {
+                        let kind = match err.kind() {
+                            CoreErrorKind::InvalidDeployment(_)
+                            | CoreErrorKind::DeploymentOverBudget(_)
+                            | CoreErrorKind::InvalidManifestSignature => {
+                                ErrorKind::MalformedRequestBody
+                            }
+                            CoreErrorKind::DeploymentInProgress => ErrorKind::DeploymentInProgress,
+                            _ => ErrorKind::ApplyDeployment,
+                        };
+                        Err(Error::from(err.context(kind)))
+                    }
+                })
+            })
+            .or_else(|e| Ok(e.into_response()));
+
+        Box::new(response)
+    }
+}
+
+pub struct GetDeploymentStatus {
+    status: LastDeploymentStatus,
+}
+
+impl GetDeploymentStatus {
+    pub fn new(status: LastDeploymentStatus) -> Self {
+        GetDeploymentStatus { status }
+    }
+}
+
+impl Handler<Parameters> for GetDeploymentStatus {
+    fn handle(
+        &self,
+        _req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        debug!("Get deployment status");
+
+        let response = self
+            .status
+            .get()
+            .ok_or_else(|| Error::from(ErrorKind::NoDeployment))
+            .and_then(|status| {
+                let b = serde_json::to_string(&status).context(ErrorKind::GetDeploymentStatus)?;
+                let response = Response::builder()
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(CONTENT_LENGTH, b.len().to_string().as_str())
+                    .body(b.into())
+                    .context(ErrorKind::GetDeploymentStatus)?;
+                Ok(response)
+            })
+            .into_future()
+            .or_else(|e| Ok(e.into_response()));
+
+        Box::new(response)
+    }
+}
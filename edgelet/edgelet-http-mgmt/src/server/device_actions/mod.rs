@@ -1,4 +1,6 @@
 // Copyright (c) Microsoft. All rights reserved.
+mod reload;
 mod reprovision;
 
+pub use self::reload::ReloadConfig;
 pub use self::reprovision::ReprovisionDevice;
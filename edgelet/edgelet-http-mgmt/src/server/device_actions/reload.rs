@@ -0,0 +1,94 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::ResultExt;
+use futures::sync::mpsc::UnboundedSender;
+use futures::{Future, IntoFuture};
+use hyper::{Body, Request, Response, StatusCode};
+use log::debug;
+
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// Asks the daemon to reload its config. The reload happens after the
+/// response is sent, and a config that isn't valid is logged and ignored.
+pub struct ReloadConfig {
+    initiate_reload: UnboundedSender<()>,
+}
+
+impl ReloadConfig {
+    pub fn new(initiate_reload: UnboundedSender<()>) -> Self {
+        ReloadConfig { initiate_reload }
+    }
+}
+
+impl Handler<Parameters> for ReloadConfig {
+    fn handle(
+        &self,
+        _req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        debug!("Reload config");
+        let response = self
+            .initiate_reload
+            .unbounded_send(())
+            .map_err(|_| Error::from(ErrorKind::ReloadConfig))
+            .and_then(|_| -> Result<_, Error> {
+                let response = Response::builder()
+                    .status(StatusCode::OK)
+                    .body(Body::default())
+                    .context(ErrorKind::ReloadConfig)?;
+
+                Ok(response)
+            })
+            .or_else(|e| Ok(e.into_response()))
+            .into_future();
+
+        Box::new(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::sync::mpsc;
+    use futures::Stream;
+
+    use super::*;
+
+    fn request() -> Request<Body> {
+        Request::post("http://localhost/reload?api-version=2019-11-05")
+            .body(Body::default())
+            .unwrap()
+    }
+
+    #[test]
+    fn reload_config_success() {
+        // arrange
+        let (reload_tx, reload_rx) = mpsc::unbounded();
+        let handler = ReloadConfig::new(reload_tx);
+
+        // act
+        let response = handler.handle(request(), Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        drop(handler);
+        assert_eq!(vec![()], reload_rx.collect().wait().unwrap());
+    }
+
+    #[test]
+    fn reload_config_failed() {
+        // arrange
+        let (reload_tx, mut reload_rx) = mpsc::unbounded();
+        reload_rx.close();
+        let handler = ReloadConfig::new(reload_tx);
+
+        // act
+        let response = handler.handle(request(), Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+    }
+}
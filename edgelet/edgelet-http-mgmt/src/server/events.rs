@@ -0,0 +1,176 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Long-polls for module lifecycle events. A request gets the events numbered
+//! `since` or later, waiting up to `timeout` seconds for one if there are none
+//! yet, along with the number to ask for the events after them from.
+
+use std::time::Duration;
+
+use failure::{Fail, ResultExt};
+use futures::{Future, IntoFuture};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use serde_json;
+use url::form_urlencoded;
+
+use edgelet_core::ModuleRuntime;
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// The longest a request waits for an event.
+const MAX_TIMEOUT_SECS: u64 = 120;
+
+pub struct GetModuleEvents<M> {
+    runtime: M,
+}
+
+impl<M> GetModuleEvents<M> {
+    pub fn new(runtime: M) -> Self {
+        GetModuleEvents { runtime }
+    }
+}
+
+impl<M> Handler<Parameters> for GetModuleEvents<M>
+where
+    M: 'static + ModuleRuntime + Send,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let events = self.runtime.events().clone();
+
+        let response = parse_options(req.uri().query().unwrap_or(""))
+            .into_future()
+            .and_then(move |(since, timeout)| {
+                events
+                    .wait(since, timeout)
+                    .map_err(|err| Error::from(err.context(ErrorKind::ModuleEvents)))
+            })
+            .and_then(|events| -> Result<_, Error> {
+                let body = serde_json::to_string(&events).context(ErrorKind::ModuleEvents)?;
+                let response = Response::builder()
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(CONTENT_LENGTH, body.len().to_string().as_str())
+                    .body(body.into())
+                    .context(ErrorKind::ModuleEvents)?;
+                Ok(response)
+            })
+            .or_else(|e| Ok(e.into_response()));
+
+        Box::new(response)
+    }
+}
+
+fn parse_options(query: &str) -> Result<(u64, Duration), Error> {
+    let parse: Vec<_> = form_urlencoded::parse(query.as_bytes()).collect();
+    let since = parse
+        .iter()
+        .find(|&(ref key, _)| key == "since")
+        .map_or_else(|| Ok(0), |(_, val)| val.parse::<u64>())
+        .context(ErrorKind::MalformedRequestParameter("since"))?;
+    let timeout = parse
+        .iter()
+        .find(|&(ref key, _)| key == "timeout")
+        .map_or_else(|| Ok(0), |(_, val)| val.parse::<u64>())
+        .context(ErrorKind::MalformedRequestParameter("timeout"))?;
+    if timeout > MAX_TIMEOUT_SECS {
+        return Err(Error::from(ErrorKind::MalformedRequestParameter("timeout")));
+    }
+    Ok((since, Duration::from_secs(timeout)))
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::Stream;
+    use management::models::ErrorResponse;
+    use serde_json::Value;
+
+    use edgelet_core::{MakeModuleRuntime, ModuleLifecycleEventKind};
+    use edgelet_test_utils::crypto::TestHsm;
+    use edgelet_test_utils::module::*;
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    fn runtime() -> TestRuntime<Error, TestSettings> {
+        TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+    }
+
+    fn get(
+        handler: &GetModuleEvents<TestRuntime<Error, TestSettings>>,
+        query: &str,
+    ) -> Response<Body> {
+        let request = Request::get(format!(
+            "http://localhost/events?api-version=2019-11-05&{}",
+            query
+        ))
+        .body(Body::default())
+        .unwrap();
+        handler.handle(request, Parameters::new()).wait().unwrap()
+    }
+
+    #[test]
+    fn returns_events_since_cursor() {
+        let runtime = runtime();
+        runtime
+            .events()
+            .publish("mod1", ModuleLifecycleEventKind::Created);
+        runtime
+            .events()
+            .publish("mod1", ModuleLifecycleEventKind::Started);
+        let handler = GetModuleEvents::new(runtime);
+
+        let response = get(&handler, "since=1");
+
+        assert_eq!(StatusCode::OK, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let events: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(2, events["next"]);
+        assert_eq!(0, events["missed"]);
+        let events = events["events"].as_array().unwrap();
+        assert_eq!(1, events.len());
+        assert_eq!("mod1", events[0]["name"]);
+        assert_eq!("started", events[0]["kind"]);
+        assert!(events[0]["timestamp"].is_string());
+    }
+
+    #[test]
+    fn no_events_without_timeout() {
+        let handler = GetModuleEvents::new(runtime());
+
+        let response = get(&handler, "since=0");
+
+        assert_eq!(StatusCode::OK, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let events: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(0, events["next"]);
+        assert!(events["events"].as_array().unwrap().is_empty());
+    }
+
+    #[test]
+    fn timeout_above_maximum_fails() {
+        let handler = GetModuleEvents::new(runtime());
+
+        let response = get(&handler, "timeout=121");
+
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            "The request parameter `timeout` is malformed",
+            error.message()
+        );
+    }
+}
@@ -0,0 +1,251 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Liveness and readiness of the daemon, for supervisors that restart it when
+//! it hangs and wait for it before relying on the device.
+
+use failure::ResultExt;
+use futures::{Future, IntoFuture, Stream};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use log::debug;
+use serde_json::{json, Map, Value};
+
+use edgelet_core::{HealthSettings, Module, ModuleRuntime, ModuleStatus, UpstreamEndpoints};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// Responds as long as the daemon's HTTP server handles requests.
+pub struct GetLiveness;
+
+impl Handler<Parameters> for GetLiveness {
+    fn handle(
+        &self,
+        _req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let response = health_response(StatusCode::OK, &json!({ "live": true }))
+            .into_future()
+            .or_else(|e| Ok(e.into_response()));
+
+        Box::new(response)
+    }
+}
+
+/// Responds with `503 Service Unavailable` until the modules and the IoT Hub
+/// connection that the `HealthSettings` require are up. The management API
+/// only serves requests once the device is provisioned, so that's implied.
+pub struct GetReadiness<M> {
+    runtime: M,
+    settings: HealthSettings,
+    upstream: UpstreamEndpoints,
+}
+
+impl<M> GetReadiness<M> {
+    pub fn new(runtime: M, settings: HealthSettings, upstream: UpstreamEndpoints) -> Self {
+        GetReadiness {
+            runtime,
+            settings,
+            upstream,
+        }
+    }
+}
+
+impl<M> Handler<Parameters> for GetReadiness<M>
+where
+    M: 'static + ModuleRuntime + Send + Sync,
+{
+    fn handle(
+        &self,
+        _req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        debug!("Get readiness");
+
+        let settings = self.settings.clone();
+        let upstream_reachable = self.upstream.reachable();
+
+        // A runtime that can't list its modules isn't ready, which is reported
+        // like any other unmet check.
+        let response = self
+            .runtime
+            .list_with_details()
+            .map(|(module, state)| (module.name().to_string(), *state.status()))
+            .collect()
+            .then(move |states: Result<Vec<_>, _>| -> Result<_, Error> {
+                if let Err(err) = &states {
+                    debug!("Could not list modules for readiness: {}", err);
+                }
+                let states = states.unwrap_or_default();
+                let (ready, body) = readiness(&settings, &states, upstream_reachable);
+                let status = if ready {
+                    StatusCode::OK
+                } else {
+                    StatusCode::SERVICE_UNAVAILABLE
+                };
+                health_response(status, &body)
+            })
+            .or_else(|e| Ok(e.into_response()));
+
+        Box::new(response)
+    }
+}
+
+fn readiness(
+    settings: &HealthSettings,
+    states: &[(String, ModuleStatus)],
+    upstream_reachable: bool,
+) -> (bool, Value) {
+    let mut ready = true;
+
+    let mut modules = Map::new();
+    for name in settings.ready_modules() {
+        let status = states
+            .iter()
+            .find(|(module, _)| module == name)
+            .map(|(_, status)| *status);
+        ready &= status == Some(ModuleStatus::Running);
+        let status = status.map_or_else(|| "missing".to_string(), |status| status.to_string());
+        modules.insert(name.clone(), Value::String(status));
+    }
+
+    let mut body = json!({ "modules": modules });
+    if settings.require_upstream() {
+        ready &= upstream_reachable;
+        body["upstreamReachable"] = Value::Bool(upstream_reachable);
+    }
+    body["ready"] = Value::Bool(ready);
+
+    (ready, body)
+}
+
+fn health_response(status: StatusCode, body: &Value) -> Result<Response<Body>, Error> {
+    let b = serde_json::to_string(body).context(ErrorKind::GetHealth)?;
+    let response = Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .header(CONTENT_LENGTH, b.len().to_string().as_str())
+        .body(b.into())
+        .context(ErrorKind::GetHealth)?;
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::{MakeModuleRuntime, ModuleRuntimeState, UpstreamSettings};
+    use edgelet_test_utils::crypto::TestHsm;
+    use edgelet_test_utils::module::*;
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    fn upstream() -> UpstreamEndpoints {
+        UpstreamEndpoints::new(
+            "primary.azure-devices.net".to_string(),
+            &UpstreamSettings::default(),
+        )
+    }
+
+    fn runtime(status: ModuleStatus) -> TestRuntime<Error, TestSettings> {
+        let state = ModuleRuntimeState::default().with_status(status);
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error, _> =
+            TestModule::new("edgeAgent".to_string(), config, Ok(state));
+        TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(Ok(module))
+    }
+
+    fn get(handler: &dyn Handler<Parameters>) -> (StatusCode, Value) {
+        let request = Request::get("http://localhost/health/ready")
+            .body(Body::default())
+            .unwrap();
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+        let status = response.status();
+        let body = response.into_body().concat2().wait().unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[test]
+    fn liveness_is_ok() {
+        let (status, body) = get(&GetLiveness);
+
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!(json!({ "live": true }), body);
+    }
+
+    #[test]
+    fn ready_once_modules_run_and_upstream_is_reachable() {
+        let settings = HealthSettings::default();
+        let mut states = vec![("edgeAgent".to_string(), ModuleStatus::Running)];
+
+        let (ready, body) = readiness(&settings, &states, true);
+        assert!(!ready);
+        assert_eq!(
+            json!({
+                "ready": false,
+                "modules": { "edgeAgent": "running", "edgeHub": "missing" },
+                "upstreamReachable": true,
+            }),
+            body
+        );
+
+        states.push(("edgeHub".to_string(), ModuleStatus::Stopped));
+        assert!(!readiness(&settings, &states, true).0);
+
+        states[1].1 = ModuleStatus::Running;
+        assert!(!readiness(&settings, &states, false).0);
+        assert!(readiness(&settings, &states, true).0);
+    }
+
+    #[test]
+    fn readiness_criteria_are_configurable() {
+        let settings = HealthSettings::new(vec!["edgeAgent".to_string()], false);
+        let states = vec![("edgeAgent".to_string(), ModuleStatus::Running)];
+
+        let (ready, body) = readiness(&settings, &states, false);
+
+        assert!(ready);
+        assert_eq!(
+            json!({ "ready": true, "modules": { "edgeAgent": "running" } }),
+            body
+        );
+    }
+
+    #[test]
+    fn handler_is_unavailable_until_ready() {
+        let settings = HealthSettings::new(vec!["edgeAgent".to_string()], true);
+        let upstream = upstream();
+
+        let handler = GetReadiness::new(
+            runtime(ModuleStatus::Stopped),
+            settings.clone(),
+            upstream.clone(),
+        );
+        let (status, body) = get(&handler);
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, status);
+        assert_eq!(json!("stopped"), body["modules"]["edgeAgent"]);
+
+        upstream.report_failure();
+        let handler = GetReadiness::new(
+            runtime(ModuleStatus::Running),
+            settings.clone(),
+            upstream.clone(),
+        );
+        let (status, body) = get(&handler);
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, status);
+        assert_eq!(json!(false), body["upstreamReachable"]);
+
+        upstream.report_success();
+        let (status, body) = get(&handler);
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!(json!(true), body["ready"]);
+    }
+}
@@ -0,0 +1,183 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Removes the images that no module uses, to reclaim the space they take up
+//! on devices that have been through many deployments. With `dangling=true`
+//! only the images that no tag refers to anymore are removed.
+
+use failure::{Fail, ResultExt};
+use futures::{Future, IntoFuture};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use serde_json;
+use url::form_urlencoded;
+
+use edgelet_core::{ImagePruneFilter, ModuleRuntime, RuntimeOperation};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+pub struct PruneImages<M> {
+    runtime: M,
+}
+
+impl<M> PruneImages<M> {
+    pub fn new(runtime: M) -> Self {
+        PruneImages { runtime }
+    }
+}
+
+impl<M> Handler<Parameters> for PruneImages<M>
+where
+    M: 'static + ModuleRuntime + Send,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let response = parse_filter(req.uri().query().unwrap_or(""))
+            .map(|filter| {
+                self.runtime.prune_images(filter).map_err(|err| {
+                    Error::from(
+                        err.context(ErrorKind::RuntimeOperation(RuntimeOperation::PruneImages)),
+                    )
+                })
+            })
+            .into_future()
+            .flatten()
+            .and_then(|pruned| -> Result<_, Error> {
+                let body = serde_json::to_string(&pruned)
+                    .context(ErrorKind::RuntimeOperation(RuntimeOperation::PruneImages))?;
+                let response = Response::builder()
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(CONTENT_LENGTH, body.len().to_string().as_str())
+                    .body(body.into())
+                    .context(ErrorKind::RuntimeOperation(RuntimeOperation::PruneImages))?;
+                Ok(response)
+            })
+            .or_else(|e| Ok(e.into_response()));
+
+        Box::new(response)
+    }
+}
+
+fn parse_filter(query: &str) -> Result<ImagePruneFilter, Error> {
+    let dangling = form_urlencoded::parse(query.as_bytes())
+        .find(|(key, _)| key == "dangling")
+        .map_or_else(|| Ok(false), |(_, val)| val.parse::<bool>())
+        .context(ErrorKind::MalformedRequestParameter("dangling"))?;
+    if dangling {
+        Ok(ImagePruneFilter::Dangling)
+    } else {
+        Ok(ImagePruneFilter::Unused)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::Stream;
+    use management::models::ErrorResponse;
+    use serde_json::{json, Value};
+
+    use edgelet_core::{MakeModuleRuntime, ModuleRuntimeState};
+    use edgelet_test_utils::crypto::TestHsm;
+    use edgelet_test_utils::module::*;
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    fn runtime(
+        module: Result<TestModule<Error, TestConfig>, Error>,
+    ) -> TestRuntime<Error, TestSettings> {
+        TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(module)
+    }
+
+    fn module() -> TestModule<Error, TestConfig> {
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        TestModule::new(
+            "test-module".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default()),
+        )
+    }
+
+    fn post(
+        handler: &PruneImages<TestRuntime<Error, TestSettings>>,
+        query: &str,
+    ) -> (StatusCode, Value) {
+        let request = Request::post(format!(
+            "http://localhost/images/prune?api-version=2019-11-05&{}",
+            query
+        ))
+        .body(Body::default())
+        .unwrap();
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+        let status = response.status();
+        let body = response.into_body().concat2().wait().unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[test]
+    fn returns_pruned_images() {
+        let handler = PruneImages::new(runtime(Ok(module())));
+
+        let (status, body) = post(&handler, "dangling=true");
+
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!(
+            json!({ "images": ["sha256:dangling"], "space_reclaimed": 1024 }),
+            body
+        );
+    }
+
+    #[test]
+    fn filter_defaults_to_unused() {
+        assert_eq!(ImagePruneFilter::Unused, parse_filter("").unwrap());
+        assert_eq!(
+            ImagePruneFilter::Unused,
+            parse_filter("dangling=false").unwrap()
+        );
+        assert_eq!(
+            ImagePruneFilter::Dangling,
+            parse_filter("dangling=true").unwrap()
+        );
+    }
+
+    #[test]
+    fn malformed_filter_fails() {
+        let handler = PruneImages::new(runtime(Ok(module())));
+
+        let (status, body) = post(&handler, "dangling=maybe");
+
+        assert_eq!(StatusCode::BAD_REQUEST, status);
+        let error: ErrorResponse = serde_json::from_value(body).unwrap();
+        assert_eq!(
+            "The request parameter `dangling` is malformed\n\tcaused by: provided string was not `true` or `false`",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn runtime_error() {
+        let handler = PruneImages::new(runtime(Err(Error::General)));
+
+        let (status, body) = post(&handler, "");
+
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, status);
+        let error: ErrorResponse = serde_json::from_value(body).unwrap();
+        assert_eq!(
+            "Could not prune images\n\tcaused by: General error",
+            error.message()
+        );
+    }
+}
@@ -0,0 +1,503 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use failure::{Fail, ResultExt};
+use futures::{Future, Stream};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use log::debug;
+use serde_json::Value;
+
+use edgelet_core::watchdog::ReconcileTimer;
+use edgelet_core::{Module, ModuleRuntime, ModuleStatus, RuntimeOperation, SystemResources};
+use edgelet_http::metrics::{Exposition, MetricType, RequestMetrics, CONTENT_TYPE as METRICS};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+#[derive(Debug, Default)]
+struct ModuleCounts {
+    started: BTreeSet<String>,
+    restarts: BTreeMap<String, u64>,
+}
+
+/// Counts the module restarts requested through the management API. A module
+/// is restarted when it is restarted, or when it is started after it was
+/// already started once since the daemon started. Clones share the same
+/// counts.
+#[derive(Clone, Debug, Default)]
+pub struct ModuleMetrics {
+    counts: Arc<Mutex<ModuleCounts>>,
+}
+
+impl ModuleMetrics {
+    pub fn new() -> Self {
+        ModuleMetrics::default()
+    }
+
+    /// Records a request the management API handled successfully.
+    pub fn observe(&self, method: &Method, path: &str) {
+        if *method != Method::POST {
+            return;
+        }
+
+        let mut segments = path.trim_matches('/').split('/');
+        match (
+            segments.next(),
+            segments.next(),
+            segments.next(),
+            segments.next(),
+        ) {
+            (Some("modules"), Some(name), Some("start"), None) => self.started(name),
+            (Some("modules"), Some(name), Some("restart"), None) => self.restarted(name),
+            _ => (),
+        }
+    }
+
+    fn started(&self, name: &str) {
+        let mut counts = self.counts.lock().expect("module metrics lock poisoned");
+        if !counts.started.insert(name.to_string()) {
+            *counts.restarts.entry(name.to_string()).or_insert(0) += 1;
+        }
+    }
+
+    fn restarted(&self, name: &str) {
+        let mut counts = self.counts.lock().expect("module metrics lock poisoned");
+        counts.started.insert(name.to_string());
+        *counts.restarts.entry(name.to_string()).or_insert(0) += 1;
+    }
+
+    fn restarts(&self, name: &str) -> u64 {
+        let counts = self.counts.lock().expect("module metrics lock poisoned");
+        counts.restarts.get(name).cloned().unwrap_or(0)
+    }
+}
+
+pub struct GetMetrics<M> {
+    runtime: M,
+    modules: ModuleMetrics,
+    requests: RequestMetrics,
+    reconcile: ReconcileTimer,
+}
+
+impl<M> GetMetrics<M> {
+    pub fn new(
+        runtime: M,
+        modules: ModuleMetrics,
+        requests: RequestMetrics,
+        reconcile: ReconcileTimer,
+    ) -> Self {
+        GetMetrics {
+            runtime,
+            modules,
+            requests,
+            reconcile,
+        }
+    }
+}
+
+impl<M> Handler<Parameters> for GetMetrics<M>
+where
+    M: 'static + ModuleRuntime + Send + Sync,
+{
+    fn handle(
+        &self,
+        _req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        debug!("Get metrics");
+
+        let modules = self.modules.clone();
+        let requests = self.requests.clone();
+        let reconcile = self.reconcile.clone();
+
+        let states = self
+            .runtime
+            .list_with_details()
+            .map(|(module, state)| (module.name().to_string(), *state.status()))
+            .collect()
+            .map_err(|err| {
+                Error::from(err.context(ErrorKind::RuntimeOperation(RuntimeOperation::ListModules)))
+            });
+
+        // The module states are still worth reporting if the runtime can't
+        // report its resource usage.
+        let resources = self.runtime.system_resources().then(|resources| {
+            if let Err(err) = &resources {
+                debug!("Could not get system resources for metrics: {}", err);
+            }
+            Ok::<_, Error>(resources.ok())
+        });
+
+        let response = states
+            .join(resources)
+            .and_then(move |(states, resources)| -> Result<_, Error> {
+                let body = render(
+                    &states,
+                    resources.as_ref(),
+                    &modules,
+                    &requests,
+                    reconcile.duration(),
+                );
+
+                let response = Response::builder()
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, METRICS)
+                    .header(CONTENT_LENGTH, body.len().to_string().as_str())
+                    .body(body.into())
+                    .context(ErrorKind::RuntimeOperation(
+                        RuntimeOperation::SystemResources,
+                    ))?;
+                Ok(response)
+            })
+            .or_else(|e| Ok(e.into_response()));
+
+        Box::new(response)
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn render(
+    states: &[(String, ModuleStatus)],
+    resources: Option<&SystemResources>,
+    modules: &ModuleMetrics,
+    requests: &RequestMetrics,
+    reconcile: Option<Duration>,
+) -> String {
+    let mut exposition = Exposition::new();
+
+    exposition.family(
+        "edgelet_module_up",
+        "Whether the module is running",
+        MetricType::Gauge,
+    );
+    for (name, status) in states {
+        let up = if *status == ModuleStatus::Running {
+            1.0
+        } else {
+            0.0
+        };
+        exposition.sample("edgelet_module_up", &[("module", name)], up);
+    }
+
+    exposition.family(
+        "edgelet_module_restarts_total",
+        "Number of times the module was restarted through the management API",
+        MetricType::Counter,
+    );
+    for (name, _) in states {
+        exposition.sample(
+            "edgelet_module_restarts_total",
+            &[("module", name)],
+            modules.restarts(name) as f64,
+        );
+    }
+
+    if let Some(resources) = resources {
+        let stats = module_stats(resources.docker_stats());
+
+        exposition.family(
+            "edgelet_module_cpu_seconds_total",
+            "CPU time used by the module",
+            MetricType::Counter,
+        );
+        for (name, stats) in &stats {
+            if let Some(cpu) = stats.cpu_seconds {
+                exposition.sample("edgelet_module_cpu_seconds_total", &[("module", name)], cpu);
+            }
+        }
+
+        exposition.family(
+            "edgelet_module_memory_usage_bytes",
+            "Memory used by the module",
+            MetricType::Gauge,
+        );
+        for (name, stats) in &stats {
+            if let Some(memory) = stats.memory_bytes {
+                exposition.sample(
+                    "edgelet_module_memory_usage_bytes",
+                    &[("module", name)],
+                    memory,
+                );
+            }
+        }
+
+        exposition
+            .family(
+                "edgelet_host_cpu_usage_percent",
+                "CPU used on the host",
+                MetricType::Gauge,
+            )
+            .sample("edgelet_host_cpu_usage_percent", &[], resources.used_cpu())
+            .family(
+                "edgelet_host_memory_used_bytes",
+                "Memory used on the host",
+                MetricType::Gauge,
+            )
+            .sample(
+                "edgelet_host_memory_used_bytes",
+                &[],
+                resources.used_ram() as f64,
+            )
+            .family(
+                "edgelet_host_memory_total_bytes",
+                "Memory on the host",
+                MetricType::Gauge,
+            )
+            .sample(
+                "edgelet_host_memory_total_bytes",
+                &[],
+                resources.total_ram() as f64,
+            )
+            .family(
+                "edgelet_host_uptime_seconds",
+                "Time since the host started",
+                MetricType::Gauge,
+            )
+            .sample(
+                "edgelet_host_uptime_seconds",
+                &[],
+                resources.host_uptime() as f64,
+            )
+            .family(
+                "edgelet_process_uptime_seconds",
+                "Time since the edgelet started",
+                MetricType::Gauge,
+            )
+            .sample(
+                "edgelet_process_uptime_seconds",
+                &[],
+                resources.process_uptime() as f64,
+            );
+    }
+
+    // The watchdog hasn't reconciled yet right after the daemon starts.
+    if let Some(reconcile) = reconcile {
+        exposition
+            .family(
+                "edgelet_watchdog_reconcile_duration_seconds",
+                "Time the running reconcile of the edge runtime module has taken, or the last one took",
+                MetricType::Gauge,
+            )
+            .sample(
+                "edgelet_watchdog_reconcile_duration_seconds",
+                &[],
+                reconcile.as_secs_f64(),
+            );
+    }
+
+    requests.write(&mut exposition);
+
+    exposition.into_string()
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct ModuleStats {
+    cpu_seconds: Option<f64>,
+    memory_bytes: Option<f64>,
+}
+
+// The runtime's stats are an array of Docker container stats. Anything that
+// doesn't look like one is skipped.
+fn module_stats(stats: &str) -> BTreeMap<String, ModuleStats> {
+    let stats: Vec<Value> = match serde_json::from_str(stats) {
+        Ok(stats) => stats,
+        Err(err) => {
+            debug!("Could not parse module stats for metrics: {}", err);
+            return BTreeMap::new();
+        }
+    };
+
+    stats
+        .iter()
+        .filter_map(|stats| {
+            let name = stats["name"].as_str()?.trim_start_matches('/').to_string();
+            let cpu_seconds = stats["cpu_stats"]["cpu_usage"]["total_usage"]
+                .as_f64()
+                .map(|nanos| nanos / 1_000_000_000.0);
+            let memory_bytes = stats["memory_stats"]["usage"].as_f64();
+            Some((
+                name,
+                ModuleStats {
+                    cpu_seconds,
+                    memory_bytes,
+                },
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::prelude::*;
+    use edgelet_core::{DiskInfo, MakeModuleRuntime, ModuleRuntimeState};
+    use edgelet_test_utils::crypto::TestHsm;
+    use edgelet_test_utils::module::*;
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    const DOCKER_STATS: &str = r#"[
+        {
+            "name": "/edgeHub",
+            "cpu_stats": { "cpu_usage": { "total_usage": 2500000000 } },
+            "memory_stats": { "usage": 10485760 }
+        },
+        {
+            "name": "/tempSensor",
+            "memory_stats": {}
+        }
+    ]"#;
+
+    // Checks that every line is a comment or a sample with a numeric value,
+    // and that every sample belongs to a family declared before it.
+    fn assert_valid_exposition(text: &str) {
+        let mut families = BTreeSet::new();
+        for line in text.lines() {
+            if line.starts_with("# TYPE ") {
+                let mut parts = line["# TYPE ".len()..].split(' ');
+                families.insert(parts.next().unwrap().to_string());
+                assert!(["counter", "gauge", "histogram"].contains(&parts.next().unwrap()));
+            } else if line.starts_with("# HELP ") {
+                continue;
+            } else {
+                let (name, value) = line.split_at(line.rfind(' ').unwrap());
+                let value = value.trim();
+                assert!(
+                    value.parse::<f64>().is_ok() || value == "+Inf" || value == "NaN",
+                    "{}",
+                    line
+                );
+
+                let name = name.split('{').next().unwrap();
+                let family = ["_bucket", "_sum", "_count"]
+                    .iter()
+                    .find_map(|suffix| {
+                        let trimmed = name.trim_end_matches(suffix);
+                        if trimmed != name && families.contains(trimmed) {
+                            Some(trimmed)
+                        } else {
+                            None
+                        }
+                    })
+                    .unwrap_or(name);
+                assert!(families.contains(family), "{}", line);
+                assert!(family.starts_with("edgelet_"), "{}", line);
+            }
+        }
+    }
+
+    #[test]
+    fn render_is_valid_exposition() {
+        let resources = SystemResources::new(
+            595_023,
+            200,
+            0.25,
+            5000,
+            8000,
+            vec![DiskInfo::new(
+                "test disk".to_owned(),
+                10000,
+                20000,
+                "test system".to_owned(),
+                "test type".to_owned(),
+            )],
+            DOCKER_STATS.to_owned(),
+        );
+        let modules = ModuleMetrics::new();
+        modules.observe(&Method::POST, "/modules/edgeHub/start");
+        modules.observe(&Method::POST, "/modules/edgeHub/restart");
+        modules.observe(&Method::POST, "/modules/edgeHub/start");
+        modules.observe(&Method::POST, "/modules/tempSensor/start");
+        let requests = RequestMetrics::new();
+        requests.observe(
+            "mgmt",
+            &Method::GET,
+            StatusCode::OK,
+            std::time::Duration::from_millis(3),
+        );
+
+        let text = render(
+            &[
+                ("edgeHub".to_string(), ModuleStatus::Running),
+                ("tempSensor".to_string(), ModuleStatus::Failed),
+            ],
+            Some(&resources),
+            &modules,
+            &requests,
+            Some(Duration::from_millis(1500)),
+        );
+
+        assert_valid_exposition(&text);
+        assert!(text.contains("edgelet_module_up{module=\"edgeHub\"} 1\n"));
+        assert!(text.contains("edgelet_module_up{module=\"tempSensor\"} 0\n"));
+        assert!(text.contains("edgelet_module_restarts_total{module=\"edgeHub\"} 2\n"));
+        assert!(text.contains("edgelet_module_restarts_total{module=\"tempSensor\"} 0\n"));
+        assert!(text.contains("edgelet_module_cpu_seconds_total{module=\"edgeHub\"} 2.5\n"));
+        assert!(!text.contains("edgelet_module_cpu_seconds_total{module=\"tempSensor\"}"));
+        assert!(text.contains("edgelet_module_memory_usage_bytes{module=\"edgeHub\"} 10485760\n"));
+        assert!(text.contains("edgelet_host_memory_total_bytes 8000\n"));
+        assert!(text.contains("edgelet_watchdog_reconcile_duration_seconds 1.5\n"));
+        assert!(text.contains(
+            "edgelet_http_request_duration_seconds_count{server=\"mgmt\",method=\"GET\",status=\"200\"} 1\n"
+        ));
+    }
+
+    #[test]
+    fn restarts_ignore_other_requests() {
+        let modules = ModuleMetrics::new();
+        modules.observe(&Method::POST, "/modules/edgeHub/start");
+        modules.observe(&Method::POST, "/modules/edgeHub/stop");
+        modules.observe(&Method::GET, "/modules/edgeHub/start");
+        modules.observe(&Method::POST, "/modules/edgeHub/start/extra");
+        modules.observe(&Method::POST, "/modules");
+
+        assert_eq!(0, modules.restarts("edgeHub"));
+    }
+
+    #[test]
+    fn handler_reports_module_states_without_stats() {
+        let state = ModuleRuntimeState::default()
+            .with_status(ModuleStatus::Running)
+            .with_started_at(Some(Utc.ymd(2018, 4, 13).and_hms_milli(14, 20, 0, 1)));
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error, _> =
+            TestModule::new("test-module".to_string(), config, Ok(state));
+        let runtime = TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(Ok(module));
+        let handler = GetMetrics::new(
+            runtime,
+            ModuleMetrics::new(),
+            RequestMetrics::new(),
+            ReconcileTimer::new(),
+        );
+        let request = Request::get("http://localhost/metrics")
+            .body(Body::default())
+            .unwrap();
+
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(METRICS, response.headers()[CONTENT_TYPE].to_str().unwrap());
+
+        let body = response.into_body().concat2().wait().unwrap();
+        let text = std::str::from_utf8(&body).unwrap();
+        assert_valid_exposition(text);
+        assert!(text.contains("edgelet_module_up{module=\"test-module\"} 1\n"));
+        // the test runtime's stats aren't Docker stats
+        assert!(!text.contains("edgelet_module_memory_usage_bytes{"));
+        assert!(text.contains("edgelet_host_memory_total_bytes 8000\n"));
+        // the watchdog hasn't reconciled
+        assert!(!text.contains("edgelet_watchdog_reconcile_duration_seconds"));
+    }
+}
@@ -1,87 +1,301 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+use std::sync::Arc;
+
 use failure::{Compat, Fail, ResultExt};
 use futures::sync::mpsc::UnboundedSender;
 use futures::{future, Future};
 
 use hyper::service::{NewService, Service};
-use hyper::{Body, Request};
+use hyper::{Body, Method, Request};
 use lazy_static::lazy_static;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 
+use edgelet_core::watchdog::{ReconcileTimer, WatchdogPause};
 use edgelet_core::{
-    Authenticator, IdentityManager, Module, ModuleRuntime, ModuleRuntimeErrorReason, Policy,
+    Authenticator, DeploymentReconciler, HealthSettings, IdentityManager, ImageReference,
+    LastDeploymentStatus, LogsSettings, MaintenanceWindows, Module, ModuleRuntime,
+    ModuleRuntimeErrorReason, Policy, UpgradeGuard, UpstreamEndpoints,
 };
 use edgelet_http::authentication::Authentication;
 use edgelet_http::authorization::Authorization;
+use edgelet_http::metrics::RequestMetrics;
 use edgelet_http::route::*;
 use edgelet_http::router;
-use edgelet_http::Version;
+use edgelet_http::{Version, API_VERSION};
 
+mod deployment;
 mod device_actions;
+mod events;
+mod health;
 mod identity;
+mod images;
+mod metrics;
 mod module;
+mod support_bundle;
 mod system_info;
+mod upgrade;
+mod upstream;
+mod version;
+mod watchdog;
 
+use self::deployment::{ApplyDeployment, GetDeploymentStatus};
 use self::device_actions::*;
+use self::events::GetModuleEvents;
+use self::health::{GetLiveness, GetReadiness};
 use self::identity::*;
+use self::images::PruneImages;
+use self::metrics::GetMetrics;
+pub use self::metrics::ModuleMetrics;
 pub use self::module::*;
+use self::support_bundle::GetSupportBundle;
 use self::system_info::*;
+use self::upgrade::{PrepareUpgrade, ResumeFromUpgrade};
+use self::upstream::GetUpstream;
+use self::version::{version_response, FEATURES};
+use self::watchdog::{PauseWatchdog, ResumeModule, ResumeWatchdog};
 use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
 
 lazy_static! {
     static ref AGENT_NAME: String = "edgeAgent".to_string();
 }
 
+const METRICS_PATH: &str = "/metrics";
+const LIVENESS_PATH: &str = "/health/live";
+const READINESS_PATH: &str = "/health/ready";
+const VERSION_PATH: &str = "/version";
+
 #[derive(Clone)]
 pub struct ManagementService {
     inner: RouterService<RegexRecognizer>,
+    metrics: Option<Metrics>,
+    health: Option<Health>,
+    exec: bool,
+}
+
+#[derive(Clone)]
+struct Health {
+    live: Arc<dyn Handler<Parameters> + Sync>,
+    ready: Arc<dyn Handler<Parameters> + Sync>,
+}
+
+#[derive(Clone)]
+struct Metrics {
+    handler: Arc<dyn Handler<Parameters> + Sync>,
+    modules: ModuleMetrics,
 }
 
 impl ManagementService {
+    /// `config` is the daemon's settings, which support bundles include with
+    /// their secrets redacted. `logs` says how the logs of modules are read.
+    /// `deployments` applies the deployments that are put to `/deployment`,
+    /// whose outcome `deployment_status` keeps. `upgrade` is quiesced when
+    /// the daemon is prepared for an upgrade, `pause` pauses the watchdog,
+    /// `maintenance` are the windows that modules are only updated in, and
+    /// `upstream` is the IoT Hub that the daemon is connected to.
+    #[allow(clippy::too_many_arguments)]
     pub fn new<M, I>(
         runtime: &M,
         identity: &I,
         initiate_shutdown_and_reprovision: UnboundedSender<()>,
+        initiate_reload: UnboundedSender<()>,
+        config: String,
+        logs: &LogsSettings,
+        deployments: &DeploymentReconciler<M>,
+        deployment_status: &LastDeploymentStatus,
+        upgrade: &UpgradeGuard,
+        pause: &WatchdogPause,
+        maintenance: &MaintenanceWindows,
+        upstream: &UpstreamEndpoints,
     ) -> impl Future<Item = Self, Error = Error>
     where
         M: ModuleRuntime + Authenticator<Request = Request<Body>> + Clone + Send + Sync + 'static,
         for<'r> &'r <M as ModuleRuntime>::Error: Into<ModuleRuntimeErrorReason>,
-        <M::Module as Module>::Config: DeserializeOwned + Serialize,
+        <M::Module as Module>::Config: DeserializeOwned + ImageReference + Serialize,
         M::Logs: Into<Body>,
         I: IdentityManager + Clone + Send + Sync + 'static,
         I::Identity: Serialize,
         <M::AuthenticateFuture as Future>::Error: Fail,
     {
         let router = router!(
-            get     Version2018_06_28 runtime Policy::Anonymous             => "/modules"                           => ListModules::new(runtime.clone()),
+            get     Version2018_06_28 runtime Policy::Anonymous             => "/modules"                           => ListModules::new(runtime.clone()).with_pause(pause.clone()),
             post    Version2018_06_28 runtime Policy::Module(&*AGENT_NAME)  => "/modules"                           => CreateModule::new(runtime.clone()),
-            get     Version2018_06_28 runtime Policy::Anonymous             => "/modules/(?P<name>[^/]+)"           => GetModule,
-            put     Version2018_06_28 runtime Policy::Module(&*AGENT_NAME)  => "/modules/(?P<name>[^/]+)"           => UpdateModule::new(runtime.clone()),
+            get     Version2018_06_28 runtime Policy::Anonymous             => "/modules/(?P<name>[^/]+)"           => GetModule::new(runtime.clone()),
+            put     Version2018_06_28 runtime Policy::Module(&*AGENT_NAME)  => "/modules/(?P<name>[^/]+)"           => UpdateModule::new(runtime.clone()).with_maintenance_windows(maintenance.clone()),
+            patch   Version2019_11_05 runtime Policy::Module(&*AGENT_NAME)  => "/modules/(?P<name>[^/]+)"           => PatchModule::new(runtime.clone()),
             post    Version2019_01_30 runtime Policy::Module(&*AGENT_NAME)  => "/modules/(?P<name>[^/]+)/prepareupdate"   => PrepareUpdateModule::new(runtime.clone()),
             delete  Version2018_06_28 runtime Policy::Module(&*AGENT_NAME)  => "/modules/(?P<name>[^/]+)"           => DeleteModule::new(runtime.clone()),
             post    Version2018_06_28 runtime Policy::Anonymous             => "/modules/(?P<name>[^/]+)/start"     => StartModule::new(runtime.clone()),
             post    Version2018_06_28 runtime Policy::Anonymous             => "/modules/(?P<name>[^/]+)/stop"      => StopModule::new(runtime.clone()),
             post    Version2018_06_28 runtime Policy::Anonymous             => "/modules/(?P<name>[^/]+)/restart"   => RestartModule::new(runtime.clone()),
-            get     Version2018_06_28 runtime Policy::Anonymous             => "/modules/(?P<name>[^/]+)/logs"      => ModuleLogs::new(runtime.clone()),
+            post    Version2019_11_05 runtime Policy::Anonymous             => "/modules/(?P<name>[^/]+)/resume"    => ResumeModule::new(pause.clone()),
+            post    Version2019_11_05 runtime Policy::Anonymous             => "/modules/restart"                   => RestartModules::new(runtime.clone()),
+            get     Version2018_06_28 runtime Policy::Anonymous             => "/modules/(?P<name>[^/]+)/logs"      => ModuleLogs::new(runtime.clone(), logs.max_line_length()),
+            get     Version2019_11_05 runtime Policy::Anonymous             => "/modules/(?P<name>[^/]+)/stats"     => ModuleStats::new(runtime.clone()),
+            get     Version2019_11_05 runtime Policy::Anonymous             => "/modules/(?P<name>[^/]+)/wait"      => WaitModule::new(runtime.clone()),
+            get     Version2019_11_05 runtime Policy::Anonymous             => "/modules/(?P<name>[^/]+)/effective-spec" => GetEffectiveSpec::new(runtime.clone()),
+            post    Version2019_11_05 runtime Policy::Module(&*AGENT_NAME)  => "/modules/(?P<name>[^/]+)/exec"      => ExecModule::new(runtime.clone()),
+            post    Version2019_11_05 runtime Policy::Anonymous             => "/diff"                              => DiffModules::new(runtime.clone()),
+            get     Version2019_11_05 runtime Policy::Anonymous             => "/events"                            => GetModuleEvents::new(runtime.clone()),
+
+            put     Version2019_11_05 runtime Policy::Module(&*AGENT_NAME)  => "/deployment"                        => ApplyDeployment::new(deployments.clone()),
+            get     Version2019_11_05 runtime Policy::Anonymous             => "/deployment"                        => GetDeploymentStatus::new(deployment_status.clone()),
+
+            post    Version2019_11_05 runtime Policy::Anonymous             => "/images/prune"                      => PruneImages::new(runtime.clone()),
 
             get     Version2018_06_28 runtime Policy::Module(&*AGENT_NAME)  => "/identities"                        => ListIdentities::new(identity.clone()),
             post    Version2018_06_28 runtime Policy::Module(&*AGENT_NAME)  => "/identities"                        => CreateIdentity::new(identity.clone()),
             put     Version2018_06_28 runtime Policy::Module(&*AGENT_NAME)  => "/identities/(?P<name>[^/]+)"        => UpdateIdentity::new(identity.clone()),
             delete  Version2018_06_28 runtime Policy::Module(&*AGENT_NAME)  => "/identities/(?P<name>[^/]+)"        => DeleteIdentity::new(identity.clone()),
 
-            get     Version2018_06_28 runtime Policy::Anonymous             => "/systeminfo"                        => GetSystemInfo::new(runtime.clone()),
+            get     Version2018_06_28 runtime Policy::Anonymous             => "/systeminfo"                        => GetSystemInfo::new(runtime.clone()).with_upstream(upstream.clone()),
             get     Version2019_11_05 runtime Policy::Anonymous             => "/systeminfo/resources"              => GetSystemResources::new(runtime.clone()),
+            get     Version2019_11_05 runtime Policy::Anonymous             => "/support-bundle"                    => GetSupportBundle::new(runtime.clone(), config, logs.max_line_length()),
 
             post    Version2019_10_22 runtime Policy::Module(&*AGENT_NAME)  => "/device/reprovision"                => ReprovisionDevice::new(initiate_shutdown_and_reprovision),
+            post    Version2019_11_05 runtime Policy::Anonymous             => "/reload"                            => ReloadConfig::new(initiate_reload),
+            post    Version2019_11_05 runtime Policy::Anonymous             => "/prepare-upgrade"                   => PrepareUpgrade::new(runtime.clone(), upgrade.clone()),
+            post    Version2019_11_05 runtime Policy::Anonymous             => "/resume"                            => ResumeFromUpgrade::new(runtime.clone(), upgrade.clone()),
+            post    Version2019_11_05 runtime Policy::Anonymous             => "/watchdog/pause"                    => PauseWatchdog::new(pause.clone()),
+            post    Version2019_11_05 runtime Policy::Anonymous             => "/watchdog/resume"                   => ResumeWatchdog::new(pause.clone()),
+            get     Version2019_11_05 runtime Policy::Anonymous             => "/upstream"                          => GetUpstream::new(upstream.clone()),
         );
 
         router.new_service().then(|inner| {
             let inner = inner.context(ErrorKind::StartService)?;
-            Ok(ManagementService { inner })
+            Ok(ManagementService {
+                inner,
+                metrics: None,
+                health: None,
+                exec: false,
+            })
         })
     }
+
+    /// Serves Prometheus metrics at `/metrics`, which unlike the rest of the
+    /// API doesn't need an `api-version`. `requests` are the latencies of the
+    /// requests handled by the daemon's HTTP servers, and `reconcile` times the
+    /// reconciles of the watchdog.
+    pub fn with_metrics<M>(
+        mut self,
+        runtime: &M,
+        requests: RequestMetrics,
+        reconcile: &ReconcileTimer,
+    ) -> Self
+    where
+        M: ModuleRuntime + Clone + Send + Sync + 'static,
+    {
+        let modules = ModuleMetrics::new();
+        self.metrics = Some(Metrics {
+            handler: Arc::new(GetMetrics::new(
+                runtime.clone(),
+                modules.clone(),
+                requests,
+                reconcile.clone(),
+            )),
+            modules,
+        });
+        self
+    }
+
+    /// Serves liveness at `/health/live` and readiness, as `settings` define
+    /// it, at `/health/ready`. Like `/metrics` they don't need an
+    /// `api-version`, so that probes can be plain requests.
+    pub fn with_health<M>(
+        mut self,
+        runtime: &M,
+        settings: &HealthSettings,
+        upstream: &UpstreamEndpoints,
+    ) -> Self
+    where
+        M: ModuleRuntime + Clone + Send + Sync + 'static,
+    {
+        self.health = Some(Health {
+            live: Arc::new(GetLiveness),
+            ready: Arc::new(GetReadiness::new(
+                runtime.clone(),
+                settings.clone(),
+                upstream.clone(),
+            )),
+        });
+        self
+    }
+
+    /// Runs commands in modules at `/modules/{name}/exec`, which is otherwise
+    /// refused with `403 Forbidden` before the request is authorized.
+    pub fn with_exec(mut self) -> Self {
+        self.exec = true;
+        self
+    }
+}
+
+// Where a request is served from, before any `api-version` is checked.
+enum Route<'a> {
+    ExecDisabled,
+    Handler(&'a Arc<dyn Handler<Parameters> + Sync>),
+    Version,
+    Api,
+}
+
+impl ManagementService {
+    fn route(&self, method: &Method, path: &str) -> Route<'_> {
+        if !self.exec && is_exec(method, path) {
+            return Route::ExecDisabled;
+        }
+
+        if method != Method::GET {
+            return Route::Api;
+        }
+
+        match (path, &self.health, &self.metrics) {
+            (LIVENESS_PATH, Some(health), _) => Route::Handler(&health.live),
+            (READINESS_PATH, Some(health), _) => Route::Handler(&health.ready),
+            (METRICS_PATH, _, Some(metrics)) => Route::Handler(&metrics.handler),
+            (VERSION_PATH, _, _) => Route::Version,
+            _ => Route::Api,
+        }
+    }
+
+    // Whether a request for `path` with the latest `api-version` would reach
+    // a handler.
+    fn serves(&self, method: &Method, path: &str) -> bool {
+        match self.route(method, path) {
+            Route::ExecDisabled => false,
+            Route::Handler(_) | Route::Version => true,
+            Route::Api => self.inner.recognizes(method, API_VERSION, path),
+        }
+    }
+
+    /// The features that `/version` advertises, which are the ones whose
+    /// requests this service serves.
+    fn features(&self) -> Vec<&'static str> {
+        FEATURES
+            .iter()
+            .filter(|feature| self.serves(&feature.method, feature.path))
+            .map(|feature| feature.name)
+            .collect()
+    }
+}
+
+// Whether the request runs a command at `/modules/{name}/exec`.
+fn is_exec(method: &Method, path: &str) -> bool {
+    if method != Method::POST {
+        return false;
+    }
+
+    let mut segments = path.split('/');
+    match (
+        segments.next(),
+        segments.next(),
+        segments.next(),
+        segments.next(),
+        segments.next(),
+    ) {
+        (Some(""), Some("modules"), Some(name), Some("exec"), None) => !name.is_empty(),
+        _ => false,
+    }
 }
 
 impl Service for ManagementService {
@@ -91,7 +305,33 @@ impl Service for ManagementService {
     type Future = <RouterService<RegexRecognizer> as Service>::Future;
 
     fn call(&mut self, req: Request<Body>) -> Self::Future {
-        self.inner.call(req)
+        match self.route(req.method(), req.uri().path()) {
+            Route::ExecDisabled => {
+                let response = Error::from(ErrorKind::ExecDisabled).into_response();
+                return Box::new(future::ok(response));
+            }
+            Route::Handler(handler) => {
+                return Box::new(handler.handle(req, Parameters::new()).map_err(Fail::compat));
+            }
+            Route::Version => {
+                return Box::new(future::ok(version_response(&self.features())));
+            }
+            Route::Api => (),
+        }
+
+        let metrics = match &self.metrics {
+            Some(metrics) => metrics.clone(),
+            None => return self.inner.call(req),
+        };
+
+        let method = req.method().clone();
+        let path = req.uri().path().to_string();
+        Box::new(self.inner.call(req).map(move |response| {
+            if response.status().is_success() {
+                metrics.modules.observe(&method, &path);
+            }
+            response
+        }))
     }
 }
 
@@ -107,3 +347,273 @@ impl NewService for ManagementService {
         future::ok(self.clone())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::sync::mpsc;
+    use futures::{Async, Stream};
+    use hyper::StatusCode;
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::hash::MessageDigest;
+    use openssl::nid::Nid;
+    use openssl::pkey::PKey;
+    use openssl::sign::Signer;
+    use serde_json::{json, Value};
+
+    use edgelet_core::{
+        canonicalize, Deployment, DeploymentState, DeploymentStatus, MakeModuleRuntime,
+        ManifestTrustKey, ModuleRuntimeState, SignedManifest, UpstreamSettings,
+    };
+    use edgelet_test_utils::crypto::TestHsm;
+    use edgelet_test_utils::identity::TestIdentityManager;
+    use edgelet_test_utils::module::*;
+
+    use super::*;
+    use crate::server::module::tests::Error as TestError;
+
+    fn runtime() -> TestRuntime<TestError, TestSettings> {
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<TestError, _> = TestModule::new(
+            "edgeAgent".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default()),
+        );
+        TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(Ok(module))
+    }
+
+    fn upstream() -> UpstreamEndpoints {
+        UpstreamEndpoints::new(
+            "primary.azure-devices.net".to_string(),
+            &UpstreamSettings::default(),
+        )
+    }
+
+    fn service(runtime: &TestRuntime<TestError, TestSettings>) -> ManagementService {
+        let deployments = DeploymentReconciler::new(
+            runtime.clone(),
+            Deployment::new(None),
+            Duration::from_secs(10),
+        );
+        service_with_deployments(runtime, &deployments, &LastDeploymentStatus::new())
+    }
+
+    fn service_with_deployments(
+        runtime: &TestRuntime<TestError, TestSettings>,
+        deployments: &DeploymentReconciler<TestRuntime<TestError, TestSettings>>,
+        deployment_status: &LastDeploymentStatus,
+    ) -> ManagementService {
+        let (reprovision, _) = mpsc::unbounded();
+        let (reload, _) = mpsc::unbounded();
+        ManagementService::new(
+            runtime,
+            &TestIdentityManager::new(vec![]),
+            reprovision,
+            reload,
+            String::new(),
+            &LogsSettings::default(),
+            deployments,
+            deployment_status,
+            &UpgradeGuard::new("/nonexistent/upgrade_state"),
+            &WatchdogPause::new(),
+            &MaintenanceWindows::default(),
+            &upstream(),
+        )
+        .wait()
+        .unwrap()
+    }
+
+    #[test]
+    fn features_that_are_off_are_not_advertised() {
+        let features = service(&runtime()).features();
+
+        assert!(features.contains(&"restart_endpoint"));
+        assert!(features.contains(&"upstream"));
+        assert!(!features.contains(&"exec"));
+        assert!(!features.contains(&"health"));
+        assert!(!features.contains(&"metrics"));
+    }
+
+    #[test]
+    fn every_feature_is_advertised_when_enabled() {
+        let runtime = runtime();
+        let service = service(&runtime)
+            .with_exec()
+            .with_metrics(&runtime, RequestMetrics::new(), &ReconcileTimer::new())
+            .with_health(&runtime, &HealthSettings::default(), &upstream());
+
+        // Each feature is served by a route of its own, so one that isn't
+        // advertised here has a request that doesn't match its route.
+        assert_eq!(
+            FEATURES
+                .iter()
+                .map(|feature| feature.name)
+                .collect::<Vec<_>>(),
+            service.features()
+        );
+    }
+
+    #[test]
+    fn version_does_not_need_api_version() {
+        let mut service = service(&runtime()).with_exec();
+        let request = Request::get("http://localhost/version")
+            .body(Body::default())
+            .unwrap();
+
+        let response = service.call(request).wait().unwrap();
+        assert!(response.status().is_success());
+        let body = response.into_body().concat2().wait().unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!("2019-11-05", body["apiVersion"]);
+        let features = body["features"].as_array().unwrap();
+        assert!(features.iter().any(|feature| feature == "exec"));
+        assert!(!features.iter().any(|feature| feature == "metrics"));
+    }
+
+    fn call(service: &mut ManagementService, request: Request<Body>) -> (StatusCode, Value) {
+        let response = service.call(request).wait().unwrap();
+        let status = response.status();
+        let body = response.into_body().concat2().wait().unwrap();
+        let body = if body.is_empty() {
+            Value::Null
+        } else {
+            serde_json::from_slice(&body).unwrap()
+        };
+        (status, body)
+    }
+
+    fn put_deployment(payload: &Value) -> Request<Body> {
+        Request::put("http://localhost/deployment?api-version=2019-11-05")
+            .body(payload.to_string().into())
+            .unwrap()
+    }
+
+    fn get_deployment() -> Request<Body> {
+        Request::get("http://localhost/deployment?api-version=2019-11-05")
+            .body(Body::default())
+            .unwrap()
+    }
+
+    #[test]
+    fn deployment_is_applied_and_its_status_served() {
+        let runtime = runtime();
+        let (status_tx, status_rx) = mpsc::unbounded();
+        let deployments = DeploymentReconciler::new(
+            runtime.clone(),
+            Deployment::new(None).with_status_channel(status_tx),
+            Duration::from_secs(10),
+        );
+        let deployment_status = LastDeploymentStatus::new();
+        let mut follow = deployment_status.follow(status_rx);
+        // Takes in the statuses that were reported so far.
+        let mut catch_up = || {
+            future::poll_fn(|| {
+                let _ = follow.poll();
+                Ok::<_, ()>(Async::Ready(()))
+            })
+            .wait()
+            .unwrap();
+        };
+        let mut service = service_with_deployments(&runtime, &deployments, &deployment_status);
+
+        let (status, _) = call(&mut service, get_deployment());
+        assert_eq!(StatusCode::NOT_FOUND, status);
+
+        let payload = json!({
+            "modules": {
+                "tempSensor": { "type": "docker", "config": { "image": "sensor:1.0" } },
+            },
+        });
+        let (status, _) = call(&mut service, put_deployment(&payload));
+        assert_eq!(StatusCode::NO_CONTENT, status);
+        assert_eq!(
+            vec!["create tempSensor", "start tempSensor"],
+            runtime.operations()
+        );
+        catch_up();
+        let (status, body) = call(&mut service, get_deployment());
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!("applied", body["state"]);
+
+        // A payload that isn't valid leaves the modules as they are.
+        let (status, body) = call(&mut service, put_deployment(&json!({ "modules": [] })));
+        assert_eq!(StatusCode::BAD_REQUEST, status);
+        assert_eq!("InvalidRequest", body["code"]);
+        assert_eq!(2, runtime.operations().len());
+        catch_up();
+        let (_, body) = call(&mut service, get_deployment());
+        assert_eq!("rejected", body["state"]);
+        assert_eq!(
+            &json!(["modules: must be an object of module specs by name"]),
+            &body["errors"]
+        );
+        assert_eq!(
+            Some(DeploymentState::Rejected),
+            deployment_status.get().map(|status| status.state())
+        );
+    }
+
+    #[test]
+    fn tampered_manifest_is_rejected_with_trust_key() {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let private = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+        let trust_key = ManifestTrustKey::from_pem(&private.public_key_to_pem().unwrap()).unwrap();
+        let sign = |manifest: Value| {
+            let mut signer = Signer::new(MessageDigest::sha256(), &private).unwrap();
+            signer.update(&canonicalize(&manifest)).unwrap();
+            let signature = base64::encode(&signer.sign_to_vec().unwrap());
+            serde_json::to_value(SignedManifest::new(manifest, signature)).unwrap()
+        };
+
+        let runtime = runtime();
+        let (status_tx, status_rx) = mpsc::unbounded();
+        let deployments = DeploymentReconciler::new(
+            runtime.clone(),
+            Deployment::new(None)
+                .with_status_channel(status_tx)
+                .with_trust_key(trust_key),
+            Duration::from_secs(10),
+        );
+        let deployment_status = LastDeploymentStatus::new();
+        let mut service = service_with_deployments(&runtime, &deployments, &deployment_status);
+
+        let mut payload = sign(json!({
+            "modules": {
+                "tempSensor": { "type": "docker", "config": { "image": "sensor:1.0" } },
+            },
+        }));
+        let (status, _) = call(&mut service, put_deployment(&payload));
+        assert_eq!(StatusCode::NO_CONTENT, status);
+        assert_eq!(2, runtime.operations().len());
+
+        payload["manifest"]["modules"]["tempSensor"]["config"]["image"] = json!("evil:1.0");
+        let (status, body) = call(&mut service, put_deployment(&payload));
+        assert_eq!(StatusCode::BAD_REQUEST, status);
+        assert_eq!("InvalidRequest", body["code"]);
+        assert_eq!(2, runtime.operations().len());
+        assert_eq!(
+            "sensor:1.0",
+            deployments.current().unwrap().modules()[0].config().image()
+        );
+
+        drop(deployments);
+        drop(service);
+        let statuses = status_rx.collect().wait().unwrap();
+        assert_eq!(
+            vec![DeploymentState::Applied, DeploymentState::Rejected],
+            statuses
+                .iter()
+                .map(DeploymentStatus::state)
+                .collect::<Vec<_>>()
+        );
+    }
+}
@@ -10,14 +10,12 @@ use serde::de::DeserializeOwned;
 use serde::Serialize;
 use serde_json;
 
-use edgelet_core::{
-    ImagePullPolicy, Module, ModuleRegistry, ModuleRuntime, ModuleStatus, RuntimeOperation,
-};
+use edgelet_core::{pull_image, Module, ModuleRuntime, ModuleStatus, RuntimeOperation};
 use edgelet_http::route::{Handler, Parameters};
 use edgelet_http::Error as HttpError;
 use management::models::*;
 
-use super::{spec_to_core, spec_to_details};
+use super::{dry_run_response, is_dry_run, spec_to_core, spec_to_details};
 use crate::error::{Error, ErrorKind};
 use crate::IntoResponse;
 
@@ -42,6 +40,7 @@ where
         _params: Parameters,
     ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
         let runtime = self.runtime.clone();
+        let dry_run = is_dry_run(&req);
         let response = req
             .into_body()
             .concat2()
@@ -54,62 +53,70 @@ where
             })
             .and_then(move |(spec, core_spec)| {
                 let module_name = spec.name().to_string();
-                let image_pull_policy = core_spec.image_pull_policy();
-
-                let pull_future = match image_pull_policy {
-                    ImagePullPolicy::OnCreate => Either::A(
-                        runtime
-                            .registry()
-                            .pull(core_spec.config())
-                            .then(move |result| {
+
+                if dry_run {
+                    let context = move || {
+                        ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(
+                            module_name.clone(),
+                        ))
+                    };
+                    let response = runtime
+                        .validate(&core_spec)
+                        .with_context(|_| context())
+                        .map_err(Error::from)
+                        .and_then(|()| {
+                            dry_run_response(spec.name().to_string(), "create", context)
+                        });
+                    return Either::A(futures::future::result(response));
+                }
+
+                let pull_future = pull_image(&runtime, &core_spec).then(move |result| {
+                    let image_pulled = result.with_context(|_| {
+                        ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(
+                            module_name.clone(),
+                        ))
+                    })?;
+                    Ok((module_name, image_pulled))
+                });
+
+                let create_future =
+                    pull_future.and_then(move |(name, image_pulled)| -> Result<_, Error> {
+                        if image_pulled {
+                            debug!("Successfully pulled new image for module {}", name)
+                        } else {
+                            debug!(
+                                "Skipped pulling image for module {} as per pull policy",
+                                name
+                            )
+                        }
+
+                        Ok(runtime
+                            .create(core_spec)
+                            .then(move |result| -> Result<_, Error> {
                                 result.with_context(|_| {
                                     ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(
-                                        module_name.clone(),
+                                        name.clone(),
                                     ))
                                 })?;
-                                Ok((module_name, true))
-                            }),
-                    ),
-                    ImagePullPolicy::Never => Either::B(futures::future::ok((module_name, false))),
-                };
-
-                pull_future.and_then(move |(name, image_pulled)| -> Result<_, Error> {
-                    if image_pulled {
-                        debug!("Successfully pulled new image for module {}", name)
-                    } else {
-                        debug!(
-                            "Skipped pulling image for module {} as per pull policy",
-                            name
-                        )
-                    }
-
-                    Ok(runtime
-                        .create(core_spec)
-                        .then(move |result| -> Result<_, Error> {
-                            result.with_context(|_| {
-                                ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(
-                                    name.clone(),
-                                ))
-                            })?;
-                            let details = spec_to_details(&spec, ModuleStatus::Stopped);
-                            let b = serde_json::to_string(&details).with_context(|_| {
-                                ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(
-                                    name.clone(),
-                                ))
-                            })?;
-                            let response = Response::builder()
-                                .status(StatusCode::CREATED)
-                                .header(CONTENT_TYPE, "application/json")
-                                .header(CONTENT_LENGTH, b.len().to_string().as_str())
-                                .body(b.into())
-                                .context(ErrorKind::RuntimeOperation(
-                                    RuntimeOperation::CreateModule(name),
-                                ))?;
-                            Ok(response)
-                        }))
-                })
+                                let details = spec_to_details(&spec, ModuleStatus::Stopped);
+                                let b = serde_json::to_string(&details).with_context(|_| {
+                                    ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(
+                                        name.clone(),
+                                    ))
+                                })?;
+                                let response = Response::builder()
+                                    .status(StatusCode::CREATED)
+                                    .header(CONTENT_TYPE, "application/json")
+                                    .header(CONTENT_LENGTH, b.len().to_string().as_str())
+                                    .body(b.into())
+                                    .context(ErrorKind::RuntimeOperation(
+                                        RuntimeOperation::CreateModule(name),
+                                    ))?;
+                                Ok(response)
+                            }))
+                    });
+                Either::B(create_future.flatten())
             })
-            .flatten()
             .or_else(|e| Ok(e.into_response()));
 
         Box::new(response)
@@ -194,6 +201,72 @@ mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn dry_run() {
+        let handler = CreateModule::new(RUNTIME.clone());
+        let config = Config::new(json!({"image":"microsoft/test-image"}));
+        let mut spec = ModuleSpec::new("test-module".to_string(), "docker".to_string(), config);
+        spec.set_image_pull_policy("on-create".to_string());
+        let request = Request::post("http://localhost/modules?dry_run=true")
+            .body(serde_json::to_string(&spec).unwrap().into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                let result: DryRunResult = serde_json::from_slice(&b).unwrap();
+                assert_eq!(1, result.modules().len());
+                assert_eq!("test-module", result.modules()[0].name());
+                assert_eq!("create", result.modules()[0].action());
+                Ok(())
+            })
+            .wait()
+            .unwrap();
+    }
+
+    #[test]
+    fn dry_run_validation_error() {
+        let runtime = TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(Err(Error::General));
+        let handler = CreateModule::new(runtime);
+        let config = Config::new(json!({"image":"microsoft/test-image"}));
+        let spec = ModuleSpec::new("test-module".to_string(), "docker".to_string(), config);
+        let request = Request::post("http://localhost/modules?dry_run=true")
+            .body(serde_json::to_string(&spec).unwrap().into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+                assert_eq!(
+                    "Could not create module test-module\n\tcaused by: General error",
+                    error.message()
+                );
+                Ok(())
+            })
+            .wait()
+            .unwrap();
+    }
+
     #[test]
     fn bad_body() {
         let handler = CreateModule::new(RUNTIME.clone());
@@ -295,6 +368,39 @@ mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn missing_create_options_segment() {
+        let handler = CreateModule::new(RUNTIME.clone());
+        let config = Config::new(json!({
+            "image": "microsoft/test-image",
+            "createOptions": "{\"Env\":",
+            "createOptions02": "[]}",
+        }));
+        let spec = ModuleSpec::new("test-module".to_string(), "docker".to_string(), config);
+        let request = Request::post("http://localhost/modules")
+            .body(serde_json::to_string(&spec).unwrap().into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+                assert_eq!(
+                    "Request body is malformed\n\tcaused by: Invalid module spec: create options segment \"createOptions01\" is missing",
+                    error.message()
+                );
+                Ok(())
+            })
+            .wait()
+            .unwrap();
+    }
+
     #[test]
     fn bad_image_pull_policy() {
         let handler = CreateModule::new(RUNTIME.clone());
@@ -1,8 +1,10 @@
 // Copyright (c) Microsoft. All rights reserved.
 
 use failure::{Fail, ResultExt};
+use futures::future::Either;
 use futures::{Future, IntoFuture};
 use hyper::{Body, Request, Response, StatusCode};
+use url::form_urlencoded;
 
 use edgelet_core::{ModuleRuntime, RuntimeOperation};
 use edgelet_http::route::{Handler, Parameters};
@@ -27,16 +29,26 @@ where
 {
     fn handle(
         &self,
-        _req: Request<Body>,
+        req: Request<Body>,
         params: Parameters,
     ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
         let response = params
             .name("name")
             .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
-            .map(|name| {
+            .and_then(|name| {
+                parse_force(req.uri().query().unwrap_or("")).map(|force| (name, force))
+            })
+            .map(|(name, force)| {
                 let name = name.to_string();
 
-                self.runtime.remove(&name).then(|result| match result {
+                // A forced remove also gets rid of modules that are stuck,
+                // and succeeds if the module is gone already.
+                let remove = if force {
+                    Either::A(self.runtime.force_remove(&name))
+                } else {
+                    Either::B(self.runtime.remove(&name))
+                };
+                remove.then(|result| match result {
                     Ok(_) => Ok(name),
                     Err(err) => Err(Error::from(err.context(ErrorKind::RuntimeOperation(
                         RuntimeOperation::RemoveModule(name),
@@ -59,6 +71,14 @@ where
     }
 }
 
+fn parse_force(query: &str) -> Result<bool, Error> {
+    let force = form_urlencoded::parse(query.as_bytes())
+        .find(|(key, _)| key == "force")
+        .map_or_else(|| Ok(false), |(_, val)| val.parse::<bool>())
+        .context(ErrorKind::MalformedRequestParameter("force"))?;
+    Ok(force)
+}
+
 #[cfg(test)]
 mod tests {
     use chrono::prelude::*;
@@ -106,6 +126,40 @@ mod tests {
         assert_eq!(StatusCode::NO_CONTENT, response.status());
     }
 
+    #[test]
+    fn force_delete_succeeds() {
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error, _> = TestModule::new(
+            "test-module".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default()),
+        );
+        let runtime = TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(Ok(module));
+        let handler = DeleteModule::new(runtime);
+
+        for (query, status) in &[
+            ("force=true", StatusCode::NO_CONTENT),
+            ("force=false", StatusCode::NO_CONTENT),
+            ("force=yes", StatusCode::BAD_REQUEST),
+        ] {
+            let parameters =
+                Parameters::with_captures(vec![(Some("name".to_string()), "test".to_string())]);
+            let request = Request::delete(format!("http://localhost/modules/test?{}", query))
+                .body(Body::default())
+                .unwrap();
+
+            let response = handler.handle(request, parameters).wait().unwrap();
+            assert_eq!(*status, response.status(), "{}", query);
+        }
+    }
+
     #[test]
     fn delete_bad_params() {
         // arrange
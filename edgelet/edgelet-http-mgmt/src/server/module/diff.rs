@@ -0,0 +1,225 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Previews what applying a set of modules would do to the modules on the
+//! device, such as before a control plane pushes a new deployment to it. The
+//! proposed modules are checked like the modules that are created, but
+//! nothing is changed. See `ModuleSet::plan` for how each module's action and
+//! the reasons for a restart are worked out.
+
+use failure::{Fail, ResultExt};
+use futures::{Future, Stream};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use log::debug;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json;
+
+use edgelet_core::{Module, ModuleRuntime, ModuleSet, PlannedChange};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+use management::models::{DryRunAction, DryRunResult, ModuleSpecList};
+
+use super::spec_to_core;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+pub struct DiffModules<M> {
+    runtime: M,
+}
+
+impl<M> DiffModules<M> {
+    pub fn new(runtime: M) -> Self {
+        DiffModules { runtime }
+    }
+}
+
+impl<M> Handler<Parameters> for DiffModules<M>
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+    <M::Module as Module>::Config: DeserializeOwned + Serialize,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        debug!("Diff modules");
+
+        let runtime = self.runtime.clone();
+        let response = req
+            .into_body()
+            .concat2()
+            .then(move |body| -> Result<_, Error> {
+                let body = body.context(ErrorKind::MalformedRequestBody)?;
+                let proposed: ModuleSpecList =
+                    serde_json::from_slice(&body).context(ErrorKind::MalformedRequestBody)?;
+
+                let mut specs = Vec::with_capacity(proposed.modules().len());
+                for spec in proposed.modules() {
+                    let spec = spec_to_core::<M>(spec, ErrorKind::MalformedRequestBody)?;
+                    runtime
+                        .validate(&spec)
+                        .map_err(|err| Error::from(err.context(ErrorKind::MalformedRequestBody)))?;
+                    specs.push(spec);
+                }
+                let modules = ModuleSet::new(specs).context(ErrorKind::MalformedRequestBody)?;
+                Ok((runtime, modules))
+            })
+            .and_then(|(runtime, modules)| {
+                runtime
+                    .list_with_details()
+                    .collect()
+                    .then(move |existing| -> Result<_, Error> {
+                        let existing = existing.context(ErrorKind::DiffModules)?;
+                        let plan = modules.plan(&existing).context(ErrorKind::DiffModules)?;
+                        let result = DryRunResult::new(plan.iter().map(planned_action).collect());
+
+                        let b = serde_json::to_string(&result).context(ErrorKind::DiffModules)?;
+                        let response = Response::builder()
+                            .status(StatusCode::OK)
+                            .header(CONTENT_TYPE, "application/json")
+                            .header(CONTENT_LENGTH, b.len().to_string().as_str())
+                            .body(b.into())
+                            .context(ErrorKind::DiffModules)?;
+                        Ok(response)
+                    })
+            })
+            .or_else(|e| Ok(e.into_response()));
+
+        Box::new(response)
+    }
+}
+
+fn planned_action(change: &PlannedChange) -> DryRunAction {
+    let action = DryRunAction::new(change.name().to_string(), change.action().to_string());
+    if change.reasons().is_empty() {
+        action
+    } else {
+        action.with_reasons(change.reasons().to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::{MakeModuleRuntime, ModuleRuntimeState, ModuleStatus};
+    use edgelet_http::route::Parameters;
+    use edgelet_test_utils::crypto::TestHsm;
+    use edgelet_test_utils::module::*;
+    use management::models::{Config, EnvVar, ErrorResponse, ModuleSpec};
+    use serde_json::json;
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    fn spec(name: &str, image: &str) -> ModuleSpec {
+        ModuleSpec::new(
+            name.to_string(),
+            "docker".to_string(),
+            Config::new(json!({ "image": image })),
+        )
+    }
+
+    // A runtime with one module, running the container of `spec`.
+    fn runtime(spec: &ModuleSpec) -> TestRuntime<Error, TestSettings> {
+        let core =
+            spec_to_core::<TestRuntime<Error, TestSettings>>(spec, ErrorKind::MalformedRequestBody)
+                .unwrap();
+        let state = ModuleRuntimeState::default()
+            .with_status(ModuleStatus::Running)
+            .with_labels(core.spec_labels().unwrap());
+        let module = TestModule::new(spec.name().clone(), core.config().clone(), Ok(state));
+        TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(Ok(module))
+    }
+
+    fn diff(runtime: TestRuntime<Error, TestSettings>, body: String) -> (StatusCode, Vec<u8>) {
+        let handler = DiffModules::new(runtime);
+        let request = Request::post("http://localhost/diff")
+            .body(body.into())
+            .unwrap();
+
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+        let status = response.status();
+        let body = response.into_body().concat2().wait().unwrap();
+        (status, body.to_vec())
+    }
+
+    fn plan(runtime: TestRuntime<Error, TestSettings>, proposed: Vec<ModuleSpec>) -> DryRunResult {
+        let body = serde_json::to_string(&ModuleSpecList::new(proposed)).unwrap();
+        let (status, body) = diff(runtime, body);
+        assert_eq!(StatusCode::OK, status);
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[test]
+    fn unchanged_module_is_noop() {
+        let m1 = spec("m1", "microsoft/test-image:1.0");
+        let result = plan(runtime(&m1), vec![m1]);
+
+        assert_eq!(1, result.modules().len());
+        assert_eq!("m1", result.modules()[0].name());
+        assert_eq!("no-op", result.modules()[0].action());
+        assert_eq!(None, result.modules()[0].reasons());
+    }
+
+    #[test]
+    fn changed_module_is_restarted_with_reasons() {
+        let m1 = spec("m1", "microsoft/test-image:1.0");
+        let changed = ModuleSpec::new(
+            "m1".to_string(),
+            "docker".to_string(),
+            Config::new(json!({ "image": "microsoft/test-image:2.0" }))
+                .with_env(vec![EnvVar::new("LEVEL".to_string(), "debug".to_string())]),
+        );
+        let result = plan(runtime(&m1), vec![changed]);
+
+        assert_eq!(1, result.modules().len());
+        assert_eq!("restart", result.modules()[0].action());
+        assert_eq!(
+            Some(&["image changed".to_string(), "env changed".to_string()][..]),
+            result.modules()[0].reasons()
+        );
+    }
+
+    #[test]
+    fn new_module_is_created_and_missing_one_removed() {
+        let m1 = spec("m1", "microsoft/test-image:1.0");
+        let result = plan(runtime(&m1), vec![spec("m2", "microsoft/test-image:1.0")]);
+
+        let actions: Vec<_> = result
+            .modules()
+            .iter()
+            .map(|action| (action.name().as_str(), action.action().as_str()))
+            .collect();
+        assert_eq!(vec![("m1", "remove"), ("m2", "create")], actions);
+        assert!(result
+            .modules()
+            .iter()
+            .all(|action| action.reasons().is_none()));
+    }
+
+    #[test]
+    fn invalid_modules_are_rejected() {
+        let m1 = spec("m1", "microsoft/test-image:1.0");
+        let body = serde_json::to_string(&ModuleSpecList::new(vec![
+            spec("m2", "microsoft/test-image:1.0"),
+            spec("m2", "microsoft/test-image:2.0"),
+        ]))
+        .unwrap();
+
+        let (status, body) = diff(runtime(&m1), body);
+        assert_eq!(StatusCode::BAD_REQUEST, status);
+        let error: ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert!(error.message().starts_with("Request body is malformed"));
+
+        let (status, _) = diff(runtime(&m1), "invalid".to_string());
+        assert_eq!(StatusCode::BAD_REQUEST, status);
+    }
+}
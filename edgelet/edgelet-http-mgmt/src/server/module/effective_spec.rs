@@ -0,0 +1,159 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! The spec that a module was created from once the daemon applied its
+//! defaults, merged in the global environment variables and injected its
+//! labels, which is what runs rather than what the deployment asked for.
+//! Secret references are left unresolved and registry passwords redacted.
+
+use failure::{Fail, ResultExt};
+use futures::{Future, IntoFuture};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use serde::Serialize;
+use serde_json;
+
+use edgelet_core::{Module, ModuleRuntime, RuntimeOperation};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+pub struct GetEffectiveSpec<M> {
+    runtime: M,
+}
+
+impl<M> GetEffectiveSpec<M> {
+    pub fn new(runtime: M) -> Self {
+        GetEffectiveSpec { runtime }
+    }
+}
+
+impl<M> Handler<Parameters> for GetEffectiveSpec<M>
+where
+    M: 'static + ModuleRuntime + Send,
+    <M::Module as Module>::Config: Serialize,
+{
+    fn handle(
+        &self,
+        _req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let response = params
+            .name("name")
+            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+            .map(|name| {
+                let name = name.to_string();
+
+                self.runtime
+                    .effective_spec(&name)
+                    .then(|result| match result {
+                        Ok(spec) => Ok((name, spec)),
+                        Err(err) => Err(Error::from(err.context(ErrorKind::RuntimeOperation(
+                            RuntimeOperation::GetEffectiveSpec(name),
+                        )))),
+                    })
+            })
+            .into_future()
+            .flatten()
+            .and_then(|(name, spec)| {
+                let context = || {
+                    ErrorKind::RuntimeOperation(RuntimeOperation::GetEffectiveSpec(name.clone()))
+                };
+                let body = serde_json::to_string(&spec).with_context(|_| context())?;
+                let response = Response::builder()
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(CONTENT_LENGTH, body.len().to_string().as_str())
+                    .body(body.into())
+                    .with_context(|_| context())?;
+                Ok(response)
+            })
+            .or_else(|e| Ok(e.into_response()));
+
+        Box::new(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::Stream;
+    use management::models::ErrorResponse;
+
+    use edgelet_core::{MakeModuleRuntime, ModuleRuntimeState};
+    use edgelet_test_utils::crypto::TestHsm;
+    use edgelet_test_utils::module::*;
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    fn runtime(
+        module: Result<TestModule<Error, TestConfig>, Error>,
+    ) -> TestRuntime<Error, TestSettings> {
+        TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(module)
+    }
+
+    fn request() -> (Request<Body>, Parameters) {
+        let request =
+            Request::get("http://localhost/modules/mod1/effective-spec?api-version=2019-11-05")
+                .body(Body::default())
+                .unwrap();
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "mod1".to_string())]);
+        (request, parameters)
+    }
+
+    #[test]
+    fn success() {
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error, _> = TestModule::new(
+            "mod1".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default()),
+        );
+        let handler = GetEffectiveSpec::new(runtime(Ok(module)));
+        let (request, parameters) = request();
+
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        assert_eq!(StatusCode::OK, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let spec: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!("mod1", spec["name"]);
+        assert_eq!("test", spec["type"]);
+        assert_eq!("microsoft/test-image", spec["config"]["image"]);
+    }
+
+    #[test]
+    fn runtime_error() {
+        let handler = GetEffectiveSpec::new(runtime(Err(Error::General)));
+        let (request, parameters) = request();
+
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            "Could not get the effective spec of module mod1\n\tcaused by: General error",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn bad_params_fails() {
+        let handler = GetEffectiveSpec::new(runtime(Err(Error::General)));
+        let (request, _) = request();
+
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    }
+}
@@ -0,0 +1,218 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::HashMap;
+
+use failure::{Fail, ResultExt};
+use futures::{stream, Future, IntoFuture, Stream};
+use hyper::header::CONTENT_TYPE;
+use hyper::{Body, Chunk, Request, Response, StatusCode};
+use serde_json::{self, json};
+
+use edgelet_core::{ExecOutput, ModuleRuntime, RuntimeOperation};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+use management::models::ExecRequest;
+
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// Runs a command in a module and streams what it writes to stdout and
+/// stderr as it writes it, one JSON object per line, followed by its exit
+/// code:
+///
+/// ```json
+/// {"output":"total 0\n"}
+/// {"exitCode":0}
+/// ```
+///
+/// A response that ends without the exit code was cut short by an error.
+pub struct ExecModule<M> {
+    runtime: M,
+}
+
+impl<M> ExecModule<M> {
+    pub fn new(runtime: M) -> Self {
+        ExecModule { runtime }
+    }
+}
+
+impl<M> Handler<Parameters> for ExecModule<M>
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let runtime = self.runtime.clone();
+
+        let response = params
+            .name("name")
+            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+            .map(ToString::to_string)
+            .into_future()
+            .and_then(|name| {
+                req.into_body().concat2().then(|b| -> Result<_, Error> {
+                    let b = b.context(ErrorKind::MalformedRequestBody)?;
+                    let request = serde_json::from_slice::<ExecRequest>(&b)
+                        .context(ErrorKind::MalformedRequestBody)?;
+                    if request.cmd().is_empty() {
+                        return Err(Error::from(ErrorKind::MalformedRequestBody));
+                    }
+                    Ok((name, request))
+                })
+            })
+            .and_then(move |(name, request)| {
+                let env: HashMap<_, _> = request.env().map_or_else(HashMap::new, |vars| {
+                    vars.iter()
+                        .map(|var| (var.key().clone(), var.value().clone()))
+                        .collect()
+                });
+                let context = {
+                    let name = name.clone();
+                    move || ErrorKind::RuntimeOperation(RuntimeOperation::ExecModule(name.clone()))
+                };
+
+                // Waiting for the first output means that the request fails
+                // with the right status if the command doesn't start, such as
+                // when the module isn't running.
+                runtime
+                    .exec(&name, request.cmd().to_vec(), env)
+                    .into_future()
+                    .then(move |result| match result {
+                        Ok((first, rest)) => {
+                            let output_context = context.clone();
+                            let output = stream::iter_ok(first)
+                                .chain(rest)
+                                .map(|output| exec_line(&output))
+                                .map_err(move |err| {
+                                    Error::from(err.context(output_context())).compat()
+                                });
+                            Response::builder()
+                                .status(StatusCode::OK)
+                                .header(CONTENT_TYPE, "application/x-ndjson")
+                                .body(Body::wrap_stream(output))
+                                .context(context())
+                                .map_err(Error::from)
+                        }
+                        Err((err, _)) => Err(Error::from(err.context(context()))),
+                    })
+            })
+            .or_else(|e| Ok(e.into_response()));
+
+        Box::new(response)
+    }
+}
+
+fn exec_line(output: &ExecOutput) -> Chunk {
+    let line = match output {
+        // Output that isn't valid UTF-8 is still worth seeing when diagnosing
+        // a module.
+        ExecOutput::Output(bytes) => json!({ "output": String::from_utf8_lossy(bytes) }),
+        ExecOutput::Exited(code) => json!({ "exitCode": code }),
+    };
+    Chunk::from(format!("{}\n", line))
+}
+
+#[cfg(test)]
+mod tests {
+    use management::models::ErrorResponse;
+    use serde_json::Value;
+
+    use edgelet_core::{MakeModuleRuntime, ModuleRuntimeState, ModuleStatus};
+    use edgelet_test_utils::crypto::TestHsm;
+    use edgelet_test_utils::module::*;
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    fn runtime(
+        module: Result<TestModule<Error, TestConfig>, Error>,
+    ) -> TestRuntime<Error, TestSettings> {
+        TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(module)
+    }
+
+    fn module() -> TestModule<Error, TestConfig> {
+        let state = ModuleRuntimeState::default().with_status(ModuleStatus::Running);
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        TestModule::new("mod1".to_string(), config, Ok(state))
+    }
+
+    fn post(
+        handler: &ExecModule<TestRuntime<Error, TestSettings>>,
+        body: &Value,
+    ) -> Response<Body> {
+        let request = Request::post("http://localhost/modules/mod1/exec?api-version=2019-11-05")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "mod1".to_string())]);
+        handler.handle(request, parameters).wait().unwrap()
+    }
+
+    #[test]
+    fn streams_output_and_exit_code() {
+        let handler = ExecModule::new(runtime(Ok(module())));
+
+        let response = post(
+            &handler,
+            &json!({ "cmd": ["ls", "/"], "env": [{ "key": "A", "value": "1" }] }),
+        );
+
+        assert_eq!(StatusCode::OK, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let lines: Vec<Value> = std::str::from_utf8(&body)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            vec![json!({ "output": "ls /\n" }), json!({ "exitCode": 0 })],
+            lines
+        );
+    }
+
+    #[test]
+    fn exit_code_line() {
+        assert_eq!(
+            b"{\"exitCode\":2}\n",
+            exec_line(&ExecOutput::Exited(2)).as_ref()
+        );
+        assert_eq!(
+            "{\"output\":\"a\u{fffd}b\"}\n".as_bytes(),
+            exec_line(&ExecOutput::Output(vec![b'a', 0xff, b'b'].into())).as_ref()
+        );
+    }
+
+    #[test]
+    fn empty_command_fails() {
+        let handler = ExecModule::new(runtime(Ok(module())));
+
+        let response = post(&handler, &json!({ "cmd": [] }));
+
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    }
+
+    #[test]
+    fn runtime_error() {
+        let handler = ExecModule::new(runtime(Err(Error::General)));
+
+        let response = post(&handler, &json!({ "cmd": ["ls"] }));
+
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            "Could not run command in module mod1\n\tcaused by: General error",
+            error.message()
+        );
+    }
+}
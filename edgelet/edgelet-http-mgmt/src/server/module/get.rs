@@ -1,20 +1,254 @@
 // Copyright (c) Microsoft. All rights reserved.
 
-use futures::{future, Future};
-use hyper::{Body, Request, Response};
+use failure::{Fail, ResultExt};
+use futures::{Future, IntoFuture};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use serde::Serialize;
+use serde_json;
 
+use edgelet_core::{Module, ModuleRuntime, RuntimeOperation};
 use edgelet_http::route::{Handler, Parameters};
 use edgelet_http::Error as HttpError;
 
-pub struct GetModule;
+use super::core_to_details;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
 
-impl Handler<Parameters> for GetModule {
+pub struct GetModule<M> {
+    runtime: M,
+}
+
+impl<M> GetModule<M> {
+    pub fn new(runtime: M) -> Self {
+        GetModule { runtime }
+    }
+}
+
+impl<M> Handler<Parameters> for GetModule<M>
+where
+    M: 'static + ModuleRuntime + Send,
+    <M::Module as Module>::Config: Serialize,
+{
     fn handle(
         &self,
         _req: Request<Body>,
-        _params: Parameters,
+        params: Parameters,
     ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
-        let response = Response::new(Body::default());
-        Box::new(future::ok(response))
+        let response = params
+            .name("name")
+            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+            .map(|name| {
+                let name = name.to_string();
+
+                self.runtime.get(&name).then(|result| match result {
+                    Ok((module, state)) => Ok((name, module, state)),
+                    Err(err) => Err(Error::from(err.context(ErrorKind::RuntimeOperation(
+                        RuntimeOperation::GetModule(name),
+                    )))),
+                })
+            })
+            .into_future()
+            .flatten()
+            .and_then(|(name, module, state)| {
+                let context =
+                    || ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(name.clone()));
+                let details = core_to_details(&module, &state, context)?;
+                let body = serde_json::to_string(&details).with_context(|_| context())?;
+                let response = Response::builder()
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(CONTENT_LENGTH, body.len().to_string().as_str())
+                    .body(body.into())
+                    .with_context(|_| context())?;
+                Ok(response)
+            })
+            .or_else(|e| Ok(e.into_response()));
+
+        Box::new(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::Stream;
+    use management::models::{ErrorResponse, ModuleDetails};
+
+    use chrono::prelude::*;
+    use edgelet_core::{
+        ExitReason, MakeModuleRuntime, ModuleError, ModuleErrorCode, ModuleRuntimeState,
+        ModuleStatus, PortBinding,
+    };
+    use edgelet_test_utils::crypto::TestHsm;
+    use edgelet_test_utils::module::*;
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    fn runtime(
+        module: Result<TestModule<Error, TestConfig>, Error>,
+    ) -> TestRuntime<Error, TestSettings> {
+        TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(module)
+    }
+
+    fn request() -> (Request<Body>, Parameters) {
+        let request = Request::get("http://localhost/modules/mod1?api-version=2018-06-28")
+            .body(Body::default())
+            .unwrap();
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "mod1".to_string())]);
+        (request, parameters)
+    }
+
+    #[test]
+    fn success() {
+        // arrange
+        let state = ModuleRuntimeState::default().with_status(ModuleStatus::Running);
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error, _> = TestModule::new("mod1".to_string(), config, Ok(state));
+        let handler = GetModule::new(runtime(Ok(module)));
+        let (request, parameters) = request();
+
+        // act
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let details: ModuleDetails = serde_json::from_slice(&body).unwrap();
+        assert_eq!("mod1", details.name());
+        assert_eq!("test", details.type_());
+        // The test runtime doesn't know when the module started or how often
+        // it restarted
+        assert_eq!(None, details.status().start_time());
+        assert_eq!(None, details.status().restart_count());
+    }
+
+    #[test]
+    fn oom_killed_module_has_exit_reason() {
+        // arrange
+        let state = ModuleRuntimeState::default()
+            .with_status(ModuleStatus::Failed)
+            .with_exit_code(Some(137))
+            .with_finished_at(Some(Utc::now()))
+            .with_exit_reason(Some(ExitReason::OomKilled));
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error, _> = TestModule::new("mod1".to_string(), config, Ok(state));
+        let handler = GetModule::new(runtime(Ok(module)));
+        let (request, parameters) = request();
+
+        // act
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let details: ModuleDetails = serde_json::from_slice(&body).unwrap();
+        let exit_status = details.status().exit_status().unwrap();
+        assert_eq!("137", exit_status.status_code());
+        assert_eq!(Some("OOMKilled"), exit_status.reason());
+    }
+
+    #[test]
+    fn failed_module_has_last_error() {
+        // arrange
+        let error = ModuleError::new(
+            ModuleErrorCode::NonZeroExit,
+            "Module exited with code 139".to_string(),
+            Utc::now(),
+        )
+        .with_exit_code(Some(139));
+        let state = ModuleRuntimeState::default()
+            .with_status(ModuleStatus::Failed)
+            .with_last_error(Some(error));
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error, _> = TestModule::new("mod1".to_string(), config, Ok(state));
+        let handler = GetModule::new(runtime(Ok(module)));
+        let (request, parameters) = request();
+
+        // act
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let details: ModuleDetails = serde_json::from_slice(&body).unwrap();
+        let last_error = details.status().last_error().unwrap();
+        assert_eq!("nonZeroExit", last_error.code());
+        assert_eq!("Module exited with code 139", last_error.message());
+        assert_eq!(Some(139), last_error.exit_code());
+    }
+
+    #[test]
+    fn module_has_port_bindings() {
+        // arrange
+        let state = ModuleRuntimeState::default()
+            .with_status(ModuleStatus::Running)
+            .with_ports(vec![
+                PortBinding::new("443/tcp".to_string())
+                    .with_host_ip(Some("0.0.0.0".to_string()))
+                    .with_host_port(Some("443".to_string())),
+                PortBinding::new("5671/tcp".to_string()),
+            ]);
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error, _> = TestModule::new("mod1".to_string(), config, Ok(state));
+        let handler = GetModule::new(runtime(Ok(module)));
+        let (request, parameters) = request();
+
+        // act
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let details: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            serde_json::json!([
+                { "containerPort": "443/tcp", "hostIp": "0.0.0.0", "hostPort": "443" },
+                { "containerPort": "5671/tcp" },
+            ]),
+            details["status"]["ports"]
+        );
+    }
+
+    #[test]
+    fn runtime_error() {
+        // arrange
+        let handler = GetModule::new(runtime(Err(Error::General)));
+        let (request, parameters) = request();
+
+        // act
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            "Could not get module mod1\n\tcaused by: General error",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn bad_params_fails() {
+        // arrange
+        let handler = GetModule::new(runtime(Err(Error::General)));
+        let request = Request::get("http://localhost/modules/mod1?api-version=2018-06-28")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
     }
 }
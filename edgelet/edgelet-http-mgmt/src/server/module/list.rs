@@ -1,28 +1,42 @@
 // Copyright (c) Microsoft. All rights reserved.
 
 use failure::ResultExt;
-use futures::{Future, Stream};
+use futures::{future, Future, Stream};
 use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
 use hyper::{Body, Request, Response, StatusCode};
 use log::debug;
 use serde::Serialize;
 use serde_json;
+use url::form_urlencoded;
 
-use edgelet_core::{Module, ModuleRuntime, ModuleRuntimeState, RuntimeOperation};
+use edgelet_core::watchdog::WatchdogPause;
+use edgelet_core::{Module, ModuleRuntime, ModuleRuntimeState, ModuleStatus, RuntimeOperation};
 use edgelet_http::route::{Handler, Parameters};
 use edgelet_http::Error as HttpError;
 use management::models::*;
 
+use super::{core_to_details, LabelSelector};
 use crate::error::{Error, ErrorKind};
 use crate::IntoResponse;
 
 pub struct ListModules<M> {
     runtime: M,
+    pause: Option<WatchdogPause>,
 }
 
 impl<M> ListModules<M> {
     pub fn new(runtime: M) -> Self {
-        ListModules { runtime }
+        ListModules {
+            runtime,
+            pause: None,
+        }
+    }
+
+    /// Shows the modules that `pause` paused after they kept failing as
+    /// paused.
+    pub fn with_pause(mut self, pause: WatchdogPause) -> Self {
+        self.pause = Some(pause);
+        self
     }
 }
 
@@ -33,22 +47,44 @@ where
 {
     fn handle(
         &self,
-        _req: Request<Body>,
+        req: Request<Body>,
         _params: Parameters,
     ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
         debug!("List modules");
 
+        let options = match req
+            .uri()
+            .query()
+            .map_or_else(|| Ok(ListOptions::default()), parse_options)
+        {
+            Ok(options) => options,
+            Err(err) => return Box::new(future::ok(err.into_response())),
+        };
+
+        let pause = self.pause.clone();
         let response = self
             .runtime
             .list_with_details()
             .collect()
-            .then(|result| -> Result<_, Error> {
-                let details: Result<_, Error> = result
+            .then(move |result| -> Result<_, Error> {
+                let modules: Vec<_> = result
                     .context(ErrorKind::RuntimeOperation(RuntimeOperation::ListModules))?
                     .into_iter()
-                    .map(|(module, state)| core_to_details(&module, &state))
+                    .map(|(module, state)| {
+                        let paused = pause
+                            .as_ref()
+                            .map_or(false, |pause| pause.is_module_paused(module.name()));
+                        (module, state.with_paused(paused))
+                    })
+                    .filter(|(_, state)| {
+                        options
+                            .status
+                            .as_ref()
+                            .map_or(true, |status| state.status() == status)
+                            && options.labels.matches(state.labels())
+                    })
                     .collect();
-                let body = ModuleList::new(details?);
+                let body = page(modules, &options)?;
                 let b = serde_json::to_string(&body)
                     .context(ErrorKind::RuntimeOperation(RuntimeOperation::ListModules))?;
                 let response = Response::builder()
@@ -65,41 +101,106 @@ where
     }
 }
 
-fn core_to_details<M>(module: &M, state: &ModuleRuntimeState) -> Result<ModuleDetails, Error>
+#[derive(Debug, Default, PartialEq)]
+struct ListOptions {
+    status: Option<ModuleStatus>,
+    limit: Option<usize>,
+    labels: LabelSelector,
+    // The name of the last module of the previous page
+    after: Option<String>,
+}
+
+fn parse_options(query: &str) -> Result<ListOptions, Error> {
+    let parse: Vec<_> = form_urlencoded::parse(query.as_bytes()).collect();
+    let find = |name: &str| {
+        parse
+            .iter()
+            .find(|&(ref key, _)| key == name)
+            .map(|(_, val)| val)
+    };
+
+    let status = find("status")
+        .map(|val| val.parse::<ModuleStatus>())
+        .transpose()
+        .context(ErrorKind::MalformedRequestParameter("status"))?;
+    let limit = find("limit")
+        .map(|val| val.parse::<usize>())
+        .transpose()
+        .context(ErrorKind::MalformedRequestParameter("limit"))?;
+    if limit == Some(0) {
+        return Err(Error::from(ErrorKind::MalformedRequestParameter("limit")));
+    }
+    let after = find("continuation_token")
+        .map(|val| decode_continuation_token(val))
+        .transpose()?;
+
+    let labels = LabelSelector::parse(query)?;
+
+    Ok(ListOptions {
+        status,
+        limit,
+        labels,
+        after,
+    })
+}
+
+fn encode_continuation_token(name: &str) -> String {
+    base64::encode_config(name, base64::URL_SAFE_NO_PAD)
+}
+
+fn decode_continuation_token(token: &str) -> Result<String, Error> {
+    let name = base64::decode_config(token, base64::URL_SAFE_NO_PAD)
+        .context(ErrorKind::MalformedRequestParameter("continuation_token"))?;
+    let name = String::from_utf8(name)
+        .context(ErrorKind::MalformedRequestParameter("continuation_token"))?;
+    Ok(name)
+}
+
+// Modules are paged in the order of their names and the continuation token is
+// the name of the last module of the page. Names are unique, so paging through
+// a snapshot of the runtime's modules neither skips nor repeats any of them.
+fn page<M>(
+    mut modules: Vec<(M, ModuleRuntimeState)>,
+    options: &ListOptions,
+) -> Result<ModuleList, Error>
 where
     M: 'static + Module + Send,
     M::Config: Serialize,
 {
-    let settings = serde_json::to_value(module.config())
-        .context(ErrorKind::RuntimeOperation(RuntimeOperation::ListModules))?;
-    let config = Config::new(settings).with_env(vec![]);
-    let mut runtime_status = RuntimeStatus::new(state.status().to_string());
-    if let Some(description) = state.status_description() {
-        runtime_status.set_description(description.to_string());
-    }
-    let mut status = Status::new(runtime_status);
-    if let Some(started_at) = state.started_at() {
-        status.set_start_time(started_at.to_rfc3339());
-    }
-    if let Some(code) = state.exit_code() {
-        if let Some(finished_at) = state.finished_at() {
-            status.set_exit_status(ExitStatus::new(finished_at.to_rfc3339(), code.to_string()));
+    modules.sort_by(|(a, _), (b, _)| a.name().cmp(b.name()));
+    if let Some(after) = &options.after {
+        modules.retain(|(module, _)| module.name() > after.as_str());
+    }
+
+    let mut continuation_token = None;
+    if let Some(limit) = options.limit {
+        if modules.len() > limit {
+            modules.truncate(limit);
+            continuation_token = modules
+                .last()
+                .map(|(module, _)| encode_continuation_token(module.name()));
         }
     }
 
-    Ok(ModuleDetails::new(
-        "id".to_string(),
-        module.name().to_string(),
-        module.type_().to_string(),
-        config,
-        status,
-    ))
+    let details = modules
+        .iter()
+        .map(|(module, state)| {
+            core_to_details(module, state, || {
+                ErrorKind::RuntimeOperation(RuntimeOperation::ListModules)
+            })
+        })
+        .collect::<Result<_, _>>()?;
+    let list = ModuleList::new(details);
+    Ok(match continuation_token {
+        Some(token) => list.with_continuation_token(token),
+        None => list,
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use chrono::prelude::*;
-    use edgelet_core::{MakeModuleRuntime, ModuleRuntimeState, ModuleStatus};
+    use edgelet_core::{HealthState, MakeModuleRuntime, ModuleRuntimeState, ModuleStatus};
     use edgelet_http::route::Parameters;
     use edgelet_test_utils::crypto::TestHsm;
     use edgelet_test_utils::module::*;
@@ -118,7 +219,9 @@ mod tests {
             .with_status_description(Some("description".to_string()))
             .with_started_at(Some(Utc.ymd(2018, 4, 13).and_hms_milli(14, 20, 0, 1)))
             .with_finished_at(Some(Utc.ymd(2018, 4, 13).and_hms_milli(15, 20, 0, 1)))
-            .with_image_id(Some("image-id".to_string()));
+            .with_image_id(Some("image-id".to_string()))
+            .with_health(Some(HealthState::Unhealthy))
+            .with_restart_count(Some(2));
         let config = TestConfig::new("microsoft/test-image".to_string());
         let module: TestModule<Error, _> =
             TestModule::new("test-module".to_string(), config, Ok(state));
@@ -169,12 +272,46 @@ mod tests {
                     "description",
                     module.status().runtime_status().description().unwrap()
                 );
+                assert_eq!(Some("unhealthy"), module.status().runtime_status().health());
+                assert_eq!(Some(2), module.status().restart_count());
                 Ok(())
             })
             .wait()
             .unwrap();
     }
 
+    #[test]
+    fn paused_module_is_shown_as_paused() {
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let runtime = TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(Ok(TestModule::<Error, _>::new(
+            "edgeAgent".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default()),
+        )));
+        let list = |pause: &WatchdogPause| -> ModuleList {
+            let handler = ListModules::new(runtime.clone()).with_pause(pause.clone());
+            let request = Request::get("http://localhost/modules")
+                .body(Body::default())
+                .unwrap();
+            let response = handler.handle(request, Parameters::new()).wait().unwrap();
+            let body = response.into_body().concat2().wait().unwrap();
+            serde_json::from_slice(&body).unwrap()
+        };
+
+        let pause = WatchdogPause::new();
+        assert_eq!(None, list(&pause).modules()[0].status().paused());
+
+        pause.pause_module("edgeAgent");
+        assert_eq!(Some(true), list(&pause).modules()[0].status().paused());
+    }
+
     #[test]
     fn list_failed() {
         // arrange
@@ -246,4 +383,189 @@ mod tests {
             .wait()
             .unwrap();
     }
+
+    fn modules(names: &[&str]) -> Vec<(TestModule<Error, TestConfig>, ModuleRuntimeState)> {
+        names
+            .iter()
+            .map(|name| {
+                let config = TestConfig::new("microsoft/test-image".to_string());
+                let state = ModuleRuntimeState::default().with_status(ModuleStatus::Running);
+                let module = TestModule::new((*name).to_string(), config, Ok(state.clone()));
+                (module, state)
+            })
+            .collect()
+    }
+
+    fn names(list: &ModuleList) -> Vec<&str> {
+        list.modules()
+            .iter()
+            .map(|module| module.name().as_str())
+            .collect()
+    }
+
+    #[test]
+    fn pages_through_modules_in_name_order() {
+        let options = ListOptions {
+            limit: Some(2),
+            ..ListOptions::default()
+        };
+        let first = page(modules(&["m3", "m1", "m5", "m2", "m4"]), &options).unwrap();
+        assert_eq!(vec!["m1", "m2"], names(&first));
+
+        let options = parse_options(&format!(
+            "limit=2&continuation_token={}",
+            first.continuation_token().unwrap()
+        ))
+        .unwrap();
+        // The same snapshot in a different order gives the same pages
+        let second = page(modules(&["m5", "m4", "m3", "m2", "m1"]), &options).unwrap();
+        assert_eq!(vec!["m3", "m4"], names(&second));
+
+        let options = parse_options(&format!(
+            "limit=2&continuation_token={}",
+            second.continuation_token().unwrap()
+        ))
+        .unwrap();
+        let last = page(modules(&["m1", "m2", "m3", "m4", "m5"]), &options).unwrap();
+        assert_eq!(vec!["m5"], names(&last));
+        assert_eq!(None, last.continuation_token());
+    }
+
+    #[test]
+    fn full_last_page_has_no_continuation_token() {
+        let options = ListOptions {
+            limit: Some(2),
+            ..ListOptions::default()
+        };
+        let list = page(modules(&["m1", "m2"]), &options).unwrap();
+        assert_eq!(vec!["m1", "m2"], names(&list));
+        assert_eq!(None, list.continuation_token());
+
+        let options = ListOptions {
+            after: Some("m2".to_string()),
+            ..ListOptions::default()
+        };
+        let list = page(modules(&["m1", "m2"]), &options).unwrap();
+        assert!(list.modules().is_empty());
+        assert_eq!(None, list.continuation_token());
+    }
+
+    #[test]
+    fn parse_options_rejects_malformed_parameters() {
+        for (query, parameter) in &[
+            ("limit=0", "limit"),
+            ("limit=ten", "limit"),
+            ("status=sleeping", "status"),
+            ("label=group", "label"),
+            ("continuation_token=%%%", "continuation_token"),
+        ] {
+            match parse_options(query).unwrap_err().kind() {
+                ErrorKind::MalformedRequestParameter(p) => assert_eq!(parameter, p),
+                kind => panic!("Expected `MalformedRequestParameter` but got {:?}", kind),
+            }
+        }
+    }
+
+    #[test]
+    fn status_filter() {
+        // arrange
+        let state = ModuleRuntimeState::default().with_status(ModuleStatus::Running);
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error, _> =
+            TestModule::new("test-module".to_string(), config, Ok(state));
+        let runtime = TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(Ok(module));
+        let handler = ListModules::new(runtime);
+
+        for (status, expected) in &[("running", 1), ("stopped", 0)] {
+            let request = Request::get(format!("http://localhost/modules?status={}", status))
+                .body(Body::default())
+                .unwrap();
+
+            // act
+            let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+            // assert
+            assert_eq!(StatusCode::OK, response.status());
+            let body = response.into_body().concat2().wait().unwrap();
+            let list: ModuleList = serde_json::from_slice(&body).unwrap();
+            assert_eq!(*expected, list.modules().len());
+        }
+    }
+
+    #[test]
+    fn label_filter() {
+        // arrange
+        let labels = [("group", "sensors"), ("tier", "edge")]
+            .iter()
+            .map(|(key, value)| ((*key).to_string(), (*value).to_string()))
+            .collect();
+        let state = ModuleRuntimeState::default()
+            .with_status(ModuleStatus::Running)
+            .with_labels(labels);
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error, _> =
+            TestModule::new("test-module".to_string(), config, Ok(state));
+        let runtime = TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(Ok(module));
+        let handler = ListModules::new(runtime);
+
+        for (query, expected) in &[
+            ("label=group=sensors", 1),
+            ("label=group=sensors&label=tier=edge", 1),
+            ("label=group=sensors&label=tier=cloud", 0),
+            ("label=owner=me", 0),
+        ] {
+            let request = Request::get(format!("http://localhost/modules?{}", query))
+                .body(Body::default())
+                .unwrap();
+
+            // act
+            let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+            // assert
+            assert_eq!(StatusCode::OK, response.status());
+            let body = response.into_body().concat2().wait().unwrap();
+            let list: ModuleList = serde_json::from_slice(&body).unwrap();
+            assert_eq!(*expected, list.modules().len(), "{}", query);
+            if let Some(module) = list.modules().first() {
+                assert_eq!("sensors", module.labels().unwrap()["group"]);
+            }
+        }
+    }
+
+    #[test]
+    fn malformed_limit_fails() {
+        // arrange
+        let runtime = TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(Err(Error::General));
+        let handler = ListModules::new(runtime);
+        let request = Request::get("http://localhost/modules?limit=0")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    }
 }
@@ -1,11 +1,20 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+use std::io::{self, Write};
+use std::mem;
+
 use failure::ResultExt;
-use futures::{future, Future, IntoFuture};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use futures::{future, Async, Future, IntoFuture, Poll, Stream};
+use hyper::header::{ACCEPT_ENCODING, CONTENT_ENCODING, VARY};
 use hyper::{Body, Request, Response, StatusCode};
 use url::form_urlencoded;
 
-use edgelet_core::{parse_since, LogOptions, LogTail, ModuleRuntime, RuntimeOperation};
+use edgelet_core::{
+    decode_logs_with_max_line_length, parse_since, LogChunk, LogOptions, LogTail, ModuleRuntime,
+    RuntimeOperation,
+};
 use edgelet_http::route::{Handler, Parameters};
 use edgelet_http::Error as HttpError;
 
@@ -14,11 +23,17 @@ use crate::IntoResponse;
 
 pub struct ModuleLogs<M> {
     runtime: M,
+    max_line_length: usize,
 }
 
 impl<M> ModuleLogs<M> {
-    pub fn new(runtime: M) -> Self {
-        ModuleLogs { runtime }
+    /// Lines of gzipped logs, which are de-framed, are truncated to
+    /// `max_line_length` bytes.
+    pub fn new(runtime: M, max_line_length: usize) -> Self {
+        ModuleLogs {
+            runtime,
+            max_line_length,
+        }
     }
 }
 
@@ -33,6 +48,8 @@ where
         params: Parameters,
     ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
         let runtime = self.runtime.clone();
+        let max_line_length = self.max_line_length;
+        let gzip = accepts_gzip(&req);
 
         let response = params
             .name("name")
@@ -46,18 +63,32 @@ where
                 Ok((name, options))
             })
             .map(move |(name, options)| {
-                runtime.logs(&name, &options).then(|s| -> Result<_, Error> {
-                    let s = s.with_context(|_| {
-                        ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleLogs(name.clone()))
-                    })?;
-                    let response = Response::builder()
-                        .status(StatusCode::OK)
-                        .body(s.into())
-                        .context(ErrorKind::RuntimeOperation(
+                runtime
+                    .logs(&name, &options)
+                    .then(move |s| -> Result<_, Error> {
+                        let s = s.with_context(|_| {
+                            ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleLogs(
+                                name.clone(),
+                            ))
+                        })?;
+                        let mut response = Response::builder();
+                        response.status(StatusCode::OK);
+                        let body = if gzip {
+                            response
+                                .header(CONTENT_ENCODING, "gzip")
+                                .header(VARY, "accept-encoding");
+                            Body::wrap_stream(GzipLogs::new(decode_logs_with_max_line_length(
+                                s,
+                                max_line_length,
+                            )))
+                        } else {
+                            s.into()
+                        };
+                        let response = response.body(body).context(ErrorKind::RuntimeOperation(
                             RuntimeOperation::GetModuleLogs(name),
                         ))?;
-                    Ok(response)
-                })
+                        Ok(response)
+                    })
             })
             .into_future()
             .flatten()
@@ -67,6 +98,84 @@ where
     }
 }
 
+// Whether the request's `Accept-Encoding` accepts gzip.
+fn accepts_gzip(req: &Request<Body>) -> bool {
+    req.headers()
+        .get_all(ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|coding| {
+            let mut params = coding.split(';').map(str::trim);
+            params
+                .next()
+                .map_or(false, |name| name.eq_ignore_ascii_case("gzip"))
+                && params.all(|param| {
+                    let param = param.replace(' ', "");
+                    !param.starts_with("q=")
+                        || param[2..].parse::<f32>().ok().map_or(false, |q| q > 0.0)
+                })
+        })
+}
+
+/// Gzips the de-framed logs of a module as they are read. The framing only
+/// says which of stdout and stderr a chunk is from, which a client that reads
+/// a compressed log has no use for, so only what the module wrote is sent.
+/// Each chunk is flushed, so that a client that follows the logs gets lines
+/// as the module writes them rather than when the compressor's buffer fills.
+struct GzipLogs<S> {
+    inner: S,
+    encoder: Option<GzEncoder<Vec<u8>>>,
+}
+
+impl<S> GzipLogs<S> {
+    fn new(inner: S) -> Self {
+        GzipLogs {
+            inner,
+            encoder: Some(GzEncoder::new(Vec::new(), Compression::default())),
+        }
+    }
+}
+
+impl<S> Stream for GzipLogs<S>
+where
+    S: Stream<Item = LogChunk, Error = io::Error>,
+{
+    type Item = Vec<u8>;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            let encoder = match &mut self.encoder {
+                Some(encoder) => encoder,
+                None => return Ok(Async::Ready(None)),
+            };
+
+            match self.inner.poll()? {
+                Async::Ready(Some(chunk)) => {
+                    let bytes = match chunk {
+                        LogChunk::Stdout(bytes)
+                        | LogChunk::Stderr(bytes)
+                        | LogChunk::Unknown(bytes) => bytes,
+                        LogChunk::Stdin(_) => continue,
+                    };
+                    encoder.write_all(&bytes)?;
+                    encoder.flush()?;
+                    return Ok(Async::Ready(Some(mem::replace(
+                        encoder.get_mut(),
+                        Vec::new(),
+                    ))));
+                }
+                Async::Ready(None) => {
+                    let encoder = self.encoder.take().expect("encoder was just borrowed");
+                    return Ok(Async::Ready(Some(encoder.finish()?)));
+                }
+                Async::NotReady => return Ok(Async::NotReady),
+            }
+        }
+    }
+}
+
 fn parse_options(query: &str) -> Result<LogOptions, Error> {
     let parse: Vec<_> = form_urlencoded::parse(query.as_bytes()).collect();
     let tail = parse
@@ -84,19 +193,42 @@ fn parse_options(query: &str) -> Result<LogOptions, Error> {
         .find(|&(ref key, _)| key == "since")
         .map_or_else(|| Ok(0), |(_, val)| parse_since(val))
         .context(ErrorKind::MalformedRequestParameter("since"))?;
-    let options = LogOptions::new()
+    let until = parse
+        .iter()
+        .find(|&(ref key, _)| key == "until")
+        .map(|(_, val)| parse_since(val))
+        .transpose()
+        .context(ErrorKind::MalformedRequestParameter("until"))?;
+    let timestamps = parse
+        .iter()
+        .find(|&(ref key, _)| key == "timestamps")
+        .map_or_else(|| Ok(false), |(_, val)| val.parse::<bool>())
+        .context(ErrorKind::MalformedRequestParameter("timestamps"))?;
+    let mut options = LogOptions::new()
         .with_follow(follow)
         .with_tail(tail)
-        .with_since(since);
+        .with_since(since)
+        .with_timestamps(timestamps);
+    if let Some(until) = until {
+        if until < since {
+            return Err(Error::from(ErrorKind::MalformedRequestParameter("until")));
+        }
+        options = options.with_until(until);
+    }
     Ok(options)
 }
 
 #[cfg(test)]
 mod tests {
+    use std::io::Read;
+
     use chrono::prelude::*;
-    use edgelet_core::{MakeModuleRuntime, ModuleRuntimeState, ModuleStatus};
+    use edgelet_core::{
+        MakeModuleRuntime, ModuleRuntimeState, ModuleStatus, DEFAULT_MAX_LOG_LINE_LENGTH,
+    };
     use edgelet_test_utils::crypto::TestHsm;
     use edgelet_test_utils::module::*;
+    use flate2::read::GzDecoder;
     use futures::Stream;
     use management::models::*;
     use serde_json;
@@ -120,6 +252,19 @@ mod tests {
         assert_eq!(LogTail::default(), *options.tail());
         assert_eq!(false, options.follow());
         assert_eq!(0, options.since());
+        assert_eq!(false, options.timestamps());
+    }
+
+    #[test]
+    fn logoption_timestamps() {
+        let options = parse_options("timestamps=true").unwrap();
+        assert_eq!(true, options.timestamps());
+
+        let options = parse_options("timestamps=yes");
+        assert_eq!(
+            "The request parameter `timestamps` is malformed",
+            options.err().unwrap().to_string()
+        );
     }
 
     #[test]
@@ -155,6 +300,40 @@ mod tests {
         );
     }
 
+    #[test]
+    fn logoption_until() {
+        let query = "since=1551885923&until=2019-09-27T16:00:00%2B00:00";
+        let options = parse_options(&query).unwrap();
+        assert_eq!(1_551_885_923, options.since());
+        assert_eq!(Some(1_569_600_000), options.until());
+    }
+
+    #[test]
+    fn logoption_until_defaults_to_none() {
+        let options = parse_options("since=1551885923").unwrap();
+        assert_eq!(None, options.until());
+    }
+
+    #[test]
+    fn logoption_until_error() {
+        let query = "follow=true&tail=6&until=15abc";
+        let options = parse_options(&query);
+        assert_eq!(
+            "The request parameter `until` is malformed",
+            options.err().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn logoption_until_before_since_error() {
+        let query = "since=1551885923&until=1551885000";
+        let options = parse_options(&query);
+        assert_eq!(
+            "The request parameter `until` is malformed",
+            options.err().unwrap().to_string()
+        );
+    }
+
     #[test]
     fn test_success() {
         let state = ModuleRuntimeState::default()
@@ -179,7 +358,7 @@ mod tests {
         .wait()
         .unwrap()
         .with_module(Ok(module));
-        let handler = ModuleLogs::new(runtime);
+        let handler = ModuleLogs::new(runtime, DEFAULT_MAX_LOG_LINE_LENGTH);
         let request = Request::get("http://localhost/modules/mod1/logs?api-version=2018-06-28")
             .body(Body::default())
             .unwrap();
@@ -202,6 +381,96 @@ mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn gzip_is_accepted() {
+        let request = |accept_encoding: &str| {
+            Request::get("http://localhost/modules/mod1/logs?api-version=2018-06-28")
+                .header(ACCEPT_ENCODING, accept_encoding)
+                .body(Body::default())
+                .unwrap()
+        };
+
+        assert!(accepts_gzip(&request("gzip")));
+        assert!(accepts_gzip(&request("deflate, GZIP;q=0.5")));
+        assert!(!accepts_gzip(&request("gzip;q=0")));
+        assert!(!accepts_gzip(&request("deflate, br")));
+    }
+
+    #[test]
+    fn gzip_logs_are_deframed_and_compressed() {
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error, _> = TestModule::new_with_logs(
+            "test-module".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default()),
+            vec![
+                &b"\x01\x00\x00\x00\x00\x00\x00\x0aone\r\ntwo\n\n"[..],
+                &b"\x02\x00\x00\x00\x00\x00\x00\x05oo"[..],
+                &b"ps\n"[..],
+            ],
+        );
+        let runtime = TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(Ok(module));
+        let handler = ModuleLogs::new(runtime, DEFAULT_MAX_LOG_LINE_LENGTH);
+        let request = Request::get("http://localhost/modules/mod1/logs?api-version=2018-06-28")
+            .header(ACCEPT_ENCODING, "gzip")
+            .body(Body::default())
+            .unwrap();
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "mod1".to_string())]);
+
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!("gzip", response.headers()[CONTENT_ENCODING]);
+        let body = response.into_body().concat2().wait().unwrap();
+        let mut logs = String::new();
+        GzDecoder::new(&body[..]).read_to_string(&mut logs).unwrap();
+        assert_eq!("one\r\ntwo\n\noops\n", logs);
+    }
+
+    #[test]
+    fn gzip_logs_are_truncated_to_max_line_length() {
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error, _> = TestModule::new_with_logs(
+            "test-module".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default()),
+            vec![
+                &b"\x01\x00\x00\x00\x00\x00\x00\x0a0123456789\x01\x00\x00\x00\x00\x00\x00\x03ok\n"
+                    [..],
+            ],
+        );
+        let runtime = TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(Ok(module));
+        let handler = ModuleLogs::new(runtime, 4);
+        let request = Request::get("http://localhost/modules/mod1/logs?api-version=2018-06-28")
+            .header(ACCEPT_ENCODING, "gzip")
+            .body(Body::default())
+            .unwrap();
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "mod1".to_string())]);
+
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        let body = response.into_body().concat2().wait().unwrap();
+        let mut logs = String::new();
+        GzDecoder::new(&body[..]).read_to_string(&mut logs).unwrap();
+        assert_eq!("0123... [truncated 6 bytes]\nok\n", logs);
+    }
+
     #[test]
     fn runtime_error() {
         let runtime = TestRuntime::make_runtime(
@@ -212,7 +481,7 @@ mod tests {
         .wait()
         .unwrap()
         .with_module(Err(Error::General));
-        let handler = ModuleLogs::new(runtime);
+        let handler = ModuleLogs::new(runtime, DEFAULT_MAX_LOG_LINE_LENGTH);
         let request = Request::get("http://localhost/modules/mod1/logs?api-version=2018-06-28")
             .body(Body::default())
             .unwrap();
@@ -259,7 +528,7 @@ mod tests {
         .wait()
         .unwrap()
         .with_module(Ok(module));
-        let handler = ModuleLogs::new(runtime);
+        let handler = ModuleLogs::new(runtime, DEFAULT_MAX_LOG_LINE_LENGTH);
         let request = Request::get(
             "http://localhost/modules/mod1/logs?api-version=2018-06-28&follow=asfda&tail=asfafda",
         )
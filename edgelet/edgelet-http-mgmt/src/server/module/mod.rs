@@ -1,14 +1,23 @@
 // Copyright (c) Microsoft. All rights reserved.
 
 use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
 
-use failure::Fail;
+use failure::{Fail, ResultExt};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use serde_json;
+use url::form_urlencoded::parse as parse_query;
 
 use edgelet_core::{
-    ImagePullPolicy, Module, ModuleRuntime, ModuleSpec as CoreModuleSpec, ModuleStatus,
+    join_create_options, ImagePullPolicy, Module, ModuleBind as CoreModuleBind,
+    ModuleDevice as CoreModuleDevice, ModuleLogConfig as CoreModuleLogConfig,
+    ModuleNetwork as CoreModuleNetwork, ModuleResources as CoreModuleResources, ModuleRuntime,
+    ModuleRuntimeState, ModuleSpec as CoreModuleSpec, ModuleStatus, ModuleTmpfs as CoreModuleTmpfs,
+    ModuleUlimit as CoreModuleUlimit, PreStopHook, RestartPolicy,
 };
 use management::models::*;
 
@@ -16,25 +25,37 @@ use crate::error::{Error, ErrorKind};
 
 mod create;
 mod delete;
+mod diff;
+mod effective_spec;
+mod exec;
 mod get;
 mod list;
 mod logs;
+mod patch;
 mod prepare_update;
 mod restart;
 mod start;
+mod stats;
 mod stop;
 mod update;
+mod wait;
 
 pub use self::create::CreateModule;
 pub use self::delete::DeleteModule;
+pub use self::diff::DiffModules;
+pub use self::effective_spec::GetEffectiveSpec;
+pub use self::exec::ExecModule;
 pub use self::get::GetModule;
 pub use self::list::ListModules;
 pub use self::logs::ModuleLogs;
+pub use self::patch::PatchModule;
 pub use self::prepare_update::PrepareUpdateModule;
-pub use self::restart::RestartModule;
+pub use self::restart::{RestartModule, RestartModules};
 pub use self::start::StartModule;
+pub use self::stats::ModuleStats;
 pub use self::stop::StopModule;
 pub use self::update::UpdateModule;
+pub use self::wait::WaitModule;
 
 fn spec_to_core<M>(
     spec: &ModuleSpec,
@@ -52,7 +73,11 @@ where
             .collect()
     });
 
-    let config = match serde_json::from_value(spec.config().settings().clone()) {
+    let mut settings = spec.config().settings().clone();
+    if let Err(err) = join_create_options(&mut settings) {
+        return Err(Error::from(err.context(context)));
+    }
+    let config = match serde_json::from_value(settings) {
         Ok(config) => config,
         Err(err) => return Err(Error::from(err.context(context))),
     };
@@ -65,14 +90,324 @@ where
         Err(err) => return Err(Error::from(err.context(context))),
     };
 
+    let restart_policy = match spec
+        .restart_policy()
+        .map_or(Ok(RestartPolicy::default()), str::parse)
+    {
+        Ok(restart_policy) => restart_policy,
+        Err(err) => return Err(Error::from(err.context(context))),
+    };
+
     let module_spec = match CoreModuleSpec::new(name, type_, config, env, image_pull_policy) {
         Ok(module_spec) => module_spec,
         Err(err) => return Err(Error::from(err.context(context))),
     };
 
+    let module_spec = match spec.resources() {
+        Some(resources) => module_spec.with_resources(resources_to_core(resources)),
+        None => module_spec,
+    };
+
+    let module_spec = match spec.log_config() {
+        Some(log_config) => module_spec.with_log_config(log_config_to_core(log_config)),
+        None => module_spec,
+    };
+
+    let module_spec = match spec.binds() {
+        Some(binds) => module_spec.with_binds(
+            binds
+                .iter()
+                .map(|bind| {
+                    CoreModuleBind::new(bind.source().clone(), bind.target().clone())
+                        .with_read_only(bind.read_only().unwrap_or(false))
+                })
+                .collect(),
+        ),
+        None => module_spec,
+    };
+
+    let module_spec = module_spec.with_read_only_root_fs(spec.read_only_root_fs().unwrap_or(false));
+
+    let module_spec = match spec.tmpfs() {
+        Some(tmpfs) => module_spec.with_tmpfs(
+            tmpfs
+                .iter()
+                .map(|tmpfs| {
+                    let core = CoreModuleTmpfs::new(tmpfs.target().clone());
+                    match tmpfs.size_bytes() {
+                        Some(size_bytes) => core.with_size_bytes(size_bytes),
+                        None => core,
+                    }
+                })
+                .collect(),
+        ),
+        None => module_spec,
+    };
+
+    let module_spec =
+        module_spec.with_stop_timeout(spec.stop_timeout_secs().map(Duration::from_secs));
+
+    let module_spec = module_spec.with_stop_signal(spec.stop_signal().map(ToString::to_string));
+
+    let module_spec = module_spec.with_pre_stop(spec.pre_stop().map(|pre_stop| {
+        let hook = PreStopHook::new(pre_stop.command().to_vec());
+        match pre_stop.timeout_secs() {
+            Some(timeout_secs) => hook.with_timeout(Duration::from_secs(timeout_secs)),
+            None => hook,
+        }
+    }));
+
+    let module_spec = match spec.labels() {
+        Some(labels) => module_spec.with_labels(labels.clone()),
+        None => module_spec,
+    };
+
+    let module_spec = match spec.env_files() {
+        Some(env_files) => {
+            module_spec.with_env_files(env_files.iter().map(PathBuf::from).collect())
+        }
+        None => module_spec,
+    };
+
+    let module_spec = match spec.dns() {
+        Some(dns) => module_spec.with_dns(dns.to_vec()),
+        None => module_spec,
+    };
+
+    let module_spec = match spec.dns_search() {
+        Some(dns_search) => module_spec.with_dns_search(dns_search.to_vec()),
+        None => module_spec,
+    };
+
+    let module_spec = match spec.networks() {
+        Some(networks) => module_spec.with_networks(
+            networks
+                .iter()
+                .map(|network| {
+                    CoreModuleNetwork::new(network.name().clone())
+                        .with_aliases(network.aliases().map_or_else(Vec::new, <[_]>::to_vec))
+                })
+                .collect(),
+        ),
+        None => module_spec,
+    };
+
+    let module_spec = match spec.devices() {
+        Some(devices) => module_spec.with_devices(
+            devices
+                .iter()
+                .map(|device| {
+                    let core = CoreModuleDevice::new(
+                        device.host_path().clone(),
+                        device.container_path().clone(),
+                    );
+                    match device.permissions() {
+                        Some(permissions) => core.with_permissions(permissions.to_string()),
+                        None => core,
+                    }
+                })
+                .collect(),
+        ),
+        None => module_spec,
+    };
+
+    let module_spec = module_spec.with_runtime(spec.runtime().map(ToString::to_string));
+
+    let module_spec = module_spec
+        .with_cap_add(spec.cap_add().map_or_else(Vec::new, <[String]>::to_vec))
+        .with_cap_drop(spec.cap_drop().map_or_else(Vec::new, <[String]>::to_vec))
+        .with_no_new_privileges(spec.no_new_privileges().unwrap_or(false))
+        .with_seccomp_profile(spec.seccomp_profile().map(PathBuf::from))
+        .with_apparmor_profile(spec.apparmor_profile().map(ToString::to_string));
+
+    let module_spec = match spec.ulimits() {
+        Some(ulimits) => module_spec.with_ulimits(
+            ulimits
+                .iter()
+                .map(|ulimit| {
+                    CoreModuleUlimit::new(ulimit.name().clone(), ulimit.soft(), ulimit.hard())
+                })
+                .collect(),
+        ),
+        None => module_spec,
+    };
+
+    let module_spec = module_spec
+        .with_restart_policy(restart_policy)
+        .with_max_retries(spec.max_retries())
+        .with_user(spec.user().map(ToString::to_string))
+        .with_working_dir(spec.working_dir().map(ToString::to_string))
+        .with_identity_integration(spec.identity_integration().unwrap_or(false));
+
     Ok(module_spec)
 }
 
+fn resources_to_core(resources: &ModuleResources) -> CoreModuleResources {
+    let mut core = CoreModuleResources::new();
+    if let Some(cpu_shares) = resources.cpu_shares() {
+        core = core.with_cpu_shares(cpu_shares);
+    }
+    if let Some(memory_bytes) = resources.memory_bytes() {
+        core = core.with_memory_bytes(memory_bytes);
+    }
+    if let Some(memory_swap) = resources.memory_swap() {
+        core = core.with_memory_swap(memory_swap);
+    }
+    if let Some(pids_limit) = resources.pids_limit() {
+        core = core.with_pids_limit(pids_limit);
+    }
+    core
+}
+
+fn log_config_to_core(log_config: &ModuleLogConfig) -> CoreModuleLogConfig {
+    let mut core = CoreModuleLogConfig::new();
+    if let Some(log_driver) = log_config.log_driver() {
+        core = core.with_log_driver(log_driver.to_string());
+    }
+    if let Some(max_size) = log_config.max_size() {
+        core = core.with_max_size(max_size.to_string());
+    }
+    if let Some(max_file) = log_config.max_file() {
+        core = core.with_max_file(max_file);
+    }
+    core
+}
+
+/// The `label=key=value` parameters of a request. A module is selected if it
+/// has all of the labels.
+#[derive(Debug, Default, PartialEq)]
+struct LabelSelector(Vec<(String, String)>);
+
+impl LabelSelector {
+    fn parse(query: &str) -> Result<Self, Error> {
+        parse_query(query.as_bytes())
+            .filter(|(key, _)| key == "label")
+            .map(|(_, selector)| {
+                let mut parts = selector.splitn(2, '=');
+                match (parts.next(), parts.next()) {
+                    (Some(key), Some(value)) if !key.is_empty() => {
+                        Ok((key.to_string(), value.to_string()))
+                    }
+                    _ => Err(Error::from(ErrorKind::MalformedRequestParameter("label"))),
+                }
+            })
+            .collect::<Result<_, _>>()
+            .map(LabelSelector)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn matches(&self, labels: &HashMap<String, String>) -> bool {
+        self.0
+            .iter()
+            .all(|(key, value)| labels.get(key) == Some(value))
+    }
+}
+
+fn is_dry_run(req: &Request<Body>) -> bool {
+    req.uri().query().map_or(false, |query| {
+        parse_query(query.as_bytes()).any(|(key, value)| key == "dry_run" && value == "true")
+    })
+}
+
+fn dry_run_response<F>(name: String, action: &str, context: F) -> Result<Response<Body>, Error>
+where
+    F: Fn() -> ErrorKind,
+{
+    let result = DryRunResult::new(vec![DryRunAction::new(name, action.to_string())]);
+    let b = serde_json::to_string(&result).with_context(|_| context())?;
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
+        .header(CONTENT_LENGTH, b.len().to_string().as_str())
+        .body(b.into())
+        .with_context(|_| context())?;
+    Ok(response)
+}
+
+pub(super) fn core_to_details<M, F>(
+    module: &M,
+    state: &ModuleRuntimeState,
+    context: F,
+) -> Result<ModuleDetails, Error>
+where
+    M: 'static + Module + Send,
+    M::Config: Serialize,
+    F: Fn() -> ErrorKind,
+{
+    let settings = serde_json::to_value(module.config()).with_context(|_| context())?;
+    let config = Config::new(settings).with_env(vec![]);
+    let mut runtime_status = RuntimeStatus::new(state.status().to_string());
+    if let Some(description) = state.status_description() {
+        runtime_status.set_description(description.to_string());
+    }
+    if let Some(health) = state.health() {
+        runtime_status.set_health(health.to_string());
+    }
+    let mut status = Status::new(runtime_status);
+    if let Some(started_at) = state.started_at() {
+        status.set_start_time(started_at.to_rfc3339());
+    }
+    if let Some(code) = state.exit_code() {
+        if let Some(finished_at) = state.finished_at() {
+            let mut exit_status = ExitStatus::new(finished_at.to_rfc3339(), code.to_string());
+            if let Some(reason) = state.exit_reason() {
+                exit_status.set_reason(reason.to_string());
+            }
+            status.set_exit_status(exit_status);
+        }
+    }
+    if let Some(restart_count) = state.restart_count() {
+        status.set_restart_count(restart_count);
+    }
+    if state.paused() {
+        status.set_paused(true);
+    }
+    if let Some(error) = state.last_error() {
+        let mut last_error = LastError::new(
+            error.code().to_string(),
+            error.message().to_string(),
+            error.timestamp().to_rfc3339(),
+        );
+        if let Some(code) = error.exit_code() {
+            last_error.set_exit_code(code);
+        }
+        status.set_last_error(last_error);
+    }
+    if !state.ports().is_empty() {
+        let ports = state
+            .ports()
+            .iter()
+            .map(|port| {
+                let mut binding = PortBinding::new(port.container_port().to_string());
+                if let Some(host_ip) = port.host_ip() {
+                    binding.set_host_ip(host_ip.to_string());
+                }
+                if let Some(host_port) = port.host_port() {
+                    binding.set_host_port(host_port.to_string());
+                }
+                binding
+            })
+            .collect();
+        status.set_ports(ports);
+    }
+
+    let details = ModuleDetails::new(
+        "id".to_string(),
+        module.name().to_string(),
+        module.type_().to_string(),
+        config,
+        status,
+    );
+    Ok(if state.labels().is_empty() {
+        details
+    } else {
+        details.with_labels(state.labels().clone())
+    })
+}
+
 fn spec_to_details(spec: &ModuleSpec, module_status: ModuleStatus) -> ModuleDetails {
     let id = spec.name().clone();
     let name = spec.name().clone();
@@ -90,7 +425,11 @@ fn spec_to_details(spec: &ModuleSpec, module_status: ModuleStatus) -> ModuleDeta
 
     let runtime_status = RuntimeStatus::new(module_status.to_string());
     let status = Status::new(runtime_status);
-    ModuleDetails::new(id, name, type_, config, status)
+    let details = ModuleDetails::new(id, name, type_, config, status);
+    match spec.labels() {
+        Some(labels) => details.with_labels(labels.clone()),
+        None => details,
+    }
 }
 
 #[cfg(test)]
@@ -100,10 +439,11 @@ pub mod tests {
     use hyper::{Body, Response, StatusCode};
     use serde_json;
 
-    use edgelet_core::RuntimeOperation;
+    use edgelet_core::{ModuleRuntimeErrorReason, RuntimeOperation};
     use edgelet_docker::{Error as DockerError, ErrorKind as DockerErrorKind};
     use management::models::ErrorResponse;
 
+    use super::LabelSelector;
     use crate::error::{Error as MgmtError, ErrorKind};
     use crate::IntoResponse;
 
@@ -113,6 +453,12 @@ pub mod tests {
         General,
     }
 
+    impl<'a> From<&'a Error> for ModuleRuntimeErrorReason {
+        fn from(_err: &'a Error) -> Self {
+            ModuleRuntimeErrorReason::Other
+        }
+    }
+
     impl IntoResponse for Error {
         fn into_response(self) -> Response<Body> {
             let body = serde_json::to_string(&ErrorResponse::new(self.to_string()))
@@ -124,6 +470,41 @@ pub mod tests {
         }
     }
 
+    #[test]
+    fn label_selector_needs_every_label() {
+        let selector =
+            LabelSelector::parse("label=group%3Dsensors&label=tier=edge&limit=2").unwrap();
+        let mut labels = std::collections::HashMap::new();
+        labels.insert("group".to_string(), "sensors".to_string());
+        assert!(!selector.matches(&labels));
+
+        labels.insert("tier".to_string(), "edge".to_string());
+        labels.insert("owner".to_string(), "me".to_string());
+        assert!(selector.matches(&labels));
+
+        labels.insert("tier".to_string(), "cloud".to_string());
+        assert!(!selector.matches(&labels));
+
+        assert!(LabelSelector::parse("limit=2").unwrap().is_empty());
+    }
+
+    #[test]
+    fn label_selector_needs_key_and_value() {
+        for query in &["label=group", "label==sensors"] {
+            let err = LabelSelector::parse(query).unwrap_err();
+            assert_eq!(
+                "The request parameter `label` is malformed",
+                err.to_string()
+            );
+        }
+        assert!(LabelSelector::parse("label=group=").unwrap().matches(
+            &[("group".to_string(), String::new())]
+                .iter()
+                .cloned()
+                .collect()
+        ));
+    }
+
     #[test]
     fn not_found() {
         // arrange
@@ -190,6 +571,39 @@ pub mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn not_running() {
+        // arrange
+        let error = MgmtError::from(
+            DockerError::from(DockerErrorKind::NotRunning("m1".to_string()).context(
+                DockerErrorKind::RuntimeOperation(RuntimeOperation::GetModuleStats(
+                    "m1".to_string(),
+                )),
+            ))
+            .context(ErrorKind::RuntimeOperation(
+                RuntimeOperation::GetModuleStats("m1".to_string()),
+            )),
+        );
+
+        // act
+        let response = error.into_response();
+
+        // assert
+        assert_eq!(StatusCode::CONFLICT, response.status());
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+                assert_eq!(
+                    "Could not get stats for module m1\n\tcaused by: Could not get stats for module m1\n\tcaused by: Module m1 is not running",
+                    error.message()
+                );
+                Ok(())
+            }).wait()
+            .unwrap();
+    }
+
     #[test]
     fn not_modified() {
         // arrange
@@ -218,6 +632,31 @@ pub mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn invalid_resource_limit() {
+        // arrange
+        let error = MgmtError::from(
+            DockerError::from(
+                DockerErrorKind::InvalidResourceLimit(
+                    "pids_limit",
+                    "invalid pids limit".to_string(),
+                )
+                .context(DockerErrorKind::RuntimeOperation(
+                    RuntimeOperation::CreateModule("m1".to_string()),
+                )),
+            )
+            .context(ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(
+                "m1".to_string(),
+            ))),
+        );
+
+        // act
+        let response = error.into_response();
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    }
+
     #[test]
     fn internal_server() {
         // arrange
@@ -0,0 +1,296 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Patches the spec that a module was last created from, the one that
+//! `/modules/{name}/effective-spec` returns, and recreates the module from
+//! it, so that a single field can be changed without resubmitting the whole
+//! spec. The body is a JSON Patch (RFC 6902), or a JSON Merge Patch
+//! (RFC 7396) if its content type is `application/merge-patch+json`.
+//!
+//! The patched spec is validated before the module is touched, and the name
+//! and type of the module can't be patched. Since the registry credentials of
+//! the spec are redacted, its image is only pulled again if the patch changes
+//! the config. A module that was running is started again.
+
+use std::time::Duration;
+
+use failure::{Fail, ResultExt};
+use futures::future::{self, Either};
+use futures::{Future, IntoFuture, Stream};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use json_patch::Patch;
+use log::info;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json;
+
+use edgelet_core::{
+    replace_module, update_module, Module, ModuleRuntime, ModuleRuntimeErrorReason,
+    ModuleSpec as CoreModuleSpec, ModuleStatus,
+};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+const MERGE_PATCH: &str = "application/merge-patch+json";
+
+/// The fields of a spec that name the module, which a patch can't change.
+const IMMUTABLE_FIELDS: &[&str] = &["name", "type"];
+
+/// How long a module without a stop timeout is given to exit before it's
+/// replaced.
+const STOP_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct PatchModule<M> {
+    runtime: M,
+}
+
+impl<M> PatchModule<M> {
+    pub fn new(runtime: M) -> Self {
+        PatchModule { runtime }
+    }
+}
+
+enum SpecPatch {
+    Json(Patch),
+    Merge(serde_json::Value),
+}
+
+impl<M> Handler<Parameters> for PatchModule<M>
+where
+    M: 'static + ModuleRuntime + Clone + Send + Sync,
+    for<'r> &'r <M as ModuleRuntime>::Error: Into<ModuleRuntimeErrorReason>,
+    <M::Module as Module>::Config: DeserializeOwned + Serialize,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let runtime = self.runtime.clone();
+        let merge = req
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map_or(false, |value| value.starts_with(MERGE_PATCH));
+
+        let response = params
+            .name("name")
+            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+            .map(ToString::to_string)
+            .into_future()
+            .and_then(move |name| {
+                let patch = req
+                    .into_body()
+                    .concat2()
+                    .then(move |body| -> Result<_, Error> {
+                        let body = body.context(ErrorKind::MalformedRequestBody)?;
+                        let patch = if merge {
+                            SpecPatch::Merge(
+                                serde_json::from_slice(&body)
+                                    .context(ErrorKind::MalformedRequestBody)?,
+                            )
+                        } else {
+                            SpecPatch::Json(
+                                serde_json::from_slice(&body)
+                                    .context(ErrorKind::MalformedRequestBody)?,
+                            )
+                        };
+                        Ok(patch)
+                    });
+
+                let context = {
+                    let name = name.clone();
+                    move || ErrorKind::PatchModule(name)
+                };
+                let get_context = context.clone();
+                let current = runtime
+                    .effective_spec(&name)
+                    .map_err(move |err| Error::from(err.context(context())));
+                let running = runtime
+                    .get(&name)
+                    .map(|(_, state)| *state.status() == ModuleStatus::Running)
+                    .map_err(move |err| Error::from(err.context(get_context())));
+
+                patch
+                    .join3(current, running)
+                    .map(move |(patch, current, running)| (runtime, name, patch, current, running))
+            })
+            .and_then(|(runtime, name, patch, current, running)| {
+                let (spec, config_changed) = apply_patch(&name, &current, &patch)?;
+                runtime.validate(&spec).map_err(|err| {
+                    Error::from(err.context(ErrorKind::PatchModule(name.clone())))
+                })?;
+                let body = serde_json::to_string(&spec)
+                    .with_context(|_| ErrorKind::PatchModule(name.clone()))?;
+                Ok((runtime, name, spec, config_changed, running, body))
+            })
+            .and_then(|(runtime, name, spec, config_changed, running, body)| {
+                info!("Patching module {}", name);
+
+                let timeout = spec.stop_timeout().unwrap_or(STOP_TIMEOUT);
+                let replaced = if config_changed {
+                    Either::A(update_module(runtime.clone(), spec, timeout))
+                } else {
+                    Either::B(replace_module(runtime.clone(), spec, timeout))
+                };
+
+                let context = {
+                    let name = name.clone();
+                    move || ErrorKind::PatchModule(name)
+                };
+                let start_context = context.clone();
+                replaced
+                    .map_err(move |err| Error::from(err.context(context())))
+                    .and_then(move |()| {
+                        let started = if running {
+                            info!("Starting module {}", name);
+                            Either::A(
+                                runtime
+                                    .start(&name)
+                                    .map_err(move |err| Error::from(err.context(start_context()))),
+                            )
+                        } else {
+                            Either::B(future::ok(()))
+                        };
+                        started.map(move |()| (name, body))
+                    })
+            })
+            .and_then(|(name, body)| -> Result<_, Error> {
+                let response = Response::builder()
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(CONTENT_LENGTH, body.len().to_string().as_str())
+                    .body(body.into())
+                    .context(ErrorKind::PatchModule(name))?;
+                Ok(response)
+            })
+            .or_else(|e| Ok(e.into_response()));
+
+        Box::new(response)
+    }
+}
+
+// Resolves to the patched spec, and to whether the patch changed its config.
+fn apply_patch<T>(
+    name: &str,
+    current: &CoreModuleSpec<T>,
+    patch: &SpecPatch,
+) -> Result<(CoreModuleSpec<T>, bool), Error>
+where
+    T: DeserializeOwned + Serialize,
+{
+    let current =
+        serde_json::to_value(current).with_context(|_| ErrorKind::PatchModule(name.to_string()))?;
+    let mut patched = current.clone();
+    match patch {
+        SpecPatch::Json(patch) => {
+            json_patch::patch(&mut patched, patch).context(ErrorKind::MalformedRequestBody)?
+        }
+        SpecPatch::Merge(patch) => json_patch::merge(&mut patched, patch),
+    }
+
+    if let Some(field) = IMMUTABLE_FIELDS
+        .iter()
+        .find(|&&field| patched.get(field) != current.get(field))
+    {
+        return Err(Error::from(ErrorKind::ImmutableField(*field)));
+    }
+
+    let config_changed = patched.get("config") != current.get("config");
+    let spec = serde_json::from_value(patched).context(ErrorKind::MalformedRequestBody)?;
+    Ok((spec, config_changed))
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::{MakeModuleRuntime, ModuleRuntimeState};
+    use edgelet_test_utils::crypto::TestHsm;
+    use edgelet_test_utils::module::*;
+    use management::models::ErrorResponse;
+    use serde_json::json;
+
+    use super::*;
+    use crate::server::module::tests::Error as TestError;
+
+    fn patch(content_type: &str, patch: &serde_json::Value) -> Response<Body> {
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<TestError, _> = TestModule::new(
+            "mod1".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default()),
+        );
+        let runtime = TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(Ok(module));
+        let handler = PatchModule::new(runtime);
+
+        let request = Request::patch("http://localhost/modules/mod1?api-version=2019-11-05")
+            .header(CONTENT_TYPE, content_type)
+            .body(patch.to_string().into())
+            .unwrap();
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "mod1".to_string())]);
+
+        handler.handle(request, parameters).wait().unwrap()
+    }
+
+    fn body(response: Response<Body>) -> serde_json::Value {
+        let body = response.into_body().concat2().wait().unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[test]
+    fn env_var_is_patched() {
+        let response = patch(
+            "application/json-patch+json",
+            &json!([{ "op": "add", "path": "/env/LOG_LEVEL", "value": "debug" }]),
+        );
+
+        assert_eq!(StatusCode::OK, response.status());
+        let spec = body(response);
+        assert_eq!("mod1", spec["name"]);
+        assert_eq!(json!({ "LOG_LEVEL": "debug" }), spec["env"]);
+        assert_eq!("microsoft/test-image", spec["config"]["image"]);
+    }
+
+    #[test]
+    fn env_var_is_merged() {
+        let response = patch(MERGE_PATCH, &json!({ "env": { "LOG_LEVEL": "debug" } }));
+
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(json!({ "LOG_LEVEL": "debug" }), body(response)["env"]);
+    }
+
+    #[test]
+    fn name_change_is_rejected() {
+        let response = patch(
+            "application/json-patch+json",
+            &json!([{ "op": "replace", "path": "/name", "value": "mod2" }]),
+        );
+
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        let error: ErrorResponse = serde_json::from_value(body(response)).unwrap();
+        assert_eq!(
+            "The field `name` of a module spec can't be changed",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn failed_patch_is_rejected() {
+        let response = patch(
+            "application/json-patch+json",
+            &json!([{ "op": "test", "path": "/env/LOG_LEVEL", "value": "info" }]),
+        );
+
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    }
+}
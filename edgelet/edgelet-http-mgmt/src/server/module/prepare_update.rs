@@ -1,7 +1,6 @@
 // Copyright (c) Microsoft. All rights reserved.
 
 use failure::ResultExt;
-use futures::future::Either;
 use futures::{Future, Stream};
 use hyper::{Body, Request, Response, StatusCode};
 use log::debug;
@@ -9,7 +8,7 @@ use serde::de::DeserializeOwned;
 use serde::Serialize;
 use serde_json;
 
-use edgelet_core::{ImagePullPolicy, Module, ModuleRegistry, ModuleRuntime};
+use edgelet_core::{pull_image, Module, ModuleRuntime};
 use edgelet_http::route::{Handler, Parameters};
 use edgelet_http::Error as HttpError;
 
@@ -50,21 +49,11 @@ where
             })
             .and_then(|(core_spec, runtime)| {
                 let name = core_spec.name().to_string();
-                let image_pull_policy = core_spec.image_pull_policy();
-                match image_pull_policy {
-                    ImagePullPolicy::OnCreate => Either::A(
-                        runtime
-                            .registry()
-                            .pull(core_spec.config())
-                            .then(move |result| {
-                                result.with_context(|_| {
-                                    ErrorKind::PrepareUpdateModule(name.clone())
-                                })?;
-                                Ok((name, true))
-                            }),
-                    ),
-                    ImagePullPolicy::Never => Either::B(futures::future::ok((name, false))),
-                }
+                pull_image(&runtime, &core_spec).then(move |result| {
+                    let image_pulled =
+                        result.with_context(|_| ErrorKind::PrepareUpdateModule(name.clone()))?;
+                    Ok((name, image_pulled))
+                })
             })
             .and_then(|(name, image_pulled)| -> Result<_, Error> {
                 if image_pulled {
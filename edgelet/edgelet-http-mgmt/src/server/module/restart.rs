@@ -1,13 +1,18 @@
 // Copyright (c) Microsoft. All rights reserved.
 
 use failure::{Fail, ResultExt};
-use futures::{Future, IntoFuture};
+use futures::{future, Future, IntoFuture, Stream};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
 use hyper::{Body, Request, Response, StatusCode};
+use serde::Serialize;
+use serde_json;
 
-use edgelet_core::{ModuleRuntime, RuntimeOperation};
+use edgelet_core::{Module, ModuleRuntime, RuntimeOperation};
 use edgelet_http::route::{Handler, Parameters};
 use edgelet_http::Error as HttpError;
+use management::models::ModuleList;
 
+use super::{core_to_details, LabelSelector};
 use crate::error::{Error, ErrorKind};
 use crate::IntoResponse;
 
@@ -23,7 +28,8 @@ impl<M> RestartModule<M> {
 
 impl<M> Handler<Parameters> for RestartModule<M>
 where
-    M: 'static + ModuleRuntime + Send,
+    M: 'static + ModuleRuntime + Clone + Send,
+    <M::Module as Module>::Config: Serialize,
 {
     fn handle(
         &self,
@@ -35,23 +41,125 @@ where
             .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
             .map(|name| {
                 let name = name.to_string();
+                let runtime = self.runtime.clone();
+                let get_name = name.clone();
 
-                self.runtime.restart(&name).then(|result| match result {
-                    Ok(_) => Ok(name),
-                    Err(err) => Err(Error::from(err.context(ErrorKind::RuntimeOperation(
-                        RuntimeOperation::RestartModule(name),
-                    )))),
-                })
+                // The response has the state the module is in after the restart.
+                self.runtime
+                    .restart(&name)
+                    .and_then(move |()| runtime.get(&get_name))
+                    .then(|result| match result {
+                        Ok((module, state)) => Ok((name, module, state)),
+                        Err(err) => Err(Error::from(err.context(ErrorKind::RuntimeOperation(
+                            RuntimeOperation::RestartModule(name),
+                        )))),
+                    })
             })
             .into_future()
             .flatten()
-            .and_then(|name| {
-                Ok(Response::builder()
-                    .status(StatusCode::NO_CONTENT)
-                    .body(Body::default())
-                    .context(ErrorKind::RuntimeOperation(
-                        RuntimeOperation::RestartModule(name),
-                    ))?)
+            .and_then(|(name, module, state)| {
+                let context =
+                    || ErrorKind::RuntimeOperation(RuntimeOperation::RestartModule(name.clone()));
+                let details = core_to_details(&module, &state, context)?;
+                let body = serde_json::to_string(&details).with_context(|_| context())?;
+                let response = Response::builder()
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(CONTENT_LENGTH, body.len().to_string().as_str())
+                    .body(body.into())
+                    .with_context(|_| context())?;
+                Ok(response)
+            })
+            .or_else(|e| Ok(e.into_response()));
+
+        Box::new(response)
+    }
+}
+
+/// Restarts every module that has the labels of the request's `label`
+/// parameters, of which there must be at least one.
+pub struct RestartModules<M> {
+    runtime: M,
+}
+
+impl<M> RestartModules<M> {
+    pub fn new(runtime: M) -> Self {
+        RestartModules { runtime }
+    }
+}
+
+impl<M> Handler<Parameters> for RestartModules<M>
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+    <M::Module as Module>::Config: Serialize,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let runtime = self.runtime.clone();
+
+        let response = LabelSelector::parse(req.uri().query().unwrap_or(""))
+            .and_then(|selector| {
+                if selector.is_empty() {
+                    Err(Error::from(ErrorKind::MissingRequiredParameter("label")))
+                } else {
+                    Ok(selector)
+                }
+            })
+            .into_future()
+            .and_then(move |selector| {
+                runtime
+                    .list_with_details()
+                    .collect()
+                    .map_err(|err| {
+                        Error::from(
+                            err.context(ErrorKind::RuntimeOperation(RuntimeOperation::ListModules)),
+                        )
+                    })
+                    .and_then(move |modules| {
+                        let restarts = modules
+                            .iter()
+                            .filter(|(_, state)| selector.matches(state.labels()))
+                            .map(|(module, _)| {
+                                let name = module.name().to_string();
+                                let get = runtime.clone();
+                                let get_name = name.clone();
+                                runtime
+                                    .restart(&name)
+                                    .and_then(move |()| get.get(&get_name))
+                                    .map_err(|err| {
+                                        Error::from(err.context(ErrorKind::RuntimeOperation(
+                                            RuntimeOperation::RestartModule(name),
+                                        )))
+                                    })
+                            })
+                            .collect::<Vec<_>>();
+                        future::join_all(restarts)
+                    })
+            })
+            .and_then(|mut restarted| -> Result<_, Error> {
+                restarted.sort_by(|(a, _), (b, _)| a.name().cmp(b.name()));
+                let details = restarted
+                    .iter()
+                    .map(|(module, state)| {
+                        core_to_details(module, state, || {
+                            ErrorKind::RuntimeOperation(RuntimeOperation::RestartModule(
+                                module.name().to_string(),
+                            ))
+                        })
+                    })
+                    .collect::<Result<_, _>>()?;
+                let body = serde_json::to_string(&ModuleList::new(details))
+                    .context(ErrorKind::RuntimeOperation(RuntimeOperation::ListModules))?;
+                let response = Response::builder()
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(CONTENT_LENGTH, body.len().to_string().as_str())
+                    .body(body.into())
+                    .context(ErrorKind::RuntimeOperation(RuntimeOperation::ListModules))?;
+                Ok(response)
             })
             .or_else(|e| Ok(e.into_response()));
 
@@ -62,6 +170,9 @@ where
 #[cfg(test)]
 mod tests {
     use chrono::prelude::*;
+    use futures::Stream;
+    use management::models::{ErrorResponse, ModuleDetails};
+
     use edgelet_core::{MakeModuleRuntime, ModuleRuntimeState, ModuleStatus};
     use edgelet_http::route::Parameters;
     use edgelet_test_utils::crypto::TestHsm;
@@ -102,7 +213,10 @@ mod tests {
         let response = handler.handle(request, parameters).wait().unwrap();
 
         // assert
-        assert_eq!(StatusCode::NO_CONTENT, response.status());
+        assert_eq!(StatusCode::OK, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let details: ModuleDetails = serde_json::from_slice(&body).unwrap();
+        assert_eq!("test-module", details.name());
     }
 
     #[test]
@@ -137,4 +251,71 @@ mod tests {
         // assert
         assert_eq!(StatusCode::BAD_REQUEST, response.status());
     }
+
+    fn labelled_runtime() -> TestRuntime<Error, TestSettings> {
+        let labels = [("group", "sensors"), ("tier", "edge")]
+            .iter()
+            .map(|(key, value)| ((*key).to_string(), (*value).to_string()))
+            .collect();
+        let state = ModuleRuntimeState::default()
+            .with_status(ModuleStatus::Running)
+            .with_labels(labels);
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error, _> =
+            TestModule::new("test-module".to_string(), config, Ok(state));
+        TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(Ok(module))
+    }
+
+    #[test]
+    fn restart_by_labels() {
+        let handler = RestartModules::new(labelled_runtime());
+
+        for (query, expected) in &[
+            ("label=group=sensors&label=tier=edge", vec!["test-module"]),
+            ("label=group=sensors&label=tier=cloud", vec![]),
+        ] {
+            // arrange
+            let request = Request::post(format!("http://localhost/modules/restart?{}", query))
+                .body(Body::default())
+                .unwrap();
+
+            // act
+            let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+            // assert
+            assert_eq!(StatusCode::OK, response.status());
+            let body = response.into_body().concat2().wait().unwrap();
+            let list: ModuleList = serde_json::from_slice(&body).unwrap();
+            let names: Vec<_> = list.modules().iter().map(|m| m.name().as_str()).collect();
+            assert_eq!(*expected, names, "{}", query);
+        }
+    }
+
+    #[test]
+    fn restart_by_labels_needs_a_label() {
+        // arrange
+        let handler = RestartModules::new(labelled_runtime());
+        let request = Request::post("http://localhost/modules/restart")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            "The request is missing required parameter `label`",
+            error.message()
+        );
+    }
 }
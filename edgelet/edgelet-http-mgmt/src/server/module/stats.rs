@@ -0,0 +1,163 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::{Fail, ResultExt};
+use futures::{Future, IntoFuture};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use serde_json;
+
+use edgelet_core::{ModuleRuntime, RuntimeOperation};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+pub struct ModuleStats<M> {
+    runtime: M,
+}
+
+impl<M> ModuleStats<M> {
+    pub fn new(runtime: M) -> Self {
+        ModuleStats { runtime }
+    }
+}
+
+impl<M> Handler<Parameters> for ModuleStats<M>
+where
+    M: 'static + ModuleRuntime + Send,
+{
+    fn handle(
+        &self,
+        _req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let response = params
+            .name("name")
+            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+            .map(|name| {
+                let name = name.to_string();
+
+                self.runtime.stats(&name).then(|result| match result {
+                    Ok(stats) => Ok((name, stats)),
+                    Err(err) => Err(Error::from(err.context(ErrorKind::RuntimeOperation(
+                        RuntimeOperation::GetModuleStats(name),
+                    )))),
+                })
+            })
+            .into_future()
+            .flatten()
+            .and_then(|(name, stats)| {
+                let body = serde_json::to_string(&stats).with_context(|_| {
+                    ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleStats(name.clone()))
+                })?;
+                let response = Response::builder()
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(CONTENT_LENGTH, body.len().to_string().as_str())
+                    .body(body.into())
+                    .context(ErrorKind::RuntimeOperation(
+                        RuntimeOperation::GetModuleStats(name),
+                    ))?;
+                Ok(response)
+            })
+            .or_else(|e| Ok(e.into_response()));
+
+        Box::new(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::Stream;
+    use management::models::ErrorResponse;
+    use serde_json::Value;
+
+    use edgelet_core::{MakeModuleRuntime, ModuleRuntimeState, ModuleStatus};
+    use edgelet_test_utils::crypto::TestHsm;
+    use edgelet_test_utils::module::*;
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    fn runtime(
+        module: Result<TestModule<Error, TestConfig>, Error>,
+    ) -> TestRuntime<Error, TestSettings> {
+        TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(module)
+    }
+
+    fn request() -> (Request<Body>, Parameters) {
+        let request = Request::get("http://localhost/modules/mod1/stats?api-version=2019-11-05")
+            .body(Body::default())
+            .unwrap();
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "mod1".to_string())]);
+        (request, parameters)
+    }
+
+    #[test]
+    fn success() {
+        // arrange
+        let state = ModuleRuntimeState::default().with_status(ModuleStatus::Running);
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error, _> = TestModule::new("mod1".to_string(), config, Ok(state));
+        let handler = ModuleStats::new(runtime(Ok(module)));
+        let (request, parameters) = request();
+
+        // act
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let stats: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!("mod1", stats["name"]);
+        assert_eq!(12.5, stats["cpu_percent"]);
+        assert_eq!(5000, stats["memory_usage"]);
+        assert_eq!(8000, stats["memory_limit"]);
+    }
+
+    #[test]
+    fn runtime_error() {
+        // arrange
+        let handler = ModuleStats::new(runtime(Err(Error::General)));
+        let (request, parameters) = request();
+
+        // act
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            "Could not get stats for module mod1\n\tcaused by: General error",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn bad_params_fails() {
+        // arrange
+        let state = ModuleRuntimeState::default().with_status(ModuleStatus::Running);
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error, _> = TestModule::new("mod1".to_string(), config, Ok(state));
+        let handler = ModuleStats::new(runtime(Ok(module)));
+        let request = Request::get("http://localhost/modules/mod1/stats?api-version=2019-11-05")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    }
+}
@@ -1,38 +1,63 @@
 // Copyright (c) Microsoft. All rights reserved.
 
-use failure::ResultExt;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use failure::{Fail, ResultExt};
 use futures::future::Either;
 use futures::{future, Future, Stream};
 use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
 use hyper::{Body, Request, Response, StatusCode};
-use log::debug;
-use log::info;
+use log::{debug, info, warn, Level};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use serde_json;
+use tokio::timer::Delay;
 use url::form_urlencoded::parse as parse_query;
 
-use edgelet_core::{ImagePullPolicy, Module, ModuleRegistry, ModuleRuntime, ModuleStatus};
+use edgelet_core::{
+    deferred_for, pull_image, MaintenanceWindows, Module, ModuleRuntime, ModuleRuntimeErrorReason,
+    ModuleSpec as CoreModuleSpec, ModuleStatus,
+};
 use edgelet_http::route::{Handler, Parameters};
 use edgelet_http::Error as HttpError;
+use edgelet_utils::log_failure;
+use management::models::ModuleSpec;
 
-use super::{spec_to_core, spec_to_details};
+use super::{dry_run_response, is_dry_run, spec_to_core, spec_to_details};
 use crate::error::{Error, ErrorKind};
 use crate::IntoResponse;
 
 pub struct UpdateModule<M> {
     runtime: M,
+    maintenance: Option<MaintenanceWindows>,
+    queued: QueuedUpdates,
 }
 
 impl<M> UpdateModule<M> {
     pub fn new(runtime: M) -> Self {
-        UpdateModule { runtime }
+        UpdateModule {
+            runtime,
+            maintenance: None,
+            queued: QueuedUpdates::default(),
+        }
+    }
+
+    /// Only updates modules while one of `maintenance` is open, queueing an
+    /// update until the next one opens otherwise, which is answered with
+    /// `202 Accepted`. Specs labeled as critical updates are applied right
+    /// away. Updates aren't held back by default.
+    pub fn with_maintenance_windows(mut self, maintenance: MaintenanceWindows) -> Self {
+        self.maintenance = Some(maintenance);
+        self
     }
 }
 
 impl<M> Handler<Parameters> for UpdateModule<M>
 where
     M: 'static + ModuleRuntime + Clone + Send + Sync,
+    for<'r> &'r <M as ModuleRuntime>::Error: Into<ModuleRuntimeErrorReason>,
     <M::Module as Module>::Config: DeserializeOwned + Serialize,
 {
     fn handle(
@@ -52,99 +77,228 @@ where
                     .map(|_| true)
             })
             .unwrap_or_else(|| false);
-
-        let response = req
-            .into_body()
-            .concat2()
-            .then(|b| -> Result<_, Error> {
-                let b = b.context(ErrorKind::MalformedRequestBody)?;
-                let spec = serde_json::from_slice(&b).context(ErrorKind::MalformedRequestBody)?;
-                let core_spec = spec_to_core::<M>(&spec, ErrorKind::MalformedRequestBody)?;
-                Ok((core_spec, spec))
-            })
+        let dry_run = is_dry_run(&req);
+
+        let body = req.into_body().concat2().then(|b| -> Result<_, Error> {
+            let b = b.context(ErrorKind::MalformedRequestBody)?;
+            let spec = serde_json::from_slice(&b).context(ErrorKind::MalformedRequestBody)?;
+            let core_spec = spec_to_core::<M>(&spec, ErrorKind::MalformedRequestBody)?;
+            Ok((core_spec, spec))
+        });
+
+        if dry_run {
+            let response = body
+                .and_then(move |(core_spec, _)| plan_update(&runtime, &core_spec))
+                .or_else(|e| Ok(e.into_response()));
+            return Box::new(response);
+        }
+
+        let maintenance = self.maintenance.clone();
+        let queued = self.queued.clone();
+        let response = body
             .and_then(move |(core_spec, spec)| {
                 let name = core_spec.name().to_string();
-
-                if start {
-                    info!("Updating and starting module {}", name);
-                } else {
-                    info!("Updating module {}", name);
-                }
-
-                runtime.remove(&name).then(|result| {
-                    result.with_context(|_| ErrorKind::UpdateModule(name.clone()))?;
-                    Ok((core_spec, spec, name, runtime))
-                })
-            })
-            .and_then(|(core_spec, spec, name, runtime)| {
-                debug!("Removed existing module {}", name);
-
-                match core_spec.image_pull_policy() {
-                    ImagePullPolicy::OnCreate => {
-                        Either::A(runtime.registry().pull(core_spec.config()).then(|result| {
-                            result.with_context(|_| ErrorKind::UpdateModule(name.clone()))?;
-                            Ok((core_spec, spec, name, runtime, true))
-                        }))
-                    }
-                    ImagePullPolicy::Never => {
-                        Either::B(futures::future::ok((core_spec, spec, name, runtime, false)))
+                // Replaces an update of the module that is still queued.
+                let update = queued.push(&name);
+
+                match deferred_for(maintenance.as_ref(), &core_spec) {
+                    Some(until_open) => {
+                        info!(
+                            "Updating module {} once a maintenance window opens in {} seconds",
+                            name,
+                            until_open.as_secs()
+                        );
+                        tokio::spawn(Delay::new(Instant::now() + until_open).then(
+                            move |_| {
+                                if queued.is_latest(&name, update) {
+                                    Either::A(
+                                        update_module(runtime, core_spec, start).then(
+                                            move |result| {
+                                                if let Err(err) = result {
+                                                    warn!("Could not apply the update of module {} that was held back:", name);
+                                                    log_failure(Level::Warn, &err);
+                                                }
+                                                Ok(())
+                                            },
+                                        ),
+                                    )
+                                } else {
+                                    Either::B(future::ok(()))
+                                }
+                            },
+                        ));
+                        Either::A(future::result(details_response(
+                            StatusCode::ACCEPTED,
+                            &spec,
+                            ModuleStatus::Unknown,
+                        )))
                     }
+                    None => Either::B(update_module(runtime, core_spec, start).and_then(
+                        move |status| details_response(StatusCode::OK, &spec, status),
+                    )),
                 }
             })
-            .and_then(|(core_spec, spec, name, runtime, image_pulled)| {
-                if image_pulled {
-                    debug!("Successfully pulled new image for module {}", name)
-                } else {
-                    debug!(
-                        "Skipped pulling image for module {} as per pull policy",
-                        name
-                    )
-                }
+            .or_else(|e| Ok(e.into_response()));
 
-                runtime.create(core_spec).then(|result| {
-                    result.with_context(|_| ErrorKind::UpdateModule(name.clone()))?;
-                    Ok((name, spec, runtime))
-                })
-            })
-            .and_then(move |(name, spec, runtime)| {
-                debug!("Created module {}", name);
-                if start {
-                    info!("Starting module {}", name);
-                    future::Either::A(runtime.start(&name).then(|result| {
-                        result.with_context(|_| ErrorKind::UpdateModule(name.clone()))?;
-                        Ok((ModuleStatus::Running, spec, name))
-                    }))
-                } else {
-                    future::Either::B(future::ok((ModuleStatus::Stopped, spec, name)))
-                }
+        Box::new(response)
+    }
+}
+
+// The latest update of each module, so that an update that was queued for a
+// maintenance window isn't applied if another update of the module came
+// after it.
+#[derive(Clone, Default)]
+struct QueuedUpdates(Arc<Mutex<HashMap<String, u64>>>);
+
+impl QueuedUpdates {
+    // Makes the next update of `name` the latest one.
+    fn push(&self, name: &str) -> u64 {
+        let mut updates = self.0.lock().expect("queued updates lock poisoned");
+        let update = updates.entry(name.to_string()).or_insert(0);
+        *update += 1;
+        *update
+    }
+
+    fn is_latest(&self, name: &str, update: u64) -> bool {
+        let updates = self.0.lock().expect("queued updates lock poisoned");
+        updates.get(name) == Some(&update)
+    }
+}
+
+// Recreates the module from `core_spec`, and starts it if `start` is set.
+// Resolves to the status that the module is left in.
+fn update_module<M>(
+    runtime: M,
+    core_spec: CoreModuleSpec<<M::Module as Module>::Config>,
+    start: bool,
+) -> impl Future<Item = ModuleStatus, Error = Error>
+where
+    M: 'static + ModuleRuntime + Clone + Send + Sync,
+    for<'r> &'r <M as ModuleRuntime>::Error: Into<ModuleRuntimeErrorReason>,
+{
+    let name = core_spec.name().to_string();
+
+    if start {
+        info!("Updating and starting module {}", name);
+    } else {
+        info!("Updating module {}", name);
+    }
+
+    // The existing module keeps running while the new image is
+    // pulled, and is left alone if the pull fails.
+    pull_image(&runtime, &core_spec)
+        .then(|result| {
+            let image_pulled = result.with_context(|_| ErrorKind::UpdateModule(name.clone()))?;
+            Ok((core_spec, name, runtime, image_pulled))
+        })
+        .and_then(|(core_spec, name, runtime, image_pulled)| {
+            if image_pulled {
+                debug!("Successfully pulled new image for module {}", name)
+            } else {
+                debug!(
+                    "Skipped pulling image for module {} as per pull policy",
+                    name
+                )
+            }
+
+            runtime.remove(&name).then(|result| {
+                result.with_context(|_| ErrorKind::UpdateModule(name.clone()))?;
+                Ok((core_spec, name, runtime))
             })
-            .and_then(|(status, spec, name)| -> Result<_, Error> {
-                let details = spec_to_details(&spec, status);
-                let b = serde_json::to_string(&details)
-                    .with_context(|_| ErrorKind::UpdateModule(name.clone()))?;
-                let response = Response::builder()
-                    .status(StatusCode::OK)
-                    .header(CONTENT_TYPE, "application/json")
-                    .header(CONTENT_LENGTH, b.len().to_string().as_str())
-                    .body(b.into())
-                    .context(ErrorKind::UpdateModule(name))?;
-                Ok(response)
+        })
+        .and_then(|(core_spec, name, runtime)| {
+            debug!("Removed existing module {}", name);
+
+            runtime.create(core_spec).then(|result| {
+                result.with_context(|_| ErrorKind::UpdateModule(name.clone()))?;
+                Ok((name, runtime))
             })
-            .or_else(|e| Ok(e.into_response()));
+        })
+        .and_then(move |(name, runtime)| {
+            debug!("Created module {}", name);
+            if start {
+                info!("Starting module {}", name);
+                future::Either::A(runtime.start(&name).then(move |result| {
+                    result.with_context(|_| ErrorKind::UpdateModule(name.clone()))?;
+                    Ok(ModuleStatus::Running)
+                }))
+            } else {
+                future::Either::B(future::ok(ModuleStatus::Stopped))
+            }
+        })
+}
 
-        Box::new(response)
+fn details_response(
+    status_code: StatusCode,
+    spec: &ModuleSpec,
+    status: ModuleStatus,
+) -> Result<Response<Body>, Error> {
+    let details = spec_to_details(spec, status);
+    let b = serde_json::to_string(&details)
+        .with_context(|_| ErrorKind::UpdateModule(spec.name().clone()))?;
+    let response = Response::builder()
+        .status(status_code)
+        .header(CONTENT_TYPE, "application/json")
+        .header(CONTENT_LENGTH, b.len().to_string().as_str())
+        .body(b.into())
+        .with_context(|_| ErrorKind::UpdateModule(spec.name().clone()))?;
+    Ok(response)
+}
+
+// Works out what `handle` would do with the module without changing the runtime.
+fn plan_update<M>(
+    runtime: &M,
+    core_spec: &CoreModuleSpec<<M::Module as Module>::Config>,
+) -> impl Future<Item = Response<Body>, Error = Error>
+where
+    M: ModuleRuntime,
+    for<'r> &'r <M as ModuleRuntime>::Error: Into<ModuleRuntimeErrorReason>,
+    <M::Module as Module>::Config: Serialize,
+{
+    let name = core_spec.name().to_string();
+    info!("Checking update of module {}", name);
+
+    if let Err(err) = runtime.validate(core_spec) {
+        return Either::A(future::err(Error::from(
+            err.context(ErrorKind::UpdateModule(name)),
+        )));
     }
+
+    let desired = serde_json::to_value(core_spec.config());
+    Either::B(runtime.get(&name).then(move |result| {
+        let action = match result {
+            Ok((module, _)) => match (serde_json::to_value(module.config()), desired) {
+                (Ok(current), Ok(desired)) if current == desired => "no-op",
+                _ => "update",
+            },
+            Err(err) => match (&err).into() {
+                ModuleRuntimeErrorReason::NotFound => "create",
+                ModuleRuntimeErrorReason::Other => {
+                    return Err(Error::from(err.context(ErrorKind::UpdateModule(name))));
+                }
+            },
+        };
+
+        dry_run_response(name.clone(), action, || {
+            ErrorKind::UpdateModule(name.clone())
+        })
+    }))
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
+
     use chrono::prelude::*;
-    use edgelet_core::{MakeModuleRuntime, ModuleRuntimeState, ModuleStatus};
+    use edgelet_core::{
+        MaintenanceWindow, MakeModuleRuntime, ModuleRuntimeState, ModuleStatus,
+        CRITICAL_UPDATE_LABEL,
+    };
     use edgelet_http::route::Parameters;
     use edgelet_test_utils::crypto::TestHsm;
     use edgelet_test_utils::module::*;
     use lazy_static::lazy_static;
-    use management::models::{Config, ErrorResponse, ModuleDetails, ModuleSpec};
+    use management::models::{Config, DryRunResult, ErrorResponse, ModuleDetails, ModuleSpec};
     use serde_json::json;
 
     use super::*;
@@ -251,6 +405,60 @@ mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn dry_run_no_op() {
+        let handler = UpdateModule::new(RUNTIME.clone());
+        let config = Config::new(json!({"image":"microsoft/test-image"}));
+        let spec = ModuleSpec::new("test-module".to_string(), "docker".to_string(), config);
+        let request = Request::put("http://localhost/modules/test-module?dry_run=true")
+            .body(serde_json::to_string(&spec).unwrap().into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                let result: DryRunResult = serde_json::from_slice(&b).unwrap();
+                assert_eq!("test-module", result.modules()[0].name());
+                assert_eq!("no-op", result.modules()[0].action());
+                Ok(())
+            })
+            .wait()
+            .unwrap();
+    }
+
+    #[test]
+    fn dry_run_update() {
+        let handler = UpdateModule::new(RUNTIME.clone());
+        let config = Config::new(json!({"image":"microsoft/test-image:2.0"}));
+        let spec = ModuleSpec::new("test-module".to_string(), "docker".to_string(), config);
+        let request = Request::put("http://localhost/modules/test-module?dry_run=true")
+            .body(serde_json::to_string(&spec).unwrap().into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                let result: DryRunResult = serde_json::from_slice(&b).unwrap();
+                assert_eq!("test-module", result.modules()[0].name());
+                assert_eq!("update", result.modules()[0].action());
+                Ok(())
+            })
+            .wait()
+            .unwrap();
+    }
+
     #[test]
     fn bad_body() {
         let handler = UpdateModule::new(RUNTIME.clone());
@@ -378,4 +586,84 @@ mod tests {
             .wait()
             .unwrap();
     }
+
+    // A daily maintenance window of an hour that opens an hour from now.
+    fn closed_window() -> MaintenanceWindows {
+        let start = (Utc::now() + chrono::Duration::hours(1)).time();
+        MaintenanceWindows::new(
+            edgelet_core::TimeZone::Fixed(FixedOffset::east(0)),
+            vec![MaintenanceWindow::new(
+                vec![],
+                start,
+                start + chrono::Duration::hours(1),
+            )],
+        )
+    }
+
+    fn update_request(labels: HashMap<String, String>) -> Request<Body> {
+        let config = Config::new(json!({"image":"microsoft/test-image:2.0"}));
+        let spec = ModuleSpec::new("test-module".to_string(), "docker".to_string(), config)
+            .with_labels(labels);
+        Request::put("http://localhost/modules/test-module?start")
+            .body(serde_json::to_string(&spec).unwrap().into())
+            .unwrap()
+    }
+
+    #[test]
+    fn update_outside_of_maintenance_windows_is_queued() {
+        let runtime = TestRuntime::<Error, _>::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(Ok(TestModule::new(
+            "test-module".to_string(),
+            TestConfig::new("microsoft/test-image".to_string()),
+            Ok(ModuleRuntimeState::default()),
+        )));
+        let handler = UpdateModule::new(runtime.clone()).with_maintenance_windows(closed_window());
+        // The queued update is spawned on the runtime that handles the request.
+        let mut tokio_runtime = tokio::runtime::Runtime::new().unwrap();
+
+        let response = tokio_runtime
+            .block_on(handler.handle(update_request(HashMap::new()), Parameters::new()))
+            .unwrap();
+        assert_eq!(StatusCode::ACCEPTED, response.status());
+        let body = tokio_runtime
+            .block_on(response.into_body().concat2())
+            .unwrap();
+        let details: ModuleDetails = serde_json::from_slice(&body).unwrap();
+        assert_eq!("unknown", details.status().runtime_status().status());
+        assert!(runtime.operations().is_empty());
+
+        // A critical update is applied right away.
+        let mut labels = HashMap::new();
+        labels.insert(CRITICAL_UPDATE_LABEL.to_string(), "true".to_string());
+        let response = tokio_runtime
+            .block_on(handler.handle(update_request(labels), Parameters::new()))
+            .unwrap();
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(
+            vec![
+                "remove test-module",
+                "create test-module",
+                "start test-module"
+            ],
+            runtime.operations()
+        );
+    }
+
+    #[test]
+    fn only_the_latest_update_is_applied() {
+        let queued = QueuedUpdates::default();
+        let first = queued.push("test-module");
+        let other = queued.push("other-module");
+        let second = queued.push("test-module");
+
+        assert!(!queued.is_latest("test-module", first));
+        assert!(queued.is_latest("test-module", second));
+        assert!(queued.is_latest("other-module", other));
+    }
 }
@@ -0,0 +1,290 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Long-polls for a module to reach a status, so that a deployment script
+//! doesn't have to poll the module list. The request is answered with the
+//! module as soon as it has the status, or with `408 Request Timeout` and the
+//! module as it last was if it doesn't get it within `timeout`.
+
+use std::time::{Duration, Instant};
+
+use failure::{Fail, ResultExt};
+use futures::future::{self, Either, Loop};
+use futures::{Future, IntoFuture};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use serde::Serialize;
+use serde_json;
+use url::form_urlencoded;
+
+use edgelet_core::{
+    Module, ModuleEvents, ModuleRuntime, ModuleRuntimeState, ModuleStatus, RuntimeOperation,
+};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+use edgelet_utils::parse_duration;
+
+use super::core_to_details;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// How long a request waits if it doesn't say.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The longest a request waits.
+const MAX_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// How often the status is checked without an event. Only the changes that
+/// the daemon makes are published as events, so a module that exits or that
+/// the container runtime restarts is only seen by checking.
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+pub struct WaitModule<M> {
+    runtime: M,
+}
+
+impl<M> WaitModule<M> {
+    pub fn new(runtime: M) -> Self {
+        WaitModule { runtime }
+    }
+}
+
+impl<M> Handler<Parameters> for WaitModule<M>
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+    <M::Module as Module>::Config: Serialize,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let runtime = self.runtime.clone();
+        let events = self.runtime.events().clone();
+
+        let response = params
+            .name("name")
+            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+            .and_then(|name| {
+                let (status, timeout) = parse_options(req.uri().query().unwrap_or(""))?;
+                Ok((name.to_string(), status, timeout))
+            })
+            .into_future()
+            .and_then(move |(name, status, timeout)| {
+                let get_name = name.clone();
+                let get = move || {
+                    let name = get_name.clone();
+                    runtime.get(&name).then(|result| {
+                        result.map_err(|err| {
+                            Error::from(err.context(ErrorKind::RuntimeOperation(
+                                RuntimeOperation::GetModule(name),
+                            )))
+                        })
+                    })
+                };
+                wait_for_status(events, status, Instant::now() + timeout, get)
+                    .map(move |(reached, (module, state))| (name, reached, module, state))
+            })
+            .and_then(|(name, reached, module, state)| {
+                let context =
+                    || ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(name.clone()));
+                let details = core_to_details(&module, &state, context)?;
+                let body = serde_json::to_string(&details).with_context(|_| context())?;
+                let status = if reached {
+                    StatusCode::OK
+                } else {
+                    StatusCode::REQUEST_TIMEOUT
+                };
+                let response = Response::builder()
+                    .status(status)
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(CONTENT_LENGTH, body.len().to_string().as_str())
+                    .body(body.into())
+                    .with_context(|_| context())?;
+                Ok(response)
+            })
+            .or_else(|e| Ok(e.into_response()));
+
+        Box::new(response)
+    }
+}
+
+fn parse_options(query: &str) -> Result<(ModuleStatus, Duration), Error> {
+    let parse: Vec<_> = form_urlencoded::parse(query.as_bytes()).collect();
+    let status = parse
+        .iter()
+        .find(|&(ref key, _)| key == "state")
+        .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("state")))?
+        .1
+        .parse::<ModuleStatus>()
+        .context(ErrorKind::MalformedRequestParameter("state"))?;
+    let timeout = parse
+        .iter()
+        .find(|&(ref key, _)| key == "timeout")
+        .map_or(Ok(DEFAULT_TIMEOUT), |(_, val)| parse_duration(val))
+        .map_err(|_| Error::from(ErrorKind::MalformedRequestParameter("timeout")))?;
+    if timeout > MAX_TIMEOUT {
+        return Err(Error::from(ErrorKind::MalformedRequestParameter("timeout")));
+    }
+    Ok((status, timeout))
+}
+
+/// Gets the module with `get` until it has `status` or `deadline` passes,
+/// checking again whenever an event is published. Returns whether it got the
+/// status, with the module as it last was.
+fn wait_for_status<F, G, T>(
+    events: ModuleEvents,
+    status: ModuleStatus,
+    deadline: Instant,
+    get: F,
+) -> impl Future<Item = (bool, (T, ModuleRuntimeState)), Error = Error>
+where
+    F: Fn() -> G,
+    G: Future<Item = (T, ModuleRuntimeState), Error = Error>,
+{
+    // The events are waited for from before the module is got, so that a
+    // change that is published in between isn't missed.
+    future::loop_fn(events.next(), move |since| {
+        let events = events.clone();
+        get().and_then(move |(module, state)| {
+            if *state.status() == status {
+                return Either::A(future::ok(Loop::Break((true, (module, state)))));
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Either::A(future::ok(Loop::Break((false, (module, state)))));
+            }
+
+            let timeout = (deadline - now).min(CHECK_INTERVAL);
+            Either::B(
+                events
+                    .wait(since, timeout)
+                    .map(|events| Loop::Continue(events.next()))
+                    .map_err(|err| Error::from(err.context(ErrorKind::ModuleEvents))),
+            )
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    use futures::Stream;
+    use management::models::ModuleDetails;
+    use tokio::runtime::current_thread::Runtime;
+
+    use edgelet_core::{MakeModuleRuntime, ModuleLifecycleEventKind};
+    use edgelet_test_utils::crypto::TestHsm;
+    use edgelet_test_utils::module::*;
+
+    use super::*;
+    use crate::server::module::tests::Error as TestError;
+
+    fn runtime() -> TestRuntime<TestError, TestSettings> {
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<TestError, _> = TestModule::new(
+            "mod1".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default()),
+        );
+        TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(Ok(module))
+    }
+
+    fn get(query: &str) -> Response<Body> {
+        let handler = WaitModule::new(runtime());
+        let request = Request::get(format!(
+            "http://localhost/modules/mod1/wait?api-version=2019-11-05&{}",
+            query
+        ))
+        .body(Body::default())
+        .unwrap();
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "mod1".to_string())]);
+
+        Runtime::new()
+            .unwrap()
+            .block_on(handler.handle(request, parameters))
+            .unwrap()
+    }
+
+    fn status(response: Response<Body>) -> String {
+        let body = response.into_body().concat2().wait().unwrap();
+        let details: ModuleDetails = serde_json::from_slice(&body).unwrap();
+        details.status().runtime_status().status().to_string()
+    }
+
+    #[test]
+    fn returns_at_once_if_module_has_status() {
+        let started = Instant::now();
+
+        // The test runtime's module has the default, unknown status.
+        let response = get("state=unknown&timeout=60s");
+
+        assert_eq!(StatusCode::OK, response.status());
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!("unknown", status(response));
+    }
+
+    #[test]
+    fn times_out_if_module_does_not_get_status() {
+        let response = get("state=running&timeout=1s");
+
+        assert_eq!(StatusCode::REQUEST_TIMEOUT, response.status());
+        assert_eq!("unknown", status(response));
+    }
+
+    #[test]
+    fn returns_when_module_gets_status() {
+        let events = ModuleEvents::default();
+        let current = Arc::new(Mutex::new(ModuleStatus::Stopped));
+
+        let publisher = {
+            let events = events.clone();
+            let current = current.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(200));
+                *current.lock().unwrap() = ModuleStatus::Running;
+                events.publish("mod1", ModuleLifecycleEventKind::Started);
+            })
+        };
+
+        let started = Instant::now();
+        let (reached, ((), state)) = Runtime::new()
+            .unwrap()
+            .block_on(wait_for_status(
+                events,
+                ModuleStatus::Running,
+                Instant::now() + Duration::from_secs(60),
+                move || {
+                    let status = *current.lock().unwrap();
+                    future::ok(((), ModuleRuntimeState::default().with_status(status)))
+                },
+            ))
+            .unwrap();
+        publisher.join().unwrap();
+
+        assert!(reached);
+        assert_eq!(ModuleStatus::Running, *state.status());
+        // Woken by the event rather than by the next check.
+        assert!(started.elapsed() < CHECK_INTERVAL);
+    }
+
+    #[test]
+    fn missing_or_unknown_state_fails() {
+        assert_eq!(StatusCode::BAD_REQUEST, get("timeout=1s").status());
+        assert_eq!(StatusCode::BAD_REQUEST, get("state=sleeping").status());
+        assert_eq!(
+            StatusCode::BAD_REQUEST,
+            get("state=running&timeout=121s").status()
+        );
+    }
+}
@@ -0,0 +1,348 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Serves a zip of what support usually asks for: the recent logs and the
+//! details of every module, the host's system info and resources, and the
+//! daemon's settings with their secrets redacted.
+
+use std::io::{Cursor, Write};
+
+use failure::{Fail, ResultExt};
+use futures::{future, Future, IntoFuture, Stream};
+use hyper::header::{CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use serde::Serialize;
+use serde_json::{self, json};
+use url::form_urlencoded;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use edgelet_core::{
+    decode_logs_with_max_line_length, parse_since, redact_secrets, LogChunk, LogOptions, LogTail,
+    Module, ModuleRuntime,
+};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use super::module::core_to_details;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// The number of lines of each module's log that a bundle has, unless the
+/// request asks for a different `tail`.
+const DEFAULT_TAIL: u64 = 500;
+
+pub struct GetSupportBundle<M> {
+    runtime: M,
+    config: String,
+    max_line_length: usize,
+}
+
+impl<M> GetSupportBundle<M> {
+    /// `config` is the daemon's settings, which are redacted before they go
+    /// into a bundle. Lines of the logs are truncated to `max_line_length`
+    /// bytes.
+    pub fn new(runtime: M, config: String, max_line_length: usize) -> Self {
+        GetSupportBundle {
+            runtime,
+            config,
+            max_line_length,
+        }
+    }
+}
+
+impl<M> Handler<Parameters> for GetSupportBundle<M>
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+    <M::Module as Module>::Config: Serialize,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let runtime = self.runtime.clone();
+        let config = redact_secrets(&self.config);
+        let max_line_length = self.max_line_length;
+
+        let response = req
+            .uri()
+            .query()
+            .map_or_else(|| Ok(default_log_options()), parse_options)
+            .into_future()
+            .and_then(move |options| {
+                runtime
+                    .list_with_details()
+                    .collect()
+                    .map_err(|err| Error::from(err.context(ErrorKind::SupportBundle)))
+                    .and_then(move |modules| {
+                        let logs = future::join_all(
+                            modules
+                                .iter()
+                                .map(|(module, _)| {
+                                    module_log(&runtime, module.name(), &options, max_line_length)
+                                })
+                                .collect::<Vec<_>>(),
+                        );
+                        logs.join(host_info(&runtime))
+                            .map(move |(logs, host_info)| (modules, logs, host_info))
+                    })
+            })
+            .and_then(move |(modules, logs, host_info)| -> Result<_, Error> {
+                let mut bundle = Bundle::new();
+
+                for ((module, state), log) in modules.iter().zip(logs) {
+                    match log {
+                        Ok(log) => bundle.add(&format!("logs/{}_log.txt", module.name()), &log)?,
+                        Err(err) => bundle
+                            .add(&format!("logs/{}_err.txt", module.name()), err.as_bytes())?,
+                    }
+
+                    let details = core_to_details(module, state, || ErrorKind::SupportBundle)?;
+                    let details =
+                        serde_json::to_string_pretty(&details).context(ErrorKind::SupportBundle)?;
+                    bundle.add(
+                        &format!("inspect/{}.json", module.name()),
+                        redact_secrets(&details).as_bytes(),
+                    )?;
+                }
+
+                let host_info =
+                    serde_json::to_vec_pretty(&host_info).context(ErrorKind::SupportBundle)?;
+                bundle.add("host_info.json", &host_info)?;
+                bundle.add("config.json", config.as_bytes())?;
+
+                let body = bundle.finish()?;
+                let response = Response::builder()
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, "application/zip")
+                    .header(
+                        CONTENT_DISPOSITION,
+                        "attachment; filename=\"support_bundle.zip\"",
+                    )
+                    .header(CONTENT_LENGTH, body.len().to_string().as_str())
+                    .body(body.into())
+                    .context(ErrorKind::SupportBundle)?;
+                Ok(response)
+            })
+            .or_else(|e| Ok(e.into_response()));
+
+        Box::new(response)
+    }
+}
+
+struct Bundle {
+    writer: ZipWriter<Cursor<Vec<u8>>>,
+    options: FileOptions,
+}
+
+impl Bundle {
+    fn new() -> Self {
+        Bundle {
+            writer: ZipWriter::new(Cursor::new(Vec::new())),
+            options: FileOptions::default().compression_method(CompressionMethod::Deflated),
+        }
+    }
+
+    fn add(&mut self, name: &str, contents: &[u8]) -> Result<(), Error> {
+        self.writer
+            .start_file(name, self.options)
+            .context(ErrorKind::SupportBundle)?;
+        self.writer
+            .write_all(contents)
+            .context(ErrorKind::SupportBundle)?;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<Vec<u8>, Error> {
+        let writer = self.writer.finish().context(ErrorKind::SupportBundle)?;
+        Ok(writer.into_inner())
+    }
+}
+
+fn default_log_options() -> LogOptions {
+    LogOptions::new()
+        .with_follow(false)
+        .with_tail(LogTail::Num(DEFAULT_TAIL))
+}
+
+fn parse_options(query: &str) -> Result<LogOptions, Error> {
+    let parse: Vec<_> = form_urlencoded::parse(query.as_bytes()).collect();
+    let tail = parse
+        .iter()
+        .find(|&(ref key, _)| key == "tail")
+        .map_or_else(
+            || Ok(LogTail::Num(DEFAULT_TAIL)),
+            |(_, val)| val.parse::<LogTail>(),
+        )
+        .context(ErrorKind::MalformedRequestParameter("tail"))?;
+    let since = parse
+        .iter()
+        .find(|&(ref key, _)| key == "since")
+        .map_or_else(|| Ok(0), |(_, val)| parse_since(val))
+        .context(ErrorKind::MalformedRequestParameter("since"))?;
+    Ok(default_log_options().with_tail(tail).with_since(since))
+}
+
+// A module whose logs can't be read doesn't fail the bundle, its error goes
+// into the bundle instead.
+fn module_log<M>(
+    runtime: &M,
+    name: &str,
+    options: &LogOptions,
+    max_line_length: usize,
+) -> impl Future<Item = Result<Vec<u8>, String>, Error = Error>
+where
+    M: ModuleRuntime,
+{
+    runtime
+        .logs(name, options)
+        .map_err(|err| err.to_string())
+        .and_then(move |logs| {
+            decode_logs_with_max_line_length(logs, max_line_length)
+                .map_err(|err| err.to_string())
+                .fold(Vec::new(), |mut log, chunk| -> Result<_, String> {
+                    match chunk {
+                        LogChunk::Stdin(b)
+                        | LogChunk::Stdout(b)
+                        | LogChunk::Stderr(b)
+                        | LogChunk::Unknown(b) => log.extend_from_slice(&b),
+                    }
+                    Ok(log)
+                })
+        })
+        .then(Ok)
+}
+
+fn host_info<M>(runtime: &M) -> impl Future<Item = serde_json::Value, Error = Error>
+where
+    M: ModuleRuntime,
+{
+    let resources = runtime
+        .system_resources()
+        .then(|resources| Ok(resources.ok()));
+    runtime
+        .system_info()
+        .map_err(|err| Error::from(err.context(ErrorKind::SupportBundle)))
+        .join(resources)
+        .map(|(info, resources)| {
+            json!({
+                "osType": info.os_type(),
+                "architecture": info.architecture(),
+                "version": info.version(),
+                "resources": resources,
+            })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use edgelet_core::{
+        MakeModuleRuntime, ModuleRuntimeState, ModuleStatus, DEFAULT_MAX_LOG_LINE_LENGTH,
+    };
+    use edgelet_test_utils::crypto::TestHsm;
+    use edgelet_test_utils::module::*;
+    use management::models::ErrorResponse;
+    use zip::ZipArchive;
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    fn runtime() -> TestRuntime<Error, TestSettings> {
+        let logs = vec![
+            &[0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0d, b'R', b'o'][..],
+            &b"ses are"[..],
+            &[b' ', b'r', b'e', b'd', 0x02, 0x00][..],
+            &[0x00, 0x00, 0x00, 0x00, 0x00, 0x10][..],
+            &b"violets"[..],
+            &b" are blue"[..],
+        ];
+        let state = ModuleRuntimeState::default().with_status(ModuleStatus::Running);
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module = TestModule::new_with_logs("mod1".to_string(), config, Ok(state), logs);
+
+        TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(Ok(module))
+    }
+
+    fn read(archive: &mut ZipArchive<Cursor<Vec<u8>>>, name: &str) -> String {
+        let mut contents = String::new();
+        archive
+            .by_name(name)
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        contents
+    }
+
+    #[test]
+    fn bundle_has_logs_details_host_info_and_redacted_config() {
+        let config = r#"{"provisioning":{"source":"manual","device_connection_string":"HostName=hub;DeviceId=d1;SharedAccessKey=c2VjcmV0"}}"#;
+        let handler =
+            GetSupportBundle::new(runtime(), config.to_string(), DEFAULT_MAX_LOG_LINE_LENGTH);
+        let request =
+            Request::get("http://localhost/support-bundle?api-version=2019-11-05&tail=10")
+                .body(Body::default())
+                .unwrap();
+
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(
+            "application/zip",
+            *response.headers().get(CONTENT_TYPE).unwrap()
+        );
+        let body = response.into_body().concat2().wait().unwrap();
+        let mut archive = ZipArchive::new(Cursor::new(body.to_vec())).unwrap();
+
+        assert_eq!(
+            "Roses are redviolets are blue",
+            read(&mut archive, "logs/mod1_log.txt")
+        );
+
+        let details: serde_json::Value =
+            serde_json::from_str(&read(&mut archive, "inspect/mod1.json")).unwrap();
+        assert_eq!("mod1", details["name"]);
+        assert_eq!("running", details["status"]["runtimeStatus"]["status"]);
+
+        let host_info: serde_json::Value =
+            serde_json::from_str(&read(&mut archive, "host_info.json")).unwrap();
+        assert_eq!("os_type_sample", host_info["osType"]);
+        assert_eq!("architecture_sample", host_info["architecture"]);
+
+        assert_eq!(
+            r#"{"provisioning":{"source":"manual","device_connection_string":"***"}}"#,
+            read(&mut archive, "config.json")
+        );
+    }
+
+    #[test]
+    fn bad_tail_fails() {
+        let handler =
+            GetSupportBundle::new(runtime(), "{}".to_string(), DEFAULT_MAX_LOG_LINE_LENGTH);
+        let request =
+            Request::get("http://localhost/support-bundle?api-version=2019-11-05&tail=many")
+                .body(Body::default())
+                .unwrap();
+
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert!(
+            error
+                .message()
+                .starts_with("The request parameter `tail` is malformed"),
+            "{}",
+            error.message()
+        );
+    }
+}
@@ -8,7 +8,7 @@ use log::debug;
 use serde::Serialize;
 use serde_json;
 
-use edgelet_core::{Module, ModuleRuntime, RuntimeOperation};
+use edgelet_core::{Module, ModuleRuntime, RuntimeOperation, UpstreamEndpoints};
 use edgelet_http::route::{Handler, Parameters};
 use edgelet_http::Error as HttpError;
 use management::models::*;
@@ -18,11 +18,22 @@ use crate::IntoResponse;
 
 pub struct GetSystemInfo<M> {
     runtime: M,
+    upstream: Option<UpstreamEndpoints>,
 }
 
 impl<M> GetSystemInfo<M> {
     pub fn new(runtime: M) -> Self {
-        GetSystemInfo { runtime }
+        GetSystemInfo {
+            runtime,
+            upstream: None,
+        }
+    }
+
+    /// Reports whether `upstream` can be reached, past its offline grace
+    /// period.
+    pub fn with_upstream(mut self, upstream: UpstreamEndpoints) -> Self {
+        self.upstream = Some(upstream);
+        self
     }
 }
 
@@ -38,18 +49,37 @@ where
     ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
         debug!("Get System Information");
 
+        let upstream_reachable = self.upstream.as_ref().map(UpstreamEndpoints::reachable);
         let response = self
             .runtime
             .system_info()
-            .then(|system_info| -> Result<_, Error> {
+            .then(move |system_info| -> Result<_, Error> {
                 let system_info = system_info
                     .context(ErrorKind::RuntimeOperation(RuntimeOperation::SystemInfo))?;
 
-                let body = SystemInfo::new(
+                let mut body = SystemInfo::new(
                     system_info.os_type().to_string(),
                     system_info.architecture().to_string(),
                     system_info.version().to_string(),
                 );
+                if let Some(runtime_type) = system_info.runtime_type() {
+                    body.set_runtime_type(runtime_type.to_string());
+                }
+                if let Some(runtime_version) = system_info.runtime_version() {
+                    body.set_runtime_version(runtime_version.to_string());
+                }
+                if let Some(operating_system) = system_info.operating_system() {
+                    body.set_operating_system(operating_system.to_string());
+                }
+                if let Some(kernel_version) = system_info.kernel_version() {
+                    body.set_kernel_version(kernel_version.to_string());
+                }
+                if let Some(total_memory) = system_info.total_memory() {
+                    body.set_total_memory(total_memory);
+                }
+                if let Some(upstream_reachable) = upstream_reachable {
+                    body.set_upstream_reachable(upstream_reachable);
+                }
 
                 let b = serde_json::to_string(&body)
                     .context(ErrorKind::RuntimeOperation(RuntimeOperation::SystemInfo))?;
@@ -70,7 +100,7 @@ where
 
 #[cfg(test)]
 mod tests {
-    use edgelet_core::{self, MakeModuleRuntime, ModuleRuntimeState};
+    use edgelet_core::{self, MakeModuleRuntime, ModuleRuntimeState, UpstreamSettings};
     use edgelet_http::route::Parameters;
     use edgelet_test_utils::crypto::TestHsm;
     use edgelet_test_utils::module::*;
@@ -118,6 +148,18 @@ mod tests {
                     edgelet_core::version_with_source_version(),
                     system_info.version(),
                 );
+                assert_eq!(Some("runtime_type_sample"), system_info.runtime_type());
+                assert_eq!(
+                    Some("runtime_version_sample"),
+                    system_info.runtime_version()
+                );
+                assert_eq!(
+                    Some("operating_system_sample"),
+                    system_info.operating_system()
+                );
+                assert_eq!(Some("kernel_version_sample"), system_info.kernel_version());
+                assert_eq!(Some(1024), system_info.total_memory());
+                assert_eq!(None, system_info.upstream_reachable());
 
                 Ok(())
             })
@@ -125,6 +167,41 @@ mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn system_info_reports_whether_upstream_is_reachable() {
+        let runtime = TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(Ok(TestModule::<Error, _>::new(
+            "test-module".to_string(),
+            TestConfig::new("microsoft/test-image".to_string()),
+            Ok(ModuleRuntimeState::default()),
+        )));
+        let upstream = UpstreamEndpoints::new(
+            "primary.azure-devices.net".to_string(),
+            &UpstreamSettings::default(),
+        );
+        let handler = GetSystemInfo::new(runtime).with_upstream(upstream.clone());
+        let upstream_reachable = || {
+            let request = Request::get("http://localhost/systeminfo")
+                .body(Body::default())
+                .unwrap();
+            let response = handler.handle(request, Parameters::new()).wait().unwrap();
+            let body = response.into_body().concat2().wait().unwrap();
+            serde_json::from_slice::<SystemInfo>(&body)
+                .unwrap()
+                .upstream_reachable()
+        };
+
+        assert_eq!(Some(true), upstream_reachable());
+        upstream.report_failure();
+        assert_eq!(Some(false), upstream_reachable());
+    }
+
     #[test]
     fn system_info_failed() {
         // arrange
@@ -0,0 +1,175 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Quiesces the modules before the daemon's package is upgraded, and resumes
+//! supervising them if the upgrade is called off. The daemon that starts
+//! after an upgrade resumes by itself.
+
+use failure::{Fail, ResultExt};
+use futures::Future;
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use log::debug;
+use serde::Serialize;
+use serde_json;
+
+use edgelet_core::{ModuleRuntime, UpgradeGuard};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+pub struct PrepareUpgrade<M> {
+    runtime: M,
+    upgrade: UpgradeGuard,
+}
+
+impl<M> PrepareUpgrade<M> {
+    pub fn new(runtime: M, upgrade: UpgradeGuard) -> Self {
+        PrepareUpgrade { runtime, upgrade }
+    }
+}
+
+impl<M> Handler<Parameters> for PrepareUpgrade<M>
+where
+    M: 'static + ModuleRuntime + Send,
+{
+    fn handle(
+        &self,
+        _req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        debug!("Prepare upgrade");
+        let response = self
+            .upgrade
+            .prepare(&self.runtime)
+            .map_err(|err| Error::from(err.context(ErrorKind::PrepareUpgrade)))
+            .and_then(|state| json_response(&state, || ErrorKind::PrepareUpgrade))
+            .or_else(|e| Ok(e.into_response()));
+
+        Box::new(response)
+    }
+}
+
+pub struct ResumeFromUpgrade<M> {
+    runtime: M,
+    upgrade: UpgradeGuard,
+}
+
+impl<M> ResumeFromUpgrade<M> {
+    pub fn new(runtime: M, upgrade: UpgradeGuard) -> Self {
+        ResumeFromUpgrade { runtime, upgrade }
+    }
+}
+
+impl<M> Handler<Parameters> for ResumeFromUpgrade<M>
+where
+    M: 'static + ModuleRuntime + Send,
+{
+    fn handle(
+        &self,
+        _req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        debug!("Resume from upgrade");
+        let response = self
+            .upgrade
+            .resume(&self.runtime)
+            .map_err(|err| Error::from(err.context(ErrorKind::ResumeFromUpgrade)))
+            .and_then(|adopted| {
+                json_response(&serde_json::json!({ "adopted": adopted }), || {
+                    ErrorKind::ResumeFromUpgrade
+                })
+            })
+            .or_else(|e| Ok(e.into_response()));
+
+        Box::new(response)
+    }
+}
+
+fn json_response<T, K>(body: &T, kind: K) -> Result<Response<Body>, Error>
+where
+    T: Serialize,
+    K: Fn() -> ErrorKind,
+{
+    let b = serde_json::to_string(body).with_context(|_| kind())?;
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
+        .header(CONTENT_LENGTH, b.len().to_string().as_str())
+        .body(b.into())
+        .with_context(|_| kind())?;
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::Stream;
+    use management::models::ErrorResponse;
+    use serde_json::Value;
+
+    use edgelet_core::{MakeModuleRuntime, ModuleRuntimeState, ModuleStatus};
+    use edgelet_test_utils::crypto::TestHsm;
+    use edgelet_test_utils::module::*;
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    fn runtime() -> TestRuntime<Error, TestSettings> {
+        let state = ModuleRuntimeState::default().with_status(ModuleStatus::Running);
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error, _> =
+            TestModule::new("test-module".to_string(), config, Ok(state));
+        TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(Ok(module))
+    }
+
+    fn request(path: &str) -> Request<Body> {
+        Request::post(format!("http://localhost{}?api-version=2019-11-05", path))
+            .body(Body::default())
+            .unwrap()
+    }
+
+    #[test]
+    fn prepare_upgrade_fails_when_state_cannot_be_saved() {
+        let upgrade = UpgradeGuard::new("/nonexistent/upgrade_state");
+        let handler = PrepareUpgrade::new(runtime(), upgrade.clone());
+
+        let response = handler
+            .handle(request("/prepare-upgrade"), Parameters::new())
+            .wait()
+            .unwrap();
+
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert!(error
+            .message()
+            .starts_with("Could not prepare for the upgrade"));
+        // the modules keep being supervised
+        assert!(!upgrade.is_quiesced());
+    }
+
+    #[test]
+    fn resume_without_prepared_upgrade_adopts_nothing() {
+        let upgrade = UpgradeGuard::new("/nonexistent/upgrade_state");
+        let handler = ResumeFromUpgrade::new(runtime(), upgrade.clone());
+
+        let response = handler
+            .handle(request("/resume"), Parameters::new())
+            .wait()
+            .unwrap();
+
+        assert_eq!(StatusCode::OK, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let resumed: Value = serde_json::from_slice(&body).unwrap();
+        assert!(resumed["adopted"].as_array().unwrap().is_empty());
+        assert!(!upgrade.is_quiesced());
+    }
+}
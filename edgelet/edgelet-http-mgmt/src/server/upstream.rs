@@ -0,0 +1,100 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Which IoT Hub the daemon is connected to, when it can fail over to others.
+
+use failure::ResultExt;
+use futures::{Future, IntoFuture};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use log::debug;
+use serde_json;
+
+use edgelet_core::UpstreamEndpoints;
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+pub struct GetUpstream {
+    upstream: UpstreamEndpoints,
+}
+
+impl GetUpstream {
+    pub fn new(upstream: UpstreamEndpoints) -> Self {
+        GetUpstream { upstream }
+    }
+}
+
+impl Handler<Parameters> for GetUpstream {
+    fn handle(
+        &self,
+        _req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        debug!("Get upstream");
+        let response = upstream_response(&self.upstream)
+            .into_future()
+            .or_else(|e| Ok(e.into_response()));
+
+        Box::new(response)
+    }
+}
+
+fn upstream_response(upstream: &UpstreamEndpoints) -> Result<Response<Body>, Error> {
+    let body = serde_json::json!({
+        "active": upstream.active(),
+        "hostnames": upstream.hostnames(),
+    });
+    let b = serde_json::to_string(&body).context(ErrorKind::GetUpstream)?;
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
+        .header(CONTENT_LENGTH, b.len().to_string().as_str())
+        .body(b.into())
+        .context(ErrorKind::GetUpstream)?;
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::Stream;
+    use serde_json::{json, Value};
+
+    use edgelet_core::UpstreamSettings;
+
+    use super::*;
+
+    #[test]
+    fn reports_active_hub() {
+        let upstream = UpstreamEndpoints::new(
+            "primary.azure-devices.net".to_string(),
+            &UpstreamSettings::new(
+                vec!["secondary.azure-devices.net".to_string()],
+                1,
+                Duration::from_secs(60),
+                Duration::from_secs(600),
+            ),
+        );
+        upstream.report_failure();
+        let handler = GetUpstream::new(upstream);
+        let request = Request::get("http://localhost/upstream?api-version=2019-11-05")
+            .body(Body::default())
+            .unwrap();
+
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        assert_eq!(StatusCode::OK, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let upstream: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            json!({
+                "active": "secondary.azure-devices.net",
+                "hostnames": ["primary.azure-devices.net", "secondary.azure-devices.net"],
+            }),
+            upstream
+        );
+    }
+}
@@ -0,0 +1,147 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! The version of the management API and the features that the daemon
+//! serves, so that tools that work with several versions of the daemon can
+//! check for a feature before they use it.
+
+use failure::ResultExt;
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Method, Response, StatusCode};
+use serde_json;
+
+use edgelet_http::API_VERSION;
+
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// A feature that clients can check for, and a request that uses it. A
+/// feature is advertised if the daemon would serve its request, so that the
+/// list follows the routes that were compiled in and the ones that are
+/// enabled in the settings.
+pub(crate) struct Feature {
+    pub(crate) name: &'static str,
+    pub(crate) method: Method,
+    pub(crate) path: &'static str,
+}
+
+pub(crate) const FEATURES: &[Feature] = &[
+    Feature {
+        name: "deployment",
+        method: Method::PUT,
+        path: "/deployment",
+    },
+    Feature {
+        name: "diff",
+        method: Method::POST,
+        path: "/diff",
+    },
+    Feature {
+        name: "effective_spec",
+        method: Method::GET,
+        path: "/modules/_/effective-spec",
+    },
+    Feature {
+        name: "events",
+        method: Method::GET,
+        path: "/events",
+    },
+    Feature {
+        name: "exec",
+        method: Method::POST,
+        path: "/modules/_/exec",
+    },
+    Feature {
+        name: "force_remove",
+        method: Method::DELETE,
+        path: "/modules/_",
+    },
+    Feature {
+        name: "health",
+        method: Method::GET,
+        path: "/health/ready",
+    },
+    Feature {
+        name: "image_prune",
+        method: Method::POST,
+        path: "/images/prune",
+    },
+    Feature {
+        name: "metrics",
+        method: Method::GET,
+        path: "/metrics",
+    },
+    Feature {
+        name: "module_stats",
+        method: Method::GET,
+        path: "/modules/_/stats",
+    },
+    Feature {
+        name: "patch_module",
+        method: Method::PATCH,
+        path: "/modules/_",
+    },
+    Feature {
+        name: "reload",
+        method: Method::POST,
+        path: "/reload",
+    },
+    Feature {
+        name: "restart_endpoint",
+        method: Method::POST,
+        path: "/modules/restart",
+    },
+    Feature {
+        name: "resume_module",
+        method: Method::POST,
+        path: "/modules/_/resume",
+    },
+    Feature {
+        name: "support_bundle",
+        method: Method::GET,
+        path: "/support-bundle",
+    },
+    Feature {
+        name: "system_resources",
+        method: Method::GET,
+        path: "/systeminfo/resources",
+    },
+    Feature {
+        name: "upgrade",
+        method: Method::POST,
+        path: "/prepare-upgrade",
+    },
+    Feature {
+        name: "upstream",
+        method: Method::GET,
+        path: "/upstream",
+    },
+    Feature {
+        name: "wait_for_state",
+        method: Method::GET,
+        path: "/modules/_/wait",
+    },
+    Feature {
+        name: "watchdog_pause",
+        method: Method::POST,
+        path: "/watchdog/pause",
+    },
+];
+
+pub(crate) fn version_response(features: &[&str]) -> Response<Body> {
+    response(features).unwrap_or_else(IntoResponse::into_response)
+}
+
+fn response(features: &[&str]) -> Result<Response<Body>, Error> {
+    let body = serde_json::json!({
+        "apiVersion": API_VERSION.to_string(),
+        "features": features,
+    });
+    let b = serde_json::to_string(&body).context(ErrorKind::GetVersion)?;
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
+        .header(CONTENT_LENGTH, b.len().to_string().as_str())
+        .body(b.into())
+        .context(ErrorKind::GetVersion)?;
+    Ok(response)
+}
@@ -0,0 +1,281 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Pauses the watchdog so that maintenance tooling can work on the edge
+//! runtime module by hand without the watchdog undoing it, and resumes it. A
+//! pause lasts `timeout` seconds unless the watchdog is resumed before.
+//!
+//! A module that the watchdog paused after it kept failing is resumed by
+//! itself, and stays paused until it is.
+
+use std::time::Duration;
+
+use failure::ResultExt;
+use futures::{Future, IntoFuture};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use log::debug;
+use serde_json;
+use url::form_urlencoded;
+
+use edgelet_core::watchdog::WatchdogPause;
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// How long a pause lasts if the request doesn't say.
+const DEFAULT_TIMEOUT_SECS: u64 = 600;
+
+/// The longest a pause can last.
+const MAX_TIMEOUT_SECS: u64 = 86400;
+
+pub struct PauseWatchdog {
+    pause: WatchdogPause,
+}
+
+impl PauseWatchdog {
+    pub fn new(pause: WatchdogPause) -> Self {
+        PauseWatchdog { pause }
+    }
+}
+
+impl Handler<Parameters> for PauseWatchdog {
+    fn handle(
+        &self,
+        req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        debug!("Pause watchdog");
+        let pause = self.pause.clone();
+        let response = parse_timeout(req.uri().query().unwrap_or(""))
+            .into_future()
+            .and_then(move |timeout| {
+                pause.pause(timeout);
+                pause_response(&pause, || ErrorKind::PauseWatchdog)
+            })
+            .or_else(|e| Ok(e.into_response()));
+
+        Box::new(response)
+    }
+}
+
+pub struct ResumeWatchdog {
+    pause: WatchdogPause,
+}
+
+impl ResumeWatchdog {
+    pub fn new(pause: WatchdogPause) -> Self {
+        ResumeWatchdog { pause }
+    }
+}
+
+impl Handler<Parameters> for ResumeWatchdog {
+    fn handle(
+        &self,
+        _req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        debug!("Resume watchdog");
+        self.pause.resume();
+        let response = pause_response(&self.pause, || ErrorKind::ResumeWatchdog)
+            .into_future()
+            .or_else(|e| Ok(e.into_response()));
+
+        Box::new(response)
+    }
+}
+
+pub struct ResumeModule {
+    pause: WatchdogPause,
+}
+
+impl ResumeModule {
+    pub fn new(pause: WatchdogPause) -> Self {
+        ResumeModule { pause }
+    }
+}
+
+impl Handler<Parameters> for ResumeModule {
+    fn handle(
+        &self,
+        _req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let pause = self.pause.clone();
+        let response = params
+            .name("name")
+            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+            .and_then(|name| {
+                debug!("Resume module {}", name);
+                // A module that isn't paused is already being retried.
+                let status = if pause.resume_module(name) {
+                    StatusCode::NO_CONTENT
+                } else {
+                    StatusCode::NOT_MODIFIED
+                };
+                let response = Response::builder()
+                    .status(status)
+                    .body(Body::default())
+                    .with_context(|_| ErrorKind::ResumeModule(name.to_string()))?;
+                Ok(response)
+            })
+            .into_future()
+            .or_else(|e| Ok(e.into_response()));
+
+        Box::new(response)
+    }
+}
+
+fn parse_timeout(query: &str) -> Result<Duration, Error> {
+    let timeout = form_urlencoded::parse(query.as_bytes())
+        .find(|&(ref key, _)| key == "timeout")
+        .map_or_else(|| Ok(DEFAULT_TIMEOUT_SECS), |(_, val)| val.parse::<u64>())
+        .context(ErrorKind::MalformedRequestParameter("timeout"))?;
+    if timeout == 0 || timeout > MAX_TIMEOUT_SECS {
+        return Err(Error::from(ErrorKind::MalformedRequestParameter("timeout")));
+    }
+    Ok(Duration::from_secs(timeout))
+}
+
+// Whether the watchdog is paused, and for how many more seconds.
+fn pause_response<K>(pause: &WatchdogPause, kind: K) -> Result<Response<Body>, Error>
+where
+    K: Fn() -> ErrorKind,
+{
+    let remaining = pause.remaining();
+    let body = serde_json::json!({
+        "paused": remaining.is_some(),
+        "resumeInSecs": remaining.map(|remaining| remaining.as_secs()),
+    });
+    let b = serde_json::to_string(&body).with_context(|_| kind())?;
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
+        .header(CONTENT_LENGTH, b.len().to_string().as_str())
+        .body(b.into())
+        .with_context(|_| kind())?;
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::Stream;
+    use management::models::ErrorResponse;
+    use serde_json::Value;
+
+    use super::*;
+
+    fn request(path: &str, query: &str) -> Request<Body> {
+        Request::post(format!(
+            "http://localhost{}?api-version=2019-11-05&{}",
+            path, query
+        ))
+        .body(Body::default())
+        .unwrap()
+    }
+
+    fn body(response: Response<Body>) -> Value {
+        let body = response.into_body().concat2().wait().unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[test]
+    fn pause_lasts_for_timeout() {
+        let pause = WatchdogPause::new();
+        let handler = PauseWatchdog::new(pause.clone());
+
+        let response = handler
+            .handle(request("/watchdog/pause", "timeout=300"), Parameters::new())
+            .wait()
+            .unwrap();
+
+        assert_eq!(StatusCode::OK, response.status());
+        let paused = body(response);
+        assert_eq!(true, paused["paused"]);
+        assert!(paused["resumeInSecs"].as_u64().unwrap() <= 300);
+        assert!(pause.remaining().unwrap() > Duration::from_secs(290));
+    }
+
+    #[test]
+    fn pause_without_timeout_uses_default() {
+        let pause = WatchdogPause::new();
+        let handler = PauseWatchdog::new(pause.clone());
+
+        let response = handler
+            .handle(request("/watchdog/pause", ""), Parameters::new())
+            .wait()
+            .unwrap();
+
+        assert_eq!(StatusCode::OK, response.status());
+        assert!(pause.remaining().unwrap() > Duration::from_secs(DEFAULT_TIMEOUT_SECS - 10));
+    }
+
+    #[test]
+    fn pause_with_bad_timeout_fails() {
+        let pause = WatchdogPause::new();
+        let handler = PauseWatchdog::new(pause.clone());
+
+        for query in &["timeout=0", "timeout=86401", "timeout=soon"] {
+            let response = handler
+                .handle(request("/watchdog/pause", query), Parameters::new())
+                .wait()
+                .unwrap();
+
+            assert_eq!(StatusCode::BAD_REQUEST, response.status());
+            let body = response.into_body().concat2().wait().unwrap();
+            let error: ErrorResponse = serde_json::from_slice(&body).unwrap();
+            // A timeout that isn't a number also has the parse error as its cause.
+            assert_eq!(
+                Some("The request parameter `timeout` is malformed"),
+                error.message().lines().next()
+            );
+            assert!(!pause.is_paused());
+        }
+    }
+
+    #[test]
+    fn resume_ends_pause() {
+        let pause = WatchdogPause::new();
+        pause.pause(Duration::from_secs(300));
+        let handler = ResumeWatchdog::new(pause.clone());
+
+        let response = handler
+            .handle(request("/watchdog/resume", ""), Parameters::new())
+            .wait()
+            .unwrap();
+
+        assert_eq!(StatusCode::OK, response.status());
+        let resumed = body(response);
+        assert_eq!(false, resumed["paused"]);
+        assert!(resumed["resumeInSecs"].is_null());
+        assert!(!pause.is_paused());
+    }
+
+    #[test]
+    fn resume_module_retries_only_that_module() {
+        let pause = WatchdogPause::new();
+        pause.pause_module("edgeAgent");
+        pause.pause_module("tempSensor");
+        let handler = ResumeModule::new(pause.clone());
+        let resume = |name: &str| {
+            let parameters =
+                Parameters::with_captures(vec![(Some("name".to_string()), name.to_string())]);
+            handler
+                .handle(
+                    request(&format!("/modules/{}/resume", name), ""),
+                    parameters,
+                )
+                .wait()
+                .unwrap()
+                .status()
+        };
+
+        assert_eq!(StatusCode::NO_CONTENT, resume("edgeAgent"));
+        assert!(!pause.is_module_paused("edgeAgent"));
+        assert!(pause.is_module_paused("tempSensor"));
+
+        // resuming it again changes nothing
+        assert_eq!(StatusCode::NOT_MODIFIED, resume("edgeAgent"));
+    }
+}
@@ -0,0 +1,327 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use log::{debug, warn};
+use openssl::nid::Nid;
+use openssl::x509::{X509Ref, X509};
+
+use edgelet_core::{Certificate, CreateCertificate, DEFAULT_CERT_EXPIRY_WARNING_DAYS};
+
+/// The expiry of one certificate in the chain of a certificate managed by the
+/// workload service.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CertExpiryInfo {
+    alias: String,
+    subject: String,
+    not_after: DateTime<Utc>,
+    days_remaining: i64,
+}
+
+impl CertExpiryInfo {
+    /// The alias of the managed certificate whose chain contains this certificate.
+    pub fn alias(&self) -> &str {
+        &self.alias
+    }
+
+    /// The common name of the certificate.
+    pub fn subject(&self) -> &str {
+        &self.subject
+    }
+
+    pub fn not_after(&self) -> DateTime<Utc> {
+        self.not_after
+    }
+
+    /// Whole days until the certificate expires. Negative once it has expired.
+    pub fn days_remaining(&self) -> i64 {
+        self.days_remaining
+    }
+}
+
+/// Inspects the certificates with the given aliases, including every
+/// certificate in their chains, and reports the ones that are about to expire.
+#[derive(Clone)]
+pub struct CertExpiryMonitor<H> {
+    hsm: H,
+    aliases: Vec<String>,
+    warning_window: Duration,
+}
+
+impl<H> CertExpiryMonitor<H>
+where
+    H: CreateCertificate,
+{
+    pub fn new(hsm: H, aliases: Vec<String>) -> Self {
+        CertExpiryMonitor {
+            hsm,
+            aliases,
+            warning_window: Duration::days(i64::from(DEFAULT_CERT_EXPIRY_WARNING_DAYS)),
+        }
+    }
+
+    pub fn with_warning_window(mut self, warning_window: Duration) -> Self {
+        self.warning_window = warning_window;
+        self
+    }
+
+    pub fn warning_window(&self) -> Duration {
+        self.warning_window
+    }
+
+    /// The expiry of every certificate in the chains of the monitored
+    /// certificates. A certificate that appears in more than one chain is only
+    /// reported once. Certificates that can't be read are logged and skipped.
+    pub fn cert_expiry_status(&self) -> Vec<CertExpiryInfo> {
+        self.cert_expiry_status_at(Utc::now())
+    }
+
+    /// Logs a warning for every certificate that expires within the warning
+    /// window and returns those certificates.
+    pub fn check(&self) -> Vec<CertExpiryInfo> {
+        let expiring: Vec<_> = self
+            .cert_expiry_status()
+            .into_iter()
+            .filter(|info| info.not_after - Utc::now() <= self.warning_window)
+            .collect();
+
+        for info in &expiring {
+            if info.days_remaining < 0 {
+                warn!(
+                    "Certificate {} in the chain of {} expired on {}",
+                    info.subject, info.alias, info.not_after
+                );
+            } else {
+                warn!(
+                    "Certificate {} in the chain of {} expires on {} ({} days remaining)",
+                    info.subject, info.alias, info.not_after, info.days_remaining
+                );
+            }
+        }
+
+        expiring
+    }
+
+    fn cert_expiry_status_at(&self, now: DateTime<Utc>) -> Vec<CertExpiryInfo> {
+        let mut status: Vec<CertExpiryInfo> = vec![];
+
+        for alias in &self.aliases {
+            let chain = match self.read_chain(alias) {
+                Ok(chain) => chain,
+                Err(err) => {
+                    warn!(
+                        "Could not inspect certificate {} for expiry: {}",
+                        alias, err
+                    );
+                    continue;
+                }
+            };
+
+            for (subject, not_after) in chain {
+                if status
+                    .iter()
+                    .any(|info| info.subject == subject && info.not_after == not_after)
+                {
+                    continue;
+                }
+
+                status.push(CertExpiryInfo {
+                    alias: alias.clone(),
+                    subject,
+                    not_after,
+                    days_remaining: (not_after - now).num_days(),
+                });
+            }
+        }
+
+        status
+    }
+
+    fn read_chain(&self, alias: &str) -> Result<Vec<(String, DateTime<Utc>)>, String> {
+        let cert = self
+            .hsm
+            .get_certificate(alias.to_string())
+            .map_err(|err| err.to_string())?;
+        let pem = cert.pem().map_err(|err| err.to_string())?;
+        let chain = X509::stack_from_pem(pem.as_ref()).map_err(|err| err.to_string())?;
+        debug!(
+            "Certificate {} has {} certificates in its chain",
+            alias,
+            chain.len()
+        );
+
+        chain
+            .iter()
+            .map(|cert| Ok((common_name(cert), not_after(cert)?)))
+            .collect()
+    }
+}
+
+fn common_name(cert: &X509Ref) -> String {
+    cert.subject_name()
+        .entries_by_nid(Nid::COMMONNAME)
+        .next()
+        .and_then(|entry| entry.data().as_utf8().ok())
+        .map_or_else(String::new, |name| name.to_string())
+}
+
+// OpenSSL prints ASN.1 times as e.g. "Nov  5 00:00:00 2019 GMT".
+fn not_after(cert: &X509Ref) -> Result<DateTime<Utc>, String> {
+    let not_after = cert.not_after().to_string();
+    let normalized = not_after.split_whitespace().collect::<Vec<_>>().join(" ");
+    NaiveDateTime::parse_from_str(&normalized, "%b %d %H:%M:%S %Y GMT")
+        .map(|not_after| DateTime::from_utc(not_after, Utc))
+        .map_err(|err| format!("invalid expiry {:?}: {}", not_after, err))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::collections::HashMap;
+
+    use edgelet_core::{CertificateProperties, Error as CoreError, ErrorKind as CoreErrorKind};
+    use edgelet_test_utils::cert::TestCert;
+    use openssl::asn1::Asn1Time;
+    use openssl::hash::MessageDigest;
+    use openssl::pkey::PKey;
+    use openssl::rsa::Rsa;
+    use openssl::x509::{X509Builder, X509NameBuilder};
+
+    use super::*;
+
+    pub(crate) fn make_cert(common_name: &str, days: u32) -> Vec<u8> {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_nid(Nid::COMMONNAME, common_name)
+            .unwrap();
+        let name = name.build();
+
+        let mut builder = X509Builder::new().unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder.set_issuer_name(&name).unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder
+            .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&Asn1Time::days_from_now(days).unwrap())
+            .unwrap();
+        builder.sign(&key, MessageDigest::sha256()).unwrap();
+        builder.build().to_pem().unwrap()
+    }
+
+    #[derive(Clone, Default)]
+    pub(crate) struct TestHsm {
+        certs: HashMap<String, Vec<u8>>,
+    }
+
+    impl TestHsm {
+        pub(crate) fn with_cert(mut self, alias: &str, chain: &[Vec<u8>]) -> Self {
+            self.certs.insert(alias.to_string(), chain.concat());
+            self
+        }
+    }
+
+    impl CreateCertificate for TestHsm {
+        type Certificate = TestCert;
+
+        fn create_certificate(
+            &self,
+            _properties: &CertificateProperties,
+        ) -> Result<Self::Certificate, CoreError> {
+            unimplemented!()
+        }
+
+        fn destroy_certificate(&self, _alias: String) -> Result<(), CoreError> {
+            unimplemented!()
+        }
+
+        fn get_certificate(&self, alias: String) -> Result<Self::Certificate, CoreError> {
+            self.certs
+                .get(&alias)
+                .map(|pem| TestCert::default().with_cert(pem.clone()))
+                .ok_or_else(|| CoreError::from(CoreErrorKind::KeyStore))
+        }
+    }
+
+    #[test]
+    fn status_includes_full_chain() {
+        let device_ca = make_cert("device-ca", 3);
+        let hsm = TestHsm::default()
+            .with_cert(
+                "workload-ca",
+                &[make_cert("workload-ca", 30), device_ca.clone()],
+            )
+            .with_cert("iotedge-tls", &[make_cert("iotedged", 60), device_ca]);
+        let monitor = CertExpiryMonitor::new(
+            hsm,
+            vec!["workload-ca".to_string(), "iotedge-tls".to_string()],
+        );
+
+        // a second in, so that each certificate has a partial day left
+        let status = monitor.cert_expiry_status_at(Utc::now() + Duration::seconds(1));
+
+        let summary: Vec<_> = status
+            .iter()
+            .map(|info| (info.alias(), info.subject(), info.days_remaining()))
+            .collect();
+        // the device CA is shared by both chains and is only reported once
+        assert_eq!(
+            vec![
+                ("workload-ca", "workload-ca", 29),
+                ("workload-ca", "device-ca", 2),
+                ("iotedge-tls", "iotedged", 59),
+            ],
+            summary
+        );
+    }
+
+    #[test]
+    fn days_remaining_is_negative_after_expiry() {
+        let hsm = TestHsm::default().with_cert("workload-ca", &[make_cert("workload-ca", 1)]);
+        let monitor = CertExpiryMonitor::new(hsm, vec!["workload-ca".to_string()]);
+
+        let status = monitor.cert_expiry_status_at(Utc::now() + Duration::days(3));
+
+        assert_eq!(1, status.len());
+        assert!(status[0].days_remaining() < 0);
+    }
+
+    #[test]
+    fn check_reports_certs_within_warning_window() {
+        let hsm = TestHsm::default().with_cert(
+            "workload-ca",
+            &[make_cert("workload-ca", 30), make_cert("device-ca", 3)],
+        );
+
+        let monitor = CertExpiryMonitor::new(hsm.clone(), vec!["workload-ca".to_string()]);
+        let expiring: Vec<_> = monitor
+            .check()
+            .into_iter()
+            .map(|info| info.subject().to_string())
+            .collect();
+        assert_eq!(vec!["device-ca".to_string()], expiring);
+
+        let monitor = CertExpiryMonitor::new(hsm, vec!["workload-ca".to_string()])
+            .with_warning_window(Duration::days(1));
+        assert!(monitor.check().is_empty());
+    }
+
+    #[test]
+    fn unreadable_certs_are_skipped() {
+        let hsm = TestHsm::default()
+            .with_cert("workload-ca", &[make_cert("workload-ca", 30)])
+            .with_cert("garbage", &[b"not a certificate".to_vec()]);
+        let monitor = CertExpiryMonitor::new(
+            hsm,
+            vec![
+                "missing".to_string(),
+                "garbage".to_string(),
+                "workload-ca".to_string(),
+            ],
+        );
+
+        let status = monitor.cert_expiry_status();
+        assert_eq!(1, status.len());
+        assert_eq!("workload-ca", status[0].alias());
+    }
+}
@@ -0,0 +1,381 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use chrono::{DateTime, Duration, Utc};
+use failure::{Fail, ResultExt};
+use log::{debug, info, warn};
+use serde_json;
+
+use edgelet_core::{
+    Certificate, CertificateProperties, CreateCertificate,
+    DEFAULT_SERVER_CERT_RENEWAL_THRESHOLD_PERCENT,
+};
+
+use crate::error::{CertOperation, ErrorKind, Result};
+use crate::server::cert_to_response;
+
+/// Keeps track of the server certificates issued to modules and re-issues
+/// them once they cross the renewal threshold.
+///
+/// A re-issued certificate is swapped in only after it has been created, so
+/// a module asking for its current certificate gets either the old or the new
+/// one, never neither. The old certificate is not revoked, so it remains valid
+/// for any TLS session using it until it expires.
+#[derive(Clone)]
+pub struct ServerCertRotator<H> {
+    hsm: H,
+    certs: Arc<RwLock<HashMap<String, IssuedCert>>>,
+    renewal_threshold_percent: u8,
+}
+
+#[derive(Clone)]
+struct IssuedCert {
+    props: CertificateProperties,
+    body: String,
+    issued_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+}
+
+impl IssuedCert {
+    fn renew_at(&self, renewal_threshold_percent: u8) -> DateTime<Utc> {
+        let lifetime = (self.expires_at - self.issued_at).num_seconds();
+        self.issued_at + Duration::seconds(lifetime * i64::from(renewal_threshold_percent) / 100)
+    }
+}
+
+impl<H> ServerCertRotator<H>
+where
+    H: CreateCertificate,
+{
+    pub fn new(hsm: H) -> Self {
+        ServerCertRotator {
+            hsm,
+            certs: Arc::new(RwLock::new(HashMap::new())),
+            renewal_threshold_percent: DEFAULT_SERVER_CERT_RENEWAL_THRESHOLD_PERCENT,
+        }
+    }
+
+    /// Sets the percentage of a certificate's lifetime after which it is
+    /// re-issued. Values are clamped to between 1 and 100.
+    pub fn with_renewal_threshold_percent(mut self, renewal_threshold_percent: u8) -> Self {
+        self.renewal_threshold_percent = renewal_threshold_percent.max(1).min(100);
+        self
+    }
+
+    pub fn renewal_threshold_percent(&self) -> u8 {
+        self.renewal_threshold_percent
+    }
+
+    /// Issues a new server certificate, replacing any existing certificate
+    /// with the same alias, and returns it serialized as a `CertificateResponse`.
+    pub(crate) fn issue(&self, props: &CertificateProperties) -> Result<String> {
+        let context = ErrorKind::CertOperation(CertOperation::GetServerCert);
+
+        self.hsm
+            .destroy_certificate(props.alias().to_string())
+            .context(context.clone())?;
+        let cert = self
+            .hsm
+            .create_certificate(props)
+            .context(context.clone())?;
+        let issued = issued_cert(props, &cert, context)?;
+        let body = issued.body.clone();
+
+        self.certs
+            .write()
+            .expect("server certificate lock poisoned")
+            .insert(props.alias().to_string(), issued);
+
+        Ok(body)
+    }
+
+    /// The current certificate with the given alias, serialized as a
+    /// `CertificateResponse`, if it has been issued and has not expired.
+    pub(crate) fn current(&self, alias: &str) -> Option<String> {
+        self.certs
+            .read()
+            .expect("server certificate lock poisoned")
+            .get(alias)
+            .filter(|issued| issued.expires_at > Utc::now())
+            .map(|issued| issued.body.clone())
+    }
+
+    /// Re-issues every certificate that has crossed the renewal threshold and
+    /// returns how many were re-issued. Certificates that could not be
+    /// re-issued are logged and left in place to be retried on the next call.
+    pub fn rotate(&self) -> usize {
+        self.rotate_at(Utc::now())
+    }
+
+    fn rotate_at(&self, now: DateTime<Utc>) -> usize {
+        let due: Vec<(String, IssuedCert)> = {
+            let mut certs = self
+                .certs
+                .write()
+                .expect("server certificate lock poisoned");
+
+            // Modules that didn't come back for their certificate before it
+            // expired have most likely been removed.
+            certs.retain(|alias, issued| {
+                if issued.expires_at <= now {
+                    debug!("Forgetting expired server certificate {}", alias);
+                    false
+                } else {
+                    true
+                }
+            });
+
+            certs
+                .iter()
+                .filter(|(_, issued)| issued.renew_at(self.renewal_threshold_percent) <= now)
+                .map(|(alias, issued)| (alias.clone(), issued.clone()))
+                .collect()
+        };
+
+        let mut rotated = 0;
+        for (alias, old) in due {
+            // The lock is not held while the certificate is created, so the old
+            // certificate can still be read in the meantime.
+            let new = match self.reissue(&old) {
+                Ok(new) => new,
+                Err(err) => {
+                    warn!("Could not rotate server certificate {}: {}", alias, err);
+                    if let Some(cause) = err.cause() {
+                        warn!("\tcaused by: {}", cause);
+                    }
+                    continue;
+                }
+            };
+
+            let mut certs = self
+                .certs
+                .write()
+                .expect("server certificate lock poisoned");
+            match certs.get(&alias) {
+                Some(current) if current.body == old.body => {
+                    info!(
+                        "Rotated server certificate {}, which was due to expire on {}",
+                        alias, old.expires_at
+                    );
+                    certs.insert(alias, new);
+                    rotated += 1;
+                }
+                _ => debug!(
+                    "Server certificate {} was re-issued while it was being rotated",
+                    alias
+                ),
+            }
+        }
+
+        rotated
+    }
+
+    // Unlike `issue`, the old certificate is only destroyed if creating the new
+    // one over it fails, and a failure leaves the tracked certificate in place.
+    fn reissue(&self, old: &IssuedCert) -> Result<IssuedCert> {
+        let context = ErrorKind::CertOperation(CertOperation::RotateServerCert);
+
+        let cert = match self.hsm.create_certificate(&old.props) {
+            Ok(cert) => cert,
+            Err(_) => {
+                self.hsm
+                    .destroy_certificate(old.props.alias().to_string())
+                    .context(context.clone())?;
+                self.hsm
+                    .create_certificate(&old.props)
+                    .context(context.clone())?
+            }
+        };
+
+        issued_cert(&old.props, &cert, context)
+    }
+}
+
+fn issued_cert<T: Certificate>(
+    props: &CertificateProperties,
+    cert: &T,
+    context: ErrorKind,
+) -> Result<IssuedCert> {
+    let expires_at = cert.get_valid_to().context(context.clone())?;
+    let response = cert_to_response(cert, context.clone())?;
+    let body = serde_json::to_string(&response).context(context)?;
+
+    Ok(IssuedCert {
+        props: props.clone(),
+        body,
+        issued_at: Utc::now(),
+        expires_at,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc;
+    use std::sync::Mutex;
+    use std::thread;
+
+    use edgelet_core::{
+        CertificateType, Error as CoreError, ErrorKind as CoreErrorKind, KeyBytes, PrivateKey,
+    };
+    use edgelet_test_utils::cert::TestCert;
+    use workload::models::CertificateResponse;
+
+    use super::*;
+
+    // Issues certificates valid for 100 seconds whose PEM is "cert-<n>", where
+    // n counts the certificates created so far.
+    #[derive(Clone, Default)]
+    struct TestHsm {
+        created: Arc<AtomicUsize>,
+        fail: Arc<Mutex<bool>>,
+        on_create: Arc<Mutex<Option<mpsc::Receiver<()>>>>,
+    }
+
+    impl TestHsm {
+        fn fail(&self, fail: bool) {
+            *self.fail.lock().unwrap() = fail;
+        }
+
+        // Blocks the next certificate creation until the returned sender is used
+        fn block_create(&self) -> mpsc::Sender<()> {
+            let (sender, receiver) = mpsc::channel();
+            *self.on_create.lock().unwrap() = Some(receiver);
+            sender
+        }
+    }
+
+    impl CreateCertificate for TestHsm {
+        type Certificate = TestCert;
+
+        fn create_certificate(
+            &self,
+            _properties: &CertificateProperties,
+        ) -> std::result::Result<Self::Certificate, CoreError> {
+            let blocked = self.on_create.lock().unwrap().take();
+            if let Some(receiver) = blocked {
+                receiver.recv().unwrap();
+            }
+
+            if *self.fail.lock().unwrap() {
+                return Err(CoreError::from(CoreErrorKind::KeyStore));
+            }
+
+            let n = self.created.fetch_add(1, Ordering::SeqCst) + 1;
+            Ok(TestCert::default()
+                .with_cert(format!("cert-{}", n).into_bytes())
+                .with_private_key(PrivateKey::Key(KeyBytes::Pem("key".to_string())))
+                .with_valid_to(Utc::now() + Duration::seconds(100)))
+        }
+
+        fn destroy_certificate(&self, _alias: String) -> std::result::Result<(), CoreError> {
+            Ok(())
+        }
+
+        fn get_certificate(
+            &self,
+            _alias: String,
+        ) -> std::result::Result<Self::Certificate, CoreError> {
+            Err(CoreError::from(CoreErrorKind::KeyStore))
+        }
+    }
+
+    fn props(alias: &str) -> CertificateProperties {
+        CertificateProperties::new(
+            100,
+            "marvin".to_string(),
+            CertificateType::Server,
+            alias.to_string(),
+        )
+    }
+
+    fn pem(body: &str) -> String {
+        let response: CertificateResponse = serde_json::from_str(body).unwrap();
+        response.certificate().to_string()
+    }
+
+    #[test]
+    fn certs_are_rotated_after_the_renewal_threshold() {
+        let hsm = TestHsm::default();
+        let rotator = ServerCertRotator::new(hsm).with_renewal_threshold_percent(50);
+        rotator.issue(&props("marvin1server")).unwrap();
+
+        assert_eq!(0, rotator.rotate_at(Utc::now() + Duration::seconds(30)));
+        assert_eq!("cert-1", pem(&rotator.current("marvin1server").unwrap()));
+
+        assert_eq!(1, rotator.rotate_at(Utc::now() + Duration::seconds(60)));
+        assert_eq!("cert-2", pem(&rotator.current("marvin1server").unwrap()));
+    }
+
+    #[test]
+    fn old_cert_is_served_until_the_new_one_is_swapped_in() {
+        let hsm = TestHsm::default();
+        let rotator = ServerCertRotator::new(hsm.clone()).with_renewal_threshold_percent(50);
+        rotator.issue(&props("marvin1server")).unwrap();
+
+        let unblock = hsm.block_create();
+        let rotating = rotator.clone();
+        let rotation =
+            thread::spawn(move || rotating.rotate_at(Utc::now() + Duration::seconds(60)));
+
+        // the rotation is now waiting on the HSM, and must not be holding the lock
+        thread::sleep(std::time::Duration::from_millis(100));
+        assert_eq!("cert-1", pem(&rotator.current("marvin1server").unwrap()));
+
+        unblock.send(()).unwrap();
+        assert_eq!(1, rotation.join().unwrap());
+        assert_eq!("cert-2", pem(&rotator.current("marvin1server").unwrap()));
+    }
+
+    #[test]
+    fn old_cert_is_kept_when_rotation_fails() {
+        let hsm = TestHsm::default();
+        let rotator = ServerCertRotator::new(hsm.clone()).with_renewal_threshold_percent(50);
+        rotator.issue(&props("marvin1server")).unwrap();
+
+        hsm.fail(true);
+        assert_eq!(0, rotator.rotate_at(Utc::now() + Duration::seconds(60)));
+        assert_eq!("cert-1", pem(&rotator.current("marvin1server").unwrap()));
+
+        // and it is retried on the next check
+        hsm.fail(false);
+        assert_eq!(1, rotator.rotate_at(Utc::now() + Duration::seconds(61)));
+        assert_eq!("cert-2", pem(&rotator.current("marvin1server").unwrap()));
+    }
+
+    #[test]
+    fn cert_issued_during_rotation_is_not_overwritten() {
+        let hsm = TestHsm::default();
+        let rotator = ServerCertRotator::new(hsm.clone()).with_renewal_threshold_percent(50);
+        rotator.issue(&props("marvin1server")).unwrap();
+
+        let unblock = hsm.block_create();
+        let rotating = rotator.clone();
+        let rotation =
+            thread::spawn(move || rotating.rotate_at(Utc::now() + Duration::seconds(60)));
+        thread::sleep(std::time::Duration::from_millis(100));
+
+        // the module asks for a new certificate while the rotation is in progress
+        let issuing = rotator.clone();
+        let issued = thread::spawn(move || issuing.issue(&props("marvin1server")).unwrap())
+            .join()
+            .unwrap();
+        unblock.send(()).unwrap();
+
+        assert_eq!(0, rotation.join().unwrap());
+        let issued = pem(&issued);
+        assert_eq!(issued, pem(&rotator.current("marvin1server").unwrap()));
+    }
+
+    #[test]
+    fn expired_certs_are_forgotten() {
+        let hsm = TestHsm::default();
+        let rotator = ServerCertRotator::new(hsm);
+        rotator.issue(&props("marvin1server")).unwrap();
+
+        assert_eq!(0, rotator.rotate_at(Utc::now() + Duration::seconds(200)));
+        assert!(rotator.current("marvin1server").is_none());
+    }
+}
@@ -0,0 +1,92 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! The ciphertext returned by the encrypt endpoint is wrapped in a versioned
+//! envelope, so that what it was encrypted with can still be told apart after
+//! the algorithm changes.
+//!
+//! An envelope is `MAGIC`, a version byte and the ciphertext produced by that
+//! version. Ciphertext that doesn't start with `MAGIC` predates envelopes and
+//! is the HSM's ciphertext as is. The HSM's ciphertext starts with its own
+//! format version, which is never the first byte of `MAGIC`.
+
+use crate::error::{Error, ErrorKind, Result};
+
+const MAGIC: &[u8] = b"IEE";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum EnvelopeVersion {
+    /// Ciphertext from before envelopes were introduced.
+    Legacy,
+    /// Ciphertext from `Encrypt::encrypt` with the module's identity as the client ID.
+    V1,
+}
+
+/// The version that newly encrypted data is sealed with.
+pub(crate) const CURRENT_VERSION: EnvelopeVersion = EnvelopeVersion::V1;
+
+pub(crate) fn seal(version: EnvelopeVersion, ciphertext: &[u8]) -> Vec<u8> {
+    let version = match version {
+        EnvelopeVersion::Legacy => return ciphertext.to_vec(),
+        EnvelopeVersion::V1 => 1,
+    };
+
+    let mut envelope = Vec::with_capacity(MAGIC.len() + 1 + ciphertext.len());
+    envelope.extend_from_slice(MAGIC);
+    envelope.push(version);
+    envelope.extend_from_slice(ciphertext);
+    envelope
+}
+
+/// Returns the version an envelope was sealed with and the ciphertext in it.
+pub(crate) fn open(envelope: &[u8]) -> Result<(EnvelopeVersion, &[u8])> {
+    if !envelope.starts_with(MAGIC) {
+        return Ok((EnvelopeVersion::Legacy, envelope));
+    }
+
+    match envelope.get(MAGIC.len()) {
+        Some(1) => Ok((EnvelopeVersion::V1, &envelope[MAGIC.len() + 1..])),
+        Some(version) => Err(Error::from(ErrorKind::UnsupportedEnvelopeVersion(*version))),
+        None => Err(Error::from(ErrorKind::MalformedRequestBody)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sealed_ciphertext_opens_with_its_version() {
+        let envelope = seal(CURRENT_VERSION, b"ciphertext");
+        assert_eq!(b"IEE\x01ciphertext", envelope.as_slice());
+
+        let (version, ciphertext) = open(&envelope).unwrap();
+        assert_eq!(EnvelopeVersion::V1, version);
+        assert_eq!(b"ciphertext", ciphertext);
+    }
+
+    #[test]
+    fn ciphertext_without_envelope_is_legacy() {
+        // the HSM's ciphertext starts with its format version
+        let (version, ciphertext) = open(b"\x01ciphertext").unwrap();
+        assert_eq!(EnvelopeVersion::Legacy, version);
+        assert_eq!(b"\x01ciphertext", ciphertext);
+
+        assert_eq!(
+            b"\x01ciphertext",
+            seal(EnvelopeVersion::Legacy, b"\x01ciphertext").as_slice()
+        );
+    }
+
+    #[test]
+    fn unknown_version_is_rejected() {
+        match open(b"IEE\x02ciphertext").unwrap_err().kind() {
+            ErrorKind::UnsupportedEnvelopeVersion(2) => (),
+            kind => panic!("Expected `UnsupportedEnvelopeVersion` but got {:?}", kind),
+        }
+
+        match open(b"IEE").unwrap_err().kind() {
+            ErrorKind::MalformedRequestBody => (),
+            kind => panic!("Expected `MalformedRequestBody` but got {:?}", kind),
+        }
+    }
+}
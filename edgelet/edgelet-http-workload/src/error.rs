@@ -9,6 +9,8 @@ use log::error;
 use serde_json;
 use workload::models::ErrorResponse;
 
+use edgelet_core::{redact_failure, ErrorCode, ErrorCodeKind};
+
 use crate::IntoResponse;
 
 pub type Result<T> = ::std::result::Result<T, Error>;
@@ -41,8 +43,20 @@ pub enum ErrorKind {
     #[fail(display = "Module not found")]
     ModuleNotFound(String),
 
+    #[fail(display = "Server certificate {} not found", _0)]
+    ServerCertNotFound(String),
+
     #[fail(display = "Could not start workload service")]
     StartService,
+
+    #[fail(
+        display = "Signature algorithm `{}` is not supported. Supported algorithms: {}",
+        _0, _1
+    )]
+    UnsupportedSignatureAlgorithm(String, String),
+
+    #[fail(display = "Ciphertext envelope version {} is not supported", _0)]
+    UnsupportedEnvelopeVersion(u8),
 }
 
 impl Fail for Error {
@@ -65,6 +79,25 @@ impl Error {
     pub fn kind(&self) -> &ErrorKind {
         self.inner.get_context()
     }
+
+    pub fn code(&self) -> ErrorCode {
+        self.kind().error_code().unwrap_or(ErrorCode::Internal)
+    }
+}
+
+impl ErrorCodeKind for ErrorKind {
+    fn error_code(&self) -> Option<ErrorCode> {
+        match self {
+            ErrorKind::MalformedRequestBody
+            | ErrorKind::MalformedRequestParameter(_)
+            | ErrorKind::MissingRequiredParameter(_)
+            | ErrorKind::UnsupportedSignatureAlgorithm(_, _)
+            | ErrorKind::UnsupportedEnvelopeVersion(_) => Some(ErrorCode::InvalidRequest),
+            ErrorKind::ModuleNotFound(_) => Some(ErrorCode::ModuleNotFound),
+            ErrorKind::ServerCertNotFound(_) => Some(ErrorCode::CertificateNotFound),
+            _ => None,
+        }
+    }
 }
 
 impl From<ErrorKind> for Error {
@@ -83,18 +116,17 @@ impl From<Context<ErrorKind>> for Error {
 
 impl IntoResponse for Error {
     fn into_response(self) -> Response<Body> {
-        let mut fail: &dyn Fail = &self;
-        let mut message = self.to_string();
-        while let Some(cause) = fail.cause() {
-            message.push_str(&format!("\n\tcaused by: {}", cause.to_string()));
-            fail = cause;
-        }
+        let message = redact_failure(&self);
 
         let status_code = match *self.kind() {
-            ErrorKind::ModuleNotFound(_) => StatusCode::NOT_FOUND,
+            ErrorKind::ModuleNotFound(_) | ErrorKind::ServerCertNotFound(_) => {
+                StatusCode::NOT_FOUND
+            }
             ErrorKind::MalformedRequestBody
             | ErrorKind::MalformedRequestParameter(_)
-            | ErrorKind::MissingRequiredParameter(_) => StatusCode::BAD_REQUEST,
+            | ErrorKind::MissingRequiredParameter(_)
+            | ErrorKind::UnsupportedSignatureAlgorithm(_, _)
+            | ErrorKind::UnsupportedEnvelopeVersion(_) => StatusCode::BAD_REQUEST,
             _ => {
                 error!("Internal server error: {}", message);
                 StatusCode::INTERNAL_SERVER_ERROR
@@ -105,8 +137,8 @@ impl IntoResponse for Error {
         let body = if status_code == StatusCode::NOT_MODIFIED {
             String::new()
         } else {
-            serde_json::to_string(&ErrorResponse::new(message))
-                .expect("serialization of ErrorResponse failed.")
+            let response = ErrorResponse::new(message).with_code(self.code().to_string());
+            serde_json::to_string(&response).expect("serialization of ErrorResponse failed.")
         };
 
         let mut response = Response::builder();
@@ -127,14 +159,20 @@ impl IntoResponse for Error {
 #[derive(Clone, Copy, Debug)]
 pub enum CertOperation {
     CreateIdentityCert,
+    GetCertExpiryStatus,
     GetServerCert,
+    RotateServerCert,
 }
 
 impl fmt::Display for CertOperation {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             CertOperation::CreateIdentityCert => write!(f, "Could not create identity cert"),
+            CertOperation::GetCertExpiryStatus => {
+                write!(f, "Could not get certificate expiry status")
+            }
             CertOperation::GetServerCert => write!(f, "Could not get server cert"),
+            CertOperation::RotateServerCert => write!(f, "Could not rotate server cert"),
         }
     }
 }
@@ -12,9 +12,14 @@
 
 use hyper::{Body, Response};
 
+mod cert_expiry;
+mod cert_rotation;
+mod envelope;
 mod error;
 mod server;
 
+pub use crate::cert_expiry::{CertExpiryInfo, CertExpiryMonitor};
+pub use crate::cert_rotation::ServerCertRotator;
 pub use crate::server::WorkloadService;
 
 pub trait IntoResponse {
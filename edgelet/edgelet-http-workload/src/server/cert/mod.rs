@@ -18,9 +18,12 @@ mod identity;
 mod server;
 
 pub use self::identity::IdentityCertHandler;
-pub use self::server::ServerCertHandler;
+pub use self::server::{GetServerCertHandler, ServerCertHandler};
 
-fn cert_to_response<T: Certificate>(cert: &T, context: ErrorKind) -> Result<CertificateResponse> {
+pub(crate) fn cert_to_response<T: Certificate>(
+    cert: &T,
+    context: ErrorKind,
+) -> Result<CertificateResponse> {
     let cert_buffer = match cert.pem() {
         Ok(cert_buffer) => cert_buffer,
         Err(err) => return Err(Error::from(err.context(context))),
@@ -85,8 +88,16 @@ fn refresh_cert<T: CreateCertificate>(
         Err(err) => return Err(Error::from(err.context(context))),
     };
 
+    cert_body_to_response(body, StatusCode::CREATED, context)
+}
+
+fn cert_body_to_response(
+    body: String,
+    status: StatusCode,
+    context: ErrorKind,
+) -> Result<Response<Body>> {
     let response = Response::builder()
-        .status(StatusCode::CREATED)
+        .status(status)
         .header(CONTENT_TYPE, "application/json")
         .header(CONTENT_LENGTH, body.len().to_string().as_str())
         .body(body.into())
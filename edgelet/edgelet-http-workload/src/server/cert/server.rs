@@ -1,9 +1,9 @@
 // Copyright (c) Microsoft. All rights reserved.
 
-use super::{compute_validity, refresh_cert};
+use super::{cert_body_to_response, compute_validity};
 use failure::ResultExt;
 use futures::{future, Future, IntoFuture, Stream};
-use hyper::{Body, Request, Response};
+use hyper::{Body, Request, Response, StatusCode};
 use serde_json;
 
 use edgelet_core::{
@@ -16,17 +16,28 @@ use edgelet_utils::{
 };
 use workload::models::ServerCertificateRequest;
 
+use crate::cert_rotation::ServerCertRotator;
 use crate::error::{CertOperation, Error, ErrorKind};
 use crate::IntoResponse;
 
 pub struct ServerCertHandler<T: CreateCertificate, W: WorkloadConfig> {
-    hsm: T,
+    rotator: ServerCertRotator<T>,
     config: W,
 }
 
 impl<T: CreateCertificate, W: WorkloadConfig> ServerCertHandler<T, W> {
     pub fn new(hsm: T, config: W) -> Self {
-        ServerCertHandler { hsm, config }
+        ServerCertHandler {
+            rotator: ServerCertRotator::new(hsm),
+            config,
+        }
+    }
+
+    /// Records the issued certificates with `rotator`, so that they are
+    /// rotated along with the certificates it already tracks.
+    pub fn with_rotator(mut self, rotator: ServerCertRotator<T>) -> Self {
+        self.rotator = rotator;
+        self
     }
 }
 impl<T, W> Handler<Parameters> for ServerCertHandler<T, W>
@@ -40,7 +51,7 @@ where
         req: Request<Body>,
         params: Parameters,
     ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
-        let hsm = self.hsm.clone();
+        let rotator = self.rotator.clone();
         let cfg = self.config.clone();
         let max_duration = cfg.get_cert_max_duration(CertificateType::Server);
 
@@ -102,13 +113,12 @@ where
                     alias.clone(),
                 )
                 .with_san_entries(sans);
-                let body = refresh_cert(
-                    &hsm,
-                    alias,
-                    &props,
+                let body = rotator.issue(&props)?;
+                cert_body_to_response(
+                    body,
+                    StatusCode::CREATED,
                     ErrorKind::CertOperation(CertOperation::GetServerCert),
-                )?;
-                Ok(body)
+                )
             })
             .or_else(|e| future::ok(e.into_response()));
 
@@ -116,6 +126,52 @@ where
     }
 }
 
+pub struct GetServerCertHandler<T: CreateCertificate> {
+    rotator: ServerCertRotator<T>,
+}
+
+impl<T: CreateCertificate> GetServerCertHandler<T> {
+    pub fn new(rotator: ServerCertRotator<T>) -> Self {
+        GetServerCertHandler { rotator }
+    }
+}
+
+impl<T> Handler<Parameters> for GetServerCertHandler<T>
+where
+    T: CreateCertificate + Clone + Send + Sync + 'static,
+{
+    fn handle(
+        &self,
+        _req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let response = params
+            .name("name")
+            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+            .and_then(|name| {
+                let genid = params
+                    .name("genid")
+                    .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("genid")))?;
+                Ok(format!("{}{}server", name, genid))
+            })
+            .and_then(|alias| {
+                let body = self
+                    .rotator
+                    .current(&alias)
+                    .ok_or_else(|| ErrorKind::ServerCertNotFound(alias))?;
+                cert_body_to_response(
+                    body,
+                    StatusCode::OK,
+                    ErrorKind::CertOperation(CertOperation::GetServerCert),
+                )
+            })
+            .or_else(|e| Ok::<_, HttpError>(e.into_response()))
+            .into_future();
+
+        Box::new(response)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::result::Result as StdResult;
@@ -716,4 +772,72 @@ mod tests {
             parse_error_response(response).message(),
         );
     }
+
+    #[test]
+    fn get_returns_issued_cert() {
+        let rotator = ServerCertRotator::new(TestHsm::default().with_on_create(|_| {
+            Ok(TestCert::default()
+                .with_cert(b"Heart of Gold".to_vec())
+                .with_private_key(PrivateKey::Key(KeyBytes::Pem("Betelgeuse".to_string())))
+                .with_valid_to(Utc::now() + Duration::hours(1)))
+        }));
+        let handler = ServerCertHandler::new(TestHsm::default(), TestWorkloadData::default())
+            .with_rotator(rotator.clone());
+
+        let cert_req = ServerCertificateRequest::new(
+            "marvin".to_string(),
+            (Utc::now() + Duration::hours(1)).to_rfc3339(),
+        );
+        let request =
+            Request::post("http://localhost/modules/beeblebrox/genid/I/certificate/server")
+                .body(serde_json::to_string(&cert_req).unwrap().into())
+                .unwrap();
+        let params = || {
+            Parameters::with_captures(vec![
+                (Some("name".to_string()), "beeblebrox".to_string()),
+                (Some("genid".to_string()), "I".to_string()),
+            ])
+        };
+        let response = handler.handle(request, params()).wait().unwrap();
+        assert_eq!(StatusCode::CREATED, response.status());
+
+        let request =
+            Request::get("http://localhost/modules/beeblebrox/genid/I/certificate/server")
+                .body("".into())
+                .unwrap();
+        let response = GetServerCertHandler::new(rotator)
+            .handle(request, params())
+            .wait()
+            .unwrap();
+        assert_eq!(StatusCode::OK, response.status());
+
+        let cert_resp = response
+            .into_body()
+            .concat2()
+            .and_then(|b| Ok(serde_json::from_slice::<CertificateResponse>(&b).unwrap()))
+            .wait()
+            .unwrap();
+        assert_eq!("Heart of Gold", cert_resp.certificate());
+        assert_eq!(Some("Betelgeuse"), cert_resp.private_key().bytes());
+    }
+
+    #[test]
+    fn get_unknown_cert_is_not_found() {
+        let handler = GetServerCertHandler::new(ServerCertRotator::new(TestHsm::default()));
+        let request =
+            Request::get("http://localhost/modules/beeblebrox/genid/I/certificate/server")
+                .body("".into())
+                .unwrap();
+        let params = Parameters::with_captures(vec![
+            (Some("name".to_string()), "beeblebrox".to_string()),
+            (Some("genid".to_string()), "I".to_string()),
+        ]);
+        let response = handler.handle(request, params).wait().unwrap();
+
+        assert_eq!(StatusCode::NOT_FOUND, response.status());
+        assert_eq!(
+            "Server certificate beeblebroxIserver not found",
+            parse_error_response(response).message(),
+        );
+    }
 }
@@ -0,0 +1,114 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::ResultExt;
+use futures::{Future, IntoFuture};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use serde_json;
+
+use edgelet_core::CreateCertificate;
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+use workload::models::{CertificateExpiry, CertificateExpiryList};
+
+use crate::cert_expiry::CertExpiryMonitor;
+use crate::error::{CertOperation, Error, ErrorKind};
+use crate::IntoResponse;
+
+pub struct CertExpiryHandler<T: CreateCertificate> {
+    monitor: CertExpiryMonitor<T>,
+}
+
+impl<T: CreateCertificate> CertExpiryHandler<T> {
+    pub fn new(monitor: CertExpiryMonitor<T>) -> Self {
+        CertExpiryHandler { monitor }
+    }
+}
+
+impl<T> Handler<Parameters> for CertExpiryHandler<T>
+where
+    T: 'static + CreateCertificate + Send + Sync,
+{
+    fn handle(
+        &self,
+        _req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let certificates = self
+            .monitor
+            .cert_expiry_status()
+            .into_iter()
+            .map(|info| {
+                CertificateExpiry::new(
+                    info.alias().to_string(),
+                    info.subject().to_string(),
+                    info.not_after().to_rfc3339(),
+                    info.days_remaining(),
+                )
+            })
+            .collect();
+        let list =
+            CertificateExpiryList::new(certificates, self.monitor.warning_window().num_days());
+
+        let response = serde_json::to_string(&list)
+            .context(ErrorKind::CertOperation(CertOperation::GetCertExpiryStatus))
+            .and_then(|body| {
+                Response::builder()
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(CONTENT_LENGTH, body.len().to_string().as_str())
+                    .body(body.into())
+                    .context(ErrorKind::CertOperation(CertOperation::GetCertExpiryStatus))
+            })
+            .or_else(|e| Ok::<_, HttpError>(Error::from(e).into_response()))
+            .into_future();
+
+        Box::new(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::Stream;
+
+    use crate::cert_expiry::tests::{make_cert, TestHsm};
+
+    use super::*;
+
+    #[test]
+    fn success() {
+        let hsm = TestHsm::default().with_cert(
+            "workload-ca",
+            &[make_cert("workload-ca", 30), make_cert("device-ca", 3)],
+        );
+        let handler =
+            CertExpiryHandler::new(CertExpiryMonitor::new(hsm, vec!["workload-ca".to_string()]));
+        let request = Request::get("http://localhost/certificates/expiry")
+            .body("".into())
+            .unwrap();
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+        assert_eq!(StatusCode::OK, response.status());
+
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                let list: CertificateExpiryList = serde_json::from_slice(&b).unwrap();
+                assert_eq!(7, list.warning_days());
+
+                let certificates: Vec<_> = list
+                    .certificates()
+                    .iter()
+                    .map(|cert| (cert.alias().as_str(), cert.subject().as_str()))
+                    .collect();
+                assert_eq!(
+                    vec![("workload-ca", "workload-ca"), ("workload-ca", "device-ca")],
+                    certificates
+                );
+                assert_eq!(2, list.certificates()[1].days_remaining());
+                Ok(())
+            })
+            .wait()
+            .unwrap();
+    }
+}
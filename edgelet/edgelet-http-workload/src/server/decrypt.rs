@@ -12,6 +12,7 @@ use edgelet_http::route::{Handler, Parameters};
 use edgelet_http::Error as HttpError;
 use workload::models::{DecryptRequest, DecryptResponse};
 
+use crate::envelope::{self, EnvelopeVersion};
 use crate::error::{EncryptionOperation, Error, ErrorKind};
 use crate::IntoResponse;
 
@@ -62,9 +63,12 @@ where
                     .context(ErrorKind::MalformedRequestBody)?;
                 let initialization_vector = base64::decode(request.initialization_vector())
                     .context(ErrorKind::MalformedRequestBody)?;
-                let plaintext = hsm
-                    .decrypt(id.as_bytes(), &ciphertext, &initialization_vector)
-                    .context(ErrorKind::EncryptionOperation(EncryptionOperation::Decrypt))?;
+                let plaintext = match envelope::open(&ciphertext)? {
+                    (EnvelopeVersion::Legacy, ciphertext) | (EnvelopeVersion::V1, ciphertext) => {
+                        hsm.decrypt(id.as_bytes(), ciphertext, &initialization_vector)
+                    }
+                }
+                .context(ErrorKind::EncryptionOperation(EncryptionOperation::Decrypt))?;
                 let encoded = base64::encode(&plaintext);
                 let response = DecryptResponse::new(encoded);
                 let body = serde_json::to_string(&response)
@@ -85,15 +89,16 @@ where
 
 #[cfg(test)]
 mod tests {
-    use edgelet_core::Decrypt;
     use edgelet_core::Error as CoreError;
+    use edgelet_core::{Decrypt, Encrypt};
     use edgelet_http::route::Parameters;
     use futures::Future;
     use hyper::{Request, StatusCode};
-    use workload::models::DecryptResponse;
     use workload::models::ErrorResponse;
+    use workload::models::{DecryptResponse, EncryptRequest, EncryptResponse};
 
     use super::*;
+    use crate::server::encrypt::EncryptHandler;
 
     #[derive(Clone, Debug, Default)]
     struct TestHsm {}
@@ -301,4 +306,139 @@ mod tests {
             );
         }
     }
+
+    // "Encrypts" by XOR-ing with the client ID and initialization vector and
+    // prefixing the format version, like the HSM's ciphertext.
+    #[derive(Clone, Debug, Default)]
+    struct RoundTripHsm;
+
+    fn xor(client_id: &[u8], data: &[u8], initialization_vector: &[u8]) -> Vec<u8> {
+        data.iter()
+            .zip(client_id.iter().cycle())
+            .zip(initialization_vector.iter().cycle())
+            .map(|((d, c), i)| d ^ c ^ i)
+            .collect()
+    }
+
+    impl Encrypt for RoundTripHsm {
+        type Buffer = Vec<u8>;
+
+        fn encrypt(
+            &self,
+            client_id: &[u8],
+            plaintext: &[u8],
+            initialization_vector: &[u8],
+        ) -> Result<Self::Buffer, CoreError> {
+            let mut ciphertext = vec![1];
+            ciphertext.extend(xor(client_id, plaintext, initialization_vector));
+            Ok(ciphertext)
+        }
+    }
+
+    impl Decrypt for RoundTripHsm {
+        type Buffer = Vec<u8>;
+
+        fn decrypt(
+            &self,
+            client_id: &[u8],
+            ciphertext: &[u8],
+            initialization_vector: &[u8],
+        ) -> Result<Self::Buffer, CoreError> {
+            assert_eq!(Some(&1), ciphertext.first());
+            Ok(xor(client_id, &ciphertext[1..], initialization_vector))
+        }
+    }
+
+    fn encrypt(plaintext: &str, initialization_vector: &str) -> String {
+        let request = EncryptRequest::new(
+            base64::encode(plaintext),
+            base64::encode(initialization_vector),
+        );
+        let (request, params) = (
+            Request::builder()
+                .body(serde_json::to_string(&request).unwrap().into())
+                .unwrap(),
+            Parameters::with_captures(params_ok!().unwrap()),
+        );
+
+        let response = EncryptHandler::new(RoundTripHsm)
+            .handle(request, params)
+            .wait()
+            .unwrap();
+        assert_eq!(StatusCode::OK, response.status());
+        response
+            .into_body()
+            .concat2()
+            .map(|b| {
+                serde_json::from_slice::<EncryptResponse>(&b)
+                    .unwrap()
+                    .ciphertext()
+                    .to_string()
+            })
+            .wait()
+            .unwrap()
+    }
+
+    fn decrypt(ciphertext: String, initialization_vector: &str) -> Response<Body> {
+        let request = DecryptRequest::new(ciphertext, base64::encode(initialization_vector));
+        let (request, params) = create_args(Some(&request), params_ok!());
+        DecryptHandler::new(RoundTripHsm)
+            .handle(request, params)
+            .wait()
+            .unwrap()
+    }
+
+    fn plaintext(response: Response<Body>) -> String {
+        assert_eq!(StatusCode::OK, response.status());
+        let plaintext = response
+            .into_body()
+            .concat2()
+            .map(|b| {
+                serde_json::from_slice::<DecryptResponse>(&b)
+                    .unwrap()
+                    .plaintext()
+                    .to_string()
+            })
+            .wait()
+            .unwrap();
+        String::from_utf8(base64::decode(&plaintext).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn encrypted_data_round_trips() {
+        let ciphertext = encrypt("Don't panic", "towel");
+
+        assert_eq!("Don't panic", plaintext(decrypt(ciphertext, "towel")));
+    }
+
+    #[test]
+    fn data_encrypted_before_envelopes_is_decrypted() {
+        let ciphertext = encrypt("Don't panic", "towel");
+        let (version, hsm_ciphertext) = {
+            let envelope = base64::decode(&ciphertext).unwrap();
+            let (version, hsm_ciphertext) = envelope::open(&envelope).unwrap();
+            (version, hsm_ciphertext.to_vec())
+        };
+        assert_eq!(EnvelopeVersion::V1, version);
+
+        // earlier versions returned the HSM's ciphertext without an envelope
+        let legacy = base64::encode(&envelope::seal(EnvelopeVersion::Legacy, &hsm_ciphertext));
+        assert_eq!(
+            base64::encode(
+                &RoundTripHsm
+                    .encrypt(b"testI", b"Don't panic", b"towel")
+                    .unwrap()
+            ),
+            legacy
+        );
+        assert_eq!("Don't panic", plaintext(decrypt(legacy, "towel")));
+    }
+
+    #[test]
+    fn handler_responds_with_bad_request_for_unknown_envelope_version() {
+        let response = decrypt(base64::encode(b"IEE\x09ciphertext"), "towel");
+
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        assert_response_message_eq("Ciphertext envelope version 9 is not supported", response);
+    }
 }
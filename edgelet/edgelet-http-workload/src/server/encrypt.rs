@@ -12,6 +12,7 @@ use edgelet_http::route::{Handler, Parameters};
 use edgelet_http::Error as HttpError;
 use workload::models::{EncryptRequest, EncryptResponse};
 
+use crate::envelope;
 use crate::error::{EncryptionOperation, Error, ErrorKind};
 use crate::IntoResponse;
 
@@ -65,7 +66,10 @@ where
                 let ciphertext = hsm
                     .encrypt(id.as_bytes(), &plaintext, &initialization_vector)
                     .context(ErrorKind::EncryptionOperation(EncryptionOperation::Encrypt))?;
-                let encoded = base64::encode(&ciphertext);
+                let encoded = base64::encode(&envelope::seal(
+                    envelope::CURRENT_VERSION,
+                    ciphertext.as_ref(),
+                ));
                 let response = EncryptResponse::new(encoded);
                 let body = serde_json::to_string(&response)
                     .context(ErrorKind::EncryptionOperation(EncryptionOperation::Encrypt))?;
@@ -147,9 +151,16 @@ mod tests {
         };
     }
 
-    macro_rules! b64_reversed {
+    // The ciphertext is returned in a version 1 envelope
+    macro_rules! b64_enveloped_reversed {
         () => {
-            base64::encode(&RAW_TEXT.chars().rev().collect::<String>())
+            base64::encode(
+                &[
+                    &b"IEE\x01"[..],
+                    RAW_TEXT.chars().rev().collect::<String>().as_bytes(),
+                ]
+                .concat(),
+            )
         };
     }
 
@@ -229,7 +240,7 @@ mod tests {
             .wait()
             .unwrap();
 
-        assert_eq!(b64_reversed!(), body.ciphertext().to_string());
+        assert_eq!(b64_enveloped_reversed!(), body.ciphertext().to_string());
     }
 
     #[test]
@@ -1,6 +1,7 @@
 // Copyright (c) Microsoft. All rights reserved.
 
 mod cert;
+mod cert_expiry;
 mod decrypt;
 mod encrypt;
 mod sign;
@@ -21,13 +22,18 @@ use hyper::service::{NewService, Service};
 use hyper::{Body, Request};
 use serde::Serialize;
 
-use self::cert::{IdentityCertHandler, ServerCertHandler};
+use self::cert::{GetServerCertHandler, IdentityCertHandler, ServerCertHandler};
+use self::cert_expiry::CertExpiryHandler;
 use self::decrypt::DecryptHandler;
 use self::encrypt::EncryptHandler;
 use self::sign::SignHandler;
 use self::trust_bundle::TrustBundleHandler;
+use crate::cert_expiry::CertExpiryMonitor;
+use crate::cert_rotation::ServerCertRotator;
 use crate::error::{Error, ErrorKind};
 
+pub(crate) use self::cert::cert_to_response;
+
 #[derive(Clone)]
 pub struct WorkloadService {
     inner: RouterService<RegexRecognizer>,
@@ -39,6 +45,8 @@ impl WorkloadService {
         hsm: H,
         runtime: &M,
         config: W,
+        cert_expiry: CertExpiryMonitor<H>,
+        cert_rotation: ServerCertRotator<H>,
     ) -> impl Future<Item = Self, Error = Error>
     where
         K: KeyStore + Clone + Send + Sync + 'static,
@@ -56,9 +64,11 @@ impl WorkloadService {
             post  Version2018_06_28 runtime Policy::Caller =>    "/modules/(?P<name>[^/]+)/genid/(?P<genid>[^/]+)/decrypt"  => DecryptHandler::new(hsm.clone()),
             post  Version2018_06_28 runtime Policy::Caller =>    "/modules/(?P<name>[^/]+)/genid/(?P<genid>[^/]+)/encrypt"  => EncryptHandler::new(hsm.clone()),
             post  Version2018_06_28 runtime Policy::Caller =>    "/modules/(?P<name>[^/]+)/certificate/identity"            => IdentityCertHandler::new(hsm.clone(), config.clone()),
-            post  Version2018_06_28 runtime Policy::Caller =>    "/modules/(?P<name>[^/]+)/genid/(?P<genid>[^/]+)/certificate/server" => ServerCertHandler::new(hsm.clone(), config),
+            post  Version2018_06_28 runtime Policy::Caller =>    "/modules/(?P<name>[^/]+)/genid/(?P<genid>[^/]+)/certificate/server" => ServerCertHandler::new(hsm.clone(), config).with_rotator(cert_rotation.clone()),
+            get   Version2019_01_30 runtime Policy::Caller =>    "/modules/(?P<name>[^/]+)/genid/(?P<genid>[^/]+)/certificate/server" => GetServerCertHandler::new(cert_rotation),
 
             get   Version2018_06_28 runtime Policy::Anonymous => "/trust-bundle" => TrustBundleHandler::new(hsm),
+            get   Version2019_01_30 runtime Policy::Anonymous => "/certificates/expiry" => CertExpiryHandler::new(cert_expiry),
         );
 
         router.new_service().then(|inner| {
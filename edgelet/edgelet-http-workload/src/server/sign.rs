@@ -39,9 +39,21 @@ pub fn sign<K: KeyStore>(
     let k = key_store
         .get(&KeyIdentity::Module(id.clone()), request.key_id())
         .context(ErrorKind::ModuleNotFound(id))?;
+
+    let supported = k.supported_algorithms();
+    let algorithm = request
+        .algo()
+        .parse::<SignatureAlgorithm>()
+        .ok()
+        .filter(|algorithm| supported.contains(algorithm))
+        .ok_or_else(|| {
+            let supported: Vec<_> = supported.iter().map(ToString::to_string).collect();
+            ErrorKind::UnsupportedSignatureAlgorithm(request.algo().clone(), supported.join(", "))
+        })?;
+
     let data: Vec<u8> = base64::decode(request.data()).context(ErrorKind::MalformedRequestBody)?;
     let signature = k
-        .sign(SignatureAlgorithm::HMACSHA256, &data)
+        .sign(algorithm, &data)
         .context(ErrorKind::EncryptionOperation(EncryptionOperation::Sign))?;
     let encoded = base64::encode(signature.as_bytes());
     Ok(SignResponse::new(encoded))
@@ -175,6 +187,70 @@ mod tests {
         }
     }
 
+    #[derive(Clone, Debug)]
+    struct Sha256OnlyKey(MemoryKey);
+
+    impl Sign for Sha256OnlyKey {
+        type Signature = <MemoryKey as Sign>::Signature;
+
+        fn sign(
+            &self,
+            signature_algorithm: SignatureAlgorithm,
+            data: &[u8],
+        ) -> Result<Self::Signature, CoreError> {
+            self.0.sign(signature_algorithm, data)
+        }
+
+        fn supported_algorithms(&self) -> &'static [SignatureAlgorithm] {
+            &[SignatureAlgorithm::HMACSHA256]
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    struct Sha256OnlyKeyStore;
+
+    impl KeyStore for Sha256OnlyKeyStore {
+        type Key = Sha256OnlyKey;
+
+        fn get(&self, _identity: &KeyIdentity, _key_name: &str) -> Result<Self::Key, CoreError> {
+            Ok(Sha256OnlyKey(MemoryKey::new("key")))
+        }
+    }
+
+    fn sign_with<K>(handler: &SignHandler<K>, algo: &str) -> Response<Body>
+    where
+        K: 'static + KeyStore + Clone + Send,
+    {
+        let sign_request = SignRequest::new(
+            "primary".to_string(),
+            algo.to_string(),
+            base64::encode("The quick brown fox jumps over the lazy dog"),
+        );
+        let body = serde_json::to_string(&sign_request).unwrap();
+
+        let parameters = Parameters::with_captures(vec![
+            (Some("name".to_string()), "test".to_string()),
+            (Some("genid".to_string()), "g1".to_string()),
+        ]);
+        let request = Request::post("http://localhost/modules/name/sign")
+            .body(body.into())
+            .unwrap();
+
+        handler.handle(request, parameters).wait().unwrap()
+    }
+
+    fn error_message(response: Response<Body>) -> String {
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                let error_response: ErrorResponse = serde_json::from_slice(&b).unwrap();
+                Ok(error_response.message().to_string())
+            })
+            .wait()
+            .unwrap()
+    }
+
     #[test]
     fn success() {
         // arrange
@@ -387,4 +463,64 @@ mod tests {
             .wait()
             .unwrap();
     }
+
+    #[test]
+    fn success_with_each_algorithm() {
+        let handler = SignHandler::new(TestKeyStore::new(MemoryKey::new("key")));
+
+        for (algo, expected) in &[
+            ("HMAC-SHA256", "97yD9DBThCSxMpjmqm+xQ+9NWaFJRhdZl0edvC0aPNg="),
+            // the names this algorithm was requested with before others were supported
+            ("HMACSHA256", "97yD9DBThCSxMpjmqm+xQ+9NWaFJRhdZl0edvC0aPNg="),
+            ("hmac", "97yD9DBThCSxMpjmqm+xQ+9NWaFJRhdZl0edvC0aPNg="),
+            (
+                "HMAC-SHA384",
+                "1/RyfiwLOa4PHkDMlvYCQtW3gBhBzqb8WSxdPhrlBwBYKpbPNeHlVJlf5OAzgcI3",
+            ),
+            (
+                "HMAC-SHA512",
+                "tCrwkFe6weLUFwjkipAuCbX/fxKrQopP6GZTxz3SSPuC+UilSfe3kaW0GRXuTR7Dk1NX5OIxclDQNyr6Lr7rOg==",
+            ),
+        ] {
+            let response = sign_with(&handler, algo);
+            assert_eq!(StatusCode::OK, response.status(), "{}", algo);
+            response
+                .into_body()
+                .concat2()
+                .and_then(|b| {
+                    let sign_response: SignResponse = serde_json::from_slice(&b).unwrap();
+                    assert_eq!(*expected, sign_response.digest(), "{}", algo);
+                    Ok(())
+                })
+                .wait()
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn unknown_algorithm_is_rejected() {
+        let handler = SignHandler::new(TestKeyStore::new(MemoryKey::new("key")));
+
+        let response = sign_with(&handler, "HMAC-MD5");
+
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        assert_eq!(
+            "Signature algorithm `HMAC-MD5` is not supported. Supported algorithms: HMAC-SHA256, HMAC-SHA384, HMAC-SHA512",
+            error_message(response)
+        );
+    }
+
+    #[test]
+    fn algorithm_unsupported_by_key_is_rejected() {
+        let handler = SignHandler::new(Sha256OnlyKeyStore);
+
+        let response = sign_with(&handler, "HMAC-SHA384");
+
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        assert_eq!(
+            "Signature algorithm `HMAC-SHA384` is not supported. Supported algorithms: HMAC-SHA256",
+            error_message(response)
+        );
+        assert_eq!(StatusCode::OK, sign_with(&handler, "HMAC-SHA256").status());
+    }
 }
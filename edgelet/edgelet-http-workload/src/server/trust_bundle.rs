@@ -1,12 +1,25 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+//! Modules poll the trust bundle to keep their CA set current, so responses
+//! carry an `ETag` that is a digest of the bundle, the `Last-Modified` time at
+//! which the daemon first served that bundle, and a `Cache-Control` max-age.
+//! A request with `If-None-Match` or `If-Modified-Since` that the bundle
+//! hasn't changed since gets a 304 without a body. Rotating a CA changes the
+//! bundle and so its `ETag`.
+
 use std::str;
+use std::sync::{Arc, Mutex};
 
+use chrono::{DateTime, Utc};
 use failure::ResultExt;
 use futures::{Future, IntoFuture};
-use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::header::{
+    HeaderMap, HeaderValue, CACHE_CONTROL, CONTENT_LENGTH, CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE,
+    IF_NONE_MATCH, LAST_MODIFIED,
+};
 use hyper::{Body, Request, Response, StatusCode};
 use serde_json;
+use sha2::{Digest, Sha256};
 
 use edgelet_core::{Certificate, GetTrustBundle};
 use edgelet_http::route::{Handler, Parameters};
@@ -16,8 +29,19 @@ use workload::models::TrustBundleResponse;
 use crate::error::{EncryptionOperation, Error, ErrorKind};
 use crate::IntoResponse;
 
+/// How long modules may use a trust bundle before asking for it again.
+const MAX_AGE_SECS: u64 = 60;
+
 pub struct TrustBundleHandler<T: GetTrustBundle> {
     hsm: T,
+    served: Arc<Mutex<Option<Served>>>,
+}
+
+// The ETag of the bundle that was last served, and when it was first served.
+#[derive(Clone)]
+struct Served {
+    etag: String,
+    since: DateTime<Utc>,
 }
 
 impl<T> TrustBundleHandler<T>
@@ -25,7 +49,10 @@ where
     T: 'static + GetTrustBundle + Clone,
 {
     pub fn new(hsm: T) -> Self {
-        TrustBundleHandler { hsm }
+        TrustBundleHandler {
+            hsm,
+            served: Arc::new(Mutex::new(None)),
+        }
     }
 }
 
@@ -35,9 +62,10 @@ where
 {
     fn handle(
         &self,
-        _req: Request<Body>,
+        req: Request<Body>,
         _params: Parameters,
     ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let served = self.served.clone();
         let response = self
             .hsm
             .get_trust_bundle()
@@ -45,10 +73,21 @@ where
                 EncryptionOperation::GetTrustBundle,
             ))
             .map_err(Error::from)
-            .and_then(|cert| -> Result<_, Error> {
+            .and_then(move |cert| -> Result<_, Error> {
                 let cert = cert.pem().context(ErrorKind::EncryptionOperation(
                     EncryptionOperation::GetTrustBundle,
                 ))?;
+                let served = serve(&served, cert.as_ref());
+                if is_unchanged(req.headers(), &served) {
+                    let response = Response::builder()
+                        .status(StatusCode::NOT_MODIFIED)
+                        .body(Body::empty())
+                        .context(ErrorKind::EncryptionOperation(
+                            EncryptionOperation::GetTrustBundle,
+                        ))?;
+                    return with_cache_headers(response, &served);
+                }
+
                 let cert = str::from_utf8(cert.as_ref())
                     .context(ErrorKind::EncryptionOperation(
                         EncryptionOperation::GetTrustBundle,
@@ -65,7 +104,7 @@ where
                     .context(ErrorKind::EncryptionOperation(
                         EncryptionOperation::GetTrustBundle,
                     ))?;
-                Ok(response)
+                with_cache_headers(response, &served)
             })
             .or_else(|e| Ok(e.into_response()))
             .into_future();
@@ -74,16 +113,112 @@ where
     }
 }
 
+// Records `bundle` as served, keeping when it was first served if it is the
+// bundle that was served last.
+fn serve(served: &Mutex<Option<Served>>, bundle: &[u8]) -> Served {
+    let etag = format!("\"{}\"", base64::encode(&Sha256::digest(bundle)));
+    let mut served = served.lock().expect("trust bundle lock poisoned");
+    match &*served {
+        Some(last) if last.etag == etag => last.clone(),
+        _ => {
+            let current = Served {
+                etag,
+                since: Utc::now(),
+            };
+            *served = Some(current.clone());
+            current
+        }
+    }
+}
+
+fn with_cache_headers(
+    mut response: Response<Body>,
+    served: &Served,
+) -> Result<Response<Body>, Error> {
+    let context = ErrorKind::EncryptionOperation(EncryptionOperation::GetTrustBundle);
+    let headers = response.headers_mut();
+    headers.insert(
+        ETAG,
+        HeaderValue::from_str(&served.etag).context(context.clone())?,
+    );
+    headers.insert(
+        LAST_MODIFIED,
+        HeaderValue::from_str(&http_date(served.since)).context(context.clone())?,
+    );
+    headers.insert(
+        CACHE_CONTROL,
+        HeaderValue::from_str(&format!("max-age={}", MAX_AGE_SECS)).context(context)?,
+    );
+    Ok(response)
+}
+
+// As RFC 7232 has it, `If-Modified-Since` only counts without `If-None-Match`.
+fn is_unchanged(headers: &HeaderMap, served: &Served) -> bool {
+    if let Some(if_none_match) = headers.get(IF_NONE_MATCH) {
+        return if_none_match.to_str().ok().map_or(false, |tags| {
+            tags.split(',')
+                .map(str::trim)
+                .any(|tag| tag == "*" || tag.trim_start_matches("W/") == served.etag)
+        });
+    }
+
+    headers
+        .get(IF_MODIFIED_SINCE)
+        .and_then(|since| since.to_str().ok())
+        .and_then(|since| DateTime::parse_from_rfc2822(since).ok())
+        // HTTP dates have no fraction of a second
+        .map_or(false, |since| served.since.timestamp() <= since.timestamp())
+}
+
+fn http_date(time: DateTime<Utc>) -> String {
+    time.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
 #[cfg(test)]
 mod tests {
     use futures::Future;
     use futures::Stream;
 
+    use edgelet_core::Error as CoreError;
     use edgelet_test_utils::cert::TestCert;
     use edgelet_test_utils::crypto::TestHsm;
 
     use super::*;
 
+    // An HSM whose trust bundle can be rotated.
+    #[derive(Clone)]
+    struct RotatingHsm(Arc<Mutex<Vec<u8>>>);
+
+    impl RotatingHsm {
+        fn rotate(&self, bundle: &[u8]) {
+            *self.0.lock().unwrap() = bundle.to_vec();
+        }
+    }
+
+    impl GetTrustBundle for RotatingHsm {
+        type Certificate = TestCert;
+
+        fn get_trust_bundle(&self) -> Result<Self::Certificate, CoreError> {
+            Ok(TestCert::default().with_cert(self.0.lock().unwrap().clone()))
+        }
+    }
+
+    fn get<T>(handler: &TrustBundleHandler<T>, headers: &[(&str, &str)]) -> Response<Body>
+    where
+        T: 'static + GetTrustBundle + Send,
+    {
+        let mut request = Request::get("http://localhost/trust-bundle");
+        for (name, value) in headers {
+            request.header(*name, *value);
+        }
+        let request = request.body("".into()).unwrap();
+        handler.handle(request, Parameters::new()).wait().unwrap()
+    }
+
+    fn header<'a>(response: &'a Response<Body>, name: &str) -> &'a str {
+        response.headers().get(name).unwrap().to_str().unwrap()
+    }
+
     #[test]
     fn get_fail() {
         let handler = TrustBundleHandler::new(TestHsm::default().with_fail_call(true));
@@ -128,6 +263,8 @@ mod tests {
             .unwrap();
         let response = handler.handle(request, Parameters::new()).wait().unwrap();
         assert_eq!(StatusCode::OK, response.status());
+        assert_eq!("max-age=60", header(&response, "cache-control"));
+        assert!(header(&response, "etag").starts_with('"'));
 
         let content_length = {
             let headers = response.headers();
@@ -153,4 +290,62 @@ mod tests {
             .wait()
             .unwrap();
     }
+
+    #[test]
+    fn unchanged_bundle_is_not_modified() {
+        let hsm = RotatingHsm(Arc::new(Mutex::new(b"ca1".to_vec())));
+        let handler = TrustBundleHandler::new(hsm);
+
+        let response = get(&handler, &[]);
+        assert_eq!(StatusCode::OK, response.status());
+        let etag = header(&response, "etag").to_string();
+        let last_modified = header(&response, "last-modified").to_string();
+
+        let response = get(&handler, &[("if-none-match", &etag)]);
+        assert_eq!(StatusCode::NOT_MODIFIED, response.status());
+        assert_eq!(etag, header(&response, "etag"));
+        assert_eq!("max-age=60", header(&response, "cache-control"));
+        let body = response.into_body().concat2().wait().unwrap();
+        assert!(body.is_empty());
+
+        let weak = format!("\"other\", W/{}", etag);
+        let response = get(&handler, &[("if-none-match", &weak)]);
+        assert_eq!(StatusCode::NOT_MODIFIED, response.status());
+
+        let response = get(&handler, &[("if-modified-since", &last_modified)]);
+        assert_eq!(StatusCode::NOT_MODIFIED, response.status());
+        assert_eq!(last_modified, header(&response, "last-modified"));
+    }
+
+    #[test]
+    fn rotated_bundle_is_served_again() {
+        let hsm = RotatingHsm(Arc::new(Mutex::new(b"ca1".to_vec())));
+        let handler = TrustBundleHandler::new(hsm.clone());
+
+        let response = get(&handler, &[]);
+        let etag = header(&response, "etag").to_string();
+
+        hsm.rotate(b"ca2");
+
+        let response = get(&handler, &[("if-none-match", &etag)]);
+        assert_eq!(StatusCode::OK, response.status());
+        assert_ne!(etag, header(&response, "etag"));
+        let body = response.into_body().concat2().wait().unwrap();
+        let trust_bundle: TrustBundleResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!("ca2", trust_bundle.certificate().as_str());
+    }
+
+    #[test]
+    fn stale_if_modified_since_gets_the_bundle() {
+        let handler = TrustBundleHandler::new(
+            TestHsm::default().with_cert(TestCert::default().with_cert(b"boo".to_vec())),
+        );
+
+        let response = get(
+            &handler,
+            &[("if-modified-since", "Tue, 15 Nov 1994 08:12:31 GMT")],
+        );
+
+        assert_eq!(StatusCode::OK, response.status());
+    }
 }
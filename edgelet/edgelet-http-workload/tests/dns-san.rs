@@ -33,7 +33,7 @@ use edgelet_core::{
     ModuleStatus, WorkloadConfig, IOTEDGED_CA_ALIAS,
 };
 use edgelet_hsm::{Crypto, HsmLock};
-use edgelet_http_workload::WorkloadService;
+use edgelet_http_workload::{CertExpiryMonitor, ServerCertRotator, WorkloadService};
 use edgelet_test_utils::crypto::TestHsm;
 use edgelet_test_utils::module::{
     TestConfig, TestModule, TestProvisioningResult, TestRuntime, TestSettings,
@@ -181,9 +181,16 @@ fn create_workload_service(module_id: &str) -> (WorkloadService, Crypto) {
     };
 
     (
-        WorkloadService::new(&key_store, crypto.clone(), &runtime, config)
-            .wait()
-            .unwrap(),
+        WorkloadService::new(
+            &key_store,
+            crypto.clone(),
+            &runtime,
+            config,
+            CertExpiryMonitor::new(crypto.clone(), vec![IOTEDGED_CA_ALIAS.to_string()]),
+            ServerCertRotator::new(crypto.clone()),
+        )
+        .wait()
+        .unwrap(),
         crypto,
     )
 }
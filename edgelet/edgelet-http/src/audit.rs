@@ -0,0 +1,565 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! An append-only record of the calls that change the device, so that who
+//! created, updated or deleted a module, and when, can be established later.
+//!
+//! `AuditService` records every request that isn't a `GET`, `HEAD` or
+//! `OPTIONS`, including the ones that were rejected, with the identity that
+//! the server established for the caller: the credentials of its connection,
+//! its certificate, its pid and the module that it was found to be. Records
+//! only ever contain the method and path of a request and the name of the
+//! module it's for, never its query or body, so that the secrets in module
+//! specs and credentials don't end up in the audit log.
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+use std::sync::{Arc, Mutex};
+
+use chrono::{SecondsFormat, Utc};
+use failure::{Fail, ResultExt};
+use futures::future::Either;
+use futures::{future, Future, Stream};
+use hyper::service::{NewService, Service};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use log::warn;
+
+use edgelet_core::{AuditSettings, AuthId};
+
+use crate::authorization::Denied;
+use crate::error::{Error, ErrorKind};
+use crate::peer::{ClientCertificate, PeerCredentials};
+use crate::request_id::RequestId;
+use crate::{IntoResponse, Pid};
+
+#[cfg(unix)]
+const SYSLOG_SOCKET: &str = "/dev/log";
+const SYSLOG_TAG: &str = "iotedged-audit";
+const SYSLOG_SEVERITY_WARNING: u8 = 4;
+const SYSLOG_SEVERITY_NOTICE: u8 = 5;
+
+/// Where audit records are written. Clones write to the same sink.
+#[derive(Clone)]
+pub struct AuditLog {
+    sink: Option<Arc<Mutex<Sink>>>,
+}
+
+enum Sink {
+    File {
+        path: String,
+        file: File,
+    },
+    #[cfg(unix)]
+    Syslog {
+        socket: UnixDatagram,
+        facility: u8,
+    },
+}
+
+impl AuditLog {
+    /// Opens the sink of `settings`, so that a device whose audit log can't
+    /// be written doesn't start without one.
+    pub fn new(settings: &AuditSettings) -> Result<Self, Error> {
+        let sink = match settings {
+            AuditSettings::None => return Ok(AuditLog::disabled()),
+            AuditSettings::File { path } => {
+                let display = path.display().to_string();
+                let mut options = OpenOptions::new();
+                options.create(true).append(true);
+                // The log tells who manages the device, which is nobody
+                // else's business.
+                #[cfg(unix)]
+                options.mode(0o600);
+                let file = options
+                    .open(path)
+                    .with_context(|_| ErrorKind::AuditLog(display.clone()))?;
+                Sink::File {
+                    path: display,
+                    file,
+                }
+            }
+            #[cfg(unix)]
+            AuditSettings::Syslog { facility } => {
+                let socket = UnixDatagram::unbound()
+                    .and_then(|socket| socket.connect(SYSLOG_SOCKET).map(|()| socket))
+                    .with_context(|_| ErrorKind::AuditLog(SYSLOG_SOCKET.to_string()))?;
+                Sink::Syslog {
+                    socket,
+                    facility: facility.code(),
+                }
+            }
+            #[cfg(windows)]
+            AuditSettings::Syslog { .. } => {
+                return Err(Error::from(ErrorKind::AuditLog(
+                    "syslog, which isn't supported on Windows".to_string(),
+                )));
+            }
+        };
+
+        Ok(AuditLog {
+            sink: Some(Arc::new(Mutex::new(sink))),
+        })
+    }
+
+    pub fn disabled() -> Self {
+        AuditLog { sink: None }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.sink.is_some()
+    }
+
+    /// Writes `record`. A record that can't be written is logged rather than
+    /// failing the request it's for, which has already been handled.
+    pub fn record(&self, record: &AuditRecord) {
+        let sink = match &self.sink {
+            Some(sink) => sink,
+            None => return,
+        };
+        let line = match serde_json::to_string(record) {
+            Ok(line) => line,
+            Err(err) => {
+                warn!("Could not serialize audit record: {}", err);
+                return;
+            }
+        };
+
+        let mut sink = sink.lock().expect("audit log lock poisoned");
+        match &mut *sink {
+            Sink::File { path, file } => {
+                if let Err(err) = writeln!(file, "{}", line) {
+                    warn!("Could not write audit record to {}: {}", path, err);
+                }
+            }
+            #[cfg(unix)]
+            Sink::Syslog { socket, facility } => {
+                let severity = match record.result {
+                    AuditResult::Succeeded => SYSLOG_SEVERITY_NOTICE,
+                    AuditResult::Denied | AuditResult::Failed => SYSLOG_SEVERITY_WARNING,
+                };
+                let message = format!(
+                    "<{}>{}: {}",
+                    u16::from(*facility) * 8 + u16::from(severity),
+                    SYSLOG_TAG,
+                    line
+                );
+                if let Err(err) = socket.send(message.as_bytes()) {
+                    warn!("Could not write audit record to {}: {}", SYSLOG_SOCKET, err);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, serde_derive::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditResult {
+    Succeeded,
+    /// The caller wasn't allowed to make the request.
+    Denied,
+    Failed,
+}
+
+impl AuditResult {
+    fn of(response: &Response<Body>) -> Self {
+        let status = response.status();
+        if response.extensions().get::<Denied>().is_some()
+            || status == StatusCode::UNAUTHORIZED
+            || status == StatusCode::FORBIDDEN
+        {
+            AuditResult::Denied
+        } else if status.is_success() {
+            AuditResult::Succeeded
+        } else {
+            AuditResult::Failed
+        }
+    }
+}
+
+/// Who made a request, as far as the server could tell.
+#[derive(Clone, Debug, Default, serde_derive::Serialize)]
+pub struct Caller {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    peer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    certificate: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pid: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    module: Option<String>,
+}
+
+impl Caller {
+    fn of<B>(req: &Request<B>) -> Self {
+        let extensions = req.extensions();
+        Caller {
+            peer: extensions.get::<PeerCredentials>().map(ToString::to_string),
+            certificate: extensions
+                .get::<ClientCertificate>()
+                .map(|cert| cert.subject().to_string()),
+            pid: match extensions.get::<Pid>() {
+                Some(Pid::Value(pid)) => Some(*pid),
+                _ => None,
+            },
+            module: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, serde_derive::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditRecord {
+    time: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+    caller: Caller,
+    operation: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    module: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<u16>,
+    result: AuditResult,
+}
+
+#[derive(Clone)]
+pub struct AuditService<T> {
+    inner: T,
+    log: AuditLog,
+}
+
+impl<T> AuditService<T> {
+    pub fn new(inner: T, log: AuditLog) -> Self {
+        AuditService { inner, log }
+    }
+}
+
+impl<T> Service for AuditService<T>
+where
+    T: Service<ReqBody = Body, ResBody = Body> + Clone + Send + 'static,
+    <T as Service>::Future: Send + 'static,
+    <T as Service>::Error: Send + 'static,
+{
+    type ReqBody = Body;
+    type ResBody = Body;
+    type Error = T::Error;
+    type Future = Box<dyn Future<Item = Response<Body>, Error = Self::Error> + Send>;
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        if !self.log.is_enabled() || !is_mutating(req.method()) {
+            return Box::new(self.inner.call(req));
+        }
+
+        let log = self.log.clone();
+        let record = AuditRecord {
+            time: String::new(),
+            request_id: req
+                .extensions()
+                .get::<RequestId>()
+                .map(|id| id.as_str().to_string()),
+            caller: Caller::of(&req),
+            operation: format!("{} {}", req.method(), req.uri().path()),
+            module: path_module(req.uri().path()),
+            status: None,
+            result: AuditResult::Failed,
+        };
+
+        // Modules and identities that are created are only named in the
+        // request's body, which is read for their name and passed on.
+        if record.module.is_some() || req.method() != Method::POST || !names_in_body(&req) {
+            return Box::new(self.inner.call(req).then(move |result| {
+                finish(&log, record, &result);
+                result
+            }));
+        }
+
+        let mut inner = self.inner.clone();
+        let (parts, body) = req.into_parts();
+        let response = body.concat2().then(move |body| match body {
+            Ok(body) => {
+                let record = AuditRecord {
+                    module: body_module(&body),
+                    ..record
+                };
+                let req = Request::from_parts(parts, Body::from(body));
+                Either::A(inner.call(req).then(move |result| {
+                    finish(&log, record, &result);
+                    result
+                }))
+            }
+            Err(err) => {
+                let result = Ok(Error::from(err.context(ErrorKind::ServiceError)).into_response());
+                finish(&log, record, &result);
+                Either::B(future::result(result))
+            }
+        });
+        Box::new(response)
+    }
+}
+
+impl<T> NewService for AuditService<T>
+where
+    T: NewService,
+    <T as NewService>::Future: Send + 'static,
+    AuditService<<T as NewService>::Service>: Service,
+{
+    type ReqBody = <AuditService<<T as NewService>::Service> as Service>::ReqBody;
+    type ResBody = <AuditService<<T as NewService>::Service> as Service>::ResBody;
+    type Error = <AuditService<<T as NewService>::Service> as Service>::Error;
+    type Service = AuditService<<T as NewService>::Service>;
+    type Future = Box<dyn Future<Item = Self::Service, Error = Self::InitError> + Send>;
+    type InitError = <T as NewService>::InitError;
+
+    fn new_service(&self) -> Self::Future {
+        let log = self.log.clone();
+        Box::new(
+            self.inner
+                .new_service()
+                .map(|inner| AuditService { inner, log }),
+        )
+    }
+}
+
+// Completes `record` with how the request turned out and writes it.
+fn finish<E>(log: &AuditLog, mut record: AuditRecord, result: &Result<Response<Body>, E>) {
+    if let Ok(response) = result {
+        record.caller.module = match response.extensions().get::<AuthId>() {
+            Some(AuthId::Value(module)) => Some(module.to_string()),
+            _ => None,
+        };
+        record.status = Some(response.status().as_u16());
+        record.result = AuditResult::of(response);
+    }
+    record.time = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+    log.record(&record);
+}
+
+fn is_mutating(method: &Method) -> bool {
+    *method != Method::GET && *method != Method::HEAD && *method != Method::OPTIONS
+}
+
+// The module in paths like `/modules/{name}/restart` and `/identities/{name}`
+fn path_module(path: &str) -> Option<String> {
+    let mut segments = path.trim_start_matches('/').split('/');
+    match (segments.next(), segments.next()) {
+        (Some("modules"), Some(name)) | (Some("identities"), Some(name)) if !name.is_empty() => {
+            Some(name.to_string())
+        }
+        _ => None,
+    }
+}
+
+fn names_in_body<B>(req: &Request<B>) -> bool {
+    let path = req.uri().path().trim_end_matches('/');
+    path == "/modules" || path == "/identities"
+}
+
+// Only the name is deserialized, so nothing else of the body is kept.
+fn body_module(body: &[u8]) -> Option<String> {
+    #[derive(serde_derive::Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Named {
+        name: Option<String>,
+        module_id: Option<String>,
+    }
+
+    serde_json::from_slice::<Named>(body)
+        .ok()
+        .and_then(|named| named.name.or(named.module_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use futures::IntoFuture;
+    use hyper::Error as HyperError;
+    use serde_json::{json, Value};
+    use tempdir::TempDir;
+
+    use super::*;
+    #[cfg(unix)]
+    use crate::peer::{AllowedPeers, PeerAuthorizationService};
+
+    // Like `hyper::service::service_fn`, but `Clone` like the services that
+    // the audit log wraps.
+    #[derive(Clone)]
+    struct ServiceFn<F>(F);
+
+    fn service_fn<F, R>(f: F) -> ServiceFn<F>
+    where
+        F: Fn(Request<Body>) -> R,
+    {
+        ServiceFn(f)
+    }
+
+    impl<F, R> Service for ServiceFn<F>
+    where
+        F: Fn(Request<Body>) -> R,
+        R: IntoFuture<Item = Response<Body>, Error = HyperError>,
+    {
+        type ReqBody = Body;
+        type ResBody = Body;
+        type Error = HyperError;
+        type Future = R::Future;
+
+        fn call(&mut self, req: Request<Body>) -> Self::Future {
+            (self.0)(req).into_future()
+        }
+    }
+
+    fn file_log(dir: &TempDir) -> (AuditLog, std::path::PathBuf) {
+        let path = dir.path().join("audit.log");
+        let log = AuditLog::new(&AuditSettings::File { path: path.clone() }).unwrap();
+        (log, path)
+    }
+
+    fn records(path: &std::path::Path) -> Vec<Value> {
+        fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn create_is_recorded_without_secrets() {
+        let dir = TempDir::new("audit").unwrap();
+        let (log, path) = file_log(&dir);
+
+        let mut service = AuditService::new(
+            service_fn(|req: Request<Body>| {
+                req.into_body().concat2().map(|body| {
+                    // The handler still gets the whole body.
+                    let spec: Value = serde_json::from_slice(&body).unwrap();
+                    assert_eq!("hunter2", spec["config"]["env"]["PASSWORD"]["value"]);
+                    let mut response = Response::new(Body::empty());
+                    *response.status_mut() = StatusCode::CREATED;
+                    response
+                        .extensions_mut()
+                        .insert(AuthId::Value("edgeAgent".into()));
+                    response
+                })
+            }),
+            log,
+        );
+
+        let spec = json!({
+            "name": "tempSensor",
+            "type": "docker",
+            "config": {
+                "settings": { "image": "tempSensor:1.0" },
+                "env": { "PASSWORD": { "value": "hunter2" } },
+            },
+        });
+        let mut req = Request::post("http://localhost/modules?api-version=2019-11-05")
+            .body(Body::from(spec.to_string()))
+            .unwrap();
+        req.extensions_mut().insert(Pid::Value(4242));
+        req.extensions_mut()
+            .insert(ClientCertificate::new("CN=remote-admin".to_string()));
+        let response = service.call(req).wait().unwrap();
+        assert_eq!(StatusCode::CREATED, response.status());
+
+        // Reads aren't recorded.
+        let req = Request::get("http://localhost/modules")
+            .body(Body::empty())
+            .unwrap();
+        service.call(req).wait().unwrap();
+
+        let mut records = records(&path);
+        assert_eq!(1, records.len());
+        let record = records.remove(0);
+        assert!(record["time"].is_string());
+        assert_eq!(
+            json!({
+                "certificate": "CN=remote-admin",
+                "pid": 4242,
+                "module": "edgeAgent",
+            }),
+            record["caller"]
+        );
+        assert_eq!("POST /modules", record["operation"]);
+        assert_eq!("tempSensor", record["module"]);
+        assert_eq!(201, record["status"]);
+        assert_eq!("succeeded", record["result"]);
+        assert!(!fs::read_to_string(&path).unwrap().contains("hunter2"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejected_peer_is_recorded_as_denied() {
+        let dir = TempDir::new("audit").unwrap();
+        let (log, path) = file_log(&dir);
+
+        let mut service = AuditService::new(
+            PeerAuthorizationService::new(
+                service_fn(|_req: Request<Body>| Ok::<_, HyperError>(Response::new(Body::empty()))),
+                AllowedPeers::default().with_user("998").unwrap(),
+            ),
+            log,
+        );
+
+        let mut req = Request::delete("http://localhost/modules/tempSensor")
+            .body(Body::empty())
+            .unwrap();
+        req.extensions_mut().insert(PeerCredentials::Unix {
+            uid: 1000,
+            gid: 1000,
+        });
+        req.extensions_mut().insert(RequestId::new());
+        let response = service.call(req).wait().unwrap();
+        assert_eq!(StatusCode::FORBIDDEN, response.status());
+
+        let records = records(&path);
+        assert_eq!(1, records.len());
+        assert_eq!(json!({ "peer": "uid 1000 gid 1000" }), records[0]["caller"]);
+        assert_eq!("DELETE /modules/tempSensor", records[0]["operation"]);
+        assert_eq!("tempSensor", records[0]["module"]);
+        assert_eq!(403, records[0]["status"]);
+        assert_eq!("denied", records[0]["result"]);
+        assert!(records[0]["requestId"].is_string());
+    }
+
+    #[test]
+    fn records_are_appended() {
+        let dir = TempDir::new("audit").unwrap();
+        let (log, path) = file_log(&dir);
+        let mut service = AuditService::new(
+            service_fn(|_req: Request<Body>| Ok::<_, HyperError>(Response::new(Body::empty()))),
+            log,
+        );
+        let restart = || {
+            Request::post("http://localhost/modules/tempSensor/restart")
+                .body(Body::empty())
+                .unwrap()
+        };
+        service.call(restart()).wait().unwrap();
+
+        // A daemon that restarts adds to the log rather than replacing it.
+        let (log, _) = file_log(&dir);
+        service.log = log;
+        service.call(restart()).wait().unwrap();
+
+        let records = records(&path);
+        assert_eq!(2, records.len());
+        assert_eq!("POST /modules/tempSensor/restart", records[1]["operation"]);
+        assert_eq!("tempSensor", records[1]["module"]);
+    }
+
+    #[test]
+    fn module_is_found_in_path_or_body() {
+        assert_eq!(Some("a".to_string()), path_module("/modules/a/stop"));
+        assert_eq!(Some("b".to_string()), path_module("/identities/b"));
+        assert_eq!(None, path_module("/modules"));
+        assert_eq!(None, path_module("/device/reprovision"));
+
+        assert_eq!(
+            Some("c".to_string()),
+            body_module(br#"{"moduleId": "c", "managedBy": "IotEdge"}"#)
+        );
+        assert_eq!(None, body_module(b"not json"));
+    }
+}
@@ -13,6 +13,7 @@ use hyper::{Body, Request, Response};
 
 use edgelet_core::{AuthId, Authenticator, ModuleId, Policy};
 
+use crate::authorization::Denied;
 use crate::route::{Handler, Parameters};
 use crate::{Error, ErrorKind, IntoResponse};
 
@@ -59,14 +60,22 @@ where
 
         let inner = self.inner.clone();
 
+        // The caller's identity is also put into the response, so that the
+        // services the request went through can tell who made it.
         let response = authenticate.then(move |auth_id| match auth_id {
             Ok(auth_id) => {
-                req.extensions_mut().insert(auth_id);
-                future::Either::A(inner.handle(req, params))
+                req.extensions_mut().insert(auth_id.clone());
+                future::Either::A(inner.handle(req, params).map(move |mut response| {
+                    response.extensions_mut().insert(auth_id);
+                    response
+                }))
+            }
+            Err(err) => {
+                let mut response =
+                    Error::from(err.context(ErrorKind::Authorization)).into_response();
+                response.extensions_mut().insert(Denied);
+                future::Either::B(future::ok(response))
             }
-            Err(err) => future::Either::B(future::ok(
-                Error::from(err.context(ErrorKind::Authorization)).into_response(),
-            )),
         });
 
         Box::new(response)
@@ -81,6 +90,7 @@ mod tests {
     use edgelet_core::{AuthId, Authenticator, Error, ErrorKind, Policy};
 
     use crate::authentication::Authentication;
+    use crate::authorization::Denied;
     use crate::error::Error as HttpError;
     use crate::route::{Handler, Parameters};
 
@@ -114,6 +124,13 @@ mod tests {
         let auth = Authentication::new(inner, policy, runtime);
 
         let response = auth.handle(req, Parameters::new()).wait().unwrap();
+        assert_eq!(
+            Some("abc".to_string()),
+            response
+                .extensions()
+                .get::<AuthId>()
+                .map(ToString::to_string)
+        );
 
         let body = response
             .into_body()
@@ -155,6 +172,7 @@ mod tests {
         let response = auth.handle(req, Parameters::new()).wait().unwrap();
 
         assert_eq!(404, response.status());
+        assert_eq!(Some(&Denied), response.extensions().get::<Denied>());
     }
 
     #[derive(Clone)]
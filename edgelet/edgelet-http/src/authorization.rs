@@ -11,6 +11,11 @@ use edgelet_core::{AuthId, Policy};
 use crate::route::{Handler, Parameters};
 use crate::{Error, ErrorKind, IntoResponse};
 
+/// Put into the extensions of the response to a request that was rejected
+/// because of who made it, which is otherwise like any other `404 Not Found`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Denied;
+
 pub struct Authorization<H> {
     policy: Policy,
     inner: Arc<H>,
@@ -45,17 +50,17 @@ where
 
         let authorized = self.policy.authorize(name, auth_id);
 
-        let response = if authorized {
-            future::Either::A(
-                inner
-                    .handle(req, params)
-                    .then(|resp| resp.context(ErrorKind::Authorization).map_err(Error::from)),
-            )
-        } else {
-            future::Either::B(future::err(Error::from(ErrorKind::ModuleNotFound(
-                name.unwrap_or("").to_string(),
-            ))))
-        };
+        if !authorized {
+            let mut response =
+                Error::from(ErrorKind::ModuleNotFound(name.unwrap_or("").to_string()))
+                    .into_response();
+            response.extensions_mut().insert(Denied);
+            return Box::new(future::ok(response));
+        }
+
+        let response = inner
+            .handle(req, params)
+            .then(|resp| resp.context(ErrorKind::Authorization).map_err(Error::from));
 
         Box::new(response.or_else(|e| future::ok(e.into_response())))
     }
@@ -96,6 +101,7 @@ mod tests {
         let auth = Authorization::new(TestHandler::new(), Policy::Caller);
         let response = auth.handle(request, params).wait().unwrap();
         assert_eq!(404, response.status());
+        assert_eq!(Some(&Denied), response.extensions().get::<Denied>());
     }
 
     #[test]
@@ -2,6 +2,7 @@
 
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration as StdDuration;
 
 use chrono::{DateTime, Duration, Utc};
 use failure::{Fail, ResultExt};
@@ -15,6 +16,7 @@ use typed_headers::{http, mime, ContentLength, ContentType, HeaderMapExt};
 use url::form_urlencoded::Serializer as UrlSerializer;
 use url::Url;
 
+use edgelet_core::UpstreamEndpoints;
 use edgelet_utils::ensure_not_empty_with_context;
 
 use crate::error::{Error, ErrorKind};
@@ -62,6 +64,7 @@ pub struct Client<C, T> {
     api_version: String,
     host_name: Url,
     user_agent: Option<String>,
+    upstream: Option<UpstreamEndpoints>,
 }
 
 impl<C, T> Client<C, T>
@@ -86,6 +89,7 @@ where
             api_version,
             host_name,
             user_agent: None,
+            upstream: None,
         };
 
         Ok(client)
@@ -101,6 +105,13 @@ where
         self
     }
 
+    /// Sends requests to the active host of `upstream` instead of the host of
+    /// `host_name`, and reports to it whether it could be connected to.
+    pub fn with_upstream(mut self, upstream: UpstreamEndpoints) -> Self {
+        self.upstream = Some(upstream);
+        self
+    }
+
     pub fn inner(&self) -> &C {
         &self.inner
     }
@@ -117,6 +128,22 @@ where
         &self.host_name
     }
 
+    pub fn upstream(&self) -> Option<&UpstreamEndpoints> {
+        self.upstream.as_ref()
+    }
+
+    fn active_host_name(&self) -> Url {
+        let mut host_name = self.host_name.clone();
+        if let Some(upstream) = &self.upstream {
+            let active = upstream.active();
+            if host_name.set_host(Some(&active)).is_err() {
+                debug!("Could not send requests to invalid host {:?}", active);
+                return self.host_name.clone();
+            }
+        }
+        host_name
+    }
+
     fn add_sas_token(&self, req: &mut Request<Body>, path: &str) -> Result<(), Error> {
         if let Some(ref source) = self.token_source {
             let token_duration = Duration::hours(1);
@@ -162,9 +189,11 @@ where
 
         // build the full url
         let path_query = format!("{}?{}", path, query);
-        self.host_name
+        let host_name = self.active_host_name();
+        let upstream = self.upstream.clone();
+        host_name
             .join(&path_query)
-            .with_context(|_| ErrorKind::UrlJoin(self.host_name.clone(), path_query))
+            .with_context(|_| ErrorKind::UrlJoin(host_name.clone(), path_query))
             .context(ErrorKind::Http)
             .map_err(Error::from)
             .and_then(|url| {
@@ -203,19 +232,38 @@ where
             .map(|req| {
                 self.inner
                     .call(req)
-                    .then(|resp| resp.context(ErrorKind::Http).map_err(Error::from))
+                    .then(move |resp| {
+                        if let Some(upstream) = upstream {
+                            match &resp {
+                                Ok(_) => upstream.report_success(),
+                                Err(err) if err.is_connect() => upstream.report_failure(),
+                                Err(_) => (),
+                            }
+                        }
+                        resp.context(ErrorKind::Http).map_err(Error::from)
+                    })
                     .and_then(|resp| {
-                        let (http::response::Parts { status, .. }, body) = resp.into_parts();
+                        let (
+                            http::response::Parts {
+                                status, headers, ..
+                            },
+                            body,
+                        ) = resp.into_parts();
+                        let retry_after = headers
+                            .get(http::header::RETRY_AFTER)
+                            .and_then(|value| value.to_str().ok())
+                            .and_then(|value| parse_retry_after(value, Utc::now()));
                         body.concat2().then(move |res| {
                             let body = res.context(ErrorKind::Http)?;
-                            Ok((status, body))
+                            Ok((status, retry_after, body))
                         })
                     })
-                    .and_then(|(status, body)| {
+                    .and_then(|(status, retry_after, body)| {
                         if status.is_success() {
                             Ok(body)
                         } else {
-                            Err(Error::http_with_error_response(status, &*body))
+                            Err(Error::http_with_error_response(status, &*body)
+                                .with_retry_after(retry_after))
                         }
                     })
                     .and_then(|body| {
@@ -234,6 +282,21 @@ where
     }
 }
 
+/// Parses a `Retry-After` header, which is either a number of seconds or an
+/// HTTP date.
+fn parse_retry_after(value: &str, now: DateTime<Utc>) -> Option<StdDuration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(StdDuration::from_secs(secs));
+    }
+
+    DateTime::parse_from_rfc2822(value).ok().map(|date| {
+        (date.with_timezone(&Utc) - now)
+            .to_std()
+            .unwrap_or_else(|_| StdDuration::from_secs(0))
+    })
+}
+
 impl<C, T> Clone for Client<C, T>
 where
     T: TokenSource + Clone,
@@ -245,6 +308,7 @@ where
             api_version: self.api_version.clone(),
             host_name: self.host_name.clone(),
             user_agent: self.user_agent.clone(),
+            upstream: self.upstream.clone(),
         }
     }
 }
@@ -262,6 +326,8 @@ mod tests {
     use typed_headers::{mime, ContentType};
     use url::form_urlencoded::parse as parse_query;
 
+    use edgelet_core::UpstreamSettings;
+
     use crate::error::ErrorKind;
 
     struct StaticTokenSource {
@@ -446,6 +512,42 @@ mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn request_goes_to_active_upstream() {
+        let api_version = "2018-04-10".to_string();
+        let host_name = Url::parse("https://primary.azure-devices.net").unwrap();
+        let response = r#""response""#;
+        let token_source: Option<StaticTokenSource> = None;
+        let upstream = UpstreamEndpoints::new(
+            "primary.azure-devices.net".to_string(),
+            &UpstreamSettings::new(
+                vec!["secondary.azure-devices.net".to_string()],
+                1,
+                StdDuration::from_secs(60),
+                StdDuration::from_secs(600),
+            ),
+        );
+        upstream.report_failure();
+
+        let handler = move |req: Request<Body>| {
+            assert_eq!(Some("secondary.azure-devices.net"), req.uri().host());
+            assert_eq!(Some("https"), req.uri().scheme_str());
+            Ok(Response::new(response.into()))
+        };
+        let client = Client::new(handler, token_source, api_version, host_name.clone())
+            .unwrap()
+            .with_upstream(upstream);
+
+        let task = client.request::<String, String>(Method::GET, "/boo", None, None, false);
+
+        let _result: String = tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task)
+            .unwrap()
+            .unwrap();
+        assert_eq!(&host_name, client.host_name());
+    }
+
     #[test]
     fn request_adds_sas_token() {
         let api_version = "2018-04-10".to_string();
@@ -604,4 +706,54 @@ mod tests {
             .unwrap();
         assert_eq!(result, "response");
     }
+
+    #[test]
+    fn error_response_has_retry_after() {
+        let api_version = "2018-04-10".to_string();
+        let host_name = Url::parse("http://localhost").unwrap();
+        let token_source: Option<StaticTokenSource> = None;
+
+        let handler = |_req: Request<Body>| {
+            Ok(Response::builder()
+                .status(hyper::StatusCode::TOO_MANY_REQUESTS)
+                .header(hyper::header::RETRY_AFTER, "5")
+                .body(Body::empty())
+                .unwrap())
+        };
+        let client = Client::new(handler, token_source, api_version, host_name).unwrap();
+
+        let task = client.request::<(), String>(Method::GET, "/boo", None, None, false);
+
+        let err = tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task)
+            .unwrap_err();
+        assert_eq!(
+            &ErrorKind::HttpWithErrorResponse(hyper::StatusCode::TOO_MANY_REQUESTS, String::new()),
+            err.kind()
+        );
+        assert_eq!(Some(StdDuration::from_secs(5)), err.retry_after());
+    }
+
+    #[test]
+    fn parse_retry_after_success() {
+        let now = DateTime::parse_from_rfc3339("2015-10-21T07:28:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        assert_eq!(
+            Some(StdDuration::from_secs(120)),
+            parse_retry_after("120", now)
+        );
+        assert_eq!(
+            Some(StdDuration::from_secs(30)),
+            parse_retry_after("Wed, 21 Oct 2015 07:28:30 GMT", now)
+        );
+        // a date in the past means retry now
+        assert_eq!(
+            Some(StdDuration::from_secs(0)),
+            parse_retry_after("Wed, 21 Oct 2015 07:27:00 GMT", now)
+        );
+        assert_eq!(None, parse_retry_after("soon", now));
+    }
 }
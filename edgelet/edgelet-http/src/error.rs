@@ -3,6 +3,7 @@
 use std::fmt::{self, Display};
 use std::net::SocketAddr;
 use std::str;
+use std::time::Duration;
 
 use failure::{Backtrace, Compat, Context, Fail};
 use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
@@ -11,15 +12,21 @@ use serde_json::json;
 use systemd::Fd;
 use url::Url;
 
+use edgelet_core::{redact_failure, ErrorCode, ErrorCodeKind};
+
 use crate::IntoResponse;
 
 #[derive(Debug)]
 pub struct Error {
     inner: Context<ErrorKind>,
+    retry_after: Option<Duration>,
 }
 
 #[derive(Debug, Fail, PartialEq)]
 pub enum ErrorKind {
+    #[fail(display = "Could not open the audit log {}", _0)]
+    AuditLog(String),
+
     #[fail(display = "An error occurred while authorizing the HTTP request")]
     Authorization,
 
@@ -44,6 +51,15 @@ pub enum ErrorKind {
     #[fail(display = "A valid certificate was not found")]
     CertificateNotFound,
 
+    #[fail(display = "The client certificate {} has been revoked", _0)]
+    CertificateRevoked(String),
+
+    #[fail(
+        display = "Could not load the certificate revocation list {}, which must be issued by a client CA",
+        _0
+    )]
+    CertificateRevocationList(String),
+
     #[fail(display = "Could not perform HTTP request")]
     Http,
 
@@ -84,6 +100,15 @@ pub enum ErrorKind {
     #[fail(display = "Module not found")]
     ModuleNotFound(String),
 
+    #[fail(
+        display = "Could not reach the OCSP responder to check whether the client certificate {} has been revoked",
+        _0
+    )]
+    OcspUnreachable(String),
+
+    #[fail(display = "Clients with {} may not use this API", _0)]
+    PeerNotAllowed(String),
+
     #[fail(display = "An error occurred for path {}", _0)]
     Path(String),
 
@@ -96,21 +121,46 @@ pub enum ErrorKind {
     )]
     PKCS12Identity(String),
 
+    #[fail(
+        display = "Could not check whether the client certificate {} has been revoked",
+        _0
+    )]
+    RevocationCheck(String),
+
     #[fail(display = "An error occurred in the service")]
     ServiceError,
 
+    #[fail(
+        display = "Socket {} has {} but is expected to have {}",
+        path, actual, expected
+    )]
+    SocketOwnership {
+        path: String,
+        expected: String,
+        actual: String,
+    },
+
     #[fail(display = "An error occurred configuring the TLS stack")]
     TlsBootstrapError,
 
     #[fail(display = "An error occurred during creation of the TLS identity from cert")]
     TlsIdentityCreationError,
 
+    #[fail(display = "The TLS handshake with a client failed")]
+    TlsHandshake,
+
     #[fail(display = "Token source error")]
     TokenSource,
 
     #[fail(display = "Could not parse trust bundle")]
     TrustBundle,
 
+    #[fail(display = "Group {:?} could not be found", _0)]
+    UnknownGroup(String),
+
+    #[fail(display = "User {:?} could not be found", _0)]
+    UnknownUser(String),
+
     #[fail(
         display = "Could not form well-formed URL by joining {:?} with {:?}",
         _0, _1
@@ -150,39 +200,67 @@ impl Error {
 
         kind.into()
     }
+
+    /// Sets how long the server asked the client to wait before retrying the
+    /// request, from the response's `Retry-After` header.
+    pub fn with_retry_after(mut self, retry_after: Option<Duration>) -> Self {
+        self.retry_after = retry_after;
+        self
+    }
+
+    pub fn retry_after(&self) -> Option<Duration> {
+        self.retry_after
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        self.kind().error_code().unwrap_or(ErrorCode::Internal)
+    }
+}
+
+impl ErrorCodeKind for ErrorKind {
+    fn error_code(&self) -> Option<ErrorCode> {
+        match self {
+            ErrorKind::Authorization => Some(ErrorCode::Unauthorized),
+            ErrorKind::InvalidApiVersion(_) => Some(ErrorCode::UnsupportedApiVersion),
+            ErrorKind::ModuleNotFound(_) => Some(ErrorCode::ModuleNotFound),
+            ErrorKind::PeerNotAllowed(_) => Some(ErrorCode::Forbidden),
+            _ => None,
+        }
+    }
 }
 
 impl From<ErrorKind> for Error {
     fn from(kind: ErrorKind) -> Self {
         Error {
             inner: Context::new(kind),
+            retry_after: None,
         }
     }
 }
 
 impl From<Context<ErrorKind>> for Error {
     fn from(inner: Context<ErrorKind>) -> Self {
-        Error { inner }
+        Error {
+            inner,
+            retry_after: None,
+        }
     }
 }
 
 impl IntoResponse for Error {
     fn into_response(self) -> Response<Body> {
-        let mut fail: &dyn Fail = &self;
-        let mut message = self.to_string();
-        while let Some(cause) = fail.cause() {
-            message.push_str(&format!("\n\tcaused by: {}", cause.to_string()));
-            fail = cause;
-        }
+        let message = redact_failure(&self);
 
         let status_code = match *self.kind() {
             ErrorKind::Authorization | ErrorKind::ModuleNotFound(_) => StatusCode::NOT_FOUND,
             ErrorKind::InvalidApiVersion(_) => StatusCode::BAD_REQUEST,
+            ErrorKind::PeerNotAllowed(_) => StatusCode::FORBIDDEN,
             _ => StatusCode::INTERNAL_SERVER_ERROR,
         };
 
         let body = json!({
             "message": message,
+            "code": self.code(),
         })
         .to_string();
 
@@ -218,10 +296,12 @@ impl Display for BindListenerType {
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum InvalidUrlReason {
+    ClientAuthWithoutTls,
     FdNeitherNumberNorName,
     FileNotFound,
     InvalidScheme,
     InvalidCredentials,
+    InvalidPipePath,
     NoAddress,
     NoHost,
     UnrecognizedSocket,
@@ -230,6 +310,10 @@ pub enum InvalidUrlReason {
 impl Display for InvalidUrlReason {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            InvalidUrlReason::ClientAuthWithoutTls => write!(
+                f,
+                "URL must use the https scheme to authenticate clients by their certificate"
+            ),
             InvalidUrlReason::FdNeitherNumberNorName => {
                 write!(f, "URL could not be parsed as fd number nor fd name")
             }
@@ -238,6 +322,9 @@ impl Display for InvalidUrlReason {
             InvalidUrlReason::InvalidCredentials => {
                 write!(f, "Username or password could not be parsed from URL")
             }
+            InvalidUrlReason::InvalidPipePath => {
+                write!(f, "URL path is not of the form /pipe/<name>")
+            }
             InvalidUrlReason::NoAddress => write!(f, "URL has no address"),
             InvalidUrlReason::NoHost => write!(f, "URL has no host"),
             InvalidUrlReason::UnrecognizedSocket => {
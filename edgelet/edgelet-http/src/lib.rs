@@ -17,9 +17,10 @@ use std::fmt;
 use std::fmt::{Debug, Formatter};
 #[cfg(target_os = "linux")]
 use std::net;
-use std::net::ToSocketAddrs;
 #[cfg(target_os = "linux")]
 use std::os::unix::io::FromRawFd;
+#[cfg(unix)]
+use std::path::Path;
 #[cfg(windows)]
 use std::sync::Arc;
 #[cfg(unix)]
@@ -36,26 +37,39 @@ use native_tls::Identity;
 use native_tls::TlsAcceptor;
 use openssl::pkcs12::Pkcs12;
 use openssl::pkey::PKey;
+#[cfg(unix)]
+use openssl::ssl::{SslAcceptor, SslFiletype, SslMethod, SslVerifyMode, SslVersion};
 use openssl::stack::Stack;
+#[cfg(unix)]
+use openssl::x509::X509Name;
 use openssl::x509::X509;
 #[cfg(target_os = "linux")]
 use systemd::Socket;
 use tokio::net::TcpListener;
+#[cfg(windows)]
+use tokio_named_pipe::PipeListener;
 #[cfg(target_os = "linux")]
 use tokio_uds::UnixListener;
 use url::Url;
 
 use edgelet_core::crypto::{Certificate, CreateCertificate, KeyBytes, PrivateKey};
-use edgelet_core::{Protocol, UrlExt, UNIX_SCHEME};
+use edgelet_core::{Protocol, UNIX_SCHEME};
 use edgelet_utils::log_failure;
 
+pub mod audit;
 pub mod authentication;
 pub mod authorization;
 pub mod certificate_manager;
 pub mod client;
 pub mod error;
+mod listener;
 pub mod logging;
+pub mod metrics;
+pub mod peer;
 mod pid;
+pub mod request_id;
+#[cfg(unix)]
+mod revocation;
 pub mod route;
 mod unix;
 mod util;
@@ -63,12 +77,18 @@ mod version;
 
 pub use certificate_manager::CertificateManager;
 pub use error::{BindListenerType, Error, ErrorKind, InvalidUrlReason};
+pub use listener::{ClientAuth, ListenAddress, Listener, OcspResponder, SocketPermissions};
 pub use pid::Pid;
+pub use request_id::{RequestId, RequestIdService, REQUEST_ID_HEADER};
+#[cfg(unix)]
+pub use unix::check_socket_ownership;
 pub use util::proxy::MaybeProxyClient;
 pub use util::UrlConnector;
 pub use version::{Version, API_VERSION};
 
 use crate::pid::PidService;
+#[cfg(unix)]
+use crate::revocation::RevocationCheck;
 use crate::util::incoming::Incoming;
 
 const HTTP_SCHEME: &str = "http";
@@ -232,6 +252,18 @@ where
 
             debug!("accepted new connection ({})", addr);
             let pid = socket.pid()?;
+            // Drop the connection rather than let it through unchecked
+            let credentials = match socket.credentials() {
+                Ok(credentials) => credentials,
+                Err(err) => {
+                    error!(
+                        "could not get the credentials of connection ({}): {}",
+                        addr, err
+                    );
+                    return Ok(());
+                }
+            };
+            let client_certificate = socket.client_certificate();
             let fut = new_service
                 .new_service()
                 .then(move |srv| match srv {
@@ -243,7 +275,10 @@ where
                     }
                 })
                 .and_then(move |(srv, addr)| {
-                    let service = PidService::new(pid, srv);
+                    let mut service = PidService::new(pid, credentials, srv);
+                    if let Some(client_certificate) = client_certificate {
+                        service = service.with_client_certificate(client_certificate);
+                    }
                     protocol
                         .serve_connection(socket, service)
                         .then(move |result| match result {
@@ -279,8 +314,12 @@ where
         match &self.incoming {
             Incoming::Tcp(listener) => listener.local_addr().ok().map(|addr| addr.port()),
             #[cfg(unix)]
-            Incoming::Tls(listener, _, _) => listener.local_addr().ok().map(|addr| addr.port()),
+            Incoming::Tls(listener, _, _) | Incoming::MutualTls(listener, _, _, _) => {
+                listener.local_addr().ok().map(|addr| addr.port())
+            }
             Incoming::Unix(_) => None,
+            #[cfg(windows)]
+            Incoming::Pipe(_) => None,
         }
     }
 }
@@ -295,11 +334,19 @@ pub trait HyperExt {
     where
         C: CreateCertificate + Clone,
         S: NewService<ReqBody = Body>;
+
+    fn bind_listener<C, S>(
+        &self,
+        listener: &Listener,
+        new_service: S,
+        cert_manager: Option<TlsAcceptorParams<'_, C>>,
+    ) -> Result<Server<S>, Error>
+    where
+        C: CreateCertificate + Clone,
+        S: NewService<ReqBody = Body>;
 }
 
-// This variable is used on Unix but not Windows
 impl HyperExt for Http {
-    #[cfg_attr(not(unix), allow(unused_variables))]
     fn bind_url<C, S>(
         &self,
         url: Url,
@@ -310,36 +357,49 @@ impl HyperExt for Http {
         C: CreateCertificate + Clone,
         S: NewService<ReqBody = Body>,
     {
-        let incoming = match url.scheme() {
-            HTTP_SCHEME | TCP_SCHEME => {
-                let addr = url
-                    .to_socket_addrs()
-                    .context(ErrorKind::InvalidUrl(url.to_string()))?
-                    .next()
-                    .ok_or_else(|| {
-                        ErrorKind::InvalidUrlWithReason(
-                            url.to_string(),
-                            InvalidUrlReason::NoAddress,
-                        )
-                    })?;
+        self.bind_listener(&Listener::parse(&url)?, new_service, tls_params)
+    }
 
-                let listener = TcpListener::bind(&addr)
-                    .with_context(|_| ErrorKind::BindListener(BindListenerType::Address(addr)))?;
+    // This variable is used on Unix but not Windows
+    #[cfg_attr(not(unix), allow(unused_variables))]
+    fn bind_listener<C, S>(
+        &self,
+        listener: &Listener,
+        new_service: S,
+        tls_params: Option<TlsAcceptorParams<'_, C>>,
+    ) -> Result<Server<S>, Error>
+    where
+        C: CreateCertificate + Clone,
+        S: NewService<ReqBody = Body>,
+    {
+        if listener.client_auth().is_some() && !listener.is_tls() {
+            return Err(Error::from(ErrorKind::InvalidUrlWithReason(
+                listener.url().to_string(),
+                InvalidUrlReason::ClientAuthWithoutTls,
+            )));
+        }
+
+        let incoming = match listener.address() {
+            ListenAddress::Tcp(addr) => {
+                let listener = TcpListener::bind(addr)
+                    .with_context(|_| ErrorKind::BindListener(BindListenerType::Address(*addr)))?;
                 Incoming::Tcp(listener)
             }
             #[cfg(unix)]
-            HTTPS_SCHEME => {
-                let addr = url
-                    .to_socket_addrs()
-                    .context(ErrorKind::InvalidUrl(url.to_string()))?
-                    .next()
-                    .ok_or_else(|| {
-                        ErrorKind::InvalidUrlWithReason(
-                            url.to_string(),
-                            InvalidUrlReason::NoAddress,
-                        )
-                    })?;
+            ListenAddress::Tls(addr) if listener.client_auth().is_some() => {
+                let client_auth = listener.client_auth().expect("checked by the guard");
+                let min_protocol_version = tls_params
+                    .as_ref()
+                    .map(|params| params.min_protocol_version);
+                let acceptor = mutual_tls_acceptor(client_auth, min_protocol_version)?;
+                let revocation_check = Arc::new(RevocationCheck::new(client_auth)?);
 
+                let listener = TcpListener::bind(addr)
+                    .with_context(|_| ErrorKind::BindListener(BindListenerType::Address(*addr)))?;
+                Incoming::MutualTls(listener, acceptor, revocation_check, Mutex::new(vec![]))
+            }
+            #[cfg(unix)]
+            ListenAddress::Tls(addr) => {
                 let cert = tls_params
                     .as_ref()
                     .map(|params| params.cert_manager.get_pkcs12_certificate())
@@ -365,22 +425,18 @@ impl HyperExt for Http {
                     .context(ErrorKind::TlsBootstrapError)?;
                 let tls_acceptor = tokio_tls::TlsAcceptor::from(tls_acceptor);
 
-                let listener = TcpListener::bind(&addr)
-                    .with_context(|_| ErrorKind::BindListener(BindListenerType::Address(addr)))?;
+                let listener = TcpListener::bind(addr)
+                    .with_context(|_| ErrorKind::BindListener(BindListenerType::Address(*addr)))?;
                 Incoming::Tls(listener, tls_acceptor, Mutex::new(vec![]))
             }
-            UNIX_SCHEME => {
-                let path = url
-                    .to_uds_file_path()
-                    .map_err(|_| ErrorKind::InvalidUrl(url.to_string()))?;
-                unix::listener(path)?
-            }
+            ListenAddress::Unix(path) => unix::listener(path, listener.socket_permissions())?,
+            #[cfg(windows)]
+            ListenAddress::Pipe(path) => Incoming::Pipe(
+                PipeListener::bind(path)
+                    .with_context(|_| ErrorKind::Path(path.display().to_string()))?,
+            ),
             #[cfg(target_os = "linux")]
-            FD_SCHEME => {
-                let host = url.host_str().ok_or_else(|| {
-                    ErrorKind::InvalidUrlWithReason(url.to_string(), InvalidUrlReason::NoHost)
-                })?;
-
+            ListenAddress::Fd(host) => {
                 // Try to parse the host as an FD number, then as an FD name
                 let socket = host
                     .parse()
@@ -389,7 +445,7 @@ impl HyperExt for Http {
                     .or_else(|_| systemd::listener_name(host))
                     .with_context(|_| {
                         ErrorKind::InvalidUrlWithReason(
-                            url.to_string(),
+                            listener.url().to_string(),
                             InvalidUrlReason::FdNeitherNumberNorName,
                         )
                     })?;
@@ -413,19 +469,13 @@ impl HyperExt for Http {
                     }
                     Socket::Unknown => {
                         return Err(ErrorKind::InvalidUrlWithReason(
-                            url.to_string(),
+                            listener.url().to_string(),
                             InvalidUrlReason::UnrecognizedSocket,
                         )
                         .into())
                     }
                 }
             }
-            _ => {
-                return Err(Error::from(ErrorKind::InvalidUrlWithReason(
-                    url.to_string(),
-                    InvalidUrlReason::InvalidScheme,
-                )))
-            }
         };
 
         Ok(Server {
@@ -436,6 +486,48 @@ impl HyperExt for Http {
     }
 }
 
+// Requires clients to present a certificate issued by one of the CAs in
+// `client_ca`, which are also sent to them as the acceptable issuers.
+#[cfg(unix)]
+fn mutual_tls_acceptor(
+    client_auth: &ClientAuth,
+    min_protocol_version: Option<Protocol>,
+) -> Result<SslAcceptor, Error> {
+    let path_context = |path: &Path| ErrorKind::Path(path.display().to_string());
+
+    let mut builder = SslAcceptor::mozilla_intermediate(SslMethod::tls())
+        .context(ErrorKind::TlsBootstrapError)?;
+    builder
+        .set_certificate_chain_file(client_auth.server_cert())
+        .with_context(|_| path_context(client_auth.server_cert()))?;
+    builder
+        .set_private_key_file(client_auth.server_key(), SslFiletype::PEM)
+        .with_context(|_| path_context(client_auth.server_key()))?;
+    builder
+        .check_private_key()
+        .context(ErrorKind::TlsIdentityCreationError)?;
+
+    builder
+        .set_ca_file(client_auth.client_ca())
+        .with_context(|_| path_context(client_auth.client_ca()))?;
+    builder.set_client_ca_list(
+        X509Name::load_client_ca_file(client_auth.client_ca())
+            .with_context(|_| path_context(client_auth.client_ca()))?,
+    );
+    builder.set_verify(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
+
+    let min_protocol_version = match min_protocol_version {
+        Some(Protocol::Tls10) => SslVersion::TLS1,
+        Some(Protocol::Tls11) => SslVersion::TLS1_1,
+        Some(Protocol::Tls12) | None => SslVersion::TLS1_2,
+    };
+    builder
+        .set_min_proto_version(Some(min_protocol_version))
+        .context(ErrorKind::TlsBootstrapError)?;
+
+    Ok(builder.build())
+}
+
 #[cfg_attr(not(unix), allow(dead_code))]
 pub struct TlsAcceptorParams<'a, C>
 where
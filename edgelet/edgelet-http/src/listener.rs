@@ -0,0 +1,406 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! The addresses that servers listen on, parsed from the URIs they are
+//! configured with:
+//!
+//! - `http://host:port` and `tcp://host:port` listen over TCP
+//! - `https://host:port` listens over TCP with TLS (Unix only), optionally
+//!   requiring clients to authenticate with a certificate
+//! - `unix:///path/to/sock` listens on a Unix domain socket
+//! - `npipe://./pipe/name` listens on the named pipe `\\.\pipe\name` (Windows only)
+//! - `fd://name` uses a socket activated by systemd (Linux only)
+
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use failure::ResultExt;
+use url::Url;
+
+use edgelet_core::{OcspFailurePolicy, UrlExt, UNIX_SCHEME};
+
+use crate::error::{Error, ErrorKind, InvalidUrlReason};
+#[cfg(target_os = "linux")]
+use crate::FD_SCHEME;
+#[cfg(unix)]
+use crate::HTTPS_SCHEME;
+#[cfg(windows)]
+use crate::PIPE_SCHEME;
+use crate::{HTTP_SCHEME, TCP_SCHEME};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ListenAddress {
+    Tcp(SocketAddr),
+    #[cfg(unix)]
+    Tls(SocketAddr),
+    Unix(PathBuf),
+    #[cfg(windows)]
+    Pipe(PathBuf),
+    /// The number or name of the socket
+    #[cfg(target_os = "linux")]
+    Fd(String),
+}
+
+/// The mode and group that a Unix domain socket is created with, so that
+/// only the members of the group can connect to it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SocketPermissions {
+    mode: u32,
+    group: Option<String>,
+}
+
+impl SocketPermissions {
+    pub fn new(mode: u32) -> Self {
+        SocketPermissions { mode, group: None }
+    }
+
+    pub fn with_group(mut self, group: String) -> Self {
+        self.group = Some(group);
+        self
+    }
+
+    pub fn mode(&self) -> u32 {
+        self.mode
+    }
+
+    pub fn group(&self) -> Option<&str> {
+        self.group.as_ref().map(AsRef::as_ref)
+    }
+}
+
+/// The certificates of a TLS listener that only accepts clients with a
+/// certificate issued by one of the CAs in `client_ca`. All of them are PEM
+/// files, and `server_cert` may be followed by the rest of its chain.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClientAuth {
+    server_cert: PathBuf,
+    server_key: PathBuf,
+    client_ca: PathBuf,
+    crl_file: Option<PathBuf>,
+    ocsp_responder: Option<OcspResponder>,
+}
+
+impl ClientAuth {
+    pub fn new(server_cert: PathBuf, server_key: PathBuf, client_ca: PathBuf) -> Self {
+        ClientAuth {
+            server_cert,
+            server_key,
+            client_ca,
+            crl_file: None,
+            ocsp_responder: None,
+        }
+    }
+
+    /// Rejects clients whose certificate is in the PEM or DER certificate
+    /// revocation list in `crl_file`, which must be issued by one of the CAs
+    /// in `client_ca`.
+    pub fn with_crl_file(mut self, crl_file: PathBuf) -> Self {
+        self.crl_file = Some(crl_file);
+        self
+    }
+
+    /// Rejects clients whose certificate `ocsp_responder` reports as revoked.
+    pub fn with_ocsp_responder(mut self, ocsp_responder: OcspResponder) -> Self {
+        self.ocsp_responder = Some(ocsp_responder);
+        self
+    }
+
+    pub fn server_cert(&self) -> &Path {
+        &self.server_cert
+    }
+
+    pub fn server_key(&self) -> &Path {
+        &self.server_key
+    }
+
+    pub fn client_ca(&self) -> &Path {
+        &self.client_ca
+    }
+
+    pub fn crl_file(&self) -> Option<&Path> {
+        self.crl_file.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn ocsp_responder(&self) -> Option<&OcspResponder> {
+        self.ocsp_responder.as_ref()
+    }
+}
+
+/// The OCSP responder that the certificates of clients are checked with
+/// after the TLS handshake. Requests are posted to `uri`, which must be an
+/// http URL, and a responder that hasn't answered after `timeout` is
+/// unreachable.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OcspResponder {
+    uri: Url,
+    on_unreachable: OcspFailurePolicy,
+    timeout: Duration,
+}
+
+impl OcspResponder {
+    pub fn new(uri: Url, on_unreachable: OcspFailurePolicy, timeout: Duration) -> Self {
+        OcspResponder {
+            uri,
+            on_unreachable,
+            timeout,
+        }
+    }
+
+    pub fn uri(&self) -> &Url {
+        &self.uri
+    }
+
+    pub fn on_unreachable(&self) -> OcspFailurePolicy {
+        self.on_unreachable
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Listener {
+    url: Url,
+    address: ListenAddress,
+    socket_permissions: Option<SocketPermissions>,
+    client_auth: Option<ClientAuth>,
+}
+
+impl Listener {
+    pub fn parse(url: &Url) -> Result<Self, Error> {
+        let address = match url.scheme() {
+            HTTP_SCHEME | TCP_SCHEME => ListenAddress::Tcp(socket_addr(url)?),
+            #[cfg(unix)]
+            HTTPS_SCHEME => ListenAddress::Tls(socket_addr(url)?),
+            UNIX_SCHEME => ListenAddress::Unix(
+                url.to_uds_file_path()
+                    .map_err(|_| ErrorKind::InvalidUrl(url.to_string()))?,
+            ),
+            #[cfg(windows)]
+            PIPE_SCHEME => ListenAddress::Pipe(pipe_path(url)?),
+            #[cfg(target_os = "linux")]
+            FD_SCHEME => ListenAddress::Fd(
+                url.host_str()
+                    .ok_or_else(|| {
+                        ErrorKind::InvalidUrlWithReason(url.to_string(), InvalidUrlReason::NoHost)
+                    })?
+                    .to_string(),
+            ),
+            _ => {
+                return Err(Error::from(ErrorKind::InvalidUrlWithReason(
+                    url.to_string(),
+                    InvalidUrlReason::InvalidScheme,
+                )))
+            }
+        };
+
+        Ok(Listener {
+            url: url.clone(),
+            address,
+            socket_permissions: None,
+            client_auth: None,
+        })
+    }
+
+    /// Applies `socket_permissions` to the socket if this listens on a Unix
+    /// domain socket. Otherwise the socket gets the permissions of the one it
+    /// replaces, if any.
+    pub fn with_socket_permissions(mut self, socket_permissions: SocketPermissions) -> Self {
+        self.socket_permissions = Some(socket_permissions);
+        self
+    }
+
+    /// Authenticates the server with its own certificate rather than one
+    /// from the certificate manager, and requires clients to present a
+    /// certificate. Binding fails unless this listens over TLS.
+    pub fn with_client_auth(mut self, client_auth: ClientAuth) -> Self {
+        self.client_auth = Some(client_auth);
+        self
+    }
+
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    pub fn address(&self) -> &ListenAddress {
+        &self.address
+    }
+
+    pub fn socket_permissions(&self) -> Option<&SocketPermissions> {
+        self.socket_permissions.as_ref()
+    }
+
+    pub fn client_auth(&self) -> Option<&ClientAuth> {
+        self.client_auth.as_ref()
+    }
+
+    pub(crate) fn is_tls(&self) -> bool {
+        #[cfg(unix)]
+        {
+            if let ListenAddress::Tls(_) = self.address {
+                return true;
+            }
+        }
+
+        false
+    }
+}
+
+fn socket_addr(url: &Url) -> Result<SocketAddr, Error> {
+    let addr = url
+        .to_socket_addrs()
+        .context(ErrorKind::InvalidUrl(url.to_string()))?
+        .next()
+        .ok_or_else(|| {
+            ErrorKind::InvalidUrlWithReason(url.to_string(), InvalidUrlReason::NoAddress)
+        })?;
+    Ok(addr)
+}
+
+// `npipe://./pipe/name` is the pipe `\\.\pipe\name`
+#[cfg(windows)]
+fn pipe_path(url: &Url) -> Result<PathBuf, Error> {
+    let host = url
+        .host_str()
+        .filter(|host| !host.trim().is_empty())
+        .ok_or_else(|| {
+            ErrorKind::InvalidUrlWithReason(url.to_string(), InvalidUrlReason::NoHost)
+        })?;
+    let name = url.path().trim_start_matches("/pipe/").to_string();
+    if !url.path().starts_with("/pipe/") || name.is_empty() {
+        return Err(Error::from(ErrorKind::InvalidUrlWithReason(
+            url.to_string(),
+            InvalidUrlReason::InvalidPipePath,
+        )));
+    }
+
+    Ok(PathBuf::from(format!(
+        r"\\{}\pipe\{}",
+        host,
+        name.replace("/", "\\")
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(url: &str) -> Result<Listener, Error> {
+        Listener::parse(&Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn parses_tcp() {
+        for url in &["http://127.0.0.1:8080", "tcp://127.0.0.1:8080"] {
+            let listener = parse(url).unwrap();
+            assert_eq!(
+                &ListenAddress::Tcp("127.0.0.1:8080".parse().unwrap()),
+                listener.address()
+            );
+            assert_eq!(None, listener.socket_permissions());
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn parses_tls() {
+        let listener = parse("https://127.0.0.1:8443").unwrap();
+        assert_eq!(
+            &ListenAddress::Tls("127.0.0.1:8443".parse().unwrap()),
+            listener.address()
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn parses_unix() {
+        let listener = parse("unix:///var/lib/iotedge/mgmt.sock").unwrap();
+        assert_eq!(
+            &ListenAddress::Unix(PathBuf::from("/var/lib/iotedge/mgmt.sock")),
+            listener.address()
+        );
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn parses_pipe() {
+        let listener = parse("npipe://./pipe/iotedge_mgmt").unwrap();
+        assert_eq!(
+            &ListenAddress::Pipe(PathBuf::from(r"\\.\pipe\iotedge_mgmt")),
+            listener.address()
+        );
+
+        for url in &["npipe://./iotedge_mgmt", "npipe://./pipe/"] {
+            let err = parse(url).unwrap_err();
+            assert_eq!(
+                &ErrorKind::InvalidUrlWithReason(
+                    (*url).to_string(),
+                    InvalidUrlReason::InvalidPipePath
+                ),
+                err.kind()
+            );
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn parses_fd() {
+        let listener = parse("fd://iotedge.mgmt.socket").unwrap();
+        assert_eq!(
+            &ListenAddress::Fd("iotedge.mgmt.socket".to_string()),
+            listener.address()
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_scheme() {
+        let err = parse("ftp://127.0.0.1:21").unwrap_err();
+        assert_eq!(
+            &ErrorKind::InvalidUrlWithReason(
+                "ftp://127.0.0.1:21".to_string(),
+                InvalidUrlReason::InvalidScheme
+            ),
+            err.kind()
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn socket_permissions() {
+        let listener = parse("unix:///var/lib/iotedge/mgmt.sock")
+            .unwrap()
+            .with_socket_permissions(SocketPermissions::new(0o660).with_group("iotedge".into()));
+        let permissions = listener.socket_permissions().unwrap();
+        assert_eq!(0o660, permissions.mode());
+        assert_eq!(Some("iotedge"), permissions.group());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn client_auth() {
+        let listener = parse("https://0.0.0.0:15580").unwrap();
+        assert_eq!(None, listener.client_auth());
+
+        let listener = listener.with_client_auth(ClientAuth::new(
+            "/etc/iotedge/mgmt/server.pem".into(),
+            "/etc/iotedge/mgmt/server.key.pem".into(),
+            "/etc/iotedge/mgmt/client-ca.pem".into(),
+        ));
+        let client_auth = listener.client_auth().unwrap();
+        assert_eq!(
+            Path::new("/etc/iotedge/mgmt/client-ca.pem"),
+            client_auth.client_ca()
+        );
+        assert_eq!(None, client_auth.crl_file());
+        assert_eq!(None, client_auth.ocsp_responder());
+
+        let client_auth = client_auth
+            .clone()
+            .with_crl_file("/etc/iotedge/mgmt/client-ca.crl".into());
+        assert_eq!(
+            Some(Path::new("/etc/iotedge/mgmt/client-ca.crl")),
+            client_auth.crl_file()
+        );
+    }
+}
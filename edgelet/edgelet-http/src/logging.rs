@@ -1,6 +1,8 @@
 // Copyright (c) Microsoft. All rights reserved.
 #![allow(deprecated)]
 
+use std::time::Instant;
+
 use chrono::prelude::*;
 use futures::prelude::*;
 use hyper::header::{CONTENT_LENGTH, USER_AGENT};
@@ -10,15 +12,29 @@ use log::info;
 
 use edgelet_core::AuthId;
 
+use crate::metrics::RequestMetrics;
+use crate::request_id::RequestId;
+
 #[derive(Clone)]
 pub struct LoggingService<T> {
     label: String,
     inner: T,
+    metrics: Option<RequestMetrics>,
 }
 
 impl<T> LoggingService<T> {
     pub fn new(label: String, inner: T) -> Self {
-        LoggingService { label, inner }
+        LoggingService {
+            label,
+            inner,
+            metrics: None,
+        }
+    }
+
+    /// Also records the latency of every request in `metrics`.
+    pub fn with_metrics(mut self, metrics: RequestMetrics) -> Self {
+        self.metrics = Some(metrics);
+        self
     }
 }
 
@@ -39,6 +55,9 @@ where
 
     fn call(&mut self, req: Request<Self::ReqBody>) -> Self::Future {
         let label = self.label.clone();
+        let metrics = self.metrics.clone();
+        let started = Instant::now();
+        let method = req.method().clone();
         let uri = req.uri().query().map_or_else(
             || req.uri().path().to_string(),
             |q| format!("{}?{}", req.uri().path(), q),
@@ -54,10 +73,18 @@ where
             .extensions()
             .get::<AuthId>()
             .map_or_else(|| "-".to_string(), ToString::to_string);
+        let request_id = req
+            .extensions()
+            .get::<RequestId>()
+            .map_or_else(|| "-".to_string(), ToString::to_string);
 
         let inner = self.inner.call(req);
 
         Box::new(inner.map(move |response| {
+            if let Some(metrics) = metrics {
+                metrics.observe(&label, &method, response.status(), started.elapsed());
+            }
+
             let body_length = response
                 .headers()
                 .get(CONTENT_LENGTH)
@@ -65,7 +92,7 @@ where
                 .unwrap_or_else(|| "-".to_string());
 
             info!(
-                "[{}] - - - [{}] \"{}\" {} {} \"-\" \"{}\" auth_id({})",
+                "[{}] - - - [{}] \"{}\" {} {} \"-\" \"{}\" auth_id({}) request_id({})",
                 label,
                 Utc::now(),
                 request,
@@ -73,6 +100,7 @@ where
                 body_length,
                 user_agent,
                 auth_id,
+                request_id,
             );
 
             response
@@ -96,10 +124,11 @@ where
 
     fn new_service(&self) -> Self::Future {
         let label = self.label.clone();
-        Box::new(
-            self.inner
-                .new_service()
-                .map(|inner| LoggingService { label, inner }),
-        )
+        let metrics = self.metrics.clone();
+        Box::new(self.inner.new_service().map(|inner| LoggingService {
+            label,
+            inner,
+            metrics,
+        }))
     }
 }
@@ -0,0 +1,275 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Metrics in the Prometheus text exposition format, and the latencies of the
+//! requests handled by the daemon's HTTP servers.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use hyper::{Method, StatusCode};
+
+/// The content type of the Prometheus text exposition format.
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+const REQUEST_DURATION: &str = "edgelet_http_request_duration_seconds";
+
+// Upper bounds, in seconds, of the request latency buckets
+const REQUEST_DURATION_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MetricType {
+    Counter,
+    Gauge,
+    Histogram,
+}
+
+impl MetricType {
+    fn as_str(self) -> &'static str {
+        match self {
+            MetricType::Counter => "counter",
+            MetricType::Gauge => "gauge",
+            MetricType::Histogram => "histogram",
+        }
+    }
+}
+
+/// Builds a scrape response. Each metric family is declared with `family`
+/// before its samples are added.
+#[derive(Debug, Default)]
+pub struct Exposition {
+    text: String,
+}
+
+impl Exposition {
+    pub fn new() -> Self {
+        Exposition::default()
+    }
+
+    pub fn family(&mut self, name: &str, help: &str, type_: MetricType) -> &mut Self {
+        let help = help.replace('\\', r"\\").replace('\n', r"\n");
+        let _ = writeln!(self.text, "# HELP {} {}", name, help);
+        let _ = writeln!(self.text, "# TYPE {} {}", name, type_.as_str());
+        self
+    }
+
+    pub fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: f64) -> &mut Self {
+        self.text.push_str(name);
+        if !labels.is_empty() {
+            let labels: Vec<_> = labels
+                .iter()
+                .map(|(label, value)| format!("{}=\"{}\"", label, escape_label_value(value)))
+                .collect();
+            let _ = write!(self.text, "{{{}}}", labels.join(","));
+        }
+        let _ = writeln!(self.text, " {}", format_value(value));
+        self
+    }
+
+    pub fn into_string(self) -> String {
+        self.text
+    }
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', "\\\"")
+        .replace('\n', r"\n")
+}
+
+fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() && value.is_sign_positive() {
+        "+Inf".to_string()
+    } else if value.is_infinite() {
+        "-Inf".to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+struct RequestKey {
+    server: String,
+    method: String,
+    status: u16,
+}
+
+#[derive(Clone, Debug, Default)]
+struct Histogram {
+    // Not cumulative; bucket i counts the observations that are no greater
+    // than its bound but greater than the one before it.
+    buckets: [u64; REQUEST_DURATION_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        if let Some(i) = REQUEST_DURATION_BUCKETS
+            .iter()
+            .position(|bound| value <= *bound)
+        {
+            self.buckets[i] += 1;
+        }
+        self.count += 1;
+        self.sum += value;
+    }
+}
+
+/// The latencies of the requests handled by the daemon's HTTP servers, by
+/// server, method and status code. Clones share the same latencies.
+#[derive(Clone, Debug, Default)]
+pub struct RequestMetrics {
+    requests: Arc<Mutex<BTreeMap<RequestKey, Histogram>>>,
+}
+
+impl RequestMetrics {
+    pub fn new() -> Self {
+        RequestMetrics::default()
+    }
+
+    pub fn observe(&self, server: &str, method: &Method, status: StatusCode, duration: Duration) {
+        let key = RequestKey {
+            server: server.to_string(),
+            method: method.to_string(),
+            status: status.as_u16(),
+        };
+        self.requests
+            .lock()
+            .expect("request metrics lock poisoned")
+            .entry(key)
+            .or_default()
+            .observe(duration.as_secs_f64());
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    pub fn write(&self, exposition: &mut Exposition) {
+        exposition.family(
+            REQUEST_DURATION,
+            "Latency of the requests handled by the edgelet",
+            MetricType::Histogram,
+        );
+
+        let requests = self.requests.lock().expect("request metrics lock poisoned");
+        for (key, histogram) in requests.iter() {
+            let status = key.status.to_string();
+            let labels = [
+                ("server", key.server.as_str()),
+                ("method", key.method.as_str()),
+                ("status", status.as_str()),
+            ];
+
+            let bucket_name = format!("{}_bucket", REQUEST_DURATION);
+            let mut cumulative = 0;
+            for (bound, count) in REQUEST_DURATION_BUCKETS.iter().zip(&histogram.buckets) {
+                cumulative += count;
+                let le = format_value(*bound);
+                exposition.sample(
+                    &bucket_name,
+                    &with_label(&labels, ("le", &le)),
+                    cumulative as f64,
+                );
+            }
+            exposition.sample(
+                &bucket_name,
+                &with_label(&labels, ("le", "+Inf")),
+                histogram.count as f64,
+            );
+            exposition.sample(&format!("{}_sum", REQUEST_DURATION), &labels, histogram.sum);
+            exposition.sample(
+                &format!("{}_count", REQUEST_DURATION),
+                &labels,
+                histogram.count as f64,
+            );
+        }
+    }
+}
+
+fn with_label<'a>(
+    labels: &[(&'a str, &'a str)],
+    label: (&'a str, &'a str),
+) -> Vec<(&'a str, &'a str)> {
+    let mut labels = labels.to_vec();
+    labels.push(label);
+    labels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exposition_escapes_labels_and_help() {
+        let mut exposition = Exposition::new();
+        exposition
+            .family(
+                "edgelet_test",
+                "A test\\metric\nwith two lines",
+                MetricType::Gauge,
+            )
+            .sample("edgelet_test", &[("module", "a\"b\\c\nd")], 1.5)
+            .sample("edgelet_test", &[], f64::INFINITY);
+
+        assert_eq!(
+            "# HELP edgelet_test A test\\\\metric\\nwith two lines\n\
+             # TYPE edgelet_test gauge\n\
+             edgelet_test{module=\"a\\\"b\\\\c\\nd\"} 1.5\n\
+             edgelet_test +Inf\n",
+            exposition.into_string()
+        );
+    }
+
+    #[test]
+    fn request_latencies_are_cumulative_histograms() {
+        let metrics = RequestMetrics::new();
+        metrics.observe(
+            "mgmt",
+            &Method::GET,
+            StatusCode::OK,
+            Duration::from_millis(20),
+        );
+        metrics.observe(
+            "mgmt",
+            &Method::GET,
+            StatusCode::OK,
+            Duration::from_secs(20),
+        );
+
+        let mut exposition = Exposition::new();
+        metrics.write(&mut exposition);
+        let text = exposition.into_string();
+
+        let labels = r#"server="mgmt",method="GET",status="200""#;
+        assert!(text.contains("# TYPE edgelet_http_request_duration_seconds histogram\n"));
+        assert!(text.contains(&format!(
+            "edgelet_http_request_duration_seconds_bucket{{{},le=\"0.01\"}} 0\n",
+            labels
+        )));
+        assert!(text.contains(&format!(
+            "edgelet_http_request_duration_seconds_bucket{{{},le=\"0.025\"}} 1\n",
+            labels
+        )));
+        assert!(text.contains(&format!(
+            "edgelet_http_request_duration_seconds_bucket{{{},le=\"10\"}} 1\n",
+            labels
+        )));
+        assert!(text.contains(&format!(
+            "edgelet_http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} 2\n",
+            labels
+        )));
+        assert!(text.contains(&format!(
+            "edgelet_http_request_duration_seconds_sum{{{}}} 20.02\n",
+            labels
+        )));
+        assert!(text.contains(&format!(
+            "edgelet_http_request_duration_seconds_count{{{}}} 2\n",
+            labels
+        )));
+    }
+}
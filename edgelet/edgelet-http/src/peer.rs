@@ -0,0 +1,328 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Authorizes the clients of an API by the user they run as, which the OS
+//! reports for connections over Unix domain sockets and named pipes.
+//!
+//! The server puts the `PeerCredentials` of every connection into the
+//! extensions of its requests, and `PeerAuthorizationService` rejects the
+//! requests of clients that aren't in its `AllowedPeers` with a 403. Clients
+//! that connect over TCP have no credentials, so their requests aren't checked.
+//!
+//! Clients of a listener that requires TLS client authentication are instead
+//! identified by their certificate, whose `ClientCertificate` is put into the
+//! extensions of their requests so that handlers can authorize them by it.
+
+use std::fmt;
+
+#[cfg(windows)]
+use failure::ResultExt;
+use futures::{future, Future};
+use hyper::service::{NewService, Service};
+use hyper::{Body, Request, Response};
+#[cfg(unix)]
+use nix::unistd::Uid;
+use openssl::x509::X509Ref;
+
+use crate::error::{Error, ErrorKind};
+#[cfg(windows)]
+use crate::pid::current_process_sid;
+#[cfg(unix)]
+use crate::unix::{group_id, user_id};
+use crate::IntoResponse;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PeerCredentials {
+    #[cfg(unix)]
+    Unix { uid: u32, gid: u32 },
+    /// The SID of the user that the client process runs as
+    #[cfg(windows)]
+    Sid(String),
+}
+
+impl fmt::Display for PeerCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(unix)]
+            PeerCredentials::Unix { uid, gid } => write!(f, "uid {} gid {}", uid, gid),
+            #[cfg(windows)]
+            PeerCredentials::Sid(sid) => write!(f, "user {}", sid),
+        }
+    }
+}
+
+/// The certificate that a client authenticated with over TLS.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClientCertificate {
+    subject: String,
+}
+
+impl ClientCertificate {
+    pub fn new(subject: String) -> Self {
+        ClientCertificate { subject }
+    }
+
+    pub fn from_x509(cert: &X509Ref) -> Self {
+        let subject = cert
+            .subject_name()
+            .entries()
+            .map(|entry| {
+                let name = entry.object().nid().short_name().unwrap_or("?");
+                let value = entry
+                    .data()
+                    .as_utf8()
+                    .map(|value| value.to_string())
+                    .unwrap_or_default();
+                format!("{}={}", name, value)
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        ClientCertificate { subject }
+    }
+
+    /// The subject's distinguished name, like `CN=remote-admin, O=Contoso`
+    pub fn subject(&self) -> &str {
+        &self.subject
+    }
+}
+
+/// The users, and on Unix the groups, whose clients may use an API.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AllowedPeers {
+    #[cfg(unix)]
+    uids: Vec<u32>,
+    #[cfg(unix)]
+    gids: Vec<u32>,
+    #[cfg(windows)]
+    sids: Vec<String>,
+}
+
+impl AllowedPeers {
+    /// Allows only the user that this process runs as.
+    pub fn service_account() -> Result<Self, Error> {
+        #[cfg(unix)]
+        {
+            Ok(AllowedPeers {
+                uids: vec![Uid::effective().as_raw()],
+                gids: vec![],
+            })
+        }
+
+        #[cfg(windows)]
+        {
+            Ok(AllowedPeers {
+                sids: vec![current_process_sid().context(ErrorKind::Initialization)?],
+            })
+        }
+    }
+
+    /// Also allows `user`, a user name or a uid on Unix and a SID like
+    /// `S-1-5-18` on Windows.
+    pub fn with_user(mut self, user: &str) -> Result<Self, Error> {
+        #[cfg(unix)]
+        {
+            let uid = match user.parse() {
+                Ok(uid) => uid,
+                Err(_) => user_id(user)?.as_raw(),
+            };
+            self.uids.push(uid);
+        }
+
+        #[cfg(windows)]
+        {
+            if !user.starts_with("S-") {
+                return Err(Error::from(ErrorKind::UnknownUser(user.to_string())));
+            }
+            self.sids.push(user.to_string());
+        }
+
+        Ok(self)
+    }
+
+    /// Also allows the members of `group`, a group name or a gid, by the
+    /// primary group of the client process.
+    #[cfg(unix)]
+    pub fn with_group(mut self, group: &str) -> Result<Self, Error> {
+        let gid = match group.parse() {
+            Ok(gid) => gid,
+            Err(_) => group_id(group)?.as_raw(),
+        };
+        self.gids.push(gid);
+        Ok(self)
+    }
+
+    pub fn allows(&self, credentials: &PeerCredentials) -> bool {
+        match credentials {
+            #[cfg(unix)]
+            PeerCredentials::Unix { uid, gid } => {
+                self.uids.contains(uid) || self.gids.contains(gid)
+            }
+            #[cfg(windows)]
+            PeerCredentials::Sid(sid) => self.sids.iter().any(|s| s.eq_ignore_ascii_case(sid)),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct PeerAuthorizationService<T> {
+    inner: T,
+    allowed: AllowedPeers,
+}
+
+impl<T> PeerAuthorizationService<T> {
+    pub fn new(inner: T, allowed: AllowedPeers) -> Self {
+        PeerAuthorizationService { inner, allowed }
+    }
+}
+
+impl<T> Service for PeerAuthorizationService<T>
+where
+    T: Service<ResBody = Body>,
+    <T as Service>::Future: Send + 'static,
+    <T as Service>::Error: Send + 'static,
+{
+    type ReqBody = T::ReqBody;
+    type ResBody = Body;
+    type Error = T::Error;
+    type Future = Box<dyn Future<Item = Response<Body>, Error = Self::Error> + Send>;
+
+    fn call(&mut self, req: Request<Self::ReqBody>) -> Self::Future {
+        match req.extensions().get::<PeerCredentials>() {
+            Some(credentials) if !self.allowed.allows(credentials) => {
+                let err = Error::from(ErrorKind::PeerNotAllowed(credentials.to_string()));
+                Box::new(future::ok(err.into_response()))
+            }
+            _ => Box::new(self.inner.call(req)),
+        }
+    }
+}
+
+impl<T> NewService for PeerAuthorizationService<T>
+where
+    T: NewService,
+    <T as NewService>::Future: Send + 'static,
+    PeerAuthorizationService<<T as NewService>::Service>: Service,
+{
+    type ReqBody = <PeerAuthorizationService<<T as NewService>::Service> as Service>::ReqBody;
+    type ResBody = <PeerAuthorizationService<<T as NewService>::Service> as Service>::ResBody;
+    type Error = <PeerAuthorizationService<<T as NewService>::Service> as Service>::Error;
+    type Service = PeerAuthorizationService<<T as NewService>::Service>;
+    type Future = Box<dyn Future<Item = Self::Service, Error = Self::InitError> + Send>;
+    type InitError = <T as NewService>::InitError;
+
+    fn new_service(&self) -> Self::Future {
+        let allowed = self.allowed.clone();
+        Box::new(
+            self.inner
+                .new_service()
+                .map(|inner| PeerAuthorizationService { inner, allowed }),
+        )
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use futures::Stream;
+    use hyper::service::service_fn;
+    use hyper::{Error as HyperError, StatusCode};
+    use serde_json::Value;
+
+    use super::*;
+
+    const SERVICE_UID: u32 = 998;
+    const SERVICE_GID: u32 = 997;
+
+    fn allowed() -> AllowedPeers {
+        AllowedPeers {
+            uids: vec![SERVICE_UID],
+            gids: vec![],
+        }
+    }
+
+    // Calls the service with a request from a connection that presents
+    // `credentials`, as the server does for Unix domain sockets.
+    fn call(allowed: AllowedPeers, credentials: Option<PeerCredentials>) -> Response<Body> {
+        let mut service = PeerAuthorizationService::new(
+            service_fn(|_req: Request<Body>| {
+                Ok::<_, HyperError>(Response::new(Body::from("modules")))
+            }),
+            allowed,
+        );
+
+        let mut req = Request::get("http://localhost/modules")
+            .body(Body::empty())
+            .unwrap();
+        if let Some(credentials) = credentials {
+            req.extensions_mut().insert(credentials);
+        }
+        service.call(req).wait().unwrap()
+    }
+
+    #[test]
+    fn allows_service_account() {
+        let response = call(
+            allowed(),
+            Some(PeerCredentials::Unix {
+                uid: SERVICE_UID,
+                gid: SERVICE_GID,
+            }),
+        );
+        assert_eq!(StatusCode::OK, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        assert_eq!(b"modules", body.as_ref());
+    }
+
+    #[test]
+    fn rejects_other_users() {
+        let response = call(
+            allowed(),
+            Some(PeerCredentials::Unix {
+                uid: 1000,
+                gid: 1000,
+            }),
+        );
+        assert_eq!(StatusCode::FORBIDDEN, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let error: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            "Clients with uid 1000 gid 1000 may not use this API",
+            error["message"]
+        );
+    }
+
+    #[test]
+    fn allows_members_of_allowed_groups() {
+        let allowed = allowed().with_group("1000").unwrap();
+        let response = call(
+            allowed,
+            Some(PeerCredentials::Unix {
+                uid: 1000,
+                gid: 1000,
+            }),
+        );
+        assert_eq!(StatusCode::OK, response.status());
+    }
+
+    #[test]
+    fn allows_connections_without_credentials() {
+        let response = call(allowed(), None);
+        assert_eq!(StatusCode::OK, response.status());
+    }
+
+    #[test]
+    fn numeric_user_needs_no_lookup() {
+        let allowed = AllowedPeers::default().with_user("1000").unwrap();
+        assert!(allowed.allows(&PeerCredentials::Unix { uid: 1000, gid: 0 }));
+        assert!(!allowed.allows(&PeerCredentials::Unix { uid: 0, gid: 0 }));
+    }
+
+    #[test]
+    fn unknown_user_fails() {
+        let err = AllowedPeers::default()
+            .with_user("no-such-user-for-iotedge")
+            .unwrap_err();
+        assert_eq!(
+            &ErrorKind::UnknownUser("no-such-user-for-iotedge".to_string()),
+            err.kind()
+        );
+    }
+}
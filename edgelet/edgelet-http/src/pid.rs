@@ -10,6 +10,8 @@ use tokio_uds::UnixStream;
 #[cfg(windows)]
 use tokio_uds_windows::UnixStream;
 
+use crate::peer::{ClientCertificate, PeerCredentials};
+
 #[derive(Clone, Copy, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
 pub enum Pid {
     None,
@@ -69,12 +71,24 @@ mod tests {
 #[derive(Clone)]
 pub struct PidService<T> {
     pid: Pid,
+    credentials: Option<PeerCredentials>,
+    client_certificate: Option<ClientCertificate>,
     inner: T,
 }
 
 impl<T> PidService<T> {
-    pub fn new(pid: Pid, inner: T) -> Self {
-        PidService { pid, inner }
+    pub fn new(pid: Pid, credentials: Option<PeerCredentials>, inner: T) -> Self {
+        PidService {
+            pid,
+            credentials,
+            client_certificate: None,
+            inner,
+        }
+    }
+
+    pub fn with_client_certificate(mut self, client_certificate: ClientCertificate) -> Self {
+        self.client_certificate = Some(client_certificate);
+        self
     }
 }
 
@@ -92,22 +106,34 @@ where
     fn call(&mut self, req: Request<Self::ReqBody>) -> Self::Future {
         let mut req = req;
         req.extensions_mut().insert(self.pid);
+        if let Some(credentials) = &self.credentials {
+            req.extensions_mut().insert(credentials.clone());
+        }
+        if let Some(client_certificate) = &self.client_certificate {
+            req.extensions_mut().insert(client_certificate.clone());
+        }
         self.inner.call(req)
     }
 }
 
 pub trait UnixStreamExt {
     fn pid(&self) -> io::Result<Pid>;
+
+    fn credentials(&self) -> io::Result<PeerCredentials>;
 }
 
 impl UnixStreamExt for UnixStream {
     fn pid(&self) -> io::Result<Pid> {
         get_pid(self)
     }
+
+    fn credentials(&self) -> io::Result<PeerCredentials> {
+        get_credentials(self)
+    }
 }
 
 #[cfg(target_os = "linux")]
-use self::impl_linux::get_pid;
+use self::impl_linux::{get_credentials, get_pid};
 
 #[cfg(target_os = "linux")]
 mod impl_linux {
@@ -120,6 +146,17 @@ mod impl_linux {
     use super::*;
 
     pub fn get_pid(sock: &UnixStream) -> io::Result<Pid> {
+        peer_cred(sock).map(|ucred| Pid::Value(ucred.pid))
+    }
+
+    pub fn get_credentials(sock: &UnixStream) -> io::Result<PeerCredentials> {
+        peer_cred(sock).map(|ucred| PeerCredentials::Unix {
+            uid: ucred.uid,
+            gid: ucred.gid,
+        })
+    }
+
+    fn peer_cred(sock: &UnixStream) -> io::Result<ucred> {
         let raw_fd = sock.as_raw_fd();
         let mut ucred = ucred {
             pid: 0,
@@ -148,7 +185,7 @@ mod impl_linux {
             )
         };
         if ret == 0 && ucred_size as usize == mem::size_of::<ucred>() {
-            Ok(Pid::Value(ucred.pid))
+            Ok(ucred)
         } else {
             Err(io::Error::last_os_error())
         }
@@ -156,7 +193,7 @@ mod impl_linux {
 }
 
 #[cfg(target_os = "macos")]
-pub use self::impl_macos::get_pid;
+pub use self::impl_macos::{get_credentials, get_pid};
 
 #[cfg(target_os = "macos")]
 pub mod impl_macos {
@@ -185,19 +222,47 @@ pub mod impl_macos {
             }
         }
     }
+
+    pub fn get_credentials(sock: &UnixStream) -> io::Result<PeerCredentials> {
+        unsafe {
+            let raw_fd = sock.as_raw_fd();
+
+            let mut uid = 0;
+            let mut gid = 0;
+
+            let ret = getpeereid(raw_fd, &mut uid, &mut gid);
+
+            if ret == 0 {
+                Ok(PeerCredentials::Unix { uid, gid })
+            } else {
+                Err(io::Error::last_os_error())
+            }
+        }
+    }
 }
 
 #[cfg(windows)]
-use self::impl_windows::get_pid;
+pub use self::impl_windows::{current_process_sid, get_credentials, get_pid, get_pipe_credentials};
 
 #[cfg(windows)]
 mod impl_windows {
-    use std::io;
-    use std::os::windows::io::AsRawSocket;
-
-    use winapi::ctypes::c_long;
+    use std::os::windows::io::{AsRawHandle, AsRawSocket};
+    use std::{io, mem, ptr, slice};
+
+    use winapi::ctypes::{c_long, c_void};
+    use winapi::shared::minwindef::FALSE;
+    use winapi::shared::sddl::ConvertSidToStringSidW;
+    use winapi::um::handleapi::CloseHandle;
+    use winapi::um::processthreadsapi::{GetCurrentProcessId, OpenProcess, OpenProcessToken};
+    use winapi::um::securitybaseapi::GetTokenInformation;
+    use winapi::um::winbase::{GetNamedPipeClientProcessId, LocalFree};
+    use winapi::um::winnt::{
+        TokenUser, HANDLE, PROCESS_QUERY_LIMITED_INFORMATION, TOKEN_QUERY, TOKEN_USER,
+    };
     use winapi::um::winsock2::{ioctlsocket, WSAGetLastError, SOCKET_ERROR};
 
+    use tokio_named_pipe::PipeStream;
+
     use super::*;
 
     // SIO_AF_UNIX_GETPEERPID is defined in the Windows header afunix.h.
@@ -221,4 +286,77 @@ mod impl_windows {
             Ok(Pid::Value(pid as _))
         }
     }
+
+    pub fn get_credentials(sock: &UnixStream) -> io::Result<PeerCredentials> {
+        match get_pid(sock)? {
+            #[allow(clippy::cast_sign_loss)]
+            Pid::Value(pid) => process_sid(pid as u32).map(PeerCredentials::Sid),
+            _ => Err(io::Error::new(
+                io::ErrorKind::Other,
+                "could not get the process of the peer",
+            )),
+        }
+    }
+
+    pub fn get_pipe_credentials(pipe: &PipeStream) -> io::Result<PeerCredentials> {
+        let mut pid = 0;
+        let ret = unsafe { GetNamedPipeClientProcessId(pipe.as_raw_handle() as HANDLE, &mut pid) };
+        if ret == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        process_sid(pid).map(PeerCredentials::Sid)
+    }
+
+    /// The SID of the user that process `pid` runs as, like `S-1-5-18`.
+    fn process_sid(pid: u32) -> io::Result<String> {
+        unsafe {
+            let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, FALSE, pid);
+            if process.is_null() {
+                return Err(io::Error::last_os_error());
+            }
+
+            let mut token = ptr::null_mut();
+            let ret = OpenProcessToken(process, TOKEN_QUERY, &mut token);
+            CloseHandle(process);
+            if ret == 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let sid = token_user_sid(token);
+            CloseHandle(token);
+            sid
+        }
+    }
+
+    pub fn current_process_sid() -> io::Result<String> {
+        process_sid(unsafe { GetCurrentProcessId() })
+    }
+
+    unsafe fn token_user_sid(token: HANDLE) -> io::Result<String> {
+        let mut len = 0;
+        GetTokenInformation(token, TokenUser, ptr::null_mut(), 0, &mut len);
+
+        // u64s so that the buffer is aligned for the pointer in TOKEN_USER
+        let mut buf = vec![0_u64; len as usize / mem::size_of::<u64>() + 1];
+        if GetTokenInformation(
+            token,
+            TokenUser,
+            buf.as_mut_ptr() as *mut c_void,
+            len,
+            &mut len,
+        ) == 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        let user = &*(buf.as_ptr() as *const TOKEN_USER);
+
+        let mut sid = ptr::null_mut();
+        if ConvertSidToStringSidW(user.User.Sid, &mut sid) == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let sid_len = (0..).take_while(|i| *sid.offset(*i) != 0).count();
+        let result = String::from_utf16_lossy(slice::from_raw_parts(sid, sid_len));
+        LocalFree(sid as *mut c_void);
+        Ok(result)
+    }
 }
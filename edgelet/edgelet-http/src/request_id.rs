@@ -0,0 +1,250 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Every request handled by the daemon's HTTP servers gets an ID, so that the
+//! log lines written while handling it can be correlated with the caller.
+//!
+//! The ID is taken from the request's `x-ms-request-id` header, or generated
+//! if there isn't one, and echoed back in the response's `x-ms-request-id`
+//! header. Handlers find it in the request's extensions, and anything that runs
+//! while the request's future is being polled can get it from
+//! `RequestId::current`.
+
+use std::cell::RefCell;
+use std::fmt;
+
+use futures::prelude::*;
+use futures::try_ready;
+use hyper::header::HeaderValue;
+use hyper::service::{NewService, Service};
+use hyper::{Request, Response};
+use uuid::Uuid;
+
+pub const REQUEST_ID_HEADER: &str = "x-ms-request-id";
+
+// Longer IDs are replaced rather than echoed back
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+thread_local! {
+    static CURRENT: RefCell<Option<RequestId>> = RefCell::new(None);
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RequestId(String);
+
+impl RequestId {
+    pub fn new() -> Self {
+        RequestId(Uuid::new_v4().to_string())
+    }
+
+    /// The ID in the request's `x-ms-request-id` header, or a new one if the
+    /// header is missing or isn't a usable ID.
+    pub fn from_request<B>(req: &Request<B>) -> Self {
+        req.headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+            .filter(|id| !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN)
+            .map_or_else(RequestId::new, |id| RequestId(id.to_string()))
+    }
+
+    /// The ID of the request currently being handled on this thread, if any.
+    pub fn current() -> Option<Self> {
+        CURRENT.with(|current| current.borrow().clone())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    fn scope<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        let previous = CURRENT.with(|current| current.replace(Some(self.clone())));
+        let result = f();
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+        result
+    }
+}
+
+impl Default for RequestId {
+    fn default() -> Self {
+        RequestId::new()
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Clone)]
+pub struct RequestIdService<T> {
+    inner: T,
+}
+
+impl<T> RequestIdService<T> {
+    pub fn new(inner: T) -> Self {
+        RequestIdService { inner }
+    }
+}
+
+impl<T> Service for RequestIdService<T>
+where
+    T: Service,
+    <T as Service>::Future: Send + 'static,
+{
+    type ReqBody = T::ReqBody;
+    type ResBody = T::ResBody;
+    type Error = T::Error;
+    type Future = Box<dyn Future<Item = Response<Self::ResBody>, Error = Self::Error> + Send>;
+
+    fn call(&mut self, req: Request<Self::ReqBody>) -> Self::Future {
+        let request_id = RequestId::from_request(&req);
+        let mut req = req;
+        req.extensions_mut().insert(request_id.clone());
+
+        let inner = request_id.scope(|| self.inner.call(req));
+        Box::new(InRequestScope {
+            request_id,
+            inner: Some(inner),
+        })
+    }
+}
+
+impl<T> NewService for RequestIdService<T>
+where
+    T: NewService,
+    <T as NewService>::Future: Send + 'static,
+    RequestIdService<<T as NewService>::Service>: Service,
+{
+    type ReqBody = <RequestIdService<<T as NewService>::Service> as Service>::ReqBody;
+    type ResBody = <RequestIdService<<T as NewService>::Service> as Service>::ResBody;
+    type Error = <RequestIdService<<T as NewService>::Service> as Service>::Error;
+    type Service = RequestIdService<<T as NewService>::Service>;
+    type Future = Box<dyn Future<Item = Self::Service, Error = Self::InitError> + Send>;
+    type InitError = <T as NewService>::InitError;
+
+    fn new_service(&self) -> Self::Future {
+        Box::new(
+            self.inner
+                .new_service()
+                .map(|inner| RequestIdService { inner }),
+        )
+    }
+}
+
+// Polls the handler's future with the request's ID as the current one, and
+// echoes the ID back in the response.
+struct InRequestScope<F> {
+    request_id: RequestId,
+    inner: Option<F>,
+}
+
+impl<F, B> Future for InRequestScope<F>
+where
+    F: Future<Item = Response<B>>,
+{
+    type Item = Response<B>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let inner = self.inner.as_mut().expect("future polled after completion");
+        let mut response = try_ready!(self.request_id.scope(|| inner.poll()));
+        self.inner = None;
+
+        // The ID is either generated or came from a header, so it's always a
+        // valid header value.
+        if let Ok(value) = HeaderValue::from_str(self.request_id.as_str()) {
+            response.headers_mut().insert(REQUEST_ID_HEADER, value);
+        }
+        Ok(Async::Ready(response))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use futures::future;
+    use hyper::service::service_fn;
+    use hyper::{Body, Error as HyperError};
+
+    use super::*;
+
+    // Calls the service with `req` and returns the response along with the ID
+    // the handler saw while its future was being polled, which is the one its
+    // log lines are written with.
+    fn call(req: Request<Body>) -> (Response<Body>, Option<RequestId>) {
+        let logged = Arc::new(Mutex::new(None));
+        let logged_inner = logged.clone();
+        let mut service = RequestIdService::new(service_fn(move |req: Request<Body>| {
+            let logged = logged_inner.clone();
+            assert_eq!(
+                req.extensions().get::<RequestId>(),
+                RequestId::current().as_ref()
+            );
+            future::lazy(move || {
+                *logged.lock().unwrap() = RequestId::current();
+                Ok::<_, HyperError>(Response::new(Body::empty()))
+            })
+        }));
+
+        let response = service.call(req).wait().unwrap();
+        let logged = logged.lock().unwrap().clone();
+        (response, logged)
+    }
+
+    #[test]
+    fn echoes_incoming_request_id() {
+        let req = Request::get("http://localhost/modules")
+            .header(REQUEST_ID_HEADER, "fb9cbe4c-2a0f-4c42-9c1e-2f5fbd2a6f3e")
+            .body(Body::empty())
+            .unwrap();
+
+        let (response, logged) = call(req);
+        assert_eq!(
+            "fb9cbe4c-2a0f-4c42-9c1e-2f5fbd2a6f3e",
+            response.headers()[REQUEST_ID_HEADER].to_str().unwrap()
+        );
+        assert_eq!(
+            Some("fb9cbe4c-2a0f-4c42-9c1e-2f5fbd2a6f3e"),
+            logged.as_ref().map(RequestId::as_str)
+        );
+    }
+
+    #[test]
+    fn echoed_request_id_matches_logged_id() {
+        let req = Request::get("http://localhost/modules")
+            .body(Body::empty())
+            .unwrap();
+
+        let (response, logged) = call(req);
+        let echoed = response.headers()[REQUEST_ID_HEADER].to_str().unwrap();
+        assert!(Uuid::parse_str(echoed).is_ok());
+        assert_eq!(Some(echoed), logged.as_ref().map(RequestId::as_str));
+    }
+
+    #[test]
+    fn replaces_unusable_request_id() {
+        let req = Request::get("http://localhost/modules")
+            .header(REQUEST_ID_HEADER, "x".repeat(MAX_REQUEST_ID_LEN + 1))
+            .body(Body::empty())
+            .unwrap();
+
+        let (response, _) = call(req);
+        let echoed = response.headers()[REQUEST_ID_HEADER].to_str().unwrap();
+        assert!(Uuid::parse_str(echoed).is_ok());
+    }
+
+    #[test]
+    fn current_is_cleared_after_request() {
+        let req = Request::get("http://localhost/modules")
+            .body(Body::empty())
+            .unwrap();
+
+        let _ = call(req);
+        assert_eq!(None, RequestId::current());
+    }
+}
@@ -0,0 +1,480 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Rejects the clients of a listener that requires TLS client authentication
+//! whose certificate has been revoked, by a certificate revocation list, an
+//! OCSP responder, or both.
+//!
+//! The check runs once the TLS handshake has finished, so that asking the
+//! OCSP responder doesn't hold up the handshakes of other clients. A client
+//! that fails it is disconnected before any of its requests are served.
+
+use std::fs;
+use std::sync::Arc;
+
+use failure::{Fail, ResultExt};
+use futures::future::{self, Either};
+use futures::{Future, Stream};
+use hyper::client::HttpConnector;
+use hyper::header::CONTENT_TYPE;
+use hyper::{Body, Client, Request, Uri};
+use log::{warn, Level};
+use openssl::hash::MessageDigest;
+use openssl::ocsp::{
+    OcspCertId, OcspCertStatus, OcspFlag, OcspRequest, OcspResponse, OcspResponseStatus,
+};
+use openssl::stack::Stack;
+use openssl::x509::store::X509StoreBuilder;
+use openssl::x509::{CrlStatus, X509Crl, X509Ref, X509VerifyResult, X509};
+use tokio::prelude::FutureExt;
+
+use edgelet_core::OcspFailurePolicy;
+use edgelet_utils::log_failure;
+
+use crate::error::{Error, ErrorKind};
+use crate::listener::{ClientAuth, OcspResponder};
+use crate::peer::ClientCertificate;
+
+// How far the clocks of the responder and the daemon may be apart, in
+// seconds, when checking that a response is current.
+const OCSP_CLOCK_SKEW_SECS: u32 = 300;
+
+pub(crate) struct RevocationCheck {
+    client_ca: Vec<X509>,
+    crl: Option<X509Crl>,
+    ocsp: Option<(Uri, OcspResponder)>,
+    client: Client<HttpConnector>,
+}
+
+impl RevocationCheck {
+    pub(crate) fn new(client_auth: &ClientAuth) -> Result<Self, Error> {
+        let path_context = |path: &std::path::Path| ErrorKind::Path(path.display().to_string());
+
+        let client_ca = fs::read(client_auth.client_ca())
+            .with_context(|_| path_context(client_auth.client_ca()))?;
+        let client_ca = X509::stack_from_pem(&client_ca)
+            .with_context(|_| path_context(client_auth.client_ca()))?;
+
+        let crl = match client_auth.crl_file() {
+            Some(path) => Some(load_crl(
+                &fs::read(path).with_context(|_| path_context(path))?,
+                &client_ca,
+                &path.display().to_string(),
+            )?),
+            None => None,
+        };
+
+        let ocsp = match client_auth.ocsp_responder() {
+            Some(responder) => {
+                let uri = responder
+                    .uri()
+                    .as_str()
+                    .parse::<Uri>()
+                    .with_context(|_| ErrorKind::InvalidUrl(responder.uri().to_string()))?;
+                Some((uri, responder.clone()))
+            }
+            None => None,
+        };
+
+        Ok(RevocationCheck {
+            client_ca,
+            crl,
+            ocsp,
+            client: Client::new(),
+        })
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.crl.is_some() || self.ocsp.is_some()
+    }
+
+    /// Fails with `ErrorKind::CertificateRevoked` if `cert` has been revoked,
+    /// with `ErrorKind::OcspUnreachable` if the OCSP responder couldn't be
+    /// reached in time and the client is to be rejected for that, and with
+    /// `ErrorKind::RevocationCheck` if the responder's answer can't be
+    /// trusted or doesn't say. Only an unreachable responder may fail open,
+    /// since a response that doesn't verify may be an attacker's. `chain` is
+    /// the rest of the chain that the client sent, which may have the issuer
+    /// of `cert`.
+    pub(crate) fn check(
+        &self,
+        cert: &X509Ref,
+        chain: &[X509],
+    ) -> impl Future<Item = (), Error = Error> + Send {
+        let subject = ClientCertificate::from_x509(cert).subject().to_string();
+
+        if let Some(crl) = &self.crl {
+            if let CrlStatus::Revoked(_) = crl.get_by_cert(&cert.to_owned()) {
+                return Either::A(future::err(Error::from(ErrorKind::CertificateRevoked(
+                    subject,
+                ))));
+            }
+        }
+
+        let (uri, responder) = match &self.ocsp {
+            Some(ocsp) => ocsp,
+            None => return Either::A(future::ok(())),
+        };
+        let on_unreachable = responder.on_unreachable();
+        let status = self
+            .issuer(cert, chain)
+            .ok_or_else(|| {
+                Error::from(
+                    failure::err_msg("the issuer of the certificate is not known")
+                        .context(ErrorKind::RevocationCheck(subject.clone())),
+                )
+            })
+            .map(|issuer| self.query_ocsp(uri, responder, cert, &issuer, &subject));
+
+        Either::B(
+            future::result(status)
+                .flatten()
+                .and_then(move |status| {
+                    if status == OcspCertStatus::GOOD {
+                        Ok(())
+                    } else if status == OcspCertStatus::REVOKED {
+                        Err(Error::from(ErrorKind::CertificateRevoked(subject)))
+                    } else {
+                        Err(Error::from(
+                            failure::err_msg("the OCSP responder does not know the certificate")
+                                .context(ErrorKind::RevocationCheck(subject)),
+                        ))
+                    }
+                })
+                .or_else(move |err| match (err.kind(), on_unreachable) {
+                    (ErrorKind::OcspUnreachable(_), OcspFailurePolicy::FailOpen) => {
+                        warn!("Accepting client whose certificate could not be checked:");
+                        log_failure(Level::Warn, &err);
+                        Ok(())
+                    }
+                    _ => Err(err),
+                }),
+        )
+    }
+
+    fn issuer(&self, cert: &X509Ref, chain: &[X509]) -> Option<X509> {
+        self.client_ca
+            .iter()
+            .chain(chain)
+            .find(|issuer| issuer.issued(cert) == X509VerifyResult::OK)
+            .cloned()
+    }
+
+    // Asks the responder for the status of `cert`, whose response must be
+    // signed by its issuer or by a responder that its issuer delegated to.
+    fn query_ocsp(
+        &self,
+        uri: &Uri,
+        responder: &OcspResponder,
+        cert: &X509Ref,
+        issuer: &X509,
+        subject: &str,
+    ) -> impl Future<Item = OcspCertStatus, Error = Error> + Send {
+        let context = {
+            let subject = subject.to_string();
+            move || ErrorKind::RevocationCheck(subject.clone())
+        };
+        let unreachable = {
+            let subject = subject.to_string();
+            move || ErrorKind::OcspUnreachable(subject.clone())
+        };
+
+        let request = ocsp_request(cert, issuer).and_then(|body| {
+            Request::post(uri.clone())
+                .header(CONTENT_TYPE, "application/ocsp-request")
+                .body(Body::from(body))
+                .map_err(|err| Error::from(err.context(context())))
+        });
+        let request = match request {
+            Ok(request) => request,
+            Err(err) => return Either::A(future::err(err)),
+        };
+        let issuer = issuer.clone();
+        let cert_id = OcspCertId::from_cert(MessageDigest::sha1(), cert, &issuer);
+
+        let response = self
+            .client
+            .request(request)
+            .and_then(|response| response.into_body().concat2())
+            .timeout(responder.timeout())
+            .map_err(move |err| Error::from(err.context(unreachable())))
+            .and_then(move |body| -> Result<_, Error> {
+                let cert_id = cert_id.with_context(|_| context())?;
+                let status = ocsp_status(&body, &cert_id, issuer).with_context(|_| context())?;
+                Ok(status)
+            });
+        Either::B(response)
+    }
+}
+
+// Fails unless the CRL was issued by one of the CAs that client certificates
+// must be issued by, since any other CA's list wouldn't apply to them.
+fn load_crl(crl: &[u8], client_ca: &[X509], path: &str) -> Result<X509Crl, Error> {
+    let context = || ErrorKind::CertificateRevocationList(path.to_string());
+
+    let crl = X509Crl::from_pem(crl)
+        .or_else(|_| X509Crl::from_der(crl))
+        .with_context(|_| context())?;
+    let issued_by_client_ca = client_ca.iter().any(|ca| {
+        ca.public_key()
+            .and_then(|key| crl.verify(&key))
+            .unwrap_or(false)
+    });
+    if issued_by_client_ca {
+        Ok(crl)
+    } else {
+        Err(Error::from(context()))
+    }
+}
+
+fn ocsp_request(cert: &X509Ref, issuer: &X509Ref) -> Result<Vec<u8>, Error> {
+    let subject = ClientCertificate::from_x509(cert).subject().to_string();
+    let request = (|| {
+        let mut request = OcspRequest::new()?;
+        request.add_id(OcspCertId::from_cert(MessageDigest::sha1(), cert, issuer)?)?;
+        request.to_der()
+    })()
+    .context(ErrorKind::RevocationCheck(subject))?;
+    Ok(request)
+}
+
+fn ocsp_status(
+    body: &[u8],
+    cert_id: &OcspCertId,
+    issuer: X509,
+) -> Result<OcspCertStatus, failure::Error> {
+    let response = OcspResponse::from_der(body)?;
+    if response.status() != OcspResponseStatus::SUCCESSFUL {
+        return Err(failure::err_msg(format!(
+            "the OCSP responder failed with status {}",
+            response.status().as_raw()
+        )));
+    }
+    let response = response.basic()?;
+
+    let mut store = X509StoreBuilder::new()?;
+    store.add_cert(issuer.clone())?;
+    let store = store.build();
+    let mut certs = Stack::new()?;
+    certs.push(issuer)?;
+    response.verify(&certs, &store, OcspFlag::empty())?;
+
+    let status = response
+        .find_status(cert_id)
+        .ok_or_else(|| failure::err_msg("the OCSP response is for another certificate"))?;
+    status.check_validity(OCSP_CLOCK_SKEW_SECS, None)?;
+    Ok(status.status)
+}
+
+/// Checks the certificate of the client on the other end of `stream`, with
+/// the CAs in its chain as candidates for the issuer.
+pub(crate) fn check_stream<S>(
+    check: &Arc<RevocationCheck>,
+    stream: S,
+    cert: Option<X509>,
+    chain: Vec<X509>,
+) -> impl Future<Item = S, Error = Error> + Send
+where
+    S: Send,
+{
+    match cert {
+        Some(cert) if check.is_enabled() => {
+            Either::A(check.check(&cert, &chain).map(move |()| stream))
+        }
+        _ => Either::B(future::ok(stream)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::path::PathBuf;
+    use std::thread;
+    use std::time::Duration;
+
+    use tokio::runtime::current_thread::Runtime;
+    use url::Url;
+
+    use super::*;
+
+    fn test_file(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("test-files/revocation")
+            .join(name)
+    }
+
+    fn client_auth() -> ClientAuth {
+        ClientAuth::new(
+            test_file("server.pem"),
+            test_file("server.key.pem"),
+            test_file("ca.pem"),
+        )
+    }
+
+    fn cert(name: &str) -> X509 {
+        X509::from_pem(&fs::read(test_file(name)).unwrap()).unwrap()
+    }
+
+    // A responder that nothing listens on, so that connecting to it fails.
+    fn unreachable_responder(on_unreachable: OcspFailurePolicy) -> OcspResponder {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        OcspResponder::new(
+            Url::parse(&format!("http://127.0.0.1:{}", port)).unwrap(),
+            on_unreachable,
+            Duration::from_secs(5),
+        )
+    }
+
+    // A responder that answers each request with `response`.
+    fn responder(response: Vec<u8>, on_unreachable: OcspFailurePolicy) -> OcspResponder {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                read_request(&mut stream);
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/ocsp-response\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    response.len()
+                )
+                .and_then(|()| stream.write_all(&response));
+            }
+        });
+        OcspResponder::new(
+            Url::parse(&format!("http://127.0.0.1:{}", port)).unwrap(),
+            on_unreachable,
+            Duration::from_secs(5),
+        )
+    }
+
+    // Reads the headers and the body of a request, so that the response isn't
+    // sent while some of the request is still unread.
+    fn read_request(stream: &mut impl Read) {
+        let mut request = vec![];
+        let mut buf = [0; 1024];
+        loop {
+            if let Some(end) = request.windows(4).position(|window| window == b"\r\n\r\n") {
+                let headers = String::from_utf8_lossy(&request[..end]).to_lowercase();
+                let length = headers
+                    .lines()
+                    .filter(|line| line.starts_with("content-length:"))
+                    .filter_map(|line| line["content-length:".len()..].trim().parse::<usize>().ok())
+                    .next()
+                    .unwrap_or(0);
+                if request.len() >= end + 4 + length {
+                    return;
+                }
+            }
+            let read = stream.read(&mut buf).unwrap();
+            if read == 0 {
+                return;
+            }
+            request.extend_from_slice(&buf[..read]);
+        }
+    }
+
+    #[test]
+    fn certificate_in_crl_is_revoked() {
+        let check =
+            RevocationCheck::new(&client_auth().with_crl_file(test_file("crl.pem"))).unwrap();
+
+        let err = check.check(&cert("revoked.pem"), &[]).wait().unwrap_err();
+        assert_eq!(
+            &ErrorKind::CertificateRevoked("CN=revoked-client, O=iotedge tests".to_string()),
+            err.kind()
+        );
+
+        check.check(&cert("valid.pem"), &[]).wait().unwrap();
+    }
+
+    #[test]
+    fn crl_of_another_ca_is_rejected() {
+        let client_auth = ClientAuth::new(
+            test_file("server.pem"),
+            test_file("server.key.pem"),
+            test_file("valid.pem"),
+        )
+        .with_crl_file(test_file("crl.pem"));
+
+        let err = RevocationCheck::new(&client_auth).err().unwrap();
+        assert_eq!(
+            &ErrorKind::CertificateRevocationList(test_file("crl.pem").display().to_string()),
+            err.kind()
+        );
+    }
+
+    #[test]
+    fn unreachable_responder_fails_closed() {
+        let check = RevocationCheck::new(
+            &client_auth()
+                .with_ocsp_responder(unreachable_responder(OcspFailurePolicy::FailClosed)),
+        )
+        .unwrap();
+
+        let err = Runtime::new()
+            .unwrap()
+            .block_on(check.check(&cert("valid.pem"), &[]))
+            .unwrap_err();
+        assert_eq!(
+            &ErrorKind::OcspUnreachable("CN=valid-client, O=iotedge tests".to_string()),
+            err.kind()
+        );
+    }
+
+    #[test]
+    fn unreachable_responder_fails_open_if_configured() {
+        let check = RevocationCheck::new(
+            &client_auth().with_ocsp_responder(unreachable_responder(OcspFailurePolicy::FailOpen)),
+        )
+        .unwrap();
+
+        Runtime::new()
+            .unwrap()
+            .block_on(check.check(&cert("valid.pem"), &[]))
+            .unwrap();
+    }
+
+    #[test]
+    fn response_with_bad_signature_fails_closed_even_if_open_is_configured() {
+        // Says that the certificate is good, but is signed by a responder
+        // that the client CA didn't delegate to.
+        let check = RevocationCheck::new(&client_auth().with_ocsp_responder(responder(
+            fs::read(test_file("ocsp_bad_signature.der")).unwrap(),
+            OcspFailurePolicy::FailOpen,
+        )))
+        .unwrap();
+
+        let err = Runtime::new()
+            .unwrap()
+            .block_on(check.check(&cert("valid.pem"), &[]))
+            .unwrap_err();
+        assert_eq!(
+            &ErrorKind::RevocationCheck("CN=valid-client, O=iotedge tests".to_string()),
+            err.kind()
+        );
+    }
+
+    #[test]
+    fn crl_is_checked_before_the_responder() {
+        let check = RevocationCheck::new(
+            &client_auth()
+                .with_crl_file(test_file("crl.pem"))
+                .with_ocsp_responder(unreachable_responder(OcspFailurePolicy::FailOpen)),
+        )
+        .unwrap();
+
+        let err = Runtime::new()
+            .unwrap()
+            .block_on(check.check(&cert("revoked.pem"), &[]))
+            .unwrap_err();
+        assert_eq!(
+            &ErrorKind::CertificateRevoked("CN=revoked-client, O=iotedge tests".to_string()),
+            err.kind()
+        );
+    }
+}
@@ -91,6 +91,14 @@ pub trait Builder: Sized {
         self.route(Method::PUT, version, pattern, handler)
     }
 
+    fn patch<S, H>(self, version: Version, pattern: S, handler: H) -> Self
+    where
+        S: AsRef<str>,
+        H: Handler<<Self::Recognizer as Recognizer>::Parameters> + Sync,
+    {
+        self.route(Method::PATCH, version, pattern, handler)
+    }
+
     fn delete<S, H>(self, version: Version, pattern: S, handler: H) -> Self
     where
         S: AsRef<str>,
@@ -134,6 +142,17 @@ pub struct RouterService<R: Recognizer> {
     inner: Arc<R>,
 }
 
+impl<R> RouterService<R>
+where
+    R: Recognizer,
+{
+    /// Whether a request for `path` with `method` and `version` is routed to
+    /// a handler.
+    pub fn recognizes(&self, method: &Method, version: Version, path: &str) -> bool {
+        self.inner.recognize(method, version, path).is_ok()
+    }
+}
+
 impl<R> Clone for RouterService<R>
 where
     R: Recognizer,
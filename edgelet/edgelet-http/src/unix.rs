@@ -1,15 +1,25 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+#[cfg(unix)]
+use std::ffi::CString;
 use std::fs;
 #[cfg(unix)]
-use std::os::unix::fs::MetadataExt;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
 use std::path::Path;
+#[cfg(unix)]
+use std::{io, mem, ptr};
 
+#[cfg(unix)]
+use edgelet_core::SocketOwnership;
 use failure::ResultExt;
 use log::debug;
 #[cfg(unix)]
+use log::warn;
+#[cfg(unix)]
 use nix::sys::stat::{umask, Mode};
 #[cfg(unix)]
+use nix::unistd::{chown, Gid, Uid};
+#[cfg(unix)]
 use scopeguard::defer;
 #[cfg(unix)]
 use tokio_uds::UnixListener;
@@ -17,9 +27,23 @@ use tokio_uds::UnixListener;
 use tokio_uds_windows::UnixListener;
 
 use crate::error::{Error, ErrorKind};
+use crate::listener::SocketPermissions;
 use crate::util::{incoming::Incoming, socket_file_exists};
 
-pub fn listener<P: AsRef<Path>>(path: P) -> Result<Incoming, Error> {
+/// Binds the socket at `path`. It gets `permissions` if they're set, and
+/// otherwise the permissions of the socket that was there before, if any.
+#[cfg_attr(not(unix), allow(unused_variables))]
+pub fn listener<P: AsRef<Path>>(
+    path: P,
+    permissions: Option<&SocketPermissions>,
+) -> Result<Incoming, Error> {
+    #[cfg(unix)]
+    {
+        if let Some(permissions) = permissions {
+            return listener_with_permissions(path.as_ref(), permissions);
+        }
+    }
+
     let listener = if socket_file_exists(path.as_ref()) {
         // get the previous file's metadata
         #[cfg(unix)]
@@ -50,6 +74,188 @@ pub fn listener<P: AsRef<Path>>(path: P) -> Result<Incoming, Error> {
     Ok(listener)
 }
 
+#[cfg(unix)]
+fn listener_with_permissions(
+    path: &Path,
+    permissions: &SocketPermissions,
+) -> Result<Incoming, Error> {
+    // Look up the group first, so that a missing group doesn't remove the
+    // previous socket
+    let group = permissions.group().map(group_id).transpose()?;
+
+    if socket_file_exists(path) {
+        debug!("unlinking {}...", path.display());
+        fs::remove_file(path).with_context(|_| ErrorKind::Path(path.display().to_string()))?;
+        debug!("unlinked {}", path.display());
+    }
+
+    // Nobody outside of the group may connect while the socket is still
+    // owned by the daemon's group
+    #[cfg(target_os = "macos")]
+    #[allow(clippy::cast_possible_truncation)]
+    let mode = Mode::from_bits_truncate((permissions.mode() & 0o770) as u16);
+
+    #[cfg(not(target_os = "macos"))]
+    let mode = Mode::from_bits_truncate(permissions.mode() & 0o770);
+
+    let prev = umask(!mode);
+    defer! {{ umask(prev); }}
+
+    debug!("binding {}...", path.display());
+    let listener =
+        UnixListener::bind(path).with_context(|_| ErrorKind::Path(path.display().to_string()))?;
+    debug!("bound {}", path.display());
+
+    if let Some(group) = group {
+        debug!("setting group {} for {}...", group, path.display());
+        chown(path, None, Some(group))
+            .with_context(|_| ErrorKind::Path(path.display().to_string()))?;
+    }
+    debug!(
+        "settings permissions {:#o} for {}...",
+        permissions.mode(),
+        path.display()
+    );
+    fs::set_permissions(path, fs::Permissions::from_mode(permissions.mode()))
+        .with_context(|_| ErrorKind::Path(path.display().to_string()))?;
+
+    Ok(Incoming::Unix(listener))
+}
+
+/// Checks that the socket at `path` has the owner, group and mode that
+/// `expected` sets. A socket that doesn't is corrected if `expected` allows
+/// it to be repaired, and is an error with what it has otherwise.
+#[cfg(unix)]
+pub fn check_socket_ownership(path: &Path, expected: &SocketOwnership) -> Result<(), Error> {
+    let owner = expected
+        .owner()
+        .map(|owner| match owner.parse() {
+            Ok(uid) => Ok(Uid::from_raw(uid)),
+            Err(_) => user_id(owner),
+        })
+        .transpose()?;
+    let group = expected
+        .group()
+        .map(|group| match group.parse() {
+            Ok(gid) => Ok(Gid::from_raw(gid)),
+            Err(_) => group_id(group),
+        })
+        .transpose()?;
+
+    let metadata = get_metadata(path)?;
+    let mut wrong_expected = vec![];
+    let mut wrong_actual = vec![];
+    if let Some(owner) = owner {
+        if metadata.uid() != owner.as_raw() {
+            wrong_expected.push(format!("owner {}", owner));
+            wrong_actual.push(format!("owner {}", metadata.uid()));
+        }
+    }
+    if let Some(group) = group {
+        if metadata.gid() != group.as_raw() {
+            wrong_expected.push(format!("group {}", group));
+            wrong_actual.push(format!("group {}", metadata.gid()));
+        }
+    }
+    if let Some(mode) = expected.mode() {
+        if metadata.mode() & 0o7777 != mode {
+            wrong_expected.push(format!("mode {:04o}", mode));
+            wrong_actual.push(format!("mode {:04o}", metadata.mode() & 0o7777));
+        }
+    }
+    if wrong_expected.is_empty() {
+        return Ok(());
+    }
+
+    let (expected_desc, actual) = (wrong_expected.join(", "), wrong_actual.join(", "));
+    if !expected.repair() {
+        return Err(Error::from(ErrorKind::SocketOwnership {
+            path: path.display().to_string(),
+            expected: expected_desc,
+            actual,
+        }));
+    }
+
+    warn!(
+        "Socket {} has {} instead of {}, correcting it",
+        path.display(),
+        actual,
+        expected_desc
+    );
+    if owner.is_some() || group.is_some() {
+        chown(path, owner, group).with_context(|_| ErrorKind::Path(path.display().to_string()))?;
+    }
+    if let Some(mode) = expected.mode() {
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))
+            .with_context(|_| ErrorKind::Path(path.display().to_string()))?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+pub(crate) fn group_id(name: &str) -> Result<Gid, Error> {
+    let c_name = CString::new(name).with_context(|_| ErrorKind::UnknownGroup(name.to_string()))?;
+    let mut group: libc::group = unsafe { mem::zeroed() };
+    let mut buf: Vec<libc::c_char> = vec![0; 1024];
+    let mut result = ptr::null_mut();
+
+    loop {
+        let ret = unsafe {
+            libc::getgrnam_r(
+                c_name.as_ptr(),
+                &mut group,
+                buf.as_mut_ptr(),
+                buf.len(),
+                &mut result,
+            )
+        };
+        match ret {
+            0 if result.is_null() => {
+                return Err(Error::from(ErrorKind::UnknownGroup(name.to_string())))
+            }
+            0 => return Ok(Gid::from_raw(group.gr_gid)),
+            libc::ERANGE => {
+                let len = buf.len() * 2;
+                buf.resize(len, 0);
+            }
+            err => Err(io::Error::from_raw_os_error(err))
+                .with_context(|_| ErrorKind::UnknownGroup(name.to_string()))?,
+        }
+    }
+}
+
+#[cfg(unix)]
+pub(crate) fn user_id(name: &str) -> Result<Uid, Error> {
+    let c_name = CString::new(name).with_context(|_| ErrorKind::UnknownUser(name.to_string()))?;
+    let mut passwd: libc::passwd = unsafe { mem::zeroed() };
+    let mut buf: Vec<libc::c_char> = vec![0; 1024];
+    let mut result = ptr::null_mut();
+
+    loop {
+        let ret = unsafe {
+            libc::getpwnam_r(
+                c_name.as_ptr(),
+                &mut passwd,
+                buf.as_mut_ptr(),
+                buf.len(),
+                &mut result,
+            )
+        };
+        match ret {
+            0 if result.is_null() => {
+                return Err(Error::from(ErrorKind::UnknownUser(name.to_string())))
+            }
+            0 => return Ok(Uid::from_raw(passwd.pw_uid)),
+            libc::ERANGE => {
+                let len = buf.len() * 2;
+                buf.resize(len, 0);
+            }
+            err => Err(io::Error::from_raw_os_error(err))
+                .with_context(|_| ErrorKind::UnknownUser(name.to_string()))?,
+        }
+    }
+}
+
 #[cfg(unix)]
 fn get_metadata(path: &Path) -> Result<fs::Metadata, Error> {
     let metadata =
@@ -102,7 +308,7 @@ mod tests {
         assert_eq!(0o600, file.metadata().unwrap().mode() & 0o7777);
         drop(file);
 
-        let listener = listener(&path).unwrap();
+        let listener = listener(&path, None).unwrap();
         let _srv = listener.for_each(move |(_socket, _addr)| Ok(()));
 
         let file_stat = stat(&path).unwrap();
@@ -110,4 +316,118 @@ mod tests {
 
         dir.close().unwrap();
     }
+
+    fn current_group() -> (Gid, String) {
+        let gid = Gid::current();
+        let group = unsafe { libc::getgrgid(gid.as_raw()) };
+        assert!(!group.is_null());
+        let name = unsafe { std::ffi::CStr::from_ptr((*group).gr_name) };
+        (gid, name.to_str().unwrap().to_string())
+    }
+
+    #[test]
+    fn test_permissions() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("permissions.sock");
+        // Replaces the previous socket without keeping its permissions
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(path.clone())
+            .unwrap();
+        drop(file);
+
+        let (gid, group) = current_group();
+        let permissions = SocketPermissions::new(0o660).with_group(group);
+        let listener = listener(&path, Some(&permissions)).unwrap();
+        let _srv = listener.for_each(move |(_socket, _addr)| Ok(()));
+
+        let file_stat = stat(&path).unwrap();
+        assert_eq!(0o660, file_stat.st_mode & 0o777);
+        assert_eq!(gid.as_raw(), file_stat.st_gid);
+
+        dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_socket_ownership_is_repaired() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("repair.sock");
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(path.clone())
+            .unwrap();
+        drop(file);
+
+        let (gid, group) = current_group();
+        let expected = SocketOwnership::new()
+            .with_owner(Uid::current().to_string())
+            .with_group(group)
+            .with_mode(0o660);
+        check_socket_ownership(&path, &expected).unwrap();
+
+        let file_stat = stat(&path).unwrap();
+        assert_eq!(0o660, file_stat.st_mode & 0o7777);
+        assert_eq!(gid.as_raw(), file_stat.st_gid);
+
+        // Already as expected
+        check_socket_ownership(&path, &expected.with_repair(false)).unwrap();
+
+        dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_socket_ownership_fails_without_repair() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("no_repair.sock");
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(path.clone())
+            .unwrap();
+        drop(file);
+
+        let expected = SocketOwnership::new()
+            .with_group(Gid::current().to_string())
+            .with_mode(0o666)
+            .with_repair(false);
+        let err = check_socket_ownership(&path, &expected).unwrap_err();
+
+        assert_eq!(
+            &ErrorKind::SocketOwnership {
+                path: path.display().to_string(),
+                expected: "mode 0666".to_string(),
+                actual: "mode 0600".to_string(),
+            },
+            err.kind()
+        );
+        assert_eq!(0o600, stat(&path).unwrap().st_mode & 0o7777);
+
+        dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_permissions_unknown_group() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("unknown_group.sock");
+
+        let permissions =
+            SocketPermissions::new(0o660).with_group("no-such-group-for-iotedge".to_string());
+        let err = match listener(&path, Some(&permissions)) {
+            Ok(_) => panic!("Expected binding the socket to fail"),
+            Err(err) => err,
+        };
+
+        assert_eq!(
+            &ErrorKind::UnknownGroup("no-such-group-for-iotedge".to_string()),
+            err.kind()
+        );
+        assert!(!path.exists());
+
+        dir.close().unwrap();
+    }
 }
@@ -2,17 +2,25 @@
 
 use std::io;
 #[cfg(unix)]
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 
+#[cfg(unix)]
+use failure::Fail;
 use futures::{Poll, Stream};
 #[cfg(unix)]
 use log::Level;
+#[cfg(unix)]
+use openssl::ssl::SslAcceptor;
 #[cfg(windows)]
 use tokio::net::TcpListener;
 #[cfg(unix)]
 use tokio::net::{TcpListener, TcpStream};
 #[cfg(unix)]
 use tokio::prelude::*;
+#[cfg(windows)]
+use tokio_named_pipe::PipeListener;
+#[cfg(unix)]
+use tokio_openssl::{SslAcceptorExt, SslStream};
 #[cfg(unix)]
 use tokio_tls::{Accept, TlsAcceptor};
 #[cfg(unix)]
@@ -23,8 +31,17 @@ use tokio_uds_windows::UnixListener;
 #[cfg(unix)]
 use edgelet_utils::log_failure;
 
+#[cfg(unix)]
+use crate::error::{Error, ErrorKind};
+#[cfg(unix)]
+use crate::revocation::{check_stream, RevocationCheck};
 use crate::util::{IncomingSocketAddr, StreamSelector};
 
+/// The TLS handshake of a client that must authenticate with a certificate,
+/// followed by the check of whether that certificate has been revoked.
+#[cfg(unix)]
+type MutualTlsHandshake = Box<dyn Future<Item = SslStream<TcpStream>, Error = Error> + Send>;
+
 pub enum Incoming {
     Tcp(TcpListener),
     #[cfg(unix)]
@@ -33,7 +50,17 @@ pub enum Incoming {
         TlsAcceptor,
         Mutex<Vec<(Accept<TcpStream>, IncomingSocketAddr)>>,
     ),
+    /// TLS that requires clients to authenticate with a certificate
+    #[cfg(unix)]
+    MutualTls(
+        TcpListener,
+        SslAcceptor,
+        Arc<RevocationCheck>,
+        Mutex<Vec<(MutualTlsHandshake, IncomingSocketAddr)>>,
+    ),
     Unix(UnixListener),
+    #[cfg(windows)]
+    Pipe(PipeListener),
 }
 
 impl Stream for Incoming {
@@ -58,57 +85,45 @@ impl Stream for Incoming {
             Incoming::Tls(ref mut listener, ref mut acceptor, ref mut connections) => {
                 // check if we have a tcp connection and if we do then kick off TLS handshake
                 // and store the future representing that operation in "connections"
-                if let Err(err) = listener.poll_accept().map(|val| {
-                    if let Async::Ready((tcp_stream, addr)) = val {
-                        connections
-                            .lock()
-                            .expect("Unable to lock the connections mutex")
-                            .push((acceptor.accept(tcp_stream), IncomingSocketAddr::Tcp(addr)));
-                    }
-                }) {
-                    return Err(err);
+                let mut connections = connections
+                    .lock()
+                    .expect("Unable to lock the connections mutex");
+                if let Async::Ready((tcp_stream, addr)) = listener.poll_accept()? {
+                    connections.push((acceptor.accept(tcp_stream), IncomingSocketAddr::Tcp(addr)));
                 }
-
+                return poll_handshakes(&mut connections, StreamSelector::Tls);
+            }
+            #[cfg(unix)]
+            Incoming::MutualTls(
+                ref mut listener,
+                ref mut acceptor,
+                ref revocation_check,
+                ref mut connections,
+            ) => {
                 let mut connections = connections
                     .lock()
                     .expect("Unable to lock the connections mutex");
-
-                loop {
-                    // Look through the connections list for the first connection that is ready to be
-                    // passed to the stream selector. Return a tuple containing the index and state.
-                    let val = connections
-                        .iter_mut()
-                        .map(|(fut, _)| fut.poll())
-                        .enumerate()
-                        .find(|(_, result)| match result {
-                            Ok(v) => v.is_ready(),
-                            Err(_) => true,
-                        });
-
-                    // Validate that the poll is ready, and remove that value. Then return the connection.
-                    // If no connections are available in connection manager, return Async::NotReady
-                    match val {
-                        Some((i, result)) => {
-                            let (_, addr) = connections.remove(i);
-                            match result {
-                                Ok(Async::Ready(tls_stream)) => {
-                                    return Ok(Async::Ready(Some((
-                                        StreamSelector::Tls(tls_stream),
-                                        addr,
-                                    ))));
-                                }
-                                // The prior block included a filter that specifically asked for is_ready state,
-                                // so this line is unreachable.
-                                Ok(_) => unreachable!(),
-                                Err(err) => {
-                                    // Ignore TLS handshake errors
-                                    log_failure(Level::Warn, &err);
-                                }
-                            }
-                        }
-                        None => return Ok(Async::NotReady),
-                    }
+                if let Async::Ready((tcp_stream, addr)) = listener.poll_accept()? {
+                    let revocation_check = revocation_check.clone();
+                    let handshake: MutualTlsHandshake = Box::new(
+                        acceptor
+                            .accept_async(tcp_stream)
+                            .map_err(|err| Error::from(err.context(ErrorKind::TlsHandshake)))
+                            .and_then(move |stream| {
+                                let (cert, chain) = {
+                                    let ssl = stream.get_ref().ssl();
+                                    let chain = ssl
+                                        .peer_cert_chain()
+                                        .map(|chain| chain.iter().map(ToOwned::to_owned).collect())
+                                        .unwrap_or_default();
+                                    (ssl.peer_certificate(), chain)
+                                };
+                                check_stream(&revocation_check, stream, cert, chain)
+                            }),
+                    );
+                    connections.push((handshake, IncomingSocketAddr::Tcp(addr)));
                 }
+                return poll_handshakes(&mut connections, StreamSelector::MutualTls);
             }
             Incoming::Unix(ref mut listener) => {
                 let accept = match listener.poll_accept() {
@@ -122,6 +137,61 @@ impl Stream for Incoming {
                     Some((StreamSelector::Unix(stream), IncomingSocketAddr::Unix(addr)))
                 })
             }
+            #[cfg(windows)]
+            Incoming::Pipe(ref mut listener) => listener.poll_accept()?.map(|stream| {
+                Some((
+                    StreamSelector::Pipe(stream),
+                    IncomingSocketAddr::Pipe(listener.path().to_path_buf()),
+                ))
+            }),
         })
     }
 }
+
+// Looks through the connections for the first one whose TLS handshake has
+// finished and returns it. Connections whose handshake failed are dropped.
+#[cfg(unix)]
+fn poll_handshakes<F, S>(
+    connections: &mut Vec<(F, IncomingSocketAddr)>,
+    stream: S,
+) -> Poll<Option<(StreamSelector, IncomingSocketAddr)>, io::Error>
+where
+    F: Future,
+    F::Error: Fail,
+    S: Fn(F::Item) -> StreamSelector,
+{
+    loop {
+        // Look through the connections list for the first connection that is ready to be
+        // passed to the stream selector. Return a tuple containing the index and state.
+        let val = connections
+            .iter_mut()
+            .map(|(fut, _)| fut.poll())
+            .enumerate()
+            .find(|(_, result)| match result {
+                Ok(v) => v.is_ready(),
+                Err(_) => true,
+            });
+
+        // Validate that the poll is ready, and remove that value. Then return the connection.
+        // If no connections are available in connection manager, return Async::NotReady
+        match val {
+            Some((i, result)) => {
+                let (_, addr) = connections.remove(i);
+                match result {
+                    Ok(Async::Ready(tls_stream)) => {
+                        return Ok(Async::Ready(Some((stream(tls_stream), addr))));
+                    }
+                    // The prior block included a filter that specifically asked for is_ready state,
+                    // so this line is unreachable.
+                    Ok(_) => unreachable!(),
+                    Err(err) => {
+                        // Ignore TLS handshake errors, like clients without
+                        // a trusted certificate
+                        log_failure(Level::Warn, &err);
+                    }
+                }
+            }
+            None => return Ok(Async::NotReady),
+        }
+    }
+}
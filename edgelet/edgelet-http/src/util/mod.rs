@@ -6,6 +6,8 @@ use std::net::SocketAddr;
 #[cfg(unix)]
 use std::os::unix::net::SocketAddr as UnixSocketAddr;
 use std::path::Path;
+#[cfg(windows)]
+use std::path::PathBuf;
 
 use bytes::{Buf, BufMut};
 use futures::Poll;
@@ -15,12 +17,17 @@ use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::net::TcpStream;
 #[cfg(windows)]
 use tokio_named_pipe::PipeStream;
+#[cfg(unix)]
+use tokio_openssl::SslStream;
 use tokio_tls::TlsStream;
 #[cfg(unix)]
 use tokio_uds::UnixStream;
 #[cfg(windows)]
 use tokio_uds_windows::UnixStream;
 
+use crate::peer::{ClientCertificate, PeerCredentials};
+#[cfg(windows)]
+use crate::pid::get_pipe_credentials;
 use crate::pid::{Pid, UnixStreamExt};
 
 pub mod connector;
@@ -37,6 +44,8 @@ pub enum StreamSelector {
     Tls(TlsStream<TcpStream>),
     #[cfg(windows)]
     Tls(Box<TlsStream<TcpStream>>),
+    #[cfg(unix)]
+    MutualTls(SslStream<TcpStream>),
     #[cfg(windows)]
     Pipe(PipeStream),
     Unix(UnixStream),
@@ -48,11 +57,40 @@ impl StreamSelector {
         match *self {
             StreamSelector::Tcp(_) => Ok(Pid::Any),
             StreamSelector::Tls(_) => Ok(Pid::Any),
+            #[cfg(unix)]
+            StreamSelector::MutualTls(_) => Ok(Pid::Any),
             #[cfg(windows)]
             StreamSelector::Pipe(_) => Ok(Pid::Any),
             StreamSelector::Unix(ref stream) => stream.pid(),
         }
     }
+
+    /// The credentials of the process on the other end of a local
+    /// connection. `None` for connections over TCP.
+    pub fn credentials(&self) -> io::Result<Option<PeerCredentials>> {
+        match self {
+            StreamSelector::Tcp(_) | StreamSelector::Tls(_) => Ok(None),
+            #[cfg(unix)]
+            StreamSelector::MutualTls(_) => Ok(None),
+            #[cfg(windows)]
+            StreamSelector::Pipe(stream) => get_pipe_credentials(stream).map(Some),
+            StreamSelector::Unix(stream) => stream.credentials().map(Some),
+        }
+    }
+
+    /// The certificate that the client authenticated with, for connections
+    /// over TLS that require one.
+    pub fn client_certificate(&self) -> Option<ClientCertificate> {
+        match self {
+            #[cfg(unix)]
+            StreamSelector::MutualTls(stream) => stream
+                .get_ref()
+                .ssl()
+                .peer_certificate()
+                .map(|cert| ClientCertificate::from_x509(&cert)),
+            _ => None,
+        }
+    }
 }
 
 impl Read for StreamSelector {
@@ -60,6 +98,8 @@ impl Read for StreamSelector {
         match self {
             StreamSelector::Tcp(stream) => stream.read(buf),
             StreamSelector::Tls(stream) => stream.read(buf),
+            #[cfg(unix)]
+            StreamSelector::MutualTls(stream) => stream.read(buf),
             #[cfg(windows)]
             StreamSelector::Pipe(stream) => stream.read(buf),
             StreamSelector::Unix(stream) => stream.read(buf),
@@ -72,6 +112,8 @@ impl Write for StreamSelector {
         match self {
             StreamSelector::Tcp(stream) => stream.write(buf),
             StreamSelector::Tls(stream) => stream.write(buf),
+            #[cfg(unix)]
+            StreamSelector::MutualTls(stream) => stream.write(buf),
             #[cfg(windows)]
             StreamSelector::Pipe(stream) => stream.write(buf),
             StreamSelector::Unix(stream) => stream.write(buf),
@@ -82,6 +124,8 @@ impl Write for StreamSelector {
         match self {
             StreamSelector::Tcp(stream) => stream.flush(),
             StreamSelector::Tls(stream) => stream.flush(),
+            #[cfg(unix)]
+            StreamSelector::MutualTls(stream) => stream.flush(),
             #[cfg(windows)]
             StreamSelector::Pipe(stream) => stream.flush(),
             StreamSelector::Unix(stream) => stream.flush(),
@@ -95,6 +139,8 @@ impl AsyncRead for StreamSelector {
         match *self {
             StreamSelector::Tcp(ref stream) => stream.prepare_uninitialized_buffer(buf),
             StreamSelector::Tls(ref stream) => stream.prepare_uninitialized_buffer(buf),
+            #[cfg(unix)]
+            StreamSelector::MutualTls(ref stream) => stream.prepare_uninitialized_buffer(buf),
             #[cfg(windows)]
             StreamSelector::Pipe(ref stream) => stream.prepare_uninitialized_buffer(buf),
             StreamSelector::Unix(ref stream) => stream.prepare_uninitialized_buffer(buf),
@@ -106,6 +152,8 @@ impl AsyncRead for StreamSelector {
         match self {
             StreamSelector::Tcp(stream) => stream.read_buf(buf),
             StreamSelector::Tls(stream) => stream.read_buf(buf),
+            #[cfg(unix)]
+            StreamSelector::MutualTls(stream) => stream.read_buf(buf),
             #[cfg(windows)]
             StreamSelector::Pipe(stream) => stream.read_buf(buf),
             StreamSelector::Unix(stream) => stream.read_buf(buf),
@@ -118,6 +166,8 @@ impl AsyncWrite for StreamSelector {
         match self {
             StreamSelector::Tcp(stream) => AsyncWrite::shutdown(stream),
             StreamSelector::Tls(stream) => TlsStream::shutdown(stream),
+            #[cfg(unix)]
+            StreamSelector::MutualTls(stream) => AsyncWrite::shutdown(stream),
             #[cfg(windows)]
             StreamSelector::Pipe(stream) => PipeStream::shutdown(stream),
             StreamSelector::Unix(stream) => AsyncWrite::shutdown(stream),
@@ -129,6 +179,8 @@ impl AsyncWrite for StreamSelector {
         match self {
             StreamSelector::Tcp(stream) => stream.write_buf(buf),
             StreamSelector::Tls(stream) => stream.write_buf(buf),
+            #[cfg(unix)]
+            StreamSelector::MutualTls(stream) => stream.write_buf(buf),
             #[cfg(windows)]
             StreamSelector::Pipe(stream) => stream.write_buf(buf),
             StreamSelector::Unix(stream) => stream.write_buf(buf),
@@ -139,12 +191,16 @@ impl AsyncWrite for StreamSelector {
 pub enum IncomingSocketAddr {
     Tcp(SocketAddr),
     Unix(UnixSocketAddr),
+    #[cfg(windows)]
+    Pipe(PathBuf),
 }
 
 impl fmt::Display for IncomingSocketAddr {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match *self {
             IncomingSocketAddr::Tcp(ref socket) => socket.fmt(f),
+            #[cfg(windows)]
+            IncomingSocketAddr::Pipe(ref path) => write!(f, "{}", path.display()),
             IncomingSocketAddr::Unix(ref socket) => {
                 if let Some(path) = socket.as_pathname() {
                     write!(f, "{}", path.display())
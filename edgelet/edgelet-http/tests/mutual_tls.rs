@@ -0,0 +1,207 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+#![cfg(not(windows))]
+#![deny(rust_2018_idioms, warnings)]
+#![deny(clippy::all, clippy::pedantic)]
+#![allow(clippy::must_use_candidate)]
+
+use std::fs;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+
+use edgelet_hsm::Crypto;
+use edgelet_http::peer::ClientCertificate;
+use edgelet_http::route::{Builder, Parameters, RegexRoutesBuilder, Router};
+use edgelet_http::{
+    ClientAuth, Error as HttpError, HyperExt, Listener, TlsAcceptorParams, Version,
+};
+
+use futures::{future, Future};
+use hyper::server::conn::Http;
+use hyper::{Body, Request, Response, StatusCode};
+use openssl::asn1::Asn1Time;
+use openssl::bn::BigNum;
+use openssl::hash::MessageDigest;
+use openssl::pkey::{PKey, Private};
+use openssl::rsa::Rsa;
+use openssl::ssl::{SslConnector, SslMethod};
+use openssl::x509::extension::{BasicConstraints, SubjectAlternativeName};
+use openssl::x509::{X509Builder, X509NameBuilder, X509};
+use tempdir::TempDir;
+use url::Url;
+
+struct Identity {
+    cert: X509,
+    key: PKey<Private>,
+}
+
+impl Identity {
+    // Creates a CA certificate when `issuer` is `None`, and otherwise a leaf
+    // certificate for `localhost` issued by `issuer`.
+    fn new(common_name: &str, serial: u32, issuer: Option<&Identity>) -> Self {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", common_name).unwrap();
+        name.append_entry_by_text("O", "iotedge tests").unwrap();
+        let name = name.build();
+
+        let mut builder = X509Builder::new().unwrap();
+        builder.set_version(2).unwrap();
+        let serial = BigNum::from_u32(serial).unwrap().to_asn1_integer().unwrap();
+        builder.set_serial_number(&serial).unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder
+            .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+
+        match issuer {
+            Some(issuer) => {
+                builder.set_issuer_name(issuer.cert.subject_name()).unwrap();
+                let san = SubjectAlternativeName::new()
+                    .dns("localhost")
+                    .build(&builder.x509v3_context(Some(&issuer.cert), None))
+                    .unwrap();
+                builder.append_extension(san).unwrap();
+                builder.sign(&issuer.key, MessageDigest::sha256()).unwrap();
+            }
+            None => {
+                builder.set_issuer_name(&name).unwrap();
+                let constraints = BasicConstraints::new().critical().ca().build().unwrap();
+                builder.append_extension(constraints).unwrap();
+                builder.sign(&key, MessageDigest::sha256()).unwrap();
+            }
+        }
+
+        Identity {
+            cert: builder.build(),
+            key,
+        }
+    }
+
+    fn write(&self, dir: &Path, name: &str) -> (PathBuf, PathBuf) {
+        let cert = dir.join(format!("{}.pem", name));
+        let key = dir.join(format!("{}.key.pem", name));
+        fs::write(&cert, self.cert.to_pem().unwrap()).unwrap();
+        fs::write(&key, self.key.private_key_to_pem_pkcs8().unwrap()).unwrap();
+        (cert, key)
+    }
+}
+
+struct TestServer {
+    _dir: TempDir,
+    _runtime: tokio::runtime::Runtime,
+    ca_path: PathBuf,
+    port: u16,
+}
+
+// Serves the subject of the client certificate at `/subject`, and only
+// accepts clients with a certificate issued by `client_ca`.
+fn start_server(client_ca: &Identity) -> TestServer {
+    let dir = TempDir::new("mutual_tls_test").unwrap();
+    let server_ca = Identity::new("server-ca", 1, None);
+    let server = Identity::new("localhost", 2, Some(&server_ca));
+    let (ca_path, _) = server_ca.write(dir.path(), "server-ca");
+    let (server_cert, server_key) = server.write(dir.path(), "server");
+    let (client_ca, _) = client_ca.write(dir.path(), "client-ca");
+
+    let listener = Listener::parse(&Url::parse("https://127.0.0.1:0").unwrap())
+        .unwrap()
+        .with_client_auth(ClientAuth::new(server_cert, server_key, client_ca));
+
+    let recognizer = RegexRoutesBuilder::default()
+        .get(Version::Version2018_06_28, "/subject", subject)
+        .finish();
+    let router = Router::from(recognizer);
+
+    let server = Http::new()
+        .bind_listener(&listener, router, None::<TlsAcceptorParams<'_, Crypto>>)
+        .unwrap();
+    let port = server.port().expect("HTTP server must have port");
+
+    let mut runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.spawn(server.run().map_err(|err| eprintln!("{}", err)));
+
+    TestServer {
+        _dir: dir,
+        _runtime: runtime,
+        ca_path,
+        port,
+    }
+}
+
+// Returns the response, or `None` if the server didn't accept the client.
+fn get_subject(server: &TestServer, client: &Identity) -> Option<String> {
+    let mut connector = SslConnector::builder(SslMethod::tls()).unwrap();
+    connector.set_ca_file(&server.ca_path).unwrap();
+    connector.set_certificate(&client.cert).unwrap();
+    connector.set_private_key(&client.key).unwrap();
+    let connector = connector.build();
+
+    let stream = TcpStream::connect(("127.0.0.1", server.port)).unwrap();
+    let mut stream = connector.connect("localhost", stream).ok()?;
+    stream
+        .write_all(
+            b"GET /subject?api-version=2018-06-28 HTTP/1.1\r\n\
+              Host: localhost\r\n\
+              Connection: close\r\n\r\n",
+        )
+        .ok()?;
+
+    // With TLS 1.3 the server may only reject the certificate after the
+    // client considers the handshake done, so what was read before the
+    // connection closed is the response.
+    let mut response = vec![];
+    let _ = stream.read_to_end(&mut response);
+    if response.is_empty() {
+        None
+    } else {
+        Some(String::from_utf8_lossy(&response).into_owned())
+    }
+}
+
+#[test]
+fn trusted_client_certificate_is_accepted() {
+    let client_ca = Identity::new("client-ca", 3, None);
+    let client = Identity::new("trusted-client", 4, Some(&client_ca));
+    let server = start_server(&client_ca);
+
+    let response = get_subject(&server, &client).expect("server rejected the client");
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    assert!(
+        response.ends_with("CN=trusted-client, O=iotedge tests"),
+        "{}",
+        response
+    );
+}
+
+#[test]
+fn untrusted_client_certificate_is_rejected() {
+    let client_ca = Identity::new("client-ca", 3, None);
+    let other_ca = Identity::new("other-ca", 5, None);
+    let client = Identity::new("untrusted-client", 6, Some(&other_ca));
+    let server = start_server(&client_ca);
+
+    assert_eq!(None, get_subject(&server, &client));
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn subject(
+    req: Request<Body>,
+    _params: Parameters,
+) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+    let subject = req
+        .extensions()
+        .get::<ClientCertificate>()
+        .map_or_else(String::new, |cert| cert.subject().to_string());
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .body(subject.into())
+        .unwrap();
+    Box::new(future::ok(response))
+}
@@ -23,7 +23,9 @@ use percent_encoding::{define_encode_set, percent_encode, PATH_SEGMENT_ENCODE_SE
 use url::form_urlencoded::Serializer as UrlSerializer;
 
 use edgelet_core::crypto::{KeyIdentity, KeyStore, Sign, Signature, SignatureAlgorithm};
-use edgelet_core::{AuthType, Identity, IdentityManager, IdentityOperation, IdentitySpec};
+use edgelet_core::{
+    AuthType, Identity, IdentityManager, IdentityOperation, IdentitySpec, UpstreamEndpoints,
+};
 use edgelet_http::client::{ClientImpl, TokenSource};
 use iothubservice::{
     AuthMechanism, AuthType as HubAuthType, DeviceClient, ErrorKind as HubErrorKind, Module,
@@ -101,6 +103,7 @@ where
     hub_id: String,
     device_id: String,
     key: K,
+    upstream: Option<UpstreamEndpoints>,
 }
 
 impl<K> SasTokenSource<K>
@@ -112,8 +115,15 @@ where
             hub_id,
             device_id,
             key,
+            upstream: None,
         }
     }
+
+    /// Signs tokens for the active hub of `upstream` instead of `hub_id`.
+    pub fn with_upstream(mut self, upstream: UpstreamEndpoints) -> Self {
+        self.upstream = Some(upstream);
+        self
+    }
 }
 
 impl<K> TokenSource for SasTokenSource<K>
@@ -124,7 +134,11 @@ where
 
     fn get(&self, expiry: &DateTime<Utc>) -> Result<String, Error> {
         let expiry = expiry.timestamp().to_string();
-        let audience = format!("{}/devices/{}", self.hub_id, self.device_id);
+        let hub_id = self
+            .upstream
+            .as_ref()
+            .map_or_else(|| self.hub_id.clone(), UpstreamEndpoints::active);
+        let audience = format!("{}/devices/{}", hub_id, self.device_id);
 
         let resource_uri =
             percent_encode(audience.to_lowercase().as_bytes(), IOTHUB_ENCODE_SET).to_string();
@@ -153,6 +167,7 @@ where
             hub_id: self.hub_id.clone(),
             device_id: self.device_id.clone(),
             key: self.key.clone(),
+            upstream: self.upstream.clone(),
         }
     }
 }
@@ -5,7 +5,7 @@ use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
-use failure::Fail;
+use failure::{Fail, ResultExt};
 use futures::prelude::*;
 use futures::{future, stream, Async, Future, Stream};
 use hyper::client::HttpConnector;
@@ -14,9 +14,10 @@ use hyper::{Body, Chunk as HyperChunk, Request};
 use hyper_tls::HttpsConnector;
 
 use edgelet_core::{
-    AuthId, Authenticator, GetTrustBundle, LogOptions, MakeModuleRuntime, ModuleRegistry,
-    ModuleRuntime, ModuleRuntimeState, ModuleSpec, ProvisioningResult as CoreProvisioningResult,
-    RuntimeOperation, SystemInfo, SystemResources,
+    AuthId, Authenticator, ExecOutput, GetTrustBundle, ImagePruneFilter, LogOptions,
+    MakeModuleRuntime, ModuleEvents, ModuleRegistry, ModuleRuntime, ModuleRuntimeState, ModuleSpec,
+    ModuleStats, ProvisioningResult as CoreProvisioningResult, PrunedImages, RuntimeOperation,
+    SystemInfo, SystemResources,
 };
 use edgelet_docker::DockerConfig;
 use kube_client::{get_config, Client as KubeClient, HttpClient, TokenSource, ValueToken};
@@ -25,12 +26,13 @@ use provisioning::ProvisioningResult;
 use crate::convert::pod_to_module;
 use crate::error::{Error, ErrorKind};
 use crate::module::{authenticate, create_module, init_trust_bundle, KubeModule};
-use crate::registry::create_image_pull_secrets;
+use crate::registry::{create_image_pull_secrets, ImagePullSecret};
 use crate::settings::Settings;
 
 pub struct KubeModuleRuntime<T, S> {
     client: Arc<Mutex<RefCell<KubeClient<T, S>>>>,
     settings: Settings,
+    events: ModuleEvents,
 }
 
 impl<T, S> KubeModuleRuntime<T, S> {
@@ -38,6 +40,7 @@ impl<T, S> KubeModuleRuntime<T, S> {
         KubeModuleRuntime {
             client: Arc::new(Mutex::new(RefCell::new(client))),
             settings,
+            events: ModuleEvents::default(),
         }
     }
 
@@ -64,6 +67,7 @@ impl<T, S> Clone for KubeModuleRuntime<T, S> {
         KubeModuleRuntime {
             client: self.client(),
             settings: self.settings().clone(),
+            events: self.events.clone(),
         }
     }
 }
@@ -81,6 +85,7 @@ where
     type Error = Error;
     type PullFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
     type RemoveFuture = Box<dyn Future<Item = (), Error = Self::Error>>;
+    type ImageExistsFuture = future::FutureResult<bool, Self::Error>;
     type Config = DockerConfig;
 
     fn pull(&self, config: &Self::Config) -> Self::PullFuture {
@@ -90,6 +95,12 @@ where
     fn remove(&self, _: &str) -> Self::RemoveFuture {
         Box::new(future::ok(()))
     }
+
+    // Images are pulled by the nodes the modules are scheduled on, so the
+    // pull secrets are always created.
+    fn image_exists(&self, _: &Self::Config) -> Self::ImageExistsFuture {
+        future::ok(false)
+    }
 }
 
 impl MakeModuleRuntime
@@ -163,15 +174,44 @@ where
     type SystemResourcesFuture =
         Box<dyn Future<Item = SystemResources, Error = Self::Error> + Send>;
     type RemoveAllFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type StatsFuture = Box<dyn Future<Item = ModuleStats, Error = Self::Error> + Send>;
+    type PruneImagesFuture = Box<dyn Future<Item = PrunedImages, Error = Self::Error> + Send>;
+    type ExecStream = Box<dyn Stream<Item = ExecOutput, Error = Self::Error> + Send>;
+    type EffectiveSpecFuture =
+        Box<dyn Future<Item = ModuleSpec<Self::Config>, Error = Self::Error> + Send>;
 
     fn create(&self, module: ModuleSpec<Self::Config>) -> Self::CreateFuture {
         Box::new(create_module(self, module))
     }
 
+    fn validate(&self, module: &ModuleSpec<Self::Config>) -> Result<(), Self::Error> {
+        let name = module.name().to_string();
+        module.validate().with_context(|_| {
+            ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(name.clone()))
+        })?;
+
+        // The pull secret is only built when the module is created, so check
+        // that it can be built from the credentials we were given.
+        if let Some(secret) = module.config().auth().and_then(ImagePullSecret::from_auth) {
+            secret.data().with_context(|_| {
+                ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(name.clone()))
+            })?;
+        }
+
+        Ok(())
+    }
+
     fn get(&self, _id: &str) -> Self::GetFuture {
         unimplemented!()
     }
 
+    fn effective_spec(&self, id: &str) -> Self::EffectiveSpecFuture {
+        // TODO: record the specs that deployments are created from on k8s
+        Box::new(future::err(Error::from(ErrorKind::RuntimeOperation(
+            RuntimeOperation::GetEffectiveSpec(id.to_string()),
+        ))))
+    }
+
     fn start(&self, _id: &str) -> Self::StartFuture {
         Box::new(future::ok(()))
     }
@@ -235,13 +275,14 @@ where
                             "Kubernetes".to_string(),
                             serde_json::to_string(&architectures).unwrap(),
                         )
+                        .with_runtime_type("kubernetes".to_string())
                     }),
             )
         } else {
-            future::Either::B(future::ok(SystemInfo::new(
-                "Kubernetes".to_string(),
-                "Kubernetes".to_string(),
-            )))
+            future::Either::B(future::ok(
+                SystemInfo::new("Kubernetes".to_string(), "Kubernetes".to_string())
+                    .with_runtime_type("kubernetes".to_string()),
+            ))
         };
         Box::new(fut)
     }
@@ -303,6 +344,32 @@ where
     fn remove_all(&self) -> Self::RemoveAllFuture {
         Box::new(future::ok(()))
     }
+
+    fn stats(&self, id: &str) -> Self::StatsFuture {
+        // TODO: add support for module stats on k8s
+        Box::new(future::err(Error::from(ErrorKind::RuntimeOperation(
+            RuntimeOperation::GetModuleStats(id.to_string()),
+        ))))
+    }
+
+    fn prune_images(&self, _filter: ImagePruneFilter) -> Self::PruneImagesFuture {
+        // Images are managed by the kubelet on k8s
+        Box::new(future::err(Error::from(ErrorKind::RuntimeOperation(
+            RuntimeOperation::PruneImages,
+        ))))
+    }
+
+    fn exec(&self, id: &str, _cmd: Vec<String>, _env: HashMap<String, String>) -> Self::ExecStream {
+        // TODO: run commands in pods on k8s
+        Box::new(stream::once(Err(Error::from(ErrorKind::RuntimeOperation(
+            RuntimeOperation::ExecModule(id.to_string()),
+        )))))
+    }
+
+    fn events(&self) -> &ModuleEvents {
+        // TODO: publish module lifecycle events on k8s
+        &self.events
+    }
 }
 
 impl<T, S> Authenticator for KubeModuleRuntime<T, S>
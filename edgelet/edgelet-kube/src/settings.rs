@@ -4,11 +4,12 @@ use std::path::Path;
 
 use config::{Config, Environment};
 use edgelet_core::{
-    Certificates, Connect, Listen, ModuleSpec, Provisioning, RuntimeSettings,
-    Settings as BaseSettings, WatchdogSettings,
+    AdmissionSettings, AuditSettings, Certificates, Connect, HealthSettings, Listen,
+    LogForwardingSettings, LogsSettings, ModuleSpec, Provisioning, RuntimeSettings,
+    Settings as BaseSettings, UpstreamSettings, WatchdogSettings, WebhookSettings,
 };
-use edgelet_docker::{DockerConfig, DEFAULTS};
-use edgelet_utils::YamlFileSource;
+use edgelet_docker::{read_settings_file, DockerConfig, DEFAULTS, ENV_OVERRIDES_PREFIX};
+use edgelet_utils::{EnvOverrides, YamlFileSource};
 use failure::ResultExt;
 
 use crate::error::Error;
@@ -36,13 +37,17 @@ impl Settings {
             .context(ErrorKind::Config)?;
 
         config
-            .merge(YamlFileSource::File(filename.into()))
+            .merge(read_settings_file(filename).context(ErrorKind::Config)?)
             .context(ErrorKind::Config)?;
 
         config
             .merge(Environment::with_prefix("iotedge"))
             .context(ErrorKind::Config)?;
 
+        config
+            .merge(EnvOverrides::from_env(ENV_OVERRIDES_PREFIX))
+            .context(ErrorKind::Config)?;
+
         let settings = config.try_into().context(ErrorKind::Config)?;
 
         Ok(settings)
@@ -111,6 +116,10 @@ impl RuntimeSettings for Settings {
         self.base.hostname()
     }
 
+    fn https_proxy(&self) -> Option<&str> {
+        self.base.https_proxy()
+    }
+
     fn connect(&self) -> &Connect {
         self.base.connect()
     }
@@ -130,6 +139,38 @@ impl RuntimeSettings for Settings {
     fn watchdog(&self) -> &WatchdogSettings {
         self.base.watchdog()
     }
+
+    fn upstream(&self) -> &UpstreamSettings {
+        self.base.upstream()
+    }
+
+    fn health(&self) -> &HealthSettings {
+        self.base.health()
+    }
+
+    fn audit(&self) -> &AuditSettings {
+        self.base.audit()
+    }
+
+    fn logs(&self) -> &LogsSettings {
+        self.base.logs()
+    }
+
+    fn log_forwarding(&self) -> &LogForwardingSettings {
+        self.base.log_forwarding()
+    }
+
+    fn admission(&self) -> &AdmissionSettings {
+        self.base.admission()
+    }
+
+    fn webhook(&self) -> Option<&WebhookSettings> {
+        self.base.webhook()
+    }
+
+    fn manifest_trust_key(&self) -> Option<&Path> {
+        self.base.manifest_trust_key()
+    }
 }
 
 #[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
@@ -1,7 +1,9 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+use std::collections::HashMap;
 use std::marker::PhantomData;
 use std::path::Path;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use edgelet_core::*;
@@ -34,6 +36,7 @@ where
     type Error = E;
     type PullFuture = FutureResult<(), Self::Error>;
     type RemoveFuture = FutureResult<(), Self::Error>;
+    type ImageExistsFuture = FutureResult<bool, Self::Error>;
     type Config = C;
 
     fn pull(&self, _config: &Self::Config) -> Self::PullFuture {
@@ -49,6 +52,13 @@ where
             None => future::ok(()),
         }
     }
+
+    fn image_exists(&self, _config: &Self::Config) -> Self::ImageExistsFuture {
+        match self.err {
+            Some(ref e) => future::err(e.clone()),
+            None => future::ok(false),
+        }
+    }
 }
 
 #[derive(Clone, Debug, serde_derive::Serialize, serde_derive::Deserialize)]
@@ -66,6 +76,12 @@ impl TestConfig {
     }
 }
 
+impl ImageReference for TestConfig {
+    fn image(&self) -> &str {
+        &self.image
+    }
+}
+
 #[derive(Clone, Default, serde_derive::Serialize)]
 pub struct TestSettings;
 
@@ -94,6 +110,10 @@ impl RuntimeSettings for TestSettings {
         unimplemented!()
     }
 
+    fn https_proxy(&self) -> Option<&str> {
+        unimplemented!()
+    }
+
     fn connect(&self) -> &Connect {
         unimplemented!()
     }
@@ -113,6 +133,38 @@ impl RuntimeSettings for TestSettings {
     fn watchdog(&self) -> &WatchdogSettings {
         unimplemented!()
     }
+
+    fn upstream(&self) -> &UpstreamSettings {
+        unimplemented!()
+    }
+
+    fn health(&self) -> &HealthSettings {
+        unimplemented!()
+    }
+
+    fn audit(&self) -> &AuditSettings {
+        unimplemented!()
+    }
+
+    fn logs(&self) -> &LogsSettings {
+        unimplemented!()
+    }
+
+    fn log_forwarding(&self) -> &LogForwardingSettings {
+        unimplemented!()
+    }
+
+    fn admission(&self) -> &AdmissionSettings {
+        unimplemented!()
+    }
+
+    fn webhook(&self) -> Option<&WebhookSettings> {
+        unimplemented!()
+    }
+
+    fn manifest_trust_key(&self) -> Option<&Path> {
+        unimplemented!()
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -191,6 +243,8 @@ where
     module: Option<Result<TestModule<E, S::Config>, E>>,
     registry: TestRegistry<E, S::Config>,
     settings: S,
+    events: ModuleEvents,
+    operations: Arc<Mutex<Vec<String>>>,
 }
 
 impl<E, S> TestRuntime<E, S>
@@ -207,6 +261,19 @@ where
         self.registry = registry;
         self
     }
+
+    /// The modules that were created, started, stopped and removed, in order,
+    /// like `"start tempSensor"`. Clones of the runtime share them.
+    pub fn operations(&self) -> Vec<String> {
+        self.operations.lock().unwrap().clone()
+    }
+
+    fn record(&self, operation: &str, name: &str) {
+        self.operations
+            .lock()
+            .unwrap()
+            .push(format!("{} {}", operation, name));
+    }
 }
 
 impl<E, S> Authenticator for TestRuntime<E, S>
@@ -309,6 +376,8 @@ where
             module: None,
             registry: TestRegistry::new(None),
             settings,
+            events: ModuleEvents::default(),
+            operations: Arc::default(),
         })
     }
 }
@@ -339,29 +408,59 @@ where
     type SystemInfoFuture = FutureResult<SystemInfo, Self::Error>;
     type SystemResourcesFuture = FutureResult<SystemResources, Self::Error>;
     type RemoveAllFuture = FutureResult<(), Self::Error>;
+    type StatsFuture = FutureResult<ModuleStats, Self::Error>;
+    type PruneImagesFuture = FutureResult<PrunedImages, Self::Error>;
+    type ExecStream = Box<dyn Stream<Item = ExecOutput, Error = Self::Error> + Send>;
+    type EffectiveSpecFuture = FutureResult<ModuleSpec<Self::Config>, Self::Error>;
 
-    fn create(&self, _module: ModuleSpec<Self::Config>) -> Self::CreateFuture {
+    fn create(&self, module: ModuleSpec<Self::Config>) -> Self::CreateFuture {
+        self.record("create", module.name());
         match self.module.as_ref().unwrap() {
             Ok(_) => future::ok(()),
             Err(ref e) => future::err(e.clone()),
         }
     }
 
+    fn validate(&self, _module: &ModuleSpec<Self::Config>) -> Result<(), Self::Error> {
+        match self.module.as_ref().unwrap() {
+            Ok(_) => Ok(()),
+            Err(ref e) => Err(e.clone()),
+        }
+    }
+
     fn get(&self, _id: &str) -> Self::GetFuture {
         match self.module.as_ref().unwrap() {
-            Ok(ref m) => future::ok((m.clone(), ModuleRuntimeState::default())),
+            Ok(ref m) => future::ok((m.clone(), m.state.clone().unwrap_or_default())),
+            Err(ref e) => future::err(e.clone()),
+        }
+    }
+
+    fn effective_spec(&self, _id: &str) -> Self::EffectiveSpecFuture {
+        match self.module.as_ref().unwrap() {
+            Ok(ref m) => future::ok(
+                ModuleSpec::new(
+                    m.name().to_string(),
+                    m.type_().to_string(),
+                    m.config().clone(),
+                    HashMap::new(),
+                    ImagePullPolicy::default(),
+                )
+                .expect("test module has an invalid spec"),
+            ),
             Err(ref e) => future::err(e.clone()),
         }
     }
 
-    fn start(&self, _id: &str) -> Self::StartFuture {
+    fn start(&self, id: &str) -> Self::StartFuture {
+        self.record("start", id);
         match self.module.as_ref().unwrap() {
             Ok(_) => future::ok(()),
             Err(ref e) => future::err(e.clone()),
         }
     }
 
-    fn stop(&self, _id: &str, _wait_before_kill: Option<Duration>) -> Self::StopFuture {
+    fn stop(&self, id: &str, _wait_before_kill: Option<Duration>) -> Self::StopFuture {
+        self.record("stop", id);
         match self.module.as_ref().unwrap() {
             Ok(_) => future::ok(()),
             Err(ref e) => future::err(e.clone()),
@@ -375,7 +474,8 @@ where
         }
     }
 
-    fn remove(&self, _id: &str) -> Self::RemoveFuture {
+    fn remove(&self, id: &str) -> Self::RemoveFuture {
+        self.record("remove", id);
         match self.module.as_ref().unwrap() {
             Ok(_) => future::ok(()),
             Err(ref e) => future::err(e.clone()),
@@ -384,10 +484,17 @@ where
 
     fn system_info(&self) -> Self::SystemInfoFuture {
         match self.module.as_ref().unwrap() {
-            Ok(_) => future::ok(SystemInfo::new(
-                "os_type_sample".to_string(),
-                "architecture_sample".to_string(),
-            )),
+            Ok(_) => future::ok(
+                SystemInfo::new(
+                    "os_type_sample".to_string(),
+                    "architecture_sample".to_string(),
+                )
+                .with_runtime_type("runtime_type_sample".to_string())
+                .with_runtime_version(Some("runtime_version_sample".to_string()))
+                .with_operating_system(Some("operating_system_sample".to_string()))
+                .with_kernel_version(Some("kernel_version_sample".to_string()))
+                .with_total_memory(Some(1024)),
+            ),
             Err(ref e) => future::err(e.clone()),
         }
     }
@@ -444,4 +551,33 @@ where
     fn remove_all(&self) -> Self::RemoveAllFuture {
         future::ok(())
     }
+
+    fn stats(&self, id: &str) -> Self::StatsFuture {
+        match self.module.as_ref().unwrap() {
+            Ok(_) => future::ok(ModuleStats::new(id.to_string(), 12.5, 5000, 8000)),
+            Err(ref e) => future::err(e.clone()),
+        }
+    }
+
+    fn prune_images(&self, _filter: ImagePruneFilter) -> Self::PruneImagesFuture {
+        match self.module.as_ref().unwrap() {
+            Ok(_) => future::ok(PrunedImages::new(vec!["sha256:dangling".to_string()], 1024)),
+            Err(ref e) => future::err(e.clone()),
+        }
+    }
+
+    // The command line is echoed back.
+    fn exec(&self, _id: &str, cmd: Vec<String>, _env: HashMap<String, String>) -> Self::ExecStream {
+        match self.module.as_ref().unwrap() {
+            Ok(_) => Box::new(stream::iter_ok(vec![
+                ExecOutput::Output(format!("{}\n", cmd.join(" ")).into()),
+                ExecOutput::Exited(0),
+            ])),
+            Err(ref e) => Box::new(stream::once(Err(e.clone()))),
+        }
+    }
+
+    fn events(&self) -> &ModuleEvents {
+        &self.events
+    }
 }
@@ -0,0 +1,138 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::HashMap;
+use std::env;
+
+use config::{ConfigError, Source, Value};
+
+const SEPARATOR: &str = "__";
+
+/// A `config::Source` of overrides for single settings from environment
+/// variables, so that a setting can be changed without editing the file.
+///
+/// A variable named `{prefix}__WATCHDOG__MAX_RETRIES` sets `watchdog.max_retries`.
+/// Each part of the name is lower-cased to get the key, so settings with
+/// upper-case letters in their keys can't be overridden. The values are
+/// strings that are converted to the type of the setting, and a value that
+/// can't be converted fails with an error that names the variable.
+#[derive(Clone, Debug)]
+pub struct EnvOverrides {
+    vars: Vec<(String, String)>,
+}
+
+impl EnvOverrides {
+    /// The environment variables of the process that start with `prefix`.
+    pub fn from_env(prefix: &str) -> Self {
+        EnvOverrides::from_vars(prefix, env::vars())
+    }
+
+    pub fn from_vars<I>(prefix: &str, vars: I) -> Self
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let prefix = format!("{}{}", prefix, SEPARATOR);
+        let vars = vars
+            .into_iter()
+            .filter(|(name, _)| name.starts_with(&prefix) && name.len() > prefix.len())
+            .collect();
+        EnvOverrides { vars }
+    }
+}
+
+impl Source for EnvOverrides {
+    fn clone_into_box(&self) -> Box<dyn Source + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn collect(&self) -> Result<HashMap<String, Value>, ConfigError> {
+        let mut result = HashMap::new();
+        for (name, value) in &self.vars {
+            let key = name
+                .split(SEPARATOR)
+                .skip(1)
+                .map(str::to_lowercase)
+                .collect::<Vec<_>>();
+            if key.iter().any(String::is_empty) {
+                return Err(ConfigError::Message(format!(
+                    "environment variable {} does not name a setting",
+                    name
+                )));
+            }
+
+            let origin = format!("environment variable {}", name);
+            result.insert(key.join("."), Value::new(Some(&origin), value.clone()));
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use config::Config;
+    use serde_derive::Deserialize;
+
+    use super::*;
+    use crate::YamlFileSource;
+
+    #[derive(Debug, Deserialize)]
+    struct Watchdog {
+        max_retries: u32,
+        exit_on_stall: bool,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Settings {
+        hostname: String,
+        watchdog: Watchdog,
+    }
+
+    fn settings(vars: &[(&str, &str)]) -> Result<Settings, ConfigError> {
+        let mut config = Config::default();
+        config.merge(YamlFileSource::String(
+            "hostname: device\nwatchdog:\n  max_retries: 3\n  exit_on_stall: false\n",
+        ))?;
+        config.merge(EnvOverrides::from_vars(
+            "EDGELET",
+            vars.iter()
+                .map(|(name, value)| ((*name).to_string(), (*value).to_string())),
+        ))?;
+        config.try_into()
+    }
+
+    #[test]
+    fn overrides_nested_scalar() {
+        let settings = settings(&[
+            ("EDGELET__WATCHDOG__MAX_RETRIES", "10"),
+            ("EDGELET__WATCHDOG__EXIT_ON_STALL", "true"),
+            ("EDGELETX__HOSTNAME", "ignored"),
+            ("PATH", "/usr/bin"),
+        ])
+        .unwrap();
+
+        assert_eq!("device", settings.hostname);
+        assert_eq!(10, settings.watchdog.max_retries);
+        assert!(settings.watchdog.exit_on_stall);
+    }
+
+    #[test]
+    fn coercion_failure_names_variable() {
+        let err = settings(&[("EDGELET__WATCHDOG__MAX_RETRIES", "many")]).unwrap_err();
+
+        assert!(
+            err.to_string()
+                .contains("environment variable EDGELET__WATCHDOG__MAX_RETRIES"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn variable_without_setting_fails() {
+        let err = settings(&[("EDGELET__WATCHDOG__", "1")]).unwrap_err();
+
+        assert_eq!(
+            "environment variable EDGELET__WATCHDOG__ does not name a setting",
+            err.to_string()
+        );
+    }
+}
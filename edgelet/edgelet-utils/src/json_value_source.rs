@@ -0,0 +1,101 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use config::{ConfigError, Source, Value};
+use serde_json::{Map, Value as JsonValue};
+
+/// A `config::Source` for settings that were already read, such as a
+/// config.yaml that was migrated after it was read with `YamlFileSource`.
+#[derive(Clone, Debug)]
+pub struct JsonValueSource {
+    origin: Option<String>,
+    value: Map<String, JsonValue>,
+}
+
+impl JsonValueSource {
+    /// Errors in the settings name the file at `origin` that they were read from.
+    pub fn new(origin: &Path, value: Map<String, JsonValue>) -> Self {
+        JsonValueSource {
+            origin: Some(origin.to_string_lossy().into_owned()),
+            value,
+        }
+    }
+}
+
+impl Source for JsonValueSource {
+    fn clone_into_box(&self) -> Box<dyn Source + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn collect(&self) -> Result<HashMap<String, Value>, ConfigError> {
+        let mut result = HashMap::new();
+        for (key, value) in &self.value {
+            result.insert(key.clone(), from_json_value(self.origin.as_ref(), value)?);
+        }
+        Ok(result)
+    }
+}
+
+// Like `from_yaml_value` in `YamlFileSource`, null has no config value to
+// become.
+fn from_json_value(uri: Option<&String>, value: &JsonValue) -> Result<Value, ConfigError> {
+    match value {
+        JsonValue::String(value) => Ok(Value::new(uri, value.clone())),
+        JsonValue::Number(value) => match (value.as_i64(), value.as_f64()) {
+            (Some(value), _) => Ok(Value::new(uri, value)),
+            (None, Some(value)) => Ok(Value::new(uri, value)),
+            (None, None) => Err(ConfigError::Message(format!("invalid number {}", value))),
+        },
+        JsonValue::Bool(value) => Ok(Value::new(uri, *value)),
+        JsonValue::Object(table) => {
+            let mut m = HashMap::new();
+            for (key, value) in table {
+                m.insert(key.clone(), from_json_value(uri, value)?);
+            }
+            Ok(Value::new(uri, m))
+        }
+        JsonValue::Array(array) => {
+            let mut l = vec![];
+            for value in array {
+                l.push(from_json_value(uri, value)?);
+            }
+            Ok(Value::new(uri, l))
+        }
+        JsonValue::Null => Err(ConfigError::Message(format!(
+            "null values are not supported{}",
+            uri.map_or_else(String::new, |uri| format!(" in {}", uri))
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use config::Config;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn settings_round_trip() {
+        let settings = json!({
+            "hostname": "device",
+            "schema_version": 1,
+            "watchdog": { "max_retries": 3, "exit_on_stall": true },
+            "dns": ["1.1.1.1", "8.8.8.8"],
+        });
+        let source = match settings.clone() {
+            JsonValue::Object(settings) => {
+                JsonValueSource::new(Path::new("/etc/iotedge/config.yaml"), settings)
+            }
+            _ => unreachable!(),
+        };
+
+        let mut config = Config::default();
+        config.merge(source).unwrap();
+        let read: JsonValue = config.try_into().unwrap();
+
+        assert_eq!(settings, read);
+    }
+}
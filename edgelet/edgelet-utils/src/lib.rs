@@ -12,7 +12,9 @@
     clippy::use_self
 )]
 
+mod env_overrides;
 mod error;
+mod json_value_source;
 mod logging;
 pub mod macros;
 mod ser_de;
@@ -20,10 +22,15 @@ mod yaml_file_source;
 
 use std::collections::HashMap;
 
+pub use crate::env_overrides::EnvOverrides;
 pub use crate::error::{Error, ErrorKind};
+pub use crate::json_value_source::JsonValueSource;
 pub use crate::logging::log_failure;
 pub use crate::macros::ensure_not_empty_with_context;
-pub use crate::ser_de::{serde_clone, serialize_ordered, string_or_struct};
+pub use crate::ser_de::{
+    bool_or_struct, deserialize_duration, deserialize_enum_ci, parse_duration, serde_clone,
+    serialize_as_string_or_struct, serialize_ordered, string_or_seq, string_or_struct,
+};
 pub use crate::yaml_file_source::YamlFileSource;
 
 pub fn parse_query(query: &str) -> HashMap<&str, &str> {
@@ -1,14 +1,19 @@
 // Copyright (c) Microsoft. All rights reserved.
 
 use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
 use std::fmt;
 use std::hash::BuildHasher;
 use std::marker::PhantomData;
 use std::result::Result as StdResult;
 use std::str::FromStr;
+use std::time::Duration;
 
 use failure::ResultExt;
-use serde::de::{self, Deserialize, DeserializeOwned, Deserializer, MapAccess, Visitor};
+use serde::de::{
+    self, Deserialize, DeserializeOwned, Deserializer, IntoDeserializer, MapAccess, SeqAccess,
+    Visitor,
+};
 use serde::ser::{Serialize, Serializer};
 use serde_json;
 
@@ -18,7 +23,8 @@ use crate::error::{ErrorKind, Result};
 
 pub fn string_or_struct<'de, T, D>(deserializer: D) -> StdResult<T, D::Error>
 where
-    T: Deserialize<'de> + FromStr<Err = serde_json::Error>,
+    T: Deserialize<'de> + FromStr,
+    T::Err: fmt::Display,
     D: Deserializer<'de>,
 {
     // This is a Visitor that forwards string types to T's `FromStr` impl and
@@ -30,7 +36,8 @@ where
 
     impl<'de, T> Visitor<'de> for StringOrStruct<T>
     where
-        T: Deserialize<'de> + FromStr<Err = serde_json::Error>,
+        T: Deserialize<'de> + FromStr,
+        T::Err: fmt::Display,
     {
         type Value = T;
 
@@ -60,6 +67,218 @@ where
     deserializer.deserialize_any(StringOrStruct(PhantomData))
 }
 
+pub fn string_or_seq<'de, T, D>(deserializer: D) -> StdResult<Vec<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    // This is a Visitor that wraps a bare string into a single element `Vec`
+    // (deserializing the element from the string itself) and forwards sequence
+    // types to `Vec<T>`'s `Deserialize` impl.
+    struct StringOrSeq<T>(PhantomData<fn() -> T>);
+
+    impl<'de, T> Visitor<'de> for StringOrSeq<T>
+    where
+        T: Deserialize<'de>,
+    {
+        type Value = Vec<T>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+            formatter.write_str("string or sequence")
+        }
+
+        fn visit_str<E>(self, value: &str) -> StdResult<Vec<T>, E>
+        where
+            E: de::Error,
+        {
+            let item = Deserialize::deserialize(value.into_deserializer())?;
+            Ok(vec![item])
+        }
+
+        fn visit_seq<S>(self, visitor: S) -> StdResult<Vec<T>, S::Error>
+        where
+            S: SeqAccess<'de>,
+        {
+            // `SeqAccessDeserializer` is a wrapper that turns a `SeqAccess`
+            // into a `Deserializer`, allowing it to be used as the input to
+            // `Vec<T>`'s `Deserialize` implementation.
+            Deserialize::deserialize(de::value::SeqAccessDeserializer::new(visitor))
+        }
+    }
+
+    deserializer.deserialize_any(StringOrSeq(PhantomData))
+}
+
+pub fn serialize_as_string_or_struct<T, S>(value: &T, serializer: S) -> StdResult<S::Ok, S::Error>
+where
+    T: fmt::Display + FromStr + PartialEq + Serialize,
+    S: Serializer,
+{
+    // Prefer the compact string form, but only if it parses back into an
+    // equivalent value. Otherwise fall back to T's own `Serialize` impl.
+    let mut compact = String::new();
+    let round_trips = fmt::write(&mut compact, format_args!("{}", value)).is_ok()
+        && T::from_str(&compact)
+            .ok()
+            .map_or(false, |parsed| parsed == *value);
+
+    if round_trips {
+        serializer.serialize_str(&compact)
+    } else {
+        value.serialize(serializer)
+    }
+}
+
+pub fn bool_or_struct<'de, T, D>(deserializer: D) -> StdResult<Option<T>, D::Error>
+where
+    T: Deserialize<'de> + Default,
+    D: Deserializer<'de>,
+{
+    // This is a Visitor that maps `true` to T's `Default` impl, `false` to
+    // `None` and forwards map types to T's `Deserialize` impl.
+    struct BoolOrStruct<T>(PhantomData<fn() -> T>);
+
+    impl<'de, T> Visitor<'de> for BoolOrStruct<T>
+    where
+        T: Deserialize<'de> + Default,
+    {
+        type Value = Option<T>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+            formatter.write_str("bool or map")
+        }
+
+        fn visit_bool<E>(self, value: bool) -> StdResult<Option<T>, E>
+        where
+            E: de::Error,
+        {
+            if value {
+                Ok(Some(T::default()))
+            } else {
+                Ok(None)
+            }
+        }
+
+        fn visit_map<M>(self, visitor: M) -> StdResult<Option<T>, M::Error>
+        where
+            M: MapAccess<'de>,
+        {
+            Deserialize::deserialize(de::value::MapAccessDeserializer::new(visitor)).map(Some)
+        }
+    }
+
+    deserializer.deserialize_any(BoolOrStruct(PhantomData))
+}
+
+pub fn deserialize_duration<'de, D>(deserializer: D) -> StdResult<Duration, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    // This is a Visitor that interprets integers as a number of seconds and
+    // strings as a number followed by one of the "ms", "s", "m" or "h" units.
+    struct DurationVisitor;
+
+    impl<'de> Visitor<'de> for DurationVisitor {
+        type Value = Duration;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+            formatter.write_str("a number of seconds or a duration string like \"30s\"")
+        }
+
+        fn visit_u64<E>(self, value: u64) -> StdResult<Duration, E>
+        where
+            E: de::Error,
+        {
+            Ok(Duration::from_secs(value))
+        }
+
+        fn visit_i64<E>(self, value: i64) -> StdResult<Duration, E>
+        where
+            E: de::Error,
+        {
+            u64::try_from(value).map(Duration::from_secs).map_err(|_| {
+                de::Error::custom(format!(
+                    "invalid duration {}: duration cannot be negative",
+                    value
+                ))
+            })
+        }
+
+        fn visit_str<E>(self, value: &str) -> StdResult<Duration, E>
+        where
+            E: de::Error,
+        {
+            parse_duration(value).map_err(de::Error::custom)
+        }
+    }
+
+    deserializer.deserialize_any(DurationVisitor)
+}
+
+/// Parses a duration the way `deserialize_duration` parses a string, for the
+/// ones that aren't deserialized, like request parameters.
+pub fn parse_duration(value: &str) -> StdResult<Duration, String> {
+    let value = value.trim();
+    let unit_start = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or_else(|| value.len());
+    let (amount, unit) = value.split_at(unit_start);
+
+    let amount: u64 = amount
+        .parse()
+        .map_err(|_| format!("invalid duration {:?}: expected a number", value))?;
+
+    match unit.trim() {
+        "ms" => Ok(Duration::from_millis(amount)),
+        "" | "s" => Ok(Duration::from_secs(amount)),
+        "m" => amount
+            .checked_mul(60)
+            .map(Duration::from_secs)
+            .ok_or_else(|| format!("invalid duration {:?}: value is too large", value)),
+        "h" => amount
+            .checked_mul(60 * 60)
+            .map(Duration::from_secs)
+            .ok_or_else(|| format!("invalid duration {:?}: value is too large", value)),
+        unit => Err(format!(
+            "invalid duration {:?}: unknown unit {:?}, expected one of \"ms\", \"s\", \"m\" or \"h\"",
+            value, unit
+        )),
+    }
+}
+
+pub fn deserialize_enum_ci<'de, T, D>(deserializer: D) -> StdResult<T, D::Error>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+    D: Deserializer<'de>,
+{
+    // This is a Visitor that lowercases the incoming string before handing it
+    // to T's `FromStr` impl, so T only needs to match lowercase variant names.
+    struct EnumCaseInsensitive<T>(PhantomData<fn() -> T>);
+
+    impl<'de, T> Visitor<'de> for EnumCaseInsensitive<T>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        type Value = T;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+            formatter.write_str("a case-insensitive variant name")
+        }
+
+        fn visit_str<E>(self, value: &str) -> StdResult<T, E>
+        where
+            E: de::Error,
+        {
+            T::from_str(&value.to_lowercase())
+                .map_err(|err| de::Error::custom(format!("unknown variant {:?}: {}", value, err)))
+        }
+    }
+
+    deserializer.deserialize_str(EnumCaseInsensitive(PhantomData))
+}
+
 pub fn serde_clone<T>(inp: &T) -> Result<T>
 where
     T: Serialize + DeserializeOwned,
@@ -90,7 +309,7 @@ mod tests {
 
     use super::*;
 
-    #[derive(Debug, Deserialize)]
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
     struct Options {
         opt1: String,
         opt2: Option<String>,
@@ -104,12 +323,146 @@ mod tests {
         }
     }
 
-    #[derive(Debug, Deserialize)]
+    impl fmt::Display for Options {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let s = serde_json::to_string(self).map_err(|_| fmt::Error)?;
+            f.write_str(&s)
+        }
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
     struct Container {
-        #[serde(deserialize_with = "string_or_struct")]
+        #[serde(
+            deserialize_with = "string_or_struct",
+            serialize_with = "serialize_as_string_or_struct"
+        )]
         options: Options,
     }
 
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Lossy {
+        name: String,
+        extra: Option<String>,
+    }
+
+    impl FromStr for Lossy {
+        type Err = serde_json::Error;
+
+        fn from_str(s: &str) -> StdResult<Self, Self::Err> {
+            Ok(Lossy {
+                name: s.to_string(),
+                extra: None,
+            })
+        }
+    }
+
+    impl fmt::Display for Lossy {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str(&self.name)
+        }
+    }
+
+    #[derive(Debug, Serialize)]
+    struct LossyContainer {
+        #[serde(serialize_with = "serialize_as_string_or_struct")]
+        lossy: Lossy,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Pairs {
+        key1: String,
+        key2: Option<String>,
+    }
+
+    #[derive(Debug)]
+    struct ParseError(String);
+
+    impl fmt::Display for ParseError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "invalid pair list: {}", self.0)
+        }
+    }
+
+    impl FromStr for Pairs {
+        type Err = ParseError;
+
+        fn from_str(s: &str) -> StdResult<Self, Self::Err> {
+            let mut pairs: HashMap<&str, &str> = HashMap::new();
+            for pair in s.split(';').filter(|p| !p.is_empty()) {
+                let mut tokens = pair.splitn(2, '=');
+                let key = tokens.next().unwrap_or("");
+                let val = tokens.next().ok_or_else(|| ParseError(pair.to_string()))?;
+                pairs.insert(key, val);
+            }
+
+            Ok(Pairs {
+                key1: pairs
+                    .get("key1")
+                    .map(ToString::to_string)
+                    .ok_or_else(|| ParseError(s.to_string()))?,
+                key2: pairs.get("key2").map(ToString::to_string),
+            })
+        }
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct PairsContainer {
+        #[serde(deserialize_with = "string_or_struct")]
+        pairs: Pairs,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Ports {
+        #[serde(deserialize_with = "string_or_seq")]
+        ports: Vec<String>,
+    }
+
+    #[derive(Debug, Default, Deserialize, PartialEq)]
+    struct Metrics {
+        #[serde(default)]
+        interval: u64,
+        #[serde(default)]
+        endpoint: Option<String>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Features {
+        #[serde(deserialize_with = "bool_or_struct")]
+        metrics: Option<Metrics>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Watchdog {
+        #[serde(deserialize_with = "deserialize_duration")]
+        interval: Duration,
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Policy {
+        Never,
+        OnFailure,
+        Always,
+    }
+
+    impl FromStr for Policy {
+        type Err = String;
+
+        fn from_str(s: &str) -> StdResult<Self, Self::Err> {
+            match s {
+                "never" => Ok(Policy::Never),
+                "on-failure" => Ok(Policy::OnFailure),
+                "always" => Ok(Policy::Always),
+                _ => Err("expected one of never, on-failure or always".to_string()),
+            }
+        }
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct PolicyContainer {
+        #[serde(deserialize_with = "deserialize_enum_ci")]
+        policy: Policy,
+    }
+
     #[derive(Debug, Serialize)]
     struct Setting {
         #[serde(serialize_with = "serialize_ordered")]
@@ -156,6 +509,267 @@ mod tests {
         let _ = serde_json::from_str::<Container>(&container_json).unwrap_err();
     }
 
+    #[test]
+    fn ser_string_sourced_value_to_str() {
+        let options_str = json!({
+            "opt1": "val1",
+            "opt2": "val2"
+        })
+        .to_string();
+        let container_json = json!({ "options": options_str }).to_string();
+
+        let container: Container = serde_json::from_str(&container_json).unwrap();
+        let serialized = serde_json::to_value(&container).unwrap();
+        assert_eq!(json!({ "options": options_str }), serialized);
+
+        let round_tripped: Container = serde_json::from_value(serialized).unwrap();
+        assert_eq!(container.options, round_tripped.options);
+    }
+
+    #[test]
+    fn ser_lossy_value_falls_back_to_struct() {
+        let container = LossyContainer {
+            lossy: Lossy {
+                name: "name".to_string(),
+                extra: Some("extra".to_string()),
+            },
+        };
+
+        let serialized = serde_json::to_value(&container).unwrap();
+        assert_eq!(
+            json!({
+                "lossy": {
+                    "name": "name",
+                    "extra": "extra"
+                }
+            }),
+            serialized
+        );
+    }
+
+    #[test]
+    fn ser_round_tripping_value_to_str() {
+        let container = LossyContainer {
+            lossy: Lossy {
+                name: "name".to_string(),
+                extra: None,
+            },
+        };
+
+        let serialized = serde_json::to_value(&container).unwrap();
+        assert_eq!(json!({ "lossy": "name" }), serialized);
+    }
+
+    #[test]
+    fn toml_string_or_struct_round_trips() {
+        for container_toml in &[
+            "options = '{\"opt1\":\"val1\",\"opt2\":\"val2\"}'\n",
+            "[options]\nopt1 = \"val1\"\nopt2 = \"val2\"\n",
+        ] {
+            let container: Container = toml::from_str(container_toml).unwrap();
+            assert_eq!(&container.options.opt1, "val1");
+            assert_eq!(container.options.opt2.as_ref().unwrap(), "val2");
+
+            let serialized = toml::to_string(&container).unwrap();
+            let round_tripped: Container = toml::from_str(&serialized).unwrap();
+            assert_eq!(container.options, round_tripped.options);
+        }
+    }
+
+    #[test]
+    fn toml_lossy_value_falls_back_to_table() {
+        let container = LossyContainer {
+            lossy: Lossy {
+                name: "name".to_string(),
+                extra: Some("extra".to_string()),
+            },
+        };
+
+        let serialized = toml::to_string(&container).unwrap();
+        let lossy: HashMap<String, Lossy> = toml::from_str(&serialized).unwrap();
+        assert_eq!(container.lossy, lossy["lossy"]);
+    }
+
+    #[test]
+    fn deser_custom_err_from_str() {
+        let container_json = json!({ "pairs": "key1=val1;key2=val2" }).to_string();
+
+        let container: PairsContainer = serde_json::from_str(&container_json).unwrap();
+        assert_eq!(
+            Pairs {
+                key1: "val1".to_string(),
+                key2: Some("val2".to_string()),
+            },
+            container.pairs
+        );
+    }
+
+    #[test]
+    fn deser_custom_err_from_map() {
+        let container_json = json!({ "pairs": { "key1": "val1" } }).to_string();
+
+        let container: PairsContainer = serde_json::from_str(&container_json).unwrap();
+        assert_eq!("val1", container.pairs.key1);
+        assert_eq!(None, container.pairs.key2);
+    }
+
+    #[test]
+    fn deser_custom_err_from_bad_str_fails() {
+        let container_json = json!({ "pairs": "key2" }).to_string();
+
+        let err = serde_json::from_str::<PairsContainer>(&container_json).unwrap_err();
+        assert!(err.to_string().contains("invalid pair list: key2"));
+    }
+
+    #[test]
+    fn deser_seq_from_empty_array() {
+        let ports_json = json!({ "ports": [] }).to_string();
+
+        let ports: Ports = serde_json::from_str(&ports_json).unwrap();
+        assert!(ports.ports.is_empty());
+    }
+
+    #[test]
+    fn deser_seq_from_str() {
+        let ports_json = json!({ "ports": "8883/tcp" }).to_string();
+
+        let ports: Ports = serde_json::from_str(&ports_json).unwrap();
+        assert_eq!(vec!["8883/tcp".to_string()], ports.ports);
+    }
+
+    #[test]
+    fn deser_seq_from_array() {
+        let ports_json = json!({ "ports": ["8883/tcp", "1883/tcp"] }).to_string();
+
+        let ports: Ports = serde_json::from_str(&ports_json).unwrap();
+        assert_eq!(
+            vec!["8883/tcp".to_string(), "1883/tcp".to_string()],
+            ports.ports
+        );
+    }
+
+    #[test]
+    fn deser_seq_from_bad_type_fails() {
+        let ports_json = json!({ "ports": 8883 }).to_string();
+
+        let _ = serde_json::from_str::<Ports>(&ports_json).unwrap_err();
+    }
+
+    #[test]
+    fn deser_bool_or_struct_from_true() {
+        let features_json = json!({ "metrics": true }).to_string();
+
+        let features: Features = serde_json::from_str(&features_json).unwrap();
+        assert_eq!(Some(Metrics::default()), features.metrics);
+    }
+
+    #[test]
+    fn deser_bool_or_struct_from_false() {
+        let features_json = json!({ "metrics": false }).to_string();
+
+        let features: Features = serde_json::from_str(&features_json).unwrap();
+        assert_eq!(None, features.metrics);
+    }
+
+    #[test]
+    fn deser_bool_or_struct_from_map() {
+        let features_json = json!({
+            "metrics": {
+                "interval": 30,
+                "endpoint": "http://localhost:9600"
+            }
+        })
+        .to_string();
+
+        let features: Features = serde_json::from_str(&features_json).unwrap();
+        assert_eq!(
+            Some(Metrics {
+                interval: 30,
+                endpoint: Some("http://localhost:9600".to_string()),
+            }),
+            features.metrics
+        );
+    }
+
+    #[test]
+    fn deser_bool_or_struct_from_bad_type_fails() {
+        let features_json = json!({ "metrics": "yes" }).to_string();
+
+        let _ = serde_json::from_str::<Features>(&features_json).unwrap_err();
+    }
+
+    #[test]
+    fn deser_duration_from_int() {
+        let watchdog_json = json!({ "interval": 15 }).to_string();
+
+        let watchdog: Watchdog = serde_json::from_str(&watchdog_json).unwrap();
+        assert_eq!(Duration::from_secs(15), watchdog.interval);
+    }
+
+    #[test]
+    fn deser_duration_from_str_with_units() {
+        for (input, expected) in &[
+            ("500ms", Duration::from_millis(500)),
+            ("30s", Duration::from_secs(30)),
+            ("5m", Duration::from_secs(5 * 60)),
+            ("2h", Duration::from_secs(2 * 60 * 60)),
+            ("45", Duration::from_secs(45)),
+        ] {
+            let watchdog_json = json!({ "interval": input }).to_string();
+
+            let watchdog: Watchdog = serde_json::from_str(&watchdog_json).unwrap();
+            assert_eq!(*expected, watchdog.interval);
+        }
+    }
+
+    #[test]
+    fn deser_duration_from_negative_int_fails() {
+        let watchdog_json = json!({ "interval": -5 }).to_string();
+
+        let err = serde_json::from_str::<Watchdog>(&watchdog_json).unwrap_err();
+        assert!(err.to_string().contains("-5"));
+    }
+
+    #[test]
+    fn deser_duration_from_bad_unit_fails() {
+        let watchdog_json = json!({ "interval": "10d" }).to_string();
+
+        let err = serde_json::from_str::<Watchdog>(&watchdog_json).unwrap_err();
+        assert!(err.to_string().contains("\"d\""));
+    }
+
+    #[test]
+    fn deser_duration_from_bad_number_fails() {
+        let watchdog_json = json!({ "interval": "s30" }).to_string();
+
+        let err = serde_json::from_str::<Watchdog>(&watchdog_json).unwrap_err();
+        assert!(err.to_string().contains("s30"));
+    }
+
+    #[test]
+    fn deser_enum_ci_mixed_case() {
+        for (input, expected) in &[
+            ("Always", Policy::Always),
+            ("always", Policy::Always),
+            ("ALWAYS", Policy::Always),
+            ("On-Failure", Policy::OnFailure),
+            ("nEvEr", Policy::Never),
+        ] {
+            let container_json = json!({ "policy": input }).to_string();
+
+            let container: PolicyContainer = serde_json::from_str(&container_json).unwrap();
+            assert_eq!(*expected, container.policy);
+        }
+    }
+
+    #[test]
+    fn deser_enum_ci_unknown_variant_fails() {
+        let container_json = json!({ "policy": "Sometimes" }).to_string();
+
+        let err = serde_json::from_str::<PolicyContainer>(&container_json).unwrap_err();
+        assert!(err.to_string().contains("\"Sometimes\""));
+    }
+
     #[test]
     fn serde_clone_succeeds() {
         #[derive(Serialize, Deserialize)]
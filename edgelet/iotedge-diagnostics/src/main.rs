@@ -31,6 +31,17 @@ fn main() -> Result<(), Error> {
                         .help("URI of management endpoint"),
                 ),
         )
+        .subcommand(
+            clap::SubCommand::with_name("workload")
+                .about("connect to the workload endpoint")
+                .arg(
+                    clap::Arg::with_name("workload-uri")
+                        .long("workload-uri")
+                        .required(true)
+                        .takes_value(true)
+                        .help("URI of workload endpoint"),
+                ),
+        )
         .subcommand(
             clap::SubCommand::with_name("iothub")
                 .about("connect to Azure IoT Hub")
@@ -64,39 +75,12 @@ fn main() -> Result<(), Error> {
             let management_uri = url::Url::parse(management_uri)
                 .map_err(|err| format!("could not parse management URI: {}", err))?;
 
-            let list_modules_response = match management_uri.scheme() {
-                "unix" => {
-                    let client =
-                        hyper::Client::builder().build::<_, hyper::Body>(UnixConnector::new());
-                    let uri = UnixUri::new(
-                        management_uri
-                            .to_uds_file_path()
-                            .map_err(|err| format!("couldn't get file path from URI: {}", err))?,
-                        "/modules/?api-version=2018-06-28",
-                    );
-                    client.get(uri.into())
-                }
-
-                "http" => {
-                    let client = hyper::Client::new();
-                    let uri = management_uri
-                        .join("/modules/?api-version=2018-06-28")
-                        .map_err(|err| {
-                            format!("could not construct list-modules request URI: {}", err)
-                        })?;
-                    client.get(uri.to_string().parse().map_err(|err| {
-                        format!("could not convert list-modules request URI from url::Url to hyper::Uri: {}", err)
-                    })?)
-                }
-
-                scheme => {
-                    return Err(format!(
-                        "unrecognized scheme {:?} in management URI {:?}",
-                        scheme, management_uri
-                    )
-                    .into());
-                }
-            };
+            let list_modules_response = get(
+                &management_uri,
+                "/modules/?api-version=2018-06-28",
+                "management",
+                "list-modules",
+            )?;
 
             let f = list_modules_response
                 .then(|response| {
@@ -122,6 +106,38 @@ fn main() -> Result<(), Error> {
             runtime.block_on(f)?;
         }
 
+        ("workload", Some(matches)) => {
+            let workload_uri = matches
+                .value_of("workload-uri")
+                .expect("parameter is required");
+            let workload_uri = url::Url::parse(workload_uri)
+                .map_err(|err| format!("could not parse workload URI: {}", err))?;
+
+            // The trust bundle is the one thing that the workload endpoint
+            // serves to any caller, so getting it only needs the endpoint to
+            // be reachable.
+            let f = get(
+                &workload_uri,
+                "/trust-bundle?api-version=2018-06-28",
+                "workload",
+                "trust-bundle",
+            )?
+            .then(|response| {
+                let response = response
+                    .map_err(|err| format!("could not execute trust-bundle request: {}", err))?;
+                if response.status() != hyper::StatusCode::OK {
+                    return Err(format!(
+                        "trust-bundle request did not succeed: {}",
+                        response.status()
+                    )
+                    .into());
+                }
+                Ok::<_, Error>(())
+            });
+
+            runtime.block_on(f)?;
+        }
+
         ("iothub", Some(matches)) => {
             let iothub_hostname = matches.value_of("hostname").expect("parameter is required");
 
@@ -159,6 +175,45 @@ fn main() -> Result<(), Error> {
     Ok(())
 }
 
+fn get(
+    endpoint_uri: &url::Url,
+    path_and_query: &str,
+    endpoint: &str,
+    request: &str,
+) -> Result<hyper::client::ResponseFuture, Error> {
+    match endpoint_uri.scheme() {
+        "unix" => {
+            let client = hyper::Client::builder().build::<_, hyper::Body>(UnixConnector::new());
+            let uri = UnixUri::new(
+                endpoint_uri
+                    .to_uds_file_path()
+                    .map_err(|err| format!("couldn't get file path from URI: {}", err))?,
+                path_and_query,
+            );
+            Ok(client.get(uri.into()))
+        }
+
+        "http" => {
+            let client = hyper::Client::new();
+            let uri = endpoint_uri
+                .join(path_and_query)
+                .map_err(|err| format!("could not construct {} request URI: {}", request, err))?;
+            Ok(client.get(uri.to_string().parse().map_err(|err| {
+                format!(
+                    "could not convert {} request URI from url::Url to hyper::Uri: {}",
+                    request, err
+                )
+            })?))
+        }
+
+        scheme => Err(format!(
+            "unrecognized scheme {:?} in {} URI {:?}",
+            scheme, endpoint, endpoint_uri
+        )
+        .into()),
+    }
+}
+
 struct Error(String);
 
 impl std::fmt::Debug for Error {
@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::env::consts::ARCH;
 use std::str;
 
@@ -6,26 +7,36 @@ use byte_unit::{Byte, ByteUnit};
 #[cfg(unix)]
 use sysinfo::{DiskExt, SystemExt};
 
-/// Additional info for the JSON output of `iotedge check`
+/// Additional info for the JSON output of `iotedge check`, and the host info
+/// of support bundles
 #[derive(Clone, Debug, serde_derive::Serialize)]
-pub(super) struct AdditionalInfo {
+pub(crate) struct AdditionalInfo {
     pub(super) docker_version: Option<String>,
     pub(super) iotedged_version: Option<String>,
     now: chrono::DateTime<chrono::Utc>,
     os: OsInfo,
     system_info: SystemInfo,
+
+    /// What the checks found about the device
+    #[serde(flatten)]
+    checks: BTreeMap<&'static str, serde_json::Value>,
 }
 
 impl AdditionalInfo {
-    pub(super) fn new() -> Self {
+    pub(crate) fn new() -> Self {
         AdditionalInfo {
             docker_version: None,
             iotedged_version: None,
             now: chrono::Utc::now(),
             os: OsInfo::new(),
             system_info: SystemInfo::new(),
+            checks: BTreeMap::new(),
         }
     }
+
+    pub(super) fn insert(&mut self, key: &'static str, value: serde_json::Value) {
+        self.checks.insert(key, value);
+    }
 }
 
 /// A subset of the fields from /etc/os-release.
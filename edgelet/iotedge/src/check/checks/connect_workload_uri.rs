@@ -0,0 +1,232 @@
+use std::borrow::Cow;
+use std::ffi::{OsStr, OsString};
+
+use failure::{self, Context, Fail, ResultExt};
+use url::Url;
+
+use edgelet_core::{self, RuntimeSettings, UrlExt};
+
+use crate::check::{checker::Checker, Check, CheckResult};
+
+#[derive(Default, serde_derive::Serialize)]
+pub(crate) struct ConnectWorkloadUri {
+    connect_workload_uri: Option<String>,
+    listen_workload_uri: Option<String>,
+    mount: Option<String>,
+}
+
+impl Checker for ConnectWorkloadUri {
+    fn id(&self) -> &'static str {
+        "connect-workload-uri"
+    }
+    fn description(&self) -> &'static str {
+        "daemon workload endpoint is reachable from containers"
+    }
+    fn execute(&mut self, check: &mut Check) -> CheckResult {
+        self.inner_execute(check)
+            .unwrap_or_else(CheckResult::Failed)
+    }
+    fn get_json(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap()
+    }
+}
+
+impl ConnectWorkloadUri {
+    fn inner_execute(&mut self, check: &mut Check) -> Result<CheckResult, failure::Error> {
+        let settings = if let Some(settings) = &check.settings {
+            settings
+        } else {
+            return Ok(CheckResult::Skipped);
+        };
+
+        let docker_host_arg = if let Some(docker_host_arg) = &check.docker_host_arg {
+            docker_host_arg
+        } else {
+            return Ok(CheckResult::Skipped);
+        };
+
+        let connect_workload_uri = settings.connect().workload_uri();
+        let listen_workload_uri = settings.listen().workload_uri();
+
+        self.connect_workload_uri = Some(format!("{}", connect_workload_uri));
+        self.listen_workload_uri = Some(format!("{}", listen_workload_uri));
+
+        let mut args: Vec<Cow<'_, OsStr>> = vec![
+            Cow::Borrowed(OsStr::new("run")),
+            Cow::Borrowed(OsStr::new("--rm")),
+        ];
+
+        for (name, value) in settings.agent().env() {
+            args.push(Cow::Borrowed(OsStr::new("-e")));
+            args.push(Cow::Owned(format!("{}={}", name, value).into()));
+        }
+
+        // The container gets the socket the same way that modules do, so a
+        // socket that the container can't reach is one that modules can't
+        // reach either.
+        if let Some(mount) = module_mount(connect_workload_uri, listen_workload_uri)? {
+            args.push(Cow::Borrowed(OsStr::new("-v")));
+            args.push(Cow::Owned(mount.clone().into()));
+            self.mount = Some(mount);
+        }
+
+        args.extend(vec![
+            Cow::Borrowed(OsStr::new(&check.diagnostics_image_name)),
+            Cow::Borrowed(OsStr::new("/iotedge-diagnostics")),
+            Cow::Borrowed(OsStr::new("workload")),
+            Cow::Borrowed(OsStr::new("--workload-uri")),
+            Cow::Owned(OsString::from(connect_workload_uri.to_string())),
+        ]);
+
+        match super::docker(docker_host_arg, args) {
+            Ok(_) => Ok(CheckResult::Ok),
+            Err((Some(stderr), err)) => {
+                let hint = mount_hint(connect_workload_uri, listen_workload_uri)?;
+                Err(err.context(stderr).context(hint).into())
+            }
+            Err((None, err)) => Err(err.context("Could not spawn docker process").into()),
+        }
+    }
+}
+
+/// The mount that modules get the workload socket with, `None` if the
+/// workload endpoint isn't a socket.
+fn module_mount(connect: &Url, listen: &Url) -> Result<Option<String>, failure::Error> {
+    match (connect.scheme(), listen.scheme()) {
+        ("http", "http") => Ok(None),
+
+        ("unix", "unix") | ("unix", "fd") => {
+            let path = mount_path(connect, "connect.workload_uri")?;
+            Ok(Some(format!("{}:{}", path, path)))
+        }
+
+        (scheme1, scheme2) if scheme1 != scheme2 => Err(Context::new(
+            format!(
+                "config.yaml has invalid combination of schemes for connect.workload_uri ({:?}) and listen.workload_uri ({:?})",
+                scheme1, scheme2,
+            ))
+            .into()),
+
+        (scheme, _) => Err(Context::new(format!(
+            "Could not parse connect.workload_uri: scheme {} is invalid",
+            scheme
+        ))
+        .into()),
+    }
+}
+
+/// What to change when the workload endpoint can't be reached from a
+/// container, naming the exact mount that modules should use.
+fn mount_hint(connect: &Url, listen: &Url) -> Result<String, failure::Error> {
+    let mount = match module_mount(connect, listen)? {
+        Some(mount) => mount,
+        None => {
+            return Ok(format!(
+                "Could not reach the workload endpoint {} from a container. Make sure that iotedged is running and that {} is reachable from the containers' network.",
+                connect, connect,
+            ));
+        }
+    };
+
+    let connect_path = mount_path(connect, "connect.workload_uri")?;
+
+    // With socket activation the socket is wherever the socket unit puts it,
+    // so only a socket that iotedged creates itself can be compared.
+    if listen.scheme() == "unix" {
+        let listen_path = mount_path(listen, "listen.workload_uri")?;
+        if listen_path != connect_path {
+            return Ok(format!(
+                "Could not reach the workload endpoint from a container that mounts it with `-v {}`, because iotedged listens on {} and not on {}. Modules should mount it with `-v {}:{}`, or connect.workload_uri in config.yaml should be changed to {}.",
+                mount, listen_path, connect_path, listen_path, connect_path, listen,
+            ));
+        }
+    }
+
+    Ok(format!(
+        "Could not reach the workload endpoint from a container that mounts it with `-v {}`. Modules should mount it with `-v {}`; make sure that iotedged is running and listening on {}.",
+        mount, mount, connect_path,
+    ))
+}
+
+fn mount_path(uri: &Url, setting: &str) -> Result<String, failure::Error> {
+    let path = uri.to_uds_file_path().context(format!(
+        "Could not parse {}: does not represent a valid file path",
+        setting
+    ))?;
+
+    // On Windows we mount the parent folder because we can't mount the socket files directly
+    #[cfg(windows)]
+    let path = path.parent().ok_or_else(|| {
+        Context::new(format!(
+            "Could not parse {}: does not have a parent directory",
+            setting
+        ))
+    })?;
+
+    let path = path.to_str().ok_or_else(|| {
+        Context::new(format!(
+            "Could not parse {}: file path is not valid utf-8",
+            setting
+        ))
+    })?;
+
+    Ok(path.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(unix)]
+    fn misconfigured_mount_hints_at_the_mount_to_use() {
+        let connect = Url::parse("unix:///var/run/iotedge/workload.sock").unwrap();
+        let listen = Url::parse("unix:///var/lib/iotedge/workload.sock").unwrap();
+
+        assert_eq!(
+            Some("/var/run/iotedge/workload.sock:/var/run/iotedge/workload.sock".to_owned()),
+            module_mount(&connect, &listen).unwrap()
+        );
+
+        let hint = mount_hint(&connect, &listen).unwrap();
+        assert!(
+            hint.contains("`-v /var/lib/iotedge/workload.sock:/var/run/iotedge/workload.sock`"),
+            "{}",
+            hint
+        );
+        assert!(
+            hint.contains("unix:///var/lib/iotedge/workload.sock"),
+            "{}",
+            hint
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn unreachable_socket_hints_at_the_standard_mount() {
+        let connect = Url::parse("unix:///var/run/iotedge/workload.sock").unwrap();
+
+        for listen in &[
+            "unix:///var/run/iotedge/workload.sock",
+            "fd://iotedge.workload.socket",
+        ] {
+            let hint = mount_hint(&connect, &Url::parse(listen).unwrap()).unwrap();
+            assert!(
+                hint.contains(
+                    "Modules should mount it with `-v /var/run/iotedge/workload.sock:/var/run/iotedge/workload.sock`"
+                ),
+                "{}",
+                hint
+            );
+        }
+    }
+
+    #[test]
+    fn mismatched_schemes_fail() {
+        let connect = Url::parse("http://localhost:15581").unwrap();
+        let listen = Url::parse("unix:///var/run/iotedge/workload.sock").unwrap();
+
+        assert!(module_mount(&connect, &listen).is_err());
+        assert!(mount_hint(&connect, &listen).is_err());
+    }
+}
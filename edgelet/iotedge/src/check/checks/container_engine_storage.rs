@@ -0,0 +1,320 @@
+use std::path::{Path, PathBuf};
+
+use failure::{self, Context, ResultExt};
+#[cfg(unix)]
+use sysinfo::{DiskExt, SystemExt};
+
+use crate::check::{checker::Checker, Check, CheckResult};
+
+const MIB: u64 = 1024 * 1024;
+
+/// Storage drivers that are deprecated or that are known to fill up or corrupt the disk of
+/// long-running devices.
+const PROBLEMATIC_STORAGE_DRIVERS: &[(&str, &str)] = &[
+    ("aufs", "is deprecated"),
+    (
+        "devicemapper",
+        "is deprecated, and in its default loop-lvm mode is not suitable for production",
+    ),
+    ("overlay", "is deprecated in favor of overlay2"),
+    (
+        "vfs",
+        "does not share layers between images, so it uses much more disk space",
+    ),
+];
+
+#[derive(Default, serde_derive::Serialize)]
+pub(crate) struct ContainerEngineStorageDriver {
+    storage_driver: Option<String>,
+    docker_root_dir: Option<PathBuf>,
+}
+
+impl Checker for ContainerEngineStorageDriver {
+    fn id(&self) -> &'static str {
+        "container-engine-storage-driver"
+    }
+    fn description(&self) -> &'static str {
+        "production readiness: container engine storage driver"
+    }
+    fn execute(&mut self, check: &mut Check) -> CheckResult {
+        self.inner_execute(check)
+            .unwrap_or_else(CheckResult::Failed)
+    }
+    fn get_json(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap()
+    }
+}
+
+impl ContainerEngineStorageDriver {
+    fn inner_execute(&mut self, check: &mut Check) -> Result<CheckResult, failure::Error> {
+        let docker_host_arg = if let Some(docker_host_arg) = &check.docker_host_arg {
+            docker_host_arg
+        } else {
+            return Ok(CheckResult::Skipped);
+        };
+
+        let output = super::docker(docker_host_arg, &["info", "--format", "{{json .}}"])
+            .map_err(|(_, err)| err)
+            .context("Could not query the container engine for its storage driver")?;
+
+        let result = self.check_docker_info(&output)?;
+        check.docker_root_dir = self.docker_root_dir.clone();
+        check.add_additional_info(
+            "docker_storage_driver",
+            serde_json::to_value(&self.storage_driver)?,
+        );
+        Ok(result)
+    }
+
+    fn check_docker_info(&mut self, docker_info: &[u8]) -> Result<CheckResult, failure::Error> {
+        let DockerInfo {
+            driver: storage_driver,
+            docker_root_dir,
+        } = serde_json::from_slice(docker_info)
+            .context("Could not parse the container engine's info")?;
+        self.docker_root_dir = Some(docker_root_dir);
+
+        let problem = PROBLEMATIC_STORAGE_DRIVERS
+            .iter()
+            .find(|(driver, _)| *driver == storage_driver);
+        self.storage_driver = Some(storage_driver);
+        if let Some((driver, reason)) = problem {
+            return Ok(CheckResult::Warning(
+                Context::new(format!(
+                    "Container engine uses the {} storage driver, which {}.\n\
+                     Please configure the container engine to use the overlay2 storage driver.",
+                    driver, reason,
+                ))
+                .into(),
+            ));
+        }
+
+        Ok(CheckResult::Ok)
+    }
+}
+
+#[derive(Default, serde_derive::Serialize)]
+pub(crate) struct ContainerEngineDiskSpace {
+    docker_root_dir: Option<PathBuf>,
+    free_bytes: Option<u64>,
+}
+
+impl Checker for ContainerEngineDiskSpace {
+    fn id(&self) -> &'static str {
+        "container-engine-disk-space"
+    }
+    fn description(&self) -> &'static str {
+        "container engine has enough free disk space"
+    }
+    fn execute(&mut self, check: &mut Check) -> CheckResult {
+        self.inner_execute(check)
+            .unwrap_or_else(CheckResult::Failed)
+    }
+    fn get_json(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap()
+    }
+}
+
+impl ContainerEngineDiskSpace {
+    fn inner_execute(&mut self, check: &mut Check) -> Result<CheckResult, failure::Error> {
+        let docker_root_dir = if let Some(docker_root_dir) = &check.docker_root_dir {
+            docker_root_dir
+        } else {
+            return Ok(CheckResult::Skipped);
+        };
+
+        #[cfg(unix)]
+        {
+            let mut system = sysinfo::System::new();
+            system.refresh_all();
+            let disks: Vec<_> = system
+                .get_disks()
+                .iter()
+                .map(|disk| {
+                    (
+                        disk.get_mount_point().to_owned(),
+                        disk.get_available_space(),
+                    )
+                })
+                .collect();
+
+            let free_bytes = free_space(&disks, docker_root_dir).ok_or_else(|| {
+                Context::new(format!(
+                    "Could not find the disk that holds the container engine's data root {}",
+                    docker_root_dir.display(),
+                ))
+            })?;
+
+            Ok(self.check_free_space(
+                docker_root_dir,
+                free_bytes,
+                check.disk_space_warning * MIB,
+                check.disk_space_error * MIB,
+            ))
+        }
+
+        // The container engine's data root on Windows is in the host's ProgramData, which is
+        // on the system drive that the OS itself warns about when it fills up.
+        #[cfg(windows)]
+        {
+            self.docker_root_dir = Some(docker_root_dir.clone());
+            Ok(CheckResult::Ignored)
+        }
+    }
+
+    #[cfg_attr(windows, allow(dead_code))]
+    fn check_free_space(
+        &mut self,
+        docker_root_dir: &Path,
+        free_bytes: u64,
+        warning_bytes: u64,
+        error_bytes: u64,
+    ) -> CheckResult {
+        self.docker_root_dir = Some(docker_root_dir.to_owned());
+        self.free_bytes = Some(free_bytes);
+
+        let message = format!(
+            "The disk that holds the container engine's data root {} has {} bytes ({} MiB) free.",
+            docker_root_dir.display(),
+            free_bytes,
+            free_bytes / MIB,
+        );
+
+        if free_bytes < error_bytes {
+            return CheckResult::Failed(
+                Context::new(format!(
+                    "{}\n\
+                     Modules can't be pulled or started once it's full. Please free up space, for example with `docker system prune`.",
+                    message,
+                ))
+                .into(),
+            );
+        }
+
+        if free_bytes < warning_bytes {
+            return CheckResult::Warning(
+                Context::new(format!(
+                    "{}\n\
+                     Modules can't be pulled or started once it's full. Please consider freeing up space.",
+                    message,
+                ))
+                .into(),
+            );
+        }
+
+        CheckResult::Ok
+    }
+}
+
+/// The free space on the disk whose mount point is the longest prefix of `path`.
+#[cfg_attr(windows, allow(dead_code))]
+fn free_space(disks: &[(PathBuf, u64)], path: &Path) -> Option<u64> {
+    disks
+        .iter()
+        .filter(|(mount_point, _)| path.starts_with(mount_point))
+        .max_by_key(|(mount_point, _)| mount_point.components().count())
+        .map(|(_, free_bytes)| *free_bytes)
+}
+
+#[derive(serde_derive::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct DockerInfo {
+    driver: String,
+    docker_root_dir: PathBuf,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overlay2_storage_driver_is_ok() {
+        let mut check = ContainerEngineStorageDriver::default();
+        let docker_info =
+            br#"{"Containers":3,"Driver":"overlay2","DockerRootDir":"/var/lib/docker"}"#;
+        match check.check_docker_info(docker_info).unwrap() {
+            CheckResult::Ok => (),
+            check_result => panic!("overlay2 returned {:?}", check_result),
+        }
+        assert_eq!(
+            Some("overlay2"),
+            check.storage_driver.as_ref().map(String::as_str)
+        );
+        assert_eq!(
+            Some(Path::new("/var/lib/docker")),
+            check.docker_root_dir.as_ref().map(PathBuf::as_path)
+        );
+    }
+
+    #[test]
+    fn problematic_storage_driver_warns() {
+        let mut check = ContainerEngineStorageDriver::default();
+        let docker_info = br#"{"Driver":"devicemapper","DockerRootDir":"/var/lib/docker"}"#;
+        match check.check_docker_info(docker_info).unwrap() {
+            CheckResult::Warning(warning) => assert!(
+                warning
+                    .to_string()
+                    .starts_with("Container engine uses the devicemapper storage driver"),
+                "{}",
+                warning,
+            ),
+            check_result => panic!("devicemapper returned {:?}", check_result),
+        }
+    }
+
+    #[test]
+    fn malformed_docker_info_fails() {
+        let mut check = ContainerEngineStorageDriver::default();
+        check
+            .check_docker_info(br#"{"Driver":"overlay2"}"#)
+            .unwrap_err();
+    }
+
+    #[test]
+    fn free_space_by_severity() {
+        let root = Path::new("/var/lib/docker");
+
+        let mut check = ContainerEngineDiskSpace::default();
+        match check.check_free_space(root, 2048 * MIB, 1024 * MIB, 256 * MIB) {
+            CheckResult::Ok => (),
+            check_result => panic!("2 GiB free returned {:?}", check_result),
+        }
+        assert_eq!(Some(2048 * MIB), check.free_bytes);
+
+        match check.check_free_space(root, 512 * MIB, 1024 * MIB, 256 * MIB) {
+            CheckResult::Warning(warning) => assert!(
+                warning.to_string().starts_with(
+                    "The disk that holds the container engine's data root /var/lib/docker has 536870912 bytes (512 MiB) free."
+                ),
+                "{}",
+                warning,
+            ),
+            check_result => panic!("512 MiB free returned {:?}", check_result),
+        }
+
+        match check.check_free_space(root, 100 * MIB, 1024 * MIB, 256 * MIB) {
+            CheckResult::Failed(err) => assert!(
+                err.to_string()
+                    .contains("has 104857600 bytes (100 MiB) free"),
+                "{}",
+                err,
+            ),
+            check_result => panic!("100 MiB free returned {:?}", check_result),
+        }
+    }
+
+    #[test]
+    fn free_space_is_from_the_closest_mount_point() {
+        let disks = vec![
+            (PathBuf::from("/"), 10 * MIB),
+            (PathBuf::from("/var"), 20 * MIB),
+            (PathBuf::from("/var/lib/docker-other"), 30 * MIB),
+        ];
+        assert_eq!(
+            Some(20 * MIB),
+            free_space(&disks, Path::new("/var/lib/docker"))
+        );
+        assert_eq!(Some(10 * MIB), free_space(&disks, Path::new("/data")));
+        assert_eq!(None, free_space(&[], Path::new("/var/lib/docker")));
+    }
+}
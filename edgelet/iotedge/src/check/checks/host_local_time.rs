@@ -1,10 +1,16 @@
-use failure::{self, Context, Fail};
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use failure::{self, Context, Fail, ResultExt};
 
 use crate::check::{checker::Checker, Check, CheckResult};
 
 #[derive(Default, serde_derive::Serialize)]
 pub(crate) struct HostLocalTime {
     offset: Option<i64>,
+    reference: Option<String>,
 }
 
 impl Checker for HostLocalTime {
@@ -37,35 +43,197 @@ impl HostLocalTime {
             }
         }
 
-        let mini_sntp::SntpTimeQueryResult {
-            local_clock_offset, ..
-        } = match mini_sntp::query(&check.ntp_server) {
-            Ok(result) => result,
+        // The IoT Hub's clock is the fallback for devices that can't reach the NTP server,
+        // like those whose firewall only lets HTTPS out.
+        let offset = match mini_sntp::query(&check.ntp_server) {
+            Ok(mini_sntp::SntpTimeQueryResult {
+                local_clock_offset, ..
+            }) => Some((check.ntp_server.clone(), local_clock_offset)),
             Err(err) => {
-                if is_server_unreachable_error(&err) {
-                    return Ok(CheckResult::Warning(
-                        err.context("Could not query NTP server").into(),
-                    ));
-                } else {
+                if !is_server_unreachable_error(&err) {
                     return Err(err.context("Could not query NTP server").into());
                 }
+
+                check.iothub_hostname.as_ref().and_then(|iothub_hostname| {
+                    iothub_clock_offset(iothub_hostname)
+                        .ok()
+                        .map(|offset| (iothub_hostname.clone(), offset))
+                })
             }
         };
 
-        let offset = local_clock_offset.num_seconds().abs();
+        Ok(self.check_offset(offset, check.max_clock_skew))
+    }
+
+    /// `offset` is how far ahead of the reference the local clock is, or `None` if no
+    /// reference could be reached, in which case the check is skipped.
+    fn check_offset(
+        &mut self,
+        offset: Option<(String, chrono::Duration)>,
+        max_clock_skew: u64,
+    ) -> CheckResult {
+        let (reference, offset) = match offset {
+            Some(offset) => offset,
+            None => return CheckResult::Skipped,
+        };
+
+        let offset = offset.num_seconds().abs();
         self.offset = Some(offset);
-        if offset >= 10 {
-            return Ok(CheckResult::Warning(Context::new(format!(
-            "Time on the device is out of sync with the NTP server. This may cause problems connecting to IoT Hub.\n\
+        self.reference = Some(reference);
+        #[allow(clippy::cast_possible_wrap)]
+        let max_clock_skew = max_clock_skew as i64;
+        if offset > max_clock_skew {
+            return CheckResult::Warning(Context::new(format!(
+            "Time on the device is out of sync with {} by {}s, which is more than the allowed {}s. This may cause problems connecting to IoT Hub.\n\
              Please ensure time on device is accurate, for example by {}.",
+            self.reference.as_ref().expect("just set"),
+            offset,
+            max_clock_skew,
             if cfg!(windows) {
                 "setting up the Windows Time service to automatically sync with a time server"
             } else {
                 "installing an NTP daemon"
             },
-        )).into()));
+        )).into());
         }
 
-        Ok(CheckResult::Ok)
+        CheckResult::Ok
+    }
+}
+
+/// How far ahead of the IoT Hub's clock the local clock is, going by the `Date` header of a
+/// response from the hub. The header only has whole seconds, and the local time it's compared
+/// against is halfway between sending the request and reading the response.
+fn iothub_clock_offset(iothub_hostname: &str) -> Result<chrono::Duration, failure::Error> {
+    let host_addr = (iothub_hostname, 443)
+        .to_socket_addrs()
+        .with_context(|_| format!("Could not resolve {}", iothub_hostname))?
+        .next()
+        .ok_or_else(|| Context::new(format!("Could not resolve {}", iothub_hostname)))?;
+
+    let stream = TcpStream::connect_timeout(&host_addr, Duration::from_secs(10))
+        .with_context(|_| format!("Could not connect to {}", iothub_hostname))?;
+    stream
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .with_context(|_| format!("Could not connect to {}", iothub_hostname))?;
+
+    let tls_connector = native_tls::TlsConnector::new()
+        .with_context(|_| format!("Could not connect to {}", iothub_hostname))?;
+    let mut stream = tls_connector
+        .connect(iothub_hostname, stream)
+        .with_context(|_| format!("Could not connect to {}", iothub_hostname))?;
+
+    let sent = Utc::now();
+    write!(
+        stream,
+        "HEAD / HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        iothub_hostname,
+    )
+    .with_context(|_| format!("Could not send request to {}", iothub_hostname))?;
+    let mut response = vec![];
+    stream
+        .read_to_end(&mut response)
+        .with_context(|_| format!("Could not read response from {}", iothub_hostname))?;
+    let received = Utc::now();
+
+    let date = parse_date_header(&String::from_utf8_lossy(&response)).ok_or_else(|| {
+        Context::new(format!(
+            "Response from {} has no valid Date header",
+            iothub_hostname
+        ))
+    })?;
+
+    let local = sent + (received - sent) / 2;
+    Ok(local - date)
+}
+
+fn parse_date_header(response: &str) -> Option<DateTime<Utc>> {
+    response
+        .lines()
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| {
+            let mut parts = line.splitn(2, ':');
+            let name = parts.next()?;
+            let value = parts.next()?;
+            if name.trim().eq_ignore_ascii_case("date") {
+                Some(value.trim())
+            } else {
+                None
+            }
+        })
+        .next()
+        .and_then(|value| DateTime::parse_from_rfc2822(value).ok())
+        .map(|date| date.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offset_within_max_clock_skew_is_ok() {
+        let mut check = HostLocalTime::default();
+        match check.check_offset(
+            Some(("pool.ntp.org:123".to_owned(), chrono::Duration::seconds(-3))),
+            5,
+        ) {
+            CheckResult::Ok => (),
+            check_result => panic!("offset of -3s returned {:?}", check_result),
+        }
+        assert_eq!(Some(3), check.offset);
+        assert_eq!(
+            Some("pool.ntp.org:123"),
+            check.reference.as_ref().map(String::as_str)
+        );
+    }
+
+    #[test]
+    fn offset_beyond_max_clock_skew_warns() {
+        let mut check = HostLocalTime::default();
+        match check.check_offset(
+            Some(("hub.azure-devices.net".to_owned(), chrono::Duration::seconds(7))),
+            5,
+        ) {
+            CheckResult::Warning(warning) => assert!(
+                warning
+                    .to_string()
+                    .starts_with("Time on the device is out of sync with hub.azure-devices.net by 7s, which is more than the allowed 5s."),
+                "{}",
+                warning,
+            ),
+            check_result => panic!("offset of 7s returned {:?}", check_result),
+        }
+        assert_eq!(Some(7), check.offset);
+    }
+
+    #[test]
+    fn unreachable_reference_is_skipped() {
+        let mut check = HostLocalTime::default();
+        match check.check_offset(None, 5) {
+            CheckResult::Skipped => (),
+            check_result => panic!("unreachable reference returned {:?}", check_result),
+        }
+        assert_eq!(None, check.offset);
+    }
+
+    #[test]
+    fn parses_date_header() {
+        let response = "HTTP/1.1 404 Not Found\r\n\
+                        Content-Length: 0\r\n\
+                        date: Wed, 14 Oct 2026 10:20:30 GMT\r\n\
+                        \r\n";
+        assert_eq!(
+            Some(
+                DateTime::parse_from_rfc3339("2026-10-14T10:20:30Z")
+                    .unwrap()
+                    .with_timezone(&Utc)
+            ),
+            parse_date_header(response)
+        );
+
+        assert_eq!(
+            None,
+            parse_date_header("HTTP/1.1 200 OK\r\n\r\nDate: nope\r\n")
+        );
     }
 }
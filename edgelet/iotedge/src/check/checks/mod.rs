@@ -1,11 +1,13 @@
 mod certificates_quickstart;
 mod connect_management_uri;
+mod connect_workload_uri;
 mod container_connect_iothub;
 mod container_engine_dns;
 mod container_engine_installed;
 mod container_engine_ipv6;
 mod container_engine_is_moby;
 mod container_engine_logrotate;
+mod container_engine_storage;
 mod container_local_time;
 mod host_connect_dps_endpoint;
 mod host_connect_iothub;
@@ -20,12 +22,16 @@ mod windows_host_version;
 
 pub(crate) use self::certificates_quickstart::CertificatesQuickstart;
 pub(crate) use self::connect_management_uri::ConnectManagementUri;
+pub(crate) use self::connect_workload_uri::ConnectWorkloadUri;
 pub(crate) use self::container_connect_iothub::get_host_container_iothub_tests;
 pub(crate) use self::container_engine_dns::ContainerEngineDns;
 pub(crate) use self::container_engine_installed::ContainerEngineInstalled;
 pub(crate) use self::container_engine_ipv6::ContainerEngineIPv6;
 pub(crate) use self::container_engine_is_moby::ContainerEngineIsMoby;
 pub(crate) use self::container_engine_logrotate::ContainerEngineLogrotate;
+pub(crate) use self::container_engine_storage::{
+    ContainerEngineDiskSpace, ContainerEngineStorageDriver,
+};
 pub(crate) use self::container_local_time::ContainerLocalTime;
 pub(crate) use self::host_connect_dps_endpoint::HostConnectDpsEndpoint;
 pub(crate) use self::host_connect_iothub::get_host_connect_iothub_tests;
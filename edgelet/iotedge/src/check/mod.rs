@@ -1,7 +1,7 @@
 // Copyright (c) Microsoft. All rights reserved.
 
 use std;
-use std::collections::{BTreeMap, BTreeSet};
+use std::collections::BTreeSet;
 use std::io::Write;
 use std::path::PathBuf;
 
@@ -19,7 +19,13 @@ use crate::error::{Error, ErrorKind, FetchLatestVersionsReason};
 use crate::LatestVersions;
 
 mod additional_info;
-use self::additional_info::AdditionalInfo;
+pub(crate) use self::additional_info::AdditionalInfo;
+
+mod output;
+use self::output::{CheckOutputSerializable, CheckResultSerializable, Severity};
+
+mod selection;
+pub use self::selection::CheckSelection;
 
 mod stdout;
 use self::stdout::Stdout;
@@ -36,11 +42,17 @@ pub struct Check {
     config_file: PathBuf,
     container_engine_config_path: PathBuf,
     diagnostics_image_name: String,
+    #[cfg_attr(windows, allow(dead_code))]
+    disk_space_error: u64,
+    #[cfg_attr(windows, allow(dead_code))]
+    disk_space_warning: u64,
     dont_run: BTreeSet<String>,
     iotedged: PathBuf,
     latest_versions: Result<super::LatestVersions, Option<Error>>,
+    max_clock_skew: u64,
     ntp_server: String,
     output_format: OutputFormat,
+    selection: CheckSelection,
     verbose: bool,
     warnings_as_errors: bool,
 
@@ -52,12 +64,14 @@ pub struct Check {
     settings: Option<Settings>,
     docker_host_arg: Option<String>,
     docker_server_version: Option<String>,
+    docker_root_dir: Option<PathBuf>,
     device_ca_cert_path: Option<PathBuf>,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum OutputFormat {
     Json,
+    JUnit,
     Text,
 }
 
@@ -75,7 +89,8 @@ pub enum CheckResult {
     /// Check is not applicable and was ignored. Should be treated as success.
     Ignored,
 
-    /// Check was skipped because of errors from some previous checks. Should be treated as an error.
+    /// Check was skipped because of errors from some previous checks, or because a server it needs is unreachable.
+    /// Should be treated as an error.
     Skipped,
 
     /// Check failed, and further checks should be performed.
@@ -90,12 +105,16 @@ impl Check {
         config_file: PathBuf,
         container_engine_config_path: PathBuf,
         diagnostics_image_name: String,
+        disk_space_error: u64,
+        disk_space_warning: u64,
         dont_run: BTreeSet<String>,
         expected_iotedged_version: Option<String>,
         iotedged: PathBuf,
         iothub_hostname: Option<String>,
+        max_clock_skew: u64,
         ntp_server: String,
         output_format: OutputFormat,
+        selection: CheckSelection,
         verbose: bool,
         warnings_as_errors: bool,
     ) -> impl Future<Item = Self, Error = Error> + Send {
@@ -197,11 +216,15 @@ impl Check {
                 config_file,
                 container_engine_config_path,
                 diagnostics_image_name,
+                disk_space_error,
+                disk_space_warning,
                 dont_run,
                 iotedged,
                 latest_versions: latest_versions.map_err(Some),
+                max_clock_skew,
                 ntp_server,
                 output_format,
+                selection,
                 verbose,
                 warnings_as_errors,
 
@@ -210,16 +233,19 @@ impl Check {
                 settings: None,
                 docker_host_arg: None,
                 docker_server_version: None,
+                docker_root_dir: None,
                 iothub_hostname,
                 device_ca_cert_path: None,
             })
         }))
     }
 
-    fn checks() -> [(&'static str, Vec<Box<dyn Checker>>); 2] {
+    /// The category ID, the category name and the checks of each category
+    fn checks() -> [(&'static str, &'static str, Vec<Box<dyn Checker>>); 2] {
         /* Note: keep ordering consistant. Later tests may depend on earlier tests. */
         [
             (
+                "configuration",
                 "Configuration checks",
                 vec![
                     Box::new(WellFormedConfig::default()),
@@ -228,6 +254,7 @@ impl Check {
                     Box::new(WindowsHostVersion::default()),
                     Box::new(Hostname::default()),
                     Box::new(ConnectManagementUri::default()),
+                    Box::new(ConnectWorkloadUri::default()),
                     Box::new(IotedgedVersion::default()),
                     Box::new(HostLocalTime::default()),
                     Box::new(ContainerLocalTime::default()),
@@ -237,11 +264,13 @@ impl Check {
                     Box::new(CertificatesQuickstart::default()),
                     Box::new(ContainerEngineIsMoby::default()),
                     Box::new(ContainerEngineLogrotate::default()),
+                    Box::new(ContainerEngineStorageDriver::default()),
+                    Box::new(ContainerEngineDiskSpace::default()),
                     Box::new(EdgeAgentStorageMounted::default()),
                     Box::new(EdgeHubStorageMounted::default()),
                 ],
             ),
-            ("Connectivity checks", {
+            ("connectivity", "Connectivity checks", {
                 let mut tests: Vec<Box<dyn Checker>> = Vec::new();
                 tests.push(Box::new(HostConnectDpsEndpoint::default()));
                 tests.extend(get_host_connect_iothub_tests());
@@ -251,28 +280,43 @@ impl Check {
         ]
     }
 
+    /// Adds `value` to the `additional_info` of the JSON output under `key`, for checks to
+    /// report what they found about the device.
+    pub(crate) fn add_additional_info(&mut self, key: &'static str, value: serde_json::Value) {
+        self.additional_info.insert(key, value);
+    }
+
     pub fn possible_ids() -> impl Iterator<Item = &'static str> {
         let result: Vec<&'static str> = Check::checks()
             .iter()
-            .flat_map(|(_, section_checks)| section_checks)
+            .flat_map(|(_, _, section_checks)| section_checks)
             .map(|check| check.id())
             .collect();
 
         result.into_iter()
     }
 
+    pub fn possible_category_ids() -> impl Iterator<Item = &'static str> {
+        let result: Vec<&'static str> = Check::checks()
+            .iter()
+            .map(|(category_id, _, _)| *category_id)
+            .collect();
+
+        result.into_iter()
+    }
+
     pub fn print_list() -> Result<(), Error> {
         // All our text is ASCII, so we can measure text width in bytes rather than using unicode-segmentation to count graphemes.
         let checks = Check::checks();
         let widest_section_name_len = checks
             .iter()
-            .map(|(section_name, _)| section_name.len())
+            .map(|(_, section_name, _)| section_name.len())
             .max()
             .expect("Have at least one section");
         let section_name_column_width = widest_section_name_len + 1;
         let widest_check_id_len = checks
             .iter()
-            .flat_map(|(_, section_checks)| section_checks)
+            .flat_map(|(_, _, section_checks)| section_checks)
             .map(|check| check.id().len())
             .max()
             .expect("Have at least one check");
@@ -287,7 +331,7 @@ impl Check {
         );
         println!();
 
-        for (section_name, section_checks) in &checks {
+        for (_, section_name, section_checks) in &checks {
             for check in section_checks {
                 println!(
                     "{:section_name_column_width$}{:check_id_column_width$}{}",
@@ -306,7 +350,7 @@ impl Check {
     }
 
     fn execute_inner(&mut self) -> Result<(), Error> {
-        let mut checks: BTreeMap<&str, CheckOutputSerializable> = Default::default();
+        let mut checks: Vec<CheckOutputSerializable> = vec![];
         let mut check_data = Check::checks();
 
         let mut stdout = Stdout::new(self.output_format);
@@ -317,11 +361,18 @@ impl Check {
         let mut num_fatal = 0_usize;
         let mut num_errors = 0_usize;
 
-        for (section_name, section_checks) in &mut check_data {
+        for (category_id, section_name, section_checks) in &mut check_data {
             if num_fatal > 0 {
                 break;
             }
 
+            if !section_checks
+                .iter()
+                .any(|check| self.selection.selects(*category_id, check.id()))
+            {
+                continue;
+            }
+
             if self.output_format == OutputFormat::Text {
                 println!("{}", section_name);
                 println!("{}", "-".repeat(section_name.len()));
@@ -335,23 +386,33 @@ impl Check {
                     break;
                 }
 
-                let check_result = if self.dont_run.contains(check.id()) {
+                if !self.selection.selects(*category_id, check_id) {
+                    continue;
+                }
+
+                let dont_run = self.dont_run.contains(check.id());
+                let check_result = if dont_run {
                     CheckResult::Ignored
                 } else {
                     check.execute(self)
                 };
 
+                let output = |result, severity| {
+                    CheckOutputSerializable::new(
+                        *section_name,
+                        check_id,
+                        check_name,
+                        result,
+                        severity,
+                        check.get_json(),
+                    )
+                };
+
                 match check_result {
                     CheckResult::Ok => {
                         num_successful += 1;
 
-                        checks.insert(
-                            check_id,
-                            CheckOutputSerializable {
-                                result: CheckResultSerializable::Ok,
-                                additional_info: check.get_json(),
-                            },
-                        );
+                        checks.push(output(CheckResultSerializable::Ok, Severity::Info));
 
                         stdout.write_success(|stdout| {
                             writeln!(stdout, "\u{221a} {} - OK", check_name)?;
@@ -362,17 +423,9 @@ impl Check {
                     CheckResult::Warning(ref warning) if !self.warnings_as_errors => {
                         num_warnings += 1;
 
-                        checks.insert(
-                            check_id,
-                            CheckOutputSerializable {
-                                result: CheckResultSerializable::Warning {
-                                    details: warning
-                                        .iter_chain()
-                                        .map(ToString::to_string)
-                                        .collect(),
-                                },
-                                additional_info: check.get_json(),
-                            },
+                        checks.push(
+                            output(CheckResultSerializable::Warning, Severity::Warning)
+                                .with_error(warning),
                         );
 
                         stdout.write_warning(|stdout| {
@@ -398,30 +451,33 @@ impl Check {
                     }
 
                     CheckResult::Ignored => {
-                        checks.insert(
-                            check_id,
-                            CheckOutputSerializable {
-                                result: CheckResultSerializable::Ignored,
-                                additional_info: check.get_json(),
-                            },
+                        checks.push(
+                            output(CheckResultSerializable::Skipped, Severity::Info).with_message(
+                                if dont_run {
+                                    "ignored because of --dont-run"
+                                } else {
+                                    "ignored because it does not apply to this device"
+                                }
+                                .to_owned(),
+                            ),
                         );
                     }
 
                     CheckResult::Skipped => {
                         num_skipped += 1;
 
-                        checks.insert(
-                            check_id,
-                            CheckOutputSerializable {
-                                result: CheckResultSerializable::Skipped,
-                                additional_info: check.get_json(),
-                            },
+                        checks.push(
+                            output(CheckResultSerializable::Skipped, Severity::Warning)
+                                .with_message(
+                                    "skipped because of previous failures or an unreachable server"
+                                        .to_owned(),
+                                ),
                         );
 
                         if self.verbose {
                             stdout.write_warning(|stdout| {
                                 writeln!(stdout, "\u{203c} {} - Warning", check_name)?;
-                                writeln!(stdout, "    skipping because of previous failures")?;
+                                writeln!(stdout, "    skipping because of previous failures or an unreachable server")?;
                                 Ok(())
                             });
                         }
@@ -430,14 +486,9 @@ impl Check {
                     CheckResult::Fatal(err) => {
                         num_fatal += 1;
 
-                        checks.insert(
-                            check_id,
-                            CheckOutputSerializable {
-                                result: CheckResultSerializable::Fatal {
-                                    details: err.iter_chain().map(ToString::to_string).collect(),
-                                },
-                                additional_info: check.get_json(),
-                            },
+                        checks.push(
+                            output(CheckResultSerializable::Error, Severity::Fatal)
+                                .with_error(&err),
                         );
 
                         stdout.write_error(|stdout| {
@@ -465,14 +516,9 @@ impl Check {
                     CheckResult::Warning(err) | CheckResult::Failed(err) => {
                         num_errors += 1;
 
-                        checks.insert(
-                            check_id,
-                            CheckOutputSerializable {
-                                result: CheckResultSerializable::Error {
-                                    details: err.iter_chain().map(ToString::to_string).collect(),
-                                },
-                                additional_info: check.get_json(),
-                            },
+                        checks.push(
+                            output(CheckResultSerializable::Error, Severity::Error)
+                                .with_error(&err),
                         );
 
                         stdout.write_error(|stdout| {
@@ -537,7 +583,7 @@ impl Check {
             stdout.write_warning(|stdout| {
                 write!(
                     stdout,
-                    "{} check(s) were skipped due to errors from other checks.",
+                    "{} check(s) were skipped due to errors from other checks or unreachable servers.",
                     num_skipped,
                 )?;
                 if self.verbose {
@@ -555,18 +601,26 @@ impl Check {
             Ok(())
         };
 
-        if self.output_format == OutputFormat::Json {
-            let check_results = CheckResultsSerializable {
-                additional_info: &self.additional_info,
-                checks,
-            };
+        match self.output_format {
+            OutputFormat::Json => {
+                if let Err(err) =
+                    output::write_json(std::io::stdout(), &self.additional_info, &checks)
+                {
+                    eprintln!("Could not write JSON output: {}", err,);
+                    return Err(ErrorKind::Diagnostics.into());
+                }
 
-            if let Err(err) = serde_json::to_writer(std::io::stdout(), &check_results) {
-                eprintln!("Could not write JSON output: {}", err,);
-                return Err(ErrorKind::Diagnostics.into());
+                println!();
             }
 
-            println!();
+            OutputFormat::JUnit => {
+                if let Err(err) = output::write_junit(std::io::stdout(), &checks) {
+                    eprintln!("Could not write JUnit output: {}", err,);
+                    return Err(ErrorKind::Diagnostics.into());
+                }
+            }
+
+            OutputFormat::Text => (),
         }
 
         result
@@ -598,30 +652,6 @@ fn write_lines<'a>(
     Ok(())
 }
 
-#[derive(Debug, serde_derive::Serialize)]
-struct CheckResultsSerializable<'a> {
-    additional_info: &'a AdditionalInfo,
-    checks: BTreeMap<&'static str, CheckOutputSerializable>,
-}
-
-#[derive(Debug, serde_derive::Serialize)]
-#[serde(tag = "result")]
-#[serde(rename_all = "snake_case")]
-enum CheckResultSerializable {
-    Ok,
-    Warning { details: Vec<String> },
-    Ignored,
-    Skipped,
-    Fatal { details: Vec<String> },
-    Error { details: Vec<String> },
-}
-
-#[derive(Debug, serde_derive::Serialize)]
-struct CheckOutputSerializable {
-    result: CheckResultSerializable,
-    additional_info: serde_json::Value,
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -643,12 +673,16 @@ mod tests {
                     config_file.into(),
                     "daemon.json".into(), // unused for this test
                     "mcr.microsoft.com/azureiotedge-diagnostics:1.0.0".to_owned(), // unused for this test
+                    256,  // unused for this test
+                    1024, // unused for this test
                     Default::default(),
                     Some("1.0.0".to_owned()),      // unused for this test
                     "iotedged".into(),             // unused for this test
                     None,                          // unused for this test
+                    5,                             // unused for this test
                     "pool.ntp.org:123".to_owned(), // unused for this test
                     super::OutputFormat::Text,     // unused for this test
+                    Default::default(),
                     false,
                     false,
                 ))
@@ -714,12 +748,16 @@ mod tests {
                 config_file.into(),
                 "daemon.json".into(), // unused for this test
                 "mcr.microsoft.com/azureiotedge-diagnostics:1.0.0".to_owned(), // unused for this test
+                256,  // unused for this test
+                1024, // unused for this test
                 Default::default(),
                 Some("1.0.0".to_owned()),      // unused for this test
                 "iotedged".into(),             // unused for this test
                 None,                          // unused for this test
+                5,                             // unused for this test
                 "pool.ntp.org:123".to_owned(), // unused for this test
                 super::OutputFormat::Text,     // unused for this test
+                Default::default(),
                 false,
                 false,
             ))
@@ -761,12 +799,16 @@ mod tests {
                 config_file.into(),
                 "daemon.json".into(), // unused for this test
                 "mcr.microsoft.com/azureiotedge-diagnostics:1.0.0".to_owned(), // unused for this test
+                256,  // unused for this test
+                1024, // unused for this test
                 Default::default(),
                 Some("1.0.0".to_owned()), // unused for this test
                 "iotedged".into(),        // unused for this test
                 Some("something.something.com".to_owned()), // pretend user specified --iothub-hostname
+                5,                                          // unused for this test
                 "pool.ntp.org:123".to_owned(),              // unused for this test
                 super::OutputFormat::Text,                  // unused for this test
+                Default::default(),
                 false,
                 false,
             ))
@@ -800,12 +842,16 @@ mod tests {
                 config_file.into(),
                 "daemon.json".into(), // unused for this test
                 "mcr.microsoft.com/azureiotedge-diagnostics:1.0.0".to_owned(), // unused for this test
+                256,  // unused for this test
+                1024, // unused for this test
                 Default::default(),
                 Some("1.0.0".to_owned()),      // unused for this test
                 "iotedged".into(),             // unused for this test
                 None,                          // pretend user did not specify --iothub-hostname
+                5,                             // unused for this test
                 "pool.ntp.org:123".to_owned(), // unused for this test
                 super::OutputFormat::Text,     // unused for this test
+                Default::default(),
                 false,
                 false,
             ))
@@ -843,12 +889,16 @@ mod tests {
                 config_file.into(),
                 "daemon.json".into(), // unused for this test
                 "mcr.microsoft.com/azureiotedge-diagnostics:1.0.0".to_owned(), // unused for this test
+                256,  // unused for this test
+                1024, // unused for this test
                 Default::default(),
                 Some("1.0.0".to_owned()),      // unused for this test
                 "iotedged".into(),             // unused for this test
                 None,                          // unused for this test
+                5,                             // unused for this test
                 "pool.ntp.org:123".to_owned(), // unused for this test
                 super::OutputFormat::Text,     // unused for this test
+                Default::default(),
                 false,
                 false,
             ))
@@ -896,12 +946,16 @@ mod tests {
                 config_file.into(),
                 "daemon.json".into(), // unused for this test
                 "mcr.microsoft.com/azureiotedge-diagnostics:1.0.0".to_owned(), // unused for this test
+                256,  // unused for this test
+                1024, // unused for this test
                 Default::default(),
                 Some("1.0.0".to_owned()),      // unused for this test
                 "iotedged".into(),             // unused for this test
                 None,                          // unused for this test
+                5,                             // unused for this test
                 "pool.ntp.org:123".to_owned(), // unused for this test
                 super::OutputFormat::Text,     // unused for this test
+                Default::default(),
                 false,
                 false,
             ))
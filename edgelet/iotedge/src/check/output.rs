@@ -0,0 +1,284 @@
+//! The machine-readable forms of the check results, for pipelines that gate on them.
+//!
+//! - JSON is an object with the `additional_info` about the device and a `checks` array of
+//!   every check in the order they ran.
+//! - JUnit has a test suite for every category of checks. Errors are failures, and warnings,
+//!   skipped and ignored checks are skipped with their message.
+
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug, PartialEq, serde_derive::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum CheckResultSerializable {
+    Ok,
+    Warning,
+    Error,
+    Skipped,
+}
+
+/// How much a result matters. `Fatal` errors stopped the rest of the checks from running.
+#[derive(Clone, Copy, Debug, PartialEq, serde_derive::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum Severity {
+    Info,
+    Warning,
+    Error,
+    Fatal,
+}
+
+#[derive(Debug, serde_derive::Serialize)]
+pub(super) struct CheckOutputSerializable {
+    id: &'static str,
+    title: &'static str,
+    category: &'static str,
+    result: CheckResultSerializable,
+    severity: Severity,
+    message: Option<String>,
+    details: Vec<String>,
+    additional_info: serde_json::Value,
+}
+
+impl CheckOutputSerializable {
+    pub(super) fn new(
+        category: &'static str,
+        id: &'static str,
+        title: &'static str,
+        result: CheckResultSerializable,
+        severity: Severity,
+        additional_info: serde_json::Value,
+    ) -> Self {
+        CheckOutputSerializable {
+            id,
+            title,
+            category,
+            result,
+            severity,
+            message: None,
+            details: vec![],
+            additional_info,
+        }
+    }
+
+    pub(super) fn with_message(mut self, message: String) -> Self {
+        self.message = Some(message);
+        self
+    }
+
+    /// Uses `err` for the message, and it and its causes for the details.
+    pub(super) fn with_error(mut self, err: &failure::Error) -> Self {
+        self.message = Some(err.to_string());
+        self.details = err.iter_chain().map(ToString::to_string).collect();
+        self
+    }
+}
+
+#[derive(Debug, serde_derive::Serialize)]
+struct CheckResultsSerializable<'a, T> {
+    additional_info: &'a T,
+    checks: &'a [CheckOutputSerializable],
+}
+
+pub(super) fn write_json<T>(
+    writer: impl Write,
+    additional_info: &T,
+    checks: &[CheckOutputSerializable],
+) -> Result<(), serde_json::Error>
+where
+    T: Serialize,
+{
+    serde_json::to_writer(
+        writer,
+        &CheckResultsSerializable {
+            additional_info,
+            checks,
+        },
+    )
+}
+
+pub(super) fn write_junit(
+    mut writer: impl Write,
+    checks: &[CheckOutputSerializable],
+) -> io::Result<()> {
+    fn counts<'a>(checks: impl Iterator<Item = &'a CheckOutputSerializable>) -> String {
+        let (mut tests, mut failures, mut skipped) = (0, 0, 0);
+        for check in checks {
+            tests += 1;
+            match check.result {
+                CheckResultSerializable::Ok => (),
+                CheckResultSerializable::Error => failures += 1,
+                CheckResultSerializable::Warning | CheckResultSerializable::Skipped => skipped += 1,
+            }
+        }
+        format!(
+            r#"tests="{}" failures="{}" skipped="{}""#,
+            tests, failures, skipped
+        )
+    }
+
+    writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        writer,
+        r#"<testsuites name="iotedge check" {}>"#,
+        counts(checks.iter())
+    )?;
+
+    let mut categories: Vec<&str> = vec![];
+    for check in checks {
+        if !categories.contains(&check.category) {
+            categories.push(check.category);
+        }
+    }
+
+    for category in categories {
+        let category_checks = || {
+            checks
+                .iter()
+                .filter(move |check| check.category == category)
+        };
+
+        writeln!(
+            writer,
+            r#"  <testsuite name="{}" {}>"#,
+            xml_escape(category),
+            counts(category_checks())
+        )?;
+
+        for check in category_checks() {
+            let testcase = format!(
+                r#"    <testcase classname="{}" name="{}""#,
+                xml_escape(category),
+                xml_escape(&format!("{}: {}", check.id, check.title)),
+            );
+            let message = xml_escape(check.message.as_ref().map_or("", String::as_str));
+
+            match check.result {
+                CheckResultSerializable::Ok => writeln!(writer, "{} />", testcase)?,
+
+                CheckResultSerializable::Error => {
+                    writeln!(writer, "{}>", testcase)?;
+                    writeln!(
+                        writer,
+                        r#"      <failure type="{}" message="{}">{}</failure>"#,
+                        if check.severity == Severity::Fatal {
+                            "fatal"
+                        } else {
+                            "error"
+                        },
+                        message,
+                        xml_escape(&check.details.join("\n")),
+                    )?;
+                    writeln!(writer, "    </testcase>")?;
+                }
+
+                CheckResultSerializable::Warning | CheckResultSerializable::Skipped => {
+                    writeln!(writer, "{}>", testcase)?;
+                    writeln!(writer, r#"      <skipped message="{}" />"#, message)?;
+                    writeln!(writer, "    </testcase>")?;
+                }
+            }
+        }
+
+        writeln!(writer, "  </testsuite>")?;
+    }
+
+    writeln!(writer, "</testsuites>")?;
+
+    Ok(())
+}
+
+fn xml_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\n' => escaped.push_str("&#10;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use failure::Context;
+    use serde_json::json;
+
+    use super::*;
+
+    fn checks() -> Vec<CheckOutputSerializable> {
+        vec![
+            CheckOutputSerializable::new(
+                "Configuration checks",
+                "config-yaml-well-formed",
+                "config.yaml is well-formed",
+                CheckResultSerializable::Ok,
+                Severity::Info,
+                json!({}),
+            ),
+            CheckOutputSerializable::new(
+                "Configuration checks",
+                "host-local-time",
+                "host time is close to real time",
+                CheckResultSerializable::Warning,
+                Severity::Warning,
+                json!({ "offset": 7, "reference": "pool.ntp.org:123" }),
+            )
+            .with_error(
+                &Context::new("Time on the device is out of sync with pool.ntp.org:123 by 7s")
+                    .into(),
+            ),
+            CheckOutputSerializable::new(
+                "Configuration checks",
+                "container-engine-is-moby",
+                "production readiness: container engine",
+                CheckResultSerializable::Skipped,
+                Severity::Warning,
+                json!({ "docker_server_version": null }),
+            )
+            .with_message(
+                "skipped because of previous failures or an unreachable server".to_owned(),
+            ),
+            CheckOutputSerializable::new(
+                "Connectivity checks",
+                "host-connect-iothub-amqp",
+                "host can connect to and perform TLS handshake with IoT Hub AMQP port",
+                CheckResultSerializable::Error,
+                Severity::Error,
+                json!({}),
+            )
+            .with_error(
+                &failure::err_msg("connection refused")
+                    .context("Could not connect to <hub> & port 5671")
+                    .into(),
+            ),
+        ]
+    }
+
+    #[test]
+    fn json_matches_golden_file() {
+        let mut output = vec![];
+        write_json(&mut output, &json!({ "os": { "id": "linux" } }), &checks()).unwrap();
+
+        let output: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        let golden: serde_json::Value =
+            serde_json::from_str(include_str!("../../test-files/check/output.json")).unwrap();
+        assert_eq!(golden, output);
+    }
+
+    #[test]
+    fn junit_matches_golden_file() {
+        let mut output = vec![];
+        write_junit(&mut output, &checks()).unwrap();
+
+        assert_eq!(
+            include_str!("../../test-files/check/output.xml"),
+            String::from_utf8(output).unwrap()
+        );
+    }
+}
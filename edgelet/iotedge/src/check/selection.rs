@@ -0,0 +1,97 @@
+use std::collections::BTreeSet;
+
+use crate::error::{Error, ErrorKind};
+
+use super::Check;
+
+/// The checks that `--include` and `--exclude` select, by check ID or category ID.
+///
+/// With no includes every check is selected. Excludes win over includes, so
+/// `--include connectivity --exclude host-connect-dps-endpoint` runs every connectivity check
+/// but that one.
+#[derive(Clone, Debug, Default)]
+pub struct CheckSelection {
+    include: BTreeSet<String>,
+    exclude: BTreeSet<String>,
+}
+
+impl CheckSelection {
+    /// Fails if any of `include` and `exclude` isn't the ID of a check or a category, so that a
+    /// typo doesn't silently select nothing.
+    pub fn new<I, E>(include: I, exclude: E) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = String>,
+        E: IntoIterator<Item = String>,
+    {
+        let include: BTreeSet<_> = include.into_iter().collect();
+        let exclude: BTreeSet<_> = exclude.into_iter().collect();
+
+        let known: BTreeSet<_> = Check::possible_ids()
+            .chain(Check::possible_category_ids())
+            .collect();
+        if let Some(unknown) = include
+            .iter()
+            .chain(&exclude)
+            .find(|id| !known.contains(id.as_str()))
+        {
+            return Err(ErrorKind::UnknownCheckOrCategory(unknown.clone()).into());
+        }
+
+        Ok(CheckSelection { include, exclude })
+    }
+
+    pub(super) fn selects(&self, category_id: &str, check_id: &str) -> bool {
+        let matches = |ids: &BTreeSet<String>| ids.contains(category_id) || ids.contains(check_id);
+        (self.include.is_empty() || matches(&self.include)) && !matches(&self.exclude)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_selection(include: &[&str], exclude: &[&str]) -> Result<CheckSelection, Error> {
+        CheckSelection::new(
+            include.iter().map(|&id| id.to_owned()),
+            exclude.iter().map(|&id| id.to_owned()),
+        )
+    }
+
+    #[test]
+    fn selects_everything_by_default() {
+        let selection = CheckSelection::default();
+        assert!(selection.selects("configuration", "host-local-time"));
+        assert!(selection.selects("connectivity", "host-connect-dps-endpoint"));
+    }
+
+    #[test]
+    fn include_only_selects_included_checks_and_categories() {
+        let selection =
+            new_selection(&["configuration", "host-connect-dps-endpoint"], &[]).unwrap();
+        assert!(selection.selects("configuration", "host-local-time"));
+        assert!(selection.selects("connectivity", "host-connect-dps-endpoint"));
+        assert!(!selection.selects("connectivity", "host-connect-iothub-amqp"));
+    }
+
+    #[test]
+    fn exclude_wins_over_include() {
+        let selection = new_selection(&[], &["connectivity"]).unwrap();
+        assert!(selection.selects("configuration", "host-local-time"));
+        assert!(!selection.selects("connectivity", "host-connect-dps-endpoint"));
+
+        let selection = new_selection(&["configuration"], &["host-local-time"]).unwrap();
+        assert!(selection.selects("configuration", "config-yaml-well-formed"));
+        assert!(!selection.selects("configuration", "host-local-time"));
+    }
+
+    #[test]
+    fn unknown_id_fails() {
+        let err = new_selection(&["configuration"], &["host-local-tiem"]).unwrap_err();
+        assert_eq!(
+            "host-local-tiem is not the ID of a check or a category. Run 'iotedge check-list' to see all of them.",
+            err.to_string()
+        );
+
+        new_selection(&["connectivty"], &[]).unwrap_err();
+    }
+}
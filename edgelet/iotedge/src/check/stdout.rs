@@ -10,7 +10,8 @@ pub(super) enum Stdout {
         error_color_spec: termcolor::ColorSpec,
     },
 
-    DefaultJson,
+    /// JSON and JUnit output is written once all the checks have run.
+    Structured,
 
     DefaultText,
 }
@@ -70,7 +71,7 @@ impl Stdout {
             }
         } else {
             match output_format {
-                super::OutputFormat::Json => Stdout::DefaultJson,
+                super::OutputFormat::Json | super::OutputFormat::JUnit => Stdout::Structured,
                 super::OutputFormat::Text => Stdout::DefaultText,
             }
         }
@@ -86,7 +87,7 @@ impl Stdout {
                 success_color_spec,
                 ..
             } => write_colored(stdout, success_color_spec, f),
-            Stdout::DefaultJson => Ok(()),
+            Stdout::Structured => Ok(()),
             Stdout::DefaultText => f(&mut std::io::stdout()),
         };
         result.expect("could not write to stdout");
@@ -102,7 +103,7 @@ impl Stdout {
                 warning_color_spec,
                 ..
             } => write_colored(stdout, warning_color_spec, f),
-            Stdout::DefaultJson => Ok(()),
+            Stdout::Structured => Ok(()),
             Stdout::DefaultText => f(&mut std::io::stdout()),
         };
         result.expect("could not write to stdout");
@@ -118,7 +119,7 @@ impl Stdout {
                 error_color_spec,
                 ..
             } => write_colored(stdout, error_color_spec, f),
-            Stdout::DefaultJson => Ok(()),
+            Stdout::Structured => Ok(()),
             Stdout::DefaultText => f(&mut std::io::stdout()),
         };
         result.expect("could not write to stdout");
@@ -12,15 +12,27 @@ pub struct Error {
 
 #[derive(Clone, Debug, Fail)]
 pub enum ErrorKind {
+    #[fail(display = "Invalid value for --disk-space-error parameter")]
+    BadDiskSpaceErrorParameter,
+
+    #[fail(display = "Invalid value for --disk-space-warning parameter")]
+    BadDiskSpaceWarningParameter,
+
     #[fail(display = "Invalid value for --host parameter")]
     BadHostParameter,
 
+    #[fail(display = "Invalid value for --max-clock-skew parameter")]
+    BadMaxClockSkewParameter,
+
     #[fail(display = "Invalid value for --since parameter")]
     BadSinceParameter,
 
     #[fail(display = "Invalid value for --tail parameter")]
     BadTailParameter,
 
+    #[fail(display = "Invalid value for --until parameter")]
+    BadUntilParameter,
+
     #[fail(display = "")]
     Diagnostics,
 
@@ -39,9 +51,21 @@ pub enum ErrorKind {
     #[fail(display = "A module runtime error occurred")]
     ModuleRuntime,
 
+    #[fail(display = "Could not preload images")]
+    Preload,
+
+    #[fail(display = "Could not restart the daemon")]
+    RestartDaemon,
+
     #[fail(display = "Could not generate support bundle")]
     SupportBundle,
 
+    #[fail(
+        display = "{} is not the ID of a check or a category. Run 'iotedge check-list' to see all of them.",
+        _0
+    )]
+    UnknownCheckOrCategory(String),
+
     #[fail(display = "Could not write to stdout")]
     WriteToStdout,
 
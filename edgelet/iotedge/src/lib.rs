@@ -20,16 +20,24 @@ mod check;
 mod error;
 mod list;
 mod logs;
+mod preload;
 mod restart;
+mod service;
 mod support_bundle;
 mod unknown;
 mod version;
 
-pub use crate::check::{Check, OutputFormat};
+pub use crate::check::{Check, CheckSelection, OutputFormat};
 pub use crate::error::{Error, ErrorKind, FetchLatestVersionsReason};
 pub use crate::list::List;
-pub use crate::logs::Logs;
-pub use crate::restart::Restart;
+pub use crate::logs::{configured_max_line_length, Logs};
+pub use crate::preload::Preload;
+pub use crate::restart::{Quiesce, Restart, RestartDaemon};
+#[cfg(unix)]
+pub use crate::service::Process;
+#[cfg(windows)]
+pub use crate::service::WindowsService;
+pub use crate::service::{service_manager, ServiceManager, Systemd};
 pub use crate::support_bundle::{OutputLocation, SupportBundle};
 pub use crate::unknown::Unknown;
 pub use crate::version::Version;
@@ -74,7 +74,7 @@ where
 }
 
 fn humanize_state(state: &ModuleRuntimeState) -> String {
-    match *state.status() {
+    let description = match *state.status() {
         ModuleStatus::Unknown => "Unknown".to_string(),
         ModuleStatus::Stopped => state.finished_at().map_or_else(
             || "Stopped".to_string(),
@@ -97,15 +97,28 @@ fn humanize_state(state: &ModuleRuntimeState) -> String {
                 })
             })
             .unwrap_or_else(|| "Failed".to_string()),
-        ModuleStatus::Running => state.started_at().map_or_else(
-            || "Up".to_string(),
-            |time| {
-                format!(
-                    "Up {}",
-                    time_string(&HumanTime::from(Utc::now() - *time), Tense::Present)
-                )
-            },
-        ),
+        ModuleStatus::Running => {
+            let up = state.started_at().map_or_else(
+                || "Up".to_string(),
+                |time| {
+                    format!(
+                        "Up {}",
+                        time_string(&HumanTime::from(Utc::now() - *time), Tense::Present)
+                    )
+                },
+            );
+            match state.health() {
+                Some(health) => format!("{} ({})", up, health),
+                None => up,
+            }
+        }
+    };
+
+    // The watchdog stopped retrying the module until it is resumed.
+    if state.paused() {
+        format!("{}, paused", description)
+    } else {
+        description
     }
 }
 
@@ -1,11 +1,16 @@
 // Copyright (c) Microsoft. All rights reserved.
 
 use std::io::{self, Write};
+use std::path::Path;
 
 use failure::Fail;
 use futures::prelude::*;
 
-use edgelet_core::{Chunked, LogChunk, LogDecode, LogOptions, ModuleRuntime};
+use edgelet_core::{
+    decode_logs_with_max_line_length, LogChunk, LogOptions, ModuleRuntime, RuntimeSettings,
+    DEFAULT_MAX_LOG_LINE_LENGTH,
+};
+use edgelet_docker::Settings;
 
 use crate::error::{Error, ErrorKind};
 use crate::Command;
@@ -13,14 +18,17 @@ use crate::Command;
 pub struct Logs<M> {
     id: String,
     options: LogOptions,
+    max_line_length: usize,
     runtime: M,
 }
 
 impl<M> Logs<M> {
-    pub fn new(id: String, options: LogOptions, runtime: M) -> Self {
+    /// Lines are truncated to `max_line_length` bytes.
+    pub fn new(id: String, options: LogOptions, max_line_length: usize, runtime: M) -> Self {
         Logs {
             id,
             options,
+            max_line_length,
             runtime,
         }
     }
@@ -34,15 +42,31 @@ where
 
     fn execute(self) -> Self::Future {
         let id = self.id.clone();
-        let result = pull_logs(&self.runtime, &id, &self.options, io::stdout()).map(drop);
+        let result = pull_logs(
+            &self.runtime,
+            &id,
+            &self.options,
+            self.max_line_length,
+            io::stdout(),
+        )
+        .map(drop);
         Box::new(result)
     }
 }
 
+/// The `logs.max_line_length` of the daemon's config, or the default if the
+/// config can't be read, as it usually can't by users that aren't root.
+pub fn configured_max_line_length(config_file: &Path) -> usize {
+    Settings::new(config_file)
+        .map(|settings| settings.logs().max_line_length())
+        .unwrap_or(DEFAULT_MAX_LOG_LINE_LENGTH)
+}
+
 pub fn pull_logs<M, W>(
     runtime: &M,
     id: &str,
     options: &LogOptions,
+    max_line_length: usize,
     writer: W,
 ) -> impl Future<Item = W, Error = Error> + Send
 where
@@ -53,9 +77,7 @@ where
         .logs(id, options)
         .map_err(|err| Error::from(err.context(ErrorKind::ModuleRuntime)))
         .and_then(move |logs| {
-            let chunked =
-                Chunked::new(logs.map_err(|_| io::Error::new(io::ErrorKind::Other, "unknown")));
-            LogDecode::new(chunked)
+            decode_logs_with_max_line_length(logs, max_line_length)
                 .map_err(|err| Error::from(err.context(ErrorKind::ModuleRuntime)))
                 .fold(writer, |mut w, chunk| -> Result<W, Error> {
                     match chunk {
@@ -63,9 +85,13 @@ where
                         | LogChunk::Stdout(b)
                         | LogChunk::Stderr(b)
                         | LogChunk::Unknown(b) => w
-                            .write(&b)
+                            .write_all(&b)
                             .map_err(|err| Error::from(err.context(ErrorKind::WriteToStdout)))?,
                     };
+                    // Flush every frame so that followed logs show up as they arrive
+                    // instead of waiting for a newline or a full buffer.
+                    w.flush()
+                        .map_err(|err| Error::from(err.context(ErrorKind::WriteToStdout)))?;
                     Ok(w)
                 })
         })
@@ -14,21 +14,15 @@ use failure::{Fail, ResultExt};
 use futures::Future;
 use url::Url;
 
-use edgelet_core::{parse_since, LogOptions, LogTail};
+use edgelet_client::ManagementClient;
+use edgelet_core::{parse_since, redact_failure, LogOptions};
 use edgelet_http_mgmt::ModuleClient;
 
 use iotedge::*;
 
 fn main() {
     if let Err(ref error) = run() {
-        let fail: &dyn Fail = error;
-
-        eprintln!("{}", error.to_string());
-
-        for cause in fail.iter_causes() {
-            eprintln!("\tcaused by: {}", cause);
-        }
-
+        eprintln!("{}", redact_failure(error));
         eprintln!();
 
         process::exit(1);
@@ -127,6 +121,22 @@ fn run() -> Result<(), Error> {
                         .takes_value(true)
                         .default_value(&default_diagnostics_image_name),
                 )
+                .arg(
+                    Arg::with_name("disk-space-error")
+                        .long("disk-space-error")
+                        .value_name("MEBIBYTES")
+                        .help("Sets the free space, in MiB, on the container engine's disk below which it's an error.")
+                        .takes_value(true)
+                        .default_value("256"),
+                )
+                .arg(
+                    Arg::with_name("disk-space-warning")
+                        .long("disk-space-warning")
+                        .value_name("MEBIBYTES")
+                        .help("Sets the free space, in MiB, on the container engine's disk below which it's a warning.")
+                        .takes_value(true)
+                        .default_value("1024"),
+                )
                 .arg(
                     Arg::with_name("dont-run")
                         .long("dont-run")
@@ -136,6 +146,14 @@ fn run() -> Result<(), Error> {
                         .takes_value(true)
                         .possible_values(&possible_check_id_values),
                 )
+                .arg(
+                    Arg::with_name("exclude")
+                        .long("exclude")
+                        .value_name("EXCLUDE")
+                        .help("Space-separated list of check IDs and category IDs (configuration, connectivity). The checks listed here, and the checks in the categories listed here, will not be run or reported.")
+                        .multiple(true)
+                        .takes_value(true),
+                )
                 .arg(
                     Arg::with_name("expected-iotedged-version")
                         .long("expected-iotedged-version")
@@ -143,6 +161,14 @@ fn run() -> Result<(), Error> {
                         .help("Sets the expected version of the iotedged binary. Defaults to the value contained in <http://aka.ms/latest-iotedge-stable>")
                         .takes_value(true),
                 )
+                .arg(
+                    Arg::with_name("include")
+                        .long("include")
+                        .value_name("INCLUDE")
+                        .help("Space-separated list of check IDs and category IDs (configuration, connectivity). Only the checks listed here, and the checks in the categories listed here, will be run and reported.")
+                        .multiple(true)
+                        .takes_value(true),
+                )
                 .arg(
                     Arg::with_name("iotedged")
                         .long("iotedged")
@@ -160,6 +186,14 @@ fn run() -> Result<(), Error> {
                         .help("Sets the hostname of the Azure IoT Hub that this device would connect to. If using manual provisioning, this does not need to be specified.")
                         .takes_value(true),
                 )
+                .arg(
+                    Arg::with_name("max-clock-skew")
+                        .long("max-clock-skew")
+                        .value_name("SECONDS")
+                        .help("Sets how far, in seconds, host local time may be from the NTP server or IoT Hub before it's a warning.")
+                        .takes_value(true)
+                        .default_value("5"),
+                )
                 .arg(
                     Arg::with_name("ntp-server")
                         .long("ntp-server")
@@ -175,7 +209,7 @@ fn run() -> Result<(), Error> {
                         .value_name("FORMAT")
                         .help("Output format. Note that JSON output contains some additional information like OS name, OS version, disk space, etc.")
                         .takes_value(true)
-                        .possible_values(&["json", "text"])
+                        .possible_values(&["json", "junit", "text"])
                         .default_value("text"),
                 )
                 .arg(
@@ -197,12 +231,38 @@ fn run() -> Result<(), Error> {
         .subcommand(SubCommand::with_name("list").about("List modules"))
         .subcommand(
             SubCommand::with_name("restart")
-                .about("Restart a module")
+                .about("Restart a module, or the daemon with --daemon")
                 .arg(
                     Arg::with_name("MODULE")
                         .help("Sets the module identity to restart")
+                        .required_unless("daemon")
+                        .conflicts_with("daemon")
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("daemon")
+                        .help("Restarts the daemon through its service manager, keeping the modules running so that the daemon adopts them when it starts again")
+                        .long("daemon")
+                        .takes_value(false),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("preload")
+                .about("Load images from a `docker save` tarball, for devices that can't reach the registry")
+                .arg(
+                    Arg::with_name("TARBALL")
+                        .help("Sets the tarball to load the images from")
                         .required(true)
                         .index(1),
+                )
+                .arg(
+                    Arg::with_name("config-file")
+                        .short("c")
+                        .long("config-file")
+                        .value_name("FILE")
+                        .help("Sets daemon configuration file, whose container runtime the images are loaded into")
+                        .takes_value(true)
+                        .default_value_os(default_config_path.as_os_str()),
                 ),
         )
         .subcommand(
@@ -230,11 +290,33 @@ fn run() -> Result<(), Error> {
                         .value_name("DURATION or TIMESTAMP")
                         .default_value("1 day"),
                 )
+                .arg(
+                    Arg::with_name("until")
+                        .help("Only return logs up to this time, as a duration (1 day, 90 minutes, 2 days 3 hours 2 minutes), rfc3339 timestamp, or UNIX timestamp")
+                        .long("until")
+                        .takes_value(true)
+                        .value_name("DURATION or TIMESTAMP"),
+                )
                 .arg(
                     Arg::with_name("follow")
                         .help("Follow output log")
                         .short("f")
                         .long("follow"),
+                )
+                .arg(
+                    Arg::with_name("timestamps")
+                        .help("Show the time each line was written")
+                        .short("t")
+                        .long("timestamps"),
+                )
+                .arg(
+                    Arg::with_name("config-file")
+                        .short("c")
+                        .long("config-file")
+                        .value_name("FILE")
+                        .help("Sets daemon configuration file, whose logs.max_line_length lines are truncated to. The default length is used if it can't be read")
+                        .takes_value(true)
+                        .default_value_os(default_config_path.as_os_str()),
                 ),
         )
         .subcommand(
@@ -249,6 +331,23 @@ fn run() -> Result<(), Error> {
                         .value_name("FILENAME")
                         .default_value("support_bundle.zip"),
                 )
+                .arg(
+                    Arg::with_name("config-file")
+                        .short("c")
+                        .long("config-file")
+                        .value_name("FILE")
+                        .help("Sets daemon configuration file, which is included with its secrets redacted")
+                        .takes_value(true)
+                        .default_value_os(default_config_path.as_os_str()),
+                )
+                .arg(
+                    Arg::with_name("tail")
+                        .help("Number of lines to include from the end of each module's log")
+                        .long("tail")
+                        .takes_value(true)
+                        .value_name("NUM")
+                        .default_value("all"),
+                )
                 .arg(
                     Arg::with_name("since")
                         .help("Only return logs since this time, as a duration (1d, 90m, 2h30m), rfc3339 timestamp, or UNIX timestamp")
@@ -280,16 +379,18 @@ fn run() -> Result<(), Error> {
         .subcommand(SubCommand::with_name("version").about("Show the version information"))
         .get_matches();
 
-    let runtime = || -> Result<_, Error> {
-        let url = matches.value_of("host").map_or_else(
+    let host = || -> Result<_, Error> {
+        matches.value_of("host").map_or_else(
             || Err(Error::from(ErrorKind::MissingHostParameter)),
             |h| {
                 Url::parse(h)
                     .context(ErrorKind::BadHostParameter)
                     .map_err(Error::from)
             },
-        )?;
-        let runtime = ModuleClient::new(&url).context(ErrorKind::ModuleRuntime)?;
+        )
+    };
+    let runtime = || -> Result<_, Error> {
+        let runtime = ModuleClient::new(&host()?).context(ErrorKind::ModuleRuntime)?;
         Ok(runtime)
     };
 
@@ -309,6 +410,14 @@ fn run() -> Result<(), Error> {
                 args.value_of("diagnostics-image-name")
                     .expect("arg has a default value")
                     .to_string(),
+                args.value_of("disk-space-error")
+                    .expect("arg has a default value")
+                    .parse::<u64>()
+                    .context(ErrorKind::BadDiskSpaceErrorParameter)?,
+                args.value_of("disk-space-warning")
+                    .expect("arg has a default value")
+                    .parse::<u64>()
+                    .context(ErrorKind::BadDiskSpaceWarningParameter)?,
                 args.values_of("dont-run")
                     .into_iter()
                     .flatten()
@@ -321,16 +430,31 @@ fn run() -> Result<(), Error> {
                     .to_os_string()
                     .into(),
                 args.value_of("iothub-hostname").map(ToOwned::to_owned),
+                args.value_of("max-clock-skew")
+                    .expect("arg has a default value")
+                    .parse::<u64>()
+                    .context(ErrorKind::BadMaxClockSkewParameter)?,
                 args.value_of("ntp-server")
                     .expect("arg has a default value")
                     .to_string(),
                 args.value_of("output")
                     .map(|arg| match arg {
                         "json" => OutputFormat::Json,
+                        "junit" => OutputFormat::JUnit,
                         "text" => OutputFormat::Text,
                         _ => unreachable!(),
                     })
                     .expect("arg has a default value"),
+                CheckSelection::new(
+                    args.values_of("include")
+                        .into_iter()
+                        .flatten()
+                        .map(ToOwned::to_owned),
+                    args.values_of("exclude")
+                        .into_iter()
+                        .flatten()
+                        .map(ToOwned::to_owned),
+                )?,
                 args.is_present("verbose"),
                 args.is_present("warnings-as-errors"),
             )
@@ -338,6 +462,14 @@ fn run() -> Result<(), Error> {
         ),
         ("check-list", _) => Check::print_list(),
         ("list", _) => tokio_runtime.block_on(List::new(runtime()?, io::stdout()).execute()),
+        ("restart", Some(args)) if args.is_present("daemon") => tokio_runtime.block_on(
+            RestartDaemon::new(
+                ManagementClient::new(&host()?).context(ErrorKind::RestartDaemon)?,
+                service_manager(),
+                io::stdout(),
+            )
+            .execute(),
+        ),
         ("restart", Some(args)) => tokio_runtime.block_on(
             Restart::new(
                 args.value_of("MODULE").unwrap().to_string(),
@@ -346,9 +478,24 @@ fn run() -> Result<(), Error> {
             )
             .execute(),
         ),
+        ("preload", Some(args)) => tokio_runtime.block_on(
+            Preload::new(
+                args.value_of_os("config-file")
+                    .expect("arg has a default value")
+                    .to_os_string()
+                    .into(),
+                args.value_of_os("TARBALL")
+                    .expect("arg is required")
+                    .to_os_string()
+                    .into(),
+                io::stdout(),
+            )
+            .execute(),
+        ),
         ("logs", Some(args)) => {
             let id = args.value_of("MODULE").unwrap().to_string();
             let follow = args.is_present("follow");
+            let timestamps = args.is_present("timestamps");
             let tail = args
                 .value_of("tail")
                 .map(str::parse)
@@ -363,14 +510,40 @@ fn run() -> Result<(), Error> {
                 .transpose()
                 .context(ErrorKind::BadSinceParameter)?
                 .expect("arg has a default value");
-            let options = LogOptions::new()
+            let until = args
+                .value_of("until")
+                .map(|s| parse_since(s))
+                .transpose()
+                .context(ErrorKind::BadUntilParameter)?;
+            let mut options = LogOptions::new()
                 .with_follow(follow)
                 .with_tail(tail)
-                .with_since(since);
-            tokio_runtime.block_on(Logs::new(id, options, runtime()?).execute())
+                .with_since(since)
+                .with_timestamps(timestamps);
+            if let Some(until) = until {
+                options = options.with_until(until);
+            }
+            let max_line_length = configured_max_line_length(Path::new(
+                args.value_of_os("config-file")
+                    .expect("arg has a default value"),
+            ));
+            tokio_runtime.block_on(Logs::new(id, options, max_line_length, runtime()?).execute())
         }
         ("support-bundle", Some(args)) => {
             let location = args.value_of_os("output").expect("arg has a default value");
+            let config_file = args
+                .value_of_os("config-file")
+                .expect("arg has a default value")
+                .to_os_string()
+                .into();
+            let tail = args
+                .value_of("tail")
+                .map(str::parse)
+                .transpose()
+                .map_err(|err: edgelet_core::Error| {
+                    Error::from(err.context(ErrorKind::BadTailParameter))
+                })?
+                .expect("arg has a default value");
             let since = args
                 .value_of("since")
                 .map(|s| parse_since(s))
@@ -379,7 +552,7 @@ fn run() -> Result<(), Error> {
                 .expect("arg has a default value");
             let options = LogOptions::new()
                 .with_follow(false)
-                .with_tail(LogTail::All)
+                .with_tail(tail)
                 .with_since(since);
             let include_ms_only = args.is_present("include-edge-runtime-only");
             let verbose = !args.is_present("quiet");
@@ -397,6 +570,7 @@ fn run() -> Result<(), Error> {
                     verbose,
                     iothub_hostname,
                     output_location,
+                    config_file,
                     runtime()?,
                 )
                 .execute(),
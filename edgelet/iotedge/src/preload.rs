@@ -0,0 +1,58 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use failure::{Fail, ResultExt};
+use futures::{future, Future};
+
+use edgelet_docker::{import_image, Settings};
+
+use crate::error::{Error, ErrorKind};
+use crate::Command;
+
+/// Loads images from a `docker save` tarball into the container runtime that
+/// the daemon's config uses, so that devices that can't reach the registry can
+/// start modules from them.
+pub struct Preload<W> {
+    config_file: PathBuf,
+    tarball: PathBuf,
+    output: Arc<Mutex<W>>,
+}
+
+impl<W> Preload<W> {
+    pub fn new(config_file: PathBuf, tarball: PathBuf, output: W) -> Self {
+        Preload {
+            config_file,
+            tarball,
+            output: Arc::new(Mutex::new(output)),
+        }
+    }
+}
+
+impl<W> Command for Preload<W>
+where
+    W: 'static + Write + Send,
+{
+    type Future = Box<dyn Future<Item = (), Error = Error> + Send>;
+
+    fn execute(self) -> Self::Future {
+        let settings = match Settings::new(&self.config_file) {
+            Ok(settings) => settings,
+            Err(err) => return Box::new(future::err(Error::from(err.context(ErrorKind::Preload)))),
+        };
+
+        let tarball = self.tarball;
+        let write = self.output;
+        let result = import_image(&settings, &tarball)
+            .map_err(|err| Error::from(err.context(ErrorKind::Preload)))
+            .and_then(move |()| {
+                let mut w = write.lock().unwrap();
+                writeln!(w, "Imported images from {}", tarball.display())
+                    .context(ErrorKind::WriteToStdout)?;
+                Ok(())
+            });
+        Box::new(result)
+    }
+}
@@ -4,11 +4,14 @@ use std::io::Write;
 use std::sync::{Arc, Mutex};
 
 use failure::{Fail, ResultExt};
+use futures::future::{self, Either};
 use futures::Future;
 
+use edgelet_client::ManagementClient;
 use edgelet_core::ModuleRuntime;
 
 use crate::error::{Error, ErrorKind};
+use crate::service::ServiceManager;
 use crate::Command;
 
 pub struct Restart<M, W> {
@@ -49,3 +52,215 @@ where
         Box::new(result)
     }
 }
+
+/// The running daemon, which leaves its modules running while it restarts.
+pub trait Quiesce {
+    /// Saves the modules that are running, so that the daemon that starts
+    /// next adopts them instead of recreating them.
+    fn prepare(&self) -> Box<dyn Future<Item = (), Error = Error> + Send>;
+
+    /// Goes back to supervising the modules if the daemon isn't restarted.
+    fn resume(&self) -> Box<dyn Future<Item = (), Error = Error> + Send>;
+}
+
+impl Quiesce for ManagementClient {
+    fn prepare(&self) -> Box<dyn Future<Item = (), Error = Error> + Send> {
+        Box::new(
+            self.prepare_upgrade()
+                .map_err(|err| Error::from(err.context(ErrorKind::RestartDaemon))),
+        )
+    }
+
+    fn resume(&self) -> Box<dyn Future<Item = (), Error = Error> + Send> {
+        Box::new(
+            self.resume_from_upgrade()
+                .map_err(|err| Error::from(err.context(ErrorKind::RestartDaemon))),
+        )
+    }
+}
+
+/// Restarts the daemon through its service manager, keeping the modules
+/// running so that the daemon adopts them when it starts again.
+pub struct RestartDaemon<Q, S, W> {
+    daemon: Q,
+    manager: S,
+    output: Arc<Mutex<W>>,
+}
+
+impl<Q, S, W> RestartDaemon<Q, S, W> {
+    pub fn new(daemon: Q, manager: S, output: W) -> Self {
+        RestartDaemon {
+            daemon,
+            manager,
+            output: Arc::new(Mutex::new(output)),
+        }
+    }
+}
+
+impl<Q, S, W> Command for RestartDaemon<Q, S, W>
+where
+    Q: 'static + Quiesce + Send,
+    S: 'static + ServiceManager + Send,
+    W: 'static + Write + Send,
+{
+    type Future = Box<dyn Future<Item = (), Error = Error> + Send>;
+
+    fn execute(self) -> Self::Future {
+        let RestartDaemon {
+            daemon,
+            manager,
+            output,
+        } = self;
+        let result = daemon
+            .prepare()
+            .and_then(move |()| match manager.restart() {
+                Ok(()) => Either::A(future::ok(())),
+                // The daemon that is still running supervises the modules again.
+                Err(err) => Either::B(daemon.resume().then(move |_| Err(err))),
+            })
+            .and_then(move |()| {
+                let mut w = output.lock().unwrap();
+                writeln!(w, "Restarted the daemon").context(ErrorKind::WriteToStdout)?;
+                Ok(())
+            });
+        Box::new(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use tempfile::tempdir;
+
+    use edgelet_core::{MakeModuleRuntime, ModuleRuntimeState, ModuleStatus, UpgradeGuard};
+    use edgelet_test_utils::crypto::TestHsm;
+    use edgelet_test_utils::module::*;
+
+    use super::*;
+
+    #[allow(dead_code)]
+    #[derive(Clone, Copy, Debug, Fail)]
+    pub enum TestError {
+        #[fail(display = "General error")]
+        General,
+    }
+
+    type Runtime = TestRuntime<TestError, TestSettings>;
+
+    fn runtime() -> Runtime {
+        let state = ModuleRuntimeState::default().with_status(ModuleStatus::Running);
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<TestError, _> =
+            TestModule::new("mod1".to_string(), config, Ok(state));
+        TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(Ok(module))
+    }
+
+    // The daemon that is running, which quiesces through the same guard as
+    // the management API.
+    struct TestDaemon {
+        runtime: Runtime,
+        upgrade: UpgradeGuard,
+    }
+
+    impl Quiesce for TestDaemon {
+        fn prepare(&self) -> Box<dyn Future<Item = (), Error = Error> + Send> {
+            let prepared = self
+                .upgrade
+                .prepare(&self.runtime)
+                .wait()
+                .map(|_| ())
+                .map_err(|err| Error::from(err.context(ErrorKind::RestartDaemon)));
+            Box::new(future::result(prepared))
+        }
+
+        fn resume(&self) -> Box<dyn Future<Item = (), Error = Error> + Send> {
+            let resumed = self
+                .upgrade
+                .resume(&self.runtime)
+                .wait()
+                .map(|_| ())
+                .map_err(|err| Error::from(err.context(ErrorKind::RestartDaemon)));
+            Box::new(future::result(resumed))
+        }
+    }
+
+    // Restarts the daemon by starting a new one from the same state, which
+    // resumes like the daemon does when it starts.
+    struct TestServiceManager {
+        runtime: Runtime,
+        state_path: PathBuf,
+        adopted: Arc<Mutex<Option<Vec<String>>>>,
+        fail: bool,
+    }
+
+    impl ServiceManager for TestServiceManager {
+        fn restart(&self) -> Result<(), Error> {
+            if self.fail {
+                return Err(Error::from(ErrorKind::RestartDaemon));
+            }
+
+            let upgrade = UpgradeGuard::new(&self.state_path);
+            assert!(upgrade.is_pending());
+            let adopted = upgrade.resume(&self.runtime).wait().unwrap();
+            *self.adopted.lock().unwrap() = Some(adopted);
+            Ok(())
+        }
+    }
+
+    fn restart(
+        state_path: &Path,
+        fail: bool,
+    ) -> (Result<(), Error>, UpgradeGuard, Option<Vec<String>>) {
+        let daemon = TestDaemon {
+            runtime: runtime(),
+            upgrade: UpgradeGuard::new(state_path),
+        };
+        let upgrade = daemon.upgrade.clone();
+        let adopted = Arc::new(Mutex::new(None));
+        let manager = TestServiceManager {
+            runtime: runtime(),
+            state_path: state_path.to_path_buf(),
+            adopted: adopted.clone(),
+            fail,
+        };
+
+        let result = RestartDaemon::new(daemon, manager, Vec::new())
+            .execute()
+            .wait();
+        let adopted = adopted.lock().unwrap().take();
+        (result, upgrade, adopted)
+    }
+
+    #[test]
+    fn restarted_daemon_adopts_running_modules() {
+        let dir = tempdir().unwrap();
+        let state_path = dir.path().join("upgrade_state");
+
+        let (result, _, adopted) = restart(&state_path, false);
+
+        result.unwrap();
+        assert_eq!(Some(vec!["mod1".to_string()]), adopted);
+        assert!(!state_path.exists());
+    }
+
+    #[test]
+    fn daemon_that_is_not_restarted_supervises_modules_again() {
+        let dir = tempdir().unwrap();
+        let state_path = dir.path().join("upgrade_state");
+
+        let (result, upgrade, adopted) = restart(&state_path, true);
+
+        assert!(result.is_err());
+        assert_eq!(None, adopted);
+        assert!(!upgrade.is_quiesced());
+        assert!(!upgrade.is_pending());
+    }
+}
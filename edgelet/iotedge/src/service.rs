@@ -0,0 +1,278 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Restarts the daemon through whatever manages its process, so that it is
+//! stopped and started again in order instead of being signalled from under
+//! its service manager.
+
+use std::io;
+#[cfg(unix)]
+use std::path::Path;
+use std::process::Command as ShellCommand;
+
+use failure::ResultExt;
+
+use crate::error::{Error, ErrorKind};
+
+/// What manages the daemon's process.
+pub trait ServiceManager {
+    /// Stops the daemon and starts it again, returning once it has started.
+    fn restart(&self) -> Result<(), Error>;
+}
+
+impl<S> ServiceManager for Box<S>
+where
+    S: ServiceManager + ?Sized,
+{
+    fn restart(&self) -> Result<(), Error> {
+        (**self).restart()
+    }
+}
+
+/// The service manager of this host: systemd if it's running, the Windows
+/// service control manager on Windows, and the daemon's bare process
+/// otherwise.
+pub fn service_manager() -> Box<dyn ServiceManager + Send> {
+    #[cfg(unix)]
+    {
+        if Systemd::is_running() {
+            Box::new(Systemd::new("iotedge".to_string()))
+        } else {
+            Box::new(Process::new("iotedged".to_string()))
+        }
+    }
+    #[cfg(windows)]
+    {
+        Box::new(WindowsService::new("iotedge".to_string()))
+    }
+}
+
+/// The systemd unit of the daemon.
+pub struct Systemd {
+    unit: String,
+}
+
+impl Systemd {
+    pub fn new(unit: String) -> Self {
+        Systemd { unit }
+    }
+
+    /// Whether the host was booted with systemd, like `sd_booted`.
+    #[cfg(unix)]
+    pub fn is_running() -> bool {
+        Path::new("/run/systemd/system").is_dir()
+    }
+}
+
+impl ServiceManager for Systemd {
+    fn restart(&self) -> Result<(), Error> {
+        // The unit is of `Type=notify`, so this returns once the daemon that
+        // is started again is ready.
+        run(ShellCommand::new("systemctl")
+            .arg("restart")
+            .arg(&self.unit))
+    }
+}
+
+/// The Windows service of the daemon.
+#[cfg(windows)]
+pub struct WindowsService {
+    name: String,
+}
+
+#[cfg(windows)]
+impl WindowsService {
+    pub fn new(name: String) -> Self {
+        WindowsService { name }
+    }
+}
+
+#[cfg(windows)]
+impl ServiceManager for WindowsService {
+    fn restart(&self) -> Result<(), Error> {
+        run(ShellCommand::new("powershell.exe")
+            .arg("-NoProfile")
+            .arg("-NonInteractive")
+            .arg("-Command")
+            .arg(format!("Restart-Service -Name '{}'", self.name)))
+    }
+}
+
+#[cfg(unix)]
+pub use self::process::Process;
+
+#[cfg(unix)]
+mod process {
+    use std::ffi::{OsStr, OsString};
+    use std::fs;
+    use std::io;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
+    use std::process::{Command as ShellCommand, Stdio};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use failure::ResultExt;
+
+    use super::ServiceManager;
+    use crate::error::{Error, ErrorKind};
+
+    /// How long the daemon is given to shut down, like the `TimeoutStopSec`
+    /// of its systemd unit.
+    const STOP_TIMEOUT: Duration = Duration::from_secs(40);
+
+    const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+    /// The daemon's process, on a host without a service manager. It's
+    /// stopped with SIGTERM like a service manager would stop it, and started
+    /// again with the command line, environment and working directory that it
+    /// had.
+    pub struct Process {
+        name: String,
+    }
+
+    impl Process {
+        pub fn new(name: String) -> Self {
+            Process { name }
+        }
+    }
+
+    impl ServiceManager for Process {
+        fn restart(&self) -> Result<(), Error> {
+            let process = find_process(&self.name).context(ErrorKind::RestartDaemon)?;
+            let (program, args) = process.args.split_first().ok_or(ErrorKind::RestartDaemon)?;
+
+            // Safe because kill doesn't touch memory.
+            if unsafe { libc::kill(process.pid, libc::SIGTERM) } != 0 {
+                return Err(io::Error::last_os_error())
+                    .context(ErrorKind::RestartDaemon)
+                    .map_err(Error::from);
+            }
+
+            let stopping = Instant::now();
+            while process.dir.exists() {
+                if stopping.elapsed() > STOP_TIMEOUT {
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!("{} did not stop within {:?}", self.name, STOP_TIMEOUT),
+                    ))
+                    .context(ErrorKind::RestartDaemon)
+                    .map_err(Error::from);
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+
+            ShellCommand::new(program)
+                .args(args)
+                .env_clear()
+                .envs(process.env)
+                .current_dir(process.cwd)
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()
+                .context(ErrorKind::RestartDaemon)?;
+            Ok(())
+        }
+    }
+
+    struct DaemonProcess {
+        pid: libc::pid_t,
+        dir: PathBuf,
+        args: Vec<OsString>,
+        env: Vec<(OsString, OsString)>,
+        cwd: PathBuf,
+    }
+
+    // The first process whose program is `name`.
+    fn find_process(name: &str) -> io::Result<DaemonProcess> {
+        for entry in fs::read_dir("/proc")? {
+            let entry = entry?;
+            let pid = match entry.file_name().to_str().and_then(|pid| pid.parse().ok()) {
+                Some(pid) => pid,
+                None => continue,
+            };
+
+            // Processes can exit while they're looked at.
+            let args = match fs::read(entry.path().join("cmdline")) {
+                Ok(cmdline) => split_nul(&cmdline),
+                Err(_) => continue,
+            };
+            let is_daemon = args
+                .first()
+                .and_then(|program| Path::new(program).file_name())
+                .map_or(false, |program| program == name);
+            if !is_daemon {
+                continue;
+            }
+
+            let env = split_nul(&fs::read(entry.path().join("environ"))?)
+                .into_iter()
+                .filter_map(|var| {
+                    let var = var.as_bytes();
+                    let eq = var.iter().position(|&b| b == b'=')?;
+                    Some((
+                        OsStr::from_bytes(&var[..eq]).to_os_string(),
+                        OsStr::from_bytes(&var[eq + 1..]).to_os_string(),
+                    ))
+                })
+                .collect();
+            let cwd = fs::read_link(entry.path().join("cwd"))?;
+            return Ok(DaemonProcess {
+                pid,
+                dir: entry.path(),
+                args,
+                env,
+                cwd,
+            });
+        }
+
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} is not running", name),
+        ))
+    }
+
+    // The NUL-terminated strings of a file like /proc/<pid>/cmdline.
+    fn split_nul(contents: &[u8]) -> Vec<OsString> {
+        let mut values: Vec<_> = contents
+            .split(|&b| b == 0)
+            .map(|value| OsStr::from_bytes(value).to_os_string())
+            .collect();
+        if contents.last() == Some(&0) || contents.is_empty() {
+            values.pop();
+        }
+        values
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn nul_terminated_strings_are_split() {
+            assert_eq!(
+                vec![
+                    OsString::from("/usr/bin/iotedged"),
+                    OsString::from("-c"),
+                    OsString::from(""),
+                ],
+                split_nul(b"/usr/bin/iotedged\0-c\0\0")
+            );
+            assert!(split_nul(b"").is_empty());
+        }
+    }
+}
+
+fn run(command: &mut ShellCommand) -> Result<(), Error> {
+    let status = command.status().context(ErrorKind::RestartDaemon)?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("{:?} failed with {}", command, status),
+        ))
+        .context(ErrorKind::RestartDaemon)
+        .map_err(Error::from)
+    }
+}
@@ -2,7 +2,7 @@
 
 use std::env;
 use std::ffi::OsString;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::{stdout, Cursor, Seek};
 use std::path::{Path, PathBuf};
 use std::process::Command as ShellCommand;
@@ -13,10 +13,11 @@ use futures::{Future, Stream};
 use tokio::prelude::*;
 use zip::{write::FileOptions, CompressionMethod, ZipWriter};
 
-use edgelet_core::{LogOptions, LogTail, Module, ModuleRuntime};
+use edgelet_core::{redact_secrets, LogOptions, LogTail, Module, ModuleRuntime};
 
+use crate::check::AdditionalInfo;
 use crate::error::{Error, ErrorKind};
-use crate::logs::pull_logs;
+use crate::logs::{configured_max_line_length, pull_logs};
 use crate::Command;
 
 pub struct SupportBundle<M> {
@@ -26,6 +27,7 @@ pub struct SupportBundle<M> {
     verbose: bool,
     iothub_hostname: Option<String>,
     output_location: OutputLocation,
+    config_file: PathBuf,
 }
 
 struct BundleState<M, W>
@@ -37,6 +39,8 @@ where
     include_ms_only: bool,
     verbose: bool,
     iothub_hostname: Option<String>,
+    config_file: PathBuf,
+    max_line_length: usize,
     file_options: FileOptions,
     zip_writer: ZipWriter<W>,
 }
@@ -87,6 +91,7 @@ where
         verbose: bool,
         iothub_hostname: Option<String>,
         output_location: OutputLocation,
+        config_file: PathBuf,
         runtime: M,
     ) -> Self {
         SupportBundle {
@@ -96,6 +101,7 @@ where
             verbose,
             iothub_hostname,
             output_location,
+            config_file,
         }
     }
 
@@ -123,6 +129,8 @@ where
             include_ms_only: self.include_ms_only,
             verbose: self.verbose,
             iothub_hostname: self.iothub_hostname,
+            max_line_length: configured_max_line_length(&self.config_file),
+            config_file: self.config_file,
             file_options,
             zip_writer,
         })
@@ -140,6 +148,8 @@ where
             .and_then(Self::write_docker_log)
             .and_then(Self::write_all_inspects)
             .and_then(Self::write_all_network_inspects)
+            .and_then(Self::write_host_info)
+            .and_then(Self::write_config)
     }
 
     fn write_module_logs<W>(
@@ -233,6 +243,8 @@ where
             include_ms_only,
             verbose,
             iothub_hostname,
+            config_file,
+            max_line_length,
             file_options,
             mut zip_writer,
         } = state;
@@ -243,13 +255,22 @@ where
             .into_future()
             .map_err(|err| Error::from(err.context(ErrorKind::SupportBundle)))
             .and_then(move |_| {
-                pull_logs(&runtime, &module_name, &log_options, zip_writer).map(move |zw| {
+                pull_logs(
+                    &runtime,
+                    &module_name,
+                    &log_options,
+                    max_line_length,
+                    zip_writer,
+                )
+                .map(move |zw| {
                     let state = BundleState {
                         runtime,
                         log_options,
                         include_ms_only,
                         verbose,
                         iothub_hostname,
+                        config_file,
+                        max_line_length,
                         file_options,
                         zip_writer: zw,
                     };
@@ -427,7 +448,10 @@ where
 
         let (file_name, output) = if let Ok(result) = inspect {
             if result.status.success() {
-                (format!("inspect/{}.json", module_name), result.stdout)
+                (
+                    format!("inspect/{}.json", module_name),
+                    redact_secrets(&String::from_utf8_lossy(&result.stdout)).into_bytes(),
+                )
             } else {
                 (format!("inspect/{}_err.json", module_name), result.stderr)
             }
@@ -546,6 +570,61 @@ where
         state.print_verbose(&format!("Got docker network inspect for {}", network_name));
         Ok(state)
     }
+
+    fn write_host_info<W>(mut state: BundleState<M, W>) -> Result<BundleState<M, W>, Error>
+    where
+        W: Write + Seek + Send,
+    {
+        state.print_verbose("Writing host info to file");
+        let host_info = serde_json::to_vec_pretty(&AdditionalInfo::new())
+            .map_err(|err| Error::from(err.context(ErrorKind::SupportBundle)))?;
+
+        state
+            .zip_writer
+            .start_file_from_path(&Path::new("host_info.json"), state.file_options)
+            .map_err(|err| Error::from(err.context(ErrorKind::SupportBundle)))?;
+
+        state
+            .zip_writer
+            .write(&host_info)
+            .map_err(|err| Error::from(err.context(ErrorKind::SupportBundle)))?;
+
+        state.print_verbose("Wrote host info to file");
+        Ok(state)
+    }
+
+    // The config has the device's credentials, so only a redacted copy goes
+    // into the bundle.
+    fn write_config<W>(mut state: BundleState<M, W>) -> Result<BundleState<M, W>, Error>
+    where
+        W: Write + Seek + Send,
+    {
+        state.print_verbose("Writing redacted config to file");
+        let (file_name, output) = match fs::read_to_string(&state.config_file) {
+            Ok(config) => ("config.yaml", redact_secrets(&config)),
+            Err(err) => {
+                println!(
+                    "Could not read {}. Including error in bundle.\nError message: {}",
+                    state.config_file.display(),
+                    err
+                );
+                ("config_err.txt", err.to_string())
+            }
+        };
+
+        state
+            .zip_writer
+            .start_file_from_path(&Path::new(file_name), state.file_options)
+            .map_err(|err| Error::from(err.context(ErrorKind::SupportBundle)))?;
+
+        state
+            .zip_writer
+            .write(output.as_bytes())
+            .map_err(|err| Error::from(err.context(ErrorKind::SupportBundle)))?;
+
+        state.print_verbose("Wrote redacted config to file");
+        Ok(state)
+    }
 }
 
 impl<M, W> BundleState<M, W>
@@ -585,7 +664,7 @@ mod tests {
     use regex::Regex;
     use tempfile::tempdir;
 
-    use edgelet_core::{MakeModuleRuntime, ModuleRuntimeState};
+    use edgelet_core::{MakeModuleRuntime, ModuleRuntimeState, DEFAULT_MAX_LOG_LINE_LENGTH};
     use edgelet_test_utils::crypto::TestHsm;
     use edgelet_test_utils::module::*;
 
@@ -603,6 +682,11 @@ mod tests {
         let module_name = "test-module";
         let runtime = make_runtime(module_name);
         let tmp_dir = tempdir().unwrap();
+        fs::write(
+            tmp_dir.path().join("config.yaml"),
+            "provisioning:\n  source: \"manual\"\n  device_connection_string: \"HostName=hub;DeviceId=d1;SharedAccessKey=c2VjcmV0\"\n",
+        )
+        .unwrap();
         let file_path = tmp_dir
             .path()
             .join("iotedge_bundle.zip")
@@ -616,6 +700,7 @@ mod tests {
             false,
             None,
             OutputLocation::File(OsString::from(file_path.to_owned())),
+            tmp_dir.path().join("config.yaml"),
             runtime,
         );
 
@@ -677,6 +762,20 @@ mod tests {
         // expect check
         File::open(PathBuf::from(&extract_path).join("check.json")).unwrap();
 
+        // expect host info
+        let host_info: serde_json::Value = serde_json::from_str(
+            &fs::read_to_string(PathBuf::from(&extract_path).join("host_info.json")).unwrap(),
+        )
+        .unwrap();
+        assert!(host_info["os"]["arch"].is_string());
+
+        // expect redacted config
+        let config = fs::read_to_string(PathBuf::from(&extract_path).join("config.yaml")).unwrap();
+        assert_eq!(
+            "provisioning:\n  source: \"manual\"\n  device_connection_string: \"***\"\n",
+            config
+        );
+
         // expect network inspect
         let network_in_inspect = Regex::new(r".*\.json").unwrap();
         assert!(fs::read_dir(PathBuf::from(&extract_path).join("network"))
@@ -702,9 +801,15 @@ mod tests {
             .with_tail(LogTail::Num(0))
             .with_since(0);
 
-        let result: Vec<u8> = pull_logs(&runtime, module_name, &options, Vec::new())
-            .wait()
-            .unwrap();
+        let result: Vec<u8> = pull_logs(
+            &runtime,
+            module_name,
+            &options,
+            DEFAULT_MAX_LOG_LINE_LENGTH,
+            Vec::new(),
+        )
+        .wait()
+        .unwrap();
         let result_str = str::from_utf8(&result).unwrap();
         assert_eq!("Roses are redviolets are blue", result_str);
     }
@@ -725,6 +830,7 @@ mod tests {
             true,
             None,
             OutputLocation::File(OsString::from(file_path.to_owned())),
+            tmp_dir.path().join("config.yaml"),
             runtime,
         );
 
@@ -746,6 +852,7 @@ mod tests {
             true,
             None,
             OutputLocation::File(OsString::from(file_path)),
+            tmp_dir.path().join("config.yaml"),
             runtime,
         );
 
@@ -777,6 +884,7 @@ mod tests {
             true,
             None,
             OutputLocation::File(OsString::from(file_path.to_owned())),
+            tmp_dir.path().join("config.yaml"),
             runtime,
         );
 
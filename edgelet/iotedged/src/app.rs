@@ -1,7 +1,7 @@
 // Copyright (c) Microsoft. All rights reserved.
 
 use std::ffi::{OsStr, OsString};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use clap::{crate_authors, crate_description, crate_name, App, Arg};
 use failure::ResultExt;
@@ -47,7 +47,7 @@ fn create_app(default_config_file: &OsStr) -> App<'_, '_> {
     }
 }
 
-fn init_common(running_as_windows_service: bool) -> Result<Settings, Error> {
+fn init_common(running_as_windows_service: bool) -> Result<(Settings, PathBuf), Error> {
     let default_config_file = if cfg!(windows) {
         let program_data: PathBuf =
             std::env::var_os("PROGRAMDATA").map_or_else(|| r"C:\ProgramData".into(), Into::into);
@@ -87,21 +87,28 @@ fn init_common(running_as_windows_service: bool) -> Result<Settings, Error> {
 
     info!("Using config file: {}", config_file.display());
 
-    let settings = Settings::new(&config_file)
-        .context(ErrorKind::Initialize(InitializeErrorReason::LoadSettings))?;
+    let settings = load_settings(&config_file)?;
 
-    Ok(settings)
+    Ok((settings, config_file))
 }
 
-pub fn init() -> Result<Settings, Error> {
+/// Returns the settings and the path of the config file they were read from,
+/// so that they can be reloaded.
+pub fn init() -> Result<(Settings, PathBuf), Error> {
     init_common(false)
 }
 
 #[cfg(windows)]
-pub fn init_win_svc() -> Result<Settings, Error> {
+pub fn init_win_svc() -> Result<(Settings, PathBuf), Error> {
     init_common(true)
 }
 
+pub fn load_settings(config_file: &Path) -> Result<Settings, Error> {
+    let settings = Settings::new(config_file)
+        .context(ErrorKind::Initialize(InitializeErrorReason::LoadSettings))?;
+    Ok(settings)
+}
+
 #[cfg(windows)]
 pub fn init_win_svc_logging() {
     logging::init_win_log();
@@ -0,0 +1,570 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! The modules that are deployed through `/deployment` on the management
+//! API, besides the edge runtime module that the watchdog keeps running. The
+//! module set that was applied last is saved to the cache directory, so that
+//! the first deployment after the daemon restarts is applied from where the
+//! last one left the modules.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use futures::future::{self, Either};
+use futures::sync::mpsc;
+use futures::Future;
+use log::{info, warn, Level};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use edgelet_core::{
+    AdmissionSettings, Deployment, DeploymentReconciler, ImageReference, LastDeploymentStatus,
+    ManifestTrustKey, ModuleRuntime, ModuleRuntimeErrorReason, ModuleSetStore, UpgradeGuard,
+};
+use edgelet_utils::log_failure;
+
+/// How long the modules of a deployment are given to exit before they are
+/// killed, unless their spec has a stop timeout.
+pub const DEPLOYED_MODULE_STOP_TIME: Duration = Duration::from_secs(30);
+
+/// The reconciler of the deployments that are applied to `runtime`, up to
+/// `parallelism` modules at a time, which saves each module set that it
+/// applies to `store_path`, and the status of the last deployment. With a
+/// `trust_key`, only the manifests that are signed with it are applied, and
+/// only the module sets that fit in `admission` are. The status only follows
+/// the deployments for as long as the returned future runs.
+pub fn init<M>(
+    runtime: &M,
+    store_path: PathBuf,
+    parallelism: usize,
+    trust_key: Option<ManifestTrustKey>,
+    admission: AdmissionSettings,
+) -> (
+    DeploymentReconciler<M>,
+    LastDeploymentStatus,
+    impl Future<Item = (), Error = ()> + Send,
+)
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+    M::Config: DeserializeOwned + ImageReference + Serialize,
+    for<'r> &'r <M as ModuleRuntime>::Error: Into<ModuleRuntimeErrorReason>,
+{
+    let store = ModuleSetStore::new(store_path);
+    let (status_tx, status_rx) = mpsc::unbounded();
+    let mut deployment = Deployment::new(store.load())
+        .with_store(store)
+        .with_status_channel(status_tx)
+        .with_admission(admission);
+    if let Some(trust_key) = trust_key {
+        deployment = deployment.with_trust_key(trust_key);
+    }
+
+    let status = LastDeploymentStatus::new();
+    let follow = status.follow(status_rx);
+    let deployments =
+        DeploymentReconciler::new(runtime.clone(), deployment, DEPLOYED_MODULE_STOP_TIME)
+            .with_parallelism(parallelism);
+    (deployments, status, follow)
+}
+
+/// Brings up the modules that were deployed before the daemon restarted. With
+/// `adopt`, the ones that are still running as they were deployed are taken
+/// over rather than started, and the others are recreated. A module that
+/// can't be started is logged, since it doesn't keep the daemon or the other
+/// modules from starting.
+pub fn start<M>(
+    deployments: &DeploymentReconciler<M>,
+    adopt: bool,
+) -> impl Future<Item = (), Error = ()> + Send
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+    M::Config: DeserializeOwned + ImageReference + Serialize,
+    for<'r> &'r <M as ModuleRuntime>::Error: Into<ModuleRuntimeErrorReason>,
+{
+    let modules = deployments
+        .current()
+        .map_or(0, |modules| modules.modules().len());
+    let started = if adopt {
+        info!("Adopting {} deployed modules...", modules);
+        Either::A(
+            deployments
+                .adopt()
+                .map(|adopted| info!("Adopted the deployed modules {:?}", adopted)),
+        )
+    } else {
+        info!("Starting {} deployed modules...", modules);
+        Either::B(deployments.start())
+    };
+    started.then(|result| {
+        match result {
+            Ok(()) => info!("Started the deployed modules"),
+            Err(err) => {
+                warn!("Could not start the deployed modules:");
+                log_failure(Level::Warn, &err);
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Stops the deployed modules when the daemon shuts down, the modules that
+/// depend on others first, unless they are left running for an upgrade. A
+/// module that can't be stopped is logged, since it doesn't keep the daemon
+/// from shutting down.
+pub fn stop<M>(
+    deployments: &DeploymentReconciler<M>,
+    upgrade: &UpgradeGuard,
+) -> impl Future<Item = (), Error = ()> + Send
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+    M::Config: DeserializeOwned + ImageReference + Serialize,
+    for<'r> &'r <M as ModuleRuntime>::Error: Into<ModuleRuntimeErrorReason>,
+{
+    if upgrade.is_quiesced() {
+        info!("Leaving the deployed modules running for the upgrade");
+        return Either::A(future::ok(()));
+    }
+
+    info!("Stopping the deployed modules...");
+    Either::B(deployments.stop().then(|result| {
+        match result {
+            Ok(()) => info!("Stopped the deployed modules"),
+            Err(err) => {
+                warn!("Could not stop the deployed modules:");
+                log_failure(Level::Warn, &err);
+            }
+        }
+        Ok(())
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use failure::Fail;
+    use futures::Async;
+    use serde_json::json;
+    use tempdir::TempDir;
+
+    use std::collections::HashMap;
+
+    use edgelet_core::{
+        DeploymentState, ErrorKind, MakeModuleRuntime, ModuleRuntimeState, ModuleStatus,
+        SPEC_HASH_LABEL,
+    };
+    use edgelet_test_utils::crypto::TestHsm;
+    use edgelet_test_utils::module::*;
+
+    use super::*;
+
+    #[derive(Clone, Copy, Debug, Fail)]
+    enum TestError {
+        #[fail(display = "General error")]
+        General,
+    }
+
+    impl<'a> From<&'a TestError> for ModuleRuntimeErrorReason {
+        fn from(_err: &'a TestError) -> Self {
+            ModuleRuntimeErrorReason::Other
+        }
+    }
+
+    fn runtime() -> TestRuntime<TestError, TestSettings> {
+        runtime_with_state(ModuleRuntimeState::default())
+    }
+
+    fn runtime_with_state(state: ModuleRuntimeState) -> TestRuntime<TestError, TestSettings> {
+        let module = TestModule::new(
+            "tempSensor".to_string(),
+            TestConfig::new("sensor:1.0".to_string()),
+            Ok(state),
+        );
+        TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(Ok(module))
+    }
+
+    fn payload(image: &str) -> serde_json::Value {
+        json!({
+            "modules": {
+                "tempSensor": { "type": "test", "config": { "image": image } },
+            },
+        })
+    }
+
+    fn payload_with_dependency() -> serde_json::Value {
+        json!({
+            "modules": {
+                "filter": {
+                    "type": "test",
+                    "config": { "image": "filter:1.0" },
+                    "dependsOn": ["tempSensor"],
+                },
+                "tempSensor": { "type": "test", "config": { "image": "sensor:1.0" } },
+            },
+        })
+    }
+
+    // Takes in the statuses that were reported so far.
+    fn catch_up(follow: &mut impl Future<Item = (), Error = ()>) {
+        future::poll_fn(|| {
+            let _ = follow.poll();
+            Ok::<_, ()>(Async::Ready(()))
+        })
+        .wait()
+        .unwrap();
+    }
+
+    #[test]
+    fn deployments_are_applied_and_reported() {
+        let dir = TempDir::new("deployment").unwrap();
+        let runtime = runtime();
+        let (deployments, status, mut follow) = init(
+            &runtime,
+            dir.path().join("deployment_state"),
+            1,
+            None,
+            AdmissionSettings::new(),
+        );
+        assert!(status.get().is_none());
+
+        deployments.apply(&payload("sensor:1.0")).wait().unwrap();
+        assert_eq!(
+            vec!["create tempSensor", "start tempSensor"],
+            runtime.operations()
+        );
+        catch_up(&mut follow);
+        assert_eq!(DeploymentState::Applied, status.get().unwrap().state());
+
+        assert!(deployments.apply(&json!({ "modules": [] })).wait().is_err());
+        catch_up(&mut follow);
+        assert_eq!(DeploymentState::Rejected, status.get().unwrap().state());
+        assert_eq!(2, runtime.operations().len());
+    }
+
+    #[test]
+    fn deployment_over_budget_is_rejected() {
+        let dir = TempDir::new("deployment").unwrap();
+        let runtime = runtime();
+        let (deployments, status, mut follow) = init(
+            &runtime,
+            dir.path().join("deployment_state"),
+            1,
+            None,
+            AdmissionSettings::new().with_max_modules(1),
+        );
+        deployments.apply(&payload("sensor:1.0")).wait().unwrap();
+
+        let err = deployments
+            .apply(&payload_with_dependency())
+            .wait()
+            .unwrap_err();
+        match err.kind() {
+            ErrorKind::DeploymentOverBudget(_) => (),
+            kind => panic!("Expected `DeploymentOverBudget` error but got {:?}", kind),
+        }
+        assert_eq!(
+            vec!["create tempSensor", "start tempSensor"],
+            runtime.operations()
+        );
+        assert_eq!(1, deployments.current().unwrap().modules().len());
+        catch_up(&mut follow);
+        assert_eq!(DeploymentState::Rejected, status.get().unwrap().state());
+    }
+
+    #[test]
+    fn deployments_are_applied_one_at_a_time() {
+        let dir = TempDir::new("deployment").unwrap();
+        let (deployments, _, _) = init(
+            &runtime(),
+            dir.path().join("deployment_state"),
+            1,
+            None,
+            AdmissionSettings::new(),
+        );
+
+        let first = deployments.apply(&payload("sensor:1.0"));
+        let err = deployments
+            .apply(&payload("sensor:2.0"))
+            .wait()
+            .unwrap_err();
+        match err.kind() {
+            ErrorKind::DeploymentInProgress => (),
+            kind => panic!("Expected `DeploymentInProgress` error but got {:?}", kind),
+        }
+
+        // One that is given up on doesn't hold up the next.
+        drop(first);
+        deployments.apply(&payload("sensor:2.0")).wait().unwrap();
+    }
+
+    #[test]
+    fn deployments_are_applied_with_the_parallelism() {
+        let dir = TempDir::new("deployment").unwrap();
+        let (deployments, _, _) = init(
+            &runtime(),
+            dir.path().join("deployment_state"),
+            4,
+            None,
+            AdmissionSettings::new(),
+        );
+        assert_eq!(4, deployments.parallelism());
+    }
+
+    #[test]
+    fn deployment_after_restart_is_applied_from_the_saved_modules() {
+        let dir = TempDir::new("deployment").unwrap();
+        let (deployments, _, _) = init(
+            &runtime(),
+            dir.path().join("deployment_state"),
+            1,
+            None,
+            AdmissionSettings::new(),
+        );
+        deployments.apply(&payload("sensor:1.0")).wait().unwrap();
+
+        // The module that didn't change is left alone, and the one that did
+        // is recreated.
+        let runtime = runtime();
+        let (deployments, _, _) = init(
+            &runtime,
+            dir.path().join("deployment_state"),
+            1,
+            None,
+            AdmissionSettings::new(),
+        );
+        assert!(deployments.current().is_some());
+        deployments.apply(&payload("sensor:1.0")).wait().unwrap();
+        assert!(runtime.operations().is_empty());
+
+        deployments.apply(&payload("sensor:2.0")).wait().unwrap();
+        assert_eq!(
+            vec![
+                "stop tempSensor",
+                "remove tempSensor",
+                "create tempSensor",
+                "start tempSensor"
+            ],
+            runtime.operations()
+        );
+    }
+
+    #[test]
+    fn deployed_modules_are_started_in_startup_order() {
+        let dir = TempDir::new("deployment").unwrap();
+        let (deployments, _, _) = init(
+            &runtime(),
+            dir.path().join("deployment_state"),
+            1,
+            None,
+            AdmissionSettings::new(),
+        );
+        deployments
+            .apply(&payload_with_dependency())
+            .wait()
+            .unwrap();
+
+        let runtime = runtime();
+        let (deployments, _, _) = init(
+            &runtime,
+            dir.path().join("deployment_state"),
+            1,
+            None,
+            AdmissionSettings::new(),
+        );
+        start(&deployments, false).wait().unwrap();
+        assert_eq!(
+            vec!["start tempSensor", "start filter"],
+            runtime.operations()
+        );
+    }
+
+    #[test]
+    fn nothing_is_started_without_deployed_modules() {
+        let dir = TempDir::new("deployment").unwrap();
+        let runtime = runtime();
+        let (deployments, _, _) = init(
+            &runtime,
+            dir.path().join("deployment_state"),
+            1,
+            None,
+            AdmissionSettings::new(),
+        );
+        start(&deployments, false).wait().unwrap();
+        assert!(runtime.operations().is_empty());
+    }
+
+    #[test]
+    fn modules_that_do_not_start_do_not_stop_the_daemon() {
+        let dir = TempDir::new("deployment").unwrap();
+        let (deployments, _, _) = init(
+            &runtime(),
+            dir.path().join("deployment_state"),
+            1,
+            None,
+            AdmissionSettings::new(),
+        );
+        deployments.apply(&payload("sensor:1.0")).wait().unwrap();
+
+        let runtime = runtime().with_module(Err(TestError::General));
+        let (deployments, _, _) = init(
+            &runtime,
+            dir.path().join("deployment_state"),
+            1,
+            None,
+            AdmissionSettings::new(),
+        );
+        start(&deployments, false).wait().unwrap();
+        assert_eq!(vec!["start tempSensor"], runtime.operations());
+    }
+
+    #[test]
+    fn deployed_modules_that_are_running_as_they_were_are_adopted() {
+        let dir = TempDir::new("deployment").unwrap();
+        let (deployments, _, _) = init(
+            &runtime(),
+            dir.path().join("deployment_state"),
+            1,
+            None,
+            AdmissionSettings::new(),
+        );
+        deployments
+            .apply(&payload_with_dependency())
+            .wait()
+            .unwrap();
+        let spec_hash = deployments
+            .current()
+            .unwrap()
+            .modules()
+            .iter()
+            .find(|spec| spec.name() == "tempSensor")
+            .unwrap()
+            .spec_hash()
+            .unwrap();
+
+        // Only tempSensor is running, so filter is created.
+        let mut labels = HashMap::new();
+        labels.insert(SPEC_HASH_LABEL.to_string(), spec_hash);
+        let runtime = runtime_with_state(
+            ModuleRuntimeState::default()
+                .with_status(ModuleStatus::Running)
+                .with_labels(labels),
+        );
+        let (deployments, _, _) = init(
+            &runtime,
+            dir.path().join("deployment_state"),
+            1,
+            None,
+            AdmissionSettings::new(),
+        );
+        start(&deployments, true).wait().unwrap();
+        assert_eq!(vec!["create filter", "start filter"], runtime.operations());
+    }
+
+    #[test]
+    fn deployed_modules_that_changed_are_recreated_when_adopted() {
+        let dir = TempDir::new("deployment").unwrap();
+        let (deployments, _, _) = init(
+            &runtime(),
+            dir.path().join("deployment_state"),
+            1,
+            None,
+            AdmissionSettings::new(),
+        );
+        deployments.apply(&payload("sensor:1.0")).wait().unwrap();
+
+        let runtime =
+            runtime_with_state(ModuleRuntimeState::default().with_status(ModuleStatus::Running));
+        let (deployments, _, _) = init(
+            &runtime,
+            dir.path().join("deployment_state"),
+            1,
+            None,
+            AdmissionSettings::new(),
+        );
+        start(&deployments, true).wait().unwrap();
+        assert_eq!(
+            vec![
+                "stop tempSensor",
+                "remove tempSensor",
+                "create tempSensor",
+                "start tempSensor"
+            ],
+            runtime.operations()
+        );
+    }
+
+    #[test]
+    fn deployed_modules_are_stopped_in_shutdown_order() {
+        let dir = TempDir::new("deployment").unwrap();
+        let runtime = runtime();
+        let (deployments, _, _) = init(
+            &runtime,
+            dir.path().join("deployment_state"),
+            1,
+            None,
+            AdmissionSettings::new(),
+        );
+        deployments
+            .apply(&payload_with_dependency())
+            .wait()
+            .unwrap();
+
+        let upgrade = UpgradeGuard::new(dir.path().join("upgrade_state"));
+        stop(&deployments, &upgrade).wait().unwrap();
+        assert_eq!(
+            vec!["stop filter", "stop tempSensor"],
+            runtime.operations()[4..].to_vec()
+        );
+    }
+
+    #[test]
+    fn deployed_modules_are_left_running_for_an_upgrade() {
+        let dir = TempDir::new("deployment").unwrap();
+        let runtime = runtime();
+        let (deployments, _, _) = init(
+            &runtime,
+            dir.path().join("deployment_state"),
+            1,
+            None,
+            AdmissionSettings::new(),
+        );
+        deployments.apply(&payload("sensor:1.0")).wait().unwrap();
+
+        let upgrade = UpgradeGuard::new(dir.path().join("upgrade_state"));
+        upgrade.prepare(&runtime).wait().unwrap();
+        stop(&deployments, &upgrade).wait().unwrap();
+        assert_eq!(
+            vec!["create tempSensor", "start tempSensor"],
+            runtime.operations()
+        );
+    }
+
+    #[test]
+    fn modules_that_do_not_stop_do_not_hold_up_the_shutdown() {
+        let dir = TempDir::new("deployment").unwrap();
+        let (deployments, _, _) = init(
+            &runtime(),
+            dir.path().join("deployment_state"),
+            1,
+            None,
+            AdmissionSettings::new(),
+        );
+        deployments.apply(&payload("sensor:1.0")).wait().unwrap();
+
+        let runtime = runtime().with_module(Err(TestError::General));
+        let (deployments, _, _) = init(
+            &runtime,
+            dir.path().join("deployment_state"),
+            1,
+            None,
+            AdmissionSettings::new(),
+        );
+        let upgrade = UpgradeGuard::new(dir.path().join("upgrade_state"));
+        stop(&deployments, &upgrade).wait().unwrap();
+        assert_eq!(vec!["stop tempSensor"], runtime.operations());
+    }
+}
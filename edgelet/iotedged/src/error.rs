@@ -34,12 +34,21 @@ pub enum ErrorKind {
     #[fail(display = "The daemon could not start up successfully: {}", _0)]
     Initialize(InitializeErrorReason),
 
+    #[fail(display = "Could not forward the logs of module {}", _0)]
+    ForwardLogs(String),
+
     #[fail(display = "Invalid signed token was provided.")]
     InvalidSignedToken,
 
     #[fail(display = "The management service encountered an error")]
     ManagementService,
 
+    #[fail(display = "Could not notify the webhook about module {}", _0)]
+    NotifyWebhook(String),
+
+    #[fail(display = "The daemon could not reload its config")]
+    ReloadSettings,
+
     #[fail(display = "The reprovisioning operation failed")]
     ReprovisionFailure,
 
@@ -159,6 +168,7 @@ impl From<&ErrorKind> for i32 {
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum InitializeErrorReason {
+    CertificateFiles,
     CertificateSettings,
     CreateCertificateManager,
     CreateMasterEncryptionKey,
@@ -171,6 +181,7 @@ pub enum InitializeErrorReason {
     EdgeRuntime,
     ExternalProvisioningClient(ExternalProvisioningErrorReason),
     Hsm,
+    HeartbeatMonitor,
     HttpClient,
     HybridAuthDirCreate,
     HybridAuthKeyCreate,
@@ -186,13 +197,16 @@ pub enum InitializeErrorReason {
     InvalidProxyUri,
     IssuerCAExpiration,
     LoadSettings,
+    LogForwarding,
     ManagementService,
+    ManifestTrustKey,
     ManualProvisioningClient,
     ModuleRuntime,
     PrepareWorkloadCa,
     #[cfg(windows)]
     RegisterWindowsService,
     RemoveExistingModules,
+    ResumeFromUpgrade,
     SaveSettings,
     #[cfg(windows)]
     StartWindowsService,
@@ -217,6 +231,10 @@ pub enum ExternalProvisioningErrorReason {
 impl fmt::Display for InitializeErrorReason {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            InitializeErrorReason::CertificateFiles => {
+                write!(f, "Could not use the certificate files in the config.yaml")
+            }
+
             InitializeErrorReason::CertificateSettings => {
                 write!(f, "Could not configure Edge gateway certificates")
             }
@@ -261,6 +279,10 @@ impl fmt::Display for InitializeErrorReason {
 
             InitializeErrorReason::Hsm => write!(f, "Could not initialize HSM"),
 
+            InitializeErrorReason::HeartbeatMonitor => {
+                write!(f, "Could not start the watchdog heartbeat monitor")
+            }
+
             InitializeErrorReason::HttpClient => write!(f, "Could not initialize HTTP client"),
 
             InitializeErrorReason::HybridAuthDirCreate => {
@@ -316,10 +338,19 @@ impl fmt::Display for InitializeErrorReason {
 
             InitializeErrorReason::LoadSettings => write!(f, "Could not load settings"),
 
+            InitializeErrorReason::LogForwarding => {
+                write!(f, "Could not start forwarding module logs to the journal")
+            }
+
             InitializeErrorReason::ManagementService => {
                 write!(f, "Could not start management service")
             }
 
+            InitializeErrorReason::ManifestTrustKey => write!(
+                f,
+                "Could not load the key that deployment manifests must be signed with"
+            ),
+
             InitializeErrorReason::ManualProvisioningClient => {
                 write!(f, "Could not initialize manual provisioning client")
             }
@@ -341,6 +372,10 @@ impl fmt::Display for InitializeErrorReason {
                 write!(f, "Could not remove existing modules")
             }
 
+            InitializeErrorReason::ResumeFromUpgrade => {
+                write!(f, "Could not resume the modules after the upgrade")
+            }
+
             InitializeErrorReason::SaveSettings => write!(f, "Could not save settings file"),
 
             #[cfg(windows)]
@@ -14,9 +14,12 @@
 )]
 
 pub mod app;
+pub mod deployment;
 mod error;
+pub mod log_forwarding;
 pub mod logging;
 pub mod signal;
+pub mod webhook;
 pub mod workload;
 
 #[cfg(not(target_os = "windows"))]
@@ -32,7 +35,9 @@ use std::fs;
 use std::fs::{DirBuilder, File, OpenOptions};
 use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
+use std::process;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use failure::{Context, Fail, ResultExt};
 use futures::future::{Either, IntoFuture};
@@ -40,36 +45,45 @@ use futures::sync::oneshot::{self, Receiver};
 use futures::{future, Future, Stream};
 use hyper::server::conn::Http;
 use hyper::{Body, Request, Uri};
-use log::{debug, info, Level};
+use log::{debug, error, info, warn, Level};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use sha2::{Digest, Sha256};
+use tokio::timer::Interval;
 use url::Url;
 
-use dps::DPS_API_VERSION;
+use dps::{RetryPolicy, DPS_API_VERSION};
 use edgelet_core::crypto::{
     Activate, CreateCertificate, Decrypt, DerivedKeyStore, Encrypt, GetDeviceIdentityCertificate,
     GetHsmVersion, GetIssuerAlias, GetTrustBundle, KeyIdentity, KeyStore, MakeRandom,
     MasterEncryptionKey, MemoryKey, MemoryKeyStore, Sign, Signature, SignatureAlgorithm,
     IOTEDGED_CA_ALIAS,
 };
-use edgelet_core::watchdog::Watchdog;
+use edgelet_core::watchdog::{ReconcileTimer, Watchdog, WatchdogPause};
 use edgelet_core::{
-    AttestationMethod, Authenticator, Certificate, CertificateIssuer, CertificateProperties,
-    CertificateType, Dps, MakeModuleRuntime, ManualAuthMethod, Module, ModuleRuntime,
-    ModuleRuntimeErrorReason, ModuleSpec, ProvisioningResult as CoreProvisioningResult,
-    ProvisioningType, RuntimeSettings, SymmetricKeyAttestationInfo, TpmAttestationInfo,
-    WorkloadConfig, X509AttestationInfo,
+    check_certificate_files, AttestationMethod, Authenticator, Certificate, CertificateIssuer,
+    CertificateProperties, CertificateType, DeploymentReconciler, Dps, DpsRetry, Heartbeat,
+    HeartbeatMonitor, ImageReference, LastDeploymentStatus, Listen, MakeModuleRuntime,
+    ManifestTrustKey, ManualAuthMethod, Module, ModuleRuntime, ModuleRuntimeErrorReason,
+    ModuleSetStore, ModuleSpec, ProvisioningResult as CoreProvisioningResult, ProvisioningType,
+    RuntimeSettings, SocketOwnership, SymmetricKeyAttestationInfo, TpmAttestationInfo,
+    UpgradeGuard, UpstreamEndpoints, WatchdogSettings, WorkloadConfig, X509AttestationInfo,
 };
 use edgelet_hsm::tpm::{TpmKey, TpmKeyStore};
 use edgelet_hsm::{Crypto, HsmLock, X509};
+use edgelet_http::audit::{AuditLog, AuditService};
 use edgelet_http::certificate_manager::CertificateManager;
 use edgelet_http::client::{Client as HttpClient, ClientImpl};
 use edgelet_http::logging::LoggingService;
-use edgelet_http::{HyperExt, MaybeProxyClient, PemCertificate, TlsAcceptorParams, API_VERSION};
+use edgelet_http::metrics::RequestMetrics;
+use edgelet_http::peer::{AllowedPeers, PeerAuthorizationService};
+use edgelet_http::{
+    ClientAuth, HyperExt, Listener, MaybeProxyClient, OcspResponder, PemCertificate,
+    RequestIdService, SocketPermissions, TlsAcceptorParams, API_VERSION,
+};
 use edgelet_http_external_provisioning::ExternalProvisioningClient;
 use edgelet_http_mgmt::ManagementService;
-use edgelet_http_workload::WorkloadService;
+use edgelet_http_workload::{CertExpiryMonitor, ServerCertRotator, WorkloadService};
 use edgelet_iothub::{HubIdentityManager, SasTokenSource};
 use edgelet_utils::log_failure;
 pub use error::{Error, ErrorKind, InitializeErrorReason};
@@ -89,6 +103,9 @@ const EDGE_RUNTIME_MODULEID: &str = "$edgeAgent";
 const EDGE_RUNTIME_MODULE_NAME: &str = "edgeAgent";
 const AUTH_SCHEME: &str = "sasToken";
 
+/// Mode of the management socket when `listen.management_socket_group` is set
+const MANAGEMENT_SOCKET_MODE: u32 = 0o660;
+
 /// The following constants are all environment variables names injected into
 /// the Edge Agent container.
 ///
@@ -96,6 +113,10 @@ const AUTH_SCHEME: &str = "sasToken";
 /// is expected to work with.
 const HOSTNAME_KEY: &str = "IOTEDGE_IOTHUBHOSTNAME";
 
+/// This variable holds the proxy that the daemon connects through, so that
+/// the edge agent connects through it too.
+const HTTPS_PROXY_KEY: &str = "https_proxy";
+
 /// This variable holds the host name for the edge device. This name is used
 /// by the edge agent to provide the edge hub container an alias name in the
 /// network so that TLS cert validation works.
@@ -157,6 +178,21 @@ const EDGE_PROVISIONING_BACKUP_FILENAME: &str = "provisioning_backup.json";
 /// This is the name of the settings backup file
 const EDGE_SETTINGS_STATE_FILENAME: &str = "settings_state";
 
+/// This is the name of the file that the spec of the edge runtime module is
+/// saved to whenever it is applied. It is in the cache subdirectory, so that
+/// it is forgotten when the modules are removed to reconfigure the device.
+const EDGE_MODULE_SET_STATE_FILENAME: &str = "module_set_state";
+
+/// This is the name of the file that the modules of the last deployment on
+/// the management API are saved to. Like the spec of the edge runtime module,
+/// it is forgotten when the device is reconfigured.
+const EDGE_DEPLOYMENT_STATE_FILENAME: &str = "deployment_state";
+
+/// This is the name of the file that the modules that are running are saved
+/// to before an upgrade. It isn't in the cache subdirectory, which is removed
+/// when the device is reconfigured.
+const EDGE_UPGRADE_STATE_FILENAME: &str = "upgrade_state";
+
 /// This is the name of the hybrid id subdirectory that will
 /// contain the hybrid key and other related files
 const EDGE_HYBRID_IDENTITY_SUBDIR: &str = "hybrid_id";
@@ -205,12 +241,17 @@ const DEVICE_IDENTITY_KEY_PATH_ENV_KEY: &str = "IOTEDGE_DEVICE_IDENTITY_PK";
 
 const IOTEDGED_COMMONNAME: &str = "iotedged workload ca";
 const IOTEDGED_TLS_COMMONNAME: &str = "iotedged";
+const IOTEDGED_TLS_ALIAS: &str = "iotedge-tls";
 // 5 mins
 const IOTEDGED_MIN_EXPIRATION_DURATION: i64 = 5 * 60;
 // 2 hours
 const IOTEDGE_ID_CERT_MAX_DURATION_SECS: i64 = 2 * 3600;
 // 90 days
 const IOTEDGE_SERVER_CERT_MAX_DURATION_SECS: i64 = 90 * 24 * 3600;
+// 1 hour
+const CERT_EXPIRY_CHECK_INTERVAL_SECS: u64 = 3600;
+// 1 minute
+const SERVER_CERT_ROTATION_CHECK_INTERVAL_SECS: u64 = 60;
 
 // HSM lib version that the iotedge runtime required
 const IOTEDGE_COMPAT_HSM_VERSION: &str = "1.0.3";
@@ -221,11 +262,15 @@ enum StartApiReturnStatus {
     Shutdown,
 }
 
+/// Reads the settings from the config file again, for reloading them.
+type SettingsLoader<S> = Arc<dyn Fn() -> Result<S, Error> + Send + Sync>;
+
 pub struct Main<M>
 where
     M: MakeModuleRuntime,
 {
     settings: M::Settings,
+    load_settings: Option<SettingsLoader<M::Settings>>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -245,14 +290,28 @@ where
     M: MakeModuleRuntime<ProvisioningResult = ProvisioningResult> + Send + 'static,
     M::ModuleRuntime: 'static + Authenticator<Request = Request<Body>> + Clone + Send + Sync,
     <<M::ModuleRuntime as ModuleRuntime>::Module as Module>::Config:
-        Clone + DeserializeOwned + Serialize,
+        Clone + DeserializeOwned + ImageReference + Serialize,
     M::Settings: 'static + Clone + Serialize,
     <M::ModuleRuntime as ModuleRuntime>::Logs: Into<Body>,
     <M::ModuleRuntime as Authenticator>::Error: Fail + Sync,
     for<'r> &'r <M::ModuleRuntime as ModuleRuntime>::Error: Into<ModuleRuntimeErrorReason>,
 {
     pub fn new(settings: M::Settings) -> Self {
-        Main { settings }
+        Main {
+            settings,
+            load_settings: None,
+        }
+    }
+
+    /// Reloads the config with `load_settings` on SIGHUP and when asked to
+    /// by the management API. Only changes to the spec of the edge runtime
+    /// module are applied while the daemon runs.
+    pub fn with_settings_loader<L>(mut self, load_settings: L) -> Self
+    where
+        L: Fn() -> Result<M::Settings, Error> + Send + Sync + 'static,
+    {
+        self.load_settings = Some(Arc::new(load_settings));
+        self
     }
 
     // Allowing cognitive complexity errors for now. TODO: Refactor method later.
@@ -262,9 +321,17 @@ where
         F: Future<Item = (), Error = ()> + Send + 'static,
         G: Fn() -> F,
     {
-        let Main { settings } = self;
+        let Main {
+            settings,
+            load_settings,
+        } = self;
         let hsm_lock = HsmLock::new();
 
+        // Fail before anything is provisioned or started with a file that can't be used.
+        check_certificate_files(&settings).context(ErrorKind::Initialize(
+            InitializeErrorReason::CertificateFiles,
+        ))?;
+
         let mut tokio_runtime = tokio::runtime::Runtime::new()
             .context(ErrorKind::Initialize(InitializeErrorReason::Tokio))?;
 
@@ -317,6 +384,11 @@ where
                 InitializeErrorReason::CreateCacheDirectory,
             ))?;
 
+        let upgrade =
+            UpgradeGuard::new(Path::new(&settings.homedir()).join(EDGE_UPGRADE_STATE_FILENAME));
+        // Outlives the restarts of the APIs, so that a pause isn't lost to them.
+        let watchdog_pause = WatchdogPause::new();
+
         macro_rules! start_edgelet {
             ($key_store:ident, $provisioning_result:ident, $root_key:ident, $force_reprovision:ident, $id_cert_thumprint:ident, $provision:ident,) => {{
                 info!("Finished provisioning edge device.");
@@ -344,8 +416,19 @@ where
                         .context(ErrorKind::Initialize(
                             InitializeErrorReason::RemoveExistingModules,
                         ))?;
+                    for state in &[EDGE_MODULE_SET_STATE_FILENAME, EDGE_DEPLOYMENT_STATE_FILENAME] {
+                        ModuleSetStore::new(cache_subdir_path.join(state))
+                            .clear()
+                            .context(ErrorKind::Initialize(
+                                InitializeErrorReason::RemoveExistingModules,
+                            ))?;
+                    }
                 }
 
+                // The modules that were left running when the daemon was upgraded are
+                // adopted as they are, so they aren't removed even if the device is reconfigured.
+                let resuming = upgrade.is_pending();
+
                 // Detect if the settings were changed and if the device needs to be reconfigured
                 check_settings_state::<M, _>(
                     &cache_subdir_path,
@@ -355,14 +438,54 @@ where
                     &crypto,
                     &mut tokio_runtime,
                     $id_cert_thumprint,
+                    resuming,
                 )?;
 
+                if resuming {
+                    let adopted = tokio_runtime
+                        .block_on(upgrade.resume(&runtime))
+                        .context(ErrorKind::Initialize(
+                            InitializeErrorReason::ResumeFromUpgrade,
+                        ))?;
+                    info!("Resumed from the upgrade with modules {:?}", adopted);
+                }
+
                 let cfg = WorkloadData::new(
                     $provisioning_result.hub_name().to_string(),
                     $provisioning_result.device_id().to_string(),
                     IOTEDGE_ID_CERT_MAX_DURATION_SECS,
                     IOTEDGE_SERVER_CERT_MAX_DURATION_SECS,
                 );
+
+                // Outlives the restarts of the APIs below. It only starts once the device
+                // is provisioned, which can take longer than the stall timeout.
+                let heartbeat = Heartbeat::new();
+                let _heartbeat_monitor = start_heartbeat_monitor(settings.watchdog(), &heartbeat)?;
+
+                // Likewise for the deployments on the management API, so that one isn't
+                // applied twice over a restart.
+                let trust_key = settings
+                    .manifest_trust_key()
+                    .map(load_manifest_trust_key)
+                    .transpose()?;
+                let (deployments, deployment_status, follow_deployments) = deployment::init(
+                    &runtime,
+                    cache_subdir_path.join(EDGE_DEPLOYMENT_STATE_FILENAME),
+                    settings.watchdog().reconcile_parallelism(),
+                    trust_key,
+                    settings.admission().clone(),
+                );
+                let deployments = deployments
+                    .with_maintenance_windows(settings.watchdog().maintenance().clone());
+                tokio_runtime.spawn(follow_deployments);
+                // In the background, so that a module that is slow to start doesn't hold up
+                // the APIs. The modules that were left running over an upgrade are taken
+                // over rather than started.
+                tokio_runtime.spawn(deployment::start(
+                    &deployments,
+                    settings.watchdog().adopt_existing_modules() || resuming,
+                ));
+
                 // This "do-while" loop runs until a StartApiReturnStatus::Shutdown
                 // is received. If the TLS cert needs a restart, we will loop again.
                 loop {
@@ -374,7 +497,13 @@ where
                         cfg.clone(),
                         $root_key.clone(),
                         make_shutdown_signal(),
+                        load_settings.clone(),
                         &crypto,
+                        &deployments,
+                        &deployment_status,
+                        &upgrade,
+                        &watchdog_pause,
+                        &heartbeat,
                         &mut tokio_runtime,
                     )?;
 
@@ -397,6 +526,12 @@ where
                         break;
                     }
                 }
+
+                // The watchdog has stopped the edge agent by now, and the APIs are down, so
+                // no deployment can start the modules again.
+                tokio_runtime
+                    .block_on(deployment::stop(&deployments, &upgrade))
+                    .unwrap_or(());
             }};
         }
 
@@ -645,21 +780,31 @@ where
     S: RuntimeSettings,
 {
     if let ProvisioningType::External(external) = settings.provisioning().provisioning_type() {
-        // Set the external provisioning endpoint environment variable for use by the custom HSM library.
-        env::set_var(
-            EXTERNAL_PROVISIONING_ENDPOINT_KEY,
-            external.endpoint().as_str(),
-        );
+        let external_provisioning_client = match (external.endpoint(), external.command()) {
+            (Some(endpoint), _) => {
+                // Set the external provisioning endpoint environment variable for use by the custom HSM library.
+                env::set_var(EXTERNAL_PROVISIONING_ENDPOINT_KEY, endpoint.as_str());
 
-        let external_provisioning_client = ExternalProvisioningClient::new(external.endpoint())
-            .context(ErrorKind::Initialize(
-                InitializeErrorReason::ExternalProvisioningClient(
-                    ExternalProvisioningErrorReason::ClientInitialization,
-                ),
-            ))?;
+                info!("Retrieving provisioning information from the external endpoint...");
+                ExternalProvisioningClient::new(endpoint).context(ErrorKind::Initialize(
+                    InitializeErrorReason::ExternalProvisioningClient(
+                        ExternalProvisioningErrorReason::ClientInitialization,
+                    ),
+                ))?
+            }
+            (None, Some(command)) => {
+                info!(
+                    "Retrieving provisioning information from the external command {}...",
+                    command.program().display()
+                );
+                ExternalProvisioningClient::from_command(command)
+            }
+            (None, None) => {
+                unreachable!("external provisioning has either an endpoint or a command")
+            }
+        };
         let external_provisioning = ExternalProvisioning::new(external_provisioning_client);
 
-        info!("Retrieving provisioning information from the external endpoint...");
         let provision_fut = external_provisioning
             .provision(MemoryKeyStore::new())
             .map_err(|err| {
@@ -889,10 +1034,15 @@ where
         prepare_httpclient_and_identity_data_for_x509_provisioning(
             hsm_lock,
             auto_generated_ca_lifetime_seconds,
+            settings.https_proxy(),
         )
     } else {
-        let hyper_client = MaybeProxyClient::new(get_proxy_uri(None)?, None, None)
-            .context(ErrorKind::Initialize(InitializeErrorReason::HttpClient))?;
+        let hyper_client = MaybeProxyClient::new(
+            get_proxy_uri(settings.https_proxy().map(ToString::to_string))?,
+            None,
+            None,
+        )
+        .context(ErrorKind::Initialize(InitializeErrorReason::HttpClient))?;
 
         Ok((hyper_client, None))
     }
@@ -901,6 +1051,7 @@ where
 fn prepare_httpclient_and_identity_data_for_x509_provisioning(
     hsm_lock: Arc<HsmLock>,
     auto_generated_ca_lifetime_seconds: u64,
+    https_proxy: Option<&str>,
 ) -> Result<(MaybeProxyClient, Option<IdentityCertificateData>), Error> {
     info!("Initializing hsm X509 interface...");
     let x509 = X509::new(hsm_lock, auto_generated_ca_lifetime_seconds)
@@ -938,8 +1089,12 @@ fn prepare_httpclient_and_identity_data_for_x509_provisioning(
         InitializeErrorReason::InvalidDeviceCertCredentials,
     ))?;
 
-    let hyper_client = MaybeProxyClient::new(get_proxy_uri(None)?, Some(pem), None)
-        .context(ErrorKind::Initialize(InitializeErrorReason::HttpClient))?;
+    let hyper_client = MaybeProxyClient::new(
+        get_proxy_uri(https_proxy.map(ToString::to_string))?,
+        Some(pem),
+        None,
+    )
+    .context(ErrorKind::Initialize(InitializeErrorReason::HttpClient))?;
 
     let cert_data = IdentityCertificateData {
         common_name,
@@ -1261,6 +1416,7 @@ impl From<serde_json::Error> for DiffError {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn check_settings_state<M, C>(
     subdir: &Path,
     filename: &str,
@@ -1269,6 +1425,7 @@ fn check_settings_state<M, C>(
     crypto: &C,
     tokio_runtime: &mut tokio::runtime::Runtime,
     id_cert_thumbprint: Option<&str>,
+    keep_modules: bool,
 ) -> Result<(), Error>
 where
     M: MakeModuleRuntime + 'static,
@@ -1303,6 +1460,7 @@ where
             crypto,
             tokio_runtime,
             id_cert_thumbprint,
+            keep_modules,
         )?;
     }
     Ok(())
@@ -1343,6 +1501,7 @@ where
     Ok(ProvisioningAuthMethod::SharedAccessKey)
 }
 
+#[allow(clippy::too_many_arguments)]
 fn reconfigure<M, C>(
     subdir: &Path,
     filename: &str,
@@ -1351,6 +1510,7 @@ fn reconfigure<M, C>(
     crypto: &C,
     tokio_runtime: &mut tokio::runtime::Runtime,
     id_cert_thumbprint: Option<&str>,
+    keep_modules: bool,
 ) -> Result<(), Error>
 where
     M: MakeModuleRuntime + 'static,
@@ -1358,13 +1518,17 @@ where
     C: CreateCertificate + GetIssuerAlias + MasterEncryptionKey,
 {
     // Remove all edge containers and destroy the cache (settings and dps backup)
-    info!("Removing all modules...");
-    tokio_runtime
-        .block_on(runtime.remove_all())
-        .context(ErrorKind::Initialize(
-            InitializeErrorReason::RemoveExistingModules,
-        ))?;
-    info!("Finished removing modules.");
+    if keep_modules {
+        info!("Keeping the modules that are resumed from the upgrade.");
+    } else {
+        info!("Removing all modules...");
+        tokio_runtime
+            .block_on(runtime.remove_all())
+            .context(ErrorKind::Initialize(
+                InitializeErrorReason::RemoveExistingModules,
+            ))?;
+        info!("Finished removing modules.");
+    }
 
     // Ignore errors from this operation because we could be recovering from a previous bad
     // configuration and shouldn't stall the current configuration because of that
@@ -1401,7 +1565,13 @@ fn start_api<HC, K, F, C, W, M>(
     workload_config: W,
     root_key: K,
     shutdown_signal: F,
+    load_settings: Option<SettingsLoader<M::Settings>>,
     crypto: &C,
+    deployments: &DeploymentReconciler<M::ModuleRuntime>,
+    deployment_status: &LastDeploymentStatus,
+    upgrade: &UpgradeGuard,
+    watchdog_pause: &WatchdogPause,
+    heartbeat: &Heartbeat,
     tokio_runtime: &mut tokio::runtime::Runtime,
 ) -> Result<(StartApiReturnStatus, bool), Error>
 where
@@ -1421,8 +1591,8 @@ where
     M::ModuleRuntime: Authenticator<Request = Request<Body>> + Send + Sync + Clone + 'static,
     M: MakeModuleRuntime + 'static,
     <<M::ModuleRuntime as ModuleRuntime>::Module as Module>::Config:
-        Clone + DeserializeOwned + Serialize,
-    M::Settings: 'static,
+        Clone + DeserializeOwned + ImageReference + Serialize,
+    M::Settings: 'static + Serialize,
     <M::ModuleRuntime as ModuleRuntime>::Logs: Into<Body>,
     <M::ModuleRuntime as Authenticator>::Error: Fail + Sync,
     for<'r> &'r <M::ModuleRuntime as ModuleRuntime>::Error: Into<ModuleRuntimeErrorReason>,
@@ -1430,27 +1600,40 @@ where
     let hub_name = workload_config.iot_hub_name().to_string();
     let device_id = workload_config.device_id().to_string();
     let hostname = format!("https://{}", hub_name);
-    let token_source = SasTokenSource::new(hub_name.clone(), device_id.clone(), root_key);
+    let upstream = UpstreamEndpoints::new(hub_name.clone(), settings.upstream());
+    if upstream.hostnames().len() > 1 {
+        info!(
+            "IoT Hub {} fails over to {:?}",
+            hub_name,
+            &upstream.hostnames()[1..]
+        );
+    }
+    let token_source = SasTokenSource::new(hub_name.clone(), device_id.clone(), root_key)
+        .with_upstream(upstream.clone());
     let http_client = HttpClient::new(
         hyper_client,
         Some(token_source),
         IOTHUB_API_VERSION.to_string(),
         Url::parse(&hostname).context(ErrorKind::Initialize(InitializeErrorReason::HttpClient))?,
     )
-    .context(ErrorKind::Initialize(InitializeErrorReason::HttpClient))?;
+    .context(ErrorKind::Initialize(InitializeErrorReason::HttpClient))?
+    .with_upstream(upstream.clone());
     let device_client = DeviceClient::new(http_client, device_id.clone())
         .context(ErrorKind::Initialize(InitializeErrorReason::DeviceClient))?;
     let id_man = HubIdentityManager::new(key_store.clone(), device_client);
 
     let (mgmt_tx, mgmt_rx) = oneshot::channel();
     let (mgmt_stop_and_reprovision_tx, mgmt_stop_and_reprovision_rx) = mpsc::unbounded();
+    let (reload_tx, reload_rx) = mpsc::unbounded();
+    let (agent_spec_tx, agent_spec_rx) = mpsc::unbounded();
     let (work_tx, work_rx) = oneshot::channel();
+    let reconcile_timer = ReconcileTimer::new();
 
     let edgelet_cert_props = CertificateProperties::new(
         settings.certificates().auto_generated_ca_lifetime_seconds(),
         IOTEDGED_TLS_COMMONNAME.to_string(),
         CertificateType::Server,
-        "iotedge-tls".to_string(),
+        IOTEDGED_TLS_ALIAS.to_string(),
     )
     .with_issuer(CertificateIssuer::DeviceCa);
 
@@ -1477,6 +1660,12 @@ where
 
     let cert_manager = Arc::new(cert_manager);
 
+    let request_metrics = if settings.listen().enable_metrics() {
+        Some(RequestMetrics::new())
+    } else {
+        None
+    };
+
     let mgmt = start_management::<_, _, _, M>(
         settings,
         runtime,
@@ -1484,6 +1673,14 @@ where
         mgmt_rx,
         cert_manager.clone(),
         mgmt_stop_and_reprovision_tx,
+        reload_tx,
+        deployments,
+        deployment_status,
+        upgrade,
+        watchdog_pause,
+        &upstream,
+        request_metrics.clone(),
+        &reconcile_timer,
     );
 
     let workload = start_workload::<_, _, _, _, M>(
@@ -1494,6 +1691,7 @@ where
         crypto,
         cert_manager,
         workload_config,
+        request_metrics,
     );
 
     let (runt_tx, runt_rx) = oneshot::channel();
@@ -1504,8 +1702,47 @@ where
         &device_id,
         &settings,
         runt_rx,
+        agent_spec_rx,
+        upgrade,
+        watchdog_pause,
+        heartbeat,
+        &reconcile_timer,
     )?;
 
+    // Forwarding runs for as long as the daemon does; nothing waits for it.
+    if let Some(forward) = log_forwarding::start(
+        settings.log_forwarding(),
+        settings.logs().max_line_length(),
+        runtime.clone(),
+    )? {
+        tokio_runtime.spawn(forward);
+    }
+
+    // Likewise for the webhook, whose posts never hold up managing modules.
+    if let Some(notify) =
+        webhook::start(settings.webhook(), settings.https_proxy(), runtime.clone())?
+    {
+        tokio_runtime.spawn(notify);
+    }
+
+    // Reloads end once the watchdog that the specs are sent to has stopped.
+    if let Some(load_settings) = load_settings {
+        let reload_runtime = runtime.clone();
+        let reloads =
+            reload_rx.select(signal::reload()).for_each(move |()| {
+                match reload_agent_spec::<M>(&load_settings, &reload_runtime, &hub_name, &device_id)
+                {
+                    Ok(spec) => agent_spec_tx.unbounded_send(spec).map_err(|_| ()),
+                    Err(err) => {
+                        warn!("Could not reload the config, keeping the current one:");
+                        log_failure(Level::Warn, &err);
+                        Ok(())
+                    }
+                }
+            });
+        tokio_runtime.spawn(reloads);
+    }
+
     // This mpsc sender/receiver is used for getting notifications from the mgmt service
     // indicating that the daemon should shut down and attempt to reprovision the device.
     let mgmt_stop_and_reprovision_signaled = mgmt_stop_and_reprovision_rx
@@ -1572,11 +1809,16 @@ where
 
     let shutdown = shutdown_signal.map(move |_| {
         debug!("shutdown signaled");
+        notify_service_manager("STOPPING=1");
         // Signal the watchdog to shutdown
         runt_tx.send(()).unwrap_or(());
     });
     tokio_runtime.spawn(shutdown);
 
+    // A restart through the service manager waits for this, so that the modules are
+    // adopted before it returns.
+    notify_service_manager("READY=1");
+
     let services = mgmt
         .join4(workload, edge_rt_with_cleanup, expiration_timer)
         .then(|result| match result {
@@ -1587,6 +1829,19 @@ where
     Ok((restart_code, should_reprovision))
 }
 
+// Tells systemd about the state of a unit of `Type=notify`. Other service
+// managers aren't told.
+fn notify_service_manager(state: &str) {
+    #[cfg(target_os = "linux")]
+    {
+        if let Err(err) = systemd::notify(state) {
+            warn!("Could not notify the service manager of {}: {}", state, err);
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = state;
+}
+
 fn init_runtime<M>(
     settings: M::Settings,
     tokio_runtime: &mut tokio::runtime::Runtime,
@@ -1660,6 +1915,10 @@ fn manual_provision_x509(
     tokio_runtime.block_on(provision)
 }
 
+fn dps_retry_policy(retry: &DpsRetry) -> RetryPolicy {
+    RetryPolicy::new(retry.max_attempts(), retry.max_elapsed())
+}
+
 fn dps_x509_provision_init<HC>(
     dps: &Dps,
     hyper_client: HC,
@@ -1692,7 +1951,8 @@ where
     )
     .context(ErrorKind::Initialize(
         InitializeErrorReason::DpsProvisioningClient,
-    ))?;
+    ))?
+    .with_retry_policy(dps_retry_policy(dps.retry()));
 
     Ok((memory_hsm, dps_x509))
 }
@@ -1840,7 +2100,8 @@ where
     )
     .context(ErrorKind::Initialize(
         InitializeErrorReason::DpsProvisioningClient,
-    ))?;
+    ))?
+    .with_retry_policy(dps_retry_policy(provisioning.retry()));
     Ok((memory_hsm, dps))
 }
 
@@ -1918,7 +2179,8 @@ where
     )
     .context(ErrorKind::Initialize(
         InitializeErrorReason::DpsProvisioningClient,
-    ))?;
+    ))?
+    .with_retry_policy(dps_retry_policy(provisioning.retry()));
     Ok((tpm, dps))
 }
 
@@ -1956,6 +2218,7 @@ where
     tokio_runtime.block_on(provision)
 }
 
+#[allow(clippy::too_many_arguments)]
 fn start_runtime<K, HC, M>(
     runtime: M::ModuleRuntime,
     id_man: &HubIdentityManager<DerivedKeyStore<K>, HC, K>,
@@ -1963,6 +2226,13 @@ fn start_runtime<K, HC, M>(
     device_id: &str,
     settings: &M::Settings,
     shutdown: Receiver<()>,
+    agent_spec_updates: mpsc::UnboundedReceiver<
+        ModuleSpec<<M::ModuleRuntime as ModuleRuntime>::Config>,
+    >,
+    upgrade: &UpgradeGuard,
+    watchdog_pause: &WatchdogPause,
+    heartbeat: &Heartbeat,
+    reconcile_timer: &ReconcileTimer,
 ) -> Result<impl Future<Item = (), Error = Error>, Error>
 where
     K: 'static + Sign + Clone + Send + Sync,
@@ -1974,23 +2244,116 @@ where
     <M::ModuleRuntime as ModuleRuntime>::Logs: Into<Body>,
     for<'r> &'r <M::ModuleRuntime as ModuleRuntime>::Error: Into<ModuleRuntimeErrorReason>,
 {
-    let spec = settings.agent().clone();
+    let spec = agent_spec::<M>(settings, hostname, device_id)
+        .context(ErrorKind::Initialize(InitializeErrorReason::EdgeRuntime))?;
+
+    let watchdog = Watchdog::new(
+        runtime,
+        id_man.clone(),
+        settings.watchdog().max_retries(),
+        settings.watchdog().backoff().clone(),
+    )
+    .with_poll_interval(settings.watchdog().poll_interval())
+    .with_unhealthy_restart_threshold(settings.watchdog().unhealthy_restart_threshold())
+    .with_spec_updates(agent_spec_updates)
+    .with_upgrade_guard(upgrade.clone())
+    .with_pause(watchdog_pause.clone())
+    .with_pause_on_error(settings.watchdog().pause_on_error())
+    .with_maintenance_windows(settings.watchdog().maintenance().clone())
+    .with_heartbeat(heartbeat.clone())
+    .with_reconcile_timer(reconcile_timer.clone())
+    .with_module_set_store(ModuleSetStore::new(
+        Path::new(settings.homedir())
+            .join(EDGE_SETTINGS_SUBDIR)
+            .join(EDGE_MODULE_SET_STATE_FILENAME),
+    ));
+    let runtime_future = watchdog
+        .run_until(spec, EDGE_RUNTIME_MODULEID, shutdown.map_err(|_| ()))
+        .map_err(Error::from);
+
+    Ok(runtime_future)
+}
+
+// The key that deployment manifests must be signed with, from the PEM file at
+// `path`. A key that can't be read fails startup rather than applying
+// manifests that aren't verified.
+fn load_manifest_trust_key(path: &Path) -> Result<ManifestTrustKey, Error> {
+    let pem = fs::read(path).context(ErrorKind::Initialize(
+        InitializeErrorReason::ManifestTrustKey,
+    ))?;
+    let trust_key = ManifestTrustKey::from_pem(&pem).context(ErrorKind::Initialize(
+        InitializeErrorReason::ManifestTrustKey,
+    ))?;
+    Ok(trust_key)
+}
+
+// Logs an error once the watchdog hasn't checked the EdgeAgent for the stall
+// timeout, which the service manager can't tell from the daemon being alive.
+// With exit on stall the daemon exits then, for the service manager to
+// restart it.
+fn start_heartbeat_monitor(
+    settings: &WatchdogSettings,
+    heartbeat: &Heartbeat,
+) -> Result<HeartbeatMonitor, Error> {
+    let exit_on_stall = settings.exit_on_stall();
+    let monitor = HeartbeatMonitor::start(
+        heartbeat.clone(),
+        settings.stall_timeout(),
+        move |elapsed| {
+            error!(
+                "The watchdog has not checked the edge runtime for {} seconds and may be stuck",
+                elapsed.as_secs()
+            );
+            if exit_on_stall {
+                error!("Exiting so that the daemon is restarted");
+                process::exit(1);
+            }
+        },
+    )
+    .context(ErrorKind::Initialize(
+        InitializeErrorReason::HeartbeatMonitor,
+    ))?;
+    Ok(monitor)
+}
+
+// The spec of the EdgeAgent, with the environment variables it needs.
+fn agent_spec<M>(
+    settings: &M::Settings,
+    hostname: &str,
+    device_id: &str,
+) -> Result<ModuleSpec<<M::ModuleRuntime as ModuleRuntime>::Config>, edgelet_core::Error>
+where
+    M: MakeModuleRuntime,
+{
+    let spec = settings.agent();
     let env = build_env(spec.env(), hostname, device_id, settings);
-    let spec = ModuleSpec::<<M::ModuleRuntime as ModuleRuntime>::Config>::new(
+    ModuleSpec::new(
         EDGE_RUNTIME_MODULE_NAME.to_string(),
         spec.type_().to_string(),
         spec.config().clone(),
         env,
         spec.image_pull_policy(),
     )
-    .context(ErrorKind::Initialize(InitializeErrorReason::EdgeRuntime))?;
-
-    let watchdog = Watchdog::new(runtime, id_man.clone(), settings.watchdog().max_retries());
-    let runtime_future = watchdog
-        .run_until(spec, EDGE_RUNTIME_MODULEID, shutdown.map_err(|_| ()))
-        .map_err(Error::from);
+}
 
-    Ok(runtime_future)
+// Reads the config again and builds the spec of the EdgeAgent from it, making
+// sure that the runtime would accept it.
+fn reload_agent_spec<M>(
+    load_settings: &SettingsLoader<M::Settings>,
+    runtime: &M::ModuleRuntime,
+    hostname: &str,
+    device_id: &str,
+) -> Result<ModuleSpec<<M::ModuleRuntime as ModuleRuntime>::Config>, Error>
+where
+    M: MakeModuleRuntime,
+{
+    info!("Reloading config...");
+    let settings = load_settings().context(ErrorKind::ReloadSettings)?;
+    let spec =
+        agent_spec::<M>(&settings, hostname, device_id).context(ErrorKind::ReloadSettings)?;
+    runtime.validate(&spec).context(ErrorKind::ReloadSettings)?;
+    info!("Reloaded config");
+    Ok(spec)
 }
 
 // Add the environment variables needed by the EdgeAgent.
@@ -2036,6 +2399,11 @@ where
         EDGE_RUNTIME_MODE_KEY.to_string(),
         EDGE_RUNTIME_MODE.to_string(),
     );
+    // So that the edge agent and the modules it starts, which connect over
+    // AMQP or MQTT over WebSockets, tunnel through the same proxy.
+    if let Some(https_proxy) = settings.https_proxy() {
+        env.insert(HTTPS_PROXY_KEY.to_string(), https_proxy.to_string());
+    }
     for (key, val) in spec_env.iter() {
         env.insert(key.clone(), val.clone());
     }
@@ -2043,6 +2411,7 @@ where
     env
 }
 
+#[allow(clippy::too_many_arguments)]
 fn start_management<C, K, HC, M>(
     settings: &M::Settings,
     runtime: &M::ModuleRuntime,
@@ -2050,48 +2419,200 @@ fn start_management<C, K, HC, M>(
     shutdown: Receiver<()>,
     cert_manager: Arc<CertificateManager<C>>,
     initiate_shutdown_and_reprovision: mpsc::UnboundedSender<()>,
+    initiate_reload: mpsc::UnboundedSender<()>,
+    deployments: &DeploymentReconciler<M::ModuleRuntime>,
+    deployment_status: &LastDeploymentStatus,
+    upgrade: &UpgradeGuard,
+    watchdog_pause: &WatchdogPause,
+    upstream: &UpstreamEndpoints,
+    request_metrics: Option<RequestMetrics>,
+    reconcile_timer: &ReconcileTimer,
 ) -> impl Future<Item = (), Error = Error>
 where
     C: CreateCertificate + Clone,
     K: 'static + Sign + Clone + Send + Sync,
     HC: 'static + ClientImpl + Send + Sync,
     M: MakeModuleRuntime,
+    M::Settings: Serialize,
     M::ModuleRuntime: Authenticator<Request = Request<Body>> + Send + Sync + Clone + 'static,
     <<M::ModuleRuntime as Authenticator>::AuthenticateFuture as Future>::Error: Fail,
     for<'r> &'r <M::ModuleRuntime as ModuleRuntime>::Error: Into<ModuleRuntimeErrorReason>,
-    <<M::ModuleRuntime as ModuleRuntime>::Module as Module>::Config: DeserializeOwned + Serialize,
+    <<M::ModuleRuntime as ModuleRuntime>::Module as Module>::Config:
+        DeserializeOwned + ImageReference + Serialize,
     <M::ModuleRuntime as ModuleRuntime>::Logs: Into<Body>,
 {
     info!("Starting management API...");
 
     let label = "mgmt".to_string();
     let url = settings.listen().management_uri().clone();
+    let socket_group = settings
+        .listen()
+        .management_socket_group()
+        .map(ToString::to_string);
+    let socket_ownership = settings.listen().management_socket().cloned();
+    let client_auth = settings.listen().management_tls().map(|tls| {
+        let mut client_auth = ClientAuth::new(
+            tls.server_cert().to_path_buf(),
+            tls.server_key().to_path_buf(),
+            tls.client_ca().to_path_buf(),
+        );
+        if let Some(crl_file) = tls.crl_file() {
+            client_auth = client_auth.with_crl_file(crl_file.to_path_buf());
+        }
+        if let Some(ocsp) = tls.ocsp() {
+            client_auth = client_auth.with_ocsp_responder(OcspResponder::new(
+                ocsp.responder_uri().clone(),
+                ocsp.on_unreachable(),
+                ocsp.timeout(),
+            ));
+        }
+        client_auth
+    });
+    let allowed_peers = management_allowed_peers(settings.listen());
+    let audit_log = AuditLog::new(settings.audit());
     let min_protocol_version = settings.listen().min_tls_version();
+    let metrics_runtime = runtime.clone();
+    let reconcile_timer = reconcile_timer.clone();
+    let health = settings.health().clone();
+    let health_upstream = upstream.clone();
+    let enable_exec = settings.listen().enable_exec();
+    // Only for support bundles, which redact its secrets
+    let config = serde_json::to_string_pretty(settings).unwrap_or_default();
+
+    ManagementService::new(
+        runtime,
+        id_man,
+        initiate_shutdown_and_reprovision,
+        initiate_reload,
+        config,
+        settings.logs(),
+        deployments,
+        deployment_status,
+        upgrade,
+        watchdog_pause,
+        settings.watchdog().maintenance(),
+        upstream,
+    )
+    .then(move |service| -> Result<_, Error> {
+        let service = service.context(ErrorKind::Initialize(
+            InitializeErrorReason::ManagementService,
+        ))?;
+        let service = service.with_health(&metrics_runtime, &health, &health_upstream);
+        let service = match &request_metrics {
+            Some(request_metrics) => {
+                info!("Serving metrics at /metrics on the management API.");
+                service.with_metrics(&metrics_runtime, request_metrics.clone(), &reconcile_timer)
+            }
+            None => service,
+        };
+        let service = if enable_exec {
+            info!("Running commands in modules is enabled on the management API.");
+            service.with_exec()
+        } else {
+            service
+        };
+        let allowed_peers = allowed_peers.map_err(|err| {
+            err.context(ErrorKind::Initialize(
+                InitializeErrorReason::ManagementService,
+            ))
+        })?;
+        let audit_log = audit_log.map_err(|err| {
+            err.context(ErrorKind::Initialize(
+                InitializeErrorReason::ManagementService,
+            ))
+        })?;
+        let service = PeerAuthorizationService::new(service, allowed_peers);
+        // Outside of the peer check, so that the callers it rejects are
+        // recorded too.
+        let service = AuditService::new(service, audit_log);
+        let mut service = LoggingService::new(label, service);
+        if let Some(request_metrics) = request_metrics {
+            service = service.with_metrics(request_metrics);
+        }
+        let service = RequestIdService::new(service);
 
-    ManagementService::new(runtime, id_man, initiate_shutdown_and_reprovision)
-        .then(move |service| -> Result<_, Error> {
-            let service = service.context(ErrorKind::Initialize(
+        let tls_params = TlsAcceptorParams::new(&cert_manager, min_protocol_version);
+
+        let listener = Listener::parse(&url).map_err(|err| {
+            err.context(ErrorKind::Initialize(
                 InitializeErrorReason::ManagementService,
-            ))?;
-            let service = LoggingService::new(label, service);
+            ))
+        })?;
+        let listener = match socket_group {
+            Some(group) => listener.with_socket_permissions(
+                SocketPermissions::new(MANAGEMENT_SOCKET_MODE).with_group(group),
+            ),
+            None => listener,
+        };
+        let listener = match client_auth {
+            Some(client_auth) => listener.with_client_auth(client_auth),
+            None => listener,
+        };
 
-            let tls_params = TlsAcceptorParams::new(&cert_manager, min_protocol_version);
+        // A client that goes away while following logs closes its connection,
+        // which drops the response and with it the connection to Docker that
+        // the logs are read from. Without this, that only happens when the
+        // module next writes something.
+        let server = Http::new()
+            .http1_half_close(false)
+            .bind_listener(&listener, service, Some(tls_params))
+            .map_err(|err| {
+                err.context(ErrorKind::Initialize(
+                    InitializeErrorReason::ManagementService,
+                ))
+            })?;
+        check_socket(&listener, socket_ownership.as_ref()).map_err(|err| {
+            err.context(ErrorKind::Initialize(
+                InitializeErrorReason::ManagementService,
+            ))
+        })?;
+        let run = server
+            .run_until(shutdown.map_err(|_| ()))
+            .map_err(|err| Error::from(err.context(ErrorKind::ManagementService)));
+        info!("Listening on {} with 1 thread for management API.", url);
+        Ok(run)
+    })
+    .flatten()
+}
 
-            let run = Http::new()
-                .bind_url(url.clone(), service, Some(tls_params))
-                .map_err(|err| {
-                    err.context(ErrorKind::Initialize(
-                        InitializeErrorReason::ManagementService,
-                    ))
-                })?
-                .run_until(shutdown.map_err(|_| ()))
-                .map_err(|err| Error::from(err.context(ErrorKind::ManagementService)));
-            info!("Listening on {} with 1 thread for management API.", url);
-            Ok(run)
-        })
-        .flatten()
+// Only the daemon's own user may use the management API unless others are
+// configured, so that root isn't allowed implicitly.
+fn management_allowed_peers(listen: &Listen) -> Result<AllowedPeers, edgelet_http::Error> {
+    let mut allowed = AllowedPeers::service_account()?;
+    for user in listen.management_allowed_users() {
+        allowed = allowed.with_user(user)?;
+    }
+
+    #[cfg(unix)]
+    {
+        for group in listen.management_allowed_groups() {
+            allowed = allowed.with_group(group)?;
+        }
+    }
+
+    Ok(allowed)
+}
+
+// Checks the Unix domain socket that `listener` bound for the ownership and
+// mode it's expected to have, if any, before anyone connects to it.
+#[cfg_attr(not(unix), allow(unused_variables))]
+fn check_socket(
+    listener: &Listener,
+    expected: Option<&SocketOwnership>,
+) -> Result<(), edgelet_http::Error> {
+    #[cfg(unix)]
+    {
+        if let (edgelet_http::ListenAddress::Unix(path), Some(expected)) =
+            (listener.address(), expected)
+        {
+            return edgelet_http::check_socket_ownership(path, expected);
+        }
+    }
+
+    Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 fn start_workload<K, C, CE, W, M>(
     settings: &M::Settings,
     key_store: &K,
@@ -2100,6 +2621,7 @@ fn start_workload<K, C, CE, W, M>(
     crypto: &C,
     cert_manager: Arc<CertificateManager<CE>>,
     config: W,
+    request_metrics: Option<RequestMetrics>,
 ) -> impl Future<Item = (), Error = Error>
 where
     K: KeyStore + Clone + Send + Sync + 'static,
@@ -2127,30 +2649,105 @@ where
 
     let label = "work".to_string();
     let url = settings.listen().workload_uri().clone();
+    let socket_ownership = settings.listen().workload_socket().cloned();
     let min_protocol_version = settings.listen().min_tls_version();
 
-    WorkloadService::new(key_store, crypto.clone(), runtime, config)
-        .then(move |service| -> Result<_, Error> {
-            let service = service.context(ErrorKind::Initialize(
-                InitializeErrorReason::WorkloadService,
-            ))?;
-            let service = LoggingService::new(label, service);
+    // The workload CA's chain includes the device CA
+    let cert_expiry = CertExpiryMonitor::new(
+        crypto.clone(),
+        vec![
+            IOTEDGED_CA_ALIAS.to_string(),
+            IOTEDGED_TLS_ALIAS.to_string(),
+        ],
+    )
+    .with_warning_window(chrono::Duration::days(i64::from(
+        settings.certificates().expiry_warning_days(),
+    )));
+    let cert_expiry_check = cert_expiry.clone();
+    let check_cert_expiry = Interval::new(
+        Instant::now(),
+        Duration::from_secs(CERT_EXPIRY_CHECK_INTERVAL_SECS),
+    )
+    .map_err(|err| Error::from(err.context(ErrorKind::WorkloadService)))
+    .for_each(move |_| {
+        cert_expiry_check.check();
+        Ok(())
+    });
 
-            let tls_params = TlsAcceptorParams::new(&cert_manager, min_protocol_version);
+    let cert_rotation = ServerCertRotator::new(crypto.clone()).with_renewal_threshold_percent(
+        settings
+            .certificates()
+            .server_cert_renewal_threshold_percent(),
+    );
+    let rotate_server_certs = if settings.certificates().auto_rotate_server_certs() {
+        let cert_rotation = cert_rotation.clone();
+        Either::A(
+            Interval::new(
+                Instant::now(),
+                Duration::from_secs(SERVER_CERT_ROTATION_CHECK_INTERVAL_SECS),
+            )
+            .map_err(|err| Error::from(err.context(ErrorKind::WorkloadService)))
+            .for_each(move |_| {
+                cert_rotation.rotate();
+                Ok(())
+            }),
+        )
+    } else {
+        Either::B(future::empty())
+    };
 
-            let run = Http::new()
-                .bind_url(url.clone(), service, Some(tls_params))
-                .map_err(|err| {
-                    err.context(ErrorKind::Initialize(
-                        InitializeErrorReason::WorkloadService,
-                    ))
-                })?
-                .run_until(shutdown.map_err(|_| ()))
-                .map_err(|err| Error::from(err.context(ErrorKind::WorkloadService)));
-            info!("Listening on {} with 1 thread for workload API.", url);
-            Ok(run)
-        })
-        .flatten()
+    WorkloadService::new(
+        key_store,
+        crypto.clone(),
+        runtime,
+        config,
+        cert_expiry,
+        cert_rotation,
+    )
+    .then(move |service| -> Result<_, Error> {
+        let service = service.context(ErrorKind::Initialize(
+            InitializeErrorReason::WorkloadService,
+        ))?;
+        let mut service = LoggingService::new(label, service);
+        if let Some(request_metrics) = request_metrics {
+            service = service.with_metrics(request_metrics);
+        }
+        let service = RequestIdService::new(service);
+
+        let tls_params = TlsAcceptorParams::new(&cert_manager, min_protocol_version);
+
+        let listener = Listener::parse(&url).map_err(|err| {
+            err.context(ErrorKind::Initialize(
+                InitializeErrorReason::WorkloadService,
+            ))
+        })?;
+        let server = Http::new()
+            .bind_listener(&listener, service, Some(tls_params))
+            .map_err(|err| {
+                err.context(ErrorKind::Initialize(
+                    InitializeErrorReason::WorkloadService,
+                ))
+            })?;
+        check_socket(&listener, socket_ownership.as_ref()).map_err(|err| {
+            err.context(ErrorKind::Initialize(
+                InitializeErrorReason::WorkloadService,
+            ))
+        })?;
+        let run = server
+            .run_until(shutdown.map_err(|_| ()))
+            .map_err(|err| Error::from(err.context(ErrorKind::WorkloadService)));
+        info!("Listening on {} with 1 thread for workload API.", url);
+
+        // The expiry check and certificate rotation never finish on their own,
+        // so this completes with the service
+        let background = check_cert_expiry
+            .select(rotate_server_certs)
+            .map(|_| ())
+            .map_err(|(err, _)| err);
+        let run = run.select(background).map(|_| ()).map_err(|(err, _)| err);
+        Ok(run)
+    })
+    .flatten()
 }
 
 #[cfg(test)]
@@ -2456,6 +3053,7 @@ mod tests {
             &crypto,
             &mut tokio_runtime,
             None,
+            false,
         );
         match result.unwrap_err().kind() {
             ErrorKind::Initialize(InitializeErrorReason::PrepareWorkloadCa) => (),
@@ -2499,6 +3097,7 @@ mod tests {
             &crypto,
             &mut tokio_runtime,
             None,
+            false,
         );
         match result.unwrap_err().kind() {
             ErrorKind::Initialize(InitializeErrorReason::IssuerCAExpiration) => (),
@@ -2541,6 +3140,7 @@ mod tests {
             &crypto,
             &mut tokio_runtime,
             None,
+            false,
         )
         .unwrap();
         let expected = serde_json::to_string(&settings).unwrap();
@@ -2621,6 +3221,7 @@ mod tests {
             &crypto,
             &mut tokio_runtime,
             None,
+            false,
         )
         .unwrap();
         let mut written = String::new();
@@ -2639,6 +3240,7 @@ mod tests {
             &crypto,
             &mut tokio_runtime,
             None,
+            false,
         )
         .unwrap();
         let expected = serde_json::to_string(&settings1).unwrap();
@@ -3477,4 +4079,66 @@ mod tests {
             .join(EDGE_HYBRID_IDENTITY_MASTER_KEY_IV_FILENAME)
             .exists());
     }
+
+    fn reload_runtime(settings: Settings) -> TestRuntime<Error, Settings> {
+        let config = DockerConfig::new(
+            "microsoft/test-image".to_string(),
+            ContainerCreateBody::new(),
+            None,
+        )
+        .unwrap();
+        let module: TestModule<Error, _> = TestModule::new_with_config(
+            "test-module".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default()),
+        );
+        TestRuntime::make_runtime(settings, TestProvisioningResult::new(), TestHsm::default())
+            .wait()
+            .unwrap()
+            .with_module(Ok(module))
+    }
+
+    #[test]
+    fn reload_agent_spec_reads_settings_again() {
+        let settings = Settings::new(Path::new(GOOD_SETTINGS)).unwrap();
+        let runtime = reload_runtime(settings);
+        let load_settings: SettingsLoader<Settings> =
+            Arc::new(|| Ok(Settings::new(Path::new(GOOD_SETTINGS2)).unwrap()));
+
+        let spec = reload_agent_spec::<TestRuntime<_, Settings>>(
+            &load_settings,
+            &runtime,
+            "hub1.azure-devices.net",
+            "device1",
+        )
+        .unwrap();
+
+        assert_eq!(EDGE_RUNTIME_MODULE_NAME, spec.name());
+        assert_eq!("device1", spec.env()[DEVICEID_KEY]);
+        assert_eq!(EDGE_RUNTIME_MODULEID, spec.env()[MODULEID_KEY]);
+    }
+
+    #[test]
+    fn reload_agent_spec_fails_for_invalid_settings() {
+        let settings = Settings::new(Path::new(GOOD_SETTINGS)).unwrap();
+        let runtime = reload_runtime(settings);
+        let load_settings: SettingsLoader<Settings> = Arc::new(|| {
+            Err(super::Error::from(ErrorKind::Initialize(
+                InitializeErrorReason::LoadSettings,
+            )))
+        });
+
+        let err = reload_agent_spec::<TestRuntime<_, Settings>>(
+            &load_settings,
+            &runtime,
+            "hub1.azure-devices.net",
+            "device1",
+        )
+        .unwrap_err();
+
+        match err.kind() {
+            ErrorKind::ReloadSettings => (),
+            kind => panic!("Expected `ReloadSettings` but got {:?}", kind),
+        }
+    }
 }
@@ -0,0 +1,394 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Forwards what modules write to stdout and stderr to the host's journal,
+//! one entry per line with `SYSLOG_IDENTIFIER` set to the module's name, so
+//! that the tools that already read the journal pick up module output next to
+//! the host's own.
+//!
+//! The logs of each module are followed from when forwarding of it started.
+//! The container runtime ends a followed stream when the module stops, so the
+//! module list is checked every poll interval, and the stream of a module that
+//! is in it again, like one that was restarted, is reconnected from where it
+//! ended.
+
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use failure::Fail;
+#[cfg(target_os = "linux")]
+use failure::ResultExt;
+use futures::{Future, Stream};
+#[cfg(target_os = "linux")]
+use log::info;
+use log::{debug, warn, Level};
+use tokio::timer::Interval;
+
+use edgelet_core::{
+    decode_logs_with_max_line_length, LogChunk, LogForwardingSettings, LogOptions, Module,
+    ModuleRuntime,
+};
+use edgelet_utils::log_failure;
+
+use crate::error::{Error, ErrorKind, InitializeErrorReason};
+
+/// The priority of an entry, as syslog numbers them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Priority {
+    /// What a module wrote to stderr.
+    Error = 3,
+    /// What a module wrote to stdout.
+    Info = 6,
+}
+
+/// Where forwarded lines are written.
+pub trait JournalSink {
+    fn send(&self, identifier: &str, priority: Priority, message: &[u8]) -> Result<(), Error>;
+}
+
+#[cfg(target_os = "linux")]
+impl JournalSink for systemd::Journal {
+    fn send(&self, identifier: &str, priority: Priority, message: &[u8]) -> Result<(), Error> {
+        let priority = (priority as u8).to_string();
+        systemd::Journal::send(
+            self,
+            &[
+                ("MESSAGE", message),
+                ("PRIORITY", priority.as_bytes()),
+                ("SYSLOG_IDENTIFIER", identifier.as_bytes()),
+            ],
+        )
+        .with_context(|_| ErrorKind::ForwardLogs(identifier.to_string()))?;
+        Ok(())
+    }
+}
+
+// When the stream of each module in the module list was last followed from,
+// or `None` while it is connected.
+type Followed = Arc<Mutex<HashMap<String, Option<i32>>>>;
+
+/// Starts forwarding as `settings` has it, truncating lines to
+/// `max_line_length` bytes, or returns `None` if forwarding is off. Fails if the journal can't be opened, so that a device whose module
+/// output would go missing doesn't start.
+#[cfg_attr(not(target_os = "linux"), allow(unused_variables))]
+pub fn start<M>(
+    settings: &LogForwardingSettings,
+    max_line_length: usize,
+    runtime: M,
+) -> Result<Option<Box<dyn Future<Item = (), Error = ()> + Send>>, Error>
+where
+    M: 'static + ModuleRuntime + Clone + Send + Sync,
+{
+    match settings {
+        LogForwardingSettings::None => Ok(None),
+        #[cfg(target_os = "linux")]
+        LogForwardingSettings::Journald { poll_interval } => {
+            let journal = systemd::Journal::new()
+                .context(ErrorKind::Initialize(InitializeErrorReason::LogForwarding))?;
+            info!("Forwarding module logs to the journal");
+            Ok(Some(Box::new(forward_logs(
+                runtime,
+                Arc::new(journal),
+                *poll_interval,
+                max_line_length,
+            ))))
+        }
+        #[cfg(not(target_os = "linux"))]
+        LogForwardingSettings::Journald { .. } => Err(Error::from(
+            failure::err_msg("journald is only supported on Linux")
+                .context(ErrorKind::Initialize(InitializeErrorReason::LogForwarding)),
+        )),
+    }
+}
+
+/// Forwards the logs of every module in the module list to `sink`, checking
+/// for modules whose logs aren't being followed every `poll_interval`. Lines
+/// are truncated to `max_line_length` bytes.
+pub fn forward_logs<M, S>(
+    runtime: M,
+    sink: Arc<S>,
+    poll_interval: Duration,
+    max_line_length: usize,
+) -> impl Future<Item = (), Error = ()>
+where
+    M: 'static + ModuleRuntime + Clone + Send + Sync,
+    S: 'static + JournalSink + Send + Sync,
+{
+    let followed = Followed::default();
+    Interval::new(Instant::now(), poll_interval)
+        .map_err(|err| warn!("Log forwarding timer failed: {}", err))
+        .for_each(move |_| {
+            let runtime_copy = runtime.clone();
+            let sink = sink.clone();
+            let followed = followed.clone();
+            runtime.list().then(move |result| {
+                match result {
+                    Ok(modules) => {
+                        for (name, since) in reconnect(&followed, modules.iter().map(Module::name))
+                        {
+                            tokio::spawn(follow(
+                                &runtime_copy,
+                                name,
+                                since,
+                                max_line_length,
+                                sink.clone(),
+                                followed.clone(),
+                            ));
+                        }
+                    }
+                    Err(err) => {
+                        warn!("Could not list modules to forward their logs:");
+                        log_failure(Level::Warn, &err);
+                    }
+                }
+                Ok(())
+            })
+        })
+}
+
+// Marks the listed modules whose streams aren't connected as connected, and
+// returns them with where to follow them from. Modules that are gone are
+// forgotten, so that one created again under the same name is followed from
+// when it was found.
+fn reconnect<'a, I>(followed: &Followed, names: I) -> Vec<(String, i32)>
+where
+    I: Iterator<Item = &'a str>,
+{
+    let names: HashSet<&str> = names.collect();
+    let mut followed = followed.lock().expect("followed modules lock poisoned");
+    followed.retain(|name, since| since.is_none() || names.contains(name.as_str()));
+
+    let now = now();
+    let mut reconnect = vec![];
+    for name in names {
+        let since = match followed.get(name) {
+            Some(None) => continue,
+            Some(Some(since)) => *since,
+            None => now,
+        };
+        followed.insert(name.to_string(), None);
+        reconnect.push((name.to_string(), since));
+    }
+    reconnect
+}
+
+// Follows the logs of module `name` from `since` until the stream ends, and
+// records where the next stream should pick up. A line that can't be written
+// ends the stream too, so that a sink that is down is tried again at the next
+// poll rather than for every line.
+fn follow<M, S>(
+    runtime: &M,
+    name: String,
+    since: i32,
+    max_line_length: usize,
+    sink: Arc<S>,
+    followed: Followed,
+) -> impl Future<Item = (), Error = ()> + Send
+where
+    M: 'static + ModuleRuntime + Send,
+    S: 'static + JournalSink + Send + Sync,
+{
+    debug!("Forwarding the logs of module {}", name);
+    let options = LogOptions::new().with_follow(true).with_since(since);
+    let context = {
+        let name = name.clone();
+        move || ErrorKind::ForwardLogs(name.clone())
+    };
+    let sink_name = name.clone();
+    runtime
+        .logs(&name, &options)
+        .map_err({
+            let context = context.clone();
+            move |err| Error::from(err.context(context()))
+        })
+        .and_then(move |logs| {
+            decode_logs_with_max_line_length(logs, max_line_length)
+                .map_err(move |err| Error::from(err.context(context())))
+                .for_each(move |chunk| write(&*sink, &sink_name, &chunk))
+        })
+        .then(move |result| {
+            if let Err(err) = result {
+                warn!("Stopped forwarding the logs of module {}:", name);
+                log_failure(Level::Warn, &err);
+            }
+            followed
+                .lock()
+                .expect("followed modules lock poisoned")
+                .insert(name, Some(now()));
+            Ok(())
+        })
+}
+
+fn write<S>(sink: &S, name: &str, chunk: &LogChunk) -> Result<(), Error>
+where
+    S: JournalSink + ?Sized,
+{
+    let (priority, bytes) = match chunk {
+        LogChunk::Stdout(bytes) | LogChunk::Unknown(bytes) => (Priority::Info, bytes),
+        LogChunk::Stderr(bytes) => (Priority::Error, bytes),
+        LogChunk::Stdin(_) => return Ok(()),
+    };
+
+    // The container runtime frames each write of the module, which is usually
+    // one line but may be several.
+    for line in bytes.split(|b| *b == b'\n') {
+        let line = if line.ends_with(b"\r") {
+            &line[..line.len() - 1]
+        } else {
+            line
+        };
+        if !line.is_empty() {
+            sink.send(name, priority, line)?;
+        }
+    }
+    Ok(())
+}
+
+fn now() -> i32 {
+    i32::try_from(Utc::now().timestamp()).unwrap_or(i32::max_value())
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::{MakeModuleRuntime, ModuleRuntimeState, DEFAULT_MAX_LOG_LINE_LENGTH};
+    use edgelet_test_utils::crypto::TestHsm;
+    use edgelet_test_utils::module::*;
+
+    use super::*;
+
+    #[derive(Clone, Copy, Debug, Fail)]
+    enum TestError {
+        #[fail(display = "General error")]
+        General,
+    }
+
+    #[derive(Default)]
+    struct TestJournal {
+        entries: Mutex<Vec<(String, Priority, String)>>,
+    }
+
+    impl JournalSink for TestJournal {
+        fn send(&self, identifier: &str, priority: Priority, message: &[u8]) -> Result<(), Error> {
+            self.entries.lock().unwrap().push((
+                identifier.to_string(),
+                priority,
+                String::from_utf8(message.to_vec()).unwrap(),
+            ));
+            Ok(())
+        }
+    }
+
+    fn runtime(
+        module: Result<TestModule<TestError, TestConfig>, TestError>,
+    ) -> TestRuntime<TestError, TestSettings> {
+        TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(module)
+    }
+
+    fn module(logs: Vec<&'static [u8]>) -> TestModule<TestError, TestConfig> {
+        TestModule::new_with_logs(
+            "tempSensor".to_string(),
+            TestConfig::new("microsoft/test-image".to_string()),
+            Ok(ModuleRuntimeState::default()),
+            logs,
+        )
+    }
+
+    #[test]
+    fn lines_are_sent_with_the_module_as_identifier() {
+        let runtime = runtime(Ok(module(vec![
+            b"\x01\x00\x00\x00\x00\x00\x00\x0aone\r\ntwo\n\n",
+            b"\x02\x00\x00\x00\x00\x00\x00\x05oops\n",
+        ])));
+        let journal = Arc::new(TestJournal::default());
+        let followed = Followed::default();
+
+        follow(
+            &runtime,
+            "tempSensor".to_string(),
+            0,
+            DEFAULT_MAX_LOG_LINE_LENGTH,
+            journal.clone(),
+            followed.clone(),
+        )
+        .wait()
+        .unwrap();
+
+        assert_eq!(
+            vec![
+                ("tempSensor".to_string(), Priority::Info, "one".to_string()),
+                ("tempSensor".to_string(), Priority::Info, "two".to_string()),
+                (
+                    "tempSensor".to_string(),
+                    Priority::Error,
+                    "oops".to_string()
+                ),
+            ],
+            *journal.entries.lock().unwrap()
+        );
+        // The next stream picks up from where this one ended.
+        assert!(followed.lock().unwrap()["tempSensor"].is_some());
+    }
+
+    #[test]
+    fn failed_stream_is_reconnected() {
+        let journal = Arc::new(TestJournal::default());
+        let followed = Followed::default();
+
+        follow(
+            &runtime(Err(TestError::General)),
+            "tempSensor".to_string(),
+            0,
+            DEFAULT_MAX_LOG_LINE_LENGTH,
+            journal.clone(),
+            followed.clone(),
+        )
+        .wait()
+        .unwrap();
+
+        assert!(journal.entries.lock().unwrap().is_empty());
+        assert_eq!(
+            vec!["tempSensor".to_string()],
+            reconnect(&followed, vec!["tempSensor"].into_iter())
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn ended_streams_are_reconnected_from_where_they_ended() {
+        let followed = Followed::default();
+        followed
+            .lock()
+            .unwrap()
+            .insert("restarted".to_string(), Some(1000));
+        followed
+            .lock()
+            .unwrap()
+            .insert("removed".to_string(), Some(1000));
+        followed.lock().unwrap().insert("running".to_string(), None);
+
+        let mut reconnected = reconnect(
+            &followed,
+            vec!["restarted", "running", "created"].into_iter(),
+        );
+        reconnected.sort();
+
+        assert_eq!(2, reconnected.len());
+        assert_eq!("created", reconnected[0].0);
+        assert_eq!(("restarted".to_string(), 1000), reconnected[1]);
+        let followed = followed.lock().unwrap();
+        assert_eq!(None, followed["created"]);
+        assert_eq!(None, followed["restarted"]);
+        assert!(!followed.contains_key("removed"));
+    }
+}
@@ -1,14 +1,19 @@
 // Copyright (c) Microsoft. All rights reserved.
 
 use std::env;
-use std::io::Write;
+use std::io::{self, Write};
+use std::str::FromStr;
 
 #[cfg(target_os = "windows")]
 use clap::crate_name;
 
+use edgelet_core::redact_secrets;
+use edgelet_http::RequestId;
 use edgelet_utils::log_failure;
 use env_logger;
-use log::{Level, LevelFilter};
+use env_logger::fmt::Formatter;
+use log::{warn, Level, LevelFilter, Record};
+use serde_json::json;
 #[cfg(target_os = "windows")]
 use win_logger::EventLogger;
 
@@ -17,43 +22,136 @@ use crate::error::Error;
 #[cfg(target_os = "windows")]
 const IOTEDGED_SERVICE_NAME: &str = crate_name!();
 const ENV_LOG: &str = "IOTEDGE_LOG";
+const ENV_LOG_FORMAT: &str = "IOTEDGE_LOG_FORMAT";
+
+/// How log records are written to stdout, selected with the
+/// `IOTEDGE_LOG_FORMAT` environment variable.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogFormat {
+    /// Human-readable lines prefixed with their syslog level. This is the default.
+    Text,
+    /// One JSON object per line, for log aggregation pipelines.
+    Json,
+}
+
+impl Default for LogFormat {
+    fn default() -> Self {
+        LogFormat::Text
+    }
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(s.to_string()),
+        }
+    }
+}
 
 pub fn init() {
-    env_logger::Builder::new()
-        .format(|fmt, record| {
-            let level = match record.level() {
-                Level::Trace => "TRCE",
-                Level::Debug => "DBUG",
-                Level::Info => "INFO",
-                Level::Warn => "WARN",
-                Level::Error => "ERR!",
-            };
-            let timestamp = fmt.timestamp();
-
-            if record.level() >= Level::Debug {
-                writeln!(
-                    fmt,
-                    "<{}>{} [{}] - [{}] {}",
-                    syslog_level(record.level()),
-                    timestamp,
-                    level,
-                    record.target(),
-                    record.args()
-                )
-            } else {
-                writeln!(
-                    fmt,
-                    "<{}>{} [{}] - {}",
-                    syslog_level(record.level()),
-                    timestamp,
-                    level,
-                    record.args()
+    let (format, invalid_format) = match env::var(ENV_LOG_FORMAT) {
+        Ok(value) => match value.parse() {
+            Ok(format) => (format, None),
+            Err(value) => (LogFormat::default(), Some(value)),
+        },
+        Err(_) => (LogFormat::default(), None),
+    };
+
+    let mut builder = env_logger::Builder::new();
+    match format {
+        LogFormat::Text => builder.format(format_text),
+        LogFormat::Json => builder.format(|fmt, record| {
+            writeln!(
+                fmt,
+                "{}",
+                json_line(
+                    &fmt.timestamp().to_string(),
+                    record,
+                    RequestId::current().as_ref()
                 )
-            }
-        })
+            )
+        }),
+    };
+    builder
         .filter_level(LevelFilter::Info)
         .parse(&env::var(ENV_LOG).unwrap_or_default())
         .init();
+
+    if let Some(value) = invalid_format {
+        warn!(
+            "Unknown log format {:?} in {}, using text instead",
+            value, ENV_LOG_FORMAT
+        );
+    }
+}
+
+fn format_text(fmt: &mut Formatter, record: &Record<'_>) -> io::Result<()> {
+    let level = match record.level() {
+        Level::Trace => "TRCE",
+        Level::Debug => "DBUG",
+        Level::Info => "INFO",
+        Level::Warn => "WARN",
+        Level::Error => "ERR!",
+    };
+    let timestamp = fmt.timestamp();
+    let request_id =
+        RequestId::current().map_or_else(String::new, |id| format!(" request_id({})", id));
+    let message = redact_secrets(&record.args().to_string());
+
+    if record.level() >= Level::Debug {
+        writeln!(
+            fmt,
+            "<{}>{} [{}] - [{}] {}{}",
+            syslog_level(record.level()),
+            timestamp,
+            level,
+            record.target(),
+            message,
+            request_id
+        )
+    } else {
+        writeln!(
+            fmt,
+            "<{}>{} [{}] - {}{}",
+            syslog_level(record.level()),
+            timestamp,
+            level,
+            message,
+            request_id
+        )
+    }
+}
+
+// serde_json escapes newlines, so a multi-line message like an error chain
+// stays on one line. Secrets are redacted from the message before it is
+// escaped.
+fn json_line(timestamp: &str, record: &Record<'_>, request_id: Option<&RequestId>) -> String {
+    let mut line = json!({
+        "timestamp": timestamp,
+        "level": record.level().to_string(),
+        "target": record.target(),
+        "message": redact_secrets(&record.args().to_string()),
+    });
+
+    let fields = line.as_object_mut().expect("line is an object");
+    if let Some(module_path) = record.module_path() {
+        fields.insert("module_path".to_string(), json!(module_path));
+    }
+    if let Some(file) = record.file() {
+        fields.insert("file".to_string(), json!(file));
+    }
+    if let Some(number) = record.line() {
+        fields.insert("line".to_string(), json!(number));
+    }
+    if let Some(request_id) = request_id {
+        fields.insert("request_id".to_string(), json!(request_id.as_str()));
+    }
+
+    line.to_string()
 }
 
 #[cfg(target_os = "windows")]
@@ -81,3 +179,108 @@ fn syslog_level(level: Level) -> i8 {
 pub fn log_error(error: &Error) {
     log_failure(Level::Error, error);
 }
+
+#[cfg(test)]
+mod tests {
+    use log::Record;
+    use serde_json::Value;
+
+    use super::*;
+
+    #[test]
+    fn log_format_from_str() {
+        assert_eq!(LogFormat::Text, "text".parse().unwrap());
+        assert_eq!(LogFormat::Json, "JSON".parse().unwrap());
+        assert!("xml".parse::<LogFormat>().is_err());
+    }
+
+    #[test]
+    fn json_line_is_valid_json() {
+        let messages = [
+            "Starting Azure IoT Edge Security Daemon".to_string(),
+            "Could not start module\n\tcaused by: \"quoted\" error".to_string(),
+        ];
+
+        for message in &messages {
+            let line = json_line(
+                "2019-01-01T00:00:00Z",
+                &Record::builder()
+                    .args(format_args!("{}", message))
+                    .level(Level::Warn)
+                    .target("edgelet_core")
+                    .module_path(Some("edgelet_core::module"))
+                    .file(Some("edgelet-core/src/module.rs"))
+                    .line(Some(42))
+                    .build(),
+                None,
+            );
+
+            assert!(!line.contains('\n'));
+            let value: Value = serde_json::from_str(&line).unwrap();
+            assert_eq!("2019-01-01T00:00:00Z", value["timestamp"]);
+            assert_eq!("WARN", value["level"]);
+            assert_eq!("edgelet_core", value["target"]);
+            assert_eq!(message.as_str(), value["message"]);
+            assert_eq!("edgelet_core::module", value["module_path"]);
+            assert_eq!(42, value["line"]);
+        }
+    }
+
+    #[test]
+    fn json_line_redacts_secrets() {
+        let line = json_line(
+            "2019-01-01T00:00:00Z",
+            &Record::builder()
+                .args(format_args!(
+                    "Could not pull image\n\tcaused by: invalid create options {}",
+                    r#"{"auth":{"username":"user","password":"hunter2"}}"#
+                ))
+                .level(Level::Warn)
+                .target("edgelet_docker")
+                .build(),
+            None,
+        );
+
+        assert!(!line.contains("hunter2"));
+        let value: Value = serde_json::from_str(&line).unwrap();
+        assert!(value["message"]
+            .as_str()
+            .unwrap()
+            .ends_with(r#"{"auth":{"username":"user","password":"***"}}"#));
+    }
+
+    #[test]
+    fn json_line_omits_missing_fields() {
+        let line = json_line(
+            "2019-01-01T00:00:00Z",
+            &Record::builder()
+                .args(format_args!("message"))
+                .level(Level::Info)
+                .target("iotedged")
+                .build(),
+            None,
+        );
+
+        let value: Value = serde_json::from_str(&line).unwrap();
+        let fields = value.as_object().unwrap();
+        assert_eq!(4, fields.len());
+        assert!(!fields.contains_key("file"));
+    }
+
+    #[test]
+    fn json_line_includes_request_id() {
+        let request_id = RequestId::new();
+        let line = json_line(
+            "2019-01-01T00:00:00Z",
+            &Record::builder()
+                .args(format_args!("message"))
+                .level(Level::Info)
+                .target("edgelet_http_mgmt")
+                .build(),
+            Some(&request_id),
+        );
+
+        let value: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(request_id.as_str(), value["request_id"]);
+    }
+}
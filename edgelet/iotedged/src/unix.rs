@@ -13,8 +13,9 @@ type ModuleRuntime = edgelet_kube::KubeModuleRuntime<
 >;
 
 pub fn run() -> Result<(), Error> {
-    let settings = app::init()?;
-    let main = super::Main::<ModuleRuntime>::new(settings);
+    let (settings, config_file) = app::init()?;
+    let main = super::Main::<ModuleRuntime>::new(settings)
+        .with_settings_loader(move || app::load_settings(&config_file));
 
     main.run_until(signal::shutdown)?;
     Ok(())
@@ -0,0 +1,543 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Posts to a webhook, like a Slack incoming webhook, when a module fails or
+//! keeps restarting, so that someone hears about it without watching the
+//! device.
+//!
+//! The module list is checked every poll interval. A module is reported once
+//! when it enters the failed state and once when its restart count reaches the
+//! threshold, and again only after it has recovered or its count was reset,
+//! like when the module is recreated. A post that fails is retried with
+//! backoff and dropped with a warning once it runs out of retries, since
+//! managing the modules doesn't wait for it.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use failure::{Fail, ResultExt};
+use futures::future::{self, Either, Loop};
+use futures::{Future, IntoFuture, Stream};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Method, Request};
+use log::{info, warn, Level};
+use tokio::timer::{Delay, Interval, Timeout};
+
+use edgelet_core::{Module, ModuleRuntime, ModuleRuntimeState, ModuleStatus, WebhookSettings};
+use edgelet_http::client::ClientImpl;
+use edgelet_http::MaybeProxyClient;
+use edgelet_utils::log_failure;
+
+use crate::error::{Error, ErrorKind, InitializeErrorReason};
+use crate::get_proxy_uri;
+
+#[derive(Clone, Copy, Debug, PartialEq, serde_derive::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationEvent {
+    /// The module entered the failed state.
+    Failed,
+    /// The module has restarted as many times as the restart threshold.
+    RestartThresholdExceeded,
+}
+
+/// What is posted to the webhook, as JSON.
+#[derive(Clone, Debug, PartialEq, serde_derive::Serialize)]
+pub struct Notification {
+    module: String,
+    event: NotificationEvent,
+    restart_count: u32,
+    last_error: Option<String>,
+    timestamp: DateTime<Utc>,
+    /// A summary of the rest, which is what chat services like Slack show.
+    text: String,
+}
+
+impl Notification {
+    pub fn new(
+        module: String,
+        event: NotificationEvent,
+        restart_count: u32,
+        last_error: Option<String>,
+        timestamp: DateTime<Utc>,
+    ) -> Self {
+        let what = match event {
+            NotificationEvent::Failed => "failed".to_string(),
+            NotificationEvent::RestartThresholdExceeded => {
+                format!("has restarted {} times", restart_count)
+            }
+        };
+        let text = match &last_error {
+            Some(last_error) => format!("Module {} {}: {}", module, what, last_error),
+            None => format!("Module {} {}", module, what),
+        };
+
+        Notification {
+            module,
+            event,
+            restart_count,
+            last_error,
+            timestamp,
+            text,
+        }
+    }
+
+    pub fn module(&self) -> &str {
+        &self.module
+    }
+
+    pub fn event(&self) -> NotificationEvent {
+        self.event
+    }
+}
+
+// What was seen of a module when the module list was last checked.
+#[derive(Clone, Copy, Debug, Default)]
+struct Observed {
+    failed: bool,
+    restart_count: u32,
+}
+
+type Seen = Arc<Mutex<HashMap<String, Observed>>>;
+
+/// Starts notifying the webhook of `settings`, or returns `None` if there is
+/// none. Posts go through `https_proxy` like the daemon's other requests.
+pub fn start<M>(
+    settings: Option<&WebhookSettings>,
+    https_proxy: Option<&str>,
+    runtime: M,
+) -> Result<Option<Box<dyn Future<Item = (), Error = ()> + Send>>, Error>
+where
+    M: 'static + ModuleRuntime + Clone + Send + Sync,
+{
+    match settings {
+        None => Ok(None),
+        Some(settings) => {
+            let client = MaybeProxyClient::new(
+                get_proxy_uri(https_proxy.map(ToString::to_string))?,
+                None,
+                None,
+            )
+            .context(ErrorKind::Initialize(InitializeErrorReason::HttpClient))?;
+
+            // The URL of a webhook is often its secret, so it isn't logged.
+            info!("Notifying the webhook when modules fail");
+            Ok(Some(Box::new(notify(
+                runtime,
+                Arc::new(client),
+                settings.clone(),
+            ))))
+        }
+    }
+}
+
+/// Checks the modules every poll interval of `settings`, and posts what
+/// happened to them to its webhook through `client`.
+pub fn notify<M, C>(
+    runtime: M,
+    client: Arc<C>,
+    settings: WebhookSettings,
+) -> impl Future<Item = (), Error = ()>
+where
+    M: 'static + ModuleRuntime + Clone + Send + Sync,
+    C: 'static + ClientImpl,
+{
+    let seen = Seen::default();
+    let poll_interval = settings.poll_interval();
+    let settings = Arc::new(settings);
+    Interval::new(Instant::now(), poll_interval)
+        .map_err(|err| warn!("Webhook timer failed: {}", err))
+        .for_each(move |_| {
+            let client = client.clone();
+            let settings = settings.clone();
+            let seen = seen.clone();
+            runtime
+                .list_with_details()
+                .map(|(module, state)| (module.name().to_string(), state))
+                .collect()
+                .then(move |result| {
+                    match result {
+                        Ok(modules) => {
+                            let notifications = observe(
+                                &mut seen.lock().expect("seen modules lock poisoned"),
+                                &settings,
+                                &modules,
+                                Utc::now(),
+                            );
+                            for notification in notifications {
+                                info!("Notifying the webhook: {}", notification.text);
+                                let module = notification.module.clone();
+                                tokio::spawn(
+                                    post(client.clone(), &settings, &notification).map_err(
+                                        move |err| {
+                                            warn!(
+                                                "Could not notify the webhook about module {}:",
+                                                module
+                                            );
+                                            log_failure(Level::Warn, &err);
+                                        },
+                                    ),
+                                );
+                            }
+                        }
+                        Err(err) => {
+                            warn!("Could not list modules to notify the webhook about:");
+                            log_failure(Level::Warn, &err);
+                        }
+                    }
+                    Ok(())
+                })
+        })
+}
+
+// Records the state of the listed modules, and returns what is to be reported
+// about them. Modules that are gone are forgotten.
+fn observe(
+    seen: &mut HashMap<String, Observed>,
+    settings: &WebhookSettings,
+    modules: &[(String, ModuleRuntimeState)],
+    now: DateTime<Utc>,
+) -> Vec<Notification> {
+    seen.retain(|name, _| modules.iter().any(|(module, _)| module == name));
+
+    let threshold = settings.restart_threshold();
+    let mut notifications = vec![];
+    for (name, state) in modules.iter().filter(|(name, _)| settings.reports(name)) {
+        let observed = Observed {
+            failed: *state.status() == ModuleStatus::Failed,
+            restart_count: state.restart_count().unwrap_or(0),
+        };
+        let last = seen.insert(name.clone(), observed).unwrap_or_default();
+
+        let mut report = |event| {
+            let last_error = state
+                .last_error()
+                .map(|err| err.message().to_string())
+                .or_else(|| state.status_description().map(ToString::to_string));
+            notifications.push(Notification::new(
+                name.clone(),
+                event,
+                observed.restart_count,
+                last_error,
+                now,
+            ));
+        };
+        if observed.failed && !last.failed {
+            report(NotificationEvent::Failed);
+        }
+        if threshold > 0 && observed.restart_count >= threshold && last.restart_count < threshold {
+            report(NotificationEvent::RestartThresholdExceeded);
+        }
+    }
+    notifications
+}
+
+/// Posts `notification` to the webhook of `settings`, retrying as it says.
+pub fn post<C>(
+    client: Arc<C>,
+    settings: &WebhookSettings,
+    notification: &Notification,
+) -> impl Future<Item = (), Error = Error> + Send
+where
+    C: 'static + ClientImpl,
+{
+    let module = notification.module.clone();
+    let url = settings.url().to_string();
+    let timeout = settings.timeout();
+    let retries = settings.retries();
+    let backoff = settings.backoff();
+
+    serde_json::to_vec(notification)
+        .with_context(|_| ErrorKind::NotifyWebhook(module.clone()))
+        .map_err(Error::from)
+        .into_future()
+        .and_then(move |body| {
+            future::loop_fn((0, backoff), move |(attempt, delay)| {
+                let module = module.clone();
+                send(&*client, &url, body.clone(), timeout, &module).then(
+                    move |result| match result {
+                        Ok(()) => Either::A(future::ok(Loop::Break(()))),
+                        Err(err) if attempt < retries => {
+                            warn!(
+                                "Could not notify the webhook about module {}, retrying in {:?}:",
+                                module, delay
+                            );
+                            log_failure(Level::Warn, &err);
+                            Either::B(
+                                Delay::new(Instant::now() + delay)
+                                    .then(move |_| Ok(Loop::Continue((attempt + 1, delay * 2)))),
+                            )
+                        }
+                        Err(err) => Either::A(future::err(err)),
+                    },
+                )
+            })
+        })
+}
+
+// One attempt at a post, which succeeds if the webhook answers with a success
+// status within `timeout`.
+fn send<C>(
+    client: &C,
+    url: &str,
+    body: Vec<u8>,
+    timeout: Duration,
+    module: &str,
+) -> impl Future<Item = (), Error = Error> + Send
+where
+    C: ClientImpl,
+{
+    let context = {
+        let module = module.to_string();
+        move || ErrorKind::NotifyWebhook(module.clone())
+    };
+
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(url)
+        .header(CONTENT_TYPE, "application/json")
+        .header(CONTENT_LENGTH, body.len().to_string().as_str())
+        .body(Body::from(body))
+        .with_context(|_| context());
+    match request {
+        Ok(request) => Either::A(Timeout::new(client.call(request), timeout).then(
+            move |result| -> Result<(), Error> {
+                let response = result.map_err(|err| Error::from(err.context(context())))?;
+                let status = response.status();
+                if status.is_success() {
+                    Ok(())
+                } else {
+                    Err(Error::from(
+                        failure::err_msg(format!("The webhook answered {}", status))
+                            .context(context()),
+                    ))
+                }
+            },
+        )),
+        Err(err) => Either::B(future::err(Error::from(err))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use chrono::TimeZone;
+    use hyper::{Client, Response, StatusCode};
+    use tokio::runtime::current_thread::Runtime;
+    use url::Url;
+
+    use edgelet_core::{ModuleError, ModuleErrorCode};
+    use edgelet_test_utils::run_tcp_server;
+
+    use super::*;
+
+    fn state(status: ModuleStatus, restart_count: u32) -> ModuleRuntimeState {
+        ModuleRuntimeState::default()
+            .with_status(status)
+            .with_restart_count(Some(restart_count))
+    }
+
+    fn settings(url: &str) -> WebhookSettings {
+        WebhookSettings::new(Url::parse(url).unwrap())
+            .with_restart_threshold(3)
+            .with_backoff(Duration::from_millis(10))
+    }
+
+    fn events(notifications: &[Notification]) -> Vec<(&str, NotificationEvent)> {
+        notifications
+            .iter()
+            .map(|notification| (notification.module(), notification.event()))
+            .collect()
+    }
+
+    // A webhook that answers the requests it gets with `statuses` in turn,
+    // and with the last one after that, recording their bodies.
+    fn webhook(
+        runtime: &mut Runtime,
+        statuses: Vec<StatusCode>,
+    ) -> (String, Arc<Mutex<Vec<serde_json::Value>>>) {
+        let received = Arc::new(Mutex::new(vec![]));
+        let requests = Arc::new(AtomicUsize::new(0));
+
+        let bodies = received.clone();
+        let handler = move |req: Request<Body>| {
+            assert_eq!(Method::POST, *req.method());
+            assert_eq!("/hooks/edge", req.uri().path());
+            assert_eq!("application/json", req.headers()[CONTENT_TYPE]);
+
+            let request = requests.fetch_add(1, Ordering::SeqCst);
+            let status = *statuses
+                .get(request)
+                .unwrap_or_else(|| statuses.last().unwrap());
+            let bodies = bodies.clone();
+            req.into_body().concat2().map(move |body| {
+                bodies
+                    .lock()
+                    .unwrap()
+                    .push(serde_json::from_slice(&body).unwrap());
+                Response::builder()
+                    .status(status)
+                    .body(Body::empty())
+                    .unwrap()
+            })
+        };
+        let (server, port) = run_tcp_server("127.0.0.1", handler);
+        runtime.spawn(server.map_err(|err| panic!("{}", err)));
+
+        (format!("http://localhost:{}/hooks/edge", port), received)
+    }
+
+    fn notification() -> Notification {
+        Notification::new(
+            "tempSensor".to_string(),
+            NotificationEvent::RestartThresholdExceeded,
+            3,
+            Some("exited with code 139".to_string()),
+            Utc.ymd(2020, 1, 6).and_hms(12, 0, 0),
+        )
+    }
+
+    #[test]
+    fn failure_is_reported_once() {
+        let settings = settings("https://hooks.example.com/edge");
+        let mut seen = HashMap::new();
+        let now = Utc::now();
+
+        let failed = vec![(
+            "tempSensor".to_string(),
+            state(ModuleStatus::Failed, 0).with_last_error(Some(ModuleError::new(
+                ModuleErrorCode::NonZeroExit,
+                "exited with code 139".to_string(),
+                now,
+            ))),
+        )];
+        let notifications = observe(&mut seen, &settings, &failed, now);
+        assert_eq!(
+            vec![("tempSensor", NotificationEvent::Failed)],
+            events(&notifications)
+        );
+        assert_eq!(
+            Some("exited with code 139".to_string()),
+            notifications[0].last_error
+        );
+        assert!(observe(&mut seen, &settings, &failed, now).is_empty());
+
+        // Failing again after recovering is reported again.
+        let running = vec![("tempSensor".to_string(), state(ModuleStatus::Running, 0))];
+        assert!(observe(&mut seen, &settings, &running, now).is_empty());
+        assert_eq!(1, observe(&mut seen, &settings, &failed, now).len());
+    }
+
+    #[test]
+    fn restart_threshold_is_reported_when_it_is_reached() {
+        let settings = settings("https://hooks.example.com/edge");
+        let mut seen = HashMap::new();
+        let now = Utc::now();
+        let restarted = |restart_count| {
+            vec![(
+                "tempSensor".to_string(),
+                state(ModuleStatus::Running, restart_count),
+            )]
+        };
+
+        assert!(observe(&mut seen, &settings, &restarted(2), now).is_empty());
+        let notifications = observe(&mut seen, &settings, &restarted(3), now);
+        assert_eq!(
+            vec![("tempSensor", NotificationEvent::RestartThresholdExceeded)],
+            events(&notifications)
+        );
+        assert_eq!(3, notifications[0].restart_count);
+        assert!(observe(&mut seen, &settings, &restarted(4), now).is_empty());
+
+        // A module that was recreated starts counting from zero again.
+        assert!(observe(&mut seen, &settings, &[], now).is_empty());
+        assert!(observe(&mut seen, &settings, &restarted(0), now).is_empty());
+        assert_eq!(1, observe(&mut seen, &settings, &restarted(3), now).len());
+    }
+
+    #[test]
+    fn only_listed_modules_are_reported() {
+        let settings =
+            settings("https://hooks.example.com/edge").with_modules(vec!["edgeHub".to_string()]);
+        let mut seen = HashMap::new();
+        let modules = vec![
+            ("edgeHub".to_string(), state(ModuleStatus::Failed, 0)),
+            ("tempSensor".to_string(), state(ModuleStatus::Failed, 0)),
+        ];
+
+        assert_eq!(
+            vec![("edgeHub", NotificationEvent::Failed)],
+            events(&observe(&mut seen, &settings, &modules, Utc::now()))
+        );
+    }
+
+    #[test]
+    fn notification_is_posted_as_json() {
+        let mut runtime = Runtime::new().unwrap();
+        let (url, received) = webhook(&mut runtime, vec![StatusCode::OK]);
+
+        runtime
+            .block_on(post(
+                Arc::new(Client::new()),
+                &settings(&url),
+                &notification(),
+            ))
+            .unwrap();
+
+        assert_eq!(
+            vec![serde_json::json!({
+                "module": "tempSensor",
+                "event": "restart_threshold_exceeded",
+                "restart_count": 3,
+                "last_error": "exited with code 139",
+                "timestamp": "2020-01-06T12:00:00Z",
+                "text": "Module tempSensor has restarted 3 times: exited with code 139",
+            })],
+            *received.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn failed_post_is_retried() {
+        let mut runtime = Runtime::new().unwrap();
+        let (url, received) = webhook(
+            &mut runtime,
+            vec![
+                StatusCode::INTERNAL_SERVER_ERROR,
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::NO_CONTENT,
+            ],
+        );
+
+        runtime
+            .block_on(post(
+                Arc::new(Client::new()),
+                &settings(&url).with_retries(2),
+                &notification(),
+            ))
+            .unwrap();
+
+        assert_eq!(3, received.lock().unwrap().len());
+    }
+
+    #[test]
+    fn post_is_dropped_once_it_runs_out_of_retries() {
+        let mut runtime = Runtime::new().unwrap();
+        let (url, received) = webhook(&mut runtime, vec![StatusCode::INTERNAL_SERVER_ERROR]);
+
+        let err = runtime
+            .block_on(post(
+                Arc::new(Client::new()),
+                &settings(&url).with_retries(1),
+                &notification(),
+            ))
+            .unwrap_err();
+
+        assert_eq!(
+            &ErrorKind::NotifyWebhook("tempSensor".to_string()),
+            err.kind()
+        );
+        assert_eq!(2, received.lock().unwrap().len());
+    }
+}
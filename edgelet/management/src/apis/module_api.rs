@@ -57,6 +57,8 @@ pub trait ModuleApi: Send + Sync {
         follow: bool,
         tail: &str,
         since: i32,
+        until: Option<i32>,
+        timestamps: bool,
     ) -> Box<dyn Future<Item = hyper::Body, Error = Error<serde_json::Value>> + Send>;
     fn restart_module(
         &self,
@@ -327,17 +329,24 @@ where
         follow: bool,
         tail: &str,
         since: i32,
+        until: Option<i32>,
+        timestamps: bool,
     ) -> Box<dyn Future<Item = hyper::Body, Error = Error<serde_json::Value>> + Send> {
         let configuration: &configuration::Configuration<C> = self.configuration.borrow();
 
         let method = hyper::Method::GET;
 
-        let query = ::url::form_urlencoded::Serializer::new(String::new())
+        let mut query = ::url::form_urlencoded::Serializer::new(String::new());
+        query
             .append_pair("api-version", &api_version.to_string())
             .append_pair("follow", &follow.to_string())
             .append_pair("tail", &tail.to_string())
             .append_pair("since", &since.to_string())
-            .finish();
+            .append_pair("timestamps", &timestamps.to_string());
+        if let Some(until) = until {
+            query.append_pair("until", &until.to_string());
+        }
+        let query = query.finish();
         let uri_str = format!(
             "/modules/{name}/logs?{}",
             query,
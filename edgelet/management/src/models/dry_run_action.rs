@@ -0,0 +1,79 @@
+/*
+ * IoT Edge Management API
+ *
+ * No description provided (generated by Swagger Codegen https://github.com/swagger-api/swagger-codegen)
+ *
+ * OpenAPI spec version: 2019-11-05
+ *
+ * Generated by: https://github.com/swagger-api/swagger-codegen.git
+ */
+
+use serde_derive::{Deserialize, Serialize};
+#[allow(unused_imports)]
+use serde_json::Value;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DryRunAction {
+    /// The name of the module.
+    #[serde(rename = "name")]
+    name: String,
+    /// The action that would be taken: create, update, remove, restart or no-op.
+    #[serde(rename = "action")]
+    action: String,
+    /// Why the module would be restarted.
+    #[serde(rename = "reasons", skip_serializing_if = "Option::is_none")]
+    reasons: Option<Vec<String>>,
+}
+
+impl DryRunAction {
+    pub fn new(name: String, action: String) -> Self {
+        DryRunAction {
+            name,
+            action,
+            reasons: None,
+        }
+    }
+
+    pub fn set_name(&mut self, name: String) {
+        self.name = name;
+    }
+
+    pub fn with_name(mut self, name: String) -> Self {
+        self.name = name;
+        self
+    }
+
+    pub fn name(&self) -> &String {
+        &self.name
+    }
+
+    pub fn set_action(&mut self, action: String) {
+        self.action = action;
+    }
+
+    pub fn with_action(mut self, action: String) -> Self {
+        self.action = action;
+        self
+    }
+
+    pub fn action(&self) -> &String {
+        &self.action
+    }
+
+    pub fn set_reasons(&mut self, reasons: Vec<String>) {
+        self.reasons = Some(reasons);
+    }
+
+    pub fn with_reasons(mut self, reasons: Vec<String>) -> Self {
+        self.reasons = Some(reasons);
+        self
+    }
+
+    pub fn reasons(&self) -> Option<&[String]> {
+        self.reasons.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_reasons(&mut self) {
+        self.reasons = None;
+    }
+}
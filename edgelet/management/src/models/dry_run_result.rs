@@ -0,0 +1,38 @@
+/*
+ * IoT Edge Management API
+ *
+ * No description provided (generated by Swagger Codegen https://github.com/swagger-api/swagger-codegen)
+ *
+ * OpenAPI spec version: 2019-11-05
+ *
+ * Generated by: https://github.com/swagger-api/swagger-codegen.git
+ */
+
+use serde_derive::{Deserialize, Serialize};
+#[allow(unused_imports)]
+use serde_json::Value;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DryRunResult {
+    #[serde(rename = "modules")]
+    modules: Vec<crate::models::DryRunAction>,
+}
+
+impl DryRunResult {
+    pub fn new(modules: Vec<crate::models::DryRunAction>) -> Self {
+        DryRunResult { modules }
+    }
+
+    pub fn set_modules(&mut self, modules: Vec<crate::models::DryRunAction>) {
+        self.modules = modules;
+    }
+
+    pub fn with_modules(mut self, modules: Vec<crate::models::DryRunAction>) -> Self {
+        self.modules = modules;
+        self
+    }
+
+    pub fn modules(&self) -> &[crate::models::DryRunAction] {
+        &self.modules
+    }
+}
@@ -16,11 +16,16 @@ use serde_json::Value;
 pub struct ErrorResponse {
     #[serde(rename = "message")]
     message: String,
+    #[serde(rename = "code", skip_serializing_if = "Option::is_none")]
+    code: Option<String>,
 }
 
 impl ErrorResponse {
     pub fn new(message: String) -> Self {
-        ErrorResponse { message }
+        ErrorResponse {
+            message,
+            code: None,
+        }
     }
 
     pub fn set_message(&mut self, message: String) {
@@ -35,4 +40,21 @@ impl ErrorResponse {
     pub fn message(&self) -> &String {
         &self.message
     }
+
+    pub fn set_code(&mut self, code: String) {
+        self.code = Some(code);
+    }
+
+    pub fn with_code(mut self, code: String) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    pub fn code(&self) -> Option<&String> {
+        self.code.as_ref()
+    }
+
+    pub fn reset_code(&mut self) {
+        self.code = None;
+    }
 }
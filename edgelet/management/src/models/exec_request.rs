@@ -0,0 +1,59 @@
+/*
+ * IoT Edge Management API
+ *
+ * No description provided (generated by Swagger Codegen https://github.com/swagger-api/swagger-codegen)
+ *
+ * OpenAPI spec version: 2019-11-05
+ *
+ * Generated by: https://github.com/swagger-api/swagger-codegen.git
+ */
+
+use serde_derive::{Deserialize, Serialize};
+#[allow(unused_imports)]
+use serde_json::Value;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ExecRequest {
+    /// The command to run and its arguments.
+    #[serde(rename = "cmd")]
+    cmd: Vec<String>,
+    /// Environment variables set for the command, in addition to the module's.
+    #[serde(rename = "env", skip_serializing_if = "Option::is_none")]
+    env: Option<Vec<crate::models::EnvVar>>,
+}
+
+impl ExecRequest {
+    pub fn new(cmd: Vec<String>) -> Self {
+        ExecRequest { cmd, env: None }
+    }
+
+    pub fn set_cmd(&mut self, cmd: Vec<String>) {
+        self.cmd = cmd;
+    }
+
+    pub fn with_cmd(mut self, cmd: Vec<String>) -> Self {
+        self.cmd = cmd;
+        self
+    }
+
+    pub fn cmd(&self) -> &[String] {
+        &self.cmd
+    }
+
+    pub fn set_env(&mut self, env: Vec<crate::models::EnvVar>) {
+        self.env = Some(env);
+    }
+
+    pub fn with_env(mut self, env: Vec<crate::models::EnvVar>) -> Self {
+        self.env = Some(env);
+        self
+    }
+
+    pub fn env(&self) -> Option<&[crate::models::EnvVar]> {
+        self.env.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_env(&mut self) {
+        self.env = None;
+    }
+}
@@ -0,0 +1,92 @@
+/*
+ * IoT Edge Management API
+ *
+ * No description provided (generated by Swagger Codegen https://github.com/swagger-api/swagger-codegen)
+ *
+ * OpenAPI spec version: 2019-11-05
+ *
+ * Generated by: https://github.com/swagger-api/swagger-codegen.git
+ */
+
+use serde_derive::{Deserialize, Serialize};
+#[allow(unused_imports)]
+use serde_json::Value;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LastError {
+    #[serde(rename = "code")]
+    code: String,
+    #[serde(rename = "message")]
+    message: String,
+    #[serde(rename = "timestamp")]
+    timestamp: String,
+    #[serde(rename = "exitCode", skip_serializing_if = "Option::is_none")]
+    exit_code: Option<i64>,
+}
+
+impl LastError {
+    pub fn new(code: String, message: String, timestamp: String) -> Self {
+        LastError {
+            code,
+            message,
+            timestamp,
+            exit_code: None,
+        }
+    }
+
+    pub fn set_code(&mut self, code: String) {
+        self.code = code;
+    }
+
+    pub fn with_code(mut self, code: String) -> Self {
+        self.code = code;
+        self
+    }
+
+    pub fn code(&self) -> &String {
+        &self.code
+    }
+
+    pub fn set_message(&mut self, message: String) {
+        self.message = message;
+    }
+
+    pub fn with_message(mut self, message: String) -> Self {
+        self.message = message;
+        self
+    }
+
+    pub fn message(&self) -> &String {
+        &self.message
+    }
+
+    pub fn set_timestamp(&mut self, timestamp: String) {
+        self.timestamp = timestamp;
+    }
+
+    pub fn with_timestamp(mut self, timestamp: String) -> Self {
+        self.timestamp = timestamp;
+        self
+    }
+
+    pub fn timestamp(&self) -> &String {
+        &self.timestamp
+    }
+
+    pub fn set_exit_code(&mut self, exit_code: i64) {
+        self.exit_code = Some(exit_code);
+    }
+
+    pub fn with_exit_code(mut self, exit_code: i64) -> Self {
+        self.exit_code = Some(exit_code);
+        self
+    }
+
+    pub fn exit_code(&self) -> Option<i64> {
+        self.exit_code
+    }
+
+    pub fn reset_exit_code(&mut self) {
+        self.exit_code = None;
+    }
+}
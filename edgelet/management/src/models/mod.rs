@@ -1,9 +1,15 @@
 mod config;
 pub use self::config::Config;
+mod dry_run_action;
+pub use self::dry_run_action::DryRunAction;
+mod dry_run_result;
+pub use self::dry_run_result::DryRunResult;
 mod env_var;
 pub use self::env_var::EnvVar;
 mod error_response;
 pub use self::error_response::ErrorResponse;
+mod exec_request;
+pub use self::exec_request::ExecRequest;
 mod exit_status;
 pub use self::exit_status::ExitStatus;
 mod identity;
@@ -12,14 +18,36 @@ mod identity_list;
 pub use self::identity_list::IdentityList;
 mod identity_spec;
 pub use self::identity_spec::IdentitySpec;
+mod last_error;
+pub use self::last_error::LastError;
 mod update_identity;
 pub use self::update_identity::UpdateIdentity;
+mod module_bind;
+pub use self::module_bind::ModuleBind;
 mod module_details;
 pub use self::module_details::ModuleDetails;
 mod module_list;
 pub use self::module_list::ModuleList;
+mod module_log_config;
+pub use self::module_log_config::ModuleLogConfig;
+mod module_resources;
+pub use self::module_resources::ModuleResources;
+mod module_network;
+pub use self::module_network::ModuleNetwork;
+mod module_device;
+pub use self::module_device::ModuleDevice;
+mod module_pre_stop;
+pub use self::module_pre_stop::ModulePreStop;
 mod module_spec;
 pub use self::module_spec::ModuleSpec;
+mod module_spec_list;
+pub use self::module_spec_list::ModuleSpecList;
+mod module_tmpfs;
+pub use self::module_tmpfs::ModuleTmpfs;
+mod module_ulimit;
+pub use self::module_ulimit::ModuleUlimit;
+mod port_binding;
+pub use self::port_binding::PortBinding;
 mod runtime_status;
 pub use self::runtime_status::RuntimeStatus;
 mod status;
@@ -0,0 +1,79 @@
+/*
+ * IoT Edge Management API
+ *
+ * No description provided (generated by Swagger Codegen https://github.com/swagger-api/swagger-codegen)
+ *
+ * OpenAPI spec version: 2019-11-05
+ *
+ * Generated by: https://github.com/swagger-api/swagger-codegen.git
+ */
+
+use serde_derive::{Deserialize, Serialize};
+#[allow(unused_imports)]
+use serde_json::Value;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ModuleBind {
+    /// Absolute path on the host.
+    #[serde(rename = "source")]
+    source: String,
+    /// Absolute path in the module's container.
+    #[serde(rename = "target")]
+    target: String,
+    /// Whether the module can only read the path. Defaults to false.
+    #[serde(rename = "readOnly", skip_serializing_if = "Option::is_none")]
+    read_only: Option<bool>,
+}
+
+impl ModuleBind {
+    pub fn new(source: String, target: String) -> Self {
+        ModuleBind {
+            source,
+            target,
+            read_only: None,
+        }
+    }
+
+    pub fn set_source(&mut self, source: String) {
+        self.source = source;
+    }
+
+    pub fn with_source(mut self, source: String) -> Self {
+        self.source = source;
+        self
+    }
+
+    pub fn source(&self) -> &String {
+        &self.source
+    }
+
+    pub fn set_target(&mut self, target: String) {
+        self.target = target;
+    }
+
+    pub fn with_target(mut self, target: String) -> Self {
+        self.target = target;
+        self
+    }
+
+    pub fn target(&self) -> &String {
+        &self.target
+    }
+
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = Some(read_only);
+    }
+
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = Some(read_only);
+        self
+    }
+
+    pub fn read_only(&self) -> Option<bool> {
+        self.read_only
+    }
+
+    pub fn reset_read_only(&mut self) {
+        self.read_only = None;
+    }
+}
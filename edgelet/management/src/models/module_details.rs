@@ -27,6 +27,8 @@ pub struct ModuleDetails {
     config: crate::models::Config,
     #[serde(rename = "status")]
     status: crate::models::Status,
+    #[serde(rename = "labels", skip_serializing_if = "Option::is_none")]
+    labels: Option<::std::collections::HashMap<String, String>>,
 }
 
 impl ModuleDetails {
@@ -43,6 +45,7 @@ impl ModuleDetails {
             type_,
             config,
             status,
+            labels: None,
         }
     }
 
@@ -110,4 +113,21 @@ impl ModuleDetails {
     pub fn status(&self) -> &crate::models::Status {
         &self.status
     }
+
+    pub fn set_labels(&mut self, labels: ::std::collections::HashMap<String, String>) {
+        self.labels = Some(labels);
+    }
+
+    pub fn with_labels(mut self, labels: ::std::collections::HashMap<String, String>) -> Self {
+        self.labels = Some(labels);
+        self
+    }
+
+    pub fn labels(&self) -> Option<&::std::collections::HashMap<String, String>> {
+        self.labels.as_ref()
+    }
+
+    pub fn reset_labels(&mut self) {
+        self.labels = None;
+    }
 }
@@ -0,0 +1,79 @@
+/*
+ * IoT Edge Management API
+ *
+ * No description provided (generated by Swagger Codegen https://github.com/swagger-api/swagger-codegen)
+ *
+ * OpenAPI spec version: 2019-11-05
+ *
+ * Generated by: https://github.com/swagger-api/swagger-codegen.git
+ */
+
+use serde_derive::{Deserialize, Serialize};
+#[allow(unused_imports)]
+use serde_json::Value;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ModuleDevice {
+    /// Absolute path of the device on the host.
+    #[serde(rename = "hostPath")]
+    host_path: String,
+    /// Absolute path of the device in the module's container.
+    #[serde(rename = "containerPath")]
+    container_path: String,
+    /// Some of r (read), w (write) and m (mknod).
+    #[serde(rename = "permissions", skip_serializing_if = "Option::is_none")]
+    permissions: Option<String>,
+}
+
+impl ModuleDevice {
+    pub fn new(host_path: String, container_path: String) -> Self {
+        ModuleDevice {
+            host_path,
+            container_path,
+            permissions: None,
+        }
+    }
+
+    pub fn set_host_path(&mut self, host_path: String) {
+        self.host_path = host_path;
+    }
+
+    pub fn with_host_path(mut self, host_path: String) -> Self {
+        self.host_path = host_path;
+        self
+    }
+
+    pub fn host_path(&self) -> &String {
+        &self.host_path
+    }
+
+    pub fn set_container_path(&mut self, container_path: String) {
+        self.container_path = container_path;
+    }
+
+    pub fn with_container_path(mut self, container_path: String) -> Self {
+        self.container_path = container_path;
+        self
+    }
+
+    pub fn container_path(&self) -> &String {
+        &self.container_path
+    }
+
+    pub fn set_permissions(&mut self, permissions: String) {
+        self.permissions = Some(permissions);
+    }
+
+    pub fn with_permissions(mut self, permissions: String) -> Self {
+        self.permissions = Some(permissions);
+        self
+    }
+
+    pub fn permissions(&self) -> Option<&str> {
+        self.permissions.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_permissions(&mut self) {
+        self.permissions = None;
+    }
+}
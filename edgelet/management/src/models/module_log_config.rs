@@ -0,0 +1,87 @@
+/*
+ * IoT Edge Management API
+ *
+ * No description provided (generated by Swagger Codegen https://github.com/swagger-api/swagger-codegen)
+ *
+ * OpenAPI spec version: 2019-11-05
+ *
+ * Generated by: https://github.com/swagger-api/swagger-codegen.git
+ */
+
+use serde_derive::{Deserialize, Serialize};
+#[allow(unused_imports)]
+use serde_json::Value;
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct ModuleLogConfig {
+    /// Docker log driver of the module.
+    #[serde(rename = "logDriver", skip_serializing_if = "Option::is_none")]
+    log_driver: Option<String>,
+    /// Size a log file is rotated at, with an optional k, m or g suffix.
+    #[serde(rename = "maxSize", skip_serializing_if = "Option::is_none")]
+    max_size: Option<String>,
+    /// Number of rotated log files to keep.
+    #[serde(rename = "maxFile", skip_serializing_if = "Option::is_none")]
+    max_file: Option<u32>,
+}
+
+impl ModuleLogConfig {
+    pub fn new() -> Self {
+        ModuleLogConfig {
+            log_driver: None,
+            max_size: None,
+            max_file: None,
+        }
+    }
+
+    pub fn set_log_driver(&mut self, log_driver: String) {
+        self.log_driver = Some(log_driver);
+    }
+
+    pub fn with_log_driver(mut self, log_driver: String) -> Self {
+        self.log_driver = Some(log_driver);
+        self
+    }
+
+    pub fn log_driver(&self) -> Option<&str> {
+        self.log_driver.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_log_driver(&mut self) {
+        self.log_driver = None;
+    }
+
+    pub fn set_max_size(&mut self, max_size: String) {
+        self.max_size = Some(max_size);
+    }
+
+    pub fn with_max_size(mut self, max_size: String) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    pub fn max_size(&self) -> Option<&str> {
+        self.max_size.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_max_size(&mut self) {
+        self.max_size = None;
+    }
+
+    pub fn set_max_file(&mut self, max_file: u32) {
+        self.max_file = Some(max_file);
+    }
+
+    pub fn with_max_file(mut self, max_file: u32) -> Self {
+        self.max_file = Some(max_file);
+        self
+    }
+
+    pub fn max_file(&self) -> Option<u32> {
+        self.max_file
+    }
+
+    pub fn reset_max_file(&mut self) {
+        self.max_file = None;
+    }
+}
@@ -0,0 +1,62 @@
+/*
+ * IoT Edge Management API
+ *
+ * No description provided (generated by Swagger Codegen https://github.com/swagger-api/swagger-codegen)
+ *
+ * OpenAPI spec version: 2019-11-05
+ *
+ * Generated by: https://github.com/swagger-api/swagger-codegen.git
+ */
+
+use serde_derive::{Deserialize, Serialize};
+#[allow(unused_imports)]
+use serde_json::Value;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ModuleNetwork {
+    /// Name of the network.
+    #[serde(rename = "name")]
+    name: String,
+    /// Names the module can be reached by on the network, in addition to its own.
+    #[serde(rename = "aliases", skip_serializing_if = "Option::is_none")]
+    aliases: Option<Vec<String>>,
+}
+
+impl ModuleNetwork {
+    pub fn new(name: String) -> Self {
+        ModuleNetwork {
+            name,
+            aliases: None,
+        }
+    }
+
+    pub fn set_name(&mut self, name: String) {
+        self.name = name;
+    }
+
+    pub fn with_name(mut self, name: String) -> Self {
+        self.name = name;
+        self
+    }
+
+    pub fn name(&self) -> &String {
+        &self.name
+    }
+
+    pub fn set_aliases(&mut self, aliases: Vec<String>) {
+        self.aliases = Some(aliases);
+    }
+
+    pub fn with_aliases(mut self, aliases: Vec<String>) -> Self {
+        self.aliases = Some(aliases);
+        self
+    }
+
+    pub fn aliases(&self) -> Option<&[String]> {
+        self.aliases.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_aliases(&mut self) {
+        self.aliases = None;
+    }
+}
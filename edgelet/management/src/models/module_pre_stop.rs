@@ -0,0 +1,62 @@
+/*
+ * IoT Edge Management API
+ *
+ * No description provided (generated by Swagger Codegen https://github.com/swagger-api/swagger-codegen)
+ *
+ * OpenAPI spec version: 2019-11-05
+ *
+ * Generated by: https://github.com/swagger-api/swagger-codegen.git
+ */
+
+use serde_derive::{Deserialize, Serialize};
+#[allow(unused_imports)]
+use serde_json::Value;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ModulePreStop {
+    /// The command and its arguments, run in the module's container.
+    #[serde(rename = "command")]
+    command: Vec<String>,
+    /// How long the command is given to finish before the module is stopped anyway.
+    #[serde(rename = "timeoutSecs", skip_serializing_if = "Option::is_none")]
+    timeout_secs: Option<u64>,
+}
+
+impl ModulePreStop {
+    pub fn new(command: Vec<String>) -> Self {
+        ModulePreStop {
+            command,
+            timeout_secs: None,
+        }
+    }
+
+    pub fn set_command(&mut self, command: Vec<String>) {
+        self.command = command;
+    }
+
+    pub fn with_command(mut self, command: Vec<String>) -> Self {
+        self.command = command;
+        self
+    }
+
+    pub fn command(&self) -> &[String] {
+        &self.command
+    }
+
+    pub fn set_timeout_secs(&mut self, timeout_secs: u64) {
+        self.timeout_secs = Some(timeout_secs);
+    }
+
+    pub fn with_timeout_secs(mut self, timeout_secs: u64) -> Self {
+        self.timeout_secs = Some(timeout_secs);
+        self
+    }
+
+    pub fn timeout_secs(&self) -> Option<u64> {
+        self.timeout_secs
+    }
+
+    pub fn reset_timeout_secs(&mut self) {
+        self.timeout_secs = None;
+    }
+}
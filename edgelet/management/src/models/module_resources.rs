@@ -0,0 +1,108 @@
+/*
+ * IoT Edge Management API
+ *
+ * No description provided (generated by Swagger Codegen https://github.com/swagger-api/swagger-codegen)
+ *
+ * OpenAPI spec version: 2019-11-05
+ *
+ * Generated by: https://github.com/swagger-api/swagger-codegen.git
+ */
+
+use serde_derive::{Deserialize, Serialize};
+#[allow(unused_imports)]
+use serde_json::Value;
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct ModuleResources {
+    /// Relative CPU weight of the module versus other modules.
+    #[serde(rename = "cpuShares", skip_serializing_if = "Option::is_none")]
+    cpu_shares: Option<u32>,
+    /// Memory limit in bytes.
+    #[serde(rename = "memoryBytes", skip_serializing_if = "Option::is_none")]
+    memory_bytes: Option<u64>,
+    /// Total memory limit (memory + swap) in bytes. Set as -1 for unlimited swap.
+    #[serde(rename = "memorySwap", skip_serializing_if = "Option::is_none")]
+    memory_swap: Option<i64>,
+    /// Maximum number of processes. Set as -1 for unlimited.
+    #[serde(rename = "pidsLimit", skip_serializing_if = "Option::is_none")]
+    pids_limit: Option<i64>,
+}
+
+impl ModuleResources {
+    pub fn new() -> Self {
+        ModuleResources {
+            cpu_shares: None,
+            memory_bytes: None,
+            memory_swap: None,
+            pids_limit: None,
+        }
+    }
+
+    pub fn set_cpu_shares(&mut self, cpu_shares: u32) {
+        self.cpu_shares = Some(cpu_shares);
+    }
+
+    pub fn with_cpu_shares(mut self, cpu_shares: u32) -> Self {
+        self.cpu_shares = Some(cpu_shares);
+        self
+    }
+
+    pub fn cpu_shares(&self) -> Option<u32> {
+        self.cpu_shares
+    }
+
+    pub fn reset_cpu_shares(&mut self) {
+        self.cpu_shares = None;
+    }
+
+    pub fn set_memory_bytes(&mut self, memory_bytes: u64) {
+        self.memory_bytes = Some(memory_bytes);
+    }
+
+    pub fn with_memory_bytes(mut self, memory_bytes: u64) -> Self {
+        self.memory_bytes = Some(memory_bytes);
+        self
+    }
+
+    pub fn memory_bytes(&self) -> Option<u64> {
+        self.memory_bytes
+    }
+
+    pub fn reset_memory_bytes(&mut self) {
+        self.memory_bytes = None;
+    }
+
+    pub fn set_memory_swap(&mut self, memory_swap: i64) {
+        self.memory_swap = Some(memory_swap);
+    }
+
+    pub fn with_memory_swap(mut self, memory_swap: i64) -> Self {
+        self.memory_swap = Some(memory_swap);
+        self
+    }
+
+    pub fn memory_swap(&self) -> Option<i64> {
+        self.memory_swap
+    }
+
+    pub fn reset_memory_swap(&mut self) {
+        self.memory_swap = None;
+    }
+
+    pub fn set_pids_limit(&mut self, pids_limit: i64) {
+        self.pids_limit = Some(pids_limit);
+    }
+
+    pub fn with_pids_limit(mut self, pids_limit: i64) -> Self {
+        self.pids_limit = Some(pids_limit);
+        self
+    }
+
+    pub fn pids_limit(&self) -> Option<i64> {
+        self.pids_limit
+    }
+
+    pub fn reset_pids_limit(&mut self) {
+        self.pids_limit = None;
+    }
+}
@@ -23,6 +23,61 @@ pub struct ModuleSpec {
     config: crate::models::Config,
     #[serde(rename = "imagePullPolicy", skip_serializing_if = "Option::is_none")]
     image_pull_policy: Option<String>,
+    #[serde(rename = "resources", skip_serializing_if = "Option::is_none")]
+    resources: Option<crate::models::ModuleResources>,
+    #[serde(rename = "logConfig", skip_serializing_if = "Option::is_none")]
+    log_config: Option<crate::models::ModuleLogConfig>,
+    #[serde(rename = "binds", skip_serializing_if = "Option::is_none")]
+    binds: Option<Vec<crate::models::ModuleBind>>,
+    #[serde(rename = "readOnlyRootFs", skip_serializing_if = "Option::is_none")]
+    read_only_root_fs: Option<bool>,
+    #[serde(rename = "tmpfs", skip_serializing_if = "Option::is_none")]
+    tmpfs: Option<Vec<crate::models::ModuleTmpfs>>,
+    #[serde(rename = "stopTimeoutSecs", skip_serializing_if = "Option::is_none")]
+    stop_timeout_secs: Option<u64>,
+    #[serde(rename = "stopSignal", skip_serializing_if = "Option::is_none")]
+    stop_signal: Option<String>,
+    #[serde(rename = "preStop", skip_serializing_if = "Option::is_none")]
+    pre_stop: Option<crate::models::ModulePreStop>,
+    #[serde(rename = "labels", skip_serializing_if = "Option::is_none")]
+    labels: Option<::std::collections::HashMap<String, String>>,
+    #[serde(rename = "envFiles", skip_serializing_if = "Option::is_none")]
+    env_files: Option<Vec<String>>,
+    #[serde(rename = "dns", skip_serializing_if = "Option::is_none")]
+    dns: Option<Vec<String>>,
+    #[serde(rename = "dnsSearch", skip_serializing_if = "Option::is_none")]
+    dns_search: Option<Vec<String>>,
+    #[serde(rename = "networks", skip_serializing_if = "Option::is_none")]
+    networks: Option<Vec<crate::models::ModuleNetwork>>,
+    #[serde(rename = "devices", skip_serializing_if = "Option::is_none")]
+    devices: Option<Vec<crate::models::ModuleDevice>>,
+    #[serde(rename = "runtime", skip_serializing_if = "Option::is_none")]
+    runtime: Option<String>,
+    #[serde(rename = "capAdd", skip_serializing_if = "Option::is_none")]
+    cap_add: Option<Vec<String>>,
+    #[serde(rename = "capDrop", skip_serializing_if = "Option::is_none")]
+    cap_drop: Option<Vec<String>>,
+    #[serde(rename = "noNewPrivileges", skip_serializing_if = "Option::is_none")]
+    no_new_privileges: Option<bool>,
+    #[serde(rename = "seccompProfile", skip_serializing_if = "Option::is_none")]
+    seccomp_profile: Option<String>,
+    #[serde(rename = "apparmorProfile", skip_serializing_if = "Option::is_none")]
+    apparmor_profile: Option<String>,
+    #[serde(rename = "ulimits", skip_serializing_if = "Option::is_none")]
+    ulimits: Option<Vec<crate::models::ModuleUlimit>>,
+    #[serde(rename = "restartPolicy", skip_serializing_if = "Option::is_none")]
+    restart_policy: Option<String>,
+    #[serde(rename = "maxRetries", skip_serializing_if = "Option::is_none")]
+    max_retries: Option<u32>,
+    #[serde(rename = "user", skip_serializing_if = "Option::is_none")]
+    user: Option<String>,
+    #[serde(rename = "workingDir", skip_serializing_if = "Option::is_none")]
+    working_dir: Option<String>,
+    #[serde(
+        rename = "identityIntegration",
+        skip_serializing_if = "Option::is_none"
+    )]
+    identity_integration: Option<bool>,
 }
 
 impl ModuleSpec {
@@ -32,6 +87,32 @@ impl ModuleSpec {
             type_,
             config,
             image_pull_policy: None,
+            resources: None,
+            log_config: None,
+            binds: None,
+            read_only_root_fs: None,
+            tmpfs: None,
+            stop_timeout_secs: None,
+            stop_signal: None,
+            pre_stop: None,
+            labels: None,
+            env_files: None,
+            dns: None,
+            dns_search: None,
+            networks: None,
+            devices: None,
+            runtime: None,
+            cap_add: None,
+            cap_drop: None,
+            no_new_privileges: None,
+            seccomp_profile: None,
+            apparmor_profile: None,
+            ulimits: None,
+            restart_policy: None,
+            max_retries: None,
+            user: None,
+            working_dir: None,
+            identity_integration: None,
         }
     }
 
@@ -90,4 +171,446 @@ impl ModuleSpec {
     pub fn reset_image_pull_policy(&mut self) {
         self.image_pull_policy = None;
     }
+
+    pub fn set_resources(&mut self, resources: crate::models::ModuleResources) {
+        self.resources = Some(resources);
+    }
+
+    pub fn with_resources(mut self, resources: crate::models::ModuleResources) -> Self {
+        self.resources = Some(resources);
+        self
+    }
+
+    pub fn resources(&self) -> Option<&crate::models::ModuleResources> {
+        self.resources.as_ref()
+    }
+
+    pub fn reset_resources(&mut self) {
+        self.resources = None;
+    }
+
+    pub fn set_log_config(&mut self, log_config: crate::models::ModuleLogConfig) {
+        self.log_config = Some(log_config);
+    }
+
+    pub fn with_log_config(mut self, log_config: crate::models::ModuleLogConfig) -> Self {
+        self.log_config = Some(log_config);
+        self
+    }
+
+    pub fn log_config(&self) -> Option<&crate::models::ModuleLogConfig> {
+        self.log_config.as_ref()
+    }
+
+    pub fn reset_log_config(&mut self) {
+        self.log_config = None;
+    }
+
+    pub fn set_binds(&mut self, binds: Vec<crate::models::ModuleBind>) {
+        self.binds = Some(binds);
+    }
+
+    pub fn with_binds(mut self, binds: Vec<crate::models::ModuleBind>) -> Self {
+        self.binds = Some(binds);
+        self
+    }
+
+    pub fn binds(&self) -> Option<&[crate::models::ModuleBind]> {
+        self.binds.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_binds(&mut self) {
+        self.binds = None;
+    }
+
+    pub fn set_read_only_root_fs(&mut self, read_only_root_fs: bool) {
+        self.read_only_root_fs = Some(read_only_root_fs);
+    }
+
+    pub fn with_read_only_root_fs(mut self, read_only_root_fs: bool) -> Self {
+        self.read_only_root_fs = Some(read_only_root_fs);
+        self
+    }
+
+    pub fn read_only_root_fs(&self) -> Option<bool> {
+        self.read_only_root_fs
+    }
+
+    pub fn reset_read_only_root_fs(&mut self) {
+        self.read_only_root_fs = None;
+    }
+
+    pub fn set_tmpfs(&mut self, tmpfs: Vec<crate::models::ModuleTmpfs>) {
+        self.tmpfs = Some(tmpfs);
+    }
+
+    pub fn with_tmpfs(mut self, tmpfs: Vec<crate::models::ModuleTmpfs>) -> Self {
+        self.tmpfs = Some(tmpfs);
+        self
+    }
+
+    pub fn tmpfs(&self) -> Option<&[crate::models::ModuleTmpfs]> {
+        self.tmpfs.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_tmpfs(&mut self) {
+        self.tmpfs = None;
+    }
+
+    pub fn set_stop_timeout_secs(&mut self, stop_timeout_secs: u64) {
+        self.stop_timeout_secs = Some(stop_timeout_secs);
+    }
+
+    pub fn with_stop_timeout_secs(mut self, stop_timeout_secs: u64) -> Self {
+        self.stop_timeout_secs = Some(stop_timeout_secs);
+        self
+    }
+
+    pub fn stop_timeout_secs(&self) -> Option<u64> {
+        self.stop_timeout_secs
+    }
+
+    pub fn reset_stop_timeout_secs(&mut self) {
+        self.stop_timeout_secs = None;
+    }
+
+    pub fn set_stop_signal(&mut self, stop_signal: String) {
+        self.stop_signal = Some(stop_signal);
+    }
+
+    pub fn with_stop_signal(mut self, stop_signal: String) -> Self {
+        self.stop_signal = Some(stop_signal);
+        self
+    }
+
+    pub fn stop_signal(&self) -> Option<&str> {
+        self.stop_signal.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_stop_signal(&mut self) {
+        self.stop_signal = None;
+    }
+
+    pub fn set_pre_stop(&mut self, pre_stop: crate::models::ModulePreStop) {
+        self.pre_stop = Some(pre_stop);
+    }
+
+    pub fn with_pre_stop(mut self, pre_stop: crate::models::ModulePreStop) -> Self {
+        self.pre_stop = Some(pre_stop);
+        self
+    }
+
+    pub fn pre_stop(&self) -> Option<&crate::models::ModulePreStop> {
+        self.pre_stop.as_ref()
+    }
+
+    pub fn reset_pre_stop(&mut self) {
+        self.pre_stop = None;
+    }
+
+    pub fn set_labels(&mut self, labels: ::std::collections::HashMap<String, String>) {
+        self.labels = Some(labels);
+    }
+
+    pub fn with_labels(mut self, labels: ::std::collections::HashMap<String, String>) -> Self {
+        self.labels = Some(labels);
+        self
+    }
+
+    pub fn labels(&self) -> Option<&::std::collections::HashMap<String, String>> {
+        self.labels.as_ref()
+    }
+
+    pub fn reset_labels(&mut self) {
+        self.labels = None;
+    }
+
+    pub fn set_env_files(&mut self, env_files: Vec<String>) {
+        self.env_files = Some(env_files);
+    }
+
+    pub fn with_env_files(mut self, env_files: Vec<String>) -> Self {
+        self.env_files = Some(env_files);
+        self
+    }
+
+    pub fn env_files(&self) -> Option<&[String]> {
+        self.env_files.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_env_files(&mut self) {
+        self.env_files = None;
+    }
+
+    pub fn set_dns(&mut self, dns: Vec<String>) {
+        self.dns = Some(dns);
+    }
+
+    pub fn with_dns(mut self, dns: Vec<String>) -> Self {
+        self.dns = Some(dns);
+        self
+    }
+
+    pub fn dns(&self) -> Option<&[String]> {
+        self.dns.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_dns(&mut self) {
+        self.dns = None;
+    }
+
+    pub fn set_dns_search(&mut self, dns_search: Vec<String>) {
+        self.dns_search = Some(dns_search);
+    }
+
+    pub fn with_dns_search(mut self, dns_search: Vec<String>) -> Self {
+        self.dns_search = Some(dns_search);
+        self
+    }
+
+    pub fn dns_search(&self) -> Option<&[String]> {
+        self.dns_search.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_dns_search(&mut self) {
+        self.dns_search = None;
+    }
+
+    pub fn set_networks(&mut self, networks: Vec<crate::models::ModuleNetwork>) {
+        self.networks = Some(networks);
+    }
+
+    pub fn with_networks(mut self, networks: Vec<crate::models::ModuleNetwork>) -> Self {
+        self.networks = Some(networks);
+        self
+    }
+
+    pub fn networks(&self) -> Option<&[crate::models::ModuleNetwork]> {
+        self.networks.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_networks(&mut self) {
+        self.networks = None;
+    }
+
+    pub fn set_devices(&mut self, devices: Vec<crate::models::ModuleDevice>) {
+        self.devices = Some(devices);
+    }
+
+    pub fn with_devices(mut self, devices: Vec<crate::models::ModuleDevice>) -> Self {
+        self.devices = Some(devices);
+        self
+    }
+
+    pub fn devices(&self) -> Option<&[crate::models::ModuleDevice]> {
+        self.devices.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_devices(&mut self) {
+        self.devices = None;
+    }
+
+    pub fn set_runtime(&mut self, runtime: String) {
+        self.runtime = Some(runtime);
+    }
+
+    pub fn with_runtime(mut self, runtime: String) -> Self {
+        self.runtime = Some(runtime);
+        self
+    }
+
+    pub fn runtime(&self) -> Option<&str> {
+        self.runtime.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_runtime(&mut self) {
+        self.runtime = None;
+    }
+
+    pub fn set_cap_add(&mut self, cap_add: Vec<String>) {
+        self.cap_add = Some(cap_add);
+    }
+
+    pub fn with_cap_add(mut self, cap_add: Vec<String>) -> Self {
+        self.cap_add = Some(cap_add);
+        self
+    }
+
+    pub fn cap_add(&self) -> Option<&[String]> {
+        self.cap_add.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_cap_add(&mut self) {
+        self.cap_add = None;
+    }
+
+    pub fn set_cap_drop(&mut self, cap_drop: Vec<String>) {
+        self.cap_drop = Some(cap_drop);
+    }
+
+    pub fn with_cap_drop(mut self, cap_drop: Vec<String>) -> Self {
+        self.cap_drop = Some(cap_drop);
+        self
+    }
+
+    pub fn cap_drop(&self) -> Option<&[String]> {
+        self.cap_drop.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_cap_drop(&mut self) {
+        self.cap_drop = None;
+    }
+
+    pub fn set_no_new_privileges(&mut self, no_new_privileges: bool) {
+        self.no_new_privileges = Some(no_new_privileges);
+    }
+
+    pub fn with_no_new_privileges(mut self, no_new_privileges: bool) -> Self {
+        self.no_new_privileges = Some(no_new_privileges);
+        self
+    }
+
+    pub fn no_new_privileges(&self) -> Option<bool> {
+        self.no_new_privileges
+    }
+
+    pub fn reset_no_new_privileges(&mut self) {
+        self.no_new_privileges = None;
+    }
+
+    pub fn set_seccomp_profile(&mut self, seccomp_profile: String) {
+        self.seccomp_profile = Some(seccomp_profile);
+    }
+
+    pub fn with_seccomp_profile(mut self, seccomp_profile: String) -> Self {
+        self.seccomp_profile = Some(seccomp_profile);
+        self
+    }
+
+    pub fn seccomp_profile(&self) -> Option<&str> {
+        self.seccomp_profile.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_seccomp_profile(&mut self) {
+        self.seccomp_profile = None;
+    }
+
+    pub fn set_apparmor_profile(&mut self, apparmor_profile: String) {
+        self.apparmor_profile = Some(apparmor_profile);
+    }
+
+    pub fn with_apparmor_profile(mut self, apparmor_profile: String) -> Self {
+        self.apparmor_profile = Some(apparmor_profile);
+        self
+    }
+
+    pub fn apparmor_profile(&self) -> Option<&str> {
+        self.apparmor_profile.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_apparmor_profile(&mut self) {
+        self.apparmor_profile = None;
+    }
+
+    pub fn set_ulimits(&mut self, ulimits: Vec<crate::models::ModuleUlimit>) {
+        self.ulimits = Some(ulimits);
+    }
+
+    pub fn with_ulimits(mut self, ulimits: Vec<crate::models::ModuleUlimit>) -> Self {
+        self.ulimits = Some(ulimits);
+        self
+    }
+
+    pub fn ulimits(&self) -> Option<&[crate::models::ModuleUlimit]> {
+        self.ulimits.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_ulimits(&mut self) {
+        self.ulimits = None;
+    }
+
+    pub fn set_restart_policy(&mut self, restart_policy: String) {
+        self.restart_policy = Some(restart_policy);
+    }
+
+    pub fn with_restart_policy(mut self, restart_policy: String) -> Self {
+        self.restart_policy = Some(restart_policy);
+        self
+    }
+
+    pub fn restart_policy(&self) -> Option<&str> {
+        self.restart_policy.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_restart_policy(&mut self) {
+        self.restart_policy = None;
+    }
+
+    pub fn set_max_retries(&mut self, max_retries: u32) {
+        self.max_retries = Some(max_retries);
+    }
+
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = Some(max_retries);
+        self
+    }
+
+    pub fn max_retries(&self) -> Option<u32> {
+        self.max_retries
+    }
+
+    pub fn reset_max_retries(&mut self) {
+        self.max_retries = None;
+    }
+
+    pub fn set_user(&mut self, user: String) {
+        self.user = Some(user);
+    }
+
+    pub fn with_user(mut self, user: String) -> Self {
+        self.user = Some(user);
+        self
+    }
+
+    pub fn user(&self) -> Option<&str> {
+        self.user.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_user(&mut self) {
+        self.user = None;
+    }
+
+    pub fn set_working_dir(&mut self, working_dir: String) {
+        self.working_dir = Some(working_dir);
+    }
+
+    pub fn with_working_dir(mut self, working_dir: String) -> Self {
+        self.working_dir = Some(working_dir);
+        self
+    }
+
+    pub fn working_dir(&self) -> Option<&str> {
+        self.working_dir.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_working_dir(&mut self) {
+        self.working_dir = None;
+    }
+
+    pub fn set_identity_integration(&mut self, identity_integration: bool) {
+        self.identity_integration = Some(identity_integration);
+    }
+
+    pub fn with_identity_integration(mut self, identity_integration: bool) -> Self {
+        self.identity_integration = Some(identity_integration);
+        self
+    }
+
+    pub fn identity_integration(&self) -> Option<bool> {
+        self.identity_integration
+    }
+
+    pub fn reset_identity_integration(&mut self) {
+        self.identity_integration = None;
+    }
 }
@@ -0,0 +1,62 @@
+/*
+ * IoT Edge Management API
+ *
+ * No description provided (generated by Swagger Codegen https://github.com/swagger-api/swagger-codegen)
+ *
+ * OpenAPI spec version: 2019-11-05
+ *
+ * Generated by: https://github.com/swagger-api/swagger-codegen.git
+ */
+
+use serde_derive::{Deserialize, Serialize};
+#[allow(unused_imports)]
+use serde_json::Value;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ModuleTmpfs {
+    /// Absolute path in the module's container.
+    #[serde(rename = "target")]
+    target: String,
+    /// Size limit in bytes.
+    #[serde(rename = "sizeBytes", skip_serializing_if = "Option::is_none")]
+    size_bytes: Option<u64>,
+}
+
+impl ModuleTmpfs {
+    pub fn new(target: String) -> Self {
+        ModuleTmpfs {
+            target,
+            size_bytes: None,
+        }
+    }
+
+    pub fn set_target(&mut self, target: String) {
+        self.target = target;
+    }
+
+    pub fn with_target(mut self, target: String) -> Self {
+        self.target = target;
+        self
+    }
+
+    pub fn target(&self) -> &String {
+        &self.target
+    }
+
+    pub fn set_size_bytes(&mut self, size_bytes: u64) {
+        self.size_bytes = Some(size_bytes);
+    }
+
+    pub fn with_size_bytes(mut self, size_bytes: u64) -> Self {
+        self.size_bytes = Some(size_bytes);
+        self
+    }
+
+    pub fn size_bytes(&self) -> Option<u64> {
+        self.size_bytes
+    }
+
+    pub fn reset_size_bytes(&mut self) {
+        self.size_bytes = None;
+    }
+}
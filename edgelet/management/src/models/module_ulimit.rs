@@ -0,0 +1,71 @@
+/*
+ * IoT Edge Management API
+ *
+ * No description provided (generated by Swagger Codegen https://github.com/swagger-api/swagger-codegen)
+ *
+ * OpenAPI spec version: 2019-11-05
+ *
+ * Generated by: https://github.com/swagger-api/swagger-codegen.git
+ */
+
+use serde_derive::{Deserialize, Serialize};
+#[allow(unused_imports)]
+use serde_json::Value;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ModuleUlimit {
+    /// Name of the resource limit, like nofile.
+    #[serde(rename = "name")]
+    name: String,
+    /// The limit the module's processes start with, or -1 for unlimited.
+    #[serde(rename = "soft")]
+    soft: i64,
+    /// The limit up to which the module's processes can raise the soft limit, or -1 for unlimited.
+    #[serde(rename = "hard")]
+    hard: i64,
+}
+
+impl ModuleUlimit {
+    pub fn new(name: String, soft: i64, hard: i64) -> Self {
+        ModuleUlimit { name, soft, hard }
+    }
+
+    pub fn set_name(&mut self, name: String) {
+        self.name = name;
+    }
+
+    pub fn with_name(mut self, name: String) -> Self {
+        self.name = name;
+        self
+    }
+
+    pub fn name(&self) -> &String {
+        &self.name
+    }
+
+    pub fn set_soft(&mut self, soft: i64) {
+        self.soft = soft;
+    }
+
+    pub fn with_soft(mut self, soft: i64) -> Self {
+        self.soft = soft;
+        self
+    }
+
+    pub fn soft(&self) -> i64 {
+        self.soft
+    }
+
+    pub fn set_hard(&mut self, hard: i64) {
+        self.hard = hard;
+    }
+
+    pub fn with_hard(mut self, hard: i64) -> Self {
+        self.hard = hard;
+        self
+    }
+
+    pub fn hard(&self) -> i64 {
+        self.hard
+    }
+}
@@ -0,0 +1,80 @@
+/*
+ * IoT Edge Management API
+ *
+ * No description provided (generated by Swagger Codegen https://github.com/swagger-api/swagger-codegen)
+ *
+ * OpenAPI spec version: 2019-11-05
+ *
+ * Generated by: https://github.com/swagger-api/swagger-codegen.git
+ */
+
+use serde_derive::{Deserialize, Serialize};
+#[allow(unused_imports)]
+use serde_json::Value;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PortBinding {
+    #[serde(rename = "containerPort")]
+    container_port: String,
+    #[serde(rename = "hostIp", skip_serializing_if = "Option::is_none")]
+    host_ip: Option<String>,
+    #[serde(rename = "hostPort", skip_serializing_if = "Option::is_none")]
+    host_port: Option<String>,
+}
+
+impl PortBinding {
+    pub fn new(container_port: String) -> Self {
+        PortBinding {
+            container_port,
+            host_ip: None,
+            host_port: None,
+        }
+    }
+
+    pub fn set_container_port(&mut self, container_port: String) {
+        self.container_port = container_port;
+    }
+
+    pub fn with_container_port(mut self, container_port: String) -> Self {
+        self.container_port = container_port;
+        self
+    }
+
+    pub fn container_port(&self) -> &String {
+        &self.container_port
+    }
+
+    pub fn set_host_ip(&mut self, host_ip: String) {
+        self.host_ip = Some(host_ip);
+    }
+
+    pub fn with_host_ip(mut self, host_ip: String) -> Self {
+        self.host_ip = Some(host_ip);
+        self
+    }
+
+    pub fn host_ip(&self) -> Option<&str> {
+        self.host_ip.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_host_ip(&mut self) {
+        self.host_ip = None;
+    }
+
+    pub fn set_host_port(&mut self, host_port: String) {
+        self.host_port = Some(host_port);
+    }
+
+    pub fn with_host_port(mut self, host_port: String) -> Self {
+        self.host_port = Some(host_port);
+        self
+    }
+
+    pub fn host_port(&self) -> Option<&str> {
+        self.host_port.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_host_port(&mut self) {
+        self.host_port = None;
+    }
+}
@@ -20,6 +20,14 @@ pub struct Status {
     exit_status: Option<crate::models::ExitStatus>,
     #[serde(rename = "runtimeStatus")]
     runtime_status: crate::models::RuntimeStatus,
+    #[serde(rename = "restartCount", skip_serializing_if = "Option::is_none")]
+    restart_count: Option<u32>,
+    #[serde(rename = "paused", skip_serializing_if = "Option::is_none")]
+    paused: Option<bool>,
+    #[serde(rename = "lastError", skip_serializing_if = "Option::is_none")]
+    last_error: Option<crate::models::LastError>,
+    #[serde(rename = "ports", skip_serializing_if = "Option::is_none")]
+    ports: Option<Vec<crate::models::PortBinding>>,
 }
 
 impl Status {
@@ -28,6 +36,10 @@ impl Status {
             start_time: None,
             exit_status: None,
             runtime_status,
+            restart_count: None,
+            paused: None,
+            last_error: None,
+            ports: None,
         }
     }
 
@@ -77,4 +89,72 @@ impl Status {
     pub fn runtime_status(&self) -> &crate::models::RuntimeStatus {
         &self.runtime_status
     }
+
+    pub fn set_restart_count(&mut self, restart_count: u32) {
+        self.restart_count = Some(restart_count);
+    }
+
+    pub fn with_restart_count(mut self, restart_count: u32) -> Self {
+        self.restart_count = Some(restart_count);
+        self
+    }
+
+    pub fn restart_count(&self) -> Option<u32> {
+        self.restart_count
+    }
+
+    pub fn reset_restart_count(&mut self) {
+        self.restart_count = None;
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = Some(paused);
+    }
+
+    pub fn with_paused(mut self, paused: bool) -> Self {
+        self.paused = Some(paused);
+        self
+    }
+
+    pub fn paused(&self) -> Option<bool> {
+        self.paused
+    }
+
+    pub fn reset_paused(&mut self) {
+        self.paused = None;
+    }
+
+    pub fn set_last_error(&mut self, last_error: crate::models::LastError) {
+        self.last_error = Some(last_error);
+    }
+
+    pub fn with_last_error(mut self, last_error: crate::models::LastError) -> Self {
+        self.last_error = Some(last_error);
+        self
+    }
+
+    pub fn last_error(&self) -> Option<&crate::models::LastError> {
+        self.last_error.as_ref()
+    }
+
+    pub fn reset_last_error(&mut self) {
+        self.last_error = None;
+    }
+
+    pub fn set_ports(&mut self, ports: Vec<crate::models::PortBinding>) {
+        self.ports = Some(ports);
+    }
+
+    pub fn with_ports(mut self, ports: Vec<crate::models::PortBinding>) -> Self {
+        self.ports = Some(ports);
+        self
+    }
+
+    pub fn ports(&self) -> Option<&[crate::models::PortBinding]> {
+        self.ports.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_ports(&mut self) {
+        self.ports = None;
+    }
 }
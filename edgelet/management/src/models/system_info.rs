@@ -20,6 +20,18 @@ pub struct SystemInfo {
     architecture: String,
     #[serde(rename = "version")]
     version: String,
+    #[serde(rename = "runtimeType", skip_serializing_if = "Option::is_none")]
+    runtime_type: Option<String>,
+    #[serde(rename = "runtimeVersion", skip_serializing_if = "Option::is_none")]
+    runtime_version: Option<String>,
+    #[serde(rename = "operatingSystem", skip_serializing_if = "Option::is_none")]
+    operating_system: Option<String>,
+    #[serde(rename = "kernelVersion", skip_serializing_if = "Option::is_none")]
+    kernel_version: Option<String>,
+    #[serde(rename = "totalMemory", skip_serializing_if = "Option::is_none")]
+    total_memory: Option<u64>,
+    #[serde(rename = "upstreamReachable", skip_serializing_if = "Option::is_none")]
+    upstream_reachable: Option<bool>,
 }
 
 impl SystemInfo {
@@ -28,6 +40,12 @@ impl SystemInfo {
             os_type,
             architecture,
             version,
+            runtime_type: None,
+            runtime_version: None,
+            operating_system: None,
+            kernel_version: None,
+            total_memory: None,
+            upstream_reachable: None,
         }
     }
 
@@ -69,4 +87,106 @@ impl SystemInfo {
     pub fn version(&self) -> &String {
         &self.version
     }
+
+    pub fn set_runtime_type(&mut self, runtime_type: String) {
+        self.runtime_type = Some(runtime_type);
+    }
+
+    pub fn with_runtime_type(mut self, runtime_type: String) -> Self {
+        self.runtime_type = Some(runtime_type);
+        self
+    }
+
+    pub fn runtime_type(&self) -> Option<&str> {
+        self.runtime_type.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_runtime_type(&mut self) {
+        self.runtime_type = None;
+    }
+
+    pub fn set_runtime_version(&mut self, runtime_version: String) {
+        self.runtime_version = Some(runtime_version);
+    }
+
+    pub fn with_runtime_version(mut self, runtime_version: String) -> Self {
+        self.runtime_version = Some(runtime_version);
+        self
+    }
+
+    pub fn runtime_version(&self) -> Option<&str> {
+        self.runtime_version.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_runtime_version(&mut self) {
+        self.runtime_version = None;
+    }
+
+    pub fn set_operating_system(&mut self, operating_system: String) {
+        self.operating_system = Some(operating_system);
+    }
+
+    pub fn with_operating_system(mut self, operating_system: String) -> Self {
+        self.operating_system = Some(operating_system);
+        self
+    }
+
+    pub fn operating_system(&self) -> Option<&str> {
+        self.operating_system.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_operating_system(&mut self) {
+        self.operating_system = None;
+    }
+
+    pub fn set_kernel_version(&mut self, kernel_version: String) {
+        self.kernel_version = Some(kernel_version);
+    }
+
+    pub fn with_kernel_version(mut self, kernel_version: String) -> Self {
+        self.kernel_version = Some(kernel_version);
+        self
+    }
+
+    pub fn kernel_version(&self) -> Option<&str> {
+        self.kernel_version.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_kernel_version(&mut self) {
+        self.kernel_version = None;
+    }
+
+    pub fn set_total_memory(&mut self, total_memory: u64) {
+        self.total_memory = Some(total_memory);
+    }
+
+    pub fn with_total_memory(mut self, total_memory: u64) -> Self {
+        self.total_memory = Some(total_memory);
+        self
+    }
+
+    pub fn total_memory(&self) -> Option<u64> {
+        self.total_memory
+    }
+
+    pub fn reset_total_memory(&mut self) {
+        self.total_memory = None;
+    }
+
+    pub fn set_upstream_reachable(&mut self, upstream_reachable: bool) {
+        self.upstream_reachable = Some(upstream_reachable);
+    }
+
+    pub fn with_upstream_reachable(mut self, upstream_reachable: bool) -> Self {
+        self.upstream_reachable = Some(upstream_reachable);
+        self
+    }
+
+    pub fn upstream_reachable(&self) -> Option<bool> {
+        self.upstream_reachable
+    }
+
+    pub fn reset_upstream_reachable(&mut self) {
+        self.upstream_reachable = None;
+    }
 }
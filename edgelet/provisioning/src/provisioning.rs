@@ -16,6 +16,7 @@ use serde_json;
 use url::Url;
 
 use dps::registration::{DpsAuthKind, DpsClient, DpsTokenSource};
+use dps::RetryPolicy;
 use edgelet_core::crypto::{Activate, KeyIdentity, KeyStore, MemoryKey, MemoryKeyStore};
 use edgelet_core::ProvisioningResult as CoreProvisioningResult;
 use edgelet_hsm::tpm::{TpmKey, TpmKeyStore};
@@ -470,6 +471,7 @@ where
     registration_id: String,
     hsm_tpm_ek: HsmTpmKey,
     hsm_tpm_srk: HsmTpmKey,
+    retry_policy: RetryPolicy,
 }
 
 impl<C> DpsTpmProvisioning<C>
@@ -499,9 +501,15 @@ where
             registration_id,
             hsm_tpm_ek,
             hsm_tpm_srk,
+            retry_policy: RetryPolicy::default(),
         };
         Ok(result)
     }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
 }
 
 impl<C> Provision for DpsTpmProvisioning<C>
@@ -522,7 +530,8 @@ where
             self.registration_id.clone(),
             DpsAuthKind::Tpm { ek, srk },
             key_activator,
-        );
+        )
+        .map(|c| c.with_retry_policy(self.retry_policy.clone()));
 
         let d = match c {
             Ok(c) => Either::A(
@@ -565,6 +574,7 @@ where
     client: HttpClient<C, DpsTokenSource<MemoryKey>>,
     scope_id: String,
     registration_id: String,
+    retry_policy: RetryPolicy,
 }
 
 impl<C> DpsSymmetricKeyProvisioning<C>
@@ -589,9 +599,15 @@ where
             client,
             scope_id,
             registration_id,
+            retry_policy: RetryPolicy::default(),
         };
         Ok(result)
     }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
 }
 
 impl<C> Provision for DpsSymmetricKeyProvisioning<C>
@@ -610,7 +626,8 @@ where
             self.registration_id.clone(),
             DpsAuthKind::SymmetricKey,
             key_activator,
-        );
+        )
+        .map(|c| c.with_retry_policy(self.retry_policy.clone()));
 
         let d = match c {
             Ok(c) => Either::A(
@@ -652,6 +669,7 @@ where
     client: HttpClient<C, DpsTokenSource<MemoryKey>>,
     scope_id: String,
     registration_id: String,
+    retry_policy: RetryPolicy,
 }
 
 impl<C> DpsX509Provisioning<C>
@@ -676,9 +694,15 @@ where
             client,
             scope_id,
             registration_id,
+            retry_policy: RetryPolicy::default(),
         };
         Ok(result)
     }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
 }
 
 impl<C> Provision for DpsX509Provisioning<C>
@@ -697,7 +721,8 @@ where
             self.registration_id.clone(),
             DpsAuthKind::X509,
             key_activator,
-        );
+        )
+        .map(|c| c.with_retry_policy(self.retry_policy.clone()));
 
         let d = match c {
             Ok(c) => Either::A(
@@ -756,19 +781,34 @@ impl<'a, P: 'a> BackupProvisioning<'a, P> {
         Ok(())
     }
 
-    fn restore(path: PathBuf) -> Result<ProvisioningResult, Error> {
+    fn read_backup(path: PathBuf) -> Result<ProvisioningResult, Error> {
         let mut file = File::open(path).context(ErrorKind::CouldNotRestore)?;
         let mut buffer = String::new();
         let _ = file
             .read_to_string(&mut buffer)
             .context(ErrorKind::CouldNotRestore)?;
+        let prov_result = serde_json::from_str(&buffer).context(ErrorKind::CouldNotRestore)?;
+        Ok(prov_result)
+    }
+
+    fn restore(path: PathBuf) -> Result<ProvisioningResult, Error> {
+        let mut prov_result = Self::read_backup(path)?;
         info!("Restoring device credentials from backup");
-        let mut prov_result: ProvisioningResult =
-            serde_json::from_str(&buffer).context(ErrorKind::CouldNotRestore)?;
         prov_result.reconfigure = ReprovisioningStatus::DeviceDataNotUpdated;
         Ok(prov_result)
     }
 
+    fn log_hub_change(path: PathBuf, prov_result: &ProvisioningResult) {
+        if let Ok(previous) = Self::read_backup(path) {
+            if previous.hub_name != prov_result.hub_name {
+                info!(
+                    "Device \"{}\" was moved from hub \"{}\" to hub \"{}\"",
+                    prov_result.device_id, previous.hub_name, prov_result.hub_name
+                );
+            }
+        }
+    }
+
     fn diff_with_backup_inner(
         path: PathBuf,
         prov_result: &ProvisioningResult,
@@ -824,6 +864,7 @@ where
                 .provision(key_activator)
                 .and_then(move |mut prov_result| {
                     debug!("Provisioning result {:?}", prov_result);
+                    Self::log_hub_change(restore_path.clone(), &prov_result);
                     let reconfigure = match prov_result.reconfigure {
                         ReprovisioningStatus::DeviceDataUpdated => {
                             if Self::diff_with_backup(restore_path, &prov_result) {
@@ -862,9 +903,11 @@ where
 mod tests {
     use super::*;
 
+    use dps::{DeviceRegistrationResult, RegistrationOperationStatus, DPS_API_VERSION};
     use edgelet_core::{Error as CoreError, ManualDeviceConnectionString};
     use external_provisioning::models::{Credentials, DeviceProvisioningInfo};
     use failure::Fail;
+    use hyper::{self, Body, Method, Request, Response, StatusCode};
     use std::fmt::{self, Display};
     use tempdir::TempDir;
     use tokio;
@@ -1219,6 +1262,136 @@ mod tests {
         assert_eq!(result.reconfigure, ReprovisioningStatus::InitialAssignment)
     }
 
+    // Mocks the DPS endpoint of an X.509 registration. The device authenticates with its identity
+    // certificate during the TLS handshake, so requests carry no SAS token. A hub of `None`
+    // rejects the certificate.
+    fn dps_x509_handler(
+        hub: Option<&'static str>,
+        substatus: &'static str,
+    ) -> impl Fn(Request<Body>) -> future::FutureResult<Response<Body>, hyper::Error> + Send + Sync
+    {
+        move |req: Request<Body>| {
+            assert!(req.headers().get(hyper::header::AUTHORIZATION).is_none());
+
+            let hub = match hub {
+                Some(hub) => hub,
+                None => {
+                    let response = Response::builder()
+                        .status(StatusCode::UNAUTHORIZED)
+                        .body(Body::empty())
+                        .unwrap();
+                    return future::ok(response);
+                }
+            };
+
+            let status = if req.method() == Method::PUT {
+                assert_eq!("/scope/registrations/reg/register", req.uri().path());
+                RegistrationOperationStatus::new("operation".to_string())
+                    .with_status("assigning".to_string())
+            } else {
+                assert_eq!(Method::GET, req.method());
+                assert_eq!(
+                    "/scope/registrations/reg/operations/operation",
+                    req.uri().path()
+                );
+                RegistrationOperationStatus::new("operation".to_string())
+                    .with_status("assigned".to_string())
+                    .with_registration_state(
+                        DeviceRegistrationResult::new()
+                            .with_registration_id("reg".to_string())
+                            .with_status("assigned".to_string())
+                            .with_device_id("device".to_string())
+                            .with_assigned_hub(hub.to_string())
+                            .with_substatus(substatus.to_string()),
+                    )
+            };
+            future::ok(Response::new(
+                serde_json::to_string(&status).unwrap().into(),
+            ))
+        }
+    }
+
+    fn dps_x509_provision(
+        hub: Option<&'static str>,
+        substatus: &'static str,
+        backup_path: PathBuf,
+    ) -> Result<ProvisioningResult, Error> {
+        let provisioning = DpsX509Provisioning::new(
+            dps_x509_handler(hub, substatus),
+            Url::parse("https://global.azure-devices-provisioning.net/").unwrap(),
+            "scope".to_string(),
+            "reg".to_string(),
+            DPS_API_VERSION.to_string(),
+        )
+        .unwrap();
+        let task =
+            BackupProvisioning::new(&provisioning, backup_path).provision(MemoryKeyStore::new());
+        tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task)
+    }
+
+    #[test]
+    fn dps_x509_assignment_is_backed_up() {
+        let tmp_dir = TempDir::new("backup").unwrap();
+        let file_path = tmp_dir.path().join("dps_backup.json");
+
+        let prov_result =
+            dps_x509_provision(Some("hub1"), "initialAssignment", file_path.clone()).unwrap();
+        assert_eq!("device", prov_result.device_id());
+        assert_eq!("hub1", prov_result.hub_name());
+        assert_eq!(
+            ReprovisioningStatus::InitialAssignment,
+            prov_result.reconfigure()
+        );
+
+        let backup = BackupProvisioning::<ManualProvisioning>::read_backup(file_path).unwrap();
+        assert_eq!("device", backup.device_id());
+        assert_eq!("hub1", backup.hub_name());
+    }
+
+    #[test]
+    fn dps_x509_hub_change_reconfigures() {
+        let tmp_dir = TempDir::new("backup").unwrap();
+        let file_path = tmp_dir.path().join("dps_backup.json");
+        dps_x509_provision(Some("hub1"), "initialAssignment", file_path.clone()).unwrap();
+
+        // DPS reports a device that it moved to another hub as migrated
+        let prov_result =
+            dps_x509_provision(Some("hub2"), "deviceDataMigrated", file_path.clone()).unwrap();
+        assert_eq!("hub2", prov_result.hub_name());
+        assert_eq!(
+            ReprovisioningStatus::InitialAssignment,
+            prov_result.reconfigure()
+        );
+
+        let backup = BackupProvisioning::<ManualProvisioning>::read_backup(file_path).unwrap();
+        assert_eq!("hub2", backup.hub_name());
+    }
+
+    #[test]
+    fn dps_x509_unauthorized_cert_fails() {
+        let tmp_dir = TempDir::new("backup").unwrap();
+        let file_path = tmp_dir.path().join("dps_backup.json");
+
+        let err = dps_x509_provision(None, "initialAssignment", file_path.clone()).unwrap_err();
+        assert_eq!(&ErrorKind::CouldNotRestore, err.kind());
+    }
+
+    #[test]
+    fn dps_x509_unauthorized_cert_restores_backup() {
+        let tmp_dir = TempDir::new("backup").unwrap();
+        let file_path = tmp_dir.path().join("dps_backup.json");
+        dps_x509_provision(Some("hub1"), "initialAssignment", file_path.clone()).unwrap();
+
+        let prov_result = dps_x509_provision(None, "initialAssignment", file_path).unwrap();
+        assert_eq!("hub1", prov_result.hub_name());
+        assert_eq!(
+            ReprovisioningStatus::DeviceDataNotUpdated,
+            prov_result.reconfigure()
+        );
+    }
+
     struct TestExternalProvisioningInterface {
         pub error: Option<TestError>,
         pub provisioning_info: DeviceProvisioningInfo,
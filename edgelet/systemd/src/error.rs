@@ -41,6 +41,14 @@ pub enum ErrorKind {
     #[fail(display = "Environment variable {} is set to an invalid value.", _0)]
     InvalidVar(String),
 
+    #[cfg(target_os = "linux")]
+    #[fail(display = "Could not write to the journal.")]
+    Journal,
+
+    #[cfg(target_os = "linux")]
+    #[fail(display = "Could not notify the service manager.")]
+    Notify,
+
     #[fail(
         display = "Could not parse process ID from environment variable {}.",
         _0
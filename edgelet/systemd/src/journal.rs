@@ -0,0 +1,78 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Writes entries to the journal over its native protocol, so that they can
+//! carry fields such as `SYSLOG_IDENTIFIER` that a plain syslog message can't.
+//! Based off of [`sd_journal_send`](https://github.com/systemd/systemd/blob/master/src/libsystemd/sd-journal/journal-send.c)
+
+use std::convert::TryFrom;
+use std::os::unix::net::UnixDatagram;
+
+use failure::ResultExt;
+
+use crate::error::{Error, ErrorKind};
+
+const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+
+pub struct Journal {
+    socket: UnixDatagram,
+}
+
+impl Journal {
+    pub fn new() -> Result<Self, Error> {
+        let socket = UnixDatagram::unbound()
+            .and_then(|socket| socket.connect(JOURNAL_SOCKET).map(|()| socket))
+            .context(ErrorKind::Journal)?;
+        Ok(Journal { socket })
+    }
+
+    /// Writes an entry with `fields`, which are upper-case names like
+    /// `MESSAGE` and `PRIORITY` with their values.
+    ///
+    /// Entries must fit in a single datagram.
+    pub fn send(&self, fields: &[(&str, &[u8])]) -> Result<(), Error> {
+        self.socket
+            .send(&entry(fields))
+            .context(ErrorKind::Journal)?;
+        Ok(())
+    }
+}
+
+// Values with a newline in them are sent as the name, a newline, the length
+// as a little-endian u64 and the value, rather than as `NAME=value`.
+fn entry(fields: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut entry = vec![];
+    for (name, value) in fields {
+        entry.extend_from_slice(name.as_bytes());
+        if value.contains(&b'\n') {
+            entry.push(b'\n');
+            let len = u64::try_from(value.len()).expect("usize fits in u64");
+            entry.extend_from_slice(&len.to_le_bytes());
+        } else {
+            entry.push(b'=');
+        }
+        entry.extend_from_slice(value);
+        entry.push(b'\n');
+    }
+    entry
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entry_has_one_field_per_line() {
+        assert_eq!(
+            b"MESSAGE=hello\nSYSLOG_IDENTIFIER=tempSensor\n".to_vec(),
+            entry(&[("MESSAGE", b"hello"), ("SYSLOG_IDENTIFIER", b"tempSensor")]),
+        );
+    }
+
+    #[test]
+    fn entry_with_newline_is_length_prefixed() {
+        assert_eq!(
+            b"MESSAGE\n\x03\x00\x00\x00\x00\x00\x00\x00a\nb\nPRIORITY=6\n".to_vec(),
+            entry(&[("MESSAGE", b"a\nb"), ("PRIORITY", b"6")]),
+        );
+    }
+}
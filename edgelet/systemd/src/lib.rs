@@ -14,6 +14,8 @@ use std::net::SocketAddr;
 
 mod error;
 #[cfg(target_os = "linux")]
+mod journal;
+#[cfg(target_os = "linux")]
 mod linux;
 
 pub use self::error::{Error, ErrorKind, SocketLookupType};
@@ -28,4 +30,6 @@ pub enum Socket {
 }
 
 #[cfg(target_os = "linux")]
-pub use self::linux::{listener, listener_name, listeners_name, LISTEN_FDS_START};
+pub use self::journal::Journal;
+#[cfg(target_os = "linux")]
+pub use self::linux::{listener, listener_name, listeners_name, notify, LISTEN_FDS_START};
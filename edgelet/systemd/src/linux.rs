@@ -1,6 +1,7 @@
 // Copyright (c) Microsoft. All rights reserved.
 
-//! Implements the daemon interface for socket activation.
+//! Implements the daemon interface for socket activation and for notifying
+//! the service manager of the daemon's state.
 //! Provides two methods to get the resulting socket by name.
 //! Based off of [`systemd_socket`](https://github.com/viraptor/systemd_socket)
 //! and [`systemd-daemon`](https://github.com/systemd/systemd/tree/master/src/libsystemd/sd-daemon)
@@ -13,8 +14,9 @@ use failure::ResultExt;
 use log::debug;
 use nix::fcntl;
 use nix::sys::socket::{self, AddressFamily, SockType};
+use nix::sys::socket::{MsgFlags, SockAddr, SockFlag, UnixAddr};
 use nix::sys::stat;
-use nix::unistd::Pid;
+use nix::unistd::{self, Pid};
 
 use crate::error::{Error, ErrorKind, SocketLookupType};
 use crate::{Fd, Socket};
@@ -24,6 +26,7 @@ pub const LISTEN_FDS_START: Fd = 3;
 const ENV_PID: &str = "LISTEN_PID";
 const ENV_FDS: &str = "LISTEN_FDS";
 const ENV_NAMES: &str = "LISTEN_FDNAMES";
+const ENV_NOTIFY_SOCKET: &str = "NOTIFY_SOCKET";
 
 /// Returns the first listener for a file descriptor number.
 ///
@@ -68,6 +71,48 @@ pub fn listeners_name(name: &str) -> Result<Vec<Socket>, Error> {
     Ok(sockets.clone())
 }
 
+/// Sends `state`, like `READY=1` or `STOPPING=1`, to the service manager, so
+/// that a unit of `Type=notify` is only started once the daemon is ready.
+/// Returns whether it was sent, which it isn't if the daemon wasn't started by
+/// a service manager that listens for it.
+pub fn notify(state: &str) -> Result<bool, Error> {
+    let path = match env::var(ENV_NOTIFY_SOCKET) {
+        Ok(path) => path,
+        Err(env::VarError::NotPresent) => return Ok(false),
+        Err(_) => {
+            return Err(Error::from(ErrorKind::InvalidVar(
+                ENV_NOTIFY_SOCKET.to_string(),
+            )))
+        }
+    };
+    debug!("Notifying the service manager: {}", state);
+
+    // A path that starts with @ is in the abstract namespace.
+    let addr = if path.starts_with('@') {
+        UnixAddr::new_abstract(path[1..].as_bytes())
+    } else {
+        UnixAddr::new(path.as_str())
+    }
+    .context(ErrorKind::Notify)?;
+
+    let fd = socket::socket(
+        AddressFamily::Unix,
+        SockType::Datagram,
+        SockFlag::SOCK_CLOEXEC,
+        None,
+    )
+    .context(ErrorKind::Syscall("socket"))?;
+    let sent = socket::sendto(
+        fd,
+        state.as_bytes(),
+        &SockAddr::Unix(addr),
+        MsgFlags::empty(),
+    );
+    unistd::close(fd).context(ErrorKind::Syscall("close"))?;
+    sent.context(ErrorKind::Notify)?;
+    Ok(true)
+}
+
 fn unsetenv_all() {
     env::remove_var(ENV_PID);
     env::remove_var(ENV_FDS);
@@ -244,11 +289,11 @@ fn is_socket_unix(
 mod tests {
     use super::*;
 
+    use std::os::unix::net::UnixDatagram;
     use std::panic;
     use std::sync::{Mutex, MutexGuard};
 
     use lazy_static::lazy_static;
-    use nix::unistd;
 
     lazy_static! {
         static ref LOCK: Mutex<()> = Mutex::new(());
@@ -314,6 +359,31 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_notify() {
+        let _l = lock_env();
+        let path = env::temp_dir().join(format!("systemd-notify-{}", Pid::this()));
+        let socket = UnixDatagram::bind(&path).unwrap();
+        env::set_var(ENV_NOTIFY_SOCKET, &path);
+
+        let sent = notify("READY=1");
+        env::remove_var(ENV_NOTIFY_SOCKET);
+        let mut buf = [0; 16];
+        let len = socket.recv(&mut buf).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(sent.unwrap());
+        assert_eq!(b"READY=1", &buf[..len]);
+    }
+
+    #[test]
+    fn test_notify_without_service_manager() {
+        let _l = lock_env();
+        env::remove_var(ENV_NOTIFY_SOCKET);
+
+        assert!(!notify("READY=1").unwrap());
+    }
+
     #[test]
     fn test_listen_fds_with_missing_env() {
         let _l = lock_env();
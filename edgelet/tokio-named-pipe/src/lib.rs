@@ -9,12 +9,14 @@ use std::convert::AsRef;
 use std::fs::OpenOptions;
 use std::io::{self, Read, Write};
 use std::iter::once;
+use std::mem;
 use std::os::windows::prelude::*;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
-use futures::Poll;
+use futures::{Async, Poll};
 use mio_named_pipes::NamedPipe;
+use miow::pipe::NamedPipeBuilder;
 use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::reactor::PollEvented2;
 use winapi::shared::winerror::ERROR_PIPE_BUSY;
@@ -22,6 +24,7 @@ use winapi::um::namedpipeapi::WaitNamedPipeW;
 use winapi::um::winbase::*;
 
 const PIPE_WAIT_TIMEOUT_MS: u32 = 10 * 1000;
+const PIPE_BUFFER_SIZE: u32 = 65536;
 
 #[derive(Debug)]
 pub struct PipeStream {
@@ -90,6 +93,12 @@ impl PipeStream {
     }
 }
 
+impl AsRawHandle for PipeStream {
+    fn as_raw_handle(&self) -> RawHandle {
+        self.io.get_ref().as_raw_handle()
+    }
+}
+
 impl Read for PipeStream {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         self.io.read(buf)
@@ -133,3 +142,51 @@ impl AsyncWrite for PipeStream {
         Ok(().into())
     }
 }
+
+/// The server end of a named pipe. Every client gets its own instance of the
+/// pipe, so there is always one that is waiting for the next client.
+#[derive(Debug)]
+pub struct PipeListener {
+    path: PathBuf,
+    pending: PollEvented2<NamedPipe>,
+}
+
+impl PipeListener {
+    /// Creates the pipe at `path`, which looks like `\\.\pipe\name`. Fails if
+    /// another process already has a pipe with that name.
+    pub fn bind<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let pending = PollEvented2::new(create_instance(&path, true)?);
+        Ok(PipeListener { path, pending })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn poll_accept(&mut self) -> Poll<PipeStream, io::Error> {
+        match self.pending.get_ref().connect() {
+            Ok(()) => (),
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
+                self.pending.clear_write_ready()?;
+                return Ok(Async::NotReady);
+            }
+            Err(err) => return Err(err),
+        }
+
+        let next = PollEvented2::new(create_instance(&self.path, false)?);
+        let io = mem::replace(&mut self.pending, next);
+        Ok(Async::Ready(PipeStream { io }))
+    }
+}
+
+fn create_instance(path: &Path, first: bool) -> io::Result<NamedPipe> {
+    let pipe = NamedPipeBuilder::new(path)
+        .first(first)
+        .inbound(true)
+        .outbound(true)
+        .in_buffer_size(PIPE_BUFFER_SIZE)
+        .out_buffer_size(PIPE_BUFFER_SIZE)
+        .create()?;
+    Ok(unsafe { NamedPipe::from_raw_handle(pipe.into_raw_handle()) })
+}
@@ -9,7 +9,7 @@ use std::str;
 
 use futures::sink::Sink;
 use futures::stream::Stream;
-use futures::Future;
+use futures::{future, Future};
 use mio::{Events, Poll, PollOpt, Ready, Token};
 use mio_named_pipes::NamedPipe;
 use rand::Rng;
@@ -17,7 +17,7 @@ use tokio::codec::{FramedRead, FramedWrite, LinesCodec};
 use tokio::io as tio;
 use winapi::shared::winerror::ERROR_FILE_NOT_FOUND;
 
-use tokio_named_pipe::PipeStream;
+use tokio_named_pipe::{PipeListener, PipeStream};
 
 macro_rules! t {
     ($e:expr) => {
@@ -50,6 +50,21 @@ fn connect_succeeds() {
     let _stream = PipeStream::connect(path, None).unwrap();
 }
 
+#[test]
+fn listener_accepts_each_client() {
+    let num: u64 = rand::thread_rng().gen();
+    let path = format!(r"\\.\pipe\my-listener-{}", num);
+    let mut listener = t!(PipeListener::bind(&path));
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+
+    // A new instance of the pipe only exists once the previous client has
+    // been accepted
+    let _first = t!(PipeStream::connect(&path, None));
+    let _accepted = t!(runtime.block_on(future::poll_fn(|| listener.poll_accept())));
+    let _second = t!(PipeStream::connect(&path, None));
+    let _accepted = t!(runtime.block_on(future::poll_fn(|| listener.poll_accept())));
+}
+
 #[test]
 fn read_data() {
     let data = b"cow say moo";
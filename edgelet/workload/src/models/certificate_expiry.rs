@@ -0,0 +1,92 @@
+/*
+ * IoT Edge Module Workload API
+ *
+ * No description provided (generated by Swagger Codegen https://github.com/swagger-api/swagger-codegen)
+ *
+ * OpenAPI spec version: 2019-01-30
+ *
+ * Generated by: https://github.com/swagger-api/swagger-codegen.git
+ */
+
+use serde_derive::{Deserialize, Serialize};
+#[allow(unused_imports)]
+use serde_json::Value;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CertificateExpiry {
+    /// Alias of the managed certificate whose chain contains this certificate.
+    #[serde(rename = "alias")]
+    alias: String,
+    /// Common name of the certificate.
+    #[serde(rename = "subject")]
+    subject: String,
+    /// Certificate expiration date-time (ISO 8601)
+    #[serde(rename = "notAfter")]
+    not_after: String,
+    /// Whole days until the certificate expires. Negative once it has expired.
+    #[serde(rename = "daysRemaining")]
+    days_remaining: i64,
+}
+
+impl CertificateExpiry {
+    pub fn new(alias: String, subject: String, not_after: String, days_remaining: i64) -> Self {
+        CertificateExpiry {
+            alias,
+            subject,
+            not_after,
+            days_remaining,
+        }
+    }
+
+    pub fn set_alias(&mut self, alias: String) {
+        self.alias = alias;
+    }
+
+    pub fn with_alias(mut self, alias: String) -> Self {
+        self.alias = alias;
+        self
+    }
+
+    pub fn alias(&self) -> &String {
+        &self.alias
+    }
+
+    pub fn set_subject(&mut self, subject: String) {
+        self.subject = subject;
+    }
+
+    pub fn with_subject(mut self, subject: String) -> Self {
+        self.subject = subject;
+        self
+    }
+
+    pub fn subject(&self) -> &String {
+        &self.subject
+    }
+
+    pub fn set_not_after(&mut self, not_after: String) {
+        self.not_after = not_after;
+    }
+
+    pub fn with_not_after(mut self, not_after: String) -> Self {
+        self.not_after = not_after;
+        self
+    }
+
+    pub fn not_after(&self) -> &String {
+        &self.not_after
+    }
+
+    pub fn set_days_remaining(&mut self, days_remaining: i64) {
+        self.days_remaining = days_remaining;
+    }
+
+    pub fn with_days_remaining(mut self, days_remaining: i64) -> Self {
+        self.days_remaining = days_remaining;
+        self
+    }
+
+    pub fn days_remaining(&self) -> i64 {
+        self.days_remaining
+    }
+}
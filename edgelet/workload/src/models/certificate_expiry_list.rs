@@ -0,0 +1,60 @@
+/*
+ * IoT Edge Module Workload API
+ *
+ * No description provided (generated by Swagger Codegen https://github.com/swagger-api/swagger-codegen)
+ *
+ * OpenAPI spec version: 2019-01-30
+ *
+ * Generated by: https://github.com/swagger-api/swagger-codegen.git
+ */
+
+use serde_derive::{Deserialize, Serialize};
+#[allow(unused_imports)]
+use serde_json::Value;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CertificateExpiryList {
+    #[serde(rename = "certificates")]
+    certificates: Vec<crate::models::CertificateExpiry>,
+    /// Number of days before expiry at which a certificate is reported as expiring.
+    #[serde(rename = "warningDays")]
+    warning_days: i64,
+}
+
+impl CertificateExpiryList {
+    pub fn new(certificates: Vec<crate::models::CertificateExpiry>, warning_days: i64) -> Self {
+        CertificateExpiryList {
+            certificates,
+            warning_days,
+        }
+    }
+
+    pub fn set_certificates(&mut self, certificates: Vec<crate::models::CertificateExpiry>) {
+        self.certificates = certificates;
+    }
+
+    pub fn with_certificates(
+        mut self,
+        certificates: Vec<crate::models::CertificateExpiry>,
+    ) -> Self {
+        self.certificates = certificates;
+        self
+    }
+
+    pub fn certificates(&self) -> &[crate::models::CertificateExpiry] {
+        &self.certificates
+    }
+
+    pub fn set_warning_days(&mut self, warning_days: i64) {
+        self.warning_days = warning_days;
+    }
+
+    pub fn with_warning_days(mut self, warning_days: i64) -> Self {
+        self.warning_days = warning_days;
+        self
+    }
+
+    pub fn warning_days(&self) -> i64 {
+        self.warning_days
+    }
+}
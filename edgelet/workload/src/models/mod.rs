@@ -1,3 +1,7 @@
+mod certificate_expiry;
+pub use self::certificate_expiry::CertificateExpiry;
+mod certificate_expiry_list;
+pub use self::certificate_expiry_list::CertificateExpiryList;
 mod certificate_response;
 pub use self::certificate_response::CertificateResponse;
 mod decrypt_request;